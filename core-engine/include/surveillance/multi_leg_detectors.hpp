@@ -0,0 +1,82 @@
+#pragma once
+
+#include <atomic>
+#include <mutex>
+#include <optional>
+#include <string>
+#include <unordered_set>
+
+#include "trade_pattern_detector.hpp"
+
+namespace dharmaguard {
+namespace surveillance {
+
+/**
+ * @brief Wash trades hidden across the legs of multi-leg strategies
+ *
+ * Flags a leg that is offset, in the same contract at about the same price
+ * and quantity, either by another leg of its own strategy or by a leg of a
+ * recent strategy from the same or a related account. Each leg on its own
+ * is an ordinary trade, so per-trade wash detection does not see these.
+ */
+class CrossLegWashDetector : public IPatternDetector {
+public:
+    CrossLegWashDetector() = default;
+
+    std::optional<SurveillanceAlert> detect_pattern(
+        const TradeData& trade,
+        const HistoricalContext& historical_context) override;
+
+    void update_config(const PatternConfig& config) override;
+
+    std::string get_name() const override { return "cross_leg_wash"; }
+    bool is_enabled() const override { return enabled_.load(); }
+    void set_enabled(bool enabled) override { enabled_.store(enabled); }
+
+private:
+    std::atomic<bool> enabled_{true};
+
+    // Matching tolerances, as a percentage of the leg being checked
+    std::atomic<double> price_tolerance_percent_{0.5};
+    std::atomic<double> quantity_tolerance_percent_{5.0};
+
+    // Offsets below this traded value are ignored
+    std::atomic<double> min_offset_value_{100000.0};
+};
+
+/**
+ * @brief Baskets reversed leg for leg by a later basket
+ *
+ * Compares the basket a trade belongs to with recent baskets of the same or
+ * related accounts and alerts once per pair when enough legs are matched by
+ * an opposite-side trade in the same instrument.
+ */
+class BasketRoundTripDetector : public IPatternDetector {
+public:
+    BasketRoundTripDetector() = default;
+
+    std::optional<SurveillanceAlert> detect_pattern(
+        const TradeData& trade,
+        const HistoricalContext& historical_context) override;
+
+    void update_config(const PatternConfig& config) override;
+
+    std::string get_name() const override { return "basket_round_trip"; }
+    bool is_enabled() const override { return enabled_.load(); }
+    void set_enabled(bool enabled) override { enabled_.store(enabled); }
+
+private:
+    std::atomic<bool> enabled_{true};
+    std::atomic<double> quantity_tolerance_percent_{5.0};
+
+    // Share of the larger basket's legs that must be reversed
+    std::atomic<double> min_matched_leg_ratio_{0.8};
+    std::atomic<double> min_basket_value_{500000.0};
+
+    // Basket pairs already alerted on, so later legs do not repeat the alert
+    std::mutex alerted_mutex_;
+    std::unordered_set<std::string> alerted_pairs_;
+};
+
+} // namespace surveillance
+} // namespace dharmaguard
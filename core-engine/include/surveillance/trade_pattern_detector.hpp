@@ -5,6 +5,9 @@
 #include <unordered_map>
 #include <string>
 #include <chrono>
+#include <deque>
+#include <functional>
+#include <optional>
 #include <atomic>
 #include <thread>
 #include <queue>
@@ -164,6 +167,9 @@ private:
     
     // Validate trade data
     bool validate_trade_data(const TradeData& trade) const;
+
+    // Add a strategy or basket leg to its execution group and expose the group to detectors
+    void attach_execution_group(const TradeData& trade, HistoricalContext& context);
 };
 
 /**
@@ -258,6 +264,29 @@ struct TradeData {
     uint32_t instrument_id_hash;
     uint32_t account_id_hash;
     uint32_t client_id_hash;
+
+    // Multi-leg strategy membership; strategy_id is empty for single-leg trades
+    std::string strategy_id;
+    enum class StrategyType { NONE, SPREAD, STRADDLE, STRANGLE, BUTTERFLY, CONDOR, CALENDAR, CUSTOM }
+        strategy_type = StrategyType::NONE;
+    uint16_t leg_number = 0;   // 1-based, 0 when not reported
+    uint16_t leg_count = 0;    // legs in the strategy, 0 when not reported
+
+    // Basket execution membership; empty for trades not placed as part of a basket
+    std::string basket_id;
+
+    // Derivative contract details, used to relate legs on the same underlying
+    std::string underlying_symbol;
+    enum class OptionType { NONE, CALL, PUT } option_type = OptionType::NONE;
+    double strike_price = 0.0;
+    std::chrono::system_clock::time_point expiry;
+
+    bool is_strategy_leg() const { return !strategy_id.empty(); }
+    bool is_basket_leg() const { return !basket_id.empty(); }
+
+    bool is_buy() const {
+        return trade_type == TradeType::BUY || trade_type == TradeType::COVER;
+    }
     
     // Validation
     bool is_valid() const {
@@ -265,7 +294,45 @@ struct TradeData {
                !instrument_symbol.empty() && 
                quantity > 0 && 
                price > 0.0 && 
-               value > 0.0;
+               value > 0.0 &&
+               (leg_count == 0 || leg_number <= leg_count) &&
+               (strategy_id.empty() ? leg_number == 0 && leg_count == 0 : true);
+    }
+};
+
+/**
+ * @brief Trades executed together as one multi-leg strategy or basket
+ *
+ * Per-trade analysis sees each leg in isolation, so offsetting legs that
+ * only make sense together (a buy and sell of the same contract inside one
+ * spread, or a basket reversed by a related account) look like ordinary
+ * trades. Groups are assembled as legs arrive and handed to detectors
+ * through HistoricalContext.
+ */
+struct ExecutionGroup {
+    enum class Kind { STRATEGY, BASKET } kind = Kind::STRATEGY;
+    std::string group_id;
+    std::string exchange;
+    std::string account_id;
+    std::string client_id;
+    TradeData::StrategyType strategy_type = TradeData::StrategyType::NONE;
+
+    // Legs the exchange reported for the strategy; 0 for baskets, which complete when idle
+    uint16_t expected_legs = 0;
+    std::vector<TradeData> legs;
+    std::chrono::system_clock::time_point first_leg_time;
+    std::chrono::system_clock::time_point last_leg_time;
+
+    bool is_complete() const {
+        return expected_legs > 0 && legs.size() >= expected_legs;
+    }
+
+    double total_value() const {
+        double total = 0.0;
+        for (const auto& leg : legs) {
+            total += leg.value;
+        }
+        return total;
     }
 };
 
@@ -297,6 +364,12 @@ struct HistoricalContext {
     // Cross-references
     std::vector<std::string> related_accounts;
     std::vector<std::string> related_instruments;
+
+    // Strategy or basket the current trade belongs to, with the legs seen so far
+    std::optional<ExecutionGroup> execution_group;
+
+    // Groups recently executed by this account and its related accounts
+    std::vector<ExecutionGroup> recent_groups;
 };
 
 } // namespace surveillance
@@ -0,0 +1,210 @@
+#include "surveillance/multi_leg_detectors.hpp"
+
+#include <spdlog/spdlog.h>
+
+#include <algorithm>
+#include <cmath>
+#include <sstream>
+
+namespace dharmaguard {
+namespace surveillance {
+
+namespace {
+
+// Alerted basket pairs remembered before the set is reset
+constexpr size_t kMaxAlertedPairs = 100000;
+
+bool within_percent(double value, double reference, double tolerance_percent) {
+    if (reference <= 0.0) {
+        return false;
+    }
+    return std::abs(value - reference) / reference * 100.0 <= tolerance_percent;
+}
+
+// Opposite sides of the same contract at a comparable size
+bool offsets(const TradeData& leg, const TradeData& other, double quantity_tolerance_percent) {
+    return leg.trade_id != other.trade_id &&
+           leg.instrument_symbol == other.instrument_symbol &&
+           leg.exchange == other.exchange &&
+           leg.is_buy() != other.is_buy() &&
+           within_percent(static_cast<double>(other.quantity), static_cast<double>(leg.quantity),
+                          quantity_tolerance_percent);
+}
+
+std::string describe(const ExecutionGroup& group) {
+    std::ostringstream out;
+    out << (group.kind == ExecutionGroup::Kind::STRATEGY ? "strategy " : "basket ")
+        << group.group_id << " (" << group.legs.size();
+    if (group.expected_legs > 0) {
+        out << "/" << group.expected_legs;
+    }
+    out << " legs, account " << group.account_id << ")";
+    return out.str();
+}
+
+SurveillanceAlert make_alert(const std::string& pattern_name,
+                             const TradeData& trade,
+                             std::string title,
+                             std::string description,
+                             std::vector<std::string> trade_ids,
+                             double risk_score,
+                             double confidence) {
+    SurveillanceAlert alert;
+    alert.pattern_name = pattern_name;
+    alert.alert_type = "MULTI_LEG";
+    alert.severity = risk_score >= 80.0 ? AlertSeverity::CRITICAL : AlertSeverity::HIGH;
+    alert.title = std::move(title);
+    alert.description = std::move(description);
+    alert.account_id = trade.account_id;
+    alert.client_id = trade.client_id;
+    alert.instrument_symbol = trade.instrument_symbol;
+    alert.trade_ids = std::move(trade_ids);
+    alert.risk_score = risk_score;
+    alert.confidence_level = confidence;
+    alert.detection_timestamp = std::chrono::system_clock::now();
+    return alert;
+}
+
+} // namespace
+
+std::optional<SurveillanceAlert> CrossLegWashDetector::detect_pattern(
+    const TradeData& trade,
+    const HistoricalContext& historical_context) {
+
+    if (!trade.is_strategy_leg() || !historical_context.execution_group) {
+        return std::nullopt;
+    }
+    const auto& group = *historical_context.execution_group;
+    const double price_tolerance = price_tolerance_percent_.load();
+    const double quantity_tolerance = quantity_tolerance_percent_.load();
+
+    auto matches = [&](const TradeData& other) {
+        return offsets(trade, other, quantity_tolerance) &&
+               within_percent(other.price, trade.price, price_tolerance) &&
+               std::min(trade.value, other.value) >= min_offset_value_.load();
+    };
+
+    // Legs of the same strategy that cancel out carry no market risk
+    for (const auto& leg : group.legs) {
+        if (matches(leg)) {
+            return make_alert(
+                get_name(), trade,
+                "Offsetting legs within a multi-leg strategy",
+                "Leg " + std::to_string(trade.leg_number) + " of " + describe(group) +
+                    " offsets trade " + leg.trade_id + " in " + trade.instrument_symbol +
+                    " within the same strategy",
+                {trade.trade_id, leg.trade_id},
+                85.0, 90.0);
+        }
+    }
+
+    // A leg reversed by another strategy of the same or a related account
+    for (const auto& other_group : historical_context.recent_groups) {
+        if (other_group.kind != ExecutionGroup::Kind::STRATEGY || other_group.group_id == group.group_id) {
+            continue;
+        }
+        for (const auto& leg : other_group.legs) {
+            if (!matches(leg)) {
+                continue;
+            }
+            const bool same_account = other_group.account_id == trade.account_id;
+            return make_alert(
+                get_name(), trade,
+                same_account ? "Cross-strategy wash within one account"
+                             : "Cross-leg wash with a related account",
+                "Leg " + std::to_string(trade.leg_number) + " of " + describe(group) +
+                    " offsets trade " + leg.trade_id + " of " + describe(other_group) +
+                    " in " + trade.instrument_symbol,
+                {trade.trade_id, leg.trade_id},
+                same_account ? 80.0 : 75.0,
+                same_account ? 85.0 : 70.0);
+        }
+    }
+
+    return std::nullopt;
+}
+
+void CrossLegWashDetector::update_config(const PatternConfig& config) {
+    price_tolerance_percent_.store(config.get<double>("price_tolerance_percent", 0.5));
+    quantity_tolerance_percent_.store(config.get<double>("quantity_tolerance_percent", 5.0));
+    min_offset_value_.store(config.get<double>("min_offset_value", 100000.0));
+}
+
+std::optional<SurveillanceAlert> BasketRoundTripDetector::detect_pattern(
+    const TradeData& trade,
+    const HistoricalContext& historical_context) {
+
+    if (!trade.is_basket_leg() || !historical_context.execution_group) {
+        return std::nullopt;
+    }
+    const auto& basket = *historical_context.execution_group;
+    if (basket.kind != ExecutionGroup::Kind::BASKET || basket.legs.size() < 2 ||
+        basket.total_value() < min_basket_value_.load()) {
+        return std::nullopt;
+    }
+    const double quantity_tolerance = quantity_tolerance_percent_.load();
+
+    for (const auto& earlier : historical_context.recent_groups) {
+        if (earlier.kind != ExecutionGroup::Kind::BASKET || earlier.group_id == basket.group_id ||
+            earlier.last_leg_time > basket.first_leg_time) {
+            continue;
+        }
+
+        // Each earlier leg can reverse at most one leg of this basket
+        std::vector<bool> used(earlier.legs.size(), false);
+        std::vector<std::string> trade_ids;
+        size_t matched = 0;
+        for (const auto& leg : basket.legs) {
+            for (size_t i = 0; i < earlier.legs.size(); ++i) {
+                if (!used[i] && offsets(leg, earlier.legs[i], quantity_tolerance)) {
+                    used[i] = true;
+                    ++matched;
+                    trade_ids.push_back(leg.trade_id);
+                    trade_ids.push_back(earlier.legs[i].trade_id);
+                    break;
+                }
+            }
+        }
+
+        const double ratio = static_cast<double>(matched) /
+                             static_cast<double>(std::max(basket.legs.size(), earlier.legs.size()));
+        if (ratio < min_matched_leg_ratio_.load()) {
+            continue;
+        }
+
+        const auto pair_key = earlier.exchange + "_" + earlier.group_id + "|" + basket.group_id;
+        {
+            std::lock_guard<std::mutex> lock(alerted_mutex_);
+            if (alerted_pairs_.size() >= kMaxAlertedPairs) {
+                alerted_pairs_.clear();
+            }
+            if (!alerted_pairs_.insert(pair_key).second) {
+                return std::nullopt;
+            }
+        }
+
+        const bool same_account = earlier.account_id == basket.account_id;
+        spdlog::debug("Basket {} reverses {} of {} legs of basket {}",
+                      basket.group_id, matched, earlier.legs.size(), earlier.group_id);
+        return make_alert(
+            get_name(), trade,
+            same_account ? "Basket reversed by the same account"
+                         : "Basket reversed by a related account",
+            describe(basket) + " reverses " + std::to_string(matched) + " legs of " +
+                describe(earlier),
+            std::move(trade_ids),
+            std::min(100.0, 60.0 + ratio * 30.0),
+            same_account ? 85.0 : 70.0);
+    }
+
+    return std::nullopt;
+}
+
+void BasketRoundTripDetector::update_config(const PatternConfig& config) {
+    quantity_tolerance_percent_.store(config.get<double>("quantity_tolerance_percent", 5.0));
+    min_matched_leg_ratio_.store(config.get<double>("min_matched_leg_ratio", 0.8));
+    min_basket_value_.store(config.get<double>("min_basket_value", 500000.0));
+}
+
+} // namespace surveillance
+} // namespace dharmaguard
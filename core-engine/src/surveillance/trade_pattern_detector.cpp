@@ -4,6 +4,7 @@
 #include "surveillance/wash_trading_detector.hpp"
 #include "surveillance/insider_trading_detector.hpp"
 #include "surveillance/front_running_detector.hpp"
+#include "surveillance/multi_leg_detectors.hpp"
 #include "utils/logger.hpp"
 #include "utils/config_manager.hpp"
 #include "utils/metrics_collector.hpp"
@@ -55,6 +56,18 @@ public:
     
     // Performance optimization: pre-allocated vectors
     thread_local std::vector<TradeData> batch_buffer_;
+
+    // Strategy and basket executions, keyed by exchange and group id
+    std::mutex group_mutex_;
+    std::unordered_map<std::string, ExecutionGroup> open_groups_;
+    // Finished groups per account, newest last, kept for the group window
+    std::unordered_map<std::string, std::deque<ExecutionGroup>> completed_groups_;
+    std::chrono::system_clock::time_point last_group_sweep_;
+
+    // How long finished groups stay available for cross-group matching, and
+    // how long a basket may go without a new leg before it is considered done
+    std::chrono::minutes group_window_{60};
+    std::chrono::seconds basket_idle_timeout_{30};
 };
 
 TradePatternDetector::TradePatternDetector(size_t num_threads, size_t queue_size)
@@ -360,6 +373,9 @@ void TradePatternDetector::process_trade_internal(const TradeData& trade) {
     
     // Update context cache
     impl_->context_cache_[context_key] = context;
+
+    // Legs are judged together with the rest of their strategy or basket
+    attach_execution_group(trade, context);
     
     // Run all enabled pattern detectors in parallel
     std::vector<std::pair<std::string, std::shared_ptr<IPatternDetector>>> enabled_detectors;
@@ -407,9 +423,96 @@ void TradePatternDetector::initialize_builtin_detectors() {
     register_pattern_detector("front_running", 
         std::make_shared<FrontRunningDetector>());
     
+    register_pattern_detector("cross_leg_wash", 
+        std::make_shared<CrossLegWashDetector>());
+    
+    register_pattern_detector("basket_round_trip", 
+        std::make_shared<BasketRoundTripDetector>());
+    
     spdlog::info("Initialized {} built-in pattern detectors", detectors_.size());
 }
 
+void TradePatternDetector::attach_execution_group(const TradeData& trade, HistoricalContext& context) {
+    if (!trade.is_strategy_leg() && !trade.is_basket_leg()) {
+        return;
+    }
+
+    // A strategy placed inside a basket is grouped by its strategy id
+    const auto kind = trade.is_strategy_leg() ? ExecutionGroup::Kind::STRATEGY
+                                              : ExecutionGroup::Kind::BASKET;
+    const auto& group_id = trade.is_strategy_leg() ? trade.strategy_id : trade.basket_id;
+    const auto key = trade.exchange + "_" + group_id;
+
+    std::lock_guard<std::mutex> lock(impl_->group_mutex_);
+
+    auto finish = [this](ExecutionGroup&& group) {
+        impl_->completed_groups_[group.account_id].push_back(std::move(group));
+    };
+
+    // Move idle baskets and abandoned strategies to the completed set, and
+    // drop completed groups that have aged out of the window
+    if (trade.timestamp - impl_->last_group_sweep_ >= std::chrono::seconds(1)) {
+        for (auto it = impl_->open_groups_.begin(); it != impl_->open_groups_.end();) {
+            auto idle = trade.timestamp - it->second.last_leg_time;
+            bool basket_done = it->second.kind == ExecutionGroup::Kind::BASKET &&
+                               idle >= impl_->basket_idle_timeout_;
+            if (it->first != key && (basket_done || idle >= impl_->group_window_)) {
+                finish(std::move(it->second));
+                it = impl_->open_groups_.erase(it);
+            } else {
+                ++it;
+            }
+        }
+        const auto cutoff = trade.timestamp - impl_->group_window_;
+        for (auto it = impl_->completed_groups_.begin(); it != impl_->completed_groups_.end();) {
+            auto& groups = it->second;
+            while (!groups.empty() && groups.front().last_leg_time < cutoff) {
+                groups.pop_front();
+            }
+            it = groups.empty() ? impl_->completed_groups_.erase(it) : std::next(it);
+        }
+        impl_->last_group_sweep_ = trade.timestamp;
+    }
+
+    auto& group = impl_->open_groups_[key];
+    if (group.legs.empty()) {
+        group.kind = kind;
+        group.group_id = group_id;
+        group.exchange = trade.exchange;
+        group.account_id = trade.account_id;
+        group.client_id = trade.client_id;
+        group.strategy_type = trade.strategy_type;
+        group.expected_legs = kind == ExecutionGroup::Kind::STRATEGY ? trade.leg_count : 0;
+        group.first_leg_time = trade.timestamp;
+    }
+    group.legs.push_back(trade);
+    group.last_leg_time = std::max(group.last_leg_time, trade.timestamp);
+    context.execution_group = group;
+
+    // Groups of the account itself and of accounts related to it
+    context.recent_groups.clear();
+    std::vector<std::string> accounts = context.related_accounts;
+    accounts.push_back(trade.account_id);
+    for (const auto& account : accounts) {
+        auto completed = impl_->completed_groups_.find(account);
+        if (completed != impl_->completed_groups_.end()) {
+            context.recent_groups.insert(context.recent_groups.end(),
+                                         completed->second.begin(), completed->second.end());
+        }
+    }
+    for (const auto& [open_key, open] : impl_->open_groups_) {
+        if (open_key != key &&
+            std::find(accounts.begin(), accounts.end(), open.account_id) != accounts.end()) {
+            context.recent_groups.push_back(open);
+        }
+    }
+
+    if (group.is_complete()) {
+        finish(std::move(group));
+        impl_->open_groups_.erase(key);
+    }
+}
+
 bool TradePatternDetector::load_configuration(const std::string& config_path) {
     try {
         std::ifstream config_file(config_path);
@@ -0,0 +1,42 @@
+-- Migration: Report Template Bundles
+-- Version: 1.2.0
+-- Description: Tenant-owned report templates with portable, signed bundles
+
+-- ===========================================
+-- TENANT-OWNED TEMPLATES
+-- ===========================================
+
+-- NULL tenant_id marks a platform default template
+ALTER TABLE report_templates
+    ADD COLUMN tenant_id UUID REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    ADD COLUMN schema_version INTEGER NOT NULL DEFAULT 1,
+    ADD COLUMN metric_selections JSONB NOT NULL DEFAULT '[]';
+
+ALTER TABLE report_templates DROP CONSTRAINT report_templates_template_name_key;
+
+CREATE UNIQUE INDEX idx_report_templates_tenant_name
+    ON report_templates (COALESCE(tenant_id, '00000000-0000-0000-0000-000000000000'::UUID), template_name);
+
+-- ===========================================
+-- IMPORT HISTORY
+-- ===========================================
+
+-- Every bundle import attempt, successful or rejected
+CREATE TABLE report_template_imports (
+    import_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    template_id UUID REFERENCES report_templates(template_id) ON DELETE SET NULL,
+    source_tenant_id UUID,
+    source_template_id UUID,
+    bundle_schema_version INTEGER NOT NULL,
+    bundle_signature TEXT NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    errors JSONB DEFAULT '[]',
+    imported_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT chk_import_status CHECK (status IN ('IMPORTED', 'REJECTED'))
+);
+
+CREATE INDEX idx_template_imports_tenant ON report_template_imports(tenant_id, imported_at DESC);
+
+COMMENT ON TABLE report_template_imports IS 'Audit log of report template bundle imports across tenants and environments';
@@ -0,0 +1,5 @@
+-- Migration: Audit Trail Keyset Pagination
+-- Version: 1.3.0
+-- Description: Index supporting (timestamp, log_id) keyset iteration per tenant
+
+CREATE INDEX idx_audit_logs_tenant_keyset ON audit_logs(tenant_id, timestamp DESC, log_id DESC);
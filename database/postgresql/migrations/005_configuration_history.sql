@@ -0,0 +1,110 @@
+-- Migration: Configuration History
+-- Version: 1.4.0
+-- Description: Trigger-maintained history of users, permissions and tenant settings for as-of queries
+
+-- ===========================================
+-- HISTORY STORAGE
+-- ===========================================
+
+-- One row per version of a tracked row; valid_to is NULL for the current version
+CREATE TABLE configuration_history (
+    history_id BIGSERIAL PRIMARY KEY,
+    table_name VARCHAR(64) NOT NULL,
+    entity_id UUID NOT NULL,
+    owner_id UUID, -- tenant_id, or user_id for user_permissions
+    row_data JSONB NOT NULL,
+    operation CHAR(1) NOT NULL,
+    valid_from TIMESTAMPTZ NOT NULL,
+    valid_to TIMESTAMPTZ,
+    changed_by TEXT,
+
+    CONSTRAINT chk_history_operation CHECK (operation IN ('I', 'U', 'D')),
+    CONSTRAINT chk_history_validity CHECK (valid_to IS NULL OR valid_to >= valid_from)
+);
+
+CREATE INDEX idx_config_history_entity ON configuration_history(table_name, entity_id, valid_from DESC);
+CREATE INDEX idx_config_history_owner ON configuration_history(table_name, owner_id, valid_from DESC);
+CREATE UNIQUE INDEX idx_config_history_current ON configuration_history(table_name, entity_id) WHERE valid_to IS NULL;
+
+-- ===========================================
+-- HISTORY TRIGGER
+-- ===========================================
+
+-- TG_ARGV[0]: primary key column, TG_ARGV[1]: owner column
+-- Credentials are never copied into history
+CREATE OR REPLACE FUNCTION record_configuration_history()
+RETURNS TRIGGER AS $$
+DECLARE
+    old_row JSONB;
+    new_row JSONB;
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        old_row := to_jsonb(OLD);
+        UPDATE configuration_history
+        SET valid_to = NOW()
+        WHERE table_name = TG_TABLE_NAME
+          AND entity_id = (old_row ->> TG_ARGV[0])::UUID
+          AND valid_to IS NULL;
+    END IF;
+
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        new_row := to_jsonb(NEW) - 'password_hash' - 'salt' - 'mfa_secret';
+        INSERT INTO configuration_history (table_name, entity_id, owner_id, row_data, operation, valid_from, changed_by)
+        VALUES (
+            TG_TABLE_NAME,
+            (new_row ->> TG_ARGV[0])::UUID,
+            (new_row ->> TG_ARGV[1])::UUID,
+            new_row,
+            LEFT(TG_OP, 1),
+            NOW(),
+            current_setting('app.current_user_id', true)
+        );
+        RETURN NEW;
+    END IF;
+
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER users_history_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON users
+    FOR EACH ROW
+    EXECUTE FUNCTION record_configuration_history('user_id', 'tenant_id');
+
+CREATE TRIGGER user_permissions_history_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON user_permissions
+    FOR EACH ROW
+    EXECUTE FUNCTION record_configuration_history('permission_id', 'user_id');
+
+CREATE TRIGGER tenants_history_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON tenants
+    FOR EACH ROW
+    EXECUTE FUNCTION record_configuration_history('tenant_id', 'tenant_id');
+
+CREATE TRIGGER tenant_configurations_history_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON tenant_configurations
+    FOR EACH ROW
+    EXECUTE FUNCTION record_configuration_history('config_id', 'tenant_id');
+
+-- ===========================================
+-- BACKFILL
+-- ===========================================
+
+-- Seed the current state so history starts from the migration point
+INSERT INTO configuration_history (table_name, entity_id, owner_id, row_data, operation, valid_from)
+SELECT 'users', user_id, tenant_id, to_jsonb(u) - 'password_hash' - 'salt' - 'mfa_secret', 'I', COALESCE(updated_at, created_at, NOW())
+FROM users u;
+
+INSERT INTO configuration_history (table_name, entity_id, owner_id, row_data, operation, valid_from)
+SELECT 'user_permissions', permission_id, user_id, to_jsonb(p), 'I', COALESCE(granted_at, NOW())
+FROM user_permissions p;
+
+INSERT INTO configuration_history (table_name, entity_id, owner_id, row_data, operation, valid_from)
+SELECT 'tenants', tenant_id, tenant_id, to_jsonb(t), 'I', COALESCE(updated_at, created_at, NOW())
+FROM tenants t;
+
+INSERT INTO configuration_history (table_name, entity_id, owner_id, row_data, operation, valid_from)
+SELECT 'tenant_configurations', config_id, tenant_id, to_jsonb(c), 'I', COALESCE(updated_at, created_at, NOW())
+FROM tenant_configurations c;
+
+COMMENT ON TABLE configuration_history IS 'Point-in-time history of users, permissions and tenant settings for investigations';
@@ -0,0 +1,6 @@
+-- Migration: Audit Trail Filter Indexes
+-- Version: 1.5.0
+-- Description: Indexes backing the action and IP filters on audit trail queries
+
+CREATE INDEX idx_audit_logs_tenant_action_timestamp ON audit_logs(tenant_id, action, timestamp DESC);
+CREATE INDEX idx_audit_logs_tenant_ip_timestamp ON audit_logs(tenant_id, ip_address, timestamp DESC) WHERE ip_address IS NOT NULL;
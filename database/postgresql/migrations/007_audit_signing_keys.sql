@@ -0,0 +1,65 @@
+-- Migration: Audit Signing Keys
+-- Version: 1.6.0
+-- Description: Signing key lifecycle and bulk re-signing after key compromise
+
+-- ===========================================
+-- SIGNING KEYS
+-- ===========================================
+
+CREATE TABLE audit_signing_keys (
+    key_id VARCHAR(64) PRIMARY KEY,
+    status VARCHAR(20) NOT NULL DEFAULT 'ACTIVE',
+    activated_at TIMESTAMPTZ DEFAULT NOW(),
+    retired_at TIMESTAMPTZ,
+    compromised_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_signing_key_status CHECK (status IN ('ACTIVE', 'RETIRED', 'COMPROMISED'))
+);
+
+-- ===========================================
+-- RE-SIGNING RUNS
+-- ===========================================
+
+CREATE TABLE audit_resign_runs (
+    run_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    compromised_key_id VARCHAR(64) NOT NULL REFERENCES audit_signing_keys(key_id),
+    new_key_id VARCHAR(64) NOT NULL REFERENCES audit_signing_keys(key_id),
+    tenant_id UUID REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    rationale TEXT NOT NULL,
+    requested_by UUID REFERENCES users(user_id),
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    events_resigned BIGINT NOT NULL DEFAULT 0,
+    events_skipped BIGINT NOT NULL DEFAULT 0,
+    error TEXT,
+    started_at TIMESTAMPTZ DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_resign_run_status CHECK (status IN ('RUNNING', 'COMPLETED', 'FAILED')),
+    CONSTRAINT chk_resign_rationale CHECK (length(rationale) >= 10)
+);
+
+-- Superseded signatures are kept so the original signing can still be shown
+CREATE TABLE audit_signature_history (
+    history_id BIGSERIAL PRIMARY KEY,
+    event_id UUID NOT NULL,
+    run_id UUID NOT NULL REFERENCES audit_resign_runs(run_id),
+    key_id VARCHAR(64),
+    signature TEXT,
+    event_hash TEXT NOT NULL,
+    superseded_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Events whose payload no longer matched their hash are never re-signed
+CREATE TABLE audit_resign_skips (
+    run_id UUID NOT NULL REFERENCES audit_resign_runs(run_id),
+    event_id UUID NOT NULL,
+    reason TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ DEFAULT NOW(),
+
+    PRIMARY KEY (run_id, event_id)
+);
+
+CREATE INDEX idx_signature_history_event ON audit_signature_history(event_id, superseded_at DESC);
+
+COMMENT ON TABLE audit_resign_runs IS 'Bulk re-signing of audit events after a signing key compromise';
+COMMENT ON TABLE audit_signature_history IS 'Signatures replaced during re-signing, preserved for evidence';
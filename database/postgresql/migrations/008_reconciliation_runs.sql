@@ -0,0 +1,27 @@
+-- Migration: Cross-Store Reconciliation Runs
+-- Version: 1.7.0
+-- Description: Nightly reconciliation of audit rows, MongoDB documents, anchors and case references
+
+CREATE TABLE reconciliation_runs (
+    run_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    reconciled_date DATE NOT NULL,
+    triggered_by VARCHAR(20) NOT NULL DEFAULT 'SCHEDULED',
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    postgres_count BIGINT,
+    mongodb_count BIGINT,
+    postgres_checksum VARCHAR(64),
+    mongodb_checksum VARCHAR(64),
+    discrepancy_count INTEGER NOT NULL DEFAULT 0,
+    discrepancies JSONB NOT NULL DEFAULT '[]',
+    error TEXT,
+    started_at TIMESTAMPTZ DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_reconciliation_trigger CHECK (triggered_by IN ('SCHEDULED', 'MANUAL')),
+    CONSTRAINT chk_reconciliation_status CHECK (status IN ('RUNNING', 'CLEAN', 'DISCREPANCIES', 'FAILED'))
+);
+
+CREATE INDEX idx_reconciliation_runs_date ON reconciliation_runs(reconciled_date DESC, started_at DESC);
+CREATE INDEX idx_system_events_type_time ON system_events(event_type, timestamp DESC);
+
+COMMENT ON TABLE reconciliation_runs IS 'Cross-store consistency checks; discrepancies are also raised in system_events';
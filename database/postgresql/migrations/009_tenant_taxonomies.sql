@@ -0,0 +1,55 @@
+-- Migration: Tenant Severity Scales and Category Taxonomies
+-- Version: 1.8.0
+-- Description: Tenant-defined alert/violation severities and categories mapped to platform defaults
+
+-- ===========================================
+-- TENANT SEVERITY SCALES
+-- ===========================================
+
+CREATE TABLE tenant_severity_levels (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    code VARCHAR(50) NOT NULL,
+    label VARCHAR(100) NOT NULL,
+    rank INTEGER NOT NULL,
+    platform_severity alert_severity NOT NULL,
+    -- Compliance score penalty per alert at this level; NULL uses the platform default
+    score_weight DECIMAL(6,2),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    PRIMARY KEY (tenant_id, code),
+    UNIQUE (tenant_id, rank),
+    CONSTRAINT chk_severity_score_weight CHECK (score_weight IS NULL OR score_weight >= 0)
+);
+
+-- ===========================================
+-- TENANT CATEGORY TAXONOMIES
+-- ===========================================
+
+CREATE TABLE tenant_categories (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    code VARCHAR(50) NOT NULL,
+    label VARCHAR(100) NOT NULL,
+    parent_code VARCHAR(50),
+    platform_category VARCHAR(50) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    PRIMARY KEY (tenant_id, code),
+    FOREIGN KEY (tenant_id, parent_code) REFERENCES tenant_categories(tenant_id, code) DEFERRABLE INITIALLY DEFERRED
+);
+
+-- Tenant codes recorded alongside the mapped platform severity
+ALTER TABLE surveillance_alerts
+    ADD COLUMN tenant_severity VARCHAR(50),
+    ADD COLUMN tenant_category VARCHAR(50);
+
+ALTER TABLE compliance_violations
+    ADD COLUMN tenant_severity VARCHAR(50),
+    ADD COLUMN tenant_category VARCHAR(50);
+
+CREATE INDEX idx_alerts_tenant_severity ON surveillance_alerts(tenant_id, tenant_severity) WHERE tenant_severity IS NOT NULL;
+CREATE INDEX idx_violations_tenant_category ON compliance_violations(tenant_id, tenant_category) WHERE tenant_category IS NOT NULL;
+
+COMMENT ON TABLE tenant_severity_levels IS 'Tenant severity scales; platform_severity drives platform reporting and scoring';
+COMMENT ON TABLE tenant_categories IS 'Tenant category taxonomies mapped to platform categories for reporting';
@@ -0,0 +1,69 @@
+-- Migration: End-of-Day Exchange File Ingestion
+-- Version: 1.9.0
+-- Description: Ingestion run reports, row-level rejects and EOD margin statements
+
+CREATE TABLE ingestion_runs (
+    run_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    source_uri TEXT NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    file_sha256 VARCHAR(64) NOT NULL,
+    exchange VARCHAR(10),
+    file_kind VARCHAR(20),
+    business_date DATE,
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    rows_total INTEGER NOT NULL DEFAULT 0,
+    rows_loaded INTEGER NOT NULL DEFAULT 0,
+    rows_skipped INTEGER NOT NULL DEFAULT 0,
+    rows_rejected INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    started_at TIMESTAMPTZ DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_ingestion_exchange CHECK (exchange IS NULL OR exchange IN ('NSE', 'BSE')),
+    CONSTRAINT chk_ingestion_file_kind CHECK (file_kind IS NULL OR file_kind IN ('TRADES', 'POSITIONS', 'MARGIN')),
+    CONSTRAINT chk_ingestion_status CHECK (status IN ('RUNNING', 'COMPLETED', 'PARTIAL', 'REJECTED', 'FAILED', 'DUPLICATE'))
+);
+
+-- A file is loaded at most once per tenant; failed attempts may be retried
+CREATE UNIQUE INDEX idx_ingestion_runs_loaded_file ON ingestion_runs(tenant_id, file_sha256)
+    WHERE status IN ('RUNNING', 'COMPLETED', 'PARTIAL');
+CREATE INDEX idx_ingestion_runs_tenant_time ON ingestion_runs(tenant_id, started_at DESC);
+
+CREATE TABLE ingestion_rejects (
+    reject_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    run_id UUID NOT NULL REFERENCES ingestion_runs(run_id) ON DELETE CASCADE,
+    line_number INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    raw_record TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_ingestion_rejects_run ON ingestion_rejects(run_id, line_number);
+
+-- Margin obligations reported by the exchange/clearing corporation per account and day
+CREATE TABLE margin_statements (
+    statement_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    account_id UUID NOT NULL REFERENCES trading_accounts(account_id),
+    exchange VARCHAR(10) NOT NULL,
+    business_date DATE NOT NULL,
+    initial_margin DECIMAL(25,8) NOT NULL DEFAULT 0,
+    exposure_margin DECIMAL(25,8) NOT NULL DEFAULT 0,
+    total_margin DECIMAL(25,8) NOT NULL,
+    collateral_available DECIMAL(25,8) NOT NULL DEFAULT 0,
+    shortfall DECIMAL(25,8) GENERATED ALWAYS AS (GREATEST(total_margin - collateral_available, 0)) STORED,
+    ingestion_run_id UUID REFERENCES ingestion_runs(run_id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    UNIQUE(account_id, exchange, business_date)
+);
+
+CREATE INDEX idx_margin_statements_shortfall ON margin_statements(tenant_id, business_date DESC) WHERE shortfall > 0;
+
+-- Trade files are re-sent on corrections; trade numbers identify a trade per exchange and day
+CREATE INDEX idx_trades_exchange_number ON trades(tenant_id, exchange, trade_number);
+
+COMMENT ON TABLE ingestion_runs IS 'One row per end-of-day exchange file picked up from the ingestion inbox';
+COMMENT ON TABLE margin_statements IS 'End-of-day margin obligations loaded from exchange margin files';
@@ -0,0 +1,46 @@
+-- Migration: Audit Event Schema Registry
+-- Version: 1.10.0
+-- Description: JSON Schemas for audit event payloads per resource type and action, with per-tenant enforcement
+
+CREATE TABLE audit_event_schemas (
+    schema_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    resource_type VARCHAR(50) NOT NULL,
+    -- NULL applies to every action on the resource type without a more specific schema
+    action VARCHAR(100),
+    version INTEGER NOT NULL,
+    schema JSONB NOT NULL,
+    description TEXT,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID REFERENCES users(user_id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    deactivated_at TIMESTAMPTZ
+);
+
+-- Versions are immutable; registering a schema adds a version and retires the previous one
+CREATE UNIQUE INDEX idx_audit_event_schemas_version ON audit_event_schemas(resource_type, COALESCE(action, ''), version);
+CREATE UNIQUE INDEX idx_audit_event_schemas_active ON audit_event_schemas(resource_type, COALESCE(action, ''))
+    WHERE is_active;
+
+CREATE TABLE audit_schema_enforcement (
+    tenant_id UUID PRIMARY KEY REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    mode VARCHAR(20) NOT NULL DEFAULT 'LENIENT',
+    updated_by UUID REFERENCES users(user_id),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT chk_audit_schema_mode CHECK (mode IN ('STRICT', 'LENIENT'))
+);
+
+-- Events accepted in lenient mode despite failing validation
+CREATE TABLE audit_schema_violations (
+    violation_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    event_id UUID NOT NULL,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    schema_id UUID NOT NULL REFERENCES audit_event_schemas(schema_id),
+    errors JSONB NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_schema_violations_tenant ON audit_schema_violations(tenant_id, created_at DESC);
+
+COMMENT ON TABLE audit_event_schemas IS 'JSON Schemas validating {old_values, new_values} of incoming audit events';
+COMMENT ON TABLE audit_schema_enforcement IS 'Per-tenant schema mode: STRICT rejects invalid events, LENIENT records them';
@@ -0,0 +1,83 @@
+-- Migration: Audit Retention and Cold Storage Archival
+-- Version: 1.11.0
+-- Description: Per-tenant retention rules, archived event ranges in object storage and investigation restores
+
+CREATE TABLE audit_retention_rules (
+    rule_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    -- NULL covers every resource type without a more specific rule
+    resource_type VARCHAR(50),
+    -- Days events stay in Postgres/MongoDB before moving to cold storage
+    online_days INTEGER NOT NULL,
+    -- Days events must be kept in total; archives are object-locked until then
+    retention_days INTEGER NOT NULL,
+    regulatory_reference VARCHAR(100),
+    updated_by UUID REFERENCES users(user_id),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT chk_retention_days CHECK (online_days > 0 AND retention_days >= online_days)
+);
+
+CREATE UNIQUE INDEX idx_audit_retention_rules_scope ON audit_retention_rules(tenant_id, COALESCE(resource_type, ''));
+
+-- One archive per tenant, UTC day and rule scope
+CREATE TABLE audit_archives (
+    archive_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    resource_type VARCHAR(50),
+    archived_date DATE NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+    event_count INTEGER NOT NULL DEFAULT 0,
+    object_uri TEXT,
+    content_sha256 VARCHAR(64),
+    compressed_bytes BIGINT,
+    retain_until DATE,
+    -- Kept so the MongoDB and IPFS copies can be purged (or the purge retried) after archival
+    event_ids UUID[] NOT NULL DEFAULT '{}',
+    ipfs_hashes TEXT[] NOT NULL DEFAULT '{}',
+    copies_purged BOOLEAN NOT NULL DEFAULT FALSE,
+    error TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    archived_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_audit_archive_status CHECK (status IN ('PENDING', 'ARCHIVED', 'FAILED'))
+);
+
+CREATE INDEX idx_audit_archives_tenant_date ON audit_archives(tenant_id, archived_date);
+
+CREATE TABLE audit_archive_restores (
+    restore_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    range_start DATE NOT NULL,
+    range_end DATE NOT NULL,
+    reason TEXT NOT NULL,
+    requested_by UUID REFERENCES users(user_id),
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    archives_restored INTEGER NOT NULL DEFAULT 0,
+    events_restored INTEGER NOT NULL DEFAULT 0,
+    events_failed_verification INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    expires_at TIMESTAMPTZ NOT NULL,
+    started_at TIMESTAMPTZ DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_audit_restore_range CHECK (range_start <= range_end),
+    CONSTRAINT chk_audit_restore_status CHECK (status IN ('RUNNING', 'COMPLETED', 'FAILED', 'EXPIRED'))
+);
+
+-- Restored events are served from here, read-only, until the restore expires
+CREATE TABLE audit_restored_events (
+    restore_id UUID NOT NULL REFERENCES audit_archive_restores(restore_id) ON DELETE CASCADE,
+    event_id UUID NOT NULL,
+    archive_id UUID NOT NULL REFERENCES audit_archives(archive_id),
+    timestamp TIMESTAMPTZ NOT NULL,
+    integrity_verified BOOLEAN NOT NULL,
+    event JSONB NOT NULL,
+
+    PRIMARY KEY (restore_id, event_id)
+);
+
+CREATE INDEX idx_audit_restored_events_time ON audit_restored_events(restore_id, timestamp);
+
+COMMENT ON TABLE audit_retention_rules IS 'Online and total retention of audit events; total retention floor set by AUDIT_MIN_RETENTION_DAYS';
+COMMENT ON TABLE audit_archives IS 'Compressed NDJSON archives of expired audit events in object storage';
@@ -0,0 +1,41 @@
+-- Migration: Crypto-Shredding of Personal Data in Audit Events
+-- Version: 1.12.0
+-- Description: Per-subject data keys for PII fields of audit events, and a log of DPDP erasure requests
+
+-- Data keys are stored wrapped with AUDIT_PII_MASTER_KEY. Erasing a subject
+-- destroys its wrapped key; the sealed fields stay in place, so event hashes,
+-- signatures and blockchain anchors still verify.
+CREATE TABLE audit_subject_keys (
+    key_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    subject_id UUID NOT NULL,
+    wrapped_key BYTEA,
+    master_key_id VARCHAR(50) NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    shredded_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_audit_subject_key_state CHECK ((wrapped_key IS NULL) = (shredded_at IS NOT NULL))
+);
+
+-- A subject who reappears after erasure gets a new key; shredded keys are kept as tombstones
+CREATE UNIQUE INDEX idx_audit_subject_keys_live ON audit_subject_keys(tenant_id, subject_id)
+    WHERE shredded_at IS NULL;
+
+CREATE TABLE audit_erasures (
+    erasure_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    subject_id UUID NOT NULL,
+    keys_shredded INTEGER NOT NULL,
+    requested_by UUID REFERENCES users(user_id),
+    reason TEXT NOT NULL,
+    regulatory_reference VARCHAR(100),
+    erased_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_erasures_subject ON audit_erasures(tenant_id, subject_id);
+
+-- Sealed IP addresses cannot be stored as INET
+ALTER TABLE audit_logs ADD COLUMN ip_address_sealed TEXT;
+
+COMMENT ON TABLE audit_subject_keys IS 'Wrapped per-subject data keys for PII in audit events; NULL wrapped_key means crypto-shredded';
+COMMENT ON TABLE audit_erasures IS 'DPDP right-to-erasure requests carried out by crypto-shredding';
@@ -0,0 +1,24 @@
+-- Migration: Compliance Analytics SQL Sandbox
+-- Version: 1.13.0
+-- Description: Log of every read-only SQL query run by compliance officers through the analytics sandbox
+
+CREATE TABLE analytics_query_log (
+    query_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(user_id),
+    query_text TEXT NOT NULL,
+    query_sha256 VARCHAR(64) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    row_count INTEGER,
+    truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    duration_ms INTEGER,
+    error TEXT,
+    executed_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT chk_analytics_query_status CHECK (status IN ('COMPLETED', 'REJECTED', 'FAILED', 'TIMED_OUT'))
+);
+
+CREATE INDEX idx_analytics_query_log_tenant ON analytics_query_log(tenant_id, executed_at DESC);
+CREATE INDEX idx_analytics_query_log_user ON analytics_query_log(user_id, executed_at DESC);
+
+COMMENT ON TABLE analytics_query_log IS 'Every analytics sandbox query, including rejected ones; rows are never updated';
@@ -0,0 +1,29 @@
+-- Migration: Encrypted Report Delivery
+-- Version: 1.14.0
+-- Description: Per-recipient email delivery of reports, optionally as password-protected archives with the password sent by SMS
+
+CREATE TABLE report_deliveries (
+    delivery_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    report_id UUID NOT NULL REFERENCES regulatory_reports_v2(report_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    recipient_email VARCHAR(255) NOT NULL,
+    -- Only the last digits are kept; the full number is needed just long enough to send the SMS
+    recipient_phone_suffix VARCHAR(4),
+    encrypted BOOLEAN NOT NULL,
+    attachment_name VARCHAR(255) NOT NULL,
+    attachment_sha256 VARCHAR(64) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    failure_reason TEXT,
+    password_sent_at TIMESTAMPTZ,
+    email_sent_at TIMESTAMPTZ,
+    requested_by UUID REFERENCES users(user_id),
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT chk_report_delivery_status CHECK (status IN ('DELIVERED', 'FAILED')),
+    CONSTRAINT chk_report_delivery_password CHECK (NOT encrypted OR status = 'FAILED' OR password_sent_at IS NOT NULL)
+);
+
+CREATE INDEX idx_report_deliveries_report ON report_deliveries(report_id, created_at DESC);
+CREATE INDEX idx_report_deliveries_tenant ON report_deliveries(tenant_id, created_at DESC);
+
+COMMENT ON TABLE report_deliveries IS 'One row per recipient per delivery; archive passwords are never stored';
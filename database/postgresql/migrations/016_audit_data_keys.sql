@@ -0,0 +1,26 @@
+-- Migration: Envelope Encryption of Audit Values
+-- Version: 1.15.0
+-- Description: Per-tenant data keys for encrypting old_values/new_values of audit events
+
+-- Each tenant has one ACTIVE data key for new events; retired keys stay so
+-- older events can still be decrypted. Data keys are wrapped by a master key
+-- (local keyring or KMS); rotating the master key rewraps these rows and
+-- leaves the encrypted events, and therefore their hashes, untouched.
+CREATE TABLE audit_data_keys (
+    key_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    wrapped_key BYTEA NOT NULL,
+    master_key_id VARCHAR(255) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'ACTIVE',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    retired_at TIMESTAMPTZ,
+    rewrapped_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_audit_data_key_status CHECK (status IN ('ACTIVE', 'RETIRED')),
+    CONSTRAINT chk_audit_data_key_retired CHECK ((status = 'RETIRED') = (retired_at IS NOT NULL))
+);
+
+CREATE UNIQUE INDEX idx_audit_data_keys_active ON audit_data_keys(tenant_id) WHERE status = 'ACTIVE';
+CREATE INDEX idx_audit_data_keys_master ON audit_data_keys(master_key_id);
+
+COMMENT ON TABLE audit_data_keys IS 'Wrapped AES-256 data keys for audit value encryption; rows are never deleted';
@@ -0,0 +1,75 @@
+-- Migration: Notification De-duplication and Digests
+-- Version: 1.16.0
+-- Description: Notifications with de-duplication windows, per-user digest preferences and digest history
+
+CREATE TABLE notifications (
+    notification_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    channel VARCHAR(20) NOT NULL,
+    priority VARCHAR(20) NOT NULL,
+    category VARCHAR(100) NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    -- Identical notifications collapsed into this one, including itself
+    occurrence_count INTEGER NOT NULL DEFAULT 1,
+    status VARCHAR(20) NOT NULL,
+    digest_id UUID,
+    failure_reason TEXT,
+    first_occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_notification_channel CHECK (channel IN ('EMAIL', 'IN_APP')),
+    CONSTRAINT chk_notification_priority CHECK (priority IN ('LOW', 'MEDIUM', 'HIGH', 'CRITICAL')),
+    CONSTRAINT chk_notification_status CHECK (status IN ('PENDING', 'PENDING_DIGEST', 'DELIVERED', 'DIGESTED', 'FAILED'))
+);
+
+CREATE INDEX idx_notifications_user ON notifications(tenant_id, user_id, last_occurred_at DESC);
+CREATE INDEX idx_notifications_pending_digest ON notifications(user_id, channel) WHERE status = 'PENDING_DIGEST';
+
+-- One row per fingerprint; the window restarts on the first occurrence after it ends
+CREATE TABLE notification_dedup (
+    fingerprint VARCHAR(64) PRIMARY KEY,
+    notification_id UUID NOT NULL REFERENCES notifications(notification_id) ON DELETE CASCADE
+        DEFERRABLE INITIALLY DEFERRED,
+    window_ends_at TIMESTAMPTZ NOT NULL,
+    suppressed INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX idx_notification_dedup_window ON notification_dedup(window_ends_at);
+
+CREATE TABLE notification_preferences (
+    user_id UUID PRIMARY KEY REFERENCES users(user_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    digest_frequency VARCHAR(20) NOT NULL DEFAULT 'OFF',
+    -- Notifications at or below this priority wait for the digest; CRITICAL is never batched
+    digest_max_priority VARCHAR(20) NOT NULL DEFAULT 'LOW',
+    dedup_window_secs INTEGER NOT NULL DEFAULT 900,
+    next_digest_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT chk_notification_digest_frequency CHECK (digest_frequency IN ('OFF', 'HOURLY', 'DAILY')),
+    CONSTRAINT chk_notification_digest_priority CHECK (digest_max_priority IN ('LOW', 'MEDIUM', 'HIGH')),
+    CONSTRAINT chk_notification_dedup_window CHECK (dedup_window_secs BETWEEN 0 AND 86400)
+);
+
+CREATE INDEX idx_notification_preferences_due ON notification_preferences(next_digest_at)
+    WHERE digest_frequency <> 'OFF';
+
+CREATE TABLE notification_digests (
+    digest_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    channel VARCHAR(20) NOT NULL,
+    notification_count INTEGER NOT NULL,
+    occurrence_count INTEGER NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    failure_reason TEXT,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT chk_notification_digest_status CHECK (status IN ('SENT', 'FAILED'))
+);
+
+CREATE INDEX idx_notification_digests_user ON notification_digests(user_id, created_at DESC);
@@ -0,0 +1,34 @@
+-- Migration: Scheduled Integrity Checks
+-- Version: 1.17.0
+-- Description: Sampled and full re-verification sweeps of stored audit events
+
+-- One row per sweep. failures lists each failing event with the checks it
+-- failed, capped at the first 500; events_failed is the full count. A
+-- RUNNING sweep whose progress has stalled for an hour is taken to be
+-- abandoned by a restarted replica and is failed by the next one.
+CREATE TABLE integrity_checks (
+    check_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    mode VARCHAR(20) NOT NULL,
+    triggered_by VARCHAR(20) NOT NULL DEFAULT 'SCHEDULED',
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    sample_size INTEGER,
+    events_checked BIGINT NOT NULL DEFAULT 0,
+    events_failed BIGINT NOT NULL DEFAULT 0,
+    failures JSONB NOT NULL DEFAULT '[]',
+    webhook_delivered_at TIMESTAMPTZ,
+    error TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    progressed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_integrity_check_mode CHECK (mode IN ('SAMPLE', 'FULL')),
+    CONSTRAINT chk_integrity_check_trigger CHECK (triggered_by IN ('SCHEDULED', 'MANUAL')),
+    CONSTRAINT chk_integrity_check_status CHECK (status IN ('RUNNING', 'PASSED', 'DISCREPANCIES', 'FAILED')),
+    CONSTRAINT chk_integrity_check_sample CHECK ((mode = 'SAMPLE') = (sample_size IS NOT NULL))
+);
+
+CREATE INDEX idx_integrity_checks_started ON integrity_checks(started_at DESC);
+-- At most one sweep of each mode at a time
+CREATE UNIQUE INDEX idx_integrity_checks_running ON integrity_checks(mode) WHERE status = 'RUNNING';
+
+COMMENT ON TABLE integrity_checks IS 'Audit event re-verification sweeps; discrepancies are also raised in system_events';
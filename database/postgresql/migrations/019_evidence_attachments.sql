@@ -0,0 +1,50 @@
+-- Migration: Case and Violation Evidence
+-- Version: 1.18.0
+-- Description: Evidence uploads with extracted text, full-text indexed for case and violation search
+
+-- Each attachment belongs to exactly one violation or one case
+-- (alert_investigations). The file itself lives in the evidence store under
+-- storage_key; extracted_text is what the PDF text layer or OCR produced.
+CREATE TABLE evidence_attachments (
+    attachment_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    violation_id UUID REFERENCES compliance_violations(violation_id) ON DELETE CASCADE,
+    investigation_id UUID REFERENCES alert_investigations(investigation_id) ON DELETE CASCADE,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    byte_size BIGINT NOT NULL,
+    sha256 VARCHAR(64) NOT NULL,
+    storage_key TEXT NOT NULL,
+    uploaded_by UUID REFERENCES users(user_id),
+    extraction_status VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+    extraction_method VARCHAR(50),
+    extracted_text TEXT,
+    text_search TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('english'::regconfig, file_name), 'B')
+        || to_tsvector('english'::regconfig, COALESCE(extracted_text, ''))
+    ) STORED,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    claimed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    extracted_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_evidence_target CHECK ((violation_id IS NULL) <> (investigation_id IS NULL)),
+    CONSTRAINT chk_evidence_extraction_status
+        CHECK (extraction_status IN ('PENDING', 'PROCESSING', 'EXTRACTED', 'FAILED', 'UNSUPPORTED'))
+);
+
+CREATE INDEX idx_evidence_violation ON evidence_attachments(violation_id) WHERE violation_id IS NOT NULL;
+CREATE INDEX idx_evidence_investigation ON evidence_attachments(investigation_id) WHERE investigation_id IS NOT NULL;
+CREATE INDEX idx_evidence_queue ON evidence_attachments(next_attempt_at)
+    WHERE extraction_status IN ('PENDING', 'PROCESSING');
+CREATE INDEX idx_evidence_text_search ON evidence_attachments USING GIN (text_search);
+
+-- The records' own text is searched alongside their evidence
+CREATE INDEX idx_violations_text_search ON compliance_violations
+    USING GIN (to_tsvector('english'::regconfig, violation_type || ' ' || description));
+CREATE INDEX idx_investigations_text_search ON alert_investigations
+    USING GIN (to_tsvector('english'::regconfig, COALESCE(findings, '') || ' ' || COALESCE(recommendations, '')));
+
+COMMENT ON TABLE evidence_attachments IS 'Evidence files on violations and cases, with OCR/PDF text for search';
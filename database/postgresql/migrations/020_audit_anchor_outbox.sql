@@ -0,0 +1,32 @@
+-- Migration: Audit Anchoring Outbox
+-- Version: 1.19.0
+-- Description: Durable retries of IPFS pinning and blockchain anchoring that failed at write time
+
+-- Written in the same transaction as the audit row whenever a step fails, so
+-- an event is never left unpinned or unanchored without a record of it. The
+-- worker retries with exponential backoff and writes the CID or transaction
+-- hash back to the MongoDB document once the step succeeds; rows that keep
+-- failing become DEAD and raise an ops alert.
+CREATE TABLE audit_anchor_outbox (
+    outbox_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    event_id UUID NOT NULL REFERENCES audit_logs(log_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL,
+    operation VARCHAR(30) NOT NULL,
+    event_hash VARCHAR(64) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    result TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_anchor_outbox_operation CHECK (operation IN ('IPFS_PIN', 'BLOCKCHAIN_ANCHOR')),
+    CONSTRAINT chk_anchor_outbox_status CHECK (status IN ('PENDING', 'DONE', 'DEAD')),
+    CONSTRAINT uq_anchor_outbox_event_operation UNIQUE (event_id, operation)
+);
+
+CREATE INDEX idx_anchor_outbox_due ON audit_anchor_outbox(next_attempt_at) WHERE status = 'PENDING';
+CREATE INDEX idx_anchor_outbox_dead ON audit_anchor_outbox(created_at DESC) WHERE status = 'DEAD';
+
+COMMENT ON TABLE audit_anchor_outbox IS 'IPFS pins and blockchain anchors still owed to audit events';
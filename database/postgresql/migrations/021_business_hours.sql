@@ -0,0 +1,47 @@
+-- Migration: Tenant Business Hours
+-- Version: 1.20.0
+-- Description: Per-tenant market sessions and holidays for SLA timers, off-hours detection and report scheduling
+
+-- Sessions are a JSON array of {"weekday": "Mon", "open": "09:15:00", "close": "15:30:00"}
+-- in the calendar's time zone. Tenants without a row follow NSE equity market
+-- hours (09:15-15:30 Asia/Kolkata, Monday to Friday).
+CREATE TABLE tenant_business_calendars (
+    tenant_id UUID PRIMARY KEY REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    timezone VARCHAR(64) NOT NULL DEFAULT 'Asia/Kolkata',
+    sessions JSONB NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_business_calendar_sessions CHECK (jsonb_typeof(sessions) = 'array')
+);
+
+-- Exchange holidays; the whole local date is closed
+CREATE TABLE tenant_holidays (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    holiday_date DATE NOT NULL,
+    description VARCHAR(200),
+    PRIMARY KEY (tenant_id, holiday_date)
+);
+
+-- Resolution deadline, counted in business time from creation
+ALTER TABLE compliance_violations ADD COLUMN sla_due_at TIMESTAMPTZ;
+
+CREATE INDEX idx_violations_open_sla ON compliance_violations(tenant_id, sla_due_at) WHERE resolved_at IS NULL;
+
+-- One scheduled report per tenant, report type and business day, however many
+-- reporting replicas run the scheduler
+CREATE TABLE scheduled_report_runs (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_type VARCHAR(50) NOT NULL,
+    business_date DATE NOT NULL,
+    report_id UUID,
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    PRIMARY KEY (tenant_id, report_type, business_date),
+
+    CONSTRAINT chk_scheduled_report_status CHECK (status IN ('RUNNING', 'COMPLETED', 'FAILED'))
+);
+
+COMMENT ON TABLE tenant_business_calendars IS 'Weekly market sessions per tenant, in the exchange time zone';
+COMMENT ON TABLE scheduled_report_runs IS 'Reports generated after a tenant business day closed';
@@ -0,0 +1,33 @@
+-- Migration: IPFS Pin Tracking
+-- Version: 1.21.0
+-- Description: Pin lifecycle of audit documents on the local IPFS node and a remote pinning service
+
+-- One row per CID and provider: LOCAL is the service's own IPFS node, any
+-- other provider is a remote pinning service (IPFS Pinning Service API).
+-- Remote pins start QUEUED and are requested by the verification job, which
+-- also re-pins LOCAL documents that went missing from the node. Rows become
+-- UNPINNED when retention archives the event.
+CREATE TABLE ipfs_pins (
+    pin_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    cid VARCHAR(100) NOT NULL,
+    tenant_id UUID NOT NULL,
+    event_id UUID,
+    provider VARCHAR(50) NOT NULL,
+    status VARCHAR(20) NOT NULL,
+    remote_request_id VARCHAR(100),
+    size_bytes BIGINT,
+    pinned_at TIMESTAMPTZ,
+    last_verified_at TIMESTAMPTZ,
+    repin_count INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    unpinned_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_ipfs_pin_status CHECK (status IN ('QUEUED', 'PINNING', 'PINNED', 'FAILED', 'UNPINNED')),
+    CONSTRAINT uq_ipfs_pin_cid_provider UNIQUE (cid, provider)
+);
+
+CREATE INDEX idx_ipfs_pins_verification ON ipfs_pins(provider, last_verified_at NULLS FIRST) WHERE status <> 'UNPINNED';
+CREATE INDEX idx_ipfs_pins_event ON ipfs_pins(event_id);
+
+COMMENT ON TABLE ipfs_pins IS 'Where each audit document CID is pinned and when that was last verified';
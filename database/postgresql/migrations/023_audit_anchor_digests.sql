@@ -0,0 +1,31 @@
+-- Migration: Daily Audit Anchor Digests
+-- Version: 1.22.0
+-- Description: One on-chain anchor per UTC day covering every event hash of that day, with Merkle inclusion proofs
+
+-- The Merkle root over the day's event hashes, in leaf order, and the chain
+-- transaction that anchored it. Leaves are H(0x00 || event_hash) and nodes
+-- H(0x01 || left || right) with SHA-256; an unpaired node is promoted to the
+-- next level unchanged.
+CREATE TABLE audit_anchor_digests (
+    digest_date DATE PRIMARY KEY,
+    status VARCHAR(20) NOT NULL DEFAULT 'BUILDING',
+    event_count INTEGER NOT NULL DEFAULT 0,
+    merkle_root VARCHAR(64),
+    transaction_hash VARCHAR(100),
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    anchored_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_anchor_digest_status CHECK (status IN ('BUILDING', 'ANCHORED', 'FAILED'))
+);
+
+-- Event hashes in leaf order, kept so proofs never depend on re-reading MongoDB
+CREATE TABLE audit_anchor_digest_leaves (
+    digest_date DATE NOT NULL REFERENCES audit_anchor_digests(digest_date) ON DELETE CASCADE,
+    leaf_index INTEGER NOT NULL,
+    event_id UUID NOT NULL UNIQUE,
+    event_hash VARCHAR(64) NOT NULL,
+    PRIMARY KEY (digest_date, leaf_index)
+);
+
+COMMENT ON TABLE audit_anchor_digests IS 'Daily Merkle roots of audit event hashes anchored on chain';
@@ -0,0 +1,56 @@
+-- Migration: Break-Glass Access
+-- Version: 1.23.0
+-- Description: Dual-control, time-boxed emergency SuperAdmin access to a tenant with an audit bundle per window
+
+-- A request stays PENDING until two SuperAdmins other than the requester
+-- approve it, then is ACTIVE until expires_at. Requests that are never
+-- approved or ACTIVE windows that run out become EXPIRED; a window closed
+-- early is REVOKED. Once a window has ended, every action the requester
+-- took during it is collected into break_glass_bundles.
+CREATE TABLE break_glass_grants (
+    grant_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    requested_by UUID NOT NULL REFERENCES users(user_id),
+    reason TEXT NOT NULL,
+    duration_minutes INTEGER NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    request_expires_at TIMESTAMPTZ NOT NULL,
+    activated_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+    closed_at TIMESTAMPTZ,
+    closed_by UUID REFERENCES users(user_id),
+    close_reason TEXT,
+
+    CONSTRAINT chk_break_glass_status CHECK (status IN ('PENDING', 'ACTIVE', 'REJECTED', 'EXPIRED', 'REVOKED')),
+    CONSTRAINT chk_break_glass_duration CHECK (duration_minutes > 0)
+);
+
+-- One open request or window per SuperAdmin and tenant
+CREATE UNIQUE INDEX uq_break_glass_open ON break_glass_grants(tenant_id, requested_by) WHERE status IN ('PENDING', 'ACTIVE');
+CREATE INDEX idx_break_glass_tenant ON break_glass_grants(tenant_id, requested_at DESC);
+CREATE INDEX idx_break_glass_sweep ON break_glass_grants(status, expires_at);
+
+CREATE TABLE break_glass_approvals (
+    grant_id UUID NOT NULL REFERENCES break_glass_grants(grant_id) ON DELETE CASCADE,
+    approver_id UUID NOT NULL REFERENCES users(user_id),
+    decision VARCHAR(10) NOT NULL,
+    comment TEXT,
+    decided_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (grant_id, approver_id),
+    CONSTRAINT chk_break_glass_decision CHECK (decision IN ('APPROVE', 'REJECT'))
+);
+
+-- bundle holds the grant, its approvals and the requester's audit_logs rows
+-- for the window; content_hash is the SHA-256 of that document.
+CREATE TABLE break_glass_bundles (
+    grant_id UUID PRIMARY KEY REFERENCES break_glass_grants(grant_id) ON DELETE CASCADE,
+    action_count INTEGER NOT NULL,
+    bundle JSONB NOT NULL,
+    content_hash VARCHAR(64) NOT NULL,
+    generated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE break_glass_grants IS 'Emergency SuperAdmin access to a tenant, approved by two other SuperAdmins';
+COMMENT ON TABLE break_glass_bundles IS 'Audit bundle of everything done during a break-glass window';
@@ -0,0 +1,44 @@
+-- Migration: Tenant Data Takeout
+-- Version: 1.24.0
+-- Description: Asynchronous full exports of a tenant's data, delivered through the report delivery channels
+
+-- Exports are QUEUED by the API and built by the reporting service worker.
+-- delivery holds the pending recipients (with full phone numbers for the
+-- archive password SMS) only until the first delivery attempt. The archive is
+-- kept for re-delivery until expires_at, then dropped and the export EXPIRED.
+CREATE TABLE tenant_exports (
+    export_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    requested_by UUID REFERENCES users(user_id),
+    format VARCHAR(10) NOT NULL,
+    sections TEXT[] NOT NULL,
+    period_start DATE,
+    period_end DATE,
+    status VARCHAR(20) NOT NULL DEFAULT 'QUEUED',
+    delivery JSONB,
+    manifest JSONB,
+    archive BYTEA,
+    archive_sha256 VARCHAR(64),
+    archive_bytes BIGINT,
+    error TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    claimed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_tenant_export_format CHECK (format IN ('JSON', 'CSV')),
+    CONSTRAINT chk_tenant_export_status CHECK (status IN ('QUEUED', 'RUNNING', 'COMPLETED', 'FAILED', 'EXPIRED')),
+    CONSTRAINT chk_tenant_export_period CHECK (period_start IS NULL OR period_end IS NULL OR period_start <= period_end)
+);
+
+CREATE INDEX idx_tenant_exports_tenant ON tenant_exports(tenant_id, created_at DESC);
+CREATE INDEX idx_tenant_exports_queue ON tenant_exports(status, created_at) WHERE status IN ('QUEUED', 'RUNNING');
+
+-- Deliveries now belong to a report or an export
+ALTER TABLE report_deliveries ALTER COLUMN report_id DROP NOT NULL;
+ALTER TABLE report_deliveries ADD COLUMN export_id UUID REFERENCES tenant_exports(export_id) ON DELETE CASCADE;
+ALTER TABLE report_deliveries ADD CONSTRAINT chk_report_delivery_source CHECK ((report_id IS NULL) <> (export_id IS NULL));
+CREATE INDEX idx_report_deliveries_export ON report_deliveries(export_id, created_at DESC) WHERE export_id IS NOT NULL;
+
+COMMENT ON TABLE tenant_exports IS 'Tenant takeout exports of users, violations, cases, reports and audit summaries';
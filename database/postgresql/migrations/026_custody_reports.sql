@@ -0,0 +1,26 @@
+-- Migration: Chain-of-Custody Reports
+-- Version: 1.25.0
+-- Description: Register of signed chain-of-custody PDFs generated for audited resources
+
+-- statement is the exact JSON that was hashed and signed, so a recipient of
+-- the PDF can have any copy checked against this register. pdf_signature is
+-- a detached Ed25519 signature of the PDF bytes themselves.
+CREATE TABLE custody_reports (
+    report_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    resource_type VARCHAR(100) NOT NULL,
+    resource_id UUID NOT NULL,
+    event_count INTEGER NOT NULL,
+    all_verified BOOLEAN NOT NULL,
+    statement JSONB NOT NULL,
+    statement_hash CHAR(64) NOT NULL,
+    statement_signature TEXT NOT NULL,
+    pdf_hash CHAR(64) NOT NULL,
+    pdf_signature TEXT NOT NULL,
+    signing_key_id VARCHAR(100) NOT NULL,
+    requested_by UUID,
+    generated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_custody_reports_resource ON custody_reports(tenant_id, resource_type, resource_id, generated_at DESC);
+CREATE UNIQUE INDEX idx_custody_reports_pdf_hash ON custody_reports(pdf_hash);
@@ -0,0 +1,35 @@
+-- Migration: Multi-Leg and Basket Trades
+-- Version: 1.26.0
+-- Description: Strategy leg and basket identifiers on trades, with cross-leg surveillance patterns
+
+-- A strategy execution is the set of trades sharing (tenant_id, exchange,
+-- strategy_id) on one business day; leg_count is the number of legs the
+-- exchange reported for it, so incomplete executions can be told apart.
+ALTER TABLE trades
+    ADD COLUMN strategy_id VARCHAR(50),
+    ADD COLUMN strategy_type VARCHAR(30),
+    ADD COLUMN leg_number SMALLINT,
+    ADD COLUMN leg_count SMALLINT,
+    ADD COLUMN basket_id VARCHAR(50),
+    ADD CONSTRAINT chk_trade_legs CHECK (
+        (strategy_id IS NOT NULL OR (leg_number IS NULL AND leg_count IS NULL))
+        AND (leg_number IS NULL OR leg_number > 0)
+        AND (leg_count IS NULL OR leg_count > 0)
+        AND (leg_number IS NULL OR leg_count IS NULL OR leg_number <= leg_count)
+    );
+
+CREATE INDEX idx_trades_strategy ON trades(tenant_id, strategy_id, trade_time) WHERE strategy_id IS NOT NULL;
+CREATE INDEX idx_trades_basket ON trades(tenant_id, basket_id, trade_time) WHERE basket_id IS NOT NULL;
+
+INSERT INTO surveillance_patterns (pattern_name, description, algorithm_type, parameters, threshold_config) VALUES
+('cross_leg_wash',
+ 'Legs of one strategy, or of strategies in related accounts, that offset each other in the same contract with no change in beneficial ownership',
+ 'MULTI_LEG',
+ '{"lookback_minutes": 15, "price_tolerance_percent": 0.5, "quantity_tolerance_percent": 5}',
+ '{"min_offset_value": 100000}'),
+('basket_round_trip',
+ 'Basket executions reversed leg for leg by a later basket from the same or related accounts',
+ 'MULTI_LEG',
+ '{"lookback_minutes": 60, "min_matched_leg_ratio": 0.8}',
+ '{"min_basket_value": 500000}')
+ON CONFLICT (pattern_name) DO NOTHING;
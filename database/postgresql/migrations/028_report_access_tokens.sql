@@ -0,0 +1,54 @@
+-- Migration: Report Recipient Access Tokens
+-- Version: 1.27.0
+-- Description: Expiring portal tokens granting external recipients download access to chosen reports, with an access log
+
+-- Only the SHA-256 of the token is stored; the token itself is shown once when issued.
+CREATE TABLE report_access_tokens (
+    token_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    token_hash CHAR(64) NOT NULL,
+    recipient_name VARCHAR(255) NOT NULL,
+    recipient_email VARCHAR(255),
+    -- e.g. the audit firm or exchange inspection team
+    recipient_organisation VARCHAR(255),
+    purpose TEXT,
+    report_ids UUID[] NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    max_downloads INTEGER,
+    download_count INTEGER NOT NULL DEFAULT 0,
+    last_used_at TIMESTAMPTZ,
+    created_by UUID REFERENCES users(user_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ,
+    revoked_by UUID REFERENCES users(user_id),
+    revocation_reason TEXT,
+
+    CONSTRAINT chk_report_access_scope CHECK (cardinality(report_ids) > 0),
+    CONSTRAINT chk_report_access_downloads CHECK (max_downloads IS NULL OR max_downloads > 0),
+    CONSTRAINT chk_report_access_expiry CHECK (expires_at > created_at)
+);
+
+CREATE UNIQUE INDEX idx_report_access_tokens_hash ON report_access_tokens(token_hash);
+CREATE INDEX idx_report_access_tokens_tenant ON report_access_tokens(tenant_id, created_at DESC);
+
+-- Every use of a token, including refused ones
+CREATE TABLE report_access_log (
+    access_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    token_id UUID NOT NULL REFERENCES report_access_tokens(token_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_id UUID,
+    action VARCHAR(20) NOT NULL,
+    outcome VARCHAR(20) NOT NULL,
+    reason TEXT,
+    ip_address INET,
+    user_agent TEXT,
+    accessed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_report_access_action CHECK (action IN ('LIST', 'DOWNLOAD')),
+    CONSTRAINT chk_report_access_outcome CHECK (outcome IN ('GRANTED', 'DENIED'))
+);
+
+CREATE INDEX idx_report_access_log_token ON report_access_log(token_id, accessed_at DESC);
+CREATE INDEX idx_report_access_log_tenant ON report_access_log(tenant_id, accessed_at DESC);
+
+COMMENT ON TABLE report_access_tokens IS 'Scope-limited report download tokens for external parties such as auditors and exchange inspectors';
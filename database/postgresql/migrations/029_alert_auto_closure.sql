@@ -0,0 +1,118 @@
+-- Migration: Alert Auto-Closure Rules
+-- Version: 1.28.0
+-- Description: Tenant policies that close inactive alerts with a disposition, and a record of every alert they closed
+
+-- How an alert was finally disposed of, e.g. "expired" for auto-closed alerts
+ALTER TABLE surveillance_alerts
+    ADD COLUMN disposition VARCHAR(50),
+    ADD COLUMN auto_closed_by_rule UUID;
+
+-- Latest touch on an alert: its own updates, its investigations, and
+-- evidence attached to them
+CREATE OR REPLACE FUNCTION alert_last_activity_at(p_alert_id UUID)
+RETURNS TIMESTAMPTZ AS $$
+    SELECT GREATEST(
+        a.created_at,
+        a.updated_at,
+        a.escalated_at,
+        (SELECT MAX(GREATEST(i.created_at, i.updated_at, i.started_at, i.completed_at))
+         FROM alert_investigations i WHERE i.alert_id = a.alert_id),
+        (SELECT MAX(e.created_at)
+         FROM evidence_attachments e
+         JOIN alert_investigations i ON i.investigation_id = e.investigation_id
+         WHERE i.alert_id = a.alert_id)
+    )
+    FROM surveillance_alerts a
+    WHERE a.alert_id = p_alert_id;
+$$ LANGUAGE sql STABLE;
+
+-- A rule matches alerts in one of `statuses` with no activity for
+-- `inactive_days`. Empty filter arrays match every alert; severities are
+-- platform levels, tenant_severities the tenant's own codes.
+CREATE TABLE alert_closure_rules (
+    rule_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    description TEXT,
+    severities alert_severity[] NOT NULL DEFAULT '{}',
+    tenant_severities VARCHAR(50)[] NOT NULL DEFAULT '{}',
+    alert_types VARCHAR(100)[] NOT NULL DEFAULT '{}',
+    statuses alert_status[] NOT NULL DEFAULT '{OPEN}',
+    inactive_days INTEGER NOT NULL,
+    -- Alerts scoring above this are never auto-closed
+    max_risk_score DECIMAL(5,2),
+    close_status alert_status NOT NULL DEFAULT 'RESOLVED',
+    disposition VARCHAR(50) NOT NULL DEFAULT 'expired',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID REFERENCES users(user_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (tenant_id, name),
+    CONSTRAINT chk_closure_inactive_days CHECK (inactive_days BETWEEN 1 AND 3650),
+    CONSTRAINT chk_closure_statuses CHECK (cardinality(statuses) > 0 AND NOT statuses && '{RESOLVED,FALSE_POSITIVE}'),
+    CONSTRAINT chk_closure_close_status CHECK (close_status IN ('RESOLVED', 'FALSE_POSITIVE')),
+    CONSTRAINT chk_closure_risk_score CHECK (max_risk_score IS NULL OR max_risk_score BETWEEN 0 AND 100)
+);
+
+CREATE INDEX idx_alert_closure_rules_enabled ON alert_closure_rules(tenant_id) WHERE enabled;
+
+-- Alerts a rule would close now, shared by rule previews and the closure job
+CREATE OR REPLACE FUNCTION alert_closure_candidates(p_rule_id UUID)
+RETURNS TABLE (alert_id UUID, last_activity_at TIMESTAMPTZ) AS $$
+    SELECT a.alert_id, activity.last_activity_at
+    FROM alert_closure_rules r
+    JOIN surveillance_alerts a ON a.tenant_id = r.tenant_id
+    CROSS JOIN LATERAL (SELECT alert_last_activity_at(a.alert_id) AS last_activity_at) activity
+    WHERE r.rule_id = p_rule_id
+      AND a.status = ANY(r.statuses)
+      AND (cardinality(r.severities) = 0 OR a.severity = ANY(r.severities))
+      AND (cardinality(r.tenant_severities) = 0 OR a.tenant_severity = ANY(r.tenant_severities))
+      AND (cardinality(r.alert_types) = 0 OR a.alert_type = ANY(r.alert_types))
+      AND (r.max_risk_score IS NULL OR a.risk_score <= r.max_risk_score)
+      -- Cheap bound before the full activity lookup
+      AND COALESCE(a.updated_at, a.created_at) < NOW() - make_interval(days => r.inactive_days)
+      AND activity.last_activity_at < NOW() - make_interval(days => r.inactive_days)
+$$ LANGUAGE sql STABLE;
+
+ALTER TABLE surveillance_alerts
+    ADD CONSTRAINT fk_alert_auto_closed_by_rule
+    FOREIGN KEY (auto_closed_by_rule) REFERENCES alert_closure_rules(rule_id) ON DELETE SET NULL;
+
+-- One row per pass of the closure job
+CREATE TABLE alert_closure_runs (
+    run_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    -- SCHEDULED, or MANUAL when started through the API
+    triggered_by VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    rules_evaluated INTEGER NOT NULL DEFAULT 0,
+    alerts_closed INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_closure_run_trigger CHECK (triggered_by IN ('SCHEDULED', 'MANUAL')),
+    CONSTRAINT chk_closure_run_status CHECK (status IN ('RUNNING', 'COMPLETED', 'FAILED'))
+);
+
+-- Every alert closed by a rule, with the rule as it stood at the time
+CREATE TABLE alert_auto_closures (
+    closure_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    run_id UUID NOT NULL REFERENCES alert_closure_runs(run_id),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    alert_id UUID NOT NULL REFERENCES surveillance_alerts(alert_id) ON DELETE CASCADE,
+    rule_id UUID REFERENCES alert_closure_rules(rule_id) ON DELETE SET NULL,
+    rule_snapshot JSONB NOT NULL,
+    previous_status alert_status NOT NULL,
+    close_status alert_status NOT NULL,
+    disposition VARCHAR(50) NOT NULL,
+    last_activity_at TIMESTAMPTZ NOT NULL,
+    reason TEXT NOT NULL,
+    closed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_alert_auto_closures_tenant ON alert_auto_closures(tenant_id, closed_at DESC);
+CREATE INDEX idx_alert_auto_closures_rule ON alert_auto_closures(rule_id, closed_at DESC);
+CREATE INDEX idx_alert_auto_closures_alert ON alert_auto_closures(alert_id);
+
+COMMENT ON TABLE alert_auto_closures IS 'Audit of alerts closed by tenant auto-closure rules, kept even if the rule is deleted';
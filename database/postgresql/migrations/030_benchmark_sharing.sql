@@ -0,0 +1,35 @@
+-- Migration: Benchmark Sharing
+-- Version: 1.29.0
+-- Description: Tenant consent to differentially private industry benchmarks, and the benchmarks released
+
+-- A tenant contributes to benchmarks while withdrawn_at is NULL
+CREATE TABLE benchmark_consents (
+    tenant_id UUID PRIMARY KEY REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    consented_by UUID REFERENCES users(user_id),
+    opted_in_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    withdrawn_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_benchmark_consents_active ON benchmark_consents(tenant_id) WHERE withdrawn_at IS NULL;
+
+-- Each metric and month is released once with noise already added, so
+-- repeated queries return the same number instead of fresh noise to average
+CREATE TABLE benchmark_releases (
+    release_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    metric VARCHAR(50) NOT NULL,
+    period_month DATE NOT NULL,
+    value DOUBLE PRECISION NOT NULL,
+    -- Exact count for operators; participants only see it rounded down to a band
+    contributors INTEGER NOT NULL,
+    epsilon DOUBLE PRECISION NOT NULL,
+    clip_upper DOUBLE PRECISION NOT NULL,
+    released_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (metric, period_month),
+    CONSTRAINT chk_benchmark_metric CHECK (metric IN ('ALERT_RATE', 'RESOLUTION_HOURS')),
+    CONSTRAINT chk_benchmark_month CHECK (period_month = date_trunc('month', period_month)::date),
+    CONSTRAINT chk_benchmark_epsilon CHECK (epsilon > 0)
+);
+
+COMMENT ON TABLE benchmark_releases IS 'Noise-added cross-tenant benchmarks; no per-tenant values are stored';
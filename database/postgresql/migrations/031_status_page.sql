@@ -0,0 +1,39 @@
+-- Migration: Operations Status Page
+-- Version: 1.30.0
+-- Description: Readiness probe history behind the status page's availability SLOs, and operator-posted incident banners
+
+-- One row per probe of a service's /ready; kept for the SLO window plus a margin
+CREATE TABLE status_probes (
+    probe_id BIGSERIAL PRIMARY KEY,
+    component VARCHAR(50) NOT NULL,
+    up BOOLEAN NOT NULL,
+    latency_ms INTEGER,
+    checked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_status_probes_component ON status_probes(component, checked_at DESC);
+CREATE INDEX idx_status_probes_checked ON status_probes(checked_at);
+
+-- Banners shown on the public status page; the text is published as written
+CREATE TABLE status_incidents (
+    incident_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    title VARCHAR(200) NOT NULL,
+    message TEXT NOT NULL,
+    -- NONE for notices such as planned maintenance
+    impact VARCHAR(20) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'INVESTIGATING',
+    components TEXT[] NOT NULL DEFAULT '{}',
+    posted_by VARCHAR(100),
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_status_incident_impact CHECK (impact IN ('NONE', 'DEGRADED', 'OUTAGE')),
+    CONSTRAINT chk_status_incident_status CHECK (status IN ('INVESTIGATING', 'IDENTIFIED', 'MONITORING', 'RESOLVED')),
+    CONSTRAINT chk_status_incident_resolved CHECK ((status = 'RESOLVED') = (resolved_at IS NOT NULL))
+);
+
+CREATE INDEX idx_status_incidents_recent ON status_incidents(COALESCE(resolved_at, 'infinity'::timestamptz) DESC);
+
+COMMENT ON TABLE status_probes IS 'Readiness probes of each service, the source of the status page availability figures';
+COMMENT ON TABLE status_incidents IS 'Incident and maintenance banners published on the status page';
@@ -0,0 +1,21 @@
+-- Migration: Audit Event Documents
+-- Version: 1.31.0
+-- Description: Signed audit event documents for deployments without MongoDB (AUDIT_DOCUMENT_STORE=postgres)
+
+-- The canonical copy of each event, as MongoDB's audit_events would hold it.
+-- Not tied to audit_logs: archival removes the row first and the document
+-- only once the archive is written.
+CREATE TABLE audit_event_documents (
+    event_id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    -- Copied out of the document so re-signing runs can find a key's events
+    signing_key_id TEXT,
+    document JSONB NOT NULL,
+    stored_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_audit_event_documents_timestamp ON audit_event_documents(timestamp);
+CREATE INDEX idx_audit_event_documents_signing_key ON audit_event_documents(signing_key_id, tenant_id);
+
+COMMENT ON TABLE audit_event_documents IS 'Document store for audit events when MongoDB is not deployed';
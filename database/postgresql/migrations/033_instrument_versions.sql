@@ -0,0 +1,77 @@
+-- Migration: Instrument Reference Data Versions
+-- Version: 1.32.0
+-- Description: Effective-dated instrument reference data so history resolves instruments as they were on the trade date
+
+-- One row per period an instrument's reference data held. effective_from is
+-- NULL for the version in effect since listing, effective_to is exclusive and
+-- NULL for the open-ended latest version. instruments keeps the version in
+-- effect today.
+CREATE TABLE instrument_versions (
+    version_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    instrument_id UUID NOT NULL REFERENCES instruments(instrument_id) ON DELETE CASCADE,
+    effective_from DATE,
+    effective_to DATE,
+    symbol VARCHAR(50) NOT NULL,
+    isin VARCHAR(12),
+    lot_size INTEGER NOT NULL,
+    tick_size DECIMAL(10,4) NOT NULL,
+    is_active BOOLEAN NOT NULL,
+    change_type VARCHAR(30) NOT NULL,
+    -- Prices before effective_from multiplied by this are comparable with prices after it
+    -- (0.5 for a 1:2 split); quantities are divided by it
+    adjustment_factor DECIMAL(20,10) NOT NULL DEFAULT 1,
+    -- Exchange circular or corporate action reference
+    reference VARCHAR(100),
+    notes TEXT,
+    recorded_by UUID REFERENCES users(user_id),
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    UNIQUE (instrument_id, effective_from),
+    CONSTRAINT chk_instrument_version_period CHECK (effective_to > effective_from),
+    CONSTRAINT chk_instrument_version_change CHECK (change_type IN (
+        'LISTING', 'SYMBOL_CHANGE', 'LOT_SIZE_REVISION', 'TICK_SIZE_REVISION', 'ISIN_CHANGE',
+        'SPLIT', 'BONUS', 'MERGER', 'SUSPENSION', 'DELISTING', 'RELISTING', 'CORRECTION'
+    )),
+    CONSTRAINT chk_instrument_version_lot CHECK (lot_size > 0),
+    CONSTRAINT chk_instrument_version_factor CHECK (adjustment_factor > 0)
+);
+
+CREATE UNIQUE INDEX idx_instrument_versions_listing ON instrument_versions(instrument_id) WHERE effective_from IS NULL;
+CREATE UNIQUE INDEX idx_instrument_versions_open ON instrument_versions(instrument_id) WHERE effective_to IS NULL;
+CREATE INDEX idx_instrument_versions_symbol ON instrument_versions(symbol, effective_from DESC);
+CREATE INDEX idx_instrument_versions_isin ON instrument_versions(isin, effective_from DESC) WHERE isin IS NOT NULL;
+
+-- Existing instruments are taken to have always looked as they do now
+INSERT INTO instrument_versions (instrument_id, effective_from, symbol, isin, lot_size, tick_size, is_active, change_type)
+SELECT instrument_id, NULL, symbol, isin, COALESCE(lot_size, 1), COALESCE(tick_size, 0.05),
+       COALESCE(is_active, TRUE), 'LISTING'
+FROM instruments;
+
+-- Instruments added later start with the same open-ended version
+CREATE OR REPLACE FUNCTION record_instrument_listing()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO instrument_versions (instrument_id, effective_from, symbol, isin, lot_size, tick_size, is_active, change_type)
+    VALUES (NEW.instrument_id, NULL, NEW.symbol, NEW.isin, COALESCE(NEW.lot_size, 1),
+            COALESCE(NEW.tick_size, 0.05), COALESCE(NEW.is_active, TRUE), 'LISTING');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER instruments_listing_trigger
+    AFTER INSERT ON instruments
+    FOR EACH ROW
+    EXECUTE FUNCTION record_instrument_listing();
+
+-- The version of an instrument in effect on a date, for joins from trades:
+--   LEFT JOIN LATERAL instrument_as_of(t.instrument_id, t.trade_time::date) v ON TRUE
+CREATE OR REPLACE FUNCTION instrument_as_of(p_instrument_id UUID, p_as_of DATE)
+RETURNS SETOF instrument_versions AS $$
+    SELECT *
+    FROM instrument_versions
+    WHERE instrument_id = p_instrument_id
+      AND (effective_from IS NULL OR effective_from <= p_as_of)
+      AND (effective_to IS NULL OR effective_to > p_as_of)
+$$ LANGUAGE sql STABLE;
+
+COMMENT ON TABLE instrument_versions IS 'Effective-dated instrument reference data: symbol changes, corporate actions, lot size revisions';
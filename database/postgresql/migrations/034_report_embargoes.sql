@@ -0,0 +1,55 @@
+-- Migration: Report Embargoes
+-- Version: 1.33.0
+-- Description: Embargo times before which generated reports cannot be downloaded, delivered or submitted, with a log of early-access attempts
+
+-- A report is published in two phases: it is generated under embargo, then
+-- becomes available once embargo_until passes or the embargo is lifted early.
+-- Both are judged by the database clock so every service agrees on the moment.
+CREATE TABLE report_embargoes (
+    report_id UUID PRIMARY KEY REFERENCES regulatory_reports_v2(report_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    embargo_until TIMESTAMPTZ NOT NULL,
+    reason TEXT,
+    set_by UUID REFERENCES users(user_id),
+    set_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    lifted_at TIMESTAMPTZ,
+    lifted_by UUID REFERENCES users(user_id),
+    lift_reason TEXT,
+
+    CONSTRAINT chk_report_embargo_lift CHECK (lifted_at IS NULL OR lift_reason IS NOT NULL)
+);
+
+CREATE INDEX idx_report_embargoes_tenant ON report_embargoes(tenant_id, embargo_until DESC);
+
+-- Every refused request for an embargoed report
+CREATE TABLE report_embargo_attempts (
+    attempt_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    report_id UUID NOT NULL REFERENCES regulatory_reports_v2(report_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    action VARCHAR(20) NOT NULL,
+    requested_by UUID,
+    -- Set when the request came through a recipient portal token
+    token_id UUID REFERENCES report_access_tokens(token_id) ON DELETE SET NULL,
+    ip_address INET,
+    user_agent TEXT,
+    embargo_until TIMESTAMPTZ NOT NULL,
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_report_embargo_action CHECK (action IN ('VIEW', 'DOWNLOAD', 'DELIVER', 'PORTAL_DOWNLOAD', 'SUBMIT'))
+);
+
+CREATE INDEX idx_report_embargo_attempts_report ON report_embargo_attempts(report_id, attempted_at DESC);
+CREATE INDEX idx_report_embargo_attempts_tenant ON report_embargo_attempts(tenant_id, attempted_at DESC);
+
+-- When the report becomes available, or NULL when it already is
+CREATE OR REPLACE FUNCTION report_embargoed_until(p_report_id UUID)
+RETURNS TIMESTAMPTZ AS $$
+    SELECT embargo_until
+    FROM report_embargoes
+    WHERE report_id = p_report_id
+      AND lifted_at IS NULL
+      AND embargo_until > NOW()
+$$ LANGUAGE sql STABLE;
+
+COMMENT ON TABLE report_embargoes IS 'Times before which generated reports may not be downloaded, delivered or submitted';
+COMMENT ON TABLE report_embargo_attempts IS 'Refused requests for reports still under embargo';
@@ -0,0 +1,153 @@
+-- Migration: Audit Log Partitioning
+-- Version: 1.34.0
+-- Description: Monthly range partitions of audit_logs with a registry of partitions created and dropped by the audit service
+
+-- audit_logs is rebuilt as a table partitioned by month of timestamp so trail
+-- queries with a time range only touch the months they ask for, and months
+-- emptied by cold storage archival can be dropped instead of vacuumed. Every
+-- row is copied into the new table, so run it in a maintenance window on
+-- large installations.
+ALTER TABLE audit_logs RENAME TO audit_logs_unpartitioned;
+ALTER TABLE audit_logs_unpartitioned RENAME CONSTRAINT audit_logs_pkey TO audit_logs_unpartitioned_pkey;
+
+-- A partitioned table only enforces keys that include the partition key, so
+-- the outbox can no longer reference log_id; a trigger below keeps the cascade
+ALTER TABLE audit_anchor_outbox DROP CONSTRAINT audit_anchor_outbox_event_id_fkey;
+
+DROP INDEX idx_audit_logs_tenant_timestamp;
+DROP INDEX idx_audit_logs_user_timestamp;
+DROP INDEX idx_audit_logs_resource;
+DROP INDEX idx_audit_logs_tenant_keyset;
+DROP INDEX idx_audit_logs_tenant_action_timestamp;
+DROP INDEX idx_audit_logs_tenant_ip_timestamp;
+
+CREATE TABLE audit_logs (
+    log_id UUID NOT NULL DEFAULT uuid_generate_v4(),
+    tenant_id UUID REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    user_id UUID REFERENCES users(user_id),
+    session_id UUID REFERENCES user_sessions(session_id),
+    action VARCHAR(100) NOT NULL,
+    resource_type VARCHAR(50) NOT NULL,
+    resource_id UUID,
+    old_values JSONB,
+    new_values JSONB,
+    ip_address INET,
+    user_agent TEXT,
+    request_id UUID,
+    api_endpoint VARCHAR(255),
+    http_method VARCHAR(10),
+    response_status INTEGER,
+    execution_time_ms INTEGER,
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ip_address_sealed TEXT,
+
+    PRIMARY KEY (log_id, timestamp),
+    CONSTRAINT chk_http_method CHECK (http_method IN ('GET', 'POST', 'PUT', 'PATCH', 'DELETE'))
+) PARTITION BY RANGE (timestamp);
+
+-- Catches rows outside every monthly partition; the audit service keeps it empty
+CREATE TABLE audit_logs_default PARTITION OF audit_logs DEFAULT;
+
+CREATE INDEX idx_audit_logs_tenant_timestamp ON audit_logs(tenant_id, timestamp DESC);
+CREATE INDEX idx_audit_logs_user_timestamp ON audit_logs(user_id, timestamp DESC) WHERE user_id IS NOT NULL;
+CREATE INDEX idx_audit_logs_resource ON audit_logs(resource_type, resource_id);
+CREATE INDEX idx_audit_logs_tenant_keyset ON audit_logs(tenant_id, timestamp DESC, log_id DESC);
+CREATE INDEX idx_audit_logs_tenant_action_timestamp ON audit_logs(tenant_id, action, timestamp DESC);
+CREATE INDEX idx_audit_logs_tenant_ip_timestamp ON audit_logs(tenant_id, ip_address, timestamp DESC) WHERE ip_address IS NOT NULL;
+
+ALTER TABLE audit_logs ENABLE ROW LEVEL SECURITY;
+
+-- Every monthly partition the audit service has created, and what became of it
+CREATE TABLE audit_log_partitions (
+    partition_name TEXT PRIMARY KEY,
+    range_start TIMESTAMPTZ NOT NULL UNIQUE,
+    range_end TIMESTAMPTZ NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'ATTACHED',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    dropped_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_audit_log_partition_status CHECK (status IN ('ATTACHED', 'DROPPED')),
+    CONSTRAINT chk_audit_log_partition_range CHECK (range_end > range_start)
+);
+
+-- Create the partition holding the UTC month of p_month; FALSE when it already exists
+CREATE OR REPLACE FUNCTION audit_logs_create_partition(p_month DATE)
+RETURNS BOOLEAN AS $$
+DECLARE
+    v_start TIMESTAMPTZ := date_trunc('month', p_month::timestamp) AT TIME ZONE 'UTC';
+    v_end TIMESTAMPTZ := (date_trunc('month', p_month::timestamp) + INTERVAL '1 month') AT TIME ZONE 'UTC';
+    v_name TEXT := 'audit_logs_' || to_char(p_month, 'YYYY_MM');
+BEGIN
+    IF EXISTS (SELECT 1 FROM audit_log_partitions WHERE range_start = v_start) THEN
+        RETURN FALSE;
+    END IF;
+    EXECUTE format(
+        'CREATE TABLE %I PARTITION OF audit_logs FOR VALUES FROM (%L) TO (%L)',
+        v_name, v_start, v_end
+    );
+    INSERT INTO audit_log_partitions (partition_name, range_start, range_end)
+    VALUES (v_name, v_start, v_end);
+    RETURN TRUE;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Create the current month's partition and the next p_months_ahead; returns how many were new
+CREATE OR REPLACE FUNCTION audit_logs_ensure_partitions(p_months_ahead INTEGER)
+RETURNS INTEGER AS $$
+DECLARE
+    v_created INTEGER := 0;
+    v_month DATE;
+BEGIN
+    FOR v_month IN
+        SELECT generate_series(
+            date_trunc('month', NOW() AT TIME ZONE 'UTC'),
+            date_trunc('month', NOW() AT TIME ZONE 'UTC') + make_interval(months => p_months_ahead),
+            INTERVAL '1 month'
+        )::date
+    LOOP
+        IF audit_logs_create_partition(v_month) THEN
+            v_created := v_created + 1;
+        END IF;
+    END LOOP;
+    RETURN v_created;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Partitions for every month that already has events, then the months ahead
+SELECT audit_logs_create_partition(month::date)
+FROM (
+    SELECT DISTINCT date_trunc('month', timestamp AT TIME ZONE 'UTC') AS month
+    FROM audit_logs_unpartitioned
+    WHERE timestamp IS NOT NULL
+) months
+ORDER BY month;
+SELECT audit_logs_ensure_partitions(3);
+
+-- The partition key cannot be NULL; such rows never had a time recorded, so they keep the copy time
+INSERT INTO audit_logs (
+    log_id, tenant_id, user_id, session_id, action, resource_type, resource_id, old_values, new_values,
+    ip_address, user_agent, request_id, api_endpoint, http_method, response_status, execution_time_ms,
+    timestamp, ip_address_sealed
+)
+SELECT log_id, tenant_id, user_id, session_id, action, resource_type, resource_id, old_values, new_values,
+       ip_address, user_agent, request_id, api_endpoint, http_method, response_status, execution_time_ms,
+       COALESCE(timestamp, NOW()), ip_address_sealed
+FROM audit_logs_unpartitioned;
+
+DROP TABLE audit_logs_unpartitioned;
+
+-- Replaces ON DELETE CASCADE of audit_anchor_outbox.event_id
+CREATE OR REPLACE FUNCTION audit_logs_delete_outbox()
+RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM audit_anchor_outbox WHERE event_id = OLD.log_id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_audit_logs_delete_outbox
+    AFTER DELETE ON audit_logs
+    FOR EACH ROW EXECUTE FUNCTION audit_logs_delete_outbox();
+
+COMMENT ON TABLE audit_logs IS 'Complete audit trail for compliance and security, partitioned by month';
+COMMENT ON TABLE audit_log_partitions IS 'Monthly audit_logs partitions created, and dropped once archival emptied them';
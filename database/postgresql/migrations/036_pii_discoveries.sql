@@ -0,0 +1,48 @@
+-- Migration: PII Discovery
+-- Version: 1.35.0
+-- Description: Untagged personal data found by sampling audit values and report payloads, and per-tenant fields sealed as a result
+
+-- One row per tenant, source, field and kind of personal data; the scanner
+-- adds to match_count on every run that finds it again. Values themselves
+-- are never stored, only a masked example.
+CREATE TABLE pii_discoveries (
+    discovery_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    source VARCHAR(20) NOT NULL,
+    -- Dotted path inside the payload, with [] for array elements
+    field_path TEXT NOT NULL,
+    -- The key sealed when the finding is enforced
+    field_name VARCHAR(100) NOT NULL,
+    pattern VARCHAR(20) NOT NULL,
+    match_count BIGINT NOT NULL DEFAULT 0,
+    -- log_id or report_id of the latest match
+    sample_ref UUID,
+    masked_example TEXT,
+    status VARCHAR(20) NOT NULL DEFAULT 'OPEN',
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_by UUID REFERENCES users(user_id),
+    resolved_at TIMESTAMPTZ,
+    resolution_note TEXT,
+
+    CONSTRAINT chk_pii_discovery_source CHECK (source IN ('AUDIT_VALUES', 'REPORT_DATA')),
+    CONSTRAINT chk_pii_discovery_pattern CHECK (pattern IN ('PAN', 'AADHAAR', 'PHONE')),
+    CONSTRAINT chk_pii_discovery_status CHECK (status IN ('OPEN', 'ENFORCED', 'DISMISSED')),
+    CONSTRAINT uq_pii_discovery UNIQUE (tenant_id, source, field_path, pattern)
+);
+
+CREATE INDEX idx_pii_discoveries_tenant_status ON pii_discoveries(tenant_id, status, last_seen_at DESC);
+
+-- Keys sealed for the tenant in addition to AUDIT_PII_FIELDS
+CREATE TABLE audit_pii_tenant_fields (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    field_name VARCHAR(100) NOT NULL,
+    discovery_id UUID REFERENCES pii_discoveries(discovery_id) ON DELETE SET NULL,
+    added_by UUID REFERENCES users(user_id),
+    added_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (tenant_id, field_name)
+);
+
+COMMENT ON TABLE pii_discoveries IS 'Untagged PAN, Aadhaar and phone numbers found in audit values and report payloads';
+COMMENT ON TABLE audit_pii_tenant_fields IS 'Per-tenant keys sealed for crypto-shredding on top of AUDIT_PII_FIELDS';
@@ -0,0 +1,9 @@
+-- Migration: Audit Actor Snapshots
+-- Version: 1.36.0
+-- Description: Username, role and tenant name of the acting user as resolved from the user service when an event is written
+
+-- NULL for events without a user, events written before enrichment was
+-- enabled, and users the user service could not resolve at the time
+ALTER TABLE audit_logs ADD COLUMN actor_snapshot JSONB;
+
+COMMENT ON COLUMN audit_logs.actor_snapshot IS 'Acting user as known when the event was written; kept after the user is renamed or deleted';
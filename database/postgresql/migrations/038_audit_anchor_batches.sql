@@ -0,0 +1,30 @@
+-- Migration: Audit Anchor Batches
+-- Version: 1.37.0
+-- Description: Blockchain anchors shared by a batch of outbox entries under one Merkle root, with the gas paid for each
+
+-- The outbox anchors the hashes it has queued as leaves of one Merkle tree,
+-- built the same way as the daily digests, and pays for one transaction
+CREATE TABLE audit_anchor_batches (
+    batch_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    merkle_root VARCHAR(64) NOT NULL,
+    transaction_hash TEXT NOT NULL,
+    event_count INTEGER NOT NULL,
+    -- Due anchor entries when the batch was planned
+    queue_depth INTEGER NOT NULL,
+    gas_price_gwei DOUBLE PRECISION,
+    cost_gwei DOUBLE PRECISION,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_anchor_batch_size CHECK (event_count > 0)
+);
+
+CREATE INDEX idx_anchor_batches_created ON audit_anchor_batches(created_at DESC);
+
+-- Leaves are ordered by leaf_index, so an entry's path to the root can be rebuilt
+ALTER TABLE audit_anchor_outbox
+    ADD COLUMN batch_id UUID REFERENCES audit_anchor_batches(batch_id),
+    ADD COLUMN leaf_index INTEGER;
+
+CREATE INDEX idx_anchor_outbox_batch ON audit_anchor_outbox(batch_id, leaf_index) WHERE batch_id IS NOT NULL;
+
+COMMENT ON TABLE audit_anchor_batches IS 'Merkle roots anchored for batches of deferred audit event anchors';
@@ -0,0 +1,29 @@
+-- Migration: Audit Event Attachments
+-- Version: 1.38.0
+-- Description: Evidence files attached to audit events, stored in IPFS and optionally encrypted under the tenant's data key
+
+-- event_id cannot reference audit_logs, whose primary key includes the
+-- partition key; attachments are removed with their event's tenant instead
+CREATE TABLE audit_event_attachments (
+    attachment_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    event_id UUID NOT NULL,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    -- SHA-256 of the file as uploaded
+    sha256 VARCHAR(64) NOT NULL,
+    -- CID of the stored blob, which is ciphertext when data_key_id is set
+    cid TEXT NOT NULL,
+    data_key_id UUID REFERENCES audit_data_keys(key_id),
+    description TEXT,
+    uploaded_by UUID REFERENCES users(user_id),
+    uploaded_by_service VARCHAR(100),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_attachment_size CHECK (size_bytes > 0)
+);
+
+CREATE INDEX idx_attachments_event ON audit_event_attachments(event_id, created_at);
+
+COMMENT ON TABLE audit_event_attachments IS 'Screenshots, emails and files attached to audit events as investigation evidence';
@@ -0,0 +1,41 @@
+-- Migration: Operator Runbook Jobs
+-- Version: 1.39.0
+-- Description: Recovery runbooks run through the audit service's admin API as confirmed, tracked jobs
+
+CREATE TABLE operator_runbook_jobs (
+    job_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    runbook VARCHAR(40) NOT NULL,
+    tenant_id UUID REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    target_date DATE,
+    reason TEXT NOT NULL,
+    status VARCHAR(30) NOT NULL DEFAULT 'AWAITING_CONFIRMATION',
+    -- What the job would touch, as counted when it was requested
+    preview JSONB NOT NULL DEFAULT '{}',
+    -- SHA-256 of the one-time confirmation token; the token itself is only returned once
+    confirmation_hash VARCHAR(64) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    requested_by UUID REFERENCES users(user_id),
+    requested_by_service VARCHAR(100),
+    confirmed_by UUID REFERENCES users(user_id),
+    confirmed_by_service VARCHAR(100),
+    items_processed BIGINT NOT NULL DEFAULT 0,
+    items_failed BIGINT NOT NULL DEFAULT 0,
+    result JSONB NOT NULL DEFAULT '{}',
+    error TEXT,
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    started_at TIMESTAMPTZ,
+    progressed_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_runbook CHECK (runbook IN ('audit_queue_reprocess', 'projection_rebuild', 'webhook_redelivery')),
+    CONSTRAINT chk_runbook_job_status CHECK (
+        status IN ('AWAITING_CONFIRMATION', 'RUNNING', 'COMPLETED', 'FAILED', 'EXPIRED')
+    ),
+    CONSTRAINT chk_runbook_reason CHECK (length(reason) >= 10)
+);
+
+-- A runbook never runs twice at once
+CREATE UNIQUE INDEX idx_runbook_jobs_running ON operator_runbook_jobs(runbook) WHERE status = 'RUNNING';
+CREATE INDEX idx_runbook_jobs_requested ON operator_runbook_jobs(requested_at DESC);
+
+COMMENT ON TABLE operator_runbook_jobs IS 'Recovery runbooks requested and confirmed through the admin API instead of psql';
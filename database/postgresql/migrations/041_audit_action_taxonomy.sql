@@ -0,0 +1,70 @@
+-- Migration: Audit Action Taxonomy
+-- Version: 1.40.0
+-- Description: Managed namespace.verb audit actions, aliases normalized on ingest, and prefix queries on action
+
+-- Lowercase dot-separated segments; everything before the last dot is the namespace
+CREATE TABLE audit_actions (
+    action VARCHAR(100) PRIMARY KEY,
+    namespace VARCHAR(100) GENERATED ALWAYS AS (regexp_replace(action, '\.[^.]+$', '')) STORED,
+    description TEXT,
+    created_by UUID REFERENCES users(user_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Deprecated actions are kept for the events that carry them but no longer accepted as known
+    deprecated_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_audit_action_format CHECK (action ~ '^[a-z][a-z0-9_]*(\.[a-z][a-z0-9_]*)+$')
+);
+
+-- Free-form spellings producers send, rewritten to their action when events are created
+CREATE TABLE audit_action_aliases (
+    -- Compared case-insensitively; stored lowercase
+    alias VARCHAR(100) PRIMARY KEY,
+    action VARCHAR(100) NOT NULL REFERENCES audit_actions(action) ON DELETE CASCADE,
+    created_by UUID REFERENCES users(user_id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_audit_action_alias_lowercase CHECK (alias = lower(alias))
+);
+
+CREATE INDEX idx_audit_actions_namespace ON audit_actions(namespace);
+CREATE INDEX idx_audit_action_aliases_action ON audit_action_aliases(action);
+
+-- Serves action LIKE 'trade.%' within a tenant
+CREATE INDEX idx_audit_logs_tenant_action_prefix ON audit_logs(tenant_id, action text_pattern_ops);
+
+-- Actions the platform's own services record
+INSERT INTO audit_actions (action, description) VALUES
+    ('audit.attachment.add', 'Evidence file attached to an audit event'),
+    ('privacy.pii_field.enforce', 'Field sealed as personal data for a tenant'),
+    ('privacy.personal_data.erase', 'Personal data of a subject crypto-shredded'),
+    ('analytics.query.execute', 'Analytics query run over surveillance data'),
+    ('alert.auto_close', 'Alert closed by an auto-closure rule'),
+    ('user.create', 'User account created'),
+    ('user.update', 'User account changed'),
+    ('user.delete', 'User account removed'),
+    ('user.login', 'User signed in'),
+    ('user.logout', 'User signed out'),
+    ('trade.execute', 'Trade executed'),
+    ('trade.modify', 'Trade or order modified'),
+    ('trade.cancel', 'Trade or order cancelled');
+
+INSERT INTO audit_action_aliases (alias, action) VALUES
+    ('attachment_added', 'audit.attachment.add'),
+    ('pii_field_enforced', 'privacy.pii_field.enforce'),
+    ('personal_data_erased', 'privacy.personal_data.erase'),
+    ('analytics_query_executed', 'analytics.query.execute'),
+    ('alert_auto_closed', 'alert.auto_close'),
+    ('create_user', 'user.create'),
+    ('user_created', 'user.create'),
+    ('update_user', 'user.update'),
+    ('user_updated', 'user.update'),
+    ('delete_user', 'user.delete'),
+    ('user_deleted', 'user.delete'),
+    ('login', 'user.login'),
+    ('logout', 'user.logout'),
+    ('trade_executed', 'trade.execute'),
+    ('trade_modified', 'trade.modify'),
+    ('trade_cancelled', 'trade.cancel');
+
+COMMENT ON TABLE audit_actions IS 'Managed hierarchy of audit event actions';
+COMMENT ON TABLE audit_action_aliases IS 'Producer spellings of audit actions, normalized on ingest';
@@ -0,0 +1,16 @@
+-- Migration: Pending Anchor Mode
+-- Version: 1.41.0
+-- Description: Digests built without chain access wait as PENDING_ANCHOR, and the anchor_backfill runbook anchors them later
+
+ALTER TABLE audit_anchor_digests DROP CONSTRAINT chk_anchor_digest_status;
+ALTER TABLE audit_anchor_digests ADD CONSTRAINT chk_anchor_digest_status
+    CHECK (status IN ('BUILDING', 'PENDING_ANCHOR', 'ANCHORED', 'FAILED'));
+
+-- Digests whose root is fixed but not yet on chain
+CREATE INDEX idx_anchor_digests_pending ON audit_anchor_digests(digest_date) WHERE status = 'PENDING_ANCHOR';
+
+ALTER TABLE operator_runbook_jobs DROP CONSTRAINT chk_runbook;
+ALTER TABLE operator_runbook_jobs ADD CONSTRAINT chk_runbook
+    CHECK (runbook IN ('audit_queue_reprocess', 'projection_rebuild', 'webhook_redelivery', 'anchor_backfill'));
+
+COMMENT ON COLUMN audit_anchor_digests.status IS 'BUILDING, PENDING_ANCHOR (built without chain access), ANCHORED or FAILED';
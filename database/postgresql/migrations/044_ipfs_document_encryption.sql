@@ -0,0 +1,10 @@
+-- Migration: IPFS Document Encryption
+-- Version: 1.43.0
+-- Description: Data key each IPFS copy of an audit document is encrypted under, recorded beside its CID
+
+-- NULL for copies stored in clear, before encryption was enabled or with
+-- AUDIT_IPFS_ENCRYPT=false. The key stays in audit_data_keys after the
+-- tenant rotates to a new one, so older copies keep decrypting.
+ALTER TABLE ipfs_pins ADD COLUMN data_key_id UUID REFERENCES audit_data_keys(key_id);
+
+COMMENT ON COLUMN ipfs_pins.data_key_id IS 'Tenant data key the pinned document is encrypted under; the CID is that of the ciphertext';
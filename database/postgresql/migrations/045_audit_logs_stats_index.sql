@@ -0,0 +1,10 @@
+-- Migration: Audit Statistics Index
+-- Version: 1.44.0
+-- Description: Covering index for GET /audit/stats, so its groupings and histogram are index-only scans
+
+-- Created on the partitioned table, so every monthly partition, present and
+-- future, gets its own copy
+CREATE INDEX idx_audit_logs_tenant_stats ON audit_logs(tenant_id, timestamp)
+    INCLUDE (action, user_id, resource_type);
+
+COMMENT ON INDEX idx_audit_logs_tenant_stats IS 'Counts of GET /audit/stats by action, actor, resource type and time bucket';
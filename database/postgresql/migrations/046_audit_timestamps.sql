@@ -0,0 +1,36 @@
+-- Migration: RFC 3161 Trusted Timestamps
+-- Version: 1.45.0
+-- Description: Time-stamping authority tokens over event hashes or daily digest roots, as an alternative or addition to chain anchoring
+
+-- One token per event in per_event anchor mode, or per daily digest root in
+-- daily_digest mode. `token` is the DER TimeStampToken as the authority
+-- returned it, so it can be checked without this service, for instance with
+-- `openssl ts -verify`.
+CREATE TABLE audit_timestamps (
+    timestamp_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    event_id UUID,
+    tenant_id UUID,
+    digest_date DATE REFERENCES audit_anchor_digests(digest_date) ON DELETE CASCADE,
+    -- Hex SHA-256 the token's message imprint covers
+    hashed_value VARCHAR(64) NOT NULL,
+    tsa_url TEXT NOT NULL,
+    token BYTEA NOT NULL,
+    gen_time TIMESTAMPTZ NOT NULL,
+    serial_number VARCHAR(128) NOT NULL,
+    policy VARCHAR(100) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_audit_timestamp_subject CHECK (
+        (event_id IS NOT NULL AND tenant_id IS NOT NULL AND digest_date IS NULL)
+        OR (event_id IS NULL AND tenant_id IS NULL AND digest_date IS NOT NULL)
+    )
+);
+
+CREATE UNIQUE INDEX uq_audit_timestamps_event ON audit_timestamps(event_id) WHERE event_id IS NOT NULL;
+CREATE UNIQUE INDEX uq_audit_timestamps_digest ON audit_timestamps(digest_date) WHERE digest_date IS NOT NULL;
+
+ALTER TABLE audit_anchor_outbox DROP CONSTRAINT chk_anchor_outbox_operation;
+ALTER TABLE audit_anchor_outbox ADD CONSTRAINT chk_anchor_outbox_operation
+    CHECK (operation IN ('IPFS_PIN', 'BLOCKCHAIN_ANCHOR', 'TSA_TIMESTAMP'));
+
+COMMENT ON TABLE audit_timestamps IS 'RFC 3161 timestamp tokens proving an event hash or digest root existed at gen_time';
@@ -0,0 +1,11 @@
+-- Migration: Payload Compression
+-- Version: 1.46.0
+-- Description: Codec each IPFS copy of an audit document is compressed with, recorded beside its CID
+
+-- NULL for copies stored uncompressed, under AUDIT_COMPRESSION_THRESHOLD_BYTES
+-- or before compression was enabled. Compression comes before encryption, so
+-- a copy with both a data key and a codec is decrypted and then decompressed.
+ALTER TABLE ipfs_pins ADD COLUMN codec VARCHAR(10)
+    CONSTRAINT chk_ipfs_pins_codec CHECK (codec IN ('zstd'));
+
+COMMENT ON COLUMN ipfs_pins.codec IS 'Codec the pinned document is compressed with before any encryption; NULL when it is stored uncompressed';
@@ -0,0 +1,33 @@
+-- Migration: Audit API Keys
+-- Version: 1.47.0
+-- Description: Hashed API keys internal producers present as X-Api-Key to record audit events, and the producing service of each event
+
+-- Only the SHA-256 of a key is kept; key_prefix is its first characters, for
+-- telling keys apart in listings. A rotated key keeps working until
+-- expires_at, set to the end of the grace period, and links to its successor
+-- through the successor's rotated_from.
+CREATE TABLE audit_api_keys (
+    key_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    service VARCHAR(100) NOT NULL,
+    key_prefix VARCHAR(12) NOT NULL,
+    key_hash VARCHAR(64) NOT NULL UNIQUE,
+    tenant_ids UUID[] NOT NULL,
+    created_by UUID,
+    created_by_service VARCHAR(100),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ,
+    last_used_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    rotated_from UUID REFERENCES audit_api_keys(key_id),
+
+    CONSTRAINT chk_audit_api_key_tenants CHECK (cardinality(tenant_ids) > 0)
+);
+
+CREATE INDEX idx_audit_api_keys_service ON audit_api_keys(service, created_at DESC);
+
+-- NULL for events written by users, consumed from the event bus, or written
+-- before producers were recorded
+ALTER TABLE audit_logs ADD COLUMN producer VARCHAR(100);
+
+COMMENT ON TABLE audit_api_keys IS 'API keys of internal producers, scoped to tenants and to creating audit events';
+COMMENT ON COLUMN audit_logs.producer IS 'Internal service that sent the event, from its service token or API key';
@@ -0,0 +1,92 @@
+-- Migration: Audit WORM Mode
+-- Version: 1.48.0
+-- Description: Write-once enforcement of audit_logs, with every blocked UPDATE, DELETE or archival recorded
+
+-- One row once the audit service has been started with AUDIT_WORM_MODE=enforce.
+-- It cannot be updated or deleted, so WORM mode stays on for good.
+CREATE TABLE audit_worm_state (
+    singleton BOOLEAN PRIMARY KEY DEFAULT TRUE,
+    enabled_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    enabled_by VARCHAR(100) NOT NULL,
+
+    CONSTRAINT chk_audit_worm_singleton CHECK (singleton)
+);
+
+-- One row per audit_logs row an UPDATE or DELETE tried to change, or per
+-- operation of the audit service refused in WORM mode. The audit service
+-- reports unreported rows as system events and in the tenant's own trail,
+-- grouped by transaction.
+CREATE TABLE audit_worm_violations (
+    violation_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    source VARCHAR(20) NOT NULL,
+    operation VARCHAR(30) NOT NULL,
+    transaction_id BIGINT NOT NULL DEFAULT txid_current(),
+    tenant_id UUID,
+    log_id UUID,
+    db_user VARCHAR(100) NOT NULL DEFAULT session_user,
+    application_name TEXT DEFAULT current_setting('application_name', true),
+    client_addr INET DEFAULT inet_client_addr(),
+    details JSONB NOT NULL DEFAULT '{}',
+    reported_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_audit_worm_violation_source CHECK (source IN ('DATABASE', 'APPLICATION'))
+);
+
+CREATE INDEX idx_audit_worm_violations_unreported ON audit_worm_violations(attempted_at) WHERE reported_at IS NULL;
+
+-- Leaves the row as it is and records the attempt, so the attempt is kept
+-- even though the statement carries on. SECURITY DEFINER lets roles without
+-- access to audit_worm_violations be recorded too.
+CREATE OR REPLACE FUNCTION audit_logs_worm_guard()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM audit_worm_state) THEN
+        IF TG_OP = 'DELETE' THEN
+            RETURN OLD;
+        END IF;
+        RETURN NEW;
+    END IF;
+    INSERT INTO audit_worm_violations (source, operation, tenant_id, log_id, details)
+    VALUES ('DATABASE', TG_OP, OLD.tenant_id, OLD.log_id, jsonb_build_object('query', left(current_query(), 2000)));
+    RAISE WARNING 'audit_logs is write-once: % of row % was not applied and has been recorded', TG_OP, OLD.log_id;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+-- A TRUNCATE cannot be skipped row by row, so it fails instead
+CREATE OR REPLACE FUNCTION audit_logs_worm_truncate_guard()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM audit_worm_state) THEN
+        RAISE EXCEPTION 'audit_logs is write-once and cannot be truncated' USING ERRCODE = 'insufficient_privilege';
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_audit_logs_worm
+    BEFORE UPDATE OR DELETE ON audit_logs
+    FOR EACH ROW EXECUTE FUNCTION audit_logs_worm_guard();
+
+CREATE TRIGGER trg_audit_logs_worm_truncate
+    BEFORE TRUNCATE ON audit_logs
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_logs_worm_truncate_guard();
+
+CREATE OR REPLACE FUNCTION audit_worm_state_guard()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'WORM mode cannot be turned off once enabled' USING ERRCODE = 'insufficient_privilege';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_audit_worm_state_guard
+    BEFORE UPDATE OR DELETE ON audit_worm_state
+    FOR EACH ROW EXECUTE FUNCTION audit_worm_state_guard();
+
+CREATE TRIGGER trg_audit_worm_state_truncate
+    BEFORE TRUNCATE ON audit_worm_state
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_worm_state_guard();
+
+COMMENT ON TABLE audit_worm_state IS 'Set once WORM mode is enabled; audit_logs rows can then no longer be updated or deleted';
+COMMENT ON TABLE audit_worm_violations IS 'Attempts to change or remove audit_logs rows in WORM mode, reported as FATAL system events';
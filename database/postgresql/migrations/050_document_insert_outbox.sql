@@ -0,0 +1,17 @@
+-- Migration: Document Insert Outbox
+-- Version: 1.49.0
+-- Description: Outbox entries for event documents the batched MongoDB writer could not insert
+
+-- The batched writer spills a document here when MongoDB rejects its batch or
+-- the write queue stays full. The entry carries the signed document itself,
+-- as MongoDB was the only other place it was going to be kept; the retry
+-- worker inserts it unless it has been inserted since.
+ALTER TABLE audit_anchor_outbox ADD COLUMN document JSONB;
+
+ALTER TABLE audit_anchor_outbox DROP CONSTRAINT chk_anchor_outbox_operation;
+ALTER TABLE audit_anchor_outbox ADD CONSTRAINT chk_anchor_outbox_operation
+    CHECK (operation IN ('IPFS_PIN', 'BLOCKCHAIN_ANCHOR', 'TSA_TIMESTAMP', 'DOCUMENT_INSERT'));
+ALTER TABLE audit_anchor_outbox ADD CONSTRAINT chk_anchor_outbox_document
+    CHECK (document IS NULL OR operation = 'DOCUMENT_INSERT');
+
+COMMENT ON COLUMN audit_anchor_outbox.document IS 'Signed event document of a DOCUMENT_INSERT entry';
@@ -0,0 +1,42 @@
+-- Migration: Audit Replication
+-- Version: 1.50.0
+-- Description: Per-tenant hash chains of each region's events and the queue replicating them to the peer region
+
+-- Set on every event while replication is configured. An event is linked
+-- into the chain of its tenant in the region that created it: chain_seq
+-- counts the chain from 1 and chain_hash is the SHA-256 of the previous
+-- chain_hash (64 zeros for the first event) followed by the event's hash.
+-- Replicated events keep the links their origin gave them, so both regions
+-- hold the same chains.
+ALTER TABLE audit_logs ADD COLUMN origin_region VARCHAR(50);
+ALTER TABLE audit_logs ADD COLUMN chain_seq BIGINT;
+ALTER TABLE audit_logs ADD COLUMN chain_hash VARCHAR(64);
+
+CREATE INDEX idx_audit_logs_chain ON audit_logs(tenant_id, origin_region, chain_seq) WHERE chain_seq IS NOT NULL;
+
+-- Last link of every chain held in this region, its own and the peer's.
+-- Writes lock their chain's row until they commit, which orders a tenant's
+-- events within a region.
+CREATE TABLE audit_chain_heads (
+    tenant_id UUID NOT NULL,
+    origin_region VARCHAR(50) NOT NULL,
+    seq BIGINT NOT NULL DEFAULT 0,
+    head_hash VARCHAR(64),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (tenant_id, origin_region)
+);
+
+-- Events created in this region and not yet acknowledged by the peer, in
+-- the order they were chained
+CREATE TABLE audit_replication_queue (
+    queue_id BIGSERIAL PRIMARY KEY,
+    event_id UUID NOT NULL UNIQUE,
+    queued_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_attempt_at TIMESTAMPTZ,
+    last_error TEXT
+);
+
+COMMENT ON TABLE audit_chain_heads IS 'Head of each tenant''s hash chain per origin region';
+COMMENT ON TABLE audit_replication_queue IS 'Events of this region waiting to be applied by the peer region';
@@ -0,0 +1,22 @@
+-- Migration: Report File Lifecycle
+-- Version: 1.51.0
+-- Description: Size and expiry of rendered report files in the report store
+
+-- file_path and file_hash, the store key and SHA-256 of the rendered file,
+-- are set when a report is generated. Files are kept for
+-- REPORT_FILE_RETENTION_DAYS when it is set; once a file is removed from the
+-- store, file_deleted_at records when, and file_path is kept so the row still
+-- says what was there.
+ALTER TABLE regulatory_reports_v2 ADD COLUMN file_size BIGINT;
+ALTER TABLE regulatory_reports_v2 ADD COLUMN file_expires_at TIMESTAMPTZ;
+ALTER TABLE regulatory_reports_v2 ADD COLUMN file_deleted_at TIMESTAMPTZ;
+
+ALTER TABLE regulatory_reports_v2 ADD CONSTRAINT chk_report_file_size CHECK (file_size IS NULL OR file_size >= 0);
+
+-- Files due for removal
+CREATE INDEX idx_reports_v2_file_expiry ON regulatory_reports_v2(file_expires_at)
+    WHERE file_expires_at IS NOT NULL AND file_deleted_at IS NULL;
+
+COMMENT ON COLUMN regulatory_reports_v2.file_size IS 'Bytes of the rendered file in the report store';
+COMMENT ON COLUMN regulatory_reports_v2.file_expires_at IS 'When the rendered file is removed from the report store; kept indefinitely when NULL';
+COMMENT ON COLUMN regulatory_reports_v2.file_deleted_at IS 'When the rendered file was removed from the report store';
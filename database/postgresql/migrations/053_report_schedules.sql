@@ -0,0 +1,37 @@
+-- Migration: Report Schedules
+-- Version: 1.52.0
+-- Description: Cron schedules that tenants manage for generating their own reports
+
+-- Each schedule fires on a six-field cron expression (seconds first) in its
+-- time zone and generates a report covering the period_days days before the
+-- day it fires. last_run_at doubles as the claim that stops several reporting
+-- replicas from generating the same run.
+CREATE TABLE report_schedules (
+    schedule_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    name VARCHAR(200) NOT NULL,
+    report_type VARCHAR(50) NOT NULL,
+    format VARCHAR(10) NOT NULL DEFAULT 'PDF',
+    -- An active template of the report type is used when NULL
+    template_id UUID REFERENCES report_templates(template_id),
+    cron_expression VARCHAR(120) NOT NULL,
+    timezone VARCHAR(64) NOT NULL DEFAULT 'Asia/Kolkata',
+    period_days INTEGER NOT NULL DEFAULT 1,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_run_at TIMESTAMPTZ,
+    last_status VARCHAR(20),
+    last_report_id UUID,
+    last_error TEXT,
+
+    CONSTRAINT uq_report_schedule_name UNIQUE (tenant_id, name),
+    CONSTRAINT chk_report_schedule_period CHECK (period_days BETWEEN 1 AND 366),
+    CONSTRAINT chk_report_schedule_status CHECK (last_status IS NULL OR last_status IN ('RUNNING', 'COMPLETED', 'FAILED'))
+);
+
+CREATE INDEX idx_report_schedules_active ON report_schedules(updated_at) WHERE is_active;
+
+COMMENT ON TABLE report_schedules IS 'Tenant-managed cron schedules for report generation';
+COMMENT ON COLUMN report_schedules.last_run_at IS 'When the schedule last fired; claimed by one reporting replica per run';
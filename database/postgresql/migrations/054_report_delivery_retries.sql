@@ -0,0 +1,39 @@
+-- Migration: Report Delivery Retries
+-- Version: 1.53.0
+-- Description: Retried report deliveries, download-link deliveries, and recipients of scheduled reports
+
+-- A delivery that failed for a reason that may pass, such as an SMTP server
+-- deferring the message, is RETRYING until it goes out or runs out of attempts.
+-- The recipient's full phone number is kept only while it is retried, to send a
+-- new archive password; it is cleared once the delivery is DELIVERED or FAILED.
+ALTER TABLE report_deliveries DROP CONSTRAINT chk_report_delivery_status;
+ALTER TABLE report_deliveries DROP CONSTRAINT chk_report_delivery_password;
+ALTER TABLE report_deliveries ADD CONSTRAINT chk_report_delivery_status
+    CHECK (status IN ('DELIVERED', 'RETRYING', 'FAILED'));
+ALTER TABLE report_deliveries ADD CONSTRAINT chk_report_delivery_password
+    CHECK (NOT encrypted OR status <> 'DELIVERED' OR password_sent_at IS NOT NULL);
+
+-- LINK deliveries email a report portal link, backed by a one-report access token, instead of the file
+ALTER TABLE report_deliveries ADD COLUMN method VARCHAR(20) NOT NULL DEFAULT 'ATTACHMENT';
+ALTER TABLE report_deliveries ADD COLUMN access_token_id UUID REFERENCES report_access_tokens(token_id) ON DELETE SET NULL;
+ALTER TABLE report_deliveries ADD COLUMN recipient_name VARCHAR(200);
+ALTER TABLE report_deliveries ADD COLUMN recipient_phone VARCHAR(16);
+ALTER TABLE report_deliveries ADD COLUMN message TEXT;
+ALTER TABLE report_deliveries ADD COLUMN attempts INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE report_deliveries ADD COLUMN next_attempt_at TIMESTAMPTZ;
+
+ALTER TABLE report_deliveries ADD CONSTRAINT chk_report_delivery_method CHECK (method IN ('ATTACHMENT', 'LINK'));
+ALTER TABLE report_deliveries ADD CONSTRAINT chk_report_delivery_retry
+    CHECK ((status = 'RETRYING') = (next_attempt_at IS NOT NULL));
+ALTER TABLE report_deliveries ADD CONSTRAINT chk_report_delivery_phone
+    CHECK (recipient_phone IS NULL OR status = 'RETRYING');
+
+-- Deliveries due for another attempt
+CREATE INDEX idx_report_deliveries_retry ON report_deliveries(next_attempt_at) WHERE status = 'RETRYING';
+
+-- Who a scheduled report is sent to once generated; NULL sends it to nobody
+ALTER TABLE report_schedules ADD COLUMN delivery JSONB;
+
+COMMENT ON COLUMN report_deliveries.recipient_phone IS 'Full number, kept only while the delivery is retried';
+COMMENT ON COLUMN report_deliveries.next_attempt_at IS 'When a RETRYING delivery is attempted again';
+COMMENT ON COLUMN report_schedules.delivery IS 'Recipients and delivery options for reports generated on the schedule';
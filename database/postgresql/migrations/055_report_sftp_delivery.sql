@@ -0,0 +1,59 @@
+-- Migration: Report SFTP Delivery
+-- Version: 1.54.0
+-- Description: Per-tenant SFTP targets for exchange and regulator submissions, with upload receipts
+
+-- A tenant's SFTP server, such as an exchange's member upload area. The
+-- password, or private key and its passphrase, are sealed with
+-- REPORT_SFTP_CREDENTIALS_KEY by the reporting service and never returned.
+-- The server's host key is pinned by its SHA-256 fingerprint. Files are named
+-- from remote_dir and file_name_template; see the reporting service's `sftp`
+-- module for their placeholders.
+CREATE TABLE report_sftp_targets (
+    target_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    name VARCHAR(200) NOT NULL,
+    host VARCHAR(255) NOT NULL,
+    port INTEGER NOT NULL DEFAULT 22,
+    username VARCHAR(200) NOT NULL,
+    auth_method VARCHAR(20) NOT NULL,
+    credentials_sealed BYTEA NOT NULL,
+    host_key_fingerprint VARCHAR(100) NOT NULL,
+    remote_dir TEXT NOT NULL DEFAULT '.',
+    file_name_template VARCHAR(255) NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT uq_report_sftp_target_name UNIQUE (tenant_id, name),
+    CONSTRAINT chk_report_sftp_target_port CHECK (port BETWEEN 1 AND 65535),
+    CONSTRAINT chk_report_sftp_target_auth CHECK (auth_method IN ('PASSWORD', 'PRIVATE_KEY'))
+);
+
+-- One row per upload of a report to a target. The target's address and the
+-- path are copied so the receipt still says where the file went after the
+-- target is changed or removed.
+CREATE TABLE report_sftp_deliveries (
+    delivery_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_id UUID NOT NULL REFERENCES regulatory_reports_v2(report_id) ON DELETE CASCADE,
+    target_id UUID REFERENCES report_sftp_targets(target_id) ON DELETE SET NULL,
+    host VARCHAR(255) NOT NULL,
+    port INTEGER NOT NULL,
+    username VARCHAR(200) NOT NULL,
+    remote_path TEXT NOT NULL,
+    file_sha256 VARCHAR(64),
+    file_size BIGINT,
+    status VARCHAR(20) NOT NULL DEFAULT 'UPLOADING',
+    failure_reason TEXT,
+    requested_by UUID,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_report_sftp_delivery_status CHECK (status IN ('UPLOADING', 'UPLOADED', 'FAILED')),
+    CONSTRAINT chk_report_sftp_delivery_uploaded CHECK (
+        status <> 'UPLOADED' OR (file_sha256 IS NOT NULL AND completed_at IS NOT NULL)
+    )
+);
+
+CREATE INDEX idx_report_sftp_deliveries_report ON report_sftp_deliveries (tenant_id, report_id, started_at DESC);
@@ -0,0 +1,56 @@
+-- Migration: Report Webhooks
+-- Version: 1.55.0
+-- Description: Per-tenant webhooks called back when a report is COMPLETED or FAILED, with their deliveries log
+
+-- A tenant's callback URL and the report events it wants. Bodies are signed
+-- with an HMAC-SHA256 of the secret, which is returned only when the webhook is
+-- created or its secret rotated.
+CREATE TABLE report_webhooks (
+    webhook_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret VARCHAR(128) NOT NULL,
+    events TEXT[] NOT NULL DEFAULT ARRAY['COMPLETED', 'FAILED'],
+    description TEXT,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_report_webhook_events CHECK (
+        cardinality(events) > 0 AND events <@ ARRAY['COMPLETED', 'FAILED']::TEXT[]
+    )
+);
+
+CREATE INDEX idx_report_webhooks_tenant ON report_webhooks(tenant_id) WHERE is_active;
+
+-- One row per event and webhook. The payload is fixed when the event happens
+-- and sent unchanged on every attempt; a delivery is PENDING until its first
+-- attempt, RETRYING after failed ones, and DELIVERED or FAILED once settled.
+-- A report that failed was never stored, so report_id has no foreign key.
+CREATE TABLE report_webhook_deliveries (
+    delivery_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    webhook_id UUID NOT NULL REFERENCES report_webhooks(webhook_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_id UUID,
+    event VARCHAR(20) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'PENDING',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ DEFAULT NOW(),
+    response_status INTEGER,
+    failure_reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ,
+
+    CONSTRAINT chk_report_webhook_delivery_event CHECK (event IN ('COMPLETED', 'FAILED')),
+    CONSTRAINT chk_report_webhook_delivery_status CHECK (status IN ('PENDING', 'RETRYING', 'DELIVERED', 'FAILED')),
+    CONSTRAINT chk_report_webhook_delivery_retry CHECK (
+        (status IN ('PENDING', 'RETRYING')) = (next_attempt_at IS NOT NULL)
+    )
+);
+
+-- Deliveries due for an attempt
+CREATE INDEX idx_report_webhook_deliveries_due ON report_webhook_deliveries(next_attempt_at)
+    WHERE status IN ('PENDING', 'RETRYING');
+CREATE INDEX idx_report_webhook_deliveries_webhook ON report_webhook_deliveries(webhook_id, created_at DESC);
@@ -0,0 +1,37 @@
+-- Migration: Report Layouts
+-- Version: 1.56.0
+-- Description: Versioned per-tenant overrides of the PDF layout of each report type
+
+-- Each upload is a new, unchanging version of the tenant's layout for a
+-- report type: a title, page header and disclaimer, which sections appear and
+-- in what order, a logo, and optionally its own template in place of the
+-- built-in one. At most one version is current and used for new reports; with
+-- none, reports use the built-in layout.
+CREATE TABLE report_layouts (
+    layout_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_type VARCHAR(50) NOT NULL,
+    version INTEGER NOT NULL,
+    title VARCHAR(200),
+    header TEXT,
+    disclaimer TEXT,
+    -- NULL keeps the report type's default sections
+    sections TEXT[],
+    logo BYTEA,
+    logo_content_type VARCHAR(50),
+    -- A Tera template of typst markup replacing the built-in template
+    source TEXT,
+    notes TEXT,
+    is_current BOOLEAN NOT NULL DEFAULT FALSE,
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT uq_report_layout_version UNIQUE (tenant_id, report_type, version),
+    CONSTRAINT chk_report_layout_logo CHECK ((logo IS NULL) = (logo_content_type IS NULL)),
+    CONSTRAINT chk_report_layout_logo_type CHECK (logo_content_type IN ('image/png', 'image/jpeg', 'image/svg+xml'))
+);
+
+CREATE UNIQUE INDEX uq_report_layout_current ON report_layouts(tenant_id, report_type) WHERE is_current;
+
+-- The layout version a report's PDF was rendered with; NULL for the built-in layout
+ALTER TABLE regulatory_reports_v2 ADD COLUMN layout_id UUID REFERENCES report_layouts(layout_id) ON DELETE SET NULL;
@@ -0,0 +1,11 @@
+-- Migration: Order Client Codes
+-- Version: 1.57.0
+-- Description: Client codes on orders, for order-to-trade ratios per client
+
+-- The client an order was placed for, as trades record it. Orders without one
+-- are counted under their trading account, as own-account orders.
+ALTER TABLE orders ADD COLUMN client_code VARCHAR(50);
+
+CREATE INDEX idx_orders_tenant_time ON orders(tenant_id, order_time);
+
+COMMENT ON COLUMN orders.client_code IS 'Client the order was placed for; NULL for own-account orders';
@@ -0,0 +1,33 @@
+-- Migration: Report Versions
+-- Version: 1.58.0
+-- Description: Versions of regulatory reports, with amendments superseding the version they correct
+
+-- Every report starts a chain as version 1. An amendment is the next version
+-- of the chain: it names the report it amends and the first version of the
+-- chain, and the amended report records which version superseded it and when.
+-- Superseded versions are kept, so the chain is the filing history.
+ALTER TABLE regulatory_reports_v2 ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE regulatory_reports_v2 ADD COLUMN original_report_id UUID REFERENCES regulatory_reports_v2(report_id);
+ALTER TABLE regulatory_reports_v2 ADD COLUMN amends_report_id UUID REFERENCES regulatory_reports_v2(report_id);
+ALTER TABLE regulatory_reports_v2 ADD COLUMN amendment_reason TEXT;
+ALTER TABLE regulatory_reports_v2 ADD COLUMN superseded_by UUID REFERENCES regulatory_reports_v2(report_id);
+ALTER TABLE regulatory_reports_v2 ADD COLUMN superseded_at TIMESTAMPTZ;
+
+ALTER TABLE regulatory_reports_v2 ADD CONSTRAINT chk_report_version CHECK (
+    (version = 1 AND original_report_id IS NULL AND amends_report_id IS NULL AND amendment_reason IS NULL)
+    OR (version > 1 AND original_report_id IS NOT NULL AND amends_report_id IS NOT NULL
+        AND amendment_reason IS NOT NULL)
+);
+ALTER TABLE regulatory_reports_v2 ADD CONSTRAINT chk_report_superseded
+    CHECK ((superseded_by IS NULL) = (superseded_at IS NULL));
+
+-- A version is amended at most once, so a chain never forks
+CREATE UNIQUE INDEX idx_reports_v2_amends ON regulatory_reports_v2(amends_report_id) WHERE amends_report_id IS NOT NULL;
+CREATE UNIQUE INDEX idx_reports_v2_chain_version
+    ON regulatory_reports_v2(COALESCE(original_report_id, report_id), version);
+
+COMMENT ON COLUMN regulatory_reports_v2.version IS 'Version of the report within its amendment chain, from 1';
+COMMENT ON COLUMN regulatory_reports_v2.original_report_id IS 'First version of the chain; NULL on the first version itself';
+COMMENT ON COLUMN regulatory_reports_v2.amends_report_id IS 'Version this amendment supersedes';
+COMMENT ON COLUMN regulatory_reports_v2.amendment_reason IS 'Why the previous version was amended';
+COMMENT ON COLUMN regulatory_reports_v2.superseded_by IS 'Amendment that superseded this version; NULL while it is current';
@@ -0,0 +1,18 @@
+-- Migration: Tenant Report Time Zones
+-- Version: 1.59.0
+-- Description: A time zone per tenant for the report schedules that do not name their own
+
+-- Cron expressions of report schedules are read in tenant-local time. A
+-- schedule with a NULL time zone follows its tenant's, so changing the tenant's
+-- time zone moves all of them; schedules created before keep the zone they were
+-- given.
+ALTER TABLE tenants ADD COLUMN timezone VARCHAR(64) NOT NULL DEFAULT 'Asia/Kolkata';
+
+ALTER TABLE report_schedules ALTER COLUMN timezone DROP NOT NULL;
+ALTER TABLE report_schedules ALTER COLUMN timezone DROP DEFAULT;
+
+-- The schedules a change of the tenant's time zone moves
+CREATE INDEX idx_report_schedules_tenant_zone ON report_schedules(tenant_id) WHERE timezone IS NULL;
+
+COMMENT ON COLUMN tenants.timezone IS 'IANA time zone that tenant report schedules run in unless they name their own';
+COMMENT ON COLUMN report_schedules.timezone IS 'IANA time zone of the cron expression; the tenant''s when NULL';
@@ -0,0 +1,64 @@
+-- Migration: Report Reviews
+-- Version: 1.60.0
+-- Description: Maker-checker review of regulatory reports, with every status change audited
+
+-- Reports are generated as DRAFT and approved or rejected by a reviewer other
+-- than their maker. Only approved reports leave the platform, so reports
+-- generated before the workflow go back to DRAFT for review.
+UPDATE regulatory_reports_v2 SET status = 'DRAFT' WHERE status = 'GENERATED';
+
+ALTER TABLE regulatory_reports_v2 ADD COLUMN review_comments TEXT;
+
+-- Every status a report takes, from generation on. The actor is the session's
+-- app.current_user_id, or the maker for a new report, and the comment the
+-- session's app.status_comment.
+CREATE TABLE report_status_transitions (
+    transition_id BIGSERIAL PRIMARY KEY,
+    report_id UUID NOT NULL REFERENCES regulatory_reports_v2(report_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    from_status VARCHAR(50),
+    to_status VARCHAR(50) NOT NULL,
+    actor UUID,
+    comments TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_report_status_transitions_report ON report_status_transitions(report_id, transition_id);
+
+CREATE OR REPLACE FUNCTION record_report_status_transition()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND NEW.status IS NOT DISTINCT FROM OLD.status THEN
+        RETURN NEW;
+    END IF;
+    INSERT INTO report_status_transitions (report_id, tenant_id, from_status, to_status, actor, comments)
+    VALUES (
+        NEW.report_id,
+        NEW.tenant_id,
+        CASE WHEN TG_OP = 'UPDATE' THEN OLD.status END,
+        NEW.status,
+        COALESCE(NULLIF(current_setting('app.current_user_id', true), '')::UUID, NEW.generated_by),
+        NULLIF(current_setting('app.status_comment', true), '')
+    );
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER regulatory_reports_status_trigger
+    AFTER INSERT OR UPDATE OF status ON regulatory_reports_v2
+    FOR EACH ROW
+    EXECUTE FUNCTION record_report_status_transition();
+
+-- Deliveries asked for when a report was generated, sent once it is approved
+CREATE TABLE report_held_deliveries (
+    hold_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    report_id UUID NOT NULL REFERENCES regulatory_reports_v2(report_id) ON DELETE CASCADE,
+    request JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_report_held_deliveries_report ON report_held_deliveries(report_id);
+
+COMMENT ON TABLE report_status_transitions IS 'Audit trail of regulatory report status changes, maintained by trigger';
+COMMENT ON TABLE report_held_deliveries IS 'Deliveries of draft reports waiting for their approval';
+COMMENT ON COLUMN regulatory_reports_v2.review_comments IS 'Reviewer comments given on approval or rejection';
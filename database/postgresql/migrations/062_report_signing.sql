@@ -0,0 +1,43 @@
+-- Migration: Report Signing
+-- Version: 1.61.0
+-- Description: Tenant signing certificates and PKCS#7 signatures of generated report files
+
+-- The certificate a tenant's reports are signed with, such as a Class 3 DSC.
+-- The key is either in a PKCS#12 file, which is kept with its password sealed
+-- with REPORT_SIGNING_CREDENTIALS_KEY by the reporting service, or in an HSM
+-- behind a signing gateway at hsm_url, whose token is sealed the same way. The
+-- certificate itself is public and kept as PEM with its details, so they can
+-- be shown without unsealing anything.
+CREATE TABLE report_signing_certificates (
+    tenant_id UUID PRIMARY KEY REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    key_source VARCHAR(20) NOT NULL,
+    credentials_sealed BYTEA NOT NULL,
+    hsm_url TEXT,
+    hsm_key_label VARCHAR(200),
+    certificate_pem TEXT NOT NULL,
+    chain_pem TEXT,
+    subject TEXT NOT NULL,
+    issuer TEXT NOT NULL,
+    serial_number VARCHAR(100) NOT NULL,
+    fingerprint VARCHAR(64) NOT NULL,
+    not_before TIMESTAMPTZ NOT NULL,
+    not_after TIMESTAMPTZ NOT NULL,
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT chk_report_signing_key_source CHECK (key_source IN ('PKCS12', 'HSM')),
+    CONSTRAINT chk_report_signing_hsm CHECK (
+        key_source <> 'HSM' OR (hsm_url IS NOT NULL AND hsm_key_label IS NOT NULL)
+    )
+);
+
+-- digital_signature holds the base64 DER of the detached PKCS#7 signature of
+-- the report file; PDFs carry the same signature embedded. The certificate is
+-- recorded by its SHA-256 fingerprint, as it may be replaced later.
+ALTER TABLE regulatory_reports_v2
+    ADD COLUMN signed_at TIMESTAMPTZ,
+    ADD COLUMN signing_certificate_fingerprint VARCHAR(64);
+
+-- Uploaded files are matched to their report by hash to verify them
+CREATE INDEX idx_reports_v2_file_hash ON regulatory_reports_v2(tenant_id, file_hash) WHERE file_hash IS NOT NULL;
@@ -0,0 +1,79 @@
+-- Migration: Report Retention
+-- Version: 1.62.0
+-- Description: Per report type retention of report files, archival, and legal holds that block deletion
+
+-- How long a tenant keeps the files of a report type, and what happens to
+-- them then: ARCHIVE moves them to REPORT_ARCHIVE_STORE, where they are kept
+-- and still served, and DELETE removes them. A DEFAULT row covers the types
+-- without their own; REPORT_FILE_RETENTION_DAYS, deleting, covers tenants
+-- without one. A report takes its policy when it is generated.
+CREATE TABLE report_retention_policies (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_type VARCHAR(50) NOT NULL,
+    retention_days INTEGER NOT NULL,
+    action VARCHAR(10) NOT NULL,
+    updated_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (tenant_id, report_type),
+    CONSTRAINT chk_report_retention_days CHECK (retention_days BETWEEN 1 AND 36500),
+    CONSTRAINT chk_report_retention_action CHECK (action IN ('ARCHIVE', 'DELETE'))
+);
+
+-- Reports from before policies were deleted at expiry
+ALTER TABLE regulatory_reports_v2 ADD COLUMN file_retention_action VARCHAR(10);
+ALTER TABLE regulatory_reports_v2 ADD COLUMN file_archived_at TIMESTAMPTZ;
+ALTER TABLE regulatory_reports_v2 ADD COLUMN legal_hold BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE regulatory_reports_v2 SET file_retention_action = 'DELETE' WHERE file_expires_at IS NOT NULL;
+
+ALTER TABLE regulatory_reports_v2 ADD CONSTRAINT chk_report_file_retention_action
+    CHECK (file_retention_action IS NULL OR file_retention_action IN ('ARCHIVE', 'DELETE'));
+
+-- Files due for archival or removal, other than those on hold
+DROP INDEX idx_reports_v2_file_expiry;
+CREATE INDEX idx_reports_v2_file_expiry ON regulatory_reports_v2(file_expires_at)
+    WHERE file_expires_at IS NOT NULL AND file_deleted_at IS NULL AND file_archived_at IS NULL AND NOT legal_hold;
+
+CREATE INDEX idx_reports_v2_legal_hold ON regulatory_reports_v2(tenant_id) WHERE legal_hold;
+
+-- Every legal hold put on a report, and who released it. A report has at most
+-- one open hold, which regulatory_reports_v2.legal_hold mirrors.
+CREATE TABLE report_legal_holds (
+    hold_id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    report_id UUID NOT NULL REFERENCES regulatory_reports_v2(report_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    placed_by UUID,
+    placed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    released_by UUID,
+    release_reason TEXT,
+    released_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX idx_report_legal_holds_open ON report_legal_holds(report_id) WHERE released_at IS NULL;
+CREATE INDEX idx_report_legal_holds_report ON report_legal_holds(report_id, placed_at);
+
+-- A report under legal hold can neither be deleted nor have its file removed,
+-- whatever removes it
+CREATE OR REPLACE FUNCTION block_held_report_deletion()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND OLD.legal_hold THEN
+        RAISE EXCEPTION 'report % is under legal hold', OLD.report_id USING ERRCODE = 'check_violation';
+    END IF;
+    IF TG_OP = 'UPDATE' AND OLD.legal_hold AND NEW.file_deleted_at IS NOT NULL AND OLD.file_deleted_at IS NULL THEN
+        RAISE EXCEPTION 'the file of report % is under legal hold', OLD.report_id USING ERRCODE = 'check_violation';
+    END IF;
+    RETURN COALESCE(NEW, OLD);
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_block_held_report_deletion
+    BEFORE DELETE OR UPDATE OF file_deleted_at ON regulatory_reports_v2
+    FOR EACH ROW EXECUTE FUNCTION block_held_report_deletion();
+
+COMMENT ON COLUMN regulatory_reports_v2.file_retention_action IS 'ARCHIVE or DELETE, done to the file at file_expires_at';
+COMMENT ON COLUMN regulatory_reports_v2.file_archived_at IS 'When the file was moved to the archive store, from which it is served since';
+COMMENT ON COLUMN regulatory_reports_v2.legal_hold IS 'Blocks deletion of the report and removal of its file';
@@ -0,0 +1,51 @@
+-- Migration: Report Aggregate Invalidation
+-- Version: 1.63.0
+-- Description: Notify the reporting service of the trade dates each trade write touches, for its aggregate cache
+
+-- The reporting service caches trade aggregates by tenant and period and drops
+-- the periods covering the dates named here. One notice per tenant and
+-- statement, as "<tenant_id> <first date> <last date>", on the dates
+-- DATE(trade_time) gives, as the aggregates themselves group trades.
+CREATE OR REPLACE FUNCTION notify_report_aggregates_stale()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        PERFORM pg_notify(
+            'report_aggregates_stale',
+            format('%s %s %s', tenant_id, MIN(DATE(trade_time)), MAX(DATE(trade_time)))
+        )
+        FROM new_trades GROUP BY tenant_id;
+    ELSIF TG_OP = 'UPDATE' THEN
+        PERFORM pg_notify(
+            'report_aggregates_stale',
+            format('%s %s %s', tenant_id, MIN(DATE(trade_time)), MAX(DATE(trade_time)))
+        )
+        FROM (
+            SELECT tenant_id, trade_time FROM old_trades
+            UNION ALL
+            SELECT tenant_id, trade_time FROM new_trades
+        ) AS changed
+        GROUP BY tenant_id;
+    ELSE
+        PERFORM pg_notify(
+            'report_aggregates_stale',
+            format('%s %s %s', tenant_id, MIN(DATE(trade_time)), MAX(DATE(trade_time)))
+        )
+        FROM old_trades GROUP BY tenant_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Transition tables allow one event per trigger
+CREATE TRIGGER trg_trades_aggregates_stale_insert
+    AFTER INSERT ON trades REFERENCING NEW TABLE AS new_trades
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_report_aggregates_stale();
+
+CREATE TRIGGER trg_trades_aggregates_stale_update
+    AFTER UPDATE ON trades REFERENCING OLD TABLE AS old_trades NEW TABLE AS new_trades
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_report_aggregates_stale();
+
+CREATE TRIGGER trg_trades_aggregates_stale_delete
+    AFTER DELETE ON trades REFERENCING OLD TABLE AS old_trades
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_report_aggregates_stale();
@@ -0,0 +1,104 @@
+-- Migration: Trade Rollups
+-- Version: 1.64.0
+-- Description: Per day, per tenant and per instrument trade aggregates materialized nightly for trading summaries
+
+-- The days of a tenant whose trades are rolled up. The reporting service rolls
+-- up each past day nightly, and reads a day from the rollups only while it is
+-- not stale; the current day, days not rolled up yet and stale ones are read
+-- from trades. Trades written for a past day mark it stale until it is rolled
+-- up again. Days are those DATE(trade_time) gives, as trading summaries group
+-- trades.
+CREATE TABLE trade_rollup_days (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    trade_date DATE NOT NULL,
+    stale BOOLEAN NOT NULL DEFAULT TRUE,
+    trade_count BIGINT,
+    rolled_up_at TIMESTAMPTZ,
+
+    PRIMARY KEY (tenant_id, trade_date)
+);
+
+CREATE INDEX idx_trade_rollup_days_stale ON trade_rollup_days(trade_date) WHERE stale;
+
+-- A day's trades by instrument. The symbol it had that day is looked up when
+-- read, so corrected instrument versions apply to rolled up days as well.
+CREATE TABLE trade_daily_instrument_stats (
+    tenant_id UUID NOT NULL,
+    trade_date DATE NOT NULL,
+    instrument_id UUID NOT NULL REFERENCES instruments(instrument_id),
+    trade_count BIGINT NOT NULL,
+    total_volume BIGINT NOT NULL,
+    total_value DECIMAL(30,8) NOT NULL,
+    max_value DECIMAL(25,8) NOT NULL,
+    -- Sum of the trade prices, which averages over any days as price_sum / trade_count
+    price_sum DECIMAL(30,8) NOT NULL,
+
+    PRIMARY KEY (tenant_id, trade_date, instrument_id),
+    FOREIGN KEY (tenant_id, trade_date) REFERENCES trade_rollup_days(tenant_id, trade_date) ON DELETE CASCADE
+);
+
+-- A day's trades by hour of trade_time
+CREATE TABLE trade_daily_hour_stats (
+    tenant_id UUID NOT NULL,
+    trade_date DATE NOT NULL,
+    hour SMALLINT NOT NULL,
+    trade_count BIGINT NOT NULL,
+
+    PRIMARY KEY (tenant_id, trade_date, hour),
+    FOREIGN KEY (tenant_id, trade_date) REFERENCES trade_rollup_days(tenant_id, trade_date) ON DELETE CASCADE
+);
+
+-- The accounts that traded on a day, so clients active over several days are
+-- counted once
+CREATE TABLE trade_daily_account_stats (
+    tenant_id UUID NOT NULL,
+    trade_date DATE NOT NULL,
+    account_id UUID NOT NULL,
+    trade_count BIGINT NOT NULL,
+
+    PRIMARY KEY (tenant_id, trade_date, account_id),
+    FOREIGN KEY (tenant_id, trade_date) REFERENCES trade_rollup_days(tenant_id, trade_date) ON DELETE CASCADE
+);
+
+-- Mark the past days a trade write touches stale. The current day is left
+-- alone, as it is never read from rollups, so ingestion does not contend on
+-- its row. A rollup holds its day's row until it commits, so trades written
+-- meanwhile mark the day stale once it has.
+CREATE OR REPLACE FUNCTION mark_trade_rollups_stale()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO trade_rollup_days (tenant_id, trade_date)
+        SELECT DISTINCT tenant_id, DATE(trade_time) FROM new_trades
+        WHERE DATE(trade_time) < clock_timestamp()::date
+        ON CONFLICT (tenant_id, trade_date) DO UPDATE SET stale = TRUE WHERE NOT trade_rollup_days.stale;
+    ELSIF TG_OP = 'UPDATE' THEN
+        INSERT INTO trade_rollup_days (tenant_id, trade_date)
+        SELECT DISTINCT tenant_id, DATE(trade_time) FROM (
+            SELECT tenant_id, trade_time FROM old_trades
+            UNION ALL
+            SELECT tenant_id, trade_time FROM new_trades
+        ) AS changed
+        WHERE DATE(trade_time) < clock_timestamp()::date
+        ON CONFLICT (tenant_id, trade_date) DO UPDATE SET stale = TRUE WHERE NOT trade_rollup_days.stale;
+    ELSE
+        INSERT INTO trade_rollup_days (tenant_id, trade_date)
+        SELECT DISTINCT tenant_id, DATE(trade_time) FROM old_trades
+        WHERE DATE(trade_time) < clock_timestamp()::date
+        ON CONFLICT (tenant_id, trade_date) DO UPDATE SET stale = TRUE WHERE NOT trade_rollup_days.stale;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trg_trades_rollups_stale_insert
+    AFTER INSERT ON trades REFERENCING NEW TABLE AS new_trades
+    FOR EACH STATEMENT EXECUTE FUNCTION mark_trade_rollups_stale();
+
+CREATE TRIGGER trg_trades_rollups_stale_update
+    AFTER UPDATE ON trades REFERENCING OLD TABLE AS old_trades NEW TABLE AS new_trades
+    FOR EACH STATEMENT EXECUTE FUNCTION mark_trade_rollups_stale();
+
+CREATE TRIGGER trg_trades_rollups_stale_delete
+    AFTER DELETE ON trades REFERENCING OLD TABLE AS old_trades
+    FOR EACH STATEMENT EXECUTE FUNCTION mark_trade_rollups_stale();
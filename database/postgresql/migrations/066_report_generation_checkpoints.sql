@@ -0,0 +1,32 @@
+-- Migration: Report Generation Checkpoints
+-- Version: 1.65.0
+-- Description: Progress of reports generated over long periods in slices, for resuming interrupted generations
+
+-- A report over a long period is computed a slice of chunk_days at a time,
+-- and the merged aggregates of the slices done so far are kept in partial
+-- along with the last day they cover. The reporting service generating it
+-- holds the row by lease_token until leased_until, renewing the lease with
+-- every slice. A generation that fails or whose worker stops leaves the row
+-- behind, and the next generation of the same report and period carries on
+-- from completed_through once the lease has run out, as long as the row is
+-- recent enough; the row is removed when the report is complete.
+CREATE TABLE report_generation_checkpoints (
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_type VARCHAR(50) NOT NULL,
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+    chunk_days INTEGER NOT NULL,
+    chunks_total INTEGER NOT NULL,
+    chunks_done INTEGER NOT NULL DEFAULT 0,
+    completed_through DATE,
+    partial JSONB,
+    lease_token UUID NOT NULL,
+    leased_until TIMESTAMPTZ NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (tenant_id, report_type, period_start, period_end),
+    CONSTRAINT chk_report_generation_chunk_days CHECK (chunk_days > 0)
+);
+
+CREATE INDEX idx_report_generation_checkpoints_updated ON report_generation_checkpoints(tenant_id, updated_at);
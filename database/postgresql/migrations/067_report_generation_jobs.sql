@@ -0,0 +1,32 @@
+-- Migration: Report Generation Jobs
+-- Version: 1.66.0
+-- Description: Report generations in flight, so they can be cancelled and time out
+
+-- One row per report generation, keyed by the report_id the report is stored
+-- under once generated. The reporting service inserts it RUNNING when the
+-- generation starts, with the deadline of the report type's timeout, and the
+-- worker generating it watches the row: DELETE /reports/:id/cancel marks it
+-- CANCELLED and the worker abandons the generation. A generation past its
+-- deadline is abandoned and marked FAILED with a timeout reason, and so is
+-- one whose worker stopped before finishing, once the deadline has gone by.
+-- The row is marked COMPLETED in the transaction storing the report.
+CREATE TABLE report_generation_jobs (
+    report_id UUID PRIMARY KEY,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    report_type VARCHAR(50) NOT NULL,
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'RUNNING',
+    failure_reason TEXT,
+    timeout_secs INTEGER NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    deadline TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ,
+    cancelled_by UUID,
+
+    CONSTRAINT chk_report_generation_job_status CHECK (status IN ('RUNNING', 'COMPLETED', 'FAILED', 'CANCELLED')),
+    CONSTRAINT chk_report_generation_job_timeout CHECK (timeout_secs > 0)
+);
+
+CREATE INDEX idx_report_generation_jobs_tenant ON report_generation_jobs(tenant_id, started_at DESC);
+CREATE INDEX idx_report_generation_jobs_running ON report_generation_jobs(deadline) WHERE status = 'RUNNING';
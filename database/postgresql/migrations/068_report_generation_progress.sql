@@ -0,0 +1,14 @@
+-- Migration: Report Generation Progress
+-- Version: 1.67.0
+-- Description: Phase and percentage of running report generation jobs, streamed to the frontend
+
+-- A running job moves through QUERYING, while its data is read (slice by
+-- slice for long periods), AGGREGATING and RENDERING, and progress_percent
+-- only ever rises. GET /reports/:id/progress streams the row as it changes,
+-- estimating the time left from started_at.
+ALTER TABLE report_generation_jobs
+    ADD COLUMN phase VARCHAR(20) NOT NULL DEFAULT 'QUERYING',
+    ADD COLUMN progress_percent SMALLINT NOT NULL DEFAULT 0,
+    ADD COLUMN progress_updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD CONSTRAINT chk_report_generation_job_phase CHECK (phase IN ('QUERYING', 'AGGREGATING', 'RENDERING')),
+    ADD CONSTRAINT chk_report_generation_job_progress CHECK (progress_percent BETWEEN 0 AND 100);
@@ -0,0 +1,9 @@
+-- Migration: Report Parameters
+-- Version: 1.68.0
+-- Description: Parameters reports were generated with, beyond their tenant and period
+
+-- The parameters the report type declares, defaults filled in, as the report
+-- was generated with them; amendments of the report are generated with the
+-- same parameters. Reports from before report types took parameters have none.
+ALTER TABLE regulatory_reports_v2
+    ADD COLUMN report_parameters JSONB NOT NULL DEFAULT '{}'::jsonb;
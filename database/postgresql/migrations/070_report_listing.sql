@@ -0,0 +1,8 @@
+-- Migration: Report Listing
+-- Version: 1.69.0
+-- Description: Index for listing a tenant's reports most recently generated first
+
+-- GET /reports pages through a tenant's reports by generated_at, filtered by
+-- status and period among others; the index serves the order and the status
+-- filter, the period filters use idx_reports_v2_tenant_period.
+CREATE INDEX idx_reports_v2_tenant_generated ON regulatory_reports_v2(tenant_id, generated_at DESC, status);
@@ -0,0 +1,26 @@
+-- Migration: Trade Charges
+-- Version: 1.70.0
+-- Description: Statutory and exchange charges levied on each trade, for brokerage and fee summaries
+
+-- trades carries the brokerage and the taxes of a trade as a single amount;
+-- the back office books the contract note's breakdown here, one row per trade,
+-- so brokerage summaries reconcile charge by charge. Brokerage recorded here
+-- takes precedence over trades.brokerage. Trades without a breakdown are
+-- reported with their taxes unallocated.
+CREATE TABLE trade_charges (
+    trade_id UUID PRIMARY KEY REFERENCES trades(trade_id) ON DELETE CASCADE,
+    tenant_id UUID NOT NULL REFERENCES tenants(tenant_id) ON DELETE CASCADE,
+    brokerage DECIMAL(15,8) NOT NULL DEFAULT 0,
+    -- Securities transaction tax
+    stt DECIMAL(15,8) NOT NULL DEFAULT 0,
+    -- Exchange transaction charges
+    exchange_charges DECIMAL(15,8) NOT NULL DEFAULT 0,
+    sebi_fees DECIMAL(15,8) NOT NULL DEFAULT 0,
+    stamp_duty DECIMAL(15,8) NOT NULL DEFAULT 0,
+    -- GST on brokerage, exchange charges and SEBI fees
+    gst DECIMAL(15,8) NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_trade_charges_tenant ON trade_charges(tenant_id);
@@ -0,0 +1,13 @@
+-- Migration: Peak Margins
+-- Version: 1.71.0
+-- Description: Peak intraday margin requirement of margin statements, for margin utilization and shortfall reports
+
+-- Under the exchanges' peak margin framework a client's margin is snapshotted
+-- several times a day and the highest requirement, not the end-of-day one, is
+-- what must have been collected. Margin files carrying it load it here; the
+-- statements of those that do not are reported on total_margin. The stored
+-- shortfall stays on total_margin, as the end-of-day files report it.
+ALTER TABLE margin_statements
+    ADD COLUMN peak_margin DECIMAL(25,8) CHECK (peak_margin >= 0);
+
+CREATE INDEX idx_margin_statements_tenant_date ON margin_statements(tenant_id, business_date, account_id);
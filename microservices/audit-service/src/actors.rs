@@ -0,0 +1,111 @@
+//! Actor snapshots from the user service
+//!
+//! Events only name their actor by user_id, which stops meaning anything to a
+//! reader once the user is renamed or deleted. With USER_SERVICE_URL set, the
+//! username, role and tenant name of the acting user are fetched from the
+//! user service's GET /api/v1/users/:user_id/actor when the event is written
+//! and embedded in it, so they are hashed and signed with the rest of the
+//! event and kept in audit_logs.actor_snapshot.
+//!
+//! Lookups are cached for AUDIT_ACTOR_CACHE_TTL_SECS, users the user service
+//! does not know included. A lookup that fails or takes longer than
+//! AUDIT_ACTOR_LOOKUP_TIMEOUT_MS leaves the event without a snapshot rather
+//! than holding up the write. Outcomes are counted in
+//! `audit_actor_lookups_total`.
+
+use metrics::counter;
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+/// The acting user as recorded with an event
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ActorSnapshot {
+    pub username: String,
+    pub role: String,
+    pub tenant_id: Uuid,
+    pub tenant_name: String,
+}
+
+/// Body of the user service's actor endpoint
+#[derive(Deserialize)]
+struct ActorResponse {
+    data: Option<ActorSnapshot>,
+}
+
+pub struct ActorResolver {
+    base_url: String,
+    /// Bearer token the user service accepts from internal callers
+    token: Option<String>,
+    http: reqwest::Client,
+    /// `None` for users the user service does not know
+    cache: Cache<Uuid, Option<ActorSnapshot>>,
+}
+
+impl ActorResolver {
+    /// `None` when USER_SERVICE_URL is unset
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let base_url = match std::env::var("USER_SERVICE_URL") {
+            Ok(url) if !url.is_empty() => url.trim_end_matches('/').to_string(),
+            _ => return Ok(None),
+        };
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(var("AUDIT_ACTOR_LOOKUP_TIMEOUT_MS", 500)))
+            .build()?;
+        Ok(Some(Self {
+            base_url,
+            token: std::env::var("USER_SERVICE_TOKEN").ok().filter(|token| !token.is_empty()),
+            http,
+            cache: Cache::builder()
+                .max_capacity(var("AUDIT_ACTOR_CACHE_SIZE", 10_000))
+                .time_to_live(Duration::from_secs(var("AUDIT_ACTOR_CACHE_TTL_SECS", 300)))
+                .build(),
+        }))
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Snapshot of `user_id`, or `None` when it is unknown or cannot be looked up now
+    pub async fn resolve(&self, user_id: Uuid) -> Option<ActorSnapshot> {
+        if let Some(cached) = self.cache.get(&user_id).await {
+            counter!("audit_actor_lookups_total", 1, "outcome" => "cached");
+            return cached;
+        }
+        match self.fetch(user_id).await {
+            Ok(snapshot) => {
+                let outcome = if snapshot.is_some() { "resolved" } else { "not_found" };
+                counter!("audit_actor_lookups_total", 1, "outcome" => outcome);
+                self.cache.insert(user_id, snapshot.clone()).await;
+                snapshot
+            }
+            Err(e) => {
+                counter!("audit_actor_lookups_total", 1, "outcome" => "failed");
+                warn!("Writing audit event without a snapshot of user {}: {}", user_id, e);
+                None
+            }
+        }
+    }
+
+    async fn fetch(&self, user_id: Uuid) -> anyhow::Result<Option<ActorSnapshot>> {
+        let mut request = self.http.get(format!("{}/api/v1/users/{}/actor", self.base_url, user_id));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body: ActorResponse = response.error_for_status()?.json().await?;
+        Ok(body.data)
+    }
+}
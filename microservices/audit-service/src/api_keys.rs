@@ -0,0 +1,274 @@
+//! API keys of internal producers, for ingestion without a user JWT
+//!
+//! Platform admins issue a key per producing service through /admin/api-keys.
+//! It is shown once: audit_api_keys keeps its SHA-256 and the first characters
+//! to tell keys apart. A key is scoped to the tenants it was issued for and only
+//! records events; `auth::authorize` refuses it on every route but event
+//! creation, and the service it was issued to is recorded on each event as
+//! `producer`.
+//!
+//! Rotating a key issues its successor for the same service and tenants and
+//! leaves the old key working for AUDIT_API_KEY_ROTATION_GRACE_SECS, so the
+//! producer can switch over without dropping events. Revocation is immediate
+//! on the replica that handles it; other replicas may accept a revoked key for
+//! up to AUDIT_API_KEY_CACHE_SECS.
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use moka::future::Cache;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::auth::Caller;
+
+/// Leads every key, so leaked keys are easy to search for
+const KEY_PREFIX: &str = "dgak_";
+
+/// Characters of a key kept in clear to identify it
+const DISPLAYED_CHARS: usize = 12;
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct ApiKey {
+    pub key_id: Uuid,
+    pub service: String,
+    /// Start of the key, e.g. dgak_3f9a1c2
+    pub key_prefix: String,
+    pub tenant_ids: Vec<Uuid>,
+    pub created_by: Option<Uuid>,
+    pub created_by_service: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Key this one replaced when it was issued by a rotation
+    pub rotated_from: Option<Uuid>,
+}
+
+/// A newly issued key, the only time it is returned
+#[derive(Serialize)]
+pub struct IssuedApiKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    pub api_key: String,
+}
+
+#[derive(Deserialize)]
+pub struct IssueApiKeyRequest {
+    pub service: String,
+    pub tenant_ids: Vec<Uuid>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct RotateApiKeyRequest {
+    /// How long the old key keeps working; AUDIT_API_KEY_ROTATION_GRACE_SECS unless set
+    pub grace_secs: Option<u64>,
+    /// Expiry of the new key; it does not expire unless set
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct ApiKeyListParams {
+    pub service: Option<String>,
+    #[serde(default)]
+    pub include_revoked: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum ApiKeyError {
+    #[error("service must be 1 to 100 characters")]
+    InvalidService,
+    #[error("an API key needs at least one tenant")]
+    NoTenants,
+    #[error("expires_at must be in the future")]
+    InvalidExpiry,
+    #[error("API key not found")]
+    NotFound,
+    #[error("API key is revoked")]
+    Revoked,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct ApiKeys {
+    db: PgPool,
+    grace: Duration,
+    /// Callers by key hash; `None` for hashes that matched no usable key
+    cache: Cache<String, Option<Caller>>,
+}
+
+impl ApiKeys {
+    pub fn from_env(db: PgPool) -> Self {
+        let secs = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            db,
+            grace: Duration::from_secs(secs("AUDIT_API_KEY_ROTATION_GRACE_SECS", 24 * 60 * 60)),
+            cache: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(Duration::from_secs(secs("AUDIT_API_KEY_CACHE_SECS", 30)))
+                .build(),
+        }
+    }
+
+    /// The caller presenting `key`; `None` for unknown, expired and revoked keys
+    pub async fn authenticate(&self, key: &str) -> Result<Option<Caller>, sqlx::Error> {
+        if !key.starts_with(KEY_PREFIX) {
+            return Ok(None);
+        }
+        let hash = hash(key);
+        if let Some(caller) = self.cache.get(&hash).await {
+            return Ok(caller);
+        }
+        // Only looked up once per cache lifetime, so last_used_at is as coarse
+        let key = sqlx::query_as::<_, ApiKey>(
+            r#"
+            UPDATE audit_api_keys SET last_used_at = NOW()
+            WHERE key_hash = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())
+            RETURNING *
+            "#,
+        )
+        .bind(&hash)
+        .fetch_optional(&self.db)
+        .await?;
+        let caller = key.map(|key| Caller::ApiKey {
+            key_id: key.key_id,
+            service: key.service,
+            tenants: key.tenant_ids,
+        });
+        if caller.is_none() {
+            counter!("audit_api_keys_rejected_total", 1);
+        }
+        self.cache.insert(hash, caller.clone()).await;
+        Ok(caller)
+    }
+
+    pub async fn issue(&self, request: &IssueApiKeyRequest, caller: &Caller) -> Result<IssuedApiKey, ApiKeyError> {
+        let service = request.service.trim();
+        if service.is_empty() || service.len() > 100 {
+            return Err(ApiKeyError::InvalidService);
+        }
+        if request.tenant_ids.is_empty() {
+            return Err(ApiKeyError::NoTenants);
+        }
+        if request.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(ApiKeyError::InvalidExpiry);
+        }
+        let mut tenant_ids = request.tenant_ids.clone();
+        tenant_ids.sort();
+        tenant_ids.dedup();
+        self.insert(service, &tenant_ids, request.expires_at, None, caller).await
+    }
+
+    /// Issue a successor of `key_id` and let the old key lapse after the grace period
+    pub async fn rotate(
+        &self,
+        key_id: Uuid,
+        request: &RotateApiKeyRequest,
+        caller: &Caller,
+    ) -> Result<IssuedApiKey, ApiKeyError> {
+        let old = get(&self.db, key_id).await?.ok_or(ApiKeyError::NotFound)?;
+        if old.revoked_at.is_some() {
+            return Err(ApiKeyError::Revoked);
+        }
+        if request.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(ApiKeyError::InvalidExpiry);
+        }
+        let grace = request.grace_secs.map(Duration::from_secs).unwrap_or(self.grace);
+        let lapses_at = Utc::now() + chrono::Duration::from_std(grace).unwrap_or_else(|_| chrono::Duration::days(1));
+        // Issued first, so a failure leaves the old key working rather than no key at all
+        let issued = self
+            .insert(&old.service, &old.tenant_ids, request.expires_at, Some(key_id), caller)
+            .await?;
+        sqlx::query("UPDATE audit_api_keys SET expires_at = LEAST(expires_at, $2) WHERE key_id = $1")
+            .bind(key_id)
+            .bind(lapses_at)
+            .execute(&self.db)
+            .await?;
+        self.cache.invalidate_all();
+        Ok(issued)
+    }
+
+    pub async fn revoke(&self, key_id: Uuid) -> Result<ApiKey, ApiKeyError> {
+        let key = sqlx::query_as::<_, ApiKey>(
+            "UPDATE audit_api_keys SET revoked_at = COALESCE(revoked_at, NOW()) WHERE key_id = $1 RETURNING *",
+        )
+        .bind(key_id)
+        .fetch_optional(&self.db)
+        .await?
+        .ok_or(ApiKeyError::NotFound)?;
+        self.cache.invalidate_all();
+        Ok(key)
+    }
+
+    async fn insert(
+        &self,
+        service: &str,
+        tenant_ids: &[Uuid],
+        expires_at: Option<DateTime<Utc>>,
+        rotated_from: Option<Uuid>,
+        caller: &Caller,
+    ) -> Result<IssuedApiKey, ApiKeyError> {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+        let api_key = format!("{}{}", KEY_PREFIX, hex::encode(secret));
+        let (created_by, created_by_service) = match caller {
+            Caller::User { user_id, .. } => (Some(*user_id), None),
+            Caller::Service { name } | Caller::ApiKey { service: name, .. } => (None, Some(name.clone())),
+        };
+        let key = sqlx::query_as::<_, ApiKey>(
+            r#"
+            INSERT INTO audit_api_keys (
+                service, key_prefix, key_hash, tenant_ids, created_by, created_by_service, expires_at, rotated_from
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+            "#,
+        )
+        .bind(service)
+        .bind(&api_key[..DISPLAYED_CHARS])
+        .bind(hash(&api_key))
+        .bind(tenant_ids)
+        .bind(created_by)
+        .bind(created_by_service)
+        .bind(expires_at)
+        .bind(rotated_from)
+        .fetch_one(&self.db)
+        .await?;
+        Ok(IssuedApiKey { key, api_key })
+    }
+}
+
+fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+pub async fn get(db: &PgPool, key_id: Uuid) -> Result<Option<ApiKey>, sqlx::Error> {
+    sqlx::query_as::<_, ApiKey>("SELECT * FROM audit_api_keys WHERE key_id = $1")
+        .bind(key_id)
+        .fetch_optional(db)
+        .await
+}
+
+pub async fn list(db: &PgPool, params: &ApiKeyListParams) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as::<_, ApiKey>(
+        r#"
+        SELECT * FROM audit_api_keys
+        WHERE ($1::text IS NULL OR service = $1) AND ($2 OR revoked_at IS NULL)
+        ORDER BY service, created_at DESC
+        "#,
+    )
+    .bind(params.service.as_deref())
+    .bind(params.include_revoked)
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,266 @@
+//! Evidence attached to audit events
+//!
+//! Investigators attach screenshots, emails and other files to an event with
+//! POST /audit/events/:event_id/attachments, sending the file as the raw
+//! request body with its Content-Type. Files are limited to
+//! AUDIT_ATTACHMENT_MAX_BYTES and the types in AUDIT_ATTACHMENT_TYPES, and
+//! stored through the anchor store, so attachments need AUDIT_ANCHOR_STORE=ipfs.
+//!
+//! With an envelope master key configured, files are encrypted under the
+//! tenant's data key before they leave the service (AUDIT_ATTACHMENT_ENCRYPT,
+//! or `encrypt` per upload); the CID is then that of the ciphertext, while
+//! `sha256` is always the hash of the file as uploaded. Downloads check the
+//! hash and are only decrypted for readers allowed to decrypt audit values.
+//! Every upload is itself audited as audit.attachment.add against the event, so
+//! the file's hash is in the signed trail.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::Caller;
+use crate::context::RequestContext;
+use crate::envelope::{EnvelopeError, Reader};
+use crate::integrity::sha256_hex;
+use crate::{AppState, AuditEvent, AuditService, CreateAuditEventRequest};
+
+const DEFAULT_TYPES: &str = "image/png,image/jpeg,image/gif,application/pdf,message/rfc822,text/plain,text/csv";
+
+#[derive(Debug, Clone)]
+pub struct AttachmentSettings {
+    pub max_bytes: usize,
+    /// Content types accepted, without parameters
+    pub allowed_types: Vec<String>,
+    /// Whether files are encrypted when the upload does not say
+    pub encrypt_by_default: bool,
+}
+
+impl AttachmentSettings {
+    pub fn from_env() -> Self {
+        Self {
+            max_bytes: std::env::var("AUDIT_ATTACHMENT_MAX_BYTES")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(10 * 1024 * 1024),
+            allowed_types: std::env::var("AUDIT_ATTACHMENT_TYPES")
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_TYPES.to_string())
+                .split(',')
+                .map(|content_type| content_type.trim().to_ascii_lowercase())
+                .filter(|content_type| !content_type.is_empty())
+                .collect(),
+            encrypt_by_default: std::env::var("AUDIT_ATTACHMENT_ENCRYPT")
+                .map(|value| value != "false")
+                .unwrap_or(true),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UploadParams {
+    pub file_name: String,
+    pub description: Option<String>,
+    /// Overrides AUDIT_ATTACHMENT_ENCRYPT for this file
+    pub encrypt: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct Attachment {
+    pub attachment_id: Uuid,
+    pub event_id: Uuid,
+    pub tenant_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    /// Of the file as uploaded
+    pub sha256: String,
+    /// Of the stored blob, which is ciphertext when `data_key_id` is set
+    pub cid: String,
+    pub data_key_id: Option<Uuid>,
+    pub description: Option<String>,
+    pub uploaded_by: Option<Uuid>,
+    pub uploaded_by_service: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+pub enum AttachmentError {
+    #[error("audit event not found")]
+    EventNotFound,
+    #[error("attachment not found")]
+    NotFound,
+    #[error("attachment is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("attachment is empty")]
+    Empty,
+    #[error("content type {0} is not accepted")]
+    UnsupportedType(String),
+    #[error("file_name must be 1 to 255 characters")]
+    InvalidName,
+    #[error("attachments are not stored without AUDIT_ANCHOR_STORE=ipfs")]
+    StorageUnavailable,
+    #[error("attachments cannot be encrypted without an envelope master key")]
+    EncryptionUnavailable,
+    #[error("attachment is encrypted and the reader may not decrypt it")]
+    Forbidden,
+    #[error("stored attachment {0} no longer matches its hash")]
+    Tampered(Uuid),
+    #[error("storage error: {0}")]
+    Storage(String),
+    #[error(transparent)]
+    Envelope(#[from] EnvelopeError),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Store a file and link it to `event`
+pub async fn attach(
+    state: &AppState,
+    settings: &AttachmentSettings,
+    event: &AuditEvent,
+    caller: &Caller,
+    content_type: Option<&str>,
+    body: &[u8],
+    params: UploadParams,
+) -> Result<Attachment, AttachmentError> {
+    if body.is_empty() {
+        return Err(AttachmentError::Empty);
+    }
+    if body.len() > settings.max_bytes {
+        return Err(AttachmentError::TooLarge(settings.max_bytes));
+    }
+    // Parameters such as charset are not part of what is accepted
+    let content_type = content_type
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    if !settings.allowed_types.contains(&content_type) {
+        return Err(AttachmentError::UnsupportedType(content_type));
+    }
+    // Only the last path component, as some clients send the full local path
+    let file_name = params.file_name.rsplit(['/', '\\']).next().unwrap_or_default().trim().to_string();
+    if file_name.is_empty() || file_name.chars().count() > 255 {
+        return Err(AttachmentError::InvalidName);
+    }
+    if !state.anchors.keeps_copies() {
+        return Err(AttachmentError::StorageUnavailable);
+    }
+
+    let attachment_id = Uuid::new_v4();
+    let sha256 = sha256_hex(body);
+    let (data_key_id, blob) = match (params.encrypt.unwrap_or(settings.encrypt_by_default), &state.envelope) {
+        (true, Some(envelope)) => {
+            let (key_id, sealed) = envelope
+                .encrypt_blob(&state.db, event.tenant_id, attachment_id.as_bytes(), body)
+                .await?;
+            (Some(key_id), sealed)
+        }
+        // Asked for explicitly, so storing it in clear would be a surprise
+        (true, None) if params.encrypt == Some(true) => return Err(AttachmentError::EncryptionUnavailable),
+        _ => (None, body.to_vec()),
+    };
+    let cid = state
+        .anchors
+        .put(&blob)
+        .await
+        .map_err(|e| AttachmentError::Storage(e.to_string()))?
+        .ok_or(AttachmentError::StorageUnavailable)?;
+
+    let (uploaded_by, uploaded_by_service) = match caller {
+        Caller::User { user_id, .. } => (Some(*user_id), None),
+        Caller::Service { name } | Caller::ApiKey { service: name, .. } => (None, Some(name.clone())),
+    };
+    let attachment = sqlx::query_as::<_, Attachment>(
+        r#"
+        INSERT INTO audit_event_attachments (
+            attachment_id, event_id, tenant_id, file_name, content_type, size_bytes, sha256, cid,
+            data_key_id, description, uploaded_by, uploaded_by_service
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        RETURNING *
+        "#,
+    )
+    .bind(attachment_id)
+    .bind(event.event_id)
+    .bind(event.tenant_id)
+    .bind(&file_name)
+    .bind(&content_type)
+    .bind(body.len() as i64)
+    .bind(&sha256)
+    .bind(&cid)
+    .bind(data_key_id)
+    .bind(&params.description)
+    .bind(uploaded_by)
+    .bind(&uploaded_by_service)
+    .fetch_one(&state.db)
+    .await?;
+
+    record_attachment(state, &attachment).await;
+    Ok(attachment)
+}
+
+pub async fn list(db: &PgPool, event_id: Uuid) -> Result<Vec<Attachment>, sqlx::Error> {
+    sqlx::query_as::<_, Attachment>("SELECT * FROM audit_event_attachments WHERE event_id = $1 ORDER BY created_at")
+        .bind(event_id)
+        .fetch_all(db)
+        .await
+}
+
+pub async fn find(db: &PgPool, event_id: Uuid, attachment_id: Uuid) -> Result<Option<Attachment>, sqlx::Error> {
+    sqlx::query_as::<_, Attachment>("SELECT * FROM audit_event_attachments WHERE event_id = $1 AND attachment_id = $2")
+        .bind(event_id)
+        .bind(attachment_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// The file as uploaded, checked against its hash
+pub async fn content(state: &AppState, attachment: &Attachment, reader: &Reader) -> Result<Vec<u8>, AttachmentError> {
+    let blob = state
+        .anchors
+        .retrieve(&attachment.cid)
+        .await
+        .map_err(|e| AttachmentError::Storage(e.to_string()))?;
+    let file = match (attachment.data_key_id, &state.envelope) {
+        (None, _) => blob,
+        (Some(key_id), Some(envelope)) if reader.may_decrypt(attachment.tenant_id) => {
+            envelope
+                .decrypt_blob(&state.db, attachment.tenant_id, key_id, attachment.attachment_id.as_bytes(), &blob)
+                .await?
+        }
+        (Some(_), _) => return Err(AttachmentError::Forbidden),
+    };
+    if sha256_hex(&file) != attachment.sha256 {
+        return Err(AttachmentError::Tampered(attachment.attachment_id));
+    }
+    Ok(file)
+}
+
+async fn record_attachment(state: &AppState, attachment: &Attachment) {
+    let request = CreateAuditEventRequest {
+        tenant_id: attachment.tenant_id,
+        user_id: attachment.uploaded_by,
+        action: "audit.attachment.add".to_string(),
+        resource_type: "AUDIT_EVENT".to_string(),
+        resource_id: Some(attachment.event_id),
+        old_values: None,
+        new_values: Some(serde_json::json!({
+            "attachment_id": attachment.attachment_id,
+            "file_name": attachment.file_name,
+            "content_type": attachment.content_type,
+            "size_bytes": attachment.size_bytes,
+            "sha256": attachment.sha256,
+            "cid": attachment.cid,
+            "encrypted": attachment.data_key_id.is_some(),
+        })),
+        metadata: None,
+    };
+    let context = RequestContext::new(None, Some("audit-attachments"), None);
+    if let Err(e) = AuditService::from_state(state.clone()).create_audit_event(request, &context).await {
+        error!("Failed to audit attachment {} of audit event {}: {}", attachment.attachment_id, attachment.event_id, e);
+    }
+}
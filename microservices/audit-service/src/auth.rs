@@ -0,0 +1,428 @@
+//! Caller authentication and tenant scoping for the HTTP and gRPC APIs
+//!
+//! Every request carries a bearer token: either a user-service JWT (HS256,
+//! JWT_SECRET) or one of the AUDIT_SERVICE_TOKENS held by internal producers.
+//! Producers may instead present an X-Api-Key issued through /admin/api-keys,
+//! which only creates events for its own tenants; see `api_keys`.
+//! The [`authorize`] middleware turns it into a [`Caller`] and refuses:
+//!
+//! - a `tenant_id` in the query string, the path (`/admin/tenants/:tenant_id`)
+//!   or the JSON body that the caller may not access;
+//! - `/admin` routes unless the caller is a service, holds a platform role, or
+//!   holds a tenant admin role and the route is under its own tenant;
+//! - events written by a user on behalf of another user;
+//! - a restricted resource type (AUDIT_RESTRICTED_RESOURCE_TYPES) in the path
+//!   or the `resource_type` query parameter, unless the caller is a service or
+//!   holds one of AUDIT_RESTRICTED_ROLES.
+//!
+//! Lookups by id, where the tenant is only known once the row is loaded, check
+//! [`Caller::may_access`] and [`Caller::may_see`] in the handler and answer 404
+//! for other tenants and restricted events. Listings and exports leave out
+//! [`Caller::hidden_resource_types`].
+//! Events consumed from the internal event bus are trusted as they are.
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::{FromRequestParts, Query, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use metrics::counter;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::is_json;
+
+use crate::AppState;
+
+/// Same as the default limit of the Json extractor that reads the body next
+const MAX_INSPECTED_BODY: usize = 2 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: Uuid,
+    tenant_id: Uuid,
+    role: String,
+    /// Further tenants the user may act for, e.g. group entities of a broker
+    #[serde(default)]
+    tenant_ids: Vec<Uuid>,
+}
+
+/// Tenants a caller may read and write
+#[derive(Debug, Clone)]
+pub enum TenantScope {
+    All,
+    Only(Vec<Uuid>),
+}
+
+/// The authenticated caller of a request
+#[derive(Debug, Clone)]
+pub enum Caller {
+    User {
+        user_id: Uuid,
+        role: String,
+        tenants: TenantScope,
+        /// Holds one of AUDIT_TENANT_ADMIN_ROLES
+        tenant_admin: bool,
+        /// Restricted resource types whose events the user may not see
+        hidden: Arc<Vec<String>>,
+    },
+    /// Internal producer presenting one of AUDIT_SERVICE_TOKENS
+    Service { name: String },
+    /// Internal producer presenting an X-Api-Key; only creates events
+    ApiKey {
+        key_id: Uuid,
+        service: String,
+        tenants: Vec<Uuid>,
+    },
+}
+
+impl Caller {
+    pub fn may_access(&self, tenant_id: Uuid) -> bool {
+        match self {
+            Caller::Service { .. } => true,
+            Caller::User { tenants: TenantScope::All, .. } => true,
+            Caller::User { tenants: TenantScope::Only(tenants), .. } | Caller::ApiKey { tenants, .. } => {
+                tenants.contains(&tenant_id)
+            }
+        }
+    }
+
+    /// Whether events of `resource_type` are visible to the caller
+    pub fn may_see(&self, resource_type: &str) -> bool {
+        match self {
+            Caller::Service { .. } | Caller::ApiKey { .. } => true,
+            Caller::User { hidden, .. } => !hidden.iter().any(|hidden| hidden == resource_type),
+        }
+    }
+
+    /// Resource types to leave out of listings; empty for services and AUDIT_RESTRICTED_ROLES
+    pub fn hidden_resource_types(&self) -> Vec<String> {
+        match self {
+            Caller::Service { .. } | Caller::ApiKey { .. } => Vec::new(),
+            Caller::User { hidden, .. } => hidden.as_ref().clone(),
+        }
+    }
+
+    /// The producing service recorded on events the caller creates; `None` for users
+    pub fn producer(&self) -> Option<&str> {
+        match self {
+            Caller::Service { name } => Some(name),
+            Caller::ApiKey { service, .. } => Some(service),
+            Caller::User { .. } => None,
+        }
+    }
+
+    /// Services and platform roles, which are not bound to a tenant
+    fn is_platform(&self) -> bool {
+        matches!(self, Caller::Service { .. } | Caller::User { tenants: TenantScope::All, .. })
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Caller::User { user_id, role, .. } => format!("user {} ({})", user_id, role),
+            Caller::Service { name } => format!("service {}", name),
+            Caller::ApiKey { key_id, service, .. } => format!("service {} (API key {})", service, key_id),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Only missing on routes outside the authorize middleware
+        parts.extensions.get::<Caller>().cloned().ok_or(StatusCode::UNAUTHORIZED)
+    }
+}
+
+pub struct Authenticator {
+    decoding_key: DecodingKey,
+    /// Name and SHA-256 of each service token
+    service_tokens: Vec<(String, [u8; 32])>,
+    platform_roles: HashSet<String>,
+    tenant_admin_roles: HashSet<String>,
+    restricted_resource_types: Arc<Vec<String>>,
+    /// Roles that see events of restricted resource types
+    restricted_roles: HashSet<String>,
+}
+
+impl Authenticator {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let roles = |value: String| value.split(',').map(|role| role.trim().to_uppercase()).filter(|r| !r.is_empty()).collect();
+
+        let secret = var("JWT_SECRET").ok_or_else(|| anyhow::anyhow!("JWT_SECRET must be set to authenticate callers"))?;
+        let mut service_tokens = Vec::new();
+        // Comma-separated name:token pairs, like AUDIT_RETIRED_SIGNING_KEYS
+        for entry in var("AUDIT_SERVICE_TOKENS").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            match entry.trim().split_once(':') {
+                Some((name, token)) if !name.is_empty() && token.len() >= 32 => {
+                    service_tokens.push((name.to_string(), Sha256::digest(token.as_bytes()).into()))
+                }
+                _ => warn!("Ignoring malformed AUDIT_SERVICE_TOKENS entry; tokens need at least 32 characters"),
+            }
+        }
+        if service_tokens.is_empty() {
+            warn!("AUDIT_SERVICE_TOKENS is not set; only user tokens are accepted and gRPC ingestion is refused");
+        }
+
+        Ok(Self {
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            service_tokens,
+            platform_roles: roles(var("AUDIT_PLATFORM_ROLES").unwrap_or_else(|| "SUPER_ADMIN".to_string())),
+            tenant_admin_roles: roles(
+                var("AUDIT_TENANT_ADMIN_ROLES").unwrap_or_else(|| "TENANT_ADMIN,COMPLIANCE_OFFICER".to_string()),
+            ),
+            restricted_resource_types: Arc::new(
+                var("AUDIT_RESTRICTED_RESOURCE_TYPES")
+                    .unwrap_or_else(|| "STR_FILING,INSIDER_WATCHLIST".to_string())
+                    .split(',')
+                    .map(|resource_type| resource_type.trim().to_string())
+                    .filter(|resource_type| !resource_type.is_empty())
+                    .collect(),
+            ),
+            restricted_roles: roles(
+                var("AUDIT_RESTRICTED_ROLES").unwrap_or_else(|| "SUPER_ADMIN,TENANT_ADMIN,COMPLIANCE_OFFICER".to_string()),
+            ),
+        })
+    }
+
+    fn service(&self, token: &str) -> Option<Caller> {
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        self.service_tokens
+            .iter()
+            .find(|(_, known)| *known == digest)
+            .map(|(name, _)| Caller::Service { name: name.clone() })
+    }
+
+    pub fn authenticate(&self, token: &str) -> Option<Caller> {
+        if let Some(service) = self.service(token) {
+            return Some(service);
+        }
+        let claims = match jsonwebtoken::decode::<Claims>(token, &self.decoding_key, &Validation::new(Algorithm::HS256)) {
+            Ok(data) => data.claims,
+            Err(e) => {
+                warn!("Rejected bearer token on audit API: {}", e);
+                return None;
+            }
+        };
+        let role = claims.role.to_uppercase();
+        let tenants = if self.platform_roles.contains(&role) {
+            TenantScope::All
+        } else {
+            let mut tenants = claims.tenant_ids;
+            tenants.push(claims.tenant_id);
+            tenants.sort();
+            tenants.dedup();
+            TenantScope::Only(tenants)
+        };
+        let hidden = if self.restricted_roles.contains(&role) {
+            Arc::new(Vec::new())
+        } else {
+            self.restricted_resource_types.clone()
+        };
+        Some(Caller::User {
+            user_id: claims.sub,
+            tenant_admin: self.tenant_admin_roles.contains(&role),
+            role,
+            tenants,
+            hidden,
+        })
+    }
+
+    /// Interceptor for the gRPC ingestion server, which only internal producers may call
+    pub fn grpc_interceptor(
+        self: Arc<Self>,
+    ) -> impl Fn(tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> + Clone {
+        move |request: tonic::Request<()>| {
+            let caller = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .and_then(|token| self.service(token.trim()));
+            match caller {
+                Some(caller) => {
+                    let mut request = request;
+                    request.extensions_mut().insert(caller);
+                    Ok(request)
+                }
+                None => {
+                    counter!("audit_auth_rejected_total", 1, "reason" => "grpc_unauthenticated");
+                    Err(tonic::Status::unauthenticated("a service token is required"))
+                }
+            }
+        }
+    }
+}
+
+fn reject(status: StatusCode, reason: &'static str, message: &str) -> Response {
+    counter!("audit_auth_rejected_total", 1, "reason" => reason);
+    let mut response = (status, Json(serde_json::json!({"error": message}))).into_response();
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    response
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Routes an API key may call: event creation in every API version
+fn is_ingestion(method: &Method, path: &str) -> bool {
+    *method == Method::POST && path.ends_with("/audit/events")
+}
+
+/// The `:tenant_id` of `/admin/tenants/:tenant_id/...`; `Err` when it is not a UUID
+fn path_tenant(path: &str) -> Result<Option<Uuid>, ()> {
+    let mut segments = path.split('/');
+    while let Some(segment) = segments.next() {
+        if segment == "tenants" {
+            return match segments.next() {
+                Some(tenant) => tenant.parse().map(Some).map_err(|_| ()),
+                None => Ok(None),
+            };
+        }
+    }
+    Ok(None)
+}
+
+/// The `:resource_type` of `/audit/trail/...` and `/audit/custody/...`
+fn path_resource_type(path: &str) -> Option<&str> {
+    let mut segments = path.split('/');
+    while let Some(segment) = segments.next() {
+        if segment == "audit" {
+            return match segments.next() {
+                Some("trail" | "custody") => segments.next(),
+                _ => None,
+            };
+        }
+    }
+    None
+}
+
+/// Top-level `tenant_id`s of a JSON object, or of each object in a JSON array
+fn body_tenants(body: &serde_json::Value) -> Vec<Option<Uuid>> {
+    let tenant = |value: &serde_json::Value| {
+        value
+            .get("tenant_id")
+            .and_then(|tenant| tenant.as_str())
+            .map(|tenant| tenant.parse().ok())
+    };
+    match body {
+        serde_json::Value::Array(items) => items.iter().filter_map(tenant).collect(),
+        other => tenant(other).into_iter().collect(),
+    }
+}
+
+/// Authenticate the caller and refuse requests outside its tenants and roles
+pub async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let api_key = request.headers().get("x-api-key").and_then(|value| value.to_str().ok()).map(str::trim);
+    let caller = match api_key {
+        Some(_) if !is_ingestion(request.method(), &path) => {
+            return reject(StatusCode::FORBIDDEN, "api_key_scope", "API keys may only create audit events");
+        }
+        Some(key) => match state.api_keys.authenticate(key).await {
+            Ok(caller) => caller,
+            Err(e) => {
+                error!("Failed to look up API key: {}", e);
+                return reject(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "api_key_lookup",
+                    "API keys cannot be checked right now",
+                );
+            }
+        },
+        None => bearer(request.headers()).and_then(|token| state.authenticator.authenticate(token)),
+    };
+    let Some(caller) = caller else {
+        return reject(StatusCode::UNAUTHORIZED, "unauthenticated", "a valid bearer token or API key is required");
+    };
+    let refuse = |tenant_id: Uuid| {
+        warn!("Refused {} access to tenant {} on {}", caller.describe(), tenant_id, path);
+        reject(StatusCode::FORBIDDEN, "cross_tenant", "not permitted for this tenant")
+    };
+
+    let Ok(path_tenant) = path_tenant(&path) else {
+        return reject(StatusCode::BAD_REQUEST, "invalid_tenant", "invalid tenant_id");
+    };
+    if path.contains("/admin/") && !caller.is_platform() {
+        let own_tenant_admin = matches!((&caller, path_tenant), (Caller::User { tenant_admin: true, .. }, Some(t)) if caller.may_access(t));
+        if !own_tenant_admin {
+            return reject(StatusCode::FORBIDDEN, "role", "this endpoint needs a platform or tenant admin role");
+        }
+    }
+    if let Some(tenant_id) = path_tenant.filter(|tenant_id| !caller.may_access(*tenant_id)) {
+        return refuse(tenant_id);
+    }
+
+    let restricted = |resource_type: &str| {
+        warn!("Refused {} access to restricted {} events on {}", caller.describe(), resource_type, path);
+        reject(StatusCode::FORBIDDEN, "restricted_resource", "events of this resource type are restricted")
+    };
+    if let Some(resource_type) = path_resource_type(&path).filter(|resource_type| !caller.may_see(resource_type)) {
+        return restricted(resource_type);
+    }
+
+    if let Ok(Query(params)) = Query::<HashMap<String, String>>::try_from_uri(request.uri()) {
+        if let Some(tenant) = params.get("tenant_id") {
+            match tenant.parse::<Uuid>() {
+                Ok(tenant_id) if !caller.may_access(tenant_id) => return refuse(tenant_id),
+                Ok(_) => {}
+                Err(_) => return reject(StatusCode::BAD_REQUEST, "invalid_tenant", "invalid tenant_id"),
+            }
+        }
+        if let Some(resource_type) = params.get("resource_type").filter(|resource_type| !caller.may_see(resource_type)) {
+            return restricted(resource_type);
+        }
+    }
+
+    let inspected =
+        is_json(request.headers()) && matches!(*request.method(), Method::POST | Method::PUT | Method::PATCH);
+    let request = if inspected {
+        let (parts, body) = request.into_parts();
+        let Ok(bytes) = to_bytes(body, MAX_INSPECTED_BODY).await else {
+            return reject(StatusCode::PAYLOAD_TOO_LARGE, "body_too_large", "request body is too large");
+        };
+        // Malformed bodies are left for the handler's extractor to reject
+        if let Ok(body) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+            for tenant in body_tenants(&body) {
+                match tenant {
+                    Some(tenant_id) if !caller.may_access(tenant_id) => return refuse(tenant_id),
+                    Some(_) => {}
+                    None => return reject(StatusCode::BAD_REQUEST, "invalid_tenant", "invalid tenant_id"),
+                }
+            }
+            // Users record their own actions; only services attribute events to others
+            if let (Caller::User { user_id, .. }, true) = (&caller, path.ends_with("/audit/events")) {
+                let acting_for = body.get("user_id").and_then(|user| user.as_str()).map(|user| user.parse::<Uuid>());
+                if matches!(acting_for, Some(Ok(other)) if other != *user_id) {
+                    return reject(StatusCode::FORBIDDEN, "impersonation", "events may only be recorded for the caller");
+                }
+            }
+        }
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        request
+    };
+
+    let mut request = request;
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
@@ -0,0 +1,155 @@
+//! Kafka transport
+//!
+//! The `kafka` client is synchronous, so publishing and consuming run on
+//! blocking threads. Offsets are committed to the group after each message
+//! set; a failing message is retried in place with backoff and then published
+//! to `<topic>.dlq`, wrapped with its source offset and last error.
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use kafka::client::KafkaClient;
+use kafka::consumer::{Consumer, FetchOffset, GroupOffsetStorage};
+use kafka::producer::{Producer, Record, RequiredAcks};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use super::{dead_letter_topic, BusEvent, BusSettings, EventBus, EventHandler};
+
+pub struct KafkaBus {
+    brokers: Vec<String>,
+    producer: Arc<Mutex<Producer>>,
+    settings: BusSettings,
+}
+
+impl KafkaBus {
+    pub fn new(brokers: &str, settings: BusSettings) -> anyhow::Result<Self> {
+        let brokers: Vec<String> = brokers.split(',').map(|b| b.trim().to_string()).collect();
+        let producer = Producer::from_hosts(brokers.clone())
+            .with_ack_timeout(Duration::from_secs(5))
+            .with_required_acks(RequiredAcks::One)
+            .create()?;
+        Ok(Self {
+            brokers,
+            producer: Arc::new(Mutex::new(producer)),
+            settings,
+        })
+    }
+}
+
+fn send(producer: &Mutex<Producer>, topic: &str, key: &[u8], payload: &[u8]) -> anyhow::Result<()> {
+    producer
+        .lock()
+        .map_err(|_| anyhow::anyhow!("kafka producer lock poisoned"))?
+        .send(&Record::from_key_value(topic, key, payload))?;
+    Ok(())
+}
+
+#[async_trait]
+impl EventBus for KafkaBus {
+    fn transport(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        let brokers = self.brokers.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut client = KafkaClient::new(brokers);
+            client.load_metadata_all()?;
+            Ok(())
+        })
+        .await?
+    }
+
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8]) -> anyhow::Result<()> {
+        let producer = self.producer.clone();
+        let (topic, key, payload) = (topic.to_string(), key.to_string(), payload.to_vec());
+        tokio::task::spawn_blocking(move || send(&producer, &topic, key.as_bytes(), &payload)).await?
+    }
+
+    async fn consume(
+        &self,
+        topic: &str,
+        group: &str,
+        consumer: &str,
+        handler: Arc<dyn EventHandler>,
+    ) -> anyhow::Result<()> {
+        let mut kafka_consumer = Consumer::from_hosts(self.brokers.clone())
+            .with_topic(topic.to_string())
+            .with_group(group.to_string())
+            .with_client_id(consumer.to_string())
+            .with_fallback_offset(FetchOffset::Earliest)
+            .with_offset_storage(Some(GroupOffsetStorage::Kafka))
+            .create()?;
+        info!("Consuming Kafka topic {} as {}/{}", topic, group, consumer);
+
+        let runtime = tokio::runtime::Handle::current();
+        let producer = self.producer.clone();
+        let settings = self.settings.clone();
+        let topic = topic.to_string();
+
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            loop {
+                for message_set in kafka_consumer.poll()?.iter() {
+                    for message in message_set.messages() {
+                        let mut event = BusEvent {
+                            id: format!("{}/{}", message_set.partition(), message.offset),
+                            topic: topic.clone(),
+                            key: (!message.key.is_empty()).then(|| String::from_utf8_lossy(message.key).into_owned()),
+                            payload: message.value.to_vec(),
+                            delivery: 0,
+                        };
+
+                        let mut last_error = None;
+                        while event.delivery < settings.max_deliveries {
+                            event.delivery += 1;
+                            match runtime.block_on(handler.handle(&event)) {
+                                Ok(()) => {
+                                    last_error = None;
+                                    break;
+                                }
+                                Err(e) => {
+                                    warn!(
+                                        "Handler failed for {} message {} (delivery {} of {}): {}",
+                                        topic, event.id, event.delivery, settings.max_deliveries, e
+                                    );
+                                    last_error = Some(e.to_string());
+                                    if event.delivery < settings.max_deliveries {
+                                        std::thread::sleep(backoff(event.delivery, &settings));
+                                    }
+                                }
+                            }
+                        }
+
+                        if let Some(last_error) = last_error {
+                            let envelope = serde_json::json!({
+                                "source_topic": topic,
+                                "source_id": event.id,
+                                "key": event.key,
+                                "deliveries": event.delivery,
+                                "error": last_error,
+                                "payload": STANDARD.encode(&event.payload),
+                            });
+                            send(
+                                &producer,
+                                &dead_letter_topic(&topic),
+                                message.key,
+                                envelope.to_string().as_bytes(),
+                            )?;
+                            error!("Moved {} message {} to {}", topic, event.id, dead_letter_topic(&topic));
+                        }
+                    }
+                    kafka_consumer.consume_messageset(message_set)?;
+                }
+                kafka_consumer.commit_consumed()?;
+            }
+        })
+        .await?
+    }
+}
+
+/// Exponential backoff between in-place retries, capped at the redelivery interval
+fn backoff(delivery: u32, settings: &BusSettings) -> Duration {
+    let backoff = Duration::from_millis(500u64.saturating_mul(1 << delivery.min(10)));
+    backoff.min(settings.redelivery_after)
+}
@@ -0,0 +1,100 @@
+//! Internal event bus
+//!
+//! Services publish to and consume from named topics through `EventBus`; the
+//! transport is chosen with EVENT_BUS. Kafka is the default for full platform
+//! deployments and Redis Streams is a lighter option for deployments without
+//! Kafka. Both give at-least-once delivery to consumer groups: a handler error
+//! leaves the event to be redelivered, and an event that still fails after
+//! EVENT_BUS_MAX_DELIVERIES attempts is moved to the `<topic>.dlq` topic.
+
+pub mod kafka;
+pub mod redis_streams;
+
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Created audit events, for downstream consumers
+pub const AUDIT_EVENTS_TOPIC: &str = "audit.events";
+
+/// Audit events submitted by other services over the bus instead of HTTP/gRPC
+pub const AUDIT_INGEST_TOPIC: &str = "audit.ingest";
+
+#[derive(Debug, Clone)]
+pub struct BusEvent {
+    /// Transport position (stream entry id or partition/offset)
+    pub id: String,
+    pub topic: String,
+    pub key: Option<String>,
+    pub payload: Vec<u8>,
+    /// 1 on first delivery
+    pub delivery: u32,
+}
+
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    /// An error leaves the event unacknowledged so it is redelivered
+    async fn handle(&self, event: &BusEvent) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    fn transport(&self) -> &'static str;
+
+    /// Confirm the transport is reachable, for startup readiness
+    async fn ping(&self) -> anyhow::Result<()>;
+
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8]) -> anyhow::Result<()>;
+
+    /// Consume `topic` as `consumer` within `group` until the transport fails
+    async fn consume(
+        &self,
+        topic: &str,
+        group: &str,
+        consumer: &str,
+        handler: Arc<dyn EventHandler>,
+    ) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub struct BusSettings {
+    pub max_deliveries: u32,
+    /// How long a delivered event may stay unacknowledged before it is redelivered
+    pub redelivery_after: Duration,
+}
+
+impl BusSettings {
+    fn from_env() -> Self {
+        let number = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_deliveries: number("EVENT_BUS_MAX_DELIVERIES", 5).max(1) as u32,
+            redelivery_after: Duration::from_millis(number("EVENT_BUS_REDELIVERY_MS", 60_000)),
+        }
+    }
+}
+
+pub fn dead_letter_topic(topic: &str) -> String {
+    format!("{}.dlq", topic)
+}
+
+/// Build the configured bus: EVENT_BUS=kafka|redis; unset or `none` disables it
+pub async fn from_env() -> anyhow::Result<Option<Arc<dyn EventBus>>> {
+    let settings = BusSettings::from_env();
+    match std::env::var("EVENT_BUS").unwrap_or_default().to_ascii_lowercase().as_str() {
+        "" | "none" => Ok(None),
+        "kafka" => {
+            let brokers = std::env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".to_string());
+            Ok(Some(Arc::new(kafka::KafkaBus::new(&brokers, settings)?)))
+        }
+        "redis" => {
+            let redis_url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
+            Ok(Some(Arc::new(redis_streams::RedisStreamsBus::connect(&redis_url, settings).await?)))
+        }
+        other => anyhow::bail!("unknown EVENT_BUS transport '{}'", other),
+    }
+}
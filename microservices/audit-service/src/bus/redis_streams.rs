@@ -0,0 +1,229 @@
+//! Redis Streams transport
+//!
+//! Each topic is a stream with `key` and `payload` fields and each consumer
+//! group a stream consumer group. Events are acknowledged with XACK once the
+//! handler succeeds. Failed or abandoned events stay in the group's pending
+//! list; after `redelivery_after` any consumer in the group claims them with
+//! XCLAIM. Once an event's delivery count reaches `max_deliveries` it is
+//! copied to `<topic>.dlq` with its last error and acknowledged.
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::streams::{
+    StreamClaimReply, StreamId, StreamMaxlen, StreamPendingCountReply, StreamRangeReply, StreamReadOptions,
+    StreamReadReply,
+};
+use redis::AsyncCommands;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+use super::{dead_letter_topic, BusEvent, BusSettings, EventBus, EventHandler};
+
+/// Approximate number of entries kept per stream
+const STREAM_MAXLEN: usize = 1_000_000;
+
+/// Entries read or claimed per round trip
+const BATCH_SIZE: usize = 100;
+
+/// How long XREADGROUP blocks waiting for new entries
+const BLOCK_MS: usize = 5_000;
+
+pub struct RedisStreamsBus {
+    client: redis::Client,
+    /// Shared connection for publishing; consumers open their own because XREADGROUP blocks
+    publisher: ConnectionManager,
+    settings: BusSettings,
+}
+
+impl RedisStreamsBus {
+    pub async fn connect(redis_url: &str, settings: BusSettings) -> anyhow::Result<Self> {
+        let client = redis::Client::open(redis_url)?;
+        let publisher = ConnectionManager::new(client.clone()).await?;
+        Ok(Self {
+            client,
+            publisher,
+            settings,
+        })
+    }
+
+    async fn ensure_group(&self, con: &mut ConnectionManager, topic: &str, group: &str) -> anyhow::Result<()> {
+        // Start from the beginning so events published before the group existed are not lost
+        match con.xgroup_create_mkstream::<_, _, _, ()>(topic, group, "0").await {
+            Ok(()) => {
+                info!("Created consumer group {} on stream {}", group, topic);
+                Ok(())
+            }
+            Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Claim events idle for longer than `redelivery_after`, dead-lettering exhausted ones
+    async fn reclaim(
+        &self,
+        con: &mut ConnectionManager,
+        topic: &str,
+        group: &str,
+        consumer: &str,
+        handler: &Arc<dyn EventHandler>,
+    ) -> anyhow::Result<()> {
+        let idle_ms = self.settings.redelivery_after.as_millis() as usize;
+        let pending: StreamPendingCountReply = redis::cmd("XPENDING")
+            .arg(topic)
+            .arg(group)
+            .arg("IDLE")
+            .arg(idle_ms)
+            .arg("-")
+            .arg("+")
+            .arg(BATCH_SIZE)
+            .query_async(con)
+            .await?;
+
+        for entry in pending.ids {
+            if entry.times_delivered as u32 >= self.settings.max_deliveries {
+                self.dead_letter(con, topic, group, &entry.id, entry.times_delivered as u32).await?;
+                continue;
+            }
+
+            // XCLAIM only succeeds if the entry is still idle, so consumers never claim the same entry twice
+            let claimed: StreamClaimReply = con.xclaim(topic, group, consumer, idle_ms, &[&entry.id]).await?;
+            for stream_id in claimed.ids {
+                self.deliver(con, topic, group, stream_id, entry.times_delivered as u32 + 1, handler)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn deliver(
+        &self,
+        con: &mut ConnectionManager,
+        topic: &str,
+        group: &str,
+        stream_id: StreamId,
+        delivery: u32,
+        handler: &Arc<dyn EventHandler>,
+    ) -> anyhow::Result<()> {
+        let event = BusEvent {
+            id: stream_id.id.clone(),
+            topic: topic.to_string(),
+            key: stream_id.get::<String>("key"),
+            payload: stream_id.get::<Vec<u8>>("payload").unwrap_or_default(),
+            delivery,
+        };
+
+        match handler.handle(&event).await {
+            Ok(()) => {
+                con.xack::<_, _, _, ()>(topic, group, &[&event.id]).await?;
+                con.hdel::<_, _, ()>(error_key(topic, group), &event.id).await?;
+            }
+            Err(e) => {
+                warn!(
+                    "Handler failed for {} entry {} (delivery {} of {}): {}",
+                    topic, event.id, delivery, self.settings.max_deliveries, e
+                );
+                // Kept for the dead-letter entry; the event itself stays pending for redelivery
+                con.hset::<_, _, _, ()>(error_key(topic, group), &event.id, e.to_string()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn dead_letter(
+        &self,
+        con: &mut ConnectionManager,
+        topic: &str,
+        group: &str,
+        id: &str,
+        deliveries: u32,
+    ) -> anyhow::Result<()> {
+        let range: StreamRangeReply = con.xrange(topic, id, id).await?;
+        let last_error: Option<String> = con.hget(error_key(topic, group), id).await?;
+
+        if let Some(entry) = range.ids.into_iter().next() {
+            let key = entry.get::<String>("key").unwrap_or_default();
+            let payload = entry.get::<Vec<u8>>("payload").unwrap_or_default();
+            let deliveries = deliveries.to_string();
+            let last_error = last_error.unwrap_or_default();
+            con.xadd_maxlen::<_, _, _, _, ()>(
+                dead_letter_topic(topic),
+                StreamMaxlen::Approx(STREAM_MAXLEN),
+                "*",
+                &[
+                    ("key", key.as_bytes()),
+                    ("payload", payload.as_slice()),
+                    ("source_id", id.as_bytes()),
+                    ("group", group.as_bytes()),
+                    ("deliveries", deliveries.as_bytes()),
+                    ("error", last_error.as_bytes()),
+                ],
+            )
+            .await?;
+            error!("Moved {} entry {} to {} after {} deliveries", topic, id, dead_letter_topic(topic), deliveries);
+        } else {
+            // Trimmed from the stream before it could be handled
+            error!("Dropping {} entry {} that no longer exists after {} deliveries", topic, id, deliveries);
+        }
+
+        con.xack::<_, _, _, ()>(topic, group, &[id]).await?;
+        con.hdel::<_, _, ()>(error_key(topic, group), id).await?;
+        Ok(())
+    }
+}
+
+fn error_key(topic: &str, group: &str) -> String {
+    format!("{}:{}:errors", topic, group)
+}
+
+#[async_trait]
+impl EventBus for RedisStreamsBus {
+    fn transport(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        let mut con = self.publisher.clone();
+        redis::cmd("PING").query_async::<_, String>(&mut con).await?;
+        Ok(())
+    }
+
+    async fn publish(&self, topic: &str, key: &str, payload: &[u8]) -> anyhow::Result<()> {
+        let mut con = self.publisher.clone();
+        con.xadd_maxlen::<_, _, _, _, ()>(
+            topic,
+            StreamMaxlen::Approx(STREAM_MAXLEN),
+            "*",
+            &[("key", key.as_bytes()), ("payload", payload)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn consume(
+        &self,
+        topic: &str,
+        group: &str,
+        consumer: &str,
+        handler: Arc<dyn EventHandler>,
+    ) -> anyhow::Result<()> {
+        let mut con = ConnectionManager::new(self.client.clone()).await?;
+        self.ensure_group(&mut con, topic, group).await?;
+        info!("Consuming Redis stream {} as {}/{}", topic, group, consumer);
+
+        let options = StreamReadOptions::default()
+            .group(group, consumer)
+            .count(BATCH_SIZE)
+            .block(BLOCK_MS);
+
+        loop {
+            self.reclaim(&mut con, topic, group, consumer, &handler).await?;
+
+            let reply: StreamReadReply = con.xread_options(&[topic], &[">"], &options).await?;
+            for stream in reply.keys {
+                for stream_id in stream.ids {
+                    self.deliver(&mut con, topic, group, stream_id, 1, &handler).await?;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! In-process read cache for audit events and verification results
+//!
+//! Dashboards poll the same recent events and their verification status over
+//! and over; each miss costs a MongoDB read and, for verification, an IPFS
+//! fetch and a chain lookup. Entries live in one bounded LRU partition per
+//! tenant, so a busy tenant evicts only its own entries, and the set of
+//! partitions is itself bounded. Events are cached as stored, so personal
+//! data and envelope-encrypted values are still revealed per reader.
+//!
+//! Stored events change only through re-signing, which invalidates them as it
+//! goes. Verification results also depend on IPFS and the chain and so expire
+//! quickly. AUDIT_CACHE_EVENTS_PER_TENANT=0 disables the cache.
+
+use metrics::counter;
+use moka::future::Cache;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::integrity::VerificationReport;
+use crate::AuditEvent;
+
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+    pub max_tenants: u64,
+    pub entries_per_tenant: u64,
+    pub event_ttl: Duration,
+    pub verification_ttl: Duration,
+}
+
+impl CacheSettings {
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            max_tenants: var("AUDIT_CACHE_MAX_TENANTS", 1000),
+            entries_per_tenant: var("AUDIT_CACHE_EVENTS_PER_TENANT", 5000),
+            event_ttl: Duration::from_secs(var("AUDIT_CACHE_EVENT_TTL_SECS", 900)),
+            verification_ttl: Duration::from_secs(var("AUDIT_CACHE_VERIFICATION_TTL_SECS", 60)),
+        }
+    }
+}
+
+struct Partition {
+    events: Cache<Uuid, Arc<AuditEvent>>,
+    verifications: Cache<Uuid, Arc<VerificationReport>>,
+}
+
+pub struct AuditCache {
+    settings: CacheSettings,
+    partitions: Cache<Uuid, Arc<Partition>>,
+    /// Tenant of each cached event, for lookups by event id alone
+    tenant_of: Cache<Uuid, Uuid>,
+}
+
+impl AuditCache {
+    pub fn new(settings: CacheSettings) -> Self {
+        let partitions = Cache::builder().max_capacity(settings.max_tenants).build();
+        let tenant_of = Cache::builder()
+            .max_capacity(settings.max_tenants.saturating_mul(settings.entries_per_tenant))
+            .time_to_live(settings.event_ttl)
+            .build();
+        Self {
+            settings,
+            partitions,
+            tenant_of,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.settings.entries_per_tenant > 0 && self.settings.max_tenants > 0
+    }
+
+    async fn partition(&self, tenant_id: Uuid) -> Arc<Partition> {
+        self.partitions
+            .get_with(tenant_id, async {
+                Arc::new(Partition {
+                    events: Cache::builder()
+                        .max_capacity(self.settings.entries_per_tenant)
+                        .time_to_live(self.settings.event_ttl)
+                        .build(),
+                    verifications: Cache::builder()
+                        .max_capacity(self.settings.entries_per_tenant)
+                        .time_to_live(self.settings.verification_ttl)
+                        .build(),
+                })
+            })
+            .await
+    }
+
+    pub async fn event(&self, event_id: Uuid) -> Option<Arc<AuditEvent>> {
+        if !self.enabled() {
+            return None;
+        }
+        let event = match self.tenant_of.get(&event_id).await {
+            Some(tenant_id) => match self.partitions.get(&tenant_id).await {
+                Some(partition) => partition.events.get(&event_id).await,
+                None => None,
+            },
+            None => None,
+        };
+        record("event", event.is_some());
+        event
+    }
+
+    pub async fn insert_event(&self, event: AuditEvent) -> Arc<AuditEvent> {
+        let event = Arc::new(event);
+        if self.enabled() {
+            self.tenant_of.insert(event.event_id, event.tenant_id).await;
+            self.partition(event.tenant_id)
+                .await
+                .events
+                .insert(event.event_id, event.clone())
+                .await;
+        }
+        event
+    }
+
+    pub async fn verification(&self, tenant_id: Uuid, event_id: Uuid) -> Option<Arc<VerificationReport>> {
+        if !self.enabled() {
+            return None;
+        }
+        let report = match self.partitions.get(&tenant_id).await {
+            Some(partition) => partition.verifications.get(&event_id).await,
+            None => None,
+        };
+        record("verification", report.is_some());
+        report
+    }
+
+    pub async fn insert_verification(&self, tenant_id: Uuid, report: VerificationReport) -> Arc<VerificationReport> {
+        let report = Arc::new(report);
+        if self.enabled() {
+            self.partition(tenant_id)
+                .await
+                .verifications
+                .insert(report.event_id, report.clone())
+                .await;
+        }
+        report
+    }
+
+    /// Drop an event whose stored copy changed, with its verification result
+    pub async fn invalidate_event(&self, tenant_id: Uuid, event_id: Uuid) {
+        self.tenant_of.invalidate(&event_id).await;
+        if let Some(partition) = self.partitions.get(&tenant_id).await {
+            partition.events.invalidate(&event_id).await;
+            partition.verifications.invalidate(&event_id).await;
+        }
+    }
+
+    /// Drop every verification result, e.g. once a signing key is compromised
+    pub fn invalidate_verifications(&self) {
+        for (_, partition) in self.partitions.iter() {
+            partition.verifications.invalidate_all();
+        }
+    }
+}
+
+fn record(cache: &'static str, hit: bool) {
+    counter!(
+        "audit_cache_requests_total",
+        1,
+        "cache" => cache,
+        "result" => if hit { "hit" } else { "miss" }
+    );
+}
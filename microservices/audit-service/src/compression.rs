@@ -0,0 +1,85 @@
+//! zstd compression of large audit payloads
+//!
+//! Events carrying whole KYC documents or order books in old_values and
+//! new_values make for large MongoDB documents and IPFS objects. Once a
+//! payload is over AUDIT_COMPRESSION_THRESHOLD_BYTES (0 turns compression
+//! off) it is compressed with zstd at AUDIT_COMPRESSION_LEVEL before it is
+//! stored, and the codec is recorded next to it: `values_codec` in the MongoDB
+//! document, whose values are then kept in `values_compressed`, and
+//! ipfs_pins.codec for the IPFS copy, which is compressed before it is
+//! encrypted. Reads decompress according to the recorded codec, so nothing
+//! else sees a difference, and payloads stored before compression, or under
+//! the threshold, are read as they are.
+//!
+//! Hashes always cover the uncompressed payload. Postgres documents are left
+//! alone, as Postgres already compresses large JSONB values itself.
+
+use metrics::counter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Zstd,
+}
+
+impl Codec {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Codec::Zstd => "zstd",
+        }
+    }
+
+    pub fn parse(codec: &str) -> anyhow::Result<Self> {
+        match codec {
+            "zstd" => Ok(Codec::Zstd),
+            other => anyhow::bail!("unknown payload codec '{}'", other),
+        }
+    }
+
+    pub fn decompress(self, compressed: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Codec::Zstd => Ok(zstd::stream::decode_all(compressed)?),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Compression {
+    /// Payloads larger than this are compressed; 0 when compression is off
+    threshold: usize,
+    level: i32,
+}
+
+impl Compression {
+    pub fn from_env() -> Self {
+        Self {
+            threshold: std::env::var("AUDIT_COMPRESSION_THRESHOLD_BYTES")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(16 * 1024),
+            level: std::env::var("AUDIT_COMPRESSION_LEVEL")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(3),
+        }
+    }
+
+    /// Whether a payload of `len` bytes is stored compressed
+    pub fn applies(&self, len: usize) -> bool {
+        self.threshold > 0 && len > self.threshold
+    }
+
+    /// `payload` compressed, or `None` when it is under the threshold
+    pub fn compress(&self, store: &'static str, payload: &[u8]) -> anyhow::Result<Option<(Codec, Vec<u8>)>> {
+        if !self.applies(payload.len()) {
+            return Ok(None);
+        }
+        let compressed = zstd::stream::encode_all(payload, self.level)?;
+        counter!("audit_payloads_compressed_total", 1, "store" => store);
+        counter!(
+            "audit_compression_saved_bytes_total",
+            payload.len().saturating_sub(compressed.len()) as u64,
+            "store" => store
+        );
+        Ok(Some((Codec::Zstd, compressed)))
+    }
+}
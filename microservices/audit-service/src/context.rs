@@ -0,0 +1,142 @@
+//! Client context (IP, user agent, request id, producer) captured from incoming requests
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, HeaderMap},
+};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::auth::Caller;
+use crate::AppState;
+
+/// Proxies whose X-Forwarded-For entries are believed
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parse a comma-separated list of addresses and CIDR ranges
+    pub fn parse(spec: &str) -> Self {
+        let mut networks = Vec::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (addr, prefix) = entry.split_once('/').unwrap_or((entry, ""));
+            let Ok(addr) = addr.parse::<IpAddr>() else {
+                warn!("Ignoring malformed trusted proxy entry: {}", entry);
+                continue;
+            };
+            let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix = if prefix.is_empty() { Ok(max_prefix) } else { prefix.parse::<u8>() };
+            match prefix {
+                Ok(prefix) if prefix <= max_prefix => networks.push((addr, prefix)),
+                _ => warn!("Ignoring malformed trusted proxy entry: {}", entry),
+            }
+        }
+        Self { networks }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|&(network, prefix)| match (network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        })
+    }
+}
+
+/// Per-request client details recorded on audit events
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<Uuid>,
+    /// Internal service that sent the request; `None` for users and the event bus
+    #[serde(default)]
+    pub producer: Option<String>,
+}
+
+impl RequestContext {
+    pub fn new(ip_address: Option<IpAddr>, user_agent: Option<&str>, request_id: Option<&str>) -> Self {
+        Self {
+            ip_address,
+            user_agent: user_agent.map(|value| value.chars().take(512).collect()),
+            // Generated when absent so every event can still be correlated
+            request_id: Some(
+                request_id
+                    .and_then(|value| Uuid::parse_str(value.trim()).ok())
+                    .unwrap_or_else(Uuid::new_v4),
+            ),
+            producer: None,
+        }
+    }
+
+    pub fn with_producer(mut self, producer: Option<&str>) -> Self {
+        self.producer = producer.map(str::to_string);
+        self
+    }
+
+    pub fn from_headers(headers: &HeaderMap, peer: Option<IpAddr>, proxies: &TrustedProxies) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        Self::new(
+            peer.map(|peer| client_ip(headers, peer, proxies)),
+            header("user-agent"),
+            header("x-request-id"),
+        )
+    }
+}
+
+/// Walk X-Forwarded-For from the nearest hop, skipping trusted proxies
+///
+/// Entries are only honoured while every hop so far is trusted, so a client
+/// cannot spoof its address by sending its own X-Forwarded-For header.
+fn client_ip(headers: &HeaderMap, peer: IpAddr, proxies: &TrustedProxies) -> IpAddr {
+    if !proxies.contains(peer) {
+        return peer;
+    }
+
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        client = ip;
+        if !proxies.contains(ip) {
+            break;
+        }
+    }
+    client
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for RequestContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        let producer = parts.extensions.get::<Caller>().and_then(Caller::producer);
+        Ok(Self::from_headers(&parts.headers, peer, &state.trusted_proxies).with_producer(producer))
+    }
+}
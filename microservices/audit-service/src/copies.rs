@@ -0,0 +1,124 @@
+//! Per-tenant encryption of IPFS copies of audit documents
+//!
+//! IPFS serves a document to anyone who learns its CID. With an envelope
+//! master key configured, each event's canonical payload is therefore
+//! encrypted under the tenant's active data key before it is put in the anchor
+//! store (AUDIT_IPFS_ENCRYPT, on by default), bound to the event id, and the
+//! data key's id is recorded in ipfs_pins beside the CID. The CID is then that
+//! of the ciphertext, while event_hash still covers the payload.
+//!
+//! Reads look the key up by CID, so copies written before a key rotation keep
+//! decrypting under their own key, and copies written in clear, before
+//! encryption was enabled or with it turned off, are read as they are.
+//! Payloads over the compression threshold are compressed first, and
+//! decompressed after decryption according to ipfs_pins.codec.
+
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::compression::{Codec, Compression};
+use crate::envelope::Envelope;
+use crate::store::AnchorStore;
+use crate::AuditEvent;
+
+/// A payload as put in the anchor store
+#[derive(Debug, Clone)]
+pub struct StoredCopy {
+    pub cid: String,
+    /// Data key the copy is encrypted under; `None` when it is in clear
+    pub data_key_id: Option<Uuid>,
+    /// Codec the payload is compressed with; `None` when it is not
+    pub codec: Option<Codec>,
+    pub size_bytes: usize,
+}
+
+pub struct DocumentCopies {
+    db: PgPool,
+    anchors: Arc<dyn AnchorStore>,
+    envelope: Option<Arc<Envelope>>,
+    encrypt: bool,
+    compression: Compression,
+}
+
+impl DocumentCopies {
+    pub fn new(db: PgPool, anchors: Arc<dyn AnchorStore>, envelope: Option<Arc<Envelope>>) -> anyhow::Result<Self> {
+        let encrypt = match std::env::var("AUDIT_IPFS_ENCRYPT").as_deref() {
+            Ok("false") => false,
+            Ok("true") | Ok("") | Err(_) => true,
+            Ok(other) => anyhow::bail!("AUDIT_IPFS_ENCRYPT must be true or false, not {}", other),
+        };
+        Ok(Self {
+            db,
+            anchors,
+            envelope,
+            encrypt,
+            compression: Compression::from_env(),
+        })
+    }
+
+    /// Whether new copies are encrypted
+    pub fn encrypts(&self) -> bool {
+        self.encrypt && self.envelope.is_some() && self.anchors.keeps_copies()
+    }
+
+    /// Whether a payload of `len` bytes is put in the anchor store as it is
+    pub fn stores_verbatim(&self, len: usize) -> bool {
+        !self.encrypts() && !self.compression.applies(len)
+    }
+
+    /// Put the payload of `event` in the anchor store; `None` when no copies are kept
+    pub async fn put(&self, event: &AuditEvent, payload: &[u8]) -> anyhow::Result<Option<StoredCopy>> {
+        if !self.anchors.keeps_copies() {
+            return Ok(None);
+        }
+        let (codec, payload) = match self.compression.compress("ipfs", payload)? {
+            Some((codec, compressed)) => (Some(codec), compressed),
+            None => (None, payload.to_vec()),
+        };
+        let (data_key_id, blob) = match &self.envelope {
+            Some(envelope) if self.encrypt => {
+                let (key_id, sealed) = envelope
+                    .encrypt_blob(&self.db, event.tenant_id, event.event_id.as_bytes(), &payload)
+                    .await?;
+                (Some(key_id), sealed)
+            }
+            _ => (None, payload),
+        };
+        Ok(self.anchors.put(&blob).await?.map(|cid| StoredCopy {
+            cid,
+            data_key_id,
+            codec,
+            size_bytes: blob.len(),
+        }))
+    }
+
+    /// The payload stored under `cid` for an event, decrypted and decompressed as it was stored
+    pub async fn retrieve(&self, tenant_id: Uuid, event_id: Uuid, cid: &str) -> anyhow::Result<Vec<u8>> {
+        let blob = self.anchors.retrieve(cid).await?;
+        let (data_key_id, codec): (Option<Uuid>, Option<String>) = sqlx::query_as(
+            "SELECT data_key_id, codec FROM ipfs_pins WHERE cid = $1 AND event_id = $2 \
+             ORDER BY data_key_id IS NULL, codec IS NULL LIMIT 1",
+        )
+        .bind(cid)
+        .bind(event_id)
+        .fetch_optional(&self.db)
+        .await?
+        .unwrap_or((None, None));
+        let payload = match (data_key_id, &self.envelope) {
+            (None, _) => blob,
+            (Some(key_id), Some(envelope)) => envelope
+                .decrypt_blob(&self.db, tenant_id, key_id, event_id.as_bytes(), &blob)
+                .await?,
+            (Some(key_id), None) => anyhow::bail!(
+                "IPFS document {} is encrypted under data key {} and no envelope master key is configured",
+                cid,
+                key_id
+            ),
+        };
+        match codec {
+            Some(codec) => Codec::parse(&codec)?.decompress(&payload),
+            None => Ok(payload),
+        }
+    }
+}
@@ -0,0 +1,585 @@
+//! Signed chain-of-custody reports for a single audited resource
+//!
+//! A report lists every audit event recorded against the resource, oldest
+//! first, with its hash, HMAC signature, IPFS CID, blockchain anchor (per-event
+//! transaction or daily digest) and the outcome of a fresh verification.
+//!
+//! Two Ed25519 signatures with the AUDIT_REPORT_SIGNING_KEY make a report
+//! checkable outside the platform:
+//! - the *statement*, the canonical JSON of the report's contents, is hashed
+//!   and signed, and both are printed in the PDF's attestation section;
+//! - the PDF bytes are signed as well, and the detached signature is returned
+//!   in the X-Custody-Signature header.
+//!
+//! Both are kept in `custody_reports`, so anyone holding a copy can look it up
+//! by the SHA-256 of the file and compare.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use ed25519_dalek::{Signer, SigningKey};
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Row};
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::actors::ActorSnapshot;
+use crate::integrity::sha256_hex;
+use crate::{audit_event_from_row, AuditEvent, AuditService, AUDIT_LOG_COLUMNS};
+
+/// Larger trails should be split by time range into several reports
+pub const MAX_CUSTODY_EVENTS: usize = 5000;
+
+#[derive(Debug, Error)]
+pub enum CustodyError {
+    #[error("no audit events recorded for the resource")]
+    NotFound,
+    #[error("the resource has more than {0} audit events")]
+    TooManyEvents(usize),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("failed to verify audit event {0}: {1}")]
+    Verification(Uuid, String),
+    #[error("failed to render PDF: {0}")]
+    Render(String),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// Ed25519 key chain-of-custody reports are signed with
+pub struct ReportSigner {
+    key_id: String,
+    key: SigningKey,
+}
+
+impl ReportSigner {
+    /// `None` when AUDIT_REPORT_SIGNING_KEY (32 bytes of hex) is unset
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let seed = match std::env::var("AUDIT_REPORT_SIGNING_KEY") {
+            Ok(seed) if !seed.is_empty() => seed,
+            _ => return Ok(None),
+        };
+        let seed: [u8; 32] = hex::decode(seed.trim())?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("AUDIT_REPORT_SIGNING_KEY must be 32 bytes of hex"))?;
+        Ok(Some(Self {
+            key_id: std::env::var("AUDIT_REPORT_SIGNING_KEY_ID").unwrap_or_else(|_| "custody-1".to_string()),
+            key: SigningKey::from_bytes(&seed),
+        }))
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Hex Ed25519 public key recipients verify against
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key.verifying_key().to_bytes())
+    }
+
+    fn sign(&self, data: &[u8]) -> String {
+        hex::encode(self.key.sign(data).to_bytes())
+    }
+}
+
+/// One event's line in the report
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CustodyEntry {
+    pub sequence: usize,
+    pub event_id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub action: String,
+    pub user_id: Option<Uuid>,
+    /// Omitted when absent so statements of earlier reports serialize unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<ActorSnapshot>,
+    pub ip_address: Option<String>,
+    pub event_hash: Option<String>,
+    pub signature: Option<String>,
+    pub signing_key_id: Option<String>,
+    pub ipfs_hash: Option<String>,
+    /// Per-event anchor transaction
+    pub blockchain_hash: Option<String>,
+    /// Daily digest the event was anchored in, for digest-mode deployments
+    pub digest_date: Option<NaiveDate>,
+    pub digest_root: Option<String>,
+    pub digest_transaction: Option<String>,
+    pub verified: bool,
+    pub blockchain_confirmed: bool,
+    pub ipfs_accessible: bool,
+    /// When a time-stamping authority vouched for the hash; absent from statements made without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamped_at: Option<DateTime<Utc>>,
+    pub failed_checks: Vec<String>,
+}
+
+/// Exactly what is hashed and signed; stored alongside the register entry
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CustodyStatement {
+    pub report_id: Uuid,
+    pub tenant_id: Uuid,
+    pub resource_type: String,
+    pub resource_id: Uuid,
+    pub generated_at: DateTime<Utc>,
+    pub signing_key_id: String,
+    pub events: Vec<CustodyEntry>,
+}
+
+/// Register entry for a generated report
+#[derive(Serialize, Debug, Clone, FromRow)]
+pub struct CustodyReport {
+    pub report_id: Uuid,
+    pub tenant_id: Uuid,
+    pub resource_type: String,
+    pub resource_id: Uuid,
+    pub event_count: i32,
+    pub all_verified: bool,
+    pub statement_hash: String,
+    pub statement_signature: String,
+    pub pdf_hash: String,
+    pub pdf_signature: String,
+    pub signing_key_id: String,
+    pub requested_by: Option<Uuid>,
+    pub generated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CustodyReportDetail {
+    #[serde(flatten)]
+    pub report: CustodyReport,
+    pub statement: serde_json::Value,
+    /// Present while the report's signing key is the service's current key
+    pub public_key: Option<String>,
+}
+
+const REPORT_COLUMNS: &str = "report_id, tenant_id, resource_type, resource_id, event_count, all_verified, \
+     statement_hash, statement_signature, pdf_hash, pdf_signature, signing_key_id, requested_by, generated_at";
+
+async fn resource_events(
+    db: &PgPool,
+    tenant_id: Uuid,
+    resource_type: &str,
+    resource_id: Uuid,
+) -> Result<Vec<AuditEvent>, CustodyError> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM audit_logs WHERE tenant_id = $1 AND resource_type = $2 AND resource_id = $3 \
+         ORDER BY timestamp ASC, log_id ASC LIMIT $4",
+        AUDIT_LOG_COLUMNS
+    ))
+    .bind(tenant_id)
+    .bind(resource_type)
+    .bind(resource_id)
+    .bind(MAX_CUSTODY_EVENTS as i64 + 1)
+    .fetch_all(db)
+    .await?;
+    if rows.len() > MAX_CUSTODY_EVENTS {
+        return Err(CustodyError::TooManyEvents(MAX_CUSTODY_EVENTS));
+    }
+    Ok(rows.iter().map(audit_event_from_row).collect())
+}
+
+/// Digest anchors of the events that were anchored in a daily digest
+async fn digest_anchors(
+    db: &PgPool,
+    event_ids: &[Uuid],
+) -> Result<HashMap<Uuid, (NaiveDate, Option<String>, Option<String>)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, (Uuid, NaiveDate, Option<String>, Option<String>)>(
+        "SELECT l.event_id, d.digest_date, d.merkle_root, d.transaction_hash \
+         FROM audit_anchor_digest_leaves l JOIN audit_anchor_digests d ON d.digest_date = l.digest_date \
+         WHERE l.event_id = ANY($1)",
+    )
+    .bind(event_ids)
+    .fetch_all(db)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(event_id, date, root, transaction)| (event_id, (date, root, transaction)))
+        .collect())
+}
+
+/// Verify the resource's trail, sign it and render the PDF
+pub async fn generate(
+    service: &AuditService,
+    signer: &ReportSigner,
+    tenant_id: Uuid,
+    resource_type: &str,
+    resource_id: Uuid,
+    requested_by: Option<Uuid>,
+) -> Result<(CustodyReport, Vec<u8>), CustodyError> {
+    let events = resource_events(&service.db, tenant_id, resource_type, resource_id).await?;
+    if events.is_empty() {
+        return Err(CustodyError::NotFound);
+    }
+    let ids: Vec<Uuid> = events.iter().map(|event| event.event_id).collect();
+    let mut anchors = digest_anchors(&service.db, &ids).await?;
+
+    let mut entries = Vec::with_capacity(events.len());
+    for (index, event) in events.iter().enumerate() {
+        let report = service
+            .cached_verification(event)
+            .await
+            .map_err(|e| CustodyError::Verification(event.event_id, e.to_string()))?;
+        let anchor = anchors.remove(&event.event_id);
+        entries.push(CustodyEntry {
+            sequence: index + 1,
+            event_id: event.event_id,
+            timestamp: event.timestamp,
+            action: event.action.clone(),
+            user_id: event.user_id,
+            actor: event.actor.clone(),
+            ip_address: event.ip_address.clone(),
+            event_hash: event.event_hash.clone(),
+            signature: event.signature.clone(),
+            signing_key_id: event.signing_key_id.clone(),
+            ipfs_hash: event.ipfs_hash.clone(),
+            blockchain_hash: event.blockchain_hash.clone(),
+            digest_date: anchor.as_ref().map(|(date, _, _)| *date),
+            digest_root: anchor.as_ref().and_then(|(_, root, _)| root.clone()),
+            digest_transaction: anchor.and_then(|(_, _, transaction)| transaction),
+            verified: report.verified,
+            blockchain_confirmed: report.blockchain_confirmed,
+            ipfs_accessible: report.ipfs_accessible,
+            timestamped_at: report.timestamped_at,
+            failed_checks: report
+                .checks
+                .iter()
+                .filter(|check| !check.passed)
+                .map(|check| match &check.reason {
+                    Some(reason) => format!("{}: {}", check.check, reason),
+                    None => check.check.clone(),
+                })
+                .collect(),
+        });
+    }
+
+    let statement = CustodyStatement {
+        report_id: Uuid::new_v4(),
+        tenant_id,
+        resource_type: resource_type.to_string(),
+        resource_id,
+        generated_at: Utc::now(),
+        signing_key_id: signer.key_id().to_string(),
+        events: entries,
+    };
+    let statement_json = serde_json::to_value(&statement)?;
+    let statement_hash = sha256_hex(&serde_json::to_vec(&statement_json)?);
+    let statement_signature = signer.sign(statement_hash.as_bytes());
+
+    let pdf = render(&statement, &statement_hash, &statement_signature, &signer.public_key())
+        .map_err(|e| CustodyError::Render(e.to_string()))?;
+    let pdf_hash = sha256_hex(&pdf);
+    let pdf_signature = signer.sign(&pdf);
+
+    let all_verified = statement.events.iter().all(|entry| entry.verified);
+    let report = sqlx::query_as::<_, CustodyReport>(&format!(
+        "INSERT INTO custody_reports \
+             (report_id, tenant_id, resource_type, resource_id, event_count, all_verified, statement, \
+              statement_hash, statement_signature, pdf_hash, pdf_signature, signing_key_id, requested_by, generated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14) \
+         RETURNING {}",
+        REPORT_COLUMNS
+    ))
+    .bind(statement.report_id)
+    .bind(tenant_id)
+    .bind(resource_type)
+    .bind(resource_id)
+    .bind(statement.events.len() as i32)
+    .bind(all_verified)
+    .bind(&statement_json)
+    .bind(&statement_hash)
+    .bind(&statement_signature)
+    .bind(&pdf_hash)
+    .bind(&pdf_signature)
+    .bind(signer.key_id())
+    .bind(requested_by)
+    .bind(statement.generated_at)
+    .fetch_one(&service.db)
+    .await?;
+
+    info!(
+        "Generated chain-of-custody report {} for {} {} ({} events, all verified: {})",
+        report.report_id, resource_type, resource_id, report.event_count, all_verified
+    );
+    Ok((report, pdf))
+}
+
+/// Look a report up by id, or by the SHA-256 of a PDF someone was handed
+pub async fn find(
+    db: &PgPool,
+    signer: &ReportSigner,
+    report_id: Option<Uuid>,
+    pdf_hash: Option<&str>,
+) -> Result<Option<CustodyReportDetail>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        "SELECT {}, statement FROM custody_reports WHERE report_id = $1 OR pdf_hash = $2",
+        REPORT_COLUMNS
+    ))
+    .bind(report_id)
+    .bind(pdf_hash.map(str::to_ascii_lowercase))
+    .fetch_optional(db)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let report = CustodyReport::from_row(&row)?;
+    let statement: serde_json::Value = row.try_get("statement")?;
+    let public_key = (report.signing_key_id == signer.key_id()).then(|| signer.public_key());
+    Ok(Some(CustodyReportDetail {
+        report,
+        statement,
+        public_key,
+    }))
+}
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 18.0;
+const PT_TO_MM: f32 = 0.3528;
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+    Mono,
+}
+
+/// Top-to-bottom text layout over as many A4 pages as needed
+struct Layout {
+    doc: PdfDocumentReference,
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+    mono: IndirectFontRef,
+    layer: PdfLayerReference,
+    y: f32,
+    pages: usize,
+    footer: String,
+}
+
+impl Layout {
+    fn new(title: &str, footer: String) -> Result<Self, printpdf::Error> {
+        let (doc, page, layer) = PdfDocument::new(title, Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "page 1");
+        let regular = doc.add_builtin_font(BuiltinFont::Helvetica)?;
+        let bold = doc.add_builtin_font(BuiltinFont::HelveticaBold)?;
+        let mono = doc.add_builtin_font(BuiltinFont::Courier)?;
+        let layer = doc.get_page(page).get_layer(layer);
+        let mut layout = Self {
+            doc,
+            regular,
+            bold,
+            mono,
+            layer,
+            y: PAGE_HEIGHT - MARGIN,
+            pages: 1,
+            footer,
+        };
+        layout.write_footer();
+        Ok(layout)
+    }
+
+    fn write_footer(&mut self) {
+        let text = format!("{} - page {}", self.footer, self.pages);
+        self.layer.use_text(text, 7.0, Mm(MARGIN), Mm(MARGIN / 2.0), &self.regular);
+    }
+
+    fn ensure(&mut self, height: f32) {
+        if self.y - height >= MARGIN {
+            return;
+        }
+        self.pages += 1;
+        let (page, layer) = self
+            .doc
+            .add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), format!("page {}", self.pages));
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.y = PAGE_HEIGHT - MARGIN;
+        self.write_footer();
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    /// Write `text`, wrapping at word boundaries (or anywhere, for monospace)
+    fn text(&mut self, font: Font, size: f32, indent: f32, text: &str) {
+        // Helvetica averages about half an em per character, Courier is 0.6 em
+        let char_width = size * PT_TO_MM * if matches!(font, Font::Mono) { 0.6 } else { 0.5 };
+        let max_chars = ((PAGE_WIDTH - 2.0 * MARGIN - indent) / char_width).max(10.0) as usize;
+        let line_height = size * PT_TO_MM * 1.4;
+        for line in wrap(&ascii(text), max_chars, matches!(font, Font::Mono)) {
+            self.ensure(line_height);
+            self.y -= line_height;
+            let font_ref = match font {
+                Font::Regular => &self.regular,
+                Font::Bold => &self.bold,
+                Font::Mono => &self.mono,
+            };
+            self.layer.use_text(line, size, Mm(MARGIN + indent), Mm(self.y), font_ref);
+        }
+    }
+
+    fn finish(self) -> Result<Vec<u8>, printpdf::Error> {
+        self.doc.save_to_bytes()
+    }
+}
+
+/// The built-in PDF fonts only cover Latin-1; anything else prints as '?'
+fn ascii(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '?' })
+        .collect()
+}
+
+fn wrap(text: &str, max_chars: usize, hard: bool) -> Vec<String> {
+    if hard {
+        let chars: Vec<char> = text.chars().collect();
+        return chars.chunks(max_chars).map(|chunk| chunk.iter().collect()).collect();
+    }
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn or_none(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("none")
+}
+
+fn render(
+    statement: &CustodyStatement,
+    statement_hash: &str,
+    statement_signature: &str,
+    public_key: &str,
+) -> Result<Vec<u8>, printpdf::Error> {
+    let mut layout = Layout::new(
+        "Chain of Custody Report",
+        format!("DharmaGuard chain-of-custody report {}", statement.report_id),
+    )?;
+
+    layout.text(Font::Bold, 16.0, 0.0, "Chain of Custody Report");
+    layout.gap(3.0);
+    let verified = statement.events.iter().filter(|entry| entry.verified).count();
+    for line in [
+        format!("Report ID: {}", statement.report_id),
+        format!("Tenant: {}", statement.tenant_id),
+        format!("Resource: {} {}", statement.resource_type, statement.resource_id),
+        format!("Generated: {}", statement.generated_at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+        format!(
+            "Events: {} ({} verified, {} failed verification)",
+            statement.events.len(),
+            verified,
+            statement.events.len() - verified
+        ),
+    ] {
+        layout.text(Font::Regular, 10.0, 0.0, &line);
+    }
+    layout.gap(2.0);
+    layout.text(
+        Font::Regular,
+        8.0,
+        0.0,
+        "Each event below is listed in the order it was recorded, with the SHA-256 hash of its canonical \
+         payload, the HMAC signature applied when it was written, where its record was pinned on IPFS and \
+         how its hash was anchored on chain. Verification re-computes the hash and re-checks the signature, \
+         the blockchain anchor and the IPFS record at the time this report was generated.",
+    );
+
+    for entry in &statement.events {
+        layout.gap(4.0);
+        layout.ensure(30.0);
+        layout.text(
+            Font::Bold,
+            9.0,
+            0.0,
+            &format!(
+                "#{}  {}  {}",
+                entry.sequence,
+                entry.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+                entry.action
+            ),
+        );
+        let actor = match (&entry.actor, entry.user_id) {
+            (Some(actor), _) => format!("{} ({}, {})", actor.username, actor.role, actor.tenant_name),
+            (None, Some(user_id)) => user_id.to_string(),
+            (None, None) => "system".to_string(),
+        };
+        layout.text(
+            Font::Regular,
+            8.0,
+            4.0,
+            &format!("Event {} by {} from {}", entry.event_id, actor, or_none(&entry.ip_address)),
+        );
+        layout.text(Font::Mono, 7.0, 4.0, &format!("hash      {}", or_none(&entry.event_hash)));
+        layout.text(
+            Font::Mono,
+            7.0,
+            4.0,
+            &format!("signature {} (key {})", or_none(&entry.signature), or_none(&entry.signing_key_id)),
+        );
+        layout.text(Font::Mono, 7.0, 4.0, &format!("ipfs      {}", or_none(&entry.ipfs_hash)));
+        match (&entry.blockchain_hash, entry.digest_date) {
+            (Some(transaction), _) => layout.text(Font::Mono, 7.0, 4.0, &format!("anchor tx {}", transaction)),
+            (None, Some(date)) => {
+                layout.text(
+                    Font::Mono,
+                    7.0,
+                    4.0,
+                    &format!("digest    {} root {}", date, or_none(&entry.digest_root)),
+                );
+                layout.text(Font::Mono, 7.0, 4.0, &format!("anchor tx {}", or_none(&entry.digest_transaction)));
+            }
+            (None, None) => layout.text(Font::Mono, 7.0, 4.0, "anchor    not anchored"),
+        }
+        if let Some(timestamped_at) = entry.timestamped_at {
+            layout.text(Font::Mono, 7.0, 4.0, &format!("tsa       {}", timestamped_at.to_rfc3339()));
+        }
+        let status = if entry.verified {
+            "Verification: VERIFIED".to_string()
+        } else {
+            format!("Verification: FAILED - {}", entry.failed_checks.join("; "))
+        };
+        layout.text(
+            if entry.verified { Font::Regular } else { Font::Bold },
+            8.0,
+            4.0,
+            &format!(
+                "{} (blockchain {}, IPFS {})",
+                status,
+                if entry.blockchain_confirmed { "confirmed" } else { "not confirmed" },
+                if entry.ipfs_accessible { "accessible" } else { "not accessible" }
+            ),
+        );
+    }
+
+    layout.gap(8.0);
+    layout.ensure(50.0);
+    layout.text(Font::Bold, 12.0, 0.0, "Attestation");
+    layout.gap(2.0);
+    layout.text(
+        Font::Regular,
+        8.0,
+        0.0,
+        "The SHA-256 digest below covers the canonical JSON statement of every entry in this report. It is \
+         signed with the Ed25519 key identified below. The statement is held in the platform's custody report \
+         register under the report ID and can be retrieved to re-compute the digest and check the signature \
+         against the public key.",
+    );
+    layout.gap(2.0);
+    layout.text(Font::Regular, 8.0, 0.0, &format!("Signing key: {}", statement.signing_key_id));
+    layout.text(Font::Mono, 7.0, 0.0, &format!("public key  {}", public_key));
+    layout.text(Font::Mono, 7.0, 0.0, &format!("digest      {}", statement_hash));
+    layout.text(Font::Mono, 7.0, 0.0, &format!("signature   {}", &statement_signature[..64]));
+    layout.text(Font::Mono, 7.0, 0.0, &format!("            {}", &statement_signature[64..]));
+
+    layout.finish()
+}
@@ -0,0 +1,372 @@
+//! Field-level diffs between an event's old_values and new_values
+//!
+//! Paths are dotted keys with `[n]` for array elements (`accounts[1].ifsc`);
+//! keys that would be ambiguous are written `["a.b"]`, and the empty path is
+//! the value as a whole. Objects are compared key by key. Arrays are aligned
+//! on their longest common subsequence, so an element inserted in the middle
+//! is one addition rather than a change to every element after it; elements
+//! left unmatched at the same spot are diffed in place. Added and changed
+//! elements carry their index in new_values, removed ones their index in
+//! old_values.
+//!
+//! Values under AUDIT_DIFF_REDACT_PATHS are replaced before a diff leaves the
+//! service. The change itself is still reported, so a caller sees that a
+//! password changed without seeing it.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::AuditEvent;
+
+/// Stands in for a redacted value
+pub const REDACTED: &str = "[REDACTED]";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldChange {
+    pub path: String,
+    pub kind: ChangeKind,
+    /// Absent for additions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<Value>,
+    /// Absent for removals
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_value: Option<Value>,
+    /// Some or all of the values were withheld
+    pub redacted: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EventDiff {
+    pub event_id: Uuid,
+    pub action: String,
+    pub resource_type: String,
+    pub resource_id: Option<Uuid>,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PatternSegment {
+    Key(String),
+    Index(usize),
+    /// `*` or `[*]`
+    Any,
+}
+
+/// One entry of AUDIT_DIFF_REDACT_PATHS
+///
+/// A bare key (`password`) matches that key at any depth; anything else
+/// (`credentials.*`, `accounts[*].number`) is matched from the top. A match
+/// redacts the value at the path and everything under it.
+#[derive(Debug, Clone)]
+pub struct RedactionRule {
+    segments: Vec<PatternSegment>,
+    anywhere: bool,
+}
+
+impl RedactionRule {
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let mut segments = Vec::new();
+        for part in pattern.split('.') {
+            let (key, mut rest) = match part.find('[') {
+                Some(at) => part.split_at(at),
+                None => (part, ""),
+            };
+            match key {
+                "" if rest.is_empty() => return None,
+                "" => {}
+                "*" => segments.push(PatternSegment::Any),
+                key => segments.push(PatternSegment::Key(key.to_string())),
+            }
+            while !rest.is_empty() {
+                let close = rest.find(']')?;
+                segments.push(match &rest[1..close] {
+                    "*" => PatternSegment::Any,
+                    index => PatternSegment::Index(index.parse().ok()?),
+                });
+                rest = &rest[close + 1..];
+                if !rest.is_empty() && !rest.starts_with('[') {
+                    return None;
+                }
+            }
+        }
+        let anywhere = segments.len() == 1 && matches!(segments[0], PatternSegment::Key(_));
+        Some(Self { segments, anywhere })
+    }
+
+    /// Whether the value at `path` falls under this rule
+    fn covers(&self, path: &[Segment]) -> bool {
+        if self.anywhere {
+            return path.iter().any(|segment| self.matches(&self.segments[0], segment));
+        }
+        path.len() >= self.segments.len()
+            && self
+                .segments
+                .iter()
+                .zip(path)
+                .all(|(pattern, segment)| self.matches(pattern, segment))
+    }
+
+    fn matches(&self, pattern: &PatternSegment, segment: &Segment) -> bool {
+        match (pattern, segment) {
+            (PatternSegment::Any, _) => true,
+            (PatternSegment::Key(expected), Segment::Key(key)) => expected == key,
+            (PatternSegment::Index(expected), Segment::Index(index)) => expected == index,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffSettings {
+    pub redact: Vec<RedactionRule>,
+    /// Largest old × new element count aligned by common subsequence; bigger
+    /// arrays are compared index by index
+    pub max_array_cells: usize,
+}
+
+impl DiffSettings {
+    pub fn from_env() -> Self {
+        let patterns = std::env::var("AUDIT_DIFF_REDACT_PATHS")
+            .unwrap_or_else(|_| "password,password_hash,secret,api_key,token,private_key".to_string());
+        let redact = patterns
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .filter_map(|pattern| {
+                let rule = RedactionRule::parse(pattern);
+                if rule.is_none() {
+                    tracing::warn!("Ignoring malformed AUDIT_DIFF_REDACT_PATHS entry {:?}", pattern);
+                }
+                rule
+            })
+            .collect();
+        Self {
+            redact,
+            max_array_cells: std::env::var("AUDIT_DIFF_MAX_ARRAY_CELLS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(250_000),
+        }
+    }
+
+    fn redacted(&self, path: &[Segment]) -> bool {
+        self.redact.iter().any(|rule| rule.covers(path))
+    }
+}
+
+/// The field-level diff of an event whose values have already been revealed to the caller
+pub fn event_diff(settings: &DiffSettings, event: &AuditEvent) -> EventDiff {
+    let (empty, null) = (Value::Object(Map::new()), Value::Null);
+    // A create or delete diffs against an empty object, so each field is listed
+    let (old, new) = match (&event.old_values, &event.new_values) {
+        (None, None) => (&empty, &empty),
+        (Some(old), None) if old.is_object() => (old, &empty),
+        (None, Some(new)) if new.is_object() => (&empty, new),
+        (old, new) => (old.as_ref().unwrap_or(&null), new.as_ref().unwrap_or(&null)),
+    };
+
+    let mut differ = Differ { settings, path: Vec::new(), changes: Vec::new() };
+    differ.values(old, new);
+    let changes = differ.changes;
+    let count = |kind: ChangeKind| changes.iter().filter(|change| change.kind == kind).count();
+
+    EventDiff {
+        event_id: event.event_id,
+        action: event.action.clone(),
+        resource_type: event.resource_type.clone(),
+        resource_id: event.resource_id,
+        added: count(ChangeKind::Added),
+        removed: count(ChangeKind::Removed),
+        changed: count(ChangeKind::Changed),
+        changes,
+    }
+}
+
+struct Differ<'a> {
+    settings: &'a DiffSettings,
+    path: Vec<Segment>,
+    changes: Vec<FieldChange>,
+}
+
+impl Differ<'_> {
+    fn values(&mut self, old: &Value, new: &Value) {
+        match (old, new) {
+            (Value::Object(old), Value::Object(new)) => {
+                for (key, old_value) in old {
+                    self.path.push(Segment::Key(key.clone()));
+                    match new.get(key) {
+                        Some(new_value) => self.values(old_value, new_value),
+                        None => self.record(ChangeKind::Removed, Some(old_value), None),
+                    }
+                    self.path.pop();
+                }
+                for (key, new_value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                    self.path.push(Segment::Key(key.clone()));
+                    self.record(ChangeKind::Added, None, Some(new_value));
+                    self.path.pop();
+                }
+            }
+            (Value::Array(old), Value::Array(new)) => self.arrays(old, new),
+            _ if old == new => {}
+            _ => self.record(ChangeKind::Changed, Some(old), Some(new)),
+        }
+    }
+
+    fn arrays(&mut self, old: &[Value], new: &[Value]) {
+        let (n, m) = (old.len(), new.len());
+        if n.saturating_mul(m) > self.settings.max_array_cells {
+            for index in 0..n.max(m) {
+                self.path.push(Segment::Index(index));
+                match (old.get(index), new.get(index)) {
+                    (Some(old_value), Some(new_value)) => self.values(old_value, new_value),
+                    (Some(old_value), None) => self.record(ChangeKind::Removed, Some(old_value), None),
+                    (None, Some(new_value)) => self.record(ChangeKind::Added, None, Some(new_value)),
+                    (None, None) => {}
+                }
+                self.path.pop();
+            }
+            return;
+        }
+
+        // lcs[i * (m + 1) + j] is the common subsequence length of old[i..] and new[j..]
+        let width = m + 1;
+        let mut lcs = vec![0u32; (n + 1) * width];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[i * width + j] = if old[i] == new[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        let (mut removed, mut added) = (Vec::new(), Vec::new());
+        while i < n || j < m {
+            if i < n && j < m && old[i] == new[j] {
+                self.gap(old, new, &mut removed, &mut added);
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1]) {
+                removed.push(i);
+                i += 1;
+            } else {
+                added.push(j);
+                j += 1;
+            }
+        }
+        self.gap(old, new, &mut removed, &mut added);
+    }
+
+    /// Elements between two matched ones: paired up in order, the rest removed or added
+    fn gap(&mut self, old: &[Value], new: &[Value], removed: &mut Vec<usize>, added: &mut Vec<usize>) {
+        let paired = removed.len().min(added.len());
+        for (&i, &j) in removed.iter().zip(added.iter()) {
+            self.path.push(Segment::Index(j));
+            self.values(&old[i], &new[j]);
+            self.path.pop();
+        }
+        for &i in &removed[paired..] {
+            self.path.push(Segment::Index(i));
+            self.record(ChangeKind::Removed, Some(&old[i]), None);
+            self.path.pop();
+        }
+        for &j in &added[paired..] {
+            self.path.push(Segment::Index(j));
+            self.record(ChangeKind::Added, None, Some(&new[j]));
+            self.path.pop();
+        }
+        removed.clear();
+        added.clear();
+    }
+
+    fn record(&mut self, kind: ChangeKind, old: Option<&Value>, new: Option<&Value>) {
+        let mut redacted = false;
+        let (old_value, new_value) = if self.settings.redacted(&self.path) {
+            redacted = true;
+            (old.map(|_| Value::from(REDACTED)), new.map(|_| Value::from(REDACTED)))
+        } else {
+            let mut reveal = |value: &Value| {
+                let mut value = value.clone();
+                let mut path = self.path.clone();
+                redacted |= redact_within(self.settings, &mut path, &mut value);
+                value
+            };
+            (old.map(&mut reveal), new.map(&mut reveal))
+        };
+        self.changes.push(FieldChange {
+            path: render(&self.path),
+            kind,
+            old_value,
+            new_value,
+            redacted,
+        });
+    }
+}
+
+/// Redact whatever under `value` falls under a rule; true when anything was
+fn redact_within(settings: &DiffSettings, path: &mut Vec<Segment>, value: &mut Value) -> bool {
+    let mut redacted = false;
+    let mut visit = |path: &mut Vec<Segment>, segment: Segment, child: &mut Value| {
+        path.push(segment);
+        if settings.redacted(path) {
+            *child = Value::from(REDACTED);
+            redacted = true;
+        } else {
+            redacted |= redact_within(settings, path, child);
+        }
+        path.pop();
+    };
+    match value {
+        Value::Object(fields) => {
+            for (key, child) in fields.iter_mut() {
+                visit(path, Segment::Key(key.clone()), child);
+            }
+        }
+        Value::Array(elements) => {
+            for (index, child) in elements.iter_mut().enumerate() {
+                visit(path, Segment::Index(index), child);
+            }
+        }
+        _ => {}
+    }
+    redacted
+}
+
+fn render(path: &[Segment]) -> String {
+    let mut rendered = String::new();
+    for segment in path {
+        match segment {
+            Segment::Index(index) => rendered.push_str(&format!("[{}]", index)),
+            Segment::Key(key) if key.is_empty() || key.contains(['.', '[', ']', '"']) => {
+                rendered.push_str(&format!("[{}]", Value::from(key.as_str())));
+            }
+            Segment::Key(key) => {
+                if !rendered.is_empty() {
+                    rendered.push('.');
+                }
+                rendered.push_str(key);
+            }
+        }
+    }
+    rendered
+}
@@ -0,0 +1,516 @@
+//! Daily anchor digests with Merkle inclusion proofs
+//!
+//! With AUDIT_ANCHOR_MODE=daily_digest, events are not anchored one by one.
+//! Shortly after midnight UTC (AUDIT_DIGEST_SCHEDULE) the previous day's
+//! event hashes are ordered by timestamp and event id, folded into a SHA-256
+//! Merkle tree and only the root is anchored on chain. The leaves are kept in
+//! audit_anchor_digest_leaves, so GET /audit/events/:id/proof can return the
+//! path from any event up to the anchored root and an auditor can check the
+//! event against the chain without access to the other events.
+//!
+//! Leaves are H(0x00 || event_hash) and nodes H(0x01 || left || right), so a
+//! leaf can never be passed off as a node; an unpaired node at the end of a
+//! level is promoted to the next level unchanged.
+//!
+//! In pending anchor mode (AUDIT_BLOCKCHAIN_ENABLED=false) digests are still
+//! built, so leaves and roots are fixed the day after, but they stay
+//! PENDING_ANCHOR until the anchor_backfill runbook anchors their roots.
+//!
+//! With a time-stamping authority configured (see `tsa`), each built root is
+//! also timestamped. A root the authority could not be reached for is retried
+//! on the next scheduled run, for up to a week.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::integrity;
+use crate::store::DocumentStore;
+use crate::tsa::{self, TsaClient};
+use crate::BlockchainClient;
+
+/// Days back the scheduled run looks for roots still without a timestamp
+const TIMESTAMP_RETRY_DAYS: i32 = 7;
+
+pub const PROOF_ALGORITHM: &str =
+    "sha256; leaf = H(0x00 || event_hash), node = H(0x01 || left || right); unpaired nodes are promoted";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnchorMode {
+    /// Every event hash is anchored on chain when the event is written
+    PerEvent,
+    /// Only the daily Merkle root is anchored
+    DailyDigest,
+}
+
+impl AnchorMode {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("AUDIT_ANCHOR_MODE").unwrap_or_default().as_str() {
+            "" | "per_event" => Ok(Self::PerEvent),
+            "daily_digest" => Ok(Self::DailyDigest),
+            other => anyhow::bail!("unknown AUDIT_ANCHOR_MODE '{}'", other),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct AnchorDigest {
+    pub digest_date: NaiveDate,
+    pub status: String,
+    pub event_count: i32,
+    pub merkle_root: Option<String>,
+    pub transaction_hash: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub anchored_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A sibling on the way from a leaf to the root
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProofStep {
+    /// Which side the sibling is on when the two are combined
+    pub side: Side,
+    pub hash: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InclusionProof {
+    pub event_id: Uuid,
+    pub event_hash: String,
+    pub digest_date: NaiveDate,
+    pub leaf_index: i32,
+    pub leaf_count: i32,
+    pub path: Vec<ProofStep>,
+    pub merkle_root: String,
+    pub transaction_hash: Option<String>,
+    pub anchored_at: Option<DateTime<Utc>>,
+    pub anchor_status: String,
+    pub algorithm: &'static str,
+}
+
+fn leaf_hash(event_hash: &str) -> anyhow::Result<[u8; 32]> {
+    let bytes = hex::decode(event_hash)?;
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(bytes);
+    Ok(hasher.finalize().into())
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks of two"),
+        })
+        .collect()
+}
+
+pub fn merkle_root(event_hashes: &[String]) -> anyhow::Result<[u8; 32]> {
+    let mut level = event_hashes
+        .iter()
+        .map(|hash| leaf_hash(hash))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if level.is_empty() {
+        anyhow::bail!("a digest needs at least one event");
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    Ok(level[0])
+}
+
+fn merkle_path(event_hashes: &[String], mut index: usize) -> anyhow::Result<Vec<ProofStep>> {
+    let mut level = event_hashes
+        .iter()
+        .map(|hash| leaf_hash(hash))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut path = Vec::new();
+    while level.len() > 1 {
+        let sibling = index ^ 1;
+        // A promoted node has no sibling at this level
+        if sibling < level.len() {
+            path.push(ProofStep {
+                side: if sibling < index { Side::Left } else { Side::Right },
+                hash: hex::encode(level[sibling]),
+            });
+        }
+        level = next_level(&level);
+        index /= 2;
+    }
+    Ok(path)
+}
+
+/// Fold a proof path back up to the root it commits to
+pub fn root_from_path(event_hash: &str, path: &[ProofStep]) -> anyhow::Result<String> {
+    let mut node = leaf_hash(event_hash)?;
+    for step in path {
+        let sibling: [u8; 32] = hex::decode(&step.hash)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("proof step {} is not a SHA-256 hash", step.hash))?;
+        node = match step.side {
+            Side::Left => node_hash(&sibling, &node),
+            Side::Right => node_hash(&node, &sibling),
+        };
+    }
+    Ok(hex::encode(node))
+}
+
+pub struct DigestBuilder {
+    db: PgPool,
+    documents: Arc<dyn DocumentStore>,
+    blockchain: Option<Arc<BlockchainClient>>,
+    tsa: Option<Arc<TsaClient>>,
+}
+
+impl DigestBuilder {
+    pub fn new(
+        db: PgPool,
+        documents: Arc<dyn DocumentStore>,
+        blockchain: Option<Arc<BlockchainClient>>,
+        tsa: Option<Arc<TsaClient>>,
+    ) -> Self {
+        Self {
+            db,
+            documents,
+            blockchain,
+            tsa,
+        }
+    }
+
+    /// Build and anchor yesterday's digest on AUDIT_DIGEST_SCHEDULE
+    pub async fn schedule(self: Arc<Self>) -> anyhow::Result<JobScheduler> {
+        let schedule = std::env::var("AUDIT_DIGEST_SCHEDULE")
+            .ok()
+            .filter(|schedule| !schedule.is_empty())
+            .unwrap_or_else(|| "0 10 0 * * *".to_string());
+        let scheduler = JobScheduler::new().await?;
+        let job = Job::new_async(schedule.as_str(), move |_uuid, _l| {
+            let builder = self.clone();
+            Box::pin(async move {
+                let day = Utc::now().date_naive() - Duration::days(1);
+                match builder.build(day).await {
+                    Ok(Some(digest)) => info!(
+                        "Built audit digest for {} over {} events, {}: {:?}",
+                        day, digest.event_count, digest.status, digest.merkle_root
+                    ),
+                    Ok(None) => info!("No audit events on {}; nothing to anchor", day),
+                    Err(e) => error!("Audit digest for {} failed: {}", day, e),
+                }
+                if let Err(e) = builder.timestamp_missing().await {
+                    error!("Timestamping earlier audit digests failed: {}", e);
+                }
+            })
+        })?;
+        scheduler.add(job).await?;
+        scheduler.start().await?;
+        Ok(scheduler)
+    }
+
+    /// Build and anchor the digest of one UTC day; a FAILED digest can be rebuilt
+    ///
+    /// Returns None for a day without events, which needs no digest.
+    pub async fn build(&self, day: NaiveDate) -> anyhow::Result<Option<AnchorDigest>> {
+        let claimed = sqlx::query(
+            r#"
+            INSERT INTO audit_anchor_digests (digest_date) VALUES ($1)
+            ON CONFLICT (digest_date) DO UPDATE SET status = 'BUILDING', error = NULL, created_at = NOW()
+            WHERE audit_anchor_digests.status = 'FAILED'
+            "#,
+        )
+        .bind(day)
+        .execute(&self.db)
+        .await?
+        .rows_affected()
+            == 1;
+        if !claimed {
+            anyhow::bail!("the digest for {} is already built or being built", day);
+        }
+
+        match self.build_claimed(day).await {
+            Ok(digest) => {
+                if let Some(root) = digest.as_ref().and_then(|digest| digest.merkle_root.as_deref()) {
+                    self.timestamp(day, root).await;
+                }
+                Ok(digest)
+            }
+            Err(e) => {
+                warn!("Audit digest for {} failed: {}", day, e);
+                sqlx::query("DELETE FROM audit_anchor_digest_leaves WHERE digest_date = $1")
+                    .bind(day)
+                    .execute(&self.db)
+                    .await?;
+                sqlx::query("UPDATE audit_anchor_digests SET status = 'FAILED', error = $2 WHERE digest_date = $1")
+                    .bind(day)
+                    .bind(e.to_string())
+                    .execute(&self.db)
+                    .await?;
+                raise_ops_alert(&self.db, day, &e.to_string()).await?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn build_claimed(&self, day: NaiveDate) -> anyhow::Result<Option<AnchorDigest>> {
+        let start = day.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+        let ids: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT log_id FROM audit_logs WHERE timestamp >= $1 AND timestamp < $2 ORDER BY timestamp, log_id",
+        )
+        .bind(start)
+        .bind(start + Duration::days(1))
+        .fetch_all(&self.db)
+        .await?;
+        if ids.is_empty() {
+            sqlx::query("DELETE FROM audit_anchor_digests WHERE digest_date = $1")
+                .bind(day)
+                .execute(&self.db)
+                .await?;
+            return Ok(None);
+        }
+
+        let documents = self.documents.get_many(&ids).await?;
+
+        // Only hashes that still match their documents are committed to
+        let mut event_hashes = Vec::with_capacity(ids.len());
+        for id in &ids {
+            let event = documents
+                .get(id)
+                .ok_or_else(|| anyhow::anyhow!("audit event {} has no {} document", id, self.documents.backend()))?;
+            let computed_hash = integrity::sha256_hex(&integrity::canonical_payload(event)?);
+            if event.event_hash.as_deref() != Some(computed_hash.as_str()) {
+                anyhow::bail!("audit event {} no longer matches its recorded hash", id);
+            }
+            event_hashes.push(computed_hash);
+        }
+        let root = hex::encode(merkle_root(&event_hashes)?);
+
+        let mut tx = self.db.begin().await?;
+        for (leaf_index, (id, event_hash)) in ids.iter().zip(&event_hashes).enumerate() {
+            sqlx::query(
+                "INSERT INTO audit_anchor_digest_leaves (digest_date, leaf_index, event_id, event_hash) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(day)
+            .bind(leaf_index as i32)
+            .bind(id)
+            .bind(event_hash)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        let Some(blockchain) = &self.blockchain else {
+            let digest = sqlx::query_as::<_, AnchorDigest>(
+                r#"
+                UPDATE audit_anchor_digests SET status = 'PENDING_ANCHOR', event_count = $2, merkle_root = $3
+                WHERE digest_date = $1
+                RETURNING *
+                "#,
+            )
+            .bind(day)
+            .bind(ids.len() as i32)
+            .bind(&root)
+            .fetch_one(&self.db)
+            .await?;
+            info!("Audit digest for {} awaits anchoring of root {}", day, root);
+            return Ok(Some(digest));
+        };
+        let transaction_hash = blockchain
+            .store_audit_hash(&root)
+            .await
+            .map_err(|e| anyhow::anyhow!("anchoring digest root {} failed: {}", root, e))?;
+
+        let digest = sqlx::query_as::<_, AnchorDigest>(
+            r#"
+            UPDATE audit_anchor_digests
+            SET status = 'ANCHORED', event_count = $2, merkle_root = $3, transaction_hash = $4, anchored_at = NOW()
+            WHERE digest_date = $1
+            RETURNING *
+            "#,
+        )
+        .bind(day)
+        .bind(ids.len() as i32)
+        .bind(&root)
+        .bind(&transaction_hash)
+        .fetch_one(&self.db)
+        .await?;
+        Ok(Some(digest))
+    }
+
+    /// Timestamp a built root; a failure is logged and left for `timestamp_missing`
+    async fn timestamp(&self, day: NaiveDate, root: &str) {
+        let Some(tsa) = &self.tsa else {
+            return;
+        };
+        let outcome = match tsa.timestamp(root).await {
+            Ok(token) => tsa::record(&self.db, tsa::Subject::Digest(day), root, tsa.url(), &token)
+                .await
+                .map(|()| token.gen_time)
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        match outcome {
+            Ok(gen_time) => info!("Timestamped audit digest root for {} at {}", day, gen_time),
+            Err(e) => warn!("Timestamping audit digest root for {} failed: {}", day, e),
+        }
+    }
+
+    /// Timestamp recent roots a previous run could not get a token for
+    pub async fn timestamp_missing(&self) -> Result<(), sqlx::Error> {
+        if self.tsa.is_none() {
+            return Ok(());
+        }
+        for (day, root) in tsa::untimestamped_digests(&self.db, TIMESTAMP_RETRY_DAYS).await? {
+            self.timestamp(day, &root).await;
+        }
+        Ok(())
+    }
+
+    /// Anchor the root of a digest built in pending anchor mode
+    ///
+    /// A failed anchor leaves the digest PENDING_ANCHOR, with the error, for the next backfill.
+    pub async fn anchor_pending(&self, day: NaiveDate) -> anyhow::Result<AnchorDigest> {
+        let blockchain = self
+            .blockchain
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("blockchain anchoring is disabled"))?;
+        let root: Option<String> = sqlx::query_scalar(
+            "SELECT merkle_root FROM audit_anchor_digests WHERE digest_date = $1 AND status = 'PENDING_ANCHOR'",
+        )
+        .bind(day)
+        .fetch_optional(&self.db)
+        .await?
+        .flatten();
+        let root = root.ok_or_else(|| anyhow::anyhow!("the digest for {} is not awaiting anchoring", day))?;
+
+        let transaction_hash = match blockchain.store_audit_hash(&root).await.map_err(|e| e.to_string()) {
+            Ok(transaction_hash) => transaction_hash,
+            Err(e) => {
+                sqlx::query("UPDATE audit_anchor_digests SET error = $2 WHERE digest_date = $1")
+                    .bind(day)
+                    .bind(&e)
+                    .execute(&self.db)
+                    .await?;
+                anyhow::bail!("anchoring digest root {} failed: {}", root, e);
+            }
+        };
+        let digest = sqlx::query_as::<_, AnchorDigest>(
+            r#"
+            UPDATE audit_anchor_digests
+            SET status = 'ANCHORED', transaction_hash = $2, error = NULL, anchored_at = NOW()
+            WHERE digest_date = $1 AND status = 'PENDING_ANCHOR'
+            RETURNING *
+            "#,
+        )
+        .bind(day)
+        .bind(&transaction_hash)
+        .fetch_one(&self.db)
+        .await?;
+        Ok(digest)
+    }
+}
+
+/// Days whose digest awaits anchoring, oldest first
+pub async fn pending_digests(db: &PgPool) -> Result<Vec<NaiveDate>, sqlx::Error> {
+    sqlx::query_scalar("SELECT digest_date FROM audit_anchor_digests WHERE status = 'PENDING_ANCHOR' ORDER BY digest_date")
+        .fetch_all(db)
+        .await
+}
+
+async fn raise_ops_alert(db: &PgPool, day: NaiveDate, error_message: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO system_events (event_type, severity, source_system, message, details)
+        VALUES ('ANCHOR_DIGEST_FAILED', 'ERROR', 'audit-service', $1, $2)
+        "#,
+    )
+    .bind(format!("Audit anchor digest for {} failed: {}", day, error_message))
+    .bind(serde_json::json!({ "digest_date": day, "error": error_message }))
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_digest(db: &PgPool, day: NaiveDate) -> Result<Option<AnchorDigest>, sqlx::Error> {
+    sqlx::query_as::<_, AnchorDigest>("SELECT * FROM audit_anchor_digests WHERE digest_date = $1")
+        .bind(day)
+        .fetch_optional(db)
+        .await
+}
+
+/// The event's path to its day's root; None until the event's day has a digest
+pub async fn inclusion_proof(db: &PgPool, event_id: Uuid) -> anyhow::Result<Option<InclusionProof>> {
+    let leaf = sqlx::query_as::<_, (NaiveDate, i32, String)>(
+        "SELECT digest_date, leaf_index, event_hash FROM audit_anchor_digest_leaves WHERE event_id = $1",
+    )
+    .bind(event_id)
+    .fetch_optional(db)
+    .await?;
+    let Some((digest_date, leaf_index, event_hash)) = leaf else {
+        return Ok(None);
+    };
+    let digest = get_digest(db, digest_date)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("digest {} has leaves but no record", digest_date))?;
+    let merkle_root = match &digest.merkle_root {
+        Some(root) => root.clone(),
+        None => return Ok(None),
+    };
+
+    let event_hashes: Vec<String> = sqlx::query_scalar(
+        "SELECT event_hash FROM audit_anchor_digest_leaves WHERE digest_date = $1 ORDER BY leaf_index",
+    )
+    .bind(digest_date)
+    .fetch_all(db)
+    .await?;
+    let path = merkle_path(&event_hashes, leaf_index as usize)?;
+
+    Ok(Some(InclusionProof {
+        event_id,
+        event_hash,
+        digest_date,
+        leaf_index,
+        leaf_count: event_hashes.len() as i32,
+        path,
+        merkle_root,
+        transaction_hash: digest.transaction_hash,
+        anchored_at: digest.anchored_at,
+        anchor_status: digest.status,
+        algorithm: PROOF_ALGORITHM,
+    }))
+}
+
+/// Events among `ids` that are covered by an anchored digest
+pub async fn anchored_in_digest(db: &PgPool, ids: &[Uuid]) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT l.event_id FROM audit_anchor_digest_leaves l
+        JOIN audit_anchor_digests d ON d.digest_date = l.digest_date
+        WHERE l.event_id = ANY($1) AND d.status = 'ANCHORED'
+        "#,
+    )
+    .bind(ids)
+    .fetch_all(db)
+    .await
+}
@@ -0,0 +1,606 @@
+//! Discovery of untagged personal data
+//!
+//! `pii` only seals keys it has been told about, so a PAN or phone number
+//! under any other key is stored in clear and survives an erasure. A nightly
+//! scan samples old_values/new_values of recent audit events and the
+//! report_data of recent regulatory reports for values that look like a PAN,
+//! an Aadhaar number (twelve digits passing the Verhoeff check) or an Indian
+//! mobile number under keys that are not sealed, and records each field it
+//! finds in pii_discoveries per tenant. Only a masked example is kept.
+//!
+//! Enforcing a finding adds its key to the tenant's sealed fields, so it is
+//! sealed in every event written afterwards; events already stored keep their
+//! bytes because their hashes cover them. With
+//! AUDIT_PII_DISCOVERY_AUTO_ENFORCE=true new findings are enforced as soon as
+//! they are found. Each enforcement is itself audited.
+
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::context::RequestContext;
+use crate::envelope;
+use crate::pii::{self, PiiVault};
+use crate::{audit_event_from_row, AppState, AuditService, CreateAuditEventRequest, AUDIT_LOG_COLUMNS};
+
+/// Longest key that can be enforced; matches audit_pii_tenant_fields.field_name
+const MAX_FIELD_NAME: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct DiscoverySettings {
+    /// Cron expression; empty disables the scheduled scan
+    pub schedule: String,
+    /// Events and reports sampled per scan, each
+    pub sample_size: i64,
+    pub lookback_hours: i32,
+    pub auto_enforce: bool,
+}
+
+impl DiscoverySettings {
+    pub fn from_env() -> Self {
+        Self {
+            schedule: std::env::var("AUDIT_PII_DISCOVERY_SCHEDULE").unwrap_or_else(|_| "0 15 4 * * *".to_string()),
+            sample_size: std::env::var("AUDIT_PII_DISCOVERY_SAMPLE_SIZE")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(1000)
+                .max(1),
+            lookback_hours: std::env::var("AUDIT_PII_DISCOVERY_LOOKBACK_HOURS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(24)
+                .max(1),
+            auto_enforce: std::env::var("AUDIT_PII_DISCOVERY_AUTO_ENFORCE").map_or(false, |v| v == "true"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    Pan,
+    Aadhaar,
+    Phone,
+}
+
+impl Pattern {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pan => "PAN",
+            Self::Aadhaar => "AADHAAR",
+            Self::Phone => "PHONE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Source {
+    AuditValues,
+    ReportData,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::AuditValues => "AUDIT_VALUES",
+            Self::ReportData => "REPORT_DATA",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct PiiDiscovery {
+    pub discovery_id: Uuid,
+    pub tenant_id: Uuid,
+    pub source: String,
+    pub field_path: String,
+    pub field_name: String,
+    pub pattern: String,
+    pub match_count: i64,
+    pub sample_ref: Option<Uuid>,
+    pub masked_example: Option<String>,
+    /// OPEN, ENFORCED or DISMISSED
+    pub status: String,
+    pub first_seen_at: chrono::DateTime<chrono::Utc>,
+    pub last_seen_at: chrono::DateTime<chrono::Utc>,
+    pub resolved_by: Option<Uuid>,
+    pub resolved_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub resolution_note: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct TenantPiiField {
+    pub field_name: String,
+    pub discovery_id: Option<Uuid>,
+    pub added_by: Option<Uuid>,
+    pub added_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveRequest {
+    pub resolved_by: Option<Uuid>,
+    pub note: Option<String>,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct DiscoverySummary {
+    pub events_sampled: usize,
+    pub reports_sampled: usize,
+    /// Distinct tenant fields with a match in this scan
+    pub fields_matched: usize,
+    pub new_findings: usize,
+    pub auto_enforced: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiscoveryError {
+    #[error("finding not found")]
+    NotFound,
+    #[error("{0}")]
+    Conflict(&'static str),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Matches of one field in one scan
+struct Finding {
+    field_name: String,
+    matches: i64,
+    sample_ref: Uuid,
+    masked_example: String,
+}
+
+type FindingKey = (Uuid, Source, String, Pattern);
+
+/// Scan nightly at the configured time, after archival has run
+pub async fn schedule(state: AppState, settings: DiscoverySettings) -> anyhow::Result<JobScheduler> {
+    let scheduler = JobScheduler::new().await?;
+    if !settings.schedule.is_empty() {
+        let cron = settings.schedule.clone();
+        let job = Job::new_async(cron.as_str(), move |_uuid, _l| {
+            let (state, settings) = (state.clone(), settings.clone());
+            Box::pin(async move {
+                match run(&state, &settings).await {
+                    Ok(summary) => info!("Scheduled PII discovery finished: {:?}", summary),
+                    Err(e) => error!("Scheduled PII discovery failed: {}", e),
+                }
+            })
+        })?;
+        scheduler.add(job).await?;
+    }
+    scheduler.start().await?;
+    Ok(scheduler)
+}
+
+pub async fn run(state: &AppState, settings: &DiscoverySettings) -> anyhow::Result<DiscoverySummary> {
+    let mut summary = DiscoverySummary::default();
+    let tagged = pii::tagged_fields();
+    let mut sealed_by_tenant: HashMap<Uuid, HashSet<String>> = HashMap::new();
+    for (tenant_id, field_name) in
+        sqlx::query_as::<_, (Uuid, String)>("SELECT tenant_id, field_name FROM audit_pii_tenant_fields")
+            .fetch_all(&state.db)
+            .await?
+    {
+        sealed_by_tenant.entry(tenant_id).or_default().insert(field_name);
+    }
+    let no_fields = HashSet::new();
+    let mut findings: HashMap<FindingKey, Finding> = HashMap::new();
+
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT {} FROM audit_logs
+        WHERE timestamp >= NOW() - make_interval(hours => $1)
+          AND (old_values IS NOT NULL OR new_values IS NOT NULL)
+        ORDER BY random()
+        LIMIT $2
+        "#,
+        AUDIT_LOG_COLUMNS
+    ))
+    .bind(settings.lookback_hours)
+    .bind(settings.sample_size)
+    .fetch_all(&state.db)
+    .await?;
+    for row in &rows {
+        let mut event = audit_event_from_row(row);
+        if let Some(envelope) = &state.envelope {
+            if let Err(e) = envelope.decrypt(&state.db, &mut event).await {
+                warn!("Skipping audit event {} in PII discovery: {}", event.event_id, e);
+                continue;
+            }
+        }
+        let sealed = sealed_by_tenant.get(&event.tenant_id).unwrap_or(&no_fields);
+        let skip = |key: &str| tagged.contains(key) || sealed.contains(key);
+        let mut matches = Vec::new();
+        for values in [&event.old_values, &event.new_values] {
+            // Still encrypted when this instance has no envelope master key
+            if let (false, Some(values)) = (envelope::is_encrypted(values), values) {
+                scan(values, "", "", &skip, &mut matches);
+            }
+        }
+        collect(&mut findings, event.tenant_id, Source::AuditValues, event.event_id, matches);
+        summary.events_sampled += 1;
+    }
+
+    let reports = sqlx::query_as::<_, (Uuid, Uuid, serde_json::Value)>(
+        r#"
+        SELECT report_id, tenant_id, report_data FROM regulatory_reports_v2
+        WHERE created_at >= NOW() - make_interval(hours => $1)
+        ORDER BY random()
+        LIMIT $2
+        "#,
+    )
+    .bind(settings.lookback_hours)
+    .bind(settings.sample_size)
+    .fetch_all(&state.db)
+    .await?;
+    for (report_id, tenant_id, report_data) in &reports {
+        let sealed = sealed_by_tenant.get(tenant_id).unwrap_or(&no_fields);
+        let skip = |key: &str| tagged.contains(key) || sealed.contains(key);
+        let mut matches = Vec::new();
+        scan(report_data, "", "", &skip, &mut matches);
+        collect(&mut findings, *tenant_id, Source::ReportData, *report_id, matches);
+        summary.reports_sampled += 1;
+    }
+
+    summary.fields_matched = findings.len();
+    for ((tenant_id, source, field_path, pattern), finding) in findings {
+        let (discovery, inserted) = record(&state.db, tenant_id, source, &field_path, pattern, &finding).await?;
+        if !inserted {
+            continue;
+        }
+        summary.new_findings += 1;
+        counter!("audit_pii_discoveries_total", 1, "source" => source.as_str(), "pattern" => pattern.as_str());
+        warn!(
+            "Found untagged {} in {} field {} of tenant {}",
+            pattern.as_str(),
+            source.as_str(),
+            field_path,
+            tenant_id
+        );
+
+        if let (true, Some(vault)) = (settings.auto_enforce, &state.pii) {
+            let request = ResolveRequest {
+                resolved_by: None,
+                note: Some("enforced automatically on discovery".to_string()),
+            };
+            match enforce(&state.db, vault, tenant_id, discovery.discovery_id, &request).await {
+                Ok(enforced) => {
+                    record_enforcement(state, &enforced).await;
+                    summary.auto_enforced += 1;
+                }
+                Err(e) => error!("Failed to enforce PII finding {}: {}", discovery.discovery_id, e),
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Add a scan's matches to the finding, returning it and whether it is new
+async fn record(
+    db: &PgPool,
+    tenant_id: Uuid,
+    source: Source,
+    field_path: &str,
+    pattern: Pattern,
+    finding: &Finding,
+) -> Result<(PiiDiscovery, bool), sqlx::Error> {
+    let (discovery_id, inserted): (Uuid, bool) = sqlx::query_as(
+        r#"
+        INSERT INTO pii_discoveries
+            (tenant_id, source, field_path, field_name, pattern, match_count, sample_ref, masked_example)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (tenant_id, source, field_path, pattern) DO UPDATE
+        SET match_count = pii_discoveries.match_count + EXCLUDED.match_count,
+            sample_ref = EXCLUDED.sample_ref, masked_example = EXCLUDED.masked_example, last_seen_at = NOW()
+        RETURNING discovery_id, xmax = 0
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(source.as_str())
+    .bind(field_path)
+    .bind(&finding.field_name)
+    .bind(pattern.as_str())
+    .bind(finding.matches)
+    .bind(finding.sample_ref)
+    .bind(&finding.masked_example)
+    .fetch_one(db)
+    .await?;
+    let discovery = get(db, tenant_id, discovery_id).await?.ok_or(sqlx::Error::RowNotFound)?;
+    Ok((discovery, inserted))
+}
+
+fn collect(
+    findings: &mut HashMap<FindingKey, Finding>,
+    tenant_id: Uuid,
+    source: Source,
+    sample_ref: Uuid,
+    matches: Vec<(String, String, Pattern, String)>,
+) {
+    for (field_path, field_name, pattern, masked_example) in matches {
+        let finding = findings
+            .entry((tenant_id, source, field_path, pattern))
+            .or_insert_with(|| Finding {
+                field_name,
+                matches: 0,
+                sample_ref,
+                masked_example: masked_example.clone(),
+            });
+        finding.matches += 1;
+        finding.sample_ref = sample_ref;
+        finding.masked_example = masked_example;
+    }
+}
+
+/// Walk a payload, collecting (path, key, pattern, masked value) for strings under keys `skip` does not cover
+///
+/// Numbers are left alone: amounts and quantities would match far more often than phone numbers.
+fn scan(
+    value: &serde_json::Value,
+    path: &str,
+    field_name: &str,
+    skip: &dyn Fn(&str) -> bool,
+    matches: &mut Vec<(String, String, Pattern, String)>,
+) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                if skip(key) {
+                    continue;
+                }
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                scan(value, &child, key, skip, matches);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            let child = format!("{}[]", path);
+            for item in items {
+                scan(item, &child, field_name, skip, matches);
+            }
+        }
+        serde_json::Value::String(text) if !field_name.is_empty() && field_name.len() <= MAX_FIELD_NAME => {
+            if pii::is_sealed(text) {
+                return;
+            }
+            if let Some((pattern, matched)) = classify(text) {
+                matches.push((path.to_string(), field_name.to_string(), pattern, mask(matched)));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The kind of personal data a value holds, with the matching part
+pub fn classify(value: &str) -> Option<(Pattern, &str)> {
+    let trimmed = value.trim();
+    if trimmed.len() <= 20 {
+        let digits: String = trimmed.chars().filter(|c| !matches!(c, ' ' | '-' | '(' | ')' | '.')).collect();
+        let digits = digits.strip_prefix('+').unwrap_or(&digits);
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            if is_aadhaar(digits) {
+                return Some((Pattern::Aadhaar, trimmed));
+            }
+            if is_mobile(digits) {
+                return Some((Pattern::Phone, trimmed));
+            }
+        }
+    }
+    // PANs also turn up inside free text such as remarks
+    value
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .find(|token| is_pan(token))
+        .map(|token| (Pattern::Pan, token))
+}
+
+/// Five letters, four digits and a letter, the fourth letter being a holder type
+fn is_pan(token: &str) -> bool {
+    let bytes = token.as_bytes();
+    bytes.len() == 10
+        && bytes[..5].iter().all(u8::is_ascii_uppercase)
+        && b"ABCEFGHJLPT".contains(&bytes[3])
+        && bytes[5..9].iter().all(u8::is_ascii_digit)
+        && bytes[9].is_ascii_uppercase()
+}
+
+/// Twelve digits not starting with 0 or 1 whose last digit is the Verhoeff check digit
+fn is_aadhaar(digits: &str) -> bool {
+    const D: [[u8; 10]; 10] = [
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        [1, 2, 3, 4, 0, 6, 7, 8, 9, 5],
+        [2, 3, 4, 0, 1, 7, 8, 9, 5, 6],
+        [3, 4, 0, 1, 2, 8, 9, 5, 6, 7],
+        [4, 0, 1, 2, 3, 9, 5, 6, 7, 8],
+        [5, 9, 8, 7, 6, 0, 4, 3, 2, 1],
+        [6, 5, 9, 8, 7, 1, 0, 4, 3, 2],
+        [7, 6, 5, 9, 8, 2, 1, 0, 4, 3],
+        [8, 7, 6, 5, 9, 3, 2, 1, 0, 4],
+        [9, 8, 7, 6, 5, 4, 3, 2, 1, 0],
+    ];
+    const P: [[u8; 10]; 8] = [
+        [0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        [1, 5, 7, 6, 2, 8, 3, 0, 9, 4],
+        [5, 8, 0, 3, 7, 9, 6, 2, 4, 1],
+        [8, 9, 1, 6, 0, 4, 3, 5, 2, 7],
+        [9, 4, 5, 3, 1, 2, 6, 8, 7, 0],
+        [4, 2, 8, 6, 5, 7, 0, 3, 9, 1],
+        [2, 7, 9, 3, 8, 0, 6, 4, 1, 5],
+        [7, 0, 4, 6, 9, 1, 3, 2, 5, 8],
+    ];
+    let bytes = digits.as_bytes();
+    if bytes.len() != 12 || bytes[0] < b'2' {
+        return false;
+    }
+    let check = bytes
+        .iter()
+        .rev()
+        .enumerate()
+        .fold(0u8, |check, (i, digit)| D[check as usize][P[i % 8][(digit - b'0') as usize] as usize]);
+    check == 0
+}
+
+/// An Indian mobile number, with or without the 91 or 0 prefix
+fn is_mobile(digits: &str) -> bool {
+    let number = match digits.len() {
+        12 => digits.strip_prefix("91"),
+        11 => digits.strip_prefix('0'),
+        10 => Some(digits),
+        _ => None,
+    };
+    number.is_some_and(|number| matches!(number.as_bytes()[0], b'6'..=b'9'))
+}
+
+/// Keep only the last two characters
+fn mask(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let keep = chars.len().saturating_sub(2);
+    chars
+        .iter()
+        .enumerate()
+        .map(|(i, c)| if i < keep && c.is_ascii_alphanumeric() { '*' } else { *c })
+        .collect()
+}
+
+pub async fn list(db: &PgPool, tenant_id: Uuid, status: Option<&str>) -> Result<Vec<PiiDiscovery>, sqlx::Error> {
+    sqlx::query_as::<_, PiiDiscovery>(
+        r#"
+        SELECT * FROM pii_discoveries
+        WHERE tenant_id = $1 AND ($2::text IS NULL OR status = $2)
+        ORDER BY last_seen_at DESC
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(status)
+    .fetch_all(db)
+    .await
+}
+
+async fn get(db: &PgPool, tenant_id: Uuid, discovery_id: Uuid) -> Result<Option<PiiDiscovery>, sqlx::Error> {
+    sqlx::query_as::<_, PiiDiscovery>("SELECT * FROM pii_discoveries WHERE tenant_id = $1 AND discovery_id = $2")
+        .bind(tenant_id)
+        .bind(discovery_id)
+        .fetch_optional(db)
+        .await
+}
+
+pub async fn tenant_fields(db: &PgPool, tenant_id: Uuid) -> Result<Vec<TenantPiiField>, sqlx::Error> {
+    sqlx::query_as::<_, TenantPiiField>(
+        r#"
+        SELECT field_name, discovery_id, added_by, added_at
+        FROM audit_pii_tenant_fields
+        WHERE tenant_id = $1
+        ORDER BY field_name
+        "#,
+    )
+    .bind(tenant_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Seal the finding's key in the tenant's future events
+pub async fn enforce(
+    db: &PgPool,
+    vault: &PiiVault,
+    tenant_id: Uuid,
+    discovery_id: Uuid,
+    request: &ResolveRequest,
+) -> Result<PiiDiscovery, DiscoveryError> {
+    let mut tx = db.begin().await?;
+    let discovery = sqlx::query_as::<_, PiiDiscovery>(
+        r#"
+        UPDATE pii_discoveries
+        SET status = 'ENFORCED', resolved_by = $3, resolved_at = NOW(), resolution_note = $4
+        WHERE tenant_id = $1 AND discovery_id = $2 AND status <> 'ENFORCED'
+        RETURNING *
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(discovery_id)
+    .bind(request.resolved_by)
+    .bind(request.note.as_deref().map(str::trim))
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(discovery) = discovery else {
+        tx.rollback().await?;
+        return match get(db, tenant_id, discovery_id).await? {
+            Some(_) => Err(DiscoveryError::Conflict("the finding is already enforced")),
+            None => Err(DiscoveryError::NotFound),
+        };
+    };
+    sqlx::query(
+        r#"
+        INSERT INTO audit_pii_tenant_fields (tenant_id, field_name, discovery_id, added_by)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (tenant_id, field_name) DO NOTHING
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(&discovery.field_name)
+    .bind(discovery_id)
+    .bind(request.resolved_by)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    vault.forget_tenant_fields(tenant_id);
+    info!(
+        "Sealing '{}' in audit events of tenant {} after PII finding {}",
+        discovery.field_name, tenant_id, discovery_id
+    );
+    Ok(discovery)
+}
+
+/// Mark an open finding as not personal data
+pub async fn dismiss(
+    db: &PgPool,
+    tenant_id: Uuid,
+    discovery_id: Uuid,
+    request: &ResolveRequest,
+) -> Result<PiiDiscovery, DiscoveryError> {
+    let dismissed = sqlx::query_as::<_, PiiDiscovery>(
+        r#"
+        UPDATE pii_discoveries
+        SET status = 'DISMISSED', resolved_by = $3, resolved_at = NOW(), resolution_note = $4
+        WHERE tenant_id = $1 AND discovery_id = $2 AND status = 'OPEN'
+        RETURNING *
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(discovery_id)
+    .bind(request.resolved_by)
+    .bind(request.note.as_deref().map(str::trim))
+    .fetch_optional(db)
+    .await?;
+    match dismissed {
+        Some(discovery) => Ok(discovery),
+        None => match get(db, tenant_id, discovery_id).await? {
+            Some(_) => Err(DiscoveryError::Conflict("only open findings can be dismissed")),
+            None => Err(DiscoveryError::NotFound),
+        },
+    }
+}
+
+/// Audit the change to the tenant's sealed fields
+pub async fn record_enforcement(state: &AppState, discovery: &PiiDiscovery) {
+    let request = CreateAuditEventRequest {
+        tenant_id: discovery.tenant_id,
+        user_id: discovery.resolved_by,
+        action: "privacy.pii_field.enforce".to_string(),
+        resource_type: "PII_DISCOVERY".to_string(),
+        resource_id: Some(discovery.discovery_id),
+        old_values: None,
+        new_values: Some(serde_json::json!({
+            "field_name": discovery.field_name,
+            "field_path": discovery.field_path,
+            "pattern": discovery.pattern,
+            "source": discovery.source,
+        })),
+        metadata: None,
+    };
+    let context = RequestContext::new(None, Some("pii-discovery"), None);
+    if let Err(e) = AuditService::from_state(state.clone()).create_audit_event(request, &context).await {
+        error!("Failed to audit enforcement of PII finding {}: {}", discovery.discovery_id, e);
+    }
+}
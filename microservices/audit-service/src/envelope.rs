@@ -0,0 +1,621 @@
+//! Envelope encryption of audit event values
+//!
+//! old_values and new_values are encrypted with AES-256-GCM under the
+//! tenant's active data key before the event is hashed, so Postgres, MongoDB,
+//! IPFS, the event bus and archives only ever hold ciphertext. Data keys are
+//! wrapped by a master key, either a local keyring (AUDIT_ENVELOPE_MASTER_KEYS)
+//! or an AWS KMS key (AUDIT_ENVELOPE_KMS_KEY_ID), and stored in
+//! audit_data_keys.
+//!
+//! The hash covers the ciphertext, so neither kind of rotation rewrites
+//! events: a new data key only applies to events written after it, and a new
+//! master key rewraps the rows of audit_data_keys. The previous master key
+//! must stay available until the rewrap has finished.
+//!
+//! Trail responses are decrypted for callers whose bearer token carries a role
+//! in AUDIT_DECRYPT_ROLES for the event's tenant and returned encrypted to
+//! everyone else. Personal data inside the values is sealed by `pii` first, so
+//! crypto-shredding still applies once the values are decrypted. Events
+//! written before encryption was enabled stay in clear.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aws_sdk_kms::primitives::Blob;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{AppState, AuditEvent};
+
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+/// How long an instance keeps its cached active key before looking for a newer one
+const ACTIVE_KEY_CACHE: Duration = Duration::from_secs(60);
+const REWRAP_BATCH: i64 = 500;
+
+#[derive(Debug, Error)]
+pub enum EnvelopeError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("invalid envelope configuration: {0}")]
+    Config(String),
+    #[error("master key {0} is not configured")]
+    UnknownMasterKey(String),
+    #[error("KMS error: {0}")]
+    Kms(String),
+    #[error("failed to {0} audit values")]
+    Crypto(&'static str),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct DataKey {
+    pub key_id: Uuid,
+    pub tenant_id: Uuid,
+    pub master_key_id: String,
+    pub status: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub retired_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub rewrapped_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct RewrapSummary {
+    pub master_key_id: String,
+    pub rewrapped: u64,
+    pub failed: u64,
+}
+
+/// Key-encryption keys for the tenant data keys
+enum MasterKeys {
+    Local {
+        active: String,
+        keys: HashMap<String, Aes256Gcm>,
+    },
+    Kms {
+        client: aws_sdk_kms::Client,
+        key_id: String,
+    },
+}
+
+impl MasterKeys {
+    /// Parse `id:hex,id:hex`; the active key defaults to the first entry
+    fn local(spec: &str, active: Option<String>) -> Result<Self, EnvelopeError> {
+        let mut keys = HashMap::new();
+        let mut first = None;
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (id, key) = entry
+                .split_once(':')
+                .ok_or_else(|| EnvelopeError::Config("AUDIT_ENVELOPE_MASTER_KEYS entries must be id:hex".to_string()))?;
+            let key = hex::decode(key).map_err(|e| EnvelopeError::Config(format!("master key {}: {}", id, e)))?;
+            if key.len() != 32 {
+                return Err(EnvelopeError::Config(format!("master key {} must be 32 bytes of hex", id)));
+            }
+            first.get_or_insert_with(|| id.to_string());
+            keys.insert(id.to_string(), Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+        }
+        let active = active
+            .or(first)
+            .ok_or_else(|| EnvelopeError::Config("AUDIT_ENVELOPE_MASTER_KEYS is empty".to_string()))?;
+        if !keys.contains_key(&active) {
+            return Err(EnvelopeError::UnknownMasterKey(active));
+        }
+        Ok(Self::Local { active, keys })
+    }
+
+    fn active_id(&self) -> &str {
+        match self {
+            Self::Local { active, .. } => active,
+            Self::Kms { key_id, .. } => key_id,
+        }
+    }
+
+    async fn wrap(&self, tenant_id: Uuid, key_id: Uuid, data_key: &[u8]) -> Result<Vec<u8>, EnvelopeError> {
+        match self {
+            Self::Local { active, keys } => {
+                let master = keys
+                    .get(active)
+                    .ok_or_else(|| EnvelopeError::UnknownMasterKey(active.clone()))?;
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let ciphertext = master
+                    .encrypt(&nonce, Payload { msg: data_key, aad: &wrap_aad(tenant_id, key_id) })
+                    .map_err(|_| EnvelopeError::Crypto("wrap"))?;
+                Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+            }
+            Self::Kms { client, key_id: kms_key_id } => {
+                let output = client
+                    .encrypt()
+                    .key_id(kms_key_id)
+                    .plaintext(Blob::new(data_key))
+                    .encryption_context("tenant_id", tenant_id.to_string())
+                    .encryption_context("key_id", key_id.to_string())
+                    .send()
+                    .await
+                    .map_err(|e| EnvelopeError::Kms(e.to_string()))?;
+                output
+                    .ciphertext_blob()
+                    .map(|blob| blob.as_ref().to_vec())
+                    .ok_or_else(|| EnvelopeError::Kms("encrypt returned no ciphertext".to_string()))
+            }
+        }
+    }
+
+    async fn unwrap(
+        &self,
+        master_key_id: &str,
+        tenant_id: Uuid,
+        key_id: Uuid,
+        wrapped: &[u8],
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        match self {
+            Self::Local { keys, .. } => {
+                let master = keys
+                    .get(master_key_id)
+                    .ok_or_else(|| EnvelopeError::UnknownMasterKey(master_key_id.to_string()))?;
+                if wrapped.len() < NONCE_LEN {
+                    return Err(EnvelopeError::Crypto("unwrap"));
+                }
+                let (nonce, ciphertext) = wrapped.split_at(NONCE_LEN);
+                master
+                    .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &wrap_aad(tenant_id, key_id) })
+                    .map_err(|_| EnvelopeError::Crypto("unwrap"))
+            }
+            Self::Kms { client, .. } => {
+                let output = client
+                    .decrypt()
+                    .key_id(master_key_id)
+                    .ciphertext_blob(Blob::new(wrapped))
+                    .encryption_context("tenant_id", tenant_id.to_string())
+                    .encryption_context("key_id", key_id.to_string())
+                    .send()
+                    .await
+                    .map_err(|e| EnvelopeError::Kms(e.to_string()))?;
+                output
+                    .plaintext()
+                    .map(|blob| blob.as_ref().to_vec())
+                    .ok_or_else(|| EnvelopeError::Kms("decrypt returned no plaintext".to_string()))
+            }
+        }
+    }
+}
+
+struct ActiveKey {
+    key_id: Uuid,
+    cipher: Aes256Gcm,
+    created_at: chrono::DateTime<chrono::Utc>,
+    loaded_at: Instant,
+}
+
+pub struct Envelope {
+    master: MasterKeys,
+    /// Active data keys older than this are replaced on next use
+    data_key_max_age: chrono::Duration,
+    decrypt_roles: HashSet<String>,
+    decoding_key: DecodingKey,
+    active: RwLock<HashMap<Uuid, ActiveKey>>,
+    /// Unwrapped data keys by key id; key material never changes, only its wrapping
+    keys: RwLock<HashMap<Uuid, Aes256Gcm>>,
+}
+
+impl Envelope {
+    /// Build from AUDIT_ENVELOPE_*; `None` when no master key is configured
+    pub async fn from_env() -> Result<Option<Self>, EnvelopeError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+
+        let master = if let Some(key_id) = var("AUDIT_ENVELOPE_KMS_KEY_ID") {
+            let config = aws_config::load_from_env().await;
+            MasterKeys::Kms {
+                client: aws_sdk_kms::Client::new(&config),
+                key_id,
+            }
+        } else if let Some(keys) = var("AUDIT_ENVELOPE_MASTER_KEYS") {
+            MasterKeys::local(&keys, var("AUDIT_ENVELOPE_ACTIVE_MASTER_KEY"))?
+        } else {
+            return Ok(None);
+        };
+
+        let jwt_secret = var("JWT_SECRET")
+            .ok_or_else(|| EnvelopeError::Config("JWT_SECRET must be set to authorize decryption".to_string()))?;
+        let max_age_days = var("AUDIT_ENVELOPE_DATA_KEY_MAX_AGE_DAYS")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(90);
+
+        Ok(Some(Self {
+            master,
+            data_key_max_age: chrono::Duration::days(max_age_days),
+            decrypt_roles: var("AUDIT_DECRYPT_ROLES")
+                .unwrap_or_else(|| "COMPLIANCE_OFFICER,AUDITOR".to_string())
+                .split(',')
+                .map(|role| role.trim().to_string())
+                .filter(|role| !role.is_empty())
+                .collect(),
+            decoding_key: DecodingKey::from_secret(jwt_secret.as_bytes()),
+            active: RwLock::new(HashMap::new()),
+            keys: RwLock::new(HashMap::new()),
+        }))
+    }
+
+    pub fn master_key_id(&self) -> &str {
+        self.master.active_id()
+    }
+
+    /// Encrypt old_values/new_values in place; runs after PII sealing and before hashing
+    pub async fn encrypt(&self, db: &PgPool, event: &mut AuditEvent) -> Result<(), EnvelopeError> {
+        if event.old_values.is_none() && event.new_values.is_none() {
+            return Ok(());
+        }
+        let (key_id, cipher) = self.active_key(db, event.tenant_id).await?;
+        let event_id = event.event_id;
+        for (field, values) in [("old_values", &mut event.old_values), ("new_values", &mut event.new_values)] {
+            if let Some(value) = values.take() {
+                let plaintext = serde_json::to_vec(&value)?;
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let ciphertext = cipher
+                    .encrypt(&nonce, Payload { msg: &plaintext, aad: &value_aad(event_id, field) })
+                    .map_err(|_| EnvelopeError::Crypto("encrypt"))?;
+                *values = Some(serde_json::Value::String(format!(
+                    "{}{}:{}",
+                    ENCRYPTED_PREFIX,
+                    key_id,
+                    STANDARD.encode([nonce.as_slice(), ciphertext.as_slice()].concat())
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypt old_values/new_values for a response
+    ///
+    /// Only for responses: the decrypted event no longer matches its hash.
+    pub async fn decrypt(&self, db: &PgPool, event: &mut AuditEvent) -> Result<(), EnvelopeError> {
+        let (tenant_id, event_id) = (event.tenant_id, event.event_id);
+        for (field, values) in [("old_values", &mut event.old_values), ("new_values", &mut event.new_values)] {
+            let Some((key_id, sealed)) = values.as_ref().and_then(|value| value.as_str()).and_then(parse_encrypted)
+            else {
+                continue;
+            };
+            if sealed.len() < NONCE_LEN {
+                return Err(EnvelopeError::Crypto("decrypt"));
+            }
+            let cipher = self.key(db, tenant_id, key_id).await?;
+            let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+            let plaintext = cipher
+                .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &value_aad(event_id, field) })
+                .map_err(|_| EnvelopeError::Crypto("decrypt"))?;
+            *values = Some(serde_json::from_slice(&plaintext)?);
+        }
+        Ok(())
+    }
+
+    /// Encrypt a blob under the tenant's active data key; returns the key id and nonce || ciphertext
+    pub async fn encrypt_blob(
+        &self,
+        db: &PgPool,
+        tenant_id: Uuid,
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<(Uuid, Vec<u8>), EnvelopeError> {
+        let (key_id, cipher) = self.active_key(db, tenant_id).await?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad })
+            .map_err(|_| EnvelopeError::Crypto("encrypt"))?;
+        Ok((key_id, [nonce.as_slice(), ciphertext.as_slice()].concat()))
+    }
+
+    pub async fn decrypt_blob(
+        &self,
+        db: &PgPool,
+        tenant_id: Uuid,
+        key_id: Uuid,
+        aad: &[u8],
+        sealed: &[u8],
+    ) -> Result<Vec<u8>, EnvelopeError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(EnvelopeError::Crypto("decrypt"));
+        }
+        let cipher = self.key(db, tenant_id, key_id).await?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| EnvelopeError::Crypto("decrypt"))
+    }
+
+    /// The tenant's active data key, rotated once it is past its maximum age
+    async fn active_key(&self, db: &PgPool, tenant_id: Uuid) -> Result<(Uuid, Aes256Gcm), EnvelopeError> {
+        if let Some(active) = self.active.read().expect("envelope key cache poisoned").get(&tenant_id) {
+            if active.loaded_at.elapsed() < ACTIVE_KEY_CACHE && !self.expired(active.created_at) {
+                return Ok((active.key_id, active.cipher.clone()));
+            }
+        }
+
+        let current = match self.load_active(db, tenant_id).await? {
+            Some(active) if !self.expired(active.created_at) => active,
+            _ => match self.create_key(db, tenant_id).await {
+                Ok(active) => active,
+                // Another instance rotated at the same time; use its key
+                Err(EnvelopeError::Database(sqlx::Error::Database(e))) if e.code().as_deref() == Some("23505") => self
+                    .load_active(db, tenant_id)
+                    .await?
+                    .ok_or(EnvelopeError::Crypto("find the active data key for"))?,
+                Err(e) => return Err(e),
+            },
+        };
+
+        let result = (current.key_id, current.cipher.clone());
+        self.active.write().expect("envelope key cache poisoned").insert(tenant_id, current);
+        Ok(result)
+    }
+
+    async fn load_active(&self, db: &PgPool, tenant_id: Uuid) -> Result<Option<ActiveKey>, EnvelopeError> {
+        let row: Option<(Uuid, String, Vec<u8>, chrono::DateTime<chrono::Utc>)> = sqlx::query_as(
+            r#"
+            SELECT key_id, master_key_id, wrapped_key, created_at FROM audit_data_keys
+            WHERE tenant_id = $1 AND status = 'ACTIVE'
+            "#,
+        )
+        .bind(tenant_id)
+        .fetch_optional(db)
+        .await?;
+        let Some((key_id, master_key_id, wrapped, created_at)) = row else {
+            return Ok(None);
+        };
+        let data_key = self.master.unwrap(&master_key_id, tenant_id, key_id, &wrapped).await?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key));
+        self.keys.write().expect("envelope key cache poisoned").insert(key_id, cipher.clone());
+        Ok(Some(ActiveKey {
+            key_id,
+            cipher,
+            created_at,
+            loaded_at: Instant::now(),
+        }))
+    }
+
+    /// Retire the tenant's active data key and create a new one
+    async fn create_key(&self, db: &PgPool, tenant_id: Uuid) -> Result<ActiveKey, EnvelopeError> {
+        let key_id = Uuid::new_v4();
+        let data_key = Aes256Gcm::generate_key(OsRng);
+        let wrapped = self.master.wrap(tenant_id, key_id, data_key.as_slice()).await?;
+
+        let mut tx = db.begin().await?;
+        sqlx::query(
+            "UPDATE audit_data_keys SET status = 'RETIRED', retired_at = NOW() WHERE tenant_id = $1 AND status = 'ACTIVE'",
+        )
+        .bind(tenant_id)
+        .execute(&mut *tx)
+        .await?;
+        let created_at: chrono::DateTime<chrono::Utc> = sqlx::query_scalar(
+            r#"
+            INSERT INTO audit_data_keys (key_id, tenant_id, wrapped_key, master_key_id)
+            VALUES ($1, $2, $3, $4)
+            RETURNING created_at
+            "#,
+        )
+        .bind(key_id)
+        .bind(tenant_id)
+        .bind(&wrapped)
+        .bind(self.master.active_id())
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        info!("Created audit data key {} for tenant {}", key_id, tenant_id);
+        let cipher = Aes256Gcm::new(&data_key);
+        self.keys.write().expect("envelope key cache poisoned").insert(key_id, cipher.clone());
+        Ok(ActiveKey {
+            key_id,
+            cipher,
+            created_at,
+            loaded_at: Instant::now(),
+        })
+    }
+
+    fn expired(&self, created_at: chrono::DateTime<chrono::Utc>) -> bool {
+        chrono::Utc::now() - created_at > self.data_key_max_age
+    }
+
+    async fn key(&self, db: &PgPool, tenant_id: Uuid, key_id: Uuid) -> Result<Aes256Gcm, EnvelopeError> {
+        if let Some(cipher) = self.keys.read().expect("envelope key cache poisoned").get(&key_id) {
+            return Ok(cipher.clone());
+        }
+        let (master_key_id, wrapped): (String, Vec<u8>) =
+            sqlx::query_as("SELECT master_key_id, wrapped_key FROM audit_data_keys WHERE key_id = $1 AND tenant_id = $2")
+                .bind(key_id)
+                .bind(tenant_id)
+                .fetch_optional(db)
+                .await?
+                .ok_or(EnvelopeError::Crypto("find the data key for"))?;
+        let data_key = self.master.unwrap(&master_key_id, tenant_id, key_id, &wrapped).await?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key));
+        self.keys.write().expect("envelope key cache poisoned").insert(key_id, cipher.clone());
+        Ok(cipher)
+    }
+
+    /// Rotate the tenant's data key now rather than when it ages out
+    pub async fn rotate(&self, db: &PgPool, tenant_id: Uuid) -> Result<DataKey, EnvelopeError> {
+        let active = self.create_key(db, tenant_id).await?;
+        let key = sqlx::query_as::<_, DataKey>(
+            r#"
+            SELECT key_id, tenant_id, master_key_id, status, created_at, retired_at, rewrapped_at
+            FROM audit_data_keys WHERE key_id = $1
+            "#,
+        )
+        .bind(active.key_id)
+        .fetch_one(db)
+        .await?;
+        self.active.write().expect("envelope key cache poisoned").insert(tenant_id, active);
+        Ok(key)
+    }
+
+    /// Rewrap every data key still under an older master key with the active one
+    pub async fn rewrap(&self, db: &PgPool) -> Result<RewrapSummary, EnvelopeError> {
+        let active = self.master.active_id().to_string();
+        let mut summary = RewrapSummary {
+            master_key_id: active.clone(),
+            rewrapped: 0,
+            failed: 0,
+        };
+        let mut after = Uuid::nil();
+        loop {
+            let rows: Vec<(Uuid, Uuid, String, Vec<u8>)> = sqlx::query_as(
+                r#"
+                SELECT key_id, tenant_id, master_key_id, wrapped_key FROM audit_data_keys
+                WHERE master_key_id <> $1 AND key_id > $2
+                ORDER BY key_id
+                LIMIT $3
+                "#,
+            )
+            .bind(&active)
+            .bind(after)
+            .bind(REWRAP_BATCH)
+            .fetch_all(db)
+            .await?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for (key_id, tenant_id, master_key_id, wrapped) in rows {
+                after = key_id;
+                let rewrapped = async {
+                    let data_key = self.master.unwrap(&master_key_id, tenant_id, key_id, &wrapped).await?;
+                    let wrapped = self.master.wrap(tenant_id, key_id, &data_key).await?;
+                    sqlx::query(
+                        r#"
+                        UPDATE audit_data_keys SET wrapped_key = $2, master_key_id = $3, rewrapped_at = NOW()
+                        WHERE key_id = $1 AND master_key_id = $4
+                        "#,
+                    )
+                    .bind(key_id)
+                    .bind(&wrapped)
+                    .bind(&active)
+                    .bind(&master_key_id)
+                    .execute(db)
+                    .await?;
+                    Ok::<_, EnvelopeError>(())
+                }
+                .await;
+                match rewrapped {
+                    Ok(()) => summary.rewrapped += 1,
+                    Err(e) => {
+                        warn!("Failed to rewrap audit data key {} from {}: {}", key_id, master_key_id, e);
+                        summary.failed += 1;
+                    }
+                }
+            }
+        }
+
+        info!(
+            "Rewrapped {} audit data keys under master key {} ({} failed)",
+            summary.rewrapped, active, summary.failed
+        );
+        Ok(summary)
+    }
+}
+
+pub async fn list_data_keys(db: &PgPool, tenant_id: Uuid) -> Result<Vec<DataKey>, sqlx::Error> {
+    sqlx::query_as::<_, DataKey>(
+        r#"
+        SELECT key_id, tenant_id, master_key_id, status, created_at, retired_at, rewrapped_at
+        FROM audit_data_keys WHERE tenant_id = $1
+        ORDER BY created_at DESC
+        "#,
+    )
+    .bind(tenant_id)
+    .fetch_all(db)
+    .await
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: Uuid,
+    tenant_id: Uuid,
+    role: String,
+}
+
+/// Caller's right to see decrypted audit values, from an optional bearer token
+///
+/// A missing, invalid or insufficient token is not an error; the caller just
+/// gets the values encrypted.
+#[derive(Debug, Clone, Default)]
+pub struct Reader {
+    pub user_id: Option<Uuid>,
+    tenant_id: Option<Uuid>,
+}
+
+impl Reader {
+    /// Callers without a token, such as internal gRPC clients
+    pub fn anonymous() -> Self {
+        Self::default()
+    }
+
+    pub fn may_decrypt(&self, tenant_id: Uuid) -> bool {
+        self.tenant_id == Some(tenant_id)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Reader {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(envelope) = &state.envelope else {
+            return Ok(Self::anonymous());
+        };
+        let Some(token) = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return Ok(Self::anonymous());
+        };
+
+        match jsonwebtoken::decode::<Claims>(token, &envelope.decoding_key, &Validation::new(Algorithm::HS256)) {
+            Ok(data) if envelope.decrypt_roles.contains(&data.claims.role) => Ok(Self {
+                user_id: Some(data.claims.sub),
+                tenant_id: Some(data.claims.tenant_id),
+            }),
+            Ok(_) => Ok(Self::anonymous()),
+            Err(e) => {
+                warn!("Ignoring invalid bearer token on audit read: {}", e);
+                Ok(Self::anonymous())
+            }
+        }
+    }
+}
+
+fn wrap_aad(tenant_id: Uuid, key_id: Uuid) -> Vec<u8> {
+    [tenant_id.as_bytes().as_slice(), key_id.as_bytes().as_slice()].concat()
+}
+
+/// Binds the ciphertext to its event and field, so values cannot be swapped between them
+fn value_aad(event_id: Uuid, field: &str) -> Vec<u8> {
+    [event_id.as_bytes().as_slice(), field.as_bytes()].concat()
+}
+
+/// Whether old_values or new_values is still ciphertext, i.e. was not decrypted for the reader
+pub fn is_encrypted(values: &Option<serde_json::Value>) -> bool {
+    values
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .is_some_and(|value| parse_encrypted(value).is_some())
+}
+
+fn parse_encrypted(value: &str) -> Option<(Uuid, Vec<u8>)> {
+    let (key_id, sealed) = value.strip_prefix(ENCRYPTED_PREFIX)?.split_once(':')?;
+    Some((key_id.parse().ok()?, STANDARD.decode(sealed).ok()?))
+}
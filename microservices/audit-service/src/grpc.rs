@@ -0,0 +1,182 @@
+//! gRPC ingestion API for internal services
+
+use std::collections::HashMap;
+use tonic::{Request, Response, Status};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::auth::Caller;
+use crate::context::RequestContext;
+use crate::envelope::Reader;
+use crate::schemas::SchemaRejection;
+use crate::taxonomy::ActionRejection;
+use crate::trail::AuditTrailParams;
+use crate::{AppState, AuditService, CreateAuditEventRequest};
+
+pub mod proto {
+    tonic::include_proto!("dharmaguard.audit.v1");
+}
+
+use proto::audit_ingestion_server::AuditIngestion;
+pub use proto::audit_ingestion_server::AuditIngestionServer;
+
+pub struct AuditIngestionService {
+    state: AppState,
+}
+
+impl AuditIngestionService {
+    pub fn new(state: AppState) -> Self {
+        Self { state }
+    }
+
+    fn audit_service(&self) -> AuditService {
+        AuditService::from_state(self.state.clone())
+    }
+}
+
+#[tonic::async_trait]
+impl AuditIngestion for AuditIngestionService {
+    async fn create_audit_event(
+        &self,
+        request: Request<proto::CreateAuditEventRequest>,
+    ) -> Result<Response<proto::AuditEvent>, Status> {
+        // Internal callers connect directly, so the peer address is the client
+        let metadata = |key: &str| request.metadata().get(key).and_then(|value| value.to_str().ok());
+        let producer = request.extensions().get::<Caller>().and_then(Caller::producer);
+        let context = RequestContext::new(
+            request.remote_addr().map(|addr| addr.ip()),
+            metadata("user-agent"),
+            metadata("x-request-id"),
+        )
+        .with_producer(producer);
+        let request = request.into_inner();
+
+        let create = CreateAuditEventRequest {
+            tenant_id: parse_uuid("tenant_id", &request.tenant_id)?,
+            user_id: parse_optional_uuid("user_id", request.user_id.as_deref())?,
+            action: request.action,
+            resource_type: request.resource_type,
+            resource_id: parse_optional_uuid("resource_id", request.resource_id.as_deref())?,
+            old_values: parse_optional_json("old_values_json", request.old_values_json.as_deref())?,
+            new_values: parse_optional_json("new_values_json", request.new_values_json.as_deref())?,
+            metadata: (!request.metadata.is_empty()).then(|| {
+                request
+                    .metadata
+                    .into_iter()
+                    .map(|(key, value)| (key, serde_json::Value::String(value)))
+                    .collect::<HashMap<_, _>>()
+            }),
+        };
+        if create.action.is_empty() || create.resource_type.is_empty() {
+            return Err(Status::invalid_argument("action and resource_type are required"));
+        }
+
+        match self.audit_service().create_audit_event(create, &context).await {
+            Ok(event) => Ok(Response::new(to_proto_event(event))),
+            Err(e) => {
+                if let Some(SchemaRejection(check)) = e.downcast_ref::<SchemaRejection>() {
+                    return Err(Status::invalid_argument(format!("{}: {}", e, check.errors.join("; "))));
+                }
+                if e.is::<ActionRejection>() {
+                    return Err(Status::invalid_argument(e.to_string()));
+                }
+                error!("Failed to create audit event over gRPC: {}", e);
+                Err(Status::internal("failed to create audit event"))
+            }
+        }
+    }
+
+    async fn get_audit_trail(
+        &self,
+        request: Request<proto::GetAuditTrailRequest>,
+    ) -> Result<Response<proto::GetAuditTrailResponse>, Status> {
+        let request = request.into_inner();
+
+        let params = AuditTrailParams {
+            tenant_id: parse_uuid("tenant_id", &request.tenant_id)?,
+            action: request.action,
+            user_id: parse_optional_uuid("user_id", request.user_id.as_deref())?,
+            resource_type: request.resource_type,
+            resource_id: parse_optional_uuid("resource_id", request.resource_id.as_deref())?,
+            ip_address: request
+                .ip_address
+                .as_deref()
+                .map(|ip| ip.parse())
+                .transpose()
+                .map_err(|_| Status::invalid_argument("ip_address is not a valid IP address"))?,
+            from: request.from.map(from_timestamp).transpose()?,
+            to: request.to.map(from_timestamp).transpose()?,
+            limit: request.limit,
+            offset: request.offset,
+            cursor: request.cursor,
+            exact_count: request.exact_count,
+        };
+        // Only services call over gRPC, and they see every resource type
+        let (filter, page) = params
+            .into_query(Vec::new())
+            .ok_or_else(|| Status::invalid_argument("invalid cursor or time range"))?;
+
+        match self.audit_service().get_audit_trail(&filter, page, &Reader::anonymous()).await {
+            Ok(trail) => Ok(Response::new(proto::GetAuditTrailResponse {
+                events: trail.events.into_iter().map(to_proto_event).collect(),
+                total_count: trail.total_count,
+                count_is_estimate: trail.count_is_estimate,
+                next_cursor: trail.next_cursor,
+                integrity_verified: trail.integrity_verified,
+                blockchain_anchored: trail.blockchain_anchored,
+            })),
+            Err(e) => {
+                error!("Failed to get audit trail over gRPC: {}", e);
+                Err(Status::internal("failed to get audit trail"))
+            }
+        }
+    }
+}
+
+fn parse_uuid(field: &str, value: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(value).map_err(|_| Status::invalid_argument(format!("{} is not a valid UUID", field)))
+}
+
+fn parse_optional_uuid(field: &str, value: Option<&str>) -> Result<Option<Uuid>, Status> {
+    value.map(|value| parse_uuid(field, value)).transpose()
+}
+
+fn parse_optional_json(field: &str, value: Option<&str>) -> Result<Option<serde_json::Value>, Status> {
+    value
+        .map(serde_json::from_str)
+        .transpose()
+        .map_err(|_| Status::invalid_argument(format!("{} is not valid JSON", field)))
+}
+
+fn from_timestamp(timestamp: prost_types::Timestamp) -> Result<chrono::DateTime<chrono::Utc>, Status> {
+    chrono::DateTime::from_timestamp(timestamp.seconds, timestamp.nanos.max(0) as u32)
+        .ok_or_else(|| Status::invalid_argument("timestamp is out of range"))
+}
+
+fn to_timestamp(timestamp: chrono::DateTime<chrono::Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: timestamp.timestamp(),
+        nanos: timestamp.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn to_proto_event(event: crate::AuditEvent) -> proto::AuditEvent {
+    proto::AuditEvent {
+        event_id: event.event_id.to_string(),
+        tenant_id: event.tenant_id.to_string(),
+        user_id: event.user_id.map(|id| id.to_string()),
+        action: event.action,
+        resource_type: event.resource_type,
+        resource_id: event.resource_id.map(|id| id.to_string()),
+        old_values_json: event.old_values.map(|value| value.to_string()),
+        new_values_json: event.new_values.map(|value| value.to_string()),
+        ip_address: event.ip_address,
+        user_agent: event.user_agent,
+        timestamp: Some(to_timestamp(event.timestamp)),
+        event_hash: event.event_hash,
+        blockchain_hash: event.blockchain_hash,
+        ipfs_hash: event.ipfs_hash,
+        signature: event.signature,
+        signing_key_id: event.signing_key_id,
+    }
+}
@@ -0,0 +1,131 @@
+//! Event hashing, signing and integrity verification primitives
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::AuditEvent;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Serialize an event into the canonical form used for hashing.
+///
+/// Integrity fields are cleared first, so the payload is identical at write
+/// time (before they are populated) and at verification time.
+pub fn canonical_payload(event: &AuditEvent) -> Result<Vec<u8>, serde_json::Error> {
+    let canonical = AuditEvent {
+        event_hash: None,
+        blockchain_hash: None,
+        ipfs_hash: None,
+        signature: None,
+        signing_key_id: None,
+        ..event.clone()
+    };
+    serde_json::to_vec(&canonical)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    format!("{:x}", hasher.finalize())
+}
+
+/// HMAC-SHA256 signer for event hashes
+///
+/// Signs with the current key and keeps retired keys around so events signed
+/// before a rotation still verify until they are re-signed.
+pub struct EventSigner {
+    current_key_id: String,
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl EventSigner {
+    pub fn new(key_id: &str, key: &[u8]) -> Self {
+        let mut keys = HashMap::new();
+        keys.insert(key_id.to_string(), key.to_vec());
+        Self {
+            current_key_id: key_id.to_string(),
+            keys,
+        }
+    }
+
+    /// Register a previous key that is only used for verification
+    pub fn with_retired_key(mut self, key_id: &str, key: &[u8]) -> Self {
+        self.keys.insert(key_id.to_string(), key.to_vec());
+        self
+    }
+
+    pub fn current_key_id(&self) -> &str {
+        &self.current_key_id
+    }
+
+    fn mac(&self, key_id: &str, event_hash: &str) -> Option<HmacSha256> {
+        let key = self.keys.get(key_id)?;
+        let mut mac = HmacSha256::new_from_slice(key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(event_hash.as_bytes());
+        Some(mac)
+    }
+
+    /// Sign with the current key
+    pub fn sign(&self, event_hash: &str) -> String {
+        let mac = self
+            .mac(&self.current_key_id, event_hash)
+            .expect("current signing key is always registered");
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Verify against `key_id`, or the current key for events predating key ids
+    pub fn verify(&self, event_hash: &str, signature: &str, key_id: Option<&str>) -> bool {
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+        match self.mac(key_id.unwrap_or(&self.current_key_id), event_hash) {
+            Some(mac) => mac.verify_slice(&signature).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Outcome of a single verification step
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerificationCheck {
+    pub check: String,
+    pub passed: bool,
+    pub reason: Option<String>,
+}
+
+impl VerificationCheck {
+    pub fn pass(check: &str) -> Self {
+        Self {
+            check: check.to_string(),
+            passed: true,
+            reason: None,
+        }
+    }
+
+    pub fn fail(check: &str, reason: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            passed: false,
+            reason: Some(reason.into()),
+        }
+    }
+}
+
+/// Structured verification result returned by /audit/verify/:event_id
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerificationReport {
+    pub event_id: Uuid,
+    pub verified: bool,
+    pub computed_hash: String,
+    pub blockchain_confirmed: bool,
+    pub ipfs_accessible: bool,
+    /// When the time-stamping authority vouched for the hash, if one is configured
+    #[serde(default)]
+    pub timestamped_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub checks: Vec<VerificationCheck>,
+    pub verified_at: chrono::DateTime<chrono::Utc>,
+}
@@ -2,32 +2,214 @@
 //! Blockchain-enabled immutable audit trails with IPFS storage
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post},
     Router,
 };
-use mongodb::{Client as MongoClient, Database};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use sqlx::{PgPool, postgres::PgPoolOptions};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row, postgres::PgRow};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::net::TcpListener;
+use tokio::sync::broadcast;
 use tracing::{info, error, warn};
 use uuid::Uuid;
 use web3::{Web3, transports::Http, types::Address};
+use dharmaguard_common::pool::{self, Admission, PoolSettings};
+use dharmaguard_common::startup::{self, Startup};
+use dharmaguard_common::versioning::{self, Deprecation};
+
+mod actors;
+mod api_keys;
+mod attachments;
+mod auth;
+mod bus;
+mod cache;
+mod compression;
+mod context;
+mod copies;
+mod custody;
+mod diff;
+mod digest;
+mod discovery;
+mod envelope;
+mod export;
+mod grpc;
+mod integrity;
+mod outbox;
+mod partitions;
+mod pii;
+mod pins;
+mod pipeline;
+mod projection;
+mod receipts;
+mod reconcile;
+mod replication;
+mod resign;
+mod retention;
+mod runbooks;
+mod schemas;
+mod stats;
+mod store;
+mod stream;
+mod sweep;
+mod taxonomy;
+mod trail;
+mod tsa;
+mod v2;
+mod wal;
+mod worm;
+
+use crate::auth::{Authenticator, Caller};
+use crate::bus::{BusEvent, EventBus, EventHandler};
+use crate::cache::{AuditCache, CacheSettings};
+use crate::context::{RequestContext, TrustedProxies};
+use crate::copies::DocumentCopies;
+use crate::custody::{CustodyError, CustodyReportDetail, ReportSigner};
+use crate::diff::{DiffSettings, EventDiff};
+use crate::digest::{AnchorDigest, AnchorMode, DigestBuilder, InclusionProof};
+use crate::actors::{ActorResolver, ActorSnapshot};
+use crate::api_keys::{
+    ApiKey, ApiKeyError, ApiKeyListParams, ApiKeys, IssueApiKeyRequest, IssuedApiKey, RotateApiKeyRequest,
+};
+use crate::attachments::{Attachment, AttachmentError, AttachmentSettings, UploadParams};
+use crate::discovery::{DiscoveryError, DiscoverySettings, DiscoverySummary, PiiDiscovery, ResolveRequest, TenantPiiField};
+use crate::envelope::{DataKey, Envelope, Reader, RewrapSummary};
+use crate::grpc::{AuditIngestionServer, AuditIngestionService};
+use crate::integrity::{EventSigner, VerificationCheck, VerificationReport};
+use crate::pii::{Erasure, ErasureRequest, PiiVault};
+use crate::pins::{Pin, PinRegistry, PinSettings, RemotePinning, VerificationSummary};
+use crate::pipeline::{PipelineLatency, PipelineSummary, Stage, Timer};
+use crate::outbox::{AnchorBatch, AnchorPacing, OutboxEntry, OutboxSettings};
+use crate::partitions::{AuditPartition, MaintenanceSummary, PartitionSettings};
+use crate::receipts::{AuditReceipt, ReceiptKey, ReceiptSigner, ReceiptVerification, Receipted};
+use crate::reconcile::ReconciliationRun;
+use crate::replication::{
+    ChainHeads, DivergenceReport, LinkProbe, ProbedLink, ReplicaBatch, ReplicaReceipt, Replication, ReplicationError,
+    ReplicationSettings,
+};
+use crate::resign::{ResignRequest, ResignRun};
+use crate::projection::{ProjectionError, Projector, ResourceKey, ResourceState, StateParams};
+use crate::runbooks::{ConfirmRequest, PlannedJob, Runbook, RunbookError, RunbookJob, RunbookRequest, RunbookSettings};
+use crate::retention::{
+    ArchivalSummary, Archiver, ArchiveStore, AuditArchive, RestoreRequest, RestoreRun, RestoredEvent, RetentionPolicy,
+    RetentionSettings,
+};
+use crate::schemas::{
+    EnforcementMode, EventSchema, RegisterSchemaRequest, SchemaListParams, SchemaRegistry, SchemaRejection,
+    TenantSchemaMode,
+};
+use crate::stats::{AuditStats, StatsError, StatsParams};
+use crate::store::batch::{BatchSettings, DocumentBatcher};
+use crate::store::{AnchorStore, AuditStore, DocumentStore, DocumentUpdate, PostgresAuditStore};
+use crate::sweep::{IntegrityCheck, SweepRequest, SweepSettings};
+use crate::taxonomy::{
+    ActionDefinition, ActionListParams, ActionRejection, ActionTaxonomy, AliasRequest, RegisterActionRequest, TaxonomyError,
+    TaxonomyMode,
+};
+use crate::tsa::{TimestampEvidence, TsaClient};
+use crate::trail::{AuditTrailFilter, AuditTrailParams, TrailCursor, TrailPage};
+use crate::wal::{Pending, QueueReceipt, QueueStatus, QueueSummary, StoredWithoutDocument, WalSettings, WriteAheadQueue};
+use crate::worm::{VerificationParams, WormError, WormMode, WormRefusal, WormSettings, WormVerification};
+
+/// Shared migrations the service depends on, each with a relation it creates
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("007_audit_signing_keys", "audit_signing_keys"),
+    ("008_reconciliation_runs", "reconciliation_runs"),
+    ("011_audit_event_schemas", "audit_event_schemas"),
+    ("012_audit_retention", "audit_retention_rules"),
+    ("013_audit_subject_keys", "audit_subject_keys"),
+    ("016_audit_data_keys", "audit_data_keys"),
+    ("018_integrity_checks", "integrity_checks"),
+    ("020_audit_anchor_outbox", "audit_anchor_outbox"),
+    ("022_ipfs_pins", "ipfs_pins"),
+    ("023_audit_anchor_digests", "audit_anchor_digests"),
+    ("026_custody_reports", "custody_reports"),
+    ("032_audit_event_documents", "audit_event_documents"),
+    ("035_audit_logs_partitioning", "audit_log_partitions"),
+    ("036_pii_discoveries", "pii_discoveries"),
+    ("038_audit_anchor_batches", "audit_anchor_batches"),
+    ("039_audit_event_attachments", "audit_event_attachments"),
+    ("040_operator_runbook_jobs", "operator_runbook_jobs"),
+    ("041_audit_action_taxonomy", "audit_actions"),
+    ("042_pending_anchor_mode", "idx_anchor_digests_pending"),
+    ("043_audit_resource_states", "audit_resource_states"),
+    ("045_audit_logs_stats_index", "idx_audit_logs_tenant_stats"),
+    ("046_audit_timestamps", "audit_timestamps"),
+    ("048_audit_api_keys", "audit_api_keys"),
+    ("049_audit_worm_mode", "audit_worm_state"),
+    ("051_audit_replication", "audit_replication_queue"),
+];
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
-    pub mongodb: Database,
-    pub blockchain_client: Arc<BlockchainClient>,
-    pub ipfs_client: Arc<IpfsClient>,
+    /// Audit log rows, written with their pins and deferred steps
+    pub audit_store: Arc<dyn AuditStore>,
+    /// Signed canonical documents; MongoDB or Postgres per AUDIT_DOCUMENT_STORE
+    pub documents: Arc<dyn DocumentStore>,
+    /// Inserts new MongoDB documents in batches; each is inserted on the write path when AUDIT_MONGO_BATCH_SIZE is 0
+    pub document_batcher: Option<Arc<DocumentBatcher>>,
+    /// `None` in pending anchor mode, without chain access
+    pub blockchain_client: Option<Arc<BlockchainClient>>,
+    /// Payload copies; none are kept when AUDIT_ANCHOR_STORE is none
+    pub anchors: Arc<dyn AnchorStore>,
+    /// Writes and reads payload copies, encrypted per tenant with an envelope master key
+    pub copies: Arc<DocumentCopies>,
+    pub pins: Arc<PinRegistry>,
+    pub anchor_mode: AnchorMode,
+    /// RFC 3161 timestamps of event hashes or digest roots; none are taken when AUDIT_TSA_URL is unset
+    pub tsa: Option<Arc<TsaClient>>,
+    pub digests: Arc<DigestBuilder>,
+    pub signer: Arc<EventSigner>,
+    /// Newly created events, fanned out to /audit/stream subscribers
+    pub event_stream: broadcast::Sender<AuditEvent>,
+    pub trusted_proxies: Arc<TrustedProxies>,
+    pub schema_registry: Arc<SchemaRegistry>,
+    /// Registered actions and aliases that incoming actions are normalized to
+    pub taxonomy: Arc<ActionTaxonomy>,
+    /// Internal event bus; created events are not published when EVENT_BUS is unset
+    pub event_bus: Option<Arc<dyn EventBus>>,
+    /// Cold storage archival; archival and restores are unavailable when AUDIT_ARCHIVE_BUCKET is unset
+    pub archiver: Option<Arc<Archiver>>,
+    pub retention_settings: RetentionSettings,
+    /// Seals personal data for crypto-shredding; stored in clear when AUDIT_PII_MASTER_KEY is unset
+    pub pii: Option<Arc<PiiVault>>,
+    /// Embeds actor snapshots in new events; they only carry user_id when USER_SERVICE_URL is unset
+    pub actors: Option<Arc<ActorResolver>>,
+    /// Encrypts old_values/new_values; stored in clear when no AUDIT_ENVELOPE_* master key is set
+    pub envelope: Option<Arc<Envelope>>,
+    /// Recently read events and verification results, partitioned per tenant
+    pub cache: Arc<AuditCache>,
+    pub sweep_settings: Arc<SweepSettings>,
+    /// Redacted paths of GET /audit/events/:event_id/diff
+    pub diff_settings: Arc<DiffSettings>,
+    /// Stage timings of create_audit_event, for GET /admin/pipeline-latency
+    pub pipeline_latency: Arc<PipelineLatency>,
+    /// Signs chain-of-custody reports; they cannot be generated when AUDIT_REPORT_SIGNING_KEY is unset
+    pub report_signer: Option<Arc<ReportSigner>>,
+    /// Signs receipts of created events; none are issued when AUDIT_RECEIPT_SIGNING_KEY is unset
+    pub receipt_signer: Option<Arc<ReceiptSigner>>,
+    /// Bearer tokens of users and internal producers; see `auth`
+    pub authenticator: Arc<Authenticator>,
+    /// X-Api-Key credentials of internal producers, issued through /admin/api-keys
+    pub api_keys: Arc<ApiKeys>,
+    /// Holds events the stores could not take; they fail with a 500 when AUDIT_WAL_DIR is unset
+    pub write_ahead: Option<Arc<WriteAheadQueue>>,
+    /// Ships events to the peer region and applies the peer's; unset without AUDIT_REPLICATION_PEER_URL
+    pub replication: Option<Arc<Replication>>,
+    pub partition_settings: Arc<PartitionSettings>,
+    pub discovery_settings: Arc<DiscoverySettings>,
+    /// Size and type limits of evidence attached to events
+    pub attachment_settings: Arc<AttachmentSettings>,
+    pub runbook_settings: Arc<RunbookSettings>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AuditEvent {
     pub event_id: Uuid,
     pub tenant_id: Uuid,
@@ -39,13 +221,26 @@ pub struct AuditEvent {
     pub new_values: Option<serde_json::Value>,
     pub ip_address: Option<String>,
     pub user_agent: Option<String>,
+    /// Omitted when absent so events written before it existed hash unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<Uuid>,
+    /// The acting user as the user service knew them at write time; omitted like request_id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<ActorSnapshot>,
+    /// Internal service that sent the event, from its service token or API key; omitted like request_id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub producer: Option<String>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub event_hash: Option<String>,
     pub blockchain_hash: Option<String>,
     pub ipfs_hash: Option<String>,
     pub signature: Option<String>,
+    /// Key of `signature`, for verifying after rotation; omitted like request_id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_key_id: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CreateAuditEventRequest {
     pub tenant_id: Uuid,
     pub user_id: Option<Uuid>,
@@ -57,10 +252,42 @@ pub struct CreateAuditEventRequest {
     pub metadata: Option<HashMap<String, serde_json::Value>>,
 }
 
+/// Query parameters for GET /audit/trail/:resource_type/:resource_id
+#[derive(Deserialize)]
+pub struct ResourceTrailParams {
+    pub tenant_id: Uuid,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub order: Option<SortOrder>,
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    fn as_sql(self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct AuditTrailResponse {
     pub events: Vec<AuditEvent>,
     pub total_count: u64,
+    /// True when total_count is the planner estimate rather than an exact COUNT(*)
+    pub count_is_estimate: bool,
+    /// Pass back as `cursor` to fetch the next page; absent on the last page
+    pub next_cursor: Option<String>,
     pub integrity_verified: bool,
     pub blockchain_anchored: bool,
 }
@@ -72,6 +299,22 @@ pub struct BlockchainClient {
 }
 
 impl BlockchainClient {
+    /// `None` when AUDIT_BLOCKCHAIN_ENABLED=false, for deployments without chain access
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        if std::env::var("AUDIT_BLOCKCHAIN_ENABLED").map(|value| value == "false").unwrap_or(false) {
+            return Ok(None);
+        }
+        let rpc_url = std::env::var("BLOCKCHAIN_RPC_URL")
+            .unwrap_or_else(|_| "http://localhost:8545".to_string());
+        let contract_address = std::env::var("SMART_CONTRACT_ADDRESS")
+            .unwrap_or_else(|_| "0x1234567890123456789012345678901234567890".to_string());
+        let private_key = std::env::var("BLOCKCHAIN_PRIVATE_KEY")
+            .unwrap_or_else(|_| "1234567890123456789012345678901234567890123456789012345678901234".to_string());
+        Self::new(&rpc_url, &contract_address, &private_key)
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Failed to initialize blockchain client: {}", e))
+    }
+
     pub fn new(rpc_url: &str, contract_address: &str, private_key: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let transport = Http::new(rpc_url)?;
         let web3 = Web3::new(transport);
@@ -95,6 +338,12 @@ impl BlockchainClient {
         Ok(transaction_hash)
     }
     
+    /// Current gas price in gwei, which the anchoring worker paces its batches by
+    pub async fn gas_price_gwei(&self) -> Result<f64, Box<dyn std::error::Error>> {
+        let wei = self.web3.eth().gas_price().await?;
+        Ok(wei.low_u128() as f64 / 1e9)
+    }
+    
     pub async fn verify_audit_integrity(&self, audit_hash: &str) -> Result<bool, Box<dyn std::error::Error>> {
         // Verify audit trail integrity against blockchain
         // This is a simplified implementation
@@ -103,72 +352,100 @@ impl BlockchainClient {
     }
 }
 
-pub struct IpfsClient {
-    client: ipfs_api_backend_hyper::IpfsClient,
-}
-
-impl IpfsClient {
-    pub fn new(api_url: &str) -> Self {
-        let client = ipfs_api_backend_hyper::IpfsClient::from_str(api_url)
-            .unwrap_or_else(|_| ipfs_api_backend_hyper::IpfsClient::default());
-        
-        Self { client }
-    }
-    
-    pub async fn store_document(&self, data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
-        // Store document in IPFS and return hash
-        let cursor = std::io::Cursor::new(data);
-        match self.client.add(cursor).await {
-            Ok(response) => {
-                info!("Stored document in IPFS: {}", response.hash);
-                Ok(response.hash)
-            }
-            Err(e) => {
-                error!("Failed to store in IPFS: {}", e);
-                Err(Box::new(e))
-            }
-        }
-    }
-    
-    pub async fn retrieve_document(&self, hash: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        match self.client.cat(hash).await {
-            Ok(data) => {
-                let bytes: Result<Vec<_>, _> = data.collect().await;
-                match bytes {
-                    Ok(data) => Ok(data),
-                    Err(e) => Err(Box::new(e)),
-                }
-            }
-            Err(e) => Err(Box::new(e)),
-        }
-    }
-}
-
 pub struct AuditService {
     db: PgPool,
-    mongodb: Database,
-    blockchain: Arc<BlockchainClient>,
-    ipfs: Arc<IpfsClient>,
+    audit_store: Arc<dyn AuditStore>,
+    documents: Arc<dyn DocumentStore>,
+    document_batcher: Option<Arc<DocumentBatcher>>,
+    blockchain: Option<Arc<BlockchainClient>>,
+    anchors: Arc<dyn AnchorStore>,
+    copies: Arc<DocumentCopies>,
+    pins: Arc<PinRegistry>,
+    anchor_mode: AnchorMode,
+    tsa: Option<Arc<TsaClient>>,
+    signer: Arc<EventSigner>,
+    events: broadcast::Sender<AuditEvent>,
+    schemas: Arc<SchemaRegistry>,
+    taxonomy: Arc<ActionTaxonomy>,
+    bus: Option<Arc<dyn EventBus>>,
+    pii: Option<Arc<PiiVault>>,
+    actors: Option<Arc<ActorResolver>>,
+    envelope: Option<Arc<Envelope>>,
+    cache: Arc<AuditCache>,
+    latency: Arc<PipelineLatency>,
 }
 
 impl AuditService {
-    pub fn new(
-        db: PgPool,
-        mongodb: Database,
-        blockchain: Arc<BlockchainClient>,
-        ipfs: Arc<IpfsClient>,
-    ) -> Self {
+    pub fn from_state(state: AppState) -> Self {
         Self {
-            db,
-            mongodb,
-            blockchain,
-            ipfs,
+            db: state.db,
+            audit_store: state.audit_store,
+            documents: state.documents,
+            document_batcher: state.document_batcher,
+            blockchain: state.blockchain_client,
+            anchors: state.anchors,
+            copies: state.copies,
+            pins: state.pins,
+            anchor_mode: state.anchor_mode,
+            tsa: state.tsa,
+            signer: state.signer,
+            events: state.event_stream,
+            schemas: state.schema_registry,
+            taxonomy: state.taxonomy,
+            bus: state.event_bus,
+            pii: state.pii,
+            actors: state.actors,
+            envelope: state.envelope,
+            cache: state.cache,
+            latency: state.pipeline_latency,
         }
     }
     
-    pub async fn create_audit_event(&self, request: CreateAuditEventRequest) -> Result<AuditEvent, Box<dyn std::error::Error>> {
-        let event_id = Uuid::new_v4();
-        let timestamp = chrono::Utc::now();
+    pub async fn create_audit_event(
+        &self,
+        request: CreateAuditEventRequest,
+        context: &RequestContext,
+    ) -> Result<AuditEvent, Box<dyn std::error::Error>> {
+        self.create_audit_event_as(Uuid::new_v4(), chrono::Utc::now(), request, context).await
+    }
+
+    /// Create an event under a given id and timestamp, as replays from the write-ahead log do
+    ///
+    /// Fails with `StoredWithoutDocument` once the audit row is written, so the
+    /// event is finished with `store_document` rather than created twice.
+    #[tracing::instrument(
+        name = "audit_pipeline",
+        skip_all,
+        fields(
+            tenant_id = %request.tenant_id,
+            enrich_ms = tracing::field::Empty,
+            protect_ms = tracing::field::Empty,
+            hash_ms = tracing::field::Empty,
+            ipfs_ms = tracing::field::Empty,
+            anchor_ms = tracing::field::Empty,
+            postgres_ms = tracing::field::Empty,
+            mongo_ms = tracing::field::Empty,
+            publish_ms = tracing::field::Empty,
+            total_ms = tracing::field::Empty,
+        )
+    )]
+    pub async fn create_audit_event_as(
+        &self,
+        event_id: Uuid,
+        timestamp: chrono::DateTime<chrono::Utc>,
+        mut request: CreateAuditEventRequest,
+        context: &RequestContext,
+    ) -> Result<AuditEvent, Box<dyn std::error::Error>> {
+        let mut timer = Timer::start();
+        // Schemas are registered against the normalized action
+        request.action = self.taxonomy.normalize(&request.action)?;
+        let schema_check = self.schemas.check(&self.db, &request).await?;
+        if let Some(check) = &schema_check {
+            if !check.errors.is_empty() && check.mode == EnforcementMode::Strict {
+                return Err(Box::new(SchemaRejection(check.clone())));
+            }
+        }
+        timer.skip();
         
         // Create audit event
         let mut audit_event = AuditEvent {
@@ -180,138 +457,581 @@ impl AuditService {
             resource_id: request.resource_id,
             old_values: request.old_values,
             new_values: request.new_values,
-            ip_address: None, // Would be populated from request context
-            user_agent: None, // Would be populated from request context
+            ip_address: context.ip_address.map(|ip| ip.to_string()),
+            user_agent: context.user_agent.clone(),
+            request_id: context.request_id,
+            actor: None,
+            producer: context.producer.clone(),
             timestamp,
+            event_hash: None,
             blockchain_hash: None,
             ipfs_hash: None,
             signature: None,
+            signing_key_id: None,
         };
+        if let (Some(actors), Some(user_id)) = (&self.actors, audit_event.user_id) {
+            audit_event.actor = actors.resolve(user_id).await;
+        }
+        timer.lap(Stage::Enrich);
         
+        // Personal data is sealed before hashing so it can be shredded without breaking the hash,
+        // and the values are encrypted after it so every store only sees ciphertext
+        if let Some(pii) = &self.pii {
+            pii.seal(&self.db, &mut audit_event).await?;
+        }
+        if let Some(envelope) = &self.envelope {
+            envelope.encrypt(&self.db, &mut audit_event).await?;
+        }
+        timer.lap(Stage::Protect);
+
         // Calculate hash of audit event for integrity
-        let event_json = serde_json::to_string(&audit_event)?;
-        let mut hasher = Sha256::new();
-        hasher.update(event_json.as_bytes());
-        let hash = format!("{:x}", hasher.finalize());
+        let payload = integrity::canonical_payload(&audit_event)?;
+        let hash = integrity::sha256_hex(&payload);
+        audit_event.event_hash = Some(hash.clone());
+        timer.lap(Stage::Hash);
         
-        // Store in IPFS for distributed storage
-        if let Ok(ipfs_hash) = self.ipfs.store_document(event_json.as_bytes()).await {
-            audit_event.ipfs_hash = Some(ipfs_hash);
-        }
+        // Steps that fail here are retried from the outbox rather than dropped
+        let mut deferred = Vec::new();
+
+        // Store in IPFS for distributed storage, when the deployment keeps copies
+        let copy = match self.copies.put(&audit_event, &payload).await {
+            Ok(copy) => {
+                audit_event.ipfs_hash = copy.as_ref().map(|copy| copy.cid.clone());
+                copy
+            }
+            Err(e) => {
+                warn!("Deferring IPFS pin of audit event {}: {}", event_id, e);
+                deferred.push((outbox::Operation::IpfsPin, e.to_string()));
+                None
+            }
+        };
+        timer.lap(Stage::Ipfs);
         
-        // Store hash on blockchain for immutability
-        if let Ok(blockchain_hash) = self.blockchain.store_audit_hash(&hash).await {
-            audit_event.blockchain_hash = Some(blockchain_hash);
+        // Store hash on blockchain for immutability, unless it is anchored in the daily digest
+        if self.anchor_mode == AnchorMode::PerEvent {
+            match &self.blockchain {
+                Some(blockchain) => match blockchain.store_audit_hash(&hash).await {
+                    Ok(blockchain_hash) => audit_event.blockchain_hash = Some(blockchain_hash),
+                    Err(e) => {
+                        warn!("Deferring blockchain anchoring of audit event {}: {}", event_id, e);
+                        deferred.push((outbox::Operation::BlockchainAnchor, e.to_string()));
+                    }
+                },
+                // Pending anchor mode: the hash waits in the outbox for a chain
+                None => deferred.push((outbox::Operation::BlockchainAnchor, outbox::ANCHORING_DISABLED.to_string())),
+            }
+            timer.lap(Stage::Anchor);
+        }
+
+        // Trusted timestamp of the hash, taken by the outbox worker rather than on the write path
+        if self.tsa.is_some() && self.anchor_mode == AnchorMode::PerEvent {
+            deferred.push((outbox::Operation::TsaTimestamp, outbox::TIMESTAMP_QUEUED.to_string()));
         }
         
         // Generate digital signature
-        audit_event.signature = Some(hash.clone());
+        audit_event.signature = Some(self.signer.sign(&hash));
+        audit_event.signing_key_id = Some(self.signer.current_key_id().to_string());
+        timer.lap(Stage::Hash);
         
-        // Store in PostgreSQL for querying
-        sqlx::query!(
-            r#"
-            INSERT INTO audit_logs (
-                log_id, tenant_id, user_id, action, resource_type, resource_id,
-                old_values, new_values, timestamp, ip_address, user_agent
-            )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
-            "#,
-            event_id,
-            request.tenant_id,
-            request.user_id,
-            request.action,
-            request.resource_type,
-            request.resource_id,
-            request.old_values,
-            request.new_values,
-            timestamp,
-            audit_event.ip_address,
-            audit_event.user_agent
-        )
-        .execute(&self.db)
-        .await?;
+        // Store in PostgreSQL for querying, together with any deferred steps
+        self.audit_store.append(&audit_event, copy.as_ref(), &deferred).await?;
+        timer.lap(Stage::Postgres);
         
-        // Store detailed event in MongoDB for analytics
-        let collection = self.mongodb.collection::<AuditEvent>("audit_events");
-        collection.insert_one(&audit_event, None).await?;
+        if let Some(check) = schema_check.filter(|check| !check.errors.is_empty()) {
+            warn!(
+                "Audit event {} does not match schema {} (version {}); accepted in lenient mode",
+                event_id, check.schema_id, check.version
+            );
+            if let Err(e) = schemas::record_violation(&self.db, event_id, request.tenant_id, &check).await {
+                return Err(Box::new(StoredWithoutDocument { event: audit_event, error: e.to_string() }));
+            }
+        }
+        timer.skip();
+
+        // Store the signed document for verification and analytics, queued for the batch writer when it runs
+        let stored = match &self.document_batcher {
+            Some(batcher) => batcher.submit(audit_event.clone()).await,
+            None => self.documents.insert(&audit_event).await,
+        };
+        if let Err(e) = stored {
+            return Err(Box::new(StoredWithoutDocument { event: audit_event, error: e.to_string() }));
+        }
+        timer.lap(Stage::Mongo);
+
+        self.project(&audit_event).await;
+        self.announce(&audit_event).await;
+        timer.lap(Stage::Publish);
+        self.latency.observe(&tracing::Span::current(), event_id, timer);
         
-        info!("Created audit event: {} for action: {}", event_id, request.action);
+        info!("Created audit event: {} for action: {}", event_id, audit_event.action);
         Ok(audit_event)
     }
+
+    /// Finish an event whose audit row was written but whose document was not
+    pub async fn store_document(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        if self.documents.get(event.event_id).await?.is_none() {
+            self.documents.insert(event).await?;
+        }
+        self.project(event).await;
+        self.announce(event).await;
+        info!("Stored queued document of audit event {}", event.event_id);
+        Ok(())
+    }
+
+    /// The event of an audit row a replayed attempt may have written, with its document when that was written too
+    ///
+    /// A row without a document is hashed and signed again from its stored
+    /// values, under the time the event was accepted at, with the CID of its pin.
+    pub async fn stored_event(
+        &self,
+        event_id: Uuid,
+        timestamp: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<Option<AuditEvent>> {
+        if let Some(event) = self.documents.get(event_id).await? {
+            return Ok(Some(event));
+        }
+        let Some(row) = sqlx::query(&format!("SELECT {} FROM audit_logs WHERE log_id = $1", AUDIT_LOG_COLUMNS))
+            .bind(event_id)
+            .fetch_optional(&self.db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut event = AuditEvent { timestamp, ..audit_event_from_row(&row) };
+        let hash = integrity::sha256_hex(&integrity::canonical_payload(&event)?);
+        event.ipfs_hash = sqlx::query_scalar("SELECT cid FROM ipfs_pins WHERE event_id = $1 AND provider = 'LOCAL'")
+            .bind(event_id)
+            .fetch_optional(&self.db)
+            .await?;
+        event.signature = Some(self.signer.sign(&hash));
+        event.signing_key_id = Some(self.signer.current_key_id().to_string());
+        event.event_hash = Some(hash);
+        Ok(Some(event))
+    }
+
+    /// Anchor a replayed event that carries no anchor and has none waiting in the outbox
+    ///
+    /// Whether the first attempt anchored it is not kept with the audit row, so
+    /// an event whose document was never written may be anchored twice.
+    pub async fn anchor_stored(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        if self.anchor_mode != AnchorMode::PerEvent || event.blockchain_hash.is_some() {
+            return Ok(());
+        }
+        let (Some(blockchain), Some(hash)) = (&self.blockchain, &event.event_hash) else {
+            return Ok(());
+        };
+        let queued: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM audit_anchor_outbox WHERE event_id = $1 AND operation = $2)",
+        )
+        .bind(event.event_id)
+        .bind(outbox::Operation::BlockchainAnchor.as_str())
+        .fetch_one(&self.db)
+        .await?;
+        if queued {
+            return Ok(());
+        }
+        let anchor = blockchain
+            .store_audit_hash(hash)
+            .await
+            .map_err(|e| anyhow::anyhow!("blockchain anchoring failed: {}", e))?;
+        self.documents.update(event.event_id, DocumentUpdate::BlockchainHash(&anchor)).await?;
+        self.cache.invalidate_event(event.tenant_id, event.event_id).await;
+        info!("Anchored replayed audit event {}: {}", event.event_id, anchor);
+        Ok(())
+    }
+
+    pub fn projector(&self) -> Projector<'_> {
+        Projector { db: &self.db, envelope: self.envelope.as_deref(), pii: self.pii.as_deref() }
+    }
+
+    /// Advance the resource's current state; one that cannot be advanced is rebuilt on its next read
+    async fn project(&self, event: &AuditEvent) {
+        let Err(e) = self.projector().advance(event).await else {
+            return;
+        };
+        let Some(resource_id) = event.resource_id else {
+            return;
+        };
+        warn!(
+            "Failed to advance the state of {} {} by audit event {}: {}",
+            event.resource_type, resource_id, event.event_id, e
+        );
+        let key = ResourceKey { tenant_id: event.tenant_id, resource_type: &event.resource_type, resource_id };
+        if let Err(e) = projection::invalidate(&self.db, &key).await {
+            error!("Failed to drop the state of {} {}: {}", event.resource_type, resource_id, e);
+        }
+    }
+
+    /// Fan a stored event out to stream subscribers and the event bus
+    async fn announce(&self, event: &AuditEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(event.clone());
+
+        if let Some(bus) = &self.bus {
+            let payload = match serde_json::to_vec(event) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    warn!("Failed to encode audit event {} for {}: {}", event.event_id, bus.transport(), e);
+                    return;
+                }
+            };
+            if let Err(e) = bus.publish(bus::AUDIT_EVENTS_TOPIC, &event.tenant_id.to_string(), &payload).await {
+                warn!("Failed to publish audit event {} to {}: {}", event.event_id, bus.transport(), e);
+            }
+        }
+    }
     
     pub async fn get_audit_trail(
         &self,
-        tenant_id: Uuid,
-        resource_type: Option<String>,
-        resource_id: Option<Uuid>,
-        limit: u64,
-        offset: u64,
+        filter: &AuditTrailFilter,
+        page: TrailPage,
+        reader: &Reader,
     ) -> Result<AuditTrailResponse, Box<dyn std::error::Error>> {
-        let mut query = "SELECT * FROM audit_logs WHERE tenant_id = $1".to_string();
-        let mut param_count = 1;
-        
-        if resource_type.is_some() {
-            param_count += 1;
-            query.push_str(&format!(" AND resource_type = ${}", param_count));
+        let mut select = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM audit_logs", AUDIT_LOG_COLUMNS));
+        filter.push_where(&mut select);
+        if let Some(cursor) = page.cursor {
+            cursor.push_before(&mut select);
         }
-        
-        if resource_id.is_some() {
-            param_count += 1;
-            query.push_str(&format!(" AND resource_id = ${}", param_count));
+        select.push(" ORDER BY timestamp DESC, log_id DESC");
+        // One extra row tells us whether another page exists
+        select.push(" LIMIT ").push_bind(page.limit + 1);
+        if page.cursor.is_none() {
+            select.push(" OFFSET ").push_bind(page.offset);
         }
+
+        let rows = select.build().fetch_all(&self.db).await?;
         
-        query.push_str(" ORDER BY timestamp DESC");
-        query.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
-        
-        // This is simplified - in production, use proper parameter binding
-        let rows = sqlx::query(&query)
-            .bind(tenant_id)
-            .fetch_all(&self.db)
-            .await?;
-        
-        let mut events = Vec::new();
-        for row in rows {
-            let event = AuditEvent {
-                event_id: row.get("log_id"),
-                tenant_id: row.get("tenant_id"),
-                user_id: row.get("user_id"),
-                action: row.get("action"),
-                resource_type: row.get("resource_type"),
-                resource_id: row.get("resource_id"),
-                old_values: row.get("old_values"),
-                new_values: row.get("new_values"),
-                timestamp: row.get("timestamp"),
-                ip_address: row.get("ip_address"),
-                user_agent: row.get("user_agent"),
-                blockchain_hash: None, // Would fetch from MongoDB
-                ipfs_hash: None,       // Would fetch from MongoDB
-                signature: None,       // Would fetch from MongoDB
-            };
-            events.push(event);
-        }
+        let mut events: Vec<AuditEvent> = rows.iter().map(audit_event_from_row).collect();
+        let next_cursor = if events.len() as i64 > page.limit {
+            events.truncate(page.limit as usize);
+            events.last().map(|last| {
+                TrailCursor {
+                    timestamp: last.timestamp,
+                    event_id: last.event_id,
+                }
+                .encode()
+            })
+        } else {
+            None
+        };
+
+        let (total_count, count_is_estimate) = if page.exact_count {
+            let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM audit_logs");
+            filter.push_where(&mut count);
+            let total: i64 = count.build_query_scalar().fetch_one(&self.db).await?;
+            (total as u64, false)
+        } else {
+            // Planner estimate avoids a full scan on tables with millions of rows
+            let mut explain = QueryBuilder::<Postgres>::new("EXPLAIN (FORMAT JSON) SELECT 1 FROM audit_logs");
+            filter.push_where(&mut explain);
+            let plan: serde_json::Value = explain.build_query_scalar().fetch_one(&self.db).await?;
+            (trail::planner_row_estimate(&plan).unwrap_or(0), true)
+        };
         
         // Verify integrity
         let integrity_verified = self.verify_audit_trail_integrity(&events).await?;
+        self.reveal(&mut events, reader).await?;
         
         Ok(AuditTrailResponse {
             events,
-            total_count: 0, // Would implement proper count query
+            total_count,
+            count_is_estimate,
+            next_cursor,
             integrity_verified,
-            blockchain_anchored: true,
+            blockchain_anchored: self.blockchain.is_some(),
         })
     }
     
     async fn verify_audit_trail_integrity(&self, events: &[AuditEvent]) -> Result<bool, Box<dyn std::error::Error>> {
         // Verify audit trail integrity by checking blockchain anchors
+        let Some(blockchain) = &self.blockchain else {
+            return Ok(true);
+        };
         for event in events {
-            if let Some(signature) = &event.signature {
-                if !self.blockchain.verify_audit_integrity(signature).await? {
+            if let Some(event_hash) = &event.event_hash {
+                if !blockchain.verify_audit_integrity(event_hash).await? {
                     return Ok(false);
                 }
             }
         }
         Ok(true)
     }
+
+    /// Decrypt values the reader may see, then sealed personal data, after integrity checks have run
+    async fn reveal(&self, events: &mut [AuditEvent], reader: &Reader) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(envelope) = &self.envelope {
+            let mut decrypted = 0;
+            for event in events.iter_mut().filter(|event| reader.may_decrypt(event.tenant_id)) {
+                envelope.decrypt(&self.db, event).await?;
+                decrypted += 1;
+            }
+            if let (Some(user_id), true) = (reader.user_id, decrypted > 0) {
+                info!("Decrypted values of {} audit events for user {}", decrypted, user_id);
+            }
+        }
+        if let Some(pii) = &self.pii {
+            for event in events {
+                pii.reveal(&self.db, event).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Audit trail for a single resource, scoped to the tenant and an optional time range
+    pub async fn get_resource_audit_trail(
+        &self,
+        tenant_id: Uuid,
+        resource_type: &str,
+        resource_id: Uuid,
+        params: &ResourceTrailParams,
+        reader: &Reader,
+    ) -> Result<AuditTrailResponse, Box<dyn std::error::Error>> {
+        let limit = params.limit.unwrap_or(50).clamp(1, 1000);
+        let offset = params.offset.unwrap_or(0).max(0);
+        let order = params.order.unwrap_or_default();
+
+        let filter = AuditTrailFilter {
+            tenant_id,
+            action: None,
+            user_id: None,
+            resource_type: Some(resource_type.to_string()),
+            resource_id: Some(resource_id),
+            ip_address: None,
+            from: params.from,
+            to: params.to,
+            hidden_resource_types: Vec::new(),
+        };
+
+        let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM audit_logs");
+        filter.push_where(&mut count);
+        let total_count: i64 = count.build_query_scalar().fetch_one(&self.db).await?;
+
+        let mut select = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM audit_logs", AUDIT_LOG_COLUMNS));
+        filter.push_where(&mut select);
+        // Sort direction comes from a closed enum, never from user input
+        select
+            .push(format!(" ORDER BY timestamp {order}, log_id {order} LIMIT ", order = order.as_sql()))
+            .push_bind(limit)
+            .push(" OFFSET ")
+            .push_bind(offset);
+        let rows = select.build().fetch_all(&self.db).await?;
+
+        let mut events: Vec<AuditEvent> = rows.iter().map(audit_event_from_row).collect();
+        let integrity_verified = self.verify_audit_trail_integrity(&events).await?;
+        self.reveal(&mut events, reader).await?;
+
+        Ok(AuditTrailResponse {
+            events,
+            total_count: total_count as u64,
+            count_is_estimate: false,
+            next_cursor: None,
+            integrity_verified,
+            blockchain_anchored: self.blockchain.is_some(),
+        })
+    }
+
+    /// Load the canonical copy of an event from the document store, or the cache
+    pub async fn find_audit_event(&self, event_id: Uuid) -> Result<Option<AuditEvent>, Box<dyn std::error::Error>> {
+        if let Some(event) = self.cache.event(event_id).await {
+            return Ok(Some(event.as_ref().clone()));
+        }
+        match self.documents.get(event_id).await? {
+            Some(event) => Ok(Some(self.cache.insert_event(event).await.as_ref().clone())),
+            None => Ok(None),
+        }
+    }
+
+    /// A single event as `reader` may see it
+    pub async fn read_audit_event(
+        &self,
+        event_id: Uuid,
+        reader: &Reader,
+    ) -> Result<Option<AuditEvent>, Box<dyn std::error::Error>> {
+        let Some(event) = self.find_audit_event(event_id).await? else {
+            return Ok(None);
+        };
+        let mut events = [event];
+        self.reveal(&mut events, reader).await?;
+        let [event] = events;
+        Ok(Some(event))
+    }
+
+    /// The verification result of the last few minutes, or a fresh one
+    pub async fn cached_verification(&self, event: &AuditEvent) -> Result<VerificationReport, Box<dyn std::error::Error>> {
+        if let Some(report) = self.cache.verification(event.tenant_id, event.event_id).await {
+            return Ok(report.as_ref().clone());
+        }
+        let report = self.verify_audit_event(event).await?;
+        Ok(self.cache.insert_verification(event.tenant_id, report).await.as_ref().clone())
+    }
+
+    /// Re-run every integrity check for a single event
+    pub async fn verify_audit_event(&self, event: &AuditEvent) -> Result<VerificationReport, Box<dyn std::error::Error>> {
+        let payload = integrity::canonical_payload(event)?;
+        let computed_hash = integrity::sha256_hex(&payload);
+        let mut checks = Vec::new();
+
+        // Recorded hash must match the recomputed payload hash
+        checks.push(match &event.event_hash {
+            Some(stored) if *stored == computed_hash => VerificationCheck::pass("payload_hash"),
+            Some(stored) => VerificationCheck::fail(
+                "payload_hash",
+                format!("stored hash {} does not match recomputed hash {}", stored, computed_hash),
+            ),
+            None => VerificationCheck::fail("payload_hash", "event has no recorded hash"),
+        });
+
+        // Signature must be valid for the recomputed hash under a trusted key
+        let key_id = event.signing_key_id.as_deref();
+        let compromised = match key_id {
+            Some(key_id) => resign::is_key_compromised(&self.db, key_id).await?,
+            None => false,
+        };
+        checks.push(match &event.signature {
+            Some(_) if compromised => VerificationCheck::fail(
+                "signature",
+                format!("signed with compromised key {}; re-signing pending", key_id.unwrap_or_default()),
+            ),
+            Some(signature) if self.signer.verify(&computed_hash, signature, key_id) => {
+                VerificationCheck::pass("signature")
+            }
+            Some(_) => VerificationCheck::fail("signature", "signature does not match recomputed hash"),
+            None => VerificationCheck::fail("signature", "event is not signed"),
+        });
+
+        // IPFS copy must be retrievable and, once decrypted, byte-identical to the payload;
+        // a deployment without IPFS keeps no copy, so the check does not apply
+        let mut ipfs_accessible = false;
+        if self.anchors.keeps_copies() {
+            checks.push(match &event.ipfs_hash {
+                Some(cid) => match self.copies.retrieve(event.tenant_id, event.event_id, cid).await {
+                    Ok(document) => {
+                        ipfs_accessible = true;
+                        let document_hash = integrity::sha256_hex(&document);
+                        if document_hash == computed_hash {
+                            VerificationCheck::pass("ipfs_document")
+                        } else {
+                            VerificationCheck::fail(
+                                "ipfs_document",
+                                format!("IPFS document {} hashes to {}", cid, document_hash),
+                            )
+                        }
+                    }
+                    Err(e) => VerificationCheck::fail(
+                        "ipfs_document",
+                        format!("failed to retrieve IPFS document {}: {}", cid, e),
+                    ),
+                },
+                None => VerificationCheck::fail("ipfs_document", "event was never stored in IPFS"),
+            });
+        }
+
+        // Blockchain anchor must reference the recomputed hash; in pending anchor mode
+        // nothing can be looked up on chain, so the check does not apply
+        let mut blockchain_confirmed = false;
+        if let Some(blockchain) = &self.blockchain {
+            checks.push(match &event.blockchain_hash {
+                Some(anchor) if anchor.trim_start_matches("0x") != computed_hash => VerificationCheck::fail(
+                    "blockchain_anchor",
+                    format!("anchor {} does not reference hash {}", anchor, computed_hash),
+                ),
+                Some(_) => match blockchain.verify_audit_integrity(&computed_hash).await {
+                    Ok(true) => {
+                        blockchain_confirmed = true;
+                        VerificationCheck::pass("blockchain_anchor")
+                    }
+                    Ok(false) => VerificationCheck::fail("blockchain_anchor", "hash not found on chain"),
+                    Err(e) => VerificationCheck::fail(
+                        "blockchain_anchor",
+                        format!("blockchain lookup failed: {}", e),
+                    ),
+                },
+                None => match digest::inclusion_proof(&self.db, event.event_id).await? {
+                    Some(proof) if proof.event_hash != computed_hash => VerificationCheck::fail(
+                        "blockchain_anchor",
+                        format!("digest {} committed to hash {}", proof.digest_date, proof.event_hash),
+                    ),
+                    Some(proof) if proof.anchor_status != "ANCHORED" => VerificationCheck::fail(
+                        "blockchain_anchor",
+                        format!("digest {} is {}", proof.digest_date, proof.anchor_status),
+                    ),
+                    Some(proof) => match digest::root_from_path(&computed_hash, &proof.path) {
+                        Ok(root) if root != proof.merkle_root => VerificationCheck::fail(
+                            "blockchain_anchor",
+                            format!("inclusion proof leads to {}, not digest root {}", root, proof.merkle_root),
+                        ),
+                        Ok(root) => match blockchain.verify_audit_integrity(&root).await {
+                            Ok(true) => {
+                                blockchain_confirmed = true;
+                                VerificationCheck::pass("blockchain_anchor")
+                            }
+                            Ok(false) => VerificationCheck::fail("blockchain_anchor", "digest root not found on chain"),
+                            Err(e) => VerificationCheck::fail(
+                                "blockchain_anchor",
+                                format!("blockchain lookup failed: {}", e),
+                            ),
+                        },
+                        Err(e) => VerificationCheck::fail("blockchain_anchor", format!("invalid inclusion proof: {}", e)),
+                    },
+                    None => VerificationCheck::fail("blockchain_anchor", "event was never anchored"),
+                },
+            });
+        }
+
+        // RFC 3161 token over the recomputed hash, or over the root of the event's digest
+        let mut timestamped_at = None;
+        if let Some(tsa) = &self.tsa {
+            checks.push(match tsa.verify_event(&self.db, event.event_id, &computed_hash).await {
+                Ok(gen_time) => {
+                    timestamped_at = Some(gen_time);
+                    VerificationCheck::pass("tsa_timestamp")
+                }
+                Err(e) => VerificationCheck::fail("tsa_timestamp", e.to_string()),
+            });
+        }
+
+        Ok(VerificationReport {
+            event_id: event.event_id,
+            verified: checks.iter().all(|c| c.passed),
+            computed_hash,
+            blockchain_confirmed,
+            ipfs_accessible,
+            timestamped_at,
+            checks,
+            verified_at: chrono::Utc::now(),
+        })
+    }
+}
+
+/// Columns selected when reading audit_logs rows back into AuditEvents
+const AUDIT_LOG_COLUMNS: &str = "log_id, tenant_id, user_id, action, resource_type, resource_id, \
+     old_values, new_values, timestamp, COALESCE(ip_address::text, ip_address_sealed) AS ip_address, user_agent, \
+     request_id, actor_snapshot, producer";
+
+fn audit_event_from_row(row: &PgRow) -> AuditEvent {
+    AuditEvent {
+        event_id: row.get("log_id"),
+        tenant_id: row.get("tenant_id"),
+        user_id: row.get("user_id"),
+        action: row.get("action"),
+        resource_type: row.get("resource_type"),
+        resource_id: row.get("resource_id"),
+        old_values: row.get("old_values"),
+        new_values: row.get("new_values"),
+        timestamp: row.get("timestamp"),
+        ip_address: row.get("ip_address"),
+        user_agent: row.get("user_agent"),
+        request_id: row.get("request_id"),
+        actor: row
+            .get::<Option<sqlx::types::Json<ActorSnapshot>>, _>("actor_snapshot")
+            .map(|snapshot| snapshot.0),
+        producer: row.get("producer"),
+        event_hash: None,      // Would fetch from MongoDB
+        blockchain_hash: None, // Would fetch from MongoDB
+        ipfs_hash: None,       // Would fetch from MongoDB
+        signature: None,       // Would fetch from MongoDB
+        signing_key_id: None,  // Would fetch from MongoDB
+    }
 }
 
 #[tokio::main]
@@ -320,106 +1040,628 @@ async fn main() -> anyhow::Result<()> {
 
     let database_url = std::env::var("DATABASE_URL")
         .expect("DATABASE_URL must be set");
-    let mongodb_url = std::env::var("MONGODB_URL")
-        .expect("MONGODB_URL must be set");
-    let blockchain_rpc = std::env::var("BLOCKCHAIN_RPC_URL")
-        .unwrap_or_else(|_| "http://localhost:8545".to_string());
-    let contract_address = std::env::var("SMART_CONTRACT_ADDRESS")
-        .unwrap_or_else(|_| "0x1234567890123456789012345678901234567890".to_string());
-    let private_key = std::env::var("BLOCKCHAIN_PRIVATE_KEY")
-        .unwrap_or_else(|_| "1234567890123456789012345678901234567890123456789012345678901234".to_string());
-
-    let pool = PgPoolOptions::new()
-        .max_connections(20)
-        .connect(&database_url)
-        .await?;
+    let signing_key = std::env::var("AUDIT_SIGNING_KEY")
+        .expect("AUDIT_SIGNING_KEY must be set");
+    let signing_key_id = std::env::var("AUDIT_SIGNING_KEY_ID")
+        .unwrap_or_else(|_| "primary".to_string());
+    // Comma-separated key_id:key pairs kept for verifying events signed before a rotation
+    let retired_signing_keys = std::env::var("AUDIT_RETIRED_SIGNING_KEYS")
+        .unwrap_or_default();
+    // Comma-separated proxy addresses/CIDRs allowed to set X-Forwarded-For
+    let trusted_proxies = std::env::var("AUDIT_TRUSTED_PROXIES")
+        .unwrap_or_default();
+    let grpc_port: u16 = std::env::var("AUDIT_GRPC_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(50054);
 
-    // Initialize MongoDB
-    let mongo_client = MongoClient::with_uri_str(&mongodb_url).await?;
-    let mongodb = mongo_client.database("dharmaguard_audit");
+    dharmaguard_common::metrics::install();
+    let pool_settings = PoolSettings::from_env();
+    let pool = pool_settings.connect(&database_url).await?;
+    let admission = Admission::new("audit", pool_settings);
+    admission.spawn_autoscaler(pool.clone());
 
-    // Initialize blockchain client
-    let blockchain_client = Arc::new(
-        BlockchainClient::new(&blockchain_rpc, &contract_address, &private_key)
-            .map_err(|e| anyhow::anyhow!("Failed to initialize blockchain client: {}", e))?
-    );
+    // Document store: MongoDB, or Postgres for deployments without it
+    let documents = store::documents_from_env(&pool).await?;
+    info!("Keeping audit event documents in {}", documents.backend());
+
+    // Initialize blockchain client, unless the deployment has no chain access
+    let blockchain_client = BlockchainClient::from_env()?.map(Arc::new);
+    if blockchain_client.is_none() {
+        warn!("AUDIT_BLOCKCHAIN_ENABLED is false; audit hashes wait as pending anchors until the anchor_backfill runbook");
+    }
+
+    let tsa = TsaClient::from_env()?.map(Arc::new);
+    match &tsa {
+        Some(tsa) => info!("Timestamping audit hashes with the time-stamping authority at {}", tsa.url()),
+        None if blockchain_client.is_none() => {
+            warn!("AUDIT_TSA_URL is not set either; audit hashes have no third-party proof until they are anchored")
+        }
+        None => {}
+    }
+
+    // Initialize IPFS client, unless the deployment runs without IPFS
+    let anchors = store::anchors_from_env()?;
+    let remote_pinning = RemotePinning::from_env()?;
+    match &remote_pinning {
+        _ if !anchors.keeps_copies() => warn!("AUDIT_ANCHOR_STORE is none; audit payloads will not be copied to IPFS"),
+        Some(remote) => info!("Pinning audit documents on the IPFS node and with {}", remote.name),
+        None => warn!("IPFS_REMOTE_PINNING_ENDPOINT is not set; audit documents are pinned on the local IPFS node only"),
+    }
+    let pins = Arc::new(PinRegistry::new(
+        pool.clone(),
+        anchors.clone(),
+        remote_pinning,
+        PinSettings::from_env(),
+    ));
+    // Hourly re-pin verification and remote pin follow-up
+    let _pin_scheduler = if anchors.keeps_copies() {
+        Some(pins.clone().schedule().await?)
+    } else {
+        None
+    };
+
+    let mut signer = EventSigner::new(&signing_key_id, signing_key.as_bytes());
+    for entry in retired_signing_keys.split(',').filter(|e| !e.is_empty()) {
+        match entry.split_once(':') {
+            Some((key_id, key)) => signer = signer.with_retired_key(key_id, key.as_bytes()),
+            None => warn!("Ignoring malformed AUDIT_RETIRED_SIGNING_KEYS entry"),
+        }
+    }
+    let signer = Arc::new(signer);
+    resign::register_active_key(&pool, signer.current_key_id()).await?;
+
+    // Nightly cross-store reconciliation at 02:30 UTC
+    let _reconciliation_scheduler = reconcile::schedule(pool.clone(), documents.clone()).await?;
+
+    let worm_settings = WormSettings::from_env()?;
+    let replication_settings = ReplicationSettings::from_env()?;
+    let retention_settings = RetentionSettings::from_env();
+    let archiver = match ArchiveStore::from_env().await {
+        Some(store) => Some(Arc::new(Archiver::new(
+            pool.clone(),
+            documents.clone(),
+            pins.clone(),
+            store,
+            retention_settings,
+        ))),
+        None => {
+            warn!("AUDIT_ARCHIVE_BUCKET is not set; audit events will not be archived to cold storage");
+            None
+        }
+    };
+    // Nightly archival of events past their online retention at 03:30 UTC; WORM mode keeps every row
+    let _archival_scheduler = match archiver.clone() {
+        Some(_) if worm_settings.mode == WormMode::Enforce => {
+            info!("AUDIT_WORM_MODE is enforce; audit events are not archived out of audit_logs");
+            None
+        }
+        Some(archiver) => Some(archiver.schedule().await?),
+        None => None,
+    };
+
+    let pii = PiiVault::from_env()?.map(Arc::new);
+    if pii.is_none() {
+        warn!("AUDIT_PII_MASTER_KEY is not set; personal data in audit events will not be erasable");
+    }
+    let actors = ActorResolver::from_env()?.map(Arc::new);
+    match &actors {
+        Some(actors) => info!("Embedding actor snapshots from the user service at {}", actors.base_url()),
+        None => warn!("USER_SERVICE_URL is not set; audit events will only identify their actor by user_id"),
+    }
+    let envelope = Envelope::from_env().await?.map(Arc::new);
+    match &envelope {
+        Some(envelope) => info!("Encrypting audit values under master key {}", envelope.master_key_id()),
+        None => warn!("No AUDIT_ENVELOPE_* master key is set; audit values will be stored in clear"),
+    }
+    let copies = Arc::new(DocumentCopies::new(pool.clone(), anchors.clone(), envelope.clone())?);
+    if anchors.keeps_copies() && !copies.encrypts() {
+        warn!("IPFS copies of audit documents will be stored in clear; anyone holding a CID can read them");
+    }
 
-    // Initialize IPFS client
-    let ipfs_client = Arc::new(IpfsClient::new("http://localhost:5001"));
+    let report_signer = ReportSigner::from_env()?.map(Arc::new);
+    match &report_signer {
+        Some(signer) => info!("Signing chain-of-custody reports with key {}", signer.key_id()),
+        None => warn!("AUDIT_REPORT_SIGNING_KEY is not set; chain-of-custody reports are disabled"),
+    }
+    let receipt_signer = ReceiptSigner::from_env()?.map(Arc::new);
+    match &receipt_signer {
+        Some(signer) => info!("Signing audit receipts with key {}", signer.key_id()),
+        None => warn!("AUDIT_RECEIPT_SIGNING_KEY is not set; created audit events get no signed receipt"),
+    }
+
+    let taxonomy = Arc::new(ActionTaxonomy::from_env()?);
+    if taxonomy.mode() == TaxonomyMode::Strict {
+        info!("Rejecting audit events whose action is not in the action taxonomy");
+    }
+
+    let write_ahead = WriteAheadQueue::from_env()?.map(Arc::new);
+    match &write_ahead {
+        Some(write_ahead) => info!("Queueing audit events in {} while the stores are unavailable", write_ahead.directory()),
+        None => warn!("AUDIT_WAL_DIR is not set; audit events will be rejected while the stores are unavailable"),
+    }
+
+    let document_batcher = DocumentBatcher::spawn(documents.clone(), pool.clone(), BatchSettings::from_env());
+    if document_batcher.is_some() {
+        info!("Inserting new audit documents into MongoDB in batches");
+    }
 
     let app_state = AppState {
-        db: pool,
-        mongodb,
-        blockchain_client,
-        ipfs_client,
+        db: pool.clone(),
+        audit_store: Arc::new(PostgresAuditStore::new(
+            pool.clone(),
+            pins.clone(),
+            replication_settings.as_ref().map(|settings| settings.region.clone()),
+        )),
+        documents: documents.clone(),
+        document_batcher: document_batcher.map(Arc::new),
+        blockchain_client: blockchain_client.clone(),
+        anchors,
+        copies,
+        pins,
+        anchor_mode: AnchorMode::from_env()?,
+        digests: Arc::new(DigestBuilder::new(pool.clone(), documents, blockchain_client, tsa.clone())),
+        tsa,
+        signer,
+        event_stream: stream::channel(),
+        trusted_proxies: Arc::new(TrustedProxies::parse(&trusted_proxies)),
+        schema_registry: Arc::new(SchemaRegistry::default()),
+        taxonomy: taxonomy.clone(),
+        event_bus: bus::from_env().await?,
+        archiver,
+        retention_settings,
+        pii,
+        actors,
+        envelope,
+        cache: Arc::new(AuditCache::new(CacheSettings::from_env())),
+        sweep_settings: Arc::new(SweepSettings::from_env()),
+        diff_settings: Arc::new(DiffSettings::from_env()),
+        pipeline_latency: Arc::new(PipelineLatency::from_env()),
+        report_signer,
+        receipt_signer,
+        authenticator: Arc::new(Authenticator::from_env()?),
+        api_keys: Arc::new(ApiKeys::from_env(pool.clone())),
+        write_ahead,
+        replication: replication_settings.map(Replication::new).transpose()?.map(Arc::new),
+        partition_settings: Arc::new(PartitionSettings::from_env()),
+        discovery_settings: Arc::new(DiscoverySettings::from_env()),
+        attachment_settings: Arc::new(AttachmentSettings::from_env()),
+        runbook_settings: Arc::new(RunbookSettings::from_env()),
     };
 
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/audit/events", post(create_audit_event).get(get_audit_trail))
+    // Hourly sampled and weekly full re-verification of stored events
+    let _integrity_scheduler = sweep::schedule(app_state.clone()).await?;
+    if app_state.sweep_settings.webhook_url.is_none() {
+        warn!("AUDIT_INTEGRITY_WEBHOOK_URL is not set; integrity discrepancies are only raised in system_events");
+    }
+
+    // Nightly sampling of stored values for personal data under unsealed keys
+    let _discovery_scheduler =
+        discovery::schedule(app_state.clone(), app_state.discovery_settings.as_ref().clone()).await?;
+
+    // Daily digest anchoring replaces per-event anchors in daily_digest mode
+    let _digest_scheduler = match app_state.anchor_mode {
+        AnchorMode::DailyDigest => {
+            info!("Anchoring a daily digest of audit event hashes instead of each event");
+            Some(app_state.digests.clone().schedule().await?)
+        }
+        // WORM verification checks each day against its digest
+        AnchorMode::PerEvent if worm_settings.mode == WormMode::Enforce => {
+            info!("Building a daily digest of audit event hashes for WORM verification");
+            Some(app_state.digests.clone().schedule().await?)
+        }
+        AnchorMode::PerEvent => None,
+    };
+
+    // Retries of IPFS pins and blockchain anchors that failed at write time; anchors go out in paced batches
+    let outbox_settings = OutboxSettings::from_env();
+    outbox::spawn_worker(app_state.clone(), outbox_settings.clone());
+    outbox::spawn_anchor_worker(app_state.clone(), outbox_settings, AnchorPacing::from_env());
+    // Replays of events accepted while a store was down
+    wal::spawn_worker(app_state.clone(), WalSettings::from_env());
+    // Monthly audit_logs partitions ahead of time, and dropping those archival emptied
+    partitions::spawn_worker(pool.clone(), app_state.partition_settings.as_ref().clone());
+    // Changes the WORM triggers held back, raised as system events and in the tenants' trails
+    worm::spawn_reporter(app_state.clone(), worm_settings);
+    if let Some(replication) = app_state.replication.clone() {
+        info!("Replicating audit events of {} to the peer region", replication.region());
+        replication::spawn_shipper(app_state.clone(), replication);
+    }
+
+    // API routes answer 503 until these pass; /health, /ready and /metrics answer from the start
+    let mut startup = Startup::new("audit")
+        .migrations(pool.clone(), MIGRATIONS)
+        .check(app_state.documents.backend(), {
+            let documents = app_state.documents.clone();
+            move || {
+                let documents = documents.clone();
+                async move { documents.ping().await }
+            }
+        })
+        .check("schema cache", {
+            let (db, registry) = (pool.clone(), app_state.schema_registry.clone());
+            move || {
+                let (db, registry) = (db.clone(), registry.clone());
+                async move {
+                    let compiled = registry.warm(&db).await?;
+                    info!("Compiled {} active audit event schema(s)", compiled);
+                    Ok(())
+                }
+            }
+        })
+        .check("WORM mode", {
+            let db = pool.clone();
+            move || {
+                let db = db.clone();
+                async move {
+                    match worm::sync(&db, &worm_settings).await? {
+                        Some(worm) if worm_settings.mode == WormMode::Off => warn!(
+                            "AUDIT_WORM_MODE is off but audit_logs has been write-once since {}; WORM mode cannot be \
+                             turned off",
+                            worm.enabled_at
+                        ),
+                        Some(worm) => info!("audit_logs is write-once since {} ({})", worm.enabled_at, worm.enabled_by),
+                        None => {}
+                    }
+                    Ok(())
+                }
+            }
+        })
+        .check("action taxonomy", {
+            let (db, taxonomy) = (pool.clone(), taxonomy.clone());
+            move || {
+                let (db, taxonomy) = (db.clone(), taxonomy.clone());
+                async move {
+                    let loaded = taxonomy.load(&db).await?;
+                    info!("Loaded {} audit action(s) into the action taxonomy", loaded);
+                    Ok(())
+                }
+            }
+        });
+    taxonomy.clone().spawn_refresh(pool.clone());
+    if let Some(event_bus) = app_state.event_bus.clone() {
+        startup = startup.check("event bus", move || {
+            let event_bus = event_bus.clone();
+            async move { event_bus.ping().await }
+        });
+    }
+    let readiness = startup.readiness();
+
+    if let Some(event_bus) = app_state.event_bus.clone() {
+        info!("Publishing audit events over {}", event_bus.transport());
+        let handler = Arc::new(IngestHandler { state: app_state.clone() });
+        let consumer = std::env::var("HOSTNAME").unwrap_or_else(|_| Uuid::new_v4().to_string());
+        let readiness = readiness.clone();
+        tokio::spawn(async move {
+            // Consuming before the schema is known to be current would dead-letter good events
+            readiness.wait().await;
+            if let Err(e) = event_bus
+                .consume(bus::AUDIT_INGEST_TOPIC, "audit-service", &consumer, handler)
+                .await
+            {
+                error!("Audit ingest consumer on {} stopped: {}", event_bus.transport(), e);
+            }
+        });
+    }
+
+    let grpc_service = AuditIngestionServer::with_interceptor(
+        AuditIngestionService::new(app_state.clone()),
+        app_state.authenticator.clone().grpc_interceptor(),
+    );
+
+    // The v1 event shapes give way to v2; everything else is only in v1 so far
+    let v1_events = versioning::deprecate(
+        Router::new()
+            .route("/audit/events", post(create_audit_event).get(get_audit_trail))
+            .route("/audit/trail/:resource_type/:resource_id", get(get_resource_audit_trail)),
+        Deprecation::new("audit", "v1", v2::v1_deprecated_at())
+            .sunset_from_env("AUDIT_API_V1_SUNSET")
+            .successor_prefix("/api/v2"),
+    );
+    let api_v1 = Router::new()
+        .merge(v1_events)
         .route("/audit/events/:event_id", get(get_audit_event))
+        .route("/audit/events/:event_id/diff", get(get_audit_event_diff))
+        .route("/audit/state/:resource_type/:resource_id", get(get_resource_state))
+        .route("/audit/stats", get(get_audit_stats))
+        .route("/audit/queue/:event_id", get(get_queued_audit_event))
         .route("/audit/verify/:event_id", get(verify_audit_event))
-        .route("/audit/trail/:resource_type/:resource_id", get(get_resource_audit_trail))
+        .route("/audit/events/:event_id/proof", get(get_inclusion_proof))
+        .route("/audit/events/:event_id/timestamp", get(get_event_timestamp))
+        .route(
+            "/audit/events/:event_id/attachments",
+            post(upload_attachment)
+                .layer(DefaultBodyLimit::max(app_state.attachment_settings.max_bytes))
+                .get(list_attachments),
+        )
+        .route("/audit/events/:event_id/attachments/:attachment_id/content", get(download_attachment))
+        .route("/audit/receipts/verify", post(verify_audit_receipt))
+        .route("/audit/receipts/keys", get(list_receipt_keys))
+        .route("/audit/custody/:resource_type/:resource_id", post(generate_custody_report))
+        .route("/audit/custody-reports", get(find_custody_report))
+        .route("/audit/custody-reports/:report_id", get(get_custody_report))
+        .route("/admin/signing/resign-runs", post(start_resign_run))
+        .route("/admin/signing/resign-runs/:run_id", get(get_resign_run))
+        .route("/admin/reconciliation/runs", post(start_reconciliation_run))
+        .route("/admin/reconciliation/runs/:run_id", get(get_reconciliation_run))
+        .route("/admin/integrity/checks", get(list_integrity_checks).post(start_integrity_check))
+        .route("/admin/integrity/checks/:check_id", get(get_integrity_check))
+        .route("/admin/anchor-outbox", get(list_anchor_outbox))
+        .route("/admin/anchor-batches", get(list_anchor_batches))
+        .route("/admin/runbooks/jobs", get(list_runbook_jobs))
+        .route("/admin/runbooks/jobs/:job_id", get(get_runbook_job))
+        .route("/admin/runbooks/jobs/:job_id/confirm", post(confirm_runbook_job))
+        .route("/admin/runbooks/:runbook/jobs", post(plan_runbook_job))
+        .route("/admin/pipeline-latency", get(get_pipeline_latency))
+        .route("/admin/audit-queue", get(get_audit_queue))
+        .route("/admin/audit-queue/:event_id/retry", post(retry_audit_queue_entry))
+        .route("/admin/anchor-digests", post(build_anchor_digest))
+        .route("/admin/anchor-digests/:digest_date", get(get_anchor_digest))
+        .route("/admin/ipfs/pins", get(list_ipfs_pins))
+        .route("/admin/ipfs/pins/verify", post(verify_ipfs_pins))
+        .route("/admin/anchor-outbox/:outbox_id/retry", post(retry_anchor_outbox_entry))
+        .route("/admin/schemas", get(list_event_schemas).post(register_event_schema))
+        .route("/admin/schemas/:schema_id", get(get_event_schema).delete(deactivate_event_schema))
+        .route("/admin/actions", get(list_audit_actions).post(register_audit_action))
+        .route("/admin/actions/:action", get(get_audit_action).delete(deprecate_audit_action))
+        .route("/admin/actions/:action/aliases", post(add_audit_action_alias))
+        .route("/admin/action-aliases/:alias", delete(remove_audit_action_alias))
+        .route("/admin/tenants/:tenant_id/schema-mode", get(get_schema_mode).put(set_schema_mode))
+        .route("/admin/tenants/:tenant_id/retention", get(get_retention_policy).put(set_retention_policy))
+        .route("/admin/tenants/:tenant_id/archives", get(list_audit_archives))
+        .route("/admin/retention/runs", post(start_archival_run))
+        .route("/admin/audit-partitions", get(list_audit_partitions))
+        .route("/admin/audit-partitions/maintenance", post(run_partition_maintenance))
+        .route("/admin/retention/restores", post(start_archive_restore))
+        .route("/admin/retention/restores/:restore_id", get(get_archive_restore))
+        .route("/admin/retention/restores/:restore_id/events", get(list_restored_events))
+        .route(
+            "/admin/tenants/:tenant_id/subjects/:subject_id/erasure",
+            get(list_subject_erasures).post(erase_subject),
+        )
+        .route("/admin/tenants/:tenant_id/pii-discoveries", get(list_pii_discoveries))
+        .route(
+            "/admin/tenants/:tenant_id/pii-discoveries/:discovery_id/enforce",
+            post(enforce_pii_discovery),
+        )
+        .route(
+            "/admin/tenants/:tenant_id/pii-discoveries/:discovery_id/dismiss",
+            post(dismiss_pii_discovery),
+        )
+        .route("/admin/tenants/:tenant_id/pii-fields", get(list_tenant_pii_fields))
+        .route("/admin/pii-discovery/runs", post(start_pii_discovery_run))
+        .route("/admin/tenants/:tenant_id/data-keys", get(list_tenant_data_keys))
+        .route("/admin/tenants/:tenant_id/data-keys/rotate", post(rotate_tenant_data_key))
+        .route("/admin/envelope/rewrap", post(rewrap_data_keys))
+        .route("/admin/worm", get(get_worm_state))
+        .route("/admin/worm/verification", get(verify_worm))
+        .route("/admin/replication/events", post(apply_replica_batch))
+        .route("/admin/replication/heads", get(get_chain_heads))
+        .route("/admin/replication/links", post(probe_chain_links))
+        .route("/admin/replication/divergence", get(get_replication_divergence))
+        .route("/admin/api-keys", get(list_api_keys).post(issue_api_key))
+        .route("/admin/api-keys/:key_id", get(get_api_key).delete(revoke_api_key))
+        .route("/admin/api-keys/:key_id/rotate", post(rotate_api_key));
+
+    let app = versioning::versioned("audit", api_v1)
+        .nest("/api/v2", v2::router())
+        .route_layer(middleware::from_fn_with_state(admission, pool::admit))
+        // Long-lived; subscribers and exports do not hold an admission slot
+        .route("/audit/stream", get(stream::stream_audit_events))
+        .route("/audit/export", get(export::export_audit_trail))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), auth::authorize))
+        .route_layer(middleware::from_fn_with_state(readiness.clone(), startup::gate))
+        .route("/health", get(health_check))
+        .merge(startup::router(readiness))
+        .merge(dharmaguard_common::metrics::router())
         .with_state(app_state);
 
     let listener = TcpListener::bind("0.0.0.0:8084").await?;
     info!("Audit service listening on port 8084");
-    
-    axum::serve(listener, app).await?;
-    Ok(())
+
+    let grpc_addr = std::net::SocketAddr::from(([0, 0, 0, 0], grpc_port));
+    info!("Audit gRPC ingestion listening on port {}", grpc_port);
+
+    startup
+        .serve(async {
+            tokio::try_join!(
+                async {
+                    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+                        .await
+                        .map_err(anyhow::Error::from)
+                },
+                async {
+                    tonic::transport::Server::builder()
+                        .add_service(grpc_service)
+                        .serve(grpc_addr)
+                        .await
+                        .map_err(anyhow::Error::from)
+                },
+            )?;
+            Ok(())
+        })
+        .await
 }
 
-async fn health_check() -> Json<serde_json::Value> {
-    Json(serde_json::json!({"status": "healthy", "service": "audit"}))
+/// Creates audit events submitted on the ingest topic
+///
+/// A message key that is a UUID is recorded as the event's request id.
+struct IngestHandler {
+    state: AppState,
 }
 
-async fn create_audit_event(
-    State(state): State<AppState>,
-    Json(request): Json<CreateAuditEventRequest>,
-) -> Result<Json<AuditEvent>, StatusCode> {
-    let audit_service = AuditService::new(
-        state.db,
-        state.mongodb,
-        state.blockchain_client,
-        state.ipfs_client,
-    );
+#[async_trait::async_trait]
+impl EventHandler for IngestHandler {
+    async fn handle(&self, event: &BusEvent) -> anyhow::Result<()> {
+        let request: CreateAuditEventRequest = serde_json::from_slice(&event.payload)?;
+        let context = RequestContext::new(None, Some("event-bus"), event.key.as_deref());
 
-    match audit_service.create_audit_event(request).await {
-        Ok(event) => Ok(Json(event)),
-        Err(e) => {
-            error!("Failed to create audit event: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        match AuditService::from_state(self.state.clone()).create_audit_event(request, &context).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow::anyhow!("failed to create audit event: {}", e)),
         }
     }
 }
 
-async fn get_audit_trail(
-    Query(params): Query<HashMap<String, String>>,
-    State(state): State<AppState>,
-) -> Result<Json<AuditTrailResponse>, StatusCode> {
-    let tenant_id = params.get("tenant_id")
-        .and_then(|s| Uuid::parse_str(s).ok())
-        .ok_or(StatusCode::BAD_REQUEST)?;
-    
-    let resource_type = params.get("resource_type").cloned();
-    let resource_id = params.get("resource_id")
-        .and_then(|s| Uuid::parse_str(s).ok());
-    let limit = params.get("limit")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(50);
-    let offset = params.get("offset")
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(0);
-
-    let audit_service = AuditService::new(
-        state.db,
-        state.mongodb,
-        state.blockchain_client,
-        state.ipfs_client,
-    );
-
-    match audit_service.get_audit_trail(tenant_id, resource_type, resource_id, limit, offset).await {
-        Ok(trail) => Ok(Json(trail)),
+async fn health_check() -> Json<serde_json::Value> {
+    Json(serde_json::json!({"status": "healthy", "service": "audit"}))
+}
+
+/// An event accepted for creation
+pub enum Ingested {
+    Stored(AuditEvent),
+    /// Written to the write-ahead log while a store was unavailable
+    Queued(QueueReceipt),
+}
+
+/// Create an event, queueing it locally when the stores cannot take it
+pub async fn ingest_audit_event(
+    state: AppState,
+    context: RequestContext,
+    request: CreateAuditEventRequest,
+) -> Result<Ingested, (StatusCode, Json<serde_json::Value>)> {
+    let write_ahead = state.write_ahead.clone();
+    let audit_service = AuditService::from_state(state);
+    let event_id = Uuid::new_v4();
+    let timestamp = chrono::Utc::now();
+    // Kept back only when there is somewhere to queue it
+    let retained = write_ahead.as_ref().map(|_| request.clone());
+
+    // Scoped so the error, which is not Send, is gone before the queue is written
+    let queued = {
+        let e = match audit_service.create_audit_event_as(event_id, timestamp, request, &context).await {
+            Ok(event) => return Ok(Ingested::Stored(event)),
+            Err(e) => e,
+        };
+        if let Some(SchemaRejection(check)) = e.downcast_ref::<SchemaRejection>() {
+            warn!("Rejected audit event: {}", e);
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(serde_json::json!({
+                    "error": "schema_validation_failed",
+                    "schema_id": check.schema_id,
+                    "version": check.version,
+                    "errors": check.errors,
+                })),
+            ));
+        }
+        if let Some(ActionRejection(action)) = e.downcast_ref::<ActionRejection>() {
+            warn!("Rejected audit event: {}", e);
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                Json(serde_json::json!({"error": "unknown_action", "action": action})),
+            ));
+        }
+        match retained {
+            None => Err(e.to_string()),
+            Some(request) => match e.downcast::<StoredWithoutDocument>() {
+                Ok(partial) => {
+                    warn!("Queueing document of audit event {}: {}", event_id, partial.error);
+                    Ok((request, Pending::Document { event: partial.event }))
+                }
+                Err(e) => {
+                    warn!("Queueing audit event {}: {}", event_id, e);
+                    Ok((request.clone(), Pending::Create { request, context }))
+                }
+            },
+        }
+    };
+    let queued = match (queued, &write_ahead) {
+        (Ok((request, pending)), Some(write_ahead)) => write_ahead
+            .enqueue(event_id, &request, timestamp, pending)
+            .await
+            .map_err(|e| format!("write-ahead log is unavailable: {}", e)),
+        (Ok(_), None) => Err("write-ahead log is not configured".to_string()),
+        (Err(e), _) => Err(e),
+    };
+    match queued {
+        Ok(receipt) => Ok(Ingested::Queued(receipt)),
+        Err(e) => {
+            error!("Failed to create audit event: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to create audit event"})),
+            ))
+        }
+    }
+}
+
+/// Signed receipt of a stored event, when receipts are enabled
+pub fn issue_receipt(state: &AppState, event: &AuditEvent) -> Option<AuditReceipt> {
+    state.receipt_signer.as_ref()?.issue(event, state.anchor_mode)
+}
+
+/// 200 with the event and its signed receipt, or 202 with a queue receipt when it was queued
+async fn create_audit_event(
+    State(state): State<AppState>,
+    context: RequestContext,
+    Json(request): Json<CreateAuditEventRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    Ok(match ingest_audit_event(state.clone(), context, request).await? {
+        Ingested::Stored(event) => {
+            let receipt = issue_receipt(&state, &event);
+            Json(Receipted { event, receipt }).into_response()
+        }
+        Ingested::Queued(receipt) => receipt.into_response(),
+    })
+}
+
+/// Whether an event acknowledged with 202 is still queued, dead or stored
+async fn get_queued_audit_event(
+    Path(event_id): Path<Uuid>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<QueueStatus>, StatusCode> {
+    let visible = |status: &QueueStatus| caller.may_access(status.tenant_id) && caller.may_see(&status.resource_type);
+
+    if let Some(write_ahead) = &state.write_ahead {
+        match write_ahead.status(event_id) {
+            Ok(Some(status)) if visible(&status) => return Ok(Json(status)),
+            Ok(Some(_)) => return Err(StatusCode::NOT_FOUND),
+            Ok(None) => {}
+            Err(e) => {
+                error!("Failed to read write-ahead log entry {}: {}", event_id, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    // Drained entries are answered from the audit rows
+    let row = sqlx::query("SELECT tenant_id, resource_type, timestamp FROM audit_logs WHERE log_id = $1")
+        .bind(event_id)
+        .fetch_optional(&state.db)
+        .await;
+    match row {
+        Ok(Some(row)) => {
+            let status = QueueStatus::stored(event_id, row.get("tenant_id"), row.get("resource_type"), row.get("timestamp"));
+            if visible(&status) {
+                Ok(Json(status))
+            } else {
+                Err(StatusCode::NOT_FOUND)
+            }
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to look up audit event {}: {}", event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_audit_trail(
+    Query(params): Query<AuditTrailParams>,
+    caller: Caller,
+    reader: Reader,
+    State(state): State<AppState>,
+) -> Result<Json<AuditTrailResponse>, StatusCode> {
+    let (filter, page) = params
+        .into_query(caller.hidden_resource_types())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let audit_service = AuditService::from_state(state);
+
+    match audit_service.get_audit_trail(&filter, page, &reader).await {
+        Ok(trail) => Ok(Json(trail)),
         Err(e) => {
             error!("Failed to get audit trail: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -427,31 +1669,1603 @@ async fn get_audit_trail(
     }
 }
 
+/// Counts by action, actor and resource type, and a histogram, of a tenant's events in a time range
+async fn get_audit_stats(
+    Query(params): Query<StatsParams>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<AuditStats>, (StatusCode, Json<serde_json::Value>)> {
+    let stats = stats::compute(&state.db, params, caller.hidden_resource_types())
+        .await
+        .map_err(|e| match e {
+            StatsError::InvalidRange | StatsError::TooManyBuckets(..) => {
+                (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": e.to_string()})))
+            }
+            StatsError::Database(_) => {
+                error!("Failed to compute audit statistics: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(serde_json::json!({"error": "audit statistics could not be computed"})),
+                )
+            }
+        })?;
+    Ok(Json(stats))
+}
+
 async fn get_audit_event(
     Path(event_id): Path<Uuid>,
+    caller: Caller,
+    reader: Reader,
     State(state): State<AppState>,
 ) -> Result<Json<AuditEvent>, StatusCode> {
-    // Implementation for getting specific audit event
-    Err(StatusCode::NOT_IMPLEMENTED)
+    let audit_service = AuditService::from_state(state);
+
+    match audit_service.read_audit_event(event_id, &reader).await {
+        Ok(Some(event)) if caller.may_access(event.tenant_id) && caller.may_see(&event.resource_type) => Ok(Json(event)),
+        Ok(_) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Field-level changes between the event's old and new values, with sensitive paths redacted
+async fn get_audit_event_diff(
+    Path(event_id): Path<Uuid>,
+    caller: Caller,
+    reader: Reader,
+    State(state): State<AppState>,
+) -> Result<Json<EventDiff>, StatusCode> {
+    let diff_settings = state.diff_settings.clone();
+    let audit_service = AuditService::from_state(state);
+
+    let event = match audit_service.read_audit_event(event_id, &reader).await {
+        Ok(Some(event)) if caller.may_access(event.tenant_id) && caller.may_see(&event.resource_type) => event,
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    // Ciphertext would diff as one opaque change
+    if envelope::is_encrypted(&event.old_values) || envelope::is_encrypted(&event.new_values) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(Json(diff::event_diff(&diff_settings, &event)))
+}
+
+/// Current state of a resource folded from its events, or its state `as_of` an instant
+async fn get_resource_state(
+    Path((resource_type, resource_id)): Path<(String, Uuid)>,
+    Query(params): Query<StateParams>,
+    caller: Caller,
+    reader: Reader,
+    State(state): State<AppState>,
+) -> Result<Json<ResourceState>, (StatusCode, Json<serde_json::Value>)> {
+    if !caller.may_see(&resource_type) {
+        return Err(projection_error(ProjectionError::NotFound));
+    }
+    let audit_service = AuditService::from_state(state);
+    let projector = audit_service.projector();
+    let key = ResourceKey { tenant_id: params.tenant_id, resource_type: &resource_type, resource_id };
+    let result = match params.as_of {
+        Some(as_of) => projector.as_of(&key, as_of).await,
+        None => projector.current(&key).await,
+    };
+    let mut resource_state = result.map_err(projection_error)?;
+    projector.reveal(&mut resource_state, &reader).await.map_err(projection_error)?;
+    Ok(Json(resource_state))
+}
+
+fn projection_error(e: ProjectionError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        ProjectionError::NotFound => StatusCode::NOT_FOUND,
+        ProjectionError::Forbidden => StatusCode::FORBIDDEN,
+        ProjectionError::EncryptedEvent(_)
+        | ProjectionError::Envelope(_)
+        | ProjectionError::Pii(_)
+        | ProjectionError::Serialization(_)
+        | ProjectionError::Database(_) => {
+            error!("Failed to project resource state: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "resource state could not be built"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
 }
 
 async fn verify_audit_event(
     Path(event_id): Path<Uuid>,
+    caller: Caller,
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    // Implementation for verifying audit event integrity
-    Ok(Json(serde_json::json!({
-        "event_id": event_id,
-        "verified": true,
-        "blockchain_confirmed": true,
-        "ipfs_accessible": true
-    })))
+) -> Result<Json<VerificationReport>, StatusCode> {
+    let audit_service = AuditService::from_state(state);
+
+    let event = match audit_service.find_audit_event(event_id).await {
+        Ok(Some(event)) if caller.may_access(event.tenant_id) && caller.may_see(&event.resource_type) => event,
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    match audit_service.cached_verification(&event).await {
+        Ok(report) => {
+            if !report.verified {
+                warn!("Integrity verification failed for audit event {}", event_id);
+            }
+            Ok(Json(report))
+        }
+        Err(e) => {
+            error!("Failed to verify audit event {}: {}", event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Check a receipt from create_audit_event against its signature and the stored event
+async fn verify_audit_receipt(
+    caller: Caller,
+    State(state): State<AppState>,
+    Json(receipt): Json<AuditReceipt>,
+) -> Result<Json<ReceiptVerification>, StatusCode> {
+    let signer = state.receipt_signer.clone().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    if !caller.may_access(receipt.tenant_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let audit_service = AuditService::from_state(state.clone());
+
+    let event = match audit_service.find_audit_event(receipt.event_id).await {
+        Ok(Some(event)) if !caller.may_see(&event.resource_type) => return Err(StatusCode::NOT_FOUND),
+        Ok(event) => event,
+        Err(e) => {
+            error!("Failed to load audit event {}: {}", receipt.event_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let integrity = match &event {
+        Some(event) => match audit_service.cached_verification(event).await {
+            Ok(report) if report.verified => Some(VerificationCheck::pass("event_integrity")),
+            Ok(report) => Some(VerificationCheck::fail(
+                "event_integrity",
+                report
+                    .checks
+                    .iter()
+                    .filter(|check| !check.passed)
+                    .map(|check| check.check.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )),
+            Err(e) => {
+                error!("Failed to verify audit event {}: {}", receipt.event_id, e);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        },
+        None => None,
+    };
+
+    match receipts::verify(&state.db, &signer, &receipt, event.as_ref(), integrity).await {
+        Ok(verification) => {
+            if !verification.valid {
+                warn!("Receipt of audit event {} did not verify", receipt.event_id);
+            }
+            Ok(Json(verification))
+        }
+        Err(e) => {
+            error!("Failed to verify receipt of audit event {}: {}", receipt.event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Attach a file, sent as the raw body with its Content-Type, to an event as evidence
+async fn upload_attachment(
+    Path(event_id): Path<Uuid>,
+    Query(params): Query<UploadParams>,
+    caller: Caller,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<Attachment>), (StatusCode, Json<serde_json::Value>)> {
+    let audit_service = AuditService::from_state(state.clone());
+    let event = match audit_service.find_audit_event(event_id).await {
+        Ok(Some(event)) if caller.may_access(event.tenant_id) && caller.may_see(&event.resource_type) => event,
+        Ok(_) => return Err(attachment_error(event_id, AttachmentError::EventNotFound)),
+        Err(e) => {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to load audit event"})),
+            ));
+        }
+    };
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let settings = state.attachment_settings.clone();
+    match attachments::attach(&state, &settings, &event, &caller, content_type, &body, params).await {
+        Ok(attachment) => Ok((StatusCode::CREATED, Json(attachment))),
+        Err(e) => Err(attachment_error(event_id, e)),
+    }
+}
+
+async fn list_attachments(
+    Path(event_id): Path<Uuid>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Attachment>>, StatusCode> {
+    match AuditService::from_state(state.clone()).find_audit_event(event_id).await {
+        Ok(Some(event)) if caller.may_access(event.tenant_id) && caller.may_see(&event.resource_type) => {}
+        Ok(_) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+    match attachments::list(&state.db, event_id).await {
+        Ok(attachments) => Ok(Json(attachments)),
+        Err(e) => {
+            error!("Failed to list attachments of audit event {}: {}", event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The attached file as uploaded, decrypted for readers of the event's tenant
+async fn download_attachment(
+    Path((event_id, attachment_id)): Path<(Uuid, Uuid)>,
+    caller: Caller,
+    reader: Reader,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let attachment = match attachments::find(&state.db, event_id, attachment_id).await {
+        Ok(Some(attachment)) if caller.may_access(attachment.tenant_id) => attachment,
+        Ok(_) => return Err(attachment_error(event_id, AttachmentError::NotFound)),
+        Err(e) => return Err(attachment_error(event_id, e.into())),
+    };
+    match AuditService::from_state(state.clone()).find_audit_event(event_id).await {
+        Ok(Some(event)) if caller.may_see(&event.resource_type) => {}
+        Ok(_) => return Err(attachment_error(event_id, AttachmentError::NotFound)),
+        Err(e) => {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to load audit event"})),
+            ));
+        }
+    }
+    let file = attachments::content(&state, &attachment, &reader)
+        .await
+        .map_err(|e| attachment_error(event_id, e))?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, attachment.content_type.clone()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", attachment.file_name.replace('"', "")),
+            ),
+            (HeaderName::from_static("x-attachment-sha256"), attachment.sha256.clone()),
+        ],
+        file,
+    )
+        .into_response())
+}
+
+fn attachment_error(event_id: Uuid, e: AttachmentError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        AttachmentError::EventNotFound | AttachmentError::NotFound => StatusCode::NOT_FOUND,
+        AttachmentError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        AttachmentError::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        AttachmentError::Empty | AttachmentError::InvalidName => StatusCode::BAD_REQUEST,
+        AttachmentError::Forbidden => StatusCode::FORBIDDEN,
+        AttachmentError::Tampered(_) => {
+            error!("Attachment of audit event {} failed its hash check: {}", event_id, e);
+            StatusCode::CONFLICT
+        }
+        AttachmentError::StorageUnavailable | AttachmentError::EncryptionUnavailable => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        AttachmentError::Storage(_)
+        | AttachmentError::Envelope(_)
+        | AttachmentError::Database(_) => {
+            error!("Attachment of audit event {} failed: {}", event_id, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "attachment could not be stored or read"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+/// Public keys receipts are signed with, for checking them offline
+async fn list_receipt_keys(State(state): State<AppState>) -> Result<Json<Vec<ReceiptKey>>, StatusCode> {
+    let signer = state.receipt_signer.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    Ok(Json(signer.keys()))
+}
+
+/// Recent per-stage timings of the audit event write path
+async fn get_pipeline_latency(State(state): State<AppState>) -> Json<PipelineSummary> {
+    Json(state.pipeline_latency.summary())
+}
+
+#[derive(Deserialize)]
+pub struct CustodyReportParams {
+    pub tenant_id: Uuid,
+}
+
+/// Signed PDF of the resource's full audit trail with hashes, anchors and verification results
+async fn generate_custody_report(
+    Path((resource_type, resource_id)): Path<(String, Uuid)>,
+    Query(params): Query<CustodyReportParams>,
+    reader: Reader,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let reject = |status: StatusCode, message: String| (status, Json(serde_json::json!({"error": message})));
+    let signer = state.report_signer.clone().ok_or_else(|| {
+        reject(StatusCode::SERVICE_UNAVAILABLE, "chain-of-custody report signing is not configured".to_string())
+    })?;
+    let audit_service = AuditService::from_state(state);
+
+    match custody::generate(&audit_service, &signer, params.tenant_id, &resource_type, resource_id, reader.user_id).await {
+        Ok((report, pdf)) => {
+            let file_name = format!("custody-{}-{}.pdf", resource_type.to_lowercase(), resource_id);
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "application/pdf".to_string()),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+                    (HeaderName::from_static("x-custody-report-id"), report.report_id.to_string()),
+                    (HeaderName::from_static("x-custody-pdf-sha256"), report.pdf_hash),
+                    (HeaderName::from_static("x-custody-signature"), report.pdf_signature),
+                    (HeaderName::from_static("x-custody-signing-key"), report.signing_key_id),
+                ],
+                pdf,
+            )
+                .into_response())
+        }
+        Err(CustodyError::NotFound) => Err(reject(StatusCode::NOT_FOUND, CustodyError::NotFound.to_string())),
+        Err(e @ CustodyError::TooManyEvents(_)) => Err(reject(StatusCode::UNPROCESSABLE_ENTITY, e.to_string())),
+        Err(e) => {
+            error!("Failed to generate custody report for {} {}: {}", resource_type, resource_id, e);
+            Err(reject(StatusCode::INTERNAL_SERVER_ERROR, "failed to generate custody report".to_string()))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FindCustodyReportParams {
+    /// SHA-256 of a report PDF, as handed to a regulator or court
+    pub pdf_hash: String,
+}
+
+async fn get_custody_report(
+    Path(report_id): Path<Uuid>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<CustodyReportDetail>, StatusCode> {
+    custody_report(&state, &caller, Some(report_id), None).await
+}
+
+async fn find_custody_report(
+    Query(params): Query<FindCustodyReportParams>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<CustodyReportDetail>, StatusCode> {
+    custody_report(&state, &caller, None, Some(&params.pdf_hash)).await
+}
+
+async fn custody_report(
+    state: &AppState,
+    caller: &Caller,
+    report_id: Option<Uuid>,
+    pdf_hash: Option<&str>,
+) -> Result<Json<CustodyReportDetail>, StatusCode> {
+    let signer = state.report_signer.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    match custody::find(&state.db, signer, report_id, pdf_hash).await {
+        Ok(Some(detail))
+            if caller.may_access(detail.report.tenant_id) && caller.may_see(&detail.report.resource_type) =>
+        {
+            Ok(Json(detail))
+        }
+        Ok(_) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load custody report: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Merkle path from the event to its day's anchored digest root, for offline verification
+async fn get_inclusion_proof(
+    Path(event_id): Path<Uuid>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<InclusionProof>, StatusCode> {
+    let owner = sqlx::query_as::<_, (Uuid, String)>("SELECT tenant_id, resource_type FROM audit_logs WHERE log_id = $1")
+        .bind(event_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !owner.is_some_and(|(tenant_id, resource_type)| caller.may_access(tenant_id) && caller.may_see(&resource_type)) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    match digest::inclusion_proof(&state.db, event_id).await {
+        Ok(Some(proof)) => Ok(Json(proof)),
+        // Unknown event, or its day has not been digested yet
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to build inclusion proof for audit event {}: {}", event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The RFC 3161 token covering an event, with the inclusion proof when it is over the event's digest
+async fn get_event_timestamp(
+    Path(event_id): Path<Uuid>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<TimestampEvidence>, StatusCode> {
+    let owner = sqlx::query_as::<_, (Uuid, String)>("SELECT tenant_id, resource_type FROM audit_logs WHERE log_id = $1")
+        .bind(event_id)
+        .fetch_optional(&state.db)
+        .await
+        .map_err(|e| {
+            error!("Failed to load audit event {}: {}", event_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !owner.is_some_and(|(tenant_id, resource_type)| caller.may_access(tenant_id) && caller.may_see(&resource_type)) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    match tsa::evidence(&state.db, event_id).await {
+        Ok(Some(evidence)) => Ok(Json(evidence)),
+        // Unknown event, or not timestamped yet
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load the timestamp of audit event {}: {}", event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
 }
 
 async fn get_resource_audit_trail(
     Path((resource_type, resource_id)): Path<(String, Uuid)>,
+    Query(params): Query<ResourceTrailParams>,
+    reader: Reader,
     State(state): State<AppState>,
 ) -> Result<Json<AuditTrailResponse>, StatusCode> {
-    // Implementation for getting audit trail for specific resource
-    Err(StatusCode::NOT_IMPLEMENTED)
+    if let (Some(from), Some(to)) = (params.from, params.to) {
+        if from >= to {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let audit_service = AuditService::from_state(state);
+
+    match audit_service
+        .get_resource_audit_trail(params.tenant_id, &resource_type, resource_id, &params, &reader)
+        .await
+    {
+        Ok(trail) => Ok(Json(trail)),
+        Err(e) => {
+            error!("Failed to get audit trail for {}/{}: {}", resource_type, resource_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn start_resign_run(
+    State(state): State<AppState>,
+    Json(request): Json<ResignRequest>,
+) -> Result<Json<ResignRun>, StatusCode> {
+    if request.rationale.trim().len() < 10 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.compromised_key_id == state.signer.current_key_id() {
+        // Rotate the signing key before re-signing away from it
+        return Err(StatusCode::CONFLICT);
+    }
+
+    match resign::start_run(state.db, state.documents, state.signer, state.cache, request).await {
+        Ok(run) => Ok(Json(run)),
+        Err(e) => {
+            error!("Failed to start re-signing run: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_resign_run(
+    Path(run_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<ResignRun>, StatusCode> {
+    match resign::get_run(&state.db, run_id).await {
+        Ok(Some(run)) => Ok(Json(run)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load re-signing run {}: {}", run_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListIntegrityChecksParams {
+    pub limit: Option<i64>,
+}
+
+async fn start_integrity_check(
+    State(state): State<AppState>,
+    Json(request): Json<SweepRequest>,
+) -> Result<Json<IntegrityCheck>, StatusCode> {
+    if request.sample_size.is_some_and(|size| !(1..=100_000).contains(&size)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match sweep::start(state, request.mode, request.sample_size, "MANUAL").await {
+        Ok(Some(check)) => Ok(Json(check)),
+        // A check of this mode is still running
+        Ok(None) => Err(StatusCode::CONFLICT),
+        Err(e) => {
+            error!("Failed to start integrity check: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_integrity_checks(
+    Query(params): Query<ListIntegrityChecksParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<IntegrityCheck>>, StatusCode> {
+    let limit = params.limit.unwrap_or(20).clamp(1, 200);
+
+    match sweep::list_checks(&state.db, limit).await {
+        Ok(checks) => Ok(Json(checks)),
+        Err(e) => {
+            error!("Failed to list integrity checks: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_integrity_check(
+    Path(check_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<IntegrityCheck>, StatusCode> {
+    match sweep::get_check(&state.db, check_id).await {
+        Ok(Some(check)) => Ok(Json(check)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load integrity check {}: {}", check_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListAnchorOutboxParams {
+    /// PENDING (default), DONE or DEAD
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+async fn list_anchor_outbox(
+    Query(params): Query<ListAnchorOutboxParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<OutboxEntry>>, StatusCode> {
+    let status = params.status.unwrap_or_else(|| "PENDING".to_string()).to_uppercase();
+    if !["PENDING", "DONE", "DEAD"].contains(&status.as_str()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    match outbox::list_entries(&state.db, &status, limit).await {
+        Ok(entries) => Ok(Json(entries)),
+        Err(e) => {
+            error!("Failed to list anchoring outbox: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Request a recovery runbook; it only runs once confirmed with the returned token
+async fn plan_runbook_job(
+    Path(runbook): Path<Runbook>,
+    caller: Caller,
+    State(state): State<AppState>,
+    Json(request): Json<RunbookRequest>,
+) -> Result<(StatusCode, Json<PlannedJob>), (StatusCode, Json<serde_json::Value>)> {
+    let settings = state.runbook_settings.clone();
+    match runbooks::plan(&state, &settings, runbook, request, &caller).await {
+        Ok(planned) => Ok((StatusCode::CREATED, Json(planned))),
+        Err(e) => Err(runbook_error(e)),
+    }
+}
+
+async fn confirm_runbook_job(
+    Path(job_id): Path<Uuid>,
+    caller: Caller,
+    State(state): State<AppState>,
+    Json(request): Json<ConfirmRequest>,
+) -> Result<Json<RunbookJob>, (StatusCode, Json<serde_json::Value>)> {
+    let settings = state.runbook_settings.clone();
+    match runbooks::confirm(&state, &settings, job_id, &request, &caller).await {
+        Ok(job) => Ok(Json(job)),
+        Err(e) => Err(runbook_error(e)),
+    }
+}
+
+async fn get_runbook_job(
+    Path(job_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<RunbookJob>, StatusCode> {
+    match runbooks::get_job(&state.db, job_id).await {
+        Ok(Some(job)) => Ok(Json(job)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load runbook job {}: {}", job_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListRunbookJobsParams {
+    pub limit: Option<i64>,
+}
+
+async fn list_runbook_jobs(
+    Query(params): Query<ListRunbookJobsParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RunbookJob>>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    match runbooks::list_jobs(&state.db, limit).await {
+        Ok(jobs) => Ok(Json(jobs)),
+        Err(e) => {
+            error!("Failed to list runbook jobs: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn runbook_error(e: RunbookError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        RunbookError::Invalid(_) => StatusCode::BAD_REQUEST,
+        RunbookError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        RunbookError::NotFound => StatusCode::NOT_FOUND,
+        RunbookError::Rejected => StatusCode::FORBIDDEN,
+        RunbookError::AlreadyRunning(_) => StatusCode::CONFLICT,
+        RunbookError::Database(_) | RunbookError::Failed(_) => {
+            error!("Runbook request failed: {:#}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "runbook request failed"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+#[derive(Deserialize)]
+pub struct ListAnchorBatchesParams {
+    pub limit: Option<i64>,
+}
+
+/// Recent anchor batches with their size and gas cost
+async fn list_anchor_batches(
+    Query(params): Query<ListAnchorBatchesParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AnchorBatch>>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    match outbox::list_batches(&state.db, limit).await {
+        Ok(batches) => Ok(Json(batches)),
+        Err(e) => {
+            error!("Failed to list anchor batches: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Requeue an entry that exhausted its attempts; only DEAD entries can be retried
+async fn retry_anchor_outbox_entry(
+    Path(outbox_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<OutboxEntry>, StatusCode> {
+    match outbox::requeue(&state.db, outbox_id).await {
+        Ok(Some(entry)) => Ok(Json(entry)),
+        Ok(None) => Err(StatusCode::CONFLICT),
+        Err(e) => {
+            error!("Failed to requeue anchoring outbox entry {}: {}", outbox_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_audit_queue(State(state): State<AppState>) -> Result<Json<QueueSummary>, StatusCode> {
+    let write_ahead = state.write_ahead.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    match write_ahead.summary() {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => {
+            error!("Failed to summarise write-ahead log: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Requeue an event that was moved aside; only DEAD entries can be retried
+async fn retry_audit_queue_entry(
+    Path(event_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<QueueStatus>, StatusCode> {
+    let write_ahead = state.write_ahead.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    match write_ahead.requeue(event_id).await {
+        Ok(Some(status)) => Ok(Json(status)),
+        Ok(None) => Err(StatusCode::CONFLICT),
+        Err(e) => {
+            error!("Failed to requeue write-ahead log entry {}: {}", event_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListIpfsPinsParams {
+    pub status: Option<String>,
+    pub provider: Option<String>,
+    pub cid: Option<String>,
+    pub limit: Option<i64>,
+}
+
+async fn list_ipfs_pins(
+    Query(params): Query<ListIpfsPinsParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Pin>>, StatusCode> {
+    let status = params.status.map(|status| status.to_uppercase());
+    let provider = params.provider.map(|provider| provider.to_uppercase());
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    match state
+        .pins
+        .list(status.as_deref(), provider.as_deref(), params.cid.as_deref(), limit)
+        .await
+    {
+        Ok(pins) => Ok(Json(pins)),
+        Err(e) => {
+            error!("Failed to list IPFS pins: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Run pin verification now rather than waiting for the schedule
+async fn verify_ipfs_pins(State(state): State<AppState>) -> Result<Json<VerificationSummary>, StatusCode> {
+    match state.pins.verify().await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => {
+            error!("IPFS pin verification failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Body for POST /admin/anchor-digests; defaults to yesterday (UTC)
+#[derive(Deserialize)]
+pub struct AnchorDigestRequest {
+    pub date: Option<chrono::NaiveDate>,
+}
+
+/// Build a missed day or rebuild a FAILED one
+async fn build_anchor_digest(
+    State(state): State<AppState>,
+    Json(request): Json<AnchorDigestRequest>,
+) -> Result<Json<AnchorDigest>, StatusCode> {
+    let today = chrono::Utc::now().date_naive();
+    let day = request.date.unwrap_or(today - chrono::Duration::days(1));
+    // The current day is still accumulating events
+    if day >= today {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match state.digests.build(day).await {
+        Ok(Some(digest)) => Ok(Json(digest)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to build audit digest for {}: {}", day, e);
+            match digest::get_digest(&state.db, day).await {
+                Ok(Some(existing)) if existing.status != "FAILED" => Err(StatusCode::CONFLICT),
+                _ => Err(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        }
+    }
+}
+
+async fn get_anchor_digest(
+    Path(digest_date): Path<chrono::NaiveDate>,
+    State(state): State<AppState>,
+) -> Result<Json<AnchorDigest>, StatusCode> {
+    match digest::get_digest(&state.db, digest_date).await {
+        Ok(Some(digest)) => Ok(Json(digest)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit digest {}: {}", digest_date, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Body for POST /admin/reconciliation/runs; defaults to yesterday (UTC)
+#[derive(Deserialize)]
+pub struct ReconciliationRequest {
+    pub date: Option<chrono::NaiveDate>,
+}
+
+async fn start_reconciliation_run(
+    State(state): State<AppState>,
+    Json(request): Json<ReconciliationRequest>,
+) -> Result<Json<ReconciliationRun>, StatusCode> {
+    let today = chrono::Utc::now().date_naive();
+    let day = request.date.unwrap_or(today - chrono::Duration::days(1));
+    if day >= today {
+        // The day must be complete before it can be reconciled
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match reconcile::run(&state.db, state.documents.as_ref(), day, "MANUAL").await {
+        Ok(run) => Ok(Json(run)),
+        Err(e) => {
+            error!("Failed to run reconciliation for {}: {}", day, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_reconciliation_run(
+    Path(run_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<ReconciliationRun>, StatusCode> {
+    match reconcile::get_run(&state.db, run_id).await {
+        Ok(Some(run)) => Ok(Json(run)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load reconciliation run {}: {}", run_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_event_schemas(
+    Query(params): Query<SchemaListParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<EventSchema>>, StatusCode> {
+    match schemas::list_schemas(&state.db, &params).await {
+        Ok(schemas) => Ok(Json(schemas)),
+        Err(e) => {
+            error!("Failed to list audit event schemas: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn register_event_schema(
+    State(state): State<AppState>,
+    Json(request): Json<RegisterSchemaRequest>,
+) -> Result<Json<EventSchema>, (StatusCode, Json<serde_json::Value>)> {
+    if request.resource_type.trim().is_empty() || request.action.as_deref().map_or(false, |a| a.trim().is_empty()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": "resource_type and action must not be blank"})),
+        ));
+    }
+    if let Err(reason) = schemas::compile(&request.schema) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"error": reason}))));
+    }
+
+    match schemas::register_schema(&state.db, &request).await {
+        Ok(schema) => {
+            info!(
+                "Registered audit event schema {}/{} version {}",
+                schema.resource_type,
+                schema.action.as_deref().unwrap_or("*"),
+                schema.version
+            );
+            Ok(Json(schema))
+        }
+        Err(e) => {
+            error!("Failed to register audit event schema: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to register schema"})),
+            ))
+        }
+    }
+}
+
+async fn get_event_schema(
+    Path(schema_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<EventSchema>, StatusCode> {
+    match schemas::get_schema(&state.db, schema_id).await {
+        Ok(Some(schema)) => Ok(Json(schema)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit event schema {}: {}", schema_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn deactivate_event_schema(
+    Path(schema_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<EventSchema>, StatusCode> {
+    match schemas::deactivate_schema(&state.db, schema_id).await {
+        Ok(Some(schema)) => {
+            info!("Deactivated audit event schema {}", schema_id);
+            Ok(Json(schema))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to deactivate audit event schema {}: {}", schema_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_audit_actions(
+    Query(params): Query<ActionListParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ActionDefinition>>, StatusCode> {
+    match taxonomy::list_actions(&state.db, &params).await {
+        Ok(actions) => Ok(Json(actions)),
+        Err(e) => {
+            error!("Failed to list audit actions: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_audit_action(
+    Path(action): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<ActionDefinition>, StatusCode> {
+    match taxonomy::get_action(&state.db, &action).await {
+        Ok(Some(definition)) => Ok(Json(definition)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit action {}: {}", action, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn register_audit_action(
+    State(state): State<AppState>,
+    Json(request): Json<RegisterActionRequest>,
+) -> Result<(StatusCode, Json<ActionDefinition>), (StatusCode, Json<serde_json::Value>)> {
+    let definition = taxonomy::register_action(&state.db, &request).await.map_err(taxonomy_error)?;
+    info!("Registered audit action {} with {} alias(es)", definition.action, definition.aliases.len());
+    reload_taxonomy(&state).await;
+    Ok((StatusCode::CREATED, Json(definition)))
+}
+
+async fn add_audit_action_alias(
+    Path(action): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<AliasRequest>,
+) -> Result<(StatusCode, Json<ActionDefinition>), (StatusCode, Json<serde_json::Value>)> {
+    let definition = taxonomy::add_alias(&state.db, &action, &request).await.map_err(taxonomy_error)?;
+    info!("Aliased '{}' to audit action {}", request.alias.trim(), action);
+    reload_taxonomy(&state).await;
+    Ok((StatusCode::CREATED, Json(definition)))
+}
+
+async fn remove_audit_action_alias(Path(alias): Path<String>, State(state): State<AppState>) -> StatusCode {
+    match taxonomy::remove_alias(&state.db, &alias).await {
+        Ok(true) => {
+            reload_taxonomy(&state).await;
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to remove audit action alias {}: {}", alias, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Stop accepting an action as known; events already carrying it are unchanged
+async fn deprecate_audit_action(
+    Path(action): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<ActionDefinition>, StatusCode> {
+    match taxonomy::deprecate_action(&state.db, &action).await {
+        Ok(Some(definition)) => {
+            reload_taxonomy(&state).await;
+            Ok(Json(definition))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to deprecate audit action {}: {}", action, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Apply an admin change on this replica now; the others pick it up on their next refresh
+async fn reload_taxonomy(state: &AppState) {
+    if let Err(e) = state.taxonomy.load(&state.db).await {
+        error!("Failed to reload the audit action taxonomy: {}", e);
+    }
+}
+
+fn taxonomy_error(e: TaxonomyError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        TaxonomyError::InvalidAction | TaxonomyError::InvalidAlias => StatusCode::BAD_REQUEST,
+        TaxonomyError::ActionExists(_) | TaxonomyError::AliasTaken(_) => StatusCode::CONFLICT,
+        TaxonomyError::NotFound => StatusCode::NOT_FOUND,
+        TaxonomyError::Database(_) => {
+            error!("Failed to change the audit action taxonomy: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to change the action taxonomy"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+async fn get_schema_mode(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match schemas::tenant_mode(&state.db, tenant_id).await {
+        Ok(mode) => Ok(Json(serde_json::json!({"tenant_id": tenant_id, "mode": mode}))),
+        Err(e) => {
+            error!("Failed to load schema mode for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn set_schema_mode(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(request): Json<TenantSchemaMode>,
+) -> Result<Json<TenantSchemaMode>, StatusCode> {
+    match schemas::set_tenant_mode(&state.db, tenant_id, &request).await {
+        Ok(()) => {
+            info!("Set audit schema mode for tenant {} to {:?}", tenant_id, request.mode);
+            Ok(Json(request))
+        }
+        Err(e) => {
+            error!("Failed to set schema mode for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_retention_policy(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<RetentionPolicy>, StatusCode> {
+    match retention::load_rules(&state.db, tenant_id).await {
+        Ok(rules) => Ok(Json(RetentionPolicy { rules, updated_by: None })),
+        Err(e) => {
+            error!("Failed to load retention rules for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn set_retention_policy(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(policy): Json<RetentionPolicy>,
+) -> Result<Json<RetentionPolicy>, (StatusCode, Json<serde_json::Value>)> {
+    let errors = retention::validate_rules(&policy.rules, &state.retention_settings);
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+
+    match retention::replace_rules(&state.db, tenant_id, &policy).await {
+        Ok(()) => {
+            info!("Updated {} retention rules for tenant {}", policy.rules.len(), tenant_id);
+            Ok(Json(policy))
+        }
+        Err(e) => {
+            error!("Failed to update retention rules for tenant {}: {}", tenant_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to update retention rules"})),
+            ))
+        }
+    }
+}
+
+async fn list_audit_archives(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AuditArchive>>, StatusCode> {
+    match retention::list_archives(&state.db, tenant_id).await {
+        Ok(archives) => Ok(Json(archives)),
+        Err(e) => {
+            error!("Failed to list audit archives for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn start_archival_run(
+    State(state): State<AppState>,
+) -> Result<Json<ArchivalSummary>, (StatusCode, Json<serde_json::Value>)> {
+    let archiver = state.archiver.ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({"error": "archival is disabled (AUDIT_ARCHIVE_BUCKET is not set)"})),
+    ))?;
+
+    match archiver.run().await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) if e.is::<WormRefusal>() => {
+            Err((StatusCode::CONFLICT, Json(serde_json::json!({"error": e.to_string()}))))
+        }
+        Err(e) => {
+            error!("Failed to run audit archival: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to run audit archival"})),
+            ))
+        }
+    }
+}
+
+async fn list_audit_partitions(State(state): State<AppState>) -> Result<Json<Vec<AuditPartition>>, StatusCode> {
+    match partitions::list_partitions(&state.db).await {
+        Ok(partitions) => Ok(Json(partitions)),
+        Err(e) => {
+            error!("Failed to list audit partitions: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Create upcoming partitions and drop emptied ones now rather than on the next worker pass
+async fn run_partition_maintenance(State(state): State<AppState>) -> Result<Json<MaintenanceSummary>, StatusCode> {
+    match partitions::maintain(&state.db, &state.partition_settings).await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => {
+            error!("Failed to run audit partition maintenance: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn start_archive_restore(
+    State(state): State<AppState>,
+    Json(request): Json<RestoreRequest>,
+) -> Result<Json<RestoreRun>, StatusCode> {
+    let archiver = state.archiver.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    if request.reason.trim().len() < 10 || request.from > request.to {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match archiver.start_restore(request).await {
+        Ok(run) => Ok(Json(run)),
+        Err(e) => {
+            error!("Failed to start audit archive restore: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_archive_restore(
+    Path(restore_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<RestoreRun>, StatusCode> {
+    match retention::get_restore(&state.db, restore_id).await {
+        Ok(Some(run)) => Ok(Json(run)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit archive restore {}: {}", restore_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Query parameters for GET /admin/retention/restores/:restore_id/events
+#[derive(Deserialize)]
+pub struct RestoredEventParams {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+async fn list_restored_events(
+    Path(restore_id): Path<Uuid>,
+    Query(params): Query<RestoredEventParams>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RestoredEvent>>, StatusCode> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    match retention::get_restore(&state.db, restore_id).await {
+        Ok(Some(run)) if run.status == "EXPIRED" => return Err(StatusCode::GONE),
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load audit archive restore {}: {}", restore_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    match retention::restored_events(&state.db, restore_id, &caller.hidden_resource_types(), limit, offset).await {
+        Ok(events) => Ok(Json(events)),
+        Err(e) => {
+            error!("Failed to list events of audit archive restore {}: {}", restore_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn erase_subject(
+    Path((tenant_id, subject_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    Json(request): Json<ErasureRequest>,
+) -> Result<Json<Erasure>, StatusCode> {
+    let pii = state.pii.clone().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    if request.reason.trim().len() < 10 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let erasure = match pii.erase(&state.db, tenant_id, subject_id, &request).await {
+        Ok(erasure) => erasure,
+        Err(e) => {
+            error!("Failed to erase personal data of subject {}: {}", subject_id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    info!(
+        "Erased personal data of subject {} in tenant {}: {} keys shredded",
+        subject_id, tenant_id, erasure.keys_shredded
+    );
+
+    // The erasure itself is audited; the reason stays in audit_erasures rather than the immutable trail
+    let audit_request = CreateAuditEventRequest {
+        tenant_id,
+        user_id: request.requested_by,
+        action: "privacy.personal_data.erase".to_string(),
+        resource_type: "DATA_SUBJECT".to_string(),
+        resource_id: Some(subject_id),
+        old_values: None,
+        new_values: Some(serde_json::json!({
+            "erasure_id": erasure.erasure_id,
+            "keys_shredded": erasure.keys_shredded,
+            "regulatory_reference": erasure.regulatory_reference,
+        })),
+        metadata: None,
+    };
+    let context = RequestContext::new(None, Some("erasure"), None);
+    if let Err(e) = AuditService::from_state(state).create_audit_event(audit_request, &context).await {
+        error!("Failed to audit erasure {}: {}", erasure.erasure_id, e);
+    }
+
+    Ok(Json(erasure))
+}
+
+async fn list_subject_erasures(
+    Path((tenant_id, subject_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Erasure>>, StatusCode> {
+    match pii::list_erasures(&state.db, tenant_id, subject_id).await {
+        Ok(erasures) => Ok(Json(erasures)),
+        Err(e) => {
+            error!("Failed to list erasures of subject {}: {}", subject_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ListPiiDiscoveriesParams {
+    pub status: Option<String>,
+}
+
+async fn list_pii_discoveries(
+    Path(tenant_id): Path<Uuid>,
+    Query(params): Query<ListPiiDiscoveriesParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<PiiDiscovery>>, StatusCode> {
+    let status = params.status.map(|status| status.to_uppercase());
+    if let Some(status) = &status {
+        if !["OPEN", "ENFORCED", "DISMISSED"].contains(&status.as_str()) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    match discovery::list(&state.db, tenant_id, status.as_deref()).await {
+        Ok(discoveries) => Ok(Json(discoveries)),
+        Err(e) => {
+            error!("Failed to list PII findings for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn discovery_error(discovery_id: Uuid, e: DiscoveryError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        DiscoveryError::NotFound => StatusCode::NOT_FOUND,
+        DiscoveryError::Conflict(_) => StatusCode::CONFLICT,
+        DiscoveryError::Database(_) => {
+            error!("Failed to resolve PII finding {}: {}", discovery_id, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to resolve PII finding"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+/// Seal the finding's key in the tenant's future audit events
+async fn enforce_pii_discovery(
+    Path((tenant_id, discovery_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    Json(request): Json<ResolveRequest>,
+) -> Result<Json<PiiDiscovery>, (StatusCode, Json<serde_json::Value>)> {
+    let pii = state.pii.clone().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({"error": "personal data sealing is not configured"})),
+    ))?;
+    let discovery = discovery::enforce(&state.db, &pii, tenant_id, discovery_id, &request)
+        .await
+        .map_err(|e| discovery_error(discovery_id, e))?;
+    discovery::record_enforcement(&state, &discovery).await;
+    Ok(Json(discovery))
+}
+
+async fn dismiss_pii_discovery(
+    Path((tenant_id, discovery_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    Json(request): Json<ResolveRequest>,
+) -> Result<Json<PiiDiscovery>, (StatusCode, Json<serde_json::Value>)> {
+    match discovery::dismiss(&state.db, tenant_id, discovery_id, &request).await {
+        Ok(discovery) => Ok(Json(discovery)),
+        Err(e) => Err(discovery_error(discovery_id, e)),
+    }
+}
+
+async fn list_tenant_pii_fields(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TenantPiiField>>, StatusCode> {
+    match discovery::tenant_fields(&state.db, tenant_id).await {
+        Ok(fields) => Ok(Json(fields)),
+        Err(e) => {
+            error!("Failed to list sealed fields of tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Sample stored values for untagged personal data now rather than at the scheduled time
+async fn start_pii_discovery_run(State(state): State<AppState>) -> Result<Json<DiscoverySummary>, StatusCode> {
+    match discovery::run(&state, &state.discovery_settings).await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => {
+            error!("Failed to run PII discovery: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_tenant_data_keys(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DataKey>>, StatusCode> {
+    match envelope::list_data_keys(&state.db, tenant_id).await {
+        Ok(keys) => Ok(Json(keys)),
+        Err(e) => {
+            error!("Failed to list data keys for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn rotate_tenant_data_key(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<DataKey>, StatusCode> {
+    let envelope = state.envelope.clone().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    match envelope.rotate(&state.db, tenant_id).await {
+        Ok(key) => {
+            info!("Rotated audit data key for tenant {} to {}", tenant_id, key.key_id);
+            Ok(Json(key))
+        }
+        Err(e) => {
+            error!("Failed to rotate data key for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn rewrap_data_keys(State(state): State<AppState>) -> Result<Json<RewrapSummary>, StatusCode> {
+    let envelope = state.envelope.clone().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    match envelope.rewrap(&state.db).await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => {
+            error!("Failed to rewrap audit data keys: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_api_keys(
+    Query(params): Query<ApiKeyListParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ApiKey>>, StatusCode> {
+    match api_keys::list(&state.db, &params).await {
+        Ok(keys) => Ok(Json(keys)),
+        Err(e) => {
+            error!("Failed to list API keys: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_api_key(Path(key_id): Path<Uuid>, State(state): State<AppState>) -> Result<Json<ApiKey>, StatusCode> {
+    match api_keys::get(&state.db, key_id).await {
+        Ok(Some(key)) => Ok(Json(key)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load API key {}: {}", key_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn issue_api_key(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(request): Json<IssueApiKeyRequest>,
+) -> Result<(StatusCode, Json<IssuedApiKey>), (StatusCode, Json<serde_json::Value>)> {
+    let issued = state.api_keys.issue(&request, &caller).await.map_err(api_key_error)?;
+    info!(
+        "Issued API key {} for {} on {} tenant(s)",
+        issued.key.key_id,
+        issued.key.service,
+        issued.key.tenant_ids.len()
+    );
+    Ok((StatusCode::CREATED, Json(issued)))
+}
+
+async fn rotate_api_key(
+    Path(key_id): Path<Uuid>,
+    State(state): State<AppState>,
+    caller: Caller,
+    request: Option<Json<RotateApiKeyRequest>>,
+) -> Result<(StatusCode, Json<IssuedApiKey>), (StatusCode, Json<serde_json::Value>)> {
+    let Json(request) = request.unwrap_or_default();
+    let issued = state.api_keys.rotate(key_id, &request, &caller).await.map_err(api_key_error)?;
+    info!("Rotated API key {} of {} to {}", key_id, issued.key.service, issued.key.key_id);
+    Ok((StatusCode::CREATED, Json(issued)))
+}
+
+async fn revoke_api_key(
+    Path(key_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiKey>, (StatusCode, Json<serde_json::Value>)> {
+    let key = state.api_keys.revoke(key_id).await.map_err(api_key_error)?;
+    info!("Revoked API key {} of {}", key.key_id, key.service);
+    Ok(Json(key))
+}
+
+fn api_key_error(e: ApiKeyError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        ApiKeyError::InvalidService | ApiKeyError::NoTenants | ApiKeyError::InvalidExpiry => StatusCode::BAD_REQUEST,
+        ApiKeyError::NotFound => StatusCode::NOT_FOUND,
+        ApiKeyError::Revoked => StatusCode::CONFLICT,
+        ApiKeyError::Database(_) => {
+            error!("Failed to change API keys: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to change API keys"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+async fn get_worm_state(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    match worm::enforced(&state.db).await {
+        Ok(worm) => Ok(Json(serde_json::json!({"enforced": worm.is_some(), "state": worm}))),
+        Err(e) => {
+            error!("Failed to load WORM state: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn verify_worm(
+    Query(params): Query<VerificationParams>,
+    State(state): State<AppState>,
+) -> Result<Json<WormVerification>, (StatusCode, Json<serde_json::Value>)> {
+    worm::verify(&state, &params).await.map(Json).map_err(worm_error)
+}
+
+fn worm_error(e: WormError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        WormError::InvalidRange | WormError::TooManyDays(_) => StatusCode::BAD_REQUEST,
+        WormError::Database(_) | WormError::Verification(_) => {
+            error!("Failed to verify the WORM audit trail: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to verify the audit trail"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+async fn apply_replica_batch(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(batch): Json<ReplicaBatch>,
+) -> Result<Json<ReplicaReceipt>, (StatusCode, Json<serde_json::Value>)> {
+    // Only the peer region's replication worker ships events
+    if !matches!(caller, Caller::Service { .. }) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(serde_json::json!({"error": "replicated events are only accepted from a service token"})),
+        ));
+    }
+    let replication = state.replication.clone().ok_or_else(|| replication_error(ReplicationError::Disabled))?;
+    replication::apply(&state, &replication, batch).await.map(Json).map_err(replication_error)
+}
+
+async fn get_chain_heads(
+    State(state): State<AppState>,
+) -> Result<Json<ChainHeads>, (StatusCode, Json<serde_json::Value>)> {
+    let replication = state.replication.ok_or_else(|| replication_error(ReplicationError::Disabled))?;
+    replication::heads(&state.db, replication.region())
+        .await
+        .map(Json)
+        .map_err(|e| replication_error(e.into()))
+}
+
+async fn probe_chain_links(
+    State(state): State<AppState>,
+    Json(probes): Json<Vec<LinkProbe>>,
+) -> Result<Json<Vec<ProbedLink>>, (StatusCode, Json<serde_json::Value>)> {
+    replication::probe(&state.db, &probes)
+        .await
+        .map(Json)
+        .map_err(|e| replication_error(e.into()))
+}
+
+async fn get_replication_divergence(
+    State(state): State<AppState>,
+) -> Result<Json<DivergenceReport>, (StatusCode, Json<serde_json::Value>)> {
+    let replication = state.replication.clone().ok_or_else(|| replication_error(ReplicationError::Disabled))?;
+    replication::divergence(&state, &replication).await.map(Json).map_err(replication_error)
+}
+
+fn replication_error(e: ReplicationError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        ReplicationError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+        ReplicationError::OwnRegion(_) => StatusCode::CONFLICT,
+        ReplicationError::Peer(_) => StatusCode::BAD_GATEWAY,
+        ReplicationError::Database(_) | ReplicationError::Internal(_) => {
+            error!("Audit replication request failed: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "audit replication request failed"})),
+            );
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
 }
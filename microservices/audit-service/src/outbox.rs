@@ -0,0 +1,770 @@
+//! Durable retries of IPFS pinning, blockchain anchoring and trusted timestamping
+//!
+//! Creating an event must not wait on IPFS or the chain being reachable, so a
+//! failed step is recorded here in the same transaction as the audit row and
+//! the event is stored without it. A worker retries due entries with
+//! exponential backoff: it reloads the stored event, checks the payload still
+//! matches the recorded hash, performs the step and writes the CID or anchor
+//! back to the event's document. Entries that exhaust their attempts become
+//! DEAD, raise an ops alert and wait for a manual retry. The backlog per
+//! operation and status is exported as `audit_anchor_outbox_entries`.
+//!
+//! Blockchain anchors are paid for per transaction, so they are not retried
+//! one by one. A separate worker anchors queued hashes in batches, as the
+//! leaves of one Merkle tree built like the daily digests, and records the
+//! batch in audit_anchor_batches; every event in it gets the batch's
+//! transaction. [`AnchorPacing`] sizes each batch and the wait before the next
+//! from the queue depth and the gas price: a deep queue is drained in large
+//! batches at short intervals, a shallow one in small batches at long ones.
+//! Above AUDIT_ANCHOR_MAX_GAS_PRICE_GWEI, or while a batch would cost more per
+//! event than AUDIT_ANCHOR_MAX_COST_PER_EVENT_GWEI, anchoring waits for the
+//! price to drop or the queue to grow, until the oldest entry has waited
+//! AUDIT_ANCHOR_MAX_DELAY_SECS. Cost per anchored event is exported as
+//! `audit_anchor_cost_per_event_gwei`.
+//!
+//! With AUDIT_BLOCKCHAIN_ENABLED=false there is no chain to anchor on: every
+//! event gets an entry at write time and the anchoring worker leaves them
+//! PENDING. Once a chain is configured the worker drains them at its usual
+//! pace, or the anchor_backfill runbook anchors the whole backlog at once
+//! through [`backfill_batch`].
+//!
+//! RFC 3161 timestamps of event hashes (see `tsa`) are never taken at write
+//! time: every event gets a TSA_TIMESTAMP entry, which the retry worker takes
+//! up once it is due, half a minute later, and retries like a failed pin.
+//!
+//! Documents the batched MongoDB writer could not insert (see `store::batch`)
+//! are spilled here as DOCUMENT_INSERT entries that carry the document. They
+//! are due at once and taken up before pins and timestamps, which need the
+//! document to be stored.
+
+use futures::TryStreamExt;
+use metrics::{counter, gauge, histogram};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::copies::StoredCopy;
+use crate::digest;
+use crate::integrity;
+use crate::store::DocumentUpdate;
+use crate::tsa;
+use crate::{AppState, AuditEvent, BlockchainClient};
+
+/// IPFS pins claimed per worker pass
+const BATCH_SIZE: i64 = 50;
+/// How long a claimed entry is hidden from other workers
+const LEASE_SECS: f64 = 300.0;
+const RETRY_BASE_SECS: f64 = 30.0;
+const RETRY_MAX_SECS: f64 = 3600.0;
+/// Recorded as the first error of anchors deferred in pending anchor mode
+pub const ANCHORING_DISABLED: &str = "blockchain anchoring is disabled (AUDIT_BLOCKCHAIN_ENABLED=false)";
+/// Recorded as the first error of timestamps, which are all taken by the worker
+pub const TIMESTAMP_QUEUED: &str = "queued for the time-stamping authority";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Operation {
+    IpfsPin,
+    BlockchainAnchor,
+    TsaTimestamp,
+    DocumentInsert,
+}
+
+impl Operation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::IpfsPin => "IPFS_PIN",
+            Self::BlockchainAnchor => "BLOCKCHAIN_ANCHOR",
+            Self::TsaTimestamp => "TSA_TIMESTAMP",
+            Self::DocumentInsert => "DOCUMENT_INSERT",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct OutboxEntry {
+    pub outbox_id: Uuid,
+    pub event_id: Uuid,
+    pub tenant_id: Uuid,
+    pub operation: String,
+    pub event_hash: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub result: Option<String>,
+    pub next_attempt_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Anchor batch that carried a completed BLOCKCHAIN_ANCHOR
+    pub batch_id: Option<Uuid>,
+    /// Document a DOCUMENT_INSERT entry writes
+    #[serde(skip)]
+    pub document: Option<sqlx::types::Json<AuditEvent>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxSettings {
+    pub poll_interval: Duration,
+    pub max_attempts: i32,
+}
+
+impl OutboxSettings {
+    pub fn from_env() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(
+                std::env::var("AUDIT_OUTBOX_POLL_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(15),
+            ),
+            max_attempts: std::env::var("AUDIT_OUTBOX_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(20),
+        }
+    }
+}
+
+/// How the anchoring worker sizes and spaces its batches
+#[derive(Debug, Clone)]
+pub struct AnchorPacing {
+    pub min_interval: Duration,
+    /// Wait between passes when the queue is empty or anchoring is deferred
+    pub max_interval: Duration,
+    pub max_batch: i64,
+    /// Gas one anchoring transaction is expected to use
+    pub gas_per_anchor: f64,
+    pub max_gas_price_gwei: Option<f64>,
+    pub max_cost_per_event_gwei: Option<f64>,
+    /// Longest an entry waits for cheaper gas before it is anchored regardless
+    pub max_delay: Duration,
+}
+
+impl AnchorPacing {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let secs = |name: &str, default: u64| {
+            Duration::from_secs(var(name).and_then(|value| value.parse().ok()).unwrap_or(default))
+        };
+        let min_interval = secs("AUDIT_ANCHOR_MIN_INTERVAL_SECS", 5).max(Duration::from_secs(1));
+        Self {
+            min_interval,
+            max_interval: secs("AUDIT_ANCHOR_MAX_INTERVAL_SECS", 300).max(min_interval),
+            max_batch: var("AUDIT_ANCHOR_MAX_BATCH")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(500)
+                .max(1),
+            gas_per_anchor: var("AUDIT_ANCHOR_GAS_PER_TX")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(60_000.0),
+            max_gas_price_gwei: var("AUDIT_ANCHOR_MAX_GAS_PRICE_GWEI").and_then(|value| value.parse().ok()),
+            max_cost_per_event_gwei: var("AUDIT_ANCHOR_MAX_COST_PER_EVENT_GWEI").and_then(|value| value.parse().ok()),
+            max_delay: secs("AUDIT_ANCHOR_MAX_DELAY_SECS", 21600),
+        }
+    }
+
+    /// Batch for `depth` due entries, the oldest waiting `oldest_wait`, at `gas_price_gwei`
+    ///
+    /// An unknown gas price never defers anchoring.
+    pub fn plan(&self, depth: i64, oldest_wait: Duration, gas_price_gwei: Option<f64>) -> AnchorPlan {
+        if depth == 0 {
+            return AnchorPlan { batch: 0, wait: self.max_interval, deferred: None };
+        }
+        // The interval shortens linearly as the queue approaches a full batch
+        let load = (depth as f64 / self.max_batch as f64).min(1.0);
+        let wait = self.max_interval.mul_f64(1.0 - load) + self.min_interval.mul_f64(load);
+        let batch = depth.min(self.max_batch);
+        if oldest_wait >= self.max_delay {
+            return AnchorPlan { batch, wait, deferred: None };
+        }
+
+        let Some(gas_price) = gas_price_gwei else {
+            return AnchorPlan { batch, wait, deferred: None };
+        };
+        if let Some(ceiling) = self.max_gas_price_gwei.filter(|ceiling| gas_price > *ceiling) {
+            return AnchorPlan {
+                batch: 0,
+                wait: self.max_interval,
+                deferred: Some(format!("gas price {:.2} gwei is above the {:.2} gwei ceiling", gas_price, ceiling)),
+            };
+        }
+        if let Some(ceiling) = self.max_cost_per_event_gwei {
+            let per_event = gas_price * self.gas_per_anchor / batch as f64;
+            if per_event > ceiling {
+                return AnchorPlan {
+                    batch: 0,
+                    wait: self.max_interval,
+                    deferred: Some(format!(
+                        "{} events would cost {:.0} gwei each, above the {:.0} gwei ceiling",
+                        batch, per_event, ceiling
+                    )),
+                };
+            }
+        }
+        AnchorPlan { batch, wait, deferred: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorPlan {
+    /// Entries to anchor now; 0 when there are none or anchoring is deferred
+    pub batch: i64,
+    /// Until the next pass
+    pub wait: Duration,
+    pub deferred: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct AnchorBatch {
+    pub batch_id: Uuid,
+    pub merkle_root: String,
+    pub transaction_hash: String,
+    pub event_count: i32,
+    pub queue_depth: i32,
+    pub gas_price_gwei: Option<f64>,
+    pub cost_gwei: Option<f64>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Record a step that failed while the event was being written
+pub async fn enqueue(
+    tx: &mut Transaction<'_, Postgres>,
+    event: &AuditEvent,
+    operation: Operation,
+    event_hash: &str,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_anchor_outbox (event_id, tenant_id, operation, event_hash, attempts, last_error, next_attempt_at)
+        VALUES ($1, $2, $3, $4, 1, $5, NOW() + make_interval(secs => $6))
+        "#,
+    )
+    .bind(event.event_id)
+    .bind(event.tenant_id)
+    .bind(operation.as_str())
+    .bind(event_hash)
+    .bind(error)
+    .bind(RETRY_BASE_SECS)
+    .execute(&mut **tx)
+    .await?;
+    counter!("audit_anchor_outbox_enqueued_total", 1, "operation" => operation.as_str());
+    Ok(())
+}
+
+/// Leave documents the document store did not take to the retry worker
+pub async fn spill_documents(db: &PgPool, events: &[AuditEvent], error: &str) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+    for event in events {
+        let hash = event
+            .event_hash
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("audit event {} is not hashed", event.event_id))?;
+        // An event spilled before is already waiting
+        sqlx::query(
+            r#"
+            INSERT INTO audit_anchor_outbox (event_id, tenant_id, operation, event_hash, attempts, last_error, document)
+            VALUES ($1, $2, $3, $4, 1, $5, $6)
+            ON CONFLICT (event_id, operation) DO NOTHING
+            "#,
+        )
+        .bind(event.event_id)
+        .bind(event.tenant_id)
+        .bind(Operation::DocumentInsert.as_str())
+        .bind(hash)
+        .bind(error)
+        .bind(sqlx::types::Json(event))
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    counter!(
+        "audit_anchor_outbox_enqueued_total",
+        events.len() as u64,
+        "operation" => Operation::DocumentInsert.as_str()
+    );
+    Ok(())
+}
+
+pub async fn list_entries(db: &PgPool, status: &str, limit: i64) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as::<_, OutboxEntry>(
+        "SELECT * FROM audit_anchor_outbox WHERE status = $1 ORDER BY created_at DESC LIMIT $2",
+    )
+    .bind(status)
+    .bind(limit)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn list_batches(db: &PgPool, limit: i64) -> Result<Vec<AnchorBatch>, sqlx::Error> {
+    sqlx::query_as::<_, AnchorBatch>("SELECT * FROM audit_anchor_batches ORDER BY created_at DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(db)
+        .await
+}
+
+/// Give a DEAD entry a fresh set of attempts, due now
+pub async fn requeue(db: &PgPool, outbox_id: Uuid) -> Result<Option<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as::<_, OutboxEntry>(
+        r#"
+        UPDATE audit_anchor_outbox
+        SET status = 'PENDING', attempts = 0, next_attempt_at = NOW()
+        WHERE outbox_id = $1 AND status = 'DEAD'
+        RETURNING *
+        "#,
+    )
+    .bind(outbox_id)
+    .fetch_optional(db)
+    .await
+}
+
+pub fn spawn_worker(state: AppState, settings: OutboxSettings) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(settings.poll_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match drain(&state, &settings).await {
+                Ok(0) => {}
+                Ok(processed) => info!("Processed {} anchoring outbox entries", processed),
+                Err(e) => error!("Anchoring outbox run failed: {}", e),
+            }
+            if let Err(e) = export_backlog(&state.db).await {
+                warn!("Failed to export anchoring outbox backlog: {}", e);
+            }
+        }
+    });
+}
+
+/// Lease up to `limit` due entries of `operation`, oldest due first
+async fn claim(db: &PgPool, operation: Operation, limit: i64) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as::<_, OutboxEntry>(
+        r#"
+        UPDATE audit_anchor_outbox
+        SET attempts = attempts + 1, next_attempt_at = NOW() + make_interval(secs => $2)
+        WHERE outbox_id IN (
+            SELECT outbox_id FROM audit_anchor_outbox
+            WHERE status = 'PENDING' AND operation = $3 AND next_attempt_at <= NOW()
+            ORDER BY next_attempt_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+    )
+    .bind(limit)
+    .bind(LEASE_SECS)
+    .bind(operation.as_str())
+    .fetch_all(db)
+    .await
+}
+
+/// Insert, pin and timestamp due entries until none are left
+async fn drain(state: &AppState, settings: &OutboxSettings) -> anyhow::Result<usize> {
+    let mut processed = 0;
+    for operation in [Operation::DocumentInsert, Operation::IpfsPin, Operation::TsaTimestamp] {
+        loop {
+            let claimed = claim(&state.db, operation, BATCH_SIZE).await?;
+            if claimed.is_empty() {
+                break;
+            }
+
+            for entry in claimed {
+                let outcome = match operation {
+                    Operation::DocumentInsert => insert_document(state, &entry).await,
+                    Operation::IpfsPin => pin(state, &entry).await,
+                    _ => timestamp(state, &entry).await,
+                };
+                record(state, settings, &entry, outcome).await?;
+                processed += 1;
+            }
+        }
+    }
+    Ok(processed)
+}
+
+/// The entry's stored event, as long as it still hashes to what was recorded at write time
+async fn verified_event(state: &AppState, entry: &OutboxEntry) -> anyhow::Result<(AuditEvent, Vec<u8>)> {
+    let event = state
+        .documents
+        .get(entry.event_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("audit event {} has no {} document", entry.event_id, state.documents.backend()))?;
+
+    // Never pin or anchor a document that no longer matches what was hashed at write time
+    let payload = integrity::canonical_payload(&event)?;
+    let computed_hash = integrity::sha256_hex(&payload);
+    if computed_hash != entry.event_hash {
+        anyhow::bail!(
+            "stored payload of audit event {} hashes to {}, not {}",
+            entry.event_id,
+            computed_hash,
+            entry.event_hash
+        );
+    }
+    Ok((event, payload))
+}
+
+/// Returns the backend the document was inserted into
+async fn insert_document(state: &AppState, entry: &OutboxEntry) -> anyhow::Result<String> {
+    let Some(sqlx::types::Json(event)) = &entry.document else {
+        anyhow::bail!("outbox entry {} carries no document", entry.outbox_id);
+    };
+    let computed_hash = integrity::sha256_hex(&integrity::canonical_payload(event)?);
+    if computed_hash != entry.event_hash {
+        anyhow::bail!(
+            "spilled document of audit event {} hashes to {}, not {}",
+            entry.event_id,
+            computed_hash,
+            entry.event_hash
+        );
+    }
+    // The batch it was spilled from may have been partly inserted
+    if state.documents.get(entry.event_id).await?.is_none() {
+        state.documents.insert(event).await?;
+    }
+    state.cache.invalidate_event(entry.tenant_id, entry.event_id).await;
+    Ok(state.documents.backend().to_string())
+}
+
+async fn pin(state: &AppState, entry: &OutboxEntry) -> anyhow::Result<String> {
+    let (event, payload) = verified_event(state, entry).await?;
+    let copy = match &event.ipfs_hash {
+        // Written by an earlier attempt that failed to record its result; an encrypted or
+        // compressed copy is stored again instead, as how it was stored is not known
+        Some(cid) if state.copies.stores_verbatim(payload.len()) => StoredCopy {
+            cid: cid.clone(),
+            data_key_id: None,
+            codec: None,
+            size_bytes: payload.len(),
+        },
+        _ => state
+            .copies
+            .put(&event, &payload)
+            .await
+            .map_err(|e| anyhow::anyhow!("IPFS pin failed: {}", e))?
+            .ok_or_else(|| anyhow::anyhow!("IPFS pin failed: AUDIT_ANCHOR_STORE is none"))?,
+    };
+
+    state.documents.update(entry.event_id, DocumentUpdate::IpfsHash(&copy.cid)).await?;
+    let mut conn = state.db.acquire().await?;
+    state.pins.record(&mut conn, entry.tenant_id, entry.event_id, &copy).await?;
+    state.cache.invalidate_event(entry.tenant_id, entry.event_id).await;
+    Ok(copy.cid)
+}
+
+/// Returns the token's serial number
+async fn timestamp(state: &AppState, entry: &OutboxEntry) -> anyhow::Result<String> {
+    let tsa = state
+        .tsa
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("timestamping failed: AUDIT_TSA_URL is not set"))?;
+    verified_event(state, entry).await?;
+    let token = tsa.timestamp(&entry.event_hash).await?;
+    let subject = tsa::Subject::Event {
+        tenant_id: entry.tenant_id,
+        event_id: entry.event_id,
+    };
+    tsa::record(&state.db, subject, &entry.event_hash, tsa.url(), &token).await?;
+    state.cache.invalidate_event(entry.tenant_id, entry.event_id).await;
+    Ok(token.serial_number)
+}
+
+pub fn spawn_anchor_worker(state: AppState, settings: OutboxSettings, pacing: AnchorPacing) {
+    tokio::spawn(async move {
+        loop {
+            let wait = match anchor_pass(&state, &settings, &pacing).await {
+                Ok(wait) => wait,
+                Err(e) => {
+                    error!("Anchoring batch run failed: {}", e);
+                    pacing.max_interval
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    });
+}
+
+/// Anchor one batch if the plan allows it; returns the wait until the next pass
+async fn anchor_pass(state: &AppState, settings: &OutboxSettings, pacing: &AnchorPacing) -> anyhow::Result<Duration> {
+    let (depth, oldest_wait_secs): (i64, Option<f64>) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), EXTRACT(EPOCH FROM NOW() - MIN(created_at))::float8
+        FROM audit_anchor_outbox
+        WHERE status = 'PENDING' AND operation = 'BLOCKCHAIN_ANCHOR' AND next_attempt_at <= NOW()
+        "#,
+    )
+    .fetch_one(&state.db)
+    .await?;
+    gauge!("audit_anchor_queue_depth", depth as f64);
+    // Pending anchor mode: entries wait for a chain instead of using up their attempts
+    let Some(blockchain) = state.blockchain_client.as_ref().filter(|_| depth > 0) else {
+        return Ok(pacing.max_interval);
+    };
+
+    let gas_price = match blockchain.gas_price_gwei().await.map_err(|e| e.to_string()) {
+        Ok(price) => {
+            gauge!("audit_anchor_gas_price_gwei", price);
+            Some(price)
+        }
+        Err(e) => {
+            warn!("Anchoring without a gas price: {}", e);
+            None
+        }
+    };
+    let oldest_wait = Duration::from_secs_f64(oldest_wait_secs.unwrap_or_default().max(0.0));
+    let plan = pacing.plan(depth, oldest_wait, gas_price);
+    if let Some(reason) = &plan.deferred {
+        counter!("audit_anchor_deferred_total", 1);
+        info!("Deferring {} blockchain anchors: {}", depth, reason);
+    }
+    if plan.batch > 0 {
+        let claimed = claim(&state.db, Operation::BlockchainAnchor, plan.batch).await?;
+        anchor_batch(state, blockchain, settings, claimed, depth, gas_price, pacing).await?;
+    }
+    Ok(plan.wait)
+}
+
+/// Anchored and failed entries of one backfill batch
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BackfillTally {
+    pub anchored: i64,
+    pub failed: i64,
+}
+
+/// PENDING anchors, due or not
+pub async fn pending_anchors(db: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM audit_anchor_outbox WHERE status = 'PENDING' AND operation = 'BLOCKCHAIN_ANCHOR'",
+    )
+    .fetch_one(db)
+    .await
+}
+
+/// Make anchors deferred at write time due now; ones already retried keep their backoff
+pub async fn release_deferred_anchors(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let released = sqlx::query(
+        r#"
+        UPDATE audit_anchor_outbox SET next_attempt_at = NOW()
+        WHERE status = 'PENDING' AND operation = 'BLOCKCHAIN_ANCHOR' AND attempts <= 1 AND next_attempt_at > NOW()
+        "#,
+    )
+    .execute(db)
+    .await?;
+    Ok(released.rows_affected())
+}
+
+/// Anchor a full batch of due anchors, ignoring gas pacing; `None` once none are due
+pub async fn backfill_batch(
+    state: &AppState,
+    settings: &OutboxSettings,
+    pacing: &AnchorPacing,
+) -> anyhow::Result<Option<BackfillTally>> {
+    let blockchain = state
+        .blockchain_client
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!(ANCHORING_DISABLED))?;
+    let depth = pending_anchors(&state.db).await?;
+    let claimed = claim(&state.db, Operation::BlockchainAnchor, pacing.max_batch).await?;
+    if claimed.is_empty() {
+        return Ok(None);
+    }
+    let claimed_count = claimed.len() as i64;
+    let gas_price = blockchain.gas_price_gwei().await.ok();
+    let anchored = anchor_batch(state, blockchain, settings, claimed, depth, gas_price, pacing).await?;
+    Ok(Some(BackfillTally { anchored, failed: claimed_count - anchored }))
+}
+
+/// Anchor the entries' hashes under one Merkle root and hand every event the transaction
+///
+/// Returns how many entries ended up anchored.
+async fn anchor_batch(
+    state: &AppState,
+    blockchain: &BlockchainClient,
+    settings: &OutboxSettings,
+    claimed: Vec<OutboxEntry>,
+    depth: i64,
+    gas_price: Option<f64>,
+    pacing: &AnchorPacing,
+) -> anyhow::Result<i64> {
+    let mut anchored = 0;
+    let mut leaves = Vec::with_capacity(claimed.len());
+    for entry in claimed {
+        match verified_event(state, &entry).await {
+            // Anchored by an earlier attempt that failed to record its result
+            Ok((AuditEvent { blockchain_hash: Some(anchor), .. }, _)) => {
+                record(state, settings, &entry, Ok(anchor)).await?;
+                anchored += 1;
+            }
+            Ok(_) => leaves.push(entry),
+            Err(e) => record(state, settings, &entry, Err(e)).await?,
+        }
+    }
+    if leaves.is_empty() {
+        return Ok(anchored);
+    }
+
+    let hashes: Vec<String> = leaves.iter().map(|entry| entry.event_hash.clone()).collect();
+    let merkle_root = hex::encode(digest::merkle_root(&hashes)?);
+    let transaction = match blockchain.store_audit_hash(&merkle_root).await.map_err(|e| e.to_string()) {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            for entry in &leaves {
+                record(state, settings, entry, Err(anyhow::anyhow!("blockchain anchoring failed: {}", e))).await?;
+            }
+            return Ok(anchored);
+        }
+    };
+
+    let cost = gas_price.map(|price| price * pacing.gas_per_anchor);
+    let batch_id: Uuid = sqlx::query_scalar(
+        r#"
+        INSERT INTO audit_anchor_batches (merkle_root, transaction_hash, event_count, queue_depth, gas_price_gwei, cost_gwei)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING batch_id
+        "#,
+    )
+    .bind(&merkle_root)
+    .bind(&transaction)
+    .bind(leaves.len() as i32)
+    .bind(depth as i32)
+    .bind(gas_price)
+    .bind(cost)
+    .fetch_one(&state.db)
+    .await?;
+
+    histogram!("audit_anchor_batch_size", leaves.len() as f64);
+    if let Some(cost) = cost {
+        counter!("audit_anchor_cost_gwei_total", cost.round() as u64);
+        histogram!("audit_anchor_cost_per_event_gwei", cost / leaves.len() as f64);
+    }
+
+    for (leaf_index, entry) in leaves.iter().enumerate() {
+        let outcome = async {
+            state
+                .documents
+                .update(entry.event_id, DocumentUpdate::BlockchainHash(&transaction))
+                .await?;
+            state.cache.invalidate_event(entry.tenant_id, entry.event_id).await;
+            sqlx::query("UPDATE audit_anchor_outbox SET batch_id = $2, leaf_index = $3 WHERE outbox_id = $1")
+                .bind(entry.outbox_id)
+                .bind(batch_id)
+                .bind(leaf_index as i32)
+                .execute(&state.db)
+                .await?;
+            Ok::<_, anyhow::Error>(transaction.clone())
+        }
+        .await;
+        anchored += outcome.is_ok() as i64;
+        record(state, settings, entry, outcome).await?;
+    }
+    info!(
+        "Anchored {} audit events under root {} in transaction {}",
+        leaves.len(),
+        merkle_root,
+        transaction
+    );
+    Ok(anchored)
+}
+
+async fn record(
+    state: &AppState,
+    settings: &OutboxSettings,
+    entry: &OutboxEntry,
+    outcome: anyhow::Result<String>,
+) -> anyhow::Result<()> {
+    let error_message = match outcome {
+        Ok(result) => {
+            sqlx::query(
+                r#"
+                UPDATE audit_anchor_outbox
+                SET status = 'DONE', result = $2, last_error = NULL, completed_at = NOW()
+                WHERE outbox_id = $1
+                "#,
+            )
+            .bind(entry.outbox_id)
+            .bind(&result)
+            .execute(&state.db)
+            .await?;
+            counter!("audit_anchor_outbox_completed_total", 1, "operation" => entry.operation.clone());
+            info!(
+                "Completed {} of audit event {} after {} attempts",
+                entry.operation, entry.event_id, entry.attempts
+            );
+            return Ok(());
+        }
+        Err(e) => format!("{:#}", e),
+    };
+
+    if entry.attempts >= settings.max_attempts {
+        sqlx::query("UPDATE audit_anchor_outbox SET status = 'DEAD', last_error = $2 WHERE outbox_id = $1")
+            .bind(entry.outbox_id)
+            .bind(&error_message)
+            .execute(&state.db)
+            .await?;
+        raise_ops_alert(&state.db, entry, &error_message).await?;
+        counter!("audit_anchor_outbox_dead_total", 1, "operation" => entry.operation.clone());
+        error!(
+            "Gave up on {} of audit event {} after {} attempts: {}",
+            entry.operation, entry.event_id, entry.attempts, error_message
+        );
+        return Ok(());
+    }
+
+    let delay = (RETRY_BASE_SECS * 2f64.powi(entry.attempts - 1)).min(RETRY_MAX_SECS);
+    sqlx::query(
+        r#"
+        UPDATE audit_anchor_outbox
+        SET last_error = $2, next_attempt_at = NOW() + make_interval(secs => $3)
+        WHERE outbox_id = $1
+        "#,
+    )
+    .bind(entry.outbox_id)
+    .bind(&error_message)
+    .bind(delay)
+    .execute(&state.db)
+    .await?;
+    warn!(
+        "{} of audit event {} failed (attempt {}), retrying in {}s: {}",
+        entry.operation, entry.event_id, entry.attempts, delay, error_message
+    );
+    Ok(())
+}
+
+async fn raise_ops_alert(db: &PgPool, entry: &OutboxEntry, error_message: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO system_events (event_type, severity, source_system, message, details, correlation_id)
+        VALUES ('ANCHORING_ABANDONED', 'ERROR', 'audit-service', $1, $2, $3)
+        "#,
+    )
+    .bind(format!(
+        "{} of audit event {} failed {} times: {}",
+        entry.operation, entry.event_id, entry.attempts, error_message
+    ))
+    .bind(serde_json::to_value(entry).unwrap_or_default())
+    .bind(entry.event_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+async fn export_backlog(db: &PgPool) -> Result<(), sqlx::Error> {
+    let mut rows = sqlx::query_as::<_, (String, String, i64)>(
+        r#"
+        SELECT o.operation, s.status, COUNT(a.outbox_id)
+        FROM (VALUES ('IPFS_PIN'), ('BLOCKCHAIN_ANCHOR'), ('TSA_TIMESTAMP'), ('DOCUMENT_INSERT')) AS o(operation)
+        CROSS JOIN (VALUES ('PENDING'), ('DEAD')) AS s(status)
+        LEFT JOIN audit_anchor_outbox a ON a.operation = o.operation AND a.status = s.status
+        GROUP BY o.operation, s.status
+        "#,
+    )
+    .fetch(db);
+    while let Some((operation, status, count)) = rows.try_next().await? {
+        gauge!("audit_anchor_outbox_entries", count as f64, "operation" => operation, "status" => status);
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+//! Monthly partition maintenance of audit_logs
+//!
+//! audit_logs is range-partitioned by UTC month of `timestamp` (migration
+//! 035). A worker keeps partitions for the current month and the next
+//! `premake_months` in place, so inserts never fall through to the default
+//! partition. Rows leave Postgres through cold storage archival, which
+//! deletes them once their archive is written; a month old enough to be past
+//! `detach_after_months` that archival has emptied is detached and dropped,
+//! which is cheaper than vacuuming the deleted rows. Months that still hold
+//! rows under a tenant's online retention stay attached. Partition counts
+//! per status are exported as `audit_log_partitions` and rows in the default
+//! partition as `audit_logs_default_rows`.
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use metrics::gauge;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+
+/// Partition that receives rows outside every monthly range
+const DEFAULT_PARTITION: &str = "audit_logs_default";
+/// How long a drop waits for the exclusive lock on audit_logs before giving up until the next run
+const LOCK_TIMEOUT: &str = "5s";
+
+#[derive(Debug, Clone)]
+pub struct PartitionSettings {
+    pub interval: Duration,
+    /// Months after the current one that always have a partition
+    pub premake_months: i32,
+    /// Months a partition must have ended before an emptied one is dropped
+    pub detach_after_months: i32,
+}
+
+impl PartitionSettings {
+    pub fn from_env() -> Self {
+        Self {
+            interval: Duration::from_secs(
+                std::env::var("AUDIT_PARTITION_MAINTENANCE_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(3600),
+            ),
+            premake_months: std::env::var("AUDIT_PARTITION_PREMAKE_MONTHS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(3)
+                .max(1),
+            detach_after_months: std::env::var("AUDIT_PARTITION_DETACH_AFTER_MONTHS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(12)
+                .max(1),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct AuditPartition {
+    pub partition_name: String,
+    pub range_start: DateTime<Utc>,
+    pub range_end: DateTime<Utc>,
+    /// ATTACHED or DROPPED
+    pub status: String,
+    /// Planner estimate; absent once dropped
+    pub estimated_rows: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub dropped_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct MaintenanceSummary {
+    pub partitions_created: i32,
+    pub partitions_dropped: usize,
+    /// Past the detach age but still holding rows under online retention
+    pub partitions_retained: usize,
+    pub default_partition_rows: i64,
+}
+
+pub fn spawn_worker(db: PgPool, settings: PartitionSettings) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(settings.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match maintain(&db, &settings).await {
+                Ok(summary) if summary.partitions_created > 0 || summary.partitions_dropped > 0 => {
+                    info!("Audit partition maintenance finished: {:?}", summary)
+                }
+                Ok(_) => {}
+                Err(e) => error!("Audit partition maintenance failed: {}", e),
+            }
+            if let Err(e) = export_partitions(&db).await {
+                warn!("Failed to export audit partition counts: {}", e);
+            }
+        }
+    });
+}
+
+pub async fn list_partitions(db: &PgPool) -> Result<Vec<AuditPartition>, sqlx::Error> {
+    sqlx::query_as::<_, AuditPartition>(
+        r#"
+        SELECT p.partition_name, p.range_start, p.range_end, p.status,
+               GREATEST(c.reltuples, 0)::bigint AS estimated_rows, p.created_at, p.dropped_at
+        FROM audit_log_partitions p
+        LEFT JOIN pg_class c ON c.oid = to_regclass(p.partition_name)
+        ORDER BY p.range_start DESC
+        "#,
+    )
+    .fetch_all(db)
+    .await
+}
+
+/// Create the partitions ahead and drop old ones that archival has emptied
+pub async fn maintain(db: &PgPool, settings: &PartitionSettings) -> Result<MaintenanceSummary, sqlx::Error> {
+    let mut summary = MaintenanceSummary {
+        partitions_created: sqlx::query_scalar("SELECT audit_logs_ensure_partitions($1)")
+            .bind(settings.premake_months)
+            .fetch_one(db)
+            .await?,
+        ..Default::default()
+    };
+
+    let expired: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT partition_name FROM audit_log_partitions
+        WHERE status = 'ATTACHED'
+          AND range_end <= (date_trunc('month', NOW() AT TIME ZONE 'UTC') - make_interval(months => $1)) AT TIME ZONE 'UTC'
+        ORDER BY range_start
+        "#,
+    )
+    .bind(settings.detach_after_months)
+    .fetch_all(db)
+    .await?;
+
+    for partition in expired {
+        match drop_if_empty(db, &partition).await {
+            Ok(true) => {
+                summary.partitions_dropped += 1;
+                info!("Dropped audit partition {} emptied by archival", partition);
+            }
+            Ok(false) => summary.partitions_retained += 1,
+            Err(e) => warn!("Failed to drop audit partition {}: {}", partition, e),
+        }
+    }
+
+    summary.default_partition_rows = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", DEFAULT_PARTITION))
+        .fetch_one(db)
+        .await?;
+    if summary.default_partition_rows > 0 {
+        // Such rows would block creating a partition for their month
+        warn!(
+            "{} audit rows are outside every monthly partition and sit in {}",
+            summary.default_partition_rows, DEFAULT_PARTITION
+        );
+    }
+
+    Ok(summary)
+}
+
+/// Detach and drop a partition that holds no rows; `false` when it still does
+async fn drop_if_empty(db: &PgPool, partition: &str) -> Result<bool, sqlx::Error> {
+    let table = quote_ident(partition);
+    let has_rows = format!("SELECT EXISTS (SELECT 1 FROM {})", table);
+
+    // Cheap check first, so partitions still in use never wait for the lock
+    let occupied: bool = sqlx::query_scalar(&has_rows).fetch_one(db).await?;
+    if occupied {
+        return Ok(false);
+    }
+
+    let mut tx = db.begin().await?;
+    sqlx::query(&format!("SET LOCAL lock_timeout = '{}'", LOCK_TIMEOUT))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&format!("ALTER TABLE audit_logs DETACH PARTITION {}", table))
+        .execute(&mut *tx)
+        .await?;
+    // Detaching locks audit_logs, so nothing can slip in between this check and the drop
+    let occupied: bool = sqlx::query_scalar(&has_rows).fetch_one(&mut *tx).await?;
+    if occupied {
+        tx.rollback().await?;
+        return Ok(false);
+    }
+    sqlx::query(&format!("DROP TABLE {}", table)).execute(&mut *tx).await?;
+    sqlx::query("UPDATE audit_log_partitions SET status = 'DROPPED', dropped_at = NOW() WHERE partition_name = $1")
+        .bind(partition)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+async fn export_partitions(db: &PgPool) -> Result<(), sqlx::Error> {
+    let mut rows = sqlx::query_as::<_, (String, i64)>(
+        r#"
+        SELECT s.status, COUNT(p.partition_name)
+        FROM (VALUES ('ATTACHED'), ('DROPPED')) AS s(status)
+        LEFT JOIN audit_log_partitions p ON p.status = s.status
+        GROUP BY s.status
+        "#,
+    )
+    .fetch(db);
+    while let Some((status, count)) = rows.try_next().await? {
+        gauge!("audit_log_partitions", count as f64, "status" => status);
+    }
+    drop(rows);
+
+    let default_rows: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", DEFAULT_PARTITION))
+        .fetch_one(db)
+        .await?;
+    gauge!("audit_logs_default_rows", default_rows as f64);
+    Ok(())
+}
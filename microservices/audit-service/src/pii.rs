@@ -0,0 +1,508 @@
+//! Crypto-shredding of personal data in audit events
+//!
+//! Before an event is hashed, its client IP, user agent and any configured
+//! PII keys inside old_values/new_values are sealed with AES-256-GCM under a
+//! data key belonging to the data subject: the acting user for request
+//! metadata, and the resource itself for values of person-like resources
+//! (AUDIT_PII_SUBJECT_RESOURCE_TYPES). Data keys are wrapped with
+//! AUDIT_PII_MASTER_KEY and stored in audit_subject_keys. A tenant can seal
+//! further keys, listed in audit_pii_tenant_fields once a `discovery` finding
+//! is enforced.
+//!
+//! The hash covers the sealed form, so erasing a subject only destroys its
+//! key: every copy of the event (Postgres, MongoDB, IPFS, archives) keeps the
+//! same bytes and still verifies, but the personal data can no longer be
+//! decrypted. Reads reveal sealed fields while the key exists and show
+//! `[erased]` afterwards. Sealed IP addresses are not matched by the trail's
+//! ip_address filter.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::AuditEvent;
+
+const SEALED_PREFIX: &str = "pii:v1:";
+
+/// Shown in place of a sealed field whose subject has been erased
+pub const ERASED: &str = "[erased]";
+
+const NONCE_LEN: usize = 12;
+
+const DEFAULT_PII_FIELDS: &str =
+    "name,first_name,last_name,full_name,email,phone,mobile,pan,pan_number,aadhaar,address,date_of_birth,dob,bank_account";
+
+#[derive(Debug, Error)]
+pub enum PiiError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("invalid AUDIT_PII_MASTER_KEY: {0}")]
+    MasterKey(String),
+    #[error("failed to {0} personal data")]
+    Crypto(&'static str),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+}
+
+#[derive(Deserialize)]
+pub struct ErasureRequest {
+    pub requested_by: Option<Uuid>,
+    pub reason: String,
+    pub regulatory_reference: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Erasure {
+    pub erasure_id: Uuid,
+    pub tenant_id: Uuid,
+    pub subject_id: Uuid,
+    pub keys_shredded: i32,
+    pub requested_by: Option<Uuid>,
+    pub reason: String,
+    pub regulatory_reference: Option<String>,
+    pub erased_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+struct CachedKey {
+    cipher: Option<Aes256Gcm>,
+    loaded_at: Instant,
+}
+
+pub struct PiiVault {
+    master: Aes256Gcm,
+    master_key_id: String,
+    /// Keys in old_values/new_values (at any depth) that hold personal data
+    fields: HashSet<String>,
+    /// Keys each tenant seals on top of `fields`, with when they were loaded
+    tenant_fields: RwLock<HashMap<Uuid, (Arc<HashSet<String>>, Instant)>>,
+    subject_resource_types: HashSet<String>,
+    /// Other instances learn of an erasure when their cached key expires
+    cache_ttl: Duration,
+    /// Live key id per (tenant, subject)
+    live_keys: RwLock<HashMap<(Uuid, Uuid), Uuid>>,
+    /// Data keys by key id; `None` once shredded
+    keys: RwLock<HashMap<Uuid, CachedKey>>,
+}
+
+impl PiiVault {
+    /// Build the vault from AUDIT_PII_*; `None` when no master key is configured
+    pub fn from_env() -> Result<Option<Self>, PiiError> {
+        let master_key = match std::env::var("AUDIT_PII_MASTER_KEY") {
+            Ok(key) if !key.is_empty() => key,
+            _ => return Ok(None),
+        };
+        let master_key = hex::decode(master_key.trim()).map_err(|e| PiiError::MasterKey(e.to_string()))?;
+        if master_key.len() != 32 {
+            return Err(PiiError::MasterKey("expected 32 bytes of hex".to_string()));
+        }
+
+        Ok(Some(Self {
+            master: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&master_key)),
+            master_key_id: std::env::var("AUDIT_PII_MASTER_KEY_ID").unwrap_or_else(|_| "primary".to_string()),
+            fields: tagged_fields(),
+            tenant_fields: RwLock::new(HashMap::new()),
+            subject_resource_types: env_list("AUDIT_PII_SUBJECT_RESOURCE_TYPES", "USER,CLIENT"),
+            cache_ttl: Duration::from_secs(
+                std::env::var("AUDIT_PII_KEY_CACHE_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(60),
+            ),
+            live_keys: RwLock::new(HashMap::new()),
+            keys: RwLock::new(HashMap::new()),
+        }))
+    }
+
+    /// Seal the event's personal data in place; must run before the event is hashed
+    pub async fn seal(&self, db: &PgPool, event: &mut AuditEvent) -> Result<(), PiiError> {
+        let aad = event.event_id.as_bytes().to_vec();
+
+        if let Some(actor) = event.user_id {
+            if event.ip_address.is_some() || event.user_agent.is_some() {
+                let (key_id, cipher) = self.live_key(db, event.tenant_id, actor).await?;
+                for field in [&mut event.ip_address, &mut event.user_agent] {
+                    if let Some(value) = field.take() {
+                        *field = Some(seal_value(&cipher, key_id, &aad, &serde_json::Value::String(value))?);
+                    }
+                }
+            }
+        }
+
+        // Values of person-like resources belong to that person, anything else to the actor
+        let subject = match event.resource_id {
+            Some(resource_id) if self.subject_resource_types.contains(&event.resource_type) => Some(resource_id),
+            _ => event.user_id,
+        };
+        if let Some(subject) = subject {
+            let extra = self.tenant_fields(db, event.tenant_id).await?;
+            let has_pii = [&event.old_values, &event.new_values]
+                .into_iter()
+                .flatten()
+                .any(|values| self.contains_pii(&extra, values));
+            if has_pii {
+                let (key_id, cipher) = self.live_key(db, event.tenant_id, subject).await?;
+                for values in [&mut event.old_values, &mut event.new_values].into_iter().flatten() {
+                    self.seal_values(&extra, &cipher, key_id, &aad, values)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_pii_key(&self, extra: &HashSet<String>, key: &str) -> bool {
+        self.fields.contains(key) || extra.contains(key)
+    }
+
+    fn contains_pii(&self, extra: &HashSet<String>, value: &serde_json::Value) -> bool {
+        match value {
+            serde_json::Value::Object(map) => map.iter().any(|(key, value)| {
+                (self.is_pii_key(extra, key) && !value.is_null()) || self.contains_pii(extra, value)
+            }),
+            serde_json::Value::Array(items) => items.iter().any(|item| self.contains_pii(extra, item)),
+            _ => false,
+        }
+    }
+
+    fn seal_values(
+        &self,
+        extra: &HashSet<String>,
+        cipher: &Aes256Gcm,
+        key_id: Uuid,
+        aad: &[u8],
+        value: &mut serde_json::Value,
+    ) -> Result<(), PiiError> {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if self.is_pii_key(extra, key) && !value.is_null() {
+                        *value = serde_json::Value::String(seal_value(cipher, key_id, aad, value)?);
+                    } else {
+                        self.seal_values(extra, cipher, key_id, aad, value)?;
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.seal_values(extra, cipher, key_id, aad, item)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Keys the tenant seals beyond AUDIT_PII_FIELDS; other instances pick up changes within the key cache TTL
+    async fn tenant_fields(&self, db: &PgPool, tenant_id: Uuid) -> Result<Arc<HashSet<String>>, PiiError> {
+        if let Some((fields, loaded_at)) = self.tenant_fields.read().expect("pii field cache poisoned").get(&tenant_id) {
+            if loaded_at.elapsed() < self.cache_ttl {
+                return Ok(fields.clone());
+            }
+        }
+        let fields: Arc<HashSet<String>> = Arc::new(
+            sqlx::query_scalar::<_, String>("SELECT field_name FROM audit_pii_tenant_fields WHERE tenant_id = $1")
+                .bind(tenant_id)
+                .fetch_all(db)
+                .await?
+                .into_iter()
+                .collect(),
+        );
+        self.tenant_fields
+            .write()
+            .expect("pii field cache poisoned")
+            .insert(tenant_id, (fields.clone(), Instant::now()));
+        Ok(fields)
+    }
+
+    /// Drop the cached tenant fields so a newly enforced field applies to the next event
+    pub fn forget_tenant_fields(&self, tenant_id: Uuid) {
+        self.tenant_fields.write().expect("pii field cache poisoned").remove(&tenant_id);
+    }
+
+    /// Decrypt sealed fields for display; fields of erased subjects become `[erased]`
+    ///
+    /// Only for responses: the revealed event no longer matches its hash.
+    pub async fn reveal(&self, db: &PgPool, event: &mut AuditEvent) -> Result<(), PiiError> {
+        let aad = event.event_id.as_bytes().to_vec();
+        for field in [&mut event.ip_address, &mut event.user_agent] {
+            if let Some(sealed) = field.as_deref().and_then(parse_sealed) {
+                *field = Some(match self.open(db, sealed.0, &aad, &sealed.1).await? {
+                    Some(serde_json::Value::String(value)) => value,
+                    Some(other) => other.to_string(),
+                    None => ERASED.to_string(),
+                });
+            }
+        }
+        for values in [&mut event.old_values, &mut event.new_values].into_iter().flatten() {
+            self.reveal_values(db, &aad, values).await?;
+        }
+        Ok(())
+    }
+
+    /// Reveal sealed personal data inside `value`, all of it sealed by event `event_id`
+    pub async fn reveal_sealed_by(&self, db: &PgPool, event_id: Uuid, value: &mut serde_json::Value) -> Result<(), PiiError> {
+        self.reveal_values(db, event_id.as_bytes(), value).await
+    }
+
+    async fn reveal_values(&self, db: &PgPool, aad: &[u8], value: &mut serde_json::Value) -> Result<(), PiiError> {
+        // Iterative walk; async recursion would need boxing
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            match value {
+                serde_json::Value::String(text) => {
+                    if let Some((key_id, sealed)) = parse_sealed(text) {
+                        *value = self
+                            .open(db, key_id, aad, &sealed)
+                            .await?
+                            .unwrap_or_else(|| serde_json::Value::String(ERASED.to_string()));
+                    }
+                }
+                serde_json::Value::Object(map) => pending.extend(map.values_mut()),
+                serde_json::Value::Array(items) => pending.extend(items.iter_mut()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn open(&self, db: &PgPool, key_id: Uuid, aad: &[u8], sealed: &[u8]) -> Result<Option<serde_json::Value>, PiiError> {
+        let Some(cipher) = self.key(db, key_id).await? else {
+            return Ok(None);
+        };
+        if sealed.len() < NONCE_LEN {
+            return Err(PiiError::Crypto("decrypt"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+            .map_err(|_| PiiError::Crypto("decrypt"))?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    /// Data key by id, or `None` once shredded
+    async fn key(&self, db: &PgPool, key_id: Uuid) -> Result<Option<Aes256Gcm>, PiiError> {
+        if let Some(cached) = self.keys.read().expect("pii key cache poisoned").get(&key_id) {
+            if cached.loaded_at.elapsed() < self.cache_ttl {
+                return Ok(cached.cipher.clone());
+            }
+        }
+
+        let row: Option<(Uuid, Uuid, Option<Vec<u8>>)> =
+            sqlx::query_as("SELECT tenant_id, subject_id, wrapped_key FROM audit_subject_keys WHERE key_id = $1")
+                .bind(key_id)
+                .fetch_optional(db)
+                .await?;
+        let cipher = match row {
+            Some((tenant_id, subject_id, Some(wrapped))) => Some(self.unwrap_key(tenant_id, subject_id, &wrapped)?),
+            // An unknown key is treated like a shredded one
+            _ => None,
+        };
+        self.keys.write().expect("pii key cache poisoned").insert(
+            key_id,
+            CachedKey {
+                cipher: cipher.clone(),
+                loaded_at: Instant::now(),
+            },
+        );
+        Ok(cipher)
+    }
+
+    /// The subject's current data key, created on first use
+    async fn live_key(&self, db: &PgPool, tenant_id: Uuid, subject_id: Uuid) -> Result<(Uuid, Aes256Gcm), PiiError> {
+        let cached = self.live_keys.read().expect("pii key cache poisoned").get(&(tenant_id, subject_id)).copied();
+        if let Some(key_id) = cached {
+            if let Some(cipher) = self.key(db, key_id).await? {
+                return Ok((key_id, cipher));
+            }
+        }
+
+        let existing: Option<(Uuid, Vec<u8>)> = sqlx::query_as(
+            r#"
+            SELECT key_id, wrapped_key FROM audit_subject_keys
+            WHERE tenant_id = $1 AND subject_id = $2 AND shredded_at IS NULL
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(subject_id)
+        .fetch_optional(db)
+        .await?;
+
+        let (key_id, cipher) = match existing {
+            Some((key_id, wrapped)) => (key_id, self.unwrap_key(tenant_id, subject_id, &wrapped)?),
+            None => {
+                let data_key = Aes256Gcm::generate_key(OsRng);
+                let wrapped = self.wrap_key(tenant_id, subject_id, &data_key)?;
+                let inserted: Option<Uuid> = sqlx::query_scalar(
+                    r#"
+                    INSERT INTO audit_subject_keys (tenant_id, subject_id, wrapped_key, master_key_id)
+                    VALUES ($1, $2, $3, $4)
+                    ON CONFLICT (tenant_id, subject_id) WHERE shredded_at IS NULL DO NOTHING
+                    RETURNING key_id
+                    "#,
+                )
+                .bind(tenant_id)
+                .bind(subject_id)
+                .bind(&wrapped)
+                .bind(&self.master_key_id)
+                .fetch_optional(db)
+                .await?;
+                match inserted {
+                    Some(key_id) => (key_id, Aes256Gcm::new(&data_key)),
+                    // Another writer created the key first; use theirs
+                    None => {
+                        let (key_id, wrapped): (Uuid, Vec<u8>) = sqlx::query_as(
+                            r#"
+                            SELECT key_id, wrapped_key FROM audit_subject_keys
+                            WHERE tenant_id = $1 AND subject_id = $2 AND shredded_at IS NULL
+                            "#,
+                        )
+                        .bind(tenant_id)
+                        .bind(subject_id)
+                        .fetch_one(db)
+                        .await?;
+                        (key_id, self.unwrap_key(tenant_id, subject_id, &wrapped)?)
+                    }
+                }
+            }
+        };
+
+        self.live_keys
+            .write()
+            .expect("pii key cache poisoned")
+            .insert((tenant_id, subject_id), key_id);
+        self.keys.write().expect("pii key cache poisoned").insert(
+            key_id,
+            CachedKey {
+                cipher: Some(cipher.clone()),
+                loaded_at: Instant::now(),
+            },
+        );
+        Ok((key_id, cipher))
+    }
+
+    fn wrap_key(&self, tenant_id: Uuid, subject_id: Uuid, data_key: &Key<Aes256Gcm>) -> Result<Vec<u8>, PiiError> {
+        let aad = [tenant_id.as_bytes().as_slice(), subject_id.as_bytes().as_slice()].concat();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .master
+            .encrypt(&nonce, Payload { msg: data_key.as_slice(), aad: &aad })
+            .map_err(|_| PiiError::Crypto("wrap"))?;
+        Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    fn unwrap_key(&self, tenant_id: Uuid, subject_id: Uuid, wrapped: &[u8]) -> Result<Aes256Gcm, PiiError> {
+        if wrapped.len() < NONCE_LEN {
+            return Err(PiiError::Crypto("unwrap"));
+        }
+        let aad = [tenant_id.as_bytes().as_slice(), subject_id.as_bytes().as_slice()].concat();
+        let (nonce, ciphertext) = wrapped.split_at(NONCE_LEN);
+        let data_key = self
+            .master
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| PiiError::Crypto("unwrap"))?;
+        Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key)))
+    }
+
+    /// Destroy every live key of the subject and record the erasure
+    pub async fn erase(
+        &self,
+        db: &PgPool,
+        tenant_id: Uuid,
+        subject_id: Uuid,
+        request: &ErasureRequest,
+    ) -> Result<Erasure, PiiError> {
+        let mut tx = db.begin().await?;
+        let shredded: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            UPDATE audit_subject_keys SET wrapped_key = NULL, shredded_at = NOW()
+            WHERE tenant_id = $1 AND subject_id = $2 AND shredded_at IS NULL
+            RETURNING key_id
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(subject_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let erasure = sqlx::query_as::<_, Erasure>(
+            r#"
+            INSERT INTO audit_erasures (tenant_id, subject_id, keys_shredded, requested_by, reason, regulatory_reference)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(subject_id)
+        .bind(shredded.len() as i32)
+        .bind(request.requested_by)
+        .bind(request.reason.trim())
+        .bind(&request.regulatory_reference)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        self.live_keys
+            .write()
+            .expect("pii key cache poisoned")
+            .remove(&(tenant_id, subject_id));
+        let mut keys = self.keys.write().expect("pii key cache poisoned");
+        for key_id in shredded {
+            keys.remove(&key_id);
+        }
+        Ok(erasure)
+    }
+}
+
+pub async fn list_erasures(db: &PgPool, tenant_id: Uuid, subject_id: Uuid) -> Result<Vec<Erasure>, sqlx::Error> {
+    sqlx::query_as::<_, Erasure>(
+        "SELECT * FROM audit_erasures WHERE tenant_id = $1 AND subject_id = $2 ORDER BY erased_at DESC",
+    )
+    .bind(tenant_id)
+    .bind(subject_id)
+    .fetch_all(db)
+    .await
+}
+
+fn seal_value(cipher: &Aes256Gcm, key_id: Uuid, aad: &[u8], value: &serde_json::Value) -> Result<String, PiiError> {
+    let plaintext = serde_json::to_vec(value)?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: &plaintext, aad })
+        .map_err(|_| PiiError::Crypto("encrypt"))?;
+    Ok(format!(
+        "{}{}:{}",
+        SEALED_PREFIX,
+        key_id,
+        STANDARD.encode([nonce.as_slice(), ciphertext.as_slice()].concat())
+    ))
+}
+
+/// Key id and nonce-prefixed ciphertext of a sealed value
+fn parse_sealed(value: &str) -> Option<(Uuid, Vec<u8>)> {
+    let (key_id, sealed) = value.strip_prefix(SEALED_PREFIX)?.split_once(':')?;
+    Some((key_id.parse().ok()?, STANDARD.decode(sealed).ok()?))
+}
+
+/// Keys sealed for every tenant, from AUDIT_PII_FIELDS
+pub fn tagged_fields() -> HashSet<String> {
+    env_list("AUDIT_PII_FIELDS", DEFAULT_PII_FIELDS)
+}
+
+fn env_list(name: &str, default: &str) -> HashSet<String> {
+    std::env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(|entry| entry.trim().to_string())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+pub fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
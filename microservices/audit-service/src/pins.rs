@@ -0,0 +1,515 @@
+//! IPFS pin lifecycle of audit documents
+//!
+//! Every stored document is pinned explicitly on the service's IPFS node and
+//! tracked in ipfs_pins. When IPFS_REMOTE_PINNING_ENDPOINT is set the same CID
+//! is also pinned with that remote service through the IPFS Pinning Service
+//! API, so a document survives the loss of the local node. The verification
+//! job (IPFS_PIN_VERIFY_SCHEDULE) requests queued remote pins, follows their
+//! progress, and checks a batch of LOCAL pins against the node each run,
+//! re-pinning any that went missing. Retention unpins a document from every
+//! provider once its event has been archived.
+
+use chrono::{DateTime, Utc};
+use metrics::{counter, gauge};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::compression::Codec;
+use crate::copies::StoredCopy;
+use crate::store::AnchorStore;
+
+const LOCAL: &str = "LOCAL";
+
+#[derive(Debug, Clone)]
+pub struct PinSettings {
+    /// Cron expression; empty disables verification
+    pub verify_schedule: String,
+    /// Pins checked per provider and run
+    pub verify_batch: i64,
+}
+
+impl PinSettings {
+    pub fn from_env() -> Self {
+        Self {
+            verify_schedule: std::env::var("IPFS_PIN_VERIFY_SCHEDULE").unwrap_or_else(|_| "0 45 * * * *".to_string()),
+            verify_batch: std::env::var("IPFS_PIN_VERIFY_BATCH")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(500),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct Pin {
+    pub pin_id: Uuid,
+    pub cid: String,
+    pub tenant_id: Uuid,
+    pub event_id: Option<Uuid>,
+    pub provider: String,
+    pub status: String,
+    pub remote_request_id: Option<String>,
+    pub size_bytes: Option<i64>,
+    /// Data key the document is encrypted under; see `copies`
+    pub data_key_id: Option<Uuid>,
+    /// Codec the document is compressed with; see `compression`
+    pub codec: Option<String>,
+    pub pinned_at: Option<DateTime<Utc>>,
+    pub last_verified_at: Option<DateTime<Utc>>,
+    pub repin_count: i32,
+    pub last_error: Option<String>,
+    pub unpinned_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct VerificationSummary {
+    pub remote_requested: u64,
+    pub remote_checked: u64,
+    pub local_verified: u64,
+    pub local_repinned: u64,
+    pub failed: u64,
+}
+
+/// Client for a remote service implementing the IPFS Pinning Service API
+pub struct RemotePinning {
+    client: reqwest::Client,
+    endpoint: String,
+    token: String,
+    /// Recorded as the pin provider, e.g. pinata
+    pub name: String,
+}
+
+#[derive(Serialize)]
+struct PinRequest<'a> {
+    cid: &'a str,
+    name: String,
+    meta: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct PinStatus {
+    requestid: String,
+    /// queued, pinning, pinned or failed
+    status: String,
+}
+
+impl RemotePinning {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let endpoint = match std::env::var("IPFS_REMOTE_PINNING_ENDPOINT") {
+            Ok(endpoint) if !endpoint.is_empty() => endpoint.trim_end_matches('/').to_string(),
+            _ => return Ok(None),
+        };
+        let token = std::env::var("IPFS_REMOTE_PINNING_TOKEN")
+            .map_err(|_| anyhow::anyhow!("IPFS_REMOTE_PINNING_TOKEN must be set with IPFS_REMOTE_PINNING_ENDPOINT"))?;
+        Ok(Some(Self {
+            client: reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?,
+            endpoint,
+            token,
+            name: std::env::var("IPFS_REMOTE_PINNING_NAME")
+                .ok()
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| "REMOTE".to_string())
+                .to_uppercase(),
+        }))
+    }
+
+    async fn add(&self, pin: &Pin) -> anyhow::Result<PinStatus> {
+        let request = PinRequest {
+            cid: &pin.cid,
+            name: format!("audit-event-{}", pin.event_id.map(|id| id.to_string()).unwrap_or_default()),
+            meta: serde_json::json!({ "tenant_id": pin.tenant_id }),
+        };
+        Ok(self
+            .client
+            .post(format!("{}/pins", self.endpoint))
+            .bearer_auth(&self.token)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn status(&self, request_id: &str) -> anyhow::Result<PinStatus> {
+        Ok(self
+            .client
+            .get(format!("{}/pins/{}", self.endpoint, request_id))
+            .bearer_auth(&self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    async fn remove(&self, request_id: &str) -> anyhow::Result<()> {
+        let response = self
+            .client
+            .delete(format!("{}/pins/{}", self.endpoint, request_id))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+        // Already gone counts as removed
+        if response.status() != reqwest::StatusCode::NOT_FOUND {
+            response.error_for_status()?;
+        }
+        Ok(())
+    }
+}
+
+pub struct PinRegistry {
+    db: PgPool,
+    anchors: Arc<dyn AnchorStore>,
+    remote: Option<RemotePinning>,
+    settings: PinSettings,
+}
+
+impl PinRegistry {
+    pub fn new(db: PgPool, anchors: Arc<dyn AnchorStore>, remote: Option<RemotePinning>, settings: PinSettings) -> Self {
+        Self {
+            db,
+            anchors,
+            remote,
+            settings,
+        }
+    }
+
+    pub fn remote_name(&self) -> Option<&str> {
+        self.remote.as_ref().map(|remote| remote.name.as_str())
+    }
+
+    /// Track a document just pinned on the local node, and queue its remote pin
+    pub async fn record(
+        &self,
+        conn: &mut PgConnection,
+        tenant_id: Uuid,
+        event_id: Uuid,
+        copy: &StoredCopy,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO ipfs_pins (cid, tenant_id, event_id, provider, status, size_bytes, data_key_id, codec,
+                                   pinned_at, last_verified_at)
+            VALUES ($1, $2, $3, 'LOCAL', 'PINNED', $4, $5, $6, NOW(), NOW())
+            ON CONFLICT (cid, provider) DO NOTHING
+            "#,
+        )
+        .bind(&copy.cid)
+        .bind(tenant_id)
+        .bind(event_id)
+        .bind(copy.size_bytes as i64)
+        .bind(copy.data_key_id)
+        .bind(copy.codec.map(Codec::as_str))
+        .execute(&mut *conn)
+        .await?;
+
+        if let Some(remote) = self.remote_name() {
+            sqlx::query(
+                r#"
+                INSERT INTO ipfs_pins (cid, tenant_id, event_id, provider, status, size_bytes, data_key_id, codec)
+                VALUES ($1, $2, $3, $4, 'QUEUED', $5, $6, $7)
+                ON CONFLICT (cid, provider) DO NOTHING
+                "#,
+            )
+            .bind(&copy.cid)
+            .bind(tenant_id)
+            .bind(event_id)
+            .bind(remote)
+            .bind(copy.size_bytes as i64)
+            .bind(copy.data_key_id)
+            .bind(copy.codec.map(Codec::as_str))
+            .execute(&mut *conn)
+            .await?;
+        }
+        counter!("ipfs_pins_recorded_total", 1);
+        Ok(())
+    }
+
+    /// Remove a document from every provider; rows stay behind as UNPINNED
+    pub async fn unpin(&self, cid: &str) -> anyhow::Result<()> {
+        let pins = sqlx::query_as::<_, Pin>("SELECT * FROM ipfs_pins WHERE cid = $1 AND status <> 'UNPINNED'")
+            .bind(cid)
+            .fetch_all(&self.db)
+            .await?;
+
+        // Documents stored before pins were tracked are only on the local node
+        if pins.is_empty() {
+            return self.anchors.unpin(cid).await;
+        }
+
+        let mut first_error = None;
+        for pin in pins {
+            let outcome = if pin.provider == LOCAL {
+                self.anchors.unpin(cid).await
+            } else {
+                match (&self.remote, &pin.remote_request_id) {
+                    (Some(remote), Some(request_id)) if remote.name == pin.provider => remote.remove(request_id).await,
+                    (_, None) => Ok(()),
+                    _ => Err(anyhow::anyhow!("remote pinning service {} is not configured", pin.provider)),
+                }
+            };
+            match outcome {
+                Ok(()) => {
+                    sqlx::query("UPDATE ipfs_pins SET status = 'UNPINNED', unpinned_at = NOW() WHERE pin_id = $1")
+                        .bind(pin.pin_id)
+                        .execute(&self.db)
+                        .await?;
+                }
+                Err(e) => {
+                    sqlx::query("UPDATE ipfs_pins SET last_error = $2 WHERE pin_id = $1")
+                        .bind(pin.pin_id)
+                        .bind(e.to_string())
+                        .execute(&self.db)
+                        .await?;
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn schedule(self: Arc<Self>) -> anyhow::Result<Option<JobScheduler>> {
+        if self.settings.verify_schedule.is_empty() {
+            return Ok(None);
+        }
+        let scheduler = JobScheduler::new().await?;
+        let schedule = self.settings.verify_schedule.clone();
+        let job = Job::new_async(schedule.as_str(), move |_uuid, _l| {
+            let registry = self.clone();
+            Box::pin(async move {
+                match registry.verify().await {
+                    Ok(summary) => info!("IPFS pin verification finished: {:?}", summary),
+                    Err(e) => error!("IPFS pin verification failed: {}", e),
+                }
+            })
+        })?;
+        scheduler.add(job).await?;
+        scheduler.start().await?;
+        Ok(Some(scheduler))
+    }
+
+    pub async fn verify(&self) -> anyhow::Result<VerificationSummary> {
+        let mut summary = VerificationSummary::default();
+        if let Some(remote) = &self.remote {
+            self.request_remote(remote, &mut summary).await?;
+            self.check_remote(remote, &mut summary).await?;
+        }
+        self.check_local(&mut summary).await?;
+        self.export_counts().await?;
+        Ok(summary)
+    }
+
+    async fn due(&self, provider: &str, statuses: &[&str]) -> Result<Vec<Pin>, sqlx::Error> {
+        let statuses: Vec<String> = statuses.iter().map(|status| status.to_string()).collect();
+        sqlx::query_as::<_, Pin>(
+            r#"
+            SELECT * FROM ipfs_pins
+            WHERE provider = $1 AND status = ANY($2)
+            ORDER BY last_verified_at NULLS FIRST
+            LIMIT $3
+            "#,
+        )
+        .bind(provider)
+        .bind(&statuses)
+        .bind(self.settings.verify_batch)
+        .fetch_all(&self.db)
+        .await
+    }
+
+    async fn request_remote(&self, remote: &RemotePinning, summary: &mut VerificationSummary) -> anyhow::Result<()> {
+        for pin in self.due(&remote.name, &["QUEUED"]).await? {
+            match remote.add(&pin).await {
+                Ok(status) => {
+                    // A request the service rejects outright is retried on the next run
+                    let recorded = match remote_status(&status.status) {
+                        "FAILED" => "QUEUED",
+                        status => status,
+                    };
+                    sqlx::query(
+                        r#"
+                        UPDATE ipfs_pins
+                        SET status = $2, remote_request_id = $3, last_error = NULL, last_verified_at = NOW(),
+                            pinned_at = CASE WHEN $2 = 'PINNED' THEN NOW() ELSE pinned_at END
+                        WHERE pin_id = $1
+                        "#,
+                    )
+                    .bind(pin.pin_id)
+                    .bind(recorded)
+                    .bind(&status.requestid)
+                    .execute(&self.db)
+                    .await?;
+                    summary.remote_requested += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to request remote pin of {} with {}: {}", pin.cid, remote.name, e);
+                    self.record_failure(&pin, "QUEUED", &e.to_string()).await?;
+                    summary.failed += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_remote(&self, remote: &RemotePinning, summary: &mut VerificationSummary) -> anyhow::Result<()> {
+        for pin in self.due(&remote.name, &["PINNING", "PINNED"]).await? {
+            let Some(request_id) = pin.remote_request_id.as_deref() else {
+                continue;
+            };
+            let status = match remote.status(request_id).await {
+                Ok(status) => remote_status(&status.status),
+                Err(e) => {
+                    warn!("Failed to check remote pin of {} with {}: {}", pin.cid, remote.name, e);
+                    self.record_failure(&pin, &pin.status, &e.to_string()).await?;
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            summary.remote_checked += 1;
+            if status == "FAILED" {
+                // The service gave up; request the pin again on the next run
+                warn!("Remote pin of {} with {} failed; requeueing", pin.cid, remote.name);
+                sqlx::query(
+                    r#"
+                    UPDATE ipfs_pins
+                    SET status = 'QUEUED', remote_request_id = NULL, repin_count = repin_count + 1,
+                        last_error = 'pinning service reported the pin as failed', last_verified_at = NOW()
+                    WHERE pin_id = $1
+                    "#,
+                )
+                .bind(pin.pin_id)
+                .execute(&self.db)
+                .await?;
+                counter!("ipfs_repins_total", 1, "provider" => pin.provider.clone());
+                continue;
+            }
+            sqlx::query(
+                r#"
+                UPDATE ipfs_pins
+                SET status = $2, last_error = NULL, last_verified_at = NOW(),
+                    pinned_at = CASE WHEN $2 = 'PINNED' THEN COALESCE(pinned_at, NOW()) ELSE pinned_at END
+                WHERE pin_id = $1
+                "#,
+            )
+            .bind(pin.pin_id)
+            .bind(status)
+            .execute(&self.db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn check_local(&self, summary: &mut VerificationSummary) -> anyhow::Result<()> {
+        for pin in self.due(LOCAL, &["PINNED", "FAILED"]).await? {
+            let pinned = match self.anchors.is_pinned(&pin.cid).await {
+                Ok(pinned) => pinned,
+                Err(e) => {
+                    self.record_failure(&pin, &pin.status, &e.to_string()).await?;
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            if pinned {
+                sqlx::query(
+                    "UPDATE ipfs_pins SET status = 'PINNED', last_error = NULL, last_verified_at = NOW() WHERE pin_id = $1",
+                )
+                .bind(pin.pin_id)
+                .execute(&self.db)
+                .await?;
+                summary.local_verified += 1;
+                continue;
+            }
+
+            warn!("IPFS document {} is no longer pinned on the node; re-pinning", pin.cid);
+            match self.anchors.pin(&pin.cid).await {
+                Ok(()) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE ipfs_pins
+                        SET status = 'PINNED', repin_count = repin_count + 1, pinned_at = NOW(),
+                            last_error = NULL, last_verified_at = NOW()
+                        WHERE pin_id = $1
+                        "#,
+                    )
+                    .bind(pin.pin_id)
+                    .execute(&self.db)
+                    .await?;
+                    counter!("ipfs_repins_total", 1, "provider" => LOCAL);
+                    summary.local_repinned += 1;
+                }
+                Err(e) => {
+                    error!("Failed to re-pin IPFS document {}: {}", pin.cid, e);
+                    self.record_failure(&pin, "FAILED", &e.to_string()).await?;
+                    summary.failed += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn record_failure(&self, pin: &Pin, status: &str, error: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE ipfs_pins SET status = $2, last_error = $3, last_verified_at = NOW() WHERE pin_id = $1")
+            .bind(pin.pin_id)
+            .bind(status)
+            .bind(error)
+            .execute(&self.db)
+            .await?;
+        counter!("ipfs_pin_failures_total", 1, "provider" => pin.provider.clone());
+        Ok(())
+    }
+
+    async fn export_counts(&self) -> Result<(), sqlx::Error> {
+        let counts = sqlx::query_as::<_, (String, String, i64)>(
+            "SELECT provider, status, COUNT(*) FROM ipfs_pins WHERE status <> 'UNPINNED' GROUP BY provider, status",
+        )
+        .fetch_all(&self.db)
+        .await?;
+        for (provider, status, count) in counts {
+            gauge!("ipfs_pins", count as f64, "provider" => provider, "status" => status);
+        }
+        Ok(())
+    }
+
+    pub async fn list(
+        &self,
+        status: Option<&str>,
+        provider: Option<&str>,
+        cid: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Pin>, sqlx::Error> {
+        sqlx::query_as::<_, Pin>(
+            r#"
+            SELECT * FROM ipfs_pins
+            WHERE ($1::text IS NULL OR status = $1)
+              AND ($2::text IS NULL OR provider = $2)
+              AND ($3::text IS NULL OR cid = $3)
+            ORDER BY created_at DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(status)
+        .bind(provider)
+        .bind(cid)
+        .bind(limit)
+        .fetch_all(&self.db)
+        .await
+    }
+}
+
+/// Pinning Service API status as an ipfs_pins status
+fn remote_status(status: &str) -> &'static str {
+    match status {
+        "pinned" => "PINNED",
+        "failed" => "FAILED",
+        _ => "PINNING",
+    }
+}
@@ -0,0 +1,253 @@
+//! Per-stage latency of the audit event write path
+//!
+//! `create_audit_event` laps a [`Timer`] at each stage boundary. Finished runs
+//! are recorded three ways: as `*_ms` attributes on the `audit_pipeline` span,
+//! in the `audit_pipeline_stage_seconds` histogram labelled by stage, and in a
+//! bounded window of recent samples summarised by GET /admin/pipeline-latency.
+//! Failed writes are not recorded.
+
+use chrono::{DateTime, Utc};
+use metrics::{counter, histogram};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{warn, Span};
+use uuid::Uuid;
+
+/// Recent samples kept per stage for the summary
+const WINDOW: usize = 2048;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Actor snapshot lookup in the user service
+    Enrich,
+    /// PII sealing and envelope encryption of the values
+    Protect,
+    /// Canonical payload hash and its signature
+    Hash,
+    Ipfs,
+    /// Per-event blockchain anchoring, when not batched into the daily digest
+    Anchor,
+    /// audit_logs insert with pin records and outbox entries, in one transaction
+    Postgres,
+    Mongo,
+    /// Stream fan-out and event bus publish
+    Publish,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 8] = [
+        Stage::Enrich,
+        Stage::Protect,
+        Stage::Hash,
+        Stage::Ipfs,
+        Stage::Anchor,
+        Stage::Postgres,
+        Stage::Mongo,
+        Stage::Publish,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Enrich => "enrich",
+            Stage::Protect => "protect",
+            Stage::Hash => "hash",
+            Stage::Ipfs => "ipfs",
+            Stage::Anchor => "anchor",
+            Stage::Postgres => "postgres",
+            Stage::Mongo => "mongo",
+            Stage::Publish => "publish",
+        }
+    }
+
+    /// Span attribute holding this stage's duration
+    fn field(self) -> &'static str {
+        match self {
+            Stage::Enrich => "enrich_ms",
+            Stage::Protect => "protect_ms",
+            Stage::Hash => "hash_ms",
+            Stage::Ipfs => "ipfs_ms",
+            Stage::Anchor => "anchor_ms",
+            Stage::Postgres => "postgres_ms",
+            Stage::Mongo => "mongo_ms",
+            Stage::Publish => "publish_ms",
+        }
+    }
+}
+
+/// Stage durations of one write, measured between consecutive laps
+pub struct Timer {
+    started: Instant,
+    last: Instant,
+    stages: Vec<(Stage, Duration)>,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last: now,
+            stages: Vec::with_capacity(Stage::ALL.len()),
+        }
+    }
+
+    /// Attribute the time since the previous lap to `stage`, adding to any earlier lap of it
+    pub fn lap(&mut self, stage: Stage) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        match self.stages.iter_mut().find(|(s, _)| *s == stage) {
+            Some((_, duration)) => *duration += elapsed,
+            None => self.stages.push((stage, elapsed)),
+        }
+        self.last = now;
+    }
+
+    /// Leave the time since the previous lap out of every stage
+    pub fn skip(&mut self) {
+        self.last = Instant::now();
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StageSummary {
+    pub stage: &'static str,
+    /// Writes that ran this stage since the service started
+    pub count: u64,
+    /// Of which the most recent are summarised below
+    pub samples: usize,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PipelineSummary {
+    pub since: DateTime<Utc>,
+    pub budget_ms: u64,
+    /// Writes whose total exceeded the budget since the service started
+    pub over_budget: u64,
+    pub total: StageSummary,
+    pub stages: Vec<StageSummary>,
+}
+
+#[derive(Default)]
+struct Samples {
+    count: u64,
+    recent: VecDeque<f64>,
+}
+
+impl Samples {
+    fn push(&mut self, ms: f64) {
+        self.count += 1;
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ms);
+    }
+
+    fn summary(&self, stage: &'static str) -> StageSummary {
+        let mut sorted: Vec<f64> = self.recent.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let percentile = |p: f64| match sorted.len() {
+            0 => 0.0,
+            n => sorted[((p * n as f64).ceil() as usize).clamp(1, n) - 1],
+        };
+        StageSummary {
+            stage,
+            count: self.count,
+            samples: sorted.len(),
+            mean_ms: if sorted.is_empty() { 0.0 } else { sorted.iter().sum::<f64>() / sorted.len() as f64 },
+            p50_ms: percentile(0.50),
+            p95_ms: percentile(0.95),
+            p99_ms: percentile(0.99),
+            max_ms: sorted.last().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+struct Window {
+    stages: Vec<Samples>,
+    total: Samples,
+    over_budget: u64,
+}
+
+pub struct PipelineLatency {
+    since: DateTime<Utc>,
+    budget: Duration,
+    window: Mutex<Window>,
+}
+
+impl PipelineLatency {
+    pub fn from_env() -> Self {
+        let budget_ms = std::env::var("AUDIT_PIPELINE_BUDGET_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(500);
+        Self {
+            since: Utc::now(),
+            budget: Duration::from_millis(budget_ms),
+            window: Mutex::new(Window {
+                stages: Stage::ALL.iter().map(|_| Samples::default()).collect(),
+                total: Samples::default(),
+                over_budget: 0,
+            }),
+        }
+    }
+
+    /// Record a completed write on `span`, the histogram and the summary window
+    pub fn observe(&self, span: &Span, event_id: Uuid, timer: Timer) {
+        let total = timer.started.elapsed();
+        for (stage, duration) in &timer.stages {
+            span.record(stage.field(), duration.as_secs_f64() * 1000.0);
+            histogram!("audit_pipeline_stage_seconds", duration.as_secs_f64(), "stage" => stage.as_str());
+        }
+        span.record("total_ms", total.as_secs_f64() * 1000.0);
+        histogram!("audit_pipeline_stage_seconds", total.as_secs_f64(), "stage" => "total");
+
+        let over_budget = total > self.budget;
+        if over_budget {
+            counter!("audit_pipeline_over_budget_total", 1);
+            let breakdown: Vec<String> = timer
+                .stages
+                .iter()
+                .map(|(stage, duration)| format!("{}={}ms", stage.as_str(), duration.as_millis()))
+                .collect();
+            warn!(
+                "Audit event {} took {}ms against a {}ms budget ({})",
+                event_id,
+                total.as_millis(),
+                self.budget.as_millis(),
+                breakdown.join(", ")
+            );
+        }
+
+        let mut window = self.window.lock().expect("pipeline latency lock poisoned");
+        for (stage, duration) in &timer.stages {
+            let index = Stage::ALL.iter().position(|s| s == stage).expect("every stage is listed");
+            window.stages[index].push(duration.as_secs_f64() * 1000.0);
+        }
+        window.total.push(total.as_secs_f64() * 1000.0);
+        if over_budget {
+            window.over_budget += 1;
+        }
+    }
+
+    pub fn summary(&self) -> PipelineSummary {
+        let window = self.window.lock().expect("pipeline latency lock poisoned");
+        PipelineSummary {
+            since: self.since,
+            budget_ms: self.budget.as_millis() as u64,
+            over_budget: window.over_budget,
+            total: window.total.summary("total"),
+            stages: Stage::ALL
+                .iter()
+                .zip(&window.stages)
+                .map(|(stage, samples)| samples.summary(stage.as_str()))
+                .collect(),
+        }
+    }
+}
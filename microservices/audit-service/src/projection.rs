@@ -0,0 +1,430 @@
+//! Current state of each resource, folded from its audit events
+//!
+//! Every change to a resource flows through the audit log, so its state is
+//! the fold of its events in timestamp order: new_values are applied to the
+//! state as a JSON merge patch (objects merged key by key, null removing a
+//! key, anything else replacing the value), an event with old_values but no
+//! new_values deletes the resource, and one with neither leaves it as it was.
+//!
+//! The fold is materialized per (tenant, resource_type, resource_id) in
+//! audit_resource_states and advanced as each event is stored. A resource
+//! whose history predates the projection, or an event that arrives out of
+//! order (write-ahead log replays keep their original timestamp), drops the
+//! row instead, and the next read folds the whole history again. Rebuilds only
+//! see events still in audit_logs, not ones retention has archived.
+//! GET /audit/state/:resource_type/:resource_id returns the state, and
+//! `as_of` reconstructs it at an instant from the events up to then.
+//!
+//! Values are folded as they were written. Personal data stays sealed and is
+//! revealed on read through the event that sealed it. With envelope
+//! encryption the materialized state is encrypted under the tenant's data key
+//! and only returned to readers allowed to decrypt audit values.
+
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{PgPool, Postgres, Row, Transaction};
+use std::collections::HashMap;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::envelope::{self, Envelope, EnvelopeError, Reader};
+use crate::pii::{self, PiiError, PiiVault};
+use crate::{audit_event_from_row, AuditEvent, AUDIT_LOG_COLUMNS};
+
+#[derive(Deserialize)]
+pub struct StateParams {
+    pub tenant_id: Uuid,
+    /// Reconstruct the state at this instant rather than now
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+/// The fold of a resource's events
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Folded {
+    pub state: Option<Value>,
+    pub deleted: bool,
+    /// Event that sealed each sealed value in `state`, which is needed to reveal it
+    #[serde(default)]
+    pub sealed_by: HashMap<String, Uuid>,
+}
+
+impl Folded {
+    /// Fold one event with readable values into the state
+    pub fn apply(&mut self, event: &AuditEvent) {
+        match (&event.old_values, &event.new_values) {
+            (_, Some(new_values)) => {
+                let state = self.state.get_or_insert(Value::Null);
+                merge_patch(state, new_values);
+                self.deleted = false;
+                collect_sealed(new_values, &mut |sealed| {
+                    self.sealed_by.insert(sealed.to_string(), event.event_id);
+                });
+            }
+            (Some(_), None) => {
+                self.state = None;
+                self.deleted = true;
+            }
+            (None, None) => {}
+        }
+        // Only values still in the state need their sealing event
+        let mut live = HashMap::new();
+        if let Some(state) = &self.state {
+            collect_sealed(state, &mut |sealed| {
+                if let Some(event_id) = self.sealed_by.get(sealed) {
+                    live.insert(sealed.to_string(), *event_id);
+                }
+            });
+        }
+        self.sealed_by = live;
+    }
+}
+
+/// RFC 7386 merge patch
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().expect("target was made an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
+
+fn collect_sealed(value: &Value, found: &mut impl FnMut(&str)) {
+    match value {
+        Value::String(text) if pii::is_sealed(text) => found(text),
+        Value::Object(map) => map.values().for_each(|value| collect_sealed(value, found)),
+        Value::Array(items) => items.iter().for_each(|value| collect_sealed(value, found)),
+        _ => {}
+    }
+}
+
+/// A resource's state as returned by the API
+#[derive(Serialize, Debug, Clone)]
+pub struct ResourceState {
+    pub tenant_id: Uuid,
+    pub resource_type: String,
+    pub resource_id: Uuid,
+    /// Null once the resource is deleted, or while no event has written values
+    pub state: Option<Value>,
+    pub deleted: bool,
+    /// Events folded into the state
+    pub version: i64,
+    pub last_event_id: Uuid,
+    pub last_event_at: DateTime<Utc>,
+    /// Set on point-in-time reconstructions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    sealed_by: HashMap<String, Uuid>,
+}
+
+#[derive(Debug, Error)]
+pub enum ProjectionError {
+    #[error("no audit events for this resource")]
+    NotFound,
+    #[error("the resource's values are encrypted and the reader may not decrypt them")]
+    Forbidden,
+    #[error("event {0} has encrypted values and envelope encryption is not configured")]
+    EncryptedEvent(Uuid),
+    #[error(transparent)]
+    Envelope(#[from] EnvelopeError),
+    #[error(transparent)]
+    Pii(#[from] PiiError),
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// The resource a state belongs to
+#[derive(Debug, Clone, Copy)]
+pub struct ResourceKey<'a> {
+    pub tenant_id: Uuid,
+    pub resource_type: &'a str,
+    pub resource_id: Uuid,
+}
+
+impl ResourceKey<'_> {
+    /// Authenticated data binding an encrypted state to its resource
+    fn aad(&self) -> Vec<u8> {
+        format!("audit_resource_states:{}:{}:{}", self.tenant_id, self.resource_type, self.resource_id).into_bytes()
+    }
+
+    /// Serializes advancing and rebuilding the resource's state until the transaction ends
+    async fn lock(&self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(String::from_utf8_lossy(&self.aad()).into_owned())
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    fn state(&self, folded: Folded, version: i64, last: (Uuid, DateTime<Utc>), as_of: Option<DateTime<Utc>>) -> ResourceState {
+        ResourceState {
+            tenant_id: self.tenant_id,
+            resource_type: self.resource_type.to_string(),
+            resource_id: self.resource_id,
+            state: folded.state,
+            deleted: folded.deleted,
+            version,
+            last_event_id: last.0,
+            last_event_at: last.1,
+            as_of,
+            sealed_by: folded.sealed_by,
+        }
+    }
+}
+
+const STATE_COLUMNS: &str = "document, document_sealed, data_key_id, version, last_event_id, last_event_at";
+
+/// Where the projection reads events and keeps its rows
+pub struct Projector<'a> {
+    pub db: &'a PgPool,
+    pub envelope: Option<&'a Envelope>,
+    pub pii: Option<&'a PiiVault>,
+}
+
+impl Projector<'_> {
+    /// Advance the materialized state of the event's resource by a just-stored event
+    pub async fn advance(&self, event: &AuditEvent) -> Result<(), ProjectionError> {
+        let Some(resource_id) = event.resource_id else {
+            return Ok(());
+        };
+        let key = ResourceKey { tenant_id: event.tenant_id, resource_type: &event.resource_type, resource_id };
+        let mut tx = self.db.begin().await?;
+        key.lock(&mut tx).await?;
+
+        let (mut folded, version) = match self.load(&mut tx, &key).await? {
+            Some((_, _, (last_event_id, _))) if last_event_id == event.event_id => return Ok(()),
+            // Folding it in now would apply it after events it precedes
+            Some((_, _, last)) if (event.timestamp.trunc_subsecs(6), event.event_id) < (last.1, last.0) => {
+                invalidate(&mut *tx, &key).await?;
+                tx.commit().await?;
+                return Ok(());
+            }
+            Some((folded, version, _)) => (folded, version),
+            None => {
+                // Only a resource whose first event this is starts here; others are rebuilt on read
+                let earlier: bool = sqlx::query_scalar(
+                    r#"
+                    SELECT EXISTS (
+                        SELECT 1 FROM audit_logs
+                        WHERE tenant_id = $1 AND resource_type = $2 AND resource_id = $3 AND log_id <> $4
+                    )
+                    "#,
+                )
+                .bind(key.tenant_id)
+                .bind(key.resource_type)
+                .bind(key.resource_id)
+                .bind(event.event_id)
+                .fetch_one(&mut *tx)
+                .await?;
+                if earlier {
+                    return Ok(());
+                }
+                (Folded::default(), 0)
+            }
+        };
+
+        folded.apply(&self.readable(event.clone()).await?);
+        self.store(&mut tx, &key, &folded, version + 1, event).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Current state, folding the history again when it is not materialized
+    pub async fn current(&self, key: &ResourceKey<'_>) -> Result<ResourceState, ProjectionError> {
+        let mut tx = self.db.begin().await?;
+        if let Some((folded, version, last)) = self.load(&mut tx, key).await? {
+            return Ok(key.state(folded, version, last, None));
+        }
+        // Under the lock, so an event stored meanwhile is either folded here or advances the result
+        key.lock(&mut tx).await?;
+        if let Some((folded, version, last)) = self.load(&mut tx, key).await? {
+            return Ok(key.state(folded, version, last, None));
+        }
+        let (folded, version, last) = self.fold(key, None).await?;
+        self.store(&mut tx, key, &folded, version, &last).await?;
+        tx.commit().await?;
+        Ok(key.state(folded, version, (last.event_id, last.timestamp), None))
+    }
+
+    /// State as it was at `as_of`, from the events up to then
+    pub async fn as_of(&self, key: &ResourceKey<'_>, as_of: DateTime<Utc>) -> Result<ResourceState, ProjectionError> {
+        let (folded, version, last) = self.fold(key, Some(as_of)).await?;
+        Ok(key.state(folded, version, (last.event_id, last.timestamp), Some(as_of)))
+    }
+
+    /// Reveal sealed personal data for a response; fails for readers who may not decrypt
+    pub async fn reveal(&self, state: &mut ResourceState, reader: &Reader) -> Result<(), ProjectionError> {
+        if self.envelope.is_some() && !reader.may_decrypt(state.tenant_id) {
+            return Err(ProjectionError::Forbidden);
+        }
+        let (Some(pii), Some(value)) = (self.pii, state.state.as_mut()) else {
+            return Ok(());
+        };
+        // Each sealed value can only be opened with the event that sealed it
+        let mut pending = vec![value];
+        while let Some(value) = pending.pop() {
+            match value {
+                Value::String(text) => {
+                    if let Some(event_id) = state.sealed_by.get(text.as_str()).copied() {
+                        pii.reveal_sealed_by(self.db, event_id, value).await?;
+                    }
+                }
+                Value::Object(map) => pending.extend(map.values_mut()),
+                Value::Array(items) => pending.extend(items.iter_mut()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Fold the resource's events up to `until`; returns the fold, how many events and the last one
+    async fn fold(
+        &self,
+        key: &ResourceKey<'_>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<(Folded, i64, AuditEvent), ProjectionError> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {} FROM audit_logs
+            WHERE tenant_id = $1 AND resource_type = $2 AND resource_id = $3
+              AND ($4::timestamptz IS NULL OR timestamp <= $4)
+            ORDER BY timestamp, log_id
+            "#,
+            AUDIT_LOG_COLUMNS
+        ))
+        .bind(key.tenant_id)
+        .bind(key.resource_type)
+        .bind(key.resource_id)
+        .bind(until)
+        .fetch_all(self.db)
+        .await?;
+
+        let mut folded = Folded::default();
+        let mut last = None;
+        for row in &rows {
+            let event = self.readable(audit_event_from_row(row)).await?;
+            folded.apply(&event);
+            last = Some(event);
+        }
+        let last = last.ok_or(ProjectionError::NotFound)?;
+        Ok((folded, rows.len() as i64, last))
+    }
+
+    /// The event with its values decrypted, as the fold needs them
+    async fn readable(&self, mut event: AuditEvent) -> Result<AuditEvent, ProjectionError> {
+        if envelope::is_encrypted(&event.old_values) || envelope::is_encrypted(&event.new_values) {
+            let envelope = self.envelope.ok_or(ProjectionError::EncryptedEvent(event.event_id))?;
+            envelope.decrypt(self.db, &mut event).await?;
+        }
+        Ok(event)
+    }
+
+    /// The materialized fold, its version and last event
+    async fn load(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        key: &ResourceKey<'_>,
+    ) -> Result<Option<(Folded, i64, (Uuid, DateTime<Utc>))>, ProjectionError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM audit_resource_states WHERE tenant_id = $1 AND resource_type = $2 AND resource_id = $3",
+            STATE_COLUMNS
+        ))
+        .bind(key.tenant_id)
+        .bind(key.resource_type)
+        .bind(key.resource_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let last = (row.get("last_event_id"), row.get("last_event_at"));
+        let folded = match row.get::<Option<Value>, _>("document") {
+            Some(document) => serde_json::from_value(document)?,
+            None => {
+                let envelope = self.envelope.ok_or(ProjectionError::EncryptedEvent(last.0))?;
+                let document = envelope
+                    .decrypt_blob(
+                        self.db,
+                        key.tenant_id,
+                        row.get("data_key_id"),
+                        &key.aad(),
+                        &row.get::<Vec<u8>, _>("document_sealed"),
+                    )
+                    .await?;
+                serde_json::from_slice(&document)?
+            }
+        };
+        Ok(Some((folded, row.get("version"), last)))
+    }
+
+    async fn store(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        key: &ResourceKey<'_>,
+        folded: &Folded,
+        version: i64,
+        last: &AuditEvent,
+    ) -> Result<(), ProjectionError> {
+        let (document, sealed, key_id) = match self.envelope {
+            Some(envelope) => {
+                let (key_id, sealed) = envelope
+                    .encrypt_blob(self.db, key.tenant_id, &key.aad(), &serde_json::to_vec(folded)?)
+                    .await?;
+                (None, Some(sealed), Some(key_id))
+            }
+            None => (Some(serde_json::to_value(folded)?), None, None),
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO audit_resource_states (
+                tenant_id, resource_type, resource_id, document, document_sealed, data_key_id,
+                version, last_event_id, last_event_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (tenant_id, resource_type, resource_id) DO UPDATE
+            SET document = EXCLUDED.document, document_sealed = EXCLUDED.document_sealed,
+                data_key_id = EXCLUDED.data_key_id, version = EXCLUDED.version,
+                last_event_id = EXCLUDED.last_event_id, last_event_at = EXCLUDED.last_event_at, updated_at = NOW()
+            "#,
+        )
+        .bind(key.tenant_id)
+        .bind(key.resource_type)
+        .bind(key.resource_id)
+        .bind(document)
+        .bind(sealed)
+        .bind(key_id)
+        .bind(version)
+        .bind(last.event_id)
+        .bind(last.timestamp)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Drop a resource's materialized state, so the next read folds its history again
+pub async fn invalidate<'e>(
+    executor: impl sqlx::PgExecutor<'e>,
+    key: &ResourceKey<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM audit_resource_states WHERE tenant_id = $1 AND resource_type = $2 AND resource_id = $3")
+        .bind(key.tenant_id)
+        .bind(key.resource_type)
+        .bind(key.resource_id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
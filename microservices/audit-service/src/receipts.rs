@@ -0,0 +1,289 @@
+//! Signed write receipts for audit producers
+//!
+//! A stored event is answered with a receipt signed by the
+//! AUDIT_RECEIPT_SIGNING_KEY, so the producer holds proof that the platform
+//! accepted the event with a given hash at a given time, and where its anchor
+//! will be found. The signature is Ed25519 over the receipt's signing input,
+//! which is the `|`-separated line
+//!
+//! `dharmaguard-receipt-v1|event_id|tenant_id|event_hash|timestamp|issued_at|anchor`
+//!
+//! with timestamps as RFC 3339 UTC with microseconds and the anchor as
+//! `per_event:<transaction hash or empty>` or `daily_digest:<YYYY-MM-DD>`.
+//! Producers can check it offline against GET /audit/receipts/keys, or POST
+//! it to /audit/receipts/verify, which also checks the event still matches
+//! and reports its anchor as it stands now.
+//!
+//! Events queued in the write-ahead log get the queue receipt instead; they
+//! have no hash until they are stored.
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, SubsecRound, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::digest::{self, AnchorMode};
+use crate::integrity::VerificationCheck;
+use crate::AuditEvent;
+
+const SIGNING_PREFIX: &str = "dharmaguard-receipt-v1";
+
+/// Ed25519 key receipts are signed with, and retired keys they still verify under
+pub struct ReceiptSigner {
+    key_id: String,
+    key: SigningKey,
+    retired: HashMap<String, VerifyingKey>,
+}
+
+impl ReceiptSigner {
+    /// `None` when AUDIT_RECEIPT_SIGNING_KEY (32 bytes of hex) is unset
+    ///
+    /// AUDIT_RECEIPT_RETIRED_KEYS lists earlier keys as `key_id:public_key_hex`
+    /// pairs separated by commas, so receipts issued before a rotation still verify.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let seed = match std::env::var("AUDIT_RECEIPT_SIGNING_KEY") {
+            Ok(seed) if !seed.is_empty() => seed,
+            _ => return Ok(None),
+        };
+        let seed: [u8; 32] = hex::decode(seed.trim())?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("AUDIT_RECEIPT_SIGNING_KEY must be 32 bytes of hex"))?;
+
+        let mut retired = HashMap::new();
+        for entry in std::env::var("AUDIT_RECEIPT_RETIRED_KEYS").unwrap_or_default().split(',') {
+            let entry = entry.trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let (key_id, public_key) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("AUDIT_RECEIPT_RETIRED_KEYS entry '{}' is not key_id:public_key", entry))?;
+            let public_key: [u8; 32] = hex::decode(public_key.trim())?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("retired receipt key {} must be 32 bytes of hex", key_id))?;
+            retired.insert(key_id.trim().to_string(), VerifyingKey::from_bytes(&public_key)?);
+        }
+
+        Ok(Some(Self {
+            key_id: std::env::var("AUDIT_RECEIPT_SIGNING_KEY_ID").unwrap_or_else(|_| "receipt-1".to_string()),
+            key: SigningKey::from_bytes(&seed),
+            retired,
+        }))
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The current key first, then retired ones
+    pub fn keys(&self) -> Vec<ReceiptKey> {
+        let mut keys = vec![ReceiptKey {
+            key_id: self.key_id.clone(),
+            public_key: hex::encode(self.key.verifying_key().to_bytes()),
+            current: true,
+        }];
+        keys.extend(self.retired.iter().map(|(key_id, key)| ReceiptKey {
+            key_id: key_id.clone(),
+            public_key: hex::encode(key.to_bytes()),
+            current: false,
+        }));
+        keys
+    }
+
+    /// Receipt for an event as it was stored; `None` for an event without a hash
+    pub fn issue(&self, event: &AuditEvent, anchor_mode: AnchorMode) -> Option<AuditReceipt> {
+        let anchor = match anchor_mode {
+            AnchorMode::PerEvent => AnchorReference::PerEvent { transaction_hash: event.blockchain_hash.clone() },
+            AnchorMode::DailyDigest => AnchorReference::DailyDigest { digest_date: event.timestamp.date_naive() },
+        };
+        let mut receipt = AuditReceipt {
+            event_id: event.event_id,
+            tenant_id: event.tenant_id,
+            event_hash: event.event_hash.clone()?,
+            timestamp: event.timestamp.trunc_subsecs(6),
+            issued_at: Utc::now().trunc_subsecs(6),
+            anchor,
+            key_id: self.key_id.clone(),
+            signature: String::new(),
+        };
+        receipt.signature = hex::encode(self.key.sign(receipt.signing_input().as_bytes()).to_bytes());
+        Some(receipt)
+    }
+
+    fn verify(&self, receipt: &AuditReceipt) -> VerificationCheck {
+        let key = if receipt.key_id == self.key_id {
+            self.key.verifying_key()
+        } else if let Some(key) = self.retired.get(&receipt.key_id) {
+            *key
+        } else {
+            return VerificationCheck::fail("receipt_signature", format!("unknown receipt key {}", receipt.key_id));
+        };
+        let signature = match hex::decode(&receipt.signature).ok().and_then(|bytes| Signature::from_slice(&bytes).ok()) {
+            Some(signature) => signature,
+            None => return VerificationCheck::fail("receipt_signature", "signature is not a hex Ed25519 signature"),
+        };
+        match key.verify(receipt.signing_input().as_bytes(), &signature) {
+            Ok(()) => VerificationCheck::pass("receipt_signature"),
+            Err(_) => VerificationCheck::fail("receipt_signature", "signature does not match the receipt"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReceiptKey {
+    pub key_id: String,
+    /// Hex Ed25519 public key
+    pub public_key: String,
+    pub current: bool,
+}
+
+/// Where the event's anchor is, or will be, found
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum AnchorReference {
+    /// Anchored on its own; no transaction yet while the anchor waits in the outbox
+    PerEvent { transaction_hash: Option<String> },
+    /// Covered by the Merkle root of this UTC day, built shortly after it ends
+    DailyDigest { digest_date: NaiveDate },
+}
+
+impl AnchorReference {
+    fn signing_input(&self) -> String {
+        match self {
+            Self::PerEvent { transaction_hash } => format!("per_event:{}", transaction_hash.as_deref().unwrap_or_default()),
+            Self::DailyDigest { digest_date } => format!("daily_digest:{}", digest_date.format("%Y-%m-%d")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuditReceipt {
+    pub event_id: Uuid,
+    pub tenant_id: Uuid,
+    pub event_hash: String,
+    /// When the event was recorded
+    pub timestamp: DateTime<Utc>,
+    pub issued_at: DateTime<Utc>,
+    pub anchor: AnchorReference,
+    pub key_id: String,
+    /// Hex Ed25519 signature of the signing input
+    pub signature: String,
+}
+
+impl AuditReceipt {
+    pub fn signing_input(&self) -> String {
+        [
+            SIGNING_PREFIX.to_string(),
+            self.event_id.to_string(),
+            self.tenant_id.to_string(),
+            self.event_hash.clone(),
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.issued_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.anchor.signing_input(),
+        ]
+        .join("|")
+    }
+}
+
+/// The event's anchor as it stands when a receipt is verified
+#[derive(Serialize, Debug, Clone)]
+pub struct CurrentAnchor {
+    /// PENDING until a transaction holds the event hash or its digest root
+    pub status: String,
+    pub transaction_hash: Option<String>,
+    pub digest_date: Option<NaiveDate>,
+    pub merkle_root: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReceiptVerification {
+    pub event_id: Uuid,
+    /// The receipt is genuine and the stored event still carries its hash
+    pub valid: bool,
+    pub checks: Vec<VerificationCheck>,
+    /// Absent when the event is no longer stored online
+    pub anchor: Option<CurrentAnchor>,
+    pub verified_at: DateTime<Utc>,
+}
+
+/// Check a receipt's signature and compare it with the stored event
+///
+/// `integrity` is the outcome of verifying `event`, which is `None` when the
+/// event is not stored online any more.
+pub async fn verify(
+    db: &PgPool,
+    signer: &ReceiptSigner,
+    receipt: &AuditReceipt,
+    event: Option<&AuditEvent>,
+    integrity: Option<VerificationCheck>,
+) -> anyhow::Result<ReceiptVerification> {
+    let mut checks = vec![signer.verify(receipt)];
+
+    let mut anchor = None;
+    match event {
+        Some(event) => {
+            checks.push(match &event.event_hash {
+                Some(hash) if *hash == receipt.event_hash && event.tenant_id == receipt.tenant_id => {
+                    VerificationCheck::pass("event_hash")
+                }
+                Some(hash) if *hash != receipt.event_hash => VerificationCheck::fail(
+                    "event_hash",
+                    format!("stored hash {} differs from the receipt", hash),
+                ),
+                Some(_) => VerificationCheck::fail("event_hash", "stored event belongs to another tenant"),
+                None => VerificationCheck::fail("event_hash", "stored event has no hash"),
+            });
+            checks.extend(integrity);
+            anchor = Some(current_anchor(db, event, &receipt.anchor).await?);
+        }
+        None => checks.push(VerificationCheck::fail(
+            "event_hash",
+            "event is not stored online; it may have been archived to cold storage",
+        )),
+    }
+
+    Ok(ReceiptVerification {
+        event_id: receipt.event_id,
+        valid: checks.iter().all(|check| check.passed),
+        checks,
+        anchor,
+        verified_at: Utc::now(),
+    })
+}
+
+async fn current_anchor(db: &PgPool, event: &AuditEvent, promised: &AnchorReference) -> anyhow::Result<CurrentAnchor> {
+    if let AnchorReference::DailyDigest { digest_date } = promised {
+        return Ok(match digest::inclusion_proof(db, event.event_id).await? {
+            Some(proof) => CurrentAnchor {
+                status: proof.anchor_status,
+                transaction_hash: proof.transaction_hash,
+                digest_date: Some(proof.digest_date),
+                merkle_root: Some(proof.merkle_root),
+            },
+            None => CurrentAnchor {
+                status: "PENDING".to_string(),
+                transaction_hash: None,
+                digest_date: Some(*digest_date),
+                merkle_root: None,
+            },
+        });
+    }
+    Ok(CurrentAnchor {
+        status: if event.blockchain_hash.is_some() { "ANCHORED" } else { "PENDING" }.to_string(),
+        transaction_hash: event.blockchain_hash.clone(),
+        digest_date: None,
+        merkle_root: None,
+    })
+}
+
+/// A created event as returned to its producer, with the receipt alongside its fields
+#[derive(Serialize)]
+pub struct Receipted<T> {
+    #[serde(flatten)]
+    pub event: T,
+    /// Absent when AUDIT_RECEIPT_SIGNING_KEY is unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<AuditReceipt>,
+}
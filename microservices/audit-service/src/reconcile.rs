@@ -0,0 +1,317 @@
+//! Nightly reconciliation between the audit stores
+//!
+//! For one UTC day this cross-checks Postgres audit rows against the
+//! documents in the document store (ids, counts and an id checksum),
+//! re-verifies each document's payload hash and blockchain anchor, and checks
+//! that violations referenced from investigation cases still exist. Every
+//! discrepancy is persisted on the run and raised as an ops alert in
+//! system_events. The `mongodb_*` totals and the `missing_in_mongodb` check
+//! keep their names when documents are kept in Postgres.
+
+use chrono::{Duration, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::digest;
+use crate::integrity;
+use crate::store::DocumentStore;
+use crate::AuditEvent;
+
+/// Ids listed per discrepancy before the rest are only counted
+const SAMPLE_SIZE: usize = 20;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Discrepancy {
+    pub check: String,
+    pub severity: String,
+    pub count: usize,
+    pub sample_ids: Vec<Uuid>,
+    pub message: String,
+}
+
+impl Discrepancy {
+    fn new(check: &str, severity: &str, ids: &[Uuid], message: String) -> Self {
+        Self {
+            check: check.to_string(),
+            severity: severity.to_string(),
+            count: ids.len(),
+            sample_ids: ids.iter().take(SAMPLE_SIZE).copied().collect(),
+            message,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct ReconciliationRun {
+    pub run_id: Uuid,
+    pub reconciled_date: NaiveDate,
+    pub triggered_by: String,
+    pub status: String,
+    pub postgres_count: Option<i64>,
+    pub mongodb_count: Option<i64>,
+    pub postgres_checksum: Option<String>,
+    pub mongodb_checksum: Option<String>,
+    pub discrepancy_count: i32,
+    pub discrepancies: serde_json::Value,
+    pub error: Option<String>,
+    pub started_at: Option<chrono::DateTime<Utc>>,
+    pub completed_at: Option<chrono::DateTime<Utc>>,
+}
+
+struct StoreTotals {
+    postgres_count: i64,
+    mongodb_count: i64,
+    postgres_checksum: String,
+    mongodb_checksum: String,
+}
+
+/// Schedule reconciliation of the previous UTC day at 02:30
+pub async fn schedule(db: PgPool, documents: Arc<dyn DocumentStore>) -> anyhow::Result<JobScheduler> {
+    let scheduler = JobScheduler::new().await?;
+
+    let job = Job::new_async("0 30 2 * * *", move |_uuid, _l| {
+        let db = db.clone();
+        let documents = documents.clone();
+        Box::pin(async move {
+            let day = Utc::now().date_naive() - Duration::days(1);
+            info!("Running scheduled reconciliation for {}", day);
+            if let Err(e) = run(&db, documents.as_ref(), day, "SCHEDULED").await {
+                error!("Scheduled reconciliation for {} failed: {}", day, e);
+            }
+        })
+    })?;
+
+    scheduler.add(job).await?;
+    scheduler.start().await?;
+    Ok(scheduler)
+}
+
+pub async fn get_run(db: &PgPool, run_id: Uuid) -> Result<Option<ReconciliationRun>, sqlx::Error> {
+    sqlx::query_as::<_, ReconciliationRun>("SELECT * FROM reconciliation_runs WHERE run_id = $1")
+        .bind(run_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// Reconcile one UTC day, recording the run and raising an ops alert per discrepancy
+pub async fn run(
+    db: &PgPool,
+    documents: &dyn DocumentStore,
+    day: NaiveDate,
+    triggered_by: &str,
+) -> anyhow::Result<ReconciliationRun> {
+    let run_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO reconciliation_runs (reconciled_date, triggered_by) VALUES ($1, $2) RETURNING run_id",
+    )
+    .bind(day)
+    .bind(triggered_by)
+    .fetch_one(db)
+    .await?;
+
+    let outcome = reconcile_day(db, documents, day).await;
+
+    match outcome {
+        Ok((totals, discrepancies)) => {
+            for discrepancy in &discrepancies {
+                raise_ops_alert(db, run_id, day, discrepancy).await?;
+            }
+            let status = if discrepancies.is_empty() { "CLEAN" } else { "DISCREPANCIES" };
+            sqlx::query(
+                r#"
+                UPDATE reconciliation_runs
+                SET status = $2, postgres_count = $3, mongodb_count = $4, postgres_checksum = $5,
+                    mongodb_checksum = $6, discrepancy_count = $7, discrepancies = $8, completed_at = NOW()
+                WHERE run_id = $1
+                "#,
+            )
+            .bind(run_id)
+            .bind(status)
+            .bind(totals.postgres_count)
+            .bind(totals.mongodb_count)
+            .bind(&totals.postgres_checksum)
+            .bind(&totals.mongodb_checksum)
+            .bind(discrepancies.len() as i32)
+            .bind(serde_json::to_value(&discrepancies)?)
+            .execute(db)
+            .await?;
+
+            if discrepancies.is_empty() {
+                info!("Reconciliation for {} is clean", day);
+            } else {
+                warn!("Reconciliation for {} found {} discrepancies", day, discrepancies.len());
+            }
+        }
+        Err(e) => {
+            error!("Reconciliation for {} failed: {}", day, e);
+            sqlx::query(
+                "UPDATE reconciliation_runs SET status = 'FAILED', error = $2, completed_at = NOW() WHERE run_id = $1",
+            )
+            .bind(run_id)
+            .bind(e.to_string())
+            .execute(db)
+            .await?;
+        }
+    }
+
+    get_run(db, run_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("reconciliation run {} disappeared", run_id))
+}
+
+async fn reconcile_day(
+    db: &PgPool,
+    store: &dyn DocumentStore,
+    day: NaiveDate,
+) -> anyhow::Result<(StoreTotals, Vec<Discrepancy>)> {
+    let start = day.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+    let end = start + Duration::days(1);
+
+    let postgres_ids: BTreeSet<Uuid> = sqlx::query_scalar(
+        "SELECT log_id FROM audit_logs WHERE timestamp >= $1 AND timestamp < $2",
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .collect();
+
+    let documents: HashMap<Uuid, AuditEvent> = store
+        .for_day(day)
+        .await?
+        .into_iter()
+        .map(|event| (event.event_id, event))
+        .collect();
+    let mongodb_ids: BTreeSet<Uuid> = documents.keys().copied().collect();
+
+    let totals = StoreTotals {
+        postgres_count: postgres_ids.len() as i64,
+        mongodb_count: mongodb_ids.len() as i64,
+        postgres_checksum: id_checksum(&postgres_ids),
+        mongodb_checksum: id_checksum(&mongodb_ids),
+    };
+
+    let mut discrepancies = Vec::new();
+
+    let missing_in_mongodb: Vec<Uuid> = postgres_ids.difference(&mongodb_ids).copied().collect();
+    if !missing_in_mongodb.is_empty() {
+        discrepancies.push(Discrepancy::new(
+            "missing_in_mongodb",
+            "ERROR",
+            &missing_in_mongodb,
+            format!("{} audit rows have no {} document", missing_in_mongodb.len(), store.backend()),
+        ));
+    }
+    let missing_in_postgres: Vec<Uuid> = mongodb_ids.difference(&postgres_ids).copied().collect();
+    if !missing_in_postgres.is_empty() {
+        discrepancies.push(Discrepancy::new(
+            "missing_in_postgres",
+            "ERROR",
+            &missing_in_postgres,
+            format!("{} {} documents have no audit row", missing_in_postgres.len(), store.backend()),
+        ));
+    }
+
+    let mut hash_mismatches = Vec::new();
+    let mut unanchored = Vec::new();
+    let mut anchor_mismatches = Vec::new();
+    for event in documents.values() {
+        let computed_hash = integrity::sha256_hex(&integrity::canonical_payload(event)?);
+        if event.event_hash.as_deref() != Some(computed_hash.as_str()) {
+            hash_mismatches.push(event.event_id);
+            continue;
+        }
+        match &event.blockchain_hash {
+            None => unanchored.push(event.event_id),
+            Some(anchor) if anchor.trim_start_matches("0x") != computed_hash => {
+                anchor_mismatches.push(event.event_id)
+            }
+            Some(_) => {}
+        }
+    }
+    if !hash_mismatches.is_empty() {
+        discrepancies.push(Discrepancy::new(
+            "payload_hash_mismatch",
+            "ERROR",
+            &hash_mismatches,
+            format!("{} documents no longer match their recorded hash", hash_mismatches.len()),
+        ));
+    }
+    if !anchor_mismatches.is_empty() {
+        discrepancies.push(Discrepancy::new(
+            "anchor_mismatch",
+            "ERROR",
+            &anchor_mismatches,
+            format!("{} documents are anchored under a different hash", anchor_mismatches.len()),
+        ));
+    }
+    // Events covered by an anchored daily digest are anchored through its root
+    let in_digest: BTreeSet<Uuid> = digest::anchored_in_digest(db, &unanchored).await?.into_iter().collect();
+    unanchored.retain(|id| !in_digest.contains(id));
+    if !unanchored.is_empty() {
+        discrepancies.push(Discrepancy::new(
+            "unanchored",
+            "WARN",
+            &unanchored,
+            format!("{} documents were never anchored on chain", unanchored.len()),
+        ));
+    }
+
+    // Cases (investigations) reference violations by id inside supporting_documents
+    let dangling_cases: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT i.investigation_id
+        FROM alert_investigations i,
+             jsonb_array_elements(COALESCE(i.supporting_documents, '[]'::jsonb)) AS doc
+        WHERE jsonb_typeof(doc) = 'object'
+          AND doc ? 'violation_id'
+          AND NOT EXISTS (
+              SELECT 1 FROM compliance_violations v WHERE v.violation_id::text = doc->>'violation_id'
+          )
+        "#,
+    )
+    .fetch_all(db)
+    .await?;
+    if !dangling_cases.is_empty() {
+        discrepancies.push(Discrepancy::new(
+            "case_violation_missing",
+            "WARN",
+            &dangling_cases,
+            format!("{} cases reference violations that no longer exist", dangling_cases.len()),
+        ));
+    }
+
+    Ok((totals, discrepancies))
+}
+
+/// SHA-256 over the sorted ids, comparable across stores
+fn id_checksum(ids: &BTreeSet<Uuid>) -> String {
+    let joined: Vec<String> = ids.iter().map(Uuid::to_string).collect();
+    integrity::sha256_hex(joined.join("\n").as_bytes())
+}
+
+async fn raise_ops_alert(
+    db: &PgPool,
+    run_id: Uuid,
+    day: NaiveDate,
+    discrepancy: &Discrepancy,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO system_events (event_type, severity, source_system, message, details, correlation_id)
+        VALUES ('RECONCILIATION_DISCREPANCY', $1, 'audit-service', $2, $3, $4)
+        "#,
+    )
+    .bind(&discrepancy.severity)
+    .bind(format!("[{}] {}", day, discrepancy.message))
+    .bind(serde_json::to_value(discrepancy).unwrap_or_default())
+    .bind(run_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,687 @@
+//! Active-active replication of audit events between two regions
+//!
+//! With AUDIT_REPLICATION_PEER_URL set, each region takes writes on its own
+//! and ships them to the other. Event ids are random UUIDs, so the regions
+//! mint them without coordinating and never collide. Every event is linked
+//! into its tenant's hash chain of the region it was created in (AUDIT_REGION):
+//! the chain's head is locked by the Postgres transaction writing the row, and
+//! the event gets the next seq and SHA-256(previous link || event hash). A
+//! tenant's events in one region are therefore totally ordered, and events of
+//! the two regions sit in separate chains that never conflict.
+//!
+//! The same transaction queues the event in audit_replication_queue. A worker
+//! ships queued events in order, with their signed documents and links, to the
+//! peer's POST /api/v1/admin/replication/events under AUDIT_REPLICATION_TOKEN,
+//! one of the peer's AUDIT_SERVICE_TOKENS, and drops what the peer
+//! acknowledges. The peer applies an event only if its payload hashes to its
+//! event hash, its signature verifies and its link extends the chain head it
+//! holds; a replayed event it already holds under the same link is
+//! acknowledged again. An event it refuses holds back the rest of that
+//! tenant's chain until it is resolved, but not other tenants. Applied events
+//! are stored and published like local ones, keep the CID and anchor of their
+//! origin and are not pinned, anchored or replicated again, so both regions
+//! need the same signing keys and, where used, PII and envelope master keys.
+//!
+//! GET /admin/replication/divergence compares every chain head with the peer's
+//! at the last seq both hold, reporting chains that differ there as DIVERGED
+//! and the lag of the rest, with the replication backlog. The backlog is
+//! exported as `audit_replication_queue_depth`.
+
+use chrono::{DateTime, Utc};
+use metrics::{counter, gauge};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::integrity;
+use crate::outbox;
+use crate::store;
+use crate::{AppState, AuditEvent, AuditService};
+
+/// Link before the first event of a chain
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// Serialized events per batch, below the peer's 2 MiB request limit
+const MAX_BATCH_BYTES: usize = 1024 * 1024;
+/// Links probed per request to the peer
+const PROBE_CHUNK: usize = 1000;
+/// Failing queue entries listed in the divergence report
+const MAX_LISTED_FAILURES: i64 = 50;
+
+#[derive(Debug, Clone)]
+pub struct ReplicationSettings {
+    pub region: String,
+    /// Base URL of the peer region's audit service
+    pub peer_url: String,
+    pub token: String,
+    pub batch_size: i64,
+    pub interval: Duration,
+}
+
+impl ReplicationSettings {
+    /// `None` when no peer is configured
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+        let Some(peer_url) = var("AUDIT_REPLICATION_PEER_URL") else {
+            return Ok(None);
+        };
+        let region = var("AUDIT_REGION")
+            .ok_or_else(|| anyhow::anyhow!("AUDIT_REPLICATION_PEER_URL is set but AUDIT_REGION is not"))?;
+        if region.len() > 50 {
+            anyhow::bail!("AUDIT_REGION must be at most 50 characters");
+        }
+        let token = var("AUDIT_REPLICATION_TOKEN")
+            .ok_or_else(|| anyhow::anyhow!("AUDIT_REPLICATION_PEER_URL is set but AUDIT_REPLICATION_TOKEN is not"))?;
+        Ok(Some(Self {
+            region,
+            peer_url: peer_url.trim_end_matches('/').to_string(),
+            token,
+            batch_size: var("AUDIT_REPLICATION_BATCH")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(500)
+                .max(1),
+            interval: Duration::from_secs(
+                var("AUDIT_REPLICATION_INTERVAL_SECS")
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(2),
+            ),
+        }))
+    }
+}
+
+/// Where an event sits in its tenant's chain
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChainLink {
+    pub origin_region: String,
+    pub seq: i64,
+    pub hash: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplicatedEvent {
+    pub event: AuditEvent,
+    pub link: ChainLink,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplicaBatch {
+    pub origin_region: String,
+    pub events: Vec<ReplicatedEvent>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rejection {
+    pub event_id: Uuid,
+    pub tenant_id: Uuid,
+    pub reason: String,
+}
+
+/// What the receiving region did with a batch; events it neither acknowledged
+/// nor rejected follow a rejected event of their tenant and were not tried
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReplicaReceipt {
+    pub applied: Vec<Uuid>,
+    /// Already held under the same link
+    pub duplicates: Vec<Uuid>,
+    pub rejected: Vec<Rejection>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct ChainHead {
+    pub tenant_id: Uuid,
+    pub origin_region: String,
+    pub seq: i64,
+    pub head_hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainHeads {
+    pub region: String,
+    pub heads: Vec<ChainHead>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LinkProbe {
+    pub tenant_id: Uuid,
+    pub origin_region: String,
+    pub seq: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct ProbedLink {
+    pub tenant_id: Uuid,
+    pub origin_region: String,
+    pub seq: i64,
+    /// `None` when the region holds no event at this seq
+    pub chain_hash: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ChainStatus {
+    InSync,
+    PeerBehind,
+    LocalBehind,
+    Diverged,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ChainComparison {
+    pub tenant_id: Uuid,
+    pub origin_region: String,
+    pub status: ChainStatus,
+    pub local_seq: i64,
+    pub peer_seq: i64,
+    /// Last seq both regions hold, where their links were compared
+    pub compared_seq: i64,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct QueueFailure {
+    pub event_id: Uuid,
+    pub queued_at: DateTime<Utc>,
+    pub attempts: i32,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ReplicationBacklog {
+    pub pending: i64,
+    pub oldest_queued_at: Option<DateTime<Utc>>,
+    /// Entries the peer refused or that could not be shipped, most attempted first
+    pub failing: Vec<QueueFailure>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DivergenceReport {
+    pub region: String,
+    pub peer_region: String,
+    pub generated_at: DateTime<Utc>,
+    pub chains: usize,
+    pub in_sync: usize,
+    pub diverged: Vec<ChainComparison>,
+    /// Chains one region holds more of, through replication lag or a held-back event
+    pub lagging: Vec<ChainComparison>,
+    pub backlog: ReplicationBacklog,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplicationError {
+    #[error("replication is not configured (AUDIT_REPLICATION_PEER_URL is not set)")]
+    Disabled,
+    #[error("the batch holds events of this region ({0})")]
+    OwnRegion(String),
+    #[error("the peer region could not be queried: {0:#}")]
+    Peer(anyhow::Error),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("{0:#}")]
+    Internal(#[from] anyhow::Error),
+}
+
+/// A queue entry with its audit row's link; the link is missing when the row is gone
+#[derive(sqlx::FromRow)]
+struct Queued {
+    queue_id: i64,
+    event_id: Uuid,
+    tenant_id: Option<Uuid>,
+    origin_region: Option<String>,
+    chain_seq: Option<i64>,
+    chain_hash: Option<String>,
+}
+
+enum Applied {
+    Stored,
+    Duplicate,
+    Rejected(String),
+}
+
+pub struct Replication {
+    settings: ReplicationSettings,
+    http: reqwest::Client,
+}
+
+impl Replication {
+    pub fn new(settings: ReplicationSettings) -> anyhow::Result<Self> {
+        Ok(Self {
+            settings,
+            http: reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?,
+        })
+    }
+
+    pub fn region(&self) -> &str {
+        &self.settings.region
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/api/v1/admin/replication/{}", self.settings.peer_url, path)
+    }
+
+    async fn call<R: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> anyhow::Result<R> {
+        let response = request.bearer_auth(&self.settings.token).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("peer answered {}: {}", status, response.text().await.unwrap_or_default());
+        }
+        Ok(response.json().await?)
+    }
+
+    async fn send(&self, batch: &ReplicaBatch) -> anyhow::Result<ReplicaReceipt> {
+        self.call(self.http.post(self.url("events")).json(batch)).await
+    }
+
+    async fn peer_heads(&self) -> anyhow::Result<ChainHeads> {
+        self.call(self.http.get(self.url("heads"))).await
+    }
+
+    async fn probe_peer(&self, probes: &[LinkProbe]) -> anyhow::Result<Vec<ProbedLink>> {
+        let mut links = Vec::with_capacity(probes.len());
+        for chunk in probes.chunks(PROBE_CHUNK) {
+            links.extend(self.call::<Vec<ProbedLink>>(self.http.post(self.url("links")).json(chunk)).await?);
+        }
+        Ok(links)
+    }
+}
+
+pub fn link_hash(previous: Option<&str>, event_hash: &str) -> String {
+    integrity::sha256_hex(format!("{}{}", previous.unwrap_or(GENESIS), event_hash).as_bytes())
+}
+
+/// Lock the head of a chain until `tx` ends; (0, None) for a chain not started
+async fn lock_head(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: Uuid,
+    origin_region: &str,
+) -> Result<(i64, Option<String>), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        INSERT INTO audit_chain_heads (tenant_id, origin_region) VALUES ($1, $2)
+        ON CONFLICT (tenant_id, origin_region) DO UPDATE SET updated_at = NOW()
+        RETURNING seq, head_hash
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(origin_region)
+    .fetch_one(&mut **tx)
+    .await
+}
+
+async fn advance(tx: &mut Transaction<'_, Postgres>, tenant_id: Uuid, link: &ChainLink) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE audit_chain_heads SET seq = $3, head_hash = $4, updated_at = NOW() \
+         WHERE tenant_id = $1 AND origin_region = $2",
+    )
+    .bind(tenant_id)
+    .bind(&link.origin_region)
+    .bind(link.seq)
+    .bind(&link.hash)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Link a new event of this region into its tenant's chain
+pub async fn extend_chain(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: Uuid,
+    region: &str,
+    event_hash: &str,
+) -> Result<ChainLink, sqlx::Error> {
+    let (seq, head) = lock_head(tx, tenant_id, region).await?;
+    let link = ChainLink {
+        origin_region: region.to_string(),
+        seq: seq + 1,
+        hash: link_hash(head.as_deref(), event_hash),
+    };
+    advance(tx, tenant_id, &link).await?;
+    Ok(link)
+}
+
+pub async fn enqueue(tx: &mut Transaction<'_, Postgres>, event_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO audit_replication_queue (event_id) VALUES ($1)")
+        .bind(event_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+pub fn spawn_shipper(state: AppState, replication: std::sync::Arc<Replication>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(replication.settings.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match ship(&state, &replication).await {
+                Ok(0) => {}
+                Ok(shipped) => info!("Replicated {} audit events to the peer region", shipped),
+                Err(e) => warn!("Audit replication pass failed: {:#}", e),
+            }
+            match sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM audit_replication_queue")
+                .fetch_one(&state.db)
+                .await
+            {
+                Ok(depth) => gauge!("audit_replication_queue_depth", depth as f64),
+                Err(e) => warn!("Failed to export the replication backlog: {}", e),
+            }
+        }
+    });
+}
+
+async fn record_failure(db: &PgPool, event_id: Uuid, error: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE audit_replication_queue SET attempts = attempts + 1, last_attempt_at = NOW(), last_error = $2 \
+         WHERE event_id = $1",
+    )
+    .bind(event_id)
+    .bind(error)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Ship the queue in order, holding back the chain of a tenant past its first unshippable event
+async fn ship(state: &AppState, replication: &Replication) -> anyhow::Result<usize> {
+    let mut shipped = 0;
+    let mut after = 0i64;
+    let mut held_back: HashSet<Uuid> = HashSet::new();
+    loop {
+        let queued = sqlx::query_as::<_, Queued>(
+            r#"
+            SELECT q.queue_id, q.event_id, l.tenant_id, l.origin_region, l.chain_seq, l.chain_hash
+            FROM audit_replication_queue q
+            LEFT JOIN audit_logs l ON l.log_id = q.event_id
+            WHERE q.queue_id > $1
+            ORDER BY q.queue_id
+            LIMIT $2
+            "#,
+        )
+        .bind(after)
+        .bind(replication.settings.batch_size)
+        .fetch_all(&state.db)
+        .await?;
+        if queued.is_empty() {
+            return Ok(shipped);
+        }
+
+        let ids: Vec<Uuid> = queued.iter().map(|entry| entry.event_id).collect();
+        let mut documents = state.documents.get_many(&ids).await?;
+        let mut events = Vec::new();
+        let mut bytes = 0;
+        for Queued { queue_id, event_id, tenant_id, origin_region, chain_seq, chain_hash } in queued {
+            let (Some(tenant_id), Some(origin_region), Some(seq), Some(hash)) =
+                (tenant_id, origin_region, chain_seq, chain_hash)
+            else {
+                record_failure(&state.db, event_id, "the audit row is gone or has no chain link").await?;
+                after = queue_id;
+                continue;
+            };
+            if held_back.contains(&tenant_id) {
+                after = queue_id;
+                continue;
+            }
+            // Still with the batched document writer or the outbox; next pass
+            let Some(event) = documents.remove(&event_id) else {
+                held_back.insert(tenant_id);
+                after = queue_id;
+                continue;
+            };
+            bytes += serde_json::to_vec(&event)?.len();
+            if bytes > MAX_BATCH_BYTES && !events.is_empty() {
+                break;
+            }
+            after = queue_id;
+            events.push(ReplicatedEvent { event, link: ChainLink { origin_region, seq, hash } });
+        }
+        if events.is_empty() {
+            continue;
+        }
+
+        let batch = ReplicaBatch { origin_region: replication.region().to_string(), events };
+        let receipt = match replication.send(&batch).await {
+            Ok(receipt) => receipt,
+            Err(e) => {
+                record_failure(&state.db, batch.events[0].event.event_id, &format!("{:#}", e)).await?;
+                return Err(e);
+            }
+        };
+        let acknowledged: Vec<Uuid> = receipt.applied.iter().chain(&receipt.duplicates).copied().collect();
+        sqlx::query("DELETE FROM audit_replication_queue WHERE event_id = ANY($1)")
+            .bind(&acknowledged)
+            .execute(&state.db)
+            .await?;
+        shipped += acknowledged.len();
+        for rejection in &receipt.rejected {
+            counter!("audit_replication_rejected_total", 1);
+            warn!(
+                "Peer region refused audit event {} of tenant {}: {}",
+                rejection.event_id, rejection.tenant_id, rejection.reason
+            );
+            record_failure(&state.db, rejection.event_id, &rejection.reason).await?;
+            // The events of its tenant after it were not tried, and wait for the next pass
+            held_back.insert(rejection.tenant_id);
+        }
+    }
+}
+
+/// Apply a batch shipped by the peer region, in order
+pub async fn apply(
+    state: &AppState,
+    replication: &Replication,
+    batch: ReplicaBatch,
+) -> Result<ReplicaReceipt, ReplicationError> {
+    if batch.origin_region == replication.region() {
+        return Err(ReplicationError::OwnRegion(batch.origin_region));
+    }
+    let service = AuditService::from_state(state.clone());
+    let mut receipt = ReplicaReceipt::default();
+    let mut refused: HashSet<Uuid> = HashSet::new();
+    for ReplicatedEvent { event, link } in batch.events {
+        if refused.contains(&event.tenant_id) {
+            continue;
+        }
+        let applied = if link.origin_region == batch.origin_region {
+            apply_event(state, &event, &link).await?
+        } else {
+            Applied::Rejected(format!("linked into a chain of {}, not of {}", link.origin_region, batch.origin_region))
+        };
+        match applied {
+            Applied::Stored => {
+                // The row is in; a document that cannot be stored now is left to the outbox
+                if let Err(e) = service.store_document(&event).await {
+                    warn!("Spilling the document of replicated audit event {}: {}", event.event_id, e);
+                    outbox::spill_documents(&state.db, std::slice::from_ref(&event), &e.to_string()).await?;
+                }
+                counter!("audit_replication_applied_total", 1, "origin" => batch.origin_region.clone());
+                receipt.applied.push(event.event_id);
+            }
+            Applied::Duplicate => receipt.duplicates.push(event.event_id),
+            Applied::Rejected(reason) => {
+                warn!("Refused replicated audit event {} from {}: {}", event.event_id, batch.origin_region, reason);
+                refused.insert(event.tenant_id);
+                receipt.rejected.push(Rejection { event_id: event.event_id, tenant_id: event.tenant_id, reason });
+            }
+        }
+    }
+    Ok(receipt)
+}
+
+async fn apply_event(state: &AppState, event: &AuditEvent, link: &ChainLink) -> anyhow::Result<Applied> {
+    let Some(hash) = event.event_hash.as_deref() else {
+        return Ok(Applied::Rejected("the event is not hashed".to_string()));
+    };
+    let computed_hash = integrity::sha256_hex(&integrity::canonical_payload(event)?);
+    if computed_hash != hash {
+        return Ok(Applied::Rejected(format!("the payload hashes to {}, not {}", computed_hash, hash)));
+    }
+    let signed = event
+        .signature
+        .as_deref()
+        .is_some_and(|signature| state.signer.verify(hash, signature, event.signing_key_id.as_deref()));
+    if !signed {
+        return Ok(Applied::Rejected("the signature does not verify with this region's signing keys".to_string()));
+    }
+
+    let mut tx = state.db.begin().await?;
+    let (seq, head) = lock_head(&mut tx, event.tenant_id, &link.origin_region).await?;
+    if link.seq <= seq {
+        let held: Option<Option<String>> = sqlx::query_scalar("SELECT chain_hash FROM audit_logs WHERE log_id = $1")
+            .bind(event.event_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        return Ok(match held {
+            Some(Some(held)) if held == link.hash => Applied::Duplicate,
+            Some(_) => Applied::Rejected("the event is held here under another link".to_string()),
+            None => Applied::Rejected(format!("seq {} of the chain is held here by another event", link.seq)),
+        });
+    }
+    if link.seq > seq + 1 {
+        return Ok(Applied::Rejected(format!("expected seq {} of the chain, got {}", seq + 1, link.seq)));
+    }
+    if link.hash != link_hash(head.as_deref(), hash) {
+        return Ok(Applied::Rejected(format!("the link does not extend the chain at seq {}", seq)));
+    }
+    store::insert_row(&mut tx, event, Some(link)).await?;
+    advance(&mut tx, event.tenant_id, link).await?;
+    tx.commit().await?;
+    Ok(Applied::Stored)
+}
+
+pub async fn heads(db: &PgPool, region: &str) -> Result<ChainHeads, sqlx::Error> {
+    let heads = sqlx::query_as::<_, ChainHead>(
+        "SELECT tenant_id, origin_region, seq, head_hash FROM audit_chain_heads WHERE seq > 0",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(ChainHeads { region: region.to_string(), heads })
+}
+
+pub async fn probe(db: &PgPool, probes: &[LinkProbe]) -> Result<Vec<ProbedLink>, sqlx::Error> {
+    let tenant_ids: Vec<Uuid> = probes.iter().map(|probe| probe.tenant_id).collect();
+    let regions: Vec<String> = probes.iter().map(|probe| probe.origin_region.clone()).collect();
+    let seqs: Vec<i64> = probes.iter().map(|probe| probe.seq).collect();
+    sqlx::query_as::<_, ProbedLink>(
+        r#"
+        SELECT p.tenant_id, p.origin_region, p.seq, l.chain_hash
+        FROM UNNEST($1::uuid[], $2::text[], $3::bigint[]) AS p(tenant_id, origin_region, seq)
+        LEFT JOIN audit_logs l
+            ON l.tenant_id = p.tenant_id AND l.origin_region = p.origin_region AND l.chain_seq = p.seq
+        "#,
+    )
+    .bind(&tenant_ids)
+    .bind(&regions)
+    .bind(&seqs)
+    .fetch_all(db)
+    .await
+}
+
+type ChainKey = (Uuid, String);
+
+/// Compare every chain with the peer's at the last seq both regions hold
+pub async fn divergence(state: &AppState, replication: &Replication) -> Result<DivergenceReport, ReplicationError> {
+    let local = heads(&state.db, replication.region()).await?;
+    let peer = replication.peer_heads().await.map_err(ReplicationError::Peer)?;
+
+    let mut chains: BTreeMap<ChainKey, (Option<ChainHead>, Option<ChainHead>)> = BTreeMap::new();
+    for head in local.heads {
+        chains.entry((head.tenant_id, head.origin_region.clone())).or_default().0 = Some(head);
+    }
+    for head in peer.heads {
+        chains.entry((head.tenant_id, head.origin_region.clone())).or_default().1 = Some(head);
+    }
+
+    // The shorter side's head is the link to compare; the longer side is probed for it
+    let mut local_probes = Vec::new();
+    let mut peer_probes = Vec::new();
+    for ((tenant_id, origin_region), (local, peer)) in &chains {
+        let (Some(local), Some(peer)) = (local, peer) else {
+            continue;
+        };
+        let probe = LinkProbe {
+            tenant_id: *tenant_id,
+            origin_region: origin_region.clone(),
+            seq: local.seq.min(peer.seq),
+        };
+        if local.seq > peer.seq {
+            local_probes.push(probe);
+        } else if peer.seq > local.seq {
+            peer_probes.push(probe);
+        }
+    }
+    let probed = |links: Vec<ProbedLink>| -> HashMap<ChainKey, Option<String>> {
+        links.into_iter().map(|link| ((link.tenant_id, link.origin_region), link.chain_hash)).collect()
+    };
+    let local_links = probed(probe(&state.db, &local_probes).await?);
+    let peer_links = probed(replication.probe_peer(&peer_probes).await.map_err(ReplicationError::Peer)?);
+
+    let mut report = DivergenceReport {
+        region: local.region,
+        peer_region: peer.region,
+        generated_at: Utc::now(),
+        chains: chains.len(),
+        in_sync: 0,
+        diverged: Vec::new(),
+        lagging: Vec::new(),
+        backlog: backlog(&state.db).await?,
+    };
+    for (key, (local, peer)) in chains {
+        let local_seq = local.as_ref().map_or(0, |head| head.seq);
+        let peer_seq = peer.as_ref().map_or(0, |head| head.seq);
+        let compared_seq = local_seq.min(peer_seq);
+        let link_at = |head: Option<ChainHead>, links: &HashMap<ChainKey, Option<String>>| match head {
+            Some(head) if head.seq == compared_seq => head.head_hash,
+            _ => links.get(&key).cloned().flatten(),
+        };
+        let (local_link, peer_link) = (link_at(local, &local_links), link_at(peer, &peer_links));
+        let status = if compared_seq > 0 && local_link != peer_link {
+            ChainStatus::Diverged
+        } else if local_seq > peer_seq {
+            ChainStatus::PeerBehind
+        } else if peer_seq > local_seq {
+            ChainStatus::LocalBehind
+        } else {
+            ChainStatus::InSync
+        };
+        let comparison = ChainComparison {
+            tenant_id: key.0,
+            origin_region: key.1,
+            status,
+            local_seq,
+            peer_seq,
+            compared_seq,
+        };
+        match status {
+            ChainStatus::InSync => report.in_sync += 1,
+            ChainStatus::Diverged => report.diverged.push(comparison),
+            ChainStatus::PeerBehind | ChainStatus::LocalBehind => report.lagging.push(comparison),
+        }
+    }
+    if !report.diverged.is_empty() {
+        warn!("{} audit chains have diverged from {}", report.diverged.len(), report.peer_region);
+    }
+    Ok(report)
+}
+
+async fn backlog(db: &PgPool) -> Result<ReplicationBacklog, sqlx::Error> {
+    let (pending, oldest_queued_at): (i64, Option<DateTime<Utc>>) =
+        sqlx::query_as("SELECT COUNT(*), MIN(queued_at) FROM audit_replication_queue")
+            .fetch_one(db)
+            .await?;
+    let failing = sqlx::query_as::<_, QueueFailure>(
+        r#"
+        SELECT event_id, queued_at, attempts, last_attempt_at, last_error
+        FROM audit_replication_queue
+        WHERE last_error IS NOT NULL
+        ORDER BY attempts DESC, queue_id
+        LIMIT $1
+        "#,
+    )
+    .bind(MAX_LISTED_FAILURES)
+    .fetch_all(db)
+    .await?;
+    Ok(ReplicationBacklog { pending, oldest_queued_at, failing })
+}
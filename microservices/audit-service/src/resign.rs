@@ -0,0 +1,222 @@
+//! Bulk re-signing of audit events after a signing key compromise
+//!
+//! Only events whose payload still matches their recorded hash are re-signed;
+//! anything else is recorded as skipped so a compromise can never be used to
+//! launder a tampered record under the new key.
+
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::cache::AuditCache;
+use crate::integrity::{self, EventSigner};
+use crate::store::{DocumentStore, DocumentUpdate};
+
+#[derive(Deserialize)]
+pub struct ResignRequest {
+    pub compromised_key_id: String,
+    pub rationale: String,
+    pub requested_by: Option<Uuid>,
+    /// Restrict the run to one tenant; all tenants when absent
+    pub tenant_id: Option<Uuid>,
+    /// Also re-sign events written before signing key ids were recorded
+    #[serde(default)]
+    pub include_unlabelled: bool,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ResignRun {
+    pub run_id: Uuid,
+    pub compromised_key_id: String,
+    pub new_key_id: String,
+    pub tenant_id: Option<Uuid>,
+    pub rationale: String,
+    pub requested_by: Option<Uuid>,
+    pub status: String,
+    pub events_resigned: i64,
+    pub events_skipped: i64,
+    pub error: Option<String>,
+    pub started_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Record the configured signing key so runs and verification can reference it
+pub async fn register_active_key(db: &PgPool, key_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_signing_keys (key_id, status) VALUES ($1, 'ACTIVE') ON CONFLICT (key_id) DO NOTHING",
+    )
+    .bind(key_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn is_key_compromised(db: &PgPool, key_id: &str) -> Result<bool, sqlx::Error> {
+    let status: Option<String> = sqlx::query_scalar("SELECT status FROM audit_signing_keys WHERE key_id = $1")
+        .bind(key_id)
+        .fetch_optional(db)
+        .await?;
+    Ok(status.as_deref() == Some("COMPROMISED"))
+}
+
+pub async fn get_run(db: &PgPool, run_id: Uuid) -> Result<Option<ResignRun>, sqlx::Error> {
+    sqlx::query_as::<_, ResignRun>("SELECT * FROM audit_resign_runs WHERE run_id = $1")
+        .bind(run_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// Mark the key compromised, record the run, and re-sign in the background
+pub async fn start_run(
+    db: PgPool,
+    documents: Arc<dyn DocumentStore>,
+    signer: Arc<EventSigner>,
+    cache: Arc<AuditCache>,
+    request: ResignRequest,
+) -> anyhow::Result<ResignRun> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO audit_signing_keys (key_id, status, compromised_at)
+        VALUES ($1, 'COMPROMISED', NOW())
+        ON CONFLICT (key_id) DO UPDATE
+        SET status = 'COMPROMISED', compromised_at = COALESCE(audit_signing_keys.compromised_at, NOW())
+        "#,
+    )
+    .bind(&request.compromised_key_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let run = sqlx::query_as::<_, ResignRun>(
+        r#"
+        INSERT INTO audit_resign_runs (compromised_key_id, new_key_id, tenant_id, rationale, requested_by)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING *
+        "#,
+    )
+    .bind(&request.compromised_key_id)
+    .bind(signer.current_key_id())
+    .bind(request.tenant_id)
+    .bind(request.rationale.trim())
+    .bind(request.requested_by)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    // Results cached before the compromise would still pass its signatures
+    cache.invalidate_verifications();
+
+    warn!(
+        "Signing key {} marked compromised; re-signing run {} started: {}",
+        run.compromised_key_id, run.run_id, run.rationale
+    );
+
+    let run_id = run.run_id;
+    tokio::spawn(async move {
+        let outcome = resign_events(&db, documents.as_ref(), &signer, &cache, run_id, &request).await;
+        let (status, error_message) = match &outcome {
+            Ok(()) => ("COMPLETED", None),
+            Err(e) => {
+                error!("Re-signing run {} failed: {}", run_id, e);
+                ("FAILED", Some(e.to_string()))
+            }
+        };
+        if let Err(e) = sqlx::query(
+            "UPDATE audit_resign_runs SET status = $2, error = $3, completed_at = NOW() WHERE run_id = $1",
+        )
+        .bind(run_id)
+        .bind(status)
+        .bind(error_message)
+        .execute(&db)
+        .await
+        {
+            error!("Failed to finalize re-signing run {}: {}", run_id, e);
+        }
+    });
+
+    Ok(run)
+}
+
+async fn resign_events(
+    db: &PgPool,
+    documents: &dyn DocumentStore,
+    signer: &EventSigner,
+    cache: &AuditCache,
+    run_id: Uuid,
+    request: &ResignRequest,
+) -> anyhow::Result<()> {
+    let mut events = documents
+        .signed_with(&request.compromised_key_id, request.include_unlabelled, request.tenant_id)
+        .await?;
+    let (mut resigned, mut skipped) = (0i64, 0i64);
+
+    while let Some(event) = events.try_next().await? {
+        let computed_hash = integrity::sha256_hex(&integrity::canonical_payload(&event)?);
+
+        if event.event_hash.as_deref() != Some(computed_hash.as_str()) {
+            sqlx::query(
+                "INSERT INTO audit_resign_skips (run_id, event_id, reason) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            )
+            .bind(run_id)
+            .bind(event.event_id)
+            .bind("payload no longer matches recorded hash")
+            .execute(db)
+            .await?;
+            skipped += 1;
+            warn!("Skipped re-signing tampered audit event {}", event.event_id);
+            continue;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_signature_history (event_id, run_id, key_id, signature, event_hash)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(event.event_id)
+        .bind(run_id)
+        .bind(&event.signing_key_id)
+        .bind(&event.signature)
+        .bind(&computed_hash)
+        .execute(db)
+        .await?;
+
+        let signature = signer.sign(&computed_hash);
+        documents
+            .update(
+                event.event_id,
+                DocumentUpdate::Signature {
+                    signature: &signature,
+                    signing_key_id: signer.current_key_id(),
+                },
+            )
+            .await?;
+        cache.invalidate_event(event.tenant_id, event.event_id).await;
+        resigned += 1;
+
+        if (resigned + skipped) % 500 == 0 {
+            update_progress(db, run_id, resigned, skipped).await?;
+        }
+    }
+
+    update_progress(db, run_id, resigned, skipped).await?;
+    info!(
+        "Re-signing run {} finished: {} re-signed, {} skipped",
+        run_id, resigned, skipped
+    );
+    Ok(())
+}
+
+async fn update_progress(db: &PgPool, run_id: Uuid, resigned: i64, skipped: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE audit_resign_runs SET events_resigned = $2, events_skipped = $3 WHERE run_id = $1")
+        .bind(run_id)
+        .bind(resigned)
+        .bind(skipped)
+        .execute(db)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,740 @@
+//! Audit retention and cold storage archival
+//!
+//! Each tenant has retention rules per resource type (or a catch-all): events
+//! stay online for `online_days`, after which the nightly job writes them one
+//! UTC day at a time to a gzip NDJSON archive in object storage and removes
+//! them from Postgres, the document store and the IPFS pin set. Archives hold
+//! the full signed documents, so hashes, signatures and blockchain anchors still
+//! verify after a restore, and are object-locked until the rule's total
+//! `retention_days` has passed. Restores load an archived date range into
+//! audit_restored_events for a limited time rather than back into audit_logs,
+//! so restored events are never archived twice.
+//!
+//! Archival is refused, and the attempt recorded, while WORM mode is enforced;
+//! see `worm`.
+
+use aws_sdk_s3::primitives::{ByteStream, DateTime as S3DateTime};
+use aws_sdk_s3::types::{ObjectLockMode, StorageClass};
+use aws_sdk_s3::Client as S3Client;
+use chrono::{Duration, NaiveDate, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Arc;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::integrity;
+use crate::pins::PinRegistry;
+use crate::store::DocumentStore;
+use crate::worm::{self, WormRefusal};
+use crate::AuditEvent;
+
+/// Oldest days archived per rule and run, so a backlog drains over several nights
+const MAX_DAYS_PER_RUN: i64 = 31;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionSettings {
+    /// Lower bound on `retention_days` (SEBI requires eight years for most records)
+    pub min_retention_days: i32,
+    pub restore_ttl_days: i64,
+}
+
+impl RetentionSettings {
+    pub fn from_env() -> Self {
+        Self {
+            min_retention_days: std::env::var("AUDIT_MIN_RETENTION_DAYS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(2922),
+            restore_ttl_days: std::env::var("AUDIT_RESTORE_TTL_DAYS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(30),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, sqlx::FromRow)]
+pub struct RetentionRule {
+    pub resource_type: Option<String>,
+    pub online_days: i32,
+    pub retention_days: i32,
+    pub regulatory_reference: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    pub rules: Vec<RetentionRule>,
+    pub updated_by: Option<Uuid>,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct AuditArchive {
+    pub archive_id: Uuid,
+    pub tenant_id: Uuid,
+    pub resource_type: Option<String>,
+    pub archived_date: NaiveDate,
+    pub status: String,
+    pub event_count: i32,
+    pub object_uri: Option<String>,
+    pub content_sha256: Option<String>,
+    pub compressed_bytes: Option<i64>,
+    pub retain_until: Option<NaiveDate>,
+    pub copies_purged: bool,
+    pub error: Option<String>,
+    pub created_at: Option<chrono::DateTime<Utc>>,
+    pub archived_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    pub tenant_id: Uuid,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub reason: String,
+    pub requested_by: Option<Uuid>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct RestoreRun {
+    pub restore_id: Uuid,
+    pub tenant_id: Uuid,
+    pub range_start: NaiveDate,
+    pub range_end: NaiveDate,
+    pub reason: String,
+    pub requested_by: Option<Uuid>,
+    pub status: String,
+    pub archives_restored: i32,
+    pub events_restored: i32,
+    pub events_failed_verification: i32,
+    pub error: Option<String>,
+    pub expires_at: chrono::DateTime<Utc>,
+    pub started_at: Option<chrono::DateTime<Utc>>,
+    pub completed_at: Option<chrono::DateTime<Utc>>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct RestoredEvent {
+    pub event_id: Uuid,
+    pub archive_id: Uuid,
+    pub integrity_verified: bool,
+    pub event: serde_json::Value,
+}
+
+#[derive(Serialize, Default, Debug)]
+pub struct ArchivalSummary {
+    pub archives_written: usize,
+    pub events_archived: usize,
+    pub archives_failed: usize,
+    pub purges_retried: usize,
+    pub restores_expired: u64,
+}
+
+pub fn validate_rules(rules: &[RetentionRule], settings: &RetentionSettings) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut scopes = std::collections::HashSet::new();
+    for rule in rules {
+        let scope = rule.resource_type.as_deref().unwrap_or("*");
+        if !scopes.insert(scope) {
+            errors.push(format!("duplicate retention rule for '{}'", scope));
+        }
+        if rule.online_days <= 0 {
+            errors.push(format!("'{}' online_days must be positive", scope));
+        }
+        if rule.retention_days < rule.online_days {
+            errors.push(format!("'{}' retention_days must be at least online_days", scope));
+        }
+        if rule.retention_days < settings.min_retention_days {
+            errors.push(format!(
+                "'{}' retention_days must be at least {} days",
+                scope, settings.min_retention_days
+            ));
+        }
+    }
+    errors
+}
+
+pub async fn load_rules(db: &PgPool, tenant_id: Uuid) -> Result<Vec<RetentionRule>, sqlx::Error> {
+    sqlx::query_as::<_, RetentionRule>(
+        r#"
+        SELECT resource_type, online_days, retention_days, regulatory_reference
+        FROM audit_retention_rules
+        WHERE tenant_id = $1
+        ORDER BY resource_type NULLS FIRST
+        "#,
+    )
+    .bind(tenant_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Replace the tenant's rules; callers validate first
+pub async fn replace_rules(db: &PgPool, tenant_id: Uuid, policy: &RetentionPolicy) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    sqlx::query("DELETE FROM audit_retention_rules WHERE tenant_id = $1")
+        .bind(tenant_id)
+        .execute(&mut *tx)
+        .await?;
+    for rule in &policy.rules {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_retention_rules
+                (tenant_id, resource_type, online_days, retention_days, regulatory_reference, updated_by)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(&rule.resource_type)
+        .bind(rule.online_days)
+        .bind(rule.retention_days)
+        .bind(&rule.regulatory_reference)
+        .bind(policy.updated_by)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn list_archives(db: &PgPool, tenant_id: Uuid) -> Result<Vec<AuditArchive>, sqlx::Error> {
+    sqlx::query_as::<_, AuditArchive>(
+        r#"
+        SELECT archive_id, tenant_id, resource_type, archived_date, status, event_count, object_uri,
+               content_sha256, compressed_bytes, retain_until, copies_purged, error, created_at, archived_at
+        FROM audit_archives
+        WHERE tenant_id = $1
+        ORDER BY archived_date DESC, created_at DESC
+        "#,
+    )
+    .bind(tenant_id)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn get_restore(db: &PgPool, restore_id: Uuid) -> Result<Option<RestoreRun>, sqlx::Error> {
+    sqlx::query_as::<_, RestoreRun>("SELECT * FROM audit_archive_restores WHERE restore_id = $1")
+        .bind(restore_id)
+        .fetch_optional(db)
+        .await
+}
+
+pub async fn restored_events(
+    db: &PgPool,
+    restore_id: Uuid,
+    hidden_resource_types: &[String],
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<RestoredEvent>, sqlx::Error> {
+    sqlx::query_as::<_, RestoredEvent>(
+        r#"
+        SELECT event_id, archive_id, integrity_verified, event
+        FROM audit_restored_events
+        WHERE restore_id = $1 AND COALESCE(event ->> 'resource_type', '') <> ALL($2)
+        ORDER BY timestamp, event_id
+        LIMIT $3 OFFSET $4
+        "#,
+    )
+    .bind(restore_id)
+    .bind(hidden_resource_types)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(db)
+    .await
+}
+
+/// Object storage holding the archives, configured with AUDIT_ARCHIVE_BUCKET
+pub struct ArchiveStore {
+    client: S3Client,
+    bucket: String,
+    prefix: String,
+    /// Requires a bucket created with S3 Object Lock enabled
+    object_lock: bool,
+}
+
+impl ArchiveStore {
+    pub async fn from_env() -> Option<Self> {
+        let bucket = std::env::var("AUDIT_ARCHIVE_BUCKET").ok().filter(|b| !b.is_empty())?;
+        let config = aws_config::load_from_env().await;
+        Some(Self {
+            client: S3Client::new(&config),
+            bucket,
+            prefix: std::env::var("AUDIT_ARCHIVE_PREFIX").unwrap_or_else(|_| "audit-archives".to_string()),
+            object_lock: std::env::var("AUDIT_ARCHIVE_OBJECT_LOCK").map_or(false, |v| v == "true"),
+        })
+    }
+
+    fn key(&self, archive_id: Uuid, tenant_id: Uuid, day: NaiveDate, resource_type: Option<&str>) -> String {
+        format!(
+            "{}/{}/{}/{}-{}-{}.ndjson.gz",
+            self.prefix,
+            tenant_id,
+            day.format("%Y/%m"),
+            day,
+            resource_type.unwrap_or("all"),
+            archive_id
+        )
+    }
+
+    fn uri(&self, key: &str) -> String {
+        format!("s3://{}/{}", self.bucket, key)
+    }
+
+    fn key_from_uri<'a>(&self, uri: &'a str) -> Option<&'a str> {
+        uri.strip_prefix("s3://")?.strip_prefix(self.bucket.as_str())?.strip_prefix('/')
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>, retain_until: NaiveDate) -> anyhow::Result<()> {
+        let expected_len = body.len() as i64;
+        let mut put = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type("application/x-ndjson")
+            .content_encoding("gzip")
+            .storage_class(StorageClass::GlacierIr)
+            .body(ByteStream::from(body));
+        if self.object_lock {
+            let until = retain_until.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+            put = put
+                .object_lock_mode(ObjectLockMode::Compliance)
+                .object_lock_retain_until_date(S3DateTime::from_secs(until.timestamp()));
+        }
+        put.send().await?;
+
+        // Nothing is deleted from the online stores unless the object is really there
+        let head = self.client.head_object().bucket(&self.bucket).key(key).send().await?;
+        if head.content_length() != Some(expected_len) {
+            anyhow::bail!(
+                "archive object {} has {:?} bytes, expected {}",
+                key,
+                head.content_length(),
+                expected_len
+            );
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let object = self.client.get_object().bucket(&self.bucket).key(key).send().await?;
+        Ok(object.body.collect().await?.into_bytes().to_vec())
+    }
+}
+
+pub struct Archiver {
+    db: PgPool,
+    documents: Arc<dyn DocumentStore>,
+    pins: Arc<PinRegistry>,
+    store: ArchiveStore,
+    settings: RetentionSettings,
+}
+
+impl Archiver {
+    pub fn new(
+        db: PgPool,
+        documents: Arc<dyn DocumentStore>,
+        pins: Arc<PinRegistry>,
+        store: ArchiveStore,
+        settings: RetentionSettings,
+    ) -> Self {
+        Self {
+            db,
+            documents,
+            pins,
+            store,
+            settings,
+        }
+    }
+
+    /// Archive expired events nightly at 03:30 UTC, after reconciliation
+    pub async fn schedule(self: Arc<Self>) -> anyhow::Result<JobScheduler> {
+        let scheduler = JobScheduler::new().await?;
+
+        let job = Job::new_async("0 30 3 * * *", move |_uuid, _l| {
+            let archiver = self.clone();
+            Box::pin(async move {
+                match archiver.run().await {
+                    Ok(summary) => info!("Scheduled audit archival finished: {:?}", summary),
+                    Err(e) => error!("Scheduled audit archival failed: {}", e),
+                }
+            })
+        })?;
+
+        scheduler.add(job).await?;
+        scheduler.start().await?;
+        Ok(scheduler)
+    }
+
+    pub async fn run(&self) -> anyhow::Result<ArchivalSummary> {
+        if let Some(worm) = worm::enforced(&self.db).await? {
+            worm::refuse(&self.db, "ARCHIVAL", None, serde_json::json!({"worm_enabled_at": worm.enabled_at})).await?;
+            return Err(WormRefusal(worm.enabled_at, "retention archival").into());
+        }
+        let mut summary = ArchivalSummary {
+            restores_expired: self.expire_restores().await?,
+            ..Default::default()
+        };
+
+        // Finish purges interrupted on an earlier run before archiving more
+        let unpurged: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT archive_id FROM audit_archives WHERE status = 'ARCHIVED' AND NOT copies_purged",
+        )
+        .fetch_all(&self.db)
+        .await?;
+        for archive_id in unpurged {
+            self.purge_copies(archive_id).await?;
+            summary.purges_retried += 1;
+        }
+
+        let rules: Vec<(Uuid, Option<String>, i32, i32)> = sqlx::query_as(
+            "SELECT tenant_id, resource_type, online_days, retention_days FROM audit_retention_rules",
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        for (tenant_id, resource_type, online_days, retention_days) in rules {
+            let cutoff = Utc::now().date_naive() - Duration::days(online_days as i64);
+            let days: Vec<NaiveDate> = sqlx::query_scalar(
+                r#"
+                SELECT DISTINCT (timestamp AT TIME ZONE 'UTC')::date AS day
+                FROM audit_logs
+                WHERE tenant_id = $1
+                  AND timestamp < ($2::date)::timestamp AT TIME ZONE 'UTC'
+                  AND (($3::text IS NOT NULL AND resource_type = $3)
+                    OR ($3::text IS NULL AND resource_type NOT IN (
+                        SELECT resource_type FROM audit_retention_rules
+                        WHERE tenant_id = $1 AND resource_type IS NOT NULL)))
+                ORDER BY day
+                LIMIT $4
+                "#,
+            )
+            .bind(tenant_id)
+            .bind(cutoff)
+            .bind(&resource_type)
+            .bind(MAX_DAYS_PER_RUN)
+            .fetch_all(&self.db)
+            .await?;
+
+            for day in days {
+                let retain_until = day + Duration::days(retention_days as i64);
+                match self.archive_day(tenant_id, resource_type.as_deref(), day, retain_until).await {
+                    Ok(count) => {
+                        summary.archives_written += 1;
+                        summary.events_archived += count;
+                    }
+                    Err(e) => {
+                        summary.archives_failed += 1;
+                        error!("Failed to archive {} for tenant {}: {}", day, tenant_id, e);
+                    }
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    async fn archive_day(
+        &self,
+        tenant_id: Uuid,
+        resource_type: Option<&str>,
+        day: NaiveDate,
+        retain_until: NaiveDate,
+    ) -> anyhow::Result<usize> {
+        let start = day.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+        let end = start + Duration::days(1);
+
+        let ids: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT log_id FROM audit_logs
+            WHERE tenant_id = $1 AND timestamp >= $2 AND timestamp < $3
+              AND (($4::text IS NOT NULL AND resource_type = $4)
+                OR ($4::text IS NULL AND resource_type NOT IN (
+                    SELECT resource_type FROM audit_retention_rules
+                    WHERE tenant_id = $1 AND resource_type IS NOT NULL)))
+            ORDER BY timestamp, log_id
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(start)
+        .bind(end)
+        .bind(resource_type)
+        .fetch_all(&self.db)
+        .await?;
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let archive_id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO audit_archives (tenant_id, resource_type, archived_date, retain_until, event_ids)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING archive_id
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(resource_type)
+        .bind(day)
+        .bind(retain_until)
+        .bind(&ids)
+        .fetch_one(&self.db)
+        .await?;
+
+        match self.write_archive(archive_id, tenant_id, resource_type, day, retain_until, &ids).await {
+            Ok(()) => {
+                self.purge_copies(archive_id).await?;
+                info!("Archived {} audit events of {} for tenant {}", ids.len(), day, tenant_id);
+                Ok(ids.len())
+            }
+            Err(e) => {
+                sqlx::query("UPDATE audit_archives SET status = 'FAILED', error = $2 WHERE archive_id = $1")
+                    .bind(archive_id)
+                    .bind(e.to_string())
+                    .execute(&self.db)
+                    .await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Upload the archive, then delete the Postgres rows in the same step that marks it ARCHIVED
+    async fn write_archive(
+        &self,
+        archive_id: Uuid,
+        tenant_id: Uuid,
+        resource_type: Option<&str>,
+        day: NaiveDate,
+        retain_until: NaiveDate,
+        ids: &[Uuid],
+    ) -> anyhow::Result<()> {
+        let documents = self.documents.get_many(ids).await?;
+        // The archive must be complete; reconciliation reports the missing documents
+        if documents.len() != ids.len() {
+            anyhow::bail!(
+                "{} of {} events have no {} document",
+                ids.len() - documents.len(),
+                ids.len(),
+                self.documents.backend()
+            );
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        let mut ipfs_hashes = Vec::new();
+        for id in ids {
+            let event = &documents[id];
+            serde_json::to_writer(&mut encoder, event)?;
+            encoder.write_all(b"\n")?;
+            if let Some(ipfs_hash) = &event.ipfs_hash {
+                ipfs_hashes.push(ipfs_hash.clone());
+            }
+        }
+        let body = encoder.finish()?;
+        let content_sha256 = integrity::sha256_hex(&body);
+        let compressed_bytes = body.len() as i64;
+
+        let key = self.store.key(archive_id, tenant_id, day, resource_type);
+        self.store.put(&key, body, retain_until).await?;
+
+        let mut tx = self.db.begin().await?;
+        sqlx::query("DELETE FROM audit_logs WHERE log_id = ANY($1)")
+            .bind(ids)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            UPDATE audit_archives
+            SET status = 'ARCHIVED', event_count = $2, object_uri = $3, content_sha256 = $4,
+                compressed_bytes = $5, ipfs_hashes = $6, archived_at = NOW()
+            WHERE archive_id = $1
+            "#,
+        )
+        .bind(archive_id)
+        .bind(ids.len() as i32)
+        .bind(self.store.uri(&key))
+        .bind(&content_sha256)
+        .bind(compressed_bytes)
+        .bind(&ipfs_hashes)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Remove the stored documents and IPFS pins of an archived day; safe to repeat
+    async fn purge_copies(&self, archive_id: Uuid) -> anyhow::Result<()> {
+        let (ids, ipfs_hashes): (Vec<Uuid>, Vec<String>) =
+            sqlx::query_as("SELECT event_ids, ipfs_hashes FROM audit_archives WHERE archive_id = $1")
+                .bind(archive_id)
+                .fetch_one(&self.db)
+                .await?;
+
+        self.documents.delete_many(&ids).await?;
+
+        // An unpinned copy is only garbage-collected eventually, so a failure here is not fatal
+        for ipfs_hash in &ipfs_hashes {
+            if let Err(e) = self.pins.unpin(ipfs_hash).await {
+                warn!("Failed to unpin archived IPFS document {}: {}", ipfs_hash, e);
+            }
+        }
+
+        sqlx::query("UPDATE audit_archives SET copies_purged = TRUE WHERE archive_id = $1")
+            .bind(archive_id)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn expire_restores(&self) -> Result<u64, sqlx::Error> {
+        let expired = sqlx::query(
+            r#"
+            UPDATE audit_archive_restores SET status = 'EXPIRED'
+            WHERE expires_at < NOW() AND status IN ('COMPLETED', 'FAILED')
+            "#,
+        )
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        sqlx::query(
+            r#"
+            DELETE FROM audit_restored_events
+            WHERE restore_id IN (SELECT restore_id FROM audit_archive_restores WHERE status = 'EXPIRED')
+            "#,
+        )
+        .execute(&self.db)
+        .await?;
+        Ok(expired)
+    }
+
+    /// Record the restore and load the archived range in the background
+    pub async fn start_restore(self: Arc<Self>, request: RestoreRequest) -> anyhow::Result<RestoreRun> {
+        let run = sqlx::query_as::<_, RestoreRun>(
+            r#"
+            INSERT INTO audit_archive_restores (tenant_id, range_start, range_end, reason, requested_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(days => $6))
+            RETURNING *
+            "#,
+        )
+        .bind(request.tenant_id)
+        .bind(request.from)
+        .bind(request.to)
+        .bind(request.reason.trim())
+        .bind(request.requested_by)
+        .bind(self.settings.restore_ttl_days as i32)
+        .fetch_one(&self.db)
+        .await?;
+
+        info!(
+            "Restoring archived audit events {}..{} for tenant {}: {}",
+            run.range_start, run.range_end, run.tenant_id, run.reason
+        );
+
+        let restore_id = run.restore_id;
+        tokio::spawn(async move {
+            let outcome = self.restore_range(restore_id, &request).await;
+            let (status, error_message) = match &outcome {
+                Ok(()) => ("COMPLETED", None),
+                Err(e) => {
+                    error!("Audit archive restore {} failed: {}", restore_id, e);
+                    ("FAILED", Some(e.to_string()))
+                }
+            };
+            if let Err(e) = sqlx::query(
+                "UPDATE audit_archive_restores SET status = $2, error = $3, completed_at = NOW() WHERE restore_id = $1",
+            )
+            .bind(restore_id)
+            .bind(status)
+            .bind(error_message)
+            .execute(&self.db)
+            .await
+            {
+                error!("Failed to finalize audit archive restore {}: {}", restore_id, e);
+            }
+        });
+
+        Ok(run)
+    }
+
+    async fn restore_range(&self, restore_id: Uuid, request: &RestoreRequest) -> anyhow::Result<()> {
+        let archives: Vec<(Uuid, String, String)> = sqlx::query_as(
+            r#"
+            SELECT archive_id, object_uri, content_sha256
+            FROM audit_archives
+            WHERE tenant_id = $1 AND status = 'ARCHIVED' AND archived_date BETWEEN $2 AND $3
+            ORDER BY archived_date
+            "#,
+        )
+        .bind(request.tenant_id)
+        .bind(request.from)
+        .bind(request.to)
+        .fetch_all(&self.db)
+        .await?;
+
+        let (mut restored, mut failed) = (0i32, 0i32);
+        for (index, (archive_id, object_uri, content_sha256)) in archives.iter().enumerate() {
+            let key = self
+                .store
+                .key_from_uri(object_uri)
+                .ok_or_else(|| anyhow::anyhow!("archive {} is stored outside the configured bucket", archive_id))?;
+            let body = self.store.get(key).await?;
+            if integrity::sha256_hex(&body) != *content_sha256 {
+                anyhow::bail!("archive {} does not match its recorded checksum", archive_id);
+            }
+
+            for line in BufReader::new(GzDecoder::new(body.as_slice())).lines() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                let event: AuditEvent = serde_json::from_str(&line)?;
+                let computed_hash = integrity::sha256_hex(&integrity::canonical_payload(&event)?);
+                let verified = event.event_hash.as_deref() == Some(computed_hash.as_str());
+                if !verified {
+                    warn!("Restored audit event {} does not match its recorded hash", event.event_id);
+                    failed += 1;
+                }
+
+                sqlx::query(
+                    r#"
+                    INSERT INTO audit_restored_events (restore_id, event_id, archive_id, timestamp, integrity_verified, event)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT DO NOTHING
+                    "#,
+                )
+                .bind(restore_id)
+                .bind(event.event_id)
+                .bind(archive_id)
+                .bind(event.timestamp)
+                .bind(verified)
+                .bind(serde_json::to_value(&event)?)
+                .execute(&self.db)
+                .await?;
+                restored += 1;
+            }
+
+            sqlx::query(
+                r#"
+                UPDATE audit_archive_restores
+                SET archives_restored = $2, events_restored = $3, events_failed_verification = $4
+                WHERE restore_id = $1
+                "#,
+            )
+            .bind(restore_id)
+            .bind(index as i32 + 1)
+            .bind(restored)
+            .bind(failed)
+            .execute(&self.db)
+            .await?;
+        }
+
+        info!(
+            "Audit archive restore {} finished: {} events from {} archives, {} failed verification",
+            restore_id,
+            restored,
+            archives.len(),
+            failed
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,644 @@
+//! Recovery runbooks run as confirmed, tracked jobs
+//!
+//! The usual recoveries after an outage used to need psql on the production
+//! database. They are now requested with POST /admin/runbooks/:runbook/jobs:
+//!
+//! - `audit_queue_reprocess` returns every DEAD write-ahead log entry to the
+//!   queue and follows the worker's replay until each is stored or dead again,
+//!   or AUDIT_RUNBOOK_QUEUE_WAIT_SECS pass;
+//! - `projection_rebuild` restores the tenant's missing documents in the
+//!   document store (MongoDB, or Postgres per AUDIT_DOCUMENT_STORE) from the
+//!   IPFS copy of each event, or from its audit row when the deployment keeps
+//!   no copies. Copies that no longer match their event are skipped, never
+//!   restored, and restored documents are signed with the current key;
+//! - `webhook_redelivery` posts the failing integrity checks completed on a
+//!   UTC day whose webhook never went out;
+//! - `anchor_backfill` anchors what accumulated in pending anchor mode once a
+//!   chain is configured: the roots of PENDING_ANCHOR digests, then every
+//!   pending event anchor in full batches regardless of gas pacing.
+//!
+//! A request only counts what the job would touch and answers with a one-time
+//! confirmation token, kept as its SHA-256. Nothing runs until the token is
+//! posted to /admin/runbooks/jobs/:job_id/confirm within
+//! AUDIT_RUNBOOK_CONFIRMATION_TTL_SECS, and a runbook never runs twice at
+//! once. Progress and outcome are kept in operator_runbook_jobs.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder, Row};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::Caller;
+use crate::digest;
+use crate::integrity;
+use crate::outbox::{self, AnchorPacing, Operation, OutboxSettings};
+use crate::sweep;
+use crate::wal::QueueState;
+use crate::{audit_event_from_row, AppState, AuditEvent, AUDIT_LOG_COLUMNS};
+
+/// Audit rows checked against the document store at once
+const REBUILD_CHUNK: i64 = 500;
+/// Failed items listed on a job before the rest are only counted
+const MAX_RECORDED_FAILURES: usize = 200;
+const QUEUE_POLL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct RunbookSettings {
+    pub confirmation_ttl: Duration,
+    /// How long a queue reprocess follows requeued entries
+    pub queue_wait: Duration,
+}
+
+impl RunbookSettings {
+    pub fn from_env() -> Self {
+        let secs = |name: &str, default: u64| {
+            Duration::from_secs(
+                std::env::var(name)
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(default),
+            )
+        };
+        Self {
+            confirmation_ttl: secs("AUDIT_RUNBOOK_CONFIRMATION_TTL_SECS", 900),
+            queue_wait: secs("AUDIT_RUNBOOK_QUEUE_WAIT_SECS", 600),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Runbook {
+    AuditQueueReprocess,
+    ProjectionRebuild,
+    WebhookRedelivery,
+    AnchorBackfill,
+}
+
+impl Runbook {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::AuditQueueReprocess => "audit_queue_reprocess",
+            Self::ProjectionRebuild => "projection_rebuild",
+            Self::WebhookRedelivery => "webhook_redelivery",
+            Self::AnchorBackfill => "anchor_backfill",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        [Self::AuditQueueReprocess, Self::ProjectionRebuild, Self::WebhookRedelivery, Self::AnchorBackfill]
+            .into_iter()
+            .find(|runbook| runbook.as_str() == value)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RunbookRequest {
+    /// Why the runbook is needed, kept on the job
+    pub reason: String,
+    /// projection_rebuild only
+    pub tenant_id: Option<Uuid>,
+    /// webhook_redelivery only; the UTC day the checks completed
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmRequest {
+    pub confirmation_token: String,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct RunbookJob {
+    pub job_id: Uuid,
+    pub runbook: String,
+    pub tenant_id: Option<Uuid>,
+    pub target_date: Option<NaiveDate>,
+    pub reason: String,
+    /// AWAITING_CONFIRMATION, RUNNING, COMPLETED, FAILED or EXPIRED
+    pub status: String,
+    pub preview: serde_json::Value,
+    pub expires_at: DateTime<Utc>,
+    pub requested_by: Option<Uuid>,
+    pub requested_by_service: Option<String>,
+    pub confirmed_by: Option<Uuid>,
+    pub confirmed_by_service: Option<String>,
+    pub items_processed: i64,
+    pub items_failed: i64,
+    pub result: serde_json::Value,
+    pub error: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub progressed_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// A requested job with the token that confirms it, returned only once
+#[derive(Serialize)]
+pub struct PlannedJob {
+    #[serde(flatten)]
+    pub job: RunbookJob,
+    pub confirmation_token: String,
+}
+
+#[derive(Debug, Error)]
+pub enum RunbookError {
+    #[error("{0}")]
+    Invalid(&'static str),
+    #[error("{0}")]
+    Unavailable(&'static str),
+    #[error("runbook job not found")]
+    NotFound,
+    #[error("confirmation token is wrong, expired or already used")]
+    Rejected,
+    #[error("a {0} job is already running")]
+    AlreadyRunning(String),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Failed(#[from] anyhow::Error),
+}
+
+/// Tally of a running job, written back as it progresses
+#[derive(Default)]
+struct Progress {
+    processed: i64,
+    failed: i64,
+    failures: Vec<serde_json::Value>,
+    result: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Progress {
+    fn fail(&mut self, item: serde_json::Value) {
+        self.failed += 1;
+        if self.failures.len() < MAX_RECORDED_FAILURES {
+            self.failures.push(item);
+        }
+    }
+
+    fn count(&mut self, key: &str) {
+        let count = self.result.get(key).and_then(|value| value.as_i64()).unwrap_or(0);
+        self.result.insert(key.to_string(), (count + 1).into());
+    }
+
+    fn result(&self) -> serde_json::Value {
+        let mut result = self.result.clone();
+        result.insert("failures".to_string(), self.failures.clone().into());
+        result.into()
+    }
+}
+
+fn actor(caller: &Caller) -> (Option<Uuid>, Option<String>) {
+    match caller {
+        Caller::User { user_id, .. } => (Some(*user_id), None),
+        Caller::Service { name } | Caller::ApiKey { service: name, .. } => (None, Some(name.clone())),
+    }
+}
+
+pub async fn get_job(db: &PgPool, job_id: Uuid) -> Result<Option<RunbookJob>, sqlx::Error> {
+    sqlx::query_as::<_, RunbookJob>("SELECT * FROM operator_runbook_jobs WHERE job_id = $1")
+        .bind(job_id)
+        .fetch_optional(db)
+        .await
+}
+
+pub async fn list_jobs(db: &PgPool, limit: i64) -> Result<Vec<RunbookJob>, sqlx::Error> {
+    // Unconfirmed jobs past their deadline can no longer run
+    sqlx::query(
+        "UPDATE operator_runbook_jobs SET status = 'EXPIRED' WHERE status = 'AWAITING_CONFIRMATION' AND expires_at <= NOW()",
+    )
+    .execute(db)
+    .await?;
+    sqlx::query_as::<_, RunbookJob>("SELECT * FROM operator_runbook_jobs ORDER BY requested_at DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(db)
+        .await
+}
+
+/// Record a job with what it would touch; it waits for confirmation
+pub async fn plan(
+    state: &AppState,
+    settings: &RunbookSettings,
+    runbook: Runbook,
+    request: RunbookRequest,
+    caller: &Caller,
+) -> Result<PlannedJob, RunbookError> {
+    if request.reason.trim().len() < 10 {
+        return Err(RunbookError::Invalid("reason must be at least 10 characters"));
+    }
+    let preview = match runbook {
+        Runbook::AuditQueueReprocess => {
+            let queue = state
+                .write_ahead
+                .as_ref()
+                .ok_or(RunbookError::Unavailable("the write-ahead log is not configured (AUDIT_WAL_DIR)"))?;
+            let summary = queue.summary()?;
+            serde_json::json!({"dead_entries": summary.dead, "queued_entries": summary.queued})
+        }
+        Runbook::ProjectionRebuild => {
+            let tenant_id = request
+                .tenant_id
+                .ok_or(RunbookError::Invalid("projection_rebuild needs a tenant_id"))?;
+            let audit_rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_logs WHERE tenant_id = $1")
+                .bind(tenant_id)
+                .fetch_one(&state.db)
+                .await?;
+            serde_json::json!({
+                "audit_rows": audit_rows,
+                "document_store": state.documents.backend(),
+                "source": if state.anchors.keeps_copies() { "ipfs" } else { "audit_logs" },
+            })
+        }
+        Runbook::WebhookRedelivery => {
+            let date = request
+                .date
+                .ok_or(RunbookError::Invalid("webhook_redelivery needs a date"))?;
+            if state.sweep_settings.webhook_url.is_none() {
+                return Err(RunbookError::Unavailable("AUDIT_INTEGRITY_WEBHOOK_URL is not set"));
+            }
+            let checks = sweep::undelivered(&state.db, date).await?;
+            serde_json::json!({
+                "undelivered_checks": checks.len(),
+                "check_ids": checks.iter().map(|check| check.check_id).collect::<Vec<_>>(),
+            })
+        }
+        Runbook::AnchorBackfill => {
+            if state.blockchain_client.is_none() {
+                return Err(RunbookError::Unavailable("blockchain anchoring is disabled (AUDIT_BLOCKCHAIN_ENABLED=false)"));
+            }
+            serde_json::json!({
+                "pending_anchors": outbox::pending_anchors(&state.db).await?,
+                "pending_digests": digest::pending_digests(&state.db).await?,
+            })
+        }
+    };
+
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    let token = hex::encode(token);
+    let (requested_by, requested_by_service) = actor(caller);
+    let expires_at = Utc::now() + chrono::Duration::from_std(settings.confirmation_ttl).unwrap_or_else(|_| chrono::Duration::minutes(15));
+    let job = sqlx::query_as::<_, RunbookJob>(
+        r#"
+        INSERT INTO operator_runbook_jobs (
+            runbook, tenant_id, target_date, reason, preview, confirmation_hash, expires_at,
+            requested_by, requested_by_service
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING *
+        "#,
+    )
+    .bind(runbook.as_str())
+    .bind(request.tenant_id.filter(|_| runbook == Runbook::ProjectionRebuild))
+    .bind(request.date.filter(|_| runbook == Runbook::WebhookRedelivery))
+    .bind(request.reason.trim())
+    .bind(&preview)
+    .bind(integrity::sha256_hex(token.as_bytes()))
+    .bind(expires_at)
+    .bind(requested_by)
+    .bind(&requested_by_service)
+    .fetch_one(&state.db)
+    .await?;
+
+    info!("Runbook job {} ({}) awaits confirmation: {}", job.job_id, job.runbook, job.reason);
+    Ok(PlannedJob { job, confirmation_token: token })
+}
+
+/// Start a job whose confirmation token matches, and run it in the background
+pub async fn confirm(
+    state: &AppState,
+    settings: &RunbookSettings,
+    job_id: Uuid,
+    request: &ConfirmRequest,
+    caller: &Caller,
+) -> Result<RunbookJob, RunbookError> {
+    let Some(existing) = get_job(&state.db, job_id).await? else {
+        return Err(RunbookError::NotFound);
+    };
+    // A replica that restarted mid-job never finishes it
+    sqlx::query(
+        r#"
+        UPDATE operator_runbook_jobs
+        SET status = 'FAILED', error = 'abandoned while running', completed_at = NOW()
+        WHERE runbook = $1 AND status = 'RUNNING' AND progressed_at < NOW() - INTERVAL '1 hour'
+        "#,
+    )
+    .bind(&existing.runbook)
+    .execute(&state.db)
+    .await?;
+
+    let (confirmed_by, confirmed_by_service) = actor(caller);
+    let started = sqlx::query_as::<_, RunbookJob>(
+        r#"
+        UPDATE operator_runbook_jobs
+        SET status = 'RUNNING', confirmed_by = $3, confirmed_by_service = $4, started_at = NOW(), progressed_at = NOW()
+        WHERE job_id = $1 AND status = 'AWAITING_CONFIRMATION' AND confirmation_hash = $2 AND expires_at > NOW()
+        RETURNING *
+        "#,
+    )
+    .bind(job_id)
+    .bind(integrity::sha256_hex(request.confirmation_token.trim().as_bytes()))
+    .bind(confirmed_by)
+    .bind(&confirmed_by_service)
+    .fetch_optional(&state.db)
+    .await;
+    let job = match started {
+        Ok(Some(job)) => job,
+        Ok(None) => return Err(RunbookError::Rejected),
+        // idx_runbook_jobs_running
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(RunbookError::AlreadyRunning(existing.runbook))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let runbook = Runbook::parse(&job.runbook).ok_or(RunbookError::Invalid("unknown runbook"))?;
+    warn!("Runbook job {} ({}) confirmed and started", job.job_id, job.runbook);
+
+    let state = state.clone();
+    let settings = settings.clone();
+    let running = job.clone();
+    tokio::spawn(async move {
+        let mut progress = Progress::default();
+        let outcome = match runbook {
+            Runbook::AuditQueueReprocess => reprocess_queue(&state, &settings, &running, &mut progress).await,
+            Runbook::ProjectionRebuild => rebuild_projections(&state, &running, &mut progress).await,
+            Runbook::WebhookRedelivery => redeliver_webhooks(&state, &running, &mut progress).await,
+            Runbook::AnchorBackfill => backfill_anchors(&state, &running, &mut progress).await,
+        };
+        let (status, error_message) = match &outcome {
+            Ok(()) => ("COMPLETED", None),
+            Err(e) => {
+                error!("Runbook job {} ({}) failed: {:#}", running.job_id, running.runbook, e);
+                ("FAILED", Some(format!("{:#}", e)))
+            }
+        };
+        if let Err(e) = sqlx::query(
+            r#"
+            UPDATE operator_runbook_jobs
+            SET status = $2, error = $3, items_processed = $4, items_failed = $5, result = $6,
+                progressed_at = NOW(), completed_at = NOW()
+            WHERE job_id = $1
+            "#,
+        )
+        .bind(running.job_id)
+        .bind(status)
+        .bind(error_message)
+        .bind(progress.processed)
+        .bind(progress.failed)
+        .bind(progress.result())
+        .execute(&state.db)
+        .await
+        {
+            error!("Failed to finalize runbook job {}: {}", running.job_id, e);
+        } else {
+            info!(
+                "Runbook job {} ({}) {}: {} processed, {} failed",
+                running.job_id,
+                running.runbook,
+                status.to_lowercase(),
+                progress.processed,
+                progress.failed
+            );
+        }
+    });
+
+    Ok(job)
+}
+
+async fn update_progress(db: &PgPool, job_id: Uuid, progress: &Progress) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE operator_runbook_jobs
+        SET items_processed = $2, items_failed = $3, result = $4, progressed_at = NOW()
+        WHERE job_id = $1
+        "#,
+    )
+    .bind(job_id)
+    .bind(progress.processed)
+    .bind(progress.failed)
+    .bind(progress.result())
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Requeue DEAD entries and follow them until they are stored or dead again
+async fn reprocess_queue(
+    state: &AppState,
+    settings: &RunbookSettings,
+    job: &RunbookJob,
+    progress: &mut Progress,
+) -> anyhow::Result<()> {
+    let queue = state
+        .write_ahead
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("the write-ahead log is not configured"))?;
+
+    let mut pending = HashSet::new();
+    for dead in queue.summary()?.dead_entries {
+        if queue.requeue(dead.event_id).await?.is_some() {
+            pending.insert(dead.event_id);
+            progress.count("requeued");
+        }
+    }
+    update_progress(&state.db, job.job_id, progress).await?;
+
+    // The write-ahead worker replays them; this only watches where they end up
+    let deadline = Instant::now() + settings.queue_wait;
+    while !pending.is_empty() && Instant::now() < deadline {
+        tokio::time::sleep(QUEUE_POLL).await;
+        let mut settled = Vec::new();
+        for &event_id in &pending {
+            match queue.status(event_id)? {
+                None => {
+                    progress.processed += 1;
+                    settled.push(event_id);
+                }
+                Some(status) if status.status == QueueState::Dead => {
+                    progress.fail(serde_json::json!({"event_id": event_id, "error": status.last_error}));
+                    settled.push(event_id);
+                }
+                Some(_) => {}
+            }
+        }
+        for event_id in settled {
+            pending.remove(&event_id);
+        }
+        update_progress(&state.db, job.job_id, progress).await?;
+    }
+    if !pending.is_empty() {
+        progress
+            .result
+            .insert("still_queued".to_string(), (pending.len() as i64).into());
+    }
+    Ok(())
+}
+
+/// How a missing document was restored
+enum Restored {
+    FromIpfs,
+    FromRow,
+}
+
+/// Put back the tenant's documents that are missing from the document store
+async fn rebuild_projections(state: &AppState, job: &RunbookJob, progress: &mut Progress) -> anyhow::Result<()> {
+    let tenant_id = job
+        .tenant_id
+        .ok_or_else(|| anyhow::anyhow!("projection_rebuild job has no tenant_id"))?;
+    let mut after: Option<(DateTime<Utc>, Uuid)> = None;
+
+    loop {
+        let mut select = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM audit_logs WHERE tenant_id = ", AUDIT_LOG_COLUMNS));
+        select.push_bind(tenant_id);
+        if let Some((timestamp, log_id)) = after {
+            select
+                .push(" AND (timestamp, log_id) > (")
+                .push_bind(timestamp)
+                .push(", ")
+                .push_bind(log_id)
+                .push(")");
+        }
+        select.push(" ORDER BY timestamp, log_id LIMIT ").push_bind(REBUILD_CHUNK);
+        let rows = select.build().fetch_all(&state.db).await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        after = Some((last.get("timestamp"), last.get("log_id")));
+
+        let rows: Vec<AuditEvent> = rows.iter().map(audit_event_from_row).collect();
+        let ids: Vec<Uuid> = rows.iter().map(|row| row.event_id).collect();
+        let present = state.documents.get_many(&ids).await?;
+        for row in rows {
+            progress.count("checked");
+            if present.contains_key(&row.event_id) {
+                continue;
+            }
+            match restore_document(state, row.clone()).await {
+                Ok(Restored::FromIpfs) => {
+                    progress.processed += 1;
+                    progress.count("restored_from_ipfs");
+                }
+                Ok(Restored::FromRow) => {
+                    progress.processed += 1;
+                    progress.count("restored_from_audit_row");
+                }
+                Err(e) => {
+                    warn!("Could not restore the document of audit event {}: {:#}", row.event_id, e);
+                    progress.fail(serde_json::json!({"event_id": row.event_id, "error": format!("{:#}", e)}));
+                }
+            }
+        }
+        update_progress(&state.db, job.job_id, progress).await?;
+    }
+    Ok(())
+}
+
+async fn restore_document(state: &AppState, row: AuditEvent) -> anyhow::Result<Restored> {
+    let (mut event, source) = if state.anchors.keeps_copies() {
+        let cid = stored_cid(&state.db, row.event_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("no IPFS copy is recorded for the event"))?;
+        let payload = state.copies.retrieve(row.tenant_id, row.event_id, &cid).await?;
+        let mut event: AuditEvent = serde_json::from_slice(&payload)?;
+        if event.event_id != row.event_id || event.tenant_id != row.tenant_id {
+            anyhow::bail!("IPFS copy {} belongs to another event", cid);
+        }
+        event.event_hash = Some(integrity::sha256_hex(&payload));
+        event.ipfs_hash = Some(cid);
+        (event, Restored::FromIpfs)
+    } else {
+        let mut event = row;
+        event.event_hash = Some(integrity::sha256_hex(&integrity::canonical_payload(&event)?));
+        (event, Restored::FromRow)
+    };
+
+    event.blockchain_hash = sqlx::query_scalar(
+        r#"
+        SELECT result FROM audit_anchor_outbox
+        WHERE event_id = $1 AND operation = $2 AND status = 'DONE'
+        ORDER BY completed_at DESC LIMIT 1
+        "#,
+    )
+    .bind(event.event_id)
+    .bind(Operation::BlockchainAnchor.as_str())
+    .fetch_optional(&state.db)
+    .await?
+    .flatten();
+    let hash = event.event_hash.clone().unwrap_or_default();
+    event.signature = Some(state.signer.sign(&hash));
+    event.signing_key_id = Some(state.signer.current_key_id().to_string());
+
+    state.documents.insert(&event).await?;
+    state.cache.invalidate_event(event.tenant_id, event.event_id).await;
+    Ok(source)
+}
+
+/// CID of the event's payload copy, whether pinned inline or from the outbox
+async fn stored_cid(db: &PgPool, event_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT cid FROM ipfs_pins WHERE event_id = $1 AND provider = 'LOCAL'
+        UNION ALL
+        SELECT result FROM audit_anchor_outbox
+        WHERE event_id = $1 AND operation = $2 AND status = 'DONE' AND result IS NOT NULL
+        LIMIT 1
+        "#,
+    )
+    .bind(event_id)
+    .bind(Operation::IpfsPin.as_str())
+    .fetch_optional(db)
+    .await
+}
+
+async fn redeliver_webhooks(state: &AppState, job: &RunbookJob, progress: &mut Progress) -> anyhow::Result<()> {
+    let date = job
+        .target_date
+        .ok_or_else(|| anyhow::anyhow!("webhook_redelivery job has no date"))?;
+    for check in sweep::undelivered(&state.db, date).await? {
+        match sweep::deliver_webhook(state, &check).await {
+            Ok(()) => progress.processed += 1,
+            Err(e) => {
+                warn!("Failed to redeliver integrity check {} to webhook: {:#}", check.check_id, e);
+                progress.fail(serde_json::json!({"check_id": check.check_id, "error": format!("{:#}", e)}));
+            }
+        }
+        update_progress(&state.db, job.job_id, progress).await?;
+    }
+    Ok(())
+}
+
+/// Anchor pending digest roots, then pending event anchors, as one job
+async fn backfill_anchors(state: &AppState, job: &RunbookJob, progress: &mut Progress) -> anyhow::Result<()> {
+    for day in digest::pending_digests(&state.db).await? {
+        match state.digests.anchor_pending(day).await {
+            Ok(_) => {
+                progress.processed += 1;
+                progress.count("digests_anchored");
+            }
+            Err(e) => {
+                warn!("Failed to anchor the audit digest for {}: {:#}", day, e);
+                progress.fail(serde_json::json!({"digest_date": day, "error": format!("{:#}", e)}));
+            }
+        }
+        update_progress(&state.db, job.job_id, progress).await?;
+    }
+
+    let released = outbox::release_deferred_anchors(&state.db).await?;
+    progress.result.insert("anchors_released".to_string(), (released as i64).into());
+    let (settings, pacing) = (OutboxSettings::from_env(), AnchorPacing::from_env());
+    let mut events_anchored = 0;
+    while let Some(batch) = outbox::backfill_batch(state, &settings, &pacing).await? {
+        events_anchored += batch.anchored;
+        progress.processed += batch.anchored;
+        // Failed entries stay in the outbox with their error, where the worker retries them
+        progress.failed += batch.failed;
+        progress.result.insert("events_anchored".to_string(), events_anchored.into());
+        update_progress(&state.db, job.job_id, progress).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,309 @@
+//! Schema registry for audit event payloads
+//!
+//! A JSON Schema is registered per resource type, optionally narrowed to one
+//! action, and validates the document `{"old_values": ..., "new_values": ...}`
+//! of each incoming event (absent values are `null`). A schema for the exact
+//! action wins over the resource type's catch-all. Tenants in STRICT mode have
+//! invalid events rejected; in LENIENT mode (the default) they are stored and
+//! the violation is recorded in audit_schema_violations. Event types without a
+//! registered schema are accepted as before.
+
+use jsonschema::JSONSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::CreateAuditEventRequest;
+
+/// Validation errors reported per event before the rest are counted
+const MAX_REPORTED_ERRORS: usize = 20;
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct EventSchema {
+    pub schema_id: Uuid,
+    pub resource_type: String,
+    pub action: Option<String>,
+    pub version: i32,
+    pub schema: serde_json::Value,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub deactivated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterSchemaRequest {
+    pub resource_type: String,
+    pub action: Option<String>,
+    pub schema: serde_json::Value,
+    pub description: Option<String>,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct SchemaListParams {
+    pub resource_type: Option<String>,
+    #[serde(default)]
+    pub include_inactive: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EnforcementMode {
+    Strict,
+    Lenient,
+}
+
+impl EnforcementMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EnforcementMode::Strict => "STRICT",
+            EnforcementMode::Lenient => "LENIENT",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TenantSchemaMode {
+    pub mode: EnforcementMode,
+    pub updated_by: Option<Uuid>,
+}
+
+/// Outcome of validating one event against its registered schema
+#[derive(Serialize, Debug, Clone)]
+pub struct SchemaCheck {
+    pub schema_id: Uuid,
+    pub version: i32,
+    pub mode: EnforcementMode,
+    pub errors: Vec<String>,
+}
+
+/// Returned by event creation when a STRICT tenant's event fails validation
+#[derive(Debug, Error)]
+#[error("audit event does not match schema {} (version {})", .0.schema_id, .0.version)]
+pub struct SchemaRejection(pub SchemaCheck);
+
+/// Compile a schema, reporting why it is unusable
+pub fn compile(schema: &serde_json::Value) -> Result<JSONSchema, String> {
+    JSONSchema::compile(schema).map_err(|e| format!("invalid JSON Schema at {}: {}", e.instance_path, e))
+}
+
+/// Compiled schemas by id; versions are immutable so entries never go stale
+#[derive(Default)]
+pub struct SchemaRegistry {
+    compiled: RwLock<HashMap<Uuid, Arc<JSONSchema>>>,
+}
+
+impl SchemaRegistry {
+    /// Compile every active schema ahead of the first event; returns how many
+    pub async fn warm(&self, db: &PgPool) -> anyhow::Result<usize> {
+        let active = sqlx::query_as::<_, EventSchema>("SELECT * FROM audit_event_schemas WHERE is_active")
+            .fetch_all(db)
+            .await?;
+        let mut compiled = Vec::with_capacity(active.len());
+        for schema in &active {
+            // A stored schema that no longer compiles is reported when an event hits it
+            match compile(&schema.schema) {
+                Ok(validator) => compiled.push((schema.schema_id, Arc::new(validator))),
+                Err(e) => warn!("Active audit event schema {} does not compile: {}", schema.schema_id, e),
+            }
+        }
+        let count = compiled.len();
+        self.compiled.write().expect("schema cache poisoned").extend(compiled);
+        Ok(count)
+    }
+
+    /// Validate an incoming event; `None` when no schema applies
+    pub async fn check(
+        &self,
+        db: &PgPool,
+        request: &CreateAuditEventRequest,
+    ) -> anyhow::Result<Option<SchemaCheck>> {
+        let Some(schema) = active_schema(db, &request.resource_type, &request.action).await? else {
+            return Ok(None);
+        };
+
+        let cached = self.compiled.read().expect("schema cache poisoned").get(&schema.schema_id).cloned();
+        let compiled = match cached {
+            Some(compiled) => compiled,
+            None => {
+                let compiled = Arc::new(compile(&schema.schema).map_err(anyhow::Error::msg)?);
+                self.compiled
+                    .write()
+                    .expect("schema cache poisoned")
+                    .insert(schema.schema_id, compiled.clone());
+                compiled
+            }
+        };
+
+        let document = serde_json::json!({
+            "old_values": request.old_values,
+            "new_values": request.new_values,
+        });
+        let errors: Vec<String> = match compiled.validate(&document) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .take(MAX_REPORTED_ERRORS)
+                .map(|e| {
+                    let path = e.instance_path.to_string();
+                    format!("{}: {}", if path.is_empty() { "/" } else { path.as_str() }, e)
+                })
+                .collect(),
+        };
+
+        Ok(Some(SchemaCheck {
+            schema_id: schema.schema_id,
+            version: schema.version,
+            mode: tenant_mode(db, request.tenant_id).await?,
+            errors,
+        }))
+    }
+}
+
+async fn active_schema(db: &PgPool, resource_type: &str, action: &str) -> Result<Option<EventSchema>, sqlx::Error> {
+    sqlx::query_as::<_, EventSchema>(
+        r#"
+        SELECT * FROM audit_event_schemas
+        WHERE resource_type = $1 AND is_active AND (action = $2 OR action IS NULL)
+        ORDER BY action IS NULL, version DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(resource_type)
+    .bind(action)
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn list_schemas(db: &PgPool, params: &SchemaListParams) -> Result<Vec<EventSchema>, sqlx::Error> {
+    sqlx::query_as::<_, EventSchema>(
+        r#"
+        SELECT * FROM audit_event_schemas
+        WHERE ($1::text IS NULL OR resource_type = $1) AND ($2 OR is_active)
+        ORDER BY resource_type, action NULLS FIRST, version DESC
+        "#,
+    )
+    .bind(&params.resource_type)
+    .bind(params.include_inactive)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn get_schema(db: &PgPool, schema_id: Uuid) -> Result<Option<EventSchema>, sqlx::Error> {
+    sqlx::query_as::<_, EventSchema>("SELECT * FROM audit_event_schemas WHERE schema_id = $1")
+        .bind(schema_id)
+        .fetch_optional(db)
+        .await
+}
+
+/// Register a new version for the resource type/action, retiring the current one
+///
+/// Callers compile the schema first so an unusable schema is never stored.
+pub async fn register_schema(db: &PgPool, request: &RegisterSchemaRequest) -> Result<EventSchema, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    // Serialises concurrent registrations for the same resource type/action
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1 || '/' || COALESCE($2, '')))")
+        .bind(&request.resource_type)
+        .bind(&request.action)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE audit_event_schemas
+        SET is_active = FALSE, deactivated_at = NOW()
+        WHERE resource_type = $1 AND action IS NOT DISTINCT FROM $2 AND is_active
+        "#,
+    )
+    .bind(&request.resource_type)
+    .bind(&request.action)
+    .execute(&mut *tx)
+    .await?;
+
+    let schema = sqlx::query_as::<_, EventSchema>(
+        r#"
+        INSERT INTO audit_event_schemas (resource_type, action, version, schema, description, created_by)
+        SELECT $1, $2, COALESCE(MAX(version), 0) + 1, $3, $4, $5
+        FROM audit_event_schemas
+        WHERE resource_type = $1 AND action IS NOT DISTINCT FROM $2
+        RETURNING *
+        "#,
+    )
+    .bind(&request.resource_type)
+    .bind(&request.action)
+    .bind(&request.schema)
+    .bind(&request.description)
+    .bind(request.created_by)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(schema)
+}
+
+/// Stop validating against a schema; the version is kept for past violations
+pub async fn deactivate_schema(db: &PgPool, schema_id: Uuid) -> Result<Option<EventSchema>, sqlx::Error> {
+    sqlx::query_as::<_, EventSchema>(
+        r#"
+        UPDATE audit_event_schemas
+        SET is_active = FALSE, deactivated_at = COALESCE(deactivated_at, NOW())
+        WHERE schema_id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(schema_id)
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn tenant_mode(db: &PgPool, tenant_id: Uuid) -> Result<EnforcementMode, sqlx::Error> {
+    let mode: Option<String> = sqlx::query_scalar("SELECT mode FROM audit_schema_enforcement WHERE tenant_id = $1")
+        .bind(tenant_id)
+        .fetch_optional(db)
+        .await?;
+    Ok(match mode.as_deref() {
+        Some("STRICT") => EnforcementMode::Strict,
+        _ => EnforcementMode::Lenient,
+    })
+}
+
+pub async fn set_tenant_mode(db: &PgPool, tenant_id: Uuid, mode: &TenantSchemaMode) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO audit_schema_enforcement (tenant_id, mode, updated_by, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (tenant_id) DO UPDATE SET mode = $2, updated_by = $3, updated_at = NOW()
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(mode.mode.as_str())
+    .bind(mode.updated_by)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Record an event accepted in lenient mode despite failing validation
+pub async fn record_violation(
+    db: &PgPool,
+    event_id: Uuid,
+    tenant_id: Uuid,
+    check: &SchemaCheck,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_schema_violations (event_id, tenant_id, schema_id, errors) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(event_id)
+    .bind(tenant_id)
+    .bind(check.schema_id)
+    .bind(serde_json::json!(check.errors))
+    .execute(db)
+    .await?;
+    Ok(())
+}
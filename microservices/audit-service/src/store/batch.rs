@@ -0,0 +1,150 @@
+//! Batched inserts of new event documents into MongoDB
+//!
+//! Inserting each document on its own is the last store round trip of the
+//! write path. With AUDIT_MONGO_BATCH_SIZE above 0, new events hand their
+//! document to a background writer instead and return once it is queued; the
+//! writer inserts up to that many at a time, waiting at most
+//! AUDIT_MONGO_BATCH_LINGER_MS for a batch to fill. The queue holds
+//! AUDIT_MONGO_QUEUE_CAPACITY documents. When it is full, writes wait up to
+//! AUDIT_MONGO_QUEUE_WAIT_MS for room, which holds producers to the pace
+//! MongoDB keeps.
+//!
+//! A batch MongoDB rejects, and a document that found no room in time, is
+//! spilled to the outbox as a DOCUMENT_INSERT entry carrying the document,
+//! which the retry worker inserts; until then reads of the document,
+//! verification among them, do not find it. A batch neither MongoDB nor the
+//! outbox takes is retried until one of them does, and the queue backs up
+//! meanwhile. Documents still queued when the process dies are lost with it,
+//! and nightly reconciliation reports their events as missing_in_mongodb.
+//! Queue depth is exported as `audit_document_queue_depth`.
+
+use metrics::{counter, gauge, histogram};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::SendTimeoutError};
+use tracing::{error, warn};
+
+use super::DocumentStore;
+use crate::outbox;
+use crate::AuditEvent;
+
+/// Wait before a batch no store took is tried again
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct BatchSettings {
+    /// Documents per insert; 0 inserts each document on the write path
+    pub batch_size: usize,
+    pub linger: Duration,
+    pub queue_capacity: usize,
+    pub queue_wait: Duration,
+}
+
+impl BatchSettings {
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            batch_size: var("AUDIT_MONGO_BATCH_SIZE", 100) as usize,
+            linger: Duration::from_millis(var("AUDIT_MONGO_BATCH_LINGER_MS", 10)),
+            queue_capacity: var("AUDIT_MONGO_QUEUE_CAPACITY", 10_000).max(1) as usize,
+            queue_wait: Duration::from_millis(var("AUDIT_MONGO_QUEUE_WAIT_MS", 250)),
+        }
+    }
+}
+
+pub struct DocumentBatcher {
+    queue: mpsc::Sender<AuditEvent>,
+    db: PgPool,
+    settings: BatchSettings,
+}
+
+impl DocumentBatcher {
+    /// Start the writer; `None` when batching is off or documents are not kept in MongoDB
+    pub fn spawn(documents: Arc<dyn DocumentStore>, db: PgPool, settings: BatchSettings) -> Option<Self> {
+        if settings.batch_size == 0 || documents.backend() != "mongodb" {
+            return None;
+        }
+        let (queue, receiver) = mpsc::channel(settings.queue_capacity);
+        tokio::spawn(write_batches(documents, db.clone(), receiver, settings.clone()));
+        Some(Self { queue, db, settings })
+    }
+
+    /// Queue the event's document, or spill it to the outbox when the queue stays full
+    pub async fn submit(&self, event: AuditEvent) -> anyhow::Result<()> {
+        let (event, reason) = match self.queue.send_timeout(event, self.settings.queue_wait).await {
+            Ok(()) => {
+                let depth = self.settings.queue_capacity - self.queue.capacity();
+                gauge!("audit_document_queue_depth", depth as f64);
+                return Ok(());
+            }
+            Err(SendTimeoutError::Timeout(event)) => (event, "queue_full"),
+            Err(SendTimeoutError::Closed(event)) => (event, "writer_stopped"),
+        };
+        counter!("audit_document_spills_total", 1, "reason" => reason);
+        warn!("Spilling the document of audit event {} to the outbox: {}", event.event_id, reason);
+        outbox::spill_documents(&self.db, std::slice::from_ref(&event), "the document write queue was full").await
+    }
+}
+
+async fn write_batches(
+    documents: Arc<dyn DocumentStore>,
+    db: PgPool,
+    mut receiver: mpsc::Receiver<AuditEvent>,
+    settings: BatchSettings,
+) {
+    let mut batch = Vec::with_capacity(settings.batch_size);
+    while let Some(event) = receiver.recv().await {
+        batch.push(event);
+        let linger = tokio::time::sleep(settings.linger);
+        tokio::pin!(linger);
+        while batch.len() < settings.batch_size {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Some(event) => batch.push(event),
+                    None => break,
+                },
+                _ = &mut linger => break,
+            }
+        }
+        flush(documents.as_ref(), &db, &batch).await;
+        batch.clear();
+    }
+}
+
+/// Insert the batch, or spill it to the outbox; returns once either has taken it
+async fn flush(documents: &dyn DocumentStore, db: &PgPool, batch: &[AuditEvent]) {
+    histogram!("audit_document_batch_size", batch.len() as f64);
+    loop {
+        let started = Instant::now();
+        let error = match documents.insert_many(batch).await {
+            Ok(()) => {
+                histogram!("audit_document_batch_seconds", started.elapsed().as_secs_f64());
+                return;
+            }
+            Err(e) => format!("{:#}", e),
+        };
+        // Documents of the batch MongoDB did take are skipped by the retry worker
+        match outbox::spill_documents(db, batch, &error).await {
+            Ok(()) => {
+                counter!("audit_document_spills_total", batch.len() as u64, "reason" => "insert_failed");
+                warn!("Spilled {} audit documents to the outbox: {}", batch.len(), error);
+                return;
+            }
+            Err(e) => {
+                error!(
+                    "Neither MongoDB nor the outbox took {} audit documents, retrying: {}; {}",
+                    batch.len(),
+                    error,
+                    e
+                );
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
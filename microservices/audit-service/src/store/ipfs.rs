@@ -0,0 +1,80 @@
+//! Payload copies on the service's IPFS node
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use ipfs_api_backend_hyper::{IpfsApi, IpfsClient, TryFromUri};
+use tracing::{error, info};
+
+use super::AnchorStore;
+
+pub struct IpfsAnchorStore {
+    client: IpfsClient,
+}
+
+impl IpfsAnchorStore {
+    pub fn new(api_url: &str) -> Self {
+        let client = IpfsClient::from_str(api_url).unwrap_or_else(|_| IpfsClient::default());
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl AnchorStore for IpfsAnchorStore {
+    fn backend(&self) -> &'static str {
+        "ipfs"
+    }
+
+    fn keeps_copies(&self) -> bool {
+        true
+    }
+
+    async fn put(&self, payload: &[u8]) -> anyhow::Result<Option<String>> {
+        // Pinned explicitly rather than by node default
+        let cursor = std::io::Cursor::new(payload.to_vec());
+        let options = ipfs_api_backend_hyper::request::Add {
+            pin: Some(true),
+            ..Default::default()
+        };
+        match self.client.add_with_options(cursor, options).await {
+            Ok(response) => {
+                info!("Stored document in IPFS: {}", response.hash);
+                Ok(Some(response.hash))
+            }
+            Err(e) => {
+                error!("Failed to store in IPFS: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn retrieve(&self, cid: &str) -> anyhow::Result<Vec<u8>> {
+        let bytes = self
+            .client
+            .cat(cid)
+            .map_ok(|chunk| chunk.to_vec())
+            .try_concat()
+            .await?;
+        Ok(bytes)
+    }
+
+    async fn pin(&self, cid: &str) -> anyhow::Result<()> {
+        self.client.pin_add(cid, true).await?;
+        info!("Pinned document in IPFS: {}", cid);
+        Ok(())
+    }
+
+    async fn is_pinned(&self, cid: &str) -> anyhow::Result<bool> {
+        match self.client.pin_ls(Some(cid), None).await {
+            Ok(response) => Ok(response.keys.contains_key(cid)),
+            // The node answers an error rather than an empty set for unpinned paths
+            Err(e) if e.to_string().contains("not pinned") => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn unpin(&self, cid: &str) -> anyhow::Result<()> {
+        self.client.pin_rm(cid, true).await?;
+        info!("Unpinned document from IPFS: {}", cid);
+        Ok(())
+    }
+}
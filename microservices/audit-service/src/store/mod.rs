@@ -0,0 +1,283 @@
+//! Storage backends of the audit service
+//!
+//! An event is written to three places: the queryable audit log
+//! (`AuditStore`, always Postgres), the signed canonical document that
+//! verification, archival and re-signing read (`DocumentStore`), and a
+//! content-addressed copy of the payload (`AnchorStore`). Full deployments
+//! keep documents in MongoDB and copies in IPFS; smaller ones can keep
+//! documents in Postgres with AUDIT_DOCUMENT_STORE=postgres and run without
+//! IPFS with AUDIT_ANCHOR_STORE=none, in which case events carry no CID and
+//! the IPFS check is left out of verification.
+
+pub mod batch;
+pub mod ipfs;
+pub mod mongo;
+pub mod postgres;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use futures::stream::BoxStream;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+
+use crate::compression::Compression;
+use crate::copies::StoredCopy;
+use crate::outbox::Operation;
+use crate::pins::PinRegistry;
+use crate::replication::{self, ChainLink};
+use crate::AuditEvent;
+
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    /// Record the event's audit log row, its pin and any steps deferred to the
+    /// outbox, all or nothing; `copy` is the event's IPFS copy when it was stored
+    async fn append(
+        &self,
+        event: &AuditEvent,
+        copy: Option<&StoredCopy>,
+        deferred: &[(Operation, String)],
+    ) -> anyhow::Result<()>;
+}
+
+/// A change to fields set after an event's document was first written
+#[derive(Debug, Clone, Copy)]
+pub enum DocumentUpdate<'a> {
+    IpfsHash(&'a str),
+    BlockchainHash(&'a str),
+    Signature { signature: &'a str, signing_key_id: &'a str },
+}
+
+impl<'a> DocumentUpdate<'a> {
+    pub fn fields(&self) -> Vec<(&'static str, &'a str)> {
+        match *self {
+            DocumentUpdate::IpfsHash(cid) => vec![("ipfs_hash", cid)],
+            DocumentUpdate::BlockchainHash(anchor) => vec![("blockchain_hash", anchor)],
+            DocumentUpdate::Signature { signature, signing_key_id } => {
+                vec![("signature", signature), ("signing_key_id", signing_key_id)]
+            }
+        }
+    }
+}
+
+#[async_trait]
+pub trait DocumentStore: Send + Sync {
+    fn backend(&self) -> &'static str;
+
+    /// Confirm the store is reachable, for startup readiness
+    async fn ping(&self) -> anyhow::Result<()>;
+
+    async fn insert(&self, event: &AuditEvent) -> anyhow::Result<()>;
+
+    /// Insert several documents; one that fails does not stop the others being inserted
+    async fn insert_many(&self, events: &[AuditEvent]) -> anyhow::Result<()> {
+        let mut failed = None;
+        for event in events {
+            if let Err(e) = self.insert(event).await {
+                failed = Some(e);
+            }
+        }
+        failed.map_or(Ok(()), Err)
+    }
+
+    async fn get(&self, event_id: Uuid) -> anyhow::Result<Option<AuditEvent>>;
+
+    /// The documents found among `event_ids`; missing ones are left out
+    async fn get_many(&self, event_ids: &[Uuid]) -> anyhow::Result<HashMap<Uuid, AuditEvent>>;
+
+    /// Every document timestamped on the given UTC day
+    async fn for_day(&self, day: NaiveDate) -> anyhow::Result<Vec<AuditEvent>>;
+
+    /// Documents signed with `key_id`, and unsigned ones when `include_unlabelled`
+    async fn signed_with(
+        &self,
+        key_id: &str,
+        include_unlabelled: bool,
+        tenant_id: Option<Uuid>,
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AuditEvent>>>;
+
+    /// A random sample of `sample_size` documents, or all of them
+    async fn scan(&self, sample_size: Option<i64>) -> anyhow::Result<BoxStream<'_, anyhow::Result<AuditEvent>>>;
+
+    async fn update(&self, event_id: Uuid, update: DocumentUpdate<'_>) -> anyhow::Result<()>;
+
+    async fn delete_many(&self, event_ids: &[Uuid]) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+pub trait AnchorStore: Send + Sync {
+    fn backend(&self) -> &'static str;
+
+    /// False when payloads are not copied anywhere, so pins and IPFS checks do not apply
+    fn keeps_copies(&self) -> bool;
+
+    /// Store and pin the payload, returning its CID; `None` when copies are not kept
+    async fn put(&self, payload: &[u8]) -> anyhow::Result<Option<String>>;
+
+    async fn retrieve(&self, cid: &str) -> anyhow::Result<Vec<u8>>;
+
+    async fn pin(&self, cid: &str) -> anyhow::Result<()>;
+
+    async fn is_pinned(&self, cid: &str) -> anyhow::Result<bool>;
+
+    async fn unpin(&self, cid: &str) -> anyhow::Result<()>;
+}
+
+/// For deployments without IPFS; payloads are only hashed, signed and anchored
+pub struct NoopAnchorStore;
+
+#[async_trait]
+impl AnchorStore for NoopAnchorStore {
+    fn backend(&self) -> &'static str {
+        "none"
+    }
+
+    fn keeps_copies(&self) -> bool {
+        false
+    }
+
+    async fn put(&self, _payload: &[u8]) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn retrieve(&self, cid: &str) -> anyhow::Result<Vec<u8>> {
+        anyhow::bail!("cannot retrieve {}: AUDIT_ANCHOR_STORE is none", cid)
+    }
+
+    async fn pin(&self, cid: &str) -> anyhow::Result<()> {
+        anyhow::bail!("cannot pin {}: AUDIT_ANCHOR_STORE is none", cid)
+    }
+
+    async fn is_pinned(&self, cid: &str) -> anyhow::Result<bool> {
+        anyhow::bail!("cannot check the pin of {}: AUDIT_ANCHOR_STORE is none", cid)
+    }
+
+    async fn unpin(&self, cid: &str) -> anyhow::Result<()> {
+        anyhow::bail!("cannot unpin {}: AUDIT_ANCHOR_STORE is none", cid)
+    }
+}
+
+/// Build the configured document store: AUDIT_DOCUMENT_STORE=mongodb|postgres,
+/// defaulting to mongodb when MONGODB_URL is set
+pub async fn documents_from_env(db: &PgPool) -> anyhow::Result<Arc<dyn DocumentStore>> {
+    let mongodb_url = std::env::var("MONGODB_URL").ok().filter(|url| !url.is_empty());
+    let backend = std::env::var("AUDIT_DOCUMENT_STORE")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match (backend.as_str(), mongodb_url) {
+        ("" | "mongodb", Some(url)) => Ok(Arc::new(
+            mongo::MongoDocumentStore::connect(&url, Compression::from_env()).await?,
+        )),
+        ("mongodb", None) => anyhow::bail!("AUDIT_DOCUMENT_STORE is mongodb but MONGODB_URL is not set"),
+        ("" | "postgres", _) => Ok(Arc::new(postgres::PostgresDocumentStore::new(db.clone()))),
+        (other, _) => anyhow::bail!("unknown AUDIT_DOCUMENT_STORE backend '{}'", other),
+    }
+}
+
+/// Build the configured anchor store: AUDIT_ANCHOR_STORE=ipfs|none, defaulting to ipfs
+pub fn anchors_from_env() -> anyhow::Result<Arc<dyn AnchorStore>> {
+    match std::env::var("AUDIT_ANCHOR_STORE").unwrap_or_default().to_ascii_lowercase().as_str() {
+        "" | "ipfs" => {
+            let api_url = std::env::var("IPFS_API_URL").unwrap_or_else(|_| "http://localhost:5001".to_string());
+            Ok(Arc::new(ipfs::IpfsAnchorStore::new(&api_url)))
+        }
+        "none" => Ok(Arc::new(NoopAnchorStore)),
+        other => anyhow::bail!("unknown AUDIT_ANCHOR_STORE backend '{}'", other),
+    }
+}
+
+/// The audit log in Postgres, which trail queries, retention and reconciliation read directly
+pub struct PostgresAuditStore {
+    db: PgPool,
+    pins: Arc<PinRegistry>,
+    /// This region while replication is configured; new events are then chained and queued for the peer
+    region: Option<String>,
+}
+
+impl PostgresAuditStore {
+    pub fn new(db: PgPool, pins: Arc<PinRegistry>, region: Option<String>) -> Self {
+        Self { db, pins, region }
+    }
+}
+
+#[async_trait]
+impl AuditStore for PostgresAuditStore {
+    async fn append(
+        &self,
+        event: &AuditEvent,
+        copy: Option<&StoredCopy>,
+        deferred: &[(Operation, String)],
+    ) -> anyhow::Result<()> {
+        let hash = event
+            .event_hash
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("audit event {} is not hashed", event.event_id))?;
+
+        let mut tx = self.db.begin().await?;
+        let link = match &self.region {
+            Some(region) => Some(replication::extend_chain(&mut tx, event.tenant_id, region, hash).await?),
+            None => None,
+        };
+        insert_row(&mut tx, event, link.as_ref()).await?;
+        if let Some(copy) = copy {
+            self.pins.record(&mut tx, event.tenant_id, event.event_id, copy).await?;
+        }
+        for (operation, error) in deferred {
+            crate::outbox::enqueue(&mut tx, event, *operation, hash, error).await?;
+        }
+        if link.is_some() {
+            replication::enqueue(&mut tx, event.event_id).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Insert the event's audit_logs row, with its link when it is chained
+pub async fn insert_row(
+    tx: &mut Transaction<'_, Postgres>,
+    event: &AuditEvent,
+    link: Option<&ChainLink>,
+) -> anyhow::Result<()> {
+    // A sealed address is not an inet and is kept beside the column instead
+    let (ip_address, ip_address_sealed) = match &event.ip_address {
+        Some(sealed) if crate::pii::is_sealed(sealed) => (None, Some(sealed.clone())),
+        ip_address => (ip_address.clone(), None),
+    };
+    let actor_snapshot = event.actor.as_ref().map(serde_json::to_value).transpose()?;
+    tenant_query!(
+        &TenantContext::new(event.tenant_id),
+        r#"
+        INSERT INTO audit_logs (
+            tenant_id, log_id, user_id, action, resource_type, resource_id,
+            old_values, new_values, timestamp, ip_address, ip_address_sealed, user_agent, request_id,
+            actor_snapshot, producer, origin_region, chain_seq, chain_hash
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, ($10::text)::inet, $11, $12, $13, $14, $15, $16, $17, $18)
+        "#,
+        event.event_id,
+        event.user_id,
+        event.action,
+        event.resource_type,
+        event.resource_id,
+        event.old_values,
+        event.new_values,
+        event.timestamp,
+        ip_address,
+        ip_address_sealed,
+        event.user_agent,
+        event.request_id,
+        actor_snapshot,
+        event.producer,
+        link.map(|link| link.origin_region.as_str()),
+        link.map(|link| link.seq),
+        link.map(|link| link.hash.as_str())
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
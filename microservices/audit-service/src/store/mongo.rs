@@ -0,0 +1,208 @@
+//! Audit event documents in MongoDB's audit_events collection
+//!
+//! Values over the compression threshold are kept zstd-compressed in
+//! `values_compressed` instead of old_values and new_values; see `compression`.
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use mongodb::bson::{self, doc, spec::BinarySubtype, Binary, Bson, Document};
+use mongodb::options::InsertManyOptions;
+use mongodb::{Client, Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::{DocumentStore, DocumentUpdate};
+use crate::compression::{Codec, Compression};
+use crate::AuditEvent;
+
+/// Documents fetched or deleted per `$in` query
+const FETCH_CHUNK: usize = 1000;
+
+/// What is compressed of an event; everything else stays queryable
+#[derive(Serialize, Deserialize)]
+struct Values {
+    old_values: Option<serde_json::Value>,
+    new_values: Option<serde_json::Value>,
+}
+
+pub struct MongoDocumentStore {
+    database: Database,
+    compression: Compression,
+}
+
+impl MongoDocumentStore {
+    pub async fn connect(url: &str, compression: Compression) -> anyhow::Result<Self> {
+        let client = Client::with_uri_str(url).await?;
+        Ok(Self {
+            database: client.database("dharmaguard_audit"),
+            compression,
+        })
+    }
+
+    fn collection(&self) -> Collection<Document> {
+        self.database.collection::<Document>("audit_events")
+    }
+
+    fn to_document(&self, event: &AuditEvent) -> anyhow::Result<Document> {
+        let mut document = bson::to_document(event)?;
+        let values = serde_json::to_vec(&Values {
+            old_values: event.old_values.clone(),
+            new_values: event.new_values.clone(),
+        })?;
+        if let Some((codec, compressed)) = self.compression.compress("mongodb", &values)? {
+            document.remove("old_values");
+            document.remove("new_values");
+            document.insert("values_codec", codec.as_str());
+            document.insert(
+                "values_compressed",
+                Binary {
+                    subtype: BinarySubtype::Generic,
+                    bytes: compressed,
+                },
+            );
+        }
+        Ok(document)
+    }
+}
+
+/// The event a stored document holds, with compressed values restored
+fn from_document(mut document: Document) -> anyhow::Result<AuditEvent> {
+    let compressed = match (document.remove("values_codec"), document.remove("values_compressed")) {
+        (Some(Bson::String(codec)), Some(Bson::Binary(binary))) => Some((Codec::parse(&codec)?, binary.bytes)),
+        (None, None) => None,
+        _ => anyhow::bail!("audit event document has malformed compressed values"),
+    };
+    let mut event: AuditEvent = bson::from_document(document)?;
+    if let Some((codec, bytes)) = compressed {
+        let values: Values = serde_json::from_slice(&codec.decompress(&bytes)?)?;
+        event.old_values = values.old_values;
+        event.new_values = values.new_values;
+    }
+    Ok(event)
+}
+
+fn id_chunks(event_ids: &[Uuid]) -> impl Iterator<Item = Vec<String>> + '_ {
+    event_ids
+        .chunks(FETCH_CHUNK)
+        .map(|chunk| chunk.iter().map(Uuid::to_string).collect())
+}
+
+#[async_trait]
+impl DocumentStore for MongoDocumentStore {
+    fn backend(&self) -> &'static str {
+        "mongodb"
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        self.database.run_command(doc! {"ping": 1}, None).await?;
+        Ok(())
+    }
+
+    async fn insert(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        self.collection().insert_one(self.to_document(event)?, None).await?;
+        Ok(())
+    }
+
+    async fn insert_many(&self, events: &[AuditEvent]) -> anyhow::Result<()> {
+        let documents = events
+            .iter()
+            .map(|event| self.to_document(event))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // Unordered, so a document that fails does not hold back the rest of the batch
+        let options = InsertManyOptions::builder().ordered(false).build();
+        self.collection().insert_many(documents, options).await?;
+        Ok(())
+    }
+
+    async fn get(&self, event_id: Uuid) -> anyhow::Result<Option<AuditEvent>> {
+        self.collection()
+            .find_one(doc! { "event_id": event_id.to_string() }, None)
+            .await?
+            .map(from_document)
+            .transpose()
+    }
+
+    async fn get_many(&self, event_ids: &[Uuid]) -> anyhow::Result<HashMap<Uuid, AuditEvent>> {
+        let collection = self.collection();
+        let mut documents = HashMap::with_capacity(event_ids.len());
+        for chunk_ids in id_chunks(event_ids) {
+            let mut cursor = collection.find(doc! { "event_id": { "$in": chunk_ids } }, None).await?;
+            while let Some(document) = cursor.try_next().await? {
+                let event = from_document(document)?;
+                documents.insert(event.event_id, event);
+            }
+        }
+        Ok(documents)
+    }
+
+    async fn for_day(&self, day: NaiveDate) -> anyhow::Result<Vec<AuditEvent>> {
+        // Timestamps are stored as RFC 3339 UTC strings, so a date prefix selects the day exactly
+        let cursor = self
+            .collection()
+            .find(doc! { "timestamp": { "$regex": format!("^{}T", day.format("%Y-%m-%d")) } }, None)
+            .await?;
+        let documents: Vec<Document> = cursor.try_collect().await?;
+        documents.into_iter().map(from_document).collect()
+    }
+
+    async fn signed_with(
+        &self,
+        key_id: &str,
+        include_unlabelled: bool,
+        tenant_id: Option<Uuid>,
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AuditEvent>>> {
+        let key_filter = if include_unlabelled {
+            doc! { "$or": [
+                { "signing_key_id": key_id },
+                { "signing_key_id": null },
+            ] }
+        } else {
+            doc! { "signing_key_id": key_id }
+        };
+        let filter = match tenant_id {
+            Some(tenant_id) => doc! { "$and": [key_filter, { "tenant_id": tenant_id.to_string() }] },
+            None => key_filter,
+        };
+        let cursor = self.collection().find(filter, None).await?;
+        Ok(cursor
+            .map(|document| -> anyhow::Result<AuditEvent> { from_document(document?) })
+            .boxed())
+    }
+
+    async fn scan(&self, sample_size: Option<i64>) -> anyhow::Result<BoxStream<'_, anyhow::Result<AuditEvent>>> {
+        let collection = self.collection();
+        Ok(match sample_size {
+            Some(size) => collection
+                .aggregate([doc! { "$sample": { "size": size } }], None)
+                .await?
+                .map(|document| -> anyhow::Result<AuditEvent> { from_document(document?) })
+                .boxed(),
+            None => collection
+                .find(doc! {}, None)
+                .await?
+                .map(|document| -> anyhow::Result<AuditEvent> { from_document(document?) })
+                .boxed(),
+        })
+    }
+
+    async fn update(&self, event_id: Uuid, update: DocumentUpdate<'_>) -> anyhow::Result<()> {
+        let mut fields = Document::new();
+        for (field, value) in update.fields() {
+            fields.insert(field, value);
+        }
+        self.collection()
+            .update_one(doc! { "event_id": event_id.to_string() }, doc! { "$set": fields }, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_many(&self, event_ids: &[Uuid]) -> anyhow::Result<()> {
+        let collection = self.collection();
+        for chunk_ids in id_chunks(event_ids) {
+            collection.delete_many(doc! { "event_id": { "$in": chunk_ids } }, None).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Audit event documents in Postgres, for deployments without MongoDB
+//!
+//! Documents live in audit_event_documents as JSONB alongside the columns
+//! they are looked up by. Hashes are recomputed from the deserialised event,
+//! not the stored bytes, so JSONB's normalisation does not affect verification.
+
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate};
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::{DocumentStore, DocumentUpdate};
+use crate::AuditEvent;
+
+pub struct PostgresDocumentStore {
+    db: PgPool,
+}
+
+impl PostgresDocumentStore {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl DocumentStore for PostgresDocumentStore {
+    fn backend(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn ping(&self) -> anyhow::Result<()> {
+        sqlx::query("SELECT 1 FROM audit_event_documents LIMIT 1")
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn insert(&self, event: &AuditEvent) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_event_documents (event_id, tenant_id, timestamp, signing_key_id, document)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(event.event_id)
+        .bind(event.tenant_id)
+        .bind(event.timestamp)
+        .bind(&event.signing_key_id)
+        .bind(Json(event))
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn get(&self, event_id: Uuid) -> anyhow::Result<Option<AuditEvent>> {
+        let document = sqlx::query_scalar::<_, Json<AuditEvent>>(
+            "SELECT document FROM audit_event_documents WHERE event_id = $1",
+        )
+        .bind(event_id)
+        .fetch_optional(&self.db)
+        .await?;
+        Ok(document.map(|Json(event)| event))
+    }
+
+    async fn get_many(&self, event_ids: &[Uuid]) -> anyhow::Result<HashMap<Uuid, AuditEvent>> {
+        let documents = sqlx::query_scalar::<_, Json<AuditEvent>>(
+            "SELECT document FROM audit_event_documents WHERE event_id = ANY($1)",
+        )
+        .bind(event_ids)
+        .fetch_all(&self.db)
+        .await?;
+        Ok(documents
+            .into_iter()
+            .map(|Json(event)| (event.event_id, event))
+            .collect())
+    }
+
+    async fn for_day(&self, day: NaiveDate) -> anyhow::Result<Vec<AuditEvent>> {
+        let start = day.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+        let documents = sqlx::query_scalar::<_, Json<AuditEvent>>(
+            "SELECT document FROM audit_event_documents WHERE timestamp >= $1 AND timestamp < $2",
+        )
+        .bind(start)
+        .bind(start + Duration::days(1))
+        .fetch_all(&self.db)
+        .await?;
+        Ok(documents.into_iter().map(|Json(event)| event).collect())
+    }
+
+    async fn signed_with(
+        &self,
+        key_id: &str,
+        include_unlabelled: bool,
+        tenant_id: Option<Uuid>,
+    ) -> anyhow::Result<BoxStream<'_, anyhow::Result<AuditEvent>>> {
+        Ok(sqlx::query_scalar::<_, Json<AuditEvent>>(
+            r#"
+            SELECT document FROM audit_event_documents
+            WHERE (signing_key_id = $1 OR ($2 AND signing_key_id IS NULL))
+              AND ($3::uuid IS NULL OR tenant_id = $3)
+            "#,
+        )
+        .bind(key_id.to_string())
+        .bind(include_unlabelled)
+        .bind(tenant_id)
+        .fetch(&self.db)
+        .map_ok(|Json(event)| event)
+        .map_err(anyhow::Error::from)
+        .boxed())
+    }
+
+    async fn scan(&self, sample_size: Option<i64>) -> anyhow::Result<BoxStream<'_, anyhow::Result<AuditEvent>>> {
+        let query = match sample_size {
+            Some(size) => sqlx::query_scalar::<_, Json<AuditEvent>>(
+                "SELECT document FROM audit_event_documents ORDER BY random() LIMIT $1",
+            )
+            .bind(size),
+            None => sqlx::query_scalar::<_, Json<AuditEvent>>("SELECT document FROM audit_event_documents"),
+        };
+        Ok(query
+            .fetch(&self.db)
+            .map_ok(|Json(event)| event)
+            .map_err(anyhow::Error::from)
+            .boxed())
+    }
+
+    async fn update(&self, event_id: Uuid, update: DocumentUpdate<'_>) -> anyhow::Result<()> {
+        let fields: serde_json::Map<String, serde_json::Value> = update
+            .fields()
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value.into()))
+            .collect();
+        sqlx::query(
+            r#"
+            UPDATE audit_event_documents
+            SET document = document || $2,
+                signing_key_id = (document || $2) ->> 'signing_key_id'
+            WHERE event_id = $1
+            "#,
+        )
+        .bind(event_id)
+        .bind(Json(fields))
+        .execute(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_many(&self, event_ids: &[Uuid]) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM audit_event_documents WHERE event_id = ANY($1)")
+            .bind(event_ids)
+            .execute(&self.db)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Live audit event stream over server-sent events
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures::stream::{self, Stream};
+use serde::Deserialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::auth::Caller;
+use crate::{AppState, AuditEvent};
+
+/// Events buffered per subscriber before slow consumers start missing events
+pub const STREAM_CAPACITY: usize = 1024;
+
+/// Query parameters for GET /audit/stream
+#[derive(Debug, Deserialize)]
+pub struct AuditStreamParams {
+    pub tenant_id: Uuid,
+    pub action: Option<String>,
+    pub resource_type: Option<String>,
+}
+
+impl AuditStreamParams {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        event.tenant_id == self.tenant_id
+            && self.action.as_ref().map_or(true, |action| &event.action == action)
+            && self
+                .resource_type
+                .as_ref()
+                .map_or(true, |resource_type| &event.resource_type == resource_type)
+    }
+}
+
+/// Push newly created audit events matching the filter as `audit_event` messages
+///
+/// A subscriber that falls behind receives a `lagged` message with the number
+/// of events it missed and should backfill from GET /audit/events.
+pub async fn stream_audit_events(
+    Query(params): Query<AuditStreamParams>,
+    caller: Caller,
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.event_stream.subscribe();
+
+    let events = stream::unfold((receiver, params, caller), |(mut receiver, params, caller)| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) if params.matches(&event) && caller.may_see(&event.resource_type) => Event::default()
+                    .event("audit_event")
+                    .id(event.event_id.to_string())
+                    .json_data(&event)
+                    .unwrap_or_else(|_| Event::default().event("error")),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Audit stream subscriber for tenant {} lagged by {} events", params.tenant_id, missed);
+                    Event::default().event("lagged").data(missed.to_string())
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), (receiver, params, caller)));
+        }
+    });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+pub fn channel() -> broadcast::Sender<AuditEvent> {
+    broadcast::channel(STREAM_CAPACITY).0
+}
@@ -0,0 +1,385 @@
+//! Scheduled integrity sweeps of stored audit events
+//!
+//! Reconciliation compares the stores day by day; a sweep instead runs the
+//! full per-event verification (payload hash, signature, IPFS copy and
+//! on-chain anchor) over a random sample of events every hour and over every
+//! event once a week. Each sweep is recorded in integrity_checks. A sweep
+//! that finds failing events raises an INTEGRITY_DISCREPANCY ops alert in
+//! system_events and, when AUDIT_INTEGRITY_WEBHOOK_URL is set, posts the
+//! result to that webhook.
+
+use anyhow::Context;
+use futures::stream::TryStreamExt;
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::integrity::VerificationCheck;
+use crate::{AppState, AuditService};
+
+/// Failing events listed on a sweep before the rest are only counted
+const MAX_RECORDED_FAILURES: usize = 500;
+/// Events between progress updates, which also keep a sweep from looking abandoned
+const PROGRESS_EVERY: i64 = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum SweepMode {
+    Sample,
+    Full,
+}
+
+impl SweepMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sample => "SAMPLE",
+            Self::Full => "FULL",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepSettings {
+    /// Cron expressions; an empty one disables that sweep
+    pub sample_schedule: String,
+    pub full_schedule: String,
+    pub sample_size: i64,
+    /// Events verified at once; each verification fetches from IPFS and the chain
+    pub concurrency: usize,
+    pub webhook_url: Option<String>,
+    /// Signs webhook bodies as X-DharmaGuard-Signature: sha256=<hex HMAC>
+    pub webhook_secret: Option<String>,
+}
+
+impl SweepSettings {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Self {
+            sample_schedule: std::env::var("AUDIT_INTEGRITY_SAMPLE_SCHEDULE")
+                .unwrap_or_else(|_| "0 15 * * * *".to_string()),
+            full_schedule: std::env::var("AUDIT_INTEGRITY_FULL_SCHEDULE")
+                .unwrap_or_else(|_| "0 0 4 * * Sun".to_string()),
+            sample_size: var("AUDIT_INTEGRITY_SAMPLE_SIZE")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(200),
+            concurrency: var("AUDIT_INTEGRITY_CONCURRENCY")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(8)
+                .max(1),
+            webhook_url: var("AUDIT_INTEGRITY_WEBHOOK_URL"),
+            webhook_secret: var("AUDIT_INTEGRITY_WEBHOOK_SECRET"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SweepRequest {
+    pub mode: SweepMode,
+    /// SAMPLE only; defaults to AUDIT_INTEGRITY_SAMPLE_SIZE
+    pub sample_size: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct IntegrityCheck {
+    pub check_id: Uuid,
+    pub mode: String,
+    pub triggered_by: String,
+    pub status: String,
+    pub sample_size: Option<i32>,
+    pub events_checked: i64,
+    pub events_failed: i64,
+    pub failures: serde_json::Value,
+    pub webhook_delivered_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub error: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub progressed_at: chrono::DateTime<chrono::Utc>,
+    pub completed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EventFailure {
+    pub event_id: Uuid,
+    pub tenant_id: Uuid,
+    pub failed_checks: Vec<VerificationCheck>,
+}
+
+#[derive(Default)]
+struct SweepTally {
+    checked: i64,
+    failed: i64,
+    failures: Vec<EventFailure>,
+}
+
+/// Schedule the sampled and full sweeps
+pub async fn schedule(state: AppState) -> anyhow::Result<JobScheduler> {
+    let scheduler = JobScheduler::new().await?;
+
+    let settings = state.sweep_settings.clone();
+    for (mode, cron) in [
+        (SweepMode::Sample, &settings.sample_schedule),
+        (SweepMode::Full, &settings.full_schedule),
+    ] {
+        if cron.is_empty() {
+            continue;
+        }
+        let state = state.clone();
+        let job = Job::new_async(cron.as_str(), move |_uuid, _l| {
+            let state = state.clone();
+            Box::pin(async move {
+                match start(state, mode, None, "SCHEDULED").await {
+                    Ok(Some(check)) => info!("Started scheduled {} integrity check {}", mode.as_str(), check.check_id),
+                    Ok(None) => info!("Skipped scheduled {} integrity check; one is still running", mode.as_str()),
+                    Err(e) => error!("Failed to start scheduled {} integrity check: {}", mode.as_str(), e),
+                }
+            })
+        })?;
+        scheduler.add(job).await?;
+    }
+
+    scheduler.start().await?;
+    Ok(scheduler)
+}
+
+pub async fn get_check(db: &PgPool, check_id: Uuid) -> Result<Option<IntegrityCheck>, sqlx::Error> {
+    sqlx::query_as::<_, IntegrityCheck>("SELECT * FROM integrity_checks WHERE check_id = $1")
+        .bind(check_id)
+        .fetch_optional(db)
+        .await
+}
+
+pub async fn list_checks(db: &PgPool, limit: i64) -> Result<Vec<IntegrityCheck>, sqlx::Error> {
+    sqlx::query_as::<_, IntegrityCheck>("SELECT * FROM integrity_checks ORDER BY started_at DESC LIMIT $1")
+        .bind(limit)
+        .fetch_all(db)
+        .await
+}
+
+/// Record a sweep and run it in the background; None while one of the same mode is running
+pub async fn start(
+    state: AppState,
+    mode: SweepMode,
+    sample_size: Option<i64>,
+    triggered_by: &str,
+) -> anyhow::Result<Option<IntegrityCheck>> {
+    // A replica that restarted mid-sweep never finishes it
+    sqlx::query(
+        r#"
+        UPDATE integrity_checks
+        SET status = 'FAILED', error = 'abandoned while running', completed_at = NOW()
+        WHERE mode = $1 AND status = 'RUNNING' AND progressed_at < NOW() - INTERVAL '1 hour'
+        "#,
+    )
+    .bind(mode.as_str())
+    .execute(&state.db)
+    .await?;
+
+    let sample_size = match mode {
+        SweepMode::Sample => Some(sample_size.unwrap_or(state.sweep_settings.sample_size)),
+        SweepMode::Full => None,
+    };
+    let Some(check) = sqlx::query_as::<_, IntegrityCheck>(
+        r#"
+        INSERT INTO integrity_checks (mode, triggered_by, sample_size)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        RETURNING *
+        "#,
+    )
+    .bind(mode.as_str())
+    .bind(triggered_by)
+    .bind(sample_size.map(|size| size as i32))
+    .fetch_optional(&state.db)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let check_id = check.check_id;
+    tokio::spawn(async move {
+        let outcome = sweep(&state, check_id, sample_size).await;
+        if let Err(e) = finish(&state, check_id, mode, outcome).await {
+            error!("Failed to record integrity check {}: {}", check_id, e);
+        }
+    });
+    Ok(Some(check))
+}
+
+async fn sweep(state: &AppState, check_id: Uuid, sample_size: Option<i64>) -> anyhow::Result<SweepTally> {
+    let events = state.documents.scan(sample_size).await?;
+
+    let service = AuditService::from_state(state.clone());
+    let service = &service;
+    let mut verified = events
+        .map_ok(move |event| async move {
+            let report = service
+                .verify_audit_event(&event)
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to verify audit event {}: {}", event.event_id, e))?;
+            Ok::<_, anyhow::Error>((event, report))
+        })
+        .try_buffer_unordered(state.sweep_settings.concurrency);
+
+    let mut tally = SweepTally::default();
+    while let Some((event, report)) = verified.try_next().await? {
+        tally.checked += 1;
+        if !report.verified {
+            tally.failed += 1;
+            warn!("Integrity check {} found audit event {} failing verification", check_id, event.event_id);
+            if tally.failures.len() < MAX_RECORDED_FAILURES {
+                tally.failures.push(EventFailure {
+                    event_id: event.event_id,
+                    tenant_id: event.tenant_id,
+                    failed_checks: report.checks.into_iter().filter(|check| !check.passed).collect(),
+                });
+            }
+        }
+        if tally.checked % PROGRESS_EVERY == 0 {
+            sqlx::query(
+                "UPDATE integrity_checks SET events_checked = $2, events_failed = $3, progressed_at = NOW() WHERE check_id = $1",
+            )
+            .bind(check_id)
+            .bind(tally.checked)
+            .bind(tally.failed)
+            .execute(&state.db)
+            .await?;
+        }
+    }
+    Ok(tally)
+}
+
+async fn finish(
+    state: &AppState,
+    check_id: Uuid,
+    mode: SweepMode,
+    outcome: anyhow::Result<SweepTally>,
+) -> anyhow::Result<()> {
+    let tally = match outcome {
+        Ok(tally) => tally,
+        Err(e) => {
+            error!("Integrity check {} failed: {}", check_id, e);
+            sqlx::query(
+                "UPDATE integrity_checks SET status = 'FAILED', error = $2, completed_at = NOW() WHERE check_id = $1",
+            )
+            .bind(check_id)
+            .bind(format!("{:#}", e))
+            .execute(&state.db)
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let status = if tally.failed == 0 { "PASSED" } else { "DISCREPANCIES" };
+    let check = sqlx::query_as::<_, IntegrityCheck>(
+        r#"
+        UPDATE integrity_checks
+        SET status = $2, events_checked = $3, events_failed = $4, failures = $5,
+            progressed_at = NOW(), completed_at = NOW()
+        WHERE check_id = $1
+        RETURNING *
+        "#,
+    )
+    .bind(check_id)
+    .bind(status)
+    .bind(tally.checked)
+    .bind(tally.failed)
+    .bind(serde_json::to_value(&tally.failures)?)
+    .fetch_one(&state.db)
+    .await?;
+    counter!("audit_integrity_events_checked_total", tally.checked as u64, "mode" => mode.as_str());
+    counter!("audit_integrity_events_failed_total", tally.failed as u64, "mode" => mode.as_str());
+
+    if tally.failed == 0 {
+        info!("Integrity check {} passed for {} events", check_id, tally.checked);
+        return Ok(());
+    }
+
+    warn!(
+        "Integrity check {} found {} of {} events failing verification",
+        check_id, tally.failed, tally.checked
+    );
+    raise_ops_alert(&state.db, &check).await?;
+    if state.sweep_settings.webhook_url.is_some() {
+        // The ops alert is already raised
+        if let Err(e) = deliver_webhook(state, &check).await {
+            warn!("Failed to deliver integrity check {} to webhook: {:#}", check_id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Failing checks completed on the UTC day whose webhook never went out
+pub async fn undelivered(db: &PgPool, day: chrono::NaiveDate) -> Result<Vec<IntegrityCheck>, sqlx::Error> {
+    let start = day.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+    sqlx::query_as::<_, IntegrityCheck>(
+        r#"
+        SELECT * FROM integrity_checks
+        WHERE events_failed > 0 AND webhook_delivered_at IS NULL
+          AND completed_at >= $1 AND completed_at < $2
+        ORDER BY completed_at
+        "#,
+    )
+    .bind(start)
+    .bind(start + chrono::Duration::days(1))
+    .fetch_all(db)
+    .await
+}
+
+/// Post a check to AUDIT_INTEGRITY_WEBHOOK_URL and record the delivery
+pub async fn deliver_webhook(state: &AppState, check: &IntegrityCheck) -> anyhow::Result<()> {
+    let url = state
+        .sweep_settings
+        .webhook_url
+        .as_deref()
+        .context("AUDIT_INTEGRITY_WEBHOOK_URL is not set")?;
+    send_webhook(url, state.sweep_settings.webhook_secret.as_deref(), check).await?;
+    sqlx::query("UPDATE integrity_checks SET webhook_delivered_at = NOW() WHERE check_id = $1")
+        .bind(check.check_id)
+        .execute(&state.db)
+        .await?;
+    Ok(())
+}
+
+async fn raise_ops_alert(db: &PgPool, check: &IntegrityCheck) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO system_events (event_type, severity, source_system, message, details, correlation_id)
+        VALUES ('INTEGRITY_DISCREPANCY', 'ERROR', 'audit-service', $1, $2, $3)
+        "#,
+    )
+    .bind(format!(
+        "{} integrity check found {} of {} audit events failing verification",
+        check.mode, check.events_failed, check.events_checked
+    ))
+    .bind(serde_json::to_value(check).unwrap_or_default())
+    .bind(check.check_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+async fn send_webhook(url: &str, secret: Option<&str>, check: &IntegrityCheck) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(&serde_json::json!({
+        "event": "audit.integrity_discrepancy",
+        "check": check,
+    }))?;
+
+    let mut request = reqwest::Client::new()
+        .post(url)
+        .timeout(std::time::Duration::from_secs(10))
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+    if let Some(secret) = secret {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).context("invalid webhook secret")?;
+        mac.update(&body);
+        request = request.header(
+            "X-DharmaGuard-Signature",
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+        );
+    }
+    request.body(body).send().await?.error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,323 @@
+//! Managed taxonomy of audit actions
+//!
+//! Producers name actions freely ("user.update", "UPDATE_USER", "UserUpdated"),
+//! which makes trails hard to query across services. Actions registered in
+//! audit_actions form a `namespace.verb` hierarchy ("trade.order.cancel" is
+//! in namespace "trade.order"), and aliases map other spellings onto them.
+//! Creating an event rewrites its action to the registered one, matching
+//! aliases and actions case-insensitively, before schemas are looked up and
+//! the event is hashed.
+//!
+//! Actions that are neither registered nor aliased are kept as sent while
+//! AUDIT_ACTION_TAXONOMY_MODE is lenient (the default) and rejected when it is
+//! strict; deprecated actions count as unknown. Outcomes are counted in
+//! `audit_action_normalizations_total`. Each replica reloads the taxonomy every
+//! AUDIT_ACTION_TAXONOMY_REFRESH_SECS, and at once after its own admin changes.
+//!
+//! Trails take `action=trade.*` for every action under a namespace; see
+//! `trail::push_action`.
+
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaxonomyMode {
+    Lenient,
+    Strict,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct ActionDefinition {
+    pub action: String,
+    pub namespace: String,
+    pub description: Option<String>,
+    pub aliases: Vec<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub deprecated_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterActionRequest {
+    pub action: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct AliasRequest {
+    pub alias: String,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct ActionListParams {
+    /// Namespace to list, with everything below it
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub include_deprecated: bool,
+}
+
+/// Returned by event creation when strict mode meets an unregistered action
+#[derive(Debug, Error)]
+#[error("audit action '{0}' is not in the action taxonomy")]
+pub struct ActionRejection(pub String);
+
+#[derive(Debug, Error)]
+pub enum TaxonomyError {
+    #[error("actions are lowercase namespace.verb segments of letters, digits and underscores, such as trade.order.cancel")]
+    InvalidAction,
+    #[error("alias must be 1 to 100 characters")]
+    InvalidAlias,
+    #[error("action {0} is already registered")]
+    ActionExists(String),
+    #[error("'{0}' is already a registered action or an alias")]
+    AliasTaken(String),
+    #[error("action not found")]
+    NotFound,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// A valid action: at least two lowercase segments starting with a letter
+pub fn is_valid_action(action: &str) -> bool {
+    action.len() <= 100
+        && action.split('.').count() >= 2
+        && action.split('.').all(|segment| {
+            segment.starts_with(|c: char| c.is_ascii_lowercase())
+                && segment.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        })
+}
+
+#[derive(Default)]
+struct ActionIndex {
+    actions: HashSet<String>,
+    /// Lowercase alias to action
+    aliases: HashMap<String, String>,
+}
+
+pub struct ActionTaxonomy {
+    mode: TaxonomyMode,
+    refresh: Duration,
+    index: RwLock<ActionIndex>,
+}
+
+impl ActionTaxonomy {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match std::env::var("AUDIT_ACTION_TAXONOMY_MODE").as_deref() {
+            Ok("strict") => TaxonomyMode::Strict,
+            Ok("lenient") | Ok("") | Err(_) => TaxonomyMode::Lenient,
+            Ok(other) => anyhow::bail!("AUDIT_ACTION_TAXONOMY_MODE must be lenient or strict, not {}", other),
+        };
+        Ok(Self {
+            mode,
+            refresh: Duration::from_secs(
+                std::env::var("AUDIT_ACTION_TAXONOMY_REFRESH_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(60),
+            ),
+            index: RwLock::new(ActionIndex::default()),
+        })
+    }
+
+    pub fn mode(&self) -> TaxonomyMode {
+        self.mode
+    }
+
+    /// Replace the in-memory taxonomy with the active actions and their aliases; returns how many actions
+    pub async fn load(&self, db: &PgPool) -> Result<usize, sqlx::Error> {
+        let actions: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_actions WHERE deprecated_at IS NULL")
+            .fetch_all(db)
+            .await?;
+        let aliases: Vec<(String, String)> = sqlx::query_as(
+            r#"
+            SELECT a.alias, a.action FROM audit_action_aliases a
+            JOIN audit_actions USING (action)
+            WHERE audit_actions.deprecated_at IS NULL
+            "#,
+        )
+        .fetch_all(db)
+        .await?;
+        let count = actions.len();
+        *self.index.write().expect("action taxonomy poisoned") = ActionIndex {
+            actions: actions.into_iter().collect(),
+            aliases: aliases.into_iter().collect(),
+        };
+        Ok(count)
+    }
+
+    pub fn spawn_refresh(self: Arc<Self>, db: PgPool) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(self.refresh);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            // The first tick is immediate; startup has just loaded it
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = self.load(&db).await {
+                    error!("Failed to reload the audit action taxonomy: {}", e);
+                }
+            }
+        });
+    }
+
+    /// The registered action for `action`, or `action` itself when it is unknown in lenient mode
+    pub fn normalize(&self, action: &str) -> Result<String, ActionRejection> {
+        let lowered = action.trim().to_lowercase();
+        let index = self.index.read().expect("action taxonomy poisoned");
+        if let Some(canonical) = index.aliases.get(&lowered) {
+            counter!("audit_action_normalizations_total", 1, "outcome" => "aliased");
+            return Ok(canonical.clone());
+        }
+        if index.actions.contains(&lowered) {
+            counter!("audit_action_normalizations_total", 1, "outcome" => "registered");
+            return Ok(lowered);
+        }
+        match self.mode {
+            TaxonomyMode::Strict => {
+                counter!("audit_action_normalizations_total", 1, "outcome" => "rejected");
+                Err(ActionRejection(action.to_string()))
+            }
+            TaxonomyMode::Lenient => {
+                counter!("audit_action_normalizations_total", 1, "outcome" => "unknown");
+                Ok(action.to_string())
+            }
+        }
+    }
+}
+
+const DEFINITION_COLUMNS: &str = r#"
+    a.action, a.namespace, a.description, a.created_by, a.created_at, a.deprecated_at,
+    ARRAY(SELECT alias::text FROM audit_action_aliases WHERE action = a.action ORDER BY alias) AS aliases
+"#;
+
+pub async fn list_actions(db: &PgPool, params: &ActionListParams) -> Result<Vec<ActionDefinition>, sqlx::Error> {
+    let namespace = params.namespace.as_deref().map(|namespace| namespace.trim_end_matches(".*").to_lowercase());
+    sqlx::query_as::<_, ActionDefinition>(&format!(
+        r#"
+        SELECT {} FROM audit_actions a
+        WHERE ($1::text IS NULL OR a.namespace = $1 OR starts_with(a.namespace, $1 || '.'))
+          AND ($2 OR a.deprecated_at IS NULL)
+        ORDER BY a.action
+        "#,
+        DEFINITION_COLUMNS
+    ))
+    .bind(namespace)
+    .bind(params.include_deprecated)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn get_action(db: &PgPool, action: &str) -> Result<Option<ActionDefinition>, sqlx::Error> {
+    sqlx::query_as::<_, ActionDefinition>(&format!(
+        "SELECT {} FROM audit_actions a WHERE a.action = $1",
+        DEFINITION_COLUMNS
+    ))
+    .bind(action)
+    .fetch_optional(db)
+    .await
+}
+
+/// Register an action together with its first aliases
+pub async fn register_action(db: &PgPool, request: &RegisterActionRequest) -> Result<ActionDefinition, TaxonomyError> {
+    let action = request.action.trim();
+    if !is_valid_action(action) {
+        return Err(TaxonomyError::InvalidAction);
+    }
+    let mut tx = db.begin().await?;
+    let inserted = sqlx::query(
+        "INSERT INTO audit_actions (action, description, created_by) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+    )
+    .bind(action)
+    .bind(&request.description)
+    .bind(request.created_by)
+    .execute(&mut *tx)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(TaxonomyError::ActionExists(action.to_string()));
+    }
+    for alias in &request.aliases {
+        insert_alias(&mut tx, action, alias, request.created_by).await?;
+    }
+    tx.commit().await?;
+    get_action(db, action).await?.ok_or(TaxonomyError::NotFound)
+}
+
+pub async fn add_alias(db: &PgPool, action: &str, request: &AliasRequest) -> Result<ActionDefinition, TaxonomyError> {
+    let mut tx = db.begin().await?;
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM audit_actions WHERE action = $1)")
+        .bind(action)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !exists {
+        return Err(TaxonomyError::NotFound);
+    }
+    insert_alias(&mut tx, action, &request.alias, request.created_by).await?;
+    tx.commit().await?;
+    get_action(db, action).await?.ok_or(TaxonomyError::NotFound)
+}
+
+async fn insert_alias(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    action: &str,
+    alias: &str,
+    created_by: Option<Uuid>,
+) -> Result<(), TaxonomyError> {
+    let alias = alias.trim().to_lowercase();
+    if alias.is_empty() || alias.chars().count() > 100 {
+        return Err(TaxonomyError::InvalidAlias);
+    }
+    // An alias shadowing a registered action would make that action unreachable
+    let is_action: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM audit_actions WHERE action = $1)")
+        .bind(&alias)
+        .fetch_one(&mut **tx)
+        .await?;
+    if is_action {
+        return Err(TaxonomyError::AliasTaken(alias));
+    }
+    let inserted = sqlx::query(
+        "INSERT INTO audit_action_aliases (alias, action, created_by) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+    )
+    .bind(&alias)
+    .bind(action)
+    .bind(created_by)
+    .execute(&mut **tx)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(TaxonomyError::AliasTaken(alias));
+    }
+    Ok(())
+}
+
+/// `false` when there was no such alias
+pub async fn remove_alias(db: &PgPool, alias: &str) -> Result<bool, sqlx::Error> {
+    let removed = sqlx::query("DELETE FROM audit_action_aliases WHERE alias = $1")
+        .bind(alias.trim().to_lowercase())
+        .execute(db)
+        .await?;
+    Ok(removed.rows_affected() > 0)
+}
+
+/// Stop treating an action as known; events already carrying it keep it
+pub async fn deprecate_action(db: &PgPool, action: &str) -> Result<Option<ActionDefinition>, sqlx::Error> {
+    let updated = sqlx::query("UPDATE audit_actions SET deprecated_at = COALESCE(deprecated_at, NOW()) WHERE action = $1")
+        .bind(action)
+        .execute(db)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+    warn!("Deprecated audit action {}", action);
+    get_action(db, action).await
+}
@@ -0,0 +1,234 @@
+//! Pagination helpers for audit trail queries
+//!
+//! audit_logs is partitioned by month of `timestamp`, so every bound on the
+//! time range is pushed as a plain comparison on that column: Postgres can
+//! only skip partitions for conditions it can evaluate against the partition
+//! key, not for `$1 IS NULL OR timestamp >= $1` or row comparisons.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+use sqlx::{Postgres, QueryBuilder};
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Keyset position in a trail ordered by (timestamp DESC, event_id DESC)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrailCursor {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub event_id: Uuid,
+}
+
+impl TrailCursor {
+    /// Opaque, URL-safe token handed back to clients
+    pub fn encode(&self) -> String {
+        let raw = format!(
+            "{}|{}",
+            self.timestamp.to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            self.event_id
+        );
+        URL_SAFE_NO_PAD.encode(raw)
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(token).ok()?).ok()?;
+        let (timestamp, event_id) = raw.split_once('|')?;
+        Some(Self {
+            timestamp: chrono::DateTime::parse_from_rfc3339(timestamp)
+                .ok()?
+                .with_timezone(&chrono::Utc),
+            event_id: Uuid::parse_str(event_id).ok()?,
+        })
+    }
+
+    /// Rows before this position in (timestamp DESC, log_id DESC) order
+    pub fn push_before(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        // The row comparison orders ties; the plain bound lets later months be pruned
+        builder
+            .push(" AND timestamp <= ")
+            .push_bind(self.timestamp)
+            .push(" AND (timestamp, log_id) < (")
+            .push_bind(self.timestamp)
+            .push(", ")
+            .push_bind(self.event_id)
+            .push(")");
+    }
+
+    /// Rows after this position in (timestamp ASC, log_id ASC) order
+    pub fn push_after(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder
+            .push(" AND timestamp >= ")
+            .push_bind(self.timestamp)
+            .push(" AND (timestamp, log_id) > (")
+            .push_bind(self.timestamp)
+            .push(", ")
+            .push_bind(self.event_id)
+            .push(")");
+    }
+}
+
+/// Page selection for GET /audit/events
+#[derive(Debug, Clone)]
+pub struct TrailPage {
+    pub limit: i64,
+    pub offset: i64,
+    /// When set, keyset pagination is used and `offset` is ignored
+    pub cursor: Option<TrailCursor>,
+    /// Run COUNT(*) instead of using the planner's row estimate
+    pub exact_count: bool,
+}
+
+/// Pull the planner's row estimate out of `EXPLAIN (FORMAT JSON)` output
+pub fn planner_row_estimate(plan: &serde_json::Value) -> Option<u64> {
+    plan.get(0)?
+        .get("Plan")?
+        .get("Plan Rows")?
+        .as_f64()
+        .map(|rows| rows.max(0.0) as u64)
+}
+
+/// Filters accepted by GET /audit/events; every value is bound, never interpolated
+#[derive(Debug, Clone)]
+pub struct AuditTrailFilter {
+    pub tenant_id: Uuid,
+    pub action: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<Uuid>,
+    pub ip_address: Option<IpAddr>,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    /// Restricted resource types the caller may not see
+    pub hidden_resource_types: Vec<String>,
+}
+
+impl AuditTrailFilter {
+    /// Append the WHERE clause for this filter to `builder`
+    pub fn push_where(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push(" WHERE tenant_id = ").push_bind(self.tenant_id);
+
+        if let Some(action) = &self.action {
+            push_action(builder, action);
+        }
+        if let Some(user_id) = self.user_id {
+            builder.push(" AND user_id = ").push_bind(user_id);
+        }
+        if let Some(resource_type) = &self.resource_type {
+            builder.push(" AND resource_type = ").push_bind(resource_type.clone());
+        }
+        if let Some(resource_id) = self.resource_id {
+            builder.push(" AND resource_id = ").push_bind(resource_id);
+        }
+        if let Some(ip_address) = self.ip_address {
+            builder
+                .push(" AND ip_address = ")
+                .push_bind(ip_address.to_string())
+                .push("::inet");
+        }
+        push_time_range(builder, self.from, self.to);
+        if !self.hidden_resource_types.is_empty() {
+            builder
+                .push(" AND resource_type <> ALL(")
+                .push_bind(self.hidden_resource_types.clone())
+                .push(")");
+        }
+    }
+}
+
+/// Append the condition for an `action` filter
+///
+/// `trade.*` matches every action under the trade namespace, the namespace
+/// itself excluded; anything else matches that action. Either way, events
+/// stored before normalization under one of the aliases, in lowercase or
+/// uppercase, match too.
+pub fn push_action(builder: &mut QueryBuilder<'_, Postgres>, action: &str) {
+    let action = action.trim();
+    let lowered = action.to_lowercase();
+    let namespace = lowered
+        .strip_suffix(".*")
+        .filter(|namespace| !namespace.is_empty() && !namespace.contains('*'));
+    let (condition, value) = match namespace {
+        Some(namespace) => {
+            let pattern = format!("{}.%", namespace.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+            builder.push(" AND (action LIKE ").push_bind(pattern.clone());
+            (" LIKE ", pattern)
+        }
+        None => {
+            builder
+                .push(" AND (action = ANY(")
+                .push_bind(vec![action.to_string(), lowered.clone()])
+                .push(")");
+            (" = ", lowered)
+        }
+    };
+    builder
+        .push(" OR action = ANY(ARRAY(SELECT unnest(ARRAY[alias, upper(alias)]) FROM audit_action_aliases WHERE action")
+        .push(condition)
+        .push_bind(value)
+        .push(")))");
+}
+
+/// Append bounds on `timestamp`, leaving out the open ends so partitions outside the range are pruned
+fn push_time_range(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+) {
+    if let Some(from) = from {
+        builder.push(" AND timestamp >= ").push_bind(from);
+    }
+    if let Some(to) = to {
+        builder.push(" AND timestamp < ").push_bind(to);
+    }
+}
+
+/// Query parameters for GET /audit/events
+#[derive(Debug, Deserialize)]
+pub struct AuditTrailParams {
+    pub tenant_id: Uuid,
+    pub action: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<Uuid>,
+    pub ip_address: Option<IpAddr>,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub exact_count: bool,
+}
+
+impl AuditTrailParams {
+    /// Split into filter and page; `None` if the cursor or time range is invalid
+    pub fn into_query(self, hidden_resource_types: Vec<String>) -> Option<(AuditTrailFilter, TrailPage)> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from >= to {
+                return None;
+            }
+        }
+        let cursor = match &self.cursor {
+            Some(token) => Some(TrailCursor::decode(token)?),
+            None => None,
+        };
+
+        let page = TrailPage {
+            limit: self.limit.unwrap_or(50).clamp(1, 1000),
+            offset: self.offset.unwrap_or(0).max(0),
+            cursor,
+            exact_count: self.exact_count,
+        };
+        let filter = AuditTrailFilter {
+            tenant_id: self.tenant_id,
+            action: self.action,
+            user_id: self.user_id,
+            resource_type: self.resource_type,
+            resource_id: self.resource_id,
+            ip_address: self.ip_address,
+            from: self.from,
+            to: self.to,
+            hidden_resource_types,
+        };
+        Some((filter, page))
+    }
+}
@@ -0,0 +1,456 @@
+//! RFC 3161 trusted timestamps
+//!
+//! Deployments that cannot use a blockchain still need third-party evidence
+//! that an event existed, unchanged, at a given time. With AUDIT_TSA_URL set,
+//! a time-stamping authority signs the SHA-256 the service already commits
+//! to: each event hash in per_event anchor mode, through the outbox so the
+//! write never waits on the authority, or each daily digest root in
+//! daily_digest mode, right after the digest is built. Timestamping works
+//! alongside chain anchoring or, with AUDIT_BLOCKCHAIN_ENABLED=false, instead
+//! of it.
+//!
+//! Tokens are checked before they are stored in audit_timestamps and again by
+//! verification, which adds a `tsa_timestamp` check: the token must cover the
+//! recomputed hash, or the root the event's inclusion proof leads to, and be
+//! signed under the authority certificate in AUDIT_TSA_CERTIFICATE, the trust
+//! anchor; the certificate the token carries is not trusted on its own. Only
+//! RSA signatures are supported, as issued by the common authorities.
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, NaiveDate, Utc};
+use cms::content_info::ContentInfo;
+use cms::signed_data::SignedData;
+use der::asn1::{ObjectIdentifier, OctetString, Uint};
+use der::{Decode, DecodePem, Encode};
+use metrics::counter;
+use rsa::pkcs1v15::{Signature, VerifyingKey};
+use rsa::pkcs8::DecodePublicKey;
+use rsa::signature::Verifier;
+use rsa::RsaPublicKey;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use sqlx::PgPool;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+use x509_cert::spki::AlgorithmIdentifierOwned;
+use x509_cert::Certificate;
+use x509_tsp::{MessageImprint, TimeStampReq, TimeStampResp, TspVersion, TstInfo};
+
+use crate::digest;
+
+const ID_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+const ID_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+const ID_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.3");
+const ID_SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+const ID_TST_INFO: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+const ID_MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+/// rsaEncryption and sha{256,384,512}WithRSAEncryption
+const RSA_SIGNATURES: [ObjectIdentifier; 4] = [
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1"),
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11"),
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12"),
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.13"),
+];
+
+/// What a token is over
+#[derive(Debug, Clone, Copy)]
+pub enum Subject {
+    Event { tenant_id: Uuid, event_id: Uuid },
+    Digest(NaiveDate),
+}
+
+/// A token as checked against the authority certificate
+#[derive(Debug, Clone)]
+pub struct TimestampToken {
+    /// DER TimeStampToken
+    pub token: Vec<u8>,
+    pub gen_time: DateTime<Utc>,
+    /// Hex
+    pub serial_number: String,
+    pub policy: String,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct StoredTimestamp {
+    pub timestamp_id: Uuid,
+    pub event_id: Option<Uuid>,
+    pub tenant_id: Option<Uuid>,
+    pub digest_date: Option<NaiveDate>,
+    pub hashed_value: String,
+    pub tsa_url: String,
+    #[serde(skip)]
+    pub token: Vec<u8>,
+    pub gen_time: DateTime<Utc>,
+    pub serial_number: String,
+    pub policy: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A stored token with what an auditor needs to check it against an event
+#[derive(Serialize, Debug, Clone)]
+pub struct TimestampEvidence {
+    #[serde(flatten)]
+    pub timestamp: StoredTimestamp,
+    /// Base64 DER TimeStampToken
+    pub token: String,
+    /// From the event up to the timestamped root, when the token is over a digest
+    pub inclusion_proof: Option<digest::InclusionProof>,
+}
+
+#[derive(Debug, Error)]
+pub enum TsaError {
+    #[error("time-stamping authority request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("time-stamping authority answered {0}")]
+    Status(reqwest::StatusCode),
+    #[error("time-stamping authority granted no token: {0}")]
+    Rejected(String),
+    #[error("malformed timestamp token: {0}")]
+    Malformed(String),
+    #[error("malformed timestamp token: {0}")]
+    Der(#[from] der::Error),
+    #[error("timestamp token covers {0}, not the expected hash")]
+    ImprintMismatch(String),
+    #[error("timestamp token does not answer the nonce of its request")]
+    NonceMismatch,
+    #[error("timestamp token signature does not verify under the authority certificate")]
+    SignatureInvalid,
+    #[error("timestamp token was issued at {0}, outside the authority certificate's validity")]
+    OutsideValidity(DateTime<Utc>),
+    #[error("timestamp token uses unsupported algorithm {0}")]
+    UnsupportedAlgorithm(ObjectIdentifier),
+    #[error("{0}")]
+    NotTimestamped(String),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct TsaClient {
+    url: String,
+    policy: Option<ObjectIdentifier>,
+    /// Trust anchor tokens are verified against
+    certificate: Certificate,
+    http: reqwest::Client,
+}
+
+impl TsaClient {
+    /// `None` when AUDIT_TSA_URL is unset
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let url = match std::env::var("AUDIT_TSA_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => return Ok(None),
+        };
+        let certificate_path = std::env::var("AUDIT_TSA_CERTIFICATE")
+            .ok()
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| {
+                anyhow::anyhow!("AUDIT_TSA_CERTIFICATE must name the authority certificate when AUDIT_TSA_URL is set")
+            })?;
+        let certificate = Certificate::from_pem(std::fs::read(&certificate_path)?).map_err(|e| {
+            anyhow::anyhow!("AUDIT_TSA_CERTIFICATE {} is not a PEM certificate: {}", certificate_path, e)
+        })?;
+        let policy = match std::env::var("AUDIT_TSA_POLICY_OID") {
+            Ok(oid) if !oid.is_empty() => Some(
+                ObjectIdentifier::new(&oid).map_err(|e| anyhow::anyhow!("AUDIT_TSA_POLICY_OID {}: {}", oid, e))?,
+            ),
+            _ => None,
+        };
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(
+                std::env::var("AUDIT_TSA_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(10),
+            ))
+            .build()?;
+        Ok(Some(Self {
+            url,
+            policy,
+            certificate,
+            http,
+        }))
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Have the authority timestamp a hex SHA-256, and check the token it returns
+    pub async fn timestamp(&self, hash: &str) -> Result<TimestampToken, TsaError> {
+        let mut nonce = [0u8; 8];
+        OsRng.fill_bytes(&mut nonce);
+        let nonce = Uint::new(&nonce)?;
+        let request = TimeStampReq {
+            version: TspVersion::V1,
+            message_imprint: MessageImprint {
+                hash_algorithm: AlgorithmIdentifierOwned {
+                    oid: ID_SHA256,
+                    parameters: None,
+                },
+                hashed_message: OctetString::new(hash_bytes(hash)?)?,
+            },
+            req_policy: self.policy,
+            nonce: Some(nonce.clone()),
+            // The token then carries the certificate, for checks outside this service
+            cert_req: true,
+            extensions: None,
+        };
+
+        let response = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/timestamp-query")
+            .body(request.to_der()?)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(TsaError::Status(response.status()));
+        }
+        let response = TimeStampResp::from_der(&response.bytes().await?)?;
+        // Only granted requests carry a token
+        let token = response
+            .time_stamp_token
+            .ok_or_else(|| TsaError::Rejected(format!("{:?}", response.status)))?
+            .to_der()?;
+
+        let tst_info = self.check(&token, hash)?;
+        if tst_info.nonce.as_ref() != Some(&nonce) {
+            return Err(TsaError::NonceMismatch);
+        }
+        counter!("audit_tsa_timestamps_total", 1);
+        token_from(token, &tst_info)
+    }
+
+    /// Verify a stored token against a hex SHA-256, returning when it was issued
+    pub fn verify(&self, token: &[u8], hash: &str) -> Result<DateTime<Utc>, TsaError> {
+        let tst_info = self.check(token, hash)?;
+        gen_time(&tst_info)
+    }
+
+    /// Check the token covers `hash` and is signed under the authority certificate
+    fn check(&self, token: &[u8], hash: &str) -> Result<TstInfo, TsaError> {
+        let content_info = ContentInfo::from_der(token)?;
+        if content_info.content_type != ID_SIGNED_DATA {
+            return Err(TsaError::Malformed("not a CMS SignedData".to_string()));
+        }
+        let signed_data: SignedData = content_info.content.decode_as()?;
+        if signed_data.encap_content_info.econtent_type != ID_TST_INFO {
+            return Err(TsaError::Malformed("content is not a TSTInfo".to_string()));
+        }
+        let tst_der = signed_data
+            .encap_content_info
+            .econtent
+            .as_ref()
+            .ok_or_else(|| TsaError::Malformed("no TSTInfo".to_string()))?
+            .decode_as::<OctetString>()?
+            .into_bytes();
+        let tst_info = TstInfo::from_der(&tst_der)?;
+
+        let imprint = &tst_info.message_imprint;
+        if imprint.hash_algorithm.oid != ID_SHA256 {
+            return Err(TsaError::UnsupportedAlgorithm(imprint.hash_algorithm.oid));
+        }
+        if imprint.hashed_message.as_bytes() != hash_bytes(hash)?.as_slice() {
+            return Err(TsaError::ImprintMismatch(hex::encode(imprint.hashed_message.as_bytes())));
+        }
+
+        let signer = signed_data
+            .signer_infos
+            .0
+            .iter()
+            .next()
+            .ok_or_else(|| TsaError::Malformed("no signer".to_string()))?;
+        let signed_attrs = signer
+            .signed_attrs
+            .as_ref()
+            .ok_or_else(|| TsaError::Malformed("no signed attributes".to_string()))?;
+        let message_digest = signed_attrs
+            .iter()
+            .find(|attribute| attribute.oid == ID_MESSAGE_DIGEST)
+            .and_then(|attribute| attribute.values.iter().next())
+            .ok_or_else(|| TsaError::Malformed("no message digest attribute".to_string()))?
+            .decode_as::<OctetString>()?;
+        let digest_alg = signer.digest_alg.oid;
+        let tst_digest = match digest_alg {
+            ID_SHA256 => Sha256::digest(&tst_der).to_vec(),
+            ID_SHA384 => Sha384::digest(&tst_der).to_vec(),
+            ID_SHA512 => Sha512::digest(&tst_der).to_vec(),
+            other => return Err(TsaError::UnsupportedAlgorithm(other)),
+        };
+        if message_digest.as_bytes() != tst_digest.as_slice() {
+            return Err(TsaError::SignatureInvalid);
+        }
+
+        // The signature is over the signed attributes encoded as a SET OF
+        if !RSA_SIGNATURES.contains(&signer.signature_algorithm.oid) {
+            return Err(TsaError::UnsupportedAlgorithm(signer.signature_algorithm.oid));
+        }
+        let spki = self.certificate.tbs_certificate.subject_public_key_info.to_der()?;
+        let key = RsaPublicKey::from_public_key_der(&spki)
+            .map_err(|e| TsaError::Malformed(format!("authority certificate key: {}", e)))?;
+        let signature =
+            Signature::try_from(signer.signature.as_bytes()).map_err(|_| TsaError::SignatureInvalid)?;
+        let signed = signed_attrs.to_der()?;
+        let verified = match digest_alg {
+            ID_SHA256 => VerifyingKey::<Sha256>::new(key).verify(&signed, &signature),
+            ID_SHA384 => VerifyingKey::<Sha384>::new(key).verify(&signed, &signature),
+            _ => VerifyingKey::<Sha512>::new(key).verify(&signed, &signature),
+        };
+        verified.map_err(|_| TsaError::SignatureInvalid)?;
+
+        let issued = gen_time(&tst_info)?;
+        let validity = &self.certificate.tbs_certificate.validity;
+        let from = validity.not_before.to_unix_duration().as_secs() as i64;
+        let until = validity.not_after.to_unix_duration().as_secs() as i64;
+        if issued.timestamp() < from || issued.timestamp() > until {
+            return Err(TsaError::OutsideValidity(issued));
+        }
+        Ok(tst_info)
+    }
+
+    /// Verify that `event_id`, hashing to `computed_hash`, was timestamped
+    ///
+    /// Either the event's own token, or the token of the digest it is a leaf
+    /// of, reached through its inclusion proof.
+    pub async fn verify_event(
+        &self,
+        db: &PgPool,
+        event_id: Uuid,
+        computed_hash: &str,
+    ) -> Result<DateTime<Utc>, TsaError> {
+        if let Some(stored) = for_event(db, event_id).await? {
+            return self.verify(&stored.token, computed_hash);
+        }
+        let proof = digest::inclusion_proof(db, event_id)
+            .await
+            .map_err(|e| TsaError::NotTimestamped(format!("inclusion proof lookup failed: {}", e)))?
+            .ok_or_else(|| TsaError::NotTimestamped("event was never timestamped".to_string()))?;
+        if proof.event_hash != computed_hash {
+            return Err(TsaError::NotTimestamped(format!(
+                "digest {} committed to hash {}",
+                proof.digest_date, proof.event_hash
+            )));
+        }
+        let root = digest::root_from_path(computed_hash, &proof.path)
+            .map_err(|e| TsaError::NotTimestamped(format!("invalid inclusion proof: {}", e)))?;
+        if root != proof.merkle_root {
+            return Err(TsaError::NotTimestamped(format!(
+                "inclusion proof leads to {}, not digest root {}",
+                root, proof.merkle_root
+            )));
+        }
+        let stored = for_digest(db, proof.digest_date)
+            .await?
+            .ok_or_else(|| TsaError::NotTimestamped(format!("digest {} was never timestamped", proof.digest_date)))?;
+        self.verify(&stored.token, &root)
+    }
+}
+
+fn hash_bytes(hash: &str) -> Result<Vec<u8>, TsaError> {
+    match hex::decode(hash) {
+        Ok(bytes) if bytes.len() == 32 => Ok(bytes),
+        _ => Err(TsaError::Malformed(format!("{} is not a hex SHA-256", hash))),
+    }
+}
+
+fn gen_time(tst_info: &TstInfo) -> Result<DateTime<Utc>, TsaError> {
+    let since_epoch = tst_info.gen_time.to_unix_duration();
+    DateTime::from_timestamp(since_epoch.as_secs() as i64, since_epoch.subsec_nanos())
+        .ok_or_else(|| TsaError::Malformed("genTime out of range".to_string()))
+}
+
+fn token_from(token: Vec<u8>, tst_info: &TstInfo) -> Result<TimestampToken, TsaError> {
+    Ok(TimestampToken {
+        gen_time: gen_time(tst_info)?,
+        serial_number: hex::encode(tst_info.serial_number.as_bytes()),
+        policy: tst_info.policy.to_string(),
+        token,
+    })
+}
+
+/// Keep a token; a subject already timestamped keeps its first token
+pub async fn record(
+    db: &PgPool,
+    subject: Subject,
+    hash: &str,
+    tsa_url: &str,
+    token: &TimestampToken,
+) -> Result<(), sqlx::Error> {
+    let (event_id, tenant_id, digest_date, conflict) = match subject {
+        Subject::Event { tenant_id, event_id } => (Some(event_id), Some(tenant_id), None, "event_id"),
+        Subject::Digest(day) => (None, None, Some(day), "digest_date"),
+    };
+    sqlx::query(&format!(
+        r#"
+        INSERT INTO audit_timestamps (
+            event_id, tenant_id, digest_date, hashed_value, tsa_url, token, gen_time, serial_number, policy
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT ({0}) WHERE {0} IS NOT NULL DO NOTHING
+        "#,
+        conflict
+    ))
+    .bind(event_id)
+    .bind(tenant_id)
+    .bind(digest_date)
+    .bind(hash)
+    .bind(tsa_url)
+    .bind(&token.token)
+    .bind(token.gen_time)
+    .bind(&token.serial_number)
+    .bind(&token.policy)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn for_event(db: &PgPool, event_id: Uuid) -> Result<Option<StoredTimestamp>, sqlx::Error> {
+    sqlx::query_as::<_, StoredTimestamp>("SELECT * FROM audit_timestamps WHERE event_id = $1")
+        .bind(event_id)
+        .fetch_optional(db)
+        .await
+}
+
+pub async fn for_digest(db: &PgPool, day: NaiveDate) -> Result<Option<StoredTimestamp>, sqlx::Error> {
+    sqlx::query_as::<_, StoredTimestamp>("SELECT * FROM audit_timestamps WHERE digest_date = $1")
+        .bind(day)
+        .fetch_optional(db)
+        .await
+}
+
+/// The token covering `event_id`, its own or its digest's; `None` while it has neither
+pub async fn evidence(db: &PgPool, event_id: Uuid) -> anyhow::Result<Option<TimestampEvidence>> {
+    if let Some(timestamp) = for_event(db, event_id).await? {
+        return Ok(Some(TimestampEvidence {
+            token: STANDARD.encode(&timestamp.token),
+            timestamp,
+            inclusion_proof: None,
+        }));
+    }
+    let Some(proof) = digest::inclusion_proof(db, event_id).await? else {
+        return Ok(None);
+    };
+    Ok(for_digest(db, proof.digest_date).await?.map(|timestamp| TimestampEvidence {
+        token: STANDARD.encode(&timestamp.token),
+        timestamp,
+        inclusion_proof: Some(proof),
+    }))
+}
+
+/// Built digests of the last `days` days whose root has no token yet, with that root
+pub async fn untimestamped_digests(db: &PgPool, days: i32) -> Result<Vec<(NaiveDate, String)>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT d.digest_date, d.merkle_root FROM audit_anchor_digests d
+        WHERE d.status IN ('ANCHORED', 'PENDING_ANCHOR') AND d.merkle_root IS NOT NULL
+          AND d.digest_date >= CURRENT_DATE - $1
+          AND NOT EXISTS (SELECT 1 FROM audit_timestamps t WHERE t.digest_date = d.digest_date)
+        ORDER BY d.digest_date
+        "#,
+    )
+    .bind(days)
+    .fetch_all(db)
+    .await
+}
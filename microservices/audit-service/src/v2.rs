@@ -0,0 +1,175 @@
+//! Version 2 of the audit event API
+//!
+//! v2 groups an event's resource, changes, client context and integrity
+//! proofs into nested objects and renames `timestamp` to `occurred_at` and
+//! `user_id` to `actor_id`. The handlers run the v1 handlers, which own the
+//! service calls and error mapping, and only reshape what they return; a
+//! queued event's 202 receipt and a stored event's signed receipt are the
+//! same in both versions.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::{get, post},
+    Router,
+};
+use chrono::TimeZone;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::actors::ActorSnapshot;
+use crate::auth::Caller;
+use crate::context::RequestContext;
+use crate::envelope::Reader;
+use crate::receipts::Receipted;
+use crate::trail::AuditTrailParams;
+use crate::{AppState, AuditEvent, AuditTrailResponse, CreateAuditEventRequest, Ingested, ResourceTrailParams};
+
+/// When the v1 event endpoints were deprecated in favour of these
+pub fn v1_deprecated_at() -> chrono::DateTime<chrono::Utc> {
+    chrono::Utc.with_ymd_and_hms(2026, 10, 14, 0, 0, 0).single().expect("valid date")
+}
+
+#[derive(Serialize)]
+pub struct ResourceRef {
+    #[serde(rename = "type")]
+    pub resource_type: String,
+    pub id: Option<Uuid>,
+}
+
+#[derive(Serialize)]
+pub struct Changes {
+    pub old: Option<serde_json::Value>,
+    pub new: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+pub struct ClientContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub producer: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Integrity {
+    pub event_hash: Option<String>,
+    pub blockchain_hash: Option<String>,
+    pub ipfs_hash: Option<String>,
+    pub signature: Option<String>,
+    pub signing_key_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEventV2 {
+    pub event_id: Uuid,
+    pub tenant_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<ActorSnapshot>,
+    pub action: String,
+    pub resource: ResourceRef,
+    pub changes: Changes,
+    pub client: ClientContext,
+    pub occurred_at: chrono::DateTime<chrono::Utc>,
+    pub integrity: Integrity,
+}
+
+impl From<AuditEvent> for AuditEventV2 {
+    fn from(event: AuditEvent) -> Self {
+        Self {
+            event_id: event.event_id,
+            tenant_id: event.tenant_id,
+            actor_id: event.user_id,
+            actor: event.actor,
+            action: event.action,
+            resource: ResourceRef {
+                resource_type: event.resource_type,
+                id: event.resource_id,
+            },
+            changes: Changes {
+                old: event.old_values,
+                new: event.new_values,
+            },
+            client: ClientContext {
+                ip_address: event.ip_address,
+                user_agent: event.user_agent,
+                request_id: event.request_id,
+                producer: event.producer,
+            },
+            occurred_at: event.timestamp,
+            integrity: Integrity {
+                event_hash: event.event_hash,
+                blockchain_hash: event.blockchain_hash,
+                ipfs_hash: event.ipfs_hash,
+                signature: event.signature,
+                signing_key_id: event.signing_key_id,
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditTrailResponseV2 {
+    pub events: Vec<AuditEventV2>,
+    pub total_count: u64,
+    pub count_is_estimate: bool,
+    pub next_cursor: Option<String>,
+    pub integrity_verified: bool,
+    pub blockchain_anchored: bool,
+}
+
+impl From<AuditTrailResponse> for AuditTrailResponseV2 {
+    fn from(trail: AuditTrailResponse) -> Self {
+        Self {
+            events: trail.events.into_iter().map(AuditEventV2::from).collect(),
+            total_count: trail.total_count,
+            count_is_estimate: trail.count_is_estimate,
+            next_cursor: trail.next_cursor,
+            integrity_verified: trail.integrity_verified,
+            blockchain_anchored: trail.blockchain_anchored,
+        }
+    }
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/audit/events", post(create_audit_event).get(get_audit_trail))
+        .route("/audit/trail/:resource_type/:resource_id", get(get_resource_audit_trail))
+}
+
+async fn create_audit_event(
+    State(state): State<AppState>,
+    context: RequestContext,
+    Json(request): Json<CreateAuditEventRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    Ok(match crate::ingest_audit_event(state.clone(), context, request).await? {
+        Ingested::Stored(event) => {
+            let receipt = crate::issue_receipt(&state, &event);
+            Json(Receipted { event: AuditEventV2::from(event), receipt }).into_response()
+        }
+        Ingested::Queued(receipt) => receipt.into_response(),
+    })
+}
+
+async fn get_audit_trail(
+    params: Query<AuditTrailParams>,
+    caller: Caller,
+    reader: Reader,
+    state: State<AppState>,
+) -> Result<Json<AuditTrailResponseV2>, StatusCode> {
+    let Json(trail) = crate::get_audit_trail(params, caller, reader, state).await?;
+    Ok(Json(trail.into()))
+}
+
+async fn get_resource_audit_trail(
+    path: Path<(String, Uuid)>,
+    params: Query<ResourceTrailParams>,
+    reader: Reader,
+    state: State<AppState>,
+) -> Result<Json<AuditTrailResponseV2>, StatusCode> {
+    let Json(trail) = crate::get_resource_audit_trail(path, params, reader, state).await?;
+    Ok(Json(trail.into()))
+}
@@ -0,0 +1,475 @@
+//! Local write-ahead queue for events the stores could not take
+//!
+//! When Postgres or the document store is unavailable, creating an event would
+//! otherwise fail with a 500 and the event would be lost unless the producer
+//! retried it. With AUDIT_WAL_DIR set, such an event is instead written to a
+//! sled log on local disk and the caller gets 202 Accepted once that write is
+//! flushed: the event id is final, but the event is not stored or anchored yet
+//! and does not appear in trails until it drains. GET /audit/queue/:event_id
+//! answers QUEUED, DEAD or STORED.
+//!
+//! A worker replays entries in acceptance order under their original id and
+//! timestamp, removing each only after its stores confirm it, and stops at the
+//! first failure so queued events are stored in the order they were accepted.
+//! An event whose audit row was written but not its document is queued at that
+//! stage, so replays never write the row twice; one whose row turns out to have
+//! committed before the connection dropped is finished the same way from the
+//! stored row, and anchored if it has no anchor. Entries rejected by a STRICT
+//! schema or the strict action taxonomy on replay, or still failing after
+//! AUDIT_WAL_MAX_ATTEMPTS while the stores answer, are moved aside as DEAD and
+//! wait for a manual retry. The depth is exported as `audit_wal_entries`.
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use metrics::{counter, gauge};
+use serde::{Deserialize, Serialize};
+use sled::Transactional;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::context::RequestContext;
+use crate::schemas::SchemaRejection;
+use crate::taxonomy::ActionRejection;
+use crate::{AppState, AuditEvent, AuditService, CreateAuditEventRequest};
+
+pub struct WalSettings {
+    pub drain_interval: Duration,
+    pub max_attempts: u32,
+}
+
+impl WalSettings {
+    pub fn from_env() -> Self {
+        Self {
+            drain_interval: Duration::from_secs(
+                std::env::var("AUDIT_WAL_DRAIN_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(5),
+            ),
+            max_attempts: std::env::var("AUDIT_WAL_MAX_ATTEMPTS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(50),
+        }
+    }
+}
+
+/// Returned by event creation when the audit row was written but the rest of
+/// the event was not; it must be finished, not created again
+#[derive(Debug, thiserror::Error)]
+#[error("audit event {} was stored without its document: {error}", .event.event_id)]
+pub struct StoredWithoutDocument {
+    pub event: AuditEvent,
+    pub error: String,
+}
+
+/// What is left to do for a queued event
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "stage", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Pending {
+    /// Nothing was stored; the event is created again under its id and timestamp
+    Create {
+        request: CreateAuditEventRequest,
+        context: RequestContext,
+    },
+    /// The audit row exists; the document is written and the event published
+    Document { event: AuditEvent },
+}
+
+impl Pending {
+    fn stage(&self) -> &'static str {
+        match self {
+            Pending::Create { .. } => "CREATE",
+            Pending::Document { .. } => "DOCUMENT",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct QueuedEvent {
+    event_id: Uuid,
+    tenant_id: Uuid,
+    resource_type: String,
+    accepted_at: DateTime<Utc>,
+    attempts: u32,
+    last_error: Option<String>,
+    pending: Pending,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum QueueState {
+    Queued,
+    Dead,
+    Stored,
+}
+
+/// Where an accepted event is, for GET /audit/queue/:event_id
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueStatus {
+    pub event_id: Uuid,
+    #[serde(skip)]
+    pub tenant_id: Uuid,
+    #[serde(skip)]
+    pub resource_type: String,
+    pub status: QueueState,
+    /// CREATE or DOCUMENT while queued
+    pub stage: Option<&'static str>,
+    pub accepted_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+impl QueueStatus {
+    pub fn stored(event_id: Uuid, tenant_id: Uuid, resource_type: String, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            event_id,
+            tenant_id,
+            resource_type,
+            status: QueueState::Stored,
+            stage: None,
+            accepted_at: timestamp,
+            attempts: 0,
+            last_error: None,
+        }
+    }
+
+    fn of(entry: &QueuedEvent, status: QueueState) -> Self {
+        Self {
+            event_id: entry.event_id,
+            tenant_id: entry.tenant_id,
+            resource_type: entry.resource_type.clone(),
+            status,
+            stage: Some(entry.pending.stage()),
+            accepted_at: entry.accepted_at,
+            attempts: entry.attempts,
+            last_error: entry.last_error.clone(),
+        }
+    }
+}
+
+/// Acknowledgement of a queued event: it is durable locally but not stored yet
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueReceipt {
+    pub event_id: Uuid,
+    pub status: QueueState,
+    pub accepted_at: DateTime<Utc>,
+}
+
+impl IntoResponse for QueueReceipt {
+    fn into_response(self) -> Response {
+        let location = format!("/api/v1/audit/queue/{}", self.event_id);
+        (StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(self)).into_response()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct QueueSummary {
+    pub directory: String,
+    pub queued: usize,
+    pub dead: usize,
+    pub oldest_accepted_at: Option<DateTime<Utc>>,
+    /// Dead entries, oldest first
+    pub dead_entries: Vec<QueueStatus>,
+}
+
+pub struct WriteAheadQueue {
+    directory: String,
+    db: sled::Db,
+    /// Entries by big-endian sequence number, so iteration is acceptance order
+    queued: sled::Tree,
+    dead: sled::Tree,
+    /// Event id to sequence number, for entries in either tree
+    index: sled::Tree,
+}
+
+impl WriteAheadQueue {
+    /// The queue under AUDIT_WAL_DIR; `None` when it is not set
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        match std::env::var("AUDIT_WAL_DIR") {
+            Ok(directory) if !directory.trim().is_empty() => Ok(Some(Self::open(directory.trim())?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn open(directory: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let db = sled::open(directory.as_ref())?;
+        Ok(Self {
+            directory: directory.as_ref().display().to_string(),
+            queued: db.open_tree("queued")?,
+            dead: db.open_tree("dead")?,
+            index: db.open_tree("index")?,
+            db,
+        })
+    }
+
+    pub fn directory(&self) -> &str {
+        &self.directory
+    }
+
+    /// Queue an event; it is on disk when this returns
+    pub async fn enqueue(
+        &self,
+        event_id: Uuid,
+        request: &CreateAuditEventRequest,
+        accepted_at: DateTime<Utc>,
+        pending: Pending,
+    ) -> anyhow::Result<QueueReceipt> {
+        let entry = QueuedEvent {
+            event_id,
+            tenant_id: request.tenant_id,
+            resource_type: request.resource_type.clone(),
+            accepted_at,
+            attempts: 0,
+            last_error: None,
+            pending,
+        };
+        let key = self.db.generate_id()?.to_be_bytes().to_vec();
+        let value = serde_json::to_vec(&entry)?;
+        (&self.queued, &self.index)
+            .transaction(|(queued, index)| {
+                queued.insert(key.as_slice(), value.as_slice())?;
+                index.insert(&event_id.as_bytes()[..], key.as_slice())?;
+                Ok(())
+            })
+            .map_err(|e: sled::transaction::TransactionError| anyhow::anyhow!("write-ahead log write failed: {:?}", e))?;
+        self.db.flush_async().await?;
+
+        counter!("audit_wal_enqueued_total", 1, "stage" => entry.pending.stage());
+        Ok(QueueReceipt {
+            event_id,
+            status: QueueState::Queued,
+            accepted_at,
+        })
+    }
+
+    /// The queued or dead entry for an event; `None` once it has drained
+    pub fn status(&self, event_id: Uuid) -> anyhow::Result<Option<QueueStatus>> {
+        let Some(key) = self.index.get(event_id.as_bytes())? else {
+            return Ok(None);
+        };
+        if let Some(value) = self.queued.get(&key)? {
+            return Ok(Some(QueueStatus::of(&serde_json::from_slice(&value)?, QueueState::Queued)));
+        }
+        if let Some(value) = self.dead.get(&key)? {
+            return Ok(Some(QueueStatus::of(&serde_json::from_slice(&value)?, QueueState::Dead)));
+        }
+        Ok(None)
+    }
+
+    pub fn summary(&self) -> anyhow::Result<QueueSummary> {
+        let oldest_accepted_at = match self.queued.first()? {
+            Some((_, value)) => Some(serde_json::from_slice::<QueuedEvent>(&value)?.accepted_at),
+            None => None,
+        };
+        let dead_entries = self
+            .dead
+            .iter()
+            .values()
+            .map(|value| Ok(QueueStatus::of(&serde_json::from_slice(&value?)?, QueueState::Dead)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(QueueSummary {
+            directory: self.directory.clone(),
+            queued: self.queued.len(),
+            dead: self.dead.len(),
+            oldest_accepted_at,
+            dead_entries,
+        })
+    }
+
+    /// Return a dead entry to its place in the queue with its attempts reset;
+    /// `None` when the event has no dead entry
+    pub async fn requeue(&self, event_id: Uuid) -> anyhow::Result<Option<QueueStatus>> {
+        let Some(key) = self.index.get(event_id.as_bytes())? else {
+            return Ok(None);
+        };
+        let Some(value) = self.dead.get(&key)? else {
+            return Ok(None);
+        };
+        let mut entry: QueuedEvent = serde_json::from_slice(&value)?;
+        entry.attempts = 0;
+        let value = serde_json::to_vec(&entry)?;
+        (&self.queued, &self.dead)
+            .transaction(|(queued, dead)| {
+                dead.remove(&key[..])?;
+                queued.insert(&key[..], value.as_slice())?;
+                Ok(())
+            })
+            .map_err(|e: sled::transaction::TransactionError| anyhow::anyhow!("write-ahead log write failed: {:?}", e))?;
+        self.db.flush_async().await?;
+        Ok(Some(QueueStatus::of(&entry, QueueState::Queued)))
+    }
+
+    /// Remove an entry whose event is now stored
+    async fn acknowledge(&self, key: &[u8], event_id: Uuid) -> anyhow::Result<()> {
+        (&self.queued, &self.index)
+            .transaction(|(queued, index)| {
+                queued.remove(key)?;
+                index.remove(&event_id.as_bytes()[..])?;
+                Ok(())
+            })
+            .map_err(|e: sled::transaction::TransactionError| anyhow::anyhow!("write-ahead log write failed: {:?}", e))?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn update(&self, key: &[u8], entry: &QueuedEvent) -> anyhow::Result<()> {
+        self.queued.insert(key, serde_json::to_vec(entry)?)?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn bury(&self, key: &[u8], entry: &QueuedEvent) -> anyhow::Result<()> {
+        let value = serde_json::to_vec(entry)?;
+        (&self.queued, &self.dead)
+            .transaction(|(queued, dead)| {
+                queued.remove(key)?;
+                dead.insert(key, value.as_slice())?;
+                Ok(())
+            })
+            .map_err(|e: sled::transaction::TransactionError| anyhow::anyhow!("write-ahead log write failed: {:?}", e))?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+}
+
+pub fn spawn_worker(state: AppState, settings: WalSettings) {
+    let Some(queue) = state.write_ahead.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(settings.drain_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match drain(&state, &queue, &settings).await {
+                Ok(0) => {}
+                Ok(drained) => info!("Stored {} audit events from the write-ahead log", drained),
+                Err(e) => error!("Audit write-ahead log drain failed: {}", e),
+            }
+            gauge!("audit_wal_entries", queue.queued.len() as f64, "status" => "QUEUED");
+            gauge!("audit_wal_entries", queue.dead.len() as f64, "status" => "DEAD");
+        }
+    });
+}
+
+/// How replaying one entry went
+enum Replay {
+    Stored,
+    /// The audit row was written this time but the document was not
+    Partial(AuditEvent, String),
+    Rejected(String),
+    Failed(String),
+}
+
+/// Replay entries in order until the queue is empty or one fails
+async fn drain(state: &AppState, queue: &WriteAheadQueue, settings: &WalSettings) -> anyhow::Result<usize> {
+    let mut drained = 0;
+    while let Some((key, value)) = queue.queued.first()? {
+        let mut entry: QueuedEvent = serde_json::from_slice(&value)?;
+        match replay(state, &entry).await {
+            Replay::Stored => {
+                queue.acknowledge(&key, entry.event_id).await?;
+                counter!("audit_wal_drained_total", 1, "stage" => entry.pending.stage());
+                drained += 1;
+            }
+            Replay::Partial(event, error) => {
+                warn!("Audit event {} is stored; its document is still queued: {}", entry.event_id, error);
+                entry.attempts += 1;
+                entry.last_error = Some(error);
+                entry.pending = Pending::Document { event };
+                queue.update(&key, &entry).await?;
+                break;
+            }
+            Replay::Rejected(error) => {
+                warn!("Queued audit event {} was rejected on replay: {}", entry.event_id, error);
+                entry.last_error = Some(error);
+                queue.bury(&key, &entry).await?;
+                counter!("audit_wal_dead_total", 1, "reason" => "rejected");
+            }
+            Replay::Failed(error) => {
+                entry.attempts += 1;
+                entry.last_error = Some(error);
+                // Attempts only run out while the stores answer; an outage alone never kills an event
+                if entry.attempts >= settings.max_attempts && stores_reachable(state).await {
+                    error!(
+                        "Queued audit event {} failed {} times with the stores up; moving it aside: {}",
+                        entry.event_id,
+                        entry.attempts,
+                        entry.last_error.as_deref().unwrap_or_default()
+                    );
+                    queue.bury(&key, &entry).await?;
+                    counter!("audit_wal_dead_total", 1, "reason" => "attempts");
+                    continue;
+                }
+                queue.update(&key, &entry).await?;
+                break;
+            }
+        }
+    }
+    Ok(drained)
+}
+
+async fn replay(state: &AppState, entry: &QueuedEvent) -> Replay {
+    let service = AuditService::from_state(state.clone());
+    match &entry.pending {
+        Pending::Create { request, context } => {
+            // The row may have committed just before the connection to Postgres dropped
+            match already_stored(state, entry.event_id).await {
+                Ok(true) => return finish_stored(&service, entry).await,
+                Ok(false) => {}
+                Err(e) => return Replay::Failed(e.to_string()),
+            }
+            match service
+                .create_audit_event_as(entry.event_id, entry.accepted_at, request.clone(), context)
+                .await
+            {
+                Ok(_) => Replay::Stored,
+                Err(e) if e.is::<SchemaRejection>() => Replay::Rejected(e.to_string()),
+                Err(e) if e.is::<ActionRejection>() => Replay::Rejected(e.to_string()),
+                Err(e) => match e.downcast::<StoredWithoutDocument>() {
+                    Ok(partial) => Replay::Partial(partial.event, partial.error),
+                    Err(e) => Replay::Failed(e.to_string()),
+                },
+            }
+        }
+        Pending::Document { event } => match service.store_document(event).await {
+            Ok(()) => Replay::Stored,
+            Err(e) => Replay::Failed(e.to_string()),
+        },
+    }
+}
+
+/// Store the document of an event whose audit row an earlier attempt wrote, publish and anchor it
+async fn finish_stored(service: &AuditService, entry: &QueuedEvent) -> Replay {
+    let event = match service.stored_event(entry.event_id, entry.accepted_at).await {
+        Ok(Some(event)) => event,
+        Ok(None) => return Replay::Failed(format!("the audit row of event {} is gone", entry.event_id)),
+        Err(e) => return Replay::Failed(e.to_string()),
+    };
+    if let Err(e) = service.store_document(&event).await {
+        return Replay::Failed(e.to_string());
+    }
+    match service.anchor_stored(&event).await {
+        Ok(()) => Replay::Stored,
+        Err(e) => Replay::Failed(e.to_string()),
+    }
+}
+
+async fn already_stored(state: &AppState, event_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM audit_logs WHERE log_id = $1)")
+        .bind(event_id)
+        .fetch_one(&state.db)
+        .await
+}
+
+async fn stores_reachable(state: &AppState) -> bool {
+    sqlx::query("SELECT 1").execute(&state.db).await.is_ok() && state.documents.ping().await.is_ok()
+}
@@ -0,0 +1,432 @@
+//! Write-once (WORM) enforcement of audit_logs
+//!
+//! Starting the service with AUDIT_WORM_MODE=enforce records in
+//! audit_worm_state that the trail is write-once, which cannot be undone.
+//! From then on triggers on audit_logs leave every row an UPDATE or DELETE
+//! touches as it is and record the attempt in audit_worm_violations, and a
+//! TRUNCATE fails. Retention archival, the only operation of this service that
+//! removes rows, is refused and recorded the same way.
+//!
+//! Every AUDIT_WORM_REPORT_SECS recorded attempts are raised as a FATAL
+//! WORM_VIOLATION in system_events and as an `audit.worm.violation` event in
+//! the affected tenant's trail, once per transaction and tenant.
+//!
+//! GET /admin/worm/verification proves for each day with a daily digest that
+//! its rows are still those committed to the anchored root: none missing or
+//! added, each document still hashing to its leaf, and the root still on
+//! chain and under its timestamp token. Digests are built in either anchor
+//! mode while WORM mode is enforced, so every day since can be checked.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use thiserror::Error;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::context::RequestContext;
+use crate::digest::{self, AnchorDigest};
+use crate::{integrity, tsa, AppState, AuditService, CreateAuditEventRequest};
+
+/// Days one verification may cover
+const MAX_VERIFIED_DAYS: i64 = 92;
+/// Event ids listed per day and finding before the rest are only counted
+const MAX_LISTED_IDS: usize = 100;
+/// Transactions reported per run
+const REPORT_BATCH: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WormMode {
+    Off,
+    Enforce,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WormSettings {
+    pub mode: WormMode,
+    pub report_interval: std::time::Duration,
+}
+
+impl WormSettings {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match std::env::var("AUDIT_WORM_MODE").unwrap_or_default().as_str() {
+            "" | "off" => WormMode::Off,
+            "enforce" => WormMode::Enforce,
+            other => anyhow::bail!("unknown AUDIT_WORM_MODE '{}'; use off or enforce", other),
+        };
+        Ok(Self {
+            mode,
+            report_interval: std::time::Duration::from_secs(
+                std::env::var("AUDIT_WORM_REPORT_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(30),
+            ),
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct WormState {
+    pub enabled_at: DateTime<Utc>,
+    pub enabled_by: String,
+}
+
+/// Attempts of one transaction against one tenant's rows
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+struct ViolationGroup {
+    attempted_at: DateTime<Utc>,
+    source: String,
+    operation: String,
+    transaction_id: i64,
+    tenant_id: Option<Uuid>,
+    db_user: String,
+    application_name: Option<String>,
+    client_addr: Option<String>,
+    row_count: i64,
+    /// The first rows, up to MAX_LISTED_IDS
+    log_ids: Vec<Uuid>,
+    details: serde_json::Value,
+    #[serde(skip)]
+    violation_ids: Vec<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct VerificationParams {
+    /// Defaults to 30 days before `to`
+    pub from: Option<NaiveDate>,
+    /// Defaults to yesterday, the last day that can have a digest
+    pub to: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DayVerification {
+    pub day: NaiveDate,
+    pub merkle_root: String,
+    pub anchored_count: i64,
+    pub current_count: i64,
+    /// The stored leaves still fold to the anchored root
+    pub leaves_match_root: bool,
+    pub missing_count: usize,
+    pub missing: Vec<Uuid>,
+    /// Missing rows that retention archived before WORM mode; not counted against the day
+    pub archived_count: usize,
+    pub added_count: usize,
+    pub added: Vec<Uuid>,
+    /// Rows whose document is gone or no longer hashes to its leaf
+    pub altered_count: usize,
+    pub altered: Vec<Uuid>,
+    /// `None` when the root was not anchored or there is no chain access
+    pub anchored_on_chain: Option<bool>,
+    /// `None` when the root has no timestamp token or no TSA is configured
+    pub timestamp_valid: Option<bool>,
+    pub verified: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WormVerification {
+    pub enforced_since: Option<DateTime<Utc>>,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub days: Vec<DayVerification>,
+    /// Days with rows but no digest to check them against
+    pub unsealed_days: Vec<NaiveDate>,
+    pub verified: bool,
+    pub verified_at: DateTime<Utc>,
+}
+
+/// Returned by operations that would remove audit rows while WORM mode is enforced
+#[derive(Debug, Error)]
+#[error("audit_logs is write-once since {0}; {1} would remove audit rows")]
+pub struct WormRefusal(pub DateTime<Utc>, pub &'static str);
+
+#[derive(Debug, Error)]
+pub enum WormError {
+    #[error("from must not be after to")]
+    InvalidRange,
+    #[error("a verification covers at most {0} days")]
+    TooManyDays(i64),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("{0}")]
+    Verification(#[from] anyhow::Error),
+}
+
+/// Enable WORM mode when it is requested and return the state either way
+pub async fn sync(db: &PgPool, settings: &WormSettings) -> Result<Option<WormState>, sqlx::Error> {
+    if settings.mode == WormMode::Enforce {
+        sqlx::query("INSERT INTO audit_worm_state (enabled_by) VALUES ('audit-service') ON CONFLICT DO NOTHING")
+            .execute(db)
+            .await?;
+    }
+    enforced(db).await
+}
+
+/// When WORM mode was enabled; `None` while it is off
+pub async fn enforced(db: &PgPool) -> Result<Option<WormState>, sqlx::Error> {
+    sqlx::query_as::<_, WormState>("SELECT enabled_at, enabled_by FROM audit_worm_state")
+        .fetch_optional(db)
+        .await
+}
+
+/// Record an operation of this service refused because WORM mode is enforced
+pub async fn refuse(
+    db: &PgPool,
+    operation: &str,
+    tenant_id: Option<Uuid>,
+    details: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_worm_violations (source, operation, tenant_id, details) VALUES ('APPLICATION', $1, $2, $3)",
+    )
+    .bind(operation)
+    .bind(tenant_id)
+    .bind(details)
+    .execute(db)
+    .await?;
+    warn!("Refused {} in WORM mode", operation);
+    Ok(())
+}
+
+pub fn spawn_reporter(state: AppState, settings: WormSettings) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(settings.report_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = report(&state).await {
+                error!("Reporting WORM violations failed: {}", e);
+            }
+        }
+    });
+}
+
+/// Raise recorded attempts that have not been reported yet
+async fn report(state: &AppState) -> anyhow::Result<()> {
+    let groups = sqlx::query_as::<_, ViolationGroup>(
+        r#"
+        SELECT MIN(attempted_at) AS attempted_at, source, operation, transaction_id, tenant_id,
+               MIN(db_user) AS db_user, MIN(application_name) AS application_name,
+               MIN(host(client_addr)) AS client_addr, COUNT(*) AS row_count,
+               (array_remove(array_agg(log_id ORDER BY attempted_at), NULL))[1:$1] AS log_ids,
+               (array_agg(details ORDER BY attempted_at))[1] AS details,
+               array_agg(violation_id) AS violation_ids
+        FROM audit_worm_violations
+        WHERE reported_at IS NULL
+        GROUP BY source, operation, transaction_id, tenant_id
+        ORDER BY MIN(attempted_at)
+        LIMIT $2
+        "#,
+    )
+    .bind(MAX_LISTED_IDS as i32)
+    .bind(REPORT_BATCH)
+    .fetch_all(&state.db)
+    .await?;
+
+    for group in groups {
+        error!(
+            "WORM violation: {} {} of {} audit row(s) by {} in transaction {}",
+            group.source.to_lowercase(),
+            group.operation,
+            group.row_count,
+            group.db_user,
+            group.transaction_id
+        );
+        // Left unreported when the tenant's trail cannot take it, so the next run tries again
+        if let Some(tenant_id) = group.tenant_id {
+            let request = CreateAuditEventRequest {
+                tenant_id,
+                user_id: None,
+                action: "audit.worm.violation".to_string(),
+                resource_type: "AUDIT_LOG".to_string(),
+                resource_id: group.log_ids.first().copied().filter(|_| group.row_count == 1),
+                old_values: None,
+                new_values: Some(serde_json::to_value(&group)?),
+                metadata: None,
+            };
+            let context = RequestContext::new(None, Some("worm-guard"), None);
+            if let Err(e) = AuditService::from_state(state.clone()).create_audit_event(request, &context).await {
+                error!("Failed to audit WORM violation of tenant {}: {}", tenant_id, e);
+                continue;
+            }
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO system_events (event_type, severity, source_system, message, details)
+            VALUES ('WORM_VIOLATION', 'FATAL', 'audit-service', $1, $2)
+            "#,
+        )
+        .bind(format!(
+            "{} of {} audit row(s) attempted in WORM mode by {}",
+            group.operation, group.row_count, group.db_user
+        ))
+        .bind(serde_json::to_value(&group)?)
+        .execute(&state.db)
+        .await?;
+        sqlx::query("UPDATE audit_worm_violations SET reported_at = NOW() WHERE violation_id = ANY($1)")
+            .bind(&group.violation_ids)
+            .execute(&state.db)
+            .await?;
+        counter!(
+            "audit_worm_violations_total",
+            group.row_count as u64,
+            "source" => group.source.clone(),
+            "operation" => group.operation.clone()
+        );
+    }
+    Ok(())
+}
+
+/// Check the days of a range against their anchored digests
+pub async fn verify(state: &AppState, params: &VerificationParams) -> Result<WormVerification, WormError> {
+    let to = params.to.unwrap_or_else(|| Utc::now().date_naive() - Duration::days(1));
+    let from = params.from.unwrap_or(to - Duration::days(29));
+    if from > to {
+        return Err(WormError::InvalidRange);
+    }
+    if (to - from).num_days() >= MAX_VERIFIED_DAYS {
+        return Err(WormError::TooManyDays(MAX_VERIFIED_DAYS));
+    }
+    let enforced_since = enforced(&state.db).await?.map(|worm| worm.enabled_at);
+
+    let digests = sqlx::query_as::<_, AnchorDigest>(
+        r#"
+        SELECT * FROM audit_anchor_digests
+        WHERE digest_date BETWEEN $1 AND $2 AND merkle_root IS NOT NULL
+        ORDER BY digest_date
+        "#,
+    )
+    .bind(from)
+    .bind(to)
+    .fetch_all(&state.db)
+    .await?;
+    let mut days = Vec::with_capacity(digests.len());
+    for digest in &digests {
+        days.push(verify_day(state, digest).await?);
+    }
+
+    let start = from.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+    let unsealed_days: Vec<NaiveDate> = sqlx::query_scalar(
+        r#"
+        SELECT DISTINCT (timestamp AT TIME ZONE 'UTC')::date AS day
+        FROM audit_logs
+        WHERE timestamp >= $1 AND timestamp < $2
+          AND (timestamp AT TIME ZONE 'UTC')::date <> ALL($3)
+        ORDER BY day
+        "#,
+    )
+    .bind(start)
+    .bind(start + Duration::days((to - from).num_days() + 1))
+    .bind(digests.iter().map(|digest| digest.digest_date).collect::<Vec<_>>())
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(WormVerification {
+        enforced_since,
+        from,
+        to,
+        verified: enforced_since.is_some() && unsealed_days.is_empty() && days.iter().all(|day| day.verified),
+        days,
+        unsealed_days,
+        verified_at: Utc::now(),
+    })
+}
+
+async fn verify_day(state: &AppState, digest: &AnchorDigest) -> Result<DayVerification, WormError> {
+    let day = digest.digest_date;
+    let root = digest.merkle_root.clone().unwrap_or_default();
+    let leaves: Vec<(Uuid, String)> = sqlx::query_as(
+        "SELECT event_id, event_hash FROM audit_anchor_digest_leaves WHERE digest_date = $1 ORDER BY leaf_index",
+    )
+    .bind(day)
+    .fetch_all(&state.db)
+    .await?;
+    let leaf_hashes: Vec<String> = leaves.iter().map(|(_, hash)| hash.clone()).collect();
+    let leaves_match_root = !leaves.is_empty()
+        && leaves.len() as i32 == digest.event_count
+        && hex::encode(digest::merkle_root(&leaf_hashes)?) == root;
+
+    let start = day.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc();
+    let current: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT log_id FROM audit_logs WHERE timestamp >= $1 AND timestamp < $2 ORDER BY timestamp, log_id",
+    )
+    .bind(start)
+    .bind(start + Duration::days(1))
+    .fetch_all(&state.db)
+    .await?;
+    let current_ids: HashSet<Uuid> = current.iter().copied().collect();
+    let leaf_ids: HashSet<Uuid> = leaves.iter().map(|(id, _)| *id).collect();
+
+    let missing: Vec<Uuid> = leaves.iter().map(|(id, _)| *id).filter(|id| !current_ids.contains(id)).collect();
+    let archived: Vec<Uuid> = if missing.is_empty() {
+        Vec::new()
+    } else {
+        sqlx::query_scalar(
+            r#"
+            SELECT id FROM unnest($1::uuid[]) AS id
+            WHERE EXISTS (SELECT 1 FROM audit_archives a WHERE a.status = 'ARCHIVED' AND id = ANY(a.event_ids))
+            "#,
+        )
+        .bind(&missing)
+        .fetch_all(&state.db)
+        .await?
+    };
+    let missing: Vec<Uuid> = missing.into_iter().filter(|id| !archived.contains(id)).collect();
+    let added: Vec<Uuid> = current.iter().copied().filter(|id| !leaf_ids.contains(id)).collect();
+
+    let present: Vec<Uuid> = leaves.iter().map(|(id, _)| *id).filter(|id| current_ids.contains(id)).collect();
+    let documents = state.documents.get_many(&present).await?;
+    let mut altered = Vec::new();
+    for (id, leaf_hash) in leaves.iter().filter(|(id, _)| current_ids.contains(id)) {
+        let computed_hash = documents
+            .get(id)
+            .map(|event| integrity::canonical_payload(event).map(|payload| integrity::sha256_hex(&payload)))
+            .transpose()
+            .map_err(anyhow::Error::from)?;
+        if computed_hash.as_deref() != Some(leaf_hash.as_str()) {
+            altered.push(*id);
+        }
+    }
+
+    let anchored_on_chain = match (&digest.transaction_hash, &state.blockchain_client) {
+        (Some(_), Some(blockchain)) => match blockchain.verify_audit_integrity(&root).await {
+            Ok(anchored) => Some(anchored),
+            Err(e) => {
+                warn!("Could not check the anchor of the {} digest root: {}", day, e);
+                None
+            }
+        },
+        _ => None,
+    };
+    let timestamp_valid = match (&state.tsa, tsa::for_digest(&state.db, day).await?) {
+        (Some(tsa), Some(stored)) => Some(tsa.verify(&stored.token, &root).is_ok()),
+        _ => None,
+    };
+
+    let listed = |ids: &[Uuid]| ids.iter().take(MAX_LISTED_IDS).copied().collect::<Vec<_>>();
+    Ok(DayVerification {
+        day,
+        merkle_root: root,
+        anchored_count: digest.event_count as i64,
+        current_count: current.len() as i64,
+        leaves_match_root,
+        verified: leaves_match_root
+            && missing.is_empty()
+            && added.is_empty()
+            && altered.is_empty()
+            && anchored_on_chain != Some(false)
+            && timestamp_valid != Some(false),
+        missing_count: missing.len(),
+        missing: listed(&missing),
+        archived_count: archived.len(),
+        added_count: added.len(),
+        added: listed(&added),
+        altered_count: altered.len(),
+        altered: listed(&altered),
+        anchored_on_chain,
+        timestamp_valid,
+    })
+}
@@ -0,0 +1,49 @@
+//! Report approval
+//!
+//! Reports are generated as DRAFT and reviewed maker-checker style through the
+//! reporting service: someone other than their maker approves or rejects them.
+//! Until a report is approved it stays on the platform. Every route that hands
+//! a report to someone outside it calls `unapproved` first, and submission
+//! takes an APPROVED report only. Status changes are recorded in
+//! report_status_transitions by a trigger, attributed with `attribute`.
+
+use sqlx::PgConnection;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub const DRAFT: &str = "DRAFT";
+pub const APPROVED: &str = "APPROVED";
+pub const REJECTED: &str = "REJECTED";
+
+/// Statuses of approved reports, before and after submission
+const RELEASED: &[&str] = &[APPROVED, "SUBMITTED", "ACKNOWLEDGED"];
+
+pub fn is_released(status: &str) -> bool {
+    RELEASED.contains(&status)
+}
+
+/// The report's status, or `None` when there is no such report
+pub async fn status(db: &PgPool, report_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    let status =
+        sqlx::query_scalar::<_, Option<String>>("SELECT status FROM regulatory_reports_v2 WHERE report_id = $1")
+            .bind(report_id)
+            .fetch_optional(db)
+            .await?;
+    Ok(status.map(|status| status.unwrap_or_else(|| DRAFT.to_string())))
+}
+
+/// The status of a report that may not leave the platform yet, or `None` when
+/// it has been approved or does not exist
+pub async fn unapproved(db: &PgPool, report_id: Uuid) -> Result<Option<String>, sqlx::Error> {
+    Ok(status(db, report_id).await?.filter(|status| !is_released(status)))
+}
+
+/// Who changes report statuses in the transaction, and why, as the audit trail records it
+pub async fn attribute(tx: &mut PgConnection, actor: Option<Uuid>, comment: Option<&str>) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config('app.current_user_id', $1, true), set_config('app.status_comment', $2, true)")
+        .bind(actor.map(|actor| actor.to_string()).unwrap_or_default())
+        .bind(comment.unwrap_or_default())
+        .execute(&mut *tx)
+        .await?;
+    Ok(())
+}
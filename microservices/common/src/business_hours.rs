@@ -0,0 +1,308 @@
+//! Tenant business hours
+//!
+//! A tenant's calendar is a set of weekly sessions in the exchange's time zone
+//! plus the exchange holidays. Violation SLA timers only run inside sessions,
+//! off-hours detection flags activity outside them and scheduled reports run
+//! once a business day has closed. Tenants without a calendar of their own
+//! follow NSE equity market hours, 09:15 to 15:30 IST on weekdays.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// How far ahead a calendar is searched before giving up on finding a session
+const SEARCH_DAYS: i64 = 3660;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub weekday: Weekday,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Holiday {
+    pub date: NaiveDate,
+    pub description: Option<String>,
+}
+
+/// Body of GET and PUT /tenants/:tenant_id/business-hours
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CalendarConfig {
+    /// IANA zone name, e.g. Asia/Kolkata
+    pub timezone: String,
+    pub sessions: Vec<Session>,
+    #[serde(default)]
+    pub holidays: Vec<Holiday>,
+    /// False when the tenant has not configured a calendar and the default applies
+    #[serde(default)]
+    pub configured: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BusinessCalendar {
+    timezone: Tz,
+    sessions: Vec<Session>,
+    holidays: BTreeSet<NaiveDate>,
+}
+
+impl Default for BusinessCalendar {
+    fn default() -> Self {
+        let open = NaiveTime::from_hms_opt(9, 15, 0).expect("valid time");
+        let close = NaiveTime::from_hms_opt(15, 30, 0).expect("valid time");
+        let sessions = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri]
+            .into_iter()
+            .map(|weekday| Session { weekday, open, close })
+            .collect();
+        Self {
+            timezone: chrono_tz::Asia::Kolkata,
+            sessions,
+            holidays: BTreeSet::new(),
+        }
+    }
+}
+
+/// Structural checks run before a calendar replaces the tenant's current one
+pub fn validate_config(config: &CalendarConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    if config.timezone.parse::<Tz>().is_err() {
+        errors.push(format!("unknown time zone '{}'", config.timezone));
+    }
+    if config.sessions.is_empty() {
+        errors.push("at least one session is required".to_string());
+    }
+    for session in &config.sessions {
+        if session.close <= session.open {
+            errors.push(format!(
+                "{} session closes at {} before it opens at {}; overnight sessions must be split at midnight",
+                session.weekday, session.close, session.open
+            ));
+        }
+    }
+    for (i, a) in config.sessions.iter().enumerate() {
+        for b in &config.sessions[i + 1..] {
+            if a.weekday == b.weekday && a.open < b.close && b.open < a.close {
+                errors.push(format!(
+                    "{} sessions {}-{} and {}-{} overlap",
+                    a.weekday, a.open, a.close, b.open, b.close
+                ));
+            }
+        }
+    }
+    let mut seen = BTreeSet::new();
+    for holiday in &config.holidays {
+        if !seen.insert(holiday.date) {
+            errors.push(format!("holiday {} is listed more than once", holiday.date));
+        }
+    }
+    errors
+}
+
+impl BusinessCalendar {
+    /// Build from a validated config
+    pub fn from_config(config: &CalendarConfig) -> anyhow::Result<Self> {
+        let timezone = config
+            .timezone
+            .parse::<Tz>()
+            .map_err(|e| anyhow::anyhow!("unknown time zone '{}': {}", config.timezone, e))?;
+        let mut sessions = config.sessions.clone();
+        sessions.sort_by_key(|session| (session.weekday.num_days_from_monday(), session.open));
+        Ok(Self {
+            timezone,
+            sessions,
+            holidays: config.holidays.iter().map(|holiday| holiday.date).collect(),
+        })
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    /// The date `at` falls on in the calendar's time zone
+    pub fn local_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        at.with_timezone(&self.timezone).date_naive()
+    }
+
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        !self.holidays.contains(&date) && self.sessions.iter().any(|session| session.weekday == date.weekday())
+    }
+
+    /// Sessions held on `date`, as UTC intervals in order
+    pub fn sessions_on(&self, date: NaiveDate) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+        if self.holidays.contains(&date) {
+            return Vec::new();
+        }
+        self.sessions
+            .iter()
+            .filter(|session| session.weekday == date.weekday())
+            .filter_map(|session| {
+                // Times skipped by a DST change resolve to nothing and the session is dropped
+                let open = self.timezone.from_local_datetime(&date.and_time(session.open)).earliest()?;
+                let close = self.timezone.from_local_datetime(&date.and_time(session.close)).latest()?;
+                Some((open.with_timezone(&Utc), close.with_timezone(&Utc)))
+            })
+            .collect()
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        self.sessions_on(self.local_date(at))
+            .iter()
+            .any(|(open, close)| *open <= at && at < *close)
+    }
+
+    /// Session intervals from the day `from` falls on, for at most SEARCH_DAYS days
+    fn intervals_from(&self, from: DateTime<Utc>) -> impl Iterator<Item = (DateTime<Utc>, DateTime<Utc>)> + '_ {
+        let first = self.local_date(from);
+        (0..SEARCH_DAYS).flat_map(move |offset| self.sessions_on(first + Duration::days(offset)))
+    }
+
+    /// The instant `duration` of business time after `start`
+    ///
+    /// Falls back to wall-clock time when the calendar has no sessions to count.
+    pub fn add_business_time(&self, start: DateTime<Utc>, duration: Duration) -> DateTime<Utc> {
+        let mut remaining = duration;
+        for (open, close) in self.intervals_from(start) {
+            let from = open.max(start);
+            if from >= close {
+                continue;
+            }
+            let available = close - from;
+            if remaining <= available {
+                return from + remaining;
+            }
+            remaining = remaining - available;
+        }
+        start + duration
+    }
+
+    /// Business time elapsed between `start` and `end`
+    pub fn business_time_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Duration {
+        let mut elapsed = Duration::zero();
+        for (open, close) in self.intervals_from(start) {
+            if open >= end {
+                break;
+            }
+            let from = open.max(start);
+            let to = close.min(end);
+            if to > from {
+                elapsed = elapsed + (to - from);
+            }
+        }
+        elapsed
+    }
+
+    /// Next session opening at or after `at`, or `at` itself while a session is open
+    pub fn next_open(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.intervals_from(at)
+            .find(|(_, close)| *close > at)
+            .map(|(open, _)| open.max(at))
+    }
+
+    /// Next session close after `at`
+    pub fn next_close(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.intervals_from(at).map(|(_, close)| close).find(|close| *close > at)
+    }
+
+    /// Business days whose final session closed in `(after, until]`, with that close
+    pub fn days_closed_between(&self, after: DateTime<Utc>, until: DateTime<Utc>) -> Vec<(NaiveDate, DateTime<Utc>)> {
+        let mut date = self.local_date(after);
+        let last = self.local_date(until);
+        let mut closed = Vec::new();
+        while date <= last {
+            if let Some((_, close)) = self.sessions_on(date).last() {
+                if *close > after && *close <= until {
+                    closed.push((date, *close));
+                }
+            }
+            date = date.succ_opt().expect("date in range");
+        }
+        closed
+    }
+
+    /// First business day after `date`
+    pub fn next_business_day(&self, date: NaiveDate) -> Option<NaiveDate> {
+        (1..=SEARCH_DAYS)
+            .map(|offset| date + Duration::days(offset))
+            .find(|day| self.is_business_day(*day))
+    }
+
+    fn to_config(&self, holidays: Vec<Holiday>, configured: bool) -> CalendarConfig {
+        CalendarConfig {
+            timezone: self.timezone.name().to_string(),
+            sessions: self.sessions.clone(),
+            holidays,
+            configured,
+        }
+    }
+}
+
+/// The tenant's stored calendar, or the default when none is configured
+pub async fn load_config(db: &PgPool, tenant_id: Uuid) -> Result<CalendarConfig, sqlx::Error> {
+    let row = sqlx::query_as::<_, (String, sqlx::types::Json<Vec<Session>>)>(
+        "SELECT timezone, sessions FROM tenant_business_calendars WHERE tenant_id = $1",
+    )
+    .bind(tenant_id)
+    .fetch_optional(db)
+    .await?;
+    let holidays = sqlx::query_as::<_, (NaiveDate, Option<String>)>(
+        "SELECT holiday_date, description FROM tenant_holidays WHERE tenant_id = $1 ORDER BY holiday_date",
+    )
+    .bind(tenant_id)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|(date, description)| Holiday { date, description })
+    .collect();
+
+    Ok(match row {
+        Some((timezone, sessions)) => CalendarConfig {
+            timezone,
+            sessions: sessions.0,
+            holidays,
+            configured: true,
+        },
+        None => BusinessCalendar::default().to_config(holidays, false),
+    })
+}
+
+pub async fn load(db: &PgPool, tenant_id: Uuid) -> anyhow::Result<BusinessCalendar> {
+    BusinessCalendar::from_config(&load_config(db, tenant_id).await?)
+}
+
+/// Replace the tenant's calendar and holidays; callers validate first
+pub async fn replace_config(db: &PgPool, tenant_id: Uuid, config: &CalendarConfig) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO tenant_business_calendars (tenant_id, timezone, sessions, updated_at)
+        VALUES ($1, $2, $3, NOW())
+        ON CONFLICT (tenant_id) DO UPDATE
+        SET timezone = EXCLUDED.timezone, sessions = EXCLUDED.sessions, updated_at = NOW()
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(&config.timezone)
+    .bind(sqlx::types::Json(&config.sessions))
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM tenant_holidays WHERE tenant_id = $1")
+        .bind(tenant_id)
+        .execute(&mut *tx)
+        .await?;
+    for holiday in &config.holidays {
+        sqlx::query("INSERT INTO tenant_holidays (tenant_id, holiday_date, description) VALUES ($1, $2, $3)")
+            .bind(tenant_id)
+            .bind(holiday.date)
+            .bind(&holiday.description)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
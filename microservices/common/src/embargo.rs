@@ -0,0 +1,114 @@
+//! Report embargo enforcement
+//!
+//! A report generated under embargo may not leave the platform before its
+//! embargo time, whichever service is asked for it. Every route that hands out
+//! report contents or submits it calls `guard` first; a refused request is
+//! recorded in report_embargo_attempts so early-access attempts can be shown to
+//! compliance. Embargoes themselves are managed by the reporting service.
+
+use axum::http::{header, HeaderMap};
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use tracing::warn;
+use uuid::Uuid;
+
+/// What was asked of an embargoed report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    View,
+    Download,
+    Deliver,
+    PortalDownload,
+    Submit,
+}
+
+impl Access {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Access::View => "VIEW",
+            Access::Download => "DOWNLOAD",
+            Access::Deliver => "DELIVER",
+            Access::PortalDownload => "PORTAL_DOWNLOAD",
+            Access::Submit => "SUBMIT",
+        }
+    }
+}
+
+/// Who asked, as recorded with a refused attempt
+#[derive(Debug, Clone, Default)]
+pub struct Attempt {
+    pub requested_by: Option<Uuid>,
+    pub token_id: Option<Uuid>,
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl Attempt {
+    /// Client details from the first X-Forwarded-For hop set by the gateway
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            ip_address: headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|ip| ip.trim().parse().ok()),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|agent| agent.chars().take(512).collect()),
+            ..Self::default()
+        }
+    }
+}
+
+/// When the report becomes available, recording the attempt, or `None` when it already is
+pub async fn guard(
+    db: &PgPool,
+    report_id: Uuid,
+    access: Access,
+    attempt: &Attempt,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let embargo = sqlx::query_as::<_, (Uuid, DateTime<Utc>)>(
+        r#"
+        SELECT tenant_id, embargo_until
+        FROM report_embargoes
+        WHERE report_id = $1 AND lifted_at IS NULL AND embargo_until > NOW()
+        "#,
+    )
+    .bind(report_id)
+    .fetch_optional(db)
+    .await?;
+    let Some((tenant_id, embargo_until)) = embargo else {
+        return Ok(None);
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO report_embargo_attempts (
+            report_id, tenant_id, action, requested_by, token_id, ip_address, user_agent, embargo_until
+        )
+        VALUES ($1, $2, $3, $4, $5, $6::inet, $7, $8)
+        "#,
+    )
+    .bind(report_id)
+    .bind(tenant_id)
+    .bind(access.as_str())
+    .bind(attempt.requested_by)
+    .bind(attempt.token_id)
+    .bind(attempt.ip_address.map(|ip| ip.to_string()))
+    .bind(&attempt.user_agent)
+    .bind(embargo_until)
+    .execute(db)
+    .await?;
+    counter!("report_embargo_attempts_total", 1, "action" => access.as_str());
+    warn!(
+        "Refused {} of report {} of tenant {}: under embargo until {}",
+        access.as_str(),
+        report_id,
+        tenant_id,
+        embargo_until
+    );
+    Ok(Some(embargo_until))
+}
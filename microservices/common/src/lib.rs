@@ -0,0 +1,10 @@
+//! Infrastructure shared by the DharmaGuard microservices
+
+pub mod approval;
+pub mod business_hours;
+pub mod embargo;
+pub mod metrics;
+pub mod pool;
+pub mod startup;
+pub mod tenant;
+pub mod versioning;
@@ -0,0 +1,36 @@
+//! Prometheus exposition of metrics recorded through the `metrics` facade
+
+use axum::{routing::get, Router};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+
+/// Buckets for `*_wait_seconds` histograms, dense below the default 250ms wait budget
+const WAIT_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+/// Buckets for `*_stage_seconds` histograms of multi-step write paths
+const STAGE_BUCKETS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// Install the process-wide recorder; later calls return the same handle
+pub fn install() -> PrometheusHandle {
+    HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets_for_metric(Matcher::Suffix("_wait_seconds".to_string()), WAIT_BUCKETS)
+                .expect("wait buckets are not empty")
+                .set_buckets_for_metric(Matcher::Suffix("_stage_seconds".to_string()), STAGE_BUCKETS)
+                .expect("stage buckets are not empty")
+                .install_recorder()
+                .expect("no other metrics recorder is installed")
+        })
+        .clone()
+}
+
+/// GET /metrics in the Prometheus text format
+pub fn router<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let handle = install();
+    Router::new().route("/metrics", get(move || std::future::ready(handle.render())))
+}
@@ -0,0 +1,240 @@
+//! Database pool sizing and admission control
+//!
+//! sqlx pools have a fixed ceiling, so the pool is opened with DB_POOL_MAX
+//! connections and the number of requests allowed to use it at once is a
+//! separate limit that moves between DB_POOL_MIN and DB_POOL_MAX. A request
+//! waits at most DB_POOL_WAIT_BUDGET_MS for an admission slot and is then shed
+//! with 503 and Retry-After, rather than queueing on the pool until the caller
+//! times out and retries on top of the backlog.
+//!
+//! Every DB_POOL_SCALE_INTERVAL_SECS the autoscaler raises the limit while
+//! requests are waiting or being shed and the pool still has connections to
+//! give, and lowers it after several quiet intervals. Connections left idle
+//! above DB_POOL_MIN are closed by the pool's idle timeout.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use metrics::{counter, gauge, histogram};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Quiet intervals in a row before the limit is lowered
+const SHRINK_AFTER_INTERVALS: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    pub min_connections: u32,
+    pub max_connections: u32,
+    /// Longest a request may wait for an admission slot before it is shed
+    pub wait_budget: Duration,
+    pub scale_interval: Duration,
+    pub idle_timeout: Duration,
+}
+
+impl PoolSettings {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let max_connections = number("DB_POOL_MAX", 20).max(1) as u32;
+        Self {
+            min_connections: (number("DB_POOL_MIN", 5) as u32).clamp(1, max_connections),
+            max_connections,
+            wait_budget: Duration::from_millis(number("DB_POOL_WAIT_BUDGET_MS", 250)),
+            scale_interval: Duration::from_secs(number("DB_POOL_SCALE_INTERVAL_SECS", 5).max(1)),
+            idle_timeout: Duration::from_secs(number("DB_POOL_IDLE_TIMEOUT_SECS", 300)),
+        }
+    }
+
+    pub async fn connect(&self, database_url: &str) -> Result<PgPool, sqlx::Error> {
+        PgPoolOptions::new()
+            .min_connections(self.min_connections)
+            .max_connections(self.max_connections)
+            .idle_timeout(self.idle_timeout)
+            .connect(database_url)
+            .await
+    }
+}
+
+/// Admission statistics since the last autoscaler tick
+#[derive(Default)]
+struct Window {
+    admitted: u64,
+    /// Admitted after waiting for a slot
+    queued: u64,
+    shed: u64,
+    peak_in_use: u32,
+}
+
+/// The request is over the wait budget and should be retried later
+#[derive(Debug)]
+pub struct Shed {
+    retry_after: Duration,
+}
+
+impl IntoResponse for Shed {
+    fn into_response(self) -> Response {
+        let mut response = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": "service is at capacity, retry later"})),
+        )
+            .into_response();
+        let seconds = self.retry_after.as_secs().max(1);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        response
+    }
+}
+
+pub struct Admission {
+    service: &'static str,
+    semaphore: Arc<Semaphore>,
+    limit: AtomicU32,
+    settings: PoolSettings,
+    window: Mutex<Window>,
+}
+
+impl Admission {
+    /// Start at the pool's minimum; load raises the limit within one or two intervals
+    pub fn new(service: &'static str, settings: PoolSettings) -> Arc<Self> {
+        gauge!("db_pool_admission_limit", settings.min_connections as f64, "service" => service);
+        Arc::new(Self {
+            service,
+            semaphore: Arc::new(Semaphore::new(settings.min_connections as usize)),
+            limit: AtomicU32::new(settings.min_connections),
+            settings,
+            window: Mutex::new(Window::default()),
+        })
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    fn in_use(&self) -> u32 {
+        self.limit().saturating_sub(self.semaphore.available_permits() as u32)
+    }
+
+    /// Wait up to the budget for a slot; the slot is released when the permit drops
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, Shed> {
+        let started = Instant::now();
+        let (permit, queued) = match self.semaphore.clone().try_acquire_owned() {
+            Ok(permit) => (permit, false),
+            Err(_) => match tokio::time::timeout(self.settings.wait_budget, self.semaphore.clone().acquire_owned())
+                .await
+            {
+                Ok(Ok(permit)) => (permit, true),
+                // The semaphore is never closed, so only the timeout lands here
+                Ok(Err(_)) | Err(_) => {
+                    self.window.lock().expect("admission window lock poisoned").shed += 1;
+                    counter!("db_pool_admission_shed_total", 1, "service" => self.service);
+                    return Err(Shed {
+                        retry_after: self.settings.scale_interval,
+                    });
+                }
+            },
+        };
+
+        histogram!(
+            "db_pool_admission_wait_seconds",
+            started.elapsed().as_secs_f64(),
+            "service" => self.service
+        );
+        let in_use = self.in_use();
+        let mut window = self.window.lock().expect("admission window lock poisoned");
+        window.admitted += 1;
+        window.queued += queued as u64;
+        window.peak_in_use = window.peak_in_use.max(in_use);
+        Ok(permit)
+    }
+
+    pub fn spawn_autoscaler(self: &Arc<Self>, pool: PgPool) -> JoinHandle<()> {
+        let admission = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(admission.settings.scale_interval);
+            let mut quiet_intervals = 0;
+            loop {
+                ticker.tick().await;
+                admission.rescale(&pool, &mut quiet_intervals);
+            }
+        })
+    }
+
+    fn rescale(&self, pool: &PgPool, quiet_intervals: &mut u32) {
+        let window = std::mem::take(&mut *self.window.lock().expect("admission window lock poisoned"));
+        let limit = self.limit();
+        let connections = pool.size();
+        let idle_connections = pool.num_idle() as u32;
+
+        gauge!("db_pool_connections", connections as f64, "service" => self.service);
+        gauge!("db_pool_idle_connections", idle_connections as f64, "service" => self.service);
+        gauge!("db_pool_admission_in_use", window.peak_in_use as f64, "service" => self.service);
+
+        // More than one request in ten had to wait, or any was shed
+        let saturated = window.shed > 0 || window.queued * 10 > window.admitted;
+        // Raising the limit only helps while the pool can still hand out connections
+        let pool_exhausted = connections >= self.settings.max_connections && idle_connections == 0;
+
+        if saturated {
+            *quiet_intervals = 0;
+            if limit >= self.settings.max_connections {
+                return;
+            }
+            if pool_exhausted {
+                warn!(
+                    "{} database pool exhausted at {} connections; shedding {} requests",
+                    self.service, connections, window.shed
+                );
+                return;
+            }
+            let new_limit = (limit + (limit / 4).max(1)).min(self.settings.max_connections);
+            self.semaphore.add_permits((new_limit - limit) as usize);
+            self.set_limit(new_limit);
+            info!(
+                "Raised {} database admission limit {} -> {} ({} queued, {} shed)",
+                self.service, limit, new_limit, window.queued, window.shed
+            );
+        } else if window.peak_in_use * 2 < limit && limit > self.settings.min_connections {
+            *quiet_intervals += 1;
+            if *quiet_intervals < SHRINK_AFTER_INTERVALS {
+                return;
+            }
+            *quiet_intervals = 0;
+            let new_limit = limit.saturating_sub((limit / 8).max(1)).max(self.settings.min_connections);
+            // Only free slots are retired, so in-flight requests are never affected
+            if let Ok(permits) = self.semaphore.try_acquire_many(limit - new_limit) {
+                permits.forget();
+                self.set_limit(new_limit);
+                info!("Lowered {} database admission limit {} -> {}", self.service, limit, new_limit);
+            }
+        } else {
+            *quiet_intervals = 0;
+        }
+    }
+
+    fn set_limit(&self, limit: u32) {
+        self.limit.store(limit, Ordering::Relaxed);
+        gauge!("db_pool_admission_limit", limit as f64, "service" => self.service);
+    }
+}
+
+/// Middleware holding an admission slot for the duration of the request
+pub async fn admit(State(admission): State<Arc<Admission>>, request: Request, next: Next) -> Response {
+    match admission.acquire().await {
+        Ok(_permit) => next.run(request).await,
+        Err(shed) => shed.into_response(),
+    }
+}
@@ -0,0 +1,308 @@
+//! Startup orchestration and readiness
+//!
+//! A service binds its port straight away so that `/health` (liveness) and
+//! `/ready` answer, but its API routes return 503 with Retry-After until the
+//! startup checks have passed: migrations applied, caches warmed, the event
+//! bus and other dependencies reachable. Checks run in order; a failing check
+//! is retried with backoff until STARTUP_TIMEOUT_SECS, after which the service
+//! exits so the orchestrator restarts it rather than routing traffic to an
+//! instance that cannot serve it.
+//!
+//! Background work that should not start early (bus consumers, for example)
+//! awaits [`Readiness::wait`].
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use metrics::{gauge, histogram};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Longest wait between attempts of a failing check
+const MAX_BACKOFF: Duration = Duration::from_secs(15);
+
+type CheckFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
+type CheckFn = Box<dyn Fn() -> CheckFuture + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct StartupSettings {
+    /// Budget for all checks together before the service gives up
+    pub timeout: Duration,
+    /// Wait after the first failed attempt; doubles up to 15s
+    pub retry_after: Duration,
+}
+
+impl StartupSettings {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            timeout: Duration::from_secs(number("STARTUP_TIMEOUT_SECS", 120).max(1)),
+            retry_after: Duration::from_millis(number("STARTUP_RETRY_MS", 1000).max(1)),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CheckState {
+    Pending,
+    Running,
+    Passed,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CheckStatus {
+    pub name: &'static str,
+    pub state: CheckState,
+    pub attempts: u32,
+    /// Error of the latest failed attempt, kept after a later pass
+    pub last_error: Option<String>,
+    pub duration_ms: Option<u64>,
+}
+
+struct Inner {
+    service: &'static str,
+    ready: watch::Sender<bool>,
+    checks: Mutex<Vec<CheckStatus>>,
+}
+
+/// Whether the service has finished starting, shared with the gate and /ready
+#[derive(Clone)]
+pub struct Readiness(Arc<Inner>);
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        *self.0.ready.borrow()
+    }
+
+    /// Resolves once every startup check has passed
+    pub async fn wait(&self) {
+        let mut ready = self.0.ready.subscribe();
+        // The sender lives as long as `self`, so the channel cannot close here
+        let _ = ready.wait_for(|ready| *ready).await;
+    }
+
+    pub fn checks(&self) -> Vec<CheckStatus> {
+        self.0.checks.lock().expect("readiness lock poisoned").clone()
+    }
+
+    fn update(&self, index: usize, change: impl FnOnce(&mut CheckStatus)) {
+        change(&mut self.0.checks.lock().expect("readiness lock poisoned")[index]);
+    }
+}
+
+pub struct Startup {
+    settings: StartupSettings,
+    checks: Vec<(&'static str, CheckFn)>,
+    readiness: Readiness,
+}
+
+impl Startup {
+    pub fn new(service: &'static str) -> Self {
+        gauge!("service_ready", 0.0, "service" => service);
+        let (ready, _) = watch::channel(false);
+        Self {
+            settings: StartupSettings::from_env(),
+            checks: Vec::new(),
+            readiness: Readiness(Arc::new(Inner {
+                service,
+                ready,
+                checks: Mutex::new(Vec::new()),
+            })),
+        }
+    }
+
+    /// Add a check; checks run in the order they are added
+    pub fn check<F, Fut>(mut self, name: &'static str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.readiness.0.checks.lock().expect("readiness lock poisoned").push(CheckStatus {
+            name,
+            state: CheckState::Pending,
+            attempts: 0,
+            last_error: None,
+            duration_ms: None,
+        });
+        self.checks.push((name, Box::new(move || Box::pin(check()))));
+        self
+    }
+
+    /// Require the shared database migrations the service depends on, each
+    /// given as the migration's file stem and a relation it creates
+    pub fn migrations(self, db: PgPool, required: &'static [(&'static str, &'static str)]) -> Self {
+        self.check("migrations", move || {
+            let db = db.clone();
+            async move { check_migrations(&db, required).await }
+        })
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        self.readiness.clone()
+    }
+
+    /// Run the checks, then mark the service ready
+    pub async fn run(self) -> anyhow::Result<()> {
+        let service = self.readiness.0.service;
+        let started = Instant::now();
+        let deadline = started + self.settings.timeout;
+
+        for (index, (name, check)) in self.checks.iter().enumerate() {
+            let check_started = Instant::now();
+            let mut backoff = self.settings.retry_after;
+            loop {
+                self.readiness.update(index, |status| {
+                    status.state = CheckState::Running;
+                    status.attempts += 1;
+                });
+                // A dependency that never answers must not hold startup past the deadline
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                let attempt = tokio::time::timeout(remaining, check())
+                    .await
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("no answer within the startup timeout")));
+                match attempt {
+                    Ok(()) => {
+                        let elapsed = check_started.elapsed();
+                        self.readiness.update(index, |status| {
+                            status.state = CheckState::Passed;
+                            status.duration_ms = Some(elapsed.as_millis() as u64);
+                        });
+                        info!("{} startup check '{}' passed in {}ms", service, name, elapsed.as_millis());
+                        break;
+                    }
+                    Err(e) => {
+                        let message = format!("{:#}", e);
+                        let out_of_time = Instant::now() + backoff > deadline;
+                        self.readiness.update(index, |status| {
+                            status.state = if out_of_time { CheckState::Failed } else { CheckState::Pending };
+                            status.last_error = Some(message.clone());
+                        });
+                        if out_of_time {
+                            anyhow::bail!(
+                                "{} startup check '{}' did not pass within {}s: {}",
+                                service,
+                                name,
+                                self.settings.timeout.as_secs(),
+                                message
+                            );
+                        }
+                        warn!(
+                            "{} startup check '{}' failed, retrying in {}ms: {}",
+                            service,
+                            name,
+                            backoff.as_millis(),
+                            message
+                        );
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        }
+
+        self.readiness.0.ready.send_replace(true);
+        gauge!("service_ready", 1.0, "service" => service);
+        histogram!("service_startup_seconds", started.elapsed().as_secs_f64(), "service" => service);
+        info!("{} is ready after {:.1}s", service, started.elapsed().as_secs_f64());
+        Ok(())
+    }
+
+    /// Drive `server` while the checks run; the server already answers, with
+    /// API routes gated, and a failed startup stops it
+    pub async fn serve<F>(self, server: F) -> anyhow::Result<()>
+    where
+        F: Future<Output = anyhow::Result<()>>,
+    {
+        tokio::pin!(server);
+        tokio::select! {
+            result = &mut server => return result,
+            result = self.run() => result?,
+        }
+        server.await
+    }
+}
+
+async fn check_migrations(db: &PgPool, required: &[(&str, &str)]) -> anyhow::Result<()> {
+    let relations: Vec<String> = required.iter().map(|(_, relation)| relation.to_string()).collect();
+    let missing: Vec<String> = sqlx::query_scalar(
+        "SELECT relation FROM unnest($1::text[]) AS relation WHERE to_regclass(relation) IS NULL",
+    )
+    .bind(&relations)
+    .fetch_all(db)
+    .await?;
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    let migrations: Vec<&str> = required
+        .iter()
+        .filter(|(_, relation)| missing.iter().any(|m| m == relation))
+        .map(|(migration, _)| *migration)
+        .collect();
+    anyhow::bail!("database migrations not applied: {}", migrations.join(", "))
+}
+
+/// The service is still starting and the request should be retried later
+struct Starting;
+
+impl IntoResponse for Starting {
+    fn into_response(self) -> Response {
+        let mut response = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": "service is starting, retry later"})),
+        )
+            .into_response();
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(5));
+        response
+    }
+}
+
+/// Middleware answering 503 until startup has finished
+pub async fn gate(State(readiness): State<Readiness>, request: Request, next: Next) -> Response {
+    if readiness.is_ready() {
+        next.run(request).await
+    } else {
+        Starting.into_response()
+    }
+}
+
+/// GET /ready: 200 once startup has finished, 503 before, with each check's progress
+pub fn router<S>(readiness: Readiness) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new().route(
+        "/ready",
+        get(move || {
+            let readiness = readiness.clone();
+            async move {
+                let ready = readiness.is_ready();
+                let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+                let body = serde_json::json!({
+                    "service": readiness.0.service,
+                    "ready": ready,
+                    "checks": readiness.checks(),
+                });
+                (status, Json(body))
+            }
+        }),
+    )
+}
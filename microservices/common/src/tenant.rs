@@ -0,0 +1,471 @@
+//! Tenant scoping for requests and queries
+//!
+//! Handlers that touch tenant data take a [`TenantContext`], and data access
+//! functions take `&TenantContext` rather than a bare id, so a tenant-scoped
+//! query cannot be written without a tenant in hand. The `tenant_query!`
+//! family wraps the sqlx macros: the tenant is always bound as `$1`, and a
+//! statement that neither filters on `tenant_id = $1` in a WHERE clause nor
+//! inserts `$1` as its first column, `tenant_id`, fails to compile.
+//!
+//! The extractor takes the tenant from, in order, a context an authentication
+//! layer put in the request extensions, a `:tenant_id` path segment, or a
+//! `tenant_id` query parameter. It does not authorise the caller for that
+//! tenant; that stays with each service's authentication middleware.
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query, RawPathParams},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TenantContext {
+    tenant_id: Uuid,
+}
+
+impl TenantContext {
+    /// For work not driven by a request, such as scheduled jobs and bus consumers
+    pub fn new(tenant_id: Uuid) -> Self {
+        Self { tenant_id }
+    }
+
+    pub fn tenant_id(&self) -> Uuid {
+        self.tenant_id
+    }
+}
+
+impl std::fmt::Display for TenantContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.tenant_id.fmt(f)
+    }
+}
+
+/// The request names no tenant, or not a valid one
+#[derive(Debug)]
+pub struct MissingTenant(&'static str);
+
+impl IntoResponse for MissingTenant {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": self.0}))).into_response()
+    }
+}
+
+fn parse(value: &str) -> Result<TenantContext, MissingTenant> {
+    Uuid::parse_str(value)
+        .map(TenantContext::new)
+        .map_err(|_| MissingTenant("tenant_id is not a valid UUID"))
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for TenantContext
+where
+    S: Send + Sync,
+{
+    type Rejection = MissingTenant;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(context) = parts.extensions.get::<TenantContext>() {
+            return Ok(*context);
+        }
+        if let Ok(params) = RawPathParams::from_request_parts(parts, state).await {
+            if let Some((_, value)) = params.iter().find(|(name, _)| *name == "tenant_id") {
+                return parse(value);
+            }
+        }
+        let Query(query) = Query::<HashMap<String, String>>::try_from_uri(&parts.uri).unwrap_or_default();
+        match query.get("tenant_id") {
+            Some(value) => parse(value),
+            None => Err(MissingTenant("tenant_id is required")),
+        }
+    }
+}
+
+/// Whether axum's `Json` extractor reads the body: `application/json` or any
+/// `application/*+json`, case-insensitively. Authentication layers that look
+/// for a `tenant_id` in the body inspect every such request, not just those
+/// sent as exactly `application/json`.
+pub fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    let Some((kind, subtype)) = essence.split_once('/') else {
+        return false;
+    };
+    let subtype = subtype.trim();
+    kind.trim().eq_ignore_ascii_case("application")
+        && (subtype.eq_ignore_ascii_case("json")
+            || subtype.rsplit_once('+').is_some_and(|(_, suffix)| suffix.eq_ignore_ascii_case("json")))
+}
+
+/// Whether a statement scopes itself to the tenant bound as `$1`: a
+/// `tenant_id = $1` conjunct of the outermost WHERE clause, or an INSERT whose
+/// first column is `tenant_id` and whose first value is `$1`. The predicate
+/// only counts outside parentheses and ANDed with the rest of the clause, so
+/// one in a subquery does not, and neither does a clause with an OR outside
+/// parentheses or a statement with UNION, INTERSECT or EXCEPT, which may reach
+/// rows it does not filter. Comments, string literals and quoted identifiers
+/// are skipped. Evaluated at compile time by the `tenant_query!` macros.
+pub const fn is_tenant_scoped(sql: &str) -> bool {
+    let sql = sql.as_bytes();
+    let first = next_token(sql, 0);
+    if is_keyword(sql, first, b"INSERT") {
+        return inserts_tenant_first(sql, first.end);
+    }
+    let mut depth = 0;
+    let mut clause = Clause::BeforeWhere;
+    let mut scoped = false;
+    // The predicate matched; whether it is a conjunct depends on the next token
+    let mut pending = false;
+    let mut prior = Token::END;
+    let mut before = Token::END;
+    let mut last = Token::END;
+    let mut at = 0;
+    loop {
+        let token = next_token(sql, at);
+        if pending {
+            pending = false;
+            scoped = matches!(token.kind, Kind::End)
+                || is_symbol(sql, token, b';')
+                || is_keyword(sql, token, b"AND")
+                || ends_where(sql, token);
+        }
+        if matches!(token.kind, Kind::End) {
+            return scoped && depth == 0;
+        }
+        if is_symbol(sql, token, b'(') {
+            depth += 1;
+        } else if is_symbol(sql, token, b')') {
+            if depth == 0 {
+                return false;
+            }
+            depth -= 1;
+        } else if depth == 0 {
+            if combines_queries(sql, token) {
+                return false;
+            }
+            match clause {
+                Clause::BeforeWhere => {
+                    if is_keyword(sql, token, b"WHERE") {
+                        clause = Clause::Where;
+                    }
+                }
+                Clause::Where => {
+                    if is_keyword(sql, token, b"OR") {
+                        return false;
+                    } else if ends_where(sql, token) {
+                        clause = Clause::AfterWhere;
+                    } else if (is_keyword(sql, prior, b"WHERE") || is_keyword(sql, prior, b"AND"))
+                        && is_symbol(sql, last, b'=')
+                        && ((is_tenant_column(sql, before) && is_first_parameter(sql, token))
+                            || (is_first_parameter(sql, before) && is_tenant_column(sql, token)))
+                    {
+                        pending = true;
+                    }
+                }
+                Clause::AfterWhere => {}
+            }
+        }
+        prior = before;
+        before = last;
+        last = token;
+        at = token.end;
+    }
+}
+
+/// Where `is_tenant_scoped` is in a statement, outside parentheses
+#[derive(Clone, Copy)]
+enum Clause {
+    BeforeWhere,
+    Where,
+    AfterWhere,
+}
+
+/// UNION, INTERSECT or EXCEPT
+const fn combines_queries(sql: &[u8], token: Token) -> bool {
+    is_keyword(sql, token, b"UNION") || is_keyword(sql, token, b"INTERSECT") || is_keyword(sql, token, b"EXCEPT")
+}
+
+/// A keyword that follows the WHERE clause of a statement
+const fn ends_where(sql: &[u8], token: Token) -> bool {
+    is_keyword(sql, token, b"GROUP")
+        || is_keyword(sql, token, b"HAVING")
+        || is_keyword(sql, token, b"WINDOW")
+        || is_keyword(sql, token, b"ORDER")
+        || is_keyword(sql, token, b"LIMIT")
+        || is_keyword(sql, token, b"OFFSET")
+        || is_keyword(sql, token, b"FETCH")
+        || is_keyword(sql, token, b"FOR")
+        || is_keyword(sql, token, b"RETURNING")
+}
+
+/// `INTO table (tenant_id, ...) VALUES ($1, ...`, after the INSERT keyword
+const fn inserts_tenant_first(sql: &[u8], at: usize) -> bool {
+    let into = next_token(sql, at);
+    let table = next_token(sql, into.end);
+    let open = next_token(sql, table.end);
+    let column = next_token(sql, open.end);
+    if !is_keyword(sql, into, b"INTO")
+        || !matches!(table.kind, Kind::Word)
+        || !is_symbol(sql, open, b'(')
+        || !is_keyword(sql, column, b"tenant_id")
+    {
+        return false;
+    }
+    let mut depth = 1;
+    let mut token = column;
+    while depth > 0 {
+        token = next_token(sql, token.end);
+        if matches!(token.kind, Kind::End) {
+            return false;
+        } else if is_symbol(sql, token, b'(') {
+            depth += 1;
+        } else if is_symbol(sql, token, b')') {
+            depth -= 1;
+        }
+    }
+    let values = next_token(sql, token.end);
+    let open = next_token(sql, values.end);
+    is_keyword(sql, values, b"VALUES")
+        && is_symbol(sql, open, b'(')
+        && is_first_parameter(sql, next_token(sql, open.end))
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    /// A keyword or identifier, qualified ones such as `r.tenant_id` included
+    Word,
+    /// `$` and a number
+    Parameter,
+    /// A string literal or a quoted identifier
+    Quoted,
+    /// An operator such as `=` or `<=`
+    Operator,
+    Symbol,
+    End,
+}
+
+#[derive(Clone, Copy)]
+struct Token {
+    kind: Kind,
+    start: usize,
+    end: usize,
+}
+
+impl Token {
+    const END: Token = Token { kind: Kind::End, start: 0, end: 0 };
+}
+
+const fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+const fn is_operator_byte(byte: u8) -> bool {
+    matches!(byte, b'=' | b'<' | b'>' | b'!' | b'~' | b'+' | b'-' | b'*' | b'/' | b'%' | b'|' | b'&' | b'^')
+}
+
+/// The token starting at or after `at`, past whitespace and comments
+const fn next_token(sql: &[u8], mut at: usize) -> Token {
+    loop {
+        while at < sql.len() && sql[at].is_ascii_whitespace() {
+            at += 1;
+        }
+        if at + 1 < sql.len() && sql[at] == b'-' && sql[at + 1] == b'-' {
+            while at < sql.len() && sql[at] != b'\n' {
+                at += 1;
+            }
+        } else if at + 1 < sql.len() && sql[at] == b'/' && sql[at + 1] == b'*' {
+            at += 2;
+            while at + 1 < sql.len() && !(sql[at] == b'*' && sql[at + 1] == b'/') {
+                at += 1;
+            }
+            at += 2;
+        } else {
+            break;
+        }
+    }
+    if at >= sql.len() {
+        return Token { kind: Kind::End, start: sql.len(), end: sql.len() };
+    }
+
+    let start = at;
+    let byte = sql[at];
+    let kind = if byte == b'\'' || byte == b'"' {
+        // A doubled quote is an escaped one
+        at += 1;
+        while at < sql.len() {
+            if sql[at] == byte && !(at + 1 < sql.len() && sql[at + 1] == byte) {
+                break;
+            }
+            at += if sql[at] == byte { 2 } else { 1 };
+        }
+        at += 1;
+        Kind::Quoted
+    } else if byte == b'$' && at + 1 < sql.len() && sql[at + 1].is_ascii_digit() {
+        at += 1;
+        while at < sql.len() && sql[at].is_ascii_digit() {
+            at += 1;
+        }
+        Kind::Parameter
+    } else if is_word_byte(byte) {
+        while at < sql.len() && (is_word_byte(sql[at]) || sql[at] == b'.') {
+            at += 1;
+        }
+        Kind::Word
+    } else if is_operator_byte(byte) {
+        while at < sql.len() && is_operator_byte(sql[at]) {
+            at += 1;
+        }
+        Kind::Operator
+    } else {
+        at += 1;
+        Kind::Symbol
+    };
+    Token { kind, start, end: at }
+}
+
+/// The word, case-insensitively
+const fn is_keyword(sql: &[u8], token: Token, word: &[u8]) -> bool {
+    matches!(token.kind, Kind::Word) && ends_with_word(sql, token, word) && token.end - token.start == word.len()
+}
+
+/// `tenant_id` on its own or qualified by a table
+const fn is_tenant_column(sql: &[u8], token: Token) -> bool {
+    const COLUMN: &[u8] = b"tenant_id";
+    matches!(token.kind, Kind::Word)
+        && ends_with_word(sql, token, COLUMN)
+        && (token.end - token.start == COLUMN.len() || sql[token.end - COLUMN.len() - 1] == b'.')
+}
+
+const fn ends_with_word(sql: &[u8], token: Token, word: &[u8]) -> bool {
+    if token.end - token.start < word.len() {
+        return false;
+    }
+    let offset = token.end - word.len();
+    let mut at = 0;
+    while at < word.len() {
+        if !sql[offset + at].eq_ignore_ascii_case(&word[at]) {
+            return false;
+        }
+        at += 1;
+    }
+    true
+}
+
+/// `$1` itself, not the start of `$10`
+const fn is_first_parameter(sql: &[u8], token: Token) -> bool {
+    matches!(token.kind, Kind::Parameter) && token.end - token.start == 2 && sql[token.start + 1] == b'1'
+}
+
+/// A single `=`, or a piece of punctuation such as `(`
+const fn is_symbol(sql: &[u8], token: Token, symbol: u8) -> bool {
+    matches!(token.kind, Kind::Operator | Kind::Symbol) && token.end - token.start == 1 && sql[token.start] == symbol
+}
+
+/// `sqlx::query!` with the tenant bound as `$1`
+#[macro_export]
+macro_rules! tenant_query {
+    ($tenant:expr, $sql:literal $(, $arg:expr)* $(,)?) => {{
+        const _: () = assert!(
+            $crate::tenant::is_tenant_scoped($sql),
+            "a tenant-scoped statement must filter on or insert tenant_id = $1"
+        );
+        sqlx::query!($sql, $crate::tenant::TenantContext::tenant_id($tenant) $(, $arg)*)
+    }};
+}
+
+/// `sqlx::query_as!` with the tenant bound as `$1`
+#[macro_export]
+macro_rules! tenant_query_as {
+    ($out:path, $tenant:expr, $sql:literal $(, $arg:expr)* $(,)?) => {{
+        const _: () = assert!(
+            $crate::tenant::is_tenant_scoped($sql),
+            "a tenant-scoped statement must filter on or insert tenant_id = $1"
+        );
+        sqlx::query_as!($out, $sql, $crate::tenant::TenantContext::tenant_id($tenant) $(, $arg)*)
+    }};
+}
+
+/// `sqlx::query_scalar!` with the tenant bound as `$1`
+#[macro_export]
+macro_rules! tenant_query_scalar {
+    ($tenant:expr, $sql:literal $(, $arg:expr)* $(,)?) => {{
+        const _: () = assert!(
+            $crate::tenant::is_tenant_scoped($sql),
+            "a tenant-scoped statement must filter on or insert tenant_id = $1"
+        );
+        sqlx::query_scalar!($sql, $crate::tenant::TenantContext::tenant_id($tenant) $(, $arg)*)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_tenant_scoped;
+
+    #[test]
+    fn accepts_tenant_conjuncts_of_the_outermost_where() {
+        assert!(is_tenant_scoped("SELECT * FROM reports WHERE tenant_id = $1"));
+        assert!(is_tenant_scoped("SELECT * FROM reports WHERE $1 = tenant_id AND id = $2"));
+        assert!(is_tenant_scoped("SELECT * FROM reports r WHERE r.id = $2 AND r.tenant_id = $1 ORDER BY id"));
+        assert!(is_tenant_scoped("UPDATE reports SET status = $2 WHERE tenant_id = $1 RETURNING id"));
+        assert!(is_tenant_scoped("DELETE FROM reports WHERE id = $2 AND tenant_id = $1;"));
+        assert!(is_tenant_scoped("SELECT * FROM reports WHERE tenant_id = $1 AND (status = 'A' OR status = 'B')"));
+        assert!(is_tenant_scoped(
+            "SELECT * FROM reports WHERE tenant_id = $1 AND id IN (SELECT id FROM x WHERE a = 1 OR b = 2)"
+        ));
+        assert!(is_tenant_scoped("select * from reports where TENANT_ID = $1 for update"));
+    }
+
+    #[test]
+    fn rejects_a_missing_or_other_parameter() {
+        assert!(!is_tenant_scoped("SELECT * FROM reports"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE id = $1"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE tenant_id = $10"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE tenant_id = $2"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE other_tenant_id = $1"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports JOIN t ON t.tenant_id = $1 WHERE id = $2"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE id = $2 ORDER BY tenant_id = $1"));
+    }
+
+    #[test]
+    fn rejects_tenant_predicates_that_do_not_restrict_every_row() {
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE id = $2 OR tenant_id = $1"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE tenant_id = $1 OR id = $2"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE NOT tenant_id = $1"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE (tenant_id = $1 OR id = $2)"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE tenant_id = $1 IS NULL"));
+        assert!(!is_tenant_scoped(
+            "SELECT * FROM reports WHERE tenant_id = $1 UNION SELECT * FROM reports WHERE id = $2"
+        ));
+    }
+
+    #[test]
+    fn rejects_tenant_predicates_of_subqueries() {
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE id IN (SELECT id FROM x WHERE tenant_id = $1)"));
+        assert!(!is_tenant_scoped("SELECT (SELECT COUNT(*) FROM x WHERE tenant_id = $1) FROM reports WHERE id = $2"));
+        assert!(!is_tenant_scoped("SELECT * FROM (SELECT * FROM reports WHERE tenant_id = $1) r"));
+    }
+
+    #[test]
+    fn skips_comments_and_quoted_text() {
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE id = $2 -- AND tenant_id = $1"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE id = $2 /* AND tenant_id = $1 */"));
+        assert!(!is_tenant_scoped("SELECT * FROM reports WHERE name = 'x'' AND tenant_id = $1'"));
+        assert!(!is_tenant_scoped(r#"SELECT * FROM reports WHERE "tenant_id = $1" = id"#));
+        assert!(is_tenant_scoped("SELECT * FROM reports /* WHERE id = $2 OR */ WHERE tenant_id = $1"));
+        assert!(is_tenant_scoped("SELECT * FROM reports WHERE name = 'a OR b' AND tenant_id = $1 -- or not"));
+    }
+
+    #[test]
+    fn checks_the_first_column_and_value_of_inserts() {
+        assert!(is_tenant_scoped("INSERT INTO reports (tenant_id, id) VALUES ($1, $2)"));
+        assert!(is_tenant_scoped("insert into reports (tenant_id, id) values ($1, $2) returning id"));
+        assert!(!is_tenant_scoped("INSERT INTO reports (id, tenant_id) VALUES ($1, $2)"));
+        assert!(!is_tenant_scoped("INSERT INTO reports (tenant_id, id) VALUES ($2, $1)"));
+        assert!(!is_tenant_scoped("INSERT INTO reports (tenant_id, id) VALUES ($10, $2)"));
+        assert!(!is_tenant_scoped("INSERT INTO reports (tenant_id, id) SELECT $1, id FROM x"));
+    }
+}
@@ -0,0 +1,164 @@
+//! API versions and deprecation signalling
+//!
+//! Services mount each API version under /api/<version>; handlers of
+//! different versions share the same services and differ only in their DTOs.
+//! A version, or a single endpoint within one, is marked with a
+//! [`Deprecation`], which adds the `Deprecation` (RFC 9745) and `Sunset`
+//! (RFC 8594) headers and a `Link` to the successor, and counts the calls so
+//! owners can see who still depends on it. Once API_ENFORCE_SUNSET is set,
+//! deprecated endpoints past their sunset answer 410 Gone.
+//!
+//! The paths served before versioning existed remain as deprecated aliases of
+//! v1 until API_UNVERSIONED_SUNSET.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    Router,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use metrics::counter;
+use std::sync::Arc;
+use tracing::warn;
+
+/// When the unprefixed paths were deprecated in favour of /api/v1
+fn unversioned_deprecated_at() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 10, 14, 0, 0, 0).single().expect("valid date")
+}
+
+#[derive(Debug, Clone)]
+enum Successor {
+    /// Same path under another version root, e.g. /api/v2
+    Prefix(String),
+    Url(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Deprecation {
+    service: &'static str,
+    version: &'static str,
+    deprecated_at: DateTime<Utc>,
+    sunset: Option<DateTime<Utc>>,
+    successor: Option<Successor>,
+    enforce_sunset: bool,
+}
+
+impl Deprecation {
+    pub fn new(service: &'static str, version: &'static str, deprecated_at: DateTime<Utc>) -> Self {
+        Self {
+            service,
+            version,
+            deprecated_at,
+            sunset: None,
+            successor: None,
+            enforce_sunset: std::env::var("API_ENFORCE_SUNSET").is_ok_and(|value| value == "true"),
+        }
+    }
+
+    pub fn sunset(mut self, sunset: DateTime<Utc>) -> Self {
+        self.sunset = Some(sunset);
+        self
+    }
+
+    /// Sunset from an RFC 3339 environment variable, if set
+    pub fn sunset_from_env(self, name: &str) -> Self {
+        match std::env::var(name).ok().filter(|value| !value.is_empty()) {
+            Some(value) => match DateTime::parse_from_rfc3339(&value) {
+                Ok(sunset) => self.sunset(sunset.with_timezone(&Utc)),
+                Err(e) => {
+                    warn!("Ignoring invalid {} {:?}: {}", name, value, e);
+                    self
+                }
+            },
+            None => self,
+        }
+    }
+
+    /// The same path under another version root replaces this one
+    pub fn successor_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.successor = Some(Successor::Prefix(prefix.into()));
+        self
+    }
+
+    pub fn successor_url(mut self, url: impl Into<String>) -> Self {
+        self.successor = Some(Successor::Url(url.into()));
+        self
+    }
+
+    fn sunset_passed(&self) -> bool {
+        self.sunset.is_some_and(|sunset| Utc::now() >= sunset)
+    }
+
+    fn successor_link(&self, path: &str) -> Option<String> {
+        let target = match self.successor.as_ref()? {
+            Successor::Prefix(prefix) => format!("{}{}", prefix, path),
+            Successor::Url(url) => url.clone(),
+        };
+        Some(format!("<{}>; rel=\"successor-version\"", target))
+    }
+}
+
+/// Apply `deprecation` to every route of `router`
+pub fn deprecate<S>(router: Router<S>, deprecation: Deprecation) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.route_layer(middleware::from_fn_with_state(Arc::new(deprecation), signal_deprecation))
+}
+
+/// Mount `v1` under /api/v1 and, deprecated, at its original unprefixed paths
+pub fn versioned<S>(service: &'static str, v1: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let unversioned = Deprecation::new(service, "unversioned", unversioned_deprecated_at())
+        .sunset_from_env("API_UNVERSIONED_SUNSET")
+        .successor_prefix("/api/v1");
+    Router::new()
+        .nest("/api/v1", v1.clone())
+        .merge(deprecate(v1, unversioned))
+}
+
+async fn signal_deprecation(
+    State(deprecation): State<Arc<Deprecation>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    counter!(
+        "api_deprecated_requests_total",
+        1,
+        "service" => deprecation.service,
+        "version" => deprecation.version
+    );
+    let successor = deprecation.successor_link(request.uri().path());
+
+    let mut response = if deprecation.enforce_sunset && deprecation.sunset_passed() {
+        (
+            StatusCode::GONE,
+            Json(serde_json::json!({
+                "error": format!("API {} has been retired", deprecation.version),
+            })),
+        )
+            .into_response()
+    } else {
+        next.run(request).await
+    };
+
+    // An endpoint-level deprecation nested inside a version-level one wins,
+    // but every successor is advertised
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&format!("@{}", deprecation.deprecated_at.timestamp())) {
+        headers.entry("deprecation").or_insert(value);
+    }
+    if let Some(sunset) = deprecation.sunset {
+        if let Ok(value) = HeaderValue::from_str(&sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string()) {
+            headers.entry("sunset").or_insert(value);
+        }
+    }
+    if let Some(value) = successor.and_then(|link| HeaderValue::from_str(&link).ok()) {
+        headers.append(header::LINK, value);
+    }
+    response
+}
@@ -0,0 +1,610 @@
+//! Tenant auto-closure rules for stale alerts
+//!
+//! A rule closes alerts in the listed statuses that have seen no activity
+//! (alert updates, investigation work or evidence) for `inactive_days`, e.g.
+//! LOW alerts untouched for 30 days close with disposition "expired". The
+//! closure job applies every enabled rule; each alert it closes is recorded in
+//! `alert_auto_closures` with the rule as it stood and the reason, and copied
+//! to the audit service.
+
+use chrono::{DateTime, Utc};
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::{tenant_query, tenant_query_as, tenant_query_scalar};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::taxonomy::PLATFORM_SEVERITIES;
+
+/// Statuses a rule may close alerts from
+const OPEN_STATUSES: [&str; 2] = ["OPEN", "INVESTIGATING"];
+
+/// Statuses a rule may close alerts to
+const CLOSED_STATUSES: [&str; 2] = ["RESOLVED", "FALSE_POSITIVE"];
+
+pub struct AutoCloser {
+    db: PgPool,
+    /// Alerts closed per statement, so one rule cannot hold locks on a whole backlog
+    batch_size: i64,
+    audit_service_url: Option<String>,
+    /// One of the audit service's AUDIT_SERVICE_TOKENS
+    audit_service_token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl AutoCloser {
+    pub fn from_env(db: PgPool) -> Self {
+        Self {
+            db,
+            batch_size: std::env::var("ALERT_CLOSURE_BATCH_SIZE")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(500)
+                .max(1),
+            audit_service_url: std::env::var("AUDIT_SERVICE_URL").ok().filter(|url| !url.is_empty()),
+            audit_service_token: std::env::var("AUDIT_SERVICE_TOKEN").ok().filter(|token| !token.is_empty()),
+            http: reqwest::Client::new(),
+        }
+    }
+}
+
+fn default_statuses() -> Vec<String> {
+    vec!["OPEN".to_string()]
+}
+
+fn default_close_status() -> String {
+    "RESOLVED".to_string()
+}
+
+fn default_disposition() -> String {
+    "expired".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Body of rule create and replace; empty filter lists match every alert
+#[derive(Deserialize, Debug, Clone)]
+pub struct RuleRequest {
+    pub name: String,
+    pub description: Option<String>,
+    /// Platform severities
+    #[serde(default)]
+    pub severities: Vec<String>,
+    /// Tenant severity codes
+    #[serde(default)]
+    pub tenant_severities: Vec<String>,
+    #[serde(default)]
+    pub alert_types: Vec<String>,
+    #[serde(default = "default_statuses")]
+    pub statuses: Vec<String>,
+    pub inactive_days: i32,
+    /// Alerts scoring above this are left open
+    pub max_risk_score: Option<f64>,
+    #[serde(default = "default_close_status")]
+    pub close_status: String,
+    #[serde(default = "default_disposition")]
+    pub disposition: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub created_by: Option<Uuid>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ClosureRule {
+    pub rule_id: Uuid,
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub severities: Vec<String>,
+    pub tenant_severities: Vec<String>,
+    pub alert_types: Vec<String>,
+    pub statuses: Vec<String>,
+    pub inactive_days: i32,
+    pub max_risk_score: Option<f64>,
+    pub close_status: String,
+    pub disposition: String,
+    pub enabled: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub enum SaveOutcome {
+    Saved(ClosureRule),
+    NameTaken,
+    NotFound,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PreviewAlert {
+    pub alert_id: Uuid,
+    pub alert_type: String,
+    pub severity: String,
+    pub tenant_severity: Option<String>,
+    pub status: String,
+    pub last_activity_at: DateTime<Utc>,
+}
+
+/// What a rule would close if the job ran now
+#[derive(Serialize, Debug, Clone)]
+pub struct ClosurePreview {
+    pub rule_id: Uuid,
+    pub matching: i64,
+    /// Longest-inactive alerts first, at most 100
+    pub alerts: Vec<PreviewAlert>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ClosureRun {
+    pub run_id: Uuid,
+    pub triggered_by: String,
+    pub status: String,
+    pub rules_evaluated: i32,
+    pub alerts_closed: i32,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AutoClosure {
+    pub closure_id: Uuid,
+    pub run_id: Uuid,
+    pub alert_id: Uuid,
+    /// None once the rule has been deleted; `rule` still describes it
+    pub rule_id: Option<Uuid>,
+    pub rule: serde_json::Value,
+    pub previous_status: String,
+    pub close_status: String,
+    pub disposition: String,
+    pub last_activity_at: DateTime<Utc>,
+    pub reason: String,
+    pub closed_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct ClosureParams {
+    pub rule_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+/// Checks run before a rule is created or replaced
+pub async fn validate_rule(db: &PgPool, tenant: &TenantContext, rule: &RuleRequest) -> anyhow::Result<Vec<String>> {
+    let mut errors = Vec::new();
+
+    if rule.name.trim().is_empty() || rule.name.len() > 100 {
+        errors.push("name must be 1-100 characters".to_string());
+    }
+    if !(1..=3650).contains(&rule.inactive_days) {
+        errors.push("inactive_days must be between 1 and 3650".to_string());
+    }
+    for severity in &rule.severities {
+        if !PLATFORM_SEVERITIES.contains(&severity.as_str()) {
+            errors.push(format!("unknown platform severity '{}'", severity));
+        }
+    }
+    if rule.statuses.is_empty() {
+        errors.push("at least one status to close from is required".to_string());
+    }
+    for status in &rule.statuses {
+        if !OPEN_STATUSES.contains(&status.as_str()) {
+            errors.push(format!("status '{}' is not one of {}", status, OPEN_STATUSES.join(", ")));
+        }
+    }
+    if !CLOSED_STATUSES.contains(&rule.close_status.as_str()) {
+        errors.push(format!("close_status must be one of {}", CLOSED_STATUSES.join(", ")));
+    }
+    if rule.disposition.trim().is_empty() || rule.disposition.len() > 50 {
+        errors.push("disposition must be 1-50 characters".to_string());
+    }
+    if rule.max_risk_score.map_or(false, |score| !(0.0..=100.0).contains(&score)) {
+        errors.push("max_risk_score must be between 0 and 100".to_string());
+    }
+    if rule.alert_types.iter().any(|alert_type| alert_type.trim().is_empty() || alert_type.len() > 100) {
+        errors.push("alert types must be 1-100 characters".to_string());
+    }
+
+    if !rule.tenant_severities.is_empty() {
+        let known: HashSet<String> = tenant_query_scalar!(
+            tenant,
+            "SELECT code FROM tenant_severity_levels WHERE tenant_id = $1"
+        )
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .collect();
+        for code in &rule.tenant_severities {
+            if !known.contains(code) {
+                errors.push(format!("unknown tenant severity '{}'", code));
+            }
+        }
+    }
+
+    Ok(errors)
+}
+
+async fn select_rules(
+    db: &PgPool,
+    tenant_id: Option<Uuid>,
+    rule_id: Option<Uuid>,
+    enabled_only: bool,
+) -> anyhow::Result<Vec<ClosureRule>> {
+    let rules = sqlx::query_as!(
+        ClosureRule,
+        r#"
+        SELECT rule_id, tenant_id, name, description,
+               severities::text[] AS "severities!", tenant_severities AS "tenant_severities!",
+               alert_types AS "alert_types!", statuses::text[] AS "statuses!", inactive_days,
+               max_risk_score::float8 AS max_risk_score, close_status::text AS "close_status!",
+               disposition, enabled, created_by, created_at, updated_at
+        FROM alert_closure_rules
+        WHERE ($1::uuid IS NULL OR tenant_id = $1)
+          AND ($2::uuid IS NULL OR rule_id = $2)
+          AND (enabled OR NOT $3)
+        ORDER BY tenant_id, name
+        "#,
+        tenant_id,
+        rule_id,
+        enabled_only
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rules)
+}
+
+pub async fn list_rules(db: &PgPool, tenant: &TenantContext) -> anyhow::Result<Vec<ClosureRule>> {
+    select_rules(db, Some(tenant.tenant_id()), None, false).await
+}
+
+pub async fn get_rule(db: &PgPool, tenant: &TenantContext, rule_id: Uuid) -> anyhow::Result<Option<ClosureRule>> {
+    Ok(select_rules(db, Some(tenant.tenant_id()), Some(rule_id), false).await?.pop())
+}
+
+pub async fn create_rule(db: &PgPool, tenant: &TenantContext, rule: &RuleRequest) -> anyhow::Result<SaveOutcome> {
+    let inserted = tenant_query_scalar!(
+        tenant,
+        r#"
+        INSERT INTO alert_closure_rules (
+            tenant_id, name, description, severities, tenant_severities, alert_types, statuses,
+            inactive_days, max_risk_score, close_status, disposition, enabled, created_by
+        )
+        VALUES ($1, $2, $3, $4::text[]::alert_severity[], $5, $6, $7::text[]::alert_status[],
+                $8, $9::float8, ($10::text)::alert_status, $11, $12, $13)
+        RETURNING rule_id
+        "#,
+        rule.name.trim(),
+        rule.description,
+        &rule.severities,
+        &rule.tenant_severities,
+        &rule.alert_types,
+        &rule.statuses,
+        rule.inactive_days,
+        rule.max_risk_score,
+        rule.close_status,
+        rule.disposition.trim(),
+        rule.enabled,
+        rule.created_by
+    )
+    .fetch_one(db)
+    .await;
+
+    match inserted {
+        Ok(rule_id) => Ok(get_rule(db, tenant, rule_id)
+            .await?
+            .map_or(SaveOutcome::NotFound, SaveOutcome::Saved)),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(SaveOutcome::NameTaken),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace a rule's definition; alerts it already closed stay closed
+pub async fn replace_rule(
+    db: &PgPool,
+    tenant: &TenantContext,
+    rule_id: Uuid,
+    rule: &RuleRequest,
+) -> anyhow::Result<SaveOutcome> {
+    let updated = tenant_query_scalar!(
+        tenant,
+        r#"
+        UPDATE alert_closure_rules
+        SET name = $3, description = $4, severities = $5::text[]::alert_severity[],
+            tenant_severities = $6, alert_types = $7, statuses = $8::text[]::alert_status[],
+            inactive_days = $9, max_risk_score = $10::float8, close_status = ($11::text)::alert_status,
+            disposition = $12, enabled = $13, updated_at = NOW()
+        WHERE tenant_id = $1 AND rule_id = $2
+        RETURNING rule_id
+        "#,
+        rule_id,
+        rule.name.trim(),
+        rule.description,
+        &rule.severities,
+        &rule.tenant_severities,
+        &rule.alert_types,
+        &rule.statuses,
+        rule.inactive_days,
+        rule.max_risk_score,
+        rule.close_status,
+        rule.disposition.trim(),
+        rule.enabled
+    )
+    .fetch_optional(db)
+    .await;
+
+    match updated {
+        Ok(Some(_)) => Ok(get_rule(db, tenant, rule_id)
+            .await?
+            .map_or(SaveOutcome::NotFound, SaveOutcome::Saved)),
+        Ok(None) => Ok(SaveOutcome::NotFound),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(SaveOutcome::NameTaken),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Delete a rule; its closure records keep their rule snapshot
+pub async fn delete_rule(db: &PgPool, tenant: &TenantContext, rule_id: Uuid) -> anyhow::Result<bool> {
+    let result = tenant_query!(
+        tenant,
+        "DELETE FROM alert_closure_rules WHERE tenant_id = $1 AND rule_id = $2",
+        rule_id
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Alerts the rule would close now, whether or not it is enabled
+pub async fn preview(db: &PgPool, tenant: &TenantContext, rule_id: Uuid) -> anyhow::Result<Option<ClosurePreview>> {
+    if get_rule(db, tenant, rule_id).await?.is_none() {
+        return Ok(None);
+    }
+
+    let matching = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM alert_closure_candidates($1)"#,
+        rule_id
+    )
+    .fetch_one(db)
+    .await?;
+
+    let alerts = sqlx::query_as!(
+        PreviewAlert,
+        r#"
+        SELECT a.alert_id, a.alert_type, a.severity::text AS "severity!", a.tenant_severity,
+               a.status::text AS "status!", c.last_activity_at AS "last_activity_at!"
+        FROM alert_closure_candidates($1) c
+        JOIN surveillance_alerts a ON a.alert_id = c.alert_id
+        ORDER BY c.last_activity_at
+        LIMIT 100
+        "#,
+        rule_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(Some(ClosurePreview { rule_id, matching, alerts }))
+}
+
+pub fn spawn_worker(closer: Arc<AutoCloser>, every: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match run(&closer, "SCHEDULED").await {
+                Ok(run) if run.alerts_closed > 0 => info!(
+                    "Auto-closed {} alert(s) under {} rule(s) in run {}",
+                    run.alerts_closed, run.rules_evaluated, run.run_id
+                ),
+                Ok(_) => {}
+                Err(e) => error!("Alert auto-closure run failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Apply every enabled rule of every tenant
+pub async fn run(closer: &AutoCloser, triggered_by: &str) -> anyhow::Result<ClosureRun> {
+    // A run left RUNNING by a crashed worker would otherwise look in progress forever
+    sqlx::query!(
+        r#"
+        UPDATE alert_closure_runs
+        SET status = 'FAILED', error = 'interrupted before completion', completed_at = NOW()
+        WHERE status = 'RUNNING' AND started_at < NOW() - INTERVAL '1 hour'
+        "#
+    )
+    .execute(&closer.db)
+    .await?;
+
+    let run_id = sqlx::query_scalar!(
+        "INSERT INTO alert_closure_runs (triggered_by) VALUES ($1) RETURNING run_id",
+        triggered_by
+    )
+    .fetch_one(&closer.db)
+    .await?;
+
+    let rules = select_rules(&closer.db, None, None, true).await?;
+    let mut alerts_closed = 0;
+    let mut failures = Vec::new();
+    for rule in &rules {
+        match apply_rule(closer, run_id, rule).await {
+            Ok(closed) => alerts_closed += closed,
+            Err(e) => {
+                error!("Auto-closure rule {} for tenant {} failed: {}", rule.rule_id, rule.tenant_id, e);
+                failures.push(format!("rule {}: {}", rule.rule_id, e));
+            }
+        }
+    }
+
+    let status = if failures.is_empty() { "COMPLETED" } else { "FAILED" };
+    let error = (!failures.is_empty()).then(|| failures.join("; "));
+    let run = sqlx::query_as!(
+        ClosureRun,
+        r#"
+        UPDATE alert_closure_runs
+        SET status = $2, rules_evaluated = $3, alerts_closed = $4, error = $5, completed_at = NOW()
+        WHERE run_id = $1
+        RETURNING run_id, triggered_by, status, rules_evaluated, alerts_closed, error, started_at, completed_at
+        "#,
+        run_id,
+        status,
+        rules.len() as i32,
+        alerts_closed as i32,
+        error
+    )
+    .fetch_one(&closer.db)
+    .await?;
+    Ok(run)
+}
+
+/// Close the rule's matching alerts in batches; returns how many were closed
+async fn apply_rule(closer: &AutoCloser, run_id: Uuid, rule: &ClosureRule) -> anyhow::Result<usize> {
+    let snapshot = serde_json::to_value(rule)?;
+    let reason = format!(
+        "Auto-closed as {} by rule '{}' after {} days without activity",
+        rule.disposition, rule.name, rule.inactive_days
+    );
+
+    let mut total = 0;
+    loop {
+        // The status check is on the locked row, so an alert an analyst picked
+        // up after the candidate scan is skipped rather than closed
+        let closed = sqlx::query!(
+            r#"
+            WITH candidates AS (
+                SELECT a.alert_id, a.status AS previous_status, c.last_activity_at
+                FROM alert_closure_candidates($1) c
+                JOIN surveillance_alerts a ON a.alert_id = c.alert_id
+                WHERE a.status = ANY($3::text[]::alert_status[])
+                ORDER BY c.last_activity_at
+                LIMIT $2
+                FOR UPDATE OF a SKIP LOCKED
+            ),
+            closed AS (
+                UPDATE surveillance_alerts a
+                SET status = ($4::text)::alert_status, disposition = $5, auto_closed_by_rule = $1,
+                    resolved_at = NOW(), updated_at = NOW(),
+                    resolution_notes = concat_ws(E'\n\n', a.resolution_notes, $6::text)
+                FROM candidates c
+                WHERE a.alert_id = c.alert_id
+                RETURNING a.alert_id, c.previous_status, c.last_activity_at
+            )
+            INSERT INTO alert_auto_closures (
+                run_id, tenant_id, alert_id, rule_id, rule_snapshot, previous_status,
+                close_status, disposition, last_activity_at, reason
+            )
+            SELECT $7, $8, closed.alert_id, $1, $9, closed.previous_status,
+                   ($4::text)::alert_status, $5, closed.last_activity_at,
+                   format('No activity since %s. %s',
+                          to_char(closed.last_activity_at AT TIME ZONE 'UTC', 'YYYY-MM-DD HH24:MI "UTC"'), $6::text)
+            FROM closed
+            RETURNING alert_id, previous_status::text AS "previous_status!", last_activity_at, reason
+            "#,
+            rule.rule_id,
+            closer.batch_size,
+            &rule.statuses,
+            rule.close_status,
+            rule.disposition,
+            reason,
+            run_id,
+            rule.tenant_id,
+            snapshot
+        )
+        .fetch_all(&closer.db)
+        .await?;
+
+        let batch = closed.len();
+        total += batch;
+
+        // The closure table is authoritative; the audit trail copy is best effort
+        if let Some(audit_service_url) = closer.audit_service_url.as_ref().filter(|_| batch > 0) {
+            let events: Vec<serde_json::Value> = closed
+                .iter()
+                .map(|row| {
+                    serde_json::json!({
+                        "tenant_id": rule.tenant_id,
+                        "action": "ALERT_AUTO_CLOSED",
+                        "resource_type": "SURVEILLANCE_ALERT",
+                        "resource_id": row.alert_id,
+                        "old_values": {"status": row.previous_status},
+                        "new_values": {
+                            "status": rule.close_status,
+                            "disposition": rule.disposition,
+                            "rule_id": rule.rule_id,
+                            "rule_name": rule.name,
+                            "run_id": run_id,
+                            "last_activity_at": row.last_activity_at,
+                            "reason": row.reason,
+                        },
+                    })
+                })
+                .collect();
+            let http = closer.http.clone();
+            let url = format!("{}/audit/events", audit_service_url);
+            let token = closer.audit_service_token.clone();
+            tokio::spawn(async move {
+                for event in events {
+                    let mut request = http.post(&url).json(&event);
+                    if let Some(token) = &token {
+                        request = request.bearer_auth(token);
+                    }
+                    if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                        warn!("Failed to send alert auto-closure {} to the audit service: {}", event["resource_id"], e);
+                    }
+                }
+            });
+        }
+
+        if (batch as i64) < closer.batch_size {
+            return Ok(total);
+        }
+    }
+}
+
+pub async fn list_runs(db: &PgPool, limit: i64) -> anyhow::Result<Vec<ClosureRun>> {
+    let runs = sqlx::query_as!(
+        ClosureRun,
+        r#"
+        SELECT run_id, triggered_by, status, rules_evaluated, alerts_closed, error, started_at, completed_at
+        FROM alert_closure_runs
+        ORDER BY started_at DESC
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(runs)
+}
+
+/// The tenant's auto-closed alerts, newest first
+pub async fn list_closures(
+    db: &PgPool,
+    tenant: &TenantContext,
+    params: &ClosureParams,
+) -> anyhow::Result<Vec<AutoClosure>> {
+    let closures = tenant_query_as!(
+        AutoClosure,
+        tenant,
+        r#"
+        SELECT closure_id, run_id, alert_id, rule_id, rule_snapshot AS rule,
+               previous_status::text AS "previous_status!", close_status::text AS "close_status!",
+               disposition, last_activity_at, reason, closed_at
+        FROM alert_auto_closures
+        WHERE tenant_id = $1 AND ($2::uuid IS NULL OR rule_id = $2)
+        ORDER BY closed_at DESC
+        LIMIT $3
+        "#,
+        params.rule_id,
+        params.limit.unwrap_or(100).clamp(1, 1000)
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(closures)
+}
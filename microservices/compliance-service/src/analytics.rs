@@ -0,0 +1,500 @@
+//! Read-only SQL sandbox for compliance officers
+//!
+//! Analysts submit a single SELECT over a whitelist of tables. The statement is
+//! parsed and rejected unless every relation is an unqualified whitelisted
+//! table (or one of the query's own CTEs) and every function is on an
+//! allowlist. Tenant scoping does not rely on the analyst's WHERE clause: the
+//! query runs beneath CTEs named after the whitelisted tables, each filtered
+//! to the caller's tenant, so `trades` in the query only ever sees the
+//! tenant's trades. Execution happens in a READ ONLY transaction with a
+//! statement timeout and a row cap, and every attempt, rejected or not, is
+//! written to analytics_query_log and the audit service.
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query, State},
+    http::{header, request::Parts, StatusCode},
+    response::Json,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlparser::ast::{visit_expressions, visit_relations, Expr, Ident, ObjectName, Statement};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::AppState;
+
+/// Queryable tables and whether they are filtered by tenant_id
+const TABLES: &[(&str, bool)] = &[
+    ("trades", true),
+    ("orders", true),
+    ("positions", true),
+    ("margin_statements", true),
+    ("trading_accounts", true),
+    ("surveillance_alerts", true),
+    ("compliance_violations", true),
+    // Reference data shared by all tenants
+    ("instruments", false),
+];
+
+/// Functions analysts may call; anything else (pg_*, dblink, query_to_xml, ...) is rejected
+const FUNCTIONS: &[&str] = &[
+    "count", "sum", "avg", "min", "max", "stddev", "stddev_pop", "stddev_samp", "variance", "var_pop",
+    "var_samp", "percentile_cont", "percentile_disc", "mode", "bool_and", "bool_or", "array_agg", "string_agg",
+    "corr", "covar_pop", "covar_samp", "regr_slope", "row_number", "rank", "dense_rank", "percent_rank",
+    "cume_dist", "ntile", "lag", "lead", "first_value", "last_value", "nth_value", "coalesce", "nullif",
+    "greatest", "least", "abs", "round", "trunc", "floor", "ceil", "ceiling", "sqrt", "power", "ln", "log",
+    "exp", "sign", "mod", "lower", "upper", "length", "substring", "substr", "trim", "btrim", "ltrim", "rtrim",
+    "concat", "concat_ws", "left", "right", "replace", "split_part", "position", "strpos", "to_char",
+    "to_date", "to_timestamp", "to_number", "date_trunc", "date_part", "extract", "age", "now",
+    "current_date", "current_timestamp", "make_date", "make_interval", "generate_series",
+];
+
+const OFFICER_ROLE: &str = "COMPLIANCE_OFFICER";
+
+pub struct Sandbox {
+    decoding_key: DecodingKey,
+    max_rows: i64,
+    timeout: Duration,
+    audit_service_url: Option<String>,
+    /// One of the audit service's AUDIT_SERVICE_TOKENS
+    audit_service_token: Option<String>,
+    http: reqwest::Client,
+}
+
+impl Sandbox {
+    /// Built from JWT_SECRET and ANALYTICS_*; `None` disables the sandbox
+    pub fn from_env() -> Option<Self> {
+        let secret = std::env::var("JWT_SECRET").ok().filter(|secret| !secret.is_empty())?;
+        let number = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Some(Self {
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            max_rows: number("ANALYTICS_MAX_ROWS", 1000).max(1) as i64,
+            timeout: Duration::from_millis(number("ANALYTICS_TIMEOUT_MS", 10_000)),
+            audit_service_url: std::env::var("AUDIT_SERVICE_URL").ok().filter(|url| !url.is_empty()),
+            audit_service_token: std::env::var("AUDIT_SERVICE_TOKEN").ok().filter(|token| !token.is_empty()),
+            http: reqwest::Client::new(),
+        })
+    }
+}
+
+/// Platform access token claims
+#[derive(Deserialize)]
+struct Claims {
+    sub: Uuid,
+    tenant_id: Uuid,
+    role: String,
+}
+
+/// Caller identity from the bearer token
+pub struct Analyst {
+    pub user_id: Uuid,
+    pub tenant_id: Uuid,
+    pub role: String,
+}
+
+impl Analyst {
+    /// Roles are compared as the user service issues them, in any case
+    pub fn is_officer(&self) -> bool {
+        self.role.trim().eq_ignore_ascii_case(OFFICER_ROLE)
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for Analyst {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let sandbox = state.analytics.as_ref().ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        let claims = jsonwebtoken::decode::<Claims>(token, &sandbox.decoding_key, &Validation::new(Algorithm::HS256))
+            .map_err(|_| StatusCode::UNAUTHORIZED)?
+            .claims;
+        Ok(Self {
+            user_id: claims.sub,
+            tenant_id: claims.tenant_id,
+            role: claims.role,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct QueryRequest {
+    pub sql: String,
+    /// Capped at ANALYTICS_MAX_ROWS
+    pub max_rows: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct QueryResult {
+    pub query_id: Uuid,
+    pub rows: Vec<serde_json::Value>,
+    pub row_count: usize,
+    /// More rows matched than were returned
+    pub truncated: bool,
+    pub duration_ms: u64,
+}
+
+#[derive(Serialize)]
+pub struct QueryLogEntry {
+    pub query_id: Uuid,
+    pub user_id: Uuid,
+    pub query_text: String,
+    pub status: String,
+    pub row_count: Option<i32>,
+    pub truncated: bool,
+    pub duration_ms: Option<i32>,
+    pub error: Option<String>,
+    pub executed_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Tables referenced by an accepted query, in first-use order
+fn validate(sql: &str) -> Result<Vec<&'static (&'static str, bool)>, String> {
+    let statements = Parser::parse_sql(&PostgreSqlDialect {}, sql).map_err(|e| e.to_string())?;
+    let statement = match statements.as_slice() {
+        [statement] => statement,
+        _ => return Err("exactly one statement is allowed".to_string()),
+    };
+    let Statement::Query(query) = statement else {
+        return Err("only SELECT queries are allowed".to_string());
+    };
+
+    let own_ctes: HashSet<String> = query
+        .with
+        .iter()
+        .flat_map(|with| with.cte_tables.iter())
+        .map(|cte| normalize(&cte.alias.name))
+        .collect();
+    // A CTE named after a sandbox table would hide the tenant filter from its own body
+    if let Some(name) = own_ctes.iter().find(|name| TABLES.iter().any(|(table, _)| *table == name.as_str())) {
+        return Err(format!("CTE name {} is reserved for the sandbox table", name));
+    }
+
+    let mut tables = Vec::new();
+    let relations = visit_relations(statement, |relation: &ObjectName| {
+        let [name] = relation.0.as_slice() else {
+            return ControlFlow::Break(format!("schema-qualified relation {} is not allowed", relation));
+        };
+        let name = normalize(name);
+        if own_ctes.contains(&name) {
+            return ControlFlow::Continue(());
+        }
+        match TABLES.iter().find(|(table, _)| *table == name) {
+            Some(table) => {
+                if !tables.contains(&table) {
+                    tables.push(table);
+                }
+                ControlFlow::Continue(())
+            }
+            None => ControlFlow::Break(format!("table {} is not available in the sandbox", name)),
+        }
+    });
+    if let ControlFlow::Break(reason) = relations {
+        return Err(reason);
+    }
+
+    let functions = visit_expressions(statement, |expr: &Expr| match expr {
+        Expr::Function(function) => match function.name.0.as_slice() {
+            [name] if FUNCTIONS.contains(&normalize(name).as_str()) => ControlFlow::Continue(()),
+            _ => ControlFlow::Break(format!("function {} is not allowed", function.name)),
+        },
+        _ => ControlFlow::Continue(()),
+    });
+    if let ControlFlow::Break(reason) = functions {
+        return Err(reason);
+    }
+
+    if tables.is_empty() {
+        return Err("query must read from at least one sandbox table".to_string());
+    }
+    Ok(tables)
+}
+
+/// Postgres folds unquoted identifiers to lower case
+fn normalize(ident: &Ident) -> String {
+    match ident.quote_style {
+        Some(_) => ident.value.clone(),
+        None => ident.value.to_lowercase(),
+    }
+}
+
+/// Wrap the analyst's query beneath tenant-filtered CTEs that shadow the real tables
+fn scoped_sql(sql: &str, tables: &[&(&str, bool)], limit: i64) -> String {
+    let ctes: Vec<String> = tables
+        .iter()
+        .map(|(table, tenant_scoped)| {
+            if *tenant_scoped {
+                format!("{table} AS (SELECT * FROM public.{table} WHERE tenant_id = $1)")
+            } else {
+                format!("{table} AS (SELECT * FROM public.{table})")
+            }
+        })
+        .collect();
+    format!(
+        "WITH {} SELECT row_to_json(sandbox)::text FROM ({}) AS sandbox LIMIT {}",
+        ctes.join(", "),
+        sql.trim().trim_end_matches(';'),
+        limit
+    )
+}
+
+enum Outcome {
+    Completed { rows: Vec<serde_json::Value>, truncated: bool },
+    Rejected(String),
+    Failed(String),
+    TimedOut,
+}
+
+async fn execute(
+    db: &PgPool,
+    sandbox: &Sandbox,
+    tenant_id: Uuid,
+    sql: &str,
+    max_rows: i64,
+) -> Result<Outcome, sqlx::Error> {
+    let tables = match validate(sql) {
+        Ok(tables) => tables,
+        Err(reason) => return Ok(Outcome::Rejected(reason)),
+    };
+
+    let mut tx = db.begin().await?;
+    sqlx::query("SET TRANSACTION READ ONLY").execute(&mut *tx).await?;
+    sqlx::query(&format!("SET LOCAL statement_timeout = {}", sandbox.timeout.as_millis()))
+        .execute(&mut *tx)
+        .await?;
+
+    // One extra row tells us whether the result was truncated
+    let result: Result<Vec<String>, sqlx::Error> = sqlx::query_scalar(&scoped_sql(sql, &tables, max_rows + 1))
+        .bind(tenant_id)
+        .fetch_all(&mut *tx)
+        .await;
+    // Nothing can have been written; rolling back also clears the SET LOCALs
+    tx.rollback().await?;
+
+    match result {
+        Ok(rows) => {
+            let truncated = rows.len() as i64 > max_rows;
+            let rows = rows
+                .iter()
+                .take(max_rows as usize)
+                .map(|row| serde_json::from_str(row).unwrap_or(serde_json::Value::Null))
+                .collect();
+            Ok(Outcome::Completed { rows, truncated })
+        }
+        // query_canceled: the statement timeout fired
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("57014") => Ok(Outcome::TimedOut),
+        Err(sqlx::Error::Database(e)) => Ok(Outcome::Failed(e.message().to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+/// POST /analytics/query
+pub async fn run_query(
+    analyst: Analyst,
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<QueryResult>, (StatusCode, Json<serde_json::Value>)> {
+    let sandbox = state.analytics.clone().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": "analytics sandbox is not configured"})),
+        )
+    })?;
+    let query_id = Uuid::new_v4();
+    let started = Instant::now();
+
+    let outcome = if !analyst.is_officer() {
+        Outcome::Rejected(format!("role {} may not use the analytics sandbox", analyst.role))
+    } else {
+        let max_rows = request.max_rows.unwrap_or(sandbox.max_rows).clamp(1, sandbox.max_rows);
+        match execute(&state.db, &sandbox, analyst.tenant_id, &request.sql, max_rows).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                error!("Analytics query {} failed: {}", query_id, e);
+                Outcome::Failed("query could not be executed".to_string())
+            }
+        }
+    };
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let (status, row_count, truncated, error_message) = match &outcome {
+        Outcome::Completed { rows, truncated } => ("COMPLETED", Some(rows.len() as i32), *truncated, None),
+        Outcome::Rejected(reason) => ("REJECTED", None, false, Some(reason.clone())),
+        Outcome::Failed(reason) => ("FAILED", None, false, Some(reason.clone())),
+        Outcome::TimedOut => ("TIMED_OUT", None, false, Some("statement timeout exceeded".to_string())),
+    };
+
+    // Results are only returned once the query is on record
+    if let Err(e) = record(
+        &state.db,
+        &sandbox,
+        query_id,
+        &analyst,
+        &request.sql,
+        status,
+        row_count,
+        truncated,
+        duration_ms,
+        error_message.as_deref(),
+    )
+    .await
+    {
+        error!("Failed to log analytics query {}: {}", query_id, e);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({"error": "query could not be logged", "query_id": query_id})),
+        ));
+    }
+
+    match outcome {
+        Outcome::Completed { rows, truncated } => Ok(Json(QueryResult {
+            query_id,
+            row_count: rows.len(),
+            rows,
+            truncated,
+            duration_ms,
+        })),
+        Outcome::Rejected(reason) if !analyst.is_officer() => {
+            warn!("Rejected analytics query {} from user {}: {}", query_id, analyst.user_id, reason);
+            Err((StatusCode::FORBIDDEN, Json(serde_json::json!({"error": reason, "query_id": query_id}))))
+        }
+        Outcome::Rejected(reason) => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({"error": reason, "query_id": query_id})),
+        )),
+        Outcome::Failed(reason) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": reason, "query_id": query_id})),
+        )),
+        Outcome::TimedOut => Err((
+            StatusCode::REQUEST_TIMEOUT,
+            Json(serde_json::json!({
+                "error": format!("query exceeded {}ms", sandbox.timeout.as_millis()),
+                "query_id": query_id,
+            })),
+        )),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn record(
+    db: &PgPool,
+    sandbox: &Sandbox,
+    query_id: Uuid,
+    analyst: &Analyst,
+    sql: &str,
+    status: &str,
+    row_count: Option<i32>,
+    truncated: bool,
+    duration_ms: u64,
+    error_message: Option<&str>,
+) -> anyhow::Result<()> {
+    let query_sha256 = hex::encode(Sha256::digest(sql.as_bytes()));
+    sqlx::query!(
+        r#"
+        INSERT INTO analytics_query_log
+            (query_id, tenant_id, user_id, query_text, query_sha256, status, row_count, truncated, duration_ms, error)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+        query_id,
+        analyst.tenant_id,
+        analyst.user_id,
+        sql,
+        query_sha256,
+        status,
+        row_count,
+        truncated,
+        duration_ms as i32,
+        error_message
+    )
+    .execute(db)
+    .await?;
+
+    // The local log is authoritative; the audit trail copy is best effort
+    if let Some(audit_service_url) = &sandbox.audit_service_url {
+        let event = serde_json::json!({
+            "tenant_id": analyst.tenant_id,
+            "user_id": analyst.user_id,
+            "action": "ANALYTICS_QUERY_EXECUTED",
+            "resource_type": "ANALYTICS_QUERY",
+            "resource_id": query_id,
+            "new_values": {
+                "status": status,
+                "query_sha256": query_sha256,
+                "query_text": sql,
+                "row_count": row_count,
+                "duration_ms": duration_ms,
+            },
+        });
+        let mut request = sandbox.http.post(format!("{}/audit/events", audit_service_url)).json(&event);
+        if let Some(token) = &sandbox.audit_service_token {
+            request = request.bearer_auth(token);
+        }
+        tokio::spawn(async move {
+            match request.send().await.and_then(|response| response.error_for_status()) {
+                Ok(_) => {}
+                Err(e) => warn!("Failed to send analytics query {} to the audit service: {}", query_id, e),
+            }
+        });
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct QueryLogParams {
+    pub limit: Option<i64>,
+}
+
+/// GET /analytics/queries: the tenant's query log, newest first
+pub async fn list_queries(
+    analyst: Analyst,
+    State(state): State<AppState>,
+    Query(params): Query<QueryLogParams>,
+) -> Result<Json<Vec<QueryLogEntry>>, StatusCode> {
+    if !analyst.is_officer() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+
+    match load_query_log(&state.db, analyst.tenant_id, limit).await {
+        Ok(entries) => Ok(Json(entries)),
+        Err(e) => {
+            error!("Failed to load analytics query log for tenant {}: {}", analyst.tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn load_query_log(db: &PgPool, tenant_id: Uuid, limit: i64) -> anyhow::Result<Vec<QueryLogEntry>> {
+    Ok(sqlx::query_as!(
+        QueryLogEntry,
+        r#"
+        SELECT query_id, user_id, query_text, status, row_count, truncated, duration_ms, error, executed_at
+        FROM analytics_query_log
+        WHERE tenant_id = $1
+        ORDER BY executed_at DESC
+        LIMIT $2
+        "#,
+        tenant_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?)
+}
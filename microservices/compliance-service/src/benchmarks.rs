@@ -0,0 +1,374 @@
+//! Differentially private industry benchmarks
+//!
+//! Tenants opt in to pool their alert statistics into platform benchmarks:
+//! alerts per 1,000 trades and mean hours to resolve an alert. A month's
+//! benchmark is the mean of per-tenant values clipped to a fixed range, plus
+//! Laplace noise of scale `cap / (n * epsilon)`, so any one tenant's data can
+//! move the released number only within the privacy budget. A benchmark is
+//! withheld until BENCHMARK_MIN_TENANTS tenants contribute, participant counts
+//! are shown rounded down to a band of five, and each metric and month is
+//! released once and then served from `benchmark_releases`, so asking again
+//! cannot average the noise away. Only consenting tenants see benchmarks,
+//! next to their own unperturbed value.
+//!
+//! Every released metric spends BENCHMARK_EPSILON of a month's budget, so a
+//! month costs each participant `epsilon` times the number of metrics.
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Participant counts are disclosed rounded down to a multiple of this
+const PARTICIPANT_BAND: i32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct Benchmarks {
+    epsilon: f64,
+    min_tenants: usize,
+    alert_rate_cap: f64,
+    resolution_hours_cap: f64,
+}
+
+impl Benchmarks {
+    /// Built from BENCHMARK_*; `None` unless BENCHMARKS_ENABLED=true
+    pub fn from_env() -> Option<Self> {
+        if !std::env::var("BENCHMARKS_ENABLED").is_ok_and(|value| value == "true") {
+            return None;
+        }
+        let number = |name: &str, default: f64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|value| value.is_finite() && *value > 0.0)
+                .unwrap_or(default)
+        };
+        Some(Self {
+            epsilon: number("BENCHMARK_EPSILON", 1.0),
+            // Below one participant band any aggregate is close to a disclosure
+            min_tenants: (number("BENCHMARK_MIN_TENANTS", 10.0) as usize).max(PARTICIPANT_BAND as usize),
+            alert_rate_cap: number("BENCHMARK_ALERT_RATE_CAP", 50.0),
+            resolution_hours_cap: number("BENCHMARK_RESOLUTION_HOURS_CAP", 720.0),
+        })
+    }
+
+    fn cap(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::AlertRate => self.alert_rate_cap,
+            Metric::ResolutionHours => self.resolution_hours_cap,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Metric {
+    /// Alerts raised per 1,000 trades
+    AlertRate,
+    /// Mean hours from alert creation to resolution, excluding auto-closed alerts
+    ResolutionHours,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 2] = [Metric::AlertRate, Metric::ResolutionHours];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Metric::AlertRate => "ALERT_RATE",
+            Metric::ResolutionHours => "RESOLUTION_HOURS",
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            Metric::AlertRate => "alerts per 1,000 trades",
+            Metric::ResolutionHours => "hours",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Consent {
+    pub tenant_id: Uuid,
+    pub opted_in: bool,
+    pub consented_by: Option<Uuid>,
+    pub opted_in_at: Option<DateTime<Utc>>,
+    pub withdrawn_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+pub struct ConsentRequest {
+    pub opted_in: bool,
+    pub user_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct BenchmarkParams {
+    /// Calendar month as YYYY-MM; only completed months are released
+    pub month: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchmarkView {
+    pub metric: Metric,
+    pub unit: &'static str,
+    pub month: NaiveDate,
+    /// RELEASED, or WITHHELD while too few tenants contribute
+    pub status: &'static str,
+    pub benchmark: Option<f64>,
+    pub participants_at_least: Option<i32>,
+    pub epsilon: Option<f64>,
+    pub released_at: Option<DateTime<Utc>>,
+    /// The caller's own value for the month; never part of another tenant's view
+    pub your_value: Option<f64>,
+}
+
+struct Release {
+    value: f64,
+    contributors: i32,
+    epsilon: f64,
+    released_at: DateTime<Utc>,
+}
+
+/// First day of a completed month given as YYYY-MM
+pub fn parse_month(month: &str, today: NaiveDate) -> Result<NaiveDate, String> {
+    let first = NaiveDate::parse_from_str(&format!("{}-01", month.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("month '{}' is not YYYY-MM", month))?;
+    let current = today.with_day(1).expect("the first of the month exists");
+    if first >= current {
+        return Err(format!("month {} has not finished yet", month));
+    }
+    Ok(first)
+}
+
+pub async fn consent(db: &PgPool, tenant: &TenantContext) -> anyhow::Result<Consent> {
+    let tenant_id = tenant.tenant_id();
+    let row = tenant_query!(
+        tenant,
+        "SELECT consented_by, opted_in_at, withdrawn_at FROM benchmark_consents WHERE tenant_id = $1"
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(match row {
+        Some(row) => Consent {
+            tenant_id,
+            opted_in: row.withdrawn_at.is_none(),
+            consented_by: row.consented_by,
+            opted_in_at: Some(row.opted_in_at),
+            withdrawn_at: row.withdrawn_at,
+        },
+        None => Consent {
+            tenant_id,
+            opted_in: false,
+            consented_by: None,
+            opted_in_at: None,
+            withdrawn_at: None,
+        },
+    })
+}
+
+/// Opt in or withdraw; benchmarks already released are not recomputed
+pub async fn set_consent(db: &PgPool, tenant: &TenantContext, request: &ConsentRequest) -> anyhow::Result<Consent> {
+    if request.opted_in {
+        tenant_query!(
+            tenant,
+            r#"
+            INSERT INTO benchmark_consents (tenant_id, consented_by)
+            VALUES ($1, $2)
+            ON CONFLICT (tenant_id) DO UPDATE
+            SET consented_by = EXCLUDED.consented_by, opted_in_at = NOW(), withdrawn_at = NULL, updated_at = NOW()
+            WHERE benchmark_consents.withdrawn_at IS NOT NULL
+            "#,
+            request.user_id
+        )
+        .execute(db)
+        .await?;
+    } else {
+        tenant_query!(
+            tenant,
+            r#"
+            UPDATE benchmark_consents SET withdrawn_at = NOW(), updated_at = NOW()
+            WHERE tenant_id = $1 AND withdrawn_at IS NULL
+            "#
+        )
+        .execute(db)
+        .await?;
+    }
+    consent(db, tenant).await
+}
+
+/// Per-tenant values of consenting tenants for the month, or only `tenant_id`'s
+async fn tenant_values(
+    db: &PgPool,
+    metric: Metric,
+    month: NaiveDate,
+    tenant_id: Option<Uuid>,
+) -> anyhow::Result<Vec<f64>> {
+    let from = month.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc();
+    let to = (month + Months::new(1)).and_hms_opt(0, 0, 0).expect("midnight exists").and_utc();
+
+    let values = match metric {
+        Metric::AlertRate => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT (alerts.count * 1000.0 / trades.count)::float8 AS "value!"
+                FROM benchmark_consents c
+                CROSS JOIN LATERAL (
+                    SELECT COUNT(*)::float8 AS count FROM trades
+                    WHERE tenant_id = c.tenant_id AND trade_time >= $1 AND trade_time < $2
+                ) trades
+                CROSS JOIN LATERAL (
+                    SELECT COUNT(*)::float8 AS count FROM surveillance_alerts
+                    WHERE tenant_id = c.tenant_id AND created_at >= $1 AND created_at < $2
+                ) alerts
+                WHERE c.withdrawn_at IS NULL AND trades.count > 0
+                  AND ($3::uuid IS NULL OR c.tenant_id = $3)
+                "#,
+                from,
+                to,
+                tenant_id
+            )
+            .fetch_all(db)
+            .await?
+        }
+        Metric::ResolutionHours => {
+            sqlx::query_scalar!(
+                r#"
+                SELECT AVG(EXTRACT(EPOCH FROM (a.resolved_at - a.created_at)) / 3600.0)::float8 AS "value!"
+                FROM surveillance_alerts a
+                JOIN benchmark_consents c ON c.tenant_id = a.tenant_id AND c.withdrawn_at IS NULL
+                WHERE a.resolved_at >= $1 AND a.resolved_at < $2
+                  AND a.created_at IS NOT NULL AND a.auto_closed_by_rule IS NULL
+                  AND ($3::uuid IS NULL OR a.tenant_id = $3)
+                GROUP BY a.tenant_id
+                "#,
+                from,
+                to,
+                tenant_id
+            )
+            .fetch_all(db)
+            .await?
+        }
+    };
+    Ok(values)
+}
+
+async fn stored_release(db: &PgPool, metric: Metric, month: NaiveDate) -> anyhow::Result<Option<Release>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT value, contributors, epsilon, released_at
+        FROM benchmark_releases
+        WHERE metric = $1 AND period_month = $2
+        "#,
+        metric.as_str(),
+        month
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(|row| Release {
+        value: row.value,
+        contributors: row.contributors,
+        epsilon: row.epsilon,
+        released_at: row.released_at,
+    }))
+}
+
+/// Draw from Laplace(0, scale) by inverting its CDF
+fn laplace(scale: f64) -> f64 {
+    let mut rng = rand::thread_rng();
+    loop {
+        let u: f64 = rng.gen::<f64>() - 0.5;
+        let tail = 1.0 - 2.0 * u.abs();
+        if tail > 0.0 {
+            return -scale * u.signum() * tail.ln();
+        }
+    }
+}
+
+/// The month's release of the metric, computing and storing it on first request
+async fn release(
+    benchmarks: &Benchmarks,
+    db: &PgPool,
+    metric: Metric,
+    month: NaiveDate,
+) -> anyhow::Result<Option<Release>> {
+    if let Some(release) = stored_release(db, metric, month).await? {
+        return Ok(Some(release));
+    }
+
+    let values = tenant_values(db, metric, month, None).await?;
+    if values.len() < benchmarks.min_tenants {
+        return Ok(None);
+    }
+
+    // Clipping bounds each tenant's influence on the mean to cap / n
+    let cap = benchmarks.cap(metric);
+    let n = values.len() as f64;
+    let mean = values.iter().map(|value| value.clamp(0.0, cap)).sum::<f64>() / n;
+    let noisy = (mean + laplace(cap / (n * benchmarks.epsilon))).clamp(0.0, cap);
+    let value = (noisy * 100.0).round() / 100.0;
+
+    // A concurrent request may have released it first; both then serve that one
+    sqlx::query!(
+        r#"
+        INSERT INTO benchmark_releases (metric, period_month, value, contributors, epsilon, clip_upper)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (metric, period_month) DO NOTHING
+        "#,
+        metric.as_str(),
+        month,
+        value,
+        values.len() as i32,
+        benchmarks.epsilon,
+        cap
+    )
+    .execute(db)
+    .await?;
+    stored_release(db, metric, month).await
+}
+
+/// Every metric's benchmark for the month, as seen by a participating tenant
+pub async fn benchmarks_for(
+    benchmarks: &Benchmarks,
+    db: &PgPool,
+    tenant: &TenantContext,
+    month: NaiveDate,
+) -> anyhow::Result<Vec<BenchmarkView>> {
+    let mut views = Vec::with_capacity(Metric::ALL.len());
+    for metric in Metric::ALL {
+        let release = release(benchmarks, db, metric, month).await?;
+        let your_value = tenant_values(db, metric, month, Some(tenant.tenant_id()))
+            .await?
+            .pop()
+            .map(|value| (value * 100.0).round() / 100.0);
+        views.push(match release {
+            Some(release) => BenchmarkView {
+                metric,
+                unit: metric.unit(),
+                month,
+                status: "RELEASED",
+                benchmark: Some(release.value),
+                participants_at_least: Some(release.contributors / PARTICIPANT_BAND * PARTICIPANT_BAND),
+                epsilon: Some(release.epsilon),
+                released_at: Some(release.released_at),
+                your_value,
+            },
+            None => BenchmarkView {
+                metric,
+                unit: metric.unit(),
+                month,
+                status: "WITHHELD",
+                benchmark: None,
+                participants_at_least: None,
+                epsilon: None,
+                released_at: None,
+                your_value,
+            },
+        });
+    }
+    Ok(views)
+}
@@ -0,0 +1,508 @@
+//! Pre-filing validation of UCC client master bulk files
+//!
+//! Brokers register every client with the exchanges under a Unique Client
+//! Code (UCC) before trading, uploading the client master in bulk. The
+//! exchanges reject rows whose PAN is malformed, does not fit the client
+//! category, or is already mapped to a different UCC of the member. This
+//! module runs the same checks on the CSV before it is filed and reports every
+//! problem with its line number.
+//!
+//! Columns are matched like exchange files (case and punctuation ignored):
+//! `UCC`, `CLIENT_NAME`, `CATEGORY` and `PAN` are required; `PAN_EXEMPT`
+//! (`Y` for exempt clients such as Sikkim residents and government bodies),
+//! `DOB` (date of birth or incorporation), `EMAIL` and `MOBILE` are optional.
+//! Errors would be rejected by the exchange; warnings are worth a look but do
+//! not stop the row.
+
+use chrono::{Datelike, NaiveDate, Utc};
+use csv::StringRecord;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::ingestion::formats::normalise_header;
+
+/// Upload limit; exchange bulk files are capped well below this
+pub const MAX_FILE_BYTES: usize = 50 * 1024 * 1024;
+/// Problems listed in a report before the rest are only counted
+const MAX_ISSUES: usize = 5000;
+const MAX_UCC_LENGTH: usize = 10;
+const MAX_NAME_LENGTH: usize = 100;
+
+/// An exchange client category and the PAN holder types it accepts
+struct ClientCategory {
+    code: &'static str,
+    label: &'static str,
+    /// Fourth PAN character: P person, C company, H HUF, F firm, A AOP, B BOI,
+    /// T trust, L local authority, J artificial juridical person, G government
+    pan_holder_types: &'static [char],
+    natural_person: bool,
+    pan_exemption_allowed: bool,
+}
+
+const CATEGORIES: &[ClientCategory] = &[
+    ClientCategory { code: "1", label: "Individual", pan_holder_types: &['P'], natural_person: true, pan_exemption_allowed: true },
+    ClientCategory { code: "2", label: "Partnership firm", pan_holder_types: &['F'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "3", label: "Hindu undivided family", pan_holder_types: &['H'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "4", label: "Public and private company / body corporate", pan_holder_types: &['C'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "5", label: "Trust / society", pan_holder_types: &['T', 'A'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "6", label: "Mutual fund", pan_holder_types: &['T', 'C'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "7", label: "Domestic financial institution", pan_holder_types: &['C', 'J', 'G'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "8", label: "Bank", pan_holder_types: &['C', 'J', 'G'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "9", label: "Insurance company", pan_holder_types: &['C', 'J'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "10", label: "Statutory body / government", pan_holder_types: &['J', 'G', 'L'], natural_person: false, pan_exemption_allowed: true },
+    ClientCategory { code: "11", label: "Non-resident Indian", pan_holder_types: &['P'], natural_person: true, pan_exemption_allowed: false },
+    ClientCategory { code: "12", label: "Foreign portfolio investor", pan_holder_types: &['C', 'F', 'T', 'A', 'P'], natural_person: false, pan_exemption_allowed: false },
+    ClientCategory { code: "13", label: "Association of persons / body of individuals", pan_holder_types: &['A', 'B'], natural_person: false, pan_exemption_allowed: false },
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Column {
+    Ucc,
+    Name,
+    Category,
+    Pan,
+    PanExempt,
+    Dob,
+    Email,
+    Mobile,
+}
+
+impl Column {
+    fn name(self) -> &'static str {
+        match self {
+            Column::Ucc => "UCC",
+            Column::Name => "CLIENT_NAME",
+            Column::Category => "CATEGORY",
+            Column::Pan => "PAN",
+            Column::PanExempt => "PAN_EXEMPT",
+            Column::Dob => "DOB",
+            Column::Email => "EMAIL",
+            Column::Mobile => "MOBILE",
+        }
+    }
+
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Column::Ucc => &["UCC", "UCCCODE", "CLIENTCODE"],
+            Column::Name => &["CLIENTNAME", "NAME"],
+            Column::Category => &["CATEGORY", "CLIENTCATEGORY", "CATEGORYCODE"],
+            Column::Pan => &["PAN", "PANNO", "PANNUMBER"],
+            Column::PanExempt => &["PANEXEMPT", "PANEXEMPTION", "PANEXEMPTFLAG"],
+            Column::Dob => &["DOB", "DATEOFBIRTH", "DOI", "DATEOFINCORPORATION"],
+            Column::Email => &["EMAIL", "EMAILID"],
+            Column::Mobile => &["MOBILE", "MOBILENO", "MOBILENUMBER"],
+        }
+    }
+}
+
+const REQUIRED: &[Column] = &[Column::Ucc, Column::Name, Column::Category, Column::Pan];
+const OPTIONAL: &[Column] = &[Column::PanExempt, Column::Dob, Column::Email, Column::Mobile];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum IssueSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RowIssue {
+    /// 1-based line in the file, the header being line 1
+    pub line_number: usize,
+    pub ucc: Option<String>,
+    pub field: &'static str,
+    pub code: &'static str,
+    pub severity: IssueSeverity,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ClientMasterReport {
+    /// VALID when no row has an error
+    pub status: String,
+    pub rows_total: usize,
+    pub rows_valid: usize,
+    pub rows_rejected: usize,
+    pub warnings: usize,
+    pub issues: Vec<RowIssue>,
+    /// More issues were found than are listed
+    pub truncated: bool,
+}
+
+/// Client codes and PANs the tenant has already registered
+struct Registered {
+    pan_by_ucc: HashMap<String, Option<String>>,
+    ucc_by_pan: HashMap<String, String>,
+}
+
+async fn registered(db: &PgPool, tenant_id: Uuid) -> anyhow::Result<Registered> {
+    let rows = sqlx::query!(
+        "SELECT client_code, pan FROM clients WHERE tenant_id = $1",
+        tenant_id
+    )
+    .fetch_all(db)
+    .await?;
+    let mut registered = Registered {
+        pan_by_ucc: HashMap::with_capacity(rows.len()),
+        ucc_by_pan: HashMap::with_capacity(rows.len()),
+    };
+    for row in rows {
+        let ucc = row.client_code.to_ascii_uppercase();
+        if let Some(pan) = &row.pan {
+            registered.ucc_by_pan.insert(pan.clone(), ucc.clone());
+        }
+        registered.pan_by_ucc.insert(ucc, row.pan);
+    }
+    Ok(registered)
+}
+
+fn is_pan(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() == 10
+        && bytes[..5].iter().all(u8::is_ascii_uppercase)
+        && bytes[5..9].iter().all(u8::is_ascii_digit)
+        && bytes[9].is_ascii_uppercase()
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    ["%d-%m-%Y", "%d/%m/%Y", "%Y-%m-%d", "%d-%b-%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+}
+
+fn is_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !value.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+/// Ten-digit Indian mobile number, optionally prefixed with +91, 91 or 0
+fn is_mobile(value: &str) -> bool {
+    let digits: String = value.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+    let number = digits
+        .strip_prefix("+91")
+        .or_else(|| digits.strip_prefix("91").filter(|rest| rest.len() == 10))
+        .or_else(|| digits.strip_prefix('0').filter(|rest| rest.len() == 10))
+        .unwrap_or(&digits);
+    number.len() == 10
+        && number.bytes().all(|b| b.is_ascii_digit())
+        && matches!(number.as_bytes()[0], b'6'..=b'9')
+}
+
+fn age_on(dob: NaiveDate, today: NaiveDate) -> i32 {
+    let mut age = today.year() - dob.year();
+    if (today.month(), today.day()) < (dob.month(), dob.day()) {
+        age -= 1;
+    }
+    age
+}
+
+struct Validator<'a> {
+    columns: HashMap<Column, usize>,
+    registered: &'a Registered,
+    today: NaiveDate,
+    issues: Vec<RowIssue>,
+    issue_count: usize,
+    /// First line each UCC and PAN appeared on in this file
+    seen_ucc: HashMap<String, usize>,
+    seen_pan: HashMap<String, (String, usize)>,
+}
+
+impl<'a> Validator<'a> {
+    fn push(&mut self, issue: RowIssue) {
+        self.issue_count += 1;
+        if self.issues.len() < MAX_ISSUES {
+            self.issues.push(issue);
+        }
+    }
+
+    fn value<'r>(&self, row: &'r StringRecord, column: Column) -> Option<&'r str> {
+        self.columns
+            .get(&column)
+            .and_then(|&index| row.get(index))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Validate one row; returns whether it has no errors
+    fn check(&mut self, line_number: usize, row: &StringRecord) -> bool {
+        use IssueSeverity::{Error, Warning};
+        let registered = self.registered;
+        let ucc = self.value(row, Column::Ucc).map(str::to_ascii_uppercase);
+        let mut errors = 0;
+        let mut issue = |validator: &mut Self, column: Column, code: &'static str, severity: IssueSeverity, message: String| {
+            if severity == IssueSeverity::Error {
+                errors += 1;
+            }
+            validator.push(RowIssue {
+                line_number,
+                ucc: ucc.clone(),
+                field: column.name(),
+                code,
+                severity,
+                message,
+            });
+        };
+
+        match &ucc {
+            None => issue(self, Column::Ucc, "MISSING", Error, "UCC is required".to_string()),
+            Some(code) if code.len() > MAX_UCC_LENGTH || !code.bytes().all(|b| b.is_ascii_alphanumeric()) => issue(
+                self,
+                Column::Ucc,
+                "INVALID_UCC",
+                Error,
+                format!("UCC must be 1 to {} letters and digits", MAX_UCC_LENGTH),
+            ),
+            Some(code) => {
+                if let Some(&first) = self.seen_ucc.get(code) {
+                    issue(self, Column::Ucc, "DUPLICATE_UCC", Error, format!("UCC {} already appears on line {}", code, first));
+                } else {
+                    self.seen_ucc.insert(code.clone(), line_number);
+                }
+            }
+        }
+
+        match self.value(row, Column::Name) {
+            None => issue(self, Column::Name, "MISSING", Error, "client name is required".to_string()),
+            Some(name) if name.chars().count() > MAX_NAME_LENGTH => issue(
+                self,
+                Column::Name,
+                "NAME_TOO_LONG",
+                Error,
+                format!("client name is longer than {} characters", MAX_NAME_LENGTH),
+            ),
+            Some(_) => {}
+        }
+
+        let category = match self.value(row, Column::Category) {
+            None => {
+                issue(self, Column::Category, "MISSING", Error, "client category is required".to_string());
+                None
+            }
+            Some(value) => {
+                // Exchanges accept the codes with or without leading zeros
+                let code = value.trim_start_matches('0');
+                let category = CATEGORIES.iter().find(|category| category.code == code);
+                if category.is_none() {
+                    issue(self, Column::Category, "UNKNOWN_CATEGORY", Error, format!("unknown client category {}", value));
+                }
+                category
+            }
+        };
+
+        let pan_exempt = self
+            .value(row, Column::PanExempt)
+            .is_some_and(|flag| matches!(flag.to_ascii_uppercase().as_str(), "Y" | "YES" | "TRUE" | "1"));
+        let pan = self.value(row, Column::Pan).map(str::to_ascii_uppercase);
+        match &pan {
+            None if pan_exempt => {
+                if category.is_some_and(|category| !category.pan_exemption_allowed) {
+                    issue(
+                        self,
+                        Column::PanExempt,
+                        "PAN_EXEMPTION_NOT_ALLOWED",
+                        Error,
+                        format!("{} clients cannot be PAN exempt", category.map_or("", |c| c.label)),
+                    );
+                }
+            }
+            None => issue(self, Column::Pan, "MISSING", Error, "PAN is required unless the client is PAN exempt".to_string()),
+            Some(value) if !is_pan(value) => issue(
+                self,
+                Column::Pan,
+                "INVALID_PAN",
+                Error,
+                format!("{} is not a PAN (five letters, four digits, one letter)", value),
+            ),
+            Some(value) => {
+                let holder_type = value.as_bytes()[3] as char;
+                if let Some(category) = category {
+                    if !category.pan_holder_types.contains(&holder_type) {
+                        issue(
+                            self,
+                            Column::Pan,
+                            "PAN_CATEGORY_MISMATCH",
+                            Error,
+                            format!("PAN holder type {} does not fit category {} ({})", holder_type, category.code, category.label),
+                        );
+                    }
+                    // For individuals the fifth character is the initial of the surname
+                    if category.natural_person && holder_type == 'P' {
+                        let initial = value.as_bytes()[4] as char;
+                        let matches_name = self.value(row, Column::Name).is_some_and(|name| {
+                            name.split_whitespace()
+                                .filter_map(|part| part.chars().next())
+                                .any(|c| c.to_ascii_uppercase() == initial)
+                        });
+                        if !matches_name {
+                            issue(
+                                self,
+                                Column::Pan,
+                                "PAN_NAME_MISMATCH",
+                                Warning,
+                                format!("PAN initial {} does not match any part of the client name", initial),
+                            );
+                        }
+                    }
+                }
+
+                if let Some((other, first)) = self.seen_pan.get(value).cloned() {
+                    if ucc.as_deref() != Some(other.as_str()) {
+                        issue(
+                            self,
+                            Column::Pan,
+                            "DUPLICATE_PAN",
+                            Error,
+                            format!("PAN {} is already used by UCC {} on line {}", value, other, first),
+                        );
+                    }
+                } else if let Some(code) = &ucc {
+                    self.seen_pan.insert(value.clone(), (code.clone(), line_number));
+                }
+
+                if let Some(registered) = registered.ucc_by_pan.get(value) {
+                    if ucc.as_deref() != Some(registered.as_str()) {
+                        issue(
+                            self,
+                            Column::Pan,
+                            "PAN_MAPPED_TO_OTHER_UCC",
+                            Error,
+                            format!("PAN {} is registered to UCC {}", value, registered),
+                        );
+                    }
+                }
+            }
+        }
+
+        // An existing UCC may only be re-filed with the PAN it was registered with
+        if let Some(Some(registered_pan)) = ucc.as_ref().and_then(|code| registered.pan_by_ucc.get(code)) {
+            if pan.as_ref().is_some_and(|pan| pan != registered_pan) {
+                issue(
+                    self,
+                    Column::Pan,
+                    "UCC_PAN_MISMATCH",
+                    Error,
+                    format!("UCC is registered with PAN {}", registered_pan),
+                );
+            }
+        }
+
+        if let Some(value) = self.value(row, Column::Dob) {
+            match parse_date(value) {
+                None => issue(self, Column::Dob, "INVALID_DATE", Error, format!("{} is not a DD-MM-YYYY date", value)),
+                Some(date) if date > self.today => issue(self, Column::Dob, "FUTURE_DATE", Error, "date is in the future".to_string()),
+                Some(date) if category.is_some_and(|category| category.natural_person) && age_on(date, self.today) < 18 => issue(
+                    self,
+                    Column::Dob,
+                    "MINOR",
+                    Warning,
+                    "client is a minor; guardian details must accompany the registration".to_string(),
+                ),
+                Some(_) => {}
+            }
+        } else if category.is_some_and(|category| category.natural_person) {
+            issue(self, Column::Dob, "MISSING", Warning, "date of birth is missing for an individual".to_string());
+        }
+
+        if let Some(value) = self.value(row, Column::Email) {
+            if !is_email(value) {
+                issue(self, Column::Email, "INVALID_EMAIL", Error, format!("{} is not an email address", value));
+            }
+        }
+        if let Some(value) = self.value(row, Column::Mobile) {
+            if !is_mobile(value) {
+                issue(self, Column::Mobile, "INVALID_MOBILE", Error, format!("{} is not a 10-digit Indian mobile number", value));
+            }
+        }
+
+        errors == 0
+    }
+}
+
+/// Validate a client master CSV; `Err` when the file as a whole is unusable
+pub async fn validate(db: &PgPool, tenant_id: Uuid, contents: &[u8]) -> anyhow::Result<Result<ClientMasterReport, Vec<String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(contents);
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return Ok(Err(vec![format!("unreadable header row: {}", e)])),
+    };
+
+    let normalised: Vec<String> = headers.iter().map(normalise_header).collect();
+    let mut columns = HashMap::new();
+    let mut missing = Vec::new();
+    for &column in REQUIRED.iter().chain(OPTIONAL) {
+        match normalised.iter().position(|header| column.aliases().contains(&header.as_str())) {
+            Some(index) => {
+                columns.insert(column, index);
+            }
+            None if REQUIRED.contains(&column) => missing.push(format!("missing required column {}", column.name())),
+            None => {}
+        }
+    }
+    if !missing.is_empty() {
+        return Ok(Err(missing));
+    }
+
+    let registered = registered(db, tenant_id).await?;
+    let mut validator = Validator {
+        columns,
+        registered: &registered,
+        today: Utc::now().date_naive(),
+        issues: Vec::new(),
+        issue_count: 0,
+        seen_ucc: HashMap::new(),
+        seen_pan: HashMap::new(),
+    };
+
+    let (mut rows_total, mut rows_valid) = (0, 0);
+    for (index, row) in reader.records().enumerate() {
+        let line_number = index + 2;
+        rows_total += 1;
+        match row {
+            Ok(row) => {
+                if validator.check(line_number, &row) {
+                    rows_valid += 1;
+                }
+            }
+            Err(e) => validator.push(RowIssue {
+                line_number,
+                ucc: None,
+                field: "ROW",
+                code: "UNREADABLE_ROW",
+                severity: IssueSeverity::Error,
+                message: e.to_string(),
+            }),
+        }
+    }
+    if rows_total == 0 {
+        return Ok(Err(vec!["the file has no client rows".to_string()]));
+    }
+
+    let warnings = validator
+        .issues
+        .iter()
+        .filter(|issue| issue.severity == IssueSeverity::Warning)
+        .count();
+    Ok(Ok(ClientMasterReport {
+        status: if rows_valid == rows_total { "VALID" } else { "REJECTED" }.to_string(),
+        rows_total,
+        rows_valid,
+        rows_rejected: rows_total - rows_valid,
+        warnings,
+        truncated: validator.issue_count > validator.issues.len(),
+        issues: validator.issues,
+    }))
+}
@@ -0,0 +1,398 @@
+//! Case and violation evidence: uploads, text extraction and search
+//!
+//! Uploaded files are kept as-is in the evidence store and queued for
+//! extraction. A worker claims queued attachments, reads the text layer of
+//! PDFs directly and hands images, and PDFs without a text layer, to the
+//! configured OCR backend. The text is indexed together with the file name,
+//! and the violation and case search endpoints match it alongside the
+//! records' own text. Failed extractions are retried with backoff; files no
+//! backend can read are marked UNSUPPORTED but remain searchable by name.
+
+pub mod ocr;
+pub mod search;
+pub mod store;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use self::ocr::OcrBackend;
+use self::store::EvidenceStore;
+
+pub const IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/tiff"];
+
+/// Failed extractions of one attachment before it is marked FAILED
+const MAX_ATTEMPTS: i32 = 5;
+/// First retry delay, doubled on every further attempt
+const RETRY_BASE_SECS: f64 = 60.0;
+/// A PDF with less text than this is treated as scanned and sent to OCR
+const MIN_TEXT_LAYER_CHARS: usize = 32;
+/// Extracted text beyond this is dropped; a tsvector is limited to 1MB
+const MAX_TEXT_CHARS: usize = 500_000;
+
+/// The violation or case (alert investigation) an attachment belongs to
+#[derive(Debug, Clone, Copy)]
+pub enum EvidenceTarget {
+    Violation(Uuid),
+    Case(Uuid),
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Attachment {
+    pub attachment_id: Uuid,
+    pub tenant_id: Uuid,
+    pub violation_id: Option<Uuid>,
+    pub investigation_id: Option<Uuid>,
+    pub file_name: String,
+    pub content_type: String,
+    pub byte_size: i64,
+    pub sha256: String,
+    pub uploaded_by: Option<Uuid>,
+    /// PENDING, PROCESSING, EXTRACTED, FAILED or UNSUPPORTED
+    pub extraction_status: String,
+    /// PDF_TEXT, PLAIN_TEXT or OCR:<backend>
+    pub extraction_method: Option<String>,
+    pub text_length: Option<i32>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub extracted_at: Option<DateTime<Utc>>,
+}
+
+pub struct Upload {
+    pub file_name: String,
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+    pub uploaded_by: Option<Uuid>,
+}
+
+pub struct Evidence {
+    pub db: PgPool,
+    pub store: Arc<dyn EvidenceStore>,
+    pub ocr: Option<Arc<dyn OcrBackend>>,
+    /// Largest accepted upload
+    pub max_bytes: usize,
+}
+
+/// Content type of a supported file, from its leading bytes
+///
+/// The declared type is only trusted for plain text, which has no signature.
+pub fn detect_content_type(bytes: &[u8], declared: Option<&str>) -> Option<&'static str> {
+    if bytes.starts_with(b"%PDF-") {
+        Some("application/pdf")
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        Some("image/tiff")
+    } else if declared.is_some_and(|declared| declared.starts_with("text/plain")) && std::str::from_utf8(bytes).is_ok() {
+        Some("text/plain")
+    } else {
+        None
+    }
+}
+
+impl Evidence {
+    pub async fn from_env(db: PgPool) -> anyhow::Result<Option<Self>> {
+        let uri = match std::env::var("EVIDENCE_STORE") {
+            Ok(uri) if !uri.is_empty() => uri,
+            _ => return Ok(None),
+        };
+        Ok(Some(Self {
+            db,
+            store: Arc::from(store::from_uri(&uri).await?),
+            ocr: ocr::from_env()?,
+            max_bytes: std::env::var("EVIDENCE_MAX_BYTES")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(25 * 1024 * 1024),
+        }))
+    }
+
+    /// Whether the violation or case exists in the tenant
+    pub async fn target_exists(&self, tenant_id: Uuid, target: EvidenceTarget) -> anyhow::Result<bool> {
+        let exists = match target {
+            EvidenceTarget::Violation(violation_id) => sqlx::query_scalar!(
+                r#"SELECT EXISTS(SELECT 1 FROM compliance_violations WHERE violation_id = $1 AND tenant_id = $2) AS "exists!""#,
+                violation_id,
+                tenant_id
+            )
+            .fetch_one(&self.db)
+            .await?,
+            // Cases are tenant-scoped through the alert they investigate
+            EvidenceTarget::Case(investigation_id) => sqlx::query_scalar!(
+                r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM alert_investigations i
+                    JOIN surveillance_alerts a ON a.alert_id = i.alert_id
+                    WHERE i.investigation_id = $1 AND a.tenant_id = $2
+                ) AS "exists!"
+                "#,
+                investigation_id,
+                tenant_id
+            )
+            .fetch_one(&self.db)
+            .await?,
+        };
+        Ok(exists)
+    }
+
+    /// Store the file and queue it for extraction
+    pub async fn attach(&self, tenant_id: Uuid, target: EvidenceTarget, upload: Upload) -> anyhow::Result<Attachment> {
+        let attachment_id = Uuid::new_v4();
+        let storage_key = format!("{}/{}", tenant_id, attachment_id);
+        let sha256 = hex::encode(Sha256::digest(&upload.bytes));
+        let byte_size = upload.bytes.len() as i64;
+        let (violation_id, investigation_id) = match target {
+            EvidenceTarget::Violation(id) => (Some(id), None),
+            EvidenceTarget::Case(id) => (None, Some(id)),
+        };
+
+        self.store
+            .put(&storage_key, upload.bytes, &upload.content_type)
+            .await
+            .with_context(|| format!("failed to store evidence in {}", self.store.uri()))?;
+
+        let attachment = sqlx::query_as!(
+            Attachment,
+            r#"
+            INSERT INTO evidence_attachments (
+                attachment_id, tenant_id, violation_id, investigation_id, file_name, content_type,
+                byte_size, sha256, storage_key, uploaded_by
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING attachment_id, tenant_id, violation_id, investigation_id, file_name, content_type,
+                      byte_size, sha256, uploaded_by, extraction_status, extraction_method,
+                      length(extracted_text) AS text_length, attempts, last_error, created_at, extracted_at
+            "#,
+            attachment_id,
+            tenant_id,
+            violation_id,
+            investigation_id,
+            upload.file_name,
+            upload.content_type,
+            byte_size,
+            sha256,
+            storage_key,
+            upload.uploaded_by
+        )
+        .fetch_one(&self.db)
+        .await?;
+
+        info!(
+            "Attached evidence {} ({}, {} bytes) to {:?} for tenant {}",
+            attachment_id, attachment.content_type, byte_size, target, tenant_id
+        );
+        Ok(attachment)
+    }
+}
+
+pub async fn list_attachments(db: &PgPool, tenant_id: Uuid, target: EvidenceTarget) -> anyhow::Result<Vec<Attachment>> {
+    let (violation_id, investigation_id) = match target {
+        EvidenceTarget::Violation(id) => (Some(id), None),
+        EvidenceTarget::Case(id) => (None, Some(id)),
+    };
+    Ok(sqlx::query_as!(
+        Attachment,
+        r#"
+        SELECT attachment_id, tenant_id, violation_id, investigation_id, file_name, content_type,
+               byte_size, sha256, uploaded_by, extraction_status, extraction_method,
+               length(extracted_text) AS text_length, attempts, last_error, created_at, extracted_at
+        FROM evidence_attachments
+        WHERE tenant_id = $1
+          AND (violation_id = $2 OR investigation_id = $3)
+        ORDER BY created_at DESC
+        "#,
+        tenant_id,
+        violation_id,
+        investigation_id
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+/// Extracted text of one attachment, for reviewers checking what was indexed
+pub async fn extracted_text(db: &PgPool, tenant_id: Uuid, attachment_id: Uuid) -> anyhow::Result<Option<Option<String>>> {
+    Ok(sqlx::query_scalar!(
+        "SELECT extracted_text FROM evidence_attachments WHERE attachment_id = $1 AND tenant_id = $2",
+        attachment_id,
+        tenant_id
+    )
+    .fetch_optional(db)
+    .await?)
+}
+
+pub fn spawn_worker(evidence: Arc<Evidence>, every: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match evidence.extract_pending().await {
+                Ok(0) => {}
+                Ok(processed) => info!("Processed {} evidence attachment(s)", processed),
+                Err(e) => error!("Evidence extraction run failed: {}", e),
+            }
+        }
+    });
+}
+
+struct Claimed {
+    attachment_id: Uuid,
+    storage_key: String,
+    content_type: String,
+    attempts: i32,
+}
+
+enum Extraction {
+    Text { text: String, method: String },
+    Unsupported(String),
+}
+
+impl Evidence {
+    /// Extract every attachment that is due, one at a time
+    pub async fn extract_pending(&self) -> anyhow::Result<usize> {
+        let mut processed = 0;
+        while let Some(claimed) = self.claim_next().await? {
+            let outcome = match self.store.get(&claimed.storage_key).await {
+                Ok(bytes) => self.extract(bytes, &claimed.content_type).await,
+                Err(e) => Err(e.context("failed to read evidence file")),
+            };
+            self.record(&claimed, outcome).await?;
+            processed += 1;
+        }
+        Ok(processed)
+    }
+
+    /// Claim the next due attachment; a claim older than 15 minutes belongs to a crashed worker
+    async fn claim_next(&self) -> anyhow::Result<Option<Claimed>> {
+        Ok(sqlx::query_as!(
+            Claimed,
+            r#"
+            UPDATE evidence_attachments
+            SET extraction_status = 'PROCESSING', attempts = attempts + 1, claimed_at = NOW()
+            WHERE attachment_id = (
+                SELECT attachment_id FROM evidence_attachments
+                WHERE (extraction_status = 'PENDING' AND next_attempt_at <= NOW())
+                   OR (extraction_status = 'PROCESSING' AND claimed_at < NOW() - INTERVAL '15 minutes')
+                ORDER BY next_attempt_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING attachment_id, storage_key, content_type, attempts
+            "#
+        )
+        .fetch_optional(&self.db)
+        .await?)
+    }
+
+    async fn extract(&self, bytes: Vec<u8>, content_type: &str) -> anyhow::Result<Extraction> {
+        if content_type == "text/plain" {
+            return Ok(Extraction::Text {
+                text: String::from_utf8_lossy(&bytes).into_owned(),
+                method: "PLAIN_TEXT".to_string(),
+            });
+        }
+
+        if content_type == "application/pdf" {
+            let pdf = bytes.clone();
+            // A PDF that fails to parse may still be readable by OCR
+            let text_layer = tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&pdf))
+                .await?
+                .unwrap_or_default();
+            if text_layer.split_whitespace().map(str::len).sum::<usize>() >= MIN_TEXT_LAYER_CHARS {
+                return Ok(Extraction::Text {
+                    text: text_layer,
+                    method: "PDF_TEXT".to_string(),
+                });
+            }
+        }
+
+        match &self.ocr {
+            Some(ocr) if ocr.reads(content_type) => Ok(Extraction::Text {
+                text: ocr.recognize(&bytes, content_type).await?,
+                method: format!("OCR:{}", ocr.name()),
+            }),
+            Some(ocr) => Ok(Extraction::Unsupported(format!(
+                "OCR backend {} does not read {}",
+                ocr.name(),
+                content_type
+            ))),
+            None => Ok(Extraction::Unsupported("no OCR backend is configured".to_string())),
+        }
+    }
+
+    async fn record(&self, claimed: &Claimed, outcome: anyhow::Result<Extraction>) -> anyhow::Result<()> {
+        match outcome {
+            Ok(Extraction::Text { text, method }) => {
+                let text = normalize(&text);
+                sqlx::query!(
+                    r#"
+                    UPDATE evidence_attachments
+                    SET extraction_status = 'EXTRACTED', extraction_method = $2, extracted_text = $3,
+                        last_error = NULL, extracted_at = NOW()
+                    WHERE attachment_id = $1
+                    "#,
+                    claimed.attachment_id,
+                    method,
+                    text
+                )
+                .execute(&self.db)
+                .await?;
+            }
+            Ok(Extraction::Unsupported(reason)) => {
+                sqlx::query!(
+                    "UPDATE evidence_attachments SET extraction_status = 'UNSUPPORTED', last_error = $2 WHERE attachment_id = $1",
+                    claimed.attachment_id,
+                    reason
+                )
+                .execute(&self.db)
+                .await?;
+            }
+            Err(e) => {
+                let status = if claimed.attempts >= MAX_ATTEMPTS { "FAILED" } else { "PENDING" };
+                warn!(
+                    "Extraction of evidence {} failed (attempt {}): {:#}",
+                    claimed.attachment_id, claimed.attempts, e
+                );
+                sqlx::query!(
+                    r#"
+                    UPDATE evidence_attachments
+                    SET extraction_status = $2, last_error = $3,
+                        next_attempt_at = NOW() + make_interval(secs => $4)
+                    WHERE attachment_id = $1
+                    "#,
+                    claimed.attachment_id,
+                    status,
+                    format!("{:#}", e),
+                    RETRY_BASE_SECS * 2f64.powi(claimed.attempts - 1)
+                )
+                .execute(&self.db)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collapse layout whitespace and drop what Postgres text cannot hold
+fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len().min(MAX_TEXT_CHARS));
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if normalized.len() + line.len() >= MAX_TEXT_CHARS {
+            break;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        normalized.push_str(&words.join(" ").replace('\0', ""));
+        normalized.push('\n');
+    }
+    normalized
+}
@@ -0,0 +1,129 @@
+//! OCR backends for evidence without a text layer
+//!
+//! EVIDENCE_OCR_BACKEND selects one:
+//! - `tesseract` runs the tesseract CLI (EVIDENCE_OCR_BINARY, default
+//!   `tesseract`) in EVIDENCE_OCR_LANGUAGES; it reads images only
+//! - `http` posts the file to EVIDENCE_OCR_URL, which answers `{"text": "..."}`;
+//!   this fronts a hosted OCR service and reads scanned PDFs as well
+//!
+//! Without a backend, images and scanned PDFs are left UNSUPPORTED.
+
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use super::IMAGE_TYPES;
+
+#[async_trait]
+pub trait OcrBackend: Send + Sync {
+    /// Recorded as the extraction method, e.g. OCR:tesseract
+    fn name(&self) -> &'static str;
+
+    fn reads(&self, content_type: &str) -> bool;
+
+    async fn recognize(&self, bytes: &[u8], content_type: &str) -> anyhow::Result<String>;
+}
+
+pub fn from_env() -> anyhow::Result<Option<Arc<dyn OcrBackend>>> {
+    let languages = std::env::var("EVIDENCE_OCR_LANGUAGES").unwrap_or_else(|_| "eng".to_string());
+    match std::env::var("EVIDENCE_OCR_BACKEND").unwrap_or_default().as_str() {
+        "" | "none" => Ok(None),
+        "tesseract" => Ok(Some(Arc::new(Tesseract {
+            binary: std::env::var("EVIDENCE_OCR_BINARY").unwrap_or_else(|_| "tesseract".to_string()),
+            languages,
+        }))),
+        "http" => {
+            let url = std::env::var("EVIDENCE_OCR_URL").context("EVIDENCE_OCR_URL must be set for the http OCR backend")?;
+            Ok(Some(Arc::new(HttpOcr {
+                client: reqwest::Client::builder().timeout(Duration::from_secs(120)).build()?,
+                url,
+                token: std::env::var("EVIDENCE_OCR_TOKEN").ok().filter(|token| !token.is_empty()),
+                languages,
+            })))
+        }
+        other => anyhow::bail!("unknown EVIDENCE_OCR_BACKEND '{}'", other),
+    }
+}
+
+pub struct Tesseract {
+    binary: String,
+    /// Tesseract language codes joined by '+', e.g. eng+hin
+    languages: String,
+}
+
+#[async_trait]
+impl OcrBackend for Tesseract {
+    fn name(&self) -> &'static str {
+        "tesseract"
+    }
+
+    fn reads(&self, content_type: &str) -> bool {
+        IMAGE_TYPES.contains(&content_type)
+    }
+
+    async fn recognize(&self, bytes: &[u8], _content_type: &str) -> anyhow::Result<String> {
+        let mut child = tokio::process::Command::new(&self.binary)
+            .args(["stdin", "stdout", "-l", &self.languages])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to run {}", self.binary))?;
+
+        let mut stdin = child.stdin.take().context("tesseract stdin unavailable")?;
+        stdin.write_all(bytes).await?;
+        drop(stdin);
+
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "tesseract exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+pub struct HttpOcr {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+    languages: String,
+}
+
+#[derive(Deserialize)]
+struct HttpOcrResponse {
+    text: String,
+}
+
+#[async_trait]
+impl OcrBackend for HttpOcr {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn reads(&self, content_type: &str) -> bool {
+        content_type == "application/pdf" || IMAGE_TYPES.contains(&content_type)
+    }
+
+    async fn recognize(&self, bytes: &[u8], content_type: &str) -> anyhow::Result<String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .query(&[("languages", &self.languages)])
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(bytes.to_vec());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response: HttpOcrResponse = request.send().await?.error_for_status()?.json().await?;
+        Ok(response.text)
+    }
+}
@@ -0,0 +1,205 @@
+//! Full-text search over violations and cases, including their evidence
+//!
+//! Queries use web search syntax ("quoted phrases", -exclusions, OR). A
+//! record matches on its own text or on the text or file name of any of its
+//! attachments, ranked by the best of those matches; matching attachments are
+//! returned with highlighted snippets.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const HEADLINE_OPTIONS: &str = "MaxFragments=2, MaxWords=25, MinWords=8, StartSel=<mark>, StopSel=</mark>";
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    pub tenant_id: Uuid,
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EvidenceHit {
+    pub attachment_id: Uuid,
+    pub file_name: String,
+    /// Matching passages of the extracted text; absent when only the file name matched
+    pub snippet: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ViolationHit {
+    pub violation_id: Uuid,
+    pub violation_type: String,
+    pub severity: String,
+    pub description: String,
+    pub status: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub rank: f32,
+    pub evidence: Vec<EvidenceHit>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CaseHit {
+    pub investigation_id: Uuid,
+    pub alert_id: Uuid,
+    pub status: Option<String>,
+    pub priority: Option<String>,
+    pub findings: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub rank: f32,
+    pub evidence: Vec<EvidenceHit>,
+}
+
+struct OwnedEvidenceHit {
+    owner_id: Uuid,
+    attachment_id: Uuid,
+    file_name: String,
+    snippet: Option<String>,
+}
+
+fn group_by_owner(hits: Vec<OwnedEvidenceHit>) -> HashMap<Uuid, Vec<EvidenceHit>> {
+    let mut grouped: HashMap<Uuid, Vec<EvidenceHit>> = HashMap::new();
+    for hit in hits {
+        grouped.entry(hit.owner_id).or_default().push(EvidenceHit {
+            attachment_id: hit.attachment_id,
+            file_name: hit.file_name,
+            snippet: hit.snippet,
+        });
+    }
+    grouped
+}
+
+pub async fn search_violations(db: &PgPool, params: &SearchParams) -> anyhow::Result<Vec<ViolationHit>> {
+    let limit = params.limit.unwrap_or(25).clamp(1, 100);
+
+    let rows = sqlx::query!(
+        r#"
+        WITH query AS (SELECT websearch_to_tsquery('english', $2) AS q),
+        matches AS (
+            SELECT v.violation_id, ts_rank(to_tsvector('english'::regconfig, v.violation_type || ' ' || v.description), query.q) AS rank
+            FROM compliance_violations v, query
+            WHERE v.tenant_id = $1
+              AND to_tsvector('english'::regconfig, v.violation_type || ' ' || v.description) @@ query.q
+            UNION ALL
+            SELECT e.violation_id, ts_rank(e.text_search, query.q)
+            FROM evidence_attachments e, query
+            WHERE e.tenant_id = $1 AND e.violation_id IS NOT NULL AND e.text_search @@ query.q
+        )
+        SELECT v.violation_id, v.violation_type, v.severity::text AS "severity!", v.description, v.status,
+               v.created_at, MAX(m.rank) AS "rank!"
+        FROM matches m
+        JOIN compliance_violations v ON v.violation_id = m.violation_id
+        GROUP BY v.violation_id
+        ORDER BY 7 DESC
+        LIMIT $3
+        "#,
+        params.tenant_id,
+        params.q,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    let ids: Vec<Uuid> = rows.iter().map(|row| row.violation_id).collect();
+    let evidence = sqlx::query_as!(
+        OwnedEvidenceHit,
+        r#"
+        SELECT e.violation_id AS "owner_id!", e.attachment_id, e.file_name,
+               ts_headline('english', e.extracted_text, websearch_to_tsquery('english', $2), $3) AS snippet
+        FROM evidence_attachments e
+        WHERE e.violation_id = ANY($1) AND e.text_search @@ websearch_to_tsquery('english', $2)
+        ORDER BY ts_rank(e.text_search, websearch_to_tsquery('english', $2)) DESC
+        "#,
+        &ids,
+        params.q,
+        HEADLINE_OPTIONS
+    )
+    .fetch_all(db)
+    .await?;
+    let mut evidence = group_by_owner(evidence);
+
+    Ok(rows
+        .into_iter()
+        .map(|row| ViolationHit {
+            evidence: evidence.remove(&row.violation_id).unwrap_or_default(),
+            violation_id: row.violation_id,
+            violation_type: row.violation_type,
+            severity: row.severity,
+            description: row.description,
+            status: row.status,
+            created_at: row.created_at,
+            rank: row.rank,
+        })
+        .collect())
+}
+
+pub async fn search_cases(db: &PgPool, params: &SearchParams) -> anyhow::Result<Vec<CaseHit>> {
+    let limit = params.limit.unwrap_or(25).clamp(1, 100);
+
+    let rows = sqlx::query!(
+        r#"
+        WITH query AS (SELECT websearch_to_tsquery('english', $2) AS q),
+        cases AS (
+            SELECT i.* FROM alert_investigations i
+            JOIN surveillance_alerts a ON a.alert_id = i.alert_id
+            WHERE a.tenant_id = $1
+        ),
+        matches AS (
+            SELECT c.investigation_id,
+                   ts_rank(to_tsvector('english'::regconfig, COALESCE(c.findings, '') || ' ' || COALESCE(c.recommendations, '')), query.q) AS rank
+            FROM cases c, query
+            WHERE to_tsvector('english'::regconfig, COALESCE(c.findings, '') || ' ' || COALESCE(c.recommendations, '')) @@ query.q
+            UNION ALL
+            SELECT e.investigation_id, ts_rank(e.text_search, query.q)
+            FROM evidence_attachments e, query
+            WHERE e.tenant_id = $1 AND e.investigation_id IS NOT NULL AND e.text_search @@ query.q
+        )
+        SELECT c.investigation_id AS "investigation_id!", c.alert_id AS "alert_id!", c.status, c.priority,
+               c.findings, c.started_at, MAX(m.rank) AS "rank!"
+        FROM matches m
+        JOIN cases c ON c.investigation_id = m.investigation_id
+        GROUP BY c.investigation_id, c.alert_id, c.status, c.priority, c.findings, c.started_at
+        ORDER BY 7 DESC
+        LIMIT $3
+        "#,
+        params.tenant_id,
+        params.q,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+
+    let ids: Vec<Uuid> = rows.iter().map(|row| row.investigation_id).collect();
+    let evidence = sqlx::query_as!(
+        OwnedEvidenceHit,
+        r#"
+        SELECT e.investigation_id AS "owner_id!", e.attachment_id, e.file_name,
+               ts_headline('english', e.extracted_text, websearch_to_tsquery('english', $2), $3) AS snippet
+        FROM evidence_attachments e
+        WHERE e.investigation_id = ANY($1) AND e.text_search @@ websearch_to_tsquery('english', $2)
+        ORDER BY ts_rank(e.text_search, websearch_to_tsquery('english', $2)) DESC
+        "#,
+        &ids,
+        params.q,
+        HEADLINE_OPTIONS
+    )
+    .fetch_all(db)
+    .await?;
+    let mut evidence = group_by_owner(evidence);
+
+    Ok(rows
+        .into_iter()
+        .map(|row| CaseHit {
+            evidence: evidence.remove(&row.investigation_id).unwrap_or_default(),
+            investigation_id: row.investigation_id,
+            alert_id: row.alert_id,
+            status: row.status,
+            priority: row.priority,
+            findings: row.findings,
+            started_at: row.started_at,
+            rank: row.rank,
+        })
+        .collect())
+}
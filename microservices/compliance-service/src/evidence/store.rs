@@ -0,0 +1,110 @@
+//! Where uploaded evidence files are kept
+//!
+//! Files are stored under `<root>/<tenant_id>/<attachment_id>` and are never
+//! modified once written.
+
+use async_trait::async_trait;
+use aws_sdk_s3::{primitives::ByteStream, Client as S3Client};
+use std::path::PathBuf;
+
+#[async_trait]
+pub trait EvidenceStore: Send + Sync {
+    fn uri(&self) -> String;
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> anyhow::Result<()>;
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Build the store from EVIDENCE_STORE: `s3://bucket/prefix` or a directory path
+pub async fn from_uri(uri: &str) -> anyhow::Result<Box<dyn EvidenceStore>> {
+    if let Some(location) = uri.strip_prefix("s3://") {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            anyhow::bail!("EVIDENCE_STORE '{}' has no bucket", uri);
+        }
+        let config = aws_config::load_from_env().await;
+        return Ok(Box::new(S3Store {
+            client: S3Client::new(&config),
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        }));
+    }
+
+    let root = PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri));
+    tokio::fs::create_dir_all(&root).await?;
+    Ok(Box::new(DirectoryStore { root }))
+}
+
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+#[async_trait]
+impl EvidenceStore for DirectoryStore {
+    fn uri(&self) -> String {
+        format!("file://{}", self.root.display())
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> anyhow::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Written aside and renamed so a reader never sees a partial file
+        let partial = path.with_extension("part");
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        Ok(tokio::fs::read(self.root.join(key)).await?)
+    }
+}
+
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Store {
+    fn object_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+}
+
+#[async_trait]
+impl EvidenceStore for S3Store {
+    fn uri(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> anyhow::Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .content_type(content_type)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        Ok(object.body.collect().await?.into_bytes().to_vec())
+    }
+}
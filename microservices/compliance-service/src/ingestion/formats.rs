@@ -0,0 +1,495 @@
+//! End-of-day exchange file layouts
+//!
+//! Files are named `<EXCHANGE>_<KIND>_<YYYYMMDD>[_suffix].csv`, for example
+//! `NSE_TRADES_20241014.csv` or `BSE_MARGIN_20241014_01.csv`. Column headers
+//! are matched case-insensitively with punctuation ignored, so `Trade No`,
+//! `TRADE_NO` and `TradeNo` are the same column. NSE and BSE name some
+//! columns differently (BSE uses `SCRIP_CD`, `B/S` and `RATE`); both layouts
+//! are listed per field below.
+//!
+//! Trades that are legs of a multi-leg derivative strategy (spreads,
+//! straddles, butterflies) carry the exchange's strategy id with the leg
+//! number and leg count; basket executions carry the basket id. Both are
+//! optional so plain single-leg files are unaffected.
+
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use csv::StringRecord;
+use std::collections::HashMap;
+
+/// Exchange timestamps without an offset are Indian Standard Time
+const IST_OFFSET_SECONDS: i32 = 5 * 3600 + 30 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exchange {
+    Nse,
+    Bse,
+}
+
+impl Exchange {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Exchange::Nse => "NSE",
+            Exchange::Bse => "BSE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Trades,
+    Positions,
+    Margin,
+}
+
+impl FileKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileKind::Trades => "TRADES",
+            FileKind::Positions => "POSITIONS",
+            FileKind::Margin => "MARGIN",
+        }
+    }
+}
+
+/// What a file contains, derived from its name
+#[derive(Debug, Clone, Copy)]
+pub struct FileDescriptor {
+    pub exchange: Exchange,
+    pub kind: FileKind,
+    pub business_date: NaiveDate,
+}
+
+impl FileDescriptor {
+    pub fn from_file_name(name: &str) -> Result<Self, String> {
+        let upper = name.to_ascii_uppercase();
+        let stem = upper
+            .strip_suffix(".CSV")
+            .or_else(|| upper.strip_suffix(".TXT"))
+            .ok_or_else(|| format!("'{}' is not a .csv or .txt file", name))?;
+
+        let mut parts = stem.split('_');
+        let exchange = match parts.next() {
+            Some("NSE") => Exchange::Nse,
+            Some("BSE") => Exchange::Bse,
+            _ => return Err(format!("'{}' does not start with NSE_ or BSE_", name)),
+        };
+        let kind = match parts.next() {
+            Some("TRADES") => FileKind::Trades,
+            Some("POSITIONS") => FileKind::Positions,
+            Some("MARGIN") => FileKind::Margin,
+            _ => return Err(format!("'{}' is not a TRADES, POSITIONS or MARGIN file", name)),
+        };
+        let business_date = parts
+            .next()
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+            .ok_or_else(|| format!("'{}' does not carry a YYYYMMDD business date", name))?;
+
+        Ok(Self {
+            exchange,
+            kind,
+            business_date,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Field {
+    TradeNumber,
+    OrderNumber,
+    Side,
+    Quantity,
+    Price,
+    TradeTime,
+    Account,
+    ClientCode,
+    Symbol,
+    Isin,
+    NetQuantity,
+    AveragePrice,
+    ClosePrice,
+    InitialMargin,
+    ExposureMargin,
+    TotalMargin,
+    PeakMargin,
+    Collateral,
+    StrategyId,
+    StrategyType,
+    LegNumber,
+    LegCount,
+    BasketId,
+}
+
+/// Accepted (normalised) header names for a field on an exchange
+fn aliases(exchange: Exchange, field: Field) -> &'static [&'static str] {
+    match (exchange, field) {
+        (Exchange::Nse, Field::TradeNumber) => &["TRADENO", "TRADENUMBER"],
+        (Exchange::Bse, Field::TradeNumber) => &["TRADENO", "TRADEID"],
+        (Exchange::Nse, Field::OrderNumber) => &["ORDERNO", "ORDERNUMBER"],
+        (Exchange::Bse, Field::OrderNumber) => &["ORDERNO", "ORDERID"],
+        (Exchange::Nse, Field::Side) => &["BUYSELL", "BUYSELLINDICATOR"],
+        (Exchange::Bse, Field::Side) => &["BS", "BUYSELL"],
+        (Exchange::Nse, Field::Quantity) => &["TRADEQTY", "TRADEQUANTITY", "QTY"],
+        (Exchange::Bse, Field::Quantity) => &["QTY", "QUANTITY"],
+        (Exchange::Nse, Field::Price) => &["TRADEPRICE", "PRICE"],
+        (Exchange::Bse, Field::Price) => &["RATE", "PRICE"],
+        (Exchange::Nse, Field::TradeTime) => &["TRADETIME", "TRADEENTRYTIME", "TRADEDATETIME"],
+        (Exchange::Bse, Field::TradeTime) => &["TRADETIME", "TIME"],
+        (_, Field::Account) => &["ACCOUNT", "ACCOUNTNO", "ACCOUNTNUMBER"],
+        (Exchange::Nse, Field::ClientCode) => &["CLIENTCODE", "CLIENTACCOUNT"],
+        (Exchange::Bse, Field::ClientCode) => &["CLIENTCODE", "CLIENTID"],
+        (Exchange::Nse, Field::Symbol) => &["SYMBOL"],
+        (Exchange::Bse, Field::Symbol) => &["SCRIPCD", "SCRIPCODE", "SCRIPID"],
+        (_, Field::Isin) => &["ISIN", "ISINCODE"],
+        (Exchange::Nse, Field::NetQuantity) => &["NETQTY", "NETQUANTITY"],
+        (Exchange::Bse, Field::NetQuantity) => &["NETQTY", "NETPOSITION"],
+        (Exchange::Nse, Field::AveragePrice) => &["AVGPRICE", "AVERAGEPRICE"],
+        (Exchange::Bse, Field::AveragePrice) => &["AVGRATE", "AVERAGERATE"],
+        (Exchange::Nse, Field::ClosePrice) => &["CLOSEPRICE", "SETTLEMENTPRICE"],
+        (Exchange::Bse, Field::ClosePrice) => &["CLOSERATE", "CLOSEPRICE"],
+        (Exchange::Nse, Field::InitialMargin) => &["INITIALMARGIN", "SPANMARGIN"],
+        (Exchange::Bse, Field::InitialMargin) => &["VARMARGIN", "INITIALMARGIN"],
+        (_, Field::ExposureMargin) => &["EXPOSUREMARGIN", "ELM"],
+        (_, Field::TotalMargin) => &["TOTALMARGIN", "TOTALMARGINREQUIRED"],
+        (_, Field::PeakMargin) => &["PEAKMARGIN", "PEAKMARGINREQUIRED", "PEAKMARGINOBLIGATION"],
+        (_, Field::Collateral) => &["COLLATERAL", "COLLATERALAVAILABLE", "COLLATERALVALUE"],
+        (Exchange::Nse, Field::StrategyId) => &["STRATEGYID", "SPREADID", "COMBOID"],
+        (Exchange::Bse, Field::StrategyId) => &["STRATEGYID", "SPREADID"],
+        (_, Field::StrategyType) => &["STRATEGYTYPE", "SPREADTYPE"],
+        (_, Field::LegNumber) => &["LEGNO", "LEGNUMBER"],
+        (_, Field::LegCount) => &["LEGCOUNT", "NOOFLEGS", "TOTALLEGS"],
+        (_, Field::BasketId) => &["BASKETID", "BASKETNO", "BASKETORDERID"],
+    }
+}
+
+fn required_fields(kind: FileKind) -> &'static [Field] {
+    match kind {
+        FileKind::Trades => &[
+            Field::TradeNumber,
+            Field::OrderNumber,
+            Field::Side,
+            Field::Quantity,
+            Field::Price,
+            Field::TradeTime,
+            Field::Account,
+        ],
+        FileKind::Positions => &[Field::Account, Field::NetQuantity, Field::AveragePrice],
+        FileKind::Margin => &[Field::Account, Field::TotalMargin],
+    }
+}
+
+fn optional_fields(kind: FileKind) -> &'static [Field] {
+    match kind {
+        FileKind::Trades => &[
+            Field::ClientCode,
+            Field::Symbol,
+            Field::Isin,
+            Field::StrategyId,
+            Field::StrategyType,
+            Field::LegNumber,
+            Field::LegCount,
+            Field::BasketId,
+        ],
+        FileKind::Positions => &[Field::Symbol, Field::Isin, Field::ClosePrice],
+        FileKind::Margin => &[Field::InitialMargin, Field::ExposureMargin, Field::PeakMargin, Field::Collateral],
+    }
+}
+
+pub(crate) fn normalise_header(header: &str) -> String {
+    header
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Instrument as identified in a file; ISIN wins when both are present
+#[derive(Debug, Clone)]
+pub struct InstrumentRef {
+    pub symbol: Option<String>,
+    pub isin: Option<String>,
+}
+
+/// Position of a trade within a multi-leg strategy execution
+#[derive(Debug, Clone)]
+pub struct StrategyLeg {
+    pub strategy_id: String,
+    pub strategy_type: Option<String>,
+    pub leg_number: Option<i16>,
+    pub leg_count: Option<i16>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TradeRecord {
+    pub trade_number: String,
+    pub order_number: String,
+    pub trade_type: &'static str,
+    pub quantity: i64,
+    /// Decimal values are kept as validated text and cast to NUMERIC in SQL
+    pub price: String,
+    pub trade_time: DateTime<Utc>,
+    pub account: String,
+    pub client_code: Option<String>,
+    pub instrument: InstrumentRef,
+    pub strategy: Option<StrategyLeg>,
+    pub basket_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PositionRecord {
+    pub account: String,
+    pub instrument: InstrumentRef,
+    pub net_quantity: i64,
+    pub average_price: String,
+    pub close_price: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MarginRecord {
+    pub account: String,
+    pub initial_margin: String,
+    pub exposure_margin: String,
+    pub total_margin: String,
+    /// The highest of the day's intraday snapshots, for files that report it
+    pub peak_margin: Option<String>,
+    pub collateral: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum Record {
+    Trade(TradeRecord),
+    Position(PositionRecord),
+    Margin(MarginRecord),
+}
+
+/// Column positions of a file's header, resolved against its exchange layout
+pub struct Layout {
+    descriptor: FileDescriptor,
+    columns: HashMap<Field, usize>,
+}
+
+impl Layout {
+    pub fn resolve(descriptor: FileDescriptor, headers: &StringRecord) -> Result<Self, String> {
+        let normalised: Vec<String> = headers.iter().map(normalise_header).collect();
+        let position = |field: Field| {
+            aliases(descriptor.exchange, field)
+                .iter()
+                .find_map(|alias| normalised.iter().position(|header| header == alias))
+        };
+
+        let mut columns = HashMap::new();
+        let mut missing = Vec::new();
+        for &field in required_fields(descriptor.kind) {
+            match position(field) {
+                Some(index) => {
+                    columns.insert(field, index);
+                }
+                None => missing.push(aliases(descriptor.exchange, field)[0]),
+            }
+        }
+        for &field in optional_fields(descriptor.kind) {
+            if let Some(index) = position(field) {
+                columns.insert(field, index);
+            }
+        }
+        if descriptor.kind != FileKind::Margin
+            && !columns.contains_key(&Field::Symbol)
+            && !columns.contains_key(&Field::Isin)
+        {
+            missing.push(aliases(descriptor.exchange, Field::Symbol)[0]);
+        }
+
+        if missing.is_empty() {
+            Ok(Self { descriptor, columns })
+        } else {
+            Err(format!(
+                "{} {} file is missing required columns: {}",
+                descriptor.exchange.as_str(),
+                descriptor.kind.as_str(),
+                missing.join(", ")
+            ))
+        }
+    }
+
+    /// Parse and validate one data row
+    pub fn parse(&self, row: &StringRecord) -> Result<Record, String> {
+        Ok(match self.descriptor.kind {
+            FileKind::Trades => {
+                let quantity = integer(self.required(row, Field::Quantity)?)?;
+                if quantity <= 0 {
+                    return Err(format!("quantity must be positive, got {}", quantity));
+                }
+                let price = decimal(self.required(row, Field::Price)?)?;
+                if price.parse::<f64>().map_or(true, |price| price <= 0.0) {
+                    return Err(format!("price must be positive, got {}", price));
+                }
+                let trade_time = self.trade_time(self.required(row, Field::TradeTime)?)?;
+
+                Record::Trade(TradeRecord {
+                    trade_number: self.required(row, Field::TradeNumber)?.to_string(),
+                    order_number: self.required(row, Field::OrderNumber)?.to_string(),
+                    trade_type: side(self.required(row, Field::Side)?)?,
+                    quantity,
+                    price,
+                    trade_time,
+                    account: self.required(row, Field::Account)?.to_string(),
+                    client_code: self.optional(row, Field::ClientCode).map(str::to_string),
+                    instrument: self.instrument(row)?,
+                    strategy: self.strategy_leg(row)?,
+                    basket_id: self.optional(row, Field::BasketId).map(str::to_string),
+                })
+            }
+            FileKind::Positions => {
+                let close_price = self.optional(row, Field::ClosePrice).map(decimal).transpose()?;
+                Record::Position(PositionRecord {
+                    account: self.required(row, Field::Account)?.to_string(),
+                    instrument: self.instrument(row)?,
+                    net_quantity: integer(self.required(row, Field::NetQuantity)?)?,
+                    average_price: non_negative(self.required(row, Field::AveragePrice)?)?,
+                    close_price,
+                })
+            }
+            FileKind::Margin => {
+                let amount = |field| self.optional(row, field).map(non_negative).transpose();
+                Record::Margin(MarginRecord {
+                    account: self.required(row, Field::Account)?.to_string(),
+                    initial_margin: amount(Field::InitialMargin)?.unwrap_or_else(|| "0".to_string()),
+                    exposure_margin: amount(Field::ExposureMargin)?.unwrap_or_else(|| "0".to_string()),
+                    total_margin: non_negative(self.required(row, Field::TotalMargin)?)?,
+                    peak_margin: amount(Field::PeakMargin)?,
+                    collateral: amount(Field::Collateral)?.unwrap_or_else(|| "0".to_string()),
+                })
+            }
+        })
+    }
+
+    fn optional<'r>(&self, row: &'r StringRecord, field: Field) -> Option<&'r str> {
+        self.columns
+            .get(&field)
+            .and_then(|&index| row.get(index))
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn required<'r>(&self, row: &'r StringRecord, field: Field) -> Result<&'r str, String> {
+        self.optional(row, field)
+            .ok_or_else(|| format!("{} is empty", aliases(self.descriptor.exchange, field)[0]))
+    }
+
+    fn instrument(&self, row: &StringRecord) -> Result<InstrumentRef, String> {
+        let instrument = InstrumentRef {
+            symbol: self.optional(row, Field::Symbol).map(str::to_string),
+            isin: self.optional(row, Field::Isin).map(str::to_ascii_uppercase),
+        };
+        if instrument.symbol.is_none() && instrument.isin.is_none() {
+            return Err("row has neither a symbol nor an ISIN".to_string());
+        }
+        if let Some(isin) = &instrument.isin {
+            if isin.len() != 12 || !isin.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(format!("'{}' is not a valid ISIN", isin));
+            }
+        }
+        Ok(instrument)
+    }
+
+    fn strategy_leg(&self, row: &StringRecord) -> Result<Option<StrategyLeg>, String> {
+        let leg = |field| {
+            self.optional(row, field)
+                .map(|value| {
+                    value
+                        .parse::<i16>()
+                        .ok()
+                        .filter(|number| *number > 0)
+                        .ok_or_else(|| format!("'{}' is not a leg number", value))
+                })
+                .transpose()
+        };
+        let leg_number = leg(Field::LegNumber)?;
+        let leg_count = leg(Field::LegCount)?;
+        let Some(strategy_id) = self.optional(row, Field::StrategyId) else {
+            if leg_number.is_some() || leg_count.is_some() {
+                return Err("leg number or count given without a strategy id".to_string());
+            }
+            return Ok(None);
+        };
+        if let (Some(number), Some(count)) = (leg_number, leg_count) {
+            if number > count {
+                return Err(format!("leg {} is beyond the strategy's {} legs", number, count));
+            }
+        }
+        Ok(Some(StrategyLeg {
+            strategy_id: strategy_id.to_string(),
+            strategy_type: self.optional(row, Field::StrategyType).map(str::to_ascii_uppercase),
+            leg_number,
+            leg_count,
+        }))
+    }
+
+    /// Accepts a bare time of day (the file's business date is implied) or a full timestamp
+    fn trade_time(&self, value: &str) -> Result<DateTime<Utc>, String> {
+        let ist = FixedOffset::east_opt(IST_OFFSET_SECONDS).expect("IST offset is valid");
+
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+            return self.on_business_date(timestamp.with_timezone(&ist)).map(|_| timestamp.with_timezone(&Utc));
+        }
+
+        let local = ["%H:%M:%S", "%H:%M:%S%.f"]
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(value, format).ok())
+            .map(|time| self.descriptor.business_date.and_time(time))
+            .or_else(|| {
+                ["%Y-%m-%d %H:%M:%S", "%d-%m-%Y %H:%M:%S", "%d-%b-%Y %H:%M:%S", "%d/%m/%Y %H:%M:%S"]
+                    .iter()
+                    .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            })
+            .ok_or_else(|| format!("'{}' is not a recognised trade time", value))?;
+
+        let timestamp = ist
+            .from_local_datetime(&local)
+            .single()
+            .ok_or_else(|| format!("'{}' is not a valid IST time", value))?;
+        self.on_business_date(timestamp)?;
+        Ok(timestamp.with_timezone(&Utc))
+    }
+
+    fn on_business_date(&self, timestamp: DateTime<FixedOffset>) -> Result<(), String> {
+        if timestamp.date_naive() == self.descriptor.business_date {
+            Ok(())
+        } else {
+            Err(format!(
+                "trade time {} is outside business date {}",
+                timestamp, self.descriptor.business_date
+            ))
+        }
+    }
+}
+
+fn side(value: &str) -> Result<&'static str, String> {
+    match value.to_ascii_uppercase().as_str() {
+        "1" | "B" | "BUY" => Ok("BUY"),
+        "2" | "S" | "SELL" => Ok("SELL"),
+        _ => Err(format!("'{}' is not a buy/sell indicator", value)),
+    }
+}
+
+fn integer(value: &str) -> Result<i64, String> {
+    value
+        .replace(',', "")
+        .parse::<i64>()
+        .map_err(|_| format!("'{}' is not a whole number", value))
+}
+
+fn decimal(value: &str) -> Result<String, String> {
+    let cleaned = value.replace(',', "");
+    match cleaned.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(cleaned),
+        _ => Err(format!("'{}' is not a number", value)),
+    }
+}
+
+fn non_negative(value: &str) -> Result<String, String> {
+    let cleaned = decimal(value)?;
+    if cleaned.starts_with('-') {
+        return Err(format!("'{}' must not be negative", value));
+    }
+    Ok(cleaned)
+}
@@ -0,0 +1,242 @@
+//! Inboxes that end-of-day files are picked up from
+//!
+//! Both inboxes use the same layout: `<root>/<tenant_id>/<file>`. Processed
+//! files move to `<root>/<tenant_id>/processed/` and files that could not be
+//! loaded to `<root>/<tenant_id>/rejected/`, prefixed with the pickup time so
+//! a re-sent file never overwrites an earlier copy.
+
+use async_trait::async_trait;
+use aws_sdk_s3::Client as S3Client;
+use chrono::Utc;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+use uuid::Uuid;
+
+/// Files modified more recently than this may still be uploading over SFTP
+const SETTLE_TIME: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct InboxFile {
+    pub tenant_id: Uuid,
+    pub name: String,
+    /// Path or object key, as understood by the inbox that listed it
+    pub location: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Archive {
+    Processed,
+    Rejected,
+}
+
+impl Archive {
+    fn folder(&self) -> &'static str {
+        match self {
+            Archive::Processed => "processed",
+            Archive::Rejected => "rejected",
+        }
+    }
+}
+
+fn archived_name(file: &InboxFile) -> String {
+    format!("{}_{}", Utc::now().format("%Y%m%dT%H%M%S"), file.name)
+}
+
+#[async_trait]
+pub trait Inbox: Send + Sync {
+    /// URI recorded on ingestion runs
+    fn uri(&self) -> String;
+
+    /// Files waiting to be ingested, across all tenants
+    async fn list(&self) -> anyhow::Result<Vec<InboxFile>>;
+
+    async fn fetch(&self, file: &InboxFile) -> anyhow::Result<Vec<u8>>;
+
+    async fn archive(&self, file: &InboxFile, archive: Archive) -> anyhow::Result<()>;
+}
+
+/// Build the inbox from INGESTION_INBOX: `s3://bucket/prefix` or a directory path
+///
+/// The SFTP server's upload directory is mounted into the container and read
+/// as a directory inbox.
+pub async fn from_uri(uri: &str) -> anyhow::Result<Box<dyn Inbox>> {
+    if let Some(location) = uri.strip_prefix("s3://") {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            anyhow::bail!("INGESTION_INBOX '{}' has no bucket", uri);
+        }
+        let config = aws_config::load_from_env().await;
+        return Ok(Box::new(S3Inbox {
+            client: S3Client::new(&config),
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        }));
+    }
+
+    let root = PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri));
+    if !tokio::fs::metadata(&root).await?.is_dir() {
+        anyhow::bail!("INGESTION_INBOX '{}' is not a directory", uri);
+    }
+    Ok(Box::new(DirectoryInbox { root }))
+}
+
+pub struct DirectoryInbox {
+    root: PathBuf,
+}
+
+#[async_trait]
+impl Inbox for DirectoryInbox {
+    fn uri(&self) -> String {
+        format!("file://{}", self.root.display())
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<InboxFile>> {
+        let mut files = Vec::new();
+        let mut tenants = tokio::fs::read_dir(&self.root).await?;
+        while let Some(tenant_dir) = tenants.next_entry().await? {
+            let Some(tenant_id) = tenant_dir.file_name().to_str().and_then(|name| Uuid::parse_str(name).ok()) else {
+                continue;
+            };
+            if !tenant_dir.file_type().await?.is_dir() {
+                continue;
+            }
+
+            let mut entries = tokio::fs::read_dir(tenant_dir.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                    continue;
+                };
+                // Hidden and partial files belong to uploads still in progress
+                if name.starts_with('.') || name.ends_with(".part") || name.ends_with(".tmp") {
+                    continue;
+                }
+                let metadata = entry.metadata().await?;
+                if !metadata.is_file() {
+                    continue;
+                }
+                let settled = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .map_or(true, |age| age >= SETTLE_TIME);
+                if !settled {
+                    continue;
+                }
+
+                files.push(InboxFile {
+                    tenant_id,
+                    name,
+                    location: entry.path().to_string_lossy().into_owned(),
+                });
+            }
+        }
+        files.sort_by(|a, b| a.location.cmp(&b.location));
+        Ok(files)
+    }
+
+    async fn fetch(&self, file: &InboxFile) -> anyhow::Result<Vec<u8>> {
+        Ok(tokio::fs::read(&file.location).await?)
+    }
+
+    async fn archive(&self, file: &InboxFile, archive: Archive) -> anyhow::Result<()> {
+        let folder = self.root.join(file.tenant_id.to_string()).join(archive.folder());
+        tokio::fs::create_dir_all(&folder).await?;
+        tokio::fs::rename(&file.location, folder.join(archived_name(file))).await?;
+        Ok(())
+    }
+}
+
+pub struct S3Inbox {
+    client: S3Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Inbox {
+    fn key(&self, parts: &[&str]) -> String {
+        let mut segments: Vec<&str> = Vec::with_capacity(parts.len() + 1);
+        if !self.prefix.is_empty() {
+            segments.push(&self.prefix);
+        }
+        segments.extend_from_slice(parts);
+        segments.join("/")
+    }
+}
+
+#[async_trait]
+impl Inbox for S3Inbox {
+    fn uri(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    async fn list(&self) -> anyhow::Result<Vec<InboxFile>> {
+        let list_prefix = if self.prefix.is_empty() { String::new() } else { format!("{}/", self.prefix) };
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&list_prefix)
+            .into_paginator()
+            .send();
+
+        let mut files = Vec::new();
+        while let Some(page) = pages.next().await {
+            for object in page?.contents() {
+                let Some(key) = object.key() else {
+                    continue;
+                };
+                // Only objects directly under a tenant folder; archive folders are nested deeper
+                let relative = &key[list_prefix.len()..];
+                let Some((tenant, name)) = relative.split_once('/') else {
+                    continue;
+                };
+                if name.is_empty() || name.contains('/') {
+                    continue;
+                }
+                let Ok(tenant_id) = Uuid::parse_str(tenant) else {
+                    warn!("Ignoring inbox object outside a tenant folder: {}", key);
+                    continue;
+                };
+                files.push(InboxFile {
+                    tenant_id,
+                    name: name.to_string(),
+                    location: key.to_string(),
+                });
+            }
+        }
+        files.sort_by(|a, b| a.location.cmp(&b.location));
+        Ok(files)
+    }
+
+    async fn fetch(&self, file: &InboxFile) -> anyhow::Result<Vec<u8>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&file.location)
+            .send()
+            .await?;
+        Ok(object.body.collect().await?.into_bytes().to_vec())
+    }
+
+    async fn archive(&self, file: &InboxFile, archive: Archive) -> anyhow::Result<()> {
+        let tenant = file.tenant_id.to_string();
+        let target = self.key(&[&tenant, archive.folder(), &archived_name(file)]);
+
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{}", self.bucket, file.location))
+            .key(&target)
+            .send()
+            .await?;
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(&file.location)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,608 @@
+//! End-of-day exchange file ingestion
+//!
+//! Trade, position and margin files dropped by the exchanges (or the member's
+//! back office) into a tenant's inbox folder are picked up on a poll, parsed
+//! against the NSE/BSE layouts in `formats`, validated row by row and loaded in
+//! one transaction per file. Every file gets an ingestion run recording what
+//! was loaded, skipped and rejected; rejected rows are kept with their line
+//! number and raw content so the file can be corrected and re-sent.
+
+pub mod formats;
+pub mod inbox;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use self::formats::{FileDescriptor, InstrumentRef, Layout, Record};
+use self::inbox::{Archive, Inbox, InboxFile};
+
+/// Failed attempts at one file before it is moved aside instead of retried
+const MAX_ATTEMPTS: i64 = 3;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IngestionRun {
+    pub run_id: Uuid,
+    pub tenant_id: Uuid,
+    pub source_uri: String,
+    pub file_name: String,
+    pub file_sha256: String,
+    pub exchange: Option<String>,
+    pub file_kind: Option<String>,
+    pub business_date: Option<NaiveDate>,
+    pub status: String,
+    pub rows_total: i32,
+    pub rows_loaded: i32,
+    pub rows_skipped: i32,
+    pub rows_rejected: i32,
+    pub error: Option<String>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IngestionReject {
+    pub line_number: i32,
+    pub reason: String,
+    pub raw_record: Option<String>,
+}
+
+/// Run report returned by GET /ingestion/runs/:run_id
+#[derive(Serialize, Debug, Clone)]
+pub struct IngestionReport {
+    #[serde(flatten)]
+    pub run: IngestionRun,
+    pub rejects: Vec<IngestionReject>,
+}
+
+struct LoadSummary {
+    rows_total: i32,
+    rows_loaded: i32,
+    /// Rows already present from an earlier copy of the file
+    rows_skipped: i32,
+    rejects: Vec<IngestionReject>,
+}
+
+enum FileOutcome {
+    Loaded(LoadSummary),
+    /// The file as a whole is unusable; nothing was loaded
+    Rejected(String),
+}
+
+/// Poll the inbox on a fixed interval
+pub fn spawn_poller(db: PgPool, inbox: Arc<dyn Inbox>, every: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match scan(&db, inbox.as_ref()).await {
+                Ok(runs) if !runs.is_empty() => info!("Ingested {} file(s) from {}", runs.len(), inbox.uri()),
+                Ok(_) => {}
+                Err(e) => error!("Failed to scan ingestion inbox {}: {}", inbox.uri(), e),
+            }
+        }
+    });
+}
+
+/// Ingest every file currently waiting in the inbox
+pub async fn scan(db: &PgPool, inbox: &dyn Inbox) -> anyhow::Result<Vec<IngestionRun>> {
+    // A run left RUNNING by a crashed worker would otherwise block its file forever
+    sqlx::query!(
+        r#"
+        UPDATE ingestion_runs
+        SET status = 'FAILED', error = 'interrupted before completion', completed_at = NOW()
+        WHERE status = 'RUNNING' AND started_at < NOW() - INTERVAL '1 hour'
+        "#
+    )
+    .execute(db)
+    .await?;
+
+    let mut runs = Vec::new();
+    for file in inbox.list().await? {
+        match ingest(db, inbox, &file).await {
+            Ok(Some(run)) => runs.push(run),
+            Ok(None) => {}
+            Err(e) => error!("Failed to ingest {}: {}", file.location, e),
+        }
+    }
+    Ok(runs)
+}
+
+pub async fn list_runs(db: &PgPool, tenant_id: Uuid, limit: i64) -> anyhow::Result<Vec<IngestionRun>> {
+    Ok(sqlx::query_as!(
+        IngestionRun,
+        r#"
+        SELECT run_id, tenant_id, source_uri, file_name, file_sha256, exchange, file_kind, business_date,
+               status, rows_total, rows_loaded, rows_skipped, rows_rejected, error, started_at, completed_at
+        FROM ingestion_runs
+        WHERE tenant_id = $1
+        ORDER BY started_at DESC
+        LIMIT $2
+        "#,
+        tenant_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?)
+}
+
+pub async fn get_report(db: &PgPool, run_id: Uuid) -> anyhow::Result<Option<IngestionReport>> {
+    let Some(run) = get_run(db, run_id).await? else {
+        return Ok(None);
+    };
+
+    let rejects = sqlx::query_as!(
+        IngestionReject,
+        "SELECT line_number, reason, raw_record FROM ingestion_rejects WHERE run_id = $1 ORDER BY line_number",
+        run_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(Some(IngestionReport { run, rejects }))
+}
+
+async fn get_run(db: &PgPool, run_id: Uuid) -> anyhow::Result<Option<IngestionRun>> {
+    Ok(sqlx::query_as!(
+        IngestionRun,
+        r#"
+        SELECT run_id, tenant_id, source_uri, file_name, file_sha256, exchange, file_kind, business_date,
+               status, rows_total, rows_loaded, rows_skipped, rows_rejected, error, started_at, completed_at
+        FROM ingestion_runs
+        WHERE run_id = $1
+        "#,
+        run_id
+    )
+    .fetch_optional(db)
+    .await?)
+}
+
+/// Ingest one file; `None` when another worker already holds it
+async fn ingest(db: &PgPool, inbox: &dyn Inbox, file: &InboxFile) -> anyhow::Result<Option<IngestionRun>> {
+    let contents = inbox.fetch(file).await?;
+    let file_sha256 = hex::encode(Sha256::digest(&contents));
+    let descriptor = FileDescriptor::from_file_name(&file.name);
+    let (exchange, file_kind, business_date) = match &descriptor {
+        Ok(d) => (Some(d.exchange.as_str()), Some(d.kind.as_str()), Some(d.business_date)),
+        Err(_) => (None, None, None),
+    };
+
+    let previous = sqlx::query!(
+        r#"
+        SELECT COUNT(*) FILTER (WHERE status IN ('COMPLETED', 'PARTIAL')) AS "loaded!",
+               COUNT(*) FILTER (WHERE status = 'FAILED') AS "failed!"
+        FROM ingestion_runs
+        WHERE tenant_id = $1 AND file_sha256 = $2
+        "#,
+        file.tenant_id,
+        file_sha256
+    )
+    .fetch_one(db)
+    .await?;
+
+    if previous.loaded > 0 {
+        let run_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO ingestion_runs (tenant_id, source_uri, file_name, file_sha256, exchange, file_kind,
+                                        business_date, status, error, completed_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, 'DUPLICATE', 'an identical file was already loaded', NOW())
+            RETURNING run_id
+            "#,
+            file.tenant_id,
+            file.location,
+            file.name,
+            file_sha256,
+            exchange,
+            file_kind,
+            business_date
+        )
+        .fetch_one(db)
+        .await?;
+        warn!("Skipping duplicate ingestion file {} for tenant {}", file.name, file.tenant_id);
+        inbox.archive(file, Archive::Rejected).await?;
+        return get_run(db, run_id).await;
+    }
+
+    let run_id = match sqlx::query_scalar!(
+        r#"
+        INSERT INTO ingestion_runs (tenant_id, source_uri, file_name, file_sha256, exchange, file_kind, business_date)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING run_id
+        "#,
+        file.tenant_id,
+        file.location,
+        file.name,
+        file_sha256,
+        exchange,
+        file_kind,
+        business_date
+    )
+    .fetch_one(db)
+    .await
+    {
+        Ok(run_id) => run_id,
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let outcome = match descriptor {
+        Ok(descriptor) => load_file(db, run_id, file.tenant_id, descriptor, &contents).await,
+        Err(reason) => Ok(FileOutcome::Rejected(reason)),
+    };
+
+    match outcome {
+        Ok(FileOutcome::Loaded(summary)) => {
+            let status = if summary.rejects.is_empty() { "COMPLETED" } else { "PARTIAL" };
+            sqlx::query!(
+                r#"
+                UPDATE ingestion_runs
+                SET status = $2, rows_total = $3, rows_loaded = $4, rows_skipped = $5, rows_rejected = $6,
+                    completed_at = NOW()
+                WHERE run_id = $1
+                "#,
+                run_id,
+                status,
+                summary.rows_total,
+                summary.rows_loaded,
+                summary.rows_skipped,
+                summary.rejects.len() as i32
+            )
+            .execute(db)
+            .await?;
+            info!(
+                "Ingested {} for tenant {}: {} loaded, {} skipped, {} rejected",
+                file.name,
+                file.tenant_id,
+                summary.rows_loaded,
+                summary.rows_skipped,
+                summary.rejects.len()
+            );
+            inbox.archive(file, Archive::Processed).await?;
+        }
+        Ok(FileOutcome::Rejected(reason)) => {
+            warn!("Rejected ingestion file {} for tenant {}: {}", file.name, file.tenant_id, reason);
+            finish_unloaded(db, run_id, "REJECTED", &reason).await?;
+            raise_ops_alert(db, run_id, file, &reason).await?;
+            inbox.archive(file, Archive::Rejected).await?;
+        }
+        Err(e) => {
+            // Left in the inbox so the next poll retries, up to MAX_ATTEMPTS
+            error!("Failed to load ingestion file {} for tenant {}: {}", file.name, file.tenant_id, e);
+            finish_unloaded(db, run_id, "FAILED", &e.to_string()).await?;
+            raise_ops_alert(db, run_id, file, &e.to_string()).await?;
+            if previous.failed + 1 >= MAX_ATTEMPTS {
+                inbox.archive(file, Archive::Rejected).await?;
+            }
+        }
+    }
+
+    get_run(db, run_id).await
+}
+
+async fn finish_unloaded(db: &PgPool, run_id: Uuid, status: &str, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE ingestion_runs SET status = $2, error = $3, completed_at = NOW() WHERE run_id = $1",
+        run_id,
+        status,
+        reason
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Parse and validate the file, then load its valid rows in one transaction
+async fn load_file(
+    db: &PgPool,
+    run_id: Uuid,
+    tenant_id: Uuid,
+    descriptor: FileDescriptor,
+    contents: &[u8],
+) -> anyhow::Result<FileOutcome> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(contents);
+
+    let layout = match reader.headers() {
+        Ok(headers) => match Layout::resolve(descriptor, headers) {
+            Ok(layout) => layout,
+            Err(reason) => return Ok(FileOutcome::Rejected(reason)),
+        },
+        Err(e) => return Ok(FileOutcome::Rejected(format!("unreadable header row: {}", e))),
+    };
+
+    let mut rows_total = 0;
+    let mut parsed = Vec::new();
+    let mut rejects = Vec::new();
+    for row in reader.records() {
+        rows_total += 1;
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                rejects.push(IngestionReject {
+                    line_number: e.position().map_or(0, |p| p.line() as i32),
+                    reason: format!("unreadable row: {}", e),
+                    raw_record: None,
+                });
+                continue;
+            }
+        };
+        let line_number = row.position().map_or(0, |p| p.line() as i32);
+        let raw_record = row.iter().collect::<Vec<_>>().join(",");
+        match layout.parse(&row) {
+            Ok(record) => parsed.push((line_number, record, raw_record)),
+            Err(reason) => rejects.push(IngestionReject {
+                line_number,
+                reason,
+                raw_record: Some(raw_record),
+            }),
+        }
+    }
+    if rows_total == 0 {
+        return Ok(FileOutcome::Rejected("file has no data rows".to_string()));
+    }
+
+    let exchange = descriptor.exchange.as_str();
+    let mut tx = db.begin().await?;
+    let mut accounts: HashMap<String, Option<Uuid>> = HashMap::new();
+    let mut instruments: HashMap<String, Option<(Uuid, String)>> = HashMap::new();
+    let mut rows_loaded = 0;
+    let mut rows_skipped = 0;
+
+    for (line_number, record, raw_record) in parsed {
+        let account_number = match &record {
+            Record::Trade(trade) => &trade.account,
+            Record::Position(position) => &position.account,
+            Record::Margin(margin) => &margin.account,
+        };
+        let Some(account_id) = resolve_account(&mut tx, &mut accounts, tenant_id, account_number).await? else {
+            rejects.push(IngestionReject {
+                line_number,
+                reason: format!("unknown or inactive trading account '{}'", account_number),
+                raw_record: Some(raw_record),
+            });
+            continue;
+        };
+
+        let inserted = match &record {
+            Record::Trade(trade) => {
+                let Some((instrument_id, segment)) =
+                    resolve_instrument(&mut tx, &mut instruments, exchange, descriptor.business_date, &trade.instrument)
+                        .await?
+                else {
+                    rejects.push(unknown_instrument(line_number, &trade.instrument, raw_record));
+                    continue;
+                };
+                sqlx::query!(
+                    r#"
+                    INSERT INTO trades (
+                        tenant_id, account_id, instrument_id, order_id, trade_number, trade_type, quantity,
+                        price, value, net_amount, trade_time, exchange, segment, client_code, remarks,
+                        strategy_id, strategy_type, leg_number, leg_count, basket_id
+                    )
+                    SELECT $1, $2, $3, $4, $5, ($6::text)::trade_type, $7,
+                           ($8::text)::numeric, $7 * ($8::text)::numeric, $7 * ($8::text)::numeric,
+                           $9, $10, ($11::text)::market_segment, $12, $13, $14, $15, $16, $17, $18
+                    WHERE NOT EXISTS (
+                        SELECT 1 FROM trades
+                        WHERE tenant_id = $1 AND exchange = $10 AND trade_number = $5 AND trade_time = $9
+                    )
+                    "#,
+                    tenant_id,
+                    account_id,
+                    instrument_id,
+                    trade.order_number,
+                    trade.trade_number,
+                    trade.trade_type,
+                    trade.quantity,
+                    trade.price,
+                    trade.trade_time,
+                    exchange,
+                    segment,
+                    trade.client_code,
+                    format!("EOD file ingestion run {}", run_id),
+                    trade.strategy.as_ref().map(|leg| leg.strategy_id.clone()),
+                    trade.strategy.as_ref().and_then(|leg| leg.strategy_type.clone()),
+                    trade.strategy.as_ref().and_then(|leg| leg.leg_number),
+                    trade.strategy.as_ref().and_then(|leg| leg.leg_count),
+                    trade.basket_id
+                )
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                    > 0
+            }
+            Record::Position(position) => {
+                let Some((instrument_id, _)) =
+                    resolve_instrument(&mut tx, &mut instruments, exchange, descriptor.business_date, &position.instrument)
+                        .await?
+                else {
+                    rejects.push(unknown_instrument(line_number, &position.instrument, raw_record));
+                    continue;
+                };
+                // The exchange's end-of-day position is authoritative over the trade-derived one
+                sqlx::query!(
+                    r#"
+                    INSERT INTO positions (
+                        tenant_id, account_id, instrument_id, net_quantity, average_price, last_trade_price,
+                        market_value, last_updated
+                    )
+                    VALUES ($1, $2, $3, $4, ($5::text)::numeric, ($6::text)::numeric,
+                            $4 * COALESCE(($6::text)::numeric, 0), NOW())
+                    ON CONFLICT (account_id, instrument_id) DO UPDATE SET
+                        net_quantity = EXCLUDED.net_quantity,
+                        average_price = EXCLUDED.average_price,
+                        last_trade_price = COALESCE(EXCLUDED.last_trade_price, positions.last_trade_price),
+                        market_value = EXCLUDED.net_quantity
+                            * COALESCE(EXCLUDED.last_trade_price, positions.last_trade_price, 0),
+                        last_updated = NOW()
+                    "#,
+                    tenant_id,
+                    account_id,
+                    instrument_id,
+                    position.net_quantity,
+                    position.average_price,
+                    position.close_price
+                )
+                .execute(&mut *tx)
+                .await?;
+                true
+            }
+            Record::Margin(margin) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO margin_statements (
+                        tenant_id, account_id, exchange, business_date, initial_margin, exposure_margin,
+                        total_margin, collateral_available, ingestion_run_id, peak_margin
+                    )
+                    VALUES ($1, $2, $3, $4, ($5::text)::numeric, ($6::text)::numeric, ($7::text)::numeric,
+                            ($8::text)::numeric, $9, ($10::text)::numeric)
+                    ON CONFLICT (account_id, exchange, business_date) DO UPDATE SET
+                        initial_margin = EXCLUDED.initial_margin,
+                        exposure_margin = EXCLUDED.exposure_margin,
+                        total_margin = EXCLUDED.total_margin,
+                        collateral_available = EXCLUDED.collateral_available,
+                        peak_margin = EXCLUDED.peak_margin,
+                        ingestion_run_id = EXCLUDED.ingestion_run_id,
+                        updated_at = NOW()
+                    "#,
+                    tenant_id,
+                    account_id,
+                    exchange,
+                    descriptor.business_date,
+                    margin.initial_margin,
+                    margin.exposure_margin,
+                    margin.total_margin,
+                    margin.collateral,
+                    run_id,
+                    margin.peak_margin
+                )
+                .execute(&mut *tx)
+                .await?;
+                true
+            }
+        };
+
+        if inserted {
+            rows_loaded += 1;
+        } else {
+            rows_skipped += 1;
+        }
+    }
+
+    for reject in &rejects {
+        sqlx::query!(
+            "INSERT INTO ingestion_rejects (run_id, line_number, reason, raw_record) VALUES ($1, $2, $3, $4)",
+            run_id,
+            reject.line_number,
+            reject.reason,
+            reject.raw_record
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(FileOutcome::Loaded(LoadSummary {
+        rows_total,
+        rows_loaded,
+        rows_skipped,
+        rejects,
+    }))
+}
+
+async fn resolve_account(
+    tx: &mut Transaction<'_, Postgres>,
+    cache: &mut HashMap<String, Option<Uuid>>,
+    tenant_id: Uuid,
+    account_number: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    if let Some(account_id) = cache.get(account_number) {
+        return Ok(*account_id);
+    }
+    let account_id = sqlx::query_scalar!(
+        "SELECT account_id FROM trading_accounts WHERE tenant_id = $1 AND account_number = $2 AND is_active = TRUE",
+        tenant_id,
+        account_number
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    cache.insert(account_number.to_string(), account_id);
+    Ok(account_id)
+}
+
+/// Symbols and ISINs are resolved as they were on the file's business date, so
+/// a late file sent after a symbol change still loads against the right instrument
+async fn resolve_instrument(
+    tx: &mut Transaction<'_, Postgres>,
+    cache: &mut HashMap<String, Option<(Uuid, String)>>,
+    exchange: &str,
+    business_date: NaiveDate,
+    instrument: &InstrumentRef,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let key = match (&instrument.isin, &instrument.symbol) {
+        (Some(isin), _) => format!("isin:{}", isin),
+        (None, Some(symbol)) => format!("symbol:{}", symbol),
+        (None, None) => return Ok(None),
+    };
+    if let Some(resolved) = cache.get(&key) {
+        return Ok(resolved.clone());
+    }
+
+    let resolved = sqlx::query!(
+        r#"
+        SELECT i.instrument_id, i.segment::text AS "segment!"
+        FROM instruments i
+        JOIN instrument_versions v ON v.instrument_id = i.instrument_id
+        WHERE i.exchange = $1 AND v.is_active = TRUE
+          AND (($2::text IS NOT NULL AND v.isin = $2) OR ($2::text IS NULL AND v.symbol = $3))
+          AND (v.effective_from IS NULL OR v.effective_from <= $4)
+          AND (v.effective_to IS NULL OR v.effective_to > $4)
+        LIMIT 1
+        "#,
+        exchange,
+        instrument.isin,
+        instrument.symbol,
+        business_date
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .map(|row| (row.instrument_id, row.segment));
+
+    cache.insert(key, resolved.clone());
+    Ok(resolved)
+}
+
+fn unknown_instrument(line_number: i32, instrument: &InstrumentRef, raw_record: String) -> IngestionReject {
+    let identifier = instrument.isin.as_deref().or(instrument.symbol.as_deref()).unwrap_or_default();
+    IngestionReject {
+        line_number,
+        reason: format!("unknown or inactive instrument '{}'", identifier),
+        raw_record: Some(raw_record),
+    }
+}
+
+async fn raise_ops_alert(db: &PgPool, run_id: Uuid, file: &InboxFile, reason: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO system_events (event_type, severity, source_system, message, details, correlation_id)
+        VALUES ('INGESTION_FAILED', 'ERROR', 'compliance-service', $1, $2, $3)
+        "#,
+        format!("EOD file {} could not be ingested: {}", file.name, reason),
+        serde_json::json!({ "tenant_id": file.tenant_id, "location": file.location }),
+        run_id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
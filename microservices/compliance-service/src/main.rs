@@ -2,23 +2,74 @@
 //! Handles regulatory compliance, SEBI reporting, and violation management
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    middleware,
     response::Json,
     routing::{get, post, patch},
     Router,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::{PgPool, postgres::PgPoolOptions};
+use sqlx::PgPool;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tracing::{info, error};
+use tracing::{info, error, warn};
 use uuid::Uuid;
+use dharmaguard_common::approval;
+use dharmaguard_common::business_hours::{self, CalendarConfig};
+use dharmaguard_common::embargo::{self, Access, Attempt};
+use dharmaguard_common::pool::{self, Admission, PoolSettings};
+use dharmaguard_common::startup::{self, Startup};
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::versioning;
+
+mod alert_closure;
+mod analytics;
+mod benchmarks;
+mod client_master;
+mod evidence;
+mod ingestion;
+mod reference;
+mod sla;
+mod taxonomy;
+
+use crate::alert_closure::{AutoCloser, AutoClosure, ClosureParams, ClosurePreview, ClosureRule, ClosureRun, RuleRequest, SaveOutcome};
+use crate::benchmarks::{BenchmarkParams, BenchmarkView, Benchmarks, Consent, ConsentRequest};
+use crate::evidence::search::{CaseHit, SearchParams, ViolationHit};
+use crate::evidence::{Attachment, Evidence, EvidenceTarget, Upload};
+use crate::ingestion::{inbox::Inbox, IngestionReport, IngestionRun};
+use crate::reference::{AsOfParams, InstrumentVersion, RecordOutcome, ResolveParams, VersionRequest};
+use crate::sla::{SlaParams, SlaTargets, ViolationSla};
+use crate::taxonomy::{TenantTaxonomy, ValidationResponse};
+
+/// Shared migrations the service depends on, each with a relation it creates
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("009_tenant_taxonomies", "tenant_severity_levels"),
+    ("010_eod_file_ingestion", "ingestion_runs"),
+    ("014_analytics_sandbox", "analytics_query_log"),
+    ("019_evidence_attachments", "evidence_attachments"),
+    ("021_business_hours", "tenant_business_calendars"),
+    ("029_alert_auto_closure", "alert_closure_rules"),
+    ("033_instrument_versions", "instrument_versions"),
+    ("034_report_embargoes", "report_embargoes"),
+    ("061_report_reviews", "report_status_transitions"),
+    ("072_peak_margins", "idx_margin_statements_tenant_date"),
+];
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
     pub sebi_client: SebiClient,
+    /// End-of-day file inbox; ingestion is disabled when INGESTION_INBOX is unset
+    pub inbox: Option<Arc<dyn Inbox>>,
+    /// Analytics SQL sandbox; disabled when JWT_SECRET is unset
+    pub analytics: Option<Arc<analytics::Sandbox>>,
+    /// Evidence uploads; disabled when EVIDENCE_STORE is unset, search still works
+    pub evidence: Option<Arc<Evidence>>,
+    pub sla_targets: Arc<SlaTargets>,
+    pub auto_closer: Arc<AutoCloser>,
+    /// Cross-tenant benchmarks; disabled unless BENCHMARKS_ENABLED=true, consent is still recorded
+    pub benchmarks: Option<Arc<Benchmarks>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -41,6 +92,41 @@ pub struct GenerateReportRequest {
     pub tenant_id: Uuid,
 }
 
+/// Violation ingest; severity may be a platform level or a tenant severity code
+#[derive(Deserialize)]
+pub struct CreateViolationRequest {
+    pub tenant_id: Uuid,
+    pub alert_id: Option<Uuid>,
+    pub violation_type: String,
+    pub severity: Option<String>,
+    pub tenant_severity: Option<String>,
+    pub tenant_category: Option<String>,
+    pub description: String,
+    pub regulatory_reference: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct EvidenceParams {
+    pub tenant_id: Uuid,
+    pub uploaded_by: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct ClientMasterParams {
+    pub tenant_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct IngestionRunsParams {
+    pub tenant_id: Uuid,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ClosureRunsParams {
+    pub limit: Option<i64>,
+}
+
 #[derive(Clone)]
 pub struct SebiClient {
     client: reqwest::Client,
@@ -84,31 +170,144 @@ async fn main() -> anyhow::Result<()> {
     let sebi_api_key = std::env::var("SEBI_API_KEY")
         .expect("SEBI_API_KEY must be set");
 
-    let pool = PgPoolOptions::new()
-        .max_connections(20)
-        .connect(&database_url)
-        .await?;
+    dharmaguard_common::metrics::install();
+    let pool_settings = PoolSettings::from_env();
+    let pool = pool_settings.connect(&database_url).await?;
+    let admission = Admission::new("compliance", pool_settings);
+    admission.spawn_autoscaler(pool.clone());
 
     let sebi_client = SebiClient::new(sebi_api_key);
 
+    let inbox: Option<Arc<dyn Inbox>> = match std::env::var("INGESTION_INBOX") {
+        Ok(uri) if !uri.is_empty() => Some(Arc::from(ingestion::inbox::from_uri(&uri).await?)),
+        _ => None,
+    };
+    if let Some(inbox) = &inbox {
+        let poll_seconds = std::env::var("INGESTION_POLL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(300);
+        ingestion::spawn_poller(pool.clone(), inbox.clone(), std::time::Duration::from_secs(poll_seconds));
+        info!("Polling ingestion inbox {} every {}s", inbox.uri(), poll_seconds);
+    }
+
+    let analytics = analytics::Sandbox::from_env().map(Arc::new);
+    if analytics.is_none() {
+        warn!("JWT_SECRET is not set; the analytics sandbox is disabled");
+    }
+
+    let evidence = Evidence::from_env(pool.clone()).await?.map(Arc::new);
+    let evidence_limit = evidence.as_ref().map_or(25 * 1024 * 1024, |evidence| evidence.max_bytes);
+    match &evidence {
+        Some(evidence) => {
+            let poll_seconds = std::env::var("EVIDENCE_EXTRACTION_POLL_SECONDS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(15);
+            evidence::spawn_worker(evidence.clone(), std::time::Duration::from_secs(poll_seconds));
+            match &evidence.ocr {
+                Some(ocr) => info!("Storing evidence in {} with OCR by {}", evidence.store.uri(), ocr.name()),
+                None => warn!("EVIDENCE_OCR_BACKEND is not set; images and scanned PDFs will not be text-searchable"),
+            }
+        }
+        None => warn!("EVIDENCE_STORE is not set; evidence uploads are disabled"),
+    }
+
+    let benchmarks = Benchmarks::from_env().map(Arc::new);
+    if benchmarks.is_none() {
+        warn!("BENCHMARKS_ENABLED is not set; industry benchmarks are disabled");
+    }
+
+    let auto_closer = Arc::new(AutoCloser::from_env(pool.clone()));
+    let closure_poll_seconds = std::env::var("ALERT_CLOSURE_POLL_SECONDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(3600);
+    alert_closure::spawn_worker(auto_closer.clone(), std::time::Duration::from_secs(closure_poll_seconds));
+    info!("Applying alert auto-closure rules every {}s", closure_poll_seconds);
+
+    let instrument_sync_seconds = std::env::var("INSTRUMENT_SYNC_SECONDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(3600);
+    reference::spawn_worker(pool.clone(), std::time::Duration::from_secs(instrument_sync_seconds));
+    info!("Applying instrument reference data versions every {}s", instrument_sync_seconds);
+
     let app_state = AppState {
         db: pool,
         sebi_client,
+        inbox,
+        analytics,
+        evidence,
+        sla_targets: Arc::new(SlaTargets::from_env()?),
+        auto_closer,
+        benchmarks,
     };
 
-    let app = Router::new()
-        .route("/health", get(health_check))
+    let api_v1 = Router::new()
         .route("/reports", post(generate_report).get(list_reports))
         .route("/reports/:id", get(get_report))
         .route("/reports/:id/submit", post(submit_report))
-        .route("/violations", get(list_violations))
+        .route("/violations", get(list_violations).post(create_violation))
+        .route("/violations/search", get(search_violations))
+        .route("/violations/sla", get(list_violation_slas))
+        .route(
+            "/violations/:violation_id/evidence",
+            get(list_violation_evidence)
+                .post(upload_violation_evidence)
+                .layer(DefaultBodyLimit::max(evidence_limit)),
+        )
+        .route("/cases/search", get(search_cases))
+        .route(
+            "/cases/:investigation_id/evidence",
+            get(list_case_evidence)
+                .post(upload_case_evidence)
+                .layer(DefaultBodyLimit::max(evidence_limit)),
+        )
+        .route("/evidence/:attachment_id/text", get(get_evidence_text))
+        .route(
+            "/clients/master/validate",
+            post(validate_client_master).layer(DefaultBodyLimit::max(client_master::MAX_FILE_BYTES)),
+        )
+        .route("/tenants/:tenant_id/taxonomy", get(get_taxonomy).put(replace_taxonomy))
+        .route("/tenants/:tenant_id/business-hours", get(get_business_hours).put(replace_business_hours))
+        .route("/tenants/:tenant_id/alert-closure-rules", get(list_closure_rules).post(create_closure_rule))
+        .route(
+            "/tenants/:tenant_id/alert-closure-rules/:rule_id",
+            get(get_closure_rule).put(replace_closure_rule).delete(delete_closure_rule),
+        )
+        .route("/tenants/:tenant_id/alert-closure-rules/:rule_id/preview", get(preview_closure_rule))
+        .route("/tenants/:tenant_id/alert-closures", get(list_alert_closures))
+        .route("/tenants/:tenant_id/benchmarks", get(get_benchmarks))
+        .route("/tenants/:tenant_id/benchmarks/consent", get(get_benchmark_consent).put(set_benchmark_consent))
+        .route("/instruments/resolve", get(resolve_instrument))
+        .route("/instruments/:instrument_id/versions", get(list_instrument_versions).post(record_instrument_version))
+        .route("/instruments/:instrument_id/as-of", get(get_instrument_as_of))
+        .route("/alert-closure/runs", get(list_closure_runs).post(run_alert_closure))
+        .route("/ingestion/runs", get(list_ingestion_runs))
+        .route("/ingestion/runs/:run_id", get(get_ingestion_run))
+        .route("/ingestion/scan", post(scan_ingestion_inbox))
+        .route("/analytics/query", post(analytics::run_query))
+        .route("/analytics/queries", get(analytics::list_queries));
+
+    // API routes answer 503 until these pass; /health, /ready and /metrics answer from the start
+    let startup = Startup::new("compliance").migrations(app_state.db.clone(), MIGRATIONS);
+    let readiness = startup.readiness();
+
+    let app = versioning::versioned("compliance", api_v1)
+        .route_layer(middleware::from_fn_with_state(admission, pool::admit))
+        .route_layer(middleware::from_fn_with_state(readiness.clone(), startup::gate))
+        .route("/health", get(health_check))
+        .merge(startup::router(readiness))
+        .merge(dharmaguard_common::metrics::router())
         .with_state(app_state);
 
     let listener = TcpListener::bind("0.0.0.0:8082").await?;
     info!("Compliance service listening on port 8082");
-    
-    axum::serve(listener, app).await?;
-    Ok(())
+
+    startup
+        .serve(async { axum::serve(listener, app).await.map_err(anyhow::Error::from) })
+        .await
 }
 
 async fn health_check() -> Json<serde_json::Value> {
@@ -158,8 +357,40 @@ async fn generate_report(
 
 async fn submit_report(
     Path(report_id): Path<Uuid>,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    match embargo::guard(&state.db, report_id, Access::Submit, &Attempt::from_headers(&headers)).await {
+        Ok(None) => {}
+        Ok(Some(until)) => {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(serde_json::json!({"error": "report is under embargo", "available_at": until})),
+            ))
+        }
+        Err(e) => {
+            error!("Failed to check embargo of report {}: {}", report_id, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))));
+        }
+    }
+    // Only reports a reviewer has approved are filed
+    match approval::status(&state.db, report_id).await {
+        Ok(Some(status)) if status == approval::APPROVED => {}
+        Ok(Some(status)) => {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({
+                    "error": format!("report is {}; only APPROVED reports can be submitted", status),
+                    "status": status,
+                })),
+            ))
+        }
+        Ok(None) => return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "report not found"})))),
+        Err(e) => {
+            error!("Failed to check approval of report {}: {}", report_id, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))));
+        }
+    }
     // Get report from database
     let report = match sqlx::query_as!(
         ComplianceReport,
@@ -169,7 +400,7 @@ async fn submit_report(
     .fetch_one(&state.db)
     .await {
         Ok(report) => report,
-        Err(_) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "report not found"})))),
     };
 
     // Submit to SEBI
@@ -184,14 +415,14 @@ async fn submit_report(
             )
             .execute(&state.db)
             .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "failed to record submission"}))))?;
 
             Ok(Json(serde_json::json!({
                 "status": "submitted",
                 "sebi_reference": reference
             })))
         },
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "submission to SEBI failed"})))),
     }
 }
 
@@ -244,6 +475,711 @@ async fn list_violations(State(state): State<AppState>) -> Result<Json<Vec<serde
     }
 }
 
+async fn create_violation(
+    State(state): State<AppState>,
+    Json(request): Json<CreateViolationRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ValidationResponse>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to create violation: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ValidationResponse::rejected(vec!["internal error while creating violation".to_string()])),
+        )
+    };
+
+    let classification = taxonomy::classify(
+        &state.db,
+        request.tenant_id,
+        request.severity.as_deref(),
+        request.tenant_severity.as_deref(),
+        request.tenant_category.as_deref(),
+    )
+    .await
+    .map_err(internal_error)?
+    .map_err(|errors| {
+        warn!("Rejected violation for tenant {}: {:?}", request.tenant_id, errors);
+        (StatusCode::UNPROCESSABLE_ENTITY, Json(ValidationResponse::rejected(errors)))
+    })?;
+
+    let calendar = business_hours::load(&state.db, request.tenant_id)
+        .await
+        .map_err(internal_error)?;
+    let created_at = chrono::Utc::now();
+    let sla_due_at = state
+        .sla_targets
+        .due_at(&calendar, created_at, &classification.platform_severity);
+
+    let violation_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO compliance_violations (
+            tenant_id, alert_id, violation_type, severity, tenant_severity, tenant_category,
+            description, regulatory_reference, created_at, sla_due_at
+        )
+        VALUES ($1, $2, $3, ($4::text)::alert_severity, $5, $6, $7, $8, $9, $10)
+        RETURNING violation_id
+        "#,
+        request.tenant_id,
+        request.alert_id,
+        request.violation_type,
+        classification.platform_severity,
+        classification.tenant_severity,
+        classification.tenant_category,
+        request.description,
+        request.regulatory_reference,
+        created_at,
+        sla_due_at
+    )
+    .fetch_one(&state.db)
+    .await
+    .map_err(|e| internal_error(e.into()))?;
+
+    Ok(Json(serde_json::json!({
+        "violation_id": violation_id,
+        "severity": classification.platform_severity,
+        "tenant_severity": classification.tenant_severity,
+        "tenant_category": classification.tenant_category,
+        "sla_due_at": sla_due_at
+    })))
+}
+
+async fn list_violation_slas(
+    Query(params): Query<SlaParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ViolationSla>>, StatusCode> {
+    match sla::open_violations(&state.db, &params).await {
+        Ok(violations) => Ok(Json(violations)),
+        Err(e) => {
+            error!("Failed to compute violation SLAs for tenant {}: {}", params.tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_taxonomy(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<TenantTaxonomy>, StatusCode> {
+    match taxonomy::load_taxonomy(&state.db, tenant_id).await {
+        Ok(taxonomy) => Ok(Json(taxonomy)),
+        Err(e) => {
+            error!("Failed to load taxonomy for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn replace_taxonomy(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(request): Json<TenantTaxonomy>,
+) -> Result<Json<TenantTaxonomy>, (StatusCode, Json<ValidationResponse>)> {
+    let errors = taxonomy::validate_taxonomy(&request);
+    if !errors.is_empty() {
+        warn!("Rejected taxonomy for tenant {}: {:?}", tenant_id, errors);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ValidationResponse::rejected(errors))));
+    }
+
+    match taxonomy::replace_taxonomy(&state.db, tenant_id, &request).await {
+        Ok(()) => {
+            info!("Replaced severity and category taxonomy for tenant: {}", tenant_id);
+            Ok(Json(request))
+        }
+        Err(e) => {
+            error!("Failed to replace taxonomy for tenant {}: {}", tenant_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ValidationResponse::rejected(vec!["internal error while saving taxonomy".to_string()])),
+            ))
+        }
+    }
+}
+
+async fn get_business_hours(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<CalendarConfig>, StatusCode> {
+    match business_hours::load_config(&state.db, tenant_id).await {
+        Ok(config) => Ok(Json(config)),
+        Err(e) => {
+            error!("Failed to load business hours for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Replace the tenant's sessions and holidays; SLA deadlines already set are kept
+async fn replace_business_hours(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(mut request): Json<CalendarConfig>,
+) -> Result<Json<CalendarConfig>, (StatusCode, Json<ValidationResponse>)> {
+    let errors = business_hours::validate_config(&request);
+    if !errors.is_empty() {
+        warn!("Rejected business hours for tenant {}: {:?}", tenant_id, errors);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ValidationResponse::rejected(errors))));
+    }
+
+    match business_hours::replace_config(&state.db, tenant_id, &request).await {
+        Ok(()) => {
+            info!("Replaced business hours for tenant: {}", tenant_id);
+            request.configured = true;
+            Ok(Json(request))
+        }
+        Err(e) => {
+            error!("Failed to replace business hours for tenant {}: {}", tenant_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ValidationResponse::rejected(vec!["internal error while saving business hours".to_string()])),
+            ))
+        }
+    }
+}
+
+async fn list_closure_rules(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ClosureRule>>, StatusCode> {
+    match alert_closure::list_rules(&state.db, &tenant).await {
+        Ok(rules) => Ok(Json(rules)),
+        Err(e) => {
+            error!("Failed to list alert closure rules for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_closure_rule(
+    tenant: TenantContext,
+    Path((_, rule_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+) -> Result<Json<ClosureRule>, StatusCode> {
+    match alert_closure::get_rule(&state.db, &tenant, rule_id).await {
+        Ok(Some(rule)) => Ok(Json(rule)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load alert closure rule {}: {}", rule_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn create_closure_rule(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    Json(request): Json<RuleRequest>,
+) -> Result<Json<ClosureRule>, (StatusCode, Json<ValidationResponse>)> {
+    save_closure_rule(&state.db, &tenant, None, &request).await
+}
+
+/// Replace a rule's definition; alerts it already closed stay closed
+async fn replace_closure_rule(
+    tenant: TenantContext,
+    Path((_, rule_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    Json(request): Json<RuleRequest>,
+) -> Result<Json<ClosureRule>, (StatusCode, Json<ValidationResponse>)> {
+    save_closure_rule(&state.db, &tenant, Some(rule_id), &request).await
+}
+
+async fn save_closure_rule(
+    db: &PgPool,
+    tenant: &TenantContext,
+    rule_id: Option<Uuid>,
+    request: &RuleRequest,
+) -> Result<Json<ClosureRule>, (StatusCode, Json<ValidationResponse>)> {
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to save alert closure rule for tenant {}: {}", tenant, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ValidationResponse::rejected(vec!["internal error while saving closure rule".to_string()])),
+        )
+    };
+
+    let errors = alert_closure::validate_rule(db, tenant, request)
+        .await
+        .map_err(internal_error)?;
+    if !errors.is_empty() {
+        warn!("Rejected alert closure rule for tenant {}: {:?}", tenant, errors);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ValidationResponse::rejected(errors))));
+    }
+
+    let outcome = match rule_id {
+        Some(rule_id) => alert_closure::replace_rule(db, tenant, rule_id, request).await,
+        None => alert_closure::create_rule(db, tenant, request).await,
+    }
+    .map_err(internal_error)?;
+    match outcome {
+        SaveOutcome::Saved(rule) => {
+            info!("Saved alert closure rule {} for tenant: {}", rule.rule_id, tenant);
+            Ok(Json(rule))
+        }
+        SaveOutcome::NameTaken => Err((
+            StatusCode::CONFLICT,
+            Json(ValidationResponse::rejected(vec![format!("a closure rule named '{}' already exists", request.name.trim())])),
+        )),
+        SaveOutcome::NotFound => Err((
+            StatusCode::NOT_FOUND,
+            Json(ValidationResponse::rejected(vec!["closure rule not found".to_string()])),
+        )),
+    }
+}
+
+async fn delete_closure_rule(
+    tenant: TenantContext,
+    Path((_, rule_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+) -> StatusCode {
+    match alert_closure::delete_rule(&state.db, &tenant, rule_id).await {
+        Ok(true) => {
+            info!("Deleted alert closure rule {} for tenant: {}", rule_id, tenant);
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!("Failed to delete alert closure rule {}: {}", rule_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+async fn preview_closure_rule(
+    tenant: TenantContext,
+    Path((_, rule_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+) -> Result<Json<ClosurePreview>, StatusCode> {
+    match alert_closure::preview(&state.db, &tenant, rule_id).await {
+        Ok(Some(preview)) => Ok(Json(preview)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to preview alert closure rule {}: {}", rule_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_alert_closures(
+    tenant: TenantContext,
+    Query(params): Query<ClosureParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AutoClosure>>, StatusCode> {
+    match alert_closure::list_closures(&state.db, &tenant, &params).await {
+        Ok(closures) => Ok(Json(closures)),
+        Err(e) => {
+            error!("Failed to list alert auto-closures for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_closure_runs(
+    Query(params): Query<ClosureRunsParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ClosureRun>>, StatusCode> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 500);
+    match alert_closure::list_runs(&state.db, limit).await {
+        Ok(runs) => Ok(Json(runs)),
+        Err(e) => {
+            error!("Failed to list alert closure runs: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Apply the closure rules now instead of on the next poll
+async fn run_alert_closure(State(state): State<AppState>) -> Result<Json<ClosureRun>, StatusCode> {
+    match alert_closure::run(&state.auto_closer, "MANUAL").await {
+        Ok(run) => Ok(Json(run)),
+        Err(e) => {
+            error!("Alert auto-closure run failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_benchmark_consent(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Consent>, StatusCode> {
+    match benchmarks::consent(&state.db, &tenant).await {
+        Ok(consent) => Ok(Json(consent)),
+        Err(e) => {
+            error!("Failed to load benchmark consent for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Opt in to or withdraw from benchmark sharing; released benchmarks are not recomputed
+async fn set_benchmark_consent(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    Json(request): Json<ConsentRequest>,
+) -> Result<Json<Consent>, StatusCode> {
+    match benchmarks::set_consent(&state.db, &tenant, &request).await {
+        Ok(consent) => {
+            info!("Benchmark sharing for tenant {} is now {}", tenant, if consent.opted_in { "on" } else { "off" });
+            Ok(Json(consent))
+        }
+        Err(e) => {
+            error!("Failed to update benchmark consent for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The month's industry benchmarks; only tenants that share their own data see them
+async fn get_benchmarks(
+    tenant: TenantContext,
+    Query(params): Query<BenchmarkParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BenchmarkView>>, (StatusCode, Json<ValidationResponse>)> {
+    let reject = |status: StatusCode, message: String| (status, Json(ValidationResponse::rejected(vec![message])));
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to build benchmarks for tenant {}: {}", tenant, e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "internal error while building benchmarks".to_string())
+    };
+
+    let Some(settings) = &state.benchmarks else {
+        return Err(reject(StatusCode::SERVICE_UNAVAILABLE, "industry benchmarks are not enabled".to_string()));
+    };
+    let month = benchmarks::parse_month(&params.month, chrono::Utc::now().date_naive())
+        .map_err(|message| reject(StatusCode::UNPROCESSABLE_ENTITY, message))?;
+    if !benchmarks::consent(&state.db, &tenant).await.map_err(internal_error)?.opted_in {
+        return Err(reject(
+            StatusCode::FORBIDDEN,
+            "benchmarks are only available to tenants sharing their own data".to_string(),
+        ));
+    }
+
+    benchmarks::benchmarks_for(settings, &state.db, &tenant, month)
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// Every version of an instrument's reference data, oldest first
+async fn list_instrument_versions(
+    Path(instrument_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<InstrumentVersion>>, StatusCode> {
+    match reference::history(&state.db, instrument_id).await {
+        Ok(Some(versions)) => Ok(Json(versions)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to list versions of instrument {}: {}", instrument_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Record a symbol change, corporate action or revision taking effect on a date
+async fn record_instrument_version(
+    Path(instrument_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(request): Json<VersionRequest>,
+) -> Result<Json<InstrumentVersion>, (StatusCode, Json<ValidationResponse>)> {
+    let reject = |status: StatusCode, message: String| (status, Json(ValidationResponse::rejected(vec![message])));
+
+    let errors = request.validate();
+    if !errors.is_empty() {
+        warn!("Rejected reference data version for instrument {}: {:?}", instrument_id, errors);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ValidationResponse::rejected(errors))));
+    }
+
+    match reference::record(&state.db, instrument_id, &request).await {
+        Ok(RecordOutcome::Recorded(version)) => {
+            info!(
+                "Recorded {} for instrument {} effective {}",
+                version.change_type, instrument_id, request.effective_from
+            );
+            Ok(Json(version))
+        }
+        Ok(RecordOutcome::Conflict(message)) => Err(reject(StatusCode::CONFLICT, message)),
+        Ok(RecordOutcome::NotFound) => Err(reject(StatusCode::NOT_FOUND, "instrument not found".to_string())),
+        Err(e) => {
+            error!("Failed to record reference data version for instrument {}: {}", instrument_id, e);
+            Err(reject(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal error while recording reference data version".to_string(),
+            ))
+        }
+    }
+}
+
+/// The instrument as it was on a date, today by default
+async fn get_instrument_as_of(
+    Path(instrument_id): Path<Uuid>,
+    Query(params): Query<AsOfParams>,
+    State(state): State<AppState>,
+) -> Result<Json<InstrumentVersion>, StatusCode> {
+    let day = params.date.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    match reference::as_of(&state.db, instrument_id, day).await {
+        Ok(Some(version)) => Ok(Json(version)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to resolve instrument {} as of {}: {}", instrument_id, day, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Find the instrument that traded under a symbol or ISIN on a date
+async fn resolve_instrument(
+    Query(params): Query<ResolveParams>,
+    State(state): State<AppState>,
+) -> Result<Json<InstrumentVersion>, (StatusCode, Json<ValidationResponse>)> {
+    let reject = |status: StatusCode, message: String| (status, Json(ValidationResponse::rejected(vec![message])));
+
+    if params.symbol.is_none() && params.isin.is_none() {
+        return Err(reject(StatusCode::UNPROCESSABLE_ENTITY, "symbol or isin is required".to_string()));
+    }
+    match reference::resolve(&state.db, &params).await {
+        Ok(Some(version)) => Ok(Json(version)),
+        Ok(None) => Err(reject(
+            StatusCode::NOT_FOUND,
+            format!("no instrument on {} matched on {}", params.exchange, params.date),
+        )),
+        Err(e) => {
+            error!("Failed to resolve instrument on {} as of {}: {}", params.exchange, params.date, e);
+            Err(reject(StatusCode::INTERNAL_SERVER_ERROR, "internal error while resolving instrument".to_string()))
+        }
+    }
+}
+
+async fn list_ingestion_runs(
+    Query(params): Query<IngestionRunsParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<IngestionRun>>, StatusCode> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 500);
+    match ingestion::list_runs(&state.db, params.tenant_id, limit).await {
+        Ok(runs) => Ok(Json(runs)),
+        Err(e) => {
+            error!("Failed to list ingestion runs for tenant {}: {}", params.tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_ingestion_run(
+    Path(run_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<IngestionReport>, StatusCode> {
+    match ingestion::get_report(&state.db, run_id).await {
+        Ok(Some(report)) => Ok(Json(report)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load ingestion run {}: {}", run_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Ingest waiting files now instead of on the next poll
+async fn scan_ingestion_inbox(State(state): State<AppState>) -> Result<Json<Vec<IngestionRun>>, StatusCode> {
+    let Some(inbox) = &state.inbox else {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    };
+    match ingestion::scan(&state.db, inbox.as_ref()).await {
+        Ok(runs) => Ok(Json(runs)),
+        Err(e) => {
+            error!("Failed to scan ingestion inbox {}: {}", inbox.uri(), e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn upload_violation_evidence(
+    Path(violation_id): Path<Uuid>,
+    Query(params): Query<EvidenceParams>,
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<Json<Attachment>, (StatusCode, Json<serde_json::Value>)> {
+    upload_evidence(state, params, EvidenceTarget::Violation(violation_id), multipart).await
+}
+
+async fn upload_case_evidence(
+    Path(investigation_id): Path<Uuid>,
+    Query(params): Query<EvidenceParams>,
+    State(state): State<AppState>,
+    multipart: Multipart,
+) -> Result<Json<Attachment>, (StatusCode, Json<serde_json::Value>)> {
+    upload_evidence(state, params, EvidenceTarget::Case(investigation_id), multipart).await
+}
+
+/// Store the multipart `file` field and queue it for text extraction
+async fn upload_evidence(
+    state: AppState,
+    params: EvidenceParams,
+    target: EvidenceTarget,
+    mut multipart: Multipart,
+) -> Result<Json<Attachment>, (StatusCode, Json<serde_json::Value>)> {
+    let reject = |status: StatusCode, message: String| (status, Json(serde_json::json!({"error": message})));
+
+    let Some(evidence) = state.evidence.clone() else {
+        return Err(reject(StatusCode::SERVICE_UNAVAILABLE, "evidence storage is not configured".to_string()));
+    };
+
+    let mut file = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| reject(StatusCode::BAD_REQUEST, e.body_text()))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+        // Only the last path component of the client's name, within the column width
+        let file_name: String = field
+            .file_name()
+            .and_then(|name| name.rsplit(['/', '\\']).next())
+            .filter(|name| !name.is_empty())
+            .unwrap_or("evidence")
+            .chars()
+            .take(255)
+            .collect();
+        let declared = field.content_type().map(str::to_string);
+        let bytes = field.bytes().await.map_err(|e| reject(StatusCode::BAD_REQUEST, e.body_text()))?;
+        file = Some((file_name, declared, bytes));
+    }
+
+    let Some((file_name, declared, bytes)) = file.filter(|(_, _, bytes)| !bytes.is_empty()) else {
+        return Err(reject(StatusCode::UNPROCESSABLE_ENTITY, "a non-empty file field is required".to_string()));
+    };
+    let Some(content_type) = evidence::detect_content_type(&bytes, declared.as_deref()) else {
+        return Err(reject(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "evidence must be a PDF, PNG, JPEG, TIFF or plain text file".to_string(),
+        ));
+    };
+
+    let internal_error = |e: anyhow::Error| {
+        error!("Failed to attach evidence to {:?}: {:#}", target, e);
+        reject(StatusCode::INTERNAL_SERVER_ERROR, "failed to attach evidence".to_string())
+    };
+    if !evidence.target_exists(params.tenant_id, target).await.map_err(internal_error)? {
+        return Err(reject(StatusCode::NOT_FOUND, "violation or case not found in tenant".to_string()));
+    }
+
+    let upload = Upload {
+        file_name,
+        content_type: content_type.to_string(),
+        bytes: bytes.to_vec(),
+        uploaded_by: params.uploaded_by,
+    };
+    match evidence.attach(params.tenant_id, target, upload).await {
+        Ok(attachment) => Ok(Json(attachment)),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+/// Check a UCC client master CSV (multipart `file`) before it is filed with the exchanges
+async fn validate_client_master(
+    Query(params): Query<ClientMasterParams>,
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<client_master::ClientMasterReport>, (StatusCode, Json<serde_json::Value>)> {
+    let reject = |status: StatusCode, message: String| (status, Json(serde_json::json!({"error": message})));
+
+    let mut file = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| reject(StatusCode::BAD_REQUEST, e.body_text()))?
+    {
+        if field.name() == Some("file") {
+            file = Some(field.bytes().await.map_err(|e| reject(StatusCode::BAD_REQUEST, e.body_text()))?);
+        }
+    }
+    let Some(bytes) = file.filter(|bytes| !bytes.is_empty()) else {
+        return Err(reject(StatusCode::UNPROCESSABLE_ENTITY, "a non-empty file field is required".to_string()));
+    };
+
+    match client_master::validate(&state.db, params.tenant_id, &bytes).await {
+        Ok(Ok(report)) => Ok(Json(report)),
+        Ok(Err(errors)) => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!(ValidationResponse::rejected(errors))),
+        )),
+        Err(e) => {
+            error!("Failed to validate client master for tenant {}: {:#}", params.tenant_id, e);
+            Err(reject(StatusCode::INTERNAL_SERVER_ERROR, "failed to validate client master".to_string()))
+        }
+    }
+}
+
+async fn list_violation_evidence(
+    Path(violation_id): Path<Uuid>,
+    Query(params): Query<EvidenceParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Attachment>>, StatusCode> {
+    list_evidence(&state.db, params.tenant_id, EvidenceTarget::Violation(violation_id)).await
+}
+
+async fn list_case_evidence(
+    Path(investigation_id): Path<Uuid>,
+    Query(params): Query<EvidenceParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Attachment>>, StatusCode> {
+    list_evidence(&state.db, params.tenant_id, EvidenceTarget::Case(investigation_id)).await
+}
+
+async fn list_evidence(db: &PgPool, tenant_id: Uuid, target: EvidenceTarget) -> Result<Json<Vec<Attachment>>, StatusCode> {
+    match evidence::list_attachments(db, tenant_id, target).await {
+        Ok(attachments) => Ok(Json(attachments)),
+        Err(e) => {
+            error!("Failed to list evidence of {:?}: {}", target, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_evidence_text(
+    Path(attachment_id): Path<Uuid>,
+    Query(params): Query<EvidenceParams>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    match evidence::extracted_text(&state.db, params.tenant_id, attachment_id).await {
+        Ok(Some(text)) => Ok(Json(serde_json::json!({"attachment_id": attachment_id, "text": text}))),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load text of evidence {}: {}", attachment_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn search_violations(
+    Query(params): Query<SearchParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ViolationHit>>, StatusCode> {
+    if params.q.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match evidence::search::search_violations(&state.db, &params).await {
+        Ok(hits) => Ok(Json(hits)),
+        Err(e) => {
+            error!("Violation search failed for tenant {}: {}", params.tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn search_cases(
+    Query(params): Query<SearchParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CaseHit>>, StatusCode> {
+    if params.q.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    match evidence::search::search_cases(&state.db, &params).await {
+        Ok(hits) => Ok(Json(hits)),
+        Err(e) => {
+            error!("Case search failed for tenant {}: {}", params.tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
 async fn generate_report_data(
     db: &PgPool,
     request: &GenerateReportRequest,
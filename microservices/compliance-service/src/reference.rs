@@ -0,0 +1,432 @@
+//! Effective-dated instrument reference data
+//!
+//! Symbol changes, corporate actions and lot size revisions are recorded as
+//! versions of the instrument with the date they take effect instead of being
+//! edited into `instruments`. A new version splits the period it falls in, so
+//! revisions can be entered ahead of time or back-dated, and reports,
+//! backtests and late end-of-day files resolve an instrument as it was on the
+//! trade date. Attributes a version changes carry forward into later versions
+//! that still had the old value. `instruments` mirrors the version in effect
+//! today; future-dated versions are applied to it by the sync worker.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub const CHANGE_TYPES: &[&str] = &[
+    "SYMBOL_CHANGE",
+    "LOT_SIZE_REVISION",
+    "TICK_SIZE_REVISION",
+    "ISIN_CHANGE",
+    "SPLIT",
+    "BONUS",
+    "MERGER",
+    "SUSPENSION",
+    "DELISTING",
+    "RELISTING",
+    "CORRECTION",
+];
+
+/// Changes that rescale prices and quantities and so carry an adjustment factor
+const ADJUSTING_CHANGES: &[&str] = &["SPLIT", "BONUS", "MERGER"];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InstrumentVersion {
+    pub version_id: Uuid,
+    pub instrument_id: Uuid,
+    /// `None` for the version in effect since listing
+    pub effective_from: Option<NaiveDate>,
+    /// Exclusive; `None` for the latest version
+    pub effective_to: Option<NaiveDate>,
+    pub symbol: String,
+    pub isin: Option<String>,
+    pub lot_size: i32,
+    pub tick_size: f64,
+    pub is_active: bool,
+    pub change_type: String,
+    pub adjustment_factor: f64,
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+    pub recorded_by: Option<Uuid>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl InstrumentVersion {
+    pub fn is_in_effect(&self, day: NaiveDate) -> bool {
+        self.effective_from.map_or(true, |from| from <= day) && self.effective_to.map_or(true, |to| to > day)
+    }
+}
+
+/// Attributes left out keep the value in effect on `effective_from`
+#[derive(Deserialize)]
+pub struct VersionRequest {
+    pub effective_from: NaiveDate,
+    pub change_type: String,
+    pub symbol: Option<String>,
+    pub isin: Option<String>,
+    pub lot_size: Option<i32>,
+    pub tick_size: Option<f64>,
+    pub is_active: Option<bool>,
+    pub adjustment_factor: Option<f64>,
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+    pub recorded_by: Option<Uuid>,
+}
+
+impl VersionRequest {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !CHANGE_TYPES.contains(&self.change_type.as_str()) {
+            errors.push(format!("change_type must be one of {}", CHANGE_TYPES.join(", ")));
+        }
+        if let Some(symbol) = &self.symbol {
+            let symbol = symbol.trim();
+            if symbol.is_empty() || symbol.len() > 50 {
+                errors.push("symbol must be 1-50 characters".to_string());
+            }
+        }
+        if let Some(isin) = &self.isin {
+            if isin.len() != 12 || !isin.chars().all(|c| c.is_ascii_alphanumeric()) {
+                errors.push(format!("'{}' is not a valid ISIN", isin));
+            }
+        }
+        if self.lot_size.map_or(false, |lot_size| lot_size <= 0) {
+            errors.push("lot_size must be positive".to_string());
+        }
+        if self.tick_size.map_or(false, |tick_size| !(tick_size > 0.0 && tick_size < 1_000_000.0)) {
+            errors.push("tick_size must be positive".to_string());
+        }
+        match self.adjustment_factor {
+            Some(factor) if !(factor > 0.0 && factor.is_finite()) => {
+                errors.push("adjustment_factor must be positive".to_string())
+            }
+            Some(factor) if factor != 1.0 && !ADJUSTING_CHANGES.contains(&self.change_type.as_str()) => {
+                errors.push(format!("adjustment_factor only applies to {}", ADJUSTING_CHANGES.join(", ")))
+            }
+            _ => {}
+        }
+        if self.reference.as_ref().map_or(false, |reference| reference.len() > 100) {
+            errors.push("reference must be at most 100 characters".to_string());
+        }
+
+        // Each change type must actually change what it names
+        let required = match self.change_type.as_str() {
+            "SYMBOL_CHANGE" => Some(("symbol", self.symbol.is_some())),
+            "LOT_SIZE_REVISION" => Some(("lot_size", self.lot_size.is_some())),
+            "TICK_SIZE_REVISION" => Some(("tick_size", self.tick_size.is_some())),
+            "ISIN_CHANGE" => Some(("isin", self.isin.is_some())),
+            "SPLIT" | "BONUS" => Some(("adjustment_factor", self.adjustment_factor.is_some())),
+            _ => None,
+        };
+        if let Some((field, false)) = required {
+            errors.push(format!("{} requires {}", self.change_type, field));
+        }
+        match (self.change_type.as_str(), self.is_active) {
+            ("SUSPENSION" | "DELISTING", Some(true)) | ("RELISTING", Some(false)) => {
+                errors.push(format!("{} contradicts is_active", self.change_type))
+            }
+            _ => {}
+        }
+        errors
+    }
+
+    /// Suspensions and delistings deactivate the instrument unless told otherwise
+    fn is_active(&self) -> Option<bool> {
+        self.is_active.or(match self.change_type.as_str() {
+            "SUSPENSION" | "DELISTING" => Some(false),
+            "RELISTING" => Some(true),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordOutcome {
+    Recorded(InstrumentVersion),
+    /// Another version already takes effect that day, or the symbol is held by another instrument
+    Conflict(String),
+    NotFound,
+}
+
+#[derive(Deserialize)]
+pub struct AsOfParams {
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Deserialize)]
+pub struct ResolveParams {
+    pub exchange: String,
+    pub symbol: Option<String>,
+    pub isin: Option<String>,
+    pub date: NaiveDate,
+}
+
+pub async fn history(db: &PgPool, instrument_id: Uuid) -> anyhow::Result<Option<Vec<InstrumentVersion>>> {
+    let versions = sqlx::query_as!(
+        InstrumentVersion,
+        r#"
+        SELECT version_id, instrument_id, effective_from, effective_to, symbol, isin, lot_size,
+               tick_size::float8 AS "tick_size!", is_active, change_type,
+               adjustment_factor::float8 AS "adjustment_factor!", reference, notes, recorded_by, recorded_at
+        FROM instrument_versions
+        WHERE instrument_id = $1
+        ORDER BY effective_from NULLS FIRST
+        "#,
+        instrument_id
+    )
+    .fetch_all(db)
+    .await?;
+    // Every instrument has at least its listing version
+    Ok(if versions.is_empty() { None } else { Some(versions) })
+}
+
+pub async fn as_of(db: &PgPool, instrument_id: Uuid, day: NaiveDate) -> anyhow::Result<Option<InstrumentVersion>> {
+    let version = sqlx::query_as!(
+        InstrumentVersion,
+        r#"
+        SELECT version_id AS "version_id!", instrument_id AS "instrument_id!", effective_from, effective_to,
+               symbol AS "symbol!", isin, lot_size AS "lot_size!", tick_size::float8 AS "tick_size!",
+               is_active AS "is_active!", change_type AS "change_type!",
+               adjustment_factor::float8 AS "adjustment_factor!", reference, notes, recorded_by,
+               recorded_at AS "recorded_at!"
+        FROM instrument_as_of($1, $2)
+        "#,
+        instrument_id,
+        day
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(version)
+}
+
+/// The instrument an exchange symbol or ISIN referred to on `day`; the ISIN wins when both are given
+pub async fn resolve(db: &PgPool, params: &ResolveParams) -> anyhow::Result<Option<InstrumentVersion>> {
+    let version = sqlx::query_as!(
+        InstrumentVersion,
+        r#"
+        SELECT v.version_id, v.instrument_id, v.effective_from, v.effective_to, v.symbol, v.isin, v.lot_size,
+               v.tick_size::float8 AS "tick_size!", v.is_active, v.change_type,
+               v.adjustment_factor::float8 AS "adjustment_factor!", v.reference, v.notes, v.recorded_by,
+               v.recorded_at
+        FROM instrument_versions v
+        JOIN instruments i ON i.instrument_id = v.instrument_id
+        WHERE i.exchange = $1
+          AND (($2::text IS NOT NULL AND v.isin = $2) OR ($2::text IS NULL AND v.symbol = $3))
+          AND (v.effective_from IS NULL OR v.effective_from <= $4)
+          AND (v.effective_to IS NULL OR v.effective_to > $4)
+        ORDER BY v.is_active DESC
+        LIMIT 1
+        "#,
+        params.exchange,
+        params.isin.as_deref().map(str::to_ascii_uppercase),
+        params.symbol,
+        params.date
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(version)
+}
+
+/// Record a change taking effect on `request.effective_from`
+pub async fn record(db: &PgPool, instrument_id: Uuid, request: &VersionRequest) -> anyhow::Result<RecordOutcome> {
+    let mut tx = db.begin().await?;
+
+    // Serialises changes to one instrument
+    let locked = sqlx::query_scalar!(
+        "SELECT instrument_id FROM instruments WHERE instrument_id = $1 FOR UPDATE",
+        instrument_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if locked.is_none() {
+        return Ok(RecordOutcome::NotFound);
+    }
+
+    let versions = sqlx::query_as!(
+        InstrumentVersion,
+        r#"
+        SELECT version_id, instrument_id, effective_from, effective_to, symbol, isin, lot_size,
+               tick_size::float8 AS "tick_size!", is_active, change_type,
+               adjustment_factor::float8 AS "adjustment_factor!", reference, notes, recorded_by, recorded_at
+        FROM instrument_versions
+        WHERE instrument_id = $1
+        ORDER BY effective_from NULLS FIRST
+        "#,
+        instrument_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let day = request.effective_from;
+    let Some(position) = versions.iter().position(|version| version.is_in_effect(day)) else {
+        anyhow::bail!("instrument {} has no version in effect on {}", instrument_id, day);
+    };
+    let current = &versions[position];
+    if current.effective_from == Some(day) {
+        return Ok(RecordOutcome::Conflict(format!(
+            "a {} version already takes effect on {}",
+            current.change_type, day
+        )));
+    }
+
+    let symbol = request.symbol.as_deref().map(str::trim).unwrap_or(&current.symbol).to_string();
+    let isin = request.isin.as_deref().map(str::to_ascii_uppercase).or_else(|| current.isin.clone());
+    let lot_size = request.lot_size.unwrap_or(current.lot_size);
+    let tick_size = request.tick_size.unwrap_or(current.tick_size);
+    let is_active = request.is_active().unwrap_or(current.is_active);
+
+    sqlx::query!(
+        "UPDATE instrument_versions SET effective_to = $2 WHERE version_id = $1",
+        current.version_id,
+        day
+    )
+    .execute(&mut *tx)
+    .await?;
+    let version_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO instrument_versions (
+            instrument_id, effective_from, effective_to, symbol, isin, lot_size, tick_size, is_active,
+            change_type, adjustment_factor, reference, notes, recorded_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7::float8, $8, $9, $10::float8, $11, $12, $13)
+        RETURNING version_id
+        "#,
+        instrument_id,
+        day,
+        current.effective_to,
+        symbol,
+        isin,
+        lot_size,
+        tick_size,
+        is_active,
+        request.change_type,
+        request.adjustment_factor.unwrap_or(1.0),
+        request.reference.as_deref().map(str::trim),
+        request.notes,
+        request.recorded_by
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Later versions were recorded with the old values; carry the change into
+    // the run of them that still had it, up to the next version changing it
+    let (mut carry_symbol, mut carry_isin, mut carry_lot, mut carry_tick, mut carry_active) = (
+        symbol != current.symbol,
+        isin != current.isin,
+        lot_size != current.lot_size,
+        tick_size != current.tick_size,
+        is_active != current.is_active,
+    );
+    for later in &versions[position + 1..] {
+        carry_symbol &= later.symbol == current.symbol;
+        carry_isin &= later.isin == current.isin;
+        carry_lot &= later.lot_size == current.lot_size;
+        carry_tick &= later.tick_size == current.tick_size;
+        carry_active &= later.is_active == current.is_active;
+        if !(carry_symbol || carry_isin || carry_lot || carry_tick || carry_active) {
+            break;
+        }
+        sqlx::query!(
+            r#"
+            UPDATE instrument_versions
+            SET symbol = CASE WHEN $2 THEN $3 ELSE symbol END,
+                isin = CASE WHEN $4 THEN $5 ELSE isin END,
+                lot_size = CASE WHEN $6 THEN $7 ELSE lot_size END,
+                tick_size = CASE WHEN $8 THEN $9::float8 ELSE tick_size END,
+                is_active = CASE WHEN $10 THEN $11 ELSE is_active END
+            WHERE version_id = $1
+            "#,
+            later.version_id,
+            carry_symbol,
+            symbol,
+            carry_isin,
+            isin,
+            carry_lot,
+            lot_size,
+            carry_tick,
+            tick_size,
+            carry_active,
+            is_active
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    match sync_instrument(&mut tx, instrument_id).await {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Ok(RecordOutcome::Conflict(format!(
+                "symbol {} is held by another instrument on the exchange",
+                symbol
+            )));
+        }
+        Err(e) => return Err(e.into()),
+    }
+    tx.commit().await?;
+
+    let version = history(db, instrument_id)
+        .await?
+        .unwrap_or_default()
+        .into_iter()
+        .find(|version| version.version_id == version_id)
+        .ok_or_else(|| anyhow::anyhow!("instrument version {} disappeared", version_id))?;
+    Ok(RecordOutcome::Recorded(version))
+}
+
+/// Point one instrument at the version in effect today
+async fn sync_instrument(tx: &mut sqlx::PgConnection, instrument_id: Uuid) -> Result<u64, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE instruments i
+        SET symbol = v.symbol, isin = v.isin, lot_size = v.lot_size, tick_size = v.tick_size,
+            is_active = v.is_active, updated_at = NOW()
+        FROM instrument_as_of($1, CURRENT_DATE) v
+        WHERE i.instrument_id = $1
+          AND (i.symbol, i.isin, i.lot_size, i.tick_size, i.is_active)
+              IS DISTINCT FROM (v.symbol, v.isin, v.lot_size, v.tick_size, v.is_active)
+        "#,
+        instrument_id
+    )
+    .execute(tx)
+    .await?;
+    Ok(updated.rows_affected())
+}
+
+/// Apply versions that have taken effect since the last pass to `instruments`
+pub async fn sync(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE instruments i
+        SET symbol = v.symbol, isin = v.isin, lot_size = v.lot_size, tick_size = v.tick_size,
+            is_active = v.is_active, updated_at = NOW()
+        FROM instrument_versions v
+        WHERE v.instrument_id = i.instrument_id
+          AND (v.effective_from IS NULL OR v.effective_from <= CURRENT_DATE)
+          AND (v.effective_to IS NULL OR v.effective_to > CURRENT_DATE)
+          AND (i.symbol, i.isin, i.lot_size, i.tick_size, i.is_active)
+              IS DISTINCT FROM (v.symbol, v.isin, v.lot_size, v.tick_size, v.is_active)
+        "#
+    )
+    .execute(db)
+    .await?;
+    Ok(updated.rows_affected())
+}
+
+pub fn spawn_worker(db: PgPool, every: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            match sync(&db).await {
+                Ok(0) => {}
+                Ok(updated) => info!("Applied reference data versions now in effect to {} instrument(s)", updated),
+                Err(e) => error!("Instrument reference data sync failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,123 @@
+//! Violation resolution SLAs in business time
+//!
+//! Each platform severity has a resolution target in business hours
+//! (VIOLATION_SLA_HOURS, e.g. `CRITICAL=4,HIGH=8,MEDIUM=24,LOW=48`). The clock
+//! only runs inside the tenant's market sessions, so a violation raised after
+//! the close on Friday starts counting at Monday's open. The deadline is fixed
+//! when the violation is created; later calendar changes do not move it.
+
+use chrono::{DateTime, Duration, Utc};
+use dharmaguard_common::business_hours::{self, BusinessCalendar};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const DEFAULT_TARGETS: &str = "CRITICAL=4,HIGH=8,MEDIUM=24,LOW=48";
+
+#[derive(Debug, Clone)]
+pub struct SlaTargets {
+    hours: HashMap<String, f64>,
+}
+
+impl SlaTargets {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let spec = std::env::var("VIOLATION_SLA_HOURS")
+            .ok()
+            .filter(|spec| !spec.is_empty())
+            .unwrap_or_else(|| DEFAULT_TARGETS.to_string());
+        let mut hours = HashMap::new();
+        for entry in spec.split(',') {
+            let (severity, value) = entry
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("VIOLATION_SLA_HOURS entry '{}' is not SEVERITY=HOURS", entry))?;
+            let value: f64 = value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("VIOLATION_SLA_HOURS entry '{}' has no number of hours", entry))?;
+            hours.insert(severity.trim().to_uppercase(), value);
+        }
+        Ok(Self { hours })
+    }
+
+    /// Business time allowed to resolve a violation, if the severity has a target
+    pub fn target(&self, severity: &str) -> Option<Duration> {
+        self.hours
+            .get(severity)
+            .map(|hours| Duration::seconds((hours * 3600.0).round() as i64))
+    }
+
+    pub fn due_at(&self, calendar: &BusinessCalendar, created_at: DateTime<Utc>, severity: &str) -> Option<DateTime<Utc>> {
+        self.target(severity)
+            .map(|target| calendar.add_business_time(created_at, target))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SlaParams {
+    pub tenant_id: Uuid,
+    /// Only violations past their deadline
+    #[serde(default)]
+    pub breached: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ViolationSla {
+    pub violation_id: Uuid,
+    pub violation_type: String,
+    pub severity: String,
+    pub created_at: DateTime<Utc>,
+    pub sla_due_at: Option<DateTime<Utc>>,
+    /// Business hours the violation has been open
+    pub business_hours_open: f64,
+    /// Business hours left before the deadline; negative once breached
+    pub business_hours_remaining: Option<f64>,
+    pub breached: bool,
+}
+
+fn hours(duration: Duration) -> f64 {
+    duration.num_seconds() as f64 / 3600.0
+}
+
+/// Open violations of a tenant with their SLA position, most urgent first
+pub async fn open_violations(db: &PgPool, params: &SlaParams) -> anyhow::Result<Vec<ViolationSla>> {
+    let calendar = business_hours::load(db, params.tenant_id).await?;
+    let now = Utc::now();
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT violation_id, violation_type, severity::text AS "severity!",
+               created_at AS "created_at!", sla_due_at
+        FROM compliance_violations
+        WHERE tenant_id = $1 AND resolved_at IS NULL AND created_at IS NOT NULL
+        ORDER BY sla_due_at NULLS LAST, created_at
+        "#,
+        params.tenant_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let remaining = row.sla_due_at.map(|due| {
+                if due > now {
+                    hours(calendar.business_time_between(now, due))
+                } else {
+                    -hours(calendar.business_time_between(due, now))
+                }
+            });
+            ViolationSla {
+                violation_id: row.violation_id,
+                violation_type: row.violation_type,
+                severity: row.severity,
+                business_hours_open: hours(calendar.business_time_between(row.created_at, now)),
+                breached: row.sla_due_at.map_or(false, |due| due <= now),
+                business_hours_remaining: remaining,
+                created_at: row.created_at,
+                sla_due_at: row.sla_due_at,
+            }
+        })
+        .filter(|sla| !params.breached || sla.breached)
+        .collect())
+}
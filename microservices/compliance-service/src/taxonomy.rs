@@ -0,0 +1,309 @@
+//! Tenant-defined severity scales and category taxonomies
+//!
+//! Tenants classify alerts and violations with their own codes; every code maps
+//! onto a platform severity or category so platform reporting and the
+//! compliance score stay comparable across tenants.
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Platform severities in ascending order (the `alert_severity` enum)
+pub const PLATFORM_SEVERITIES: [&str; 4] = ["LOW", "MEDIUM", "HIGH", "CRITICAL"];
+
+/// Platform categories used for cross-tenant reporting
+pub const PLATFORM_CATEGORIES: &[&str] = &[
+    "PRICE_MANIPULATION",
+    "VOLUME_MANIPULATION",
+    "INSIDER_TRADING",
+    "FRONT_RUNNING",
+    "POSITION_LIMIT_BREACH",
+    "KYC_AML",
+    "REPORTING",
+    "OTHER",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SeverityLevel {
+    pub code: String,
+    pub label: String,
+    /// Position in the tenant's scale; higher is more severe
+    pub rank: i32,
+    pub platform_severity: String,
+    /// Compliance score penalty per alert; platform default when absent
+    pub score_weight: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Category {
+    pub code: String,
+    pub label: String,
+    pub parent_code: Option<String>,
+    pub platform_category: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TenantTaxonomy {
+    pub severities: Vec<SeverityLevel>,
+    pub categories: Vec<Category>,
+}
+
+#[derive(Serialize)]
+pub struct ValidationResponse {
+    pub status: String,
+    pub errors: Vec<String>,
+}
+
+impl ValidationResponse {
+    pub fn rejected(errors: Vec<String>) -> Self {
+        Self {
+            status: "REJECTED".to_string(),
+            errors,
+        }
+    }
+}
+
+/// Tenant codes resolved to their platform equivalents
+#[derive(Debug, Clone)]
+pub struct Classification {
+    pub platform_severity: String,
+    pub tenant_severity: Option<String>,
+    pub tenant_category: Option<String>,
+}
+
+fn platform_rank(severity: &str) -> Option<usize> {
+    PLATFORM_SEVERITIES.iter().position(|s| *s == severity)
+}
+
+/// Structural checks run before a taxonomy replaces the tenant's current one
+pub fn validate_taxonomy(taxonomy: &TenantTaxonomy) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let mut codes = HashSet::new();
+    let mut ranks = HashSet::new();
+    for level in &taxonomy.severities {
+        if level.code.trim().is_empty() || level.code.len() > 50 {
+            errors.push(format!("severity code '{}' must be 1-50 characters", level.code));
+        }
+        if !codes.insert(level.code.as_str()) {
+            errors.push(format!("duplicate severity code '{}'", level.code));
+        }
+        if !ranks.insert(level.rank) {
+            errors.push(format!("duplicate severity rank {}", level.rank));
+        }
+        if platform_rank(&level.platform_severity).is_none() {
+            errors.push(format!(
+                "severity '{}' maps to unknown platform severity '{}'",
+                level.code, level.platform_severity
+            ));
+        }
+        if level.score_weight.map_or(false, |weight| weight < 0.0) {
+            errors.push(format!("severity '{}' has a negative score weight", level.code));
+        }
+    }
+
+    // A more severe tenant level must never map to a less severe platform level
+    let mut ordered: Vec<&SeverityLevel> = taxonomy.severities.iter().collect();
+    ordered.sort_by_key(|level| level.rank);
+    for pair in ordered.windows(2) {
+        if let (Some(lower), Some(higher)) = (
+            platform_rank(&pair[0].platform_severity),
+            platform_rank(&pair[1].platform_severity),
+        ) {
+            if higher < lower {
+                errors.push(format!(
+                    "severity '{}' ranks above '{}' but maps to a lower platform severity",
+                    pair[1].code, pair[0].code
+                ));
+            }
+        }
+    }
+
+    let mut parents: HashMap<&str, Option<&str>> = HashMap::new();
+    for category in &taxonomy.categories {
+        if category.code.trim().is_empty() || category.code.len() > 50 {
+            errors.push(format!("category code '{}' must be 1-50 characters", category.code));
+        }
+        if parents
+            .insert(category.code.as_str(), category.parent_code.as_deref())
+            .is_some()
+        {
+            errors.push(format!("duplicate category code '{}'", category.code));
+        }
+        if !PLATFORM_CATEGORIES.contains(&category.platform_category.as_str()) {
+            errors.push(format!(
+                "category '{}' maps to unknown platform category '{}'",
+                category.code, category.platform_category
+            ));
+        }
+    }
+    for category in &taxonomy.categories {
+        if let Some(parent) = &category.parent_code {
+            if !parents.contains_key(parent.as_str()) {
+                errors.push(format!("category '{}' has unknown parent '{}'", category.code, parent));
+                continue;
+            }
+        }
+        // Walk up the tree; more steps than categories means a cycle
+        let mut current = category.parent_code.as_deref();
+        let mut steps = 0;
+        while let Some(code) = current {
+            steps += 1;
+            if code == category.code || steps > parents.len() {
+                errors.push(format!("category '{}' is part of a parent cycle", category.code));
+                break;
+            }
+            current = parents.get(code).copied().flatten();
+        }
+    }
+
+    errors
+}
+
+pub async fn load_taxonomy(db: &PgPool, tenant_id: Uuid) -> anyhow::Result<TenantTaxonomy> {
+    let severities = sqlx::query_as!(
+        SeverityLevel,
+        r#"
+        SELECT code, label, rank, platform_severity::text AS "platform_severity!",
+               score_weight::float8 AS score_weight
+        FROM tenant_severity_levels
+        WHERE tenant_id = $1
+        ORDER BY rank
+        "#,
+        tenant_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let categories = sqlx::query_as!(
+        Category,
+        r#"
+        SELECT code, label, parent_code, platform_category
+        FROM tenant_categories
+        WHERE tenant_id = $1
+        ORDER BY code
+        "#,
+        tenant_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    Ok(TenantTaxonomy { severities, categories })
+}
+
+/// Replace the tenant's taxonomy; callers validate first
+pub async fn replace_taxonomy(
+    db: &PgPool,
+    tenant_id: Uuid,
+    taxonomy: &TenantTaxonomy,
+) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+
+    sqlx::query!("DELETE FROM tenant_categories WHERE tenant_id = $1", tenant_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM tenant_severity_levels WHERE tenant_id = $1", tenant_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for level in &taxonomy.severities {
+        sqlx::query!(
+            r#"
+            INSERT INTO tenant_severity_levels (tenant_id, code, label, rank, platform_severity, score_weight)
+            VALUES ($1, $2, $3, $4, ($5::text)::alert_severity, $6::float8)
+            "#,
+            tenant_id,
+            level.code,
+            level.label,
+            level.rank,
+            level.platform_severity,
+            level.score_weight
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Parent references are deferred, so insertion order does not matter
+    for category in &taxonomy.categories {
+        sqlx::query!(
+            r#"
+            INSERT INTO tenant_categories (tenant_id, code, label, parent_code, platform_category)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            tenant_id,
+            category.code,
+            category.label,
+            category.parent_code,
+            category.platform_category
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Resolve ingest-time severity and category codes against the tenant taxonomy
+///
+/// A tenant severity code takes precedence and determines the platform
+/// severity; otherwise `platform_severity` must be a platform level.
+pub async fn classify(
+    db: &PgPool,
+    tenant_id: Uuid,
+    platform_severity: Option<&str>,
+    tenant_severity: Option<&str>,
+    tenant_category: Option<&str>,
+) -> anyhow::Result<Result<Classification, Vec<String>>> {
+    let mut errors = Vec::new();
+
+    let resolved_severity = match tenant_severity {
+        Some(code) => {
+            let mapped = sqlx::query_scalar!(
+                r#"SELECT platform_severity::text AS "platform_severity!" FROM tenant_severity_levels WHERE tenant_id = $1 AND code = $2"#,
+                tenant_id,
+                code
+            )
+            .fetch_optional(db)
+            .await?;
+            if mapped.is_none() {
+                errors.push(format!("unknown severity '{}' for this tenant", code));
+            }
+            mapped
+        }
+        None => match platform_severity {
+            Some(severity) if platform_rank(severity).is_some() => Some(severity.to_string()),
+            Some(severity) => {
+                errors.push(format!("unknown platform severity '{}'", severity));
+                None
+            }
+            None => {
+                errors.push("either severity or tenant_severity is required".to_string());
+                None
+            }
+        },
+    };
+
+    if let Some(code) = tenant_category {
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS(SELECT 1 FROM tenant_categories WHERE tenant_id = $1 AND code = $2) AS "exists!""#,
+            tenant_id,
+            code
+        )
+        .fetch_one(db)
+        .await?;
+        if !exists {
+            errors.push(format!("unknown category '{}' for this tenant", code));
+        }
+    }
+
+    Ok(match resolved_severity {
+        Some(platform_severity) if errors.is_empty() => Ok(Classification {
+            platform_severity,
+            tenant_severity: tenant_severity.map(str::to_string),
+            tenant_category: tenant_category.map(str::to_string),
+        }),
+        _ => Err(errors),
+    })
+}
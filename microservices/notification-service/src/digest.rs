@@ -0,0 +1,350 @@
+//! Per-user notification preferences and periodic digests
+//!
+//! Users without a preferences row get immediate delivery and the default
+//! de-dup window. Due digests are collected every few minutes: each user's
+//! pending notifications are sent as one message per channel and marked
+//! DIGESTED. A failed digest leaves its notifications pending and is retried on
+//! a later run. Switching digests off flushes whatever is still pending.
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+use dharmaguard_common::tenant_query_as;
+
+use crate::mailer::Mailer;
+use crate::pipeline::{self, Channel, Priority};
+
+/// Seconds before a failed digest is attempted again
+const RETRY_SECS: f64 = 300.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Preferences {
+    /// OFF, HOURLY or DAILY
+    pub digest_frequency: String,
+    /// LOW, MEDIUM or HIGH; CRITICAL is never batched
+    pub digest_max_priority: String,
+    /// 0 disables de-duplication
+    pub dedup_window_secs: i32,
+    #[serde(default)]
+    pub next_digest_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            digest_frequency: "OFF".to_string(),
+            digest_max_priority: "LOW".to_string(),
+            dedup_window_secs: std::env::var("NOTIFICATION_DEDUP_WINDOW_SECS")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(900),
+            next_digest_at: None,
+        }
+    }
+}
+
+impl Preferences {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !["OFF", "HOURLY", "DAILY"].contains(&self.digest_frequency.as_str()) {
+            errors.push("digest_frequency must be OFF, HOURLY or DAILY".to_string());
+        }
+        if !["LOW", "MEDIUM", "HIGH"].contains(&self.digest_max_priority.as_str()) {
+            errors.push("digest_max_priority must be LOW, MEDIUM or HIGH".to_string());
+        }
+        if !(0..=86_400).contains(&self.dedup_window_secs) {
+            errors.push("dedup_window_secs must be between 0 and 86400".to_string());
+        }
+        errors
+    }
+
+    /// Whether a notification of this priority waits for the digest
+    pub fn batches(&self, priority: Priority) -> bool {
+        priority != Priority::Critical
+            && self.digest_frequency != "OFF"
+            && Priority::parse(&self.digest_max_priority).is_some_and(|max| priority <= max)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePreferencesRequest {
+    pub tenant_id: Uuid,
+    #[serde(flatten)]
+    pub preferences: Preferences,
+}
+
+pub async fn load_preferences(db: &PgPool, user_id: Uuid) -> Result<Preferences, sqlx::Error> {
+    let preferences = sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT digest_frequency, digest_max_priority, dedup_window_secs, next_digest_at
+        FROM notification_preferences WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(preferences.unwrap_or_default())
+}
+
+/// Upsert; the digest clock restarts only when the frequency changes
+pub async fn save_preferences(
+    db: &PgPool,
+    tenant: &TenantContext,
+    user_id: Uuid,
+    preferences: &Preferences,
+) -> Result<Preferences, sqlx::Error> {
+    tenant_query_as!(
+        Preferences,
+        tenant,
+        r#"
+        INSERT INTO notification_preferences (
+            tenant_id, user_id, digest_frequency, digest_max_priority, dedup_window_secs, next_digest_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5,
+            CASE $3 WHEN 'HOURLY' THEN NOW() + INTERVAL '1 hour' WHEN 'DAILY' THEN NOW() + INTERVAL '1 day' END
+        )
+        ON CONFLICT (user_id) DO UPDATE SET
+            digest_frequency = EXCLUDED.digest_frequency,
+            digest_max_priority = EXCLUDED.digest_max_priority,
+            dedup_window_secs = EXCLUDED.dedup_window_secs,
+            next_digest_at = CASE
+                WHEN notification_preferences.digest_frequency = EXCLUDED.digest_frequency
+                    THEN notification_preferences.next_digest_at
+                ELSE EXCLUDED.next_digest_at
+            END,
+            updated_at = NOW()
+        WHERE notification_preferences.tenant_id = EXCLUDED.tenant_id
+        RETURNING digest_frequency, digest_max_priority, dedup_window_secs, next_digest_at
+        "#,
+        user_id,
+        preferences.digest_frequency,
+        preferences.digest_max_priority,
+        preferences.dedup_window_secs
+    )
+    .fetch_one(db)
+    .await
+}
+
+#[derive(Serialize, Default)]
+pub struct DigestRunSummary {
+    pub users: u64,
+    pub digests_sent: u64,
+    pub digests_failed: u64,
+    pub notifications: u64,
+}
+
+/// Collect due digests every five minutes
+pub async fn schedule(db: PgPool, mailer: Option<Arc<Mailer>>) -> anyhow::Result<JobScheduler> {
+    let scheduler = JobScheduler::new().await?;
+    let job = Job::new_async("0 */5 * * * *", move |_uuid, _l| {
+        let db = db.clone();
+        let mailer = mailer.clone();
+        Box::pin(async move {
+            match run_due(&db, mailer.as_deref()).await {
+                Ok(summary) if summary.users > 0 => info!(
+                    "Sent {} notification digests covering {} notifications ({} failed)",
+                    summary.digests_sent, summary.notifications, summary.digests_failed
+                ),
+                Ok(_) => {}
+                Err(e) => error!("Notification digest run failed: {}", e),
+            }
+        })
+    })?;
+    scheduler.add(job).await?;
+    scheduler.start().await?;
+    Ok(scheduler)
+}
+
+pub async fn run_due(db: &PgPool, mailer: Option<&Mailer>) -> anyhow::Result<DigestRunSummary> {
+    let due = sqlx::query_scalar!(
+        r#"
+        SELECT p.user_id FROM notification_preferences p
+        WHERE (p.digest_frequency <> 'OFF' AND (p.next_digest_at IS NULL OR p.next_digest_at <= NOW()))
+           OR (p.digest_frequency = 'OFF' AND EXISTS (
+                SELECT 1 FROM notifications n WHERE n.user_id = p.user_id AND n.status = 'PENDING_DIGEST'
+           ))
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut summary = DigestRunSummary::default();
+    for user_id in due {
+        if let Err(e) = send_user_digests(db, mailer, user_id, &mut summary).await {
+            warn!("Failed to send notification digest to user {}: {:#}", user_id, e);
+        }
+    }
+    Ok(summary)
+}
+
+struct PendingItem {
+    notification_id: Uuid,
+    tenant_id: Uuid,
+    channel: String,
+    priority: String,
+    category: String,
+    title: String,
+    occurrence_count: i32,
+    last_occurred_at: chrono::DateTime<chrono::Utc>,
+}
+
+async fn send_user_digests(
+    db: &PgPool,
+    mailer: Option<&Mailer>,
+    user_id: Uuid,
+    summary: &mut DigestRunSummary,
+) -> anyhow::Result<()> {
+    let mut tx = db.begin().await?;
+    // Claiming the slot serializes concurrent runs on the preferences row
+    let Some(frequency) = sqlx::query_scalar!(
+        r#"
+        UPDATE notification_preferences
+        SET next_digest_at = CASE digest_frequency
+            WHEN 'HOURLY' THEN NOW() + INTERVAL '1 hour'
+            WHEN 'DAILY' THEN NOW() + INTERVAL '1 day'
+        END
+        WHERE user_id = $1 AND (next_digest_at IS NULL OR next_digest_at <= NOW())
+        RETURNING digest_frequency
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(());
+    };
+
+    let pending = sqlx::query_as!(
+        PendingItem,
+        r#"
+        SELECT notification_id, tenant_id, channel, priority, category, title, occurrence_count, last_occurred_at
+        FROM notifications
+        WHERE user_id = $1 AND status = 'PENDING_DIGEST'
+        ORDER BY CASE priority WHEN 'HIGH' THEN 0 WHEN 'MEDIUM' THEN 1 ELSE 2 END, last_occurred_at DESC
+        FOR UPDATE SKIP LOCKED
+        "#,
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    if pending.is_empty() {
+        tx.commit().await?;
+        return Ok(());
+    }
+    summary.users += 1;
+
+    let mut by_channel: BTreeMap<String, Vec<PendingItem>> = BTreeMap::new();
+    for item in pending {
+        by_channel.entry(item.channel.clone()).or_default().push(item);
+    }
+
+    let mut failed = false;
+    for (channel_name, items) in by_channel {
+        let channel = if channel_name == "EMAIL" { Channel::Email } else { Channel::InApp };
+        let tenant = TenantContext::new(items[0].tenant_id);
+        let occurrences: i32 = items.iter().map(|item| item.occurrence_count).sum();
+        let subject = format!(
+            "{} digest: {} notifications",
+            if frequency == "DAILY" { "Daily" } else { "Notification" },
+            items.len()
+        );
+        let body = render(&items);
+        let digest_id = Uuid::new_v4();
+
+        let outcome = pipeline::deliver(db, mailer, user_id, channel, &subject, body.clone()).await;
+        let failure_reason = outcome.as_ref().err().map(|e| format!("{:#}", e));
+        tenant_query!(
+            &tenant,
+            r#"
+            INSERT INTO notification_digests (
+                tenant_id, digest_id, user_id, channel, notification_count, occurrence_count, status, failure_reason
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            digest_id,
+            user_id,
+            channel_name,
+            items.len() as i32,
+            occurrences,
+            if outcome.is_ok() { "SENT" } else { "FAILED" },
+            failure_reason
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        if let Err(e) = outcome {
+            warn!("Notification digest {} for user {} failed: {:#}", digest_id, user_id, e);
+            summary.digests_failed += 1;
+            failed = true;
+            continue;
+        }
+
+        let ids: Vec<Uuid> = items.iter().map(|item| item.notification_id).collect();
+        sqlx::query!(
+            "UPDATE notifications SET status = 'DIGESTED', digest_id = $2, delivered_at = NOW() WHERE notification_id = ANY($1)",
+            &ids,
+            digest_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        if channel == Channel::InApp {
+            // The digest itself is what the app shows
+            tenant_query!(
+                &tenant,
+                r#"
+                INSERT INTO notifications (
+                    tenant_id, notification_id, user_id, channel, priority, category, title, body,
+                    fingerprint, status, digest_id, delivered_at
+                )
+                VALUES ($1, $2, $3, 'IN_APP', 'LOW', 'DIGEST', $4, $5, $6, 'DELIVERED', $2, NOW())
+                "#,
+                digest_id,
+                user_id,
+                subject,
+                body,
+                hex::encode(digest_id.as_bytes())
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        summary.digests_sent += 1;
+        summary.notifications += ids.len() as u64;
+        metrics::counter!("notifications_digested_total", ids.len() as u64, "channel" => channel.as_str());
+    }
+
+    if failed {
+        sqlx::query!(
+            r#"
+            UPDATE notification_preferences SET next_digest_at = NOW() + make_interval(secs => $2)
+            WHERE user_id = $1
+            "#,
+            user_id,
+            RETRY_SECS
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+fn render(items: &[PendingItem]) -> String {
+    let mut body = String::from("Notifications since your last digest:\n\n");
+    for item in items {
+        body.push_str(&format!("[{}] {}: {}", item.priority, item.category, item.title));
+        if item.occurrence_count > 1 {
+            body.push_str(&format!(" (x{})", item.occurrence_count));
+        }
+        body.push_str(&format!(" - last at {}\n", item.last_occurred_at.format("%Y-%m-%d %H:%M UTC")));
+    }
+    body
+}
@@ -0,0 +1,56 @@
+//! SMTP delivery for the EMAIL channel
+
+use anyhow::Context;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    /// SMTP_HOST unset disables the EMAIL channel
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(host) = std::env::var("SMTP_HOST") else {
+            return Ok(None);
+        };
+        let port = std::env::var("SMTP_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(587);
+        let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+            .with_context(|| format!("invalid SMTP_HOST {}", host))?
+            .port(port);
+        if let (Ok(username), Ok(password)) = (std::env::var("SMTP_USERNAME"), std::env::var("SMTP_PASSWORD")) {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+        let from = std::env::var("SMTP_FROM_ADDRESS")
+            .unwrap_or_else(|_| "noreply@dharmaguard.com".to_string())
+            .parse()
+            .context("invalid SMTP_FROM_ADDRESS")?;
+        Ok(Some(Self {
+            transport: transport.build(),
+            from,
+        }))
+    }
+
+    /// Open and close an SMTP session, for startup readiness
+    pub async fn ping(&self) -> anyhow::Result<()> {
+        if !self.transport.test_connection().await? {
+            anyhow::bail!("SMTP relay did not accept the connection");
+        }
+        Ok(())
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> anyhow::Result<()> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse().with_context(|| format!("invalid recipient address {}", to))?)
+            .subject(subject)
+            .body(body)?;
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
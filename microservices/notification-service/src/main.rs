@@ -0,0 +1,240 @@
+//! DharmaGuard Notification Service
+//! Alert and notification delivery with de-duplication and digest batching
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    middleware,
+    response::Json,
+    routing::{get, post},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use dharmaguard_common::pool::{self, Admission, PoolSettings};
+use dharmaguard_common::startup::{self, Startup};
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query_as;
+use dharmaguard_common::versioning;
+
+mod digest;
+mod mailer;
+mod pipeline;
+
+use crate::digest::{DigestRunSummary, Preferences, UpdatePreferencesRequest};
+use crate::mailer::Mailer;
+use crate::pipeline::{NotificationRequest, SubmitOutcome};
+
+/// Shared migrations the service depends on, each with a relation it creates
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("017_notification_pipeline", "notifications"),
+];
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: PgPool,
+    /// EMAIL channel; unavailable when SMTP_HOST is unset
+    pub mailer: Option<Arc<Mailer>>,
+}
+
+#[derive(Deserialize)]
+pub struct ListNotificationsParams {
+    pub user_id: Uuid,
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct Notification {
+    pub notification_id: Uuid,
+    pub channel: String,
+    pub priority: String,
+    pub category: String,
+    pub title: String,
+    pub body: String,
+    pub occurrence_count: i32,
+    pub status: String,
+    pub digest_id: Option<Uuid>,
+    pub first_occurred_at: chrono::DateTime<chrono::Utc>,
+    pub last_occurred_at: chrono::DateTime<chrono::Utc>,
+    pub delivered_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let database_url = std::env::var("DATABASE_URL")
+        .expect("DATABASE_URL must be set");
+
+    dharmaguard_common::metrics::install();
+    let pool_settings = PoolSettings::from_env();
+    let pool = pool_settings.connect(&database_url).await?;
+    let admission = Admission::new("notification", pool_settings);
+    admission.spawn_autoscaler(pool.clone());
+
+    let mailer = Mailer::from_env()?.map(Arc::new);
+    if mailer.is_none() {
+        warn!("SMTP_HOST is not set; EMAIL notifications will fail");
+    }
+
+    let _digest_scheduler = digest::schedule(pool.clone(), mailer.clone()).await?;
+
+    let app_state = AppState {
+        db: pool,
+        mailer,
+    };
+
+    let api_v1 = Router::new()
+        .route("/notifications", post(send_notification).get(list_notifications))
+        .route(
+            "/users/:user_id/notification-preferences",
+            get(get_preferences).put(update_preferences),
+        )
+        .route("/digests/runs", post(run_digests));
+
+    // API routes answer 503 until these pass; /health, /ready and /metrics answer from the start
+    let mut startup = Startup::new("notification").migrations(app_state.db.clone(), MIGRATIONS);
+    if let Some(mailer) = app_state.mailer.clone() {
+        startup = startup.check("smtp", move || {
+            let mailer = mailer.clone();
+            async move { mailer.ping().await }
+        });
+    }
+    let readiness = startup.readiness();
+
+    let app = versioning::versioned("notification", api_v1)
+        .route_layer(middleware::from_fn_with_state(admission, pool::admit))
+        .route_layer(middleware::from_fn_with_state(readiness.clone(), startup::gate))
+        .route("/health", get(health_check))
+        .merge(startup::router(readiness))
+        .merge(dharmaguard_common::metrics::router())
+        .with_state(app_state);
+
+    let listener = TcpListener::bind("0.0.0.0:8085").await?;
+    info!("Notification service listening on port 8085");
+
+    startup
+        .serve(async { axum::serve(listener, app).await.map_err(anyhow::Error::from) })
+        .await
+}
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(serde_json::json!({"status": "healthy", "service": "notification"}))
+}
+
+async fn send_notification(
+    State(state): State<AppState>,
+    Json(request): Json<NotificationRequest>,
+) -> Result<Json<SubmitOutcome>, (StatusCode, Json<serde_json::Value>)> {
+    let errors = request.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+
+    match pipeline::submit(&state.db, state.mailer.as_deref(), request).await {
+        Ok(outcome) => Ok(Json(outcome)),
+        Err(e) => {
+            error!("Failed to submit notification: {:#}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to submit notification"})),
+            ))
+        }
+    }
+}
+
+async fn list_notifications(
+    tenant: TenantContext,
+    Query(params): Query<ListNotificationsParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Notification>>, StatusCode> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 500);
+
+    match tenant_query_as!(
+        Notification,
+        &tenant,
+        r#"
+        SELECT notification_id, channel, priority, category, title, body, occurrence_count, status,
+               digest_id, first_occurred_at, last_occurred_at, delivered_at
+        FROM notifications
+        WHERE tenant_id = $1 AND user_id = $2 AND ($3::text IS NULL OR status = $3)
+        ORDER BY last_occurred_at DESC
+        LIMIT $4
+        "#,
+        params.user_id,
+        params.status,
+        limit
+    )
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(notifications) => Ok(Json(notifications)),
+        Err(e) => {
+            error!("Failed to list notifications for user {}: {}", params.user_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_preferences(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Preferences>, StatusCode> {
+    match digest::load_preferences(&state.db, user_id).await {
+        Ok(preferences) => Ok(Json(preferences)),
+        Err(e) => {
+            error!("Failed to load notification preferences for user {}: {}", user_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn update_preferences(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(request): Json<UpdatePreferencesRequest>,
+) -> Result<Json<Preferences>, (StatusCode, Json<serde_json::Value>)> {
+    let errors = request.preferences.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+
+    let tenant = TenantContext::new(request.tenant_id);
+    match digest::save_preferences(&state.db, &tenant, user_id, &request.preferences).await {
+        Ok(preferences) => {
+            info!(
+                "Notification preferences for user {}: digests {}, de-dup window {}s",
+                user_id, preferences.digest_frequency, preferences.dedup_window_secs
+            );
+            Ok(Json(preferences))
+        }
+        // The user belongs to another tenant
+        Err(sqlx::Error::RowNotFound) => Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "user not found in tenant"})),
+        )),
+        Err(e) => {
+            error!("Failed to save notification preferences for user {}: {}", user_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to save preferences"})),
+            ))
+        }
+    }
+}
+
+/// Send due digests now instead of waiting for the next scheduled run
+async fn run_digests(State(state): State<AppState>) -> Result<Json<DigestRunSummary>, StatusCode> {
+    match digest::run_due(&state.db, state.mailer.as_deref()).await {
+        Ok(summary) => Ok(Json(summary)),
+        Err(e) => {
+            error!("Notification digest run failed: {:#}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
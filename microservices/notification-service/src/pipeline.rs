@@ -0,0 +1,271 @@
+//! De-duplication and routing of incoming notifications
+//!
+//! Each notification is fingerprinted on tenant, recipient, channel, category
+//! and either the caller's dedup_key or its title and body. The first
+//! occurrence of a fingerprint opens a window of the recipient's
+//! dedup_window_secs; repeats inside the window are folded into that
+//! notification's occurrence_count instead of being sent again. Notifications
+//! at or below the recipient's digest_max_priority wait for their next digest
+//! while digests are on. CRITICAL notifications are always sent at once.
+
+use anyhow::Context;
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+
+use crate::digest;
+use crate::mailer::Mailer;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "LOW",
+            Self::Medium => "MEDIUM",
+            Self::High => "HIGH",
+            Self::Critical => "CRITICAL",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "LOW" => Some(Self::Low),
+            "MEDIUM" => Some(Self::Medium),
+            "HIGH" => Some(Self::High),
+            "CRITICAL" => Some(Self::Critical),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Channel {
+    Email,
+    InApp,
+}
+
+impl Channel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Email => "EMAIL",
+            Self::InApp => "IN_APP",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NotificationRequest {
+    pub tenant_id: Uuid,
+    pub user_id: Uuid,
+    pub channel: Channel,
+    pub priority: Priority,
+    /// Source of the notification, e.g. SURVEILLANCE_ALERT or REPORT_DEADLINE
+    pub category: String,
+    pub title: String,
+    pub body: String,
+    /// Identity of the underlying condition; defaults to the title and body
+    pub dedup_key: Option<String>,
+}
+
+impl NotificationRequest {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.category.trim().is_empty() || self.category.len() > 100 {
+            errors.push("category must be 1-100 characters".to_string());
+        }
+        if self.title.trim().is_empty() || self.title.len() > 255 {
+            errors.push("title must be 1-255 characters".to_string());
+        }
+        if self.body.trim().is_empty() {
+            errors.push("body is required".to_string());
+        }
+        errors
+    }
+
+    fn fingerprint(&self) -> String {
+        let (tenant_id, user_id) = (self.tenant_id.to_string(), self.user_id.to_string());
+        let mut hasher = Sha256::new();
+        for part in [tenant_id.as_str(), user_id.as_str(), self.channel.as_str(), self.category.as_str()] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        match &self.dedup_key {
+            Some(key) => hasher.update(key.as_bytes()),
+            None => {
+                hasher.update(self.title.as_bytes());
+                hasher.update([0]);
+                hasher.update(self.body.as_bytes());
+            }
+        }
+        hex::encode(hasher.finalize())
+    }
+}
+
+#[derive(Serialize)]
+pub struct SubmitOutcome {
+    pub notification_id: Uuid,
+    pub status: String,
+    /// Folded into an earlier notification inside its de-dup window
+    pub duplicate: bool,
+    pub occurrence_count: i32,
+}
+
+pub async fn submit(db: &PgPool, mailer: Option<&Mailer>, request: NotificationRequest) -> anyhow::Result<SubmitOutcome> {
+    let preferences = digest::load_preferences(db, request.user_id).await?;
+    let fingerprint = request.fingerprint();
+    let notification_id = Uuid::new_v4();
+
+    let mut tx = db.begin().await?;
+    if preferences.dedup_window_secs > 0 {
+        // Atomic claim of the fingerprint: the existing owner while its window is
+        // open, otherwise this notification, which restarts the window
+        let owner = sqlx::query_scalar!(
+            r#"
+            INSERT INTO notification_dedup (fingerprint, notification_id, window_ends_at)
+            VALUES ($1, $2, NOW() + make_interval(secs => $3))
+            ON CONFLICT (fingerprint) DO UPDATE SET
+                notification_id = CASE WHEN notification_dedup.window_ends_at > NOW()
+                    THEN notification_dedup.notification_id ELSE EXCLUDED.notification_id END,
+                suppressed = CASE WHEN notification_dedup.window_ends_at > NOW()
+                    THEN notification_dedup.suppressed + 1 ELSE 0 END,
+                window_ends_at = CASE WHEN notification_dedup.window_ends_at > NOW()
+                    THEN notification_dedup.window_ends_at ELSE EXCLUDED.window_ends_at END
+            RETURNING notification_id
+            "#,
+            fingerprint,
+            notification_id,
+            f64::from(preferences.dedup_window_secs)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if owner != notification_id {
+            let existing = sqlx::query!(
+                r#"
+                UPDATE notifications SET occurrence_count = occurrence_count + 1, last_occurred_at = NOW()
+                WHERE notification_id = $1
+                RETURNING status, occurrence_count
+                "#,
+                owner
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
+
+            counter!("notifications_suppressed_total", 1, "priority" => request.priority.as_str());
+            return Ok(SubmitOutcome {
+                notification_id: owner,
+                status: existing.status,
+                duplicate: true,
+                occurrence_count: existing.occurrence_count,
+            });
+        }
+    }
+
+    let batched = preferences.batches(request.priority);
+    let status = if batched { "PENDING_DIGEST" } else { "PENDING" };
+    tenant_query!(
+        &TenantContext::new(request.tenant_id),
+        r#"
+        INSERT INTO notifications (
+            tenant_id, notification_id, user_id, channel, priority, category, title, body, fingerprint, status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+        notification_id,
+        request.user_id,
+        request.channel.as_str(),
+        request.priority.as_str(),
+        request.category,
+        request.title,
+        request.body,
+        fingerprint,
+        status
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    if batched {
+        counter!("notifications_batched_total", 1, "priority" => request.priority.as_str());
+        return Ok(SubmitOutcome {
+            notification_id,
+            status: status.to_string(),
+            duplicate: false,
+            occurrence_count: 1,
+        });
+    }
+
+    let (status, failure_reason) =
+        match deliver(db, mailer, request.user_id, request.channel, &request.title, request.body.clone()).await {
+            Ok(()) => ("DELIVERED", None),
+            Err(e) => {
+                warn!("Failed to deliver notification {}: {:#}", notification_id, e);
+                ("FAILED", Some(format!("{:#}", e)))
+            }
+        };
+    sqlx::query!(
+        r#"
+        UPDATE notifications
+        SET status = $2, failure_reason = $3, delivered_at = CASE WHEN $2 = 'DELIVERED' THEN NOW() END
+        WHERE notification_id = $1
+        "#,
+        notification_id,
+        status,
+        failure_reason
+    )
+    .execute(db)
+    .await?;
+    counter!("notifications_sent_total", 1, "priority" => request.priority.as_str(), "status" => status);
+    info!(
+        "Notification {} for user {} ({}): {}",
+        notification_id,
+        request.user_id,
+        request.channel.as_str(),
+        status
+    );
+
+    Ok(SubmitOutcome {
+        notification_id,
+        status: status.to_string(),
+        duplicate: false,
+        occurrence_count: 1,
+    })
+}
+
+/// Send over the channel; IN_APP notifications are delivered by being stored
+pub async fn deliver(
+    db: &PgPool,
+    mailer: Option<&Mailer>,
+    user_id: Uuid,
+    channel: Channel,
+    subject: &str,
+    body: String,
+) -> anyhow::Result<()> {
+    match channel {
+        Channel::InApp => Ok(()),
+        Channel::Email => {
+            let mailer = mailer.context("email delivery is not configured")?;
+            let email = sqlx::query_scalar!("SELECT email FROM users WHERE user_id = $1", user_id)
+                .fetch_optional(db)
+                .await?
+                .context("recipient does not exist")?;
+            mailer.send(&email, subject, body).await
+        }
+    }
+}
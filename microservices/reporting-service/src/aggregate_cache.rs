@@ -0,0 +1,280 @@
+//! Redis cache of report aggregates
+//!
+//! Aggregates that scan the trades table, such as TRADING_SUMMARY, are kept in
+//! Redis under (tenant, report type, period) for REPORT_AGGREGATE_CACHE_TTL_SECS.
+//! Trade writes are announced on the report_aggregates_stale channel by the
+//! triggers of migration 064, one notice per tenant and statement with the
+//! first and last trade date written, and every cached period of the tenant
+//! overlapping those dates is dropped. Each tenant also has a generation that
+//! is bumped on every notice; an aggregate is only stored when the generation
+//! is the one read before computing it, so trades landing during the scan are
+//! not cached over.
+//!
+//! Caching is off without REPORT_AGGREGATE_CACHE_URL. Redis failing or being
+//! slow only costs the cache: the aggregate is computed as if it had missed.
+//! Lookups are counted in report_aggregate_cache_{hits,misses,errors}_total by
+//! report type.
+
+use chrono::NaiveDate;
+use metrics::counter;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const CHANNEL: &str = "report_aggregates_stale";
+const PREFIX: &str = "report-aggregate";
+/// Longest a lookup or store may take before the cache is passed over
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+/// Wait before listening again after the listener fails
+const RELISTEN: Duration = Duration::from_secs(5);
+
+/// Store an aggregate and index it under its tenant, unless the tenant's
+/// generation moved since ARGV[1] was read
+const STORE: &str = r#"
+if (redis.call('GET', KEYS[2]) or '0') ~= ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+redis.call('SADD', KEYS[3], KEYS[1])
+redis.call('EXPIRE', KEYS[3], ARGV[3])
+return 1
+"#;
+
+pub struct AggregateCache {
+    redis: Option<ConnectionManager>,
+    ttl_secs: u64,
+}
+
+impl AggregateCache {
+    pub async fn from_env() -> anyhow::Result<Self> {
+        let ttl_secs = std::env::var("REPORT_AGGREGATE_CACHE_TTL_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(900u64)
+            .max(1);
+        let redis = match std::env::var("REPORT_AGGREGATE_CACHE_URL").ok().filter(|url| !url.is_empty()) {
+            Some(url) => {
+                let client = redis::Client::open(url)?;
+                let redis = ConnectionManager::new(client).await?;
+                info!("Caching report aggregates in Redis for {}s", ttl_secs);
+                Some(redis)
+            }
+            None => None,
+        };
+        Ok(Self { redis, ttl_secs })
+    }
+
+    /// The cached aggregate of the tenant's period, or the one `compute` gives,
+    /// which is cached for the next time
+    pub async fn get_or_compute<T, E, F, Fut>(
+        &self,
+        tenant_id: Uuid,
+        report_type: &str,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        compute: F,
+    ) -> Result<T, E>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let Some(connection) = &self.redis else {
+            return compute().await;
+        };
+        let mut con = connection.clone();
+        let key = entry_key(tenant_id, report_type, period_start, period_end);
+        let generation_key = generation_key(tenant_id);
+
+        let looked_up = tokio::time::timeout(
+            REDIS_TIMEOUT,
+            redis::pipe()
+                .get(&key)
+                .get(&generation_key)
+                .query_async::<_, (Option<String>, Option<i64>)>(&mut con),
+        )
+        .await;
+        let generation = match looked_up {
+            Ok(Ok((cached, generation))) => {
+                if let Some(aggregate) = cached.and_then(|cached| serde_json::from_str(&cached).ok()) {
+                    counter!("report_aggregate_cache_hits_total", 1, "report_type" => report_type.to_string());
+                    return Ok(aggregate);
+                }
+                counter!("report_aggregate_cache_misses_total", 1, "report_type" => report_type.to_string());
+                generation.unwrap_or(0)
+            }
+            Ok(Err(e)) => {
+                counter!("report_aggregate_cache_errors_total", 1, "report_type" => report_type.to_string());
+                warn!("Report aggregate cache lookup failed: {}", e);
+                return compute().await;
+            }
+            Err(_) => {
+                counter!("report_aggregate_cache_errors_total", 1, "report_type" => report_type.to_string());
+                warn!("Report aggregate cache lookup timed out");
+                return compute().await;
+            }
+        };
+
+        let aggregate = compute().await?;
+        let Ok(value) = serde_json::to_string(&aggregate) else {
+            return Ok(aggregate);
+        };
+        let stored = tokio::time::timeout(
+            REDIS_TIMEOUT,
+            redis::Script::new(STORE)
+                .key(&key)
+                .key(&generation_key)
+                .key(index_key(tenant_id))
+                .arg(generation)
+                .arg(value)
+                .arg(self.ttl_secs)
+                .invoke_async::<_, i64>(&mut con),
+        )
+        .await;
+        match stored {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                counter!("report_aggregate_cache_errors_total", 1, "report_type" => report_type.to_string());
+                warn!("Failed to cache the {} aggregate of tenant {}: {}", report_type, tenant_id, e);
+            }
+            Err(_) => {
+                counter!("report_aggregate_cache_errors_total", 1, "report_type" => report_type.to_string());
+                warn!("Caching the {} aggregate of tenant {} timed out", report_type, tenant_id);
+            }
+        }
+        Ok(aggregate)
+    }
+
+    /// Drop the tenant's cached periods that overlap `first` to `last`,
+    /// returning how many there were
+    pub async fn invalidate(&self, tenant_id: Uuid, first: NaiveDate, last: NaiveDate) -> redis::RedisResult<usize> {
+        let Some(connection) = &self.redis else {
+            return Ok(0);
+        };
+        let mut con = connection.clone();
+        con.incr::<_, _, i64>(generation_key(tenant_id), 1).await?;
+        let index = index_key(tenant_id);
+        let cached: Vec<String> = con.smembers(&index).await?;
+        let stale: Vec<String> = cached
+            .into_iter()
+            .filter(|key| period(key).map_or(true, |(start, end)| start <= last && end >= first))
+            .collect();
+        if stale.is_empty() {
+            return Ok(0);
+        }
+        redis::pipe()
+            .del(&stale)
+            .ignore()
+            .srem(&index, &stale)
+            .ignore()
+            .query_async::<_, ()>(&mut con)
+            .await?;
+        counter!("report_aggregate_cache_invalidated_total", stale.len() as u64);
+        Ok(stale.len())
+    }
+
+    /// Drop every cached aggregate, for when trade notices may have been missed
+    async fn flush(&self) -> redis::RedisResult<()> {
+        let Some(connection) = &self.redis else {
+            return Ok(());
+        };
+        let mut con = connection.clone();
+        let mut keys = Vec::new();
+        {
+            let mut scan = connection.clone();
+            let mut iter = scan.scan_match::<_, String>(format!("{}:*", PREFIX)).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+        }
+        for chunk in keys.chunks(500) {
+            let mut pipe = redis::pipe();
+            for key in chunk {
+                if key.ends_with(":generation") {
+                    pipe.incr(key, 1).ignore();
+                } else {
+                    pipe.del(key).ignore();
+                }
+            }
+            pipe.query_async::<_, ()>(&mut con).await?;
+        }
+        Ok(())
+    }
+
+    /// Follow trade writes and drop the aggregates they make stale, for as long
+    /// as the service runs
+    pub fn spawn_invalidation(self: Arc<Self>, db: PgPool) {
+        if self.redis.is_none() {
+            return;
+        }
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.listen(&db).await {
+                    warn!("Report aggregate invalidation stopped, listening again: {:#}", e);
+                }
+                tokio::time::sleep(RELISTEN).await;
+            }
+        });
+    }
+
+    async fn listen(&self, db: &PgPool) -> anyhow::Result<()> {
+        let mut listener = PgListener::connect_with(db).await?;
+        listener.listen(CHANNEL).await?;
+        loop {
+            let Some(notice) = listener.try_recv().await? else {
+                // The connection dropped, and with it the notices sent until it is back
+                warn!("Lost the trade notices connection, dropping all cached report aggregates");
+                self.flush().await?;
+                continue;
+            };
+            let Some((tenant_id, first, last)) = parse_notice(notice.payload()) else {
+                warn!("Ignoring malformed {} notice {:?}", CHANNEL, notice.payload());
+                continue;
+            };
+            if let Err(e) = self.invalidate(tenant_id, first, last).await {
+                warn!(
+                    "Failed to drop cached report aggregates of tenant {} for {} to {}, flushing: {}",
+                    tenant_id, first, last, e
+                );
+                self.flush().await?;
+            }
+        }
+    }
+}
+
+fn entry_key(tenant_id: Uuid, report_type: &str, period_start: NaiveDate, period_end: NaiveDate) -> String {
+    // The tenant is the hash tag, so a tenant's keys share a cluster slot for STORE
+    format!("{}:{{{}}}:{}:{}:{}", PREFIX, tenant_id, report_type, period_start, period_end)
+}
+
+fn generation_key(tenant_id: Uuid) -> String {
+    format!("{}:{{{}}}:generation", PREFIX, tenant_id)
+}
+
+fn index_key(tenant_id: Uuid) -> String {
+    format!("{}:{{{}}}:periods", PREFIX, tenant_id)
+}
+
+/// The period of an entry key
+fn period(key: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let mut parts = key.rsplitn(3, ':');
+    let end = parts.next()?.parse().ok()?;
+    let start = parts.next()?.parse().ok()?;
+    Some((start, end))
+}
+
+/// "<tenant_id> <first date> <last date>"
+fn parse_notice(payload: &str) -> Option<(Uuid, NaiveDate, NaiveDate)> {
+    let mut parts = payload.split(' ');
+    let tenant_id = parts.next()?.parse().ok()?;
+    let first = parts.next()?.parse().ok()?;
+    let last = parts.next()?.parse().ok()?;
+    Some((tenant_id, first, last))
+}
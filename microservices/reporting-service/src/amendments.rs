@@ -0,0 +1,272 @@
+//! Amended versions of reports
+//!
+//! POST /reports/:id/amend regenerates a report for the same type and period
+//! from the data as it now stands, as the next version of the report's chain.
+//! The amendment names the version it supersedes and why; the superseded
+//! version is kept and records what superseded it. Only the current version of
+//! a chain can be amended, so the chain never forks, and it is rendered in the
+//! format of the version it amends unless the request names another. It is
+//! generated with the parameters of the version it amends.
+//! GET /reports/:id/versions lists the whole chain from any of its versions.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+
+use crate::generators;
+use crate::jobs::Stopped;
+use crate::render::ReportFormat;
+use crate::report_files::ReportFiles;
+use crate::schedule;
+use crate::signing::CannotSign;
+use crate::xbrl::Nonconforming;
+use crate::ReportEngine;
+
+/// Upper bound on the length of an amendment reason
+const MAX_REASON_LENGTH: usize = 2000;
+
+#[derive(Deserialize)]
+pub struct AmendRequest {
+    pub reason: String,
+    /// Format of the amendment; that of the amended version when absent
+    pub format: Option<String>,
+    pub amended_by: Option<Uuid>,
+}
+
+/// How `schedule::record` links a new report into an amendment chain
+pub struct Amendment {
+    pub amends: Uuid,
+    pub original: Uuid,
+    pub version: i32,
+    pub reason: String,
+    /// The amended version's parameters, which the amendment is generated with
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+/// The version being amended was superseded first, by this report
+#[derive(Debug, thiserror::Error)]
+#[error("report has already been superseded by {0}")]
+pub struct Superseded(pub Uuid);
+
+#[derive(Serialize)]
+pub struct ReportVersion {
+    pub report_id: Uuid,
+    pub version: i32,
+    pub status: Option<String>,
+    pub generated_at: Option<DateTime<Utc>>,
+    pub amends_report_id: Option<Uuid>,
+    pub amendment_reason: Option<String>,
+    pub superseded_by: Option<Uuid>,
+    pub superseded_at: Option<DateTime<Utc>>,
+    pub embargoed_until: Option<DateTime<Utc>>,
+    pub download_url: String,
+}
+
+#[derive(Serialize)]
+pub struct ReportVersions {
+    pub original_report_id: Uuid,
+    pub current_report_id: Uuid,
+    pub report_type: Option<String>,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    /// From the first version
+    pub versions: Vec<ReportVersion>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AmendError {
+    #[error("report not found")]
+    NotFound,
+    #[error("report has been superseded by {0}; amend the current version")]
+    Superseded(Uuid),
+    #[error("invalid amendment")]
+    Invalid(Vec<String>),
+    #[error(transparent)]
+    Stopped(Stopped),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for AmendError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// Generate the next version of the report, returning its id and version
+pub async fn amend(
+    db: &PgPool,
+    files: &ReportFiles,
+    generator: &ReportEngine,
+    tenant: &TenantContext,
+    report_id: Uuid,
+    request: AmendRequest,
+) -> Result<(Uuid, i32), AmendError> {
+    let amended = tenant_query!(
+        tenant,
+        r#"
+        SELECT r.template_id, r.report_period_start, r.report_period_end, r.file_path, r.version,
+               r.original_report_id, r.superseded_by, r.report_parameters, t.report_type as "report_type?"
+        FROM regulatory_reports_v2 r
+        LEFT JOIN report_templates t ON t.template_id = r.template_id
+        WHERE r.tenant_id = $1 AND r.report_id = $2
+        "#,
+        report_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(AmendError::NotFound)?;
+    if let Some(superseded_by) = amended.superseded_by {
+        return Err(AmendError::Superseded(superseded_by));
+    }
+
+    let mut errors = Vec::new();
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        errors.push("reason is required".to_string());
+    } else if reason.len() > MAX_REASON_LENGTH {
+        errors.push(format!("reason must be at most {} characters", MAX_REASON_LENGTH));
+    }
+    let format = match &request.format {
+        Some(format) => ReportFormat::parse(format).or_else(|| {
+            errors.push(format!("unknown format {}", format));
+            None
+        }),
+        None => Some(amended.file_path.as_deref().and_then(ReportFormat::from_path).unwrap_or(ReportFormat::Pdf)),
+    };
+    let parameters = match amended.report_parameters {
+        serde_json::Value::Object(parameters) => parameters,
+        _ => serde_json::Map::new(),
+    };
+    let report_type = match amended.report_type {
+        Some(report_type) => match generators::get(&report_type) {
+            Some(generator) => {
+                // A parameter the report type has dropped since fails here rather than generating
+                if let Err(parameter_errors) = generators::resolve(generator, &parameters) {
+                    errors.extend(parameter_errors);
+                }
+                Some(report_type)
+            }
+            None => {
+                errors.push(format!("{} reports cannot be amended", report_type));
+                None
+            }
+        },
+        None => {
+            errors.push("the report's template no longer names its report type".to_string());
+            None
+        }
+    };
+    let (Some(format), Some(report_type)) = (format, report_type) else {
+        return Err(AmendError::Invalid(errors));
+    };
+    if !files.covers(format, &report_type) {
+        errors.push(format!("{} reports cannot be rendered as {}", report_type, format.as_str()));
+    }
+    if !errors.is_empty() {
+        return Err(AmendError::Invalid(errors));
+    }
+
+    let amendment = Amendment {
+        amends: report_id,
+        original: amended.original_report_id.unwrap_or(report_id),
+        version: amended.version + 1,
+        reason: reason.to_string(),
+        parameters,
+    };
+    let recorded = schedule::record(
+        db,
+        files,
+        generator,
+        tenant.tenant_id(),
+        amended.template_id,
+        &report_type,
+        format,
+        amended.report_period_start,
+        amended.report_period_end,
+        request.amended_by,
+        Some(&amendment),
+    )
+    .await;
+    match recorded {
+        Ok(amendment_id) => Ok((amendment_id, amendment.version)),
+        Err(e) => {
+            if let Some(Superseded(superseded_by)) = e.downcast_ref::<Superseded>() {
+                return Err(AmendError::Superseded(*superseded_by));
+            }
+            if let Some(Nonconforming(errors)) = e.downcast_ref::<Nonconforming>() {
+                return Err(AmendError::Invalid(errors.clone()));
+            }
+            if let Some(unsigned) = e.downcast_ref::<CannotSign>() {
+                return Err(AmendError::Invalid(vec![unsigned.to_string()]));
+            }
+            match e.downcast::<Stopped>() {
+                Ok(stopped) => Err(AmendError::Stopped(stopped)),
+                Err(e) => Err(AmendError::Internal(e)),
+            }
+        }
+    }
+}
+
+/// Every version of the chain the report belongs to
+pub async fn versions(db: &PgPool, tenant: &TenantContext, report_id: Uuid) -> Result<ReportVersions, AmendError> {
+    let report = tenant_query!(
+        tenant,
+        r#"
+        SELECT COALESCE(r.original_report_id, r.report_id) as "original_report_id!",
+               r.report_period_start, r.report_period_end, t.report_type as "report_type?"
+        FROM regulatory_reports_v2 r
+        LEFT JOIN report_templates t ON t.template_id = r.template_id
+        WHERE r.tenant_id = $1 AND r.report_id = $2
+        "#,
+        report_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(AmendError::NotFound)?;
+
+    let versions: Vec<ReportVersion> = tenant_query!(
+        tenant,
+        r#"
+        SELECT report_id, version, status, generated_at, amends_report_id, amendment_reason,
+               superseded_by, superseded_at, report_embargoed_until(report_id) as embargoed_until
+        FROM regulatory_reports_v2
+        WHERE tenant_id = $1 AND COALESCE(original_report_id, report_id) = $2
+        ORDER BY version
+        "#,
+        report.original_report_id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| ReportVersion {
+        report_id: row.report_id,
+        version: row.version,
+        status: row.status,
+        generated_at: row.generated_at,
+        amends_report_id: row.amends_report_id,
+        amendment_reason: row.amendment_reason,
+        superseded_by: row.superseded_by,
+        superseded_at: row.superseded_at,
+        embargoed_until: row.embargoed_until,
+        download_url: format!("/reports/{}/download", row.report_id),
+    })
+    .collect();
+    let current_report_id = versions
+        .iter()
+        .find(|version| version.superseded_by.is_none())
+        .map_or(report.original_report_id, |version| version.report_id);
+
+    Ok(ReportVersions {
+        original_report_id: report.original_report_id,
+        current_report_id,
+        report_type: report.report_type,
+        period_start: report.report_period_start,
+        period_end: report.report_period_end,
+        versions,
+    })
+}
@@ -0,0 +1,240 @@
+//! Caller authentication and tenant isolation for the reporting API
+//!
+//! Every API request but those of the recipient portal, which presents its own
+//! access tokens (see `portal`), carries a user-service JWT as a bearer token
+//! (HS256, JWT_SECRET). The [`authorize`] middleware takes the caller's tenant
+//! and role from its claims and refuses:
+//!
+//! - a `tenant_id` in the query string or the JSON body that the caller may not
+//!   access: its own tenant and the `tenant_ids` of its claims, or every tenant
+//!   for REPORT_PLATFORM_ROLES;
+//! - anything but reading unless the caller holds one of REPORT_GENERATOR_ROLES:
+//!   every POST, PUT, PATCH and DELETE is refused by default, so requesting,
+//!   approving and delivering reports and changing their settings (schedules,
+//!   embargoes, legal holds, layouts, signing certificates, SFTP targets,
+//!   webhooks, access tokens) all need it. Only a layout preview, which renders
+//!   a sample and stores nothing, is open to every caller.
+//!
+//! The tenant so checked, or the caller's own when the request names none, is
+//! the [`TenantContext`] of the request, so handlers scoped by it only ever
+//! see the caller's reports. The [`Caller`] itself is kept alongside it: who
+//! requested, approved or changed something is always the token's user, never
+//! a `*_by` field of the body.
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body},
+    extract::{FromRequestParts, Query, Request, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use metrics::counter;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::{is_json, TenantContext};
+
+use crate::AppState;
+
+/// Same as the default limit of the Json extractor that reads the body next
+const MAX_INSPECTED_BODY: usize = 2 * 1024 * 1024;
+
+/// The user-service access token claims
+#[derive(Deserialize)]
+struct Claims {
+    sub: Uuid,
+    tenant_id: Uuid,
+    role: String,
+    /// Further tenants the user may act for, e.g. group entities of a broker
+    #[serde(default)]
+    tenant_ids: Vec<Uuid>,
+}
+
+/// The authenticated caller of a request
+#[derive(Debug, Clone)]
+pub struct Caller {
+    user_id: Uuid,
+    tenant_id: Uuid,
+    role: String,
+    /// Tenants beyond its own the caller may access; `None` for every tenant
+    others: Option<Vec<Uuid>>,
+    /// Holds one of REPORT_GENERATOR_ROLES
+    generator: bool,
+}
+
+impl Caller {
+    pub fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+
+    pub fn may_access(&self, tenant_id: Uuid) -> bool {
+        tenant_id == self.tenant_id || self.others.as_ref().map_or(true, |others| others.contains(&tenant_id))
+    }
+}
+
+/// The caller [`authorize`] authenticated; a route outside it is refused
+#[async_trait]
+impl<S> FromRequestParts<S> for Caller
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Caller>()
+            .cloned()
+            .ok_or_else(|| reject(StatusCode::UNAUTHORIZED, "unauthenticated", "a valid bearer token is required"))
+    }
+}
+
+pub struct Authenticator {
+    decoding_key: DecodingKey,
+    platform_roles: HashSet<String>,
+    generator_roles: HashSet<String>,
+}
+
+impl Authenticator {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let roles = |value: String| {
+            value
+                .split(',')
+                .map(|role| role.trim().to_uppercase())
+                .filter(|role| !role.is_empty())
+                .collect()
+        };
+        let secret =
+            var("JWT_SECRET").ok_or_else(|| anyhow::anyhow!("JWT_SECRET must be set to authenticate callers"))?;
+        Ok(Self {
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            platform_roles: roles(var("REPORT_PLATFORM_ROLES").unwrap_or_else(|| "SUPER_ADMIN".to_string())),
+            generator_roles: roles(
+                var("REPORT_GENERATOR_ROLES")
+                    .unwrap_or_else(|| "SUPER_ADMIN,TENANT_ADMIN,COMPLIANCE_OFFICER".to_string()),
+            ),
+        })
+    }
+
+    pub fn authenticate(&self, token: &str) -> Option<Caller> {
+        let decoded = jsonwebtoken::decode::<Claims>(token, &self.decoding_key, &Validation::new(Algorithm::HS256));
+        let claims = match decoded {
+            Ok(data) => data.claims,
+            Err(e) => {
+                warn!("Rejected bearer token on reporting API: {}", e);
+                return None;
+            }
+        };
+        let role = claims.role.to_uppercase();
+        Some(Caller {
+            user_id: claims.sub,
+            tenant_id: claims.tenant_id,
+            others: (!self.platform_roles.contains(&role)).then_some(claims.tenant_ids),
+            generator: self.generator_roles.contains(&role),
+            role,
+        })
+    }
+}
+
+fn reject(status: StatusCode, reason: &'static str, message: &str) -> Response {
+    counter!("reporting_auth_rejected_total", 1, "reason" => reason);
+    let mut response = (status, Json(serde_json::json!({"error": message}))).into_response();
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    response
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Whether a caller without a REPORT_GENERATOR_ROLES role may make the request, by the path within an API version
+fn open_to_viewers(method: &Method, path: &str) -> bool {
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return true;
+    }
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    matches!((method, segments.as_slice()), (&Method::POST, ["reports", "layouts", _, "preview"]))
+}
+
+/// The body's top-level `tenant_id`; `Some(None)` when it is not a UUID
+fn body_tenant(body: &serde_json::Value) -> Option<Option<Uuid>> {
+    body.get("tenant_id")
+        .and_then(|tenant| tenant.as_str())
+        .map(|tenant| tenant.parse().ok())
+}
+
+/// Authenticate the caller and refuse requests outside its tenants and role
+pub async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    let Some(caller) = bearer(request.headers()).and_then(|token| state.authenticator.authenticate(token)) else {
+        return reject(StatusCode::UNAUTHORIZED, "unauthenticated", "a valid bearer token is required");
+    };
+    let refuse = |tenant_id: Uuid| {
+        warn!(
+            "Refused user {} ({}) access to tenant {} on {}",
+            caller.user_id, caller.role, tenant_id, path
+        );
+        reject(StatusCode::FORBIDDEN, "cross_tenant", "not permitted for this tenant")
+    };
+
+    if !caller.generator && !open_to_viewers(request.method(), &path) {
+        warn!("Refused user {} ({}) {} {}", caller.user_id, caller.role, request.method(), path);
+        return reject(
+            StatusCode::FORBIDDEN,
+            "role",
+            "changing reports and their settings needs a compliance officer or admin role",
+        );
+    }
+
+    let mut tenant = None;
+    if let Ok(Query(params)) = Query::<HashMap<String, String>>::try_from_uri(request.uri()) {
+        if let Some(value) = params.get("tenant_id") {
+            match value.parse::<Uuid>() {
+                Ok(tenant_id) if !caller.may_access(tenant_id) => return refuse(tenant_id),
+                Ok(tenant_id) => tenant = Some(tenant_id),
+                Err(_) => return reject(StatusCode::BAD_REQUEST, "invalid_tenant", "invalid tenant_id"),
+            }
+        }
+    }
+
+    let inspected =
+        is_json(request.headers()) && matches!(*request.method(), Method::POST | Method::PUT | Method::PATCH);
+    let mut request = if inspected {
+        let (parts, body) = request.into_parts();
+        let Ok(bytes) = to_bytes(body, MAX_INSPECTED_BODY).await else {
+            return reject(StatusCode::PAYLOAD_TOO_LARGE, "body_too_large", "request body is too large");
+        };
+        // Malformed bodies are left for the handler's extractor to reject
+        if let Some(named) = serde_json::from_slice::<serde_json::Value>(&bytes).ok().as_ref().and_then(body_tenant) {
+            match named {
+                Some(tenant_id) if !caller.may_access(tenant_id) => return refuse(tenant_id),
+                Some(tenant_id) if tenant.is_some_and(|tenant| tenant != tenant_id) => {
+                    return reject(StatusCode::BAD_REQUEST, "invalid_tenant", "tenant_id of query and body differ");
+                }
+                Some(tenant_id) => tenant = Some(tenant_id),
+                None => return reject(StatusCode::BAD_REQUEST, "invalid_tenant", "invalid tenant_id"),
+            }
+        }
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        request
+    };
+
+    request
+        .extensions_mut()
+        .insert(TenantContext::new(tenant.unwrap_or(caller.tenant_id)));
+    request.extensions_mut().insert(caller);
+    next.run(request).await
+}
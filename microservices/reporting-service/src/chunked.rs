@@ -0,0 +1,313 @@
+//! Reports over long periods, generated in slices
+//!
+//! A period longer than REPORT_CHUNKED_AFTER_DAYS is computed a slice of
+//! REPORT_CHUNK_DAYS at a time (1 for days, 7 for weeks) rather than in one
+//! query, and the slices' partial aggregates are merged here. After every
+//! slice the merged aggregates are checkpointed in
+//! report_generation_checkpoints under a lease, so a generation that fails or
+//! whose worker restarts is carried on from the last slice done by the next
+//! generation of the same report and period, be it the request retried, an
+//! amendment or the schedule running again. Checkpoints older than
+//! REPORT_CHUNK_RESUME_HOURS are started over, as the data may have changed
+//! since.
+//!
+//! A generation that finds the period leased to another is computed in slices
+//! all the same, without checkpointing.
+
+use chrono::{Duration, NaiveDate};
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::future::Future;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::progress::{Phase, Progress};
+
+/// How long a checkpoint stays with its generation without a slice being done
+const LEASE_SECS: f64 = 300.0;
+
+/// Aggregates of a slice of the period, which merge with those of the slices
+/// after it
+pub trait Partial: Serialize + DeserializeOwned + Default {
+    fn merge(&mut self, later: Self);
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkSettings {
+    pub chunked_after_days: i64,
+    pub chunk_days: i64,
+    pub resume_within_hours: i32,
+}
+
+impl ChunkSettings {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            chunked_after_days: number("REPORT_CHUNKED_AFTER_DAYS", 31).max(1),
+            chunk_days: number("REPORT_CHUNK_DAYS", 7).max(1),
+            resume_within_hours: number("REPORT_CHUNK_RESUME_HOURS", 24).clamp(1, 24 * 30) as i32,
+        }
+    }
+}
+
+/// The report's aggregates over the period, from `compute` over the whole
+/// period when it is short and slice by slice otherwise, reporting the slices
+/// done to `progress`
+#[allow(clippy::too_many_arguments)]
+pub async fn generate<P, F, Fut>(
+    db: &PgPool,
+    settings: &ChunkSettings,
+    progress: &Progress,
+    tenant_id: Uuid,
+    report_type: &str,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    compute: F,
+) -> Result<P, sqlx::Error>
+where
+    P: Partial,
+    F: Fn(NaiveDate, NaiveDate) -> Fut,
+    Fut: Future<Output = Result<P, sqlx::Error>>,
+{
+    if (period_end - period_start).num_days() + 1 <= settings.chunked_after_days {
+        let merged = compute(period_start, period_end).await?;
+        progress.phase(Phase::Aggregating).await;
+        return Ok(merged);
+    }
+    let slices = slices(period_start, period_end, settings.chunk_days);
+    let chunks_total = slices.len();
+    let checkpoint = Checkpoint {
+        tenant_id,
+        report_type,
+        period_start,
+        period_end,
+        lease_token: Uuid::new_v4(),
+    };
+
+    let (mut merged, mut completed_through, mut chunks_done, mut checkpointing) =
+        match checkpoint.claim(db, settings, chunks_total).await? {
+            Some((chunks_done, Some(completed_through), Some(Json(partial)))) => {
+                match serde_json::from_value::<P>(partial) {
+                    Ok(partial) => {
+                        info!(
+                            "Resuming {} for tenant {} covering {} to {} after {}",
+                            report_type, tenant_id, period_start, period_end, completed_through
+                        );
+                        (partial, Some(completed_through), chunks_done, true)
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Starting {} for tenant {} over, its checkpoint is unusable: {}",
+                            report_type, tenant_id, e
+                        );
+                        (P::default(), None, 0, true)
+                    }
+                }
+            }
+            Some(_) => (P::default(), None, 0, true),
+            None => {
+                warn!(
+                    "{} for tenant {} covering {} to {} is being generated elsewhere; not checkpointing",
+                    report_type, tenant_id, period_start, period_end
+                );
+                (P::default(), None, 0, false)
+            }
+        };
+
+    for (slice_start, slice_end) in slices {
+        if completed_through.is_some_and(|through| slice_end <= through) {
+            continue;
+        }
+        let partial = match compute(slice_start, slice_end).await {
+            Ok(partial) => partial,
+            Err(e) => {
+                if checkpointing {
+                    if let Err(release) = checkpoint.release(db).await {
+                        warn!(
+                            "Failed to release the checkpoint of {} for tenant {}: {}",
+                            report_type, tenant_id, release
+                        );
+                    }
+                }
+                return Err(e);
+            }
+        };
+        merged.merge(partial);
+        completed_through = Some(slice_end);
+        chunks_done += 1;
+        progress.queried(chunks_done as usize, chunks_total).await;
+        if checkpointing {
+            let saved = checkpoint.save(db, slice_end, chunks_done, &merged).await;
+            match saved {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!("Lost the checkpoint of {} for tenant {} to another generation", report_type, tenant_id);
+                    checkpointing = false;
+                }
+                Err(e) => {
+                    warn!("Failed to checkpoint {} for tenant {}: {}", report_type, tenant_id, e);
+                }
+            }
+        }
+    }
+
+    if checkpointing {
+        if let Err(e) = checkpoint.finish(db).await {
+            warn!("Failed to remove the checkpoint of {} for tenant {}: {}", report_type, tenant_id, e);
+        }
+    }
+    progress.phase(Phase::Aggregating).await;
+    Ok(merged)
+}
+
+/// The period in slices of `days`, the last one cut short at the period's end
+fn slices(period_start: NaiveDate, period_end: NaiveDate, days: i64) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut slices = Vec::new();
+    let mut start = period_start;
+    while start <= period_end {
+        let end = (start + Duration::days(days - 1)).min(period_end);
+        slices.push((start, end));
+        start = end + Duration::days(1);
+    }
+    slices
+}
+
+struct Checkpoint<'a> {
+    tenant_id: Uuid,
+    report_type: &'a str,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    lease_token: Uuid,
+}
+
+type Claimed = (i32, Option<NaiveDate>, Option<Json<serde_json::Value>>);
+
+impl Checkpoint<'_> {
+    /// Take the period's checkpoint, with the progress made so far; None while
+    /// another generation holds it
+    async fn claim(
+        &self,
+        db: &PgPool,
+        settings: &ChunkSettings,
+        chunks_total: usize,
+    ) -> Result<Option<Claimed>, sqlx::Error> {
+        // Checkpoints of other slices, and the tenant's ones too old to trust,
+        // are of no use any more
+        sqlx::query(
+            r#"
+            DELETE FROM report_generation_checkpoints
+            WHERE tenant_id = $1 AND leased_until < NOW()
+            AND (
+                updated_at < NOW() - make_interval(hours => $6)
+                OR (report_type = $2 AND period_start = $3 AND period_end = $4 AND chunk_days <> $5)
+            )
+            "#,
+        )
+        .bind(self.tenant_id)
+        .bind(self.report_type)
+        .bind(self.period_start)
+        .bind(self.period_end)
+        .bind(settings.chunk_days as i32)
+        .bind(settings.resume_within_hours)
+        .execute(db)
+        .await?;
+
+        sqlx::query_as::<_, Claimed>(
+            r#"
+            INSERT INTO report_generation_checkpoints (
+                tenant_id, report_type, period_start, period_end, chunk_days, chunks_total, lease_token, leased_until
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, NOW() + make_interval(secs => $8))
+            ON CONFLICT (tenant_id, report_type, period_start, period_end) DO UPDATE
+            SET lease_token = EXCLUDED.lease_token, leased_until = EXCLUDED.leased_until
+            WHERE report_generation_checkpoints.leased_until < NOW()
+            RETURNING chunks_done, completed_through, partial
+            "#,
+        )
+        .bind(self.tenant_id)
+        .bind(self.report_type)
+        .bind(self.period_start)
+        .bind(self.period_end)
+        .bind(settings.chunk_days as i32)
+        .bind(chunks_total as i32)
+        .bind(self.lease_token)
+        .bind(LEASE_SECS)
+        .fetch_optional(db)
+        .await
+    }
+
+    /// Record the slices done up to `completed_through` and renew the lease;
+    /// false when the checkpoint was taken by another generation
+    async fn save<P: Serialize>(
+        &self,
+        db: &PgPool,
+        completed_through: NaiveDate,
+        chunks_done: i32,
+        merged: &P,
+    ) -> Result<bool, sqlx::Error> {
+        let saved = sqlx::query(
+            r#"
+            UPDATE report_generation_checkpoints
+            SET completed_through = $5, chunks_done = $6, partial = $7,
+                leased_until = NOW() + make_interval(secs => $8), updated_at = NOW()
+            WHERE tenant_id = $1 AND report_type = $2 AND period_start = $3 AND period_end = $4
+            AND lease_token = $9
+            "#,
+        )
+        .bind(self.tenant_id)
+        .bind(self.report_type)
+        .bind(self.period_start)
+        .bind(self.period_end)
+        .bind(completed_through)
+        .bind(chunks_done)
+        .bind(Json(merged))
+        .bind(LEASE_SECS)
+        .bind(self.lease_token)
+        .execute(db)
+        .await?;
+        Ok(saved.rows_affected() == 1)
+    }
+
+    /// Let the next generation carry on at once
+    async fn release(&self, db: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE report_generation_checkpoints SET leased_until = NOW()
+            WHERE tenant_id = $1 AND report_type = $2 AND period_start = $3 AND period_end = $4
+            AND lease_token = $5
+            "#,
+        )
+        .bind(self.tenant_id)
+        .bind(self.report_type)
+        .bind(self.period_start)
+        .bind(self.period_end)
+        .bind(self.lease_token)
+        .execute(db)
+        .await?;
+        Ok(())
+    }
+
+    async fn finish(&self, db: &PgPool) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM report_generation_checkpoints
+            WHERE tenant_id = $1 AND report_type = $2 AND period_start = $3 AND period_end = $4
+            AND lease_token = $5
+            "#,
+        )
+        .bind(self.tenant_id)
+        .bind(self.report_type)
+        .bind(self.period_start)
+        .bind(self.period_end)
+        .bind(self.lease_token)
+        .execute(db)
+        .await?;
+        Ok(())
+    }
+}
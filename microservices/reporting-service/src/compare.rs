@@ -0,0 +1,198 @@
+//! Period-over-period comparison of two reports
+//!
+//! GET /reports/compare?base=..&target=.. compares two of the tenant's reports
+//! of the same type, such as this month's trading summary against last
+//! month's. Every number in the report data is a metric under its dotted path:
+//! `total_trades`, `risk_metrics.var_95`, `pattern_breakdown.WASH_TRADING`.
+//! Rows of a list are keyed by what identifies them rather than by position,
+//! so `instrument_breakdown.RELIANCE.total_value` compares the same instrument
+//! however the two reports rank it; rows without an identifying field are left
+//! out. A metric in only one of the reports is listed with the other side
+//! null. The percentage change is relative to the base and null when the base
+//! is zero.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+
+/// Fields naming a row of a list, joined in this order to key it
+const ROW_KEYS: &[&str] = &[
+    "date",
+    "client_code",
+    "client",
+    "instrument",
+    "segment",
+    "exchange",
+    "alert_id",
+    "alert_type",
+    "severity",
+];
+
+#[derive(Serialize)]
+pub struct ComparedReport {
+    pub report_id: Uuid,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub generated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct MetricChange {
+    pub metric: String,
+    pub base: Option<f64>,
+    pub target: Option<f64>,
+    pub change: Option<f64>,
+    pub change_percent: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct ReportComparison {
+    pub report_type: Option<String>,
+    pub base: ComparedReport,
+    pub target: ComparedReport,
+    /// By metric path
+    pub metrics: Vec<MetricChange>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CompareError {
+    #[error("{0} report not found")]
+    NotFound(&'static str),
+    #[error("a {base} report cannot be compared with a {target} report")]
+    DifferentTypes { base: String, target: String },
+    #[error(transparent)]
+    Internal(#[from] sqlx::Error),
+}
+
+struct LoadedReport {
+    report: ComparedReport,
+    report_type: Option<String>,
+    data: Value,
+}
+
+async fn load(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_id: Uuid,
+    which: &'static str,
+) -> Result<LoadedReport, CompareError> {
+    let row = tenant_query!(
+        tenant,
+        r#"
+        SELECT r.report_period_start, r.report_period_end, r.generated_at, r.report_data,
+               t.report_type as "report_type?"
+        FROM regulatory_reports_v2 r
+        LEFT JOIN report_templates t ON t.template_id = r.template_id
+        WHERE r.tenant_id = $1 AND r.report_id = $2
+        "#,
+        report_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(CompareError::NotFound(which))?;
+    Ok(LoadedReport {
+        report: ComparedReport {
+            report_id,
+            period_start: row.report_period_start,
+            period_end: row.report_period_end,
+            generated_at: row.generated_at,
+        },
+        report_type: row.report_type,
+        data: row.report_data,
+    })
+}
+
+pub async fn compare(
+    db: &PgPool,
+    tenant: &TenantContext,
+    base_id: Uuid,
+    target_id: Uuid,
+) -> Result<ReportComparison, CompareError> {
+    let base = load(db, tenant, base_id, "base").await?;
+    let target = load(db, tenant, target_id, "target").await?;
+    if let (Some(base_type), Some(target_type)) = (&base.report_type, &target.report_type) {
+        if base_type != target_type {
+            return Err(CompareError::DifferentTypes {
+                base: base_type.clone(),
+                target: target_type.clone(),
+            });
+        }
+    }
+
+    let mut paired: BTreeMap<String, (Option<f64>, Option<f64>)> = BTreeMap::new();
+    for (metric, value) in metrics(&base.data) {
+        paired.entry(metric).or_default().0 = Some(value);
+    }
+    for (metric, value) in metrics(&target.data) {
+        paired.entry(metric).or_default().1 = Some(value);
+    }
+    let metrics = paired
+        .into_iter()
+        .map(|(metric, (base, target))| {
+            let change = base.zip(target).map(|(base, target)| target - base);
+            MetricChange {
+                metric,
+                base,
+                target,
+                change,
+                change_percent: change.zip(base).filter(|(_, base)| *base != 0.0).map(|(change, base)| {
+                    change / base.abs() * 100.0
+                }),
+            }
+        })
+        .collect();
+
+    Ok(ReportComparison {
+        report_type: base.report_type.or(target.report_type),
+        base: base.report,
+        target: target.report,
+        metrics,
+    })
+}
+
+/// Every number of the report data under its path
+fn metrics(data: &Value) -> Vec<(String, f64)> {
+    let mut metrics = Vec::new();
+    collect(String::new(), data, &mut metrics);
+    metrics
+}
+
+fn collect(prefix: String, value: &Value, metrics: &mut Vec<(String, f64)>) {
+    let join = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+    match value {
+        Value::Number(number) => {
+            if let Some(number) = number.as_f64() {
+                metrics.push((prefix, number));
+            }
+        }
+        Value::Object(map) => {
+            for (key, value) in map {
+                collect(join(key), value, metrics);
+            }
+        }
+        Value::Array(rows) => {
+            for row in rows {
+                if let Some(key) = row_key(row) {
+                    collect(join(&key), row, metrics);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// What identifies a row of a list, such as its instrument
+fn row_key(row: &Value) -> Option<String> {
+    let row = row.as_object()?;
+    let parts: Vec<&str> = ROW_KEYS
+        .iter()
+        .filter_map(|key| row.get(*key).and_then(Value::as_str))
+        .collect();
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
@@ -0,0 +1,1088 @@
+//! Report delivery by email
+//!
+//! Reports containing client PII are sent as AES-256 encrypted ZIP archives.
+//! Every recipient gets a freshly generated password, sent by SMS before the
+//! email goes out, so the archive and its password never travel over the same
+//! channel. Passwords are not stored; a recipient who loses one gets a new
+//! delivery. The archived file is the report's rendered file; see `report_files`.
+//! Tenant takeout archives go out the same way.
+//!
+//! Instead of the file, a report can be sent as a report portal link backed by
+//! an access token for that report alone (see `portal`), issued per recipient.
+//! Approved reports are delivered on request, and once approved to the
+//! recipients named by the generation request or report schedule; see `review`.
+//!
+//! A report delivery that fails for a reason that may pass, such as an SMTP
+//! server deferring the message or the SMS gateway being unavailable, is
+//! RETRYING and attempted again after REPORT_DELIVERY_RETRY_SECS, doubling
+//! each time, up to REPORT_DELIVERY_MAX_ATTEMPTS attempts. The recipient's phone
+//! number is kept until then to send the new archive password. Takeout
+//! deliveries are not retried; they can be requested again while the archive
+//! is kept.
+
+use anyhow::Context;
+use lettre::message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rand::{rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, AesMode, CompressionMethod, ZipWriter};
+
+use crate::portal::{self, IssueTokenRequest, PortalSettings, RevokeTokenRequest};
+use crate::report_files::{FileColumns, FileExpired, ReportFiles};
+
+/// Unambiguous characters only, since the password is read off a phone and typed
+const PASSWORD_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnpqrstuvwxyz23456789";
+const PASSWORD_LENGTH: usize = 16;
+const MAX_RECIPIENTS: usize = 25;
+/// How often deliveries due for another attempt are looked for
+const RETRY_POLL: Duration = Duration::from_secs(30);
+const RETRY_BATCH: i64 = 20;
+/// Longest wait between two attempts
+const MAX_RETRY_DELAY_SECS: i64 = 6 * 60 * 60;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReportRecipient {
+    pub email: String,
+    /// E.164 number the archive password is sent to; required for encrypted delivery
+    pub phone: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DeliverReportRequest {
+    pub tenant_id: Uuid,
+    pub requested_by: Option<Uuid>,
+    pub recipients: Vec<ReportRecipient>,
+    /// Reports with client PII are always encrypted
+    #[serde(default)]
+    pub contains_client_pii: bool,
+    /// Defaults to `contains_client_pii`
+    pub encrypt: Option<bool>,
+    #[serde(default)]
+    pub method: DeliveryMethod,
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DeliveryMethod {
+    /// The file itself, archived when encrypted
+    #[default]
+    Attachment,
+    /// A report portal link to download the file from
+    Link,
+}
+
+impl DeliveryMethod {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Attachment => "ATTACHMENT",
+            Self::Link => "LINK",
+        }
+    }
+
+    fn parse(method: &str) -> Self {
+        match method {
+            "LINK" => Self::Link,
+            _ => Self::Attachment,
+        }
+    }
+}
+
+/// Who a report is sent to once it has been generated
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DeliveryPlan {
+    pub recipients: Vec<ReportRecipient>,
+    #[serde(default)]
+    pub contains_client_pii: bool,
+    pub encrypt: Option<bool>,
+    #[serde(default)]
+    pub method: DeliveryMethod,
+    pub message: Option<String>,
+}
+
+impl DeliveryPlan {
+    /// `None` for a plan without recipients
+    pub fn nonempty(self) -> Option<Self> {
+        (!self.recipients.is_empty()).then_some(self)
+    }
+
+    pub fn request(&self, tenant_id: Uuid, requested_by: Option<Uuid>) -> DeliverReportRequest {
+        DeliverReportRequest {
+            tenant_id,
+            requested_by,
+            recipients: self.recipients.clone(),
+            contains_client_pii: self.contains_client_pii,
+            encrypt: self.encrypt,
+            method: self.method,
+            message: self.message.clone(),
+        }
+    }
+}
+
+impl DeliverReportRequest {
+    fn encrypted(&self) -> bool {
+        self.encrypt.unwrap_or(self.contains_client_pii)
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.recipients.is_empty() {
+            errors.push("at least one recipient is required".to_string());
+        }
+        if self.recipients.len() > MAX_RECIPIENTS {
+            errors.push(format!("at most {} recipients per delivery", MAX_RECIPIENTS));
+        }
+        if self.contains_client_pii && self.encrypt == Some(false) {
+            errors.push("reports containing client PII must be delivered encrypted".to_string());
+        }
+        if self.method == DeliveryMethod::Link && self.encrypted() {
+            errors.push("encrypted deliveries are sent as attachments, not links".to_string());
+        }
+        for recipient in &self.recipients {
+            if recipient.email.parse::<Mailbox>().is_err() {
+                errors.push(format!("invalid email address: {}", recipient.email));
+            }
+            match &recipient.phone {
+                Some(phone) if !is_e164(phone) => {
+                    errors.push(format!("phone for {} must be in E.164 format", recipient.email))
+                }
+                None if self.encrypted() => errors.push(format!(
+                    "phone for {} is required to send the archive password",
+                    recipient.email
+                )),
+                _ => {}
+            }
+        }
+        errors
+    }
+}
+
+#[derive(Serialize)]
+pub struct RecipientDelivery {
+    pub delivery_id: Uuid,
+    pub recipient_email: String,
+    pub encrypted: bool,
+    pub status: String,
+    pub failure_reason: Option<String>,
+    /// When a RETRYING delivery is attempted again
+    pub next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryResponse {
+    pub report_id: Uuid,
+    pub attachment_name: String,
+    pub deliveries: Vec<RecipientDelivery>,
+}
+
+#[derive(Serialize)]
+pub struct DeliveryRecord {
+    pub delivery_id: Uuid,
+    pub recipient_email: String,
+    pub recipient_phone_suffix: Option<String>,
+    pub encrypted: bool,
+    pub method: String,
+    pub access_token_id: Option<Uuid>,
+    pub attachment_name: String,
+    pub attachment_sha256: String,
+    pub status: String,
+    pub failure_reason: Option<String>,
+    pub attempts: i32,
+    pub next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub password_sent_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub email_sent_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub requested_by: Option<Uuid>,
+    pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// What a delivery is recorded against
+#[derive(Debug, Clone, Copy)]
+pub enum Deliverable {
+    Report(Uuid),
+    Export(Uuid),
+}
+
+impl Deliverable {
+    fn report_id(self) -> Option<Uuid> {
+        match self {
+            Self::Report(id) => Some(id),
+            Self::Export(_) => None,
+        }
+    }
+
+    fn export_id(self) -> Option<Uuid> {
+        match self {
+            Self::Export(id) => Some(id),
+            Self::Report(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Deliverable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Report(id) => write!(f, "report {}", id),
+            Self::Export(id) => write!(f, "export {}", id),
+        }
+    }
+}
+
+/// Files sent together: a lone file goes out as is, several as one ZIP
+pub struct Package {
+    /// Attachment name without extension
+    pub stem: String,
+    /// What the email calls the attachment, e.g. "report"
+    pub label: &'static str,
+    pub subject: String,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeliveryError {
+    #[error("{0} is not configured")]
+    NotConfigured(&'static str),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Why sending to a recipient failed, and whether a later attempt may succeed
+struct SendFailure {
+    error: anyhow::Error,
+    transient: bool,
+}
+
+impl SendFailure {
+    fn permanent(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            error: error.into(),
+            transient: false,
+        }
+    }
+
+    fn transient(error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            error: error.into(),
+            transient: true,
+        }
+    }
+
+    fn context(self, context: &'static str) -> Self {
+        Self {
+            error: self.error.context(context),
+            ..self
+        }
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for SendFailure {
+    /// A 5xx reply will not change; 4xx replies and connection problems may
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        Self {
+            transient: !e.is_permanent(),
+            error: e.into(),
+        }
+    }
+}
+
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    /// SMTP_HOST unset disables email delivery
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(host) = std::env::var("SMTP_HOST") else {
+            return Ok(None);
+        };
+        let port = std::env::var("SMTP_PORT")
+            .ok()
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(587);
+        let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+            .with_context(|| format!("invalid SMTP_HOST {}", host))?
+            .port(port);
+        if let (Ok(username), Ok(password)) = (std::env::var("SMTP_USERNAME"), std::env::var("SMTP_PASSWORD")) {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+        let from = std::env::var("SMTP_FROM_ADDRESS")
+            .unwrap_or_else(|_| "noreply@dharmaguard.com".to_string())
+            .parse()
+            .context("invalid SMTP_FROM_ADDRESS")?;
+        Ok(Some(Self {
+            transport: transport.build(),
+            from,
+        }))
+    }
+
+    async fn send(&self, to: Mailbox, subject: &str, body: String, attachment: Attachment) -> Result<(), SendFailure> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(body))
+                    .singlepart(attachment),
+            )
+            .map_err(SendFailure::permanent)?;
+        self.transport.send(email).await?;
+        Ok(())
+    }
+
+    async fn send_plain(&self, to: Mailbox, subject: &str, body: String) -> Result<(), SendFailure> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .singlepart(SinglePart::plain(body))
+            .map_err(SendFailure::permanent)?;
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
+
+/// Twilio messaging API, the provider named by SMS_PROVIDER
+pub struct SmsGateway {
+    client: reqwest::Client,
+    account_sid: String,
+    auth_token: String,
+    from_number: String,
+}
+
+impl SmsGateway {
+    pub fn from_env() -> Option<Self> {
+        let provider = std::env::var("SMS_PROVIDER").unwrap_or_else(|_| "twilio".to_string());
+        if !provider.eq_ignore_ascii_case("twilio") {
+            warn!("Unsupported SMS_PROVIDER {}; encrypted report delivery is disabled", provider);
+            return None;
+        }
+        Some(Self {
+            client: reqwest::Client::new(),
+            account_sid: std::env::var("SMS_ACCOUNT_SID").ok()?,
+            auth_token: std::env::var("SMS_AUTH_TOKEN").ok()?,
+            from_number: std::env::var("SMS_FROM_NUMBER").ok()?,
+        })
+    }
+
+    async fn send(&self, to: &str, body: &str) -> Result<(), SendFailure> {
+        let response = self
+            .client
+            .post(format!(
+                "https://api.twilio.com/2010-04-01/Accounts/{}/Messages.json",
+                self.account_sid
+            ))
+            .basic_auth(&self.account_sid, Some(&self.auth_token))
+            .form(&[("To", to), ("From", self.from_number.as_str()), ("Body", body)])
+            .send()
+            .await
+            .map_err(SendFailure::transient)?;
+        let status = response.status();
+        if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(SendFailure::transient(anyhow::anyhow!("SMS gateway returned {}", status)));
+        }
+        if !status.is_success() {
+            return Err(SendFailure::permanent(anyhow::anyhow!("SMS gateway returned {}", status)));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetrySettings {
+    pub max_attempts: i32,
+    /// Wait before the second attempt; each later one waits twice as long as the last
+    pub first_delay: chrono::Duration,
+}
+
+impl RetrySettings {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+                .max(1)
+        };
+        Self {
+            max_attempts: number("REPORT_DELIVERY_MAX_ATTEMPTS", 5) as i32,
+            first_delay: chrono::Duration::seconds(number("REPORT_DELIVERY_RETRY_SECS", 60)),
+        }
+    }
+
+    /// When to try again after `attempts` attempts, or `None` when they are used up
+    fn next_attempt(&self, attempts: i32) -> Option<chrono::DateTime<chrono::Utc>> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let delay = self
+            .first_delay
+            .num_seconds()
+            .saturating_mul(1 << (attempts - 1).clamp(0, 20))
+            .min(MAX_RETRY_DELAY_SECS);
+        Some(chrono::Utc::now() + chrono::Duration::seconds(delay))
+    }
+}
+
+/// What was sent before an attempt succeeded or failed, as recorded on the delivery
+#[derive(Default)]
+struct Progress {
+    password_sent_at: Option<chrono::DateTime<chrono::Utc>>,
+    email_sent_at: Option<chrono::DateTime<chrono::Utc>>,
+    access_token_id: Option<Uuid>,
+}
+
+/// A package ready to be sent to each recipient
+struct Prepared<'a> {
+    deliverable: Deliverable,
+    package: &'a Package,
+    method: DeliveryMethod,
+    mailer: &'a Mailer,
+    /// Set when the delivery is encrypted
+    sms: Option<&'a SmsGateway>,
+    attachment_name: String,
+    /// What an unencrypted delivery sends, and the reference hash when a delivery fails
+    plain: Vec<u8>,
+    plain_type: &'static str,
+}
+
+/// A RETRYING delivery whose next attempt is due
+#[derive(sqlx::FromRow)]
+struct DueDelivery {
+    delivery_id: Uuid,
+    report_id: Option<Uuid>,
+    tenant_id: Uuid,
+    recipient_email: String,
+    recipient_name: Option<String>,
+    recipient_phone: Option<String>,
+    encrypted: bool,
+    method: String,
+    message: Option<String>,
+    attempts: i32,
+    requested_by: Option<Uuid>,
+}
+
+pub struct ReportDelivery {
+    mailer: Option<Mailer>,
+    sms: Option<SmsGateway>,
+    portal: PortalSettings,
+    retry: RetrySettings,
+}
+
+impl ReportDelivery {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let delivery = Self {
+            mailer: Mailer::from_env()?,
+            sms: SmsGateway::from_env(),
+            portal: PortalSettings::from_env(),
+            retry: RetrySettings::from_env(),
+        };
+        if delivery.mailer.is_none() {
+            warn!("SMTP_HOST not set; report delivery by email is disabled");
+        } else if delivery.sms.is_none() {
+            warn!("SMS gateway not configured; only unencrypted report delivery is available");
+        }
+        Ok(delivery)
+    }
+
+    /// The mailer, and the SMS gateway when the delivery is encrypted
+    fn channels(&self, encrypted: bool) -> Result<(&Mailer, Option<&SmsGateway>), DeliveryError> {
+        let mailer = self.mailer.as_ref().ok_or(DeliveryError::NotConfigured("email delivery"))?;
+        match (&self.sms, encrypted) {
+            (Some(sms), true) => Ok((mailer, Some(sms))),
+            (None, true) => Err(DeliveryError::NotConfigured("SMS password delivery")),
+            (_, false) => Ok((mailer, None)),
+        }
+    }
+
+    /// Fails when a delivery of this kind could not be sent at all
+    pub fn ensure_available(&self, request: &DeliverReportRequest) -> Result<(), DeliveryError> {
+        if request.method == DeliveryMethod::Link && self.portal.base_url.is_none() {
+            return Err(DeliveryError::NotConfigured("REPORT_PORTAL_BASE_URL"));
+        }
+        self.channels(request.encrypted()).map(|_| ())
+    }
+
+    /// Fails when no email at all can be sent
+    pub fn ensure_mail_available(&self) -> Result<(), DeliveryError> {
+        self.mailer.as_ref().map(|_| ()).ok_or(DeliveryError::NotConfigured("email delivery"))
+    }
+
+    /// Email a message without attachment, e.g. a report portal link
+    pub async fn send_notice(&self, to: &str, subject: &str, body: String) -> Result<(), DeliveryError> {
+        let mailer = self.mailer.as_ref().ok_or(DeliveryError::NotConfigured("email delivery"))?;
+        let to = to.parse::<Mailbox>().context("invalid email address")?;
+        mailer
+            .send_plain(to, subject, body)
+            .await
+            .map_err(|failure| failure.error.context("failed to send email"))?;
+        Ok(())
+    }
+
+    /// Send the report to every recipient; `None` when the tenant has no such report
+    pub async fn deliver(
+        &self,
+        db: &PgPool,
+        files: &ReportFiles,
+        report_id: Uuid,
+        request: &DeliverReportRequest,
+    ) -> Result<Option<DeliveryResponse>, DeliveryError> {
+        self.ensure_available(request)?;
+
+        let Some(package) = report_package(db, files, request.tenant_id, report_id).await? else {
+            return Ok(None);
+        };
+        let (attachment_name, deliveries) = self
+            .deliver_package(db, Deliverable::Report(report_id), request, &package)
+            .await?;
+        Ok(Some(DeliveryResponse {
+            report_id,
+            attachment_name,
+            deliveries,
+        }))
+    }
+
+    fn prepare<'a>(
+        &'a self,
+        deliverable: Deliverable,
+        package: &'a Package,
+        encrypted: bool,
+        method: DeliveryMethod,
+    ) -> Result<Prepared<'a>, DeliveryError> {
+        let (mailer, sms) = self.channels(encrypted)?;
+        let (plain, plain_name, plain_type) = match package.files.as_slice() {
+            [(name, contents)] => (contents.clone(), name.clone(), content_type_for(name)),
+            files => (
+                archive(files, None)?,
+                format!("{}.zip", package.stem),
+                "application/zip",
+            ),
+        };
+        let attachment_name = if encrypted {
+            format!("{}.zip", package.stem)
+        } else {
+            plain_name
+        };
+        Ok(Prepared {
+            deliverable,
+            package,
+            method,
+            mailer,
+            sms,
+            attachment_name,
+            plain,
+            plain_type,
+        })
+    }
+
+    /// Send a package to every recipient and record one delivery row each
+    pub async fn deliver_package(
+        &self,
+        db: &PgPool,
+        deliverable: Deliverable,
+        request: &DeliverReportRequest,
+        package: &Package,
+    ) -> Result<(String, Vec<RecipientDelivery>), DeliveryError> {
+        let encrypted = request.encrypted();
+        let prepared = self.prepare(deliverable, package, encrypted, request.method)?;
+
+        let mut deliveries = Vec::with_capacity(request.recipients.len());
+        for recipient in &request.recipients {
+            let delivery_id = Uuid::new_v4();
+            let mut progress = Progress::default();
+            let outcome = self
+                .send_to(
+                    db,
+                    &prepared,
+                    request.tenant_id,
+                    request.requested_by,
+                    recipient,
+                    request.message.as_deref(),
+                    &mut progress,
+                )
+                .await;
+
+            let (status, failure_reason, next_attempt_at, attachment_sha256) = match &outcome {
+                Ok(sent) => ("DELIVERED", None, None, hex::encode(Sha256::digest(sent))),
+                Err(failure) => {
+                    warn!("Delivery of {} to {} failed: {:#}", deliverable, recipient.email, failure.error);
+                    let next_attempt_at = self.retry_at(deliverable, failure, 1);
+                    let status = if next_attempt_at.is_some() { "RETRYING" } else { "FAILED" };
+                    let reason = format!("{:#}", failure.error);
+                    (status, Some(reason), next_attempt_at, hex::encode(Sha256::digest(&prepared.plain)))
+                }
+            };
+            // The full number is only needed to send a new password on a later attempt
+            let recipient_phone = recipient.phone.as_deref().filter(|_| encrypted && status == "RETRYING");
+
+            sqlx::query!(
+                r#"
+                INSERT INTO report_deliveries (
+                    delivery_id, report_id, export_id, tenant_id, recipient_email, recipient_phone_suffix,
+                    encrypted, attachment_name, attachment_sha256, status, failure_reason,
+                    password_sent_at, email_sent_at, requested_by, method, access_token_id, recipient_name,
+                    recipient_phone, message, next_attempt_at
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
+                "#,
+                delivery_id,
+                deliverable.report_id(),
+                deliverable.export_id(),
+                request.tenant_id,
+                recipient.email,
+                recipient.phone.as_deref().map(phone_suffix),
+                encrypted,
+                prepared.attachment_name,
+                attachment_sha256,
+                status,
+                failure_reason,
+                progress.password_sent_at,
+                progress.email_sent_at,
+                request.requested_by,
+                request.method.as_str(),
+                progress.access_token_id,
+                recipient.name,
+                recipient_phone,
+                request.message,
+                next_attempt_at
+            )
+            .execute(db)
+            .await
+            .context("failed to record report delivery")?;
+
+            deliveries.push(RecipientDelivery {
+                delivery_id,
+                recipient_email: recipient.email.clone(),
+                encrypted,
+                status: status.to_string(),
+                failure_reason,
+                next_attempt_at,
+            });
+        }
+
+        let delivered = deliveries.iter().filter(|d| d.status == "DELIVERED").count();
+        info!(
+            "Delivered {} to {} of {} recipients (encrypted: {})",
+            deliverable,
+            delivered,
+            deliveries.len(),
+            encrypted
+        );
+        Ok((prepared.attachment_name, deliveries))
+    }
+
+    /// When a failed attempt is tried again; only report deliveries are retried
+    fn retry_at(
+        &self,
+        deliverable: Deliverable,
+        failure: &SendFailure,
+        attempts: i32,
+    ) -> Option<chrono::DateTime<chrono::Utc>> {
+        match deliverable {
+            Deliverable::Report(_) if failure.transient => self.retry.next_attempt(attempts),
+            _ => None,
+        }
+    }
+
+    /// Send to one recipient; returns what was sent
+    #[allow(clippy::too_many_arguments)]
+    async fn send_to(
+        &self,
+        db: &PgPool,
+        prepared: &Prepared<'_>,
+        tenant_id: Uuid,
+        requested_by: Option<Uuid>,
+        recipient: &ReportRecipient,
+        message: Option<&str>,
+        progress: &mut Progress,
+    ) -> Result<Vec<u8>, SendFailure> {
+        let to = match &recipient.name {
+            Some(name) => format!("{} <{}>", name, recipient.email),
+            None => recipient.email.clone(),
+        }
+        .parse::<Mailbox>()
+        .map_err(SendFailure::permanent)?;
+        let with_message = |body: String| match message {
+            Some(message) => format!("{}\n\n{}", message, body),
+            None => body,
+        };
+
+        if prepared.method == DeliveryMethod::Link {
+            return self
+                .send_link(db, prepared, tenant_id, requested_by, recipient, to, with_message, progress)
+                .await;
+        }
+
+        // A fresh archive per recipient, so each password opens only its own copy
+        let (attachment, body, content_type) = match prepared.sms {
+            Some(sms) => {
+                let password = generate_password();
+                let archive = archive(&prepared.package.files, Some(&password)).map_err(SendFailure::permanent)?;
+                let phone = recipient
+                    .phone
+                    .as_deref()
+                    .ok_or_else(|| SendFailure::permanent(anyhow::anyhow!("recipient has no phone")))?;
+                sms.send(
+                    phone,
+                    &format!(
+                        "DharmaGuard: the password for {} emailed to {} is {}",
+                        prepared.attachment_name, recipient.email, password
+                    ),
+                )
+                .await
+                .map_err(|failure| failure.context("failed to send archive password by SMS"))?;
+                progress.password_sent_at = Some(chrono::Utc::now());
+                let body = format!(
+                    "The attached archive is encrypted. Its password has been sent by SMS to the \
+                     number ending {}.",
+                    phone_suffix(phone)
+                );
+                (archive, body, "application/zip")
+            }
+            None => (
+                prepared.plain.clone(),
+                format!("The {} is attached.", prepared.package.label),
+                prepared.plain_type,
+            ),
+        };
+
+        let content_type = ContentType::parse(content_type).map_err(SendFailure::permanent)?;
+        prepared
+            .mailer
+            .send(
+                to,
+                &prepared.package.subject,
+                with_message(body),
+                Attachment::new(prepared.attachment_name.clone()).body(attachment.clone(), content_type),
+            )
+            .await
+            .map_err(|failure| failure.context("failed to send email"))?;
+        progress.email_sent_at = Some(chrono::Utc::now());
+        Ok(attachment)
+    }
+
+    /// Issue the recipient an access token for the report and email its portal link
+    #[allow(clippy::too_many_arguments)]
+    async fn send_link(
+        &self,
+        db: &PgPool,
+        prepared: &Prepared<'_>,
+        tenant_id: Uuid,
+        requested_by: Option<Uuid>,
+        recipient: &ReportRecipient,
+        to: Mailbox,
+        with_message: impl Fn(String) -> String,
+        progress: &mut Progress,
+    ) -> Result<Vec<u8>, SendFailure> {
+        let Some(report_id) = prepared.deliverable.report_id() else {
+            return Err(SendFailure::permanent(anyhow::anyhow!("only reports can be sent as links")));
+        };
+        let issued = portal::issue(
+            db,
+            &self.portal,
+            self,
+            IssueTokenRequest {
+                tenant_id,
+                created_by: requested_by,
+                recipient_name: recipient.name.clone().unwrap_or_else(|| recipient.email.clone()),
+                recipient_email: Some(recipient.email.clone()),
+                recipient_organisation: None,
+                purpose: Some("Report delivery".to_string()),
+                report_ids: vec![report_id],
+                expires_at: None,
+                max_downloads: None,
+                notify: false,
+            },
+        )
+        .await;
+        let issued = match issued {
+            Ok(Ok(issued)) => issued,
+            Ok(Err(errors)) => return Err(SendFailure::permanent(anyhow::anyhow!(errors.join("; ")))),
+            Err(e) => return Err(SendFailure::transient(e).context("failed to issue access token")),
+        };
+
+        let body = format!(
+            "The {} can be downloaded until {} from:\n\n{}\n\nThe link is for you alone; please do not forward it.",
+            prepared.package.label,
+            issued.grant.expires_at.format("%Y-%m-%d %H:%M UTC"),
+            issued.portal_url
+        );
+        if let Err(failure) = prepared.mailer.send_plain(to, &prepared.package.subject, with_message(body)).await {
+            // The link never reached anyone; a later attempt issues a new one
+            let revocation = RevokeTokenRequest {
+                tenant_id,
+                revoked_by: None,
+                reason: Some("delivery email failed".to_string()),
+            };
+            if let Err(e) = portal::revoke(db, issued.grant.token_id, &revocation).await {
+                warn!("Failed to revoke undelivered access token {}: {}", issued.grant.token_id, e);
+            }
+            return Err(failure.context("failed to send email"));
+        }
+        progress.email_sent_at = Some(chrono::Utc::now());
+        progress.access_token_id = Some(issued.grant.token_id);
+        Ok(prepared.plain.clone())
+    }
+
+    /// Attempt a batch of due deliveries again; returns how many were attempted
+    async fn retry_due(&self, db: &PgPool, files: &ReportFiles) -> anyhow::Result<usize> {
+        // Leased, so another replica does not take a delivery while it is being sent
+        let due = sqlx::query_as::<_, DueDelivery>(
+            r#"
+            UPDATE report_deliveries
+            SET next_attempt_at = NOW() + INTERVAL '10 minutes'
+            WHERE delivery_id IN (
+                SELECT delivery_id FROM report_deliveries
+                WHERE status = 'RETRYING' AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING delivery_id, report_id, tenant_id, recipient_email, recipient_name, recipient_phone,
+                      encrypted, method, message, attempts, requested_by
+            "#,
+        )
+        .bind(RETRY_BATCH)
+        .fetch_all(db)
+        .await?;
+
+        for delivery in &due {
+            if let Err(e) = self.retry(db, files, delivery).await {
+                error!("Failed to retry report delivery {}: {:#}", delivery.delivery_id, e);
+            }
+        }
+        Ok(due.len())
+    }
+
+    async fn retry(&self, db: &PgPool, files: &ReportFiles, delivery: &DueDelivery) -> anyhow::Result<()> {
+        let attempts = delivery.attempts + 1;
+        let recipient = ReportRecipient {
+            email: delivery.recipient_email.clone(),
+            phone: delivery.recipient_phone.clone(),
+            name: delivery.recipient_name.clone(),
+        };
+        let mut progress = Progress::default();
+        let outcome = async {
+            let report_id = delivery
+                .report_id
+                .ok_or_else(|| SendFailure::permanent(anyhow::anyhow!("only report deliveries are retried")))?;
+            let package = report_package(db, files, delivery.tenant_id, report_id)
+                .await
+                .map_err(|e| {
+                    if e.is::<FileExpired>() {
+                        SendFailure::permanent(e)
+                    } else {
+                        SendFailure::transient(e)
+                    }
+                })?
+                .ok_or_else(|| SendFailure::permanent(anyhow::anyhow!("report no longer exists")))?;
+            let prepared = self
+                .prepare(
+                    Deliverable::Report(report_id),
+                    &package,
+                    delivery.encrypted,
+                    DeliveryMethod::parse(&delivery.method),
+                )
+                .map_err(SendFailure::permanent)?;
+            let sent = self
+                .send_to(
+                    db,
+                    &prepared,
+                    delivery.tenant_id,
+                    delivery.requested_by,
+                    &recipient,
+                    delivery.message.as_deref(),
+                    &mut progress,
+                )
+                .await?;
+            Ok::<_, SendFailure>(hex::encode(Sha256::digest(&sent)))
+        }
+        .await;
+
+        let (status, failure_reason, next_attempt_at, attachment_sha256) = match &outcome {
+            Ok(sha256) => ("DELIVERED", None, None, Some(sha256.clone())),
+            Err(failure) => {
+                let next_attempt_at = failure.transient.then(|| self.retry.next_attempt(attempts)).flatten();
+                let status = if next_attempt_at.is_some() { "RETRYING" } else { "FAILED" };
+                (status, Some(format!("{:#}", failure.error)), next_attempt_at, None)
+            }
+        };
+        sqlx::query(
+            r#"
+            UPDATE report_deliveries
+            SET status = $2, failure_reason = $3, next_attempt_at = $4, attempts = $5,
+                attachment_sha256 = COALESCE($6, attachment_sha256),
+                password_sent_at = COALESCE($7, password_sent_at),
+                email_sent_at = COALESCE($8, email_sent_at),
+                access_token_id = COALESCE($9, access_token_id),
+                recipient_phone = CASE WHEN $2 = 'RETRYING' THEN recipient_phone END
+            WHERE delivery_id = $1
+            "#,
+        )
+        .bind(delivery.delivery_id)
+        .bind(status)
+        .bind(&failure_reason)
+        .bind(next_attempt_at)
+        .bind(attempts)
+        .bind(attachment_sha256)
+        .bind(progress.password_sent_at)
+        .bind(progress.email_sent_at)
+        .bind(progress.access_token_id)
+        .execute(db)
+        .await?;
+
+        match status {
+            "DELIVERED" => info!(
+                "Delivered report delivery {} to {} on attempt {}",
+                delivery.delivery_id, delivery.recipient_email, attempts
+            ),
+            _ => warn!(
+                "Attempt {} of report delivery {} to {} failed ({}): {}",
+                attempts,
+                delivery.delivery_id,
+                delivery.recipient_email,
+                status,
+                failure_reason.unwrap_or_default()
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// The rendered file of a report as a package; `None` when the tenant has no such report
+async fn report_package(
+    db: &PgPool,
+    files: &ReportFiles,
+    tenant_id: Uuid,
+    report_id: Uuid,
+) -> anyhow::Result<Option<Package>> {
+    let Some(report) = sqlx::query!(
+        r#"
+        SELECT report_data, report_period_start, report_period_end, file_path, file_hash, file_size,
+               file_deleted_at, file_archived_at
+        FROM regulatory_reports_v2
+        WHERE report_id = $1 AND tenant_id = $2
+        "#,
+        report_id,
+        tenant_id
+    )
+    .fetch_optional(db)
+    .await
+    .context("failed to load report")?
+    else {
+        return Ok(None);
+    };
+
+    let columns = FileColumns {
+        file_path: report.file_path,
+        file_hash: report.file_hash,
+        file_size: report.file_size,
+        file_deleted_at: report.file_deleted_at,
+        file_archived_at: report.file_archived_at,
+    };
+    let file = files.open(report_id, &columns, &report.report_data).await?;
+    Ok(Some(Package {
+        stem: format!("report-{}", report_id),
+        label: "report",
+        subject: format!(
+            "DharmaGuard report for {} to {}",
+            report.report_period_start, report.report_period_end
+        ),
+        files: vec![(file.name, file.contents)],
+    }))
+}
+
+/// Attempt RETRYING report deliveries again once they are due
+pub fn spawn_retries(db: PgPool, files: Arc<ReportFiles>, delivery: Arc<ReportDelivery>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RETRY_POLL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            loop {
+                match delivery.retry_due(&db, &files).await {
+                    Ok(attempted) if (attempted as i64) < RETRY_BATCH => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Report delivery retry failed: {:#}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+pub async fn list_deliveries(
+    db: &PgPool,
+    tenant_id: Uuid,
+    deliverable: Deliverable,
+) -> anyhow::Result<Vec<DeliveryRecord>> {
+    let records = sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT delivery_id, recipient_email, recipient_phone_suffix, encrypted, method, access_token_id,
+               attachment_name, attachment_sha256, status, failure_reason, attempts, next_attempt_at,
+               password_sent_at, email_sent_at, requested_by, created_at
+        FROM report_deliveries
+        WHERE tenant_id = $1
+          AND report_id IS NOT DISTINCT FROM $2
+          AND export_id IS NOT DISTINCT FROM $3
+        ORDER BY created_at DESC
+        "#,
+        tenant_id,
+        deliverable.report_id(),
+        deliverable.export_id()
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(records)
+}
+
+fn generate_password() -> String {
+    (0..PASSWORD_LENGTH)
+        .map(|_| PASSWORD_ALPHABET[OsRng.gen_range(0..PASSWORD_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// ZIP of the files, each WinZip AES-256 encrypted when a password is given
+fn archive(files: &[(String, Vec<u8>)], password: Option<&str>) -> anyhow::Result<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let options = match password {
+        Some(password) => options.with_aes_encryption(AesMode::Aes256, password),
+        None => options,
+    };
+    for (name, contents) in files {
+        writer.start_file(name.as_str(), options)?;
+        writer.write_all(contents)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+fn content_type_for(name: &str) -> &'static str {
+    if name.ends_with(".csv") {
+        "text/csv"
+    } else {
+        "application/json"
+    }
+}
+
+fn is_e164(phone: &str) -> bool {
+    phone
+        .strip_prefix('+')
+        .is_some_and(|digits| (8..=15).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn phone_suffix(phone: &str) -> String {
+    phone[phone.len().saturating_sub(4)..].to_string()
+}
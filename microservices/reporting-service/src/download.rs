@@ -0,0 +1,142 @@
+//! Serving report files over HTTP
+//!
+//! Files are streamed from the report store rather than read into memory.
+//! A `Range` header asking for one byte range (`bytes=0-499`, `bytes=500-`
+//! or `bytes=-500`) is answered with 206 and that part of the file, and a
+//! range past the end with 416. Requests for several ranges are answered
+//! with the whole file, as are ranges under an `If-Range` that does not match
+//! the file's ETag, which is its SHA-256.
+
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::store::ReportStore;
+
+pub enum Source {
+    Store { store: Arc<dyn ReportStore>, key: String },
+    /// Content with no stored file, such as the JSON of reports from before rendering
+    Memory(Vec<u8>),
+}
+
+/// A report file ready to be sent
+pub struct Download {
+    pub name: String,
+    pub content_type: &'static str,
+    pub len: u64,
+    /// SHA-256 of the file, when it was recorded
+    pub etag: Option<String>,
+    pub source: Source,
+}
+
+enum Requested {
+    Full,
+    Partial(RangeInclusive<u64>),
+    Unsatisfiable,
+}
+
+/// The part of a file of `len` bytes a `Range` header asks for
+fn requested(range: &str, len: u64) -> Requested {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Requested::Full;
+    };
+    if spec.contains(',') {
+        return Requested::Full;
+    }
+    let Some((first, last)) = spec.split_once('-') else {
+        return Requested::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // The final `last` bytes
+        return match last.parse::<u64>() {
+            Ok(0) => Requested::Unsatisfiable,
+            Ok(_) if len == 0 => Requested::Unsatisfiable,
+            Ok(suffix) => Requested::Partial(len.saturating_sub(suffix)..=len - 1),
+            Err(_) => Requested::Full,
+        };
+    }
+    let Ok(first) = first.parse::<u64>() else {
+        return Requested::Full;
+    };
+    let last = match last {
+        "" => len.saturating_sub(1),
+        last => match last.parse::<u64>() {
+            Ok(last) if last >= first => last.min(len.saturating_sub(1)),
+            _ => return Requested::Full,
+        },
+    };
+    if first >= len {
+        return Requested::Unsatisfiable;
+    }
+    Requested::Partial(first..=last)
+}
+
+impl Download {
+    fn quoted_etag(&self) -> Option<String> {
+        self.etag.as_ref().map(|etag| format!("\"{}\"", etag))
+    }
+
+    /// Whether a range may be served: without `If-Range`, or when it names this file
+    fn range_applies(&self, headers: &HeaderMap) -> bool {
+        match headers.get(header::IF_RANGE).and_then(|value| value.to_str().ok()) {
+            None => true,
+            Some(if_range) => self.quoted_etag().is_some_and(|etag| etag == if_range.trim()),
+        }
+    }
+
+    pub async fn respond(self, headers: &HeaderMap) -> anyhow::Result<Response> {
+        let range = headers
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok())
+            .filter(|_| self.range_applies(headers))
+            .map_or(Requested::Full, |range| requested(range, self.len));
+
+        let mut response_headers = HeaderMap::new();
+        let mut set = |name: header::HeaderName, value: String| {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                response_headers.insert(name, value);
+            }
+        };
+        set(header::CONTENT_TYPE, self.content_type.to_string());
+        set(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", self.name));
+        set(header::CACHE_CONTROL, "no-store".to_string());
+        set(header::ACCEPT_RANGES, "bytes".to_string());
+        if let Some(etag) = self.quoted_etag() {
+            set(header::ETAG, etag);
+        }
+
+        let (status, range) = match range {
+            Requested::Full => (StatusCode::OK, 0..=self.len.saturating_sub(1)),
+            Requested::Partial(range) => {
+                set(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", range.start(), range.end(), self.len),
+                );
+                (StatusCode::PARTIAL_CONTENT, range)
+            }
+            Requested::Unsatisfiable => {
+                set(header::CONTENT_RANGE, format!("bytes */{}", self.len));
+                return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+            }
+        };
+        let sent = if self.len == 0 { 0 } else { range.end() - range.start() + 1 };
+        set(header::CONTENT_LENGTH, sent.to_string());
+
+        let body = match self.source {
+            Source::Store { .. } if sent == 0 => Body::empty(),
+            Source::Store { store, key } => store.stream(&key, range).await?,
+            Source::Memory(mut contents) => {
+                let start = *range.start() as usize;
+                contents.truncate(start + sent as usize);
+                contents.drain(..start);
+                Body::from(contents)
+            }
+        };
+        Ok((status, response_headers, body).into_response())
+    }
+}
@@ -0,0 +1,489 @@
+//! Drill-down from a report's figures to the rows behind them
+//!
+//! GET /reports/:id/drilldown/:metric lists the trades, surveillance alerts or
+//! orders a figure of a report was computed from, over the report's period, a
+//! page at a time (`limit`, at most 1000, and `offset`). A metric is named by
+//! its path in the report data, as in `compare`: `total_trades`,
+//! `pattern_breakdown.WASH_TRADING`, `instrument_breakdown.RELIANCE` or
+//! `clients.CL0001.positions.INFY`, a row of a list keyed by what identifies
+//! it. A trailing field of a row, as in `instrument_breakdown.RELIANCE.total_value`,
+//! drills to the same rows as the row itself, except in ORDER_TRADE_RATIO
+//! reports, where `.orders` or `.trades` chooses which rows to list. Keys
+//! holding a `/`, like a ratio's `2026-10-01/CL0001/INFY`, are URL-encoded.
+//!
+//! Rows are read as they stand now, so a figure is only reproduced exactly
+//! while the underlying data is unchanged since the report was generated.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Source {
+    Trades,
+    Alerts,
+    Orders,
+}
+
+/// Which rows a metric was computed from
+#[derive(Debug, Default)]
+struct Selection {
+    /// Trades up to the end of the period rather than within it, as positions are built
+    through_period_end: bool,
+    largest_first: bool,
+    instrument: Option<String>,
+    hour: Option<i32>,
+    /// COALESCE(client_code, account_number), as order-to-trade ratios count clients
+    client: Option<String>,
+    client_code: Option<String>,
+    segment: Option<String>,
+    /// Rows whose day, or month when `monthly`, starts on the date
+    bucket: Option<NaiveDate>,
+    monthly: bool,
+    severity: Option<&'static str>,
+    statuses: Option<&'static [&'static str]>,
+    alert_type: Option<String>,
+    /// Alerts by the day they were raised in this time zone rather than in UTC
+    timezone: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TradeRow {
+    pub trade_id: Uuid,
+    pub trade_time: DateTime<Utc>,
+    pub account_number: String,
+    pub client_code: Option<String>,
+    pub instrument: String,
+    pub trade_type: String,
+    pub quantity: i64,
+    pub price: f64,
+    pub value: f64,
+    pub exchange: String,
+    pub order_id: String,
+    pub trade_number: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AlertRow {
+    pub alert_id: Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub alert_type: String,
+    pub severity: String,
+    pub status: Option<String>,
+    pub title: String,
+    pub risk_score: f64,
+    pub account_number: Option<String>,
+    pub instrument: Option<String>,
+    pub trade_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct OrderRow {
+    pub order_id: Uuid,
+    pub client_order_id: String,
+    pub order_time: DateTime<Utc>,
+    pub account_number: String,
+    pub client_code: Option<String>,
+    pub instrument: String,
+    pub order_type: String,
+    pub trade_type: String,
+    pub quantity: i64,
+    pub price: Option<f64>,
+    pub status: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum DrilldownRows {
+    Trades(Vec<TradeRow>),
+    Alerts(Vec<AlertRow>),
+    Orders(Vec<OrderRow>),
+}
+
+#[derive(Serialize)]
+pub struct Drilldown {
+    pub report_id: Uuid,
+    pub report_type: String,
+    pub metric: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub source: Source,
+    /// Rows behind the figure, on every page
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Pass back as `offset` for the next page; absent on the last page
+    pub next_offset: Option<i64>,
+    pub rows: DrilldownRows,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DrilldownError {
+    #[error("report not found")]
+    NotFound,
+    #[error("{metric} of {report_type} reports cannot be drilled into")]
+    UnknownMetric { report_type: String, metric: String },
+    #[error(transparent)]
+    Internal(#[from] sqlx::Error),
+}
+
+/// The rows behind the metric of a report of the type
+fn select(report_type: &str, metric: &str, monthly: bool) -> Option<(Source, Selection)> {
+    let path: Vec<&str> = metric.split('.').collect();
+    let all = Selection::default();
+    Some(match (report_type, path.as_slice()) {
+        (
+            "TRADING_SUMMARY",
+            ["total_trades" | "total_volume" | "total_value" | "average_trade_size" | "unique_instruments"
+            | "active_clients"],
+        ) => (Source::Trades, all),
+        ("TRADING_SUMMARY", ["largest_trade"]) => (Source::Trades, Selection { largest_first: true, ..all }),
+        ("TRADING_SUMMARY", ["instrument_breakdown", instrument, ..]) => (
+            Source::Trades,
+            Selection {
+                instrument: Some(instrument.to_string()),
+                ..all
+            },
+        ),
+        ("TRADING_SUMMARY", ["trading_hours_distribution", hour]) => (
+            Source::Trades,
+            Selection {
+                hour: Some(hour.split(':').next()?.parse().ok()?),
+                ..all
+            },
+        ),
+
+        ("COMPLIANCE_REPORT", ["alerts_generated" | "compliance_score"]) => (Source::Alerts, all),
+        ("COMPLIANCE_REPORT", ["critical_alerts" | "violations_detected"]) => (
+            Source::Alerts,
+            Selection {
+                severity: Some("CRITICAL"),
+                ..all
+            },
+        ),
+        ("COMPLIANCE_REPORT", ["resolved_alerts"]) => (
+            Source::Alerts,
+            Selection {
+                statuses: Some(&["RESOLVED"]),
+                ..all
+            },
+        ),
+        ("COMPLIANCE_REPORT", ["pending_investigations"]) => (
+            Source::Alerts,
+            Selection {
+                statuses: Some(&["OPEN", "INVESTIGATING"]),
+                ..all
+            },
+        ),
+        ("COMPLIANCE_REPORT", ["pattern_breakdown", alert_type]) => (
+            Source::Alerts,
+            Selection {
+                alert_type: Some(alert_type.to_string()),
+                ..all
+            },
+        ),
+
+        ("CLIENT_EXPOSURE", rest) => {
+            let through = Selection {
+                through_period_end: true,
+                ..all
+            };
+            match rest {
+                ["clients_with_positions" | "total_gross_exposure" | "total_net_exposure"] => (Source::Trades, through),
+                ["clients", client_code, "positions", instrument, ..] => (
+                    Source::Trades,
+                    Selection {
+                        client_code: Some(client_code.to_string()),
+                        instrument: Some(instrument.to_string()),
+                        ..through
+                    },
+                ),
+                ["clients", client_code] | ["clients", client_code, _] => (
+                    Source::Trades,
+                    Selection {
+                        client_code: Some(client_code.to_string()),
+                        ..through
+                    },
+                ),
+                _ => return None,
+            }
+        }
+
+        ("ORDER_TRADE_RATIO", ["total_orders"]) => (Source::Orders, all),
+        ("ORDER_TRADE_RATIO", ["total_trades"]) => (Source::Trades, all),
+        ("ORDER_TRADE_RATIO", ["clients", client, rows @ ("orders" | "trades")]) => (
+            source(rows),
+            Selection {
+                client: Some(client.to_string()),
+                ..all
+            },
+        ),
+        ("ORDER_TRADE_RATIO", ["ratios", key, rows @ ("orders" | "trades")]) => {
+            let [date, client, instrument] = key.splitn(3, '/').collect::<Vec<_>>()[..] else {
+                return None;
+            };
+            (
+                source(rows),
+                Selection {
+                    bucket: Some(date.parse().ok()?),
+                    monthly,
+                    client: Some(client.to_string()),
+                    instrument: Some(instrument.to_string()),
+                    ..all
+                },
+            )
+        }
+
+        (
+            "BROKERAGE_SUMMARY",
+            ["trades" | "turnover" | "brokerage" | "stt" | "exchange_charges" | "sebi_fees" | "stamp_duty" | "gst"
+            | "unallocated_taxes" | "total_charges"],
+        ) => (Source::Trades, all),
+        ("BROKERAGE_SUMMARY", ["clients", client, ..]) => (
+            Source::Trades,
+            Selection {
+                client: Some(client.to_string()),
+                ..all
+            },
+        ),
+        ("BROKERAGE_SUMMARY", ["rows", key, ..]) => {
+            let (client, segment) = key.rsplit_once('/')?;
+            (
+                Source::Trades,
+                Selection {
+                    client: Some(client.to_string()),
+                    segment: Some(segment.to_string()),
+                    ..all
+                },
+            )
+        }
+
+        ("ALERT_AGEING", ["total_alerts" | "acknowledged_alerts" | "resolved_alerts"]) => (Source::Alerts, all),
+        ("ALERT_AGEING", ["groups", key, ..]) => {
+            let (alert_type, severity) = key.rsplit_once('/')?;
+            (
+                Source::Alerts,
+                Selection {
+                    alert_type: Some(alert_type.to_string()),
+                    severity: Some(crate::SEVERITIES.iter().copied().find(|known| *known == severity)?),
+                    ..all
+                },
+            )
+        }
+        _ => return None,
+    })
+}
+
+fn source(rows: &str) -> Source {
+    if rows == "orders" {
+        Source::Orders
+    } else {
+        Source::Trades
+    }
+}
+
+impl Selection {
+    fn push_trades(&self, query: &mut QueryBuilder<Postgres>, tenant_id: Uuid, start: NaiveDate, end: NaiveDate) {
+        query
+            .push(
+                r#"
+                FROM trades t
+                JOIN trading_accounts a ON a.account_id = t.account_id
+                JOIN instruments i ON i.instrument_id = t.instrument_id
+                LEFT JOIN LATERAL instrument_as_of(t.instrument_id, DATE(t.trade_time)) v ON TRUE
+                WHERE t.tenant_id = "#,
+            )
+            .push_bind(tenant_id);
+        if self.through_period_end {
+            query.push(" AND t.client_code IS NOT NULL AND DATE(t.trade_time) <= ").push_bind(end);
+        } else {
+            query.push(" AND DATE(t.trade_time) BETWEEN ").push_bind(start).push(" AND ").push_bind(end);
+        }
+        if let Some(instrument) = &self.instrument {
+            query.push(" AND COALESCE(v.symbol, i.symbol) = ").push_bind(instrument.clone());
+        }
+        if let Some(hour) = self.hour {
+            query.push(" AND EXTRACT(HOUR FROM t.trade_time) = ").push_bind(hour);
+        }
+        if let Some(client_code) = &self.client_code {
+            query.push(" AND t.client_code = ").push_bind(client_code.clone());
+        }
+        if let Some(client) = &self.client {
+            query.push(" AND COALESCE(t.client_code, a.account_number) = ").push_bind(client.clone());
+        }
+        if let Some(segment) = &self.segment {
+            query.push(" AND t.segment::text = ").push_bind(segment.clone());
+        }
+        if let Some(bucket) = self.bucket {
+            query
+                .push(" AND DATE(date_trunc(")
+                .push_bind(if self.monthly { "month" } else { "day" })
+                .push(", t.trade_time)) = ")
+                .push_bind(bucket);
+        }
+    }
+
+    fn push_alerts(&self, query: &mut QueryBuilder<Postgres>, tenant_id: Uuid, start: NaiveDate, end: NaiveDate) {
+        query
+            .push(
+                r#"
+                FROM surveillance_alerts s
+                LEFT JOIN trading_accounts a ON a.account_id = s.account_id
+                LEFT JOIN instruments i ON i.instrument_id = s.instrument_id
+                WHERE s.tenant_id = "#,
+            )
+            .push_bind(tenant_id);
+        match &self.timezone {
+            Some(timezone) => query.push(" AND DATE(s.created_at AT TIME ZONE ").push_bind(timezone.clone()).push(")"),
+            None => query.push(" AND DATE(s.created_at)"),
+        };
+        query.push(" BETWEEN ").push_bind(start).push(" AND ").push_bind(end);
+        if let Some(severity) = self.severity {
+            query.push(" AND s.severity::text = ").push_bind(severity);
+        }
+        if let Some(statuses) = self.statuses {
+            query.push(" AND s.status::text = ANY(").push_bind(statuses.to_vec()).push(")");
+        }
+        if let Some(alert_type) = &self.alert_type {
+            query.push(" AND s.alert_type = ").push_bind(alert_type.clone());
+        }
+    }
+
+    fn push_orders(&self, query: &mut QueryBuilder<Postgres>, tenant_id: Uuid, start: NaiveDate, end: NaiveDate) {
+        query
+            .push(
+                r#"
+                FROM orders o
+                JOIN trading_accounts a ON a.account_id = o.account_id
+                JOIN instruments i ON i.instrument_id = o.instrument_id
+                LEFT JOIN LATERAL instrument_as_of(o.instrument_id, DATE(o.order_time)) v ON TRUE
+                WHERE o.tenant_id = "#,
+            )
+            .push_bind(tenant_id)
+            .push(" AND DATE(o.order_time) BETWEEN ")
+            .push_bind(start)
+            .push(" AND ")
+            .push_bind(end);
+        if let Some(client) = &self.client {
+            query.push(" AND COALESCE(o.client_code, a.account_number) = ").push_bind(client.clone());
+        }
+        if let Some(instrument) = &self.instrument {
+            query.push(" AND COALESCE(v.symbol, i.symbol) = ").push_bind(instrument.clone());
+        }
+        if let Some(bucket) = self.bucket {
+            query
+                .push(" AND DATE(date_trunc(")
+                .push_bind(if self.monthly { "month" } else { "day" })
+                .push(", o.order_time)) = ")
+                .push_bind(bucket);
+        }
+    }
+
+    fn push_from(
+        &self,
+        source: Source,
+        query: &mut QueryBuilder<Postgres>,
+        tenant_id: Uuid,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) {
+        match source {
+            Source::Trades => self.push_trades(query, tenant_id, start, end),
+            Source::Alerts => self.push_alerts(query, tenant_id, start, end),
+            Source::Orders => self.push_orders(query, tenant_id, start, end),
+        }
+    }
+}
+
+const TRADE_COLUMNS: &str = "SELECT t.trade_id, t.trade_time, a.account_number, t.client_code, \
+     COALESCE(v.symbol, i.symbol) AS instrument, t.trade_type::text AS trade_type, t.quantity, \
+     t.price::float8 AS price, t.value::float8 AS value, t.exchange, t.order_id, t.trade_number";
+
+const ALERT_COLUMNS: &str = "SELECT s.alert_id, s.created_at, s.alert_type, s.severity::text AS severity, \
+     s.status::text AS status, s.title, s.risk_score::float8 AS risk_score, a.account_number, \
+     i.symbol AS instrument, s.trade_ids";
+
+const ORDER_COLUMNS: &str = "SELECT o.order_id, o.client_order_id, o.order_time, a.account_number, o.client_code, \
+     COALESCE(v.symbol, i.symbol) AS instrument, o.order_type, o.trade_type::text AS trade_type, o.quantity, \
+     o.price::float8 AS price, o.status";
+
+pub async fn drilldown(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_id: Uuid,
+    metric: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Drilldown, DrilldownError> {
+    let report = tenant_query!(
+        tenant,
+        r#"
+        SELECT r.report_period_start, r.report_period_end, r.report_data, t.report_type
+        FROM regulatory_reports_v2 r
+        JOIN report_templates t ON t.template_id = r.template_id
+        WHERE r.tenant_id = $1 AND r.report_id = $2
+        "#,
+        report_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(DrilldownError::NotFound)?;
+    let monthly = report.report_data.get("granularity").and_then(|g| g.as_str()) == Some("MONTHLY");
+    let (source, mut selection) =
+        select(&report.report_type, metric, monthly).ok_or_else(|| DrilldownError::UnknownMetric {
+            report_type: report.report_type.clone(),
+            metric: metric.to_string(),
+        })?;
+    let (tenant_id, start, end) = (tenant.tenant_id(), report.report_period_start, report.report_period_end);
+    // Its generator counts alerts by tenant-local day
+    if report.report_type == "ALERT_AGEING" {
+        selection.timezone = Some(crate::tenant_schedules::tenant_timezone(db, tenant_id).await?);
+    }
+
+    let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*)");
+    selection.push_from(source, &mut count, tenant_id, start, end);
+    let total: i64 = count.build_query_scalar().fetch_one(db).await?;
+
+    let (columns, order) = match source {
+        Source::Trades if selection.largest_first => (TRADE_COLUMNS, " ORDER BY t.value DESC, t.trade_id"),
+        Source::Trades => (TRADE_COLUMNS, " ORDER BY t.trade_time, t.trade_id"),
+        Source::Alerts => (ALERT_COLUMNS, " ORDER BY s.created_at, s.alert_id"),
+        Source::Orders => (ORDER_COLUMNS, " ORDER BY o.order_time, o.order_id"),
+    };
+    let mut page = QueryBuilder::<Postgres>::new(columns);
+    selection.push_from(source, &mut page, tenant_id, start, end);
+    page.push(order).push(" LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+    let (fetched, rows) = match source {
+        Source::Trades => {
+            let rows: Vec<TradeRow> = page.build_query_as().fetch_all(db).await?;
+            (rows.len() as i64, DrilldownRows::Trades(rows))
+        }
+        Source::Alerts => {
+            let rows: Vec<AlertRow> = page.build_query_as().fetch_all(db).await?;
+            (rows.len() as i64, DrilldownRows::Alerts(rows))
+        }
+        Source::Orders => {
+            let rows: Vec<OrderRow> = page.build_query_as().fetch_all(db).await?;
+            (rows.len() as i64, DrilldownRows::Orders(rows))
+        }
+    };
+
+    Ok(Drilldown {
+        report_id,
+        report_type: report.report_type,
+        metric: metric.to_string(),
+        period_start: start,
+        period_end: end,
+        source,
+        total,
+        limit,
+        offset,
+        next_offset: (offset + fetched < total).then_some(offset + fetched),
+        rows,
+    })
+}
@@ -0,0 +1,289 @@
+//! Two-phase report publication
+//!
+//! A report can be generated under embargo, e.g. so a daily summary is only
+//! released after the market closes. Until `embargo_until` it is listed but
+//! its contents cannot be viewed, downloaded, delivered, fetched through the
+//! portal or submitted; `dharmaguard_common::embargo::guard` enforces that in
+//! every service and logs each refused attempt. The embargo time can be moved
+//! while it is running and the embargo lifted early with a recorded reason,
+//! but once it has passed or been lifted the report cannot be embargoed again.
+
+use chrono::{DateTime, Duration, Utc};
+use dharmaguard_common::business_hours::{self, BusinessCalendar};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::info;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct EmbargoSettings {
+    pub max_embargo: Duration,
+}
+
+impl EmbargoSettings {
+    pub fn from_env() -> Self {
+        Self {
+            max_embargo: Duration::days(
+                std::env::var("REPORT_EMBARGO_MAX_DAYS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(30)
+                    .max(1),
+            ),
+        }
+    }
+}
+
+/// Embargo a report until a time, or until the tenant's next market close
+#[derive(Deserialize, Debug, Clone)]
+pub struct SetEmbargoRequest {
+    pub tenant_id: Uuid,
+    pub embargo_until: Option<DateTime<Utc>>,
+    /// Until the final session of the tenant's next business day closes
+    #[serde(default)]
+    pub until_market_close: bool,
+    pub reason: Option<String>,
+    pub set_by: Option<Uuid>,
+}
+
+impl SetEmbargoRequest {
+    pub fn validate(&self, settings: &EmbargoSettings) -> Vec<String> {
+        let mut errors = Vec::new();
+        match (self.embargo_until, self.until_market_close) {
+            (Some(_), true) => errors.push("give either embargo_until or until_market_close, not both".to_string()),
+            (None, false) => errors.push("embargo_until or until_market_close is required".to_string()),
+            (Some(until), false) => {
+                let now = Utc::now();
+                if until <= now {
+                    errors.push("embargo_until must be in the future".to_string());
+                } else if until - now > settings.max_embargo {
+                    errors.push(format!(
+                        "reports may not be embargoed for more than {} days",
+                        settings.max_embargo.num_days()
+                    ));
+                }
+            }
+            (None, true) => {}
+        }
+        errors
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LiftEmbargoRequest {
+    pub tenant_id: Uuid,
+    pub lifted_by: Option<Uuid>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct ReportEmbargo {
+    pub report_id: Uuid,
+    pub tenant_id: Uuid,
+    pub embargo_until: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub set_by: Option<Uuid>,
+    pub set_at: DateTime<Utc>,
+    pub lifted_at: Option<DateTime<Utc>>,
+    pub lifted_by: Option<Uuid>,
+    pub lift_reason: Option<String>,
+    /// EMBARGOED, PUBLISHED, or LIFTED when released early
+    pub phase: String,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct EarlyAccessAttempt {
+    pub attempt_id: Uuid,
+    pub action: String,
+    pub requested_by: Option<Uuid>,
+    pub token_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct EmbargoDetail {
+    #[serde(flatten)]
+    pub embargo: ReportEmbargo,
+    /// Newest first
+    pub early_access_attempts: Vec<EarlyAccessAttempt>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbargoError {
+    #[error("report not found")]
+    NotFound,
+    #[error("{0}")]
+    Conflict(&'static str),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for EmbargoError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+const EMBARGO_COLUMNS: &str = "report_id, tenant_id, embargo_until, reason, set_by, set_at, lifted_at, lifted_by, \
+     lift_reason, CASE WHEN lifted_at IS NOT NULL THEN 'LIFTED' WHEN embargo_until > NOW() THEN 'EMBARGOED' \
+     ELSE 'PUBLISHED' END AS phase";
+
+/// The close of the final session of the next business day that has not closed yet
+fn next_market_close(calendar: &BusinessCalendar, from: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let mut from = from;
+    while let Some(close) = calendar.next_close(from) {
+        let date = calendar.local_date(close);
+        if calendar.sessions_on(date).last().map(|(_, last)| *last) == Some(close) {
+            return Some(close);
+        }
+        from = close;
+    }
+    None
+}
+
+/// The embargo time the request asks for
+pub async fn resolve_until(db: &PgPool, request: &SetEmbargoRequest) -> Result<DateTime<Utc>, EmbargoError> {
+    match request.embargo_until {
+        Some(until) => Ok(until),
+        None => {
+            let calendar = business_hours::load(db, request.tenant_id).await?;
+            next_market_close(&calendar, Utc::now())
+                .ok_or(EmbargoError::Conflict("the tenant calendar has no upcoming market close"))
+        }
+    }
+}
+
+/// Embargo a report in the transaction that stores it, so it is never available before the embargo
+pub async fn insert(
+    tx: &mut Transaction<'_, Postgres>,
+    report_id: Uuid,
+    request: &SetEmbargoRequest,
+    embargo_until: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO report_embargoes (report_id, tenant_id, embargo_until, reason, set_by)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+    )
+    .bind(report_id)
+    .bind(request.tenant_id)
+    .bind(embargo_until)
+    .bind(&request.reason)
+    .bind(request.set_by)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Embargo a report, or move an embargo that is still running
+pub async fn set(db: &PgPool, report_id: Uuid, request: &SetEmbargoRequest) -> Result<ReportEmbargo, EmbargoError> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = $2)",
+    )
+    .bind(request.tenant_id)
+    .bind(report_id)
+    .fetch_one(db)
+    .await?;
+    if !exists {
+        return Err(EmbargoError::NotFound);
+    }
+
+    let embargo_until = resolve_until(db, request).await?;
+
+    // Only an embargo that is still running can be moved; a published report may already have been fetched
+    let embargo = sqlx::query_as::<_, ReportEmbargo>(&format!(
+        r#"
+        INSERT INTO report_embargoes (report_id, tenant_id, embargo_until, reason, set_by)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (report_id) DO UPDATE
+        SET embargo_until = EXCLUDED.embargo_until, reason = EXCLUDED.reason,
+            set_by = EXCLUDED.set_by, set_at = NOW()
+        WHERE report_embargoes.lifted_at IS NULL AND report_embargoes.embargo_until > NOW()
+        RETURNING {}
+        "#,
+        EMBARGO_COLUMNS
+    ))
+    .bind(report_id)
+    .bind(request.tenant_id)
+    .bind(embargo_until)
+    .bind(&request.reason)
+    .bind(request.set_by)
+    .fetch_optional(db)
+    .await?
+    .ok_or(EmbargoError::Conflict("the report has already been published"))?;
+
+    info!(
+        "Report {} of tenant {} is under embargo until {}",
+        report_id, request.tenant_id, embargo.embargo_until
+    );
+    Ok(embargo)
+}
+
+/// Release a report before its embargo time
+pub async fn lift(db: &PgPool, report_id: Uuid, request: &LiftEmbargoRequest) -> Result<ReportEmbargo, EmbargoError> {
+    let lifted = sqlx::query_as::<_, ReportEmbargo>(&format!(
+        r#"
+        UPDATE report_embargoes
+        SET lifted_at = NOW(), lifted_by = $3, lift_reason = $4
+        WHERE tenant_id = $1 AND report_id = $2 AND lifted_at IS NULL AND embargo_until > NOW()
+        RETURNING {}
+        "#,
+        EMBARGO_COLUMNS
+    ))
+    .bind(request.tenant_id)
+    .bind(report_id)
+    .bind(request.lifted_by)
+    .bind(request.reason.trim())
+    .fetch_optional(db)
+    .await?;
+
+    match lifted {
+        Some(embargo) => {
+            info!(
+                "Lifted embargo of report {} of tenant {} ahead of {}: {}",
+                report_id, request.tenant_id, embargo.embargo_until, request.reason
+            );
+            Ok(embargo)
+        }
+        None => match get(db, request.tenant_id, report_id).await? {
+            Some(_) => Err(EmbargoError::Conflict("the report is not under embargo")),
+            None => Err(EmbargoError::NotFound),
+        },
+    }
+}
+
+/// A report's embargo with its early-access attempts; `None` when it was never embargoed
+pub async fn get(db: &PgPool, tenant_id: Uuid, report_id: Uuid) -> Result<Option<EmbargoDetail>, sqlx::Error> {
+    let Some(embargo) = sqlx::query_as::<_, ReportEmbargo>(&format!(
+        "SELECT {} FROM report_embargoes WHERE tenant_id = $1 AND report_id = $2",
+        EMBARGO_COLUMNS
+    ))
+    .bind(tenant_id)
+    .bind(report_id)
+    .fetch_optional(db)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let early_access_attempts = sqlx::query_as::<_, EarlyAccessAttempt>(
+        r#"
+        SELECT attempt_id, action, requested_by, token_id, host(ip_address) AS ip_address, user_agent, attempted_at
+        FROM report_embargo_attempts
+        WHERE report_id = $1
+        ORDER BY attempted_at DESC
+        LIMIT 1000
+        "#,
+    )
+    .bind(report_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(Some(EmbargoDetail {
+        embargo,
+        early_access_attempts,
+    }))
+}
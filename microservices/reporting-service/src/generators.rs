@@ -0,0 +1,448 @@
+//! Report types and the generators behind them
+//!
+//! Each report type is a [`ReportGenerator`] in GENERATORS, declaring the
+//! parameters it takes beyond the tenant and period, the schema of the data it
+//! generates and the formats it can be rendered in; GET /reports/types lists
+//! them. Requests, schedules and amendments generate through the registry, so
+//! a new report type is a generator added here, along with its PDF template
+//! in `render` and its sheets in `sheets`.
+//!
+//! Parameters are optional: a report generated without one, such as by a
+//! schedule, takes its default. Those given are checked against their kind and
+//! choices, and by the generator's `check` for what these cannot express. The
+//! parameters a report was generated with, defaults filled in, are stored with
+//! it and reused by its amendments.
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::progress::Progress;
+use crate::render::ReportFormat;
+use crate::{
+    AlertAgeingReport, BrokerageSummaryReport, ClientExposureReport, ComplianceReport, MarginReport,
+    OrderTradeRatioReport, ReportEngine, TradingSummaryReport, SEVERITIES,
+};
+
+/// Every report type, in the order they are listed
+static GENERATORS: &[&dyn ReportGenerator] =
+    &[&TradingSummary, &Compliance, &ClientExposure, &OrderTradeRatio, &BrokerageSummary, &Margin, &AlertAgeing];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParameterKind {
+    Number,
+    Integer,
+    String,
+    Boolean,
+}
+
+impl ParameterKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Number => "number",
+            Self::Integer => "integer",
+            Self::String => "string",
+            Self::Boolean => "boolean",
+        }
+    }
+
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            Self::Number => value.is_number(),
+            Self::Integer => value.is_i64() || value.is_u64(),
+            Self::String => value.is_string(),
+            Self::Boolean => value.is_boolean(),
+        }
+    }
+}
+
+/// A parameter a report type takes in the `parameters` of a generation request
+#[derive(Debug, Clone, Serialize)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub kind: ParameterKind,
+    pub description: &'static str,
+    /// The values a string parameter may take, any when empty
+    pub choices: &'static [&'static str],
+    pub default: Value,
+}
+
+/// What a generator generates a report from
+pub struct GenerationContext<'a> {
+    pub engine: &'a ReportEngine,
+    pub tenant_id: Uuid,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    /// The declared parameters, defaults filled in
+    pub parameters: &'a Map<String, Value>,
+    pub progress: &'a Progress,
+}
+
+#[async_trait]
+pub trait ReportGenerator: Send + Sync {
+    fn report_type(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    fn parameters(&self) -> Vec<Parameter> {
+        Vec::new()
+    }
+
+    /// Problems with the parameters, defaults filled in, beyond their kinds and choices
+    fn check(&self, _parameters: &Map<String, Value>) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// JSON schema of the data `generate` returns
+    fn output_schema(&self) -> RootSchema;
+
+    /// Formats the report can be rendered in; XBRL takes a taxonomy covering it as well
+    fn formats(&self) -> &'static [ReportFormat] {
+        &ReportFormat::ALL
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value>;
+}
+
+pub fn get(report_type: &str) -> Option<&'static dyn ReportGenerator> {
+    GENERATORS.iter().copied().find(|generator| generator.report_type() == report_type)
+}
+
+pub fn all() -> impl Iterator<Item = &'static dyn ReportGenerator> {
+    GENERATORS.iter().copied()
+}
+
+/// The report types, as `a, b or c` for error messages
+pub fn names() -> String {
+    let names: Vec<&str> = all().map(|generator| generator.report_type()).collect();
+    match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+/// The parameters given for a report of the generator's type, checked against
+/// those it declares and with the missing ones defaulted
+pub fn resolve(generator: &dyn ReportGenerator, given: &Map<String, Value>) -> Result<Map<String, Value>, Vec<String>> {
+    let declared = generator.parameters();
+    let mut errors: Vec<String> = given
+        .keys()
+        .filter(|name| !declared.iter().any(|parameter| parameter.name == name.as_str()))
+        .map(|name| format!("{} reports take no parameter {}", generator.report_type(), name))
+        .collect();
+    let mut resolved = Map::new();
+    for parameter in declared {
+        match given.get(parameter.name) {
+            Some(value) if !parameter.kind.accepts(value) => {
+                errors.push(format!("parameter {} must be a {}", parameter.name, parameter.kind.as_str()));
+            }
+            Some(value) if !parameter.choices.is_empty() && !parameter.choices.iter().any(|choice| value == choice) => {
+                errors.push(format!("parameter {} must be one of {}", parameter.name, parameter.choices.join(", ")));
+            }
+            Some(value) => {
+                resolved.insert(parameter.name.to_string(), value.clone());
+            }
+            None => {
+                resolved.insert(parameter.name.to_string(), parameter.default);
+            }
+        }
+    }
+    if errors.is_empty() {
+        errors = generator.check(&resolved);
+    }
+    if errors.is_empty() {
+        Ok(resolved)
+    } else {
+        Err(errors)
+    }
+}
+
+/// A report type as GET /reports/types lists it
+#[derive(Serialize)]
+pub struct ReportTypeDescription {
+    pub report_type: &'static str,
+    pub description: &'static str,
+    pub parameters: Vec<Parameter>,
+    pub formats: Vec<&'static str>,
+    pub output_schema: RootSchema,
+}
+
+pub fn describe(generator: &dyn ReportGenerator) -> ReportTypeDescription {
+    ReportTypeDescription {
+        report_type: generator.report_type(),
+        description: generator.description(),
+        parameters: generator.parameters(),
+        formats: generator.formats().iter().map(|format| format.as_str()).collect(),
+        output_schema: generator.output_schema(),
+    }
+}
+
+struct TradingSummary;
+
+#[async_trait]
+impl ReportGenerator for TradingSummary {
+    fn report_type(&self) -> &'static str {
+        "TRADING_SUMMARY"
+    }
+
+    fn description(&self) -> &'static str {
+        "Trades, volume and value over the period, by instrument and hour of day"
+    }
+
+    fn output_schema(&self) -> RootSchema {
+        schema_for!(TradingSummaryReport)
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value> {
+        let report = context
+            .engine
+            .generate_trading_summary(context.tenant_id, context.period_start, context.period_end, context.progress)
+            .await?;
+        Ok(serde_json::to_value(report)?)
+    }
+}
+
+struct Compliance;
+
+#[async_trait]
+impl ReportGenerator for Compliance {
+    fn report_type(&self) -> &'static str {
+        "COMPLIANCE_REPORT"
+    }
+
+    fn description(&self) -> &'static str {
+        "Surveillance alerts raised over the period, their resolution and a compliance score"
+    }
+
+    fn output_schema(&self) -> RootSchema {
+        schema_for!(ComplianceReport)
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value> {
+        let report = context
+            .engine
+            .generate_compliance_report(context.tenant_id, context.period_start, context.period_end, context.progress)
+            .await?;
+        Ok(serde_json::to_value(report)?)
+    }
+}
+
+struct ClientExposure;
+
+#[async_trait]
+impl ReportGenerator for ClientExposure {
+    fn report_type(&self) -> &'static str {
+        "CLIENT_EXPOSURE"
+    }
+
+    fn description(&self) -> &'static str {
+        "Client positions at the end of the period, with the period's activity, margin and limits"
+    }
+
+    fn output_schema(&self) -> RootSchema {
+        schema_for!(ClientExposureReport)
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value> {
+        let report = context
+            .engine
+            .generate_client_exposure(context.tenant_id, context.period_start, context.period_end, context.progress)
+            .await?;
+        Ok(serde_json::to_value(report)?)
+    }
+}
+
+struct OrderTradeRatio;
+
+#[async_trait]
+impl ReportGenerator for OrderTradeRatio {
+    fn report_type(&self) -> &'static str {
+        "ORDER_TRADE_RATIO"
+    }
+
+    fn description(&self) -> &'static str {
+        "Orders per trade of each client and instrument, by day or by month"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![Parameter {
+            name: "granularity",
+            kind: ParameterKind::String,
+            description: "DAILY or MONTHLY ratios; AUTO reports a period of whole calendar months monthly",
+            choices: &["AUTO", "DAILY", "MONTHLY"],
+            default: Value::from("AUTO"),
+        }]
+    }
+
+    fn output_schema(&self) -> RootSchema {
+        schema_for!(OrderTradeRatioReport)
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value> {
+        let monthly = match context.parameters.get("granularity").and_then(Value::as_str) {
+            Some("DAILY") => Some(false),
+            Some("MONTHLY") => Some(true),
+            _ => None,
+        };
+        let report = context
+            .engine
+            .generate_order_trade_ratio(
+                context.tenant_id,
+                context.period_start,
+                context.period_end,
+                monthly,
+                context.progress,
+            )
+            .await?;
+        Ok(serde_json::to_value(report)?)
+    }
+}
+
+struct BrokerageSummary;
+
+#[async_trait]
+impl ReportGenerator for BrokerageSummary {
+    fn report_type(&self) -> &'static str {
+        "BROKERAGE_SUMMARY"
+    }
+
+    fn description(&self) -> &'static str {
+        "Brokerage, STT, exchange charges, GST and other fees of the period's trades, per client and segment"
+    }
+
+    fn output_schema(&self) -> RootSchema {
+        schema_for!(BrokerageSummaryReport)
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value> {
+        let report = context
+            .engine
+            .generate_brokerage_summary(context.tenant_id, context.period_start, context.period_end, context.progress)
+            .await?;
+        Ok(serde_json::to_value(report)?)
+    }
+}
+
+struct Margin;
+
+#[async_trait]
+impl ReportGenerator for Margin {
+    fn report_type(&self) -> &'static str {
+        "MARGIN_REPORT"
+    }
+
+    fn description(&self) -> &'static str {
+        "Peak margin utilization, shortfalls and their penalties of each client and day"
+    }
+
+    fn output_schema(&self) -> RootSchema {
+        schema_for!(MarginReport)
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value> {
+        let report = context
+            .engine
+            .generate_margin_report(context.tenant_id, context.period_start, context.period_end, context.progress)
+            .await?;
+        Ok(serde_json::to_value(report)?)
+    }
+}
+
+struct AlertAgeing;
+
+/// Hours per severity from `SEVERITY=HOURS` pairs, as VIOLATION_SLA_HOURS gives them
+fn sla_hours(name: &str, spec: &str) -> Result<HashMap<String, f64>, String> {
+    let mut hours = HashMap::new();
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (severity, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("parameter {} entry '{}' is not SEVERITY=HOURS", name, entry))?;
+        let severity = severity.trim().to_uppercase();
+        if !SEVERITIES.contains(&severity.as_str()) {
+            return Err(format!("parameter {} severity must be one of {}", name, SEVERITIES.join(", ")));
+        }
+        match value.trim().parse::<f64>() {
+            Ok(value) if value.is_finite() && value > 0.0 => hours.insert(severity, value),
+            _ => return Err(format!("parameter {} entry '{}' needs a positive number of hours", name, entry)),
+        };
+    }
+    Ok(hours)
+}
+
+fn sla_parameter(parameters: &Map<String, Value>, name: &str) -> Result<HashMap<String, f64>, String> {
+    sla_hours(name, parameters.get(name).and_then(Value::as_str).unwrap_or_default())
+}
+
+#[async_trait]
+impl ReportGenerator for AlertAgeing {
+    fn report_type(&self) -> &'static str {
+        "ALERT_AGEING"
+    }
+
+    fn description(&self) -> &'static str {
+        "Time to acknowledge and resolve the period's alerts per type and severity, against SLAs"
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter {
+                name: "acknowledge_sla_hours",
+                kind: ParameterKind::String,
+                description: "Hours to acknowledge an alert in, as SEVERITY=HOURS; severities not listed have no SLA",
+                choices: &[],
+                default: Value::from("CRITICAL=1,HIGH=4,MEDIUM=8,LOW=24"),
+            },
+            Parameter {
+                name: "resolution_sla_hours",
+                kind: ParameterKind::String,
+                description: "Hours to resolve an alert in, as SEVERITY=HOURS; severities not listed have no SLA",
+                choices: &[],
+                default: Value::from("CRITICAL=4,HIGH=8,MEDIUM=24,LOW=48"),
+            },
+            Parameter {
+                name: "clock",
+                kind: ParameterKind::String,
+                description: "BUSINESS counts the tenant's market sessions only, as violation SLAs do; WALL all time",
+                choices: &["BUSINESS", "WALL"],
+                default: Value::from("BUSINESS"),
+            },
+        ]
+    }
+
+    fn check(&self, parameters: &Map<String, Value>) -> Vec<String> {
+        ["acknowledge_sla_hours", "resolution_sla_hours"]
+            .into_iter()
+            .filter_map(|name| sla_parameter(parameters, name).err())
+            .collect()
+    }
+
+    fn output_schema(&self) -> RootSchema {
+        schema_for!(AlertAgeingReport)
+    }
+
+    async fn generate(&self, context: &GenerationContext<'_>) -> anyhow::Result<Value> {
+        let acknowledge = sla_parameter(context.parameters, "acknowledge_sla_hours").map_err(anyhow::Error::msg)?;
+        let resolution = sla_parameter(context.parameters, "resolution_sla_hours").map_err(anyhow::Error::msg)?;
+        let business_time = context.parameters.get("clock").and_then(Value::as_str) != Some("WALL");
+        let report = context
+            .engine
+            .generate_alert_ageing(
+                context.tenant_id,
+                context.period_start,
+                context.period_end,
+                acknowledge,
+                resolution,
+                business_time,
+                context.progress,
+            )
+            .await?;
+        Ok(serde_json::to_value(report)?)
+    }
+}
@@ -0,0 +1,335 @@
+//! Report generation jobs, their cancellation and timeouts
+//!
+//! Every report generated, requested, scheduled or amended, runs as a job in
+//! report_generation_jobs under the report_id it is stored as. Generating its
+//! data may take at most the timeout of its report type
+//! (REPORT_GENERATION_TIMEOUTS, e.g. `TRADING_SUMMARY=300,COMPLIANCE_REPORT=900`
+//! in seconds, and REPORT_GENERATION_TIMEOUT_SECS for the types not listed);
+//! past it the generation is dropped, and with it the queries holding
+//! connections other tenants' reports are waiting for, and the job is marked
+//! FAILED with a timeout reason.
+//!
+//! GET /reports/jobs lists a tenant's jobs, and DELETE /reports/:id/cancel
+//! cancels a RUNNING one. The worker generating it, on whichever replica, sees
+//! the cancellation within CANCEL_POLL and abandons the report. A job stays
+//! RUNNING while its report is rendered, and is marked COMPLETED in the
+//! transaction storing the report, so a report cancelled up to then is not
+//! stored. Jobs whose worker stopped are marked FAILED once
+//! ABANDONED_AFTER_SECS past their deadline.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+
+use crate::progress::Progress;
+
+const RUNNING: &str = "RUNNING";
+const CANCELLED: &str = "CANCELLED";
+
+const DEFAULT_TIMEOUTS: &str = "TRADING_SUMMARY=300,COMPLIANCE_REPORT=900,CLIENT_EXPOSURE=300,ORDER_TRADE_RATIO=900";
+/// How often a running job is checked for cancellation
+const CANCEL_POLL: Duration = Duration::from_secs(2);
+/// How long past its deadline a RUNNING job is taken for abandoned by its
+/// worker, leaving time to render and store a report generated just in time
+const ABANDONED_AFTER_SECS: f64 = 900.0;
+const MAX_LISTED: i64 = 200;
+
+#[derive(Debug, Clone)]
+pub struct JobSettings {
+    timeouts: HashMap<String, Duration>,
+    default_timeout: Duration,
+}
+
+impl JobSettings {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let spec = std::env::var("REPORT_GENERATION_TIMEOUTS")
+            .ok()
+            .filter(|spec| !spec.is_empty())
+            .unwrap_or_else(|| DEFAULT_TIMEOUTS.to_string());
+        let mut timeouts = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (report_type, secs) = entry.split_once('=').ok_or_else(|| {
+                anyhow::anyhow!("REPORT_GENERATION_TIMEOUTS entry '{}' is not REPORT_TYPE=SECONDS", entry)
+            })?;
+            let secs: u64 = secs
+                .trim()
+                .parse()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!("REPORT_GENERATION_TIMEOUTS entry '{}' has no number of seconds", entry)
+                })?;
+            timeouts.insert(report_type.trim().to_uppercase(), Duration::from_secs(secs));
+        }
+        let default_secs = std::env::var("REPORT_GENERATION_TIMEOUT_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(900u64)
+            .max(1);
+        Ok(Self { timeouts, default_timeout: Duration::from_secs(default_secs) })
+    }
+
+    pub fn timeout(&self, report_type: &str) -> Duration {
+        self.timeouts.get(report_type).copied().unwrap_or(self.default_timeout)
+    }
+}
+
+/// The report a job generates
+pub struct Job<'a> {
+    pub report_id: Uuid,
+    pub tenant_id: Uuid,
+    pub report_type: &'a str,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    /// The parameters of the report type, defaults filled in; see `generators`
+    pub parameters: &'a serde_json::Map<String, serde_json::Value>,
+}
+
+/// Why a job stopped without its report
+#[derive(Debug, thiserror::Error)]
+pub enum Stopped {
+    #[error("report generation was cancelled")]
+    Cancelled,
+    #[error("report generation timed out after {0}s")]
+    TimedOut(u64),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error("report generation job not found")]
+    NotFound,
+    #[error("report generation job is already {0}")]
+    Finished(String),
+    #[error(transparent)]
+    Internal(#[from] sqlx::Error),
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ReportJob {
+    pub report_id: Uuid,
+    pub tenant_id: Uuid,
+    pub report_type: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub status: String,
+    pub phase: String,
+    pub progress_percent: i16,
+    pub failure_reason: Option<String>,
+    pub timeout_secs: i32,
+    pub started_at: DateTime<Utc>,
+    pub deadline: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub cancelled_by: Option<Uuid>,
+}
+
+/// The query of DELETE /reports/:id/cancel
+#[derive(Deserialize)]
+pub struct CancelRequest {
+    pub cancelled_by: Option<Uuid>,
+}
+
+const JOB_COLUMNS: &str = "report_id, tenant_id, report_type, period_start, period_end, status, phase, \
+    progress_percent, failure_reason, timeout_secs, started_at, deadline, finished_at, cancelled_by";
+
+pub struct ReportJobs {
+    db: PgPool,
+    settings: JobSettings,
+}
+
+impl ReportJobs {
+    pub fn new(db: PgPool, settings: JobSettings) -> Self {
+        Self { db, settings }
+    }
+
+    /// Record the job and run `work`, its report's data, until it finishes, is
+    /// cancelled or times out. A job whose work fails is marked FAILED; one whose
+    /// work succeeds stays RUNNING until `complete` or `fail`.
+    pub async fn run<T, F>(&self, job: &Job<'_>, work: F) -> anyhow::Result<T>
+    where
+        F: Future<Output = anyhow::Result<T>>,
+    {
+        let timeout = self.settings.timeout(job.report_type);
+        if let Err(e) = expire(&self.db, job.tenant_id).await {
+            warn!("Failed to expire abandoned report generation jobs of tenant {}: {}", job.tenant_id, e);
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO report_generation_jobs (
+                report_id, tenant_id, report_type, period_start, period_end, timeout_secs, deadline
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, NOW() + make_interval(secs => $7))
+            "#,
+        )
+        .bind(job.report_id)
+        .bind(job.tenant_id)
+        .bind(job.report_type)
+        .bind(job.period_start)
+        .bind(job.period_end)
+        .bind(timeout.as_secs() as i32)
+        .bind(timeout.as_secs_f64())
+        .execute(&self.db)
+        .await?;
+
+        let outcome = tokio::select! {
+            outcome = tokio::time::timeout(timeout, work) => outcome,
+            () = self.cancelled(job.report_id) => return Err(Stopped::Cancelled.into()),
+        };
+        match outcome {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(e)) => {
+                self.fail(job.report_id, &format!("{:#}", e)).await;
+                Err(e)
+            }
+            Err(_) => {
+                let stopped = Stopped::TimedOut(timeout.as_secs());
+                self.fail(job.report_id, &stopped.to_string()).await;
+                Err(stopped.into())
+            }
+        }
+    }
+
+    pub fn progress(&self, report_id: Uuid) -> Progress {
+        Progress::new(self.db.clone(), report_id)
+    }
+
+    /// Resolves once the job is no longer RUNNING
+    async fn cancelled(&self, report_id: Uuid) {
+        loop {
+            tokio::time::sleep(CANCEL_POLL).await;
+            let status =
+                sqlx::query_scalar::<_, String>("SELECT status FROM report_generation_jobs WHERE report_id = $1")
+                    .bind(report_id)
+                    .fetch_optional(&self.db)
+                    .await;
+            match status {
+                Ok(Some(status)) if status == RUNNING => {}
+                Ok(_) => return,
+                Err(e) => warn!("Failed to check report generation job {} for cancellation: {}", report_id, e),
+            }
+        }
+    }
+
+    /// Mark a RUNNING job FAILED, such as when its report could not be rendered or stored
+    pub async fn fail(&self, report_id: Uuid, reason: &str) {
+        let failed = sqlx::query(
+            r#"
+            UPDATE report_generation_jobs SET status = 'FAILED', failure_reason = $2, finished_at = NOW()
+            WHERE report_id = $1 AND status = 'RUNNING'
+            "#,
+        )
+        .bind(report_id)
+        .bind(reason)
+        .execute(&self.db)
+        .await;
+        if let Err(e) = failed {
+            warn!("Failed to mark report generation job {} failed: {}", report_id, e);
+        }
+    }
+}
+
+/// Mark the job COMPLETED as its report is stored in `tx`; fails with `Stopped`
+/// when it was cancelled meanwhile
+pub async fn complete(tx: &mut Transaction<'_, Postgres>, report_id: Uuid) -> anyhow::Result<()> {
+    let status =
+        sqlx::query_scalar::<_, String>("SELECT status FROM report_generation_jobs WHERE report_id = $1 FOR UPDATE")
+            .bind(report_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+    match status.as_deref() {
+        Some(RUNNING) => {
+            sqlx::query(
+                "UPDATE report_generation_jobs SET status = 'COMPLETED', finished_at = NOW() WHERE report_id = $1",
+            )
+            .bind(report_id)
+            .execute(&mut **tx)
+            .await?;
+            Ok(())
+        }
+        Some(CANCELLED) => Err(Stopped::Cancelled.into()),
+        other => anyhow::bail!(
+            "the generation job of report {} is {}",
+            report_id,
+            other.unwrap_or("missing")
+        ),
+    }
+}
+
+/// Mark the tenant's jobs whose worker stopped FAILED
+async fn expire(db: &PgPool, tenant_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE report_generation_jobs
+        SET status = 'FAILED', finished_at = NOW(),
+            failure_reason = format('report generation timed out after %ss; its worker stopped', timeout_secs)
+        WHERE tenant_id = $1 AND status = 'RUNNING' AND deadline < NOW() - make_interval(secs => $2)
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(ABANDONED_AFTER_SECS)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// The tenant's most recent jobs, RUNNING ones first
+pub async fn list(db: &PgPool, tenant: &TenantContext) -> Result<Vec<ReportJob>, JobError> {
+    expire(db, tenant.tenant_id()).await?;
+    let jobs = sqlx::query_as::<_, ReportJob>(&format!(
+        r#"
+        SELECT {} FROM report_generation_jobs
+        WHERE tenant_id = $1
+        ORDER BY status = 'RUNNING' DESC, started_at DESC
+        LIMIT $2
+        "#,
+        JOB_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(MAX_LISTED)
+    .fetch_all(db)
+    .await?;
+    Ok(jobs)
+}
+
+/// Cancel the tenant's RUNNING job; its worker abandons it within CANCEL_POLL
+pub async fn cancel(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_id: Uuid,
+    request: CancelRequest,
+) -> Result<ReportJob, JobError> {
+    let cancelled = sqlx::query_as::<_, ReportJob>(&format!(
+        r#"
+        UPDATE report_generation_jobs
+        SET status = 'CANCELLED', finished_at = NOW(), cancelled_by = $3
+        WHERE tenant_id = $1 AND report_id = $2 AND status = 'RUNNING'
+        RETURNING {}
+        "#,
+        JOB_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .bind(request.cancelled_by)
+    .fetch_optional(db)
+    .await?;
+    if let Some(job) = cancelled {
+        return Ok(job);
+    }
+    let status = sqlx::query_scalar::<_, String>(
+        "SELECT status FROM report_generation_jobs WHERE tenant_id = $1 AND report_id = $2",
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_optional(db)
+    .await?;
+    match status {
+        Some(status) => Err(JobError::Finished(status)),
+        None => Err(JobError::NotFound),
+    }
+}
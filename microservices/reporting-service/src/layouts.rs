@@ -0,0 +1,915 @@
+//! Report layouts
+//!
+//! The PDF of each report type is laid out by a Tera template of typst markup
+//! under `templates/`, compiled into the service, which `render` applies a
+//! layout to before typst compiles it. A layout names the sections of the
+//! report type to show, in order, and may add a title, page header,
+//! disclaimer and logo. The built-in layout shows every section.
+//!
+//! Tenants keep versioned overrides in report_layouts. Every upload is a new
+//! version, tried against sample data before it is accepted, and by default
+//! becomes the current one; an earlier version can be made current again, or
+//! the tenant can return to the built-in layout. A version may also replace the
+//! built-in template with its own, rendered the same way. Text reaches typst
+//! through report.json (under `layout`) rather than being pasted into the
+//! markup, and typst can read nothing outside the scratch directory, so a
+//! layout cannot reach beyond its own report. Reports record the layout they
+//! were rendered with as layout_id.
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+
+use crate::render::{self, ReportFormat, ReportMeta};
+use crate::report_files::ReportFiles;
+use crate::{
+    AgeDistribution, AlertAgeing, AlertAgeingReport, BrokerageSummaryReport, Charges, ClientCharges, ClientExposure,
+    ClientExposureReport, ClientMargin, ClientOrderTradeRatio, ComplianceReport, DailyMargin, InstrumentStats,
+    MarginReport, MarginViolation, OrderTradeRatio, OrderTradeRatioReport, PositionExposure, RiskMetrics,
+    SegmentCharges, SlaBreach, TradingSummaryReport,
+};
+
+const MAX_LOGO_BYTES: usize = 512 * 1024;
+const MAX_TEXT_CHARS: usize = 2000;
+const MAX_SOURCE_BYTES: usize = 256 * 1024;
+
+/// The sections of a report type's PDF, in their built-in order
+pub fn sections(report_type: &str) -> Option<&'static [&'static str]> {
+    match report_type {
+        "TRADING_SUMMARY" => Some(&["overview", "instruments", "hours"]),
+        "COMPLIANCE_REPORT" => Some(&["score", "alerts", "patterns", "risk"]),
+        "CLIENT_EXPOSURE" => Some(&["overview", "clients", "positions", "limits"]),
+        "ORDER_TRADE_RATIO" => Some(&["overview", "clients", "flagged", "ratios"]),
+        "BROKERAGE_SUMMARY" => Some(&["overview", "clients", "segments"]),
+        "MARGIN_REPORT" => Some(&["overview", "clients", "days", "violations"]),
+        "ALERT_AGEING" => Some(&["overview", "acknowledgement", "resolution", "breaches"]),
+        _ => None,
+    }
+}
+
+/// How a report's PDF is laid out, as its template sees it
+#[derive(Serialize, Debug, Clone)]
+pub struct Layout {
+    #[serde(skip)]
+    pub layout_id: Option<Uuid>,
+    pub title: Option<String>,
+    pub header: Option<String>,
+    pub disclaimer: Option<String>,
+    pub sections: Vec<String>,
+    /// Name the logo is written under next to report.json
+    pub logo: Option<String>,
+    #[serde(skip)]
+    logo_bytes: Option<Vec<u8>>,
+    #[serde(skip)]
+    source: Option<String>,
+}
+
+impl Layout {
+    pub fn default_for(report_type: &str) -> Self {
+        Self {
+            layout_id: None,
+            title: None,
+            header: None,
+            disclaimer: None,
+            sections: sections(report_type).unwrap_or_default().iter().map(|s| s.to_string()).collect(),
+            logo: None,
+            logo_bytes: None,
+            source: None,
+        }
+    }
+
+    /// The typst source of a report of the type in this layout
+    pub fn compose(&self, report_type: &str) -> anyhow::Result<String> {
+        let template = match &self.source {
+            Some(source) => source.as_str(),
+            None => render::template(report_type)
+                .with_context(|| format!("no PDF template for {} reports", report_type))?,
+        };
+        let mut context = tera::Context::new();
+        context.insert("layout", self);
+        context.insert("report_type", report_type);
+        tera::Tera::one_off(template, &context, false).context("failed to apply the report layout")
+    }
+
+    /// The logo's file name and contents
+    pub fn logo(&self) -> Option<(&str, &[u8])> {
+        Some((self.logo.as_deref()?, self.logo_bytes.as_deref()?))
+    }
+}
+
+/// The parts of a layout a tenant sets
+#[derive(Deserialize, Default)]
+pub struct LayoutFields {
+    pub title: Option<String>,
+    pub header: Option<String>,
+    pub disclaimer: Option<String>,
+    /// The report type's sections to show, in order; all of them when not given
+    pub sections: Option<Vec<String>>,
+    /// Base64 PNG, JPEG or SVG
+    pub logo: Option<String>,
+    pub logo_content_type: Option<String>,
+    /// A Tera template of typst markup replacing the built-in one; see GET /reports/layouts/:type/default
+    pub source: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateLayoutRequest {
+    #[serde(flatten)]
+    pub layout: LayoutFields,
+    pub notes: Option<String>,
+    /// Make it the current layout; true when not given
+    pub activate: Option<bool>,
+    pub created_by: Option<Uuid>,
+}
+
+/// A PDF of the layout: a draft, a saved version, or else the current one
+#[derive(Deserialize, Default)]
+pub struct PreviewRequest {
+    pub layout: Option<LayoutFields>,
+    pub version: Option<i32>,
+    /// The tenant's report of the type whose data is shown; sample data when not given
+    pub report_id: Option<Uuid>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct LayoutVersion {
+    pub layout_id: Uuid,
+    pub tenant_id: Uuid,
+    pub report_type: String,
+    pub version: i32,
+    pub title: Option<String>,
+    pub header: Option<String>,
+    pub disclaimer: Option<String>,
+    pub sections: Option<Vec<String>>,
+    pub has_logo: bool,
+    pub logo_content_type: Option<String>,
+    pub source: Option<String>,
+    pub notes: Option<String>,
+    pub is_current: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+const CLEAR_CURRENT: &str =
+    "UPDATE report_layouts SET is_current = FALSE WHERE tenant_id = $1 AND report_type = $2 AND is_current";
+
+const LAYOUT_COLUMNS: &str = "layout_id, tenant_id, report_type, version, title, header, disclaimer, sections, \
+     logo IS NOT NULL AS has_logo, logo_content_type, source, notes, is_current, created_by, created_at";
+
+/// The built-in template of a report type, to start an override from
+#[derive(Serialize)]
+pub struct DefaultLayout {
+    pub report_type: String,
+    pub sections: Vec<String>,
+    pub source: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LayoutError {
+    #[error("{0} reports have no PDF layout")]
+    UnknownReportType(String),
+    #[error("layout version not found")]
+    NotFound,
+    #[error("invalid layout")]
+    Invalid(Vec<String>),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for LayoutError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+fn check_report_type(report_type: &str) -> Result<&'static [&'static str], LayoutError> {
+    match (sections(report_type), render::template(report_type)) {
+        (Some(sections), Some(_)) => Ok(sections),
+        _ => Err(LayoutError::UnknownReportType(report_type.to_string())),
+    }
+}
+
+/// Blank text is left out
+fn text(value: Option<String>) -> Option<String> {
+    value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+impl LayoutFields {
+    /// The layout the fields describe, or what is wrong with them
+    fn layout(self, report_type: &str) -> Result<(Layout, Option<String>), Vec<String>> {
+        let known = sections(report_type).unwrap_or_default();
+        let mut errors = Vec::new();
+        let (title, header, disclaimer) = (text(self.title), text(self.header), text(self.disclaimer));
+        if title.as_ref().is_some_and(|title| title.chars().count() > 200) {
+            errors.push("title may be at most 200 characters".to_string());
+        }
+        for (name, value) in [("header", &header), ("disclaimer", &disclaimer)] {
+            if value.as_ref().is_some_and(|value| value.chars().count() > MAX_TEXT_CHARS) {
+                errors.push(format!("{} may be at most {} characters", name, MAX_TEXT_CHARS));
+            }
+        }
+
+        let sections = match self.sections {
+            None => known.iter().map(|s| s.to_string()).collect(),
+            Some(sections) => {
+                if sections.is_empty() {
+                    errors.push("sections may not be empty".to_string());
+                }
+                for (index, section) in sections.iter().enumerate() {
+                    if !known.contains(&section.as_str()) {
+                        errors.push(format!("unknown section {}; {} has {}", section, report_type, known.join(", ")));
+                    } else if sections[..index].contains(section) {
+                        errors.push(format!("section {} is listed twice", section));
+                    }
+                }
+                sections
+            }
+        };
+
+        let logo = match (self.logo, self.logo_content_type.as_deref()) {
+            (None, None) => None,
+            (Some(logo), Some(content_type)) => match (STANDARD.decode(logo.trim()), logo_extension(content_type)) {
+                (Err(_), _) => {
+                    errors.push("logo must be base64".to_string());
+                    None
+                }
+                (_, None) => {
+                    errors.push("logo_content_type must be image/png, image/jpeg or image/svg+xml".to_string());
+                    None
+                }
+                (Ok(bytes), Some(_)) if bytes.len() > MAX_LOGO_BYTES => {
+                    errors.push(format!("logo may be at most {} KiB", MAX_LOGO_BYTES / 1024));
+                    None
+                }
+                (Ok(bytes), Some(extension)) if !looks_like(extension, &bytes) => {
+                    errors.push(format!("logo is not {}", content_type));
+                    None
+                }
+                (Ok(bytes), Some(extension)) => Some((bytes, extension)),
+            },
+            _ => {
+                errors.push("logo and logo_content_type go together".to_string());
+                None
+            }
+        };
+
+        let source = self.source.filter(|source| !source.trim().is_empty());
+        if let Some(source) = &source {
+            if source.len() > MAX_SOURCE_BYTES {
+                errors.push(format!("source may be at most {} KiB", MAX_SOURCE_BYTES / 1024));
+            } else if let Err(e) = tera::Tera::default().add_raw_template("source", source) {
+                errors.push(format!("source is not a valid template: {}", e));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let content_type = logo.as_ref().and(self.logo_content_type);
+        let (logo, logo_bytes) = match logo {
+            Some((bytes, extension)) => (Some(format!("logo.{}", extension)), Some(bytes)),
+            None => (None, None),
+        };
+        let layout = Layout {
+            layout_id: None,
+            title,
+            header,
+            disclaimer,
+            sections,
+            logo,
+            logo_bytes,
+            source,
+        };
+        Ok((layout, content_type))
+    }
+}
+
+fn logo_extension(content_type: &str) -> Option<&'static str> {
+    match content_type {
+        "image/png" => Some("png"),
+        "image/jpeg" => Some("jpg"),
+        "image/svg+xml" => Some("svg"),
+        _ => None,
+    }
+}
+
+/// Whether the bytes start the way files of the extension do
+fn looks_like(extension: &str, bytes: &[u8]) -> bool {
+    match extension {
+        "png" => bytes.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "jpg" => bytes.starts_with(&[0xFF, 0xD8, 0xFF]),
+        "svg" => std::str::from_utf8(&bytes[..bytes.len().min(1024)]).is_ok_and(|head| head.contains("<svg")),
+        _ => false,
+    }
+}
+
+/// A saved version as it is rendered
+#[derive(sqlx::FromRow)]
+struct StoredLayout {
+    layout_id: Uuid,
+    report_type: String,
+    title: Option<String>,
+    header: Option<String>,
+    disclaimer: Option<String>,
+    sections: Option<Vec<String>>,
+    logo: Option<Vec<u8>>,
+    logo_content_type: Option<String>,
+    source: Option<String>,
+}
+
+impl From<StoredLayout> for Layout {
+    fn from(stored: StoredLayout) -> Self {
+        let logo = stored.logo_content_type.as_deref().and_then(logo_extension).map(|ext| format!("logo.{}", ext));
+        Self {
+            layout_id: Some(stored.layout_id),
+            sections: stored.sections.unwrap_or_else(|| Layout::default_for(&stored.report_type).sections),
+            title: stored.title,
+            header: stored.header,
+            disclaimer: stored.disclaimer,
+            logo_bytes: stored.logo.filter(|_| logo.is_some()),
+            logo,
+            source: stored.source,
+        }
+    }
+}
+
+const STORED_COLUMNS: &str =
+    "layout_id, report_type, title, header, disclaimer, sections, logo, logo_content_type, source";
+
+/// The tenant's current layout for the report type; `None` keeps the built-in one
+pub async fn current(db: &PgPool, tenant_id: Uuid, report_type: &str) -> Result<Option<Layout>, sqlx::Error> {
+    let stored = sqlx::query_as::<_, StoredLayout>(&format!(
+        "SELECT {} FROM report_layouts WHERE tenant_id = $1 AND report_type = $2 AND is_current",
+        STORED_COLUMNS
+    ))
+    .bind(tenant_id)
+    .bind(report_type)
+    .fetch_optional(db)
+    .await?;
+    Ok(stored.map(Layout::from))
+}
+
+pub fn default_layout(report_type: &str) -> Result<DefaultLayout, LayoutError> {
+    let sections = check_report_type(report_type)?;
+    Ok(DefaultLayout {
+        report_type: report_type.to_string(),
+        sections: sections.iter().map(|s| s.to_string()).collect(),
+        source: render::template(report_type).unwrap_or_default().to_string(),
+    })
+}
+
+pub async fn list(db: &PgPool, tenant: &TenantContext, report_type: &str) -> Result<Vec<LayoutVersion>, LayoutError> {
+    check_report_type(report_type)?;
+    let versions = sqlx::query_as::<_, LayoutVersion>(&format!(
+        "SELECT {} FROM report_layouts WHERE tenant_id = $1 AND report_type = $2 ORDER BY version DESC",
+        LAYOUT_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(report_type)
+    .fetch_all(db)
+    .await?;
+    Ok(versions)
+}
+
+pub async fn get(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_type: &str,
+    version: i32,
+) -> Result<LayoutVersion, LayoutError> {
+    check_report_type(report_type)?;
+    sqlx::query_as::<_, LayoutVersion>(&format!(
+        "SELECT {} FROM report_layouts WHERE tenant_id = $1 AND report_type = $2 AND version = $3",
+        LAYOUT_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(report_type)
+    .bind(version)
+    .fetch_optional(db)
+    .await?
+    .ok_or(LayoutError::NotFound)
+}
+
+/// Upload a new version; it is rendered against sample data first so a broken layout never reaches reports
+pub async fn create(
+    db: &PgPool,
+    files: &ReportFiles,
+    tenant: &TenantContext,
+    report_type: &str,
+    request: CreateLayoutRequest,
+) -> Result<LayoutVersion, LayoutError> {
+    check_report_type(report_type)?;
+    let (layout, logo_content_type) = request.layout.layout(report_type).map_err(LayoutError::Invalid)?;
+    let sample = sample_data(report_type)?;
+    if let Err(e) = render_pdf(files, tenant.tenant_id(), report_type, layout.clone(), &sample).await {
+        return Err(LayoutError::Invalid(vec![format!("layout does not render: {:#}", e)]));
+    }
+
+    let activate = request.activate.unwrap_or(true);
+    let mut tx = db.begin().await?;
+    // Uploads of the same tenant and report type take their version numbers one at a time
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(format!("report_layouts/{}/{}", tenant.tenant_id(), report_type))
+        .execute(&mut *tx)
+        .await?;
+    if activate {
+        sqlx::query(CLEAR_CURRENT)
+            .bind(tenant.tenant_id())
+            .bind(report_type)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let version = sqlx::query_as::<_, LayoutVersion>(&format!(
+        r#"
+        INSERT INTO report_layouts (
+            tenant_id, report_type, version, title, header, disclaimer, sections, logo, logo_content_type, source,
+            notes, is_current, created_by
+        )
+        SELECT $1, $2, COALESCE(MAX(version), 0) + 1, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12
+        FROM report_layouts
+        WHERE tenant_id = $1 AND report_type = $2
+        RETURNING {}
+        "#,
+        LAYOUT_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(report_type)
+    .bind(&layout.title)
+    .bind(&layout.header)
+    .bind(&layout.disclaimer)
+    .bind(&layout.sections)
+    .bind(&layout.logo_bytes)
+    .bind(logo_content_type)
+    .bind(&layout.source)
+    .bind(text(request.notes))
+    .bind(activate)
+    .bind(request.created_by)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(version)
+}
+
+/// Make a saved version the current one, such as to roll back to it
+pub async fn activate(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_type: &str,
+    version: i32,
+) -> Result<LayoutVersion, LayoutError> {
+    check_report_type(report_type)?;
+    let mut tx = db.begin().await?;
+    sqlx::query(
+        r#"
+        UPDATE report_layouts SET is_current = FALSE
+        WHERE tenant_id = $1 AND report_type = $2 AND is_current AND version <> $3
+          AND EXISTS (SELECT 1 FROM report_layouts WHERE tenant_id = $1 AND report_type = $2 AND version = $3)
+        "#,
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_type)
+    .bind(version)
+    .execute(&mut *tx)
+    .await?;
+    let activated = sqlx::query_as::<_, LayoutVersion>(&format!(
+        r#"
+        UPDATE report_layouts SET is_current = TRUE
+        WHERE tenant_id = $1 AND report_type = $2 AND version = $3
+        RETURNING {}
+        "#,
+        LAYOUT_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(report_type)
+    .bind(version)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(LayoutError::NotFound)?;
+    tx.commit().await?;
+    Ok(activated)
+}
+
+/// Return to the built-in layout; the saved versions are kept
+pub async fn reset(db: &PgPool, tenant: &TenantContext, report_type: &str) -> Result<(), LayoutError> {
+    check_report_type(report_type)?;
+    sqlx::query(CLEAR_CURRENT)
+        .bind(tenant.tenant_id())
+        .bind(report_type)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// A PDF of a report of the type in the requested layout
+pub async fn preview(
+    db: &PgPool,
+    files: &ReportFiles,
+    tenant: &TenantContext,
+    report_type: &str,
+    request: PreviewRequest,
+) -> Result<Vec<u8>, LayoutError> {
+    check_report_type(report_type)?;
+    let tenant_id = tenant.tenant_id();
+    let layout = match (request.layout, request.version) {
+        (Some(fields), _) => fields.layout(report_type).map_err(LayoutError::Invalid)?.0,
+        (None, Some(version)) => sqlx::query_as::<_, StoredLayout>(&format!(
+            "SELECT {} FROM report_layouts WHERE tenant_id = $1 AND report_type = $2 AND version = $3",
+            STORED_COLUMNS
+        ))
+        .bind(tenant_id)
+        .bind(report_type)
+        .bind(version)
+        .fetch_optional(db)
+        .await?
+        .map(Layout::from)
+        .ok_or(LayoutError::NotFound)?,
+        (None, None) => current(db, tenant_id, report_type)
+            .await?
+            .unwrap_or_else(|| Layout::default_for(report_type)),
+    };
+    let data = match request.report_id {
+        Some(report_id) => sqlx::query_scalar::<_, serde_json::Value>(
+            "SELECT report_data FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = $2",
+        )
+        .bind(tenant_id)
+        .bind(report_id)
+        .fetch_optional(db)
+        .await?
+        .ok_or(LayoutError::NotFound)?,
+        None => sample_data(report_type)?,
+    };
+    // A layout that does not render is the tenant's to fix, so the reason goes back to them
+    render_pdf(files, tenant_id, report_type, layout, &data)
+        .await
+        .map_err(|e| LayoutError::Invalid(vec![format!("layout does not render: {:#}", e)]))
+}
+
+async fn render_pdf(
+    files: &ReportFiles,
+    tenant_id: Uuid,
+    report_type: &str,
+    layout: Layout,
+    data: &serde_json::Value,
+) -> anyhow::Result<Vec<u8>> {
+    let today = Utc::now().date_naive();
+    let meta = ReportMeta {
+        report_id: Uuid::nil(),
+        tenant_id,
+        report_type: report_type.to_string(),
+        period_start: today - chrono::Duration::days(6),
+        period_end: today,
+        generated_at: Utc::now(),
+        registration_no: None,
+        layout: Some(layout),
+    };
+    files.render(&meta, ReportFormat::Pdf, data).await
+}
+
+/// Made-up data of the report type, for previews
+fn sample_data(report_type: &str) -> Result<serde_json::Value, LayoutError> {
+    let data = match report_type {
+        "TRADING_SUMMARY" => serde_json::to_value(TradingSummaryReport {
+            total_trades: 1280,
+            total_volume: 452_300.0,
+            total_value: 18_945_120.5,
+            unique_instruments: 3,
+            active_clients: 42,
+            average_trade_size: 14_800.88,
+            largest_trade: 612_000.0,
+            trading_hours_distribution: HashMap::from([
+                ("09:00".to_string(), 410),
+                ("11:00".to_string(), 380),
+                ("14:00".to_string(), 490),
+            ]),
+            instrument_breakdown: [
+                ("RELIANCE", 620, 9_804_000.0),
+                ("TCS", 410, 6_150_000.0),
+                ("INFY", 250, 2_991_120.5),
+            ]
+            .into_iter()
+            .map(|(instrument, trade_count, total_value)| InstrumentStats {
+                instrument: instrument.to_string(),
+                trade_count,
+                total_volume: total_value / 40.0,
+                total_value,
+                avg_price: 40.0,
+            })
+            .collect(),
+        }),
+        "COMPLIANCE_REPORT" => serde_json::to_value(ComplianceReport {
+            alerts_generated: 37,
+            critical_alerts: 4,
+            resolved_alerts: 29,
+            pending_investigations: 8,
+            compliance_score: 91.5,
+            violations_detected: 4,
+            pattern_breakdown: HashMap::from([
+                ("WASH_TRADING".to_string(), 12),
+                ("SPOOFING".to_string(), 9),
+                ("FRONT_RUNNING".to_string(), 16),
+            ]),
+            risk_metrics: RiskMetrics {
+                var_95: 0.021,
+                var_99: 0.034,
+                max_drawdown: 0.087,
+                sharpe_ratio: 1.42,
+                volatility: 0.18,
+            },
+        }),
+        "CLIENT_EXPOSURE" => {
+            let position = |instrument: &str, net_quantity: i64, mark_price: f64, gross: f64| PositionExposure {
+                instrument: instrument.to_string(),
+                net_quantity,
+                mark_price,
+                market_value: net_quantity as f64 * mark_price,
+                share_of_exposure: (net_quantity as f64 * mark_price).abs() / gross,
+                bought_value: 250_000.0,
+                sold_value: 120_000.0,
+                position_limit: None,
+                exposure_limit: Some(1_500_000.0),
+                limit_utilization: Some((net_quantity as f64 * mark_price).abs() / 1_500_000.0),
+            };
+            serde_json::to_value(ClientExposureReport {
+                clients_with_positions: 1,
+                total_gross_exposure: 2_180_000.0,
+                total_net_exposure: 1_420_000.0,
+                total_margin_required: 436_000.0,
+                clients_over_limit: 1,
+                clients: vec![ClientExposure {
+                    client_code: "CL0001".to_string(),
+                    client_name: Some("Sample Client".to_string()),
+                    gross_exposure: 2_180_000.0,
+                    net_exposure: 1_420_000.0,
+                    long_exposure: 1_800_000.0,
+                    short_exposure: 380_000.0,
+                    bought_value: 500_000.0,
+                    sold_value: 240_000.0,
+                    margin_required: Some(436_000.0),
+                    collateral_available: Some(600_000.0),
+                    margin_utilization: Some(436_000.0 / 600_000.0),
+                    largest_instrument: Some("RELIANCE".to_string()),
+                    concentration: 1_800_000.0 / 2_180_000.0,
+                    exposure_limit: Some(2_000_000.0),
+                    exposure_limit_utilization: Some(2_180_000.0 / 2_000_000.0),
+                    limit_breaches: 2,
+                    positions: vec![
+                        position("RELIANCE", 750, 2_400.0, 2_180_000.0),
+                        position("INFY", -250, 1_520.0, 2_180_000.0),
+                    ],
+                }],
+            })
+        }
+        "ORDER_TRADE_RATIO" => {
+            let today = Utc::now().date_naive();
+            let ratios = vec![
+                OrderTradeRatio {
+                    date: today,
+                    client: "CL0001".to_string(),
+                    instrument: "RELIANCE".to_string(),
+                    orders: 5_400,
+                    trades: 18,
+                    ratio: 300.0,
+                    threshold_exceeded: Some(250.0),
+                },
+                OrderTradeRatio {
+                    date: today,
+                    client: "CL0002".to_string(),
+                    instrument: "INFY".to_string(),
+                    orders: 420,
+                    trades: 60,
+                    ratio: 7.0,
+                    threshold_exceeded: None,
+                },
+            ];
+            serde_json::to_value(OrderTradeRatioReport {
+                granularity: "DAILY".to_string(),
+                thresholds: vec![50.0, 250.0, 500.0],
+                total_orders: 5_820,
+                total_trades: 78,
+                overall_ratio: 5_820.0 / 78.0,
+                flagged_ratios: 1,
+                clients_flagged: 1,
+                clients: ratios
+                    .iter()
+                    .map(|row| ClientOrderTradeRatio {
+                        client: row.client.clone(),
+                        orders: row.orders,
+                        trades: row.trades,
+                        ratio: row.ratio,
+                        highest_ratio: row.ratio,
+                        flagged_ratios: row.threshold_exceeded.is_some() as i64,
+                    })
+                    .collect(),
+                ratios,
+            })
+        }
+        "BROKERAGE_SUMMARY" => {
+            let charges = |trades: i64, turnover: f64| {
+                let brokerage = turnover * 0.0003;
+                let stt = turnover * 0.001;
+                let exchange_charges = turnover * 0.0000345;
+                let sebi_fees = turnover * 0.000001;
+                let stamp_duty = turnover * 0.00015;
+                let gst = (brokerage + exchange_charges + sebi_fees) * 0.18;
+                Charges {
+                    trades,
+                    turnover,
+                    brokerage,
+                    stt,
+                    exchange_charges,
+                    sebi_fees,
+                    stamp_duty,
+                    gst,
+                    unallocated_taxes: 0.0,
+                    total_charges: brokerage + stt + exchange_charges + sebi_fees + stamp_duty + gst,
+                }
+            };
+            let rows = vec![
+                SegmentCharges {
+                    client: "CL0001".to_string(),
+                    segment: "EQUITY".to_string(),
+                    charges: charges(420, 12_600_000.0),
+                    trades_without_breakdown: 0,
+                },
+                SegmentCharges {
+                    client: "CL0001".to_string(),
+                    segment: "FUTURES".to_string(),
+                    charges: charges(85, 4_250_000.0),
+                    trades_without_breakdown: 0,
+                },
+                SegmentCharges {
+                    client: "CL0002".to_string(),
+                    segment: "EQUITY".to_string(),
+                    charges: charges(160, 2_095_120.5),
+                    trades_without_breakdown: 0,
+                },
+            ];
+            let mut totals = Charges::default();
+            let mut clients: Vec<ClientCharges> = Vec::new();
+            for row in &rows {
+                totals.add(&row.charges);
+                match clients.iter_mut().find(|client| client.client == row.client) {
+                    Some(client) => client.charges.add(&row.charges),
+                    None => clients.push(ClientCharges {
+                        client: row.client.clone(),
+                        charges: row.charges.clone(),
+                    }),
+                }
+            }
+            serde_json::to_value(BrokerageSummaryReport {
+                totals,
+                trades_without_breakdown: 0,
+                clients,
+                rows,
+            })
+        }
+        "MARGIN_REPORT" => {
+            let today = Utc::now().date_naive();
+            let day = |date: chrono::NaiveDate, client: &str, peak_margin: f64, collateral_available: f64| {
+                let shortfall = (peak_margin - collateral_available).max(0.0);
+                DailyMargin {
+                    date,
+                    client: client.to_string(),
+                    peak_margin,
+                    collateral_available,
+                    utilization: Some(peak_margin / collateral_available),
+                    shortfall,
+                    penalty: shortfall * 0.01,
+                }
+            };
+            let days = vec![
+                day(today - chrono::Duration::days(1), "CL0001", 640_000.0, 600_000.0),
+                day(today - chrono::Duration::days(1), "CL0002", 180_000.0, 250_000.0),
+                day(today, "CL0001", 720_000.0, 600_000.0),
+                day(today, "CL0002", 210_000.0, 250_000.0),
+            ];
+            let violations: Vec<MarginViolation> = days
+                .iter()
+                .filter(|day| day.shortfall > 0.0)
+                .enumerate()
+                .map(|(index, day)| MarginViolation {
+                    date: day.date,
+                    client: day.client.clone(),
+                    exchange: "NSE".to_string(),
+                    peak_margin: day.peak_margin,
+                    collateral_available: day.collateral_available,
+                    shortfall: day.shortfall,
+                    shortfall_share: day.shortfall / day.peak_margin,
+                    consecutive_days: index as i64 + 1,
+                    days_in_month: index as i64 + 1,
+                    penalty_rate: 0.01,
+                    penalty: day.penalty,
+                })
+                .collect();
+            serde_json::to_value(MarginReport {
+                business_days: 2,
+                clients_with_statements: 2,
+                highest_utilization: Some(1.2),
+                shortfall_incidents: violations.len() as i64,
+                clients_with_shortfall: 1,
+                total_shortfall: violations.iter().map(|violation| violation.shortfall).sum(),
+                total_penalty: violations.iter().map(|violation| violation.penalty).sum(),
+                statements_without_peak: 0,
+                clients: vec![
+                    ClientMargin {
+                        client: "CL0001".to_string(),
+                        client_name: Some("Sample Client".to_string()),
+                        business_days: 2,
+                        highest_peak_margin: 720_000.0,
+                        highest_utilization: Some(1.2),
+                        average_utilization: Some(1_360_000.0 / 1_200_000.0),
+                        shortfall_incidents: 2,
+                        total_shortfall: 160_000.0,
+                        largest_shortfall: 120_000.0,
+                        penalty: 1_600.0,
+                    },
+                    ClientMargin {
+                        client: "CL0002".to_string(),
+                        client_name: Some("Another Client".to_string()),
+                        business_days: 2,
+                        highest_peak_margin: 210_000.0,
+                        highest_utilization: Some(0.84),
+                        average_utilization: Some(0.78),
+                        shortfall_incidents: 0,
+                        total_shortfall: 0.0,
+                        largest_shortfall: 0.0,
+                        penalty: 0.0,
+                    },
+                ],
+                days,
+                violations,
+            })
+        }
+        "ALERT_AGEING" => {
+            let acknowledge_sla_hours =
+                HashMap::from([("CRITICAL".to_string(), 1.0), ("HIGH".to_string(), 4.0), ("MEDIUM".to_string(), 8.0)]);
+            let resolution_sla_hours =
+                HashMap::from([("CRITICAL".to_string(), 4.0), ("HIGH".to_string(), 8.0), ("MEDIUM".to_string(), 24.0)]);
+            let group = |alert_type: &str, severity: &str, acknowledged: Vec<f64>, resolved: Vec<f64>| {
+                let alerts = acknowledged.len().max(resolved.len()) as i64;
+                let breaches = |hours: &[f64], sla: Option<f64>| {
+                    hours.iter().filter(|hours| sla.is_some_and(|sla| **hours > sla)).count() as i64
+                };
+                let acknowledge_sla = acknowledge_sla_hours.get(severity).copied();
+                let resolution_sla = resolution_sla_hours.get(severity).copied();
+                AlertAgeing {
+                    alert_type: alert_type.to_string(),
+                    severity: severity.to_string(),
+                    alerts,
+                    acknowledge_sla_hours: acknowledge_sla,
+                    resolution_sla_hours: resolution_sla,
+                    time_to_acknowledge: AgeDistribution::of(
+                        acknowledged.clone(),
+                        alerts - acknowledged.len() as i64,
+                        breaches(&acknowledged, acknowledge_sla),
+                    ),
+                    time_to_resolution: AgeDistribution::of(
+                        resolved.clone(),
+                        alerts - resolved.len() as i64,
+                        breaches(&resolved, resolution_sla),
+                    ),
+                }
+            };
+            let groups = vec![
+                group("SPOOFING", "CRITICAL", vec![0.4, 0.8, 2.5], vec![3.0, 6.5]),
+                group("WASH_TRADING", "HIGH", vec![1.5, 2.0, 3.5, 6.0], vec![5.0, 7.5, 12.0, 20.0]),
+                group("FRONT_RUNNING", "MEDIUM", vec![4.0, 9.0], vec![18.0]),
+            ];
+            let sum = |field: fn(&AlertAgeing) -> i64| groups.iter().map(field).sum::<i64>();
+            serde_json::to_value(AlertAgeingReport {
+                clock: "BUSINESS".to_string(),
+                total_alerts: sum(|group| group.alerts),
+                acknowledged_alerts: sum(|group| group.time_to_acknowledge.measured),
+                resolved_alerts: sum(|group| group.time_to_resolution.measured),
+                acknowledge_breaches: sum(|group| group.time_to_acknowledge.breaches),
+                resolution_breaches: sum(|group| group.time_to_resolution.breaches),
+                acknowledged_within_sla: Some(6.0 / 9.0),
+                resolved_within_sla: Some(6.0 / 9.0),
+                breaches: vec![SlaBreach {
+                    alert_id: Uuid::new_v4(),
+                    alert_type: "WASH_TRADING".to_string(),
+                    severity: "HIGH".to_string(),
+                    raised_at: Utc::now() - chrono::Duration::days(3),
+                    acknowledge_hours: 6.0,
+                    acknowledge_breached: true,
+                    resolution_hours: 20.0,
+                    resolution_breached: true,
+                    resolved: true,
+                    overdue_hours: 12.0,
+                }],
+                acknowledge_sla_hours,
+                resolution_sla_hours,
+                groups,
+            })
+        }
+        other => return Err(LayoutError::UnknownReportType(other.to_string())),
+    };
+    Ok(data.context("failed to build sample data")?)
+}
+
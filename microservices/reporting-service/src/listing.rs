@@ -0,0 +1,178 @@
+//! The tenant's reports, filtered and a page at a time
+//!
+//! GET /reports lists the reports of the caller's tenant, and only those,
+//! most recently generated first. It filters by `report_type`, `status`, the
+//! period a report covers (`period_from` and `period_to`, matching reports
+//! whose period overlaps them) and when it was generated (`generated_from` and
+//! `generated_to`), and is read a page at a time like a drill-down (`limit`,
+//! at most 200, and `offset`). Superseded versions are listed too unless
+//! `current_only` is set.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use dharmaguard_common::approval;
+use dharmaguard_common::tenant::TenantContext;
+
+use crate::ReportResponse;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// Statuses a report can be in, as regulatory_reports_v2 constrains them
+const STATUSES: [&str; 7] = ["DRAFT", "GENERATED", "REVIEWED", "APPROVED", "SUBMITTED", "ACKNOWLEDGED", "REJECTED"];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReportFilter {
+    pub report_type: Option<String>,
+    pub status: Option<String>,
+    pub period_from: Option<NaiveDate>,
+    pub period_to: Option<NaiveDate>,
+    pub generated_from: Option<DateTime<Utc>>,
+    pub generated_to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub current_only: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl ReportFilter {
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(status) = &self.status {
+            if !STATUSES.contains(&status.as_str()) {
+                errors.push(format!("status must be one of {}", STATUSES.join(", ")));
+            }
+        }
+        if let (Some(from), Some(to)) = (self.period_from, self.period_to) {
+            if from > to {
+                errors.push("period_from must not be after period_to".to_string());
+            }
+        }
+        if let (Some(from), Some(to)) = (self.generated_from, self.generated_to) {
+            if from > to {
+                errors.push("generated_from must not be after generated_to".to_string());
+            }
+        }
+        errors
+    }
+
+    /// The tenant's reports the filter matches, from FROM on
+    fn push_from(&self, query: &mut QueryBuilder<'_, Postgres>, tenant_id: Uuid) {
+        query
+            .push(" FROM regulatory_reports_v2 r JOIN report_templates t ON t.template_id = r.template_id")
+            .push(" WHERE r.tenant_id = ")
+            .push_bind(tenant_id);
+        if let Some(report_type) = &self.report_type {
+            query.push(" AND t.report_type = ").push_bind(report_type.clone());
+        }
+        if let Some(status) = &self.status {
+            query.push(" AND r.status = ").push_bind(status.clone());
+        }
+        if let Some(from) = self.period_from {
+            query.push(" AND r.report_period_end >= ").push_bind(from);
+        }
+        if let Some(to) = self.period_to {
+            query.push(" AND r.report_period_start <= ").push_bind(to);
+        }
+        if let Some(from) = self.generated_from {
+            query.push(" AND r.generated_at >= ").push_bind(from);
+        }
+        if let Some(to) = self.generated_to {
+            query.push(" AND r.generated_at <= ").push_bind(to);
+        }
+        if self.current_only {
+            query.push(" AND r.superseded_by IS NULL");
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReportListing {
+    /// Reports the filter matches, on every page
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Pass back as `offset` for the next page; absent on the last page
+    pub next_offset: Option<i64>,
+    pub reports: Vec<ReportResponse>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ListError {
+    #[error("invalid filter")]
+    Invalid(Vec<String>),
+    #[error(transparent)]
+    Internal(#[from] sqlx::Error),
+}
+
+#[derive(sqlx::FromRow)]
+struct ListedReport {
+    report_id: Uuid,
+    tenant_id: Uuid,
+    report_type: String,
+    status: Option<String>,
+    report_period_start: NaiveDate,
+    report_period_end: NaiveDate,
+    generated_at: Option<DateTime<Utc>>,
+    file_path: Option<String>,
+    embargoed_until: Option<DateTime<Utc>>,
+    version: i32,
+    superseded_by: Option<Uuid>,
+}
+
+pub async fn list(db: &PgPool, tenant: &TenantContext, filter: &ReportFilter) -> Result<ReportListing, ListError> {
+    let errors = filter.validate();
+    if !errors.is_empty() {
+        return Err(ListError::Invalid(errors));
+    }
+    let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = filter.offset.unwrap_or(0).max(0);
+
+    let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*)");
+    filter.push_from(&mut count, tenant.tenant_id());
+    let total: i64 = count.build_query_scalar().fetch_one(db).await?;
+
+    let mut page = QueryBuilder::<Postgres>::new(
+        r#"
+        SELECT r.report_id, r.tenant_id, t.report_type, r.status, r.report_period_start, r.report_period_end,
+               r.generated_at, r.file_path, report_embargoed_until(r.report_id) AS embargoed_until, r.version,
+               r.superseded_by
+        "#,
+    );
+    filter.push_from(&mut page, tenant.tenant_id());
+    page.push(" ORDER BY r.generated_at DESC NULLS LAST, r.report_id LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(offset);
+    let rows: Vec<ListedReport> = page.build_query_as().fetch_all(db).await?;
+    let fetched = rows.len() as i64;
+
+    let reports = rows
+        .into_iter()
+        .map(|row| ReportResponse {
+            report_id: row.report_id,
+            tenant_id: row.tenant_id,
+            report_type: row.report_type,
+            status: row.status.unwrap_or_else(|| approval::DRAFT.to_string()),
+            period_start: row.report_period_start,
+            period_end: row.report_period_end,
+            file_path: row.file_path,
+            generated_at: row.generated_at,
+            download_url: Some(format!("/reports/{}/download", row.report_id)),
+            embargoed_until: row.embargoed_until,
+            version: row.version,
+            superseded_by: row.superseded_by,
+            deliveries_url: None,
+        })
+        .collect();
+    Ok(ReportListing {
+        total,
+        limit,
+        offset,
+        next_offset: (offset + fetched < total).then_some(offset + fetched),
+        reports,
+    })
+}
@@ -2,25 +2,151 @@
 //! Advanced reporting system with automated SEBI compliance reports
 
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, post, put},
     Router,
 };
+use chrono::Datelike;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use sqlx::{PgPool, postgres::PgPoolOptions, Row};
+use sqlx::{PgPool, Row};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::net::TcpListener;
-use tokio_cron_scheduler::{JobScheduler, Job};
+use tokio_cron_scheduler::JobScheduler;
 use tracing::{info, error, warn};
 use uuid::Uuid;
+use dharmaguard_common::approval;
+use dharmaguard_common::business_hours;
+use dharmaguard_common::embargo::{self as embargo_guard, Access, Attempt};
+use dharmaguard_common::pool::{self, Admission, PoolSettings};
+use dharmaguard_common::startup::{self, Startup};
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+use dharmaguard_common::versioning;
+
+mod aggregate_cache;
+mod amendments;
+mod auth;
+mod chunked;
+mod compare;
+mod delivery;
+mod drilldown;
+mod download;
+mod embargo;
+mod generators;
+mod jobs;
+mod layouts;
+mod listing;
+mod pdf_signature;
+mod portal;
+mod progress;
+mod render;
+mod report_files;
+mod retention;
+mod rollups;
+mod review;
+mod schedule;
+mod sftp;
+mod sheets;
+mod signing;
+mod takeout;
+mod template_bundles;
+mod tenant_schedules;
+mod webhooks;
+mod xbrl;
+
+use crate::aggregate_cache::AggregateCache;
+use crate::amendments::{AmendError, AmendRequest, ReportVersions};
+use crate::auth::{Authenticator, Caller};
+use crate::chunked::{ChunkSettings, Partial};
+use crate::compare::{CompareError, ReportComparison};
+use crate::delivery::{
+    Deliverable, DeliverReportRequest, DeliveryError, DeliveryPlan, DeliveryRecord, DeliveryResponse, RecipientDelivery,
+    ReportDelivery,
+};
+use crate::drilldown::{Drilldown, DrilldownError};
+use crate::generators::{GenerationContext, ReportTypeDescription};
+use crate::embargo::{EmbargoDetail, EmbargoError, EmbargoSettings, LiftEmbargoRequest, ReportEmbargo, SetEmbargoRequest};
+use crate::jobs::{CancelRequest, Job, JobError, JobSettings, ReportJob, ReportJobs, Stopped};
+use crate::layouts::{CreateLayoutRequest, DefaultLayout, LayoutError, LayoutVersion, PreviewRequest};
+use crate::listing::{ListError, ReportFilter, ReportListing};
+use crate::portal::{
+    AccessLogEntry, AccessToken, IssueTokenRequest, IssuedToken, PortalError, PortalListing, PortalSettings, Requester,
+    RevokeTokenRequest,
+};
+use crate::progress::{Phase, Progress};
+use crate::render::{ReportFormat, ReportMeta};
+use crate::report_files::{FileColumns, FileExpired, ReportFiles};
+use crate::retention::{
+    LegalHold, PlaceHoldRequest, ReleaseHoldRequest, RetentionError, RetentionPolicy, SetPolicyRequest,
+};
+use crate::review::{ReportReview, ReviewError, ReviewHistory, ReviewRequest};
+use crate::rollups::RollupSettings;
+use crate::schedule::ScheduleSettings;
+use crate::sftp::{
+    CreateTargetRequest, SftpDeliveryRequest, SftpError, SftpReceipt, SftpSettings, SftpTarget, UpdateTargetRequest,
+};
+use crate::signing::{CannotSign, SetCertificateRequest, SignatureCheck, SigningCertificate, SigningError};
+use crate::takeout::{CreateExportRequest, TakeoutSettings, TenantExport};
+use crate::template_bundles::{BundleSigner, ImportTemplateRequest, TemplateBundle, TemplateImportResponse};
+use crate::tenant_schedules::{
+    CreateScheduleRequest, ReportSchedule, ScheduleError, SetTimezoneRequest, TenantSchedules, TenantTimezone,
+    UpdateScheduleRequest,
+};
+use crate::webhooks::{
+    CreateWebhookRequest, ReportOutcome, ReportWebhook, ReportWebhooks, UpdateWebhookRequest, WebhookDelivery,
+    WebhookError, WebhookWithSecret,
+};
+use crate::xbrl::Nonconforming;
+
+/// Shared migrations the service depends on, each with a relation it creates
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("003_report_template_bundles", "report_template_imports"),
+    ("015_report_deliveries", "report_deliveries"),
+    ("021_business_hours", "scheduled_report_runs"),
+    ("025_tenant_exports", "tenant_exports"),
+    ("028_report_access_tokens", "report_access_tokens"),
+    ("033_instrument_versions", "instrument_versions"),
+    ("034_report_embargoes", "report_embargoes"),
+    ("052_report_file_lifecycle", "idx_reports_v2_file_expiry"),
+    ("053_report_schedules", "report_schedules"),
+    ("054_report_delivery_retries", "idx_report_deliveries_retry"),
+    ("055_report_sftp_delivery", "report_sftp_deliveries"),
+    ("056_report_webhooks", "report_webhook_deliveries"),
+    ("057_report_layouts", "report_layouts"),
+    ("058_order_client_codes", "idx_orders_tenant_time"),
+    ("059_report_versions", "idx_reports_v2_chain_version"),
+    ("060_tenant_report_timezones", "idx_report_schedules_tenant_zone"),
+    ("061_report_reviews", "report_status_transitions"),
+    ("062_report_signing", "report_signing_certificates"),
+    ("063_report_retention", "report_legal_holds"),
+    ("065_trade_rollups", "trade_rollup_days"),
+    ("066_report_generation_checkpoints", "report_generation_checkpoints"),
+    ("067_report_generation_jobs", "report_generation_jobs"),
+    ("071_trade_charges", "trade_charges"),
+    ("072_peak_margins", "idx_margin_statements_tenant_date"),
+];
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
     pub scheduler: Arc<JobScheduler>,
+    pub bundle_signer: Arc<BundleSigner>,
+    pub delivery: Arc<ReportDelivery>,
+    pub schedule_settings: Arc<ScheduleSettings>,
+    pub portal_settings: Arc<PortalSettings>,
+    pub embargo_settings: Arc<EmbargoSettings>,
+    pub report_files: Arc<ReportFiles>,
+    pub tenant_schedules: Arc<TenantSchedules>,
+    pub sftp_settings: Arc<SftpSettings>,
+    pub webhooks: Arc<ReportWebhooks>,
+    pub generator: Arc<ReportEngine>,
+    pub authenticator: Arc<Authenticator>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -29,20 +155,61 @@ pub struct GenerateReportRequest {
     pub report_type: String,
     pub period_start: chrono::NaiveDate,
     pub period_end: chrono::NaiveDate,
-    pub format: String, // PDF, CSV, JSON, XML
+    pub format: String, // PDF, CSV, XLSX, JSON, XML, XBRL
+    /// An active template of the report type when not given
+    pub template_id: Option<Uuid>,
+    /// The caller whatever the body says; see `auth`
+    pub generated_by: Option<Uuid>,
+    /// Hold the report back until then; see `embargo`
+    pub embargo_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Hold the report back until the tenant's next market close
+    #[serde(default)]
+    pub embargo_until_market_close: bool,
+    pub embargo_reason: Option<String>,
+    /// Sent to these recipients once approved
+    pub deliver_to: Option<DeliveryPlan>,
+    /// Parameters of the report type, as GET /reports/types lists them
+    #[serde(default)]
+    pub parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+impl GenerateReportRequest {
+    fn embargo(&self) -> Option<SetEmbargoRequest> {
+        (self.embargo_until.is_some() || self.embargo_until_market_close).then(|| SetEmbargoRequest {
+            tenant_id: self.tenant_id,
+            embargo_until: self.embargo_until,
+            until_market_close: self.embargo_until_market_close,
+            reason: self.embargo_reason.clone(),
+            set_by: self.generated_by,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ReportResponse {
     pub report_id: Uuid,
+    pub tenant_id: Uuid,
     pub report_type: String,
     pub status: String,
+    pub period_start: chrono::NaiveDate,
+    pub period_end: chrono::NaiveDate,
     pub file_path: Option<String>,
     pub generated_at: Option<chrono::DateTime<chrono::Utc>>,
     pub download_url: Option<String>,
+    /// When an embargoed report becomes available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embargoed_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// Version within the report's amendment chain, from 1
+    pub version: i32,
+    /// The amendment that superseded this version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<Uuid>,
+    /// Where the deliveries of a report sent once approved are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deliveries_url: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct TradingSummaryReport {
     pub total_trades: i64,
     pub total_volume: f64,
@@ -55,7 +222,7 @@ pub struct TradingSummaryReport {
     pub instrument_breakdown: Vec<InstrumentStats>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct InstrumentStats {
     pub instrument: String,
     pub trade_count: i64,
@@ -64,7 +231,7 @@ pub struct InstrumentStats {
     pub avg_price: f64,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct ComplianceReport {
     pub alerts_generated: i64,
     pub critical_alerts: i64,
@@ -76,7 +243,31 @@ pub struct ComplianceReport {
     pub risk_metrics: RiskMetrics,
 }
 
-#[derive(Serialize, Deserialize)]
+/// A slice's alerts, merged with those of other slices into a compliance report
+#[derive(Serialize, Deserialize, Default)]
+pub struct CompliancePartial {
+    total_alerts: i64,
+    critical_alerts: i64,
+    resolved_alerts: i64,
+    pending_investigations: i64,
+    severity_penalty: f64,
+    pattern_breakdown: HashMap<String, i64>,
+}
+
+impl Partial for CompliancePartial {
+    fn merge(&mut self, later: Self) {
+        self.total_alerts += later.total_alerts;
+        self.critical_alerts += later.critical_alerts;
+        self.resolved_alerts += later.resolved_alerts;
+        self.pending_investigations += later.pending_investigations;
+        self.severity_penalty += later.severity_penalty;
+        for (alert_type, count) in later.pattern_breakdown {
+            *self.pattern_breakdown.entry(alert_type).or_default() += count;
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
 pub struct RiskMetrics {
     pub var_95: f64,
     pub var_99: f64,
@@ -85,122 +276,551 @@ pub struct RiskMetrics {
     pub volatility: f64,
 }
 
-pub struct ReportGenerator {
+/// Client positions at the end of the period, with the period's activity, margin and limits
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClientExposureReport {
+    pub clients_with_positions: i64,
+    pub total_gross_exposure: f64,
+    pub total_net_exposure: f64,
+    pub total_margin_required: f64,
+    pub clients_over_limit: i64,
+    /// Clients by gross exposure, largest first
+    pub clients: Vec<ClientExposure>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClientExposure {
+    pub client_code: String,
+    pub client_name: Option<String>,
+    pub gross_exposure: f64,
+    pub net_exposure: f64,
+    pub long_exposure: f64,
+    pub short_exposure: f64,
+    pub bought_value: f64,
+    pub sold_value: f64,
+    pub margin_required: Option<f64>,
+    pub collateral_available: Option<f64>,
+    /// Margin required over collateral available
+    pub margin_utilization: Option<f64>,
+    pub largest_instrument: Option<String>,
+    /// The largest instrument's share of the gross exposure
+    pub concentration: f64,
+    pub exposure_limit: Option<f64>,
+    pub exposure_limit_utilization: Option<f64>,
+    /// Limits the client's positions exceed, its exposure limit included
+    pub limit_breaches: i64,
+    /// Drill-down: the client's instruments by exposure, largest first
+    pub positions: Vec<PositionExposure>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct PositionExposure {
+    pub instrument: String,
+    pub net_quantity: i64,
+    pub mark_price: f64,
+    pub market_value: f64,
+    /// The instrument's share of the client's gross exposure
+    pub share_of_exposure: f64,
+    pub bought_value: f64,
+    pub sold_value: f64,
+    pub position_limit: Option<f64>,
+    pub exposure_limit: Option<f64>,
+    /// The fuller of the position's limits, 1.0 being at the limit
+    pub limit_utilization: Option<f64>,
+}
+
+/// Orders per trade of each client and instrument, by day or by month
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct OrderTradeRatioReport {
+    /// DAILY, or MONTHLY for a period of whole calendar months
+    pub granularity: String,
+    /// Ratios at or above a threshold are flagged with the highest threshold they reach
+    pub thresholds: Vec<f64>,
+    pub total_orders: i64,
+    pub total_trades: i64,
+    pub overall_ratio: f64,
+    pub flagged_ratios: i64,
+    pub clients_flagged: i64,
+    /// Clients over the whole period, highest ratio first
+    pub clients: Vec<ClientOrderTradeRatio>,
+    /// A row per day or month, client and instrument, in date order and highest ratio first within a date
+    pub ratios: Vec<OrderTradeRatio>,
+}
+
+/// A slice's orders and trades, merged with those of other slices into an order-to-trade ratio report
+#[derive(Serialize, Deserialize, Default)]
+pub struct OrderTradeCounts {
+    counts: Vec<OrderTradeCount>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OrderTradeCount {
+    bucket: chrono::NaiveDate,
+    client: String,
+    instrument_id: Uuid,
+    instrument: String,
+    orders: i64,
+    trades: i64,
+}
+
+impl Partial for OrderTradeCounts {
+    /// A month split between slices is counted once, from both
+    fn merge(&mut self, later: Self) {
+        let mut index: HashMap<(chrono::NaiveDate, String, Uuid), usize> = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(position, count)| ((count.bucket, count.client.clone(), count.instrument_id), position))
+            .collect();
+        for count in later.counts {
+            match index.get(&(count.bucket, count.client.clone(), count.instrument_id)) {
+                Some(&position) => {
+                    self.counts[position].orders += count.orders;
+                    self.counts[position].trades += count.trades;
+                }
+                None => {
+                    index.insert((count.bucket, count.client.clone(), count.instrument_id), self.counts.len());
+                    self.counts.push(count);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClientOrderTradeRatio {
+    pub client: String,
+    pub orders: i64,
+    pub trades: i64,
+    pub ratio: f64,
+    pub highest_ratio: f64,
+    pub flagged_ratios: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct OrderTradeRatio {
+    /// The day, or the first day of the month
+    pub date: chrono::NaiveDate,
+    pub client: String,
+    pub instrument: String,
+    pub orders: i64,
+    pub trades: i64,
+    pub ratio: f64,
+    pub threshold_exceeded: Option<f64>,
+}
+
+/// Order-to-trade ratios flagged by default, after the bands SEBI's penalties for algorithmic orders start at
+const DEFAULT_OTR_THRESHOLDS: [f64; 3] = [50.0, 250.0, 500.0];
+
+/// Brokerage, statutory and exchange charges of the period's trades, per client and segment
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct BrokerageSummaryReport {
+    #[serde(flatten)]
+    pub totals: Charges,
+    /// Trades without a charge breakdown, whose taxes are unallocated
+    pub trades_without_breakdown: i64,
+    /// Clients over every segment, most charged first
+    pub clients: Vec<ClientCharges>,
+    /// A row per client and segment, by client and segment
+    pub rows: Vec<SegmentCharges>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Default, Clone)]
+pub struct Charges {
+    pub trades: i64,
+    pub turnover: f64,
+    pub brokerage: f64,
+    pub stt: f64,
+    pub exchange_charges: f64,
+    pub sebi_fees: f64,
+    pub stamp_duty: f64,
+    pub gst: f64,
+    /// trades.taxes of the trades without a charge breakdown
+    pub unallocated_taxes: f64,
+    pub total_charges: f64,
+}
+
+impl Charges {
+    fn add(&mut self, other: &Charges) {
+        self.trades += other.trades;
+        self.turnover += other.turnover;
+        self.brokerage += other.brokerage;
+        self.stt += other.stt;
+        self.exchange_charges += other.exchange_charges;
+        self.sebi_fees += other.sebi_fees;
+        self.stamp_duty += other.stamp_duty;
+        self.gst += other.gst;
+        self.unallocated_taxes += other.unallocated_taxes;
+        self.total_charges += other.total_charges;
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClientCharges {
+    pub client: String,
+    #[serde(flatten)]
+    pub charges: Charges,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SegmentCharges {
+    pub client: String,
+    pub segment: String,
+    #[serde(flatten)]
+    pub charges: Charges,
+    pub trades_without_breakdown: i64,
+}
+
+/// A slice's charges, merged with those of other slices into a brokerage summary
+#[derive(Serialize, Deserialize, Default)]
+pub struct ChargeTotals {
+    rows: Vec<SegmentCharges>,
+}
+
+impl Partial for ChargeTotals {
+    fn merge(&mut self, later: Self) {
+        let mut index: HashMap<(String, String), usize> = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(position, row)| ((row.client.clone(), row.segment.clone()), position))
+            .collect();
+        for row in later.rows {
+            match index.get(&(row.client.clone(), row.segment.clone())) {
+                Some(&position) => {
+                    self.rows[position].charges.add(&row.charges);
+                    self.rows[position].trades_without_breakdown += row.trades_without_breakdown;
+                }
+                None => {
+                    index.insert((row.client.clone(), row.segment.clone()), self.rows.len());
+                    self.rows.push(row);
+                }
+            }
+        }
+    }
+}
+
+/// Peak margin utilization, shortfalls and the penalties they attract, per client and day
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MarginReport {
+    /// Business days with margin statements in the period
+    pub business_days: i64,
+    pub clients_with_statements: i64,
+    pub highest_utilization: Option<f64>,
+    pub shortfall_incidents: i64,
+    pub clients_with_shortfall: i64,
+    pub total_shortfall: f64,
+    pub total_penalty: f64,
+    /// Statements reported on the end-of-day margin, their files carrying no peak margin
+    pub statements_without_peak: i64,
+    /// Clients over the period, largest penalty first
+    pub clients: Vec<ClientMargin>,
+    /// A row per day and client, in date order
+    pub days: Vec<DailyMargin>,
+    /// Every shortfall, in date order and largest first within a date
+    pub violations: Vec<MarginViolation>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct ClientMargin {
+    pub client: String,
+    pub client_name: Option<String>,
+    pub business_days: i64,
+    pub highest_peak_margin: f64,
+    /// Peak margin over collateral available, at its highest over the period; none on days without collateral
+    pub highest_utilization: Option<f64>,
+    pub average_utilization: Option<f64>,
+    pub shortfall_incidents: i64,
+    pub total_shortfall: f64,
+    pub largest_shortfall: f64,
+    pub penalty: f64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct DailyMargin {
+    pub date: chrono::NaiveDate,
+    pub client: String,
+    /// Over the client's exchanges
+    pub peak_margin: f64,
+    pub collateral_available: f64,
+    pub utilization: Option<f64>,
+    pub shortfall: f64,
+    pub penalty: f64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct MarginViolation {
+    pub date: chrono::NaiveDate,
+    pub client: String,
+    pub exchange: String,
+    pub peak_margin: f64,
+    pub collateral_available: f64,
+    pub shortfall: f64,
+    /// Shortfall over the peak margin
+    pub shortfall_share: f64,
+    /// Trading days in a row, this one included, the client has fallen short on the exchange
+    pub consecutive_days: i64,
+    /// Days of the calendar month so far, this one included, the client has fallen short on the exchange
+    pub days_in_month: i64,
+    pub penalty_rate: f64,
+    pub penalty: f64,
+}
+
+/// Short collection of the peak margin is penalized at 0.5% of the shortfall
+/// while it is under ₹1 lakh and under 10% of the margin and at 1% otherwise,
+/// and at 5% once it has lasted more than 3 days in a row or more than 5 days
+/// in the month, as the exchanges' peak margin circulars set out
+fn margin_penalty_rate(shortfall: f64, peak_margin: f64, consecutive_days: i64, days_in_month: i64) -> f64 {
+    if consecutive_days > 3 || days_in_month > 5 {
+        0.05
+    } else if shortfall < 100_000.0 && shortfall < 0.1 * peak_margin {
+        0.005
+    } else {
+        0.01
+    }
+}
+
+/// How long the period's alerts took to be acknowledged and resolved, per alert type and severity, against SLAs
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AlertAgeingReport {
+    /// BUSINESS when ages count the tenant's market sessions only, WALL for elapsed time
+    pub clock: String,
+    /// Hours allowed per severity; severities not listed have no SLA
+    pub acknowledge_sla_hours: HashMap<String, f64>,
+    pub resolution_sla_hours: HashMap<String, f64>,
+    pub total_alerts: i64,
+    pub acknowledged_alerts: i64,
+    pub resolved_alerts: i64,
+    pub acknowledge_breaches: i64,
+    pub resolution_breaches: i64,
+    /// Share of the alerts under an SLA that met it
+    pub acknowledged_within_sla: Option<f64>,
+    pub resolved_within_sla: Option<f64>,
+    /// A row per alert type and severity, by alert type and most severe first
+    pub groups: Vec<AlertAgeing>,
+    /// Alerts in breach of either SLA, longest overdue first
+    pub breaches: Vec<SlaBreach>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AlertAgeing {
+    pub alert_type: String,
+    pub severity: String,
+    pub alerts: i64,
+    pub acknowledge_sla_hours: Option<f64>,
+    pub resolution_sla_hours: Option<f64>,
+    pub time_to_acknowledge: AgeDistribution,
+    pub time_to_resolution: AgeDistribution,
+}
+
+/// Hours from an alert being raised, over the alerts acknowledged or resolved
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AgeDistribution {
+    pub measured: i64,
+    /// Alerts not acknowledged or resolved yet
+    pub pending: i64,
+    pub mean_hours: Option<f64>,
+    pub median_hours: Option<f64>,
+    pub p90_hours: Option<f64>,
+    pub max_hours: Option<f64>,
+    /// Measured over the SLA, and pending ones already older than it
+    pub breaches: i64,
+    /// Measured alerts by age, the last bucket having no upper bound
+    pub buckets: Vec<AgeBucket>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AgeBucket {
+    pub up_to_hours: Option<f64>,
+    pub alerts: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct SlaBreach {
+    pub alert_id: Uuid,
+    pub alert_type: String,
+    pub severity: String,
+    pub raised_at: chrono::DateTime<chrono::Utc>,
+    /// Hours to acknowledge, or the age of an alert not acknowledged yet
+    pub acknowledge_hours: f64,
+    pub acknowledge_breached: bool,
+    /// Hours to resolve, or the age of an alert not resolved yet
+    pub resolution_hours: f64,
+    pub resolution_breached: bool,
+    pub resolved: bool,
+    /// Hours past the SLA it is furthest over
+    pub overdue_hours: f64,
+}
+
+/// Upper bounds of the alert age buckets, in hours
+const AGE_BUCKET_HOURS: [f64; 5] = [1.0, 4.0, 8.0, 24.0, 72.0];
+
+/// Severities from the most severe, as alert ageing lists them and takes SLAs for
+const SEVERITIES: [&str; 4] = ["CRITICAL", "HIGH", "MEDIUM", "LOW"];
+
+impl AgeDistribution {
+    fn of(mut hours: Vec<f64>, pending: i64, breaches: i64) -> Self {
+        hours.sort_by(f64::total_cmp);
+        // Nearest rank
+        let percentile = |share: f64| {
+            (!hours.is_empty()).then(|| hours[((share * hours.len() as f64).ceil() as usize).clamp(1, hours.len()) - 1])
+        };
+        let mut buckets: Vec<AgeBucket> = AGE_BUCKET_HOURS
+            .iter()
+            .map(|up_to| AgeBucket {
+                up_to_hours: Some(*up_to),
+                alerts: 0,
+            })
+            .collect();
+        buckets.push(AgeBucket {
+            up_to_hours: None,
+            alerts: 0,
+        });
+        for age in &hours {
+            let bucket = AGE_BUCKET_HOURS.iter().position(|up_to| age <= up_to).unwrap_or(AGE_BUCKET_HOURS.len());
+            buckets[bucket].alerts += 1;
+        }
+        Self {
+            measured: hours.len() as i64,
+            pending,
+            mean_hours: (!hours.is_empty()).then(|| hours.iter().sum::<f64>() / hours.len() as f64),
+            median_hours: percentile(0.5),
+            p90_hours: percentile(0.9),
+            max_hours: hours.last().copied(),
+            breaches,
+            buckets,
+        }
+    }
+}
+
+pub struct ReportEngine {
     db: PgPool,
+    cache: Arc<AggregateCache>,
+    chunks: ChunkSettings,
+    jobs: ReportJobs,
 }
 
-impl ReportGenerator {
-    pub fn new(db: PgPool) -> Self {
-        Self { db }
+impl ReportEngine {
+    pub fn new(db: PgPool, cache: Arc<AggregateCache>, chunks: ChunkSettings, jobs: JobSettings) -> Self {
+        let jobs = ReportJobs::new(db.clone(), jobs);
+        Self { db, cache, chunks, jobs }
+    }
+
+    pub fn jobs(&self) -> &ReportJobs {
+        &self.jobs
+    }
+
+    /// The report's data from the generator of its type, generated as a job
+    /// that can be cancelled and times out; see `generators` and `jobs`
+    pub async fn generate(&self, job: &Job<'_>) -> anyhow::Result<serde_json::Value> {
+        let Some(generator) = generators::get(job.report_type) else {
+            anyhow::bail!("{} reports cannot be generated here", job.report_type);
+        };
+        let progress = self.jobs.progress(job.report_id);
+        let context = GenerationContext {
+            engine: self,
+            tenant_id: job.tenant_id,
+            period_start: job.period_start,
+            period_end: job.period_end,
+            parameters: job.parameters,
+            progress: &progress,
+        };
+        self.jobs.run(job, generator.generate(&context)).await
     }
 
+    /// Served from the aggregate cache while no trades of the period land
     pub async fn generate_trading_summary(
         &self,
         tenant_id: Uuid,
         start_date: chrono::NaiveDate,
         end_date: chrono::NaiveDate,
+        progress: &Progress,
     ) -> Result<TradingSummaryReport, sqlx::Error> {
-        // Basic trading statistics
-        let basic_stats = sqlx::query!(
-            r#"
-            SELECT 
-                COUNT(*) as total_trades,
-                COALESCE(SUM(quantity), 0) as total_volume,
-                COALESCE(SUM(value), 0) as total_value,
-                COUNT(DISTINCT instrument_id) as unique_instruments,
-                COUNT(DISTINCT account_id) as active_clients,
-                COALESCE(AVG(value), 0) as average_trade_size,
-                COALESCE(MAX(value), 0) as largest_trade
-            FROM trades 
-            WHERE tenant_id = $1 
-            AND DATE(trade_time) BETWEEN $2 AND $3
-            "#,
+        self.cache
+            .get_or_compute(tenant_id, "TRADING_SUMMARY", start_date, end_date, || {
+                self.compute_trading_summary(tenant_id, start_date, end_date, progress)
+            })
+            .await
+    }
+
+    async fn compute_trading_summary(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        progress: &Progress,
+    ) -> Result<TradingSummaryReport, sqlx::Error> {
+        // Past days come from the nightly rollups, the current day from trades
+        let partial = chunked::generate(
+            &self.db,
+            &self.chunks,
+            progress,
             tenant_id,
+            "TRADING_SUMMARY",
             start_date,
-            end_date
+            end_date,
+            |start, end| rollups::trading_partial(&self.db, tenant_id, start, end),
         )
-        .fetch_one(&self.db)
         .await?;
+        Ok(partial.into_report())
+    }
 
-        // Trading hours distribution
-        let hours_distribution = sqlx::query!(
-            r#"
-            SELECT 
-                EXTRACT(HOUR FROM trade_time) as hour,
-                COUNT(*) as trade_count
-            FROM trades 
-            WHERE tenant_id = $1 
-            AND DATE(trade_time) BETWEEN $2 AND $3
-            GROUP BY EXTRACT(HOUR FROM trade_time)
-            ORDER BY hour
-            "#,
+    pub async fn generate_compliance_report(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        progress: &Progress,
+    ) -> Result<ComplianceReport, sqlx::Error> {
+        let alerts = chunked::generate(
+            &self.db,
+            &self.chunks,
+            progress,
             tenant_id,
+            "COMPLIANCE_REPORT",
             start_date,
-            end_date
+            end_date,
+            |start, end| self.compliance_partial(tenant_id, start, end),
         )
-        .fetch_all(&self.db)
         .await?;
 
-        let mut trading_hours_distribution = HashMap::new();
-        for row in hours_distribution {
-            let hour = row.hour.unwrap_or(0.0) as i32;
-            trading_hours_distribution.insert(format!("{}:00", hour), row.trade_count.unwrap_or(0));
-        }
+        // Calculate compliance score (simplified)
+        let total_alerts = alerts.total_alerts as f64;
+        let resolved_alerts = alerts.resolved_alerts as f64;
 
-        // Instrument breakdown
-        let instrument_stats = sqlx::query!(
-            r#"
-            SELECT 
-                i.symbol as instrument,
-                COUNT(*) as trade_count,
-                COALESCE(SUM(t.quantity), 0) as total_volume,
-                COALESCE(SUM(t.value), 0) as total_value,
-                COALESCE(AVG(t.price), 0) as avg_price
-            FROM trades t
-            JOIN instruments i ON t.instrument_id = i.instrument_id
-            WHERE t.tenant_id = $1 
-            AND DATE(t.trade_time) BETWEEN $2 AND $3
-            GROUP BY i.symbol
-            ORDER BY total_value DESC
-            LIMIT 20
-            "#,
-            tenant_id,
-            start_date,
-            end_date
-        )
-        .fetch_all(&self.db)
-        .await?;
+        let compliance_score = if total_alerts > 0.0 {
+            100.0 - (alerts.severity_penalty + (total_alerts - resolved_alerts) * 2.0)
+        } else {
+            100.0
+        }.max(0.0);
 
-        let instrument_breakdown: Vec<InstrumentStats> = instrument_stats
-            .into_iter()
-            .map(|row| InstrumentStats {
-                instrument: row.instrument.unwrap_or_default(),
-                trade_count: row.trade_count.unwrap_or(0),
-                total_volume: row.total_volume.unwrap_or(0.0) as f64,
-                total_value: row.total_value.unwrap_or(0.0) as f64,
-                avg_price: row.avg_price.unwrap_or(0.0) as f64,
-            })
-            .collect();
+        // Mock risk metrics (in production, these would be calculated from actual trade data)
+        let risk_metrics = RiskMetrics {
+            var_95: 0.05,
+            var_99: 0.08,
+            max_drawdown: 0.12,
+            sharpe_ratio: 1.45,
+            volatility: 0.18,
+        };
 
-        Ok(TradingSummaryReport {
-            total_trades: basic_stats.total_trades.unwrap_or(0),
-            total_volume: basic_stats.total_volume.unwrap_or(0.0) as f64,
-            total_value: basic_stats.total_value.unwrap_or(0.0) as f64,
-            unique_instruments: basic_stats.unique_instruments.unwrap_or(0),
-            active_clients: basic_stats.active_clients.unwrap_or(0),
-            average_trade_size: basic_stats.average_trade_size.unwrap_or(0.0) as f64,
-            largest_trade: basic_stats.largest_trade.unwrap_or(0.0) as f64,
-            trading_hours_distribution,
-            instrument_breakdown,
+        Ok(ComplianceReport {
+            alerts_generated: alerts.total_alerts,
+            critical_alerts: alerts.critical_alerts,
+            resolved_alerts: alerts.resolved_alerts,
+            pending_investigations: alerts.pending_investigations,
+            compliance_score,
+            violations_detected: alerts.critical_alerts,
+            pattern_breakdown: alerts.pattern_breakdown,
+            risk_metrics,
         })
     }
 
-    pub async fn generate_compliance_report(
+    async fn compliance_partial(
         &self,
         tenant_id: Uuid,
         start_date: chrono::NaiveDate,
         end_date: chrono::NaiveDate,
-    ) -> Result<ComplianceReport, sqlx::Error> {
+    ) -> Result<CompliancePartial, sqlx::Error> {
         // Alert statistics
         let alert_stats = sqlx::query!(
             r#"
@@ -243,84 +863,1134 @@ impl ReportGenerator {
             pattern_breakdown.insert(row.alert_type, row.count.unwrap_or(0));
         }
 
-        // Calculate compliance score (simplified)
-        let total_alerts = alert_stats.total_alerts.unwrap_or(0) as f64;
-        let critical_alerts = alert_stats.critical_alerts.unwrap_or(0) as f64;
-        let resolved_alerts = alert_stats.resolved_alerts.unwrap_or(0) as f64;
-        
-        let compliance_score = if total_alerts > 0.0 {
-            100.0 - (critical_alerts * 10.0 + (total_alerts - resolved_alerts) * 2.0)
-        } else {
-            100.0
-        }.max(0.0);
-
-        // Mock risk metrics (in production, these would be calculated from actual trade data)
-        let risk_metrics = RiskMetrics {
-            var_95: 0.05,
-            var_99: 0.08,
-            max_drawdown: 0.12,
-            sharpe_ratio: 1.45,
-            volatility: 0.18,
-        };
+        // Severity penalty per alert: the tenant level's score weight when the alert
+        // carries a tenant severity, otherwise the platform default (10 per critical)
+        let severity_penalty = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(SUM(COALESCE(
+                tsl.score_weight::float8,
+                CASE WHEN a.severity = 'CRITICAL' THEN 10.0 ELSE 0.0 END
+            )), 0) as "severity_penalty!"
+            FROM surveillance_alerts a
+            LEFT JOIN tenant_severity_levels tsl
+                ON tsl.tenant_id = a.tenant_id AND tsl.code = a.tenant_severity
+            WHERE a.tenant_id = $1
+            AND DATE(a.created_at) BETWEEN $2 AND $3
+            "#,
+            tenant_id,
+            start_date,
+            end_date
+        )
+        .fetch_one(&self.db)
+        .await?;
 
-        Ok(ComplianceReport {
-            alerts_generated: alert_stats.total_alerts.unwrap_or(0),
+        Ok(CompliancePartial {
+            total_alerts: alert_stats.total_alerts.unwrap_or(0),
             critical_alerts: alert_stats.critical_alerts.unwrap_or(0),
             resolved_alerts: alert_stats.resolved_alerts.unwrap_or(0),
             pending_investigations: alert_stats.pending_investigations.unwrap_or(0),
-            compliance_score,
-            violations_detected: critical_alerts as i64,
+            severity_penalty,
             pattern_breakdown,
-            risk_metrics,
         })
     }
-}
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-
-    let database_url = std::env::var("DATABASE_URL")
-        .expect("DATABASE_URL must be set");
+    /// Client exposure at the end of the period
+    ///
+    /// Positions are rebuilt from the client's trades up to the end of the period
+    /// (BUY and COVER adding, SELL and SHORT_SELL taking away) and marked at the
+    /// instrument's last traded price by then, so past periods report what was
+    /// held at the time. Margin is the latest statement in the period of each
+    /// account the client traded through; an account shared between clients
+    /// counts toward each. Of the active limits on the client, POSITION_LIMIT
+    /// caps the quantity held of its instrument, or of every instrument when it
+    /// names none, and EXPOSURE_LIMIT caps the market value of its instrument,
+    /// or the client's gross exposure when it names none. It is computed in one
+    /// go rather than in slices (see `chunked`), positions being built from every
+    /// trade up to the end of the period.
+    pub async fn generate_client_exposure(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        progress: &Progress,
+    ) -> Result<ClientExposureReport, sqlx::Error> {
+        // Period-end positions and period activity per client and instrument
+        let holdings = sqlx::query!(
+            r#"
+            WITH holdings AS (
+                SELECT
+                    client_code,
+                    instrument_id,
+                    SUM(CASE WHEN trade_type IN ('BUY', 'COVER') THEN quantity ELSE -quantity END) as net_quantity,
+                    SUM(CASE WHEN trade_type IN ('BUY', 'COVER') AND DATE(trade_time) >= $2 THEN value ELSE 0 END)
+                        as bought_value,
+                    SUM(CASE WHEN trade_type IN ('SELL', 'SHORT_SELL') AND DATE(trade_time) >= $2 THEN value ELSE 0 END)
+                        as sold_value
+                FROM trades
+                WHERE tenant_id = $1
+                AND client_code IS NOT NULL
+                AND DATE(trade_time) <= $3
+                GROUP BY client_code, instrument_id
+            )
+            SELECT
+                h.client_code as "client_code!",
+                c.name as "client_name?",
+                h.instrument_id,
+                COALESCE(v.symbol, i.symbol) as "instrument!",
+                h.net_quantity::bigint as "net_quantity!",
+                mark.price::float8 as "mark_price!",
+                h.bought_value::float8 as "bought_value!",
+                h.sold_value::float8 as "sold_value!"
+            FROM holdings h
+            JOIN instruments i ON i.instrument_id = h.instrument_id
+            LEFT JOIN clients c ON c.tenant_id = $1 AND c.client_code = h.client_code
+            LEFT JOIN LATERAL instrument_as_of(h.instrument_id, $3) v ON TRUE
+            JOIN LATERAL (
+                SELECT t.price
+                FROM trades t
+                WHERE t.instrument_id = h.instrument_id
+                AND t.tenant_id = $1
+                AND t.trade_time < $3::date + 1
+                ORDER BY t.trade_time DESC
+                LIMIT 1
+            ) mark ON TRUE
+            WHERE h.net_quantity <> 0 OR h.bought_value > 0 OR h.sold_value > 0
+            "#,
+            tenant_id,
+            start_date,
+            end_date
+        )
+        .fetch_all(&self.db)
+        .await?;
+        progress.queried(1, 3).await;
 
-    let pool = PgPoolOptions::new()
-        .max_connections(20)
-        .connect(&database_url)
+        // Latest margin statement in the period of each account a client traded through
+        let margins = sqlx::query!(
+            r#"
+            WITH client_accounts AS (
+                SELECT DISTINCT client_code, account_id
+                FROM trades
+                WHERE tenant_id = $1
+                AND client_code IS NOT NULL
+                AND DATE(trade_time) <= $3
+            ),
+            latest AS (
+                SELECT DISTINCT ON (account_id, exchange) account_id, total_margin, collateral_available
+                FROM margin_statements
+                WHERE tenant_id = $1
+                AND business_date BETWEEN $2 AND $3
+                ORDER BY account_id, exchange, business_date DESC
+            )
+            SELECT
+                ca.client_code as "client_code!",
+                SUM(l.total_margin)::float8 as "margin_required!",
+                SUM(l.collateral_available)::float8 as "collateral_available!"
+            FROM client_accounts ca
+            JOIN latest l ON l.account_id = ca.account_id
+            GROUP BY ca.client_code
+            "#,
+            tenant_id,
+            start_date,
+            end_date
+        )
+        .fetch_all(&self.db)
         .await?;
+        progress.queried(2, 3).await;
 
-    // Initialize job scheduler for automated reports
-    let scheduler = JobScheduler::new().await?;
-    
-    // Schedule daily reports at 6 AM
-    let daily_report_job = Job::new_async("0 0 6 * * *", |_uuid, _l| {
-        Box::pin(async move {
-            info!("Generating scheduled daily reports");
-            // Implementation for scheduled report generation
-        })
-    })?;
-    
-    scheduler.add(daily_report_job).await?;
-    scheduler.start().await?;
+        let limits = sqlx::query!(
+            r#"
+            SELECT c.client_code, pl.instrument_id, pl.limit_type, pl.limit_value::float8 as "limit_value!"
+            FROM position_limits pl
+            JOIN clients c ON c.client_id = pl.client_id
+            WHERE pl.tenant_id = $1
+            AND pl.is_active
+            AND pl.limit_type IN ('POSITION_LIMIT', 'EXPOSURE_LIMIT')
+            AND pl.limit_value > 0
+            "#,
+            tenant_id
+        )
+        .fetch_all(&self.db)
+        .await?;
+        progress.phase(Phase::Aggregating).await;
 
-    let app_state = AppState {
-        db: pool,
-        scheduler: Arc::new(scheduler),
-    };
+        // The tightest limit of each kind: per client and instrument, or per client for every instrument
+        let mut instrument_limits: HashMap<(String, Option<Uuid>, String), f64> = HashMap::new();
+        for row in limits {
+            let key = (row.client_code, row.instrument_id, row.limit_type);
+            let limit = instrument_limits.entry(key).or_insert(f64::MAX);
+            *limit = limit.min(row.limit_value);
+        }
+        let limit_of = |client_code: &str, instrument_id: Option<Uuid>, limit_type: &str| {
+            instrument_limits.get(&(client_code.to_string(), instrument_id, limit_type.to_string())).copied()
+        };
 
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/reports", post(generate_report).get(list_reports))
-        .route("/reports/:id", get(get_report))
-        .route("/reports/:id/download", get(download_report))
-        .route("/reports/scheduled", get(list_scheduled_reports))
-        .with_state(app_state);
+        let mut clients: Vec<ClientExposure> = Vec::new();
+        let mut client_index: HashMap<String, usize> = HashMap::new();
+        for row in holdings {
+            let index = *client_index.entry(row.client_code.clone()).or_insert_with(|| {
+                clients.push(ClientExposure {
+                    client_code: row.client_code.clone(),
+                    client_name: row.client_name.clone(),
+                    gross_exposure: 0.0,
+                    net_exposure: 0.0,
+                    long_exposure: 0.0,
+                    short_exposure: 0.0,
+                    bought_value: 0.0,
+                    sold_value: 0.0,
+                    margin_required: None,
+                    collateral_available: None,
+                    margin_utilization: None,
+                    largest_instrument: None,
+                    concentration: 0.0,
+                    exposure_limit: None,
+                    exposure_limit_utilization: None,
+                    limit_breaches: 0,
+                    positions: Vec::new(),
+                });
+                clients.len() - 1
+            });
+            let client = &mut clients[index];
+            let market_value = row.net_quantity as f64 * row.mark_price;
+            let position_limit = limit_of(&row.client_code, Some(row.instrument_id), "POSITION_LIMIT")
+                .or_else(|| limit_of(&row.client_code, None, "POSITION_LIMIT"));
+            let exposure_limit = limit_of(&row.client_code, Some(row.instrument_id), "EXPOSURE_LIMIT");
+            let limit_utilization = [
+                position_limit.map(|limit| row.net_quantity.unsigned_abs() as f64 / limit),
+                exposure_limit.map(|limit| market_value.abs() / limit),
+            ]
+            .into_iter()
+            .flatten()
+            .reduce(f64::max);
 
-    let listener = TcpListener::bind("0.0.0.0:8083").await?;
-    info!("Reporting service listening on port 8083");
-    
-    axum::serve(listener, app).await?;
-    Ok(())
-}
+            if market_value >= 0.0 {
+                client.long_exposure += market_value;
+            } else {
+                client.short_exposure += -market_value;
+            }
+            client.net_exposure += market_value;
+            client.gross_exposure += market_value.abs();
+            client.bought_value += row.bought_value;
+            client.sold_value += row.sold_value;
+            if limit_utilization.is_some_and(|utilization| utilization > 1.0) {
+                client.limit_breaches += 1;
+            }
+            client.positions.push(PositionExposure {
+                instrument: row.instrument,
+                net_quantity: row.net_quantity,
+                mark_price: row.mark_price,
+                market_value,
+                share_of_exposure: 0.0,
+                bought_value: row.bought_value,
+                sold_value: row.sold_value,
+                position_limit,
+                exposure_limit,
+                limit_utilization,
+            });
+        }
+
+        let margins: HashMap<String, (f64, f64)> = margins
+            .into_iter()
+            .map(|row| (row.client_code, (row.margin_required, row.collateral_available)))
+            .collect();
+        for client in &mut clients {
+            client.positions.sort_by(|a, b| {
+                b.market_value.abs().total_cmp(&a.market_value.abs()).then_with(|| a.instrument.cmp(&b.instrument))
+            });
+            if client.gross_exposure > 0.0 {
+                for position in &mut client.positions {
+                    position.share_of_exposure = position.market_value.abs() / client.gross_exposure;
+                }
+                if let Some(largest) = client.positions.first() {
+                    client.largest_instrument = Some(largest.instrument.clone());
+                    client.concentration = largest.share_of_exposure;
+                }
+            }
+            if let Some(&(required, collateral)) = margins.get(&client.client_code) {
+                client.margin_required = Some(required);
+                client.collateral_available = Some(collateral);
+                client.margin_utilization = (collateral > 0.0).then(|| required / collateral);
+            }
+            client.exposure_limit = limit_of(&client.client_code, None, "EXPOSURE_LIMIT");
+            client.exposure_limit_utilization = client.exposure_limit.map(|limit| client.gross_exposure / limit);
+            if client.exposure_limit_utilization.is_some_and(|utilization| utilization > 1.0) {
+                client.limit_breaches += 1;
+            }
+        }
+        clients.sort_by(|a, b| {
+            b.gross_exposure.total_cmp(&a.gross_exposure).then_with(|| a.client_code.cmp(&b.client_code))
+        });
+
+        Ok(ClientExposureReport {
+            clients_with_positions: clients.iter().filter(|client| client.gross_exposure > 0.0).count() as i64,
+            total_gross_exposure: clients.iter().map(|client| client.gross_exposure).sum(),
+            total_net_exposure: clients.iter().map(|client| client.net_exposure).sum(),
+            total_margin_required: clients.iter().filter_map(|client| client.margin_required).sum(),
+            clients_over_limit: clients.iter().filter(|client| client.limit_breaches > 0).count() as i64,
+            clients,
+        })
+    }
+
+    /// Orders per trade for the period
+    ///
+    /// A client's orders and trades are counted per instrument and per day, or
+    /// per calendar month when the period is made of whole months. Orders and
+    /// trades without a client code count under their trading account's number.
+    /// With no trade, the orders are counted against one, so an order-only day
+    /// still shows its orders as its ratio. The thresholds are the tenant's
+    /// `order_trade_ratio_thresholds` configuration, a list of ratios, or else
+    /// DEFAULT_OTR_THRESHOLDS.
+    pub async fn generate_order_trade_ratio(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        monthly: Option<bool>,
+        progress: &Progress,
+    ) -> Result<OrderTradeRatioReport, sqlx::Error> {
+        let monthly =
+            monthly.unwrap_or_else(|| start_date.day() == 1 && (end_date + chrono::Duration::days(1)).day() == 1);
+        let configured = sqlx::query_scalar!(
+            "SELECT config_value FROM tenant_configurations WHERE tenant_id = $1 AND config_key = $2",
+            tenant_id,
+            "order_trade_ratio_thresholds"
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        let usable =
+            |thresholds: &[f64]| !thresholds.is_empty() && thresholds.iter().all(|t| t.is_finite() && *t > 0.0);
+        let thresholds = match configured.map(serde_json::from_value::<Vec<f64>>) {
+            Some(Ok(mut thresholds)) if usable(&thresholds) => {
+                thresholds.sort_by(f64::total_cmp);
+                thresholds.dedup();
+                thresholds
+            }
+            None => DEFAULT_OTR_THRESHOLDS.to_vec(),
+            Some(_) => {
+                warn!(
+                    "Tenant {} has an unusable order_trade_ratio_thresholds configuration; using the defaults",
+                    tenant_id
+                );
+                DEFAULT_OTR_THRESHOLDS.to_vec()
+            }
+        };
+
+        let counts = chunked::generate(
+            &self.db,
+            &self.chunks,
+            progress,
+            tenant_id,
+            // Checkpoints of one granularity are of no use to the other
+            if monthly { "ORDER_TRADE_RATIO_MONTHLY" } else { "ORDER_TRADE_RATIO" },
+            start_date,
+            end_date,
+            |start, end| self.order_trade_counts(tenant_id, start, end, monthly),
+        )
+        .await?;
+
+        let ratio = |orders: i64, trades: i64| orders as f64 / trades.max(1) as f64;
+        let mut ratios: Vec<OrderTradeRatio> = counts
+            .counts
+            .into_iter()
+            .map(|row| {
+                let ratio = ratio(row.orders, row.trades);
+                OrderTradeRatio {
+                    date: row.bucket,
+                    client: row.client,
+                    instrument: row.instrument,
+                    orders: row.orders,
+                    trades: row.trades,
+                    ratio,
+                    threshold_exceeded: thresholds.iter().rev().find(|threshold| ratio >= **threshold).copied(),
+                }
+            })
+            .collect();
+        ratios.sort_by(|a, b| {
+            a.date
+                .cmp(&b.date)
+                .then_with(|| b.ratio.total_cmp(&a.ratio))
+                .then_with(|| a.client.cmp(&b.client))
+                .then_with(|| a.instrument.cmp(&b.instrument))
+        });
+
+        let mut clients: HashMap<&str, ClientOrderTradeRatio> = HashMap::new();
+        for row in &ratios {
+            let client = clients.entry(&row.client).or_insert_with(|| ClientOrderTradeRatio {
+                client: row.client.clone(),
+                orders: 0,
+                trades: 0,
+                ratio: 0.0,
+                highest_ratio: 0.0,
+                flagged_ratios: 0,
+            });
+            client.orders += row.orders;
+            client.trades += row.trades;
+            client.highest_ratio = client.highest_ratio.max(row.ratio);
+            if row.threshold_exceeded.is_some() {
+                client.flagged_ratios += 1;
+            }
+        }
+        let mut clients: Vec<ClientOrderTradeRatio> = clients
+            .into_values()
+            .map(|mut client| {
+                client.ratio = ratio(client.orders, client.trades);
+                client
+            })
+            .collect();
+        clients.sort_by(|a, b| b.ratio.total_cmp(&a.ratio).then_with(|| a.client.cmp(&b.client)));
+
+        let total_orders = clients.iter().map(|client| client.orders).sum();
+        let total_trades = clients.iter().map(|client| client.trades).sum();
+        Ok(OrderTradeRatioReport {
+            granularity: if monthly { "MONTHLY" } else { "DAILY" }.to_string(),
+            thresholds,
+            total_orders,
+            total_trades,
+            overall_ratio: ratio(total_orders, total_trades),
+            flagged_ratios: ratios.iter().filter(|row| row.threshold_exceeded.is_some()).count() as i64,
+            clients_flagged: clients.iter().filter(|client| client.flagged_ratios > 0).count() as i64,
+            clients,
+            ratios,
+        })
+    }
+
+    /// Orders and trades of the slice per day or month, client and instrument
+    async fn order_trade_counts(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        monthly: bool,
+    ) -> Result<OrderTradeCounts, sqlx::Error> {
+        let counts = sqlx::query!(
+            r#"
+            WITH order_counts AS (
+                SELECT
+                    DATE(date_trunc($4, o.order_time)) as bucket,
+                    COALESCE(o.client_code, a.account_number) as client,
+                    o.instrument_id,
+                    COUNT(*) as orders
+                FROM orders o
+                JOIN trading_accounts a ON a.account_id = o.account_id
+                WHERE o.tenant_id = $1
+                AND DATE(o.order_time) BETWEEN $2 AND $3
+                GROUP BY 1, 2, 3
+            ),
+            trade_counts AS (
+                SELECT
+                    DATE(date_trunc($4, t.trade_time)) as bucket,
+                    COALESCE(t.client_code, a.account_number) as client,
+                    t.instrument_id,
+                    COUNT(*) as trades
+                FROM trades t
+                JOIN trading_accounts a ON a.account_id = t.account_id
+                WHERE t.tenant_id = $1
+                AND DATE(t.trade_time) BETWEEN $2 AND $3
+                GROUP BY 1, 2, 3
+            )
+            SELECT
+                COALESCE(oc.bucket, tc.bucket) as "bucket!",
+                COALESCE(oc.client, tc.client) as "client!",
+                i.instrument_id as "instrument_id!",
+                COALESCE(v.symbol, i.symbol) as "instrument!",
+                COALESCE(oc.orders, 0) as "orders!",
+                COALESCE(tc.trades, 0) as "trades!"
+            FROM order_counts oc
+            FULL JOIN trade_counts tc
+                ON tc.bucket = oc.bucket AND tc.client = oc.client AND tc.instrument_id = oc.instrument_id
+            JOIN instruments i ON i.instrument_id = COALESCE(oc.instrument_id, tc.instrument_id)
+            LEFT JOIN LATERAL instrument_as_of(i.instrument_id, COALESCE(oc.bucket, tc.bucket)) v ON TRUE
+            "#,
+            tenant_id,
+            start_date,
+            end_date,
+            if monthly { "month" } else { "day" }
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(OrderTradeCounts {
+            counts: counts
+                .into_iter()
+                .map(|row| OrderTradeCount {
+                    bucket: row.bucket,
+                    client: row.client,
+                    instrument_id: row.instrument_id,
+                    instrument: row.instrument,
+                    orders: row.orders,
+                    trades: row.trades,
+                })
+                .collect(),
+        })
+    }
+
+    pub async fn generate_brokerage_summary(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        progress: &Progress,
+    ) -> Result<BrokerageSummaryReport, sqlx::Error> {
+        let totals = chunked::generate(
+            &self.db,
+            &self.chunks,
+            progress,
+            tenant_id,
+            "BROKERAGE_SUMMARY",
+            start_date,
+            end_date,
+            |start, end| self.charge_totals(tenant_id, start, end),
+        )
+        .await?;
+
+        let mut rows = totals.rows;
+        rows.sort_by(|a, b| a.client.cmp(&b.client).then_with(|| a.segment.cmp(&b.segment)));
+
+        let mut clients: HashMap<&str, ClientCharges> = HashMap::new();
+        let mut report_totals = Charges::default();
+        for row in &rows {
+            clients
+                .entry(&row.client)
+                .or_insert_with(|| ClientCharges {
+                    client: row.client.clone(),
+                    charges: Charges::default(),
+                })
+                .charges
+                .add(&row.charges);
+            report_totals.add(&row.charges);
+        }
+        let mut clients: Vec<ClientCharges> = clients.into_values().collect();
+        clients.sort_by(|a, b| {
+            b.charges
+                .total_charges
+                .total_cmp(&a.charges.total_charges)
+                .then_with(|| a.client.cmp(&b.client))
+        });
+
+        Ok(BrokerageSummaryReport {
+            totals: report_totals,
+            trades_without_breakdown: rows.iter().map(|row| row.trades_without_breakdown).sum(),
+            clients,
+            rows,
+        })
+    }
+
+    /// Charges of the slice's trades per client and segment. Brokerage booked
+    /// in trade_charges takes precedence over that of the trade; the taxes of a
+    /// trade without a breakdown are unallocated.
+    async fn charge_totals(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+    ) -> Result<ChargeTotals, sqlx::Error> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT
+                COALESCE(t.client_code, a.account_number) as "client!",
+                t.segment::text as "segment!",
+                COUNT(*) as "trades!",
+                COALESCE(SUM(t.value), 0)::float8 as "turnover!",
+                COALESCE(SUM(COALESCE(c.brokerage, t.brokerage, 0)), 0)::float8 as "brokerage!",
+                COALESCE(SUM(c.stt), 0)::float8 as "stt!",
+                COALESCE(SUM(c.exchange_charges), 0)::float8 as "exchange_charges!",
+                COALESCE(SUM(c.sebi_fees), 0)::float8 as "sebi_fees!",
+                COALESCE(SUM(c.stamp_duty), 0)::float8 as "stamp_duty!",
+                COALESCE(SUM(c.gst), 0)::float8 as "gst!",
+                COALESCE(SUM(t.taxes) FILTER (WHERE c.trade_id IS NULL), 0)::float8 as "unallocated_taxes!",
+                COUNT(*) FILTER (WHERE c.trade_id IS NULL) as "trades_without_breakdown!"
+            FROM trades t
+            JOIN trading_accounts a ON a.account_id = t.account_id
+            LEFT JOIN trade_charges c ON c.trade_id = t.trade_id
+            WHERE t.tenant_id = $1
+            AND DATE(t.trade_time) BETWEEN $2 AND $3
+            GROUP BY 1, 2
+            "#,
+            tenant_id,
+            start_date,
+            end_date
+        )
+        .fetch_all(&self.db)
+        .await?;
+
+        Ok(ChargeTotals {
+            rows: rows
+                .into_iter()
+                .map(|row| SegmentCharges {
+                    client: row.client,
+                    segment: row.segment,
+                    charges: Charges {
+                        trades: row.trades,
+                        turnover: row.turnover,
+                        brokerage: row.brokerage,
+                        stt: row.stt,
+                        exchange_charges: row.exchange_charges,
+                        sebi_fees: row.sebi_fees,
+                        stamp_duty: row.stamp_duty,
+                        gst: row.gst,
+                        unallocated_taxes: row.unallocated_taxes,
+                        total_charges: row.brokerage
+                            + row.stt
+                            + row.exchange_charges
+                            + row.sebi_fees
+                            + row.stamp_duty
+                            + row.gst
+                            + row.unallocated_taxes,
+                    },
+                    trades_without_breakdown: row.trades_without_breakdown,
+                })
+                .collect(),
+        })
+    }
+
+    /// Margin utilization and shortfalls under the peak margin framework
+    ///
+    /// Clients are the trading accounts margin statements are for. A day's
+    /// margin is the peak of its intraday snapshots, or the end-of-day margin
+    /// of statements whose files carry no peak; on each exchange it falls short
+    /// by what exceeds the collateral available, and is penalized as
+    /// `margin_penalty_rate` sets out. So that the days in a row and in the
+    /// month a shortfall has lasted count from before the period, statements
+    /// are read from the start of its first month, or ten days before it if
+    /// that is earlier, and the report is computed in one go rather than in
+    /// slices (see `chunked`) for the same reason. Days in a row are trading
+    /// days of the tenant's calendar: one without a shortfall, or without a
+    /// statement at all, ends the run.
+    pub async fn generate_margin_report(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        progress: &Progress,
+    ) -> anyhow::Result<MarginReport> {
+        let calendar = business_hours::load(&self.db, tenant_id).await?;
+        let lookback = start_date
+            .with_day(1)
+            .unwrap_or(start_date)
+            .min(start_date - chrono::Duration::days(10));
+        let statements = sqlx::query!(
+            r#"
+            SELECT
+                ms.business_date,
+                ms.exchange,
+                a.account_number as client,
+                a.account_name as client_name,
+                COALESCE(ms.peak_margin, ms.total_margin)::float8 as "peak_margin!",
+                ms.collateral_available::float8 as "collateral_available!",
+                ms.peak_margin IS NULL as "without_peak!"
+            FROM margin_statements ms
+            JOIN trading_accounts a ON a.account_id = ms.account_id
+            WHERE ms.tenant_id = $1
+            AND ms.business_date BETWEEN $2 AND $3
+            ORDER BY a.account_number, ms.exchange, ms.business_date
+            "#,
+            tenant_id,
+            lookback,
+            end_date
+        )
+        .fetch_all(&self.db)
+        .await?;
+        progress.queried(1, 1).await;
+        progress.phase(Phase::Aggregating).await;
+
+        let utilization = |margin: f64, collateral: f64| (collateral > 0.0).then(|| margin / collateral);
+        let mut violations = Vec::new();
+        let mut days: HashMap<(chrono::NaiveDate, String), DailyMargin> = HashMap::new();
+        let mut names: HashMap<String, String> = HashMap::new();
+        let mut statements_without_peak = 0;
+        // Shortfall days running on the client's exchange up to its last statement, and in the month
+        let mut run: Option<(&str, &str, chrono::NaiveDate, i64, (i32, u32), i64)> = None;
+        for row in &statements {
+            let shortfall = (row.peak_margin - row.collateral_available).max(0.0);
+            let month = (row.business_date.year(), row.business_date.month());
+            let (consecutive_days, days_in_month) = match run {
+                Some((client, exchange, last_date, consecutive, in_month, count))
+                    if client == row.client && exchange == row.exchange =>
+                {
+                    let count = if in_month == month { count } else { 0 };
+                    let consecutive =
+                        if calendar.next_business_day(last_date) == Some(row.business_date) { consecutive } else { 0 };
+                    if shortfall > 0.0 {
+                        (consecutive + 1, count + 1)
+                    } else {
+                        (0, count)
+                    }
+                }
+                _ => (i64::from(shortfall > 0.0), i64::from(shortfall > 0.0)),
+            };
+            run = Some((
+                row.client.as_str(),
+                row.exchange.as_str(),
+                row.business_date,
+                consecutive_days,
+                month,
+                days_in_month,
+            ));
+            if row.business_date < start_date {
+                continue;
+            }
+
+            if row.without_peak {
+                statements_without_peak += 1;
+            }
+            names.insert(row.client.clone(), row.client_name.clone());
+            let penalty_rate = margin_penalty_rate(shortfall, row.peak_margin, consecutive_days, days_in_month);
+            let penalty = if shortfall > 0.0 { shortfall * penalty_rate } else { 0.0 };
+            let day = days.entry((row.business_date, row.client.clone())).or_insert_with(|| DailyMargin {
+                date: row.business_date,
+                client: row.client.clone(),
+                peak_margin: 0.0,
+                collateral_available: 0.0,
+                utilization: None,
+                shortfall: 0.0,
+                penalty: 0.0,
+            });
+            day.peak_margin += row.peak_margin;
+            day.collateral_available += row.collateral_available;
+            day.shortfall += shortfall;
+            day.penalty += penalty;
+            if shortfall > 0.0 {
+                violations.push(MarginViolation {
+                    date: row.business_date,
+                    client: row.client.clone(),
+                    exchange: row.exchange.clone(),
+                    peak_margin: row.peak_margin,
+                    collateral_available: row.collateral_available,
+                    shortfall,
+                    shortfall_share: shortfall / row.peak_margin,
+                    consecutive_days,
+                    days_in_month,
+                    penalty_rate,
+                    penalty,
+                });
+            }
+        }
+
+        let mut days: Vec<DailyMargin> = days
+            .into_values()
+            .map(|mut day| {
+                day.utilization = utilization(day.peak_margin, day.collateral_available);
+                day
+            })
+            .collect();
+        days.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.client.cmp(&b.client)));
+        violations.sort_by(|a, b| {
+            a.date
+                .cmp(&b.date)
+                .then_with(|| b.shortfall.total_cmp(&a.shortfall))
+                .then_with(|| a.client.cmp(&b.client))
+                .then_with(|| a.exchange.cmp(&b.exchange))
+        });
+
+        let mut clients: HashMap<&str, (ClientMargin, Vec<f64>)> = HashMap::new();
+        for day in &days {
+            let (client, utilizations) = clients.entry(&day.client).or_insert_with(|| {
+                (
+                    ClientMargin {
+                        client: day.client.clone(),
+                        client_name: names.get(&day.client).cloned(),
+                        business_days: 0,
+                        highest_peak_margin: 0.0,
+                        highest_utilization: None,
+                        average_utilization: None,
+                        shortfall_incidents: 0,
+                        total_shortfall: 0.0,
+                        largest_shortfall: 0.0,
+                        penalty: 0.0,
+                    },
+                    Vec::new(),
+                )
+            });
+            client.business_days += 1;
+            client.highest_peak_margin = client.highest_peak_margin.max(day.peak_margin);
+            client.total_shortfall += day.shortfall;
+            client.penalty += day.penalty;
+            utilizations.extend(day.utilization);
+        }
+        for violation in &violations {
+            if let Some((client, _)) = clients.get_mut(violation.client.as_str()) {
+                client.shortfall_incidents += 1;
+                client.largest_shortfall = client.largest_shortfall.max(violation.shortfall);
+            }
+        }
+        let mut clients: Vec<ClientMargin> = clients
+            .into_values()
+            .map(|(mut client, utilizations)| {
+                client.highest_utilization = utilizations.iter().copied().reduce(f64::max);
+                client.average_utilization = (!utilizations.is_empty())
+                    .then(|| utilizations.iter().sum::<f64>() / utilizations.len() as f64);
+                client
+            })
+            .collect();
+        clients.sort_by(|a, b| {
+            b.penalty
+                .total_cmp(&a.penalty)
+                .then_with(|| b.highest_utilization.unwrap_or(0.0).total_cmp(&a.highest_utilization.unwrap_or(0.0)))
+                .then_with(|| a.client.cmp(&b.client))
+        });
+
+        let mut business_days: Vec<chrono::NaiveDate> = days.iter().map(|day| day.date).collect();
+        business_days.dedup();
+        Ok(MarginReport {
+            business_days: business_days.len() as i64,
+            clients_with_statements: clients.len() as i64,
+            highest_utilization: clients.iter().filter_map(|client| client.highest_utilization).reduce(f64::max),
+            shortfall_incidents: violations.len() as i64,
+            clients_with_shortfall: clients.iter().filter(|client| client.shortfall_incidents > 0).count() as i64,
+            total_shortfall: violations.iter().map(|violation| violation.shortfall).sum(),
+            total_penalty: violations.iter().map(|violation| violation.penalty).sum(),
+            statements_without_peak,
+            clients,
+            days,
+            violations,
+        })
+    }
+
+    /// Time to acknowledge and to resolve the alerts raised in the period
+    ///
+    /// An alert is acknowledged when the first investigation into it starts,
+    /// it is escalated or it is resolved, whichever comes first. Alerts not
+    /// acknowledged or resolved yet are aged as of the generation, and breach
+    /// their SLA once they are older than it. With `business_time` ages count
+    /// the tenant's market sessions only, as violation SLAs do.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn generate_alert_ageing(
+        &self,
+        tenant_id: Uuid,
+        start_date: chrono::NaiveDate,
+        end_date: chrono::NaiveDate,
+        acknowledge_sla_hours: HashMap<String, f64>,
+        resolution_sla_hours: HashMap<String, f64>,
+        business_time: bool,
+        progress: &Progress,
+    ) -> anyhow::Result<AlertAgeingReport> {
+        let calendar = if business_time {
+            Some(business_hours::load(&self.db, tenant_id).await?)
+        } else {
+            None
+        };
+        // Periods are tenant-local days, as an alert raised at 01:00 IST belongs to that IST day
+        let timezone = tenant_schedules::tenant_timezone(&self.db, tenant_id).await?;
+        let alerts = sqlx::query!(
+            r#"
+            SELECT
+                s.alert_id,
+                s.alert_type,
+                s.severity::text as "severity!",
+                s.created_at as "raised_at!",
+                LEAST(
+                    (SELECT MIN(i.started_at) FROM alert_investigations i WHERE i.alert_id = s.alert_id),
+                    s.escalated_at,
+                    s.resolved_at
+                ) as acknowledged_at,
+                s.resolved_at
+            FROM surveillance_alerts s
+            WHERE s.tenant_id = $1
+            AND s.created_at IS NOT NULL
+            AND DATE(s.created_at AT TIME ZONE $4) BETWEEN $2 AND $3
+            "#,
+            tenant_id,
+            start_date,
+            end_date,
+            timezone
+        )
+        .fetch_all(&self.db)
+        .await?;
+        progress.queried(1, 1).await;
+        progress.phase(Phase::Aggregating).await;
+
+        let now = chrono::Utc::now();
+        let hours = |from: chrono::DateTime<chrono::Utc>, to: chrono::DateTime<chrono::Utc>| {
+            let elapsed = match &calendar {
+                Some(calendar) => calendar.business_time_between(from, to),
+                None => to - from,
+            };
+            elapsed.num_seconds().max(0) as f64 / 3600.0
+        };
+
+        #[derive(Default)]
+        struct Ages {
+            alerts: i64,
+            acknowledged: Vec<f64>,
+            resolved: Vec<f64>,
+            acknowledge_breaches: i64,
+            resolution_breaches: i64,
+        }
+        let mut groups: HashMap<(String, String), Ages> = HashMap::new();
+        let mut breaches = Vec::new();
+        let (mut under_acknowledge_sla, mut under_resolution_sla) = (0, 0);
+        for alert in alerts {
+            let acknowledge_sla = acknowledge_sla_hours.get(&alert.severity).copied();
+            let resolution_sla = resolution_sla_hours.get(&alert.severity).copied();
+            let acknowledge_hours = hours(alert.raised_at, alert.acknowledged_at.unwrap_or(now));
+            let resolution_hours = hours(alert.raised_at, alert.resolved_at.unwrap_or(now));
+            let acknowledge_breached = acknowledge_sla.is_some_and(|sla| acknowledge_hours > sla);
+            let resolution_breached = resolution_sla.is_some_and(|sla| resolution_hours > sla);
+            under_acknowledge_sla += acknowledge_sla.is_some() as i64;
+            under_resolution_sla += resolution_sla.is_some() as i64;
+
+            let ages = groups.entry((alert.alert_type.clone(), alert.severity.clone())).or_default();
+            ages.alerts += 1;
+            if alert.acknowledged_at.is_some() {
+                ages.acknowledged.push(acknowledge_hours);
+            }
+            if alert.resolved_at.is_some() {
+                ages.resolved.push(resolution_hours);
+            }
+            ages.acknowledge_breaches += acknowledge_breached as i64;
+            ages.resolution_breaches += resolution_breached as i64;
+
+            if acknowledge_breached || resolution_breached {
+                let overdue = |breached: bool, hours: f64, sla: Option<f64>| match sla {
+                    Some(sla) if breached => hours - sla,
+                    _ => 0.0,
+                };
+                breaches.push(SlaBreach {
+                    alert_id: alert.alert_id,
+                    alert_type: alert.alert_type,
+                    severity: alert.severity,
+                    raised_at: alert.raised_at,
+                    acknowledge_hours,
+                    acknowledge_breached,
+                    resolution_hours,
+                    resolution_breached,
+                    resolved: alert.resolved_at.is_some(),
+                    overdue_hours: overdue(acknowledge_breached, acknowledge_hours, acknowledge_sla)
+                        .max(overdue(resolution_breached, resolution_hours, resolution_sla)),
+                });
+            }
+        }
+        breaches.sort_by(|a, b| b.overdue_hours.total_cmp(&a.overdue_hours).then_with(|| a.alert_id.cmp(&b.alert_id)));
+
+        let severity_rank = |severity: &str| SEVERITIES.iter().position(|s| *s == severity).unwrap_or(SEVERITIES.len());
+        let mut groups: Vec<AlertAgeing> = groups
+            .into_iter()
+            .map(|((alert_type, severity), ages)| {
+                let unacknowledged = ages.alerts - ages.acknowledged.len() as i64;
+                let unresolved = ages.alerts - ages.resolved.len() as i64;
+                AlertAgeing {
+                    acknowledge_sla_hours: acknowledge_sla_hours.get(&severity).copied(),
+                    resolution_sla_hours: resolution_sla_hours.get(&severity).copied(),
+                    time_to_acknowledge: AgeDistribution::of(
+                        ages.acknowledged,
+                        unacknowledged,
+                        ages.acknowledge_breaches,
+                    ),
+                    time_to_resolution: AgeDistribution::of(ages.resolved, unresolved, ages.resolution_breaches),
+                    alerts: ages.alerts,
+                    alert_type,
+                    severity,
+                }
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            a.alert_type
+                .cmp(&b.alert_type)
+                .then_with(|| severity_rank(&a.severity).cmp(&severity_rank(&b.severity)))
+        });
+
+        let sum = |field: fn(&AlertAgeing) -> i64| groups.iter().map(field).sum::<i64>();
+        let total_alerts = sum(|group| group.alerts);
+        let acknowledged_alerts = sum(|group| group.time_to_acknowledge.measured);
+        let resolved_alerts = sum(|group| group.time_to_resolution.measured);
+        let acknowledge_breaches = sum(|group| group.time_to_acknowledge.breaches);
+        let resolution_breaches = sum(|group| group.time_to_resolution.breaches);
+        let within = |breaches: i64, under_sla: i64| {
+            (under_sla > 0).then(|| (under_sla - breaches) as f64 / under_sla as f64)
+        };
+        Ok(AlertAgeingReport {
+            clock: if business_time { "BUSINESS" } else { "WALL" }.to_string(),
+            acknowledge_sla_hours,
+            resolution_sla_hours,
+            total_alerts,
+            acknowledged_alerts,
+            resolved_alerts,
+            acknowledge_breaches,
+            resolution_breaches,
+            acknowledged_within_sla: within(acknowledge_breaches, under_acknowledge_sla),
+            resolved_within_sla: within(resolution_breaches, under_resolution_sla),
+            groups,
+            breaches,
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let database_url = std::env::var("DATABASE_URL")
+        .expect("DATABASE_URL must be set");
+    let bundle_signing_key = std::env::var("REPORT_BUNDLE_SIGNING_KEY")
+        .expect("REPORT_BUNDLE_SIGNING_KEY must be set");
+    let authenticator = Arc::new(Authenticator::from_env()?);
+
+    dharmaguard_common::metrics::install();
+    let pool_settings = PoolSettings::from_env();
+    let pool = pool_settings.connect(&database_url).await?;
+    let admission = Admission::new("reporting", pool_settings);
+    admission.spawn_autoscaler(pool.clone());
+
+    // Initialize job scheduler for automated reports
+    let scheduler = JobScheduler::new().await?;
+    
+    // Daily and weekly reports follow each tenant's business-day closes
+    let schedule_settings = ScheduleSettings::from_env();
+    let report_files = Arc::new(ReportFiles::from_env().await?);
+    // Completed and failed reports are called back to tenant webhooks in the background
+    let webhooks = Arc::new(ReportWebhooks::from_env()?);
+    webhooks::spawn_worker(pool.clone(), webhooks.clone());
+    // Trade aggregates are cached in Redis until trades of their period land,
+    // and long periods are generated in checkpointed slices
+    let aggregate_cache = Arc::new(AggregateCache::from_env().await?);
+    aggregate_cache.clone().spawn_invalidation(pool.clone());
+    let generator = Arc::new(ReportEngine::new(
+        pool.clone(),
+        aggregate_cache,
+        ChunkSettings::from_env(),
+        JobSettings::from_env()?,
+    ));
+    schedule::schedule(
+        &scheduler,
+        pool.clone(),
+        report_files.clone(),
+        generator.clone(),
+        webhooks.clone(),
+        schedule_settings.clone(),
+    )
+    .await?;
+    // Past trading days are rolled up nightly for trading summaries
+    rollups::schedule(&scheduler, pool.clone(), RollupSettings::from_env()).await?;
+    scheduler.start().await?;
+
+    let delivery = Arc::new(ReportDelivery::from_env()?);
+    // Deliveries that failed for a passing reason are attempted again
+    delivery::spawn_retries(pool.clone(), report_files.clone(), delivery.clone());
+
+    // Schedules tenants manage through /reports/schedules join the same scheduler
+    let tenant_schedules = Arc::new(TenantSchedules::from_env(
+        pool.clone(),
+        report_files.clone(),
+        generator.clone(),
+        webhooks.clone(),
+        scheduler.clone(),
+    ));
+    tenant_schedules.clone().spawn_sync();
+
+    // Files past their retention are archived or removed from the report store
+    report_files::spawn_sweeper(pool.clone(), report_files.clone());
+
+    // Tenant takeouts are built and delivered in the background
+    takeout::spawn_worker(pool.clone(), delivery.clone(), TakeoutSettings::from_env());
+
+    let app_state = AppState {
+        db: pool,
+        scheduler: Arc::new(scheduler),
+        bundle_signer: Arc::new(BundleSigner::new(bundle_signing_key.as_bytes())),
+        delivery,
+        schedule_settings: Arc::new(schedule_settings),
+        portal_settings: Arc::new(PortalSettings::from_env()),
+        embargo_settings: Arc::new(EmbargoSettings::from_env()),
+        report_files,
+        tenant_schedules,
+        sftp_settings: Arc::new(SftpSettings::from_env()?),
+        webhooks,
+        generator,
+        authenticator,
+    };
+
+    let api_v1 = Router::new()
+        .route("/reports", post(generate_report).get(list_reports))
+        .route("/reports/compare", get(compare_reports))
+        .route("/reports/jobs", get(list_report_jobs))
+        .route("/reports/types", get(list_report_types))
+        .route("/reports/:id", get(get_report))
+        .route("/reports/:id/drilldown/:metric", get(drilldown_report))
+        .route("/reports/:id/cancel", delete(cancel_report))
+        .route("/reports/:id/progress", get(stream_report_progress))
+        .route("/reports/:id/amend", post(amend_report))
+        .route("/reports/:id/review", post(review_report).get(get_report_review))
+        .route("/reports/:id/versions", get(list_report_versions))
+        .route("/reports/:id/download", get(download_report))
+        .route("/reports/:id/deliveries", post(deliver_report).get(list_report_deliveries))
+        .route("/reports/:id/sftp-deliveries", post(deliver_report_sftp).get(list_report_sftp_deliveries))
+        .route("/reports/:id/embargo", get(get_report_embargo).put(set_report_embargo))
+        .route("/reports/:id/embargo/lift", post(lift_report_embargo))
+        .route("/reports/scheduled", get(list_scheduled_reports))
+        .route("/reports/schedules", post(create_report_schedule).get(list_report_schedules))
+        .route(
+            "/reports/schedules/timezone",
+            get(get_report_schedule_timezone).put(set_report_schedule_timezone),
+        )
+        .route(
+            "/reports/schedules/:id",
+            get(get_report_schedule).patch(update_report_schedule).delete(delete_report_schedule),
+        )
+        .route("/reports/:id/legal-hold", get(get_report_legal_hold).put(place_report_legal_hold))
+        .route("/reports/:id/legal-hold/release", post(release_report_legal_hold))
+        .route("/reports/:id/signature", get(get_report_signature))
+        .route(
+            "/reports/signatures/verify",
+            post(verify_report_signature).layer(DefaultBodyLimit::max(signing::MAX_UPLOAD_BYTES)),
+        )
+        .route(
+            "/reports/signing/certificate",
+            get(get_signing_certificate).put(set_signing_certificate).delete(delete_signing_certificate),
+        )
+        .route("/reports/retention-policies", get(list_retention_policies))
+        .route(
+            "/reports/retention-policies/:report_type",
+            put(set_retention_policy).delete(delete_retention_policy),
+        )
+        .route("/reports/sftp-targets", post(create_sftp_target).get(list_sftp_targets))
+        .route(
+            "/reports/sftp-targets/:id",
+            get(get_sftp_target).patch(update_sftp_target).delete(delete_sftp_target),
+        )
+        .route("/reports/webhooks", post(create_report_webhook).get(list_report_webhooks))
+        .route(
+            "/reports/webhooks/:id",
+            get(get_report_webhook).patch(update_report_webhook).delete(delete_report_webhook),
+        )
+        .route("/reports/webhooks/:id/secret", post(rotate_report_webhook_secret))
+        .route("/reports/webhooks/:id/deliveries", get(list_report_webhook_deliveries))
+        .route("/reports/layouts/:report_type", post(create_report_layout).get(list_report_layouts))
+        .route("/reports/layouts/:report_type/default", get(get_default_report_layout))
+        .route("/reports/layouts/:report_type/current", delete(reset_report_layout))
+        .route("/reports/layouts/:report_type/preview", post(preview_report_layout))
+        .route("/reports/layouts/:report_type/versions/:version", get(get_report_layout))
+        .route("/reports/layouts/:report_type/versions/:version/activate", post(activate_report_layout))
+        .route("/reports/access-tokens", post(issue_access_token).get(list_access_tokens))
+        .route("/reports/access-tokens/:id/revoke", post(revoke_access_token))
+        .route("/reports/access-tokens/:id/access-log", get(list_access_log))
+        .route("/exports", post(create_export).get(list_exports))
+        .route("/exports/:id", get(get_export))
+        .route("/exports/:id/deliveries", post(deliver_export).get(list_export_deliveries))
+        .route("/reports/templates/import", post(import_template))
+        .route("/reports/templates/:id/export", get(export_template))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), auth::authorize))
+        // Recipients present the access tokens issued to them rather than a user token
+        .route("/portal/reports", get(portal_list_reports))
+        .route("/portal/reports/:id/download", get(portal_download_report));
+
+    // API routes answer 503 until these pass; /health, /ready and /metrics answer from the start
+    let startup = Startup::new("reporting").migrations(app_state.db.clone(), MIGRATIONS);
+    let readiness = startup.readiness();
+
+    let app = versioning::versioned("reporting", api_v1)
+        .route_layer(middleware::from_fn_with_state(admission, pool::admit))
+        .route_layer(middleware::from_fn_with_state(readiness.clone(), startup::gate))
+        .route("/health", get(health_check))
+        .merge(startup::router(readiness))
+        .merge(dharmaguard_common::metrics::router())
+        .with_state(app_state);
+
+    let listener = TcpListener::bind("0.0.0.0:8083").await?;
+    info!("Reporting service listening on port 8083");
+
+    startup
+        .serve(async { axum::serve(listener, app).await.map_err(anyhow::Error::from) })
+        .await
+}
 
 async fn health_check() -> Json<serde_json::Value> {
     Json(serde_json::json!({"status": "healthy", "service": "reporting"}))
@@ -328,150 +1998,1764 @@ async fn health_check() -> Json<serde_json::Value> {
 
 async fn generate_report(
     State(state): State<AppState>,
-    Json(request): Json<GenerateReportRequest>,
-) -> Result<Json<ReportResponse>, StatusCode> {
+    caller: Caller,
+    Json(mut request): Json<GenerateReportRequest>,
+) -> Result<Json<ReportResponse>, (StatusCode, Json<serde_json::Value>)> {
+    request.generated_by = Some(caller.user_id());
     let report_id = Uuid::new_v4();
     info!("Generating report: {:?} for tenant: {}", request.report_type, request.tenant_id);
 
-    let generator = ReportGenerator::new(state.db.clone());
-    
-    let report_data = match request.report_type.as_str() {
-        "TRADING_SUMMARY" => {
-            match generator.generate_trading_summary(
-                request.tenant_id,
-                request.period_start,
-                request.period_end,
-            ).await {
-                Ok(data) => serde_json::to_value(data).unwrap(),
-                Err(e) => {
-                    error!("Failed to generate trading summary: {}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
+    let internal = |what: &str| (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": what})));
+
+    let Some(generator) = generators::get(&request.report_type) else {
+        warn!("Unknown report type: {}", request.report_type);
+        return Err((StatusCode::BAD_REQUEST, Json(serde_json::json!({"error": "unknown report type"}))));
+    };
+    let parameters = match generators::resolve(generator, &request.parameters) {
+        Ok(parameters) => parameters,
+        Err(errors) => return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors})))),
+    };
+    let Some(format) = ReportFormat::parse(&request.format) else {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({"errors": ["format must be one of PDF, CSV, XLSX, JSON, XML or XBRL"]})),
+        ));
+    };
+    if !generator.formats().contains(&format) || !state.report_files.covers(format, &request.report_type) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({
+                "errors": [format!("{} reports cannot be rendered as {}", request.report_type, format.as_str())]
+            })),
+        ));
+    }
+
+    // Resolved before generating so an unusable embargo leaves nothing behind
+    let held = match request.embargo() {
+        Some(terms) => {
+            let errors = terms.validate(&state.embargo_settings);
+            if !errors.is_empty() {
+                return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+            }
+            match embargo::resolve_until(&state.db, &terms).await {
+                Ok(until) => Some((terms, until)),
+                Err(e) => return Err(embargo_error(e)),
             }
         }
-        "COMPLIANCE_REPORT" => {
-            match generator.generate_compliance_report(
-                request.tenant_id,
-                request.period_start,
-                request.period_end,
-            ).await {
-                Ok(data) => serde_json::to_value(data).unwrap(),
-                Err(e) => {
-                    error!("Failed to generate compliance report: {}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        None => None,
+    };
+
+    // Checked before generating too, so an undeliverable request leaves nothing behind
+    let deliver_to = request
+        .deliver_to
+        .clone()
+        .and_then(DeliveryPlan::nonempty)
+        .map(|plan| plan.request(request.tenant_id, request.generated_by));
+    if let Some(deliver_to) = &deliver_to {
+        let mut errors = deliver_to.validate();
+        if held.is_some() {
+            errors.push("reports under embargo cannot be delivered when generated".to_string());
+        }
+        if !errors.is_empty() {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+        }
+        if let Err(DeliveryError::NotConfigured(what)) = state.delivery.ensure_available(deliver_to) {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(serde_json::json!({"error": format!("{} is not configured", what)})),
+            ));
+        }
+    }
+
+    // The template names the report's type wherever the report is listed or read back
+    let template_id = match sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT template_id FROM report_templates
+        WHERE report_type = $1 AND is_active AND ($2::uuid IS NULL OR template_id = $2)
+        ORDER BY created_at
+        LIMIT 1
+        "#,
+    )
+    .bind(&request.report_type)
+    .bind(request.template_id)
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(Some(template_id)) => template_id,
+        Ok(None) => {
+            let error = match request.template_id {
+                Some(template_id) => {
+                    format!("template {} is not an active {} template", template_id, request.report_type)
                 }
-            }
+                None => format!("no active template for {} reports", request.report_type),
+            };
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": [error]}))));
         }
-        _ => {
-            warn!("Unknown report type: {}", request.report_type);
-            return Err(StatusCode::BAD_REQUEST);
+        Err(e) => {
+            error!("Failed to load templates of {} reports: {}", request.report_type, e);
+            return Err(internal("failed to generate report"));
         }
     };
 
-    // Store report in database
-    match sqlx::query!(
-        r#"
-        INSERT INTO regulatory_reports_v2 (
-            report_id, template_id, report_period_start, report_period_end, 
-            status, report_data, generated_at
+    // From here on the tenant's webhooks hear how the report went
+    let outcome = ReportOutcome {
+        tenant_id: request.tenant_id,
+        report_id: Some(report_id),
+        report_type: &request.report_type,
+        format: format.as_str(),
+        period_start: request.period_start,
+        period_end: request.period_end,
+        schedule_id: None,
+    };
+
+    // The job is cancelled with DELETE /reports/:id/cancel while it runs
+    let job = Job {
+        report_id,
+        tenant_id: request.tenant_id,
+        report_type: &request.report_type,
+        period_start: request.period_start,
+        period_end: request.period_end,
+        parameters: &parameters,
+    };
+    let report_data = match state.generator.generate(&job).await {
+        Ok(data) => data,
+        Err(e) => {
+            if let Some(stopped) = e.downcast_ref::<Stopped>() {
+                warn!("Report {} of tenant {} stopped: {}", report_id, request.tenant_id, stopped);
+                state.webhooks.failed(&state.db, &outcome, &stopped.to_string()).await;
+                return Err(stopped_error(stopped));
+            }
+            error!("Failed to generate {} report {}: {:#}", request.report_type, report_id, e);
+            state.webhooks.failed(&state.db, &outcome, "failed to generate report").await;
+            return Err(internal("failed to generate report"));
+        }
+    };
+    // XBRL filings identify the tenant by its SEBI registration
+    let registration_no = match format {
+        ReportFormat::Xbrl => match sqlx::query_scalar!(
+            "SELECT sebi_registration_no FROM tenants WHERE tenant_id = $1",
+            request.tenant_id
         )
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
-        "#,
+        .fetch_optional(&state.db)
+        .await
+        {
+            Ok(registration_no) => registration_no.flatten(),
+            Err(e) => {
+                error!("Failed to load SEBI registration of tenant {}: {}", request.tenant_id, e);
+                report_failed(&state, &outcome, "failed to generate report").await;
+                return Err(internal("failed to generate report"));
+            }
+        },
+        _ => None,
+    };
+    let layout = match format {
+        ReportFormat::Pdf => match layouts::current(&state.db, request.tenant_id, &request.report_type).await {
+            Ok(layout) => layout,
+            Err(e) => {
+                error!("Failed to load report layout of tenant {}: {}", request.tenant_id, e);
+                report_failed(&state, &outcome, "failed to generate report").await;
+                return Err(internal("failed to generate report"));
+            }
+        },
+        _ => None,
+    };
+    let meta = ReportMeta {
         report_id,
-        Uuid::new_v4(), // template_id
-        request.period_start,
-        request.period_end,
-        "GENERATED",
-        &report_data,
-        chrono::Utc::now()
-    )
-    .execute(&state.db)
-    .await {
-        Ok(_) => {
+        tenant_id: request.tenant_id,
+        report_type: request.report_type.clone(),
+        period_start: request.period_start,
+        period_end: request.period_end,
+        generated_at: chrono::Utc::now(),
+        registration_no,
+        layout,
+    };
+    state.generator.jobs().progress(report_id).phase(Phase::Rendering).await;
+    let file = match state.report_files.create(&state.db, &meta, format, &report_data).await {
+        Ok(file) => file,
+        Err(e) => {
+            if let Some(Nonconforming(errors)) = e.downcast_ref::<Nonconforming>() {
+                report_failed(&state, &outcome, &errors.join("; ")).await;
+                return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+            }
+            if let Some(unsigned) = e.downcast_ref::<CannotSign>() {
+                report_failed(&state, &outcome, &unsigned.to_string()).await;
+                return Err((StatusCode::CONFLICT, Json(serde_json::json!({"error": unsigned.to_string()}))));
+            }
+            error!("Failed to render report {}: {:#}", report_id, e);
+            report_failed(&state, &outcome, "failed to render report").await;
+            return Err(internal("failed to render report"));
+        }
+    };
+
+    // Store report in database, with its embargo and the end of its job in the same transaction
+    let stored: anyhow::Result<()> = async {
+        let mut tx = state.db.begin().await?;
+        sqlx::query!(
+            r#"
+            INSERT INTO regulatory_reports_v2 (
+                report_id, tenant_id, template_id, report_period_start, report_period_end, 
+                status, report_data, generated_by, generated_at, file_path, file_hash,
+                file_size, file_expires_at, layout_id, digital_signature, signed_at,
+                signing_certificate_fingerprint, file_retention_action, report_parameters
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
+            "#,
+            report_id,
+            request.tenant_id,
+            template_id,
+            request.period_start,
+            request.period_end,
+            approval::DRAFT,
+            &report_data,
+            request.generated_by,
+            meta.generated_at,
+            file.file_path,
+            file.file_hash,
+            file.file_size,
+            file.file_expires_at,
+            meta.layout.as_ref().and_then(|layout| layout.layout_id),
+            file.digital_signature(),
+            file.signature.as_ref().map(|signature| signature.signed_at),
+            file.signature.as_ref().map(|signature| signature.fingerprint.clone()),
+            file.file_retention_action,
+            serde_json::Value::Object(parameters.clone())
+        )
+        .execute(&mut *tx)
+        .await?;
+        if let Some((terms, until)) = &held {
+            embargo::insert(&mut tx, report_id, terms, *until).await?;
+        }
+        if let Some(deliver_to) = &deliver_to {
+            review::hold(&mut *tx, report_id, deliver_to).await?;
+        }
+        jobs::complete(&mut tx, report_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+    .await;
+
+    match stored {
+        Ok(()) => {
+            state.webhooks.completed(&state.db, &outcome).await;
+            let deliveries_url = deliver_to.as_ref().map(|_| format!("/reports/{}/deliveries", report_id));
             let response = ReportResponse {
                 report_id,
+                tenant_id: request.tenant_id,
                 report_type: request.report_type,
-                status: "GENERATED".to_string(),
-                file_path: Some(format!("/reports/{}.{}", report_id, request.format.to_lowercase())),
-                generated_at: Some(chrono::Utc::now()),
+                status: approval::DRAFT.to_string(),
+                period_start: request.period_start,
+                period_end: request.period_end,
+                file_path: Some(file.file_path),
+                generated_at: Some(meta.generated_at),
                 download_url: Some(format!("/reports/{}/download", report_id)),
+                embargoed_until: held.map(|(_, until)| until),
+                version: 1,
+                superseded_by: None,
+                deliveries_url,
             };
             Ok(Json(response))
         }
         Err(e) => {
-            error!("Failed to store report: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            state.report_files.remove(&file).await;
+            if let Some(stopped) = e.downcast_ref::<Stopped>() {
+                warn!("Report {} of tenant {} stopped: {}", report_id, request.tenant_id, stopped);
+                state.webhooks.failed(&state.db, &outcome, &stopped.to_string()).await;
+                return Err(stopped_error(stopped));
+            }
+            error!("Failed to store report: {:#}", e);
+            report_failed(&state, &outcome, "failed to store report").await;
+            Err(internal("failed to store report"))
         }
     }
 }
 
-async fn list_reports(State(state): State<AppState>) -> Result<Json<Vec<ReportResponse>>, StatusCode> {
-    match sqlx::query!(
-        r#"
-        SELECT report_id, 'UNKNOWN' as report_type, status, generated_at
-        FROM regulatory_reports_v2 
-        ORDER BY generated_at DESC 
-        LIMIT 50
-        "#
+/// Tell the tenant's webhooks the report failed, and mark its generation job FAILED
+async fn report_failed(state: &AppState, outcome: &ReportOutcome<'_>, reason: &str) {
+    if let Some(report_id) = outcome.report_id {
+        state.generator.jobs().fail(report_id, reason).await;
+    }
+    state.webhooks.failed(&state.db, outcome, reason).await;
+}
+
+fn job_error(e: JobError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        JobError::NotFound => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))),
+        JobError::Finished(ref status) => (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": e.to_string(), "status": status})),
+        ),
+        JobError::Internal(e) => {
+            error!("Report generation job request failed: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+/// The report types that can be generated, with their parameters, formats and
+/// the schema of their data
+async fn list_report_types() -> Json<Vec<ReportTypeDescription>> {
+    Json(generators::all().map(generators::describe).collect())
+}
+
+/// The tenant's most recent report generations, those still running first
+async fn list_report_jobs(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ReportJob>>, (StatusCode, Json<serde_json::Value>)> {
+    jobs::list(&state.db, &tenant).await.map(Json).map_err(job_error)
+}
+
+/// Abort the report's generation while it is running; the report is not stored
+async fn cancel_report(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    caller: Caller,
+    Query(mut request): Query<CancelRequest>,
+    State(state): State<AppState>,
+) -> Result<Json<ReportJob>, (StatusCode, Json<serde_json::Value>)> {
+    request.cancelled_by = Some(caller.user_id());
+    let job = jobs::cancel(&state.db, &tenant, report_id, request).await.map_err(job_error)?;
+    info!("Cancelled generation of {} report {} of tenant {}", job.report_type, report_id, tenant);
+    Ok(Json(job))
+}
+
+/// The report's generation progress over server-sent events while its job runs; see `progress`
+async fn stream_report_progress(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let events = progress::stream(state.db.clone(), tenant, report_id).await.map_err(job_error)?;
+    Ok(events.into_response())
+}
+
+fn stopped_error(stopped: &Stopped) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match stopped {
+        Stopped::Cancelled => StatusCode::CONFLICT,
+        Stopped::TimedOut(_) => StatusCode::GATEWAY_TIMEOUT,
+    };
+    (status, Json(serde_json::json!({"error": stopped.to_string()})))
+}
+
+/// The tenant's reports, filtered and a page at a time; see `listing`
+async fn list_reports(
+    tenant: TenantContext,
+    Query(filter): Query<ReportFilter>,
+    State(state): State<AppState>,
+) -> Result<Json<ReportListing>, (StatusCode, Json<serde_json::Value>)> {
+    match listing::list(&state.db, &tenant, &filter).await {
+        Ok(listing) => Ok(Json(listing)),
+        Err(ListError::Invalid(errors)) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))))
+        }
+        Err(ListError::Internal(e)) => {
+            error!("Failed to list reports of tenant {}: {}", tenant, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))))
+        }
+    }
+}
+
+/// 403 with the time an embargoed report becomes available
+fn embargoed(until: chrono::DateTime<chrono::Utc>) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({"error": "report is under embargo", "available_at": until})),
     )
-    .fetch_all(&state.db)
-    .await {
-        Ok(rows) => {
-            let reports: Vec<ReportResponse> = rows.into_iter().map(|row| {
-                ReportResponse {
-                    report_id: row.report_id,
-                    report_type: row.report_type.to_string(),
-                    status: row.status,
-                    file_path: Some(format!("/reports/{}.pdf", row.report_id)),
-                    generated_at: row.generated_at,
-                    download_url: Some(format!("/reports/{}/download", row.report_id)),
-                }
-            }).collect();
-            Ok(Json(reports))
+}
+
+/// Refuse a request for a report that is still under embargo
+async fn check_embargo(
+    db: &PgPool,
+    report_id: Uuid,
+    access: Access,
+    attempt: &Attempt,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    match embargo_guard::guard(db, report_id, access, attempt).await {
+        Ok(None) => Ok(()),
+        Ok(Some(until)) => Err(embargoed(until)),
+        Err(e) => {
+            error!("Failed to check embargo of report {}: {}", report_id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))))
         }
+    }
+}
+
+/// Refuse to hand a report that has not been approved to anyone outside the platform
+async fn check_approved(db: &PgPool, report_id: Uuid) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    match approval::unapproved(db, report_id).await {
+        Ok(None) => Ok(()),
+        Ok(Some(status)) => Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": format!("report is {}; only approved reports leave the platform", status),
+                "status": status,
+            })),
+        )),
         Err(e) => {
-            error!("Failed to list reports: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            error!("Failed to check approval of report {}: {}", report_id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))))
+        }
+    }
+}
+
+/// Refuse a report that is not the tenant's, before its embargo or approval state can reveal that it exists
+async fn find_report(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_id: Uuid,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    match tenant_query!(
+        tenant,
+        "SELECT report_id FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = $2",
+        report_id
+    )
+    .fetch_optional(db)
+    .await
+    {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "report not found"})))),
+        Err(e) => {
+            error!("Failed to load report {}: {}", report_id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))))
         }
     }
 }
 
+fn embargo_error(e: EmbargoError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        EmbargoError::NotFound => StatusCode::NOT_FOUND,
+        EmbargoError::Conflict(_) => StatusCode::CONFLICT,
+        EmbargoError::Internal(e) => {
+            error!("Report embargo request failed: {:#}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})));
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+/// Per-metric changes from the `base` report to the `target` report, both the tenant's and of one type
+async fn compare_reports(
+    tenant: TenantContext,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<ReportComparison>, (StatusCode, Json<serde_json::Value>)> {
+    let report_id = |name: &str| {
+        params.get(name).and_then(|id| id.parse::<Uuid>().ok()).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({"error": format!("{} must be a report id", name)})),
+            )
+        })
+    };
+    let (base, target) = (report_id("base")?, report_id("target")?);
+    let comparison = match compare::compare(&state.db, &tenant, base, target).await {
+        Ok(comparison) => comparison,
+        Err(e @ CompareError::NotFound(_)) => {
+            return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))))
+        }
+        Err(e @ CompareError::DifferentTypes { .. }) => {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": [e.to_string()]}))))
+        }
+        Err(CompareError::Internal(e)) => {
+            error!("Failed to compare reports {} and {}: {}", base, target, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))));
+        }
+    };
+    let attempt = Attempt::from_headers(&headers);
+    check_embargo(&state.db, base, Access::View, &attempt).await?;
+    check_embargo(&state.db, target, Access::View, &attempt).await?;
+    Ok(Json(comparison))
+}
+
 async fn get_report(
     Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    match sqlx::query!(
-        "SELECT report_data FROM regulatory_reports_v2 WHERE report_id = $1",
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    // Read first so the reports of other tenants, embargoed or not, look the same as missing ones
+    let report = match tenant_query!(
+        &tenant,
+        "SELECT report_data FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = $2",
         report_id
     )
-    .fetch_one(&state.db)
-    .await {
-        Ok(row) => Ok(Json(row.report_data)),
-        Err(_) => Err(StatusCode::NOT_FOUND),
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(Some(report)) => report,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "report not found"})))),
+        Err(e) => {
+            error!("Failed to load report {}: {}", report_id, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))));
+        }
+    };
+    check_embargo(&state.db, report_id, Access::View, &Attempt::from_headers(&headers)).await?;
+    Ok(Json(report.report_data))
+}
+
+fn review_error(e: ReviewError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        ReviewError::NotFound => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))),
+        ReviewError::NotDraft(_) => (StatusCode::CONFLICT, Json(serde_json::json!({"error": e.to_string()}))),
+        ReviewError::OwnReport | ReviewError::NoMaker => {
+            (StatusCode::FORBIDDEN, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        ReviewError::Invalid(errors) => (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))),
+        ReviewError::Internal(e) => {
+            error!("Report review request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
     }
 }
 
-async fn download_report(
+/// Approve or reject a draft report, sending the deliveries held for its approval
+async fn review_report(
     Path(report_id): Path<Uuid>,
-    State(_state): State<AppState>,
-) -> Result<String, StatusCode> {
-    // In a real implementation, this would serve the actual file
-    Ok(format!("Report {} download would be served here", report_id))
-}
-
-async fn list_scheduled_reports() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "scheduled_reports": [
-            {
-                "name": "Daily Trading Summary",
-                "schedule": "0 0 6 * * *",
-                "enabled": true
-            },
-            {
-                "name": "Weekly Compliance Report",
-                "schedule": "0 0 6 * * 1",
-                "enabled": true
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(request): Json<ReviewRequest>,
+) -> Result<Json<ReportReview>, (StatusCode, Json<serde_json::Value>)> {
+    let (review, released) = review::review(&state.db, &tenant, caller.user_id(), report_id, request)
+        .await
+        .map_err(review_error)?;
+    info!(
+        "Report {} of tenant {} {} by {}",
+        report_id,
+        tenant,
+        review.status.to_lowercase(),
+        review.reviewed_by
+    );
+    for deliver_to in released {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let attempt = Attempt {
+                requested_by: deliver_to.requested_by,
+                ..Attempt::default()
+            };
+            // Embargoed since it was generated: the delivery waits for a request after the embargo
+            match embargo_guard::guard(&state.db, report_id, Access::Deliver, &attempt).await {
+                Ok(None) => {}
+                Ok(Some(_)) => return,
+                Err(e) => {
+                    error!("Failed to check embargo of report {}: {}", report_id, e);
+                    return;
+                }
             }
-        ]
-    }))
+            let delivered = state
+                .delivery
+                .deliver(&state.db, &state.report_files, report_id, &deliver_to)
+                .await;
+            if let Err(e) = delivered {
+                error!("Failed to deliver approved report {}: {}", report_id, e);
+            }
+        });
+    }
+    Ok(Json(review))
+}
+
+/// The report's review status and every status change it went through
+async fn get_report_review(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<ReviewHistory>, (StatusCode, Json<serde_json::Value>)> {
+    review::history(&state.db, &tenant, report_id).await.map(Json).map_err(review_error)
+}
+
+fn amend_error(e: AmendError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        AmendError::NotFound => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))),
+        AmendError::Superseded(superseded_by) => (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({"error": e.to_string(), "superseded_by": superseded_by})),
+        ),
+        AmendError::Invalid(errors) => (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))),
+        AmendError::Stopped(stopped) => stopped_error(&stopped),
+        AmendError::Internal(e) => {
+            error!("Report amendment request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+/// Supersede the current version of a report with an amendment, returning the chain it is now the head of
+async fn amend_report(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<AmendRequest>,
+) -> Result<(StatusCode, Json<ReportVersions>), (StatusCode, Json<serde_json::Value>)> {
+    request.amended_by = Some(caller.user_id());
+    let (amendment_id, version) =
+        amendments::amend(&state.db, &state.report_files, &state.generator, &tenant, report_id, request)
+            .await
+            .map_err(amend_error)?;
+    info!(
+        "Report {} of tenant {} amended by version {} ({})",
+        report_id, tenant, version, amendment_id
+    );
+    let versions = amendments::versions(&state.db, &tenant, amendment_id).await.map_err(amend_error)?;
+    Ok((StatusCode::CREATED, Json(versions)))
+}
+
+/// Every version of the report's amendment chain, from the first
+async fn list_report_versions(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<ReportVersions>, (StatusCode, Json<serde_json::Value>)> {
+    amendments::versions(&state.db, &tenant, report_id).await.map(Json).map_err(amend_error)
+}
+
+/// The rows behind a figure of the report, `limit` of them (100 by default, at most 1000) from `offset`
+async fn drilldown_report(
+    Path((report_id, metric)): Path<(Uuid, String)>,
+    tenant: TenantContext,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Drilldown>, (StatusCode, Json<serde_json::Value>)> {
+    let limit = params
+        .get("limit")
+        .and_then(|limit| limit.parse::<i64>().ok())
+        .unwrap_or(100)
+        .clamp(1, 1000);
+    let offset = params
+        .get("offset")
+        .and_then(|offset| offset.parse::<i64>().ok())
+        .unwrap_or(0)
+        .max(0);
+    let drilldown = match drilldown::drilldown(&state.db, &tenant, report_id, &metric, limit, offset).await {
+        Ok(drilldown) => drilldown,
+        Err(e @ DrilldownError::NotFound) => {
+            return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))))
+        }
+        Err(e @ DrilldownError::UnknownMetric { .. }) => {
+            return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))))
+        }
+        Err(DrilldownError::Internal(e)) => {
+            error!("Failed to drill into {} of report {}: {}", metric, report_id, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"}))));
+        }
+    };
+    check_embargo(&state.db, report_id, Access::View, &Attempt::from_headers(&headers)).await?;
+    Ok(Json(drilldown))
+}
+
+async fn download_report(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let internal = || (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})));
+    // Reports of other tenants look the same as missing ones
+    let report = match tenant_query!(
+        &tenant,
+        r#"
+        SELECT report_data, file_path, file_hash, file_size, file_deleted_at, file_archived_at
+        FROM regulatory_reports_v2
+        WHERE tenant_id = $1 AND report_id = $2
+        "#,
+        report_id
+    )
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(Some(report)) => report,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "report not found"})))),
+        Err(e) => {
+            error!("Failed to load report {}: {}", report_id, e);
+            return Err(internal());
+        }
+    };
+    check_embargo(&state.db, report_id, Access::Download, &Attempt::from_headers(&headers)).await?;
+
+    let columns = FileColumns {
+        file_path: report.file_path,
+        file_hash: report.file_hash,
+        file_size: report.file_size,
+        file_deleted_at: report.file_deleted_at,
+        file_archived_at: report.file_archived_at,
+    };
+    let download = match state.report_files.download(report_id, &columns, &report.report_data).await {
+        Ok(download) => download,
+        Err(e) if e.is::<FileExpired>() => {
+            return Err((StatusCode::GONE, Json(serde_json::json!({"error": e.to_string()}))));
+        }
+        Err(e) => {
+            error!("Failed to open report {}: {:#}", report_id, e);
+            return Err(internal());
+        }
+    };
+    download.respond(&headers).await.map_err(|e| {
+        error!("Failed to serve report {}: {:#}", report_id, e);
+        internal()
+    })
+}
+
+async fn deliver_report(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<DeliverReportRequest>,
+) -> Result<Json<DeliveryResponse>, (StatusCode, Json<serde_json::Value>)> {
+    request.tenant_id = tenant.tenant_id();
+    request.requested_by = Some(caller.user_id());
+    let errors = request.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+    let attempt = Attempt {
+        requested_by: request.requested_by,
+        ..Attempt::from_headers(&headers)
+    };
+    find_report(&state.db, &tenant, report_id).await?;
+    check_embargo(&state.db, report_id, Access::Deliver, &attempt).await?;
+    check_approved(&state.db, report_id).await?;
+
+    match state.delivery.deliver(&state.db, &state.report_files, report_id, &request).await {
+        Ok(Some(response)) => Ok(Json(response)),
+        Ok(None) => Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "report not found"})))),
+        Err(DeliveryError::NotConfigured(what)) => {
+            warn!("Rejected delivery of report {}: {} is not configured", report_id, what);
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(serde_json::json!({"error": format!("{} is not configured", what)})),
+            ))
+        }
+        Err(DeliveryError::Internal(e)) if e.is::<FileExpired>() => {
+            Err((StatusCode::GONE, Json(serde_json::json!({"error": e.to_string()}))))
+        }
+        Err(DeliveryError::Internal(e)) => {
+            error!("Failed to deliver report {}: {:#}", report_id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "delivery failed"}))))
+        }
+    }
+}
+
+async fn get_report_embargo(
+    Path(report_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<EmbargoDetail>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match embargo::get(&state.db, tenant_id, report_id).await {
+        Ok(Some(detail)) => Ok(Json(detail)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load embargo of report {}: {}", report_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Embargo a report, or move the time of a running embargo
+async fn set_report_embargo(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<SetEmbargoRequest>,
+) -> Result<Json<ReportEmbargo>, (StatusCode, Json<serde_json::Value>)> {
+    request.tenant_id = tenant.tenant_id();
+    request.set_by = Some(caller.user_id());
+    let errors = request.validate(&state.embargo_settings);
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+    embargo::set(&state.db, report_id, &request).await.map(Json).map_err(embargo_error)
+}
+
+/// Release an embargoed report early; the reason is kept with the embargo
+async fn lift_report_embargo(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<LiftEmbargoRequest>,
+) -> Result<Json<ReportEmbargo>, (StatusCode, Json<serde_json::Value>)> {
+    request.tenant_id = tenant.tenant_id();
+    request.lifted_by = Some(caller.user_id());
+    if request.reason.trim().is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({"errors": ["reason is required to lift an embargo"]})),
+        ));
+    }
+    embargo::lift(&state.db, report_id, &request).await.map(Json).map_err(embargo_error)
+}
+
+async fn list_report_deliveries(
+    Path(report_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DeliveryRecord>>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match delivery::list_deliveries(&state.db, tenant_id, Deliverable::Report(report_id)).await {
+        Ok(records) => Ok(Json(records)),
+        Err(e) => {
+            error!("Failed to list deliveries for report {}: {}", report_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// The tenant's next daily and weekly reports, timed from its business-day closes
+async fn list_scheduled_reports(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match schedule::upcoming(&state.db, &state.schedule_settings, tenant_id).await {
+        Ok(upcoming) => Ok(Json(serde_json::json!({ "scheduled_reports": upcoming }))),
+        Err(e) => {
+            error!("Failed to compute scheduled reports for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn schedule_error(e: ScheduleError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        ScheduleError::NotFound | ScheduleError::TenantNotFound => {
+            (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        ScheduleError::Invalid(errors) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors})))
+        }
+        ScheduleError::NameTaken(_) => (StatusCode::CONFLICT, Json(serde_json::json!({"error": e.to_string()}))),
+        ScheduleError::Internal(e) => {
+            error!("Report schedule request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+/// Register the change on this replica now; the others pick it up on their next sync
+async fn resync_schedules(state: &AppState) {
+    if let Err(e) = state.tenant_schedules.sync().await {
+        warn!("Failed to reload report schedules: {:#}", e);
+    }
+}
+
+async fn create_report_schedule(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<CreateScheduleRequest>,
+) -> Result<(StatusCode, Json<ReportSchedule>), (StatusCode, Json<serde_json::Value>)> {
+    request.created_by = Some(caller.user_id());
+    let schedule = tenant_schedules::create(&state.db, &state.report_files, &state.delivery, &tenant, request)
+        .await
+        .map_err(schedule_error)?;
+    info!("Created report schedule {} for tenant {}", schedule.schedule_id, tenant);
+    resync_schedules(&state).await;
+    Ok((StatusCode::CREATED, Json(schedule)))
+}
+
+/// How many of its next runs each schedule lists, `next_runs` of them (5 by default, at most 50)
+fn next_runs(params: &HashMap<String, String>) -> usize {
+    params
+        .get("next_runs")
+        .and_then(|count| count.parse::<usize>().ok())
+        .unwrap_or(tenant_schedules::DEFAULT_NEXT_RUNS)
+        .clamp(1, tenant_schedules::MAX_NEXT_RUNS)
+}
+
+async fn list_report_schedules(
+    tenant: TenantContext,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ReportSchedule>>, StatusCode> {
+    match tenant_schedules::list(&state.db, &tenant, next_runs(&params)).await {
+        Ok(schedules) => Ok(Json(schedules)),
+        Err(e) => {
+            error!("Failed to list report schedules for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_report_schedule(
+    Path(schedule_id): Path<Uuid>,
+    tenant: TenantContext,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<ReportSchedule>, (StatusCode, Json<serde_json::Value>)> {
+    tenant_schedules::get(&state.db, &tenant, schedule_id, next_runs(&params))
+        .await
+        .map(Json)
+        .map_err(schedule_error)
+}
+
+async fn update_report_schedule(
+    Path(schedule_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    Json(request): Json<UpdateScheduleRequest>,
+) -> Result<Json<ReportSchedule>, (StatusCode, Json<serde_json::Value>)> {
+    let schedule =
+        tenant_schedules::update(&state.db, &state.report_files, &state.delivery, &tenant, schedule_id, request)
+            .await
+            .map_err(schedule_error)?;
+    resync_schedules(&state).await;
+    Ok(Json(schedule))
+}
+
+async fn delete_report_schedule(
+    Path(schedule_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<serde_json::Value>)> {
+    tenant_schedules::delete(&state.db, &tenant, schedule_id)
+        .await
+        .map_err(schedule_error)?;
+    info!("Deleted report schedule {} of tenant {}", schedule_id, tenant);
+    resync_schedules(&state).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_report_schedule_timezone(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<TenantTimezone>, (StatusCode, Json<serde_json::Value>)> {
+    tenant_schedules::timezone(&state.db, &tenant)
+        .await
+        .map(Json)
+        .map_err(|e| schedule_error(e.into()))
+}
+
+/// Set the time zone of the tenant's schedules that do not name their own
+async fn set_report_schedule_timezone(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    Json(request): Json<SetTimezoneRequest>,
+) -> Result<Json<TenantTimezone>, (StatusCode, Json<serde_json::Value>)> {
+    let timezone = tenant_schedules::set_timezone(&state.db, &tenant, request)
+        .await
+        .map_err(schedule_error)?;
+    info!(
+        "Report schedules of tenant {} now run in {} ({} following it)",
+        tenant, timezone.timezone, timezone.schedules_following
+    );
+    resync_schedules(&state).await;
+    Ok(Json(timezone))
+}
+
+fn sftp_error(e: SftpError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        SftpError::NotConfigured => {
+            warn!("Rejected SFTP request: {}", e);
+            (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        SftpError::NotFound(_) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))),
+        SftpError::Invalid(errors) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors})))
+        }
+        SftpError::NameTaken(_) | SftpError::Inactive(_) => {
+            (StatusCode::CONFLICT, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        SftpError::Internal(e) if e.is::<FileExpired>() => {
+            (StatusCode::GONE, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        SftpError::Internal(e) => {
+            error!("SFTP request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+async fn create_sftp_target(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<CreateTargetRequest>,
+) -> Result<(StatusCode, Json<SftpTarget>), (StatusCode, Json<serde_json::Value>)> {
+    request.created_by = Some(caller.user_id());
+    let target = sftp::create_target(&state.db, &state.sftp_settings, &tenant, request)
+        .await
+        .map_err(sftp_error)?;
+    info!("Created SFTP target {} for tenant {}", target.target_id, tenant);
+    Ok((StatusCode::CREATED, Json(target)))
+}
+
+async fn list_sftp_targets(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SftpTarget>>, StatusCode> {
+    match sftp::list_targets(&state.db, &tenant).await {
+        Ok(targets) => Ok(Json(targets)),
+        Err(e) => {
+            error!("Failed to list SFTP targets for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_sftp_target(
+    Path(target_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<SftpTarget>, (StatusCode, Json<serde_json::Value>)> {
+    sftp::get_target(&state.db, &tenant, target_id).await.map(Json).map_err(sftp_error)
+}
+
+async fn update_sftp_target(
+    Path(target_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    Json(request): Json<UpdateTargetRequest>,
+) -> Result<Json<SftpTarget>, (StatusCode, Json<serde_json::Value>)> {
+    sftp::update_target(&state.db, &state.sftp_settings, &tenant, target_id, request)
+        .await
+        .map(Json)
+        .map_err(sftp_error)
+}
+
+async fn delete_sftp_target(
+    Path(target_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<serde_json::Value>)> {
+    sftp::delete_target(&state.db, &tenant, target_id).await.map_err(sftp_error)?;
+    info!("Deleted SFTP target {} of tenant {}", target_id, tenant);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Upload the report to one of the tenant's SFTP targets; a failed upload answers 502 with its receipt
+async fn deliver_report_sftp(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<SftpDeliveryRequest>,
+) -> Result<(StatusCode, Json<SftpReceipt>), (StatusCode, Json<serde_json::Value>)> {
+    request.requested_by = Some(caller.user_id());
+    let attempt = Attempt {
+        requested_by: request.requested_by,
+        ..Attempt::from_headers(&headers)
+    };
+    find_report(&state.db, &tenant, report_id).await?;
+    check_embargo(&state.db, report_id, Access::Deliver, &attempt).await?;
+    check_approved(&state.db, report_id).await?;
+
+    let receipt = sftp::deliver(&state.db, &state.report_files, &state.sftp_settings, &tenant, report_id, &request)
+        .await
+        .map_err(sftp_error)?;
+    let status = if receipt.status == "UPLOADED" { StatusCode::CREATED } else { StatusCode::BAD_GATEWAY };
+    Ok((status, Json(receipt)))
+}
+
+async fn list_report_sftp_deliveries(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SftpReceipt>>, StatusCode> {
+    match sftp::list_receipts(&state.db, &tenant, report_id).await {
+        Ok(receipts) => Ok(Json(receipts)),
+        Err(e) => {
+            error!("Failed to list SFTP deliveries for report {}: {}", report_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn retention_error(e: RetentionError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        RetentionError::NotFound(_) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))),
+        RetentionError::Invalid(errors) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors})))
+        }
+        RetentionError::AlreadyHeld | RetentionError::NotHeld => {
+            (StatusCode::CONFLICT, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        RetentionError::Internal(e) => {
+            error!("Report retention request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+async fn list_retention_policies(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RetentionPolicy>>, StatusCode> {
+    match retention::list_policies(&state.db, &tenant).await {
+        Ok(policies) => Ok(Json(policies)),
+        Err(e) => {
+            error!("Failed to list retention policies for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Set the retention of a report type, or of the types without their own as DEFAULT
+async fn set_retention_policy(
+    Path(report_type): Path<String>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<SetPolicyRequest>,
+) -> Result<Json<RetentionPolicy>, (StatusCode, Json<serde_json::Value>)> {
+    request.updated_by = Some(caller.user_id());
+    let policy = retention::set_policy(&state.db, state.report_files.archives(), &tenant, &report_type, request)
+        .await
+        .map_err(retention_error)?;
+    info!(
+        "Set retention of {} reports of tenant {} to {} days, then {}",
+        policy.report_type, tenant, policy.retention_days, policy.action
+    );
+    Ok(Json(policy))
+}
+
+async fn delete_retention_policy(
+    Path(report_type): Path<String>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<serde_json::Value>)> {
+    retention::delete_policy(&state.db, &tenant, &report_type).await.map_err(retention_error)?;
+    info!("Removed the {} retention policy of tenant {}", report_type, tenant);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_report_legal_hold(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<LegalHold>, (StatusCode, Json<serde_json::Value>)> {
+    retention::legal_hold(&state.db, &tenant, report_id).await.map(Json).map_err(retention_error)
+}
+
+/// Keep the report and its file until the hold is released, whatever their retention
+async fn place_report_legal_hold(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<PlaceHoldRequest>,
+) -> Result<Json<LegalHold>, (StatusCode, Json<serde_json::Value>)> {
+    request.placed_by = Some(caller.user_id());
+    let hold = retention::place_hold(&state.db, &tenant, report_id, request)
+        .await
+        .map_err(retention_error)?;
+    info!("Placed report {} of tenant {} under legal hold", report_id, tenant);
+    Ok(Json(hold))
+}
+
+async fn release_report_legal_hold(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<ReleaseHoldRequest>,
+) -> Result<Json<LegalHold>, (StatusCode, Json<serde_json::Value>)> {
+    request.released_by = Some(caller.user_id());
+    let hold = retention::release_hold(&state.db, &tenant, report_id, request)
+        .await
+        .map_err(retention_error)?;
+    info!("Released the legal hold on report {} of tenant {}", report_id, tenant);
+    Ok(Json(hold))
+}
+
+fn signing_error(e: SigningError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        SigningError::NotConfigured => {
+            warn!("Rejected report signing request: {}", e);
+            (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        SigningError::NotFound(_) => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))),
+        SigningError::Invalid(errors) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors})))
+        }
+        SigningError::Internal(e) if e.is::<FileExpired>() => {
+            (StatusCode::GONE, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        SigningError::Internal(e) => {
+            error!("Report signing request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+async fn get_signing_certificate(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<SigningCertificate>, (StatusCode, Json<serde_json::Value>)> {
+    signing::get_certificate(&state.db, &tenant).await.map(Json).map_err(signing_error)
+}
+
+/// Sign the tenant's reports from now on with the certificate, replacing any it had
+async fn set_signing_certificate(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<SetCertificateRequest>,
+) -> Result<Json<SigningCertificate>, (StatusCode, Json<serde_json::Value>)> {
+    request.updated_by = Some(caller.user_id());
+    let certificate = signing::set_certificate(&state.db, state.report_files.signing(), &tenant, request)
+        .await
+        .map_err(signing_error)?;
+    info!(
+        "Set signing certificate {} ({}) for tenant {}",
+        certificate.fingerprint, certificate.subject, tenant
+    );
+    Ok(Json(certificate))
+}
+
+async fn delete_signing_certificate(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<serde_json::Value>)> {
+    signing::delete_certificate(&state.db, &tenant).await.map_err(signing_error)?;
+    info!("Removed the signing certificate of tenant {}", tenant);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Check the stored file of a report against its signature
+async fn get_report_signature(
+    Path(report_id): Path<Uuid>,
+    tenant: TenantContext,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<SignatureCheck>, (StatusCode, Json<serde_json::Value>)> {
+    check_embargo(&state.db, report_id, Access::View, &Attempt::from_headers(&headers)).await?;
+    signing::verify_report(&state.db, &state.report_files, &tenant, report_id)
+        .await
+        .map(Json)
+        .map_err(signing_error)
+}
+
+/// Check a report file sent as the request body, such as one a regulator received
+async fn verify_report_signature(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<Json<SignatureCheck>, (StatusCode, Json<serde_json::Value>)> {
+    if body.is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(serde_json::json!({"errors": ["the report file is required as the request body"]})),
+        ));
+    }
+    signing::verify_upload(&state.db, state.report_files.signing(), &tenant, &body)
+        .await
+        .map(Json)
+        .map_err(signing_error)
+}
+
+fn webhook_error(e: WebhookError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        WebhookError::NotFound => (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()}))),
+        WebhookError::Invalid(errors) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors})))
+        }
+        WebhookError::Internal(e) => {
+            error!("Report webhook request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+/// Register a webhook; its signing secret is only returned here and on rotation
+async fn create_report_webhook(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<WebhookWithSecret>), (StatusCode, Json<serde_json::Value>)> {
+    request.created_by = Some(caller.user_id());
+    let created = webhooks::create(&state.db, &tenant, request).await.map_err(webhook_error)?;
+    info!("Registered report webhook {} for tenant {}", created.webhook.webhook_id, tenant);
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+async fn list_report_webhooks(
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ReportWebhook>>, StatusCode> {
+    match webhooks::list(&state.db, &tenant).await {
+        Ok(webhooks) => Ok(Json(webhooks)),
+        Err(e) => {
+            error!("Failed to list report webhooks for tenant {}: {}", tenant, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_report_webhook(
+    Path(webhook_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<ReportWebhook>, (StatusCode, Json<serde_json::Value>)> {
+    webhooks::get(&state.db, &tenant, webhook_id).await.map(Json).map_err(webhook_error)
+}
+
+async fn update_report_webhook(
+    Path(webhook_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    Json(request): Json<UpdateWebhookRequest>,
+) -> Result<Json<ReportWebhook>, (StatusCode, Json<serde_json::Value>)> {
+    webhooks::update(&state.db, &tenant, webhook_id, request)
+        .await
+        .map(Json)
+        .map_err(webhook_error)
+}
+
+async fn delete_report_webhook(
+    Path(webhook_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<serde_json::Value>)> {
+    webhooks::delete(&state.db, &tenant, webhook_id).await.map_err(webhook_error)?;
+    info!("Deleted report webhook {} of tenant {}", webhook_id, tenant);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn rotate_report_webhook_secret(
+    Path(webhook_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<WebhookWithSecret>, (StatusCode, Json<serde_json::Value>)> {
+    let rotated = webhooks::rotate_secret(&state.db, &tenant, webhook_id).await.map_err(webhook_error)?;
+    info!("Rotated the secret of report webhook {} of tenant {}", webhook_id, tenant);
+    Ok(Json(rotated))
+}
+
+/// The webhook's latest deliveries, `limit` of them (100 by default, at most 1000)
+async fn list_report_webhook_deliveries(
+    Path(webhook_id): Path<Uuid>,
+    tenant: TenantContext,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WebhookDelivery>>, (StatusCode, Json<serde_json::Value>)> {
+    let limit = params
+        .get("limit")
+        .and_then(|limit| limit.parse::<i64>().ok())
+        .unwrap_or(100)
+        .clamp(1, 1000);
+    webhooks::list_deliveries(&state.db, &tenant, webhook_id, limit)
+        .await
+        .map(Json)
+        .map_err(webhook_error)
+}
+
+fn layout_error(e: LayoutError) -> (StatusCode, Json<serde_json::Value>) {
+    match e {
+        LayoutError::UnknownReportType(_) | LayoutError::NotFound => {
+            (StatusCode::NOT_FOUND, Json(serde_json::json!({"error": e.to_string()})))
+        }
+        LayoutError::Invalid(errors) => (StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))),
+        LayoutError::Internal(e) => {
+            error!("Report layout request failed: {:#}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})))
+        }
+    }
+}
+
+/// Upload a new version of the tenant's layout for the report type
+async fn create_report_layout(
+    Path(report_type): Path<String>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<CreateLayoutRequest>,
+) -> Result<(StatusCode, Json<LayoutVersion>), (StatusCode, Json<serde_json::Value>)> {
+    request.created_by = Some(caller.user_id());
+    let created = layouts::create(&state.db, &state.report_files, &tenant, &report_type, request)
+        .await
+        .map_err(layout_error)?;
+    info!(
+        "Saved version {} of the {} layout of tenant {}{}",
+        created.version,
+        report_type,
+        tenant,
+        if created.is_current { ", now current" } else { "" }
+    );
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+async fn list_report_layouts(
+    Path(report_type): Path<String>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LayoutVersion>>, (StatusCode, Json<serde_json::Value>)> {
+    layouts::list(&state.db, &tenant, &report_type).await.map(Json).map_err(layout_error)
+}
+
+/// The built-in template and sections of the report type
+async fn get_default_report_layout(
+    Path(report_type): Path<String>,
+) -> Result<Json<DefaultLayout>, (StatusCode, Json<serde_json::Value>)> {
+    layouts::default_layout(&report_type).map(Json).map_err(layout_error)
+}
+
+async fn get_report_layout(
+    Path((report_type, version)): Path<(String, i32)>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<LayoutVersion>, (StatusCode, Json<serde_json::Value>)> {
+    layouts::get(&state.db, &tenant, &report_type, version).await.map(Json).map_err(layout_error)
+}
+
+async fn activate_report_layout(
+    Path((report_type, version)): Path<(String, i32)>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<Json<LayoutVersion>, (StatusCode, Json<serde_json::Value>)> {
+    let activated = layouts::activate(&state.db, &tenant, &report_type, version).await.map_err(layout_error)?;
+    info!("Made version {} the current {} layout of tenant {}", version, report_type, tenant);
+    Ok(Json(activated))
+}
+
+/// Return the tenant to the built-in layout of the report type
+async fn reset_report_layout(
+    Path(report_type): Path<String>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<serde_json::Value>)> {
+    layouts::reset(&state.db, &tenant, &report_type).await.map_err(layout_error)?;
+    info!("Tenant {} is back on the built-in {} layout", tenant, report_type);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A PDF of the report type in a draft, saved or the current layout
+async fn preview_report_layout(
+    Path(report_type): Path<String>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    Json(request): Json<PreviewRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let pdf = layouts::preview(&state.db, &state.report_files, &tenant, &report_type, request)
+        .await
+        .map_err(layout_error)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, ReportFormat::Pdf.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, "inline; filename=\"preview.pdf\"".to_string()),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        pdf,
+    )
+        .into_response())
+}
+
+/// Issue an external recipient a token for downloading the listed reports
+async fn issue_access_token(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<IssueTokenRequest>,
+) -> Result<(StatusCode, Json<IssuedToken>), (StatusCode, Json<serde_json::Value>)> {
+    request.created_by = Some(caller.user_id());
+    let errors = request.validate(&state.portal_settings);
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+
+    let tenant_id = request.tenant_id;
+    match portal::issue(&state.db, &state.portal_settings, &state.delivery, request).await {
+        Ok(Ok(issued)) => Ok((StatusCode::CREATED, Json(issued))),
+        Ok(Err(errors)) => Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors})))),
+        Err(DeliveryError::NotConfigured(what)) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": format!("{} is not configured", what)})),
+        )),
+        Err(DeliveryError::Internal(e)) => {
+            error!("Failed to issue report access token for tenant {}: {:#}", tenant_id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "failed to issue token"}))))
+        }
+    }
+}
+
+async fn list_access_tokens(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AccessToken>>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match portal::list(&state.db, tenant_id).await {
+        Ok(tokens) => Ok(Json(tokens)),
+        Err(e) => {
+            error!("Failed to list report access tokens for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn revoke_access_token(
+    Path(token_id): Path<Uuid>,
+    tenant: TenantContext,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<RevokeTokenRequest>,
+) -> Result<Json<AccessToken>, StatusCode> {
+    request.tenant_id = tenant.tenant_id();
+    request.revoked_by = Some(caller.user_id());
+    match portal::revoke(&state.db, token_id, &request).await {
+        Ok(Some(token)) => Ok(Json(token)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to revoke report access token {}: {}", token_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_access_log(
+    Path(token_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AccessLogEntry>>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match portal::access_log(&state.db, tenant_id, token_id).await {
+        Ok(Some(entries)) => Ok(Json(entries)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load access log of token {}: {}", token_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn portal_error(e: PortalError) -> (StatusCode, Json<serde_json::Value>) {
+    let status = match &e {
+        PortalError::Unauthorized => StatusCode::UNAUTHORIZED,
+        PortalError::Refused(_) | PortalError::Embargoed(_) => StatusCode::FORBIDDEN,
+        PortalError::NotFound => StatusCode::NOT_FOUND,
+        PortalError::Expired(_) => StatusCode::GONE,
+        PortalError::Internal(e) => {
+            error!("Report portal request failed: {:#}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "internal error"})));
+        }
+    };
+    (status, Json(serde_json::json!({"error": e.to_string()})))
+}
+
+/// Reports granted by the presented access token
+async fn portal_list_reports(
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<PortalListing>, (StatusCode, Json<serde_json::Value>)> {
+    let requester = Requester::from_request(&headers, params.get("token"));
+    portal::portal_reports(&state.db, &requester).await.map(Json).map_err(portal_error)
+}
+
+async fn portal_download_report(
+    Path(report_id): Path<Uuid>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let requester = Requester::from_request(&headers, params.get("token"));
+    let file = portal::download(&state.db, &state.report_files, &requester, report_id)
+        .await
+        .map_err(portal_error)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, file.content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file.name)),
+            (header::CACHE_CONTROL, "no-store".to_string()),
+        ],
+        file.contents,
+    )
+        .into_response())
+}
+
+/// Queue a tenant takeout; it is emailed to the recipients once built
+async fn create_export(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(mut request): Json<CreateExportRequest>,
+) -> Result<(StatusCode, Json<TenantExport>), (StatusCode, Json<serde_json::Value>)> {
+    request.requested_by = Some(caller.user_id());
+    let errors = request.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+    if let Err(DeliveryError::NotConfigured(what)) = state.delivery.ensure_available(&request.delivery()) {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": format!("{} is not configured", what)})),
+        ));
+    }
+
+    match takeout::create(&state.db, &request).await {
+        Ok(export) => Ok((StatusCode::ACCEPTED, Json(export))),
+        Err(e) => {
+            error!("Failed to queue takeout for tenant {}: {:#}", request.tenant_id, e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "failed to queue export"}))))
+        }
+    }
+}
+
+async fn list_exports(
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TenantExport>>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match takeout::list(&state.db, tenant_id).await {
+        Ok(exports) => Ok(Json(exports)),
+        Err(e) => {
+            error!("Failed to list takeouts for tenant {}: {}", tenant_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_export(
+    Path(export_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<TenantExport>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match takeout::get(&state.db, tenant_id, export_id).await {
+        Ok(Some(export)) => Ok(Json(export)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load takeout {}: {}", export_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Send a completed takeout again, e.g. to a recipient who lost the password
+async fn deliver_export(
+    Path(export_id): Path<Uuid>,
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(request): Json<DeliverReportRequest>,
+) -> Result<Json<Vec<RecipientDelivery>>, (StatusCode, Json<serde_json::Value>)> {
+    let requested_by = Some(caller.user_id());
+    let request = takeout::delivery_request(request.tenant_id, requested_by, request.recipients, request.message);
+    let errors = request.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+
+    let internal = |e: &dyn std::fmt::Display| {
+        error!("Failed to deliver takeout {}: {:#}", export_id, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({"error": "delivery failed"})))
+    };
+    let export = match takeout::get(&state.db, request.tenant_id, export_id).await {
+        Ok(Some(export)) => export,
+        Ok(None) => return Err((StatusCode::NOT_FOUND, Json(serde_json::json!({"error": "export not found"})))),
+        Err(e) => return Err(internal(&e)),
+    };
+    let package = match takeout::package(&state.db, &export).await {
+        Ok(Some(package)) => package,
+        Ok(None) => {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({"error": format!("export is {}", export.status)})),
+            ))
+        }
+        Err(e) => return Err(internal(&e)),
+    };
+
+    match state
+        .delivery
+        .deliver_package(&state.db, Deliverable::Export(export_id), &request, &package)
+        .await
+    {
+        Ok((_, deliveries)) => Ok(Json(deliveries)),
+        Err(DeliveryError::NotConfigured(what)) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": format!("{} is not configured", what)})),
+        )),
+        Err(DeliveryError::Internal(e)) => Err(internal(&e)),
+    }
+}
+
+async fn list_export_deliveries(
+    Path(export_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DeliveryRecord>>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match delivery::list_deliveries(&state.db, tenant_id, Deliverable::Export(export_id)).await {
+        Ok(records) => Ok(Json(records)),
+        Err(e) => {
+            error!("Failed to list deliveries for takeout {}: {}", export_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn export_template(
+    Path(template_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+) -> Result<Json<TemplateBundle>, StatusCode> {
+    let tenant_id = params.get("tenant_id")
+        .and_then(|s| Uuid::parse_str(s).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    match template_bundles::export_template(&state.db, &state.bundle_signer, tenant_id, template_id).await {
+        Ok(Some(bundle)) => Ok(Json(bundle)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to export template {}: {}", template_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn import_template(
+    State(state): State<AppState>,
+    Json(request): Json<ImportTemplateRequest>,
+) -> Result<Json<TemplateImportResponse>, (StatusCode, Json<TemplateImportResponse>)> {
+    let tenant_id = request.tenant_id;
+
+    match template_bundles::import_template(&state.db, &state.bundle_signer, request).await {
+        Ok(response) if response.template_id.is_some() => {
+            info!("Imported report template bundle for tenant: {}", tenant_id);
+            Ok(Json(response))
+        }
+        Ok(response) => {
+            warn!("Rejected report template bundle for tenant {}: {:?}", tenant_id, response.errors);
+            Err((StatusCode::UNPROCESSABLE_ENTITY, Json(response)))
+        }
+        Err(e) => {
+            error!("Failed to import template bundle: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(TemplateImportResponse {
+                    status: "FAILED".to_string(),
+                    template_id: None,
+                    errors: vec!["internal error while importing bundle".to_string()],
+                }),
+            ))
+        }
+    }
 }
@@ -0,0 +1,273 @@
+//! Signatures embedded in PDF report files
+//!
+//! A PDF is signed the way readers such as Acrobat check it: an invisible
+//! signature field on the first page whose value is an `adbe.pkcs7.detached`
+//! signature dictionary. The dictionary's /Contents is reserved as
+//! SIGNATURE_SPACE bytes of zeros and its /ByteRange as wide numbers, the file
+//! is written once, and then the byte range is filled in with everything
+//! around /Contents, which is what gets signed. The signature is written into
+//! the reserved space without moving a byte, so the signed ranges stay valid.
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use lopdf::{Dictionary, Document, Object, StringFormat};
+
+/// Bytes reserved for the DER signature, enough for a certificate chain of a few certificates
+const SIGNATURE_SPACE: usize = 16384;
+/// Written as the byte range until the file is laid out, wide enough for any offset it is replaced with
+const RANGE_PLACEHOLDER: i64 = 1_000_000_000_000;
+
+/// Shown by PDF readers with the signature
+pub struct SignatureInfo<'a> {
+    pub signer_name: &'a str,
+    pub reason: &'a str,
+    pub signed_at: DateTime<Utc>,
+}
+
+/// A PDF laid out with space for its signature
+pub struct Prepared {
+    pdf: Vec<u8>,
+    /// Offset and length of the two signed ranges
+    byte_range: [usize; 4],
+}
+
+impl Prepared {
+    /// What the signature covers: the whole file except the reserved /Contents
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let [start, first, second, length] = self.byte_range;
+        [&self.pdf[start..start + first], &self.pdf[second..second + length]].concat()
+    }
+
+    /// The file with the DER signature written into its reserved space
+    pub fn finish(mut self, signature: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if signature.len() > SIGNATURE_SPACE {
+            anyhow::bail!(
+                "signature of {} bytes does not fit the {} reserved in the PDF",
+                signature.len(),
+                SIGNATURE_SPACE
+            );
+        }
+        // Just inside the < and > around the hex string
+        let at = self.byte_range[1] + 1;
+        let hex = hex::encode_upper(signature);
+        self.pdf[at..at + hex.len()].copy_from_slice(hex.as_bytes());
+        Ok(self.pdf)
+    }
+}
+
+/// Add a signature field to the PDF and lay it out with space for the signature
+pub fn prepare(pdf: &[u8], info: &SignatureInfo) -> anyhow::Result<Prepared> {
+    let mut document = Document::load_mem(pdf).context("failed to read the rendered PDF")?;
+    let (_, first_page) = document
+        .get_pages()
+        .into_iter()
+        .next()
+        .context("the rendered PDF has no pages")?;
+
+    let mut signature = Dictionary::new();
+    signature.set("Type", Object::Name(b"Sig".to_vec()));
+    signature.set("Filter", Object::Name(b"Adobe.PPKLite".to_vec()));
+    signature.set("SubFilter", Object::Name(b"adbe.pkcs7.detached".to_vec()));
+    signature.set("Name", Object::string_literal(info.signer_name));
+    signature.set("Reason", Object::string_literal(info.reason));
+    signature.set(
+        "M",
+        Object::string_literal(info.signed_at.format("D:%Y%m%d%H%M%S+00'00'").to_string()),
+    );
+    signature.set(
+        "ByteRange",
+        Object::Array(vec![
+            Object::Integer(0),
+            Object::Integer(RANGE_PLACEHOLDER),
+            Object::Integer(RANGE_PLACEHOLDER),
+            Object::Integer(RANGE_PLACEHOLDER),
+        ]),
+    );
+    signature.set("Contents", Object::String(vec![0; SIGNATURE_SPACE], StringFormat::Hexadecimal));
+    let signature_id = document.add_object(signature);
+
+    let mut field = Dictionary::new();
+    field.set("Type", Object::Name(b"Annot".to_vec()));
+    field.set("Subtype", Object::Name(b"Widget".to_vec()));
+    field.set("FT", Object::Name(b"Sig".to_vec()));
+    field.set("T", Object::string_literal("Signature1"));
+    field.set("V", Object::Reference(signature_id));
+    field.set("F", Object::Integer(132));
+    field.set("P", Object::Reference(first_page));
+    field.set(
+        "Rect",
+        Object::Array(vec![0.into(), 0.into(), 0.into(), 0.into()]),
+    );
+    let field_id = document.add_object(field);
+
+    // On the first page's annotations, which may be an array of their own
+    let annots = document
+        .get_object(first_page)
+        .and_then(Object::as_dict)
+        .ok()
+        .and_then(|page| page.get(b"Annots").ok())
+        .cloned();
+    match annots {
+        Some(Object::Reference(annots_id)) => {
+            document
+                .get_object_mut(annots_id)
+                .and_then(Object::as_array_mut)
+                .context("the first page's annotations are not an array")?
+                .push(Object::Reference(field_id));
+        }
+        annots => {
+            let mut annots = match annots {
+                Some(Object::Array(annots)) => annots,
+                _ => Vec::new(),
+            };
+            annots.push(Object::Reference(field_id));
+            document
+                .get_object_mut(first_page)
+                .and_then(Object::as_dict_mut)
+                .context("the first page is not a dictionary")?
+                .set("Annots", Object::Array(annots));
+        }
+    }
+
+    let mut form = Dictionary::new();
+    form.set("Fields", Object::Array(vec![Object::Reference(field_id)]));
+    // Signatures exist, and the file is only appended to from here on
+    form.set("SigFlags", Object::Integer(3));
+    let root = document
+        .trailer
+        .get(b"Root")
+        .and_then(Object::as_reference)
+        .context("the rendered PDF has no catalog")?;
+    document
+        .get_object_mut(root)
+        .and_then(Object::as_dict_mut)
+        .context("the PDF catalog is not a dictionary")?
+        .set("AcroForm", Object::Dictionary(form));
+
+    let mut pdf = Vec::new();
+    document.save_to(&mut pdf).context("failed to write the PDF")?;
+
+    let contents_start = find_reserved_contents(&pdf).context("the reserved signature space was not written")?;
+    let contents_end = contents_start + 2 * SIGNATURE_SPACE + 2;
+    let byte_range = [0, contents_start, contents_end, pdf.len() - contents_end];
+
+    let (range_start, range_end) = find_byte_range(&pdf, 0).context("the reserved byte range was not written")?;
+    let mut filled = format!(
+        "[{} {} {} {}",
+        byte_range[0], byte_range[1], byte_range[2], byte_range[3]
+    )
+    .into_bytes();
+    let width = range_end - range_start;
+    if filled.len() + 1 > width {
+        anyhow::bail!("the PDF is too large to sign");
+    }
+    filled.resize(width - 1, b' ');
+    filled.push(b']');
+    pdf[range_start..range_end].copy_from_slice(&filled);
+
+    Ok(Prepared { pdf, byte_range })
+}
+
+/// Where the `<` of the zeroed /Contents of the signature dictionary is
+fn find_reserved_contents(pdf: &[u8]) -> Option<usize> {
+    let key = b"/Contents";
+    let mut from = 0;
+    while let Some(offset) = find(&pdf[from..], key) {
+        let mut at = from + offset + key.len();
+        while pdf.get(at).is_some_and(u8::is_ascii_whitespace) {
+            at += 1;
+        }
+        let reserved = pdf.get(at + 1..at + 1 + 2 * SIGNATURE_SPACE);
+        if pdf.get(at) == Some(&b'<')
+            && reserved.is_some_and(|hex| hex.iter().all(|byte| *byte == b'0'))
+            && pdf.get(at + 1 + 2 * SIGNATURE_SPACE) == Some(&b'>')
+        {
+            return Some(at);
+        }
+        from = at;
+    }
+    None
+}
+
+/// The `[` to past the `]` of a /ByteRange array at or after `from`
+fn find_byte_range(pdf: &[u8], from: usize) -> Option<(usize, usize)> {
+    let key = b"/ByteRange";
+    let at = from + find(&pdf[from..], key)? + key.len();
+    let start = at + pdf[at..].iter().position(|byte| *byte == b'[')?;
+    let end = start + pdf[start..].iter().position(|byte| *byte == b']')? + 1;
+    Some((start, end))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// A signature found in a PDF
+pub struct Embedded {
+    /// The DER signature, without the padding after it
+    pub signature: Vec<u8>,
+    pub signed_bytes: Vec<u8>,
+    /// Whether the signed ranges reach the end of the file, so nothing was appended after signing
+    pub covers_whole_file: bool,
+}
+
+/// The last signature of the PDF, or `None` when it is not signed
+pub fn extract(pdf: &[u8]) -> anyhow::Result<Option<Embedded>> {
+    let mut last = None;
+    let mut from = 0;
+    while let Some((start, end)) = find_byte_range(pdf, from) {
+        last = Some((start, end));
+        from = end;
+    }
+    let Some((start, end)) = last else {
+        return Ok(None);
+    };
+    let numbers: Vec<usize> = std::str::from_utf8(&pdf[start + 1..end - 1])
+        .context("the signature byte range is not text")?
+        .split_whitespace()
+        .map(|number| number.parse::<usize>())
+        .collect::<Result<_, _>>()
+        .context("the signature byte range is not a list of offsets")?;
+    let [first_start, first_length, second_start, second_length] = numbers[..] else {
+        anyhow::bail!("the signature byte range does not have four offsets");
+    };
+    if first_start + first_length > second_start || second_start + second_length > pdf.len() {
+        anyhow::bail!("the signature byte range lies outside the file");
+    }
+    let hex = &pdf[first_start + first_length..second_start];
+    let hex = hex
+        .strip_prefix(b"<")
+        .and_then(|hex| hex.strip_suffix(b">"))
+        .context("the signature is not a hex string between the signed ranges")?;
+    let padded = hex::decode(hex).context("the signature is not hex")?;
+    let length = der_length(&padded).context("the signature is not DER")?;
+    Ok(Some(Embedded {
+        signature: padded[..length].to_vec(),
+        signed_bytes: [
+            &pdf[first_start..first_start + first_length],
+            &pdf[second_start..second_start + second_length],
+        ]
+        .concat(),
+        covers_whole_file: first_start == 0 && second_start + second_length == pdf.len(),
+    }))
+}
+
+/// Length of the DER value at the start of `der`, header included
+fn der_length(der: &[u8]) -> Option<usize> {
+    let first = *der.get(1)?;
+    let (header, length) = if first & 0x80 == 0 {
+        (2, usize::from(first))
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let length = der
+            .get(2..2 + count)?
+            .iter()
+            .fold(0usize, |length, byte| (length << 8) | usize::from(*byte));
+        (2 + count, length)
+    };
+    let total = header + length;
+    (total <= der.len()).then_some(total)
+}
@@ -0,0 +1,631 @@
+//! Report recipient access portal
+//!
+//! A tenant can issue an external party, such as an auditor or an exchange
+//! inspector, a bearer token that downloads a fixed set of reports and nothing
+//! else. Tokens expire, may be limited to a number of downloads and can be
+//! revoked at any time. Only the token's SHA-256 is stored; the token itself is
+//! returned once when issued and, on request, emailed to the recipient.
+//!
+//! Every use of a token is written to `report_access_log`, including refused
+//! ones, so the tenant can show who fetched what and when. Granted reports
+//! under embargo are listed with the time they become available and cannot be
+//! downloaded before it, and reports that have not been approved cannot be
+//! downloaded at all.
+
+use anyhow::Context;
+use axum::http::{header, HeaderMap};
+use chrono::{DateTime, NaiveDate, Utc};
+use dharmaguard_common::approval;
+use dharmaguard_common::embargo::{self, Access, Attempt};
+use lettre::message::Mailbox;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::net::IpAddr;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::delivery::{DeliveryError, ReportDelivery};
+use crate::report_files::{FileColumns, FileExpired, ReportFile, ReportFiles};
+
+/// Prefix that makes leaked tokens easy to recognise in logs and secret scanners
+const TOKEN_PREFIX: &str = "dgr_";
+const MAX_REPORTS_PER_TOKEN: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct PortalSettings {
+    /// Public origin of the API gateway, used to build the links sent to recipients
+    pub base_url: Option<String>,
+    pub default_ttl: chrono::Duration,
+    pub max_ttl: chrono::Duration,
+}
+
+impl PortalSettings {
+    pub fn from_env() -> Self {
+        let days = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+                .max(1)
+        };
+        let max_days = days("REPORT_PORTAL_MAX_TTL_DAYS", 30);
+        Self {
+            base_url: std::env::var("REPORT_PORTAL_BASE_URL")
+                .ok()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty()),
+            default_ttl: chrono::Duration::days(days("REPORT_PORTAL_DEFAULT_TTL_DAYS", 7).min(max_days)),
+            max_ttl: chrono::Duration::days(max_days),
+        }
+    }
+
+    fn portal_url(&self, token: &str) -> String {
+        format!("{}/api/v1/portal/reports?token={}", self.base_url.as_deref().unwrap_or(""), token)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IssueTokenRequest {
+    pub tenant_id: Uuid,
+    pub created_by: Option<Uuid>,
+    pub recipient_name: String,
+    pub recipient_email: Option<String>,
+    pub recipient_organisation: Option<String>,
+    pub purpose: Option<String>,
+    pub report_ids: Vec<Uuid>,
+    /// Defaults to REPORT_PORTAL_DEFAULT_TTL_DAYS from now
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_downloads: Option<i32>,
+    /// Email the portal link to `recipient_email`
+    #[serde(default)]
+    pub notify: bool,
+}
+
+impl IssueTokenRequest {
+    pub fn validate(&self, settings: &PortalSettings) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.recipient_name.trim().is_empty() {
+            errors.push("recipient_name is required".to_string());
+        }
+        if self.report_ids.is_empty() {
+            errors.push("at least one report is required".to_string());
+        }
+        if self.report_ids.len() > MAX_REPORTS_PER_TOKEN {
+            errors.push(format!("at most {} reports per token", MAX_REPORTS_PER_TOKEN));
+        }
+        if let Some(email) = &self.recipient_email {
+            if email.parse::<Mailbox>().is_err() {
+                errors.push(format!("invalid email address: {}", email));
+            }
+        }
+        if self.notify && self.recipient_email.is_none() {
+            errors.push("recipient_email is required to notify the recipient".to_string());
+        }
+        if let Some(expires_at) = self.expires_at {
+            let now = Utc::now();
+            if expires_at <= now {
+                errors.push("expires_at must be in the future".to_string());
+            } else if expires_at - now > settings.max_ttl {
+                errors.push(format!("tokens may not be valid for more than {} days", settings.max_ttl.num_days()));
+            }
+        }
+        if self.max_downloads.is_some_and(|max| max < 1) {
+            errors.push("max_downloads must be at least 1".to_string());
+        }
+        errors
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RevokeTokenRequest {
+    pub tenant_id: Uuid,
+    pub revoked_by: Option<Uuid>,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct AccessToken {
+    pub token_id: Uuid,
+    pub tenant_id: Uuid,
+    pub recipient_name: String,
+    pub recipient_email: Option<String>,
+    pub recipient_organisation: Option<String>,
+    pub purpose: Option<String>,
+    pub report_ids: Vec<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub max_downloads: Option<i32>,
+    pub download_count: i32,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub revoked_by: Option<Uuid>,
+    pub revocation_reason: Option<String>,
+}
+
+const TOKEN_COLUMNS: &str = "token_id, tenant_id, recipient_name, recipient_email, recipient_organisation, purpose, \
+     report_ids, expires_at, max_downloads, download_count, last_used_at, created_by, created_at, revoked_at, \
+     revoked_by, revocation_reason";
+
+impl AccessToken {
+    /// Why the token can no longer be used, if it cannot
+    fn refusal(&self) -> Option<&'static str> {
+        if self.revoked_at.is_some() {
+            Some("token has been revoked")
+        } else if self.expires_at <= Utc::now() {
+            Some("token has expired")
+        } else if self.max_downloads.is_some_and(|max| self.download_count >= max) {
+            Some("download limit reached")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct IssuedToken {
+    /// Shown only once; it cannot be recovered later
+    pub token: String,
+    pub portal_url: String,
+    pub notified: bool,
+    #[serde(flatten)]
+    pub grant: AccessToken,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct AccessLogEntry {
+    pub access_id: Uuid,
+    pub report_id: Option<Uuid>,
+    pub action: String,
+    pub outcome: String,
+    pub reason: Option<String>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub accessed_at: DateTime<Utc>,
+}
+
+/// A report as listed to the recipient
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PortalReport {
+    pub report_id: Uuid,
+    pub template_name: Option<String>,
+    pub report_type: Option<String>,
+    pub report_period_start: NaiveDate,
+    pub report_period_end: NaiveDate,
+    pub status: Option<String>,
+    pub generated_at: Option<DateTime<Utc>>,
+    /// Set while the report is under embargo
+    pub available_at: Option<DateTime<Utc>>,
+    pub download_url: String,
+}
+
+#[derive(Serialize)]
+pub struct PortalListing {
+    pub recipient_name: String,
+    pub issued_by_tenant: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub downloads_remaining: Option<i32>,
+    pub reports: Vec<PortalReport>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PortalError {
+    #[error("missing or unknown access token")]
+    Unauthorized,
+    #[error("{0}")]
+    Refused(&'static str),
+    #[error("report not found")]
+    NotFound,
+    #[error("report is under embargo until {}", .0.format("%Y-%m-%d %H:%M UTC"))]
+    Embargoed(DateTime<Utc>),
+    #[error("report file expired on {}", .0.format("%Y-%m-%d"))]
+    Expired(DateTime<Utc>),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for PortalError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// Who presented the token, as recorded in the access log
+pub struct Requester {
+    token: Option<String>,
+    ip_address: Option<IpAddr>,
+    user_agent: Option<String>,
+}
+
+impl Requester {
+    /// The bearer token, or the `token` query parameter of an emailed link, and
+    /// the first X-Forwarded-For hop set by the gateway
+    pub fn from_request(headers: &HeaderMap, query_token: Option<&String>) -> Self {
+        let header_token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        let ip_address = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        let user_agent = headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|agent| agent.chars().take(512).collect());
+        Self {
+            token: header_token.or_else(|| query_token.cloned()).filter(|token| !token.is_empty()),
+            ip_address,
+            user_agent,
+        }
+    }
+
+    /// Client details recorded when an embargo refuses the request
+    fn attempt(&self, grant: &AccessToken) -> Attempt {
+        Attempt {
+            requested_by: None,
+            token_id: Some(grant.token_id),
+            ip_address: self.ip_address,
+            user_agent: self.user_agent.clone(),
+        }
+    }
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", TOKEN_PREFIX, hex::encode(bytes))
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Issue a token; `Err` lists the requested reports the tenant does not have
+pub async fn issue(
+    db: &PgPool,
+    settings: &PortalSettings,
+    delivery: &ReportDelivery,
+    request: IssueTokenRequest,
+) -> Result<Result<IssuedToken, Vec<String>>, DeliveryError> {
+    let mut report_ids = request.report_ids.clone();
+    report_ids.sort();
+    report_ids.dedup();
+
+    let known: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT report_id FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = ANY($2)",
+    )
+    .bind(request.tenant_id)
+    .bind(&report_ids)
+    .fetch_all(db)
+    .await
+    .context("failed to look up reports")?;
+    let missing: Vec<String> = report_ids
+        .iter()
+        .filter(|id| !known.contains(id))
+        .map(|id| format!("report {} not found", id))
+        .collect();
+    if !missing.is_empty() {
+        return Ok(Err(missing));
+    }
+    if request.notify {
+        delivery.ensure_mail_available()?;
+    }
+
+    let token = generate_token();
+    let grant = sqlx::query_as::<_, AccessToken>(&format!(
+        r#"
+        INSERT INTO report_access_tokens (
+            tenant_id, token_hash, recipient_name, recipient_email, recipient_organisation, purpose,
+            report_ids, expires_at, max_downloads, created_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING {}
+        "#,
+        TOKEN_COLUMNS
+    ))
+    .bind(request.tenant_id)
+    .bind(token_hash(&token))
+    .bind(request.recipient_name.trim())
+    .bind(&request.recipient_email)
+    .bind(&request.recipient_organisation)
+    .bind(&request.purpose)
+    .bind(&report_ids)
+    .bind(request.expires_at.unwrap_or_else(|| Utc::now() + settings.default_ttl))
+    .bind(request.max_downloads)
+    .bind(request.created_by)
+    .fetch_one(db)
+    .await
+    .context("failed to store access token")?;
+    info!(
+        "Issued report access token {} of tenant {} to {} for {} reports until {}",
+        grant.token_id,
+        grant.tenant_id,
+        grant.recipient_name,
+        grant.report_ids.len(),
+        grant.expires_at
+    );
+
+    let portal_url = settings.portal_url(&token);
+    let mut notified = false;
+    if let (true, Some(email)) = (request.notify, &grant.recipient_email) {
+        let body = format!(
+            "Dear {},\n\n{} report(s) have been shared with you on DharmaGuard{}.\n\n\
+             Download them at: {}\n\nThe link is personal to you and stops working on {}.",
+            grant.recipient_name,
+            grant.report_ids.len(),
+            grant.purpose.as_deref().map(|purpose| format!(" for {}", purpose)).unwrap_or_default(),
+            portal_url,
+            grant.expires_at.format("%Y-%m-%d %H:%M UTC"),
+        );
+        // The token is already stored, so a failed email is reported rather than undone
+        match delivery.send_notice(email, "DharmaGuard reports shared with you", body).await {
+            Ok(()) => notified = true,
+            Err(e) => warn!("Failed to email access token {} to {}: {:#}", grant.token_id, email, e),
+        }
+    }
+
+    Ok(Ok(IssuedToken {
+        token,
+        portal_url,
+        notified,
+        grant,
+    }))
+}
+
+pub async fn list(db: &PgPool, tenant_id: Uuid) -> Result<Vec<AccessToken>, sqlx::Error> {
+    sqlx::query_as::<_, AccessToken>(&format!(
+        "SELECT {} FROM report_access_tokens WHERE tenant_id = $1 ORDER BY created_at DESC LIMIT 200",
+        TOKEN_COLUMNS
+    ))
+    .bind(tenant_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Revoke a token; `None` when the tenant has no such token. Revoking twice keeps the first revocation.
+pub async fn revoke(
+    db: &PgPool,
+    token_id: Uuid,
+    request: &RevokeTokenRequest,
+) -> Result<Option<AccessToken>, sqlx::Error> {
+    let revoked = sqlx::query_as::<_, AccessToken>(&format!(
+        r#"
+        UPDATE report_access_tokens
+        SET revoked_at = COALESCE(revoked_at, NOW()),
+            revoked_by = CASE WHEN revoked_at IS NULL THEN $3 ELSE revoked_by END,
+            revocation_reason = CASE WHEN revoked_at IS NULL THEN $4 ELSE revocation_reason END
+        WHERE tenant_id = $1 AND token_id = $2
+        RETURNING {}
+        "#,
+        TOKEN_COLUMNS
+    ))
+    .bind(request.tenant_id)
+    .bind(token_id)
+    .bind(request.revoked_by)
+    .bind(&request.reason)
+    .fetch_optional(db)
+    .await?;
+    if let Some(token) = &revoked {
+        info!("Revoked report access token {} of tenant {}", token.token_id, token.tenant_id);
+    }
+    Ok(revoked)
+}
+
+/// Access log of one token, newest first; `None` when the tenant has no such token
+pub async fn access_log(
+    db: &PgPool,
+    tenant_id: Uuid,
+    token_id: Uuid,
+) -> Result<Option<Vec<AccessLogEntry>>, sqlx::Error> {
+    let exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM report_access_tokens WHERE tenant_id = $1 AND token_id = $2)",
+    )
+    .bind(tenant_id)
+    .bind(token_id)
+    .fetch_one(db)
+    .await?;
+    if !exists {
+        return Ok(None);
+    }
+    sqlx::query_as::<_, AccessLogEntry>(
+        r#"
+        SELECT access_id, report_id, action, outcome, reason, host(ip_address) AS ip_address, user_agent, accessed_at
+        FROM report_access_log
+        WHERE token_id = $1
+        ORDER BY accessed_at DESC
+        LIMIT 1000
+        "#,
+    )
+    .bind(token_id)
+    .fetch_all(db)
+    .await
+    .map(Some)
+}
+
+async fn record(
+    db: &PgPool,
+    grant: &AccessToken,
+    requester: &Requester,
+    report_id: Option<Uuid>,
+    action: &str,
+    refusal: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO report_access_log (token_id, tenant_id, report_id, action, outcome, reason, ip_address, user_agent)
+        VALUES ($1, $2, $3, $4, $5, $6, $7::inet, $8)
+        "#,
+    )
+    .bind(grant.token_id)
+    .bind(grant.tenant_id)
+    .bind(report_id)
+    .bind(action)
+    .bind(if refusal.is_some() { "DENIED" } else { "GRANTED" })
+    .bind(refusal)
+    .bind(requester.ip_address.map(|ip| ip.to_string()))
+    .bind(&requester.user_agent)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// The token presented by the requester, logged and refused when it is no longer usable
+async fn authorize(
+    db: &PgPool,
+    requester: &Requester,
+    report_id: Option<Uuid>,
+    action: &str,
+) -> Result<AccessToken, PortalError> {
+    let token = requester.token.as_deref().ok_or(PortalError::Unauthorized)?;
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Err(PortalError::Unauthorized);
+    }
+    let grant = sqlx::query_as::<_, AccessToken>(&format!(
+        "SELECT {} FROM report_access_tokens WHERE token_hash = $1",
+        TOKEN_COLUMNS
+    ))
+    .bind(token_hash(token))
+    .fetch_optional(db)
+    .await?
+    .ok_or(PortalError::Unauthorized)?;
+
+    if let Some(refusal) = grant.refusal() {
+        record(db, &grant, requester, report_id, action, Some(refusal)).await?;
+        return Err(PortalError::Refused(refusal));
+    }
+    Ok(grant)
+}
+
+/// The reports the presented token grants
+pub async fn portal_reports(db: &PgPool, requester: &Requester) -> Result<PortalListing, PortalError> {
+    let grant = authorize(db, requester, None, "LIST").await?;
+    let mut reports = sqlx::query_as::<_, PortalReport>(
+        r#"
+        SELECT r.report_id, rt.template_name, rt.report_type, r.report_period_start, r.report_period_end,
+               r.status, r.generated_at, report_embargoed_until(r.report_id) AS available_at, '' AS download_url
+        FROM regulatory_reports_v2 r
+        LEFT JOIN report_templates rt ON rt.template_id = r.template_id
+        WHERE r.tenant_id = $1 AND r.report_id = ANY($2)
+        ORDER BY r.report_period_start, r.report_id
+        "#,
+    )
+    .bind(grant.tenant_id)
+    .bind(&grant.report_ids)
+    .fetch_all(db)
+    .await?;
+    for report in &mut reports {
+        report.download_url = format!("/api/v1/portal/reports/{}/download", report.report_id);
+    }
+    record(db, &grant, requester, None, "LIST", None).await?;
+    sqlx::query("UPDATE report_access_tokens SET last_used_at = NOW() WHERE token_id = $1")
+        .bind(grant.token_id)
+        .execute(db)
+        .await?;
+
+    Ok(PortalListing {
+        recipient_name: grant.recipient_name,
+        issued_by_tenant: grant.tenant_id,
+        expires_at: grant.expires_at,
+        downloads_remaining: grant.max_downloads.map(|max| max - grant.download_count),
+        reports,
+    })
+}
+
+/// The file of a granted report, counted against the token's download limit
+pub async fn download(
+    db: &PgPool,
+    files: &ReportFiles,
+    requester: &Requester,
+    report_id: Uuid,
+) -> Result<ReportFile, PortalError> {
+    let grant = authorize(db, requester, Some(report_id), "DOWNLOAD").await?;
+    if !grant.report_ids.contains(&report_id) {
+        // Out-of-scope reports look the same as missing ones
+        record(db, &grant, requester, Some(report_id), "DOWNLOAD", Some("report not granted by token")).await?;
+        return Err(PortalError::NotFound);
+    }
+    #[allow(clippy::type_complexity)]
+    let report: Option<(
+        serde_json::Value,
+        Option<String>,
+        Option<String>,
+        Option<i64>,
+        Option<DateTime<Utc>>,
+        Option<DateTime<Utc>>,
+    )> = sqlx::query_as(
+        r#"
+        SELECT report_data, file_path, file_hash, file_size, file_deleted_at, file_archived_at
+        FROM regulatory_reports_v2
+        WHERE tenant_id = $1 AND report_id = $2
+        "#,
+    )
+    .bind(grant.tenant_id)
+    .bind(report_id)
+    .fetch_optional(db)
+    .await?;
+    let Some((report_data, file_path, file_hash, file_size, file_deleted_at, file_archived_at)) = report else {
+        record(db, &grant, requester, Some(report_id), "DOWNLOAD", Some("report no longer exists")).await?;
+        return Err(PortalError::NotFound);
+    };
+    // Checked before the download is counted, so an early attempt does not use up the token
+    if let Some(until) = embargo::guard(db, report_id, Access::PortalDownload, &requester.attempt(&grant)).await? {
+        record(db, &grant, requester, Some(report_id), "DOWNLOAD", Some("report is under embargo")).await?;
+        return Err(PortalError::Embargoed(until));
+    }
+    if approval::unapproved(db, report_id).await?.is_some() {
+        let refusal = "report has not been approved";
+        record(db, &grant, requester, Some(report_id), "DOWNLOAD", Some(refusal)).await?;
+        return Err(PortalError::Refused(refusal));
+    }
+    // Read before the download is counted too, so a missing file does not use up the token
+    let columns = FileColumns {
+        file_path,
+        file_hash,
+        file_size,
+        file_deleted_at,
+        file_archived_at,
+    };
+    let file = match files.open(report_id, &columns, &report_data).await {
+        Ok(file) => file,
+        Err(e) => match e.downcast::<FileExpired>() {
+            Ok(FileExpired(deleted_at)) => {
+                record(db, &grant, requester, Some(report_id), "DOWNLOAD", Some("report file expired")).await?;
+                return Err(PortalError::Expired(deleted_at));
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+
+    // Counted with the same checks as `refusal`, so concurrent downloads cannot exceed the limit
+    let counted = sqlx::query(
+        r#"
+        UPDATE report_access_tokens
+        SET download_count = download_count + 1, last_used_at = NOW()
+        WHERE token_id = $1
+          AND revoked_at IS NULL
+          AND expires_at > NOW()
+          AND (max_downloads IS NULL OR download_count < max_downloads)
+        "#,
+    )
+    .bind(grant.token_id)
+    .execute(db)
+    .await?
+    .rows_affected();
+    if counted == 0 {
+        // Revoked, expired or used up since `authorize`
+        let refusal = "token is no longer valid";
+        record(db, &grant, requester, Some(report_id), "DOWNLOAD", Some(refusal)).await?;
+        return Err(PortalError::Refused(refusal));
+    }
+    record(db, &grant, requester, Some(report_id), "DOWNLOAD", None).await?;
+    info!(
+        "Report {} of tenant {} downloaded through access token {}",
+        report_id, grant.tenant_id, grant.token_id
+    );
+    Ok(file)
+}
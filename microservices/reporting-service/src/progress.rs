@@ -0,0 +1,196 @@
+//! Progress of running report generation jobs
+//!
+//! A job reports its phase and percentage in report_generation_jobs as it
+//! goes: QUERYING up to QUERIED_PERCENT, advancing with every slice of a
+//! period generated in slices (see `chunked`), then AGGREGATING and RENDERING.
+//! GET /reports/:id/progress streams it over server-sent events, as a
+//! `progress` message whenever it changes with the time left estimated from
+//! how long the job took so far, until a last one once the job has finished.
+//! Any replica streams any job, as it is read from the database every
+//! STREAM_POLL.
+
+use axum::response::sse::{Event, KeepAlive, Sse};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::convert::Infallible;
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+
+use crate::jobs::JobError;
+
+/// Share of the progress bar the queries take
+const QUERIED_PERCENT: usize = 75;
+const STREAM_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Querying,
+    Aggregating,
+    Rendering,
+}
+
+impl Phase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Querying => "QUERYING",
+            Self::Aggregating => "AGGREGATING",
+            Self::Rendering => "RENDERING",
+        }
+    }
+
+    /// How far along a job is once it enters the phase
+    fn percent(self) -> usize {
+        match self {
+            Self::Querying => 0,
+            Self::Aggregating => QUERIED_PERCENT,
+            Self::Rendering => 85,
+        }
+    }
+}
+
+/// Where a job reports its progress; a failure to record it is only logged
+pub struct Progress {
+    db: PgPool,
+    report_id: Uuid,
+}
+
+impl Progress {
+    pub fn new(db: PgPool, report_id: Uuid) -> Self {
+        Self { db, report_id }
+    }
+
+    pub async fn phase(&self, phase: Phase) {
+        self.record(phase, phase.percent()).await;
+    }
+
+    /// `done` of the `total` slices of the period have been read
+    pub async fn queried(&self, done: usize, total: usize) {
+        self.record(Phase::Querying, QUERIED_PERCENT * done / total.max(1)).await;
+    }
+
+    async fn record(&self, phase: Phase, percent: usize) {
+        let recorded = sqlx::query(
+            r#"
+            UPDATE report_generation_jobs
+            SET phase = $2, progress_percent = GREATEST(progress_percent, $3), progress_updated_at = NOW()
+            WHERE report_id = $1 AND status = 'RUNNING'
+            "#,
+        )
+        .bind(self.report_id)
+        .bind(phase.as_str())
+        .bind(percent.min(99) as i16)
+        .execute(&self.db)
+        .await;
+        if let Err(e) = recorded {
+            warn!("Failed to record the progress of report generation job {}: {}", self.report_id, e);
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct JobProgress {
+    pub report_id: Uuid,
+    pub status: String,
+    pub phase: String,
+    pub percent: i16,
+    /// Seconds the job is expected to run for still, once it has made some progress
+    pub eta_secs: Option<i64>,
+    pub failure_reason: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ProgressRow {
+    status: String,
+    phase: String,
+    progress_percent: i16,
+    failure_reason: Option<String>,
+    started_at: DateTime<Utc>,
+    progress_updated_at: DateTime<Utc>,
+}
+
+async fn load(db: &PgPool, tenant: &TenantContext, report_id: Uuid) -> Result<JobProgress, JobError> {
+    let row = sqlx::query_as::<_, ProgressRow>(
+        r#"
+        SELECT status, phase, progress_percent, failure_reason, started_at, progress_updated_at
+        FROM report_generation_jobs
+        WHERE tenant_id = $1 AND report_id = $2
+        "#,
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(JobError::NotFound)?;
+
+    let running = row.status == "RUNNING";
+    let percent = if row.status == "COMPLETED" { 100 } else { row.progress_percent };
+    let eta_secs = (running && percent > 0).then(|| {
+        let elapsed = (Utc::now() - row.started_at).num_seconds().max(0);
+        elapsed * i64::from(100 - percent) / i64::from(percent)
+    });
+    Ok(JobProgress {
+        report_id,
+        status: row.status,
+        phase: row.phase,
+        percent,
+        eta_secs,
+        failure_reason: row.failure_reason,
+        started_at: row.started_at,
+        updated_at: row.progress_updated_at,
+    })
+}
+
+/// The job's progress as `progress` messages, the first at once and the last
+/// once it has finished
+pub async fn stream(
+    db: PgPool,
+    tenant: TenantContext,
+    report_id: Uuid,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, JobError> {
+    let first = load(&db, &tenant, report_id).await?;
+    let events = stream::unfold(
+        (db, tenant, Some(first), None::<JobProgress>),
+        move |(db, tenant, mut next, last)| async move {
+            loop {
+                let progress = match next.take() {
+                    Some(progress) => progress,
+                    None => {
+                        // The job finished with the last message sent
+                        if last.as_ref().map_or(false, |last| last.status != "RUNNING") {
+                            return None;
+                        }
+                        tokio::time::sleep(STREAM_POLL).await;
+                        match load(&db, &tenant, report_id).await {
+                            Ok(progress) => progress,
+                            Err(JobError::NotFound) => return None,
+                            Err(e) => {
+                                warn!("Failed to read the progress of report generation job {}: {}", report_id, e);
+                                continue;
+                            }
+                        }
+                    }
+                };
+                // Only the time left moves while the job is unchanged
+                let unchanged = last.as_ref().map_or(false, |last| {
+                    last.status == progress.status && last.percent == progress.percent && last.phase == progress.phase
+                });
+                if unchanged {
+                    continue;
+                }
+                let event = Event::default()
+                    .event("progress")
+                    .json_data(&progress)
+                    .unwrap_or_else(|_| Event::default().event("error"));
+                return Some((Ok(event), (db, tenant, None, Some(progress))));
+            }
+        },
+    );
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
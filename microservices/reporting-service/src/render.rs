@@ -0,0 +1,252 @@
+//! Rendering report data to files
+//!
+//! PDF reports are laid out by a template per report type, found under
+//! `templates/` and compiled into the service, in the tenant's layout; see
+//! `layouts`. The typst source it gives, the logo if any, and the report as
+//! `report.json` (its metadata, with the generated data under `data` and the
+//! layout under `layout`) are written to a scratch directory and compiled by
+//! the typst CLI at
+//! REPORT_TYPST_BIN (typst 0.11 or later), which is given
+//! REPORT_RENDER_TIMEOUT_SECS to finish. CSV and xlsx lay the report out as
+//! sheets; see `sheets`. XBRL instances follow the configured taxonomy; see
+//! `xbrl`. XML nests the fields of the data as `field` elements, and JSON is
+//! the data as stored.
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::layouts::Layout;
+use crate::sheets;
+use crate::xbrl::Taxonomy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Pdf,
+    Csv,
+    Xlsx,
+    Json,
+    Xml,
+    Xbrl,
+}
+
+impl ReportFormat {
+    pub const ALL: [Self; 6] = [Self::Pdf, Self::Csv, Self::Xlsx, Self::Json, Self::Xml, Self::Xbrl];
+
+    pub fn parse(format: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|candidate| candidate.as_str().eq_ignore_ascii_case(format))
+    }
+
+    /// The format a stored file was rendered in, judged by its extension
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = path.rsplit_once('.')?.1;
+        Self::ALL.into_iter().find(|candidate| candidate.extension() == extension)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pdf => "PDF",
+            Self::Csv => "CSV",
+            Self::Xlsx => "XLSX",
+            Self::Json => "JSON",
+            Self::Xml => "XML",
+            Self::Xbrl => "XBRL",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Csv => "csv",
+            Self::Xlsx => "xlsx",
+            Self::Json => "json",
+            Self::Xml => "xml",
+            Self::Xbrl => "xbrl",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Pdf => "application/pdf",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            Self::Json => "application/json",
+            Self::Xml => "application/xml",
+            Self::Xbrl => "application/xbrl+xml",
+        }
+    }
+}
+
+/// What a rendered report says about itself besides its data
+#[derive(Serialize, Debug, Clone)]
+pub struct ReportMeta {
+    pub report_id: Uuid,
+    pub tenant_id: Uuid,
+    pub report_type: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub generated_at: DateTime<Utc>,
+    /// The tenant's SEBI registration number, which XBRL reports identify it by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registration_no: Option<String>,
+    /// The layout of a PDF; the report type's built-in one when not given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<Layout>,
+}
+
+/// The template laying out a report type as PDF
+pub fn template(report_type: &str) -> Option<&'static str> {
+    match report_type {
+        "TRADING_SUMMARY" => Some(include_str!("../templates/trading_summary.typ.tera")),
+        "COMPLIANCE_REPORT" => Some(include_str!("../templates/compliance_report.typ.tera")),
+        "CLIENT_EXPOSURE" => Some(include_str!("../templates/client_exposure.typ.tera")),
+        "ORDER_TRADE_RATIO" => Some(include_str!("../templates/order_trade_ratio.typ.tera")),
+        "BROKERAGE_SUMMARY" => Some(include_str!("../templates/brokerage_summary.typ.tera")),
+        "MARGIN_REPORT" => Some(include_str!("../templates/margin_report.typ.tera")),
+        "ALERT_AGEING" => Some(include_str!("../templates/alert_ageing.typ.tera")),
+        _ => None,
+    }
+}
+
+pub struct Renderer {
+    typst: PathBuf,
+    timeout: Duration,
+    taxonomy: Taxonomy,
+}
+
+impl Renderer {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            typst: std::env::var("REPORT_TYPST_BIN")
+                .ok()
+                .filter(|bin| !bin.is_empty())
+                .unwrap_or_else(|| "typst".to_string())
+                .into(),
+            timeout: Duration::from_secs(
+                std::env::var("REPORT_RENDER_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(60),
+            ),
+            taxonomy: Taxonomy::from_env()?,
+        })
+    }
+
+    /// Whether reports of the type can be rendered in the format
+    pub fn covers(&self, format: ReportFormat, report_type: &str) -> bool {
+        match format {
+            ReportFormat::Pdf => template(report_type).is_some(),
+            ReportFormat::Xbrl => self.taxonomy.covers(report_type),
+            ReportFormat::Csv | ReportFormat::Xlsx | ReportFormat::Json | ReportFormat::Xml => true,
+        }
+    }
+
+    pub async fn render(&self, format: ReportFormat, meta: &ReportMeta, data: &Value) -> anyhow::Result<Vec<u8>> {
+        match format {
+            ReportFormat::Pdf => self.pdf(meta, data).await,
+            ReportFormat::Csv => sheets::csv(&sheets::sheets(meta, data)?),
+            ReportFormat::Xlsx => sheets::xlsx(meta, &sheets::sheets(meta, data)?),
+            ReportFormat::Json => Ok(serde_json::to_vec_pretty(data)?),
+            ReportFormat::Xml => Ok(xml(meta, data).into_bytes()),
+            ReportFormat::Xbrl => self.taxonomy.instance(meta, data),
+        }
+    }
+
+    async fn pdf(&self, meta: &ReportMeta, data: &Value) -> anyhow::Result<Vec<u8>> {
+        let layout = match &meta.layout {
+            Some(layout) => layout.clone(),
+            None => Layout::default_for(&meta.report_type),
+        };
+        let typst_source = layout.compose(&meta.report_type)?;
+        let mut document = serde_json::to_value(meta)?;
+        document["data"] = data.clone();
+        document["layout"] = serde_json::to_value(&layout)?;
+
+        let scratch = tempfile::tempdir().context("failed to create a scratch directory")?;
+        let source = scratch.path().join("report.typ");
+        let output = scratch.path().join("report.pdf");
+        tokio::fs::write(&source, typst_source).await?;
+        tokio::fs::write(scratch.path().join("report.json"), serde_json::to_vec(&document)?).await?;
+        if let Some((name, logo)) = layout.logo() {
+            tokio::fs::write(scratch.path().join(name), logo).await?;
+        }
+
+        let compiled = tokio::time::timeout(
+            self.timeout,
+            Command::new(&self.typst)
+                .arg("compile")
+                .arg("--root")
+                .arg(scratch.path())
+                .arg(&source)
+                .arg(&output)
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .with_context(|| format!("typst did not finish within {}s", self.timeout.as_secs()))?
+        .with_context(|| format!("failed to run {}", self.typst.display()))?;
+        if !compiled.status.success() {
+            anyhow::bail!("typst failed: {}", String::from_utf8_lossy(&compiled.stderr).trim());
+        }
+        Ok(tokio::fs::read(&output).await?)
+    }
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_fields(value: &Value, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    let children: Vec<(String, &Value)> = match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().map(|(key, value)| (key.clone(), value)).collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            entries
+        }
+        Value::Array(items) => items.iter().enumerate().map(|(index, value)| (index.to_string(), value)).collect(),
+        _ => return,
+    };
+    for (name, child) in children {
+        match child {
+            Value::Object(_) | Value::Array(_) => {
+                out.push_str(&format!("{}<field name=\"{}\">\n", indent, escape_xml(&name)));
+                xml_fields(child, depth + 1, out);
+                out.push_str(&format!("{}</field>\n", indent));
+            }
+            Value::Null => out.push_str(&format!("{}<field name=\"{}\"/>\n", indent, escape_xml(&name))),
+            Value::String(text) => out.push_str(&format!(
+                "{}<field name=\"{}\">{}</field>\n",
+                indent,
+                escape_xml(&name),
+                escape_xml(text)
+            )),
+            other => out.push_str(&format!("{}<field name=\"{}\">{}</field>\n", indent, escape_xml(&name), other)),
+        }
+    }
+}
+
+fn xml(meta: &ReportMeta, data: &Value) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<report id=\"{}\" tenant=\"{}\" type=\"{}\" period-start=\"{}\" period-end=\"{}\" generated-at=\"{}\">\n",
+        meta.report_id,
+        meta.tenant_id,
+        escape_xml(&meta.report_type),
+        meta.period_start,
+        meta.period_end,
+        meta.generated_at.to_rfc3339()
+    ));
+    xml_fields(data, 1, &mut out);
+    out.push_str("</report>\n");
+    out
+}
@@ -0,0 +1,374 @@
+//! Rendered report files
+//!
+//! A report is rendered once, when it is generated, and put in the report
+//! store (see `store`) as `<tenant>/<report>.<extension>`.
+//! regulatory_reports_v2 records that key as file_path, with the file's
+//! SHA-256 as file_hash and its size as file_size. Portal downloads and email
+//! deliveries check the hash when they read the file back, so a file changed
+//! in the store is refused rather than sent. /reports/:id/download streams the
+//! file instead, with the hash as its ETag for the client to check; see
+//! `download`. Reports generated before they were rendered have no file_path
+//! and are sent as their stored data in JSON.
+//!
+//! Files are given a file_expires_at from the tenant's retention policy for
+//! the report type, or REPORT_FILE_RETENTION_DAYS past their generation (see
+//! `retention`), and a sweep carries out the policy once it has passed. A file
+//! to be archived is moved to REPORT_ARCHIVE_STORE and file_archived_at set,
+//! and is read from there since. Otherwise the file is removed from the store
+//! and file_deleted_at set; a report whose file is gone still has its data,
+//! but its file can no longer be downloaded or delivered. Reports under legal
+//! hold are left alone.
+//!
+//! Files of tenants with a signing certificate are signed before they are
+//! stored, so the hash is that of the signed file; see `signing`.
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::download::{Download, Source};
+use crate::render::{ReportFormat, ReportMeta, Renderer};
+use crate::retention::{self, ARCHIVE};
+use crate::signing::{self, FileSignature, SigningSettings};
+use crate::store::{self, ReportStore};
+
+/// How often expired files are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const SWEEP_BATCH: i64 = 100;
+
+/// A report file as it is sent
+pub struct ReportFile {
+    pub name: String,
+    pub content_type: &'static str,
+    pub contents: Vec<u8>,
+}
+
+/// Where a rendered report was stored, as recorded on the report
+pub struct StoredFile {
+    pub file_path: String,
+    pub file_hash: String,
+    pub file_size: i64,
+    pub file_expires_at: Option<DateTime<Utc>>,
+    /// ARCHIVE or DELETE, at file_expires_at
+    pub file_retention_action: Option<String>,
+    /// `None` for tenants without a signing certificate
+    pub signature: Option<FileSignature>,
+}
+
+impl StoredFile {
+    /// The digital_signature column
+    pub fn digital_signature(&self) -> Option<String> {
+        self.signature.as_ref().map(FileSignature::encoded)
+    }
+}
+
+/// The file columns of a regulatory_reports_v2 row
+#[derive(Debug, Clone, Default)]
+pub struct FileColumns {
+    pub file_path: Option<String>,
+    pub file_hash: Option<String>,
+    pub file_size: Option<i64>,
+    pub file_deleted_at: Option<DateTime<Utc>>,
+    pub file_archived_at: Option<DateTime<Utc>>,
+}
+
+/// The report's file was removed from the store when it expired
+#[derive(Debug, thiserror::Error)]
+#[error("the report file expired and was removed on {}", .0.format("%Y-%m-%d"))]
+pub struct FileExpired(pub DateTime<Utc>);
+
+pub struct ReportFiles {
+    renderer: Renderer,
+    store: Arc<dyn ReportStore>,
+    /// Where expired files are archived to, when their retention policy says so
+    archive: Option<Arc<dyn ReportStore>>,
+    /// How long files of tenants without a retention policy are kept; indefinitely when `None`
+    retention: Option<chrono::Duration>,
+    signing: SigningSettings,
+}
+
+impl ReportFiles {
+    pub async fn from_env() -> anyhow::Result<Self> {
+        let uri = std::env::var("REPORT_STORE")
+            .ok()
+            .filter(|uri| !uri.is_empty())
+            .unwrap_or_else(|| "/var/lib/dharmaguard/reports".to_string());
+        let store: Arc<dyn ReportStore> = store::from_uri(&uri).await?.into();
+        info!("Keeping report files in {}", store.uri());
+        let archive: Option<Arc<dyn ReportStore>> = match std::env::var("REPORT_ARCHIVE_STORE") {
+            Ok(uri) if !uri.is_empty() => {
+                let archive: Arc<dyn ReportStore> = store::from_uri(&uri).await?.into();
+                info!("Archiving report files to {}", archive.uri());
+                Some(archive)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            renderer: Renderer::from_env()?,
+            store,
+            archive,
+            retention: std::env::var("REPORT_FILE_RETENTION_DAYS")
+                .ok()
+                .and_then(|days| days.parse::<i64>().ok())
+                .filter(|days| *days > 0)
+                .map(chrono::Duration::days),
+            signing: SigningSettings::from_env()?,
+        })
+    }
+
+    /// Whether reports of the type can be rendered in the format
+    pub fn covers(&self, format: ReportFormat, report_type: &str) -> bool {
+        self.renderer.covers(format, report_type)
+    }
+
+    /// Whether REPORT_ARCHIVE_STORE is set, so files can be archived
+    pub fn archives(&self) -> bool {
+        self.archive.is_some()
+    }
+
+    pub fn signing(&self) -> &SigningSettings {
+        &self.signing
+    }
+
+    /// Render the report, sign it for tenants with a signing certificate and put it in the store
+    pub async fn create(
+        &self,
+        db: &PgPool,
+        meta: &ReportMeta,
+        format: ReportFormat,
+        data: &Value,
+    ) -> anyhow::Result<StoredFile> {
+        let contents = self
+            .renderer
+            .render(format, meta, data)
+            .await
+            .with_context(|| format!("failed to render report {} as {}", meta.report_id, format.as_str()))?;
+        let (contents, signature) = match signing::signer(db, &self.signing, meta.tenant_id).await? {
+            Some(signer) => {
+                let (contents, signature) = signer
+                    .sign_file(&self.signing, meta, format, contents)
+                    .await
+                    .with_context(|| format!("failed to sign report {}", meta.report_id))?;
+                (contents, Some(signature))
+            }
+            None => (contents, None),
+        };
+        let retention =
+            retention::retention(db, meta.tenant_id, &meta.report_type, meta.generated_at, self.retention).await?;
+        let file_path = format!("{}/{}.{}", meta.tenant_id, meta.report_id, format.extension());
+        let stored = StoredFile {
+            file_hash: hex::encode(Sha256::digest(&contents)),
+            file_size: contents.len() as i64,
+            file_expires_at: retention.as_ref().map(|retention| retention.expires_at),
+            file_retention_action: retention.map(|retention| retention.action),
+            file_path,
+            signature,
+        };
+        self.store
+            .put(&stored.file_path, contents, format.content_type())
+            .await
+            .with_context(|| format!("failed to store report file {}", stored.file_path))?;
+        Ok(stored)
+    }
+
+    /// Render the report without storing it, such as for a preview
+    pub async fn render(&self, meta: &ReportMeta, format: ReportFormat, data: &Value) -> anyhow::Result<Vec<u8>> {
+        self.renderer.render(format, meta, data).await
+    }
+
+    /// Remove a file whose report was not recorded
+    pub async fn remove(&self, stored: &StoredFile) {
+        if let Err(e) = self.store.delete(&stored.file_path).await {
+            warn!("Failed to remove unrecorded report file {}: {:#}", stored.file_path, e);
+        }
+    }
+
+    /// The stored file, its format and the store it is in, or `None` for reports from before rendering
+    fn stored<'a>(
+        &self,
+        columns: &'a FileColumns,
+    ) -> anyhow::Result<Option<(&'a str, ReportFormat, &Arc<dyn ReportStore>)>> {
+        let Some(file_path) = columns.file_path.as_deref() else {
+            return Ok(None);
+        };
+        if let Some(deleted_at) = columns.file_deleted_at {
+            return Err(FileExpired(deleted_at).into());
+        }
+        let format = ReportFormat::from_path(file_path)
+            .with_context(|| format!("report file {} is of an unknown format", file_path))?;
+        let store = match columns.file_archived_at {
+            Some(_) => self.archive.as_ref().with_context(|| {
+                format!("report file {} is archived, but REPORT_ARCHIVE_STORE is not configured", file_path)
+            })?,
+            None => &self.store,
+        };
+        Ok(Some((file_path, format, store)))
+    }
+
+    /// The file of a report, from its regulatory_reports_v2 columns, ready to be streamed
+    pub async fn download(
+        &self,
+        report_id: Uuid,
+        columns: &FileColumns,
+        report_data: &Value,
+    ) -> anyhow::Result<Download> {
+        let Some((file_path, format, store)) = self.stored(columns)? else {
+            let contents = serde_json::to_vec_pretty(report_data).context("failed to serialize report")?;
+            return Ok(Download {
+                name: format!("report-{}.json", report_id),
+                content_type: ReportFormat::Json.content_type(),
+                len: contents.len() as u64,
+                etag: None,
+                source: Source::Memory(contents),
+            });
+        };
+        let len = match columns.file_size {
+            Some(size) => size as u64,
+            None => store
+                .size(file_path)
+                .await
+                .with_context(|| format!("failed to look up report file {}", file_path))?,
+        };
+        Ok(Download {
+            name: format!("report-{}.{}", report_id, format.extension()),
+            content_type: format.content_type(),
+            len,
+            etag: columns.file_hash.clone(),
+            source: Source::Store {
+                store: store.clone(),
+                key: file_path.to_string(),
+            },
+        })
+    }
+
+    /// The file of a report, from its regulatory_reports_v2 columns
+    pub async fn open(
+        &self,
+        report_id: Uuid,
+        columns: &FileColumns,
+        report_data: &Value,
+    ) -> anyhow::Result<ReportFile> {
+        let Some((file_path, format, store)) = self.stored(columns)? else {
+            return Ok(ReportFile {
+                name: format!("report-{}.json", report_id),
+                content_type: ReportFormat::Json.content_type(),
+                contents: serde_json::to_vec_pretty(report_data).context("failed to serialize report")?,
+            });
+        };
+        let contents = store
+            .get(file_path)
+            .await
+            .with_context(|| format!("failed to read report file {}", file_path))?;
+        if let Some(expected) = &columns.file_hash {
+            let actual = hex::encode(Sha256::digest(&contents));
+            if &actual != expected {
+                anyhow::bail!("report file {} does not match its recorded hash", file_path);
+            }
+        }
+        Ok(ReportFile {
+            name: format!("report-{}.{}", report_id, format.extension()),
+            content_type: format.content_type(),
+            contents,
+        })
+    }
+
+    /// Move an expired file to the archive store, checking it arrived whole
+    async fn archive(&self, file_path: &str, file_hash: Option<&str>) -> anyhow::Result<()> {
+        let archive = self.archive.as_ref().context("REPORT_ARCHIVE_STORE is not configured")?;
+        let contents = self.store.get(file_path).await.context("failed to read the file")?;
+        if let Some(expected) = file_hash {
+            if hex::encode(Sha256::digest(&contents)) != expected {
+                anyhow::bail!("the file does not match its recorded hash");
+            }
+        }
+        let content_type = ReportFormat::from_path(file_path).unwrap_or(ReportFormat::Json).content_type();
+        let len = contents.len() as u64;
+        archive
+            .put(file_path, contents, content_type)
+            .await
+            .context("failed to write the file to the archive")?;
+        if archive.size(file_path).await.context("failed to look up the archived file")? != len {
+            anyhow::bail!("the archived file is incomplete");
+        }
+        // The archived copy is the one read from now on
+        if let Err(e) = self.store.delete(file_path).await {
+            warn!("Failed to remove archived report file {} from the store: {:#}", file_path, e);
+        }
+        Ok(())
+    }
+
+    /// Archive or remove a batch of expired files; returns how many were handled
+    async fn sweep(&self, db: &PgPool) -> anyhow::Result<usize> {
+        let mut tx = db.begin().await?;
+        // Locked, so replicas sweeping at the same time take different files and a hold waits for the sweep.
+        // Files to be archived stay where they are while there is no archive store.
+        let expired = sqlx::query_as::<_, (Uuid, String, Option<String>, Option<String>)>(
+            r#"
+            SELECT report_id, file_path, file_hash, file_retention_action FROM regulatory_reports_v2
+            WHERE file_expires_at <= NOW() AND file_deleted_at IS NULL AND file_archived_at IS NULL
+              AND NOT legal_hold AND file_path IS NOT NULL
+              AND ($2 OR file_retention_action IS DISTINCT FROM 'ARCHIVE')
+            ORDER BY file_expires_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+            "#,
+        )
+        .bind(SWEEP_BATCH)
+        .bind(self.archives())
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut archived = Vec::new();
+        let mut removed = Vec::new();
+        for (report_id, file_path, file_hash, action) in expired {
+            if action.as_deref() == Some(ARCHIVE) {
+                match self.archive(&file_path, file_hash.as_deref()).await {
+                    Ok(()) => archived.push(report_id),
+                    Err(e) => warn!("Failed to archive expired report file {}: {:#}", file_path, e),
+                }
+                continue;
+            }
+            match self.store.delete(&file_path).await {
+                Ok(()) => removed.push(report_id),
+                Err(e) => warn!("Failed to remove expired report file {}: {:#}", file_path, e),
+            }
+        }
+        sqlx::query("UPDATE regulatory_reports_v2 SET file_archived_at = NOW() WHERE report_id = ANY($1)")
+            .bind(&archived)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE regulatory_reports_v2 SET file_deleted_at = NOW() WHERE report_id = ANY($1)")
+            .bind(&removed)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(archived.len() + removed.len())
+    }
+}
+
+/// Archive or remove expired report files in the background
+pub fn spawn_sweeper(db: PgPool, files: Arc<ReportFiles>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            loop {
+                match files.sweep(&db).await {
+                    Ok(0) => break,
+                    Ok(handled) => info!("Archived or removed {} expired report files", handled),
+                    Err(e) => {
+                        error!("Report file sweep failed: {:#}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,351 @@
+//! Retention of report files, and legal holds
+//!
+//! A tenant sets how long the files of each report type are kept with PUT
+//! /reports/retention-policies/:report_type, such as eight years for
+//! regulatory filings and 90 days for ad-hoc summaries, and whether they are
+//! then archived or deleted. ARCHIVE moves a file to REPORT_ARCHIVE_STORE,
+//! where it is kept and from which it is still downloaded and delivered;
+//! DELETE removes it, as REPORT_FILE_RETENTION_DAYS does for tenants without a
+//! policy. The DEFAULT policy covers the types without their own. A report
+//! takes its policy when it is generated, as file_expires_at and
+//! file_retention_action, and the sweep in `report_files` carries it out.
+//!
+//! A report under legal hold is skipped by the sweep, and the database refuses
+//! to delete it or mark its file removed. PUT /reports/:id/legal-hold places a
+//! hold with its reason and POST /reports/:id/legal-hold/release releases it;
+//! every hold is kept with who placed and released it. A file whose retention
+//! ran out while it was held goes at the next sweep after the release.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+
+/// The policy of report types without their own
+pub const DEFAULT_POLICY: &str = "DEFAULT";
+pub const ARCHIVE: &str = "ARCHIVE";
+pub const DELETE: &str = "DELETE";
+const MAX_RETENTION_DAYS: i32 = 36_500;
+const MAX_REASON_LENGTH: usize = 2000;
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct RetentionPolicy {
+    pub tenant_id: Uuid,
+    pub report_type: String,
+    pub retention_days: i32,
+    pub action: String,
+    pub updated_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const POLICY_COLUMNS: &str = "tenant_id, report_type, retention_days, action, updated_by, created_at, updated_at";
+
+#[derive(Deserialize)]
+pub struct SetPolicyRequest {
+    pub retention_days: i32,
+    /// ARCHIVE or DELETE
+    pub action: String,
+    pub updated_by: Option<Uuid>,
+}
+
+/// What becomes of a new report's file
+pub struct Retention {
+    pub expires_at: DateTime<Utc>,
+    pub action: String,
+}
+
+#[derive(Deserialize)]
+pub struct PlaceHoldRequest {
+    pub reason: String,
+    pub placed_by: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct ReleaseHoldRequest {
+    pub reason: Option<String>,
+    pub released_by: Option<Uuid>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct HoldRecord {
+    pub hold_id: Uuid,
+    pub reason: String,
+    pub placed_by: Option<Uuid>,
+    pub placed_at: DateTime<Utc>,
+    pub released_by: Option<Uuid>,
+    pub release_reason: Option<String>,
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct LegalHold {
+    pub report_id: Uuid,
+    pub legal_hold: bool,
+    pub file_expires_at: Option<DateTime<Utc>>,
+    pub file_retention_action: Option<String>,
+    pub file_archived_at: Option<DateTime<Utc>>,
+    pub file_deleted_at: Option<DateTime<Utc>>,
+    /// Newest first
+    pub holds: Vec<HoldRecord>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RetentionError {
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("invalid request")]
+    Invalid(Vec<String>),
+    #[error("report is already under legal hold")]
+    AlreadyHeld,
+    #[error("report is not under legal hold")]
+    NotHeld,
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for RetentionError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// Retention of a new report's file, from the tenant's policies or else `default` days before deletion
+pub async fn retention<'e>(
+    db: impl sqlx::PgExecutor<'e>,
+    tenant_id: Uuid,
+    report_type: &str,
+    generated_at: DateTime<Utc>,
+    default: Option<Duration>,
+) -> Result<Option<Retention>, sqlx::Error> {
+    let policy = sqlx::query_as::<_, (i32, String)>(
+        r#"
+        SELECT retention_days, action FROM report_retention_policies
+        WHERE tenant_id = $1 AND report_type IN ($2, $3)
+        ORDER BY report_type = $3
+        LIMIT 1
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(report_type)
+    .bind(DEFAULT_POLICY)
+    .fetch_optional(db)
+    .await?;
+    Ok(match policy {
+        Some((days, action)) => Some(Retention {
+            expires_at: generated_at + Duration::days(i64::from(days)),
+            action,
+        }),
+        None => default.map(|default| Retention {
+            expires_at: generated_at + default,
+            action: DELETE.to_string(),
+        }),
+    })
+}
+
+pub async fn list_policies(db: &PgPool, tenant: &TenantContext) -> Result<Vec<RetentionPolicy>, sqlx::Error> {
+    sqlx::query_as::<_, RetentionPolicy>(&format!(
+        "SELECT {} FROM report_retention_policies WHERE tenant_id = $1 ORDER BY report_type = $2 DESC, report_type",
+        POLICY_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(DEFAULT_POLICY)
+    .fetch_all(db)
+    .await
+}
+
+/// Set the retention of a report type, for reports generated from now on
+pub async fn set_policy(
+    db: &PgPool,
+    archive_configured: bool,
+    tenant: &TenantContext,
+    report_type: &str,
+    request: SetPolicyRequest,
+) -> Result<RetentionPolicy, RetentionError> {
+    let report_type = report_type.trim().to_uppercase();
+    let action = request.action.trim().to_uppercase();
+    let mut errors = Vec::new();
+    if report_type.is_empty()
+        || report_type.len() > 50
+        || !report_type.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+    {
+        errors.push(format!("report type must be a report type such as TRADING_SUMMARY, or {}", DEFAULT_POLICY));
+    }
+    if !(1..=MAX_RETENTION_DAYS).contains(&request.retention_days) {
+        errors.push(format!("retention_days must be between 1 and {}", MAX_RETENTION_DAYS));
+    }
+    match action.as_str() {
+        ARCHIVE if !archive_configured => {
+            errors.push("action ARCHIVE needs REPORT_ARCHIVE_STORE, which is not configured".to_string());
+        }
+        ARCHIVE | DELETE => {}
+        _ => errors.push(format!("action must be {} or {}", ARCHIVE, DELETE)),
+    }
+    if !errors.is_empty() {
+        return Err(RetentionError::Invalid(errors));
+    }
+    let policy = sqlx::query_as::<_, RetentionPolicy>(&format!(
+        r#"
+        INSERT INTO report_retention_policies (tenant_id, report_type, retention_days, action, updated_by)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (tenant_id, report_type) DO UPDATE SET
+            retention_days = EXCLUDED.retention_days, action = EXCLUDED.action,
+            updated_by = EXCLUDED.updated_by, updated_at = NOW()
+        RETURNING {}
+        "#,
+        POLICY_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(&report_type)
+    .bind(request.retention_days)
+    .bind(&action)
+    .bind(request.updated_by)
+    .fetch_one(db)
+    .await?;
+    Ok(policy)
+}
+
+pub async fn delete_policy(db: &PgPool, tenant: &TenantContext, report_type: &str) -> Result<(), RetentionError> {
+    let deleted = sqlx::query("DELETE FROM report_retention_policies WHERE tenant_id = $1 AND report_type = $2")
+        .bind(tenant.tenant_id())
+        .bind(report_type.trim().to_uppercase())
+        .execute(db)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(RetentionError::NotFound("retention policy"));
+    }
+    Ok(())
+}
+
+pub async fn legal_hold(db: &PgPool, tenant: &TenantContext, report_id: Uuid) -> Result<LegalHold, RetentionError> {
+    let report = sqlx::query_as::<
+        _,
+        (bool, Option<DateTime<Utc>>, Option<String>, Option<DateTime<Utc>>, Option<DateTime<Utc>>),
+    >(
+        r#"
+        SELECT legal_hold, file_expires_at, file_retention_action, file_archived_at, file_deleted_at
+        FROM regulatory_reports_v2
+        WHERE tenant_id = $1 AND report_id = $2
+        "#,
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_optional(db)
+    .await?;
+    let Some((legal_hold, file_expires_at, file_retention_action, file_archived_at, file_deleted_at)) = report else {
+        return Err(RetentionError::NotFound("report"));
+    };
+    let holds = sqlx::query_as::<_, HoldRecord>(
+        r#"
+        SELECT hold_id, reason, placed_by, placed_at, released_by, release_reason, released_at
+        FROM report_legal_holds
+        WHERE report_id = $1
+        ORDER BY placed_at DESC
+        "#,
+    )
+    .bind(report_id)
+    .fetch_all(db)
+    .await?;
+    Ok(LegalHold {
+        report_id,
+        legal_hold,
+        file_expires_at,
+        file_retention_action,
+        file_archived_at,
+        file_deleted_at,
+        holds,
+    })
+}
+
+fn reason_errors(reason: Option<&str>, required: bool, field: &str) -> Vec<String> {
+    match reason.map(str::trim).filter(|reason| !reason.is_empty()) {
+        None if required => vec![format!("{} is required", field)],
+        Some(reason) if reason.chars().count() > MAX_REASON_LENGTH => {
+            vec![format!("{} may be at most {} characters", field, MAX_REASON_LENGTH)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Put the report under legal hold; its file is kept until the hold is released
+pub async fn place_hold(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_id: Uuid,
+    request: PlaceHoldRequest,
+) -> Result<LegalHold, RetentionError> {
+    let errors = reason_errors(Some(&request.reason), true, "reason");
+    if !errors.is_empty() {
+        return Err(RetentionError::Invalid(errors));
+    }
+    let mut tx = db.begin().await?;
+    // Locked, so the sweep cannot take the file while the hold is being placed
+    let held = sqlx::query_scalar::<_, bool>(
+        "SELECT legal_hold FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = $2 FOR UPDATE",
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(RetentionError::NotFound("report"))?;
+    if held {
+        return Err(RetentionError::AlreadyHeld);
+    }
+    sqlx::query("INSERT INTO report_legal_holds (report_id, tenant_id, reason, placed_by) VALUES ($1, $2, $3, $4)")
+        .bind(report_id)
+        .bind(tenant.tenant_id())
+        .bind(request.reason.trim())
+        .bind(request.placed_by)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE regulatory_reports_v2 SET legal_hold = TRUE, updated_at = NOW() WHERE report_id = $1")
+        .bind(report_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    legal_hold(db, tenant, report_id).await
+}
+
+pub async fn release_hold(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_id: Uuid,
+    request: ReleaseHoldRequest,
+) -> Result<LegalHold, RetentionError> {
+    let errors = reason_errors(request.reason.as_deref(), false, "reason");
+    if !errors.is_empty() {
+        return Err(RetentionError::Invalid(errors));
+    }
+    let mut tx = db.begin().await?;
+    let held = sqlx::query_scalar::<_, bool>(
+        "SELECT legal_hold FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = $2 FOR UPDATE",
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(RetentionError::NotFound("report"))?;
+    if !held {
+        return Err(RetentionError::NotHeld);
+    }
+    sqlx::query(
+        r#"
+        UPDATE report_legal_holds SET released_by = $2, release_reason = $3, released_at = NOW()
+        WHERE report_id = $1 AND released_at IS NULL
+        "#,
+    )
+    .bind(report_id)
+    .bind(request.released_by)
+    .bind(request.reason.as_deref().map(str::trim).filter(|reason| !reason.is_empty()))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("UPDATE regulatory_reports_v2 SET legal_hold = FALSE, updated_at = NOW() WHERE report_id = $1")
+        .bind(report_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    legal_hold(db, tenant, report_id).await
+}
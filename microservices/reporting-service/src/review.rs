@@ -0,0 +1,263 @@
+//! Maker-checker review of reports
+//!
+//! Reports are generated as DRAFT and leave the platform only once approved;
+//! see `dharmaguard_common::approval`. POST /reports/:id/review approves or
+//! rejects a draft with the reviewer's comments. The reviewer is an active
+//! compliance officer or administrator of the tenant other than the report's
+//! maker, and a rejection must say why; a rejected report is corrected by an
+//! amendment, which is a new draft. The reviewer is the authenticated caller,
+//! and a draft without a recorded maker is never reviewed, since nothing would
+//! tell its checker from its maker. GET /reports/:id/review shows where the
+//! report stands and every status it went through.
+//!
+//! Deliveries asked for when a report is generated, by the request or by a
+//! report schedule, are held until the report is approved and sent then. A
+//! rejected report's held deliveries are dropped.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use dharmaguard_common::approval::{self, APPROVED, DRAFT, REJECTED};
+use dharmaguard_common::tenant::TenantContext;
+
+use crate::delivery::DeliverReportRequest;
+
+const MAX_COMMENTS_LENGTH: usize = 4000;
+/// Roles whose members may review reports
+const REVIEWER_ROLES: &[&str] = &["COMPLIANCE_OFFICER", "TENANT_ADMIN"];
+
+#[derive(Deserialize)]
+pub struct ReviewRequest {
+    /// APPROVE or REJECT
+    pub decision: String,
+    /// Required to reject
+    pub comments: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReportReview {
+    pub report_id: Uuid,
+    pub status: String,
+    pub reviewed_by: Uuid,
+    pub reviewed_at: DateTime<Utc>,
+    pub comments: Option<String>,
+    /// Deliveries held for approval that are now sent
+    pub released_deliveries: usize,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct StatusTransition {
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub actor: Option<Uuid>,
+    pub comments: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ReviewHistory {
+    pub report_id: Uuid,
+    pub status: String,
+    pub generated_by: Option<Uuid>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub review_comments: Option<String>,
+    pub held_deliveries: i64,
+    /// Oldest first
+    pub transitions: Vec<StatusTransition>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReviewError {
+    #[error("report not found")]
+    NotFound,
+    #[error("report is {0}; only DRAFT reports are reviewed")]
+    NotDraft(String),
+    #[error("a report cannot be reviewed by its maker")]
+    OwnReport,
+    #[error("report has no recorded maker; only reports generated by a user are reviewed")]
+    NoMaker,
+    #[error("invalid review")]
+    Invalid(Vec<String>),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for ReviewError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// Hold a delivery of the report until it is approved
+pub async fn hold<'e>(
+    db: impl sqlx::PgExecutor<'e>,
+    report_id: Uuid,
+    request: &DeliverReportRequest,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO report_held_deliveries (report_id, request) VALUES ($1, $2)")
+        .bind(report_id)
+        .bind(Json(request))
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Approve or reject a draft, returning the held deliveries an approval releases
+pub async fn review(
+    db: &PgPool,
+    tenant: &TenantContext,
+    reviewer: Uuid,
+    report_id: Uuid,
+    request: ReviewRequest,
+) -> Result<(ReportReview, Vec<DeliverReportRequest>), ReviewError> {
+    let comments = request.comments.as_deref().map(str::trim).filter(|comments| !comments.is_empty());
+    let mut errors = Vec::new();
+    let status = match request.decision.trim().to_uppercase().as_str() {
+        "APPROVE" => Some(APPROVED),
+        "REJECT" => {
+            if comments.is_none() {
+                errors.push("comments are required to reject a report".to_string());
+            }
+            Some(REJECTED)
+        }
+        _ => {
+            errors.push("decision must be APPROVE or REJECT".to_string());
+            None
+        }
+    };
+    if comments.is_some_and(|comments| comments.chars().count() > MAX_COMMENTS_LENGTH) {
+        errors.push(format!("comments may be at most {} characters", MAX_COMMENTS_LENGTH));
+    }
+    let reviewer_allowed = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM users
+            WHERE user_id = $1 AND tenant_id = $2 AND is_active AND role::text = ANY($3)
+        )
+        "#,
+    )
+    .bind(reviewer)
+    .bind(tenant.tenant_id())
+    .bind(REVIEWER_ROLES)
+    .fetch_one(db)
+    .await?;
+    if !reviewer_allowed {
+        errors.push(format!(
+            "the reviewer must be an active {} of the tenant",
+            REVIEWER_ROLES.join(" or ")
+        ));
+    }
+    let Some(status) = status.filter(|_| errors.is_empty()) else {
+        return Err(ReviewError::Invalid(errors));
+    };
+
+    let mut tx = db.begin().await?;
+    let report = sqlx::query_as::<_, (Option<String>, Option<Uuid>)>(
+        "SELECT status, generated_by FROM regulatory_reports_v2 WHERE tenant_id = $1 AND report_id = $2 FOR UPDATE",
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((current, generated_by)) = report else {
+        return Err(ReviewError::NotFound);
+    };
+    let current = current.unwrap_or_else(|| DRAFT.to_string());
+    if current != DRAFT {
+        return Err(ReviewError::NotDraft(current));
+    }
+    match generated_by {
+        None => return Err(ReviewError::NoMaker),
+        Some(maker) if maker == reviewer => return Err(ReviewError::OwnReport),
+        Some(_) => {}
+    }
+
+    approval::attribute(&mut tx, Some(reviewer), comments).await?;
+    let reviewed_at = sqlx::query_scalar::<_, DateTime<Utc>>(
+        r#"
+        UPDATE regulatory_reports_v2
+        SET status = $2, reviewed_by = $3, reviewed_at = NOW(), review_comments = $4,
+            approved_by = CASE WHEN $2 = 'APPROVED' THEN $3 END,
+            approved_at = CASE WHEN $2 = 'APPROVED' THEN NOW() END,
+            updated_at = NOW()
+        WHERE report_id = $1
+        RETURNING reviewed_at
+        "#,
+    )
+    .bind(report_id)
+    .bind(status)
+    .bind(reviewer)
+    .bind(comments)
+    .fetch_one(&mut *tx)
+    .await?;
+    let held = sqlx::query_scalar::<_, Json<DeliverReportRequest>>(
+        "DELETE FROM report_held_deliveries WHERE report_id = $1 RETURNING request",
+    )
+    .bind(report_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let released: Vec<DeliverReportRequest> = match status {
+        APPROVED => held.into_iter().map(|request| request.0).collect(),
+        _ => Vec::new(),
+    };
+    Ok((
+        ReportReview {
+            report_id,
+            status: status.to_string(),
+            reviewed_by: reviewer,
+            reviewed_at,
+            comments: comments.map(str::to_string),
+            released_deliveries: released.len(),
+        },
+        released,
+    ))
+}
+
+pub async fn history(db: &PgPool, tenant: &TenantContext, report_id: Uuid) -> Result<ReviewHistory, ReviewError> {
+    let report = sqlx::query_as::<
+        _,
+        (Option<String>, Option<Uuid>, Option<Uuid>, Option<DateTime<Utc>>, Option<String>, i64),
+    >(
+        r#"
+        SELECT status, generated_by, reviewed_by, reviewed_at, review_comments,
+               (SELECT COUNT(*) FROM report_held_deliveries h WHERE h.report_id = r.report_id)
+        FROM regulatory_reports_v2 r
+        WHERE tenant_id = $1 AND report_id = $2
+        "#,
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_optional(db)
+    .await?;
+    let Some((status, generated_by, reviewed_by, reviewed_at, review_comments, held_deliveries)) = report else {
+        return Err(ReviewError::NotFound);
+    };
+    let transitions = sqlx::query_as::<_, StatusTransition>(
+        r#"
+        SELECT from_status, to_status, actor, comments, created_at
+        FROM report_status_transitions
+        WHERE tenant_id = $1 AND report_id = $2
+        ORDER BY transition_id
+        "#,
+    )
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_all(db)
+    .await?;
+    Ok(ReviewHistory {
+        report_id,
+        status: status.unwrap_or_else(|| DRAFT.to_string()),
+        generated_by,
+        reviewed_by,
+        reviewed_at,
+        review_comments,
+        held_deliveries,
+        transitions,
+    })
+}
@@ -0,0 +1,417 @@
+//! Daily trade rollups
+//!
+//! A nightly job on REPORT_ROLLUP_SCHEDULE materializes each tenant's trades
+//! of a past day into trade_daily_instrument_stats, trade_daily_hour_stats and
+//! trade_daily_account_stats, and records the day in trade_rollup_days. Days
+//! going back REPORT_ROLLUP_LOOKBACK_DAYS are rolled up until they all are, so
+//! history is filled in on the first runs; stale days, those trades were
+//! written for after they were rolled up, are rolled up again whatever their
+//! age.
+//!
+//! Trading summaries read the days of their period that are rolled up and not
+//! stale from the rollups, and the others, the current day among them, from
+//! trades, as a `TradingPartial` per slice of the period; see `chunked`. A
+//! replica rolling up a day holds it, so replicas share the run.
+
+use chrono::{Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::chunked::Partial;
+use crate::{InstrumentStats, TradingSummaryReport};
+
+#[derive(Debug, Clone)]
+pub struct RollupSettings {
+    pub schedule: String,
+    pub lookback: Duration,
+}
+
+impl RollupSettings {
+    pub fn from_env() -> Self {
+        Self {
+            schedule: std::env::var("REPORT_ROLLUP_SCHEDULE")
+                .ok()
+                .filter(|schedule| !schedule.is_empty())
+                .unwrap_or_else(|| "0 30 0 * * *".to_string()),
+            lookback: Duration::days(
+                std::env::var("REPORT_ROLLUP_LOOKBACK_DAYS")
+                    .ok()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(400i64)
+                    .max(1),
+            ),
+        }
+    }
+}
+
+pub async fn schedule(scheduler: &JobScheduler, db: PgPool, settings: RollupSettings) -> anyhow::Result<()> {
+    let rollup_schedule = settings.schedule.clone();
+    let job = Job::new_async(rollup_schedule.as_str(), move |_uuid, _lock| {
+        let db = db.clone();
+        let settings = settings.clone();
+        Box::pin(async move {
+            match run(&db, &settings).await {
+                Ok(0) => {}
+                Ok(rolled_up) => info!("Rolled up {} tenant trading days", rolled_up),
+                Err(e) => error!("Trade rollup failed: {:#}", e),
+            }
+        })
+    })?;
+    scheduler.add(job).await?;
+    info!("Rolling up trading days on {}", rollup_schedule);
+    Ok(())
+}
+
+/// Roll up the past days of active tenants that are not, newest first,
+/// returning how many were
+async fn run(db: &PgPool, settings: &RollupSettings) -> anyhow::Result<usize> {
+    let due = sqlx::query_as::<_, (Uuid, NaiveDate)>(
+        r#"
+        SELECT t.tenant_id, d.day::date AS trade_date
+        FROM tenants t
+        CROSS JOIN generate_series(CURRENT_DATE - $1::int, CURRENT_DATE - 1, INTERVAL '1 day') AS d(day)
+        WHERE t.is_active
+        AND NOT EXISTS (
+            SELECT 1 FROM trade_rollup_days r
+            WHERE r.tenant_id = t.tenant_id AND r.trade_date = d.day::date AND NOT r.stale
+        )
+        UNION
+        SELECT r.tenant_id, r.trade_date
+        FROM trade_rollup_days r
+        JOIN tenants t ON t.tenant_id = r.tenant_id
+        WHERE r.stale AND r.trade_date < CURRENT_DATE AND t.is_active
+        ORDER BY trade_date DESC, tenant_id
+        "#,
+    )
+    .bind(settings.lookback.num_days() as i32)
+    .fetch_all(db)
+    .await?;
+
+    let mut rolled_up = 0;
+    for (tenant_id, trade_date) in due {
+        match roll_up(db, tenant_id, trade_date).await {
+            Ok(true) => rolled_up += 1,
+            Ok(false) => {}
+            Err(e) => warn!("Failed to roll up the trades of tenant {} on {}: {}", tenant_id, trade_date, e),
+        }
+    }
+    Ok(rolled_up)
+}
+
+/// Materialize the tenant's trades of the day; false when another replica is
+/// rolling it up
+async fn roll_up(db: &PgPool, tenant_id: Uuid, trade_date: NaiveDate) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let held = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_xact_lock(hashtextextended($1, 0))")
+        .bind(format!("trade_rollups/{}/{}", tenant_id, trade_date))
+        .fetch_one(&mut *tx)
+        .await?;
+    if !held {
+        return Ok(false);
+    }
+    // Holding the day's row keeps trades written from here on from marking it
+    // stale until this commits, after which they do
+    sqlx::query(
+        r#"
+        INSERT INTO trade_rollup_days (tenant_id, trade_date, stale, rolled_up_at)
+        VALUES ($1, $2, FALSE, NOW())
+        ON CONFLICT (tenant_id, trade_date) DO UPDATE SET stale = FALSE, rolled_up_at = NOW()
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(trade_date)
+    .execute(&mut *tx)
+    .await?;
+    for table in ["trade_daily_instrument_stats", "trade_daily_hour_stats", "trade_daily_account_stats"] {
+        sqlx::query(&format!("DELETE FROM {} WHERE tenant_id = $1 AND trade_date = $2", table))
+            .bind(tenant_id)
+            .bind(trade_date)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO trade_daily_instrument_stats (
+            tenant_id, trade_date, instrument_id, trade_count, total_volume, total_value, max_value, price_sum
+        )
+        SELECT $1, $2, instrument_id, COUNT(*), SUM(quantity), SUM(value), MAX(value), SUM(price)
+        FROM trades
+        WHERE tenant_id = $1 AND trade_time >= $2::date AND trade_time < $2::date + 1
+        GROUP BY instrument_id
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(trade_date)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO trade_daily_hour_stats (tenant_id, trade_date, hour, trade_count)
+        SELECT $1, $2, EXTRACT(HOUR FROM trade_time)::smallint, COUNT(*)
+        FROM trades
+        WHERE tenant_id = $1 AND trade_time >= $2::date AND trade_time < $2::date + 1
+        GROUP BY EXTRACT(HOUR FROM trade_time)::smallint
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(trade_date)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO trade_daily_account_stats (tenant_id, trade_date, account_id, trade_count)
+        SELECT $1, $2, account_id, COUNT(*)
+        FROM trades
+        WHERE tenant_id = $1 AND trade_time >= $2::date AND trade_time < $2::date + 1
+        GROUP BY account_id
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(trade_date)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        UPDATE trade_rollup_days
+        SET trade_count = (
+            SELECT COALESCE(SUM(trade_count), 0) FROM trade_daily_instrument_stats
+            WHERE tenant_id = $1 AND trade_date = $2
+        )
+        WHERE tenant_id = $1 AND trade_date = $2
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(trade_date)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// The days of the period read from rollups, and the trades of the others
+const ROLLED_AND_RAW: &str = r#"
+    rolled AS (
+        SELECT trade_date FROM trade_rollup_days
+        WHERE tenant_id = $1 AND trade_date BETWEEN $2 AND $3 AND NOT stale
+    ),
+    raw AS (
+        SELECT * FROM trades
+        WHERE tenant_id = $1 AND trade_time >= $2::date AND trade_time < $3::date + 1
+        AND DATE(trade_time) NOT IN (SELECT trade_date FROM rolled)
+    )
+"#;
+
+/// A slice's trades, merged with those of other slices into a trading summary
+#[derive(Serialize, Deserialize, Default)]
+pub struct TradingPartial {
+    trades: i64,
+    volume: f64,
+    value: f64,
+    largest: f64,
+    instruments: HashSet<Uuid>,
+    accounts: HashSet<Uuid>,
+    hours: HashMap<i32, i64>,
+    symbols: HashMap<String, SymbolTotals>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SymbolTotals {
+    trade_count: i64,
+    total_volume: f64,
+    total_value: f64,
+    price_sum: f64,
+}
+
+impl Partial for TradingPartial {
+    fn merge(&mut self, later: Self) {
+        self.trades += later.trades;
+        self.volume += later.volume;
+        self.value += later.value;
+        self.largest = self.largest.max(later.largest);
+        self.instruments.extend(later.instruments);
+        self.accounts.extend(later.accounts);
+        for (hour, trade_count) in later.hours {
+            *self.hours.entry(hour).or_default() += trade_count;
+        }
+        for (symbol, totals) in later.symbols {
+            let merged = self.symbols.entry(symbol).or_default();
+            merged.trade_count += totals.trade_count;
+            merged.total_volume += totals.total_volume;
+            merged.total_value += totals.total_value;
+            merged.price_sum += totals.price_sum;
+        }
+    }
+}
+
+impl TradingPartial {
+    pub fn into_report(self) -> TradingSummaryReport {
+        let mut symbols: Vec<(String, SymbolTotals)> = self.symbols.into_iter().collect();
+        symbols.sort_by(|a, b| b.1.total_value.total_cmp(&a.1.total_value).then_with(|| a.0.cmp(&b.0)));
+        let instrument_breakdown = symbols
+            .into_iter()
+            .take(20)
+            .map(|(instrument, totals)| InstrumentStats {
+                instrument,
+                trade_count: totals.trade_count,
+                total_volume: totals.total_volume,
+                total_value: totals.total_value,
+                avg_price: if totals.trade_count > 0 { totals.price_sum / totals.trade_count as f64 } else { 0.0 },
+            })
+            .collect();
+        TradingSummaryReport {
+            total_trades: self.trades,
+            total_volume: self.volume,
+            total_value: self.value,
+            unique_instruments: self.instruments.len() as i64,
+            active_clients: self.accounts.len() as i64,
+            average_trade_size: if self.trades > 0 { self.value / self.trades as f64 } else { 0.0 },
+            largest_trade: self.largest,
+            trading_hours_distribution: self
+                .hours
+                .into_iter()
+                .map(|(hour, trade_count)| (format!("{}:00", hour), trade_count))
+                .collect(),
+            instrument_breakdown,
+        }
+    }
+}
+
+/// The tenant's trades of the period, from rollups where they are current
+pub async fn trading_partial(
+    db: &PgPool,
+    tenant_id: Uuid,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<TradingPartial, sqlx::Error> {
+    let (trades, volume, value, largest) = sqlx::query_as::<_, (i64, f64, f64, f64)>(&format!(
+        r#"
+        WITH {},
+        totals AS (
+            SELECT SUM(trade_count) AS trades, SUM(total_volume) AS volume, SUM(total_value) AS value,
+                MAX(max_value) AS largest
+            FROM trade_daily_instrument_stats
+            WHERE tenant_id = $1 AND trade_date IN (SELECT trade_date FROM rolled)
+            UNION ALL
+            SELECT COUNT(*), SUM(quantity), SUM(value), MAX(value) FROM raw
+        )
+        SELECT
+            COALESCE(SUM(trades), 0)::bigint,
+            COALESCE(SUM(volume), 0)::float8,
+            COALESCE(SUM(value), 0)::float8,
+            COALESCE(MAX(largest), 0)::float8
+        FROM totals
+        "#,
+        ROLLED_AND_RAW
+    ))
+    .bind(tenant_id)
+    .bind(start_date)
+    .bind(end_date)
+    .fetch_one(db)
+    .await?;
+
+    let instruments = sqlx::query_scalar::<_, Uuid>(&format!(
+        r#"
+        WITH {}
+        SELECT instrument_id FROM trade_daily_instrument_stats
+        WHERE tenant_id = $1 AND trade_date IN (SELECT trade_date FROM rolled)
+        UNION
+        SELECT instrument_id FROM raw
+        "#,
+        ROLLED_AND_RAW
+    ))
+    .bind(tenant_id)
+    .bind(start_date)
+    .bind(end_date)
+    .fetch_all(db)
+    .await?;
+
+    let accounts = sqlx::query_scalar::<_, Uuid>(&format!(
+        r#"
+        WITH {}
+        SELECT account_id FROM trade_daily_account_stats
+        WHERE tenant_id = $1 AND trade_date IN (SELECT trade_date FROM rolled)
+        UNION
+        SELECT account_id FROM raw
+        "#,
+        ROLLED_AND_RAW
+    ))
+    .bind(tenant_id)
+    .bind(start_date)
+    .bind(end_date)
+    .fetch_all(db)
+    .await?;
+
+    let hours = sqlx::query_as::<_, (i32, i64)>(&format!(
+        r#"
+        WITH {},
+        hours AS (
+            SELECT hour, trade_count FROM trade_daily_hour_stats
+            WHERE tenant_id = $1 AND trade_date IN (SELECT trade_date FROM rolled)
+            UNION ALL
+            SELECT EXTRACT(HOUR FROM trade_time)::smallint, 1 FROM raw
+        )
+        SELECT hour::int4, SUM(trade_count)::bigint FROM hours GROUP BY hour
+        "#,
+        ROLLED_AND_RAW
+    ))
+    .bind(tenant_id)
+    .bind(start_date)
+    .bind(end_date)
+    .fetch_all(db)
+    .await?;
+
+    // Under the symbol each instrument had on the day of its trades
+    let symbols = sqlx::query_as::<_, (Option<String>, i64, f64, f64, f64)>(&format!(
+        r#"
+        WITH {},
+        by_day AS (
+            SELECT instrument_id, trade_date, trade_count, total_volume, total_value, price_sum
+            FROM trade_daily_instrument_stats
+            WHERE tenant_id = $1 AND trade_date IN (SELECT trade_date FROM rolled)
+            UNION ALL
+            SELECT instrument_id, DATE(trade_time), COUNT(*), SUM(quantity), SUM(value), SUM(price)
+            FROM raw
+            GROUP BY instrument_id, DATE(trade_time)
+        )
+        SELECT
+            COALESCE(v.symbol, i.symbol),
+            SUM(d.trade_count)::bigint,
+            SUM(d.total_volume)::float8,
+            SUM(d.total_value)::float8,
+            SUM(d.price_sum)::float8
+        FROM by_day d
+        JOIN instruments i ON i.instrument_id = d.instrument_id
+        LEFT JOIN LATERAL instrument_as_of(d.instrument_id, d.trade_date) v ON TRUE
+        GROUP BY COALESCE(v.symbol, i.symbol)
+        "#,
+        ROLLED_AND_RAW
+    ))
+    .bind(tenant_id)
+    .bind(start_date)
+    .bind(end_date)
+    .fetch_all(db)
+    .await?;
+
+    Ok(TradingPartial {
+        trades,
+        volume,
+        value,
+        largest,
+        instruments: instruments.into_iter().collect(),
+        accounts: accounts.into_iter().collect(),
+        hours: hours.into_iter().collect(),
+        symbols: symbols
+            .into_iter()
+            .map(|(symbol, trade_count, total_volume, total_value, price_sum)| {
+                let totals = SymbolTotals { trade_count, total_volume, total_value, price_sum };
+                (symbol.unwrap_or_default(), totals)
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,467 @@
+//! Reports scheduled on tenant business days
+//!
+//! A daily trading summary is generated for every business day, and a weekly
+//! compliance report for the last business day of each week, once the day's
+//! final session has been closed for REPORT_AFTER_CLOSE_MINUTES. Holidays and
+//! weekends produce nothing, and a tenant on a different exchange calendar is
+//! reported on its own close rather than at a fixed wall-clock hour.
+//!
+//! The check runs on REPORT_SCHEDULE_CHECK and looks back
+//! REPORT_SCHEDULE_LOOKBACK_DAYS, so days missed while the service was down are
+//! caught up. scheduled_report_runs holds one row per tenant, report and
+//! business day, which keeps replicas from generating the same report twice.
+//! Scheduled reports are rendered as PDF. Schedules that tenants manage
+//! themselves are in `tenant_schedules`, which generates through `record`.
+//! Every run, completed or failed, is reported to the tenant's webhooks.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use dharmaguard_common::business_hours::{self, BusinessCalendar};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::amendments::{Amendment, Superseded};
+use crate::generators;
+use crate::jobs::{self, Job};
+use crate::progress::Phase;
+use crate::render::{ReportFormat, ReportMeta};
+use crate::report_files::ReportFiles;
+use crate::webhooks::{ReportOutcome, ReportWebhooks};
+use crate::ReportEngine;
+
+#[derive(Debug, Clone)]
+pub struct ScheduleSettings {
+    pub check_schedule: String,
+    pub after_close: Duration,
+    pub lookback: Duration,
+}
+
+impl ScheduleSettings {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            check_schedule: std::env::var("REPORT_SCHEDULE_CHECK")
+                .ok()
+                .filter(|schedule| !schedule.is_empty())
+                .unwrap_or_else(|| "0 */5 * * * *".to_string()),
+            after_close: Duration::minutes(number("REPORT_AFTER_CLOSE_MINUTES", 60)),
+            lookback: Duration::days(number("REPORT_SCHEDULE_LOOKBACK_DAYS", 3).max(1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScheduledReport {
+    DailyTradingSummary,
+    WeeklyCompliance,
+}
+
+impl ScheduledReport {
+    fn report_type(self) -> &'static str {
+        match self {
+            Self::DailyTradingSummary => "TRADING_SUMMARY",
+            Self::WeeklyCompliance => "COMPLIANCE_REPORT",
+        }
+    }
+
+    fn frequency(self) -> &'static str {
+        match self {
+            Self::DailyTradingSummary => "DAILY",
+            Self::WeeklyCompliance => "WEEKLY",
+        }
+    }
+}
+
+/// A report due for a tenant, as listed by GET /reports/scheduled
+#[derive(Serialize, Debug, Clone)]
+pub struct UpcomingReport {
+    pub report_type: String,
+    pub frequency: String,
+    pub business_date: NaiveDate,
+    pub runs_at: DateTime<Utc>,
+}
+
+pub async fn schedule(
+    scheduler: &JobScheduler,
+    db: PgPool,
+    files: Arc<ReportFiles>,
+    generator: Arc<ReportEngine>,
+    webhooks: Arc<ReportWebhooks>,
+    settings: ScheduleSettings,
+) -> anyhow::Result<()> {
+    let check_schedule = settings.check_schedule.clone();
+    let job = Job::new_async(check_schedule.as_str(), move |_uuid, _lock| {
+        let db = db.clone();
+        let files = files.clone();
+        let generator = generator.clone();
+        let webhooks = webhooks.clone();
+        let settings = settings.clone();
+        Box::pin(async move {
+            if let Err(e) = run_due(&db, &files, &generator, &webhooks, &settings).await {
+                error!("Scheduled report check failed: {}", e);
+            }
+        })
+    })?;
+    scheduler.add(job).await?;
+    info!("Checking for tenant business-day closes on {}", check_schedule);
+    Ok(())
+}
+
+/// The last business day of its week, judged by the tenant's calendar
+fn ends_week(calendar: &BusinessCalendar, date: NaiveDate) -> bool {
+    calendar
+        .next_business_day(date)
+        .map_or(true, |next| next.iso_week() != date.iso_week())
+}
+
+fn reports_for(calendar: &BusinessCalendar, date: NaiveDate) -> Vec<(ScheduledReport, NaiveDate)> {
+    let mut reports = vec![(ScheduledReport::DailyTradingSummary, date)];
+    if ends_week(calendar, date) {
+        let week_start = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+        reports.push((ScheduledReport::WeeklyCompliance, week_start));
+    }
+    reports
+}
+
+async fn run_due(
+    db: &PgPool,
+    files: &ReportFiles,
+    generator: &ReportEngine,
+    webhooks: &ReportWebhooks,
+    settings: &ScheduleSettings,
+) -> anyhow::Result<()> {
+    let tenants = sqlx::query_scalar::<_, Uuid>("SELECT tenant_id FROM tenants WHERE is_active")
+        .fetch_all(db)
+        .await?;
+    let until = Utc::now() - settings.after_close;
+    let after = until - settings.lookback;
+
+    for tenant_id in tenants {
+        let calendar = match business_hours::load(db, tenant_id).await {
+            Ok(calendar) => calendar,
+            Err(e) => {
+                warn!("Skipping scheduled reports for tenant {}: {}", tenant_id, e);
+                continue;
+            }
+        };
+        for (date, _) in calendar.days_closed_between(after, until) {
+            for (report, period_start) in reports_for(&calendar, date) {
+                if let Err(e) = run_once(db, files, generator, webhooks, tenant_id, report, period_start, date).await {
+                    error!(
+                        "Scheduled {} for tenant {} on {} failed: {}",
+                        report.report_type(),
+                        tenant_id,
+                        date,
+                        e
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run_once(
+    db: &PgPool,
+    files: &ReportFiles,
+    generator: &ReportEngine,
+    webhooks: &ReportWebhooks,
+    tenant_id: Uuid,
+    report: ScheduledReport,
+    period_start: NaiveDate,
+    business_date: NaiveDate,
+) -> anyhow::Result<()> {
+    let claimed = sqlx::query(
+        r#"
+        INSERT INTO scheduled_report_runs (tenant_id, report_type, business_date)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(report.report_type())
+    .bind(business_date)
+    .execute(db)
+    .await?
+    .rows_affected()
+        == 1;
+    if !claimed {
+        return Ok(());
+    }
+
+    let outcome = generate(db, files, generator, tenant_id, report, period_start, business_date).await;
+    let (status, report_id, error) = match &outcome {
+        Ok(report_id) => ("COMPLETED", Some(*report_id), None),
+        Err(e) => ("FAILED", None, Some(e.to_string())),
+    };
+    sqlx::query(
+        r#"
+        UPDATE scheduled_report_runs
+        SET status = $4, report_id = $5, error = $6, completed_at = NOW()
+        WHERE tenant_id = $1 AND report_type = $2 AND business_date = $3
+        "#,
+    )
+    .bind(tenant_id)
+    .bind(report.report_type())
+    .bind(business_date)
+    .bind(status)
+    .bind(report_id)
+    .bind(&error)
+    .execute(db)
+    .await?;
+
+    let notice = ReportOutcome {
+        tenant_id,
+        report_id,
+        report_type: report.report_type(),
+        format: ReportFormat::Pdf.as_str(),
+        period_start,
+        period_end: business_date,
+        schedule_id: None,
+    };
+    match &error {
+        None => webhooks.completed(db, &notice).await,
+        Some(error) => webhooks.failed(db, &notice, error).await,
+    }
+
+    let report_id = outcome?;
+    info!(
+        "Generated scheduled {} {} for tenant {} covering {} to {}",
+        report.report_type(),
+        report_id,
+        tenant_id,
+        period_start,
+        business_date
+    );
+    Ok(())
+}
+
+async fn generate(
+    db: &PgPool,
+    files: &ReportFiles,
+    generator: &ReportEngine,
+    tenant_id: Uuid,
+    report: ScheduledReport,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+) -> anyhow::Result<Uuid> {
+    let template_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT template_id FROM report_templates
+        WHERE report_type = $1 AND frequency = $2 AND is_active
+        ORDER BY created_at
+        LIMIT 1
+        "#,
+    )
+    .bind(report.report_type())
+    .bind(report.frequency())
+    .fetch_optional(db)
+    .await?
+    .ok_or_else(|| {
+        anyhow::anyhow!(
+            "no active {} template for {} reports",
+            report.frequency(),
+            report.report_type()
+        )
+    })?;
+
+    record(
+        db,
+        files,
+        generator,
+        tenant_id,
+        template_id,
+        report.report_type(),
+        ReportFormat::Pdf,
+        period_start,
+        period_end,
+        None,
+        None,
+    )
+    .await
+}
+
+/// Generate a report, render it and record it in regulatory_reports_v2, as the
+/// next version of a chain when it is an amendment; the report type's default
+/// parameters are used but for an amendment, which takes those it amends. The
+/// user it is generated for is its maker, whom `review` keeps from checking it
+#[allow(clippy::too_many_arguments)]
+pub async fn record(
+    db: &PgPool,
+    files: &ReportFiles,
+    generator: &ReportEngine,
+    tenant_id: Uuid,
+    template_id: Uuid,
+    report_type: &str,
+    format: ReportFormat,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    generated_by: Option<Uuid>,
+    amendment: Option<&Amendment>,
+) -> anyhow::Result<Uuid> {
+    let Some(report_generator) = generators::get(report_type) else {
+        anyhow::bail!("{} reports cannot be generated here", report_type);
+    };
+    let given = amendment.map(|amendment| amendment.parameters.clone()).unwrap_or_default();
+    let parameters =
+        generators::resolve(report_generator, &given).map_err(|errors| anyhow::anyhow!(errors.join("; ")))?;
+    let report_id = Uuid::new_v4();
+    let job = Job { report_id, tenant_id, report_type, period_start, period_end, parameters: &parameters };
+    let report_data = generator.generate(&job).await?;
+    // The job is marked COMPLETED with the report, and FAILED here when it is not recorded
+    let outcome: anyhow::Result<Uuid> = async {
+        // XBRL filings identify the tenant by its SEBI registration
+        let registration_no = match format {
+            ReportFormat::Xbrl => {
+                sqlx::query_scalar::<_, Option<String>>("SELECT sebi_registration_no FROM tenants WHERE tenant_id = $1")
+                    .bind(tenant_id)
+                    .fetch_optional(db)
+                    .await?
+                    .flatten()
+            }
+            _ => None,
+        };
+        let layout = match format {
+            ReportFormat::Pdf => layouts::current(db, tenant_id, report_type).await?,
+            _ => None,
+        };
+
+        let meta = ReportMeta {
+            report_id,
+            tenant_id,
+            report_type: report_type.to_string(),
+            period_start,
+            period_end,
+            generated_at: Utc::now(),
+            registration_no,
+            layout,
+        };
+        generator.jobs().progress(report_id).phase(Phase::Rendering).await;
+        let file = files.create(db, &meta, format, &report_data).await?;
+        let recorded: anyhow::Result<()> = async {
+            let mut tx = db.begin().await?;
+            // The amended version is locked so two amendments of it cannot both succeed
+            if let Some(amendment) = amendment {
+                let superseded_by = sqlx::query_scalar::<_, Option<Uuid>>(
+                    "SELECT superseded_by FROM regulatory_reports_v2 WHERE report_id = $1 FOR UPDATE",
+                )
+                .bind(amendment.amends)
+                .fetch_one(&mut *tx)
+                .await?;
+                if let Some(superseded_by) = superseded_by {
+                    return Err(Superseded(superseded_by).into());
+                }
+            }
+            sqlx::query(
+                r#"
+                INSERT INTO regulatory_reports_v2 (
+                    report_id, tenant_id, template_id, report_period_start, report_period_end, status, report_data,
+                    generated_by, generated_at, file_path, file_hash, file_size, file_expires_at, layout_id,
+                    version, original_report_id, amends_report_id, amendment_reason, digital_signature, signed_at,
+                    signing_certificate_fingerprint, file_retention_action, report_parameters
+                )
+                VALUES (
+                    $1, $2, $3, $4, $5, 'DRAFT', $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19,
+                    $20, $21, $22
+                )
+                "#,
+            )
+            .bind(meta.report_id)
+            .bind(tenant_id)
+            .bind(template_id)
+            .bind(period_start)
+            .bind(period_end)
+            .bind(&report_data)
+            .bind(generated_by)
+            .bind(meta.generated_at)
+            .bind(&file.file_path)
+            .bind(&file.file_hash)
+            .bind(file.file_size)
+            .bind(file.file_expires_at)
+            .bind(meta.layout.as_ref().and_then(|layout| layout.layout_id))
+            .bind(amendment.map_or(1, |amendment| amendment.version))
+            .bind(amendment.map(|amendment| amendment.original))
+            .bind(amendment.map(|amendment| amendment.amends))
+            .bind(amendment.map(|amendment| amendment.reason.as_str()))
+            .bind(file.digital_signature())
+            .bind(file.signature.as_ref().map(|signature| signature.signed_at))
+            .bind(file.signature.as_ref().map(|signature| signature.fingerprint.as_str()))
+            .bind(file.file_retention_action.as_deref())
+            .bind(sqlx::types::Json(&parameters))
+            .execute(&mut *tx)
+            .await?;
+            if let Some(amendment) = amendment {
+                sqlx::query(
+                    "UPDATE regulatory_reports_v2 SET superseded_by = $2, superseded_at = $3, updated_at = NOW() \
+                     WHERE report_id = $1",
+                )
+                .bind(amendment.amends)
+                .bind(meta.report_id)
+                .bind(meta.generated_at)
+                .execute(&mut *tx)
+                .await?;
+            }
+            jobs::complete(&mut tx, report_id).await?;
+            tx.commit().await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = recorded {
+            files.remove(&file).await;
+            return Err(e);
+        }
+        Ok(meta.report_id)
+    }
+    .await;
+    if let Err(e) = &outcome {
+        generator.jobs().fail(report_id, &format!("{:#}", e)).await;
+    }
+    outcome
+}
+
+/// The next daily and weekly report for a tenant
+pub async fn upcoming(db: &PgPool, settings: &ScheduleSettings, tenant_id: Uuid) -> anyhow::Result<Vec<UpcomingReport>> {
+    let calendar = business_hours::load(db, tenant_id).await?;
+    let now = Utc::now();
+    let mut upcoming = Vec::new();
+    let mut weekly_found = false;
+
+    // Search forward from the earliest close that has not been reported yet
+    let mut from = now - settings.after_close;
+    while let Some(close) = calendar.next_close(from) {
+        let date = calendar.local_date(close);
+        // Only the day's final session triggers its reports
+        if calendar.sessions_on(date).last().map(|(_, last)| *last) == Some(close) {
+            for (report, _) in reports_for(&calendar, date) {
+                let already_listed = match report {
+                    ScheduledReport::DailyTradingSummary => !upcoming.is_empty(),
+                    ScheduledReport::WeeklyCompliance => weekly_found,
+                };
+                if already_listed {
+                    continue;
+                }
+                weekly_found |= report == ScheduledReport::WeeklyCompliance;
+                upcoming.push(UpcomingReport {
+                    report_type: report.report_type().to_string(),
+                    frequency: report.frequency().to_string(),
+                    business_date: date,
+                    runs_at: close + settings.after_close,
+                });
+            }
+        }
+        if weekly_found {
+            break;
+        }
+        from = close;
+    }
+    Ok(upcoming)
+}
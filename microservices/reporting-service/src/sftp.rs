@@ -0,0 +1,786 @@
+//! Report delivery to SFTP servers
+//!
+//! Exchanges and regulators that take submissions as files on an SFTP server
+//! are kept per tenant in report_sftp_targets. A target signs in with a
+//! password or a private key, which is sealed with AES-256-GCM under
+//! REPORT_SFTP_CREDENTIALS_KEY (32 bytes of hex) and never returned, and pins
+//! the server by the SHA-256 fingerprint of its host key as `ssh-keygen -l`
+//! prints it, so an unexpected server is refused before signing in.
+//!
+//! The remote path is the target's remote_dir and file_name_template, in which
+//! these placeholders are replaced:
+//!
+//! - `{tenant_id}`, `{report_id}`, `{report_type}`
+//! - `{registration_no}`: the tenant's SEBI registration number
+//! - `{period_start}`, `{period_end}`: the report period as YYYYMMDD
+//! - `{date}`, `{time}`: the upload as YYYYMMDD and HHMMSS in India time
+//! - `{ext}`: the extension of the report file, such as `csv`
+//!
+//! Missing directories are created. The file is written as `<name>.part` and
+//! renamed once complete, so the server never picks up a partial file, and an
+//! existing file of the same name is not replaced. Every upload is recorded
+//! in report_sftp_deliveries with the path, size and SHA-256 of what was sent.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Asia::Kolkata;
+use russh::client::{self, Handle};
+use russh::Disconnect;
+use russh_keys::key::{KeyPair, PublicKey};
+use russh_sftp::client::SftpSession;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+
+use crate::report_files::{FileColumns, ReportFiles};
+
+const NONCE_LEN: usize = 12;
+const PLACEHOLDERS: &[&str] = &[
+    "tenant_id",
+    "report_id",
+    "report_type",
+    "registration_no",
+    "period_start",
+    "period_end",
+    "date",
+    "time",
+    "ext",
+];
+
+pub struct SftpSettings {
+    /// Seals target credentials; SFTP delivery is off without it
+    cipher: Option<Aes256Gcm>,
+    /// Longest an upload may take, from connecting to the rename
+    timeout: Duration,
+}
+
+impl SftpSettings {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let cipher = match std::env::var("REPORT_SFTP_CREDENTIALS_KEY") {
+            Ok(key) if !key.is_empty() => {
+                let key = hex::decode(key.trim()).context("REPORT_SFTP_CREDENTIALS_KEY is not hex")?;
+                if key.len() != 32 {
+                    anyhow::bail!("REPORT_SFTP_CREDENTIALS_KEY must be 32 bytes of hex");
+                }
+                Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+            }
+            _ => None,
+        };
+        Ok(Self {
+            cipher,
+            timeout: Duration::from_secs(
+                std::env::var("REPORT_SFTP_TIMEOUT_SECS")
+                    .ok()
+                    .and_then(|secs| secs.parse().ok())
+                    .unwrap_or(120),
+            ),
+        })
+    }
+
+    fn cipher(&self) -> Result<&Aes256Gcm, SftpError> {
+        self.cipher.as_ref().ok_or(SftpError::NotConfigured)
+    }
+
+    /// Credentials are bound to their tenant and target, so a sealed value copied to another row does not open
+    fn seal(&self, tenant_id: Uuid, target_id: Uuid, credentials: &Credentials) -> Result<Vec<u8>, SftpError> {
+        let aad = [tenant_id.as_bytes().as_slice(), target_id.as_bytes().as_slice()].concat();
+        let plaintext = serde_json::to_vec(credentials).context("failed to serialize credentials")?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()?
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: &aad })
+            .map_err(|_| anyhow::anyhow!("failed to seal credentials"))?;
+        Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    fn unseal(&self, tenant_id: Uuid, target_id: Uuid, sealed: &[u8]) -> Result<Credentials, SftpError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow::anyhow!("sealed credentials of target {} are truncated", target_id).into());
+        }
+        let aad = [tenant_id.as_bytes().as_slice(), target_id.as_bytes().as_slice()].concat();
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| anyhow::anyhow!("failed to unseal credentials of target {}", target_id))?;
+        Ok(serde_json::from_slice(&plaintext).context("failed to read credentials")?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Credentials {
+    password: Option<String>,
+    private_key: Option<String>,
+    passphrase: Option<String>,
+}
+
+impl Credentials {
+    fn auth_method(&self) -> &'static str {
+        if self.private_key.is_some() {
+            "PRIVATE_KEY"
+        } else {
+            "PASSWORD"
+        }
+    }
+
+    fn key_pair(&self) -> anyhow::Result<Option<KeyPair>> {
+        self.private_key
+            .as_deref()
+            .map(|key| russh_keys::decode_secret_key(key, self.passphrase.as_deref()))
+            .transpose()
+            .context("private_key could not be read")
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        match (&self.password, &self.private_key) {
+            (None, None) => errors.push("password or private_key is required".to_string()),
+            (Some(_), Some(_)) => errors.push("give either password or private_key, not both".to_string()),
+            (Some(password), None) if password.is_empty() => errors.push("password may not be empty".to_string()),
+            _ => {}
+        }
+        if let Err(e) = self.key_pair() {
+            errors.push(format!("{:#}", e));
+        }
+        errors
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateTargetRequest {
+    pub name: String,
+    pub host: String,
+    pub port: Option<i32>,
+    pub username: String,
+    pub password: Option<String>,
+    /// OpenSSH or PKCS#8 PEM
+    pub private_key: Option<String>,
+    pub private_key_passphrase: Option<String>,
+    /// `SHA256:...` as `ssh-keygen -l` prints it
+    pub host_key_fingerprint: String,
+    pub remote_dir: Option<String>,
+    pub file_name_template: String,
+    pub is_active: Option<bool>,
+    pub created_by: Option<Uuid>,
+}
+
+/// Fields left out keep their value; a password or private key replaces the credentials
+#[derive(Deserialize)]
+pub struct UpdateTargetRequest {
+    pub name: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<i32>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub private_key: Option<String>,
+    pub private_key_passphrase: Option<String>,
+    pub host_key_fingerprint: Option<String>,
+    pub remote_dir: Option<String>,
+    pub file_name_template: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SftpTarget {
+    pub target_id: Uuid,
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub host: String,
+    pub port: i32,
+    pub username: String,
+    pub auth_method: String,
+    pub host_key_fingerprint: String,
+    pub remote_dir: String,
+    pub file_name_template: String,
+    pub is_active: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const TARGET_COLUMNS: &str = "target_id, tenant_id, name, host, port, username, auth_method, host_key_fingerprint, \
+     remote_dir, file_name_template, is_active, created_by, created_at, updated_at";
+
+#[derive(Deserialize)]
+pub struct SftpDeliveryRequest {
+    pub target_id: Uuid,
+    pub requested_by: Option<Uuid>,
+}
+
+/// The receipt of an upload
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SftpReceipt {
+    pub delivery_id: Uuid,
+    pub report_id: Uuid,
+    pub target_id: Option<Uuid>,
+    pub host: String,
+    pub port: i32,
+    pub username: String,
+    pub remote_path: String,
+    pub file_sha256: Option<String>,
+    pub file_size: Option<i64>,
+    pub status: String,
+    pub failure_reason: Option<String>,
+    pub requested_by: Option<Uuid>,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+const RECEIPT_COLUMNS: &str = "delivery_id, report_id, target_id, host, port, username, remote_path, file_sha256, \
+     file_size, status, failure_reason, requested_by, started_at, completed_at";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SftpError {
+    #[error("REPORT_SFTP_CREDENTIALS_KEY is not configured")]
+    NotConfigured,
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("invalid SFTP target")]
+    Invalid(Vec<String>),
+    #[error("an SFTP target named '{0}' already exists")]
+    NameTaken(String),
+    #[error("SFTP target {0} is not active")]
+    Inactive(Uuid),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for SftpError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// The fields of a target a tenant sets
+struct Definition {
+    name: String,
+    host: String,
+    port: i32,
+    username: String,
+    host_key_fingerprint: String,
+    remote_dir: String,
+    file_name_template: String,
+    is_active: bool,
+}
+
+/// `SHA256:` and base64 padding are optional; the digest is what is compared
+fn normalize_fingerprint(fingerprint: &str) -> String {
+    let fingerprint = fingerprint.trim();
+    let digest = fingerprint.strip_prefix("SHA256:").unwrap_or(fingerprint);
+    format!("SHA256:{}", digest.trim_end_matches('='))
+}
+
+/// The placeholder names of a template, or why it is malformed
+fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err("has a '}' without a '{'".to_string());
+        }
+        let Some(end) = rest[start..].find('}') else {
+            return Err("has a '{' without a '}'".to_string());
+        };
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!("has an unknown placeholder {{{}}}", name));
+        }
+        names.push(name);
+        rest = &rest[start + end + 1..];
+    }
+    Ok(names)
+}
+
+/// Replace a template's placeholders; values are limited to characters safe in a file name
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    values.iter().fold(template.to_string(), |filled, (name, value)| {
+        let safe: String = value
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+            .collect();
+        filled.replace(&format!("{{{}}}", name), &safe)
+    })
+}
+
+impl Definition {
+    fn from_request(request: &CreateTargetRequest) -> Self {
+        Self {
+            name: request.name.trim().to_string(),
+            host: request.host.trim().to_string(),
+            port: request.port.unwrap_or(22),
+            username: request.username.trim().to_string(),
+            host_key_fingerprint: normalize_fingerprint(&request.host_key_fingerprint),
+            remote_dir: request.remote_dir.as_deref().unwrap_or(".").trim().to_string(),
+            file_name_template: request.file_name_template.trim().to_string(),
+            is_active: request.is_active.unwrap_or(true),
+        }
+    }
+
+    fn update(target: SftpTarget, request: &UpdateTargetRequest) -> Self {
+        let trimmed = |value: &Option<String>, current: String| {
+            value.as_deref().map_or(current, |value| value.trim().to_string())
+        };
+        Self {
+            name: trimmed(&request.name, target.name),
+            host: trimmed(&request.host, target.host),
+            port: request.port.unwrap_or(target.port),
+            username: trimmed(&request.username, target.username),
+            host_key_fingerprint: request
+                .host_key_fingerprint
+                .as_deref()
+                .map_or(target.host_key_fingerprint, normalize_fingerprint),
+            remote_dir: trimmed(&request.remote_dir, target.remote_dir),
+            file_name_template: trimmed(&request.file_name_template, target.file_name_template),
+            is_active: request.is_active.unwrap_or(target.is_active),
+        }
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("name is required".to_string());
+        } else if self.name.chars().count() > 200 {
+            errors.push("name may be at most 200 characters".to_string());
+        }
+        if self.host.is_empty() || self.host.len() > 255 || self.host.contains(char::is_whitespace) {
+            errors.push("host must be a host name or address".to_string());
+        }
+        if !(1..=65535).contains(&self.port) {
+            errors.push("port must be between 1 and 65535".to_string());
+        }
+        if self.username.is_empty() {
+            errors.push("username is required".to_string());
+        }
+        let digest = self.host_key_fingerprint.trim_start_matches("SHA256:");
+        if digest.len() != 43 || !digest.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/') {
+            errors.push("host_key_fingerprint must be a SHA256 fingerprint as ssh-keygen -l prints it".to_string());
+        }
+        if self.remote_dir.is_empty() {
+            errors.push("remote_dir may not be empty".to_string());
+        } else if self.remote_dir.split('/').any(|segment| segment == "..") {
+            errors.push("remote_dir may not contain '..'".to_string());
+        }
+        if let Err(e) = placeholders(&self.remote_dir) {
+            errors.push(format!("remote_dir {}", e));
+        }
+        if self.file_name_template.is_empty() {
+            errors.push("file_name_template is required".to_string());
+        } else if self.file_name_template.contains('/') || self.file_name_template.contains("..") {
+            errors.push("file_name_template may not contain '/' or '..'".to_string());
+        } else if self.file_name_template.len() > 255 {
+            errors.push("file_name_template may be at most 255 characters".to_string());
+        }
+        match placeholders(&self.file_name_template) {
+            Err(e) => errors.push(format!("file_name_template {}", e)),
+            // The same report would get the same name on every upload, and only the first would go through
+            Ok(names) if !names.iter().any(|name| ["report_id", "date", "time"].contains(name)) => {
+                errors.push("file_name_template must contain {report_id}, {date} or {time}".to_string());
+            }
+            Ok(_) => {}
+        }
+        errors
+    }
+}
+
+fn name_taken(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Database(db) if db.constraint() == Some("uq_report_sftp_target_name"))
+}
+
+pub async fn list_targets(db: &PgPool, tenant: &TenantContext) -> Result<Vec<SftpTarget>, sqlx::Error> {
+    sqlx::query_as::<_, SftpTarget>(&format!(
+        "SELECT {} FROM report_sftp_targets WHERE tenant_id = $1 ORDER BY name",
+        TARGET_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .fetch_all(db)
+    .await
+}
+
+pub async fn get_target(db: &PgPool, tenant: &TenantContext, target_id: Uuid) -> Result<SftpTarget, SftpError> {
+    sqlx::query_as::<_, SftpTarget>(&format!(
+        "SELECT {} FROM report_sftp_targets WHERE tenant_id = $1 AND target_id = $2",
+        TARGET_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(target_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(SftpError::NotFound("SFTP target"))
+}
+
+pub async fn create_target(
+    db: &PgPool,
+    settings: &SftpSettings,
+    tenant: &TenantContext,
+    request: CreateTargetRequest,
+) -> Result<SftpTarget, SftpError> {
+    settings.cipher()?;
+    let definition = Definition::from_request(&request);
+    let credentials = Credentials {
+        password: request.password,
+        private_key: request.private_key,
+        passphrase: request.private_key_passphrase,
+    };
+    let mut errors = definition.validate();
+    errors.extend(credentials.validate());
+    if !errors.is_empty() {
+        return Err(SftpError::Invalid(errors));
+    }
+
+    let target_id = Uuid::new_v4();
+    let sealed = settings.seal(tenant.tenant_id(), target_id, &credentials)?;
+    sqlx::query_as::<_, SftpTarget>(&format!(
+        r#"
+        INSERT INTO report_sftp_targets (
+            target_id, tenant_id, name, host, port, username, auth_method, credentials_sealed, host_key_fingerprint,
+            remote_dir, file_name_template, is_active, created_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        RETURNING {}
+        "#,
+        TARGET_COLUMNS
+    ))
+    .bind(target_id)
+    .bind(tenant.tenant_id())
+    .bind(&definition.name)
+    .bind(&definition.host)
+    .bind(definition.port)
+    .bind(&definition.username)
+    .bind(credentials.auth_method())
+    .bind(&sealed)
+    .bind(&definition.host_key_fingerprint)
+    .bind(&definition.remote_dir)
+    .bind(&definition.file_name_template)
+    .bind(definition.is_active)
+    .bind(request.created_by)
+    .fetch_one(db)
+    .await
+    .map_err(|e| if name_taken(&e) { SftpError::NameTaken(definition.name.clone()) } else { e.into() })
+}
+
+pub async fn update_target(
+    db: &PgPool,
+    settings: &SftpSettings,
+    tenant: &TenantContext,
+    target_id: Uuid,
+    request: UpdateTargetRequest,
+) -> Result<SftpTarget, SftpError> {
+    let target = get_target(db, tenant, target_id).await?;
+    let definition = Definition::update(target, &request);
+    let mut errors = definition.validate();
+    let credentials = (request.password.is_some() || request.private_key.is_some()).then(|| Credentials {
+        password: request.password,
+        private_key: request.private_key,
+        passphrase: request.private_key_passphrase,
+    });
+    if let Some(credentials) = &credentials {
+        errors.extend(credentials.validate());
+    }
+    if !errors.is_empty() {
+        return Err(SftpError::Invalid(errors));
+    }
+    let sealed = credentials
+        .as_ref()
+        .map(|credentials| settings.seal(tenant.tenant_id(), target_id, credentials))
+        .transpose()?;
+
+    sqlx::query_as::<_, SftpTarget>(&format!(
+        r#"
+        UPDATE report_sftp_targets
+        SET name = $3, host = $4, port = $5, username = $6, host_key_fingerprint = $7, remote_dir = $8,
+            file_name_template = $9, is_active = $10,
+            auth_method = COALESCE($11, auth_method), credentials_sealed = COALESCE($12, credentials_sealed),
+            updated_at = NOW()
+        WHERE tenant_id = $1 AND target_id = $2
+        RETURNING {}
+        "#,
+        TARGET_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(target_id)
+    .bind(&definition.name)
+    .bind(&definition.host)
+    .bind(definition.port)
+    .bind(&definition.username)
+    .bind(&definition.host_key_fingerprint)
+    .bind(&definition.remote_dir)
+    .bind(&definition.file_name_template)
+    .bind(definition.is_active)
+    .bind(credentials.as_ref().map(Credentials::auth_method))
+    .bind(sealed)
+    .fetch_optional(db)
+    .await
+    .map_err(|e| if name_taken(&e) { SftpError::NameTaken(definition.name.clone()) } else { e.into() })?
+    .ok_or(SftpError::NotFound("SFTP target"))
+}
+
+/// Receipts of uploads to the target are kept, without the target
+pub async fn delete_target(db: &PgPool, tenant: &TenantContext, target_id: Uuid) -> Result<(), SftpError> {
+    let deleted = sqlx::query("DELETE FROM report_sftp_targets WHERE tenant_id = $1 AND target_id = $2")
+        .bind(tenant.tenant_id())
+        .bind(target_id)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(SftpError::NotFound("SFTP target"));
+    }
+    Ok(())
+}
+
+pub async fn list_receipts(
+    db: &PgPool,
+    tenant: &TenantContext,
+    report_id: Uuid,
+) -> Result<Vec<SftpReceipt>, sqlx::Error> {
+    sqlx::query_as::<_, SftpReceipt>(&format!(
+        "SELECT {} FROM report_sftp_deliveries WHERE tenant_id = $1 AND report_id = $2 ORDER BY started_at DESC",
+        RECEIPT_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(report_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Checks the server's host key against the target's fingerprint
+struct PinnedHostKey {
+    fingerprint: String,
+}
+
+#[async_trait]
+impl client::Handler for PinnedHostKey {
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self, server_public_key: &PublicKey) -> Result<bool, Self::Error> {
+        Ok(normalize_fingerprint(&server_public_key.fingerprint()) == self.fingerprint)
+    }
+}
+
+/// Where the upload goes and how to sign in
+struct Connection {
+    host: String,
+    port: u16,
+    username: String,
+    host_key_fingerprint: String,
+    credentials: Credentials,
+}
+
+async fn connect(connection: &Connection, timeout: Duration) -> anyhow::Result<Handle<PinnedHostKey>> {
+    let config = client::Config {
+        inactivity_timeout: Some(timeout),
+        ..Default::default()
+    };
+    let handler = PinnedHostKey {
+        fingerprint: connection.host_key_fingerprint.clone(),
+    };
+    let mut session = client::connect(Arc::new(config), (connection.host.as_str(), connection.port), handler)
+        .await
+        .map_err(|e| match e {
+            russh::Error::UnknownKey => anyhow::anyhow!("the server's host key does not match host_key_fingerprint"),
+            e => anyhow::Error::from(e).context("failed to connect"),
+        })?;
+    let signed_in = match connection.credentials.key_pair()? {
+        Some(key_pair) => session.authenticate_publickey(&connection.username, Arc::new(key_pair)).await?,
+        None => {
+            let password = connection.credentials.password.as_deref().unwrap_or_default();
+            session.authenticate_password(&connection.username, password).await?
+        }
+    };
+    if !signed_in {
+        anyhow::bail!("the server refused the credentials of {}", connection.username);
+    }
+    Ok(session)
+}
+
+/// Put the file at `remote_path`, creating its directory; returns the size the server reports
+async fn upload(connection: &Connection, timeout: Duration, remote_path: &str, contents: &[u8]) -> anyhow::Result<u64> {
+    let session = connect(connection, timeout).await?;
+    let channel = session.channel_open_session().await.context("failed to open a channel")?;
+    channel.request_subsystem(true, "sftp").await.context("failed to start SFTP")?;
+    let sftp = SftpSession::new(channel.into_stream()).await.context("failed to start SFTP")?;
+
+    if let Some((dir, _)) = remote_path.rsplit_once('/') {
+        let mut path = if dir.starts_with('/') { "/".to_string() } else { String::new() };
+        for segment in dir.split('/').filter(|segment| !segment.is_empty() && *segment != ".") {
+            if !path.is_empty() && !path.ends_with('/') {
+                path.push('/');
+            }
+            path.push_str(segment);
+            if !sftp.try_exists(&path).await? {
+                sftp.create_dir(&path).await.with_context(|| format!("failed to create {}", path))?;
+            }
+        }
+    }
+    if sftp.try_exists(remote_path).await? {
+        anyhow::bail!("{} already exists on the server", remote_path);
+    }
+
+    let partial = format!("{}.part", remote_path);
+    let mut file = sftp.create(&partial).await.with_context(|| format!("failed to create {}", partial))?;
+    file.write_all(contents).await.with_context(|| format!("failed to write {}", partial))?;
+    file.shutdown().await.with_context(|| format!("failed to close {}", partial))?;
+    if let Err(e) = sftp.rename(&partial, remote_path).await {
+        let _ = sftp.remove_file(&partial).await;
+        return Err(anyhow::Error::from(e).context(format!("failed to rename {} to {}", partial, remote_path)));
+    }
+    let size = sftp.metadata(remote_path).await?.size.unwrap_or_default();
+
+    let _ = sftp.close().await;
+    let _ = session.disconnect(Disconnect::ByApplication, "", "en").await;
+    Ok(size)
+}
+
+/// Record the upload with its failure, if any, on the receipt
+async fn complete(
+    db: &PgPool,
+    delivery_id: Uuid,
+    outcome: &anyhow::Result<()>,
+) -> Result<SftpReceipt, sqlx::Error> {
+    let (status, failure_reason) = match outcome {
+        Ok(()) => ("UPLOADED", None),
+        Err(e) => ("FAILED", Some(format!("{:#}", e))),
+    };
+    sqlx::query_as::<_, SftpReceipt>(&format!(
+        r#"
+        UPDATE report_sftp_deliveries
+        SET status = $2, failure_reason = $3, completed_at = NOW()
+        WHERE delivery_id = $1
+        RETURNING {}
+        "#,
+        RECEIPT_COLUMNS
+    ))
+    .bind(delivery_id)
+    .bind(status)
+    .bind(failure_reason)
+    .fetch_one(db)
+    .await
+}
+
+/// Upload the report's file to the target; a failed upload is returned as a FAILED receipt
+pub async fn deliver(
+    db: &PgPool,
+    files: &ReportFiles,
+    settings: &SftpSettings,
+    tenant: &TenantContext,
+    report_id: Uuid,
+    request: &SftpDeliveryRequest,
+) -> Result<SftpReceipt, SftpError> {
+    settings.cipher()?;
+    let tenant_id = tenant.tenant_id();
+    let target = sqlx::query!(
+        r#"
+        SELECT host, port, username, credentials_sealed, host_key_fingerprint, remote_dir, file_name_template,
+               is_active
+        FROM report_sftp_targets
+        WHERE tenant_id = $1 AND target_id = $2
+        "#,
+        tenant_id,
+        request.target_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(SftpError::NotFound("SFTP target"))?;
+    if !target.is_active {
+        return Err(SftpError::Inactive(request.target_id));
+    }
+
+    let report = sqlx::query!(
+        r#"
+        SELECT r.report_data, r.report_period_start, r.report_period_end, r.file_path, r.file_hash, r.file_size,
+               r.file_deleted_at, r.file_archived_at, t.report_type, tn.sebi_registration_no
+        FROM regulatory_reports_v2 r
+        JOIN report_templates t ON t.template_id = r.template_id
+        JOIN tenants tn ON tn.tenant_id = r.tenant_id
+        WHERE r.tenant_id = $1 AND r.report_id = $2
+        "#,
+        tenant_id,
+        report_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(SftpError::NotFound("report"))?;
+    let columns = FileColumns {
+        file_path: report.file_path,
+        file_hash: report.file_hash,
+        file_size: report.file_size,
+        file_deleted_at: report.file_deleted_at,
+        file_archived_at: report.file_archived_at,
+    };
+    let file = files.open(report_id, &columns, &report.report_data).await?;
+
+    let now = Utc::now().with_timezone(&Kolkata);
+    let period = |date: NaiveDate| date.format("%Y%m%d").to_string();
+    let values = [
+        ("tenant_id", tenant_id.to_string()),
+        ("report_id", report_id.to_string()),
+        ("report_type", report.report_type),
+        ("registration_no", report.sebi_registration_no.unwrap_or_default()),
+        ("period_start", period(report.report_period_start)),
+        ("period_end", period(report.report_period_end)),
+        ("date", now.format("%Y%m%d").to_string()),
+        ("time", now.format("%H%M%S").to_string()),
+        ("ext", file.name.rsplit_once('.').map_or("", |(_, ext)| ext).to_string()),
+    ];
+    let remote_path = format!(
+        "{}/{}",
+        fill(&target.remote_dir, &values).trim_end_matches('/'),
+        fill(&target.file_name_template, &values)
+    );
+
+    let file_sha256 = hex::encode(Sha256::digest(&file.contents));
+    let delivery_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO report_sftp_deliveries (
+            tenant_id, report_id, target_id, host, port, username, remote_path, file_sha256, file_size, requested_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING delivery_id
+        "#,
+        tenant_id,
+        report_id,
+        request.target_id,
+        target.host,
+        target.port,
+        target.username,
+        remote_path,
+        file_sha256,
+        file.contents.len() as i64,
+        request.requested_by
+    )
+    .fetch_one(db)
+    .await?;
+
+    let outcome: anyhow::Result<()> = async {
+        let connection = Connection {
+            port: u16::try_from(target.port).context("port out of range")?,
+            host: target.host,
+            username: target.username,
+            host_key_fingerprint: target.host_key_fingerprint,
+            credentials: settings.unseal(tenant_id, request.target_id, &target.credentials_sealed)?,
+        };
+        let upload = upload(&connection, settings.timeout, &remote_path, &file.contents);
+        let uploaded = tokio::time::timeout(settings.timeout, upload)
+            .await
+            .map_err(|_| anyhow::anyhow!("the upload took longer than {}s", settings.timeout.as_secs()))??;
+        if uploaded != file.contents.len() as u64 {
+            anyhow::bail!("the server has {} bytes of the {} sent", uploaded, file.contents.len());
+        }
+        Ok(())
+    }
+    .await;
+    match &outcome {
+        Ok(()) => info!("Uploaded report {} to {} on SFTP target {}", report_id, remote_path, request.target_id),
+        Err(e) => warn!("Failed to upload report {} to SFTP target {}: {:#}", report_id, request.target_id, e),
+    }
+    Ok(complete(db, delivery_id, &outcome).await?)
+}
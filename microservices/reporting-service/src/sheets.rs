@@ -0,0 +1,750 @@
+//! Reports as tables, for CSV and Excel output
+//!
+//! Each report type is laid out as named sheets of rows under a header:
+//!
+//! - TRADING_SUMMARY: `Summary` (metric, value), `Instruments` (the
+//!   instrument breakdown by traded value) and `Trading hours` (trades per
+//!   hour of the day).
+//! - COMPLIANCE_REPORT: `Summary` (metric, value), `Alert patterns` (alerts
+//!   per pattern, most frequent first) and `Risk metrics` (metric, value).
+//! - CLIENT_EXPOSURE: `Summary` (metric, value), `Clients` (a row per client,
+//!   largest gross exposure first) and `Positions` (the clients' positions
+//!   under their client code, in the same order).
+//! - ORDER_TRADE_RATIO: `Summary` (metric, value), `Clients` (orders, trades
+//!   and ratio over the period, highest ratio first) and `Ratios` (a row per
+//!   day or month, client and instrument).
+//! - BROKERAGE_SUMMARY: `Summary` (metric, value), `Clients` (charges over
+//!   every segment, most charged first) and `Charges` (a row per client and
+//!   segment, for reconciling with the back office).
+//! - MARGIN_REPORT: `Summary` (metric, value), `Clients` (utilization,
+//!   shortfalls and penalty over the period, largest penalty first), `Days` (a
+//!   row per day and client) and `Violations` (every shortfall, per exchange).
+//! - ALERT_AGEING: `Summary` (metric, value, the SLAs among them), `Ageing` (a
+//!   row per alert type and severity with both distributions) and `Breaches`
+//!   (alerts in breach of an SLA, longest overdue first).
+//!
+//! The summaries start with the report's id, tenant, period and generation
+//! time. Other report types are one `Report` sheet with a row per value of
+//! their data, nested fields joined by dots and list items numbered from 0.
+//!
+//! An xlsx workbook has a worksheet per sheet. A CSV file has the sheets one
+//! after another, each led by a row holding only its name and separated by an
+//! empty row, so rows differ in length.
+
+use anyhow::Context;
+use rust_xlsxwriter::{DocProperties, Format, Workbook};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::render::ReportMeta;
+use crate::{
+    AgeDistribution, AlertAgeingReport, BrokerageSummaryReport, Charges, ClientExposureReport, ComplianceReport,
+    MarginReport, OrderTradeRatioReport, TradingSummaryReport, SEVERITIES,
+};
+
+pub enum Cell {
+    Text(String),
+    Number(f64),
+}
+
+impl Cell {
+    fn text(text: impl Into<String>) -> Self {
+        Self::Text(text.into())
+    }
+
+    fn to_csv(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Number(number) => number.to_string(),
+        }
+    }
+}
+
+impl From<f64> for Cell {
+    fn from(number: f64) -> Self {
+        Self::Number(number)
+    }
+}
+
+impl From<i64> for Cell {
+    fn from(number: i64) -> Self {
+        Self::Number(number as f64)
+    }
+}
+
+pub struct Sheet {
+    pub name: &'static str,
+    pub columns: &'static [&'static str],
+    pub rows: Vec<Vec<Cell>>,
+}
+
+fn summary(meta: &ReportMeta, metrics: Vec<(&str, Cell)>) -> Sheet {
+    let mut rows = vec![
+        vec![Cell::text("Report"), Cell::text(meta.report_id.to_string())],
+        vec![Cell::text("Tenant"), Cell::text(meta.tenant_id.to_string())],
+        vec![Cell::text("Period start"), Cell::text(meta.period_start.to_string())],
+        vec![Cell::text("Period end"), Cell::text(meta.period_end.to_string())],
+        vec![Cell::text("Generated at"), Cell::text(meta.generated_at.to_rfc3339())],
+    ];
+    rows.extend(metrics.into_iter().map(|(metric, value)| vec![Cell::text(metric), value]));
+    Sheet {
+        name: "Summary",
+        columns: &["Metric", "Value"],
+        rows,
+    }
+}
+
+fn trading_summary(meta: &ReportMeta, report: TradingSummaryReport) -> Vec<Sheet> {
+    let mut hours: Vec<(String, i64)> = report.trading_hours_distribution.into_iter().collect();
+    hours.sort_by_key(|(hour, _)| hour.split(':').next().and_then(|hour| hour.parse::<u32>().ok()));
+    vec![
+        summary(
+            meta,
+            vec![
+                ("Total trades", report.total_trades.into()),
+                ("Total volume", report.total_volume.into()),
+                ("Total value", report.total_value.into()),
+                ("Instruments traded", report.unique_instruments.into()),
+                ("Active clients", report.active_clients.into()),
+                ("Average trade size", report.average_trade_size.into()),
+                ("Largest trade", report.largest_trade.into()),
+            ],
+        ),
+        Sheet {
+            name: "Instruments",
+            columns: &["Instrument", "Trades", "Volume", "Value", "Average price"],
+            rows: report
+                .instrument_breakdown
+                .into_iter()
+                .map(|stats| {
+                    vec![
+                        Cell::Text(stats.instrument),
+                        stats.trade_count.into(),
+                        stats.total_volume.into(),
+                        stats.total_value.into(),
+                        stats.avg_price.into(),
+                    ]
+                })
+                .collect(),
+        },
+        Sheet {
+            name: "Trading hours",
+            columns: &["Hour", "Trades"],
+            rows: hours.into_iter().map(|(hour, trades)| vec![Cell::Text(hour), trades.into()]).collect(),
+        },
+    ]
+}
+
+fn compliance_report(meta: &ReportMeta, report: ComplianceReport) -> Vec<Sheet> {
+    let mut patterns: Vec<(String, i64)> = report.pattern_breakdown.into_iter().collect();
+    patterns.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let risk = report.risk_metrics;
+    vec![
+        summary(
+            meta,
+            vec![
+                ("Compliance score", report.compliance_score.into()),
+                ("Alerts generated", report.alerts_generated.into()),
+                ("Critical alerts", report.critical_alerts.into()),
+                ("Resolved alerts", report.resolved_alerts.into()),
+                ("Pending investigations", report.pending_investigations.into()),
+                ("Violations detected", report.violations_detected.into()),
+            ],
+        ),
+        Sheet {
+            name: "Alert patterns",
+            columns: &["Pattern", "Alerts"],
+            rows: patterns.into_iter().map(|(pattern, alerts)| vec![Cell::Text(pattern), alerts.into()]).collect(),
+        },
+        Sheet {
+            name: "Risk metrics",
+            columns: &["Metric", "Value"],
+            rows: vec![
+                vec![Cell::text("Value at risk (95%)"), risk.var_95.into()],
+                vec![Cell::text("Value at risk (99%)"), risk.var_99.into()],
+                vec![Cell::text("Maximum drawdown"), risk.max_drawdown.into()],
+                vec![Cell::text("Sharpe ratio"), risk.sharpe_ratio.into()],
+                vec![Cell::text("Volatility"), risk.volatility.into()],
+            ],
+        },
+    ]
+}
+
+/// An absent value, such as a client without margin statements, is an empty cell
+fn optional(value: Option<f64>) -> Cell {
+    value.map_or_else(|| Cell::text(""), Cell::Number)
+}
+
+fn client_exposure(meta: &ReportMeta, report: ClientExposureReport) -> Vec<Sheet> {
+    let summary = summary(
+        meta,
+        vec![
+            ("Clients with positions", report.clients_with_positions.into()),
+            ("Gross exposure", report.total_gross_exposure.into()),
+            ("Net exposure", report.total_net_exposure.into()),
+            ("Margin required", report.total_margin_required.into()),
+            ("Clients over a limit", report.clients_over_limit.into()),
+        ],
+    );
+    let mut clients = Vec::new();
+    let mut positions = Vec::new();
+    for client in report.clients {
+        clients.push(vec![
+            Cell::text(client.client_code.clone()),
+            Cell::Text(client.client_name.unwrap_or_default()),
+            client.gross_exposure.into(),
+            client.net_exposure.into(),
+            client.long_exposure.into(),
+            client.short_exposure.into(),
+            client.bought_value.into(),
+            client.sold_value.into(),
+            optional(client.margin_required),
+            optional(client.collateral_available),
+            optional(client.margin_utilization),
+            Cell::Text(client.largest_instrument.unwrap_or_default()),
+            client.concentration.into(),
+            optional(client.exposure_limit),
+            optional(client.exposure_limit_utilization),
+            client.limit_breaches.into(),
+        ]);
+        positions.extend(client.positions.into_iter().map(|position| {
+            vec![
+                Cell::text(client.client_code.clone()),
+                Cell::Text(position.instrument),
+                position.net_quantity.into(),
+                position.mark_price.into(),
+                position.market_value.into(),
+                position.share_of_exposure.into(),
+                position.bought_value.into(),
+                position.sold_value.into(),
+                optional(position.position_limit),
+                optional(position.exposure_limit),
+                optional(position.limit_utilization),
+            ]
+        }));
+    }
+    vec![
+        summary,
+        Sheet {
+            name: "Clients",
+            columns: &[
+                "Client",
+                "Name",
+                "Gross exposure",
+                "Net exposure",
+                "Long exposure",
+                "Short exposure",
+                "Bought",
+                "Sold",
+                "Margin required",
+                "Collateral available",
+                "Margin utilization",
+                "Largest instrument",
+                "Concentration",
+                "Exposure limit",
+                "Exposure limit utilization",
+                "Limit breaches",
+            ],
+            rows: clients,
+        },
+        Sheet {
+            name: "Positions",
+            columns: &[
+                "Client",
+                "Instrument",
+                "Net quantity",
+                "Mark price",
+                "Market value",
+                "Share of exposure",
+                "Bought",
+                "Sold",
+                "Position limit",
+                "Exposure limit",
+                "Limit utilization",
+            ],
+            rows: positions,
+        },
+    ]
+}
+
+fn order_trade_ratio(meta: &ReportMeta, report: OrderTradeRatioReport) -> Vec<Sheet> {
+    let thresholds: Vec<String> = report.thresholds.iter().map(f64::to_string).collect();
+    vec![
+        summary(
+            meta,
+            vec![
+                ("Granularity", Cell::Text(report.granularity)),
+                ("Thresholds", Cell::Text(thresholds.join(", "))),
+                ("Orders", report.total_orders.into()),
+                ("Trades", report.total_trades.into()),
+                ("Order-to-trade ratio", report.overall_ratio.into()),
+                ("Flagged ratios", report.flagged_ratios.into()),
+                ("Clients flagged", report.clients_flagged.into()),
+            ],
+        ),
+        Sheet {
+            name: "Clients",
+            columns: &["Client", "Orders", "Trades", "Ratio", "Highest ratio", "Flagged ratios"],
+            rows: report
+                .clients
+                .into_iter()
+                .map(|client| {
+                    vec![
+                        Cell::Text(client.client),
+                        client.orders.into(),
+                        client.trades.into(),
+                        client.ratio.into(),
+                        client.highest_ratio.into(),
+                        client.flagged_ratios.into(),
+                    ]
+                })
+                .collect(),
+        },
+        Sheet {
+            name: "Ratios",
+            columns: &["Date", "Client", "Instrument", "Orders", "Trades", "Ratio", "Threshold exceeded"],
+            rows: report
+                .ratios
+                .into_iter()
+                .map(|row| {
+                    vec![
+                        Cell::Text(row.date.to_string()),
+                        Cell::Text(row.client),
+                        Cell::Text(row.instrument),
+                        row.orders.into(),
+                        row.trades.into(),
+                        row.ratio.into(),
+                        optional(row.threshold_exceeded),
+                    ]
+                })
+                .collect(),
+        },
+    ]
+}
+
+fn charge_cells(charges: Charges) -> Vec<Cell> {
+    vec![
+        charges.trades.into(),
+        charges.turnover.into(),
+        charges.brokerage.into(),
+        charges.stt.into(),
+        charges.exchange_charges.into(),
+        charges.sebi_fees.into(),
+        charges.stamp_duty.into(),
+        charges.gst.into(),
+        charges.unallocated_taxes.into(),
+        charges.total_charges.into(),
+    ]
+}
+
+fn brokerage_summary(meta: &ReportMeta, report: BrokerageSummaryReport) -> Vec<Sheet> {
+    let totals = report.totals;
+    vec![
+        summary(
+            meta,
+            vec![
+                ("Trades", totals.trades.into()),
+                ("Turnover", totals.turnover.into()),
+                ("Brokerage", totals.brokerage.into()),
+                ("STT", totals.stt.into()),
+                ("Exchange charges", totals.exchange_charges.into()),
+                ("SEBI fees", totals.sebi_fees.into()),
+                ("Stamp duty", totals.stamp_duty.into()),
+                ("GST", totals.gst.into()),
+                ("Unallocated taxes", totals.unallocated_taxes.into()),
+                ("Total charges", totals.total_charges.into()),
+                ("Trades without breakdown", report.trades_without_breakdown.into()),
+            ],
+        ),
+        Sheet {
+            name: "Clients",
+            columns: &[
+                "Client",
+                "Trades",
+                "Turnover",
+                "Brokerage",
+                "STT",
+                "Exchange charges",
+                "SEBI fees",
+                "Stamp duty",
+                "GST",
+                "Unallocated taxes",
+                "Total charges",
+            ],
+            rows: report
+                .clients
+                .into_iter()
+                .map(|client| {
+                    let mut row = vec![Cell::Text(client.client)];
+                    row.extend(charge_cells(client.charges));
+                    row
+                })
+                .collect(),
+        },
+        Sheet {
+            name: "Charges",
+            columns: &[
+                "Client",
+                "Segment",
+                "Trades",
+                "Turnover",
+                "Brokerage",
+                "STT",
+                "Exchange charges",
+                "SEBI fees",
+                "Stamp duty",
+                "GST",
+                "Unallocated taxes",
+                "Total charges",
+                "Trades without breakdown",
+            ],
+            rows: report
+                .rows
+                .into_iter()
+                .map(|row| {
+                    let mut cells = vec![Cell::Text(row.client), Cell::Text(row.segment)];
+                    cells.extend(charge_cells(row.charges));
+                    cells.push(row.trades_without_breakdown.into());
+                    cells
+                })
+                .collect(),
+        },
+    ]
+}
+
+fn margin_report(meta: &ReportMeta, report: MarginReport) -> Vec<Sheet> {
+    vec![
+        summary(
+            meta,
+            vec![
+                ("Business days", report.business_days.into()),
+                ("Clients with statements", report.clients_with_statements.into()),
+                ("Highest utilization", optional(report.highest_utilization)),
+                ("Shortfall incidents", report.shortfall_incidents.into()),
+                ("Clients with a shortfall", report.clients_with_shortfall.into()),
+                ("Total shortfall", report.total_shortfall.into()),
+                ("Total penalty", report.total_penalty.into()),
+                ("Statements without peak margin", report.statements_without_peak.into()),
+            ],
+        ),
+        Sheet {
+            name: "Clients",
+            columns: &[
+                "Client",
+                "Name",
+                "Business days",
+                "Highest peak margin",
+                "Highest utilization",
+                "Average utilization",
+                "Shortfall incidents",
+                "Total shortfall",
+                "Largest shortfall",
+                "Penalty",
+            ],
+            rows: report
+                .clients
+                .into_iter()
+                .map(|client| {
+                    vec![
+                        Cell::Text(client.client),
+                        Cell::Text(client.client_name.unwrap_or_default()),
+                        client.business_days.into(),
+                        client.highest_peak_margin.into(),
+                        optional(client.highest_utilization),
+                        optional(client.average_utilization),
+                        client.shortfall_incidents.into(),
+                        client.total_shortfall.into(),
+                        client.largest_shortfall.into(),
+                        client.penalty.into(),
+                    ]
+                })
+                .collect(),
+        },
+        Sheet {
+            name: "Days",
+            columns: &["Date", "Client", "Peak margin", "Collateral", "Utilization", "Shortfall", "Penalty"],
+            rows: report
+                .days
+                .into_iter()
+                .map(|day| {
+                    vec![
+                        Cell::Text(day.date.to_string()),
+                        Cell::Text(day.client),
+                        day.peak_margin.into(),
+                        day.collateral_available.into(),
+                        optional(day.utilization),
+                        day.shortfall.into(),
+                        day.penalty.into(),
+                    ]
+                })
+                .collect(),
+        },
+        Sheet {
+            name: "Violations",
+            columns: &[
+                "Date",
+                "Client",
+                "Exchange",
+                "Peak margin",
+                "Collateral",
+                "Shortfall",
+                "Shortfall share",
+                "Consecutive days",
+                "Days in month",
+                "Penalty rate",
+                "Penalty",
+            ],
+            rows: report
+                .violations
+                .into_iter()
+                .map(|violation| {
+                    vec![
+                        Cell::Text(violation.date.to_string()),
+                        Cell::Text(violation.client),
+                        Cell::Text(violation.exchange),
+                        violation.peak_margin.into(),
+                        violation.collateral_available.into(),
+                        violation.shortfall.into(),
+                        violation.shortfall_share.into(),
+                        violation.consecutive_days.into(),
+                        violation.days_in_month.into(),
+                        violation.penalty_rate.into(),
+                        violation.penalty.into(),
+                    ]
+                })
+                .collect(),
+        },
+    ]
+}
+
+/// `SEVERITY=HOURS` pairs, most severe first
+fn sla_text(hours: &HashMap<String, f64>) -> String {
+    let pairs: Vec<String> = SEVERITIES
+        .iter()
+        .filter_map(|severity| hours.get(*severity).map(|hours| format!("{}={}", severity, hours)))
+        .collect();
+    pairs.join(", ")
+}
+
+fn distribution_cells(sla_hours: Option<f64>, distribution: AgeDistribution) -> Vec<Cell> {
+    vec![
+        optional(sla_hours),
+        distribution.measured.into(),
+        distribution.pending.into(),
+        optional(distribution.mean_hours),
+        optional(distribution.median_hours),
+        optional(distribution.p90_hours),
+        optional(distribution.max_hours),
+        distribution.breaches.into(),
+    ]
+}
+
+fn alert_ageing(meta: &ReportMeta, report: AlertAgeingReport) -> Vec<Sheet> {
+    vec![
+        summary(
+            meta,
+            vec![
+                ("Clock", Cell::Text(report.clock)),
+                ("Acknowledge SLA hours", Cell::Text(sla_text(&report.acknowledge_sla_hours))),
+                ("Resolution SLA hours", Cell::Text(sla_text(&report.resolution_sla_hours))),
+                ("Alerts", report.total_alerts.into()),
+                ("Acknowledged", report.acknowledged_alerts.into()),
+                ("Resolved", report.resolved_alerts.into()),
+                ("Acknowledge breaches", report.acknowledge_breaches.into()),
+                ("Resolution breaches", report.resolution_breaches.into()),
+                ("Acknowledged within SLA", optional(report.acknowledged_within_sla)),
+                ("Resolved within SLA", optional(report.resolved_within_sla)),
+            ],
+        ),
+        Sheet {
+            name: "Ageing",
+            columns: &[
+                "Alert type",
+                "Severity",
+                "Alerts",
+                "Acknowledge SLA hours",
+                "Acknowledged",
+                "Unacknowledged",
+                "Mean hours to acknowledge",
+                "Median hours to acknowledge",
+                "P90 hours to acknowledge",
+                "Max hours to acknowledge",
+                "Acknowledge breaches",
+                "Resolution SLA hours",
+                "Resolved",
+                "Unresolved",
+                "Mean hours to resolve",
+                "Median hours to resolve",
+                "P90 hours to resolve",
+                "Max hours to resolve",
+                "Resolution breaches",
+            ],
+            rows: report
+                .groups
+                .into_iter()
+                .map(|group| {
+                    let mut row = vec![Cell::Text(group.alert_type), Cell::Text(group.severity), group.alerts.into()];
+                    row.extend(distribution_cells(group.acknowledge_sla_hours, group.time_to_acknowledge));
+                    row.extend(distribution_cells(group.resolution_sla_hours, group.time_to_resolution));
+                    row
+                })
+                .collect(),
+        },
+        Sheet {
+            name: "Breaches",
+            columns: &[
+                "Alert",
+                "Alert type",
+                "Severity",
+                "Raised at",
+                "Hours to acknowledge",
+                "Acknowledge breached",
+                "Hours to resolve",
+                "Resolution breached",
+                "Resolved",
+                "Overdue hours",
+            ],
+            rows: report
+                .breaches
+                .into_iter()
+                .map(|breach| {
+                    let flag = |flag: bool| Cell::Text(flag.to_string());
+                    vec![
+                        Cell::Text(breach.alert_id.to_string()),
+                        Cell::Text(breach.alert_type),
+                        Cell::Text(breach.severity),
+                        Cell::Text(breach.raised_at.to_rfc3339()),
+                        breach.acknowledge_hours.into(),
+                        flag(breach.acknowledge_breached),
+                        breach.resolution_hours.into(),
+                        flag(breach.resolution_breached),
+                        flag(breach.resolved),
+                        breach.overdue_hours.into(),
+                    ]
+                })
+                .collect(),
+        },
+    ]
+}
+
+/// Every scalar of `value` under its dotted path
+fn flatten(prefix: String, value: &Value, fields: &mut Vec<(String, Value)>) {
+    let join = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+    match value {
+        Value::Object(map) => {
+            // Sorted, so a file does not depend on the order its maps were built in
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            for (key, value) in entries {
+                flatten(join(key), value, fields);
+            }
+        }
+        Value::Array(items) => {
+            for (index, value) in items.iter().enumerate() {
+                flatten(join(&index.to_string()), value, fields);
+            }
+        }
+        scalar => fields.push((prefix, scalar.clone())),
+    }
+}
+
+fn fields(data: &Value) -> Vec<Sheet> {
+    let mut fields = Vec::new();
+    flatten(String::new(), data, &mut fields);
+    let rows = fields
+        .into_iter()
+        .map(|(field, value)| {
+            let value = match value {
+                Value::Number(number) => number.as_f64().map_or_else(|| Cell::Text(number.to_string()), Cell::Number),
+                Value::String(text) => Cell::Text(text),
+                Value::Null => Cell::text(""),
+                other => Cell::Text(other.to_string()),
+            };
+            vec![Cell::Text(field), value]
+        })
+        .collect();
+    vec![Sheet {
+        name: "Report",
+        columns: &["Field", "Value"],
+        rows,
+    }]
+}
+
+/// The report's sheets, according to its type
+pub fn sheets(meta: &ReportMeta, data: &Value) -> anyhow::Result<Vec<Sheet>> {
+    Ok(match meta.report_type.as_str() {
+        "TRADING_SUMMARY" => trading_summary(
+            meta,
+            serde_json::from_value(data.clone()).context("trading summary data is malformed")?,
+        ),
+        "COMPLIANCE_REPORT" => compliance_report(
+            meta,
+            serde_json::from_value(data.clone()).context("compliance report data is malformed")?,
+        ),
+        "CLIENT_EXPOSURE" => client_exposure(
+            meta,
+            serde_json::from_value(data.clone()).context("client exposure data is malformed")?,
+        ),
+        "ORDER_TRADE_RATIO" => order_trade_ratio(
+            meta,
+            serde_json::from_value(data.clone()).context("order-to-trade ratio data is malformed")?,
+        ),
+        "BROKERAGE_SUMMARY" => brokerage_summary(
+            meta,
+            serde_json::from_value(data.clone()).context("brokerage summary data is malformed")?,
+        ),
+        "MARGIN_REPORT" => margin_report(
+            meta,
+            serde_json::from_value(data.clone()).context("margin report data is malformed")?,
+        ),
+        "ALERT_AGEING" => alert_ageing(
+            meta,
+            serde_json::from_value(data.clone()).context("alert ageing data is malformed")?,
+        ),
+        _ => fields(data),
+    })
+}
+
+pub fn csv(sheets: &[Sheet]) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new().flexible(true).from_writer(Vec::new());
+    for (index, sheet) in sheets.iter().enumerate() {
+        if index > 0 {
+            writer.write_record(std::iter::empty::<&str>())?;
+        }
+        writer.write_record([sheet.name])?;
+        writer.write_record(sheet.columns)?;
+        for row in &sheet.rows {
+            writer.write_record(row.iter().map(Cell::to_csv))?;
+        }
+    }
+    writer.into_inner().context("failed to write CSV")
+}
+
+pub fn xlsx(meta: &ReportMeta, sheets: &[Sheet]) -> anyhow::Result<Vec<u8>> {
+    let mut workbook = Workbook::new();
+    workbook.set_properties(
+        &DocProperties::new()
+            .set_title(format!("{} {} to {}", meta.report_type, meta.period_start, meta.period_end))
+            .set_comment(format!("Report {}", meta.report_id)),
+    );
+    let header = Format::new().set_bold();
+    for sheet in sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(sheet.name)?;
+        for (column, name) in sheet.columns.iter().enumerate() {
+            worksheet.write_string_with_format(0, column as u16, *name, &header)?;
+        }
+        for (index, row) in sheet.rows.iter().enumerate() {
+            let row_number = index as u32 + 1;
+            for (column, cell) in row.iter().enumerate() {
+                match cell {
+                    Cell::Text(text) => worksheet.write_string(row_number, column as u16, text)?,
+                    Cell::Number(number) => worksheet.write_number(row_number, column as u16, *number)?,
+                };
+            }
+        }
+        worksheet.set_freeze_panes(1, 0)?;
+        worksheet.autofit();
+    }
+    workbook.save_to_buffer().context("failed to write workbook")
+}
@@ -0,0 +1,836 @@
+//! Digital signatures of report files
+//!
+//! A tenant that sets a signing certificate, such as a Class 3 DSC, with PUT
+//! /reports/signing/certificate has every report file signed as it is
+//! generated, with a detached PKCS#7 signature over the file. PDFs carry the
+//! signature embedded, where PDF readers show and check it (see
+//! `pdf_signature`); for other formats it is kept only on the report. Either
+//! way regulatory_reports_v2 records it as digital_signature, in base64 DER,
+//! with when it was made and the certificate's fingerprint. A file that cannot
+//! be signed is not generated.
+//!
+//! The key is either:
+//!
+//! - `PKCS12`: a .p12/.pfx file with the key, its certificate and any
+//!   intermediates, and the file's password.
+//! - `HSM`: a key in an HSM behind a signing gateway. The bytes to sign are
+//!   POSTed to hsm_url as application/octet-stream, with the token as a
+//!   bearer token and the key's label as X-Key-Label, and the gateway answers
+//!   with the DER of a detached PKCS#7 signature. The tenant gives the key's
+//!   certificate, and every signature the gateway returns is checked against
+//!   it before it is used.
+//!
+//! The PKCS#12 file and password, or the gateway token, are sealed with
+//! AES-256-GCM under REPORT_SIGNING_CREDENTIALS_KEY (32 bytes of hex) and never
+//! returned; signing is off without the key. A certificate is checked by
+//! signing with it before it is saved.
+//!
+//! GET /reports/:id/signature checks the stored file of a report against its
+//! signature, and POST /reports/signatures/verify checks an uploaded file, such
+//! as one a regulator received, as an embedded PDF signature or against the
+//! signature of the tenant's report with the same hash. The signer's chain is
+//! checked against the CA certificates in REPORT_SIGNING_TRUST_FILE when it is
+//! set; certificates that have since expired are judged by whether they were
+//! valid when the report was signed.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::pkey::{PKey, Private};
+use openssl::stack::Stack;
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::verify::X509VerifyFlags;
+use openssl::x509::{X509NameRef, X509Ref, X509};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+use dharmaguard_common::tenant_query;
+
+use crate::pdf_signature::{self, SignatureInfo};
+use crate::render::{ReportFormat, ReportMeta};
+use crate::report_files::{FileColumns, ReportFiles};
+
+const NONCE_LEN: usize = 12;
+/// Largest file POST /reports/signatures/verify takes
+pub const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+const KEY_SOURCES: &[&str] = &["PKCS12", "HSM"];
+/// Signed to check a certificate before it is saved
+const PROBE: &[u8] = b"DharmaGuard report signing check";
+
+pub struct SigningSettings {
+    /// Seals signing credentials; signing is off without it
+    cipher: Option<Aes256Gcm>,
+    /// CA certificates signers are checked against; chains are not checked without it
+    trust: Option<X509Store>,
+    /// Requests to HSM signing gateways
+    client: reqwest::Client,
+}
+
+impl SigningSettings {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let cipher = match std::env::var("REPORT_SIGNING_CREDENTIALS_KEY") {
+            Ok(key) if !key.is_empty() => {
+                let key = hex::decode(key.trim()).context("REPORT_SIGNING_CREDENTIALS_KEY is not hex")?;
+                if key.len() != 32 {
+                    anyhow::bail!("REPORT_SIGNING_CREDENTIALS_KEY must be 32 bytes of hex");
+                }
+                Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+            }
+            _ => None,
+        };
+        let trust = match std::env::var("REPORT_SIGNING_TRUST_FILE") {
+            Ok(path) if !path.is_empty() => {
+                let pem = std::fs::read(&path).with_context(|| format!("failed to read {}", path))?;
+                let mut store = X509StoreBuilder::new()?;
+                for certificate in X509::stack_from_pem(&pem).with_context(|| format!("{} is not PEM", path))? {
+                    store.add_cert(certificate)?;
+                }
+                // Expiry is judged against when the report was signed, not now
+                store.set_flags(X509VerifyFlags::NO_CHECK_TIME)?;
+                Some(store.build())
+            }
+            _ => None,
+        };
+        Ok(Self {
+            cipher,
+            trust,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(
+                    std::env::var("REPORT_SIGNING_HSM_TIMEOUT_SECS")
+                        .ok()
+                        .and_then(|secs| secs.parse().ok())
+                        .unwrap_or(30),
+                ))
+                .build()?,
+        })
+    }
+
+    fn cipher(&self) -> Result<&Aes256Gcm, SigningError> {
+        self.cipher.as_ref().ok_or(SigningError::NotConfigured)
+    }
+
+    /// Credentials are bound to their tenant, so a sealed value copied to another row does not open
+    fn seal(&self, tenant_id: Uuid, credentials: &Credentials) -> Result<Vec<u8>, SigningError> {
+        let plaintext = serde_json::to_vec(credentials).context("failed to serialize credentials")?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()?
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: tenant_id.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("failed to seal credentials"))?;
+        Ok([nonce.as_slice(), ciphertext.as_slice()].concat())
+    }
+
+    fn unseal(&self, tenant_id: Uuid, sealed: &[u8]) -> Result<Credentials, SigningError> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow::anyhow!("sealed signing credentials of tenant {} are truncated", tenant_id).into());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()?
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: tenant_id.as_bytes() })
+            .map_err(|_| anyhow::anyhow!("failed to unseal signing credentials of tenant {}", tenant_id))?;
+        Ok(serde_json::from_slice(&plaintext).context("failed to read signing credentials")?)
+    }
+
+    /// Check a signature over `content`, `signed_at` being when the report says it was signed
+    fn check(&self, signature: &[u8], content: &[u8], signed_at: Option<DateTime<Utc>>) -> SignatureCheck {
+        let mut check = SignatureCheck {
+            signed: true,
+            signed_at,
+            ..SignatureCheck::default()
+        };
+        if let Err(e) = self.inspect(&mut check, signature, content) {
+            check.problems.push(format!("{:#}", e));
+        }
+        if !check.intact {
+            check.problems.push("the signature does not match the file".to_string());
+        }
+        if check.trusted == Some(false) {
+            check.problems.push("the signer's certificate does not chain to a trusted CA".to_string());
+        }
+        if check.certificate_valid_at_signing == Some(false) {
+            check.problems.push("the signer's certificate was not valid when the report was signed".to_string());
+        }
+        check
+    }
+
+    fn inspect(&self, check: &mut SignatureCheck, signature: &[u8], content: &[u8]) -> anyhow::Result<()> {
+        let pkcs7 = Pkcs7::from_der(signature).context("the signature is not PKCS#7")?;
+        let no_certificates: Stack<X509> = Stack::new()?;
+        let signer = pkcs7
+            .signers(&no_certificates, Pkcs7Flags::empty())
+            .context("the signature does not carry its signer's certificate")?
+            .into_iter()
+            .next()
+            .context("the signature has no signer")?;
+        let info = CertificateInfo::of(&signer)?;
+        check.certificate_valid_at_signing = check
+            .signed_at
+            .map(|signed_at| info.not_before <= signed_at && signed_at <= info.not_after);
+        check.signer = Some(info);
+
+        let any_chain = X509StoreBuilder::new()?.build();
+        check.intact = pkcs7
+            .verify(&no_certificates, &any_chain, Some(content), None, Pkcs7Flags::NOVERIFY)
+            .is_ok();
+        check.trusted = self.trust.as_ref().map(|trust| {
+            pkcs7
+                .verify(&no_certificates, trust, Some(content), None, Pkcs7Flags::empty())
+                .is_ok()
+        });
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Credentials {
+    /// Base64 of the PKCS#12 file
+    pkcs12: Option<String>,
+    password: Option<String>,
+    hsm_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SetCertificateRequest {
+    /// PKCS12 or HSM
+    pub key_source: String,
+    /// Base64 of the .p12/.pfx file
+    pub pkcs12: Option<String>,
+    pub password: Option<String>,
+    pub hsm_url: Option<String>,
+    pub hsm_key_label: Option<String>,
+    pub hsm_token: Option<String>,
+    /// For an HSM key, its certificate as PEM, with any intermediates after it
+    pub certificate_pem: Option<String>,
+    pub updated_by: Option<Uuid>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SigningCertificate {
+    pub tenant_id: Uuid,
+    pub key_source: String,
+    pub hsm_url: Option<String>,
+    pub hsm_key_label: Option<String>,
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    pub fingerprint: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const CERTIFICATE_COLUMNS: &str = "tenant_id, key_source, hsm_url, hsm_key_label, subject, issuer, serial_number, \
+     fingerprint, not_before, not_after, created_by, created_at, updated_at";
+
+/// The parts of a certificate shown with a signature
+#[derive(Serialize, Clone)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub serial_number: String,
+    /// SHA-256 of the DER certificate, in hex
+    pub fingerprint: String,
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    #[serde(skip)]
+    common_name: Option<String>,
+}
+
+impl CertificateInfo {
+    fn of(certificate: &X509Ref) -> anyhow::Result<Self> {
+        Ok(Self {
+            subject: name(certificate.subject_name()),
+            issuer: name(certificate.issuer_name()),
+            serial_number: certificate.serial_number().to_bn()?.to_hex_str()?.to_string(),
+            fingerprint: hex::encode(certificate.digest(MessageDigest::sha256())?),
+            not_before: time(certificate.not_before())?,
+            not_after: time(certificate.not_after())?,
+            common_name: certificate
+                .subject_name()
+                .entries_by_nid(Nid::COMMONNAME)
+                .next()
+                .and_then(|entry| entry.data().as_utf8().ok())
+                .map(|name| name.to_string()),
+        })
+    }
+}
+
+/// As `CN=..., O=..., C=...`
+fn name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().as_utf8().map(|value| value.to_string()).unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn time(time: &Asn1TimeRef) -> anyhow::Result<DateTime<Utc>> {
+    let diff = Asn1Time::from_unix(0)?.diff(time)?;
+    DateTime::from_timestamp(i64::from(diff.days) * 86_400 + i64::from(diff.secs), 0)
+        .context("certificate time is out of range")
+}
+
+/// How a report file was signed, as recorded on the report
+pub struct FileSignature {
+    /// DER of the detached PKCS#7 signature
+    pub der: Vec<u8>,
+    pub signed_at: DateTime<Utc>,
+    pub fingerprint: String,
+}
+
+impl FileSignature {
+    /// As regulatory_reports_v2 keeps it in digital_signature
+    pub fn encoded(&self) -> String {
+        STANDARD.encode(&self.der)
+    }
+}
+
+/// A report that should be signed cannot be, such as while the tenant's certificate is expired
+#[derive(Debug, thiserror::Error)]
+#[error("report cannot be signed: {0}")]
+pub struct CannotSign(pub String);
+
+enum SigningKey {
+    Pkcs12(PKey<Private>),
+    Hsm { url: String, key_label: String, token: String },
+}
+
+/// A tenant's signing key and certificate
+pub struct Signer {
+    key: SigningKey,
+    certificate: X509,
+    chain: Stack<X509>,
+    info: CertificateInfo,
+}
+
+impl Signer {
+    fn from_pkcs12(der: &[u8], password: &str) -> anyhow::Result<Self> {
+        let parsed = Pkcs12::from_der(der)
+            .context("pkcs12 is not a PKCS#12 file")?
+            .parse2(password)
+            .context("pkcs12 could not be opened with the password")?;
+        let (Some(key), Some(certificate)) = (parsed.pkey, parsed.cert) else {
+            anyhow::bail!("pkcs12 must hold a private key and its certificate");
+        };
+        if !certificate.public_key()?.public_eq(&key) {
+            anyhow::bail!("the private key in pkcs12 is not that of its certificate");
+        }
+        let mut chain = Stack::new()?;
+        for intermediate in parsed.ca.into_iter().flatten() {
+            chain.push(intermediate)?;
+        }
+        Ok(Self {
+            info: CertificateInfo::of(&certificate)?,
+            key: SigningKey::Pkcs12(key),
+            certificate,
+            chain,
+        })
+    }
+
+    fn from_hsm(url: String, key_label: String, token: String, pem: &str) -> anyhow::Result<Self> {
+        let mut certificates = X509::stack_from_pem(pem.as_bytes())
+            .context("certificate_pem is not PEM")?
+            .into_iter();
+        let certificate = certificates.next().context("certificate_pem holds no certificate")?;
+        let mut chain = Stack::new()?;
+        for intermediate in certificates {
+            chain.push(intermediate)?;
+        }
+        Ok(Self {
+            info: CertificateInfo::of(&certificate)?,
+            key: SigningKey::Hsm { url, key_label, token },
+            certificate,
+            chain,
+        })
+    }
+
+    fn chain_pem(&self) -> anyhow::Result<Option<String>> {
+        if self.chain.is_empty() {
+            return Ok(None);
+        }
+        let mut pem = Vec::new();
+        for certificate in &self.chain {
+            pem.extend(certificate.to_pem()?);
+        }
+        Ok(Some(String::from_utf8(pem)?))
+    }
+
+    /// A detached PKCS#7 signature of `data`, as DER
+    async fn sign(&self, settings: &SigningSettings, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        match &self.key {
+            SigningKey::Pkcs12(key) => {
+                let pkcs7 = Pkcs7::sign(
+                    &self.certificate,
+                    key,
+                    &self.chain,
+                    data,
+                    Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY,
+                )?;
+                Ok(pkcs7.to_der()?)
+            }
+            SigningKey::Hsm { url, key_label, token } => {
+                let response = settings
+                    .client
+                    .post(url)
+                    .bearer_auth(token)
+                    .header("X-Key-Label", key_label)
+                    .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+                    .body(data.to_vec())
+                    .send()
+                    .await
+                    .context("the HSM signing gateway could not be reached")?;
+                if !response.status().is_success() {
+                    anyhow::bail!("the HSM signing gateway answered {}", response.status());
+                }
+                let der = response.bytes().await.context("failed to read the HSM signature")?.to_vec();
+                // Only a signature by the configured certificate over these bytes is used
+                let check = settings.check(&der, data, None);
+                if !check.intact {
+                    anyhow::bail!("the HSM signature does not match what was signed");
+                }
+                if check.signer.map(|signer| signer.fingerprint) != Some(self.info.fingerprint.clone()) {
+                    anyhow::bail!("the HSM signature is not by the configured certificate");
+                }
+                Ok(der)
+            }
+        }
+    }
+
+    /// Sign a rendered report file, embedding the signature in PDFs
+    pub async fn sign_file(
+        &self,
+        settings: &SigningSettings,
+        meta: &ReportMeta,
+        format: ReportFormat,
+        contents: Vec<u8>,
+    ) -> anyhow::Result<(Vec<u8>, FileSignature)> {
+        let signed_at = Utc::now();
+        let (contents, der) = if format == ReportFormat::Pdf {
+            let reason = format!(
+                "{} report for {} to {}",
+                meta.report_type, meta.period_start, meta.period_end
+            );
+            let info = SignatureInfo {
+                signer_name: self.info.common_name.as_deref().unwrap_or(&self.info.subject),
+                reason: &reason,
+                signed_at,
+            };
+            let prepared = pdf_signature::prepare(&contents, &info)?;
+            let der = self.sign(settings, &prepared.signed_bytes()).await?;
+            (prepared.finish(&der)?, der)
+        } else {
+            let der = self.sign(settings, &contents).await?;
+            (contents, der)
+        };
+        Ok((
+            contents,
+            FileSignature {
+                der,
+                signed_at,
+                fingerprint: self.info.fingerprint.clone(),
+            },
+        ))
+    }
+}
+
+/// The tenant's signer, or `None` when its reports are not signed
+pub async fn signer(db: &PgPool, settings: &SigningSettings, tenant_id: Uuid) -> anyhow::Result<Option<Signer>> {
+    let row = sqlx::query_as::<_, (String, Vec<u8>, Option<String>, Option<String>, String, Option<String>)>(
+        r#"
+        SELECT key_source, credentials_sealed, hsm_url, hsm_key_label, certificate_pem, chain_pem
+        FROM report_signing_certificates
+        WHERE tenant_id = $1
+        "#,
+    )
+    .bind(tenant_id)
+    .fetch_optional(db)
+    .await?;
+    let Some((key_source, sealed, hsm_url, hsm_key_label, certificate_pem, chain_pem)) = row else {
+        return Ok(None);
+    };
+    let credentials = match settings.unseal(tenant_id, &sealed) {
+        Ok(credentials) => credentials,
+        Err(SigningError::NotConfigured) => {
+            return Err(CannotSign("REPORT_SIGNING_CREDENTIALS_KEY is not configured".to_string()).into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let signer = match key_source.as_str() {
+        "PKCS12" => {
+            let der = STANDARD
+                .decode(credentials.pkcs12.unwrap_or_default())
+                .context("sealed PKCS#12 file is not base64")?;
+            Signer::from_pkcs12(&der, credentials.password.as_deref().unwrap_or_default())?
+        }
+        _ => {
+            let pem = [certificate_pem, chain_pem.unwrap_or_default()].concat();
+            Signer::from_hsm(
+                hsm_url.unwrap_or_default(),
+                hsm_key_label.unwrap_or_default(),
+                credentials.hsm_token.unwrap_or_default(),
+                &pem,
+            )?
+        }
+    };
+    let now = Utc::now();
+    if now > signer.info.not_after {
+        let expired = signer.info.not_after.format("%Y-%m-%d");
+        return Err(CannotSign(format!("the signing certificate expired on {}", expired)).into());
+    }
+    if now < signer.info.not_before {
+        let valid_from = signer.info.not_before.format("%Y-%m-%d");
+        return Err(CannotSign(format!("the signing certificate is not valid until {}", valid_from)).into());
+    }
+    Ok(Some(signer))
+}
+
+/// The outcome of checking a report file's signature
+#[derive(Serialize, Default)]
+pub struct SignatureCheck {
+    /// The report the file is, when known
+    pub report_id: Option<Uuid>,
+    /// Signed, intact and with nothing else wrong
+    pub valid: bool,
+    pub signed: bool,
+    /// The signature matches the file
+    pub intact: bool,
+    /// For PDFs, whether the signature covers the whole file, so nothing was added after signing
+    pub covers_whole_file: Option<bool>,
+    /// Whether the signer's certificate chains to REPORT_SIGNING_TRUST_FILE; not checked without it
+    pub trusted: Option<bool>,
+    pub signer: Option<CertificateInfo>,
+    pub signed_at: Option<DateTime<Utc>>,
+    pub certificate_valid_at_signing: Option<bool>,
+    pub problems: Vec<String>,
+}
+
+impl SignatureCheck {
+    fn unsigned(report_id: Option<Uuid>, problem: &str) -> Self {
+        Self {
+            report_id,
+            problems: vec![problem.to_string()],
+            ..Self::default()
+        }
+    }
+
+    fn conclude(mut self) -> Self {
+        if self.covers_whole_file == Some(false) {
+            self.problems.push("the file was changed after it was signed".to_string());
+        }
+        self.valid = self.signed && self.intact && self.problems.is_empty();
+        self
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SigningError {
+    #[error("REPORT_SIGNING_CREDENTIALS_KEY is not configured")]
+    NotConfigured,
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("invalid signing certificate")]
+    Invalid(Vec<String>),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for SigningError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+pub async fn get_certificate(db: &PgPool, tenant: &TenantContext) -> Result<SigningCertificate, SigningError> {
+    sqlx::query_as::<_, SigningCertificate>(&format!(
+        "SELECT {} FROM report_signing_certificates WHERE tenant_id = $1",
+        CERTIFICATE_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .fetch_optional(db)
+    .await?
+    .ok_or(SigningError::NotFound("signing certificate"))
+}
+
+/// Set the tenant's signing certificate, replacing any it had, once a test signature by it checks out
+pub async fn set_certificate(
+    db: &PgPool,
+    settings: &SigningSettings,
+    tenant: &TenantContext,
+    request: SetCertificateRequest,
+) -> Result<SigningCertificate, SigningError> {
+    settings.cipher()?;
+    let key_source = request.key_source.trim().to_uppercase();
+    let blank = |value: &Option<String>| value.as_deref().unwrap_or_default().trim().is_empty();
+    let mut errors = Vec::new();
+    let (signer, credentials) = match key_source.as_str() {
+        "PKCS12" => {
+            if blank(&request.pkcs12) {
+                errors.push("pkcs12 is required".to_string());
+            }
+            if request.hsm_url.is_some() || request.hsm_token.is_some() || request.certificate_pem.is_some() {
+                errors.push("a PKCS12 key takes no hsm_url, hsm_token or certificate_pem".to_string());
+            }
+            let pkcs12 = request.pkcs12.as_deref().unwrap_or_default().trim();
+            let signer = STANDARD
+                .decode(pkcs12)
+                .map_err(|_| anyhow::anyhow!("pkcs12 must be base64"))
+                .and_then(|der| Signer::from_pkcs12(&der, request.password.as_deref().unwrap_or_default()));
+            let credentials = Credentials {
+                pkcs12: Some(pkcs12.to_string()),
+                password: request.password.clone(),
+                hsm_token: None,
+            };
+            (signer, credentials)
+        }
+        "HSM" => {
+            for (field, value) in [
+                ("hsm_url", &request.hsm_url),
+                ("hsm_key_label", &request.hsm_key_label),
+                ("hsm_token", &request.hsm_token),
+                ("certificate_pem", &request.certificate_pem),
+            ] {
+                if blank(value) {
+                    errors.push(format!("{} is required for an HSM key", field));
+                }
+            }
+            let url = request.hsm_url.as_deref().unwrap_or_default().trim();
+            if !blank(&request.hsm_url) && !url.starts_with("https://") {
+                errors.push("hsm_url must be an https:// URL".to_string());
+            }
+            if request.pkcs12.is_some() || request.password.is_some() {
+                errors.push("an HSM key takes no pkcs12 or password".to_string());
+            }
+            let signer = Signer::from_hsm(
+                url.to_string(),
+                request.hsm_key_label.as_deref().unwrap_or_default().trim().to_string(),
+                request.hsm_token.clone().unwrap_or_default(),
+                request.certificate_pem.as_deref().unwrap_or_default(),
+            );
+            let credentials = Credentials {
+                pkcs12: None,
+                password: None,
+                hsm_token: request.hsm_token.clone(),
+            };
+            (signer, credentials)
+        }
+        _ => {
+            errors.push(format!("key_source must be one of {}", KEY_SOURCES.join(", ")));
+            return Err(SigningError::Invalid(errors));
+        }
+    };
+    let signer = match signer {
+        Ok(signer) => Some(signer),
+        Err(e) => {
+            errors.push(format!("{:#}", e));
+            None
+        }
+    };
+    if let Some(signer) = &signer {
+        if signer.info.not_after < Utc::now() {
+            errors.push(format!(
+                "the certificate expired on {}",
+                signer.info.not_after.format("%Y-%m-%d")
+            ));
+        }
+    }
+    let Some(signer) = signer.filter(|_| errors.is_empty()) else {
+        return Err(SigningError::Invalid(errors));
+    };
+    if let Err(e) = signer.sign(settings, PROBE).await {
+        return Err(SigningError::Invalid(vec![format!("a test signature failed: {:#}", e)]));
+    }
+
+    let sealed = settings.seal(tenant.tenant_id(), &credentials)?;
+    let (hsm_url, hsm_key_label) = match &signer.key {
+        SigningKey::Hsm { url, key_label, .. } => (Some(url.clone()), Some(key_label.clone())),
+        SigningKey::Pkcs12(_) => (None, None),
+    };
+    let certificate_pem = String::from_utf8(signer.certificate.to_pem().context("failed to write certificate")?)
+        .context("certificate PEM is not text")?;
+    let certificate = sqlx::query_as::<_, SigningCertificate>(&format!(
+        r#"
+        INSERT INTO report_signing_certificates (
+            tenant_id, key_source, credentials_sealed, hsm_url, hsm_key_label, certificate_pem, chain_pem,
+            subject, issuer, serial_number, fingerprint, not_before, not_after, created_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        ON CONFLICT (tenant_id) DO UPDATE SET
+            key_source = EXCLUDED.key_source, credentials_sealed = EXCLUDED.credentials_sealed,
+            hsm_url = EXCLUDED.hsm_url, hsm_key_label = EXCLUDED.hsm_key_label,
+            certificate_pem = EXCLUDED.certificate_pem, chain_pem = EXCLUDED.chain_pem,
+            subject = EXCLUDED.subject, issuer = EXCLUDED.issuer, serial_number = EXCLUDED.serial_number,
+            fingerprint = EXCLUDED.fingerprint, not_before = EXCLUDED.not_before, not_after = EXCLUDED.not_after,
+            created_by = EXCLUDED.created_by, updated_at = NOW()
+        RETURNING {}
+        "#,
+        CERTIFICATE_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(&key_source)
+    .bind(sealed)
+    .bind(hsm_url)
+    .bind(hsm_key_label)
+    .bind(certificate_pem)
+    .bind(signer.chain_pem()?)
+    .bind(&signer.info.subject)
+    .bind(&signer.info.issuer)
+    .bind(&signer.info.serial_number)
+    .bind(&signer.info.fingerprint)
+    .bind(signer.info.not_before)
+    .bind(signer.info.not_after)
+    .bind(request.updated_by)
+    .fetch_one(db)
+    .await?;
+    Ok(certificate)
+}
+
+/// Stop signing the tenant's reports; reports already signed keep their signatures
+pub async fn delete_certificate(db: &PgPool, tenant: &TenantContext) -> Result<(), SigningError> {
+    let deleted = sqlx::query("DELETE FROM report_signing_certificates WHERE tenant_id = $1")
+        .bind(tenant.tenant_id())
+        .execute(db)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(SigningError::NotFound("signing certificate"));
+    }
+    Ok(())
+}
+
+/// Check `contents` as a PDF with an embedded signature, or else against the recorded signature
+fn check_file(
+    settings: &SigningSettings,
+    report_id: Option<Uuid>,
+    contents: &[u8],
+    recorded: Option<(String, Option<DateTime<Utc>>, Option<String>)>,
+) -> anyhow::Result<SignatureCheck> {
+    let embedded = if contents.starts_with(b"%PDF-") {
+        pdf_signature::extract(contents)?
+    } else {
+        None
+    };
+    let (fingerprint, check) = match (embedded, recorded) {
+        (Some(embedded), recorded) => {
+            let signed_at = recorded.as_ref().and_then(|(_, signed_at, _)| *signed_at);
+            let mut check = settings.check(&embedded.signature, &embedded.signed_bytes, signed_at);
+            check.covers_whole_file = Some(embedded.covers_whole_file);
+            if let Some((signature, _, _)) = &recorded {
+                if STANDARD.decode(signature).ok().as_deref() != Some(embedded.signature.as_slice()) {
+                    check.problems.push("the embedded signature is not the one recorded on the report".to_string());
+                }
+            }
+            (recorded.and_then(|(_, _, fingerprint)| fingerprint), check)
+        }
+        (None, Some((signature, signed_at, fingerprint))) => {
+            let check = match STANDARD.decode(&signature) {
+                Ok(der) => settings.check(&der, contents, signed_at),
+                Err(_) => SignatureCheck {
+                    signed: true,
+                    problems: vec!["the recorded signature is not base64".to_string()],
+                    ..SignatureCheck::default()
+                },
+            };
+            (fingerprint, check)
+        }
+        (None, None) => return Ok(SignatureCheck::unsigned(report_id, "the file is not signed")),
+    };
+    let mut check = SignatureCheck { report_id, ..check };
+    if let (Some(fingerprint), Some(signer)) = (&fingerprint, &check.signer) {
+        if &signer.fingerprint != fingerprint {
+            check.problems.push("the signer is not the certificate the report was signed with".to_string());
+        }
+    }
+    Ok(check.conclude())
+}
+
+/// Check the stored file of a report against its signature
+pub async fn verify_report(
+    db: &PgPool,
+    files: &ReportFiles,
+    tenant: &TenantContext,
+    report_id: Uuid,
+) -> Result<SignatureCheck, SigningError> {
+    let report = tenant_query!(
+        tenant,
+        r#"
+        SELECT report_data, file_path, file_hash, file_size, file_deleted_at, file_archived_at, digital_signature,
+               signed_at, signing_certificate_fingerprint
+        FROM regulatory_reports_v2
+        WHERE tenant_id = $1 AND report_id = $2
+        "#,
+        report_id
+    )
+    .fetch_optional(db)
+    .await?
+    .ok_or(SigningError::NotFound("report"))?;
+    let Some(signature) = report.digital_signature else {
+        return Ok(SignatureCheck::unsigned(Some(report_id), "the report was not signed"));
+    };
+    let columns = FileColumns {
+        file_path: report.file_path,
+        file_hash: report.file_hash,
+        file_size: report.file_size,
+        file_deleted_at: report.file_deleted_at,
+        file_archived_at: report.file_archived_at,
+    };
+    let opened = files.open(report_id, &columns, &report.report_data).await?;
+    Ok(check_file(
+        files.signing(),
+        Some(report_id),
+        &opened.contents,
+        Some((signature, report.signed_at, report.signing_certificate_fingerprint)),
+    )?)
+}
+
+/// Check an uploaded report file, matched to the tenant's report with the same hash
+pub async fn verify_upload(
+    db: &PgPool,
+    settings: &SigningSettings,
+    tenant: &TenantContext,
+    contents: &[u8],
+) -> Result<SignatureCheck, SigningError> {
+    let hash = hex::encode(Sha256::digest(contents));
+    let report = sqlx::query_as::<_, (Uuid, Option<String>, Option<DateTime<Utc>>, Option<String>)>(
+        r#"
+        SELECT report_id, digital_signature, signed_at, signing_certificate_fingerprint
+        FROM regulatory_reports_v2
+        WHERE tenant_id = $1 AND file_hash = $2
+        ORDER BY generated_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(tenant.tenant_id())
+    .bind(&hash)
+    .fetch_optional(db)
+    .await?;
+    let (report_id, recorded) = match report {
+        Some((report_id, Some(signature), signed_at, fingerprint)) => {
+            (Some(report_id), Some((signature, signed_at, fingerprint)))
+        }
+        Some((report_id, None, _, _)) => (Some(report_id), None),
+        None => (None, None),
+    };
+    let mut check = check_file(settings, report_id, contents, recorded)?;
+    if report_id.is_none() {
+        check.problems.push("the file is not a report of the tenant, or was changed".to_string());
+        check.valid = false;
+    }
+    Ok(check)
+}
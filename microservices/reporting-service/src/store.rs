@@ -0,0 +1,180 @@
+//! Where rendered report files are kept
+//!
+//! REPORT_STORE is either `s3://bucket/prefix` or a directory. S3-compatible
+//! stores such as MinIO are reached through REPORT_STORE_S3_ENDPOINT, which
+//! also switches to path-style bucket addressing; credentials and region come
+//! from the usual AWS environment. Keys are `<tenant_id>/<report_id>.<ext>`.
+
+use async_trait::async_trait;
+use aws_sdk_s3::{primitives::ByteStream, Client as S3Client};
+use axum::body::Body;
+use std::io::SeekFrom;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+#[async_trait]
+pub trait ReportStore: Send + Sync {
+    fn uri(&self) -> String;
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> anyhow::Result<()>;
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>>;
+
+    /// Bytes of the stored file
+    async fn size(&self, key: &str) -> anyhow::Result<u64>;
+
+    /// The bytes of `range`, streamed rather than read into memory
+    async fn stream(&self, key: &str, range: RangeInclusive<u64>) -> anyhow::Result<Body>;
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+/// Build the store from REPORT_STORE
+pub async fn from_uri(uri: &str) -> anyhow::Result<Box<dyn ReportStore>> {
+    if let Some(location) = uri.strip_prefix("s3://") {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            anyhow::bail!("REPORT_STORE '{}' has no bucket", uri);
+        }
+        let config = aws_config::load_from_env().await;
+        let mut s3_config = aws_sdk_s3::config::Builder::from(&config);
+        if let Some(endpoint) = std::env::var("REPORT_STORE_S3_ENDPOINT").ok().filter(|e| !e.is_empty()) {
+            s3_config = s3_config.endpoint_url(endpoint).force_path_style(true);
+        }
+        return Ok(Box::new(S3Store {
+            client: S3Client::from_conf(s3_config.build()),
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        }));
+    }
+
+    let root = PathBuf::from(uri.strip_prefix("file://").unwrap_or(uri));
+    tokio::fs::create_dir_all(&root).await?;
+    Ok(Box::new(DirectoryStore { root }))
+}
+
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+#[async_trait]
+impl ReportStore for DirectoryStore {
+    fn uri(&self) -> String {
+        format!("file://{}", self.root.display())
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, _content_type: &str) -> anyhow::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        // Written aside and renamed so a reader never sees a partial file
+        let partial = path.with_extension("part");
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        Ok(tokio::fs::read(self.root.join(key)).await?)
+    }
+
+    async fn size(&self, key: &str) -> anyhow::Result<u64> {
+        Ok(tokio::fs::metadata(self.root.join(key)).await?.len())
+    }
+
+    async fn stream(&self, key: &str, range: RangeInclusive<u64>) -> anyhow::Result<Body> {
+        let mut file = tokio::fs::File::open(self.root.join(key)).await?;
+        file.seek(SeekFrom::Start(*range.start())).await?;
+        let len = range.end() + 1 - range.start();
+        Ok(Body::from_stream(ReaderStream::new(file.take(len))))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        match tokio::fs::remove_file(self.root.join(key)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct S3Store {
+    client: S3Client,
+    bucket: String,
+    prefix: String,
+}
+
+impl S3Store {
+    fn object_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+}
+
+#[async_trait]
+impl ReportStore for S3Store {
+    fn uri(&self) -> String {
+        format!("s3://{}/{}", self.bucket, self.prefix)
+    }
+
+    async fn put(&self, key: &str, bytes: Vec<u8>, content_type: &str) -> anyhow::Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .content_type(content_type)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        Ok(object.body.collect().await?.into_bytes().to_vec())
+    }
+
+    async fn size(&self, key: &str) -> anyhow::Result<u64> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        Ok(head.content_length().unwrap_or_default().max(0) as u64)
+    }
+
+    async fn stream(&self, key: &str, range: RangeInclusive<u64>) -> anyhow::Result<Body> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .range(format!("bytes={}-{}", range.start(), range.end()))
+            .send()
+            .await?;
+        Ok(Body::from_stream(ReaderStream::new(object.body.into_async_read())))
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(self.object_key(key))
+            .send()
+            .await?;
+        Ok(())
+    }
+}
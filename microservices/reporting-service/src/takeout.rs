@@ -0,0 +1,569 @@
+//! Tenant data takeout
+//!
+//! A takeout is a ZIP with one file per section plus `manifest.json`, built in
+//! the background and emailed through the report delivery channels. Takeouts
+//! carry client PII, so they are always delivered encrypted.
+//!
+//! Layout (version 1):
+//!
+//! - `manifest.json`: `layout_version`, `export_id`, `tenant_id`, `format`,
+//!   `period_start`, `period_end`, `generated_at` and `files`, each with
+//!   `file`, `section`, `description`, `columns`, `rows` and `sha256`.
+//! - `<section>.json`: an array of objects keyed by the section's columns, or
+//! - `<section>.csv`: a header row of the columns, then one row per record.
+//!   Empty cells are nulls; nested values (arrays, objects) are JSON-encoded.
+//!
+//! Timestamps are ISO 8601 with an offset and dates `YYYY-MM-DD`. The period, when
+//! given, limits violations, cases, reports and audit summaries to records
+//! created (or, for reports, covering days) within it; users are always
+//! exported in full.
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::delivery::{Deliverable, DeliverReportRequest, DeliveryMethod, Package, ReportDelivery, ReportRecipient};
+
+pub const LAYOUT_VERSION: u32 = 1;
+
+struct Section {
+    name: &'static str,
+    description: &'static str,
+    columns: &'static [&'static str],
+    /// $1 is the tenant and, when `by_period`, $2 and $3 the optional period; one JSON object per row
+    query: &'static str,
+    by_period: bool,
+}
+
+const SECTIONS: &[Section] = &[
+    Section {
+        name: "users",
+        description: "User accounts of the tenant; credentials and MFA secrets are never exported",
+        columns: &[
+            "user_id", "username", "email", "role", "is_active", "is_verified", "mfa_enabled",
+            "last_login_at", "created_at", "updated_at",
+        ],
+        query: r#"
+            SELECT to_jsonb(t) FROM (
+                SELECT user_id, username, email, role::text AS role, is_active, is_verified, mfa_enabled,
+                       last_login_at, created_at, updated_at
+                FROM users
+                WHERE tenant_id = $1
+                ORDER BY created_at, user_id
+            ) t
+        "#,
+        by_period: false,
+    },
+    Section {
+        name: "violations",
+        description: "Compliance violations with their resolution and SLA deadline",
+        columns: &[
+            "violation_id", "alert_id", "violation_type", "severity", "description", "regulatory_reference",
+            "penalty_amount", "status", "reported_to_regulator", "reported_at", "resolution_notes",
+            "resolved_at", "resolved_by", "sla_due_at", "created_at", "updated_at",
+        ],
+        query: r#"
+            SELECT to_jsonb(t) FROM (
+                SELECT violation_id, alert_id, violation_type, severity::text AS severity, description,
+                       regulatory_reference, penalty_amount, status, reported_to_regulator, reported_at,
+                       resolution_notes, resolved_at, resolved_by, sla_due_at, created_at, updated_at
+                FROM compliance_violations
+                WHERE tenant_id = $1
+                  AND ($2::date IS NULL OR created_at >= $2::date)
+                  AND ($3::date IS NULL OR created_at < $3::date + 1)
+                ORDER BY created_at, violation_id
+            ) t
+        "#,
+        by_period: true,
+    },
+    Section {
+        name: "cases",
+        description: "Investigations opened on surveillance alerts, with findings and evidence metadata",
+        columns: &[
+            "investigation_id", "alert_id", "alert_type", "investigator_id", "status", "priority", "findings",
+            "recommendations", "supporting_documents", "evidence", "started_at", "completed_at", "created_at",
+        ],
+        query: r#"
+            SELECT to_jsonb(t) FROM (
+                SELECT i.investigation_id, i.alert_id, a.alert_type, i.investigator_id, i.status, i.priority,
+                       i.findings, i.recommendations, i.supporting_documents,
+                       COALESCE((
+                           SELECT jsonb_agg(jsonb_build_object(
+                               'attachment_id', e.attachment_id, 'file_name', e.file_name,
+                               'content_type', e.content_type, 'byte_size', e.byte_size,
+                               'sha256', e.sha256, 'uploaded_by', e.uploaded_by, 'created_at', e.created_at
+                           ) ORDER BY e.created_at)
+                           FROM evidence_attachments e
+                           WHERE e.investigation_id = i.investigation_id
+                       ), '[]'::jsonb) AS evidence,
+                       i.started_at, i.completed_at, i.created_at
+                FROM alert_investigations i
+                JOIN surveillance_alerts a ON a.alert_id = i.alert_id
+                WHERE a.tenant_id = $1
+                  AND ($2::date IS NULL OR i.created_at >= $2::date)
+                  AND ($3::date IS NULL OR i.created_at < $3::date + 1)
+                ORDER BY i.created_at, i.investigation_id
+            ) t
+        "#,
+        by_period: true,
+    },
+    Section {
+        name: "reports",
+        description: "Regulatory reports with their submission status and generated data; embargoed reports are left out",
+        columns: &[
+            "report_id", "template_id", "template_name", "report_type", "report_period_start",
+            "report_period_end", "status", "generated_at", "approved_at", "submitted_at",
+            "submission_reference", "acknowledgment_reference", "file_hash", "report_data",
+        ],
+        query: r#"
+            SELECT to_jsonb(t) FROM (
+                SELECT r.report_id, r.template_id, rt.template_name, rt.report_type, r.report_period_start,
+                       r.report_period_end, r.status, r.generated_at, r.approved_at, r.submitted_at,
+                       r.submission_reference, r.acknowledgment_reference, r.file_hash, r.report_data
+                FROM regulatory_reports_v2 r
+                LEFT JOIN report_templates rt ON rt.template_id = r.template_id
+                WHERE r.tenant_id = $1
+                  AND report_embargoed_until(r.report_id) IS NULL
+                  AND ($2::date IS NULL OR r.report_period_end >= $2::date)
+                  AND ($3::date IS NULL OR r.report_period_start <= $3::date)
+                ORDER BY r.report_period_start, r.report_id
+            ) t
+        "#,
+        by_period: true,
+    },
+    Section {
+        name: "audit_summary",
+        description: "Audit trail activity per day, action and resource type",
+        columns: &["day", "action", "resource_type", "event_count", "distinct_users", "first_at", "last_at"],
+        query: r#"
+            SELECT to_jsonb(t) FROM (
+                SELECT (timestamp AT TIME ZONE 'UTC')::date AS day, action, resource_type,
+                       COUNT(*) AS event_count, COUNT(DISTINCT user_id) AS distinct_users,
+                       MIN(timestamp) AS first_at, MAX(timestamp) AS last_at
+                FROM audit_logs
+                WHERE tenant_id = $1
+                  AND ($2::date IS NULL OR timestamp >= $2::date)
+                  AND ($3::date IS NULL OR timestamp < $3::date + 1)
+                GROUP BY 1, 2, 3
+                ORDER BY 1, 2, 3
+            ) t
+        "#,
+        by_period: true,
+    },
+];
+
+#[derive(Debug, Clone)]
+pub struct TakeoutSettings {
+    pub poll_interval: Duration,
+    pub retention: chrono::Duration,
+    pub max_attempts: i32,
+    /// A RUNNING export not finished within this is claimed again
+    pub lease: chrono::Duration,
+}
+
+impl TakeoutSettings {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            poll_interval: Duration::from_secs(number("TAKEOUT_POLL_SECS", 30).max(1) as u64),
+            retention: chrono::Duration::days(number("TAKEOUT_RETENTION_DAYS", 7).max(1)),
+            max_attempts: number("TAKEOUT_MAX_ATTEMPTS", 3).max(1) as i32,
+            lease: chrono::Duration::minutes(number("TAKEOUT_LEASE_MINUTES", 30).max(1)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateExportRequest {
+    pub tenant_id: Uuid,
+    pub requested_by: Option<Uuid>,
+    /// JSON or CSV
+    pub format: String,
+    /// Defaults to every section
+    pub sections: Option<Vec<String>>,
+    pub period_start: Option<NaiveDate>,
+    pub period_end: Option<NaiveDate>,
+    pub recipients: Vec<ReportRecipient>,
+    pub message: Option<String>,
+}
+
+/// Recipients waiting for the export to finish
+#[derive(Serialize, Deserialize)]
+struct PendingDelivery {
+    recipients: Vec<ReportRecipient>,
+    message: Option<String>,
+}
+
+impl CreateExportRequest {
+    pub fn delivery(&self) -> DeliverReportRequest {
+        delivery_request(self.tenant_id, self.requested_by, self.recipients.clone(), self.message.clone())
+    }
+
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = self.delivery().validate();
+        if !matches!(self.format.to_uppercase().as_str(), "JSON" | "CSV") {
+            errors.push(format!("unknown format {}; expected JSON or CSV", self.format));
+        }
+        for section in self.sections.iter().flatten() {
+            if !SECTIONS.iter().any(|known| known.name == section) {
+                errors.push(format!("unknown section {}", section));
+            }
+        }
+        if self.sections.as_ref().is_some_and(|sections| sections.is_empty()) {
+            errors.push("at least one section is required".to_string());
+        }
+        if let (Some(start), Some(end)) = (self.period_start, self.period_end) {
+            if start > end {
+                errors.push("period_start must not be after period_end".to_string());
+            }
+        }
+        errors
+    }
+}
+
+/// Takeouts hold client PII, so every delivery is encrypted
+pub fn delivery_request(
+    tenant_id: Uuid,
+    requested_by: Option<Uuid>,
+    recipients: Vec<ReportRecipient>,
+    message: Option<String>,
+) -> DeliverReportRequest {
+    DeliverReportRequest {
+        tenant_id,
+        requested_by,
+        recipients,
+        contains_client_pii: true,
+        encrypt: Some(true),
+        method: DeliveryMethod::Attachment,
+        message,
+    }
+}
+
+#[derive(Serialize, Debug, Clone, sqlx::FromRow)]
+pub struct TenantExport {
+    pub export_id: Uuid,
+    pub tenant_id: Uuid,
+    pub requested_by: Option<Uuid>,
+    pub format: String,
+    pub sections: Vec<String>,
+    pub period_start: Option<NaiveDate>,
+    pub period_end: Option<NaiveDate>,
+    pub status: String,
+    pub manifest: Option<serde_json::Value>,
+    pub archive_sha256: Option<String>,
+    pub archive_bytes: Option<i64>,
+    pub error: Option<String>,
+    pub attempts: i32,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Everything except the archive and pending recipients
+const EXPORT_COLUMNS: &str = "export_id, tenant_id, requested_by, format, sections, period_start, period_end, \
+     status, manifest, archive_sha256, archive_bytes, error, attempts, created_at, completed_at, expires_at";
+
+pub async fn create(db: &PgPool, request: &CreateExportRequest) -> anyhow::Result<TenantExport> {
+    let sections: Vec<String> = match &request.sections {
+        Some(sections) => SECTIONS
+            .iter()
+            .filter(|known| sections.iter().any(|section| section == known.name))
+            .map(|known| known.name.to_string())
+            .collect(),
+        None => SECTIONS.iter().map(|known| known.name.to_string()).collect(),
+    };
+    let delivery = serde_json::to_value(PendingDelivery {
+        recipients: request.recipients.clone(),
+        message: request.message.clone(),
+    })?;
+
+    let export = sqlx::query_as::<_, TenantExport>(&format!(
+        r#"
+        INSERT INTO tenant_exports (tenant_id, requested_by, format, sections, period_start, period_end, delivery)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING {}
+        "#,
+        EXPORT_COLUMNS
+    ))
+    .bind(request.tenant_id)
+    .bind(request.requested_by)
+    .bind(request.format.to_uppercase())
+    .bind(&sections)
+    .bind(request.period_start)
+    .bind(request.period_end)
+    .bind(delivery)
+    .fetch_one(db)
+    .await?;
+    info!(
+        "Queued {} takeout {} of tenant {} ({})",
+        export.format,
+        export.export_id,
+        export.tenant_id,
+        export.sections.join(", ")
+    );
+    Ok(export)
+}
+
+pub async fn get(db: &PgPool, tenant_id: Uuid, export_id: Uuid) -> Result<Option<TenantExport>, sqlx::Error> {
+    sqlx::query_as::<_, TenantExport>(&format!(
+        "SELECT {} FROM tenant_exports WHERE tenant_id = $1 AND export_id = $2",
+        EXPORT_COLUMNS
+    ))
+    .bind(tenant_id)
+    .bind(export_id)
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn list(db: &PgPool, tenant_id: Uuid) -> Result<Vec<TenantExport>, sqlx::Error> {
+    sqlx::query_as::<_, TenantExport>(&format!(
+        "SELECT {} FROM tenant_exports WHERE tenant_id = $1 ORDER BY created_at DESC LIMIT 100",
+        EXPORT_COLUMNS
+    ))
+    .bind(tenant_id)
+    .fetch_all(db)
+    .await
+}
+
+/// The stored archive as a delivery package; `None` unless the export is COMPLETED
+pub async fn package(db: &PgPool, export: &TenantExport) -> anyhow::Result<Option<Package>> {
+    let archive = sqlx::query_scalar::<_, Option<Vec<u8>>>(
+        "SELECT archive FROM tenant_exports WHERE export_id = $1 AND status = 'COMPLETED'",
+    )
+    .bind(export.export_id)
+    .fetch_optional(db)
+    .await?
+    .flatten();
+    let Some(archive) = archive else {
+        return Ok(None);
+    };
+
+    // Unpacked again so each recipient's copy is encrypted file by file
+    let mut reader = zip::ZipArchive::new(Cursor::new(archive)).context("stored takeout archive is corrupt")?;
+    let mut files = Vec::with_capacity(reader.len());
+    for index in 0..reader.len() {
+        let mut file = reader.by_index(index)?;
+        let mut contents = Vec::with_capacity(file.size() as usize);
+        std::io::copy(&mut file, &mut contents)?;
+        files.push((file.name().to_string(), contents));
+    }
+    Ok(Some(Package {
+        stem: format!("takeout-{}", export.export_id),
+        label: "data export",
+        subject: format!("DharmaGuard data export {}", export.export_id),
+        files,
+    }))
+}
+
+fn csv_cell(value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => String::new(),
+        Some(serde_json::Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn encode(section: &Section, rows: &[serde_json::Value], format: &str) -> anyhow::Result<(String, Vec<u8>)> {
+    if format == "CSV" {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(section.columns)?;
+        for row in rows {
+            writer.write_record(section.columns.iter().map(|column| csv_cell(row.get(*column))))?;
+        }
+        Ok((format!("{}.csv", section.name), writer.into_inner()?))
+    } else {
+        Ok((format!("{}.json", section.name), serde_json::to_vec_pretty(rows)?))
+    }
+}
+
+async fn build(db: &PgPool, export: &TenantExport) -> anyhow::Result<(Vec<u8>, serde_json::Value)> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut files = Vec::new();
+
+    for section in SECTIONS.iter().filter(|section| export.sections.iter().any(|name| name == section.name)) {
+        let mut query = sqlx::query_scalar::<_, serde_json::Value>(section.query).bind(export.tenant_id);
+        if section.by_period {
+            query = query.bind(export.period_start).bind(export.period_end);
+        }
+        let rows = query
+            .fetch_all(db)
+            .await
+            .with_context(|| format!("failed to export {}", section.name))?;
+        let (file_name, contents) = encode(section, &rows, &export.format)?;
+        writer.start_file(file_name.as_str(), options)?;
+        writer.write_all(&contents)?;
+        files.push(serde_json::json!({
+            "file": file_name,
+            "section": section.name,
+            "description": section.description,
+            "columns": section.columns,
+            "rows": rows.len(),
+            "sha256": hex::encode(Sha256::digest(&contents)),
+        }));
+    }
+
+    let manifest = serde_json::json!({
+        "layout_version": LAYOUT_VERSION,
+        "export_id": export.export_id,
+        "tenant_id": export.tenant_id,
+        "format": export.format,
+        "period_start": export.period_start,
+        "period_end": export.period_end,
+        "generated_at": Utc::now(),
+        "files": files,
+    });
+    writer.start_file("manifest.json", options)?;
+    writer.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    Ok((writer.finish()?.into_inner(), manifest))
+}
+
+pub fn spawn_worker(db: PgPool, delivery: Arc<ReportDelivery>, settings: TakeoutSettings) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(settings.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = run_pending(&db, &delivery, &settings).await {
+                error!("Takeout worker pass failed: {:#}", e);
+            }
+        }
+    });
+}
+
+async fn run_pending(db: &PgPool, delivery: &ReportDelivery, settings: &TakeoutSettings) -> anyhow::Result<()> {
+    let expired = sqlx::query(
+        "UPDATE tenant_exports SET status = 'EXPIRED', archive = NULL WHERE status = 'COMPLETED' AND expires_at <= NOW()",
+    )
+    .execute(db)
+    .await?
+    .rows_affected();
+    if expired > 0 {
+        info!("Dropped {} expired takeout archives", expired);
+    }
+
+    while let Some(export) = claim(db, settings).await? {
+        // A failed build waits for the next pass before it is retried
+        if !run_one(db, delivery, settings, export).await? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+async fn claim(db: &PgPool, settings: &TakeoutSettings) -> Result<Option<TenantExport>, sqlx::Error> {
+    sqlx::query_as::<_, TenantExport>(&format!(
+        r#"
+        UPDATE tenant_exports
+        SET status = 'RUNNING', claimed_at = NOW(), attempts = attempts + 1
+        WHERE export_id = (
+            SELECT export_id FROM tenant_exports
+            WHERE status = 'QUEUED' OR (status = 'RUNNING' AND claimed_at < $1)
+            ORDER BY created_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING {}
+        "#,
+        EXPORT_COLUMNS
+    ))
+    .bind(Utc::now() - settings.lease)
+    .fetch_optional(db)
+    .await
+}
+
+async fn run_one(
+    db: &PgPool,
+    delivery: &ReportDelivery,
+    settings: &TakeoutSettings,
+    export: TenantExport,
+) -> anyhow::Result<bool> {
+    let (archive, manifest) = match build(db, &export).await {
+        Ok(built) => built,
+        Err(e) => {
+            let retry = export.attempts < settings.max_attempts;
+            warn!(
+                "Takeout {} of tenant {} failed on attempt {}: {:#}",
+                export.export_id, export.tenant_id, export.attempts, e
+            );
+            sqlx::query(
+                r#"
+                UPDATE tenant_exports
+                SET status = $2, error = $3,
+                    delivery = CASE WHEN $2 = 'FAILED' THEN NULL ELSE delivery END,
+                    completed_at = CASE WHEN $2 = 'FAILED' THEN NOW() END
+                WHERE export_id = $1
+                "#,
+            )
+            .bind(export.export_id)
+            .bind(if retry { "QUEUED" } else { "FAILED" })
+            .bind(format!("{:#}", e))
+            .execute(db)
+            .await?;
+            return Ok(false);
+        }
+    };
+
+    let pending = sqlx::query_scalar::<_, Option<serde_json::Value>>(
+        r#"
+        UPDATE tenant_exports
+        SET status = 'COMPLETED', archive = $2, archive_sha256 = $3, archive_bytes = $4, manifest = $5,
+            error = NULL, completed_at = NOW(), expires_at = $6
+        WHERE export_id = $1
+        RETURNING delivery
+        "#,
+    )
+    .bind(export.export_id)
+    .bind(&archive)
+    .bind(hex::encode(Sha256::digest(&archive)))
+    .bind(archive.len() as i64)
+    .bind(&manifest)
+    .bind(Utc::now() + settings.retention)
+    .fetch_one(db)
+    .await?;
+    info!(
+        "Built takeout {} of tenant {} ({} bytes)",
+        export.export_id,
+        export.tenant_id,
+        archive.len()
+    );
+
+    let Some(pending) = pending else {
+        return Ok(true);
+    };
+    let outcome = async {
+        let pending: PendingDelivery = serde_json::from_value(pending)?;
+        let request = delivery_request(export.tenant_id, export.requested_by, pending.recipients, pending.message);
+        let package = package(db, &export)
+            .await?
+            .context("takeout archive disappeared before delivery")?;
+        delivery
+            .deliver_package(db, Deliverable::Export(export.export_id), &request, &package)
+            .await?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    // Phone numbers are only kept until the delivery has been attempted
+    sqlx::query("UPDATE tenant_exports SET delivery = NULL, error = $2 WHERE export_id = $1")
+        .bind(export.export_id)
+        .bind(outcome.as_ref().err().map(|e| format!("delivery failed: {:#}", e)))
+        .execute(db)
+        .await?;
+    if let Err(e) = outcome {
+        warn!("Delivery of takeout {} failed: {:#}", export.export_id, e);
+    }
+    Ok(true)
+}
@@ -0,0 +1,403 @@
+//! Portable report template bundles
+//!
+//! A bundle carries a template's layout and metric catalog selections in a
+//! signed, versioned envelope so it can be moved between tenants or between
+//! environments that share the bundle signing key.
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const BUNDLE_FORMAT: &str = "dharmaguard.report-template";
+/// Template schema version produced by this build
+pub const TEMPLATE_SCHEMA_VERSION: i32 = 1;
+/// Oldest template schema version this build can still import
+pub const MIN_SUPPORTED_SCHEMA_VERSION: i32 = 1;
+
+/// Metrics each report type can expose in a template
+pub fn metric_catalog(report_type: &str) -> Option<&'static [&'static str]> {
+    match report_type {
+        "TRADING_SUMMARY" => Some(&[
+            "total_trades",
+            "total_volume",
+            "total_value",
+            "unique_instruments",
+            "active_clients",
+            "average_trade_size",
+            "largest_trade",
+            "trading_hours_distribution",
+            "instrument_breakdown",
+        ]),
+        "COMPLIANCE_REPORT" => Some(&[
+            "alerts_generated",
+            "critical_alerts",
+            "resolved_alerts",
+            "pending_investigations",
+            "compliance_score",
+            "violations_detected",
+            "pattern_breakdown",
+            "risk_metrics",
+        ]),
+        "CLIENT_EXPOSURE" => Some(&[
+            "clients_with_positions",
+            "total_gross_exposure",
+            "total_net_exposure",
+            "total_margin_required",
+            "clients_over_limit",
+            "clients",
+        ]),
+        "ORDER_TRADE_RATIO" => Some(&[
+            "granularity",
+            "thresholds",
+            "total_orders",
+            "total_trades",
+            "overall_ratio",
+            "flagged_ratios",
+            "clients_flagged",
+            "clients",
+            "ratios",
+        ]),
+        "BROKERAGE_SUMMARY" => Some(&[
+            "trades",
+            "turnover",
+            "brokerage",
+            "stt",
+            "exchange_charges",
+            "sebi_fees",
+            "stamp_duty",
+            "gst",
+            "unallocated_taxes",
+            "total_charges",
+            "trades_without_breakdown",
+            "clients",
+            "rows",
+        ]),
+        "MARGIN_REPORT" => Some(&[
+            "business_days",
+            "clients_with_statements",
+            "highest_utilization",
+            "shortfall_incidents",
+            "clients_with_shortfall",
+            "total_shortfall",
+            "total_penalty",
+            "statements_without_peak",
+            "clients",
+            "days",
+            "violations",
+        ]),
+        "ALERT_AGEING" => Some(&[
+            "clock",
+            "acknowledge_sla_hours",
+            "resolution_sla_hours",
+            "total_alerts",
+            "acknowledged_alerts",
+            "resolved_alerts",
+            "acknowledge_breaches",
+            "resolution_breaches",
+            "acknowledged_within_sla",
+            "resolved_within_sla",
+            "groups",
+            "breaches",
+        ]),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TemplateDefinition {
+    pub template_name: String,
+    pub report_type: String,
+    pub regulator: String,
+    pub frequency: String,
+    pub template_structure: serde_json::Value,
+    pub validation_rules: serde_json::Value,
+    pub metric_selections: Vec<String>,
+    pub submission_deadline_days: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TemplateBundle {
+    pub bundle_format: String,
+    pub schema_version: i32,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub source_tenant_id: Option<Uuid>,
+    pub source_template_id: Uuid,
+    pub template: TemplateDefinition,
+    #[serde(default)]
+    pub signature: String,
+}
+
+#[derive(Deserialize)]
+pub struct ImportTemplateRequest {
+    pub tenant_id: Uuid,
+    pub bundle: TemplateBundle,
+    /// Optional rename, useful when the target tenant already has the name
+    pub template_name: Option<String>,
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Serialize)]
+pub struct TemplateImportResponse {
+    pub status: String,
+    pub template_id: Option<Uuid>,
+    pub errors: Vec<String>,
+}
+
+/// HMAC-SHA256 signer for template bundles
+pub struct BundleSigner {
+    key: Vec<u8>,
+}
+
+impl BundleSigner {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    fn mac(&self, bundle: &TemplateBundle) -> Result<HmacSha256, serde_json::Error> {
+        let unsigned = TemplateBundle {
+            signature: String::new(),
+            ..bundle.clone()
+        };
+        let mut mac = HmacSha256::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(&serde_json::to_vec(&unsigned)?);
+        Ok(mac)
+    }
+
+    pub fn sign(&self, bundle: &mut TemplateBundle) -> Result<(), serde_json::Error> {
+        bundle.signature = hex::encode(self.mac(bundle)?.finalize().into_bytes());
+        Ok(())
+    }
+
+    pub fn verify(&self, bundle: &TemplateBundle) -> bool {
+        let Ok(signature) = hex::decode(&bundle.signature) else {
+            return false;
+        };
+        match self.mac(bundle) {
+            Ok(mac) => mac.verify_slice(&signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Compatibility checks run before a bundle is imported
+pub fn validate_bundle(signer: &BundleSigner, bundle: &TemplateBundle) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    if bundle.bundle_format != BUNDLE_FORMAT {
+        errors.push(format!("unsupported bundle format '{}'", bundle.bundle_format));
+    }
+    if !signer.verify(bundle) {
+        errors.push("bundle signature is invalid".to_string());
+    }
+    if bundle.schema_version > TEMPLATE_SCHEMA_VERSION {
+        errors.push(format!(
+            "bundle schema version {} is newer than supported version {}",
+            bundle.schema_version, TEMPLATE_SCHEMA_VERSION
+        ));
+    } else if bundle.schema_version < MIN_SUPPORTED_SCHEMA_VERSION {
+        errors.push(format!(
+            "bundle schema version {} is older than minimum supported version {}",
+            bundle.schema_version, MIN_SUPPORTED_SCHEMA_VERSION
+        ));
+    }
+
+    let template = &bundle.template;
+    match metric_catalog(&template.report_type) {
+        Some(catalog) => {
+            for metric in &template.metric_selections {
+                if !catalog.contains(&metric.as_str()) {
+                    errors.push(format!(
+                        "metric '{}' is not available for report type {}",
+                        metric, template.report_type
+                    ));
+                }
+            }
+        }
+        None => errors.push(format!("unknown report type {}", template.report_type)),
+    }
+
+    if !template
+        .template_structure
+        .get("sections")
+        .map_or(false, |sections| sections.is_array())
+    {
+        errors.push("template_structure must contain a 'sections' array".to_string());
+    }
+    if !["DAILY", "WEEKLY", "MONTHLY", "QUARTERLY", "ANNUAL", "ON_DEMAND"]
+        .contains(&template.frequency.as_str())
+    {
+        errors.push(format!("unsupported frequency {}", template.frequency));
+    }
+
+    errors
+}
+
+/// Build a signed bundle for a template owned by the tenant (or a platform default)
+pub async fn export_template(
+    db: &PgPool,
+    signer: &BundleSigner,
+    tenant_id: Uuid,
+    template_id: Uuid,
+) -> anyhow::Result<Option<TemplateBundle>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT template_id, tenant_id, template_name, report_type, regulator, frequency,
+               template_structure, validation_rules, metric_selections,
+               submission_deadline_days, schema_version
+        FROM report_templates
+        WHERE template_id = $1 AND (tenant_id = $2 OR tenant_id IS NULL)
+        "#,
+        template_id,
+        tenant_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let mut bundle = TemplateBundle {
+        bundle_format: BUNDLE_FORMAT.to_string(),
+        schema_version: row.schema_version,
+        exported_at: chrono::Utc::now(),
+        source_tenant_id: row.tenant_id,
+        source_template_id: row.template_id,
+        template: TemplateDefinition {
+            template_name: row.template_name,
+            report_type: row.report_type,
+            regulator: row.regulator,
+            frequency: row.frequency,
+            template_structure: row.template_structure,
+            validation_rules: row.validation_rules.unwrap_or_else(|| serde_json::json!({})),
+            metric_selections: serde_json::from_value(row.metric_selections)?,
+            submission_deadline_days: row.submission_deadline_days.unwrap_or(7),
+        },
+        signature: String::new(),
+    };
+    signer.sign(&mut bundle)?;
+
+    Ok(Some(bundle))
+}
+
+/// Validate and import a bundle into the target tenant, recording the attempt
+pub async fn import_template(
+    db: &PgPool,
+    signer: &BundleSigner,
+    request: ImportTemplateRequest,
+) -> anyhow::Result<TemplateImportResponse> {
+    let bundle = &request.bundle;
+    let template_name = request
+        .template_name
+        .clone()
+        .unwrap_or_else(|| bundle.template.template_name.clone());
+
+    let mut errors = validate_bundle(signer, bundle);
+
+    let existing = sqlx::query_scalar!(
+        "SELECT template_id FROM report_templates WHERE tenant_id = $1 AND template_name = $2",
+        request.tenant_id,
+        template_name
+    )
+    .fetch_optional(db)
+    .await?;
+
+    if existing.is_some() && !request.overwrite {
+        errors.push(format!(
+            "template '{}' already exists for this tenant; set overwrite to replace it",
+            template_name
+        ));
+    }
+
+    let template_id = if errors.is_empty() {
+        let template = &bundle.template;
+        let metric_selections = serde_json::to_value(&template.metric_selections)?;
+        let template_id = match existing {
+            Some(template_id) => {
+                sqlx::query!(
+                    r#"
+                    UPDATE report_templates
+                    SET report_type = $2, regulator = $3, frequency = $4, template_structure = $5,
+                        validation_rules = $6, metric_selections = $7, submission_deadline_days = $8,
+                        schema_version = $9, updated_at = NOW()
+                    WHERE template_id = $1
+                    "#,
+                    template_id,
+                    template.report_type,
+                    template.regulator,
+                    template.frequency,
+                    template.template_structure,
+                    template.validation_rules,
+                    metric_selections,
+                    template.submission_deadline_days,
+                    TEMPLATE_SCHEMA_VERSION
+                )
+                .execute(db)
+                .await?;
+                template_id
+            }
+            None => {
+                sqlx::query_scalar!(
+                    r#"
+                    INSERT INTO report_templates (
+                        tenant_id, template_name, report_type, regulator, frequency,
+                        template_structure, validation_rules, metric_selections,
+                        submission_deadline_days, schema_version, is_mandatory
+                    )
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, false)
+                    RETURNING template_id
+                    "#,
+                    request.tenant_id,
+                    template_name,
+                    template.report_type,
+                    template.regulator,
+                    template.frequency,
+                    template.template_structure,
+                    template.validation_rules,
+                    metric_selections,
+                    template.submission_deadline_days,
+                    TEMPLATE_SCHEMA_VERSION
+                )
+                .fetch_one(db)
+                .await?
+            }
+        };
+        Some(template_id)
+    } else {
+        None
+    };
+
+    let status = if template_id.is_some() { "IMPORTED" } else { "REJECTED" };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO report_template_imports (
+            tenant_id, template_id, source_tenant_id, source_template_id,
+            bundle_schema_version, bundle_signature, status, errors
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        request.tenant_id,
+        template_id,
+        bundle.source_tenant_id,
+        bundle.source_template_id,
+        bundle.schema_version,
+        bundle.signature,
+        status,
+        serde_json::to_value(&errors)?
+    )
+    .execute(db)
+    .await?;
+
+    Ok(TemplateImportResponse {
+        status: status.to_string(),
+        template_id,
+        errors,
+    })
+}
@@ -0,0 +1,875 @@
+//! Report schedules managed by tenants
+//!
+//! Besides the business-day reports of `schedule`, a tenant keeps its own
+//! schedules in report_schedules: a report type and format generated on a cron
+//! expression in a time zone, each run covering the `period_days` days before
+//! the local day it fires on. Expressions have six fields, seconds first, like
+//! REPORT_SCHEDULE_CHECK; a five-field expression fires at second 0. A schedule
+//! may not fire more often than every 15 minutes.
+//!
+//! A schedule that names no time zone runs in its tenant's, set through
+//! /reports/schedules/timezone, so "daily at 6 AM" stays 6 AM tenant-local
+//! across daylight saving changes. Expressions are read in wall-clock time: a
+//! time the clocks skip over fires as the clocks jump, as far past the change
+//! as it was meant to be, and a time the clocks pass twice fires the first
+//! time only. Schedules list their next runs, five unless `next_runs` asks for
+//! up to MAX_NEXT_RUNS.
+//!
+//! Every replica registers the next run of each active schedule as a one-shot
+//! job on its JobScheduler, and the one after it once that run is done. It
+//! re-reads the schedules after each change made through it and every
+//! REPORT_SCHEDULES_SYNC_SECS for changes made through other replicas. A run is
+//! claimed by setting last_run_at, so only one replica generates it.
+//!
+//! A schedule with a delivery plan sends each report it generates to the
+//! plan's recipients once the report is approved; see `delivery` and `review`.
+//! An empty recipient list removes the plan.
+//! Each run, completed or failed, is reported to the tenant's webhooks; see
+//! `webhooks`.
+
+use chrono::{DateTime, Duration, FixedOffset, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio_cron_scheduler::{Job, JobScheduler};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+
+use crate::delivery::{DeliveryPlan, ReportDelivery};
+use crate::generators;
+use crate::render::ReportFormat;
+use crate::report_files::ReportFiles;
+use crate::review;
+use crate::schedule;
+use crate::webhooks::{ReportOutcome, ReportWebhooks};
+use crate::ReportEngine;
+
+const MIN_INTERVAL_MINUTES: i64 = 15;
+/// Time zone of tenants that have not set one
+const DEFAULT_TIMEZONE: &str = "Asia/Kolkata";
+const MAX_PERIOD_DAYS: i32 = 366;
+pub const DEFAULT_NEXT_RUNS: usize = 5;
+pub const MAX_NEXT_RUNS: usize = 50;
+/// A registered run this long overdue is taken to have been lost, and the next one registered
+const OVERDUE_MINUTES: i64 = 1;
+
+#[derive(Deserialize)]
+pub struct CreateScheduleRequest {
+    pub name: String,
+    pub report_type: String,
+    /// PDF when not given
+    pub format: Option<String>,
+    /// An active template of the report type when not given
+    pub template_id: Option<Uuid>,
+    pub cron_expression: String,
+    /// The tenant's time zone when not given
+    pub timezone: Option<String>,
+    pub period_days: Option<i32>,
+    pub is_active: Option<bool>,
+    pub delivery: Option<DeliveryPlan>,
+    pub created_by: Option<Uuid>,
+}
+
+/// Fields left out keep their value
+#[derive(Deserialize)]
+pub struct UpdateScheduleRequest {
+    pub name: Option<String>,
+    pub report_type: Option<String>,
+    pub format: Option<String>,
+    pub template_id: Option<Uuid>,
+    pub cron_expression: Option<String>,
+    /// An empty time zone makes the schedule follow the tenant's again
+    pub timezone: Option<String>,
+    pub period_days: Option<i32>,
+    pub is_active: Option<bool>,
+    pub delivery: Option<DeliveryPlan>,
+}
+
+/// When a schedule fires
+#[derive(Serialize, Clone)]
+pub struct ScheduledRun {
+    pub at: DateTime<Utc>,
+    /// The same moment in the schedule's time zone, with the offset in force then
+    pub local: DateTime<FixedOffset>,
+}
+
+#[derive(Serialize, Clone, sqlx::FromRow)]
+pub struct ReportSchedule {
+    pub schedule_id: Uuid,
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub report_type: String,
+    pub format: String,
+    pub template_id: Option<Uuid>,
+    pub cron_expression: String,
+    /// Null when the schedule follows the tenant's time zone
+    pub timezone: Option<String>,
+    pub period_days: i32,
+    pub is_active: bool,
+    pub delivery: Option<Json<DeliveryPlan>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_status: Option<String>,
+    pub last_report_id: Option<Uuid>,
+    pub last_error: Option<String>,
+    /// The time zone the schedule runs in, its own or the tenant's
+    #[sqlx(skip)]
+    pub effective_timezone: String,
+    /// When an active schedule fires next
+    #[sqlx(skip)]
+    pub next_run_at: Option<DateTime<Utc>>,
+    /// The next runs of an active schedule, in order
+    #[sqlx(skip)]
+    pub next_runs: Vec<ScheduledRun>,
+}
+
+const SCHEDULE_COLUMNS: &str = "schedule_id, tenant_id, name, report_type, format, template_id, cron_expression, \
+     timezone, period_days, is_active, delivery, created_by, created_at, updated_at, last_run_at, last_status, \
+     last_report_id, last_error";
+
+/// The tenant's configured time zone for its report schedules
+#[derive(Serialize)]
+pub struct TenantTimezone {
+    pub timezone: String,
+    /// Schedules that run in it rather than in a time zone of their own
+    pub schedules_following: i64,
+}
+
+#[derive(Deserialize)]
+pub struct SetTimezoneRequest {
+    pub timezone: String,
+}
+
+impl ReportSchedule {
+    fn with_next_runs(mut self, tenant_timezone: &str, count: usize) -> Self {
+        self.effective_timezone = self.timezone.clone().unwrap_or_else(|| tenant_timezone.to_string());
+        let timezone = Tz::from_str(&self.effective_timezone).ok();
+        let cron = cron::Schedule::from_str(&self.cron_expression).ok();
+        if let (true, Some(timezone), Some(cron)) = (self.is_active, timezone, cron) {
+            self.next_runs = upcoming(&cron, timezone, Utc::now())
+                .take(count)
+                .map(|at| {
+                    let local = at.with_timezone(&timezone);
+                    ScheduledRun {
+                        at,
+                        local: local.with_timezone(&local.offset().fix()),
+                    }
+                })
+                .collect();
+        }
+        self.next_run_at = self.next_runs.first().map(|run| run.at);
+        self
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScheduleError {
+    #[error("schedule not found")]
+    NotFound,
+    #[error("invalid schedule")]
+    Invalid(Vec<String>),
+    #[error("a schedule named '{0}' already exists")]
+    NameTaken(String),
+    #[error("tenant not found")]
+    TenantNotFound,
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for ScheduleError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// The fields of a schedule a tenant sets
+struct Definition {
+    name: String,
+    report_type: String,
+    format: String,
+    template_id: Option<Uuid>,
+    cron_expression: String,
+    /// None to follow the tenant's
+    timezone: Option<String>,
+    period_days: i32,
+    is_active: bool,
+    delivery: Option<DeliveryPlan>,
+}
+
+/// Five-field expressions get a seconds field of 0
+fn normalize_cron(expression: &str) -> String {
+    let expression = expression.split_whitespace().collect::<Vec<_>>().join(" ");
+    if expression.split(' ').count() == 5 {
+        format!("0 {}", expression)
+    } else {
+        expression
+    }
+}
+
+/// Times the schedule fires after `after`, in order
+///
+/// The expression is walked in wall-clock time, as if the zone were UTC, and
+/// each time then placed in the zone. Several wall-clock times placed in one
+/// skipped interval can land on the same moment as a later one; it fires once.
+fn upcoming(cron: &cron::Schedule, timezone: Tz, after: DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+    let wall_clock = Utc.from_utc_datetime(&after.with_timezone(&timezone).naive_local());
+    let mut last = after;
+    cron.after(&wall_clock).filter_map(move |wall| {
+        let at = place(timezone, wall.naive_utc());
+        (at > last).then(|| {
+            last = at;
+            at
+        })
+    })
+}
+
+/// The moment a wall-clock time stands for in the zone
+fn place(timezone: Tz, wall: NaiveDateTime) -> DateTime<Utc> {
+    match timezone.from_local_datetime(&wall) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => at.with_timezone(&Utc),
+        // Skipped by the clocks moving forward: read with the offset in force before they moved
+        LocalResult::None => {
+            let before = timezone.offset_from_utc_datetime(&(wall - Duration::days(1))).fix();
+            Utc.from_utc_datetime(&(wall - Duration::seconds(i64::from(before.local_minus_utc()))))
+        }
+    }
+}
+
+/// The time zone of the tenant's schedules that do not name their own, and of its report periods
+pub async fn tenant_timezone(db: &PgPool, tenant_id: Uuid) -> Result<String, sqlx::Error> {
+    let timezone = sqlx::query_scalar::<_, String>("SELECT timezone FROM tenants WHERE tenant_id = $1")
+        .bind(tenant_id)
+        .fetch_optional(db)
+        .await?;
+    Ok(timezone.unwrap_or_else(|| DEFAULT_TIMEZONE.to_string()))
+}
+
+/// A time zone a schedule or tenant can be set to
+fn timezone_error(timezone: &str) -> Option<String> {
+    Tz::from_str(timezone).is_err().then(|| format!("unknown timezone: {}", timezone))
+}
+
+/// A time zone given for a schedule; none when blank, to follow the tenant's
+fn own_timezone(timezone: String) -> Option<String> {
+    Some(timezone.trim().to_string()).filter(|timezone| !timezone.is_empty())
+}
+
+impl Definition {
+    fn from_request(request: CreateScheduleRequest) -> Self {
+        Self {
+            name: request.name.trim().to_string(),
+            report_type: request.report_type.trim().to_uppercase(),
+            format: request.format.as_deref().unwrap_or("PDF").trim().to_uppercase(),
+            template_id: request.template_id,
+            cron_expression: normalize_cron(&request.cron_expression),
+            timezone: request.timezone.and_then(own_timezone),
+            period_days: request.period_days.unwrap_or(1),
+            is_active: request.is_active.unwrap_or(true),
+            delivery: request.delivery.and_then(DeliveryPlan::nonempty),
+        }
+    }
+
+    fn update(schedule: ReportSchedule, request: UpdateScheduleRequest) -> Self {
+        Self {
+            name: request.name.map_or(schedule.name, |name| name.trim().to_string()),
+            report_type: request
+                .report_type
+                .map_or(schedule.report_type, |report_type| report_type.trim().to_uppercase()),
+            format: request.format.map_or(schedule.format, |format| format.trim().to_uppercase()),
+            template_id: request.template_id.or(schedule.template_id),
+            cron_expression: request
+                .cron_expression
+                .map_or(schedule.cron_expression, |expression| normalize_cron(&expression)),
+            timezone: match request.timezone {
+                Some(timezone) => own_timezone(timezone),
+                None => schedule.timezone,
+            },
+            period_days: request.period_days.unwrap_or(schedule.period_days),
+            is_active: request.is_active.unwrap_or(schedule.is_active),
+            delivery: match request.delivery {
+                Some(plan) => plan.nonempty(),
+                None => schedule.delivery.map(|plan| plan.0),
+            },
+        }
+    }
+
+    async fn validate(
+        &self,
+        db: &PgPool,
+        files: &ReportFiles,
+        delivery: &ReportDelivery,
+        tenant_timezone: &str,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let mut errors = Vec::new();
+        if self.name.is_empty() {
+            errors.push("name is required".to_string());
+        } else if self.name.chars().count() > 200 {
+            errors.push("name may be at most 200 characters".to_string());
+        }
+        let generator = generators::get(&self.report_type);
+        if generator.is_none() {
+            errors.push(format!("report_type must be {}", generators::names()));
+        }
+        match (ReportFormat::parse(&self.format), generator) {
+            (None, _) => errors.push("format must be one of PDF, CSV, XLSX, JSON, XML or XBRL".to_string()),
+            (Some(format), Some(generator))
+                if !generator.formats().contains(&format) || !files.covers(format, &self.report_type) =>
+            {
+                errors.push(format!("{} reports cannot be rendered as {}", self.report_type, format.as_str()));
+            }
+            (Some(_), _) => {}
+        }
+        let timezone = self.timezone.as_deref().unwrap_or(tenant_timezone);
+        errors.extend(timezone_error(timezone));
+        match cron::Schedule::from_str(&self.cron_expression) {
+            Err(e) => errors.push(format!("invalid cron_expression: {}", e)),
+            Ok(cron) => {
+                let timezone = Tz::from_str(timezone).unwrap_or(Tz::UTC);
+                let fires: Vec<DateTime<Utc>> = upcoming(&cron, timezone, Utc::now()).take(25).collect();
+                if fires.is_empty() {
+                    errors.push("cron_expression never fires".to_string());
+                } else if fires.windows(2).any(|pair| pair[1] - pair[0] < Duration::minutes(MIN_INTERVAL_MINUTES)) {
+                    errors.push(format!(
+                        "schedules may not fire more often than every {} minutes",
+                        MIN_INTERVAL_MINUTES
+                    ));
+                }
+            }
+        }
+        if !(1..=MAX_PERIOD_DAYS).contains(&self.period_days) {
+            errors.push(format!("period_days must be between 1 and {}", MAX_PERIOD_DAYS));
+        }
+        if let Some(plan) = &self.delivery {
+            let request = plan.request(Uuid::nil(), None);
+            errors.extend(request.validate().into_iter().map(|error| format!("delivery: {}", error)));
+            if let Err(e) = delivery.ensure_available(&request) {
+                errors.push(format!("delivery: {}", e));
+            }
+        }
+        if let Some(template_id) = self.template_id {
+            let usable = sqlx::query_scalar::<_, bool>(
+                r#"
+                SELECT EXISTS (
+                    SELECT 1 FROM report_templates WHERE template_id = $1 AND report_type = $2 AND is_active
+                )
+                "#,
+            )
+            .bind(template_id)
+            .bind(&self.report_type)
+            .fetch_one(db)
+            .await?;
+            if !usable {
+                errors.push(format!("template {} is not an active {} template", template_id, self.report_type));
+            }
+        }
+        Ok(errors)
+    }
+}
+
+fn name_taken(e: &sqlx::Error) -> bool {
+    matches!(e, sqlx::Error::Database(db) if db.constraint() == Some("uq_report_schedule_name"))
+}
+
+/// The tenant's schedules, each with its `next_runs` next runs
+pub async fn list(db: &PgPool, tenant: &TenantContext, next_runs: usize) -> Result<Vec<ReportSchedule>, sqlx::Error> {
+    let tenant_timezone = tenant_timezone(db, tenant.tenant_id()).await?;
+    let schedules = sqlx::query_as::<_, ReportSchedule>(&format!(
+        "SELECT {} FROM report_schedules WHERE tenant_id = $1 ORDER BY name",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .fetch_all(db)
+    .await?;
+    Ok(schedules
+        .into_iter()
+        .map(|schedule| schedule.with_next_runs(&tenant_timezone, next_runs))
+        .collect())
+}
+
+pub async fn get(
+    db: &PgPool,
+    tenant: &TenantContext,
+    schedule_id: Uuid,
+    next_runs: usize,
+) -> Result<ReportSchedule, ScheduleError> {
+    let tenant_timezone = tenant_timezone(db, tenant.tenant_id()).await?;
+    sqlx::query_as::<_, ReportSchedule>(&format!(
+        "SELECT {} FROM report_schedules WHERE tenant_id = $1 AND schedule_id = $2",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(schedule_id)
+    .fetch_optional(db)
+    .await?
+    .map(|schedule| schedule.with_next_runs(&tenant_timezone, next_runs))
+    .ok_or(ScheduleError::NotFound)
+}
+
+pub async fn create(
+    db: &PgPool,
+    files: &ReportFiles,
+    delivery: &ReportDelivery,
+    tenant: &TenantContext,
+    request: CreateScheduleRequest,
+) -> Result<ReportSchedule, ScheduleError> {
+    let created_by = request.created_by;
+    let definition = Definition::from_request(request);
+    let tenant_timezone = tenant_timezone(db, tenant.tenant_id()).await?;
+    let errors = definition.validate(db, files, delivery, &tenant_timezone).await?;
+    if !errors.is_empty() {
+        return Err(ScheduleError::Invalid(errors));
+    }
+    let created = sqlx::query_as::<_, ReportSchedule>(&format!(
+        r#"
+        INSERT INTO report_schedules (
+            tenant_id, name, report_type, format, template_id, cron_expression, timezone, period_days, is_active,
+            delivery, created_by
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        RETURNING {}
+        "#,
+        SCHEDULE_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(&definition.name)
+    .bind(&definition.report_type)
+    .bind(&definition.format)
+    .bind(definition.template_id)
+    .bind(&definition.cron_expression)
+    .bind(&definition.timezone)
+    .bind(definition.period_days)
+    .bind(definition.is_active)
+    .bind(definition.delivery.as_ref().map(Json))
+    .bind(created_by)
+    .fetch_one(db)
+    .await;
+    match created {
+        Ok(schedule) => Ok(schedule.with_next_runs(&tenant_timezone, DEFAULT_NEXT_RUNS)),
+        Err(e) if name_taken(&e) => Err(ScheduleError::NameTaken(definition.name)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn update(
+    db: &PgPool,
+    files: &ReportFiles,
+    delivery: &ReportDelivery,
+    tenant: &TenantContext,
+    schedule_id: Uuid,
+    request: UpdateScheduleRequest,
+) -> Result<ReportSchedule, ScheduleError> {
+    let definition = Definition::update(get(db, tenant, schedule_id, DEFAULT_NEXT_RUNS).await?, request);
+    let tenant_timezone = tenant_timezone(db, tenant.tenant_id()).await?;
+    let errors = definition.validate(db, files, delivery, &tenant_timezone).await?;
+    if !errors.is_empty() {
+        return Err(ScheduleError::Invalid(errors));
+    }
+    let updated = sqlx::query_as::<_, ReportSchedule>(&format!(
+        r#"
+        UPDATE report_schedules
+        SET name = $3, report_type = $4, format = $5, template_id = $6, cron_expression = $7, timezone = $8,
+            period_days = $9, is_active = $10, delivery = $11, updated_at = NOW()
+        WHERE tenant_id = $1 AND schedule_id = $2
+        RETURNING {}
+        "#,
+        SCHEDULE_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(schedule_id)
+    .bind(&definition.name)
+    .bind(&definition.report_type)
+    .bind(&definition.format)
+    .bind(definition.template_id)
+    .bind(&definition.cron_expression)
+    .bind(&definition.timezone)
+    .bind(definition.period_days)
+    .bind(definition.is_active)
+    .bind(definition.delivery.as_ref().map(Json))
+    .fetch_optional(db)
+    .await;
+    match updated {
+        Ok(Some(schedule)) => Ok(schedule.with_next_runs(&tenant_timezone, DEFAULT_NEXT_RUNS)),
+        // Deleted since it was read
+        Ok(None) => Err(ScheduleError::NotFound),
+        Err(e) if name_taken(&e) => Err(ScheduleError::NameTaken(definition.name)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn delete(db: &PgPool, tenant: &TenantContext, schedule_id: Uuid) -> Result<(), ScheduleError> {
+    let deleted = sqlx::query("DELETE FROM report_schedules WHERE tenant_id = $1 AND schedule_id = $2")
+        .bind(tenant.tenant_id())
+        .bind(schedule_id)
+        .execute(db)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(ScheduleError::NotFound);
+    }
+    Ok(())
+}
+
+async fn schedules_following(db: &PgPool, tenant_id: Uuid) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM report_schedules WHERE tenant_id = $1 AND timezone IS NULL")
+        .bind(tenant_id)
+        .fetch_one(db)
+        .await
+}
+
+pub async fn timezone(db: &PgPool, tenant: &TenantContext) -> Result<TenantTimezone, sqlx::Error> {
+    Ok(TenantTimezone {
+        timezone: tenant_timezone(db, tenant.tenant_id()).await?,
+        schedules_following: schedules_following(db, tenant.tenant_id()).await?,
+    })
+}
+
+/// Move the tenant's schedules that follow its time zone to another
+pub async fn set_timezone(
+    db: &PgPool,
+    tenant: &TenantContext,
+    request: SetTimezoneRequest,
+) -> Result<TenantTimezone, ScheduleError> {
+    let timezone = request.timezone.trim();
+    if let Some(error) = timezone_error(timezone) {
+        return Err(ScheduleError::Invalid(vec![error]));
+    }
+    let updated = sqlx::query("UPDATE tenants SET timezone = $2, updated_at = NOW() WHERE tenant_id = $1")
+        .bind(tenant.tenant_id())
+        .bind(timezone)
+        .execute(db)
+        .await?
+        .rows_affected();
+    if updated == 0 {
+        return Err(ScheduleError::TenantNotFound);
+    }
+    Ok(TenantTimezone {
+        timezone: timezone.to_string(),
+        schedules_following: schedules_following(db, tenant.tenant_id()).await?,
+    })
+}
+
+/// The run of a schedule registered on this replica
+struct Registered {
+    job_id: Uuid,
+    /// The schedule's updated_at and time zone when it was registered
+    updated_at: DateTime<Utc>,
+    timezone: String,
+    at: DateTime<Utc>,
+}
+
+/// The schedules registered on this replica's JobScheduler
+pub struct TenantSchedules {
+    db: PgPool,
+    files: Arc<ReportFiles>,
+    generator: Arc<ReportEngine>,
+    webhooks: Arc<ReportWebhooks>,
+    scheduler: JobScheduler,
+    sync_every: std::time::Duration,
+    /// The next run of each registered schedule
+    jobs: Mutex<HashMap<Uuid, Registered>>,
+}
+
+impl TenantSchedules {
+    pub fn from_env(
+        db: PgPool,
+        files: Arc<ReportFiles>,
+        generator: Arc<ReportEngine>,
+        webhooks: Arc<ReportWebhooks>,
+        scheduler: JobScheduler,
+    ) -> Self {
+        let seconds = std::env::var("REPORT_SCHEDULES_SYNC_SECS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(60u64)
+            .max(5);
+        Self {
+            db,
+            files,
+            generator,
+            webhooks,
+            scheduler,
+            sync_every: std::time::Duration::from_secs(seconds),
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Register new and changed schedules, and remove deleted and paused ones
+    pub async fn sync(self: &Arc<Self>) -> anyhow::Result<()> {
+        let active = sqlx::query_as::<_, (Uuid, String, String, DateTime<Utc>)>(
+            r#"
+            SELECT s.schedule_id, s.cron_expression, COALESCE(s.timezone, t.timezone), s.updated_at
+            FROM report_schedules s
+            JOIN tenants t ON t.tenant_id = s.tenant_id
+            WHERE s.is_active AND t.is_active
+            "#,
+        )
+        .fetch_all(&self.db)
+        .await?;
+        let active: HashMap<Uuid, (String, String, DateTime<Utc>)> = active
+            .into_iter()
+            .map(|(schedule_id, cron_expression, timezone, updated_at)| {
+                (schedule_id, (cron_expression, timezone, updated_at))
+            })
+            .collect();
+
+        let now = Utc::now();
+        let mut jobs = self.jobs.lock().await;
+        let stale: Vec<Uuid> = jobs
+            .iter()
+            .filter(|(schedule_id, registered)| {
+                registered.at < now - Duration::minutes(OVERDUE_MINUTES)
+                    || active.get(schedule_id).map_or(true, |(_, timezone, updated_at)| {
+                        *updated_at != registered.updated_at || *timezone != registered.timezone
+                    })
+            })
+            .map(|(schedule_id, _)| *schedule_id)
+            .collect();
+        for schedule_id in stale {
+            if let Some(registered) = jobs.remove(&schedule_id) {
+                if let Err(e) = self.scheduler.remove(&registered.job_id).await {
+                    warn!("Failed to unregister report schedule {}: {}", schedule_id, e);
+                }
+            }
+        }
+        for (schedule_id, (cron_expression, timezone, updated_at)) in active {
+            if jobs.contains_key(&schedule_id) {
+                continue;
+            }
+            let registered = match self.job(schedule_id, &cron_expression, &timezone, now) {
+                Ok(Some((job, at))) => match self.scheduler.add(job).await {
+                    Ok(job_id) => Ok(Some((job_id, at))),
+                    Err(e) => Err(anyhow::Error::from(e)),
+                },
+                Ok(None) => Ok(None),
+                Err(e) => Err(e),
+            };
+            match registered {
+                Ok(Some((job_id, at))) => {
+                    jobs.insert(
+                        schedule_id,
+                        Registered {
+                            job_id,
+                            updated_at,
+                            timezone,
+                            at,
+                        },
+                    );
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Skipping report schedule {}: {:#}", schedule_id, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// A one-shot job for the schedule's next run after `after`, if it has one
+    fn job(
+        self: &Arc<Self>,
+        schedule_id: Uuid,
+        cron_expression: &str,
+        timezone: &str,
+        after: DateTime<Utc>,
+    ) -> anyhow::Result<Option<(Job, DateTime<Utc>)>> {
+        let timezone = Tz::from_str(timezone).map_err(|e| anyhow::anyhow!("unknown timezone {}: {}", timezone, e))?;
+        let cron = cron::Schedule::from_str(cron_expression)?;
+        let Some(at) = upcoming(&cron, timezone, after).next() else {
+            return Ok(None);
+        };
+        let schedules = Arc::downgrade(self);
+        let wait = (at - Utc::now()).to_std().unwrap_or_default();
+        let job = Job::new_one_shot_async(wait, move |job_id, _lock| {
+            let schedules = schedules.clone();
+            Box::pin(async move {
+                let Some(schedules) = schedules.upgrade() else {
+                    return;
+                };
+                let ran = run(
+                    &schedules.db,
+                    &schedules.files,
+                    &schedules.generator,
+                    &schedules.webhooks,
+                    schedule_id,
+                    timezone,
+                )
+                .await;
+                if let Err(e) = ran {
+                    error!("Report schedule {} failed: {:#}", schedule_id, e);
+                }
+                schedules.fired(schedule_id, job_id).await;
+            })
+        })?;
+        Ok(Some((job, at)))
+    }
+
+    /// Register the run after the one that just fired
+    async fn fired(self: &Arc<Self>, schedule_id: Uuid, job_id: Uuid) {
+        {
+            let mut jobs = self.jobs.lock().await;
+            if jobs.get(&schedule_id).is_some_and(|registered| registered.job_id == job_id) {
+                jobs.remove(&schedule_id);
+            }
+        }
+        if let Err(e) = self.sync().await {
+            error!("Failed to register the next run of report schedule {}: {:#}", schedule_id, e);
+        }
+    }
+
+    /// Load the schedules now and pick up changes made through other replicas from then on
+    pub fn spawn_sync(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.sync_every);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.sync().await {
+                    error!("Failed to load report schedules: {:#}", e);
+                }
+            }
+        });
+    }
+}
+
+async fn run(
+    db: &PgPool,
+    files: &ReportFiles,
+    generator: &ReportEngine,
+    webhooks: &ReportWebhooks,
+    schedule_id: Uuid,
+    timezone: Tz,
+) -> anyhow::Result<()> {
+    let fired_at = Utc::now();
+    // Replicas fire within moments of each other; the first claims the run
+    let claimed = sqlx::query_as::<
+        _,
+        (Uuid, String, String, Option<Uuid>, i32, Option<Json<DeliveryPlan>>, Option<Uuid>),
+    >(
+        r#"
+        UPDATE report_schedules
+        SET last_run_at = $2, last_status = 'RUNNING', last_error = NULL
+        WHERE schedule_id = $1 AND is_active AND (last_run_at IS NULL OR last_run_at < $2 - INTERVAL '1 minute')
+        RETURNING tenant_id, report_type, format, template_id, period_days, delivery, created_by
+        "#,
+    )
+    .bind(schedule_id)
+    .bind(fired_at)
+    .fetch_optional(db)
+    .await?;
+    let Some((tenant_id, report_type, format, template_id, period_days, plan, created_by)) = claimed else {
+        return Ok(());
+    };
+
+    let period_end = fired_at.with_timezone(&timezone).date_naive() - Duration::days(1);
+    let period_start = period_end - Duration::days(i64::from(period_days) - 1);
+    // Its creator is the maker of the report, so another officer approves it
+    let outcome = generate(
+        db,
+        files,
+        generator,
+        tenant_id,
+        &report_type,
+        &format,
+        template_id,
+        period_start,
+        period_end,
+        created_by,
+    )
+    .await;
+    let (status, report_id, error) = match &outcome {
+        Ok(report_id) => ("COMPLETED", Some(*report_id), None),
+        Err(e) => ("FAILED", None, Some(format!("{:#}", e))),
+    };
+    sqlx::query(
+        r#"
+        UPDATE report_schedules
+        SET last_status = $2, last_report_id = COALESCE($3, last_report_id), last_error = $4
+        WHERE schedule_id = $1
+        "#,
+    )
+    .bind(schedule_id)
+    .bind(status)
+    .bind(report_id)
+    .bind(error)
+    .execute(db)
+    .await?;
+
+    let notice = ReportOutcome {
+        tenant_id,
+        report_id,
+        report_type: &report_type,
+        format: &format,
+        period_start,
+        period_end,
+        schedule_id: Some(schedule_id),
+    };
+    match &outcome {
+        Ok(_) => webhooks.completed(db, &notice).await,
+        Err(e) => webhooks.failed(db, &notice, &format!("{:#}", e)).await,
+    }
+
+    let report_id = outcome?;
+    info!(
+        "Generated {} {} for tenant {} covering {} to {} on schedule {}",
+        report_type, report_id, tenant_id, period_start, period_end, schedule_id
+    );
+
+    if let Some(Json(plan)) = plan {
+        review::hold(db, report_id, &plan.request(tenant_id, None)).await.map_err(|e| {
+            anyhow::anyhow!("report {} was generated but its delivery could not be held: {}", report_id, e)
+        })?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn generate(
+    db: &PgPool,
+    files: &ReportFiles,
+    generator: &ReportEngine,
+    tenant_id: Uuid,
+    report_type: &str,
+    format: &str,
+    template_id: Option<Uuid>,
+    period_start: chrono::NaiveDate,
+    period_end: chrono::NaiveDate,
+    generated_by: Option<Uuid>,
+) -> anyhow::Result<Uuid> {
+    let format = ReportFormat::parse(format).ok_or_else(|| anyhow::anyhow!("unknown report format {}", format))?;
+    let template_id = match template_id {
+        Some(template_id) => template_id,
+        None => sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT template_id FROM report_templates
+            WHERE report_type = $1 AND is_active
+            ORDER BY created_at
+            LIMIT 1
+            "#,
+        )
+        .bind(report_type)
+        .fetch_optional(db)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("no active template for {} reports", report_type))?,
+    };
+    schedule::record(
+        db,
+        files,
+        generator,
+        tenant_id,
+        template_id,
+        report_type,
+        format,
+        period_start,
+        period_end,
+        generated_by,
+        None,
+    )
+    .await
+}
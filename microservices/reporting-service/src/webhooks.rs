@@ -0,0 +1,632 @@
+//! Report completion webhooks
+//!
+//! A tenant registers callback URLs in report_webhooks for the report events
+//! it wants: COMPLETED once a report is generated and stored, and FAILED when
+//! generating one goes wrong, whether it was requested, on the business-day
+//! schedule of `schedule` or on one of the tenant's own schedules. Each event
+//! is queued in report_webhook_deliveries for every matching webhook and sent
+//! in the background as
+//!
+//! ```json
+//! {"event": "report.completed", "event_id": "...", "occurred_at": "...", "report": {...}}
+//! ```
+//!
+//! where the report carries its type, format, period, file size and SHA-256,
+//! and download_url, which is absolute when REPORT_API_BASE_URL is set. Bodies
+//! are signed like the audit service's integrity webhook, as
+//! `X-DharmaGuard-Signature: sha256=<hex HMAC-SHA256 of the body>` under the
+//! webhook's secret, which is only returned when it is created or rotated.
+//!
+//! Answers other than 2xx, and requests that fail or time out, are attempted
+//! again after REPORT_WEBHOOK_RETRY_SECS, doubling each time, up to
+//! REPORT_WEBHOOK_MAX_ATTEMPTS attempts. Redirects are not followed.
+
+use anyhow::Context;
+use chrono::{DateTime, NaiveDate, Utc};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use dharmaguard_common::tenant::TenantContext;
+
+const SECRET_PREFIX: &str = "dgwh_";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often deliveries due for an attempt are looked for
+const POLL: Duration = Duration::from_secs(5);
+const BATCH: i64 = 50;
+/// Longest wait between two attempts
+const MAX_RETRY_DELAY_SECS: i64 = 6 * 60 * 60;
+const MAX_URL_LENGTH: usize = 2000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReportEvent {
+    Completed,
+    Failed,
+}
+
+impl ReportEvent {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Completed => "COMPLETED",
+            Self::Failed => "FAILED",
+        }
+    }
+
+    /// The name a payload carries
+    fn name(self) -> &'static str {
+        match self {
+            Self::Completed => "report.completed",
+            Self::Failed => "report.failed",
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Both events when not given
+    pub events: Option<Vec<ReportEvent>>,
+    pub description: Option<String>,
+    pub is_active: Option<bool>,
+    pub created_by: Option<Uuid>,
+}
+
+/// Fields left out keep their value
+#[derive(Deserialize)]
+pub struct UpdateWebhookRequest {
+    pub url: Option<String>,
+    pub events: Option<Vec<ReportEvent>>,
+    pub description: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ReportWebhook {
+    pub webhook_id: Uuid,
+    pub tenant_id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+const WEBHOOK_COLUMNS: &str =
+    "webhook_id, tenant_id, url, events, description, is_active, created_by, created_at, updated_at";
+
+/// A webhook with its secret, only returned when the secret is set
+#[derive(Serialize)]
+pub struct WebhookWithSecret {
+    #[serde(flatten)]
+    pub webhook: ReportWebhook,
+    pub secret: String,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub delivery_id: Uuid,
+    pub webhook_id: Uuid,
+    pub report_id: Option<Uuid>,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub response_status: Option<i32>,
+    pub failure_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+const DELIVERY_COLUMNS: &str = "delivery_id, webhook_id, report_id, event, payload, status, attempts, \
+     next_attempt_at, response_status, failure_reason, created_at, delivered_at";
+
+#[derive(Debug, thiserror::Error)]
+pub enum WebhookError {
+    #[error("webhook not found")]
+    NotFound,
+    #[error("invalid webhook")]
+    Invalid(Vec<String>),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for WebhookError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// What webhooks are told about a report
+pub struct ReportOutcome<'a> {
+    pub tenant_id: Uuid,
+    /// `None` for a scheduled report that failed before it was given one
+    pub report_id: Option<Uuid>,
+    pub report_type: &'a str,
+    pub format: &'a str,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    /// The tenant schedule it was generated on
+    pub schedule_id: Option<Uuid>,
+}
+
+/// generated_at, file_hash, file_size and file_expires_at of a stored report
+type StoredReport = (Option<DateTime<Utc>>, Option<String>, Option<i64>, Option<DateTime<Utc>>);
+
+/// A delivery whose attempt is due, with where it goes
+#[derive(sqlx::FromRow)]
+struct DueDelivery {
+    delivery_id: Uuid,
+    event: String,
+    payload: serde_json::Value,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+/// Why an attempt failed, with the HTTP status when there was an answer
+struct AttemptFailure {
+    response_status: Option<u16>,
+    error: anyhow::Error,
+}
+
+pub struct ReportWebhooks {
+    client: reqwest::Client,
+    /// Prefixes download_url; it is relative, like in API responses, without it
+    api_base_url: Option<String>,
+    max_attempts: i32,
+    /// Wait before the second attempt; each later one waits twice as long as the last
+    first_delay: chrono::Duration,
+}
+
+impl ReportWebhooks {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let number = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+                .max(1)
+        };
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .context("failed to build webhook client")?,
+            api_base_url: std::env::var("REPORT_API_BASE_URL")
+                .ok()
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty()),
+            max_attempts: number("REPORT_WEBHOOK_MAX_ATTEMPTS", 8) as i32,
+            first_delay: chrono::Duration::seconds(number("REPORT_WEBHOOK_RETRY_SECS", 30)),
+        })
+    }
+
+    /// Tell the tenant's webhooks the report was generated and stored
+    pub async fn completed(&self, db: &PgPool, outcome: &ReportOutcome<'_>) {
+        let file = match outcome.report_id {
+            Some(report_id) => {
+                sqlx::query_as::<_, StoredReport>(
+                    r#"
+                    SELECT generated_at, file_hash, file_size, file_expires_at
+                    FROM regulatory_reports_v2
+                    WHERE report_id = $1
+                    "#,
+                )
+                .bind(report_id)
+                .fetch_optional(db)
+                .await
+            }
+            None => Ok(None),
+        };
+        let (generated_at, file_sha256, file_size, file_expires_at) = match file {
+            Ok(file) => file.unwrap_or_default(),
+            Err(e) => {
+                warn!("Failed to load report {:?} for its webhooks: {}", outcome.report_id, e);
+                Default::default()
+            }
+        };
+        let mut report = self.report(outcome, ReportEvent::Completed);
+        report["generated_at"] = serde_json::json!(generated_at);
+        report["file_sha256"] = serde_json::json!(file_sha256);
+        report["file_size"] = serde_json::json!(file_size);
+        report["file_expires_at"] = serde_json::json!(file_expires_at);
+        report["download_url"] = serde_json::json!(outcome.report_id.map(|report_id| self.download_url(report_id)));
+        self.enqueue(db, outcome, ReportEvent::Completed, report).await;
+    }
+
+    /// Tell the tenant's webhooks generating the report failed
+    pub async fn failed(&self, db: &PgPool, outcome: &ReportOutcome<'_>, error: &str) {
+        let mut report = self.report(outcome, ReportEvent::Failed);
+        report["error"] = serde_json::json!(error);
+        self.enqueue(db, outcome, ReportEvent::Failed, report).await;
+    }
+
+    fn download_url(&self, report_id: Uuid) -> String {
+        format!("{}/reports/{}/download", self.api_base_url.as_deref().unwrap_or_default(), report_id)
+    }
+
+    fn report(&self, outcome: &ReportOutcome<'_>, event: ReportEvent) -> serde_json::Value {
+        serde_json::json!({
+            "report_id": outcome.report_id,
+            "tenant_id": outcome.tenant_id,
+            "report_type": outcome.report_type,
+            "format": outcome.format,
+            "status": event.as_str(),
+            "period_start": outcome.period_start,
+            "period_end": outcome.period_end,
+            "schedule_id": outcome.schedule_id,
+        })
+    }
+
+    /// Queue the event for every active webhook of the tenant that wants it; never fails the report
+    async fn enqueue(&self, db: &PgPool, outcome: &ReportOutcome<'_>, event: ReportEvent, report: serde_json::Value) {
+        let payload = serde_json::json!({
+            "event": event.name(),
+            "event_id": Uuid::new_v4(),
+            "occurred_at": Utc::now(),
+            "report": report,
+        });
+        let queued = sqlx::query(
+            r#"
+            INSERT INTO report_webhook_deliveries (webhook_id, tenant_id, report_id, event, payload)
+            SELECT webhook_id, tenant_id, $2, $3, $4
+            FROM report_webhooks
+            WHERE tenant_id = $1 AND is_active AND $3 = ANY(events)
+            "#,
+        )
+        .bind(outcome.tenant_id)
+        .bind(outcome.report_id)
+        .bind(event.as_str())
+        .bind(&payload)
+        .execute(db)
+        .await;
+        match queued {
+            Ok(queued) if queued.rows_affected() > 0 => info!(
+                "Queued {} for {} webhooks of tenant {}",
+                event.name(),
+                queued.rows_affected(),
+                outcome.tenant_id
+            ),
+            Ok(_) => {}
+            Err(e) => error!(
+                "Failed to queue {} webhooks for report {:?} of tenant {}: {}",
+                event.name(),
+                outcome.report_id,
+                outcome.tenant_id,
+                e
+            ),
+        }
+    }
+
+    /// When to try again after `attempts` attempts, or `None` when they are used up
+    fn next_attempt(&self, attempts: i32) -> Option<DateTime<Utc>> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let delay = self
+            .first_delay
+            .num_seconds()
+            .saturating_mul(1 << (attempts - 1).clamp(0, 20))
+            .min(MAX_RETRY_DELAY_SECS);
+        Some(Utc::now() + chrono::Duration::seconds(delay))
+    }
+
+    /// Send a batch of due deliveries; returns how many were attempted
+    async fn send_due(&self, db: &PgPool) -> anyhow::Result<usize> {
+        // Leased, so another replica does not take a delivery while it is being sent
+        let due = sqlx::query_as::<_, DueDelivery>(
+            r#"
+            UPDATE report_webhook_deliveries d
+            SET next_attempt_at = NOW() + INTERVAL '5 minutes'
+            FROM report_webhooks w
+            WHERE w.webhook_id = d.webhook_id
+              AND d.delivery_id IN (
+                SELECT delivery_id FROM report_webhook_deliveries
+                WHERE status IN ('PENDING', 'RETRYING') AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+              )
+            RETURNING d.delivery_id, d.event, d.payload, d.attempts, w.url, w.secret
+            "#,
+        )
+        .bind(BATCH)
+        .fetch_all(db)
+        .await?;
+
+        for delivery in &due {
+            if let Err(e) = self.attempt(db, delivery).await {
+                error!("Failed to record report webhook delivery {}: {}", delivery.delivery_id, e);
+            }
+        }
+        Ok(due.len())
+    }
+
+    async fn attempt(&self, db: &PgPool, delivery: &DueDelivery) -> Result<(), sqlx::Error> {
+        let attempts = delivery.attempts + 1;
+        let outcome = self.send(delivery).await;
+        let (status, response_status, failure_reason, next_attempt_at) = match &outcome {
+            Ok(response_status) => ("DELIVERED", Some(*response_status), None, None),
+            Err(failure) => {
+                let next_attempt_at = self.next_attempt(attempts);
+                let status = if next_attempt_at.is_some() { "RETRYING" } else { "FAILED" };
+                (status, failure.response_status, Some(format!("{:#}", failure.error)), next_attempt_at)
+            }
+        };
+        sqlx::query(
+            r#"
+            UPDATE report_webhook_deliveries
+            SET status = $2, attempts = $3, response_status = $4, failure_reason = $5, next_attempt_at = $6,
+                delivered_at = CASE WHEN $2 = 'DELIVERED' THEN NOW() END
+            WHERE delivery_id = $1
+            "#,
+        )
+        .bind(delivery.delivery_id)
+        .bind(status)
+        .bind(attempts)
+        .bind(response_status.map(i32::from))
+        .bind(&failure_reason)
+        .bind(next_attempt_at)
+        .execute(db)
+        .await?;
+
+        if status != "DELIVERED" {
+            warn!(
+                "Attempt {} of report webhook delivery {} to {} failed ({}): {}",
+                attempts,
+                delivery.delivery_id,
+                delivery.url,
+                status,
+                failure_reason.unwrap_or_default()
+            );
+        }
+        Ok(())
+    }
+
+    /// Post the payload; returns the status of a 2xx answer
+    async fn send(&self, delivery: &DueDelivery) -> Result<u16, AttemptFailure> {
+        let failure = |response_status: Option<u16>, error: anyhow::Error| AttemptFailure { response_status, error };
+        let body = serde_json::to_vec(&delivery.payload).map_err(|e| failure(None, e.into()))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(delivery.secret.as_bytes())
+            .map_err(|_| failure(None, anyhow::anyhow!("invalid webhook secret")))?;
+        mac.update(&body);
+
+        let event = match delivery.event.as_str() {
+            "FAILED" => ReportEvent::Failed,
+            _ => ReportEvent::Completed,
+        };
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-DharmaGuard-Event", event.name())
+            .header("X-DharmaGuard-Delivery", delivery.delivery_id.to_string())
+            .header(
+                "X-DharmaGuard-Signature",
+                format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| failure(None, e.into()))?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(failure(Some(status.as_u16()), anyhow::anyhow!("webhook answered {}", status)));
+        }
+        Ok(status.as_u16())
+    }
+}
+
+/// Send due webhook deliveries in the background
+pub fn spawn_worker(db: PgPool, webhooks: Arc<ReportWebhooks>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            loop {
+                match webhooks.send_due(&db).await {
+                    Ok(attempted) if (attempted as i64) < BATCH => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Report webhook delivery failed: {:#}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", SECRET_PREFIX, hex::encode(bytes))
+}
+
+fn validate_url(url: &str) -> Option<String> {
+    if url.len() > MAX_URL_LENGTH {
+        return Some(format!("url may be at most {} characters", MAX_URL_LENGTH));
+    }
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "https" && parsed.host_str().is_some() => None,
+        _ => Some("url must be an https URL".to_string()),
+    }
+}
+
+/// Subscribed events in a stable order, without repeats
+fn event_names(events: &[ReportEvent]) -> Vec<String> {
+    [ReportEvent::Completed, ReportEvent::Failed]
+        .into_iter()
+        .filter(|event| events.contains(event))
+        .map(|event| event.as_str().to_string())
+        .collect()
+}
+
+pub async fn list(db: &PgPool, tenant: &TenantContext) -> Result<Vec<ReportWebhook>, sqlx::Error> {
+    sqlx::query_as::<_, ReportWebhook>(&format!(
+        "SELECT {} FROM report_webhooks WHERE tenant_id = $1 ORDER BY created_at",
+        WEBHOOK_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .fetch_all(db)
+    .await
+}
+
+pub async fn get(db: &PgPool, tenant: &TenantContext, webhook_id: Uuid) -> Result<ReportWebhook, WebhookError> {
+    sqlx::query_as::<_, ReportWebhook>(&format!(
+        "SELECT {} FROM report_webhooks WHERE tenant_id = $1 AND webhook_id = $2",
+        WEBHOOK_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(webhook_id)
+    .fetch_optional(db)
+    .await?
+    .ok_or(WebhookError::NotFound)
+}
+
+pub async fn create(
+    db: &PgPool,
+    tenant: &TenantContext,
+    request: CreateWebhookRequest,
+) -> Result<WebhookWithSecret, WebhookError> {
+    let url = request.url.trim().to_string();
+    let events = event_names(request.events.as_deref().unwrap_or(&[ReportEvent::Completed, ReportEvent::Failed]));
+    let mut errors: Vec<String> = validate_url(&url).into_iter().collect();
+    if events.is_empty() {
+        errors.push("events may not be empty".to_string());
+    }
+    if !errors.is_empty() {
+        return Err(WebhookError::Invalid(errors));
+    }
+
+    let secret = generate_secret();
+    let webhook = sqlx::query_as::<_, ReportWebhook>(&format!(
+        r#"
+        INSERT INTO report_webhooks (tenant_id, url, secret, events, description, is_active, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING {}
+        "#,
+        WEBHOOK_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(&url)
+    .bind(&secret)
+    .bind(&events)
+    .bind(request.description)
+    .bind(request.is_active.unwrap_or(true))
+    .bind(request.created_by)
+    .fetch_one(db)
+    .await?;
+    Ok(WebhookWithSecret { webhook, secret })
+}
+
+pub async fn update(
+    db: &PgPool,
+    tenant: &TenantContext,
+    webhook_id: Uuid,
+    request: UpdateWebhookRequest,
+) -> Result<ReportWebhook, WebhookError> {
+    let url = request.url.as_deref().map(str::trim);
+    let events = request.events.as_deref().map(event_names);
+    let mut errors: Vec<String> = url.and_then(validate_url).into_iter().collect();
+    if events.as_ref().is_some_and(Vec::is_empty) {
+        errors.push("events may not be empty".to_string());
+    }
+    if !errors.is_empty() {
+        return Err(WebhookError::Invalid(errors));
+    }
+
+    sqlx::query_as::<_, ReportWebhook>(&format!(
+        r#"
+        UPDATE report_webhooks
+        SET url = COALESCE($3, url), events = COALESCE($4, events), description = COALESCE($5, description),
+            is_active = COALESCE($6, is_active), updated_at = NOW()
+        WHERE tenant_id = $1 AND webhook_id = $2
+        RETURNING {}
+        "#,
+        WEBHOOK_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(webhook_id)
+    .bind(url)
+    .bind(events)
+    .bind(request.description)
+    .bind(request.is_active)
+    .fetch_optional(db)
+    .await?
+    .ok_or(WebhookError::NotFound)
+}
+
+/// Replace the secret; callbacks not yet sent are signed with the new one
+pub async fn rotate_secret(
+    db: &PgPool,
+    tenant: &TenantContext,
+    webhook_id: Uuid,
+) -> Result<WebhookWithSecret, WebhookError> {
+    let secret = generate_secret();
+    let webhook = sqlx::query_as::<_, ReportWebhook>(&format!(
+        r#"
+        UPDATE report_webhooks SET secret = $3, updated_at = NOW()
+        WHERE tenant_id = $1 AND webhook_id = $2
+        RETURNING {}
+        "#,
+        WEBHOOK_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(webhook_id)
+    .bind(&secret)
+    .fetch_optional(db)
+    .await?
+    .ok_or(WebhookError::NotFound)?;
+    Ok(WebhookWithSecret { webhook, secret })
+}
+
+/// Deliveries of the webhook, and those not yet sent, go with it
+pub async fn delete(db: &PgPool, tenant: &TenantContext, webhook_id: Uuid) -> Result<(), WebhookError> {
+    let deleted = sqlx::query("DELETE FROM report_webhooks WHERE tenant_id = $1 AND webhook_id = $2")
+        .bind(tenant.tenant_id())
+        .bind(webhook_id)
+        .execute(db)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(WebhookError::NotFound);
+    }
+    Ok(())
+}
+
+/// The latest deliveries of a webhook
+pub async fn list_deliveries(
+    db: &PgPool,
+    tenant: &TenantContext,
+    webhook_id: Uuid,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, WebhookError> {
+    get(db, tenant, webhook_id).await?;
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(&format!(
+        r#"
+        SELECT {} FROM report_webhook_deliveries
+        WHERE tenant_id = $1 AND webhook_id = $2
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+        DELIVERY_COLUMNS
+    ))
+    .bind(tenant.tenant_id())
+    .bind(webhook_id)
+    .bind(limit)
+    .fetch_all(db)
+    .await?;
+    Ok(deliveries)
+}
@@ -0,0 +1,339 @@
+//! XBRL instances for regulatory filings
+//!
+//! SEBI takes several filings only as XBRL. A taxonomy maps each report type
+//! it covers to concepts: the field of the report data a concept reports, its
+//! type (`count`, `monetary` in the taxonomy's currency, or `decimal`), the
+//! precision it is reported at, and whether it is required and in what range
+//! it must lie. A concept with an `axis` reports a map of the data, such as
+//! alerts per pattern, as one fact per key, each in a context whose typed
+//! dimension member is the key.
+//!
+//! The built-in taxonomy (`taxonomies/dharmaguard-compliance.json`) covers
+//! COMPLIANCE_REPORT and TRADING_SUMMARY under the DharmaGuard namespace.
+//! REPORT_XBRL_TAXONOMY names a file in the same layout to use instead, e.g.
+//! a mapping onto the taxonomy SEBI publishes for a filing.
+//!
+//! Reports are validated against the taxonomy before an instance is written:
+//! required concepts must be present, counts whole and every value numeric
+//! and within its range. The entity of every context is the tenant's SEBI
+//! registration number, so tenants without one cannot file XBRL.
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use crate::render::{escape_xml as escape, ReportMeta};
+
+const BUILT_IN: &str = include_str!("../taxonomies/dharmaguard-compliance.json");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConceptType {
+    Count,
+    Monetary,
+    Decimal,
+}
+
+/// A typed dimension whose members are the keys of a map in the report data
+#[derive(Debug, Clone, Deserialize)]
+pub struct Axis {
+    pub dimension: String,
+    pub domain: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Concept {
+    pub name: String,
+    /// Dotted path of the reported value in the report data
+    pub field: String,
+    #[serde(rename = "type")]
+    pub concept_type: ConceptType,
+    /// Defaults to 0 for counts, 2 for monetary values and 4 otherwise
+    pub decimals: Option<u32>,
+    #[serde(default)]
+    pub required: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub axis: Option<Axis>,
+}
+
+impl Concept {
+    fn decimals(&self) -> u32 {
+        self.decimals.unwrap_or(match self.concept_type {
+            ConceptType::Count => 0,
+            ConceptType::Monetary => 2,
+            ConceptType::Decimal => 4,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Taxonomy {
+    pub namespace: String,
+    pub prefix: String,
+    pub schema_ref: String,
+    /// Scheme of the entity identifiers, which are SEBI registration numbers
+    pub entity_scheme: String,
+    /// ISO 4217 code of monetary concepts
+    pub currency: String,
+    /// Concepts by report type
+    pub reports: HashMap<String, Vec<Concept>>,
+}
+
+/// Why a report cannot be filed under the taxonomy
+#[derive(Debug, thiserror::Error)]
+#[error("report does not conform to the XBRL taxonomy: {}", .0.join("; "))]
+pub struct Nonconforming(pub Vec<String>);
+
+/// XML names are kept to letters, digits, `_`, `-` and `.` after a letter or `_`
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+impl Taxonomy {
+    /// REPORT_XBRL_TAXONOMY when set, the built-in taxonomy otherwise
+    pub fn from_env() -> anyhow::Result<Self> {
+        let (source, taxonomy) = match std::env::var("REPORT_XBRL_TAXONOMY").ok().filter(|path| !path.is_empty()) {
+            Some(path) => {
+                let taxonomy = std::fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read XBRL taxonomy {}", path))?;
+                (path, taxonomy)
+            }
+            None => ("built-in taxonomy".to_string(), BUILT_IN.to_string()),
+        };
+        let taxonomy: Self =
+            serde_json::from_str(&taxonomy).with_context(|| format!("failed to parse XBRL {}", source))?;
+        taxonomy.check().with_context(|| format!("XBRL {} is unusable", source))?;
+        Ok(taxonomy)
+    }
+
+    fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(is_name(&self.prefix), "prefix '{}' is not an XML name", self.prefix);
+        anyhow::ensure!(
+            self.currency.len() == 3 && self.currency.chars().all(|c| c.is_ascii_uppercase()),
+            "currency '{}' is not an ISO 4217 code",
+            self.currency
+        );
+        for (report_type, concepts) in &self.reports {
+            let mut names = HashSet::new();
+            for concept in concepts {
+                anyhow::ensure!(
+                    is_name(&concept.name),
+                    "{} concept '{}' is not an XML name",
+                    report_type,
+                    concept.name
+                );
+                anyhow::ensure!(
+                    names.insert(&concept.name),
+                    "{} maps concept {} twice",
+                    report_type,
+                    concept.name
+                );
+                if let Some(axis) = &concept.axis {
+                    anyhow::ensure!(
+                        is_name(&axis.dimension) && is_name(&axis.domain),
+                        "axis of {} concept {} is not made of XML names",
+                        report_type,
+                        concept.name
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn covers(&self, report_type: &str) -> bool {
+        self.reports.contains_key(report_type)
+    }
+
+    /// The report as an XBRL instance, once it is validated against the taxonomy
+    pub fn instance(&self, meta: &ReportMeta, data: &Value) -> anyhow::Result<Vec<u8>> {
+        let concepts = self
+            .reports
+            .get(&meta.report_type)
+            .ok_or_else(|| Nonconforming(vec![format!("the taxonomy does not cover {} reports", meta.report_type)]))?;
+        let facts = self.facts(meta, concepts, data)?;
+        Ok(self.write(meta, &facts).into_bytes())
+    }
+
+    /// Every fact of the report, or everything that keeps it from conforming
+    fn facts<'a>(
+        &self,
+        meta: &ReportMeta,
+        concepts: &'a [Concept],
+        data: &Value,
+    ) -> Result<Vec<Fact<'a>>, Nonconforming> {
+        let mut problems = Vec::new();
+        if meta.registration_no.as_deref().map_or(true, str::is_empty) {
+            problems.push("the tenant has no SEBI registration number".to_string());
+        }
+        let mut facts = Vec::new();
+        for concept in concepts {
+            let Some(value) = lookup(data, &concept.field).filter(|value| !value.is_null()) else {
+                if concept.required {
+                    problems.push(format!("{} requires {}", concept.name, concept.field));
+                }
+                continue;
+            };
+            let values: Vec<(Option<String>, &Value)> = match (&concept.axis, value) {
+                (None, value) => vec![(None, value)],
+                (Some(axis), Value::Object(members)) => {
+                    if members.contains_key("") {
+                        problems.push(format!("{} has an empty {} member", concept.name, axis.dimension));
+                    }
+                    let mut members: Vec<_> = members
+                        .iter()
+                        .filter(|(key, _)| !key.is_empty())
+                        .map(|(key, value)| (Some(key.clone()), value))
+                        .collect();
+                    members.sort_by(|a, b| a.0.cmp(&b.0));
+                    members
+                }
+                (Some(_), _) => {
+                    problems.push(format!("{} needs {} to be a map", concept.name, concept.field));
+                    continue;
+                }
+            };
+            for (member, value) in values {
+                let at = match &member {
+                    Some(member) => format!("{} [{}]", concept.name, member),
+                    None => concept.name.clone(),
+                };
+                let Some(number) = value.as_f64() else {
+                    problems.push(format!("{} is not a number", at));
+                    continue;
+                };
+                if concept.concept_type == ConceptType::Count && number.fract() != 0.0 {
+                    problems.push(format!("{} must be a whole number, not {}", at, number));
+                }
+                if concept.min.is_some_and(|min| number < min) || concept.max.is_some_and(|max| number > max) {
+                    problems.push(format!("{} is out of range at {}", at, number));
+                }
+                facts.push(Fact { concept, member, number });
+            }
+        }
+        if problems.is_empty() {
+            Ok(facts)
+        } else {
+            Err(Nonconforming(problems))
+        }
+    }
+
+    fn write(&self, meta: &ReportMeta, facts: &[Fact]) -> String {
+        let prefix = &self.prefix;
+        let identifier = format!(
+            "<xbrli:identifier scheme=\"{}\">{}</xbrli:identifier>",
+            escape(&self.entity_scheme),
+            escape(meta.registration_no.as_deref().unwrap_or_default())
+        );
+        let period = format!(
+            "<xbrli:period><xbrli:startDate>{}</xbrli:startDate><xbrli:endDate>{}</xbrli:endDate></xbrli:period>",
+            meta.period_start, meta.period_end
+        );
+
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(&format!(
+            concat!(
+                "<xbrli:xbrl xmlns:xbrli=\"http://www.xbrl.org/2003/instance\"",
+                " xmlns:link=\"http://www.xbrl.org/2003/linkbase\"",
+                " xmlns:xlink=\"http://www.w3.org/1999/xlink\"",
+                " xmlns:iso4217=\"http://www.xbrl.org/2003/iso4217\"",
+                " xmlns:xbrldi=\"http://xbrl.org/2006/xbrldi\"",
+                " xmlns:{}=\"{}\">\n"
+            ),
+            prefix,
+            escape(&self.namespace)
+        ));
+        out.push_str(&format!(
+            "  <!-- Report {} of tenant {}, generated {} -->\n",
+            meta.report_id,
+            meta.tenant_id,
+            meta.generated_at.to_rfc3339()
+        ));
+        out.push_str(&format!(
+            "  <link:schemaRef xlink:type=\"simple\" xlink:href=\"{}\"/>\n",
+            escape(&self.schema_ref)
+        ));
+        out.push_str(&format!(
+            "  <xbrli:context id=\"period\"><xbrli:entity>{}</xbrli:entity>{}</xbrli:context>\n",
+            identifier, period
+        ));
+
+        // One context per dimension member, numbered in the order facts need them
+        let mut contexts: HashMap<(&str, &str), String> = HashMap::new();
+        let mut fact_contexts = Vec::with_capacity(facts.len());
+        for fact in facts {
+            let context = match (&fact.concept.axis, &fact.member) {
+                (Some(axis), Some(member)) => {
+                    if let Some(id) = contexts.get(&(axis.dimension.as_str(), member.as_str())) {
+                        id.clone()
+                    } else {
+                        let id = format!("member{}", contexts.len() + 1);
+                        out.push_str(&format!(
+                            concat!(
+                                "  <xbrli:context id=\"{}\"><xbrli:entity>{}<xbrli:segment>",
+                                "<xbrldi:typedMember dimension=\"{}:{}\"><{}:{}>{}</{}:{}></xbrldi:typedMember>",
+                                "</xbrli:segment></xbrli:entity>{}</xbrli:context>\n"
+                            ),
+                            id,
+                            identifier,
+                            prefix,
+                            axis.dimension,
+                            prefix,
+                            axis.domain,
+                            escape(member),
+                            prefix,
+                            axis.domain,
+                            period
+                        ));
+                        contexts.insert((axis.dimension.as_str(), member.as_str()), id.clone());
+                        id
+                    }
+                }
+                _ => "period".to_string(),
+            };
+            fact_contexts.push(context);
+        }
+
+        out.push_str("  <xbrli:unit id=\"pure\"><xbrli:measure>xbrli:pure</xbrli:measure></xbrli:unit>\n");
+        out.push_str(&format!(
+            "  <xbrli:unit id=\"{}\"><xbrli:measure>iso4217:{}</xbrli:measure></xbrli:unit>\n",
+            self.currency, self.currency
+        ));
+        for (fact, context) in facts.iter().zip(fact_contexts) {
+            let unit = match fact.concept.concept_type {
+                ConceptType::Monetary => self.currency.as_str(),
+                ConceptType::Count | ConceptType::Decimal => "pure",
+            };
+            let decimals = fact.concept.decimals();
+            out.push_str(&format!(
+                "  <{}:{} contextRef=\"{}\" unitRef=\"{}\" decimals=\"{}\">{:.*}</{}:{}>\n",
+                prefix,
+                fact.concept.name,
+                context,
+                unit,
+                decimals,
+                decimals as usize,
+                fact.number,
+                prefix,
+                fact.concept.name
+            ));
+        }
+        out.push_str("</xbrli:xbrl>\n");
+        out
+    }
+}
+
+struct Fact<'a> {
+    concept: &'a Concept,
+    /// Member of the concept's axis the fact is reported for
+    member: Option<String>,
+    number: f64,
+}
+
+fn lookup<'a>(data: &'a Value, field: &str) -> Option<&'a Value> {
+    field.split('.').try_fold(data, |value, key| value.get(key))
+}
@@ -0,0 +1,264 @@
+//! Incident and maintenance banners
+//!
+//! Operators post a banner with its impact and the components it affects and
+//! update it as the incident progresses. Open banners, and those resolved in
+//! the last day, are shown on the public page; an impact on a component
+//! lowers that component's state even while its probes still pass.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::probe::{self, State};
+
+pub const IMPACTS: &[&str] = &["NONE", "DEGRADED", "OUTAGE"];
+pub const STATUSES: &[&str] = &["INVESTIGATING", "IDENTIFIED", "MONITORING", "RESOLVED"];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Incident {
+    pub incident_id: Uuid,
+    pub title: String,
+    pub message: String,
+    pub impact: String,
+    pub status: String,
+    pub components: Vec<String>,
+    pub posted_by: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl Incident {
+    /// The state the incident imposes on its components while open
+    pub fn state(&self) -> Option<State> {
+        if self.resolved_at.is_some() {
+            return None;
+        }
+        match self.impact.as_str() {
+            "OUTAGE" => Some(State::Outage),
+            "DEGRADED" => Some(State::Degraded),
+            _ => None,
+        }
+    }
+}
+
+/// An incident as published; who posted it stays internal
+#[derive(Serialize, Debug, Clone)]
+pub struct Banner {
+    pub title: String,
+    pub message: String,
+    pub impact: String,
+    pub status: String,
+    pub components: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl From<&Incident> for Banner {
+    fn from(incident: &Incident) -> Self {
+        Self {
+            title: incident.title.clone(),
+            message: incident.message.clone(),
+            impact: incident.impact.clone(),
+            status: incident.status.clone(),
+            components: incident.components.clone(),
+            started_at: incident.started_at,
+            updated_at: incident.updated_at,
+            resolved_at: incident.resolved_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IncidentRequest {
+    pub title: String,
+    pub message: String,
+    pub impact: String,
+    #[serde(default)]
+    pub components: Vec<String>,
+    pub posted_by: Option<String>,
+}
+
+/// Fields left out keep their current value
+#[derive(Deserialize)]
+pub struct IncidentUpdate {
+    pub title: Option<String>,
+    pub message: Option<String>,
+    pub impact: Option<String>,
+    pub status: Option<String>,
+    pub components: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct IncidentListParams {
+    #[serde(default)]
+    pub include_resolved: bool,
+    pub limit: Option<i64>,
+}
+
+fn check_fields(
+    errors: &mut Vec<String>,
+    title: Option<&str>,
+    message: Option<&str>,
+    impact: Option<&str>,
+    status: Option<&str>,
+    components: Option<&[String]>,
+) {
+    if title.map_or(false, |title| title.trim().is_empty() || title.len() > 200) {
+        errors.push("title must be 1-200 characters".to_string());
+    }
+    if message.map_or(false, |message| message.trim().is_empty() || message.len() > 5000) {
+        errors.push("message must be 1-5000 characters".to_string());
+    }
+    if impact.map_or(false, |impact| !IMPACTS.contains(&impact)) {
+        errors.push(format!("impact must be one of {}", IMPACTS.join(", ")));
+    }
+    if status.map_or(false, |status| !STATUSES.contains(&status)) {
+        errors.push(format!("status must be one of {}", STATUSES.join(", ")));
+    }
+    for component in components.unwrap_or_default() {
+        if !probe::is_component(component) {
+            errors.push(format!("unknown component '{}'", component));
+        }
+    }
+}
+
+impl IncidentRequest {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        check_fields(
+            &mut errors,
+            Some(&self.title),
+            Some(&self.message),
+            Some(&self.impact),
+            None,
+            Some(&self.components),
+        );
+        if self.posted_by.as_ref().map_or(false, |name| name.len() > 100) {
+            errors.push("posted_by must be at most 100 characters".to_string());
+        }
+        errors
+    }
+}
+
+impl IncidentUpdate {
+    pub fn validate(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        check_fields(
+            &mut errors,
+            self.title.as_deref(),
+            self.message.as_deref(),
+            self.impact.as_deref(),
+            self.status.as_deref(),
+            self.components.as_deref(),
+        );
+        errors
+    }
+}
+
+pub async fn list(db: &PgPool, params: &IncidentListParams) -> anyhow::Result<Vec<Incident>> {
+    let incidents = sqlx::query_as!(
+        Incident,
+        r#"
+        SELECT incident_id, title, message, impact, status, components, posted_by,
+               started_at, updated_at, resolved_at
+        FROM status_incidents
+        WHERE $1 OR resolved_at IS NULL
+        ORDER BY started_at DESC
+        LIMIT $2
+        "#,
+        params.include_resolved,
+        params.limit.unwrap_or(50).clamp(1, 500)
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(incidents)
+}
+
+pub async fn get(db: &PgPool, incident_id: Uuid) -> anyhow::Result<Option<Incident>> {
+    let incident = sqlx::query_as!(
+        Incident,
+        r#"
+        SELECT incident_id, title, message, impact, status, components, posted_by,
+               started_at, updated_at, resolved_at
+        FROM status_incidents
+        WHERE incident_id = $1
+        "#,
+        incident_id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(incident)
+}
+
+/// Open incidents and those resolved in the last day, for the public page
+pub async fn published(db: &PgPool) -> anyhow::Result<Vec<Incident>> {
+    let incidents = sqlx::query_as!(
+        Incident,
+        r#"
+        SELECT incident_id, title, message, impact, status, components, posted_by,
+               started_at, updated_at, resolved_at
+        FROM status_incidents
+        WHERE resolved_at IS NULL OR resolved_at > NOW() - INTERVAL '1 day'
+        ORDER BY resolved_at IS NOT NULL, started_at DESC
+        LIMIT 20
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(incidents)
+}
+
+pub async fn create(db: &PgPool, request: &IncidentRequest) -> anyhow::Result<Incident> {
+    let incident = sqlx::query_as!(
+        Incident,
+        r#"
+        INSERT INTO status_incidents (title, message, impact, components, posted_by)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING incident_id, title, message, impact, status, components, posted_by,
+                  started_at, updated_at, resolved_at
+        "#,
+        request.title.trim(),
+        request.message.trim(),
+        request.impact,
+        &request.components,
+        request.posted_by
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(incident)
+}
+
+/// Apply an update; moving to RESOLVED stamps resolved_at and moving away clears it
+pub async fn update(db: &PgPool, incident_id: Uuid, update: &IncidentUpdate) -> anyhow::Result<Option<Incident>> {
+    let incident = sqlx::query_as!(
+        Incident,
+        r#"
+        UPDATE status_incidents
+        SET title = COALESCE($2, title),
+            message = COALESCE($3, message),
+            impact = COALESCE($4, impact),
+            components = COALESCE($5, components),
+            status = COALESCE($6, status),
+            resolved_at = CASE
+                WHEN COALESCE($6, status) <> 'RESOLVED' THEN NULL
+                ELSE COALESCE(resolved_at, NOW())
+            END,
+            updated_at = NOW()
+        WHERE incident_id = $1
+        RETURNING incident_id, title, message, impact, status, components, posted_by,
+                  started_at, updated_at, resolved_at
+        "#,
+        incident_id,
+        update.title.as_deref().map(str::trim),
+        update.message.as_deref().map(str::trim),
+        update.impact,
+        update.components.as_deref(),
+        update.status
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(incident)
+}
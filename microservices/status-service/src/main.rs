@@ -0,0 +1,264 @@
+//! DharmaGuard Status Service
+//! Public operations status page aggregating service health, SLOs, queue depth and incident banners
+
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use dharmaguard_common::pool::{self, Admission, PoolSettings};
+use dharmaguard_common::startup::{self, Startup};
+
+mod incidents;
+mod page;
+mod probe;
+mod queues;
+mod status;
+
+use crate::incidents::{Incident, IncidentListParams, IncidentRequest, IncidentUpdate};
+use crate::status::{Monitor, StatusPage, StatusSettings};
+
+/// Shared migrations the service depends on, each with a relation it creates
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("031_status_page", "status_probes"),
+];
+
+/// How long clients and proxies may reuse the public page
+const PAGE_MAX_AGE_SECS: u32 = 15;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: PgPool,
+    pub monitor: Arc<Monitor>,
+    /// SHA-256 of STATUS_ADMIN_TOKEN; incident management is off without it
+    pub admin_token: Option<[u8; 32]>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let database_url = std::env::var("DATABASE_URL")
+        .expect("DATABASE_URL must be set");
+
+    dharmaguard_common::metrics::install();
+    let pool_settings = PoolSettings::from_env();
+    let pool = pool_settings.connect(&database_url).await?;
+    let admission = Admission::new("status", pool_settings);
+    admission.spawn_autoscaler(pool.clone());
+
+    let admin_token = match std::env::var("STATUS_ADMIN_TOKEN") {
+        Ok(token) if token.len() >= 32 => Some(Sha256::digest(token.as_bytes()).into()),
+        Ok(_) => {
+            warn!("STATUS_ADMIN_TOKEN must be at least 32 characters; incident management is disabled");
+            None
+        }
+        Err(_) => {
+            warn!("STATUS_ADMIN_TOKEN is not set; incident management is disabled");
+            None
+        }
+    };
+
+    let monitor = Monitor::new(pool.clone(), StatusSettings::from_env())?;
+
+    let app_state = AppState {
+        db: pool,
+        monitor: monitor.clone(),
+        admin_token,
+    };
+
+    let api_v1 = Router::new()
+        .route("/incidents", get(list_incidents).post(create_incident))
+        .route("/incidents/:incident_id", get(get_incident).patch(update_incident))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), require_admin));
+
+    // API routes answer 503 until these pass; the page, /health, /ready and /metrics answer from the start
+    let startup = Startup::new("status").migrations(app_state.db.clone(), MIGRATIONS);
+    let readiness = startup.readiness();
+
+    let app = Router::new()
+        .nest("/api/v1", api_v1)
+        .route_layer(middleware::from_fn_with_state(admission, pool::admit))
+        .route_layer(middleware::from_fn_with_state(readiness.clone(), startup::gate))
+        .route("/status", get(status_json))
+        .route("/status.html", get(status_html))
+        .route("/health", get(health_check))
+        .merge(startup::router(readiness.clone()))
+        .merge(dharmaguard_common::metrics::router())
+        .with_state(app_state);
+
+    // Probing starts once status_probes is known to exist
+    tokio::spawn(async move {
+        readiness.wait().await;
+        status::spawn_worker(monitor);
+    });
+
+    let listener = TcpListener::bind("0.0.0.0:8086").await?;
+    info!("Status service listening on port 8086");
+
+    startup
+        .serve(async { axum::serve(listener, app).await.map_err(anyhow::Error::from) })
+        .await
+}
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(serde_json::json!({"status": "healthy", "service": "status"}))
+}
+
+/// Incident management is for operators holding STATUS_ADMIN_TOKEN
+async fn require_admin(State(state): State<AppState>, headers: HeaderMap, request: Request, next: Next) -> Response {
+    let Some(expected) = state.admin_token else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": "incident management is not configured"})),
+        )
+            .into_response();
+    };
+    let presented: Option<[u8; 32]> = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| Sha256::digest(token.trim().as_bytes()).into());
+    if presented == Some(expected) {
+        next.run(request).await
+    } else {
+        let mut response =
+            (StatusCode::UNAUTHORIZED, Json(serde_json::json!({"error": "a valid admin token is required"}))).into_response();
+        response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        response
+    }
+}
+
+/// The snapshot with caching headers, or 503 until the first probe round
+fn current_page(state: &AppState) -> Result<Arc<StatusPage>, Response> {
+    state.monitor.page().ok_or_else(|| {
+        let mut response = (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({"error": "status is not available yet, retry later"})),
+        )
+            .into_response();
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(PAGE_MAX_AGE_SECS));
+        response
+    })
+}
+
+fn cacheable(mut response: Response) -> Response {
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("public, max-age={}", PAGE_MAX_AGE_SECS)).expect("valid header value"),
+    );
+    response
+}
+
+async fn status_json(State(state): State<AppState>) -> Response {
+    match current_page(&state) {
+        Ok(page) => cacheable(Json(page.as_ref()).into_response()),
+        Err(response) => response,
+    }
+}
+
+async fn status_html(State(state): State<AppState>) -> Response {
+    match current_page(&state) {
+        Ok(page) => cacheable(Html(page::render(&page)).into_response()),
+        Err(response) => response,
+    }
+}
+
+/// Publish banner changes now instead of on the next probe round
+async fn republish(monitor: &Monitor) {
+    if let Err(e) = monitor.refresh().await {
+        warn!("Failed to refresh the status page after an incident change: {:#}", e);
+    }
+}
+
+async fn list_incidents(
+    Query(params): Query<IncidentListParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Incident>>, StatusCode> {
+    match incidents::list(&state.db, &params).await {
+        Ok(incidents) => Ok(Json(incidents)),
+        Err(e) => {
+            error!("Failed to list status incidents: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn get_incident(
+    Path(incident_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Incident>, StatusCode> {
+    match incidents::get(&state.db, incident_id).await {
+        Ok(Some(incident)) => Ok(Json(incident)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load status incident {}: {}", incident_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn create_incident(
+    State(state): State<AppState>,
+    Json(request): Json<IncidentRequest>,
+) -> Result<Json<Incident>, (StatusCode, Json<serde_json::Value>)> {
+    let errors = request.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+
+    match incidents::create(&state.db, &request).await {
+        Ok(incident) => {
+            info!("Posted status incident {} ({}): {}", incident.incident_id, incident.impact, incident.title);
+            republish(&state.monitor).await;
+            Ok(Json(incident))
+        }
+        Err(e) => {
+            error!("Failed to post status incident: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to post incident"})),
+            ))
+        }
+    }
+}
+
+/// Update an incident's text, impact or progress; status RESOLVED closes it
+async fn update_incident(
+    Path(incident_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(update): Json<IncidentUpdate>,
+) -> Result<Json<Incident>, (StatusCode, Json<serde_json::Value>)> {
+    let errors = update.validate();
+    if !errors.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(serde_json::json!({"errors": errors}))));
+    }
+
+    match incidents::update(&state.db, incident_id, &update).await {
+        Ok(Some(incident)) => {
+            info!("Updated status incident {}: {}", incident_id, incident.status);
+            republish(&state.monitor).await;
+            Ok(Json(incident))
+        }
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "incident not found"})),
+        )),
+        Err(e) => {
+            error!("Failed to update status incident {}: {}", incident_id, e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({"error": "failed to update incident"})),
+            ))
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! HTML rendering of the status snapshot
+//!
+//! A single self-contained page with no scripts or external assets, so it
+//! renders wherever an operations team opens it. Banner text is written by
+//! operators and is escaped like any other input.
+
+use chrono::{DateTime, Utc};
+use std::fmt::Write;
+
+use crate::probe::State;
+use crate::queues::QueueState;
+use crate::status::StatusPage;
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn colour(state: State) -> &'static str {
+    match state {
+        State::Operational => "#1a7f37",
+        State::Degraded => "#bf8700",
+        State::Outage => "#cf222e",
+    }
+}
+
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn optional<T: std::fmt::Display>(value: Option<T>, suffix: &str) -> String {
+    value.map_or_else(|| "&mdash;".to_string(), |value| format!("{}{}", value, suffix))
+}
+
+pub fn render(page: &StatusPage) -> String {
+    let mut html = String::new();
+    // Writing to a String cannot fail
+    let _ = write!(
+        html,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta http-equiv="refresh" content="60">
+<title>DharmaGuard status</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 880px; margin: 2rem auto; padding: 0 1rem; color: #1f2328; }}
+table {{ width: 100%; border-collapse: collapse; margin-bottom: 2rem; }}
+th, td {{ text-align: left; padding: .5rem; border-bottom: 1px solid #d0d7de; }}
+.overall {{ padding: 1rem; border-radius: 6px; color: #fff; font-size: 1.25rem; }}
+.banner {{ border-left: 4px solid; padding: .5rem 1rem; margin: 1rem 0; background: #f6f8fa; }}
+.muted {{ color: #656d76; font-size: .875rem; }}
+</style>
+</head>
+<body>
+<h1>DharmaGuard status</h1>
+<p class="overall" style="background: {}">{}</p>
+"#,
+        colour(page.state),
+        page.state.label()
+    );
+
+    for incident in &page.incidents {
+        let border = match incident.impact.as_str() {
+            "OUTAGE" => colour(State::Outage),
+            "DEGRADED" => colour(State::Degraded),
+            _ => "#0969da",
+        };
+        let affected = if incident.components.is_empty() {
+            "all services".to_string()
+        } else {
+            incident.components.join(", ")
+        };
+        let _ = write!(
+            html,
+            r#"<div class="banner" style="border-color: {}">
+<strong>{}</strong> <span class="muted">{} &middot; {}</span>
+<p>{}</p>
+<p class="muted">Started {} &middot; updated {}{}</p>
+</div>
+"#,
+            border,
+            escape(&incident.title),
+            escape(&incident.status),
+            escape(&affected),
+            escape(&incident.message),
+            timestamp(&incident.started_at),
+            timestamp(&incident.updated_at),
+            incident
+                .resolved_at
+                .map(|at| format!(" &middot; resolved {}", timestamp(&at)))
+                .unwrap_or_default()
+        );
+    }
+
+    html.push_str(
+        "<h2>Services</h2>\n<table>\n<tr><th>Service</th><th>State</th><th>Availability</th>\
+         <th>Error budget left</th><th>p95 latency</th></tr>\n",
+    );
+    for component in &page.components {
+        let _ = writeln!(
+            html,
+            r#"<tr><td>{}</td><td style="color: {}">{}</td><td>{} <span class="muted">/ {}% over {}d</span></td><td>{}</td><td>{}</td></tr>"#,
+            component.name,
+            colour(component.state),
+            component.state.label(),
+            optional(component.slo.availability_percent, "%"),
+            component.slo.target_percent,
+            component.slo.window_days,
+            optional(component.slo.error_budget_remaining_percent, "%"),
+            optional(component.slo.latency_p95_ms, " ms")
+        );
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Queues</h2>\n<table>\n<tr><th>Queue</th><th>State</th><th>Waiting</th><th>Oldest</th></tr>\n");
+    for queue in &page.queues {
+        let (label, state) = match queue.state {
+            QueueState::Normal => ("Normal", State::Operational),
+            QueueState::Backlogged => ("Backlogged", State::Degraded),
+            QueueState::Unknown => ("Unknown", State::Degraded),
+        };
+        let _ = writeln!(
+            html,
+            r#"<tr><td>{}</td><td style="color: {}">{}</td><td>{}</td><td>{}</td></tr>"#,
+            queue.name,
+            colour(state),
+            label,
+            optional(queue.depth, ""),
+            optional(queue.oldest_age_seconds, " s")
+        );
+    }
+    let _ = write!(
+        html,
+        "</table>\n<p class=\"muted\">Updated {}</p>\n</body>\n</html>\n",
+        timestamp(&page.generated_at)
+    );
+    html
+}
@@ -0,0 +1,140 @@
+//! Readiness probes of the platform services
+//!
+//! Each round asks every component's `/ready` whether it is serving and
+//! records the outcome in status_probes, the source of the availability SLOs.
+//! Only up/down and latency are kept: check names and errors in the /ready
+//! body describe internals and never reach the public page.
+
+use metrics::gauge;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use tracing::warn;
+
+/// Services on the page, with the variable holding each one's base URL
+const COMPONENTS: &[(&str, &str, &str)] = &[
+    ("user", "USER_SERVICE_URL", "http://user-service:8081"),
+    ("compliance", "COMPLIANCE_SERVICE_URL", "http://compliance-service:8082"),
+    ("reporting", "REPORTING_SERVICE_URL", "http://reporting-service:8083"),
+    ("audit", "AUDIT_SERVICE_URL", "http://audit-service:8084"),
+    ("notification", "NOTIFICATION_SERVICE_URL", "http://notification-service:8085"),
+];
+
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub name: &'static str,
+    pub url: String,
+}
+
+pub fn components_from_env() -> Vec<Component> {
+    COMPONENTS
+        .iter()
+        .map(|(name, variable, default)| Component {
+            name,
+            url: std::env::var(variable)
+                .unwrap_or_else(|_| default.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+        })
+        .collect()
+}
+
+pub fn is_component(name: &str) -> bool {
+    COMPONENTS.iter().any(|(component, _, _)| *component == name)
+}
+
+/// Public state of a component or of the platform; later variants are worse
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum State {
+    Operational,
+    Degraded,
+    Outage,
+}
+
+impl State {
+    pub fn label(&self) -> &'static str {
+        match self {
+            State::Operational => "Operational",
+            State::Degraded => "Degraded performance",
+            State::Outage => "Outage",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub component: &'static str,
+    pub up: bool,
+    /// `None` when the service did not answer at all
+    pub latency_ms: Option<i32>,
+}
+
+async fn probe(client: reqwest::Client, component: Component) -> Probe {
+    let started = Instant::now();
+    let (up, answered) = match client.get(format!("{}/ready", component.url)).send().await {
+        // 503 while a service is starting counts as down
+        Ok(response) => (response.status().is_success(), true),
+        Err(e) => {
+            warn!("Status probe of {} failed: {}", component.name, e);
+            (false, false)
+        }
+    };
+    gauge!("status_component_up", if up { 1.0 } else { 0.0 }, "component" => component.name);
+    Probe {
+        component: component.name,
+        up,
+        latency_ms: answered.then(|| started.elapsed().as_millis().min(i32::MAX as u128) as i32),
+    }
+}
+
+/// Probe every component concurrently; `timeout` bounds each request
+pub async fn round(client: &reqwest::Client, components: &[Component], timeout: Duration) -> Vec<Probe> {
+    let mut probes = JoinSet::new();
+    for component in components {
+        let (client, component) = (client.clone(), component.clone());
+        probes.spawn(async move {
+            let name = component.name;
+            tokio::time::timeout(timeout, probe(client, component))
+                .await
+                .unwrap_or(Probe { component: name, up: false, latency_ms: None })
+        });
+    }
+
+    let mut results = Vec::with_capacity(components.len());
+    while let Some(result) = probes.join_next().await {
+        match result {
+            Ok(probe) => results.push(probe),
+            Err(e) => warn!("Status probe task failed: {}", e),
+        }
+    }
+    results.sort_by_key(|probe| probe.component);
+    results
+}
+
+/// Store a round and drop probes that have left the SLO window
+pub async fn record(db: &PgPool, probes: &[Probe], keep_days: i32) -> Result<(), sqlx::Error> {
+    let components: Vec<String> = probes.iter().map(|probe| probe.component.to_string()).collect();
+    let up: Vec<bool> = probes.iter().map(|probe| probe.up).collect();
+    let latency: Vec<Option<i32>> = probes.iter().map(|probe| probe.latency_ms).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO status_probes (component, up, latency_ms)
+        SELECT * FROM unnest($1::text[], $2::bool[], $3::int4[])
+        "#,
+        &components,
+        &up,
+        &latency as &[Option<i32>]
+    )
+    .execute(db)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM status_probes WHERE checked_at < NOW() - make_interval(days => $1)",
+        keep_days
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
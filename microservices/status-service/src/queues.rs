@@ -0,0 +1,100 @@
+//! Depth of the platform's work queues
+//!
+//! Only totals across tenants are reported, with the age of the oldest item
+//! waiting. A queue whose oldest item has waited longer than the backlog
+//! threshold marks its component degraded.
+
+use metrics::gauge;
+use serde::Serialize;
+use sqlx::PgPool;
+use tracing::warn;
+
+/// Queue, owning component, and a statement returning the number of items
+/// waiting and the age in seconds of the oldest
+///
+/// Plain queries: the tables belong to other services and a deployment that
+/// has not applied one of their migrations still gets the rest of the page.
+const QUEUES: &[(&str, &str, &str)] = &[
+    (
+        "notification-delivery",
+        "notification",
+        "SELECT COUNT(*), EXTRACT(EPOCH FROM NOW() - MIN(first_occurred_at))::float8 \
+         FROM notifications WHERE status = 'PENDING'",
+    ),
+    (
+        "evidence-extraction",
+        "compliance",
+        "SELECT COUNT(*), EXTRACT(EPOCH FROM NOW() - MIN(created_at))::float8 \
+         FROM evidence_attachments WHERE extraction_status IN ('PENDING', 'PROCESSING')",
+    ),
+    (
+        "tenant-exports",
+        "reporting",
+        "SELECT COUNT(*), EXTRACT(EPOCH FROM NOW() - MIN(created_at))::float8 \
+         FROM tenant_exports WHERE status IN ('QUEUED', 'RUNNING')",
+    ),
+    (
+        "audit-anchoring",
+        "audit",
+        "SELECT COUNT(*), EXTRACT(EPOCH FROM NOW() - MIN(created_at))::float8 \
+         FROM audit_anchor_outbox WHERE status = 'PENDING'",
+    ),
+    (
+        "ipfs-pinning",
+        "audit",
+        "SELECT COUNT(*), EXTRACT(EPOCH FROM NOW() - MIN(created_at))::float8 \
+         FROM ipfs_pins WHERE status IN ('QUEUED', 'PINNING')",
+    ),
+];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum QueueState {
+    Normal,
+    Backlogged,
+    Unknown,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueStatus {
+    pub name: &'static str,
+    pub component: &'static str,
+    pub state: QueueState,
+    pub depth: Option<i64>,
+    pub oldest_age_seconds: Option<i64>,
+}
+
+pub async fn depths(db: &PgPool, backlog_after_secs: i64) -> Vec<QueueStatus> {
+    let mut queues = Vec::with_capacity(QUEUES.len());
+    for (name, component, statement) in QUEUES {
+        let measured = sqlx::query_as::<_, (i64, Option<f64>)>(statement).fetch_one(db).await;
+        queues.push(match measured {
+            Ok((depth, oldest)) => {
+                let oldest_age_seconds = oldest.map(|age| age.max(0.0) as i64);
+                gauge!("status_queue_depth", depth as f64, "queue" => *name);
+                QueueStatus {
+                    name,
+                    component,
+                    state: if oldest_age_seconds.map_or(false, |age| age > backlog_after_secs) {
+                        QueueState::Backlogged
+                    } else {
+                        QueueState::Normal
+                    },
+                    depth: Some(depth),
+                    oldest_age_seconds,
+                }
+            }
+            Err(e) => {
+                warn!("Failed to measure queue {}: {}", name, e);
+                QueueStatus {
+                    name,
+                    component,
+                    state: QueueState::Unknown,
+                    depth: None,
+                    oldest_age_seconds: None,
+                }
+            }
+        });
+    }
+    queues
+}
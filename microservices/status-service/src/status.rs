@@ -0,0 +1,230 @@
+//! The status snapshot served to the public
+//!
+//! A background loop probes the services, measures the queues and reads the
+//! published banners, then replaces the snapshot that /status and
+//! /status.html serve. Requests never reach the database, so an unauthenticated
+//! page cannot add load to the platform it reports on.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::error;
+
+use crate::incidents::{self, Banner};
+use crate::probe::{self, Component, Probe, State};
+use crate::queues::{self, QueueState, QueueStatus};
+
+#[derive(Debug, Clone)]
+pub struct StatusSettings {
+    pub components: Vec<Component>,
+    pub interval: Duration,
+    pub probe_timeout: Duration,
+    /// A component answering slower than this is shown degraded
+    pub degraded_latency_ms: i32,
+    pub slo_window_days: i32,
+    pub slo_target_percent: f64,
+    pub backlog_after_secs: i64,
+}
+
+impl StatusSettings {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        let target = std::env::var("STATUS_SLO_TARGET")
+            .ok()
+            .and_then(|value| value.parse::<f64>().ok())
+            .filter(|target| *target > 0.0 && *target < 100.0)
+            .unwrap_or(99.9);
+        Self {
+            components: probe::components_from_env(),
+            interval: Duration::from_secs(number("STATUS_PROBE_SECONDS", 30).max(5)),
+            probe_timeout: Duration::from_millis(number("STATUS_PROBE_TIMEOUT_MS", 3000).max(100)),
+            degraded_latency_ms: number("STATUS_DEGRADED_LATENCY_MS", 2000).min(i32::MAX as u64) as i32,
+            slo_window_days: number("STATUS_SLO_WINDOW_DAYS", 30).clamp(1, 365) as i32,
+            slo_target_percent: target,
+            backlog_after_secs: number("STATUS_QUEUE_BACKLOG_SECS", 900).min(i64::MAX as u64) as i64,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Slo {
+    pub window_days: i32,
+    pub target_percent: f64,
+    /// `None` until the component has been probed within the window
+    pub availability_percent: Option<f64>,
+    /// Share of the window's allowed downtime not yet used; negative once breached
+    pub error_budget_remaining_percent: Option<f64>,
+    pub latency_p95_ms: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ComponentStatus {
+    pub name: &'static str,
+    pub state: State,
+    pub latency_ms: Option<i32>,
+    pub slo: Slo,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StatusPage {
+    pub state: State,
+    pub generated_at: DateTime<Utc>,
+    pub components: Vec<ComponentStatus>,
+    pub queues: Vec<QueueStatus>,
+    pub incidents: Vec<Banner>,
+}
+
+pub struct Monitor {
+    db: PgPool,
+    client: reqwest::Client,
+    settings: StatusSettings,
+    latest: RwLock<Vec<Probe>>,
+    page: RwLock<Option<Arc<StatusPage>>>,
+}
+
+fn round_to(value: f64, places: i32) -> f64 {
+    let factor = 10f64.powi(places);
+    (value * factor).round() / factor
+}
+
+impl Monitor {
+    pub fn new(db: PgPool, settings: StatusSettings) -> anyhow::Result<Arc<Self>> {
+        let client = reqwest::Client::builder().timeout(settings.probe_timeout).build()?;
+        Ok(Arc::new(Self {
+            db,
+            client,
+            settings,
+            latest: RwLock::new(Vec::new()),
+            page: RwLock::new(None),
+        }))
+    }
+
+    /// The latest snapshot; `None` until the first round has completed
+    pub fn page(&self) -> Option<Arc<StatusPage>> {
+        self.page.read().expect("status page lock poisoned").clone()
+    }
+
+    /// Probe the services, record the round and rebuild the snapshot
+    pub async fn probe(&self) -> anyhow::Result<()> {
+        let probes = probe::round(&self.client, &self.settings.components, self.settings.probe_timeout).await;
+        // A window's worth plus a week, so the SLO can be recomputed after a change of window
+        probe::record(&self.db, &probes, self.settings.slo_window_days + 7).await?;
+        *self.latest.write().expect("status probe lock poisoned") = probes;
+        self.refresh().await
+    }
+
+    /// Rebuild the snapshot from the latest probes, e.g. after a banner changes
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let probes = self.latest.read().expect("status probe lock poisoned").clone();
+        let slos = self.slos().await?;
+        let queues = queues::depths(&self.db, self.settings.backlog_after_secs).await;
+        let published = incidents::published(&self.db).await?;
+
+        let components: Vec<ComponentStatus> = self
+            .settings
+            .components
+            .iter()
+            .map(|component| {
+                let probe = probes.iter().find(|probe| probe.component == component.name);
+                let mut state = match probe {
+                    Some(probe) if !probe.up => State::Outage,
+                    Some(probe) if probe.latency_ms.map_or(false, |ms| ms > self.settings.degraded_latency_ms) => {
+                        State::Degraded
+                    }
+                    Some(_) => State::Operational,
+                    // Not probed yet since this instance started
+                    None => State::Degraded,
+                };
+                if queues
+                    .iter()
+                    .any(|queue| queue.component == component.name && queue.state == QueueState::Backlogged)
+                {
+                    state = state.max(State::Degraded);
+                }
+                for incident in &published {
+                    if let Some(imposed) = incident.state() {
+                        if incident.components.iter().any(|name| name == component.name) {
+                            state = state.max(imposed);
+                        }
+                    }
+                }
+                let (availability, latency_p95) = slos.get(component.name).copied().unwrap_or((None, None));
+                let allowed_downtime = 100.0 - self.settings.slo_target_percent;
+                ComponentStatus {
+                    name: component.name,
+                    state,
+                    latency_ms: probe.and_then(|probe| probe.latency_ms),
+                    slo: Slo {
+                        window_days: self.settings.slo_window_days,
+                        target_percent: self.settings.slo_target_percent,
+                        availability_percent: availability.map(|a| round_to(a, 3)),
+                        error_budget_remaining_percent: availability
+                            .map(|a| round_to(100.0 * (1.0 - (100.0 - a) / allowed_downtime), 1)),
+                        latency_p95_ms: latency_p95.map(|ms| round_to(ms, 0)),
+                    },
+                }
+            })
+            .collect();
+
+        // Platform-wide incidents without components set the overall state directly
+        let mut state = components.iter().map(|c| c.state).max().unwrap_or(State::Operational);
+        for incident in published.iter().filter(|incident| incident.components.is_empty()) {
+            if let Some(imposed) = incident.state() {
+                state = state.max(imposed);
+            }
+        }
+
+        let page = StatusPage {
+            state,
+            generated_at: Utc::now(),
+            components,
+            queues,
+            incidents: published.iter().map(Banner::from).collect(),
+        };
+        *self.page.write().expect("status page lock poisoned") = Some(Arc::new(page));
+        Ok(())
+    }
+
+    /// Availability and p95 latency of successful probes per component over the window
+    async fn slos(&self) -> anyhow::Result<HashMap<String, (Option<f64>, Option<f64>)>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT component,
+                   (100.0 * COUNT(*) FILTER (WHERE up) / COUNT(*))::float8 AS "availability!",
+                   percentile_cont(0.95) WITHIN GROUP (ORDER BY latency_ms) FILTER (WHERE up) AS latency_p95
+            FROM status_probes
+            WHERE checked_at > NOW() - make_interval(days => $1)
+            GROUP BY component
+            "#,
+            self.settings.slo_window_days
+        )
+        .fetch_all(&self.db)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.component, (Some(row.availability), row.latency_p95)))
+            .collect())
+    }
+}
+
+pub fn spawn_worker(monitor: Arc<Monitor>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(monitor.settings.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = monitor.probe().await {
+                error!("Status probe round failed: {:#}", e);
+            }
+        }
+    });
+}
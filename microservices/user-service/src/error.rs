@@ -0,0 +1,61 @@
+//! Application error type and its HTTP mapping
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use thiserror::Error;
+use tracing::error;
+
+use crate::models::ApiResponse;
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("Validation failed: {0}")]
+    Validation(#[from] validator::ValidationErrors),
+
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    TooManyRequests(String),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+        };
+
+        // Never leak database or internal details to clients
+        let message = if status == StatusCode::INTERNAL_SERVER_ERROR {
+            error!("Request failed: {}", self);
+            "Internal server error".to_string()
+        } else {
+            self.to_string()
+        };
+
+        (status, Json(ApiResponse::<()>::error(message))).into_response()
+    }
+}
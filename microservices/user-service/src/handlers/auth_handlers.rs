@@ -0,0 +1,47 @@
+//! Authentication flow HTTP handlers
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::Json,
+};
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    models::*,
+    AppState,
+};
+
+/// Request a password reset link
+///
+/// Unknown addresses get the same response (and the same throttling) as real
+/// ones so the endpoint cannot be used to enumerate accounts.
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<Json<ApiResponse<String>>, AppError> {
+    payload.validate()?;
+
+    let email = payload.email.trim().to_lowercase();
+    let user = state.user_service.find_user_by_email(&email).await?;
+
+    let context = OtpRequestContext {
+        flow: OtpFlow::PasswordReset,
+        account: user.as_ref().map_or_else(|| email.clone(), |user| user.user_id.to_string()),
+        user_id: user.as_ref().map(|user| user.user_id),
+        tenant_id: user.as_ref().map(|user| user.tenant_id),
+        client: ClientFingerprint::from_headers(&headers),
+    };
+    state.otp_guard.check(&context).await?;
+
+    if let Some(user) = user {
+        let reset_token = state.user_service.generate_password_reset(user.user_id).await?;
+        state.user_service.send_password_reset_email(&user, &reset_token).await?;
+    }
+
+    Ok(Json(ApiResponse::success(
+        "If the account exists, a password reset link has been sent".to_string(),
+    )))
+}
@@ -0,0 +1,116 @@
+//! Break-glass emergency access handlers
+//!
+//! Every call is made by a SuperAdmin identified from the bearer token, so the
+//! requester and the two approvers are provably different people.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Json,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    models::*,
+    AppState,
+};
+
+/// Ask for time-boxed access to a tenant
+pub async fn request_break_glass(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<RequestBreakGlassRequest>,
+) -> Result<Json<ApiResponse<BreakGlassGrant>>, AppError> {
+    payload.validate()?;
+    let caller = state.break_glass.authenticate(&headers).await?;
+    let grant = state.break_glass.request(caller, payload).await?;
+
+    Ok(Json(ApiResponse::success(grant)))
+}
+
+pub async fn list_break_glass(
+    Query(params): Query<ListBreakGlassParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<BreakGlassGrant>>>, AppError> {
+    state.break_glass.authenticate(&headers).await?;
+    let grants = state.break_glass.list(&params).await?;
+
+    Ok(Json(ApiResponse::success(grants)))
+}
+
+pub async fn get_break_glass(
+    Path(grant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<BreakGlassDetail>>, AppError> {
+    state.break_glass.authenticate(&headers).await?;
+    let detail = state.break_glass.get(grant_id).await?;
+
+    Ok(Json(ApiResponse::success(detail)))
+}
+
+/// Approve as one of the required SuperAdmins other than the requester
+pub async fn approve_break_glass(
+    Path(grant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<BreakGlassDecisionRequest>,
+) -> Result<Json<ApiResponse<BreakGlassGrant>>, AppError> {
+    payload.validate()?;
+    let caller = state.break_glass.authenticate(&headers).await?;
+    let grant = state.break_glass.decide(grant_id, caller, true, payload.comment).await?;
+
+    Ok(Json(ApiResponse::success(grant)))
+}
+
+pub async fn reject_break_glass(
+    Path(grant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<BreakGlassDecisionRequest>,
+) -> Result<Json<ApiResponse<BreakGlassGrant>>, AppError> {
+    payload.validate()?;
+    let caller = state.break_glass.authenticate(&headers).await?;
+    let grant = state.break_glass.decide(grant_id, caller, false, payload.comment).await?;
+
+    Ok(Json(ApiResponse::success(grant)))
+}
+
+/// End a pending request or active window early; any SuperAdmin may do so
+pub async fn revoke_break_glass(
+    Path(grant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<BreakGlassDecisionRequest>,
+) -> Result<Json<ApiResponse<BreakGlassGrant>>, AppError> {
+    payload.validate()?;
+    let caller = state.break_glass.authenticate(&headers).await?;
+    let grant = state.break_glass.revoke(grant_id, caller, payload.comment).await?;
+
+    Ok(Json(ApiResponse::success(grant)))
+}
+
+/// Audit bundle of a closed window
+pub async fn get_break_glass_bundle(
+    Path(grant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<BreakGlassBundle>>, AppError> {
+    state.break_glass.authenticate(&headers).await?;
+    let bundle = state.break_glass.bundle(grant_id).await?;
+
+    Ok(Json(ApiResponse::success(bundle)))
+}
+
+/// Lets the gateway admit a SuperAdmin to a tenant only inside an active window
+pub async fn check_break_glass(
+    Query(params): Query<BreakGlassCheckParams>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Option<BreakGlassGrant>>>, AppError> {
+    let grant = state.break_glass.active_grant(params.user_id, params.tenant_id).await?;
+
+    Ok(Json(ApiResponse::success(grant)))
+}
@@ -0,0 +1,38 @@
+//! Admin point-in-time read handlers
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::{
+    error::AppError,
+    models::*,
+    AppState,
+};
+
+/// Get a user's role and permissions as of a point in time (defaults to now)
+pub async fn get_user_access_as_of(
+    Path(user_id): Path<Uuid>,
+    Query(params): Query<AsOfParams>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<UserAccessSnapshot>>, AppError> {
+    let as_of = params.as_of.unwrap_or_else(Utc::now);
+    let snapshot = state.history_service.user_access_as_of(user_id, as_of).await?;
+
+    Ok(Json(ApiResponse::success(snapshot)))
+}
+
+/// Get a tenant's settings and users as of a point in time (defaults to now)
+pub async fn get_tenant_as_of(
+    Path(tenant_id): Path<Uuid>,
+    Query(params): Query<AsOfParams>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<TenantSnapshot>>, AppError> {
+    let as_of = params.as_of.unwrap_or_else(Utc::now);
+    let snapshot = state.history_service.tenant_as_of(tenant_id, as_of).await?;
+
+    Ok(Json(ApiResponse::success(snapshot)))
+}
@@ -0,0 +1,11 @@
+//! HTTP handlers for the user service
+
+pub mod user_handlers;
+pub mod history_handlers;
+pub mod auth_handlers;
+pub mod break_glass_handlers;
+
+pub use user_handlers::*;
+pub use history_handlers::*;
+pub use auth_handlers::*;
+pub use break_glass_handlers::*;
@@ -2,7 +2,7 @@
 
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::Json,
 };
 use uuid::Uuid;
@@ -156,7 +156,18 @@ pub async fn bulk_update_users(
 pub async fn reset_password(
     Path(user_id): Path<Uuid>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> Result<Json<ApiResponse<String>>, AppError> {
+    let user = state.user_service.get_user_by_id(user_id).await?;
+    let context = OtpRequestContext {
+        flow: OtpFlow::PasswordReset,
+        account: user.user_id.to_string(),
+        user_id: Some(user.user_id),
+        tenant_id: Some(user.tenant_id),
+        client: ClientFingerprint::from_headers(&headers),
+    };
+    state.otp_guard.check(&context).await?;
+
     let reset_token = state.user_service.generate_password_reset(user_id).await?;
 
     Ok(Json(ApiResponse::success(reset_token)))
@@ -172,6 +183,16 @@ pub async fn get_user_sessions(
     Ok(Json(ApiResponse::success(sessions)))
 }
 
+/// Get the snapshot of a user the audit service embeds in their events
+pub async fn get_user_actor(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<ActorSnapshot>>, AppError> {
+    let snapshot = state.user_service.get_actor_snapshot(user_id).await?;
+
+    Ok(Json(ApiResponse::success(snapshot)))
+}
+
 /// Get user permissions
 pub async fn get_user_permissions(
     Path(user_id): Path<Uuid>,
@@ -19,8 +19,10 @@ use axum::{
     Router,
 };
 use chrono::{DateTime, Utc};
+use dharmaguard_common::pool::{self, Admission, PoolSettings};
+use dharmaguard_common::startup::{self, Readiness, Startup};
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::PgPool;
 use std::{net::SocketAddr, sync::Arc};
 use tokio::signal;
 use tower::ServiceBuilder;
@@ -30,7 +32,7 @@ use tower_http::{
     trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse, TraceLayer},
     RequestIdLayer,
 };
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use uuid::Uuid;
 
@@ -54,6 +56,12 @@ use crate::{
     services::*,
 };
 
+/// Shared migrations the service depends on, each with a relation it creates
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("005_configuration_history", "configuration_history"),
+    ("024_break_glass", "break_glass_grants"),
+];
+
 /// Application state shared across all handlers
 #[derive(Clone)]
 pub struct AppState {
@@ -61,7 +69,13 @@ pub struct AppState {
     pub redis: redis::Client,
     pub auth: AuthService,
     pub user_service: UserService,
+    pub history_service: HistoryService,
+    pub otp_guard: OtpGuard,
+    pub break_glass: BreakGlassService,
+    /// Behavioral screening of /auth requests; off when BOT_SCORING_PROVIDER=off
+    pub bot_guard: Option<BotGuard>,
     pub config: Arc<Config>,
+    pub admission: Arc<Admission>,
 }
 
 /// Health check response
@@ -83,13 +97,18 @@ async fn main() -> anyhow::Result<()> {
     let config = Arc::new(Config::from_env()?);
     info!("Configuration loaded successfully");
 
-    // Initialize database
-    let database_url = &config.database.url;
-    let pool = PgPoolOptions::new()
-        .max_connections(config.database.max_connections)
-        .min_connections(config.database.min_connections)
-        .connect(database_url)
-        .await?;
+    // Install the metrics recorder before anything records
+    dharmaguard_common::metrics::install();
+
+    // Initialize database; admission control keeps request concurrency within the pool
+    let pool_settings = PoolSettings {
+        min_connections: config.database.min_connections,
+        max_connections: config.database.max_connections,
+        ..PoolSettings::from_env()
+    };
+    let pool = pool_settings.connect(&config.database.url).await?;
+    let admission = Admission::new("user", pool_settings);
+    admission.spawn_autoscaler(pool.clone());
 
     // Run database migrations
     sqlx::migrate!("./migrations").run(&pool).await?;
@@ -97,15 +116,23 @@ async fn main() -> anyhow::Result<()> {
 
     let database = Database::new(pool);
 
-    // Initialize Redis
+    // Initialize Redis; reachability is a startup check
     let redis_client = redis::Client::open(config.redis.url.as_str())?;
-    let mut redis_conn = redis_client.get_connection()?;
-    redis::cmd("PING").execute(&mut redis_conn);
-    info!("Redis connection established");
 
     // Initialize services
     let auth_service = AuthService::new(config.jwt.clone());
     let user_service = UserService::new(database.clone(), redis_client.clone());
+    let history_service = HistoryService::new(database.clone());
+    let otp_guard = OtpGuard::new(database.clone(), redis_client.clone(), OtpGuardPolicy::default());
+    let break_glass = BreakGlassService::new(database.clone(), BreakGlassSettings::from_env()?);
+    let bot_guard = BotGuard::from_env(database.clone(), redis_client.clone())?;
+    match &bot_guard {
+        Some(guard) => info!("Screening authentication requests with {}", guard.providers().join(", ")),
+        None => warn!("BOT_SCORING_PROVIDER is off; authentication requests are not screened for bots"),
+    }
+
+    // Expires break-glass windows and collects their audit bundles
+    break_glass.clone().spawn_sweeper();
 
     // Create application state
     let app_state = AppState {
@@ -113,11 +140,31 @@ async fn main() -> anyhow::Result<()> {
         redis: redis_client,
         auth: auth_service,
         user_service,
+        history_service,
+        otp_guard,
+        break_glass,
+        bot_guard,
         config: config.clone(),
+        admission,
     };
 
+    // API routes answer 503 until these pass; /health, /ready and /metrics answer from the start
+    let startup = Startup::new("user")
+        .migrations(app_state.db.pool.clone(), MIGRATIONS)
+        .check("redis", {
+            let redis_client = app_state.redis.clone();
+            move || {
+                let redis_client = redis_client.clone();
+                async move {
+                    let mut redis_conn = redis_client.get_multiplexed_async_connection().await?;
+                    redis::cmd("PING").query_async::<_, String>(&mut redis_conn).await?;
+                    Ok(())
+                }
+            }
+        });
+
     // Build application router
-    let app = create_router(app_state).await;
+    let app = create_router(app_state, startup.readiness()).await;
 
     // Start metrics server
     start_metrics_server(&config).await?;
@@ -128,8 +175,13 @@ async fn main() -> anyhow::Result<()> {
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
     
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    startup
+        .serve(async {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown_signal())
+                .await
+                .map_err(anyhow::Error::from)
+        })
         .await?;
 
     info!("Server shutdown complete");
@@ -151,19 +203,29 @@ fn init_tracing() -> anyhow::Result<()> {
 }
 
 /// Create the main application router
-async fn create_router(state: AppState) -> Router {
-    // Health check router
-    let health_router = Router::new().route("/health", get(health_check));
+async fn create_router(state: AppState, readiness: Readiness) -> Router {
+    // Health check and readiness router
+    let health_router = Router::new()
+        .route("/health", get(health_check))
+        .merge(startup::router(readiness.clone()));
 
     // API v1 router
     let api_v1_router = Router::new()
         .nest("/users", create_user_routes())
-        .nest("/auth", create_auth_routes())
+        .nest("/auth", create_auth_routes(&state))
         .nest("/sessions", create_session_routes())
         .nest("/permissions", create_permission_routes())
         .layer(middleware::from_fn_with_state(
             state.clone(),
             mw::auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.admission.clone(),
+            pool::admit,
+        ))
+        .layer(middleware::from_fn_with_state(
+            readiness.clone(),
+            startup::gate,
         ));
 
     // Protected admin routes
@@ -172,6 +234,14 @@ async fn create_router(state: AppState) -> Router {
         .layer(middleware::from_fn_with_state(
             state.clone(),
             mw::admin_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.admission.clone(),
+            pool::admit,
+        ))
+        .layer(middleware::from_fn_with_state(
+            readiness,
+            startup::gate,
         ));
 
     // Combine all routes
@@ -204,6 +274,7 @@ fn create_user_routes() -> Router<AppState> {
     Router::new()
         .route("/", post(create_user).get(list_users))
         .route("/:user_id", get(get_user).patch(update_user).delete(delete_user))
+        .route("/:user_id/actor", get(get_user_actor))
         .route("/:user_id/sessions", get(get_user_sessions))
         .route("/:user_id/permissions", get(get_user_permissions).post(grant_permission))
         .route("/:user_id/activate", post(activate_user))
@@ -213,21 +284,25 @@ fn create_user_routes() -> Router<AppState> {
         .route("/bulk", post(bulk_create_users).patch(bulk_update_users))
 }
 
-/// Create authentication routes
-fn create_auth_routes() -> Router<AppState> {
-    Router::new()
+/// Create authentication routes; credential and code entry points are screened for bots
+fn create_auth_routes(state: &AppState) -> Router<AppState> {
+    let screened = Router::new()
         .route("/login", post(login))
-        .route("/logout", post(logout))
-        .route("/refresh", post(refresh_token))
         .route("/register", post(register))
         .route("/forgot-password", post(forgot_password))
         .route("/reset-password", post(confirm_reset_password))
-        .route("/verify-email", post(verify_email))
         .route("/resend-verification", post(resend_verification))
+        .route("/verify-mfa", post(verify_mfa))
+        .route_layer(middleware::from_fn_with_state(state.clone(), services::bot_guard::screen));
+
+    Router::new()
+        .merge(screened)
+        .route("/logout", post(logout))
+        .route("/refresh", post(refresh_token))
+        .route("/verify-email", post(verify_email))
         .route("/change-password", post(change_password))
         .route("/enable-mfa", post(enable_mfa))
         .route("/disable-mfa", post(disable_mfa))
-        .route("/verify-mfa", post(verify_mfa))
 }
 
 /// Create session management routes
@@ -252,10 +327,19 @@ fn create_permission_routes() -> Router<AppState> {
 fn create_admin_routes() -> Router<AppState> {
     Router::new()
         .route("/users/stats", get(get_user_statistics))
+        .route("/users/:user_id/access", get(get_user_access_as_of))
         .route("/sessions/stats", get(get_session_statistics))
         .route("/security/audit", get(get_security_audit))
         .route("/tenants", get(list_tenants).post(create_tenant))
         .route("/tenants/:tenant_id", get(get_tenant).patch(update_tenant))
+        .route("/tenants/:tenant_id/snapshot", get(get_tenant_as_of))
+        .route("/break-glass", get(list_break_glass).post(request_break_glass))
+        .route("/break-glass/check", get(check_break_glass))
+        .route("/break-glass/:grant_id", get(get_break_glass))
+        .route("/break-glass/:grant_id/approve", post(approve_break_glass))
+        .route("/break-glass/:grant_id/reject", post(reject_break_glass))
+        .route("/break-glass/:grant_id/revoke", post(revoke_break_glass))
+        .route("/break-glass/:grant_id/bundle", get(get_break_glass_bundle))
         .route("/system/health", get(system_health_check))
         .route("/system/metrics", get(get_system_metrics))
 }
@@ -285,7 +369,7 @@ async fn health_check(State(state): State<AppState>) -> Result<Json<HealthRespon
 
 /// Start metrics server on separate port
 async fn start_metrics_server(config: &Config) -> anyhow::Result<()> {
-    let metrics_router = Router::new().route("/metrics", get(metrics_handler));
+    let metrics_router: Router = dharmaguard_common::metrics::router();
 
     let metrics_addr = SocketAddr::from(([0, 0, 0, 0], config.metrics.port));
     
@@ -298,14 +382,6 @@ async fn start_metrics_server(config: &Config) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Metrics endpoint handler
-async fn metrics_handler() -> String {
-    use metrics_exporter_prometheus::PrometheusBuilder;
-    
-    let handle = PrometheusBuilder::new().build_recorder().handle();
-    handle.render()
-}
-
 /// Graceful shutdown signal handler
 async fn shutdown_signal() {
     let ctrl_c = async {
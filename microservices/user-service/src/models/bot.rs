@@ -0,0 +1,123 @@
+//! Behavioral bot detection models for the authentication endpoints
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::ClientFingerprint;
+
+/// Interaction telemetry the web and mobile clients send as JSON in X-Client-Signals
+///
+/// Counts only, never the keys pressed or the pointer path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientSignals {
+    /// Milliseconds from the form being shown to it being submitted
+    pub form_ms: Option<u64>,
+    pub keystrokes: u32,
+    /// Pointer moves, clicks and touches on the page
+    pub pointer_events: u32,
+    pub pasted: bool,
+    /// Fields were filled by the browser or a password manager
+    pub autofilled: bool,
+    /// `navigator.webdriver` was set
+    pub webdriver: bool,
+}
+
+/// Everything known about an authentication request before it is handled
+#[derive(Debug, Clone, Serialize)]
+pub struct BehaviorSignals {
+    /// Route under /auth, e.g. `/login`
+    pub endpoint: String,
+    #[serde(skip)]
+    pub client: ClientFingerprint,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub accept_language: bool,
+    /// `None` when the X-Client-Signals header was missing or unreadable
+    pub interaction: Option<ClientSignals>,
+    pub timing: TimingSnapshot,
+}
+
+impl BehaviorSignals {
+    pub fn from_headers(endpoint: &str, headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let client = ClientFingerprint::from_headers(headers);
+        Self {
+            endpoint: endpoint.to_string(),
+            ip_address: client.ip_address.map(|ip| ip.to_string()),
+            client,
+            user_agent: header("user-agent").map(str::to_string),
+            accept_language: header("accept-language").is_some(),
+            interaction: header("x-client-signals").and_then(|value| serde_json::from_str(value).ok()),
+            timing: TimingSnapshot::default(),
+        }
+    }
+}
+
+/// Arrival pattern of recent authentication requests from the same IP address
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimingSnapshot {
+    /// Requests within the timing window, this one included
+    pub recent_requests: u64,
+    /// Milliseconds since the previous request
+    pub last_interval_ms: Option<u64>,
+    pub mean_interval_ms: Option<f64>,
+    /// Standard deviation over mean of the gaps; scripts are far more regular than people
+    pub interval_variation: Option<f64>,
+}
+
+/// Contribution of one scorer to the bot score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BotSignal {
+    pub source: String,
+    pub score: u32,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BotDecision {
+    Allow,
+    StepUp,
+    Block,
+}
+
+impl BotDecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotDecision::Allow => "allow",
+            BotDecision::StepUp => "step_up",
+            BotDecision::Block => "block",
+        }
+    }
+}
+
+/// Outcome of screening a request; handlers find it in the request extensions
+#[derive(Debug, Clone, Serialize)]
+pub struct BotAssessment {
+    pub score: u32,
+    pub decision: BotDecision,
+    pub signals: Vec<BotSignal>,
+    /// The client solved a step-up challenge for this request
+    pub stepped_up: bool,
+}
+
+/// Proof-of-work step-up: find a `solution` whose SHA-256 of `"{nonce}:{solution}"`
+/// starts with `difficulty` zero bits, then retry with X-Step-Up-Challenge and
+/// X-Step-Up-Solution. Browsers solve it in the background in about a second.
+#[derive(Debug, Clone, Serialize)]
+pub struct StepUpChallenge {
+    pub challenge_id: Uuid,
+    pub algorithm: &'static str,
+    pub nonce: String,
+    pub difficulty: u32,
+    pub expires_at: DateTime<Utc>,
+}
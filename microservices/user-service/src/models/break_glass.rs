@@ -0,0 +1,111 @@
+//! Break-glass emergency access models
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Emergency SuperAdmin access to one tenant
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct BreakGlassGrant {
+    pub grant_id: Uuid,
+    pub tenant_id: Uuid,
+    pub requested_by: Uuid,
+    pub reason: String,
+    pub duration_minutes: i32,
+    /// PENDING, ACTIVE, REJECTED, EXPIRED or REVOKED
+    pub status: String,
+    pub requested_at: DateTime<Utc>,
+    pub request_expires_at: DateTime<Utc>,
+    pub activated_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub closed_by: Option<Uuid>,
+    pub close_reason: Option<String>,
+}
+
+/// One SuperAdmin's decision on a request
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct BreakGlassApproval {
+    pub approver_id: Uuid,
+    /// APPROVE or REJECT
+    pub decision: String,
+    pub comment: Option<String>,
+    pub decided_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BreakGlassDetail {
+    #[serde(flatten)]
+    pub grant: BreakGlassGrant,
+    pub approvals: Vec<BreakGlassApproval>,
+    /// Set once the window has ended and its audit bundle was collected
+    pub bundle_hash: Option<String>,
+}
+
+/// Open a break-glass request
+#[derive(Debug, Deserialize, Validate)]
+pub struct RequestBreakGlassRequest {
+    pub tenant_id: Uuid,
+
+    /// Incident or ticket justifying the access
+    #[validate(length(min = 20, max = 2000))]
+    pub reason: String,
+
+    /// Capped at BREAK_GLASS_MAX_MINUTES
+    #[validate(range(min = 5))]
+    pub duration_minutes: i32,
+}
+
+/// Approve, reject or revoke a break-glass request
+#[derive(Debug, Deserialize, Validate)]
+pub struct BreakGlassDecisionRequest {
+    #[validate(length(max = 2000))]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListBreakGlassParams {
+    pub tenant_id: Option<Uuid>,
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Whether a user currently holds an ACTIVE window on a tenant
+#[derive(Debug, Deserialize)]
+pub struct BreakGlassCheckParams {
+    pub user_id: Uuid,
+    pub tenant_id: Uuid,
+}
+
+/// Everything done during a break-glass window
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct BreakGlassBundle {
+    pub grant_id: Uuid,
+    pub action_count: i32,
+    pub bundle: serde_json::Value,
+    /// SHA-256 of `bundle`, for comparing exported copies
+    pub content_hash: String,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// An audit_logs row taken by the requester during the window
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct BreakGlassAction {
+    pub log_id: Uuid,
+    pub tenant_id: Option<Uuid>,
+    pub session_id: Option<Uuid>,
+    pub action: String,
+    pub resource_type: String,
+    pub resource_id: Option<Uuid>,
+    pub old_values: Option<serde_json::Value>,
+    pub new_values: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<Uuid>,
+    pub api_endpoint: Option<String>,
+    pub http_method: Option<String>,
+    pub response_status: Option<i32>,
+    pub timestamp: Option<DateTime<Utc>>,
+}
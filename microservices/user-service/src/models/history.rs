@@ -0,0 +1,86 @@
+//! Point-in-time history models for investigations
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use super::UserRole;
+
+/// `as_of` query parameter accepted by admin read endpoints
+#[derive(Debug, Deserialize)]
+pub struct AsOfParams {
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+/// User row as it existed during [valid_from, valid_to)
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct UserVersion {
+    pub user_id: Uuid,
+    pub tenant_id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub role: UserRole,
+    pub is_active: bool,
+    pub is_verified: bool,
+    pub mfa_enabled: bool,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+    pub changed_by: Option<String>,
+}
+
+/// Permission grant as it existed during [valid_from, valid_to)
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct PermissionVersion {
+    pub permission_id: Uuid,
+    pub user_id: Uuid,
+    pub resource: String,
+    pub action: String,
+    pub granted_at: Option<DateTime<Utc>>,
+    pub granted_by: Option<Uuid>,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+/// Tenant settings as they existed during [valid_from, valid_to)
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct TenantVersion {
+    pub tenant_id: Uuid,
+    pub name: String,
+    pub display_name: String,
+    pub subscription_plan: Option<String>,
+    pub max_users: Option<i32>,
+    pub max_trades_per_day: Option<i32>,
+    pub is_active: Option<bool>,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+/// Tenant configuration entry as it existed during [valid_from, valid_to)
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct TenantConfigurationVersion {
+    pub config_id: Uuid,
+    pub tenant_id: Uuid,
+    pub config_key: String,
+    pub config_value: serde_json::Value,
+    pub valid_from: DateTime<Utc>,
+    pub valid_to: Option<DateTime<Utc>>,
+}
+
+/// Who a user was and what they could do at a point in time
+#[derive(Debug, Serialize)]
+pub struct UserAccessSnapshot {
+    pub as_of: DateTime<Utc>,
+    pub user: UserVersion,
+    pub permissions: Vec<PermissionVersion>,
+}
+
+/// A tenant's settings and users at a point in time
+#[derive(Debug, Serialize)]
+pub struct TenantSnapshot {
+    pub as_of: DateTime<Utc>,
+    pub tenant: TenantVersion,
+    pub configurations: Vec<TenantConfigurationVersion>,
+    pub users: Vec<UserVersion>,
+}
@@ -10,82 +10,19 @@ pub mod user;
 pub mod session;
 pub mod permission;
 pub mod tenant;
+pub mod history;
+pub mod otp;
+pub mod break_glass;
+pub mod bot;
 
 pub use user::*;
 pub use session::*;
 pub use permission::*;
 pub use tenant::*;
-
-/// Standard response wrapper
-#[derive(Debug, Serialize)]
-pub struct ApiResponse<T> {
-    pub success: bool,
-    pub data: Option<T>,
-    pub error: Option<String>,
-    pub timestamp: DateTime<Utc>,
-}
-
-impl<T> ApiResponse<T> {
-    pub fn success(data: T) -> Self {
-        Self {
-            success: true,
-            data: Some(data),
-            error: None,
-            timestamp: Utc::now(),
-        }
-    }
-
-    pub fn error(message: String) -> ApiResponse<()> {
-        ApiResponse {
-            success: false,
-            data: None,
-            error: Some(message),
-            timestamp: Utc::now(),
-        }
-    }
-}
-
-/// Pagination parameters
-#[derive(Debug, Deserialize, Validate)]
-pub struct PaginationParams {
-    #[validate(range(min = 1, max = 100))]
-    pub limit: Option<u32>,
-    #[validate(range(min = 0))]
-    pub offset: Option<u32>,
-    pub sort_by: Option<String>,
-    pub sort_order: Option<SortOrder>,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum SortOrder {
-    Asc,
-    Desc,
-}
-
-impl Default for PaginationParams {
-    fn default() -> Self {
-        Self {
-            limit: Some(20),
-            offset: Some(0),
-            sort_by
-//! Data models for the user service
-
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
-use uuid::Uuid;
-use validator::Validate;
-
-pub mod user;
-pub mod session;
-pub mod permission;
-pub mod tenant;
-
-pub use user::*;
-pub use session::*;
-pub use permission::*;
-pub use tenant::*;
+pub use history::*;
+pub use otp::*;
+pub use break_glass::*;
+pub use bot::*;
 
 /// Standard response wrapper
 #[derive(Debug, Serialize)]
@@ -0,0 +1,110 @@
+//! OTP and password-reset abuse protection models
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+/// Flows that deliver a one-time code or reset link
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OtpFlow {
+    PasswordReset,
+    EmailVerification,
+    MfaChallenge,
+}
+
+impl OtpFlow {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OtpFlow::PasswordReset => "password_reset",
+            OtpFlow::EmailVerification => "email_verification",
+            OtpFlow::MfaChallenge => "mfa_challenge",
+        }
+    }
+}
+
+/// Client identifiers taken from the request headers
+#[derive(Debug, Clone, Default)]
+pub struct ClientFingerprint {
+    pub ip_address: Option<IpAddr>,
+    pub device_id: Option<String>,
+}
+
+impl ClientFingerprint {
+    /// First X-Forwarded-For hop (set by the gateway) and the X-Device-Id header
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let ip_address = headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        let device_id = headers
+            .get("x-device-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty());
+
+        Self { ip_address, device_id }
+    }
+}
+
+/// A single OTP or reset-link request being evaluated
+#[derive(Debug, Clone)]
+pub struct OtpRequestContext {
+    pub flow: OtpFlow,
+    /// Stable account key: the user id when known, otherwise the normalized email
+    pub account: String,
+    pub user_id: Option<Uuid>,
+    pub tenant_id: Option<Uuid>,
+    pub client: ClientFingerprint,
+}
+
+/// What a block applies to
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BlockScope {
+    Account,
+    IpAddress,
+}
+
+/// Counters observed for a request, shared by every fraud rule
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VelocitySnapshot {
+    /// Requests for this account and flow within the velocity window
+    pub account_requests: u64,
+    /// Requests from this IP across all accounts within the velocity window
+    pub ip_requests: u64,
+    /// Distinct accounts targeted from this IP within the fan-out window
+    pub ip_distinct_accounts: u64,
+    /// Whether the device has previously completed this flow for the account
+    pub known_device: Option<bool>,
+}
+
+/// Contribution of one rule to the fraud score
+#[derive(Debug, Clone, Serialize)]
+pub struct FraudSignal {
+    pub rule: String,
+    pub score: u32,
+    pub scope: BlockScope,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OtpDecision {
+    Allow,
+    Block,
+}
+
+/// Outcome of evaluating an OTP request
+#[derive(Debug, Clone, Serialize)]
+pub struct FraudAssessment {
+    pub flow: OtpFlow,
+    pub score: u32,
+    pub decision: OtpDecision,
+    pub signals: Vec<FraudSignal>,
+    pub velocity: VelocitySnapshot,
+    pub blocked_until: Option<DateTime<Utc>>,
+}
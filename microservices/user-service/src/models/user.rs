@@ -179,6 +179,16 @@ impl From<User> for UserProfile {
     }
 }
 
+/// Who a user is, as embedded by the audit service in the events they cause
+#[derive(Debug, Serialize, FromRow)]
+pub struct ActorSnapshot {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: UserRole,
+    pub tenant_id: Uuid,
+    pub tenant_name: String,
+}
+
 /// MFA enable request
 #[derive(Debug, Deserialize)]
 pub struct EnableMfaRequest {
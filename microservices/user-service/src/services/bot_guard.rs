@@ -0,0 +1,508 @@
+//! Captcha-free bot detection on the authentication endpoints
+//!
+//! The [`screen`] middleware gathers behavioral signals for each request:
+//! interaction counts the clients report in X-Client-Signals, header
+//! consistency, and the arrival pattern of requests from the same IP address
+//! (kept in Redis). Each configured [`BotScorer`] turns them into scored
+//! signals. Most people never notice any of this. Above the step-up threshold
+//! the request is answered 428 with a proof-of-work challenge the client
+//! solves in the background and retries with; above the block threshold it is
+//! refused outright. Step-ups and blocks are written to the security audit
+//! trail.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{Duration, Utc};
+use metrics::counter;
+use rand_core::{OsRng, RngCore};
+use redis::aio::MultiplexedConnection;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    database::Database,
+    error::AppError,
+    models::*,
+    AppState,
+};
+
+/// A scorer turns a request's behavioral signals into scored signals
+///
+/// Providers can be local heuristics or a remote risk engine; a provider that
+/// fails should return an error rather than guess, and the request is then
+/// scored by the others.
+#[async_trait]
+pub trait BotScorer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn score(&self, signals: &BehaviorSignals) -> anyhow::Result<Vec<BotSignal>>;
+}
+
+/// User agents of HTTP libraries and headless browsers, matched case-insensitively
+const AUTOMATION_AGENTS: &[&str] = &[
+    "headlesschrome",
+    "phantomjs",
+    "python-requests",
+    "python-urllib",
+    "aiohttp",
+    "curl/",
+    "wget/",
+    "go-http-client",
+    "scrapy",
+    "node-fetch",
+    "java-http-client",
+];
+
+/// Built-in rules over the interaction counts, headers and request timing
+pub struct HeuristicScorer {
+    /// Forms submitted faster than this were not typed by a person
+    pub min_form_ms: u64,
+    /// Score for a request without X-Client-Signals, which every DharmaGuard client sends
+    pub missing_signals_score: u32,
+}
+
+impl Default for HeuristicScorer {
+    fn default() -> Self {
+        Self {
+            min_form_ms: 800,
+            missing_signals_score: 20,
+        }
+    }
+}
+
+impl HeuristicScorer {
+    fn signal(&self, score: u32, detail: impl Into<String>) -> BotSignal {
+        BotSignal {
+            source: self.name().to_string(),
+            score,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl BotScorer for HeuristicScorer {
+    fn name(&self) -> &'static str {
+        "heuristic"
+    }
+
+    async fn score(&self, signals: &BehaviorSignals) -> anyhow::Result<Vec<BotSignal>> {
+        let mut found = Vec::new();
+
+        match &signals.user_agent {
+            None => found.push(self.signal(30, "no User-Agent header")),
+            Some(agent) => {
+                let agent = agent.to_lowercase();
+                if let Some(tool) = AUTOMATION_AGENTS.iter().find(|tool| agent.contains(*tool)) {
+                    found.push(self.signal(40, format!("automation user agent ({})", tool.trim_end_matches('/'))));
+                }
+            }
+        }
+        if !signals.accept_language {
+            found.push(self.signal(10, "no Accept-Language header"));
+        }
+
+        match &signals.interaction {
+            None => {
+                if self.missing_signals_score > 0 {
+                    found.push(self.signal(self.missing_signals_score, "no client interaction signals"));
+                }
+            }
+            Some(interaction) => {
+                if interaction.webdriver {
+                    found.push(self.signal(60, "browser is driven by automation (navigator.webdriver)"));
+                }
+                let filled_by_hand = !interaction.autofilled && !interaction.pasted;
+                if filled_by_hand && interaction.keystrokes == 0 {
+                    found.push(self.signal(25, "form submitted without keystrokes, paste or autofill"));
+                }
+                if let Some(form_ms) = interaction.form_ms.filter(|ms| filled_by_hand && *ms < self.min_form_ms) {
+                    found.push(self.signal(35, format!("form completed in {} ms", form_ms)));
+                }
+                if interaction.pointer_events == 0 && interaction.keystrokes == 0 {
+                    found.push(self.signal(15, "no pointer, touch or keyboard activity"));
+                }
+            }
+        }
+
+        let timing = &signals.timing;
+        if let Some(interval) = timing.last_interval_ms.filter(|ms| *ms < 500) {
+            found.push(self.signal(20, format!("requests {} ms apart", interval)));
+        }
+        // A handful of evenly spaced attempts is a script on a timer
+        if let (true, Some(variation)) = (timing.recent_requests >= 5, timing.interval_variation) {
+            if variation < 0.1 {
+                found.push(self.signal(
+                    30,
+                    format!("{} requests at regular intervals (variation {:.2})", timing.recent_requests, variation),
+                ));
+            }
+        }
+
+        Ok(found)
+    }
+}
+
+/// Remote risk engine: receives the signals as JSON and answers `[{source, score, detail}]`
+pub struct HttpScorer {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl HttpScorer {
+    pub fn new(url: String, token: Option<String>) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(800))
+            .build()?;
+        Ok(Self { client, url, token })
+    }
+}
+
+#[async_trait]
+impl BotScorer for HttpScorer {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn score(&self, signals: &BehaviorSignals) -> anyhow::Result<Vec<BotSignal>> {
+        let mut request = self.client.post(&self.url).json(signals);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        Ok(request.send().await?.error_for_status()?.json().await?)
+    }
+}
+
+/// Windows and thresholds applied by the guard
+#[derive(Debug, Clone)]
+pub struct BotGuardPolicy {
+    pub timing_window_seconds: u64,
+    /// Scores at or above this need a solved challenge
+    pub step_up_threshold: u32,
+    /// Scores at or above this are refused
+    pub block_threshold: u32,
+    /// Leading zero bits of a challenge at the step-up threshold; one more per 10 points above it
+    pub base_difficulty: u32,
+    pub max_difficulty: u32,
+    pub challenge_ttl_seconds: u64,
+}
+
+impl Default for BotGuardPolicy {
+    fn default() -> Self {
+        Self {
+            timing_window_seconds: 10 * 60,
+            step_up_threshold: 50,
+            block_threshold: 90,
+            base_difficulty: 18,
+            max_difficulty: 24,
+            challenge_ttl_seconds: 5 * 60,
+        }
+    }
+}
+
+impl BotGuardPolicy {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let number = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let step_up_threshold = number("BOT_STEP_UP_THRESHOLD", defaults.step_up_threshold as u64).min(100) as u32;
+        let base_difficulty = number("BOT_CHALLENGE_DIFFICULTY", defaults.base_difficulty as u64).clamp(8, 28) as u32;
+        Self {
+            step_up_threshold,
+            // 101 never blocks, only steps up
+            block_threshold: (number("BOT_BLOCK_THRESHOLD", defaults.block_threshold as u64).min(101) as u32)
+                .max(step_up_threshold),
+            base_difficulty,
+            max_difficulty: base_difficulty + 6,
+            ..defaults
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BotGuard {
+    db: Database,
+    redis: redis::Client,
+    policy: BotGuardPolicy,
+    scorers: Arc<Vec<Box<dyn BotScorer>>>,
+}
+
+impl BotGuard {
+    /// Guard configured by BOT_SCORING_PROVIDER; `None` when it is `off`
+    pub fn from_env(db: Database, redis: redis::Client) -> anyhow::Result<Option<Self>> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let mut scorers: Vec<Box<dyn BotScorer>> = Vec::new();
+        match var("BOT_SCORING_PROVIDER").as_deref().unwrap_or("heuristic") {
+            "off" => return Ok(None),
+            "heuristic" => scorers.push(Box::new(HeuristicScorer::default())),
+            // The remote engine adds to the heuristics rather than replacing them
+            "http" => {
+                let url = var("BOT_SCORING_URL")
+                    .ok_or_else(|| anyhow::anyhow!("BOT_SCORING_URL is required when BOT_SCORING_PROVIDER=http"))?;
+                scorers.push(Box::new(HeuristicScorer::default()));
+                scorers.push(Box::new(HttpScorer::new(url, var("BOT_SCORING_TOKEN"))?));
+            }
+            other => anyhow::bail!("unknown BOT_SCORING_PROVIDER {:?}; expected heuristic, http or off", other),
+        }
+        Ok(Some(Self::with_scorers(db, redis, BotGuardPolicy::from_env(), scorers)))
+    }
+
+    pub fn with_scorers(
+        db: Database,
+        redis: redis::Client,
+        policy: BotGuardPolicy,
+        scorers: Vec<Box<dyn BotScorer>>,
+    ) -> Self {
+        Self {
+            db,
+            redis,
+            policy,
+            scorers: Arc::new(scorers),
+        }
+    }
+
+    pub fn providers(&self) -> Vec<&'static str> {
+        self.scorers.iter().map(|scorer| scorer.name()).collect()
+    }
+
+    /// Record the request's timing and score it with every provider
+    pub async fn assess(&self, signals: &mut BehaviorSignals) -> Result<BotAssessment, AppError> {
+        let mut conn = self.connection().await?;
+        if let Some(ip) = signals.client.ip_address {
+            signals.timing = self.record_timing(&format!("bot:timing:ip:{}", ip), &mut conn).await?;
+        }
+
+        let mut found = Vec::new();
+        for scorer in self.scorers.iter() {
+            match scorer.score(signals).await {
+                Ok(mut scored) => found.append(&mut scored),
+                Err(e) => warn!("Bot scorer {} failed on {}: {}", scorer.name(), signals.endpoint, e),
+            }
+        }
+        let score = found.iter().map(|signal| signal.score).sum::<u32>().min(100);
+        let decision = if score >= self.policy.block_threshold {
+            BotDecision::Block
+        } else if score >= self.policy.step_up_threshold {
+            BotDecision::StepUp
+        } else {
+            BotDecision::Allow
+        };
+
+        Ok(BotAssessment {
+            score,
+            decision,
+            signals: found,
+            stepped_up: false,
+        })
+    }
+
+    /// A new single-use challenge, harder the higher the score
+    pub async fn issue_challenge(&self, signals: &BehaviorSignals, score: u32) -> Result<StepUpChallenge, AppError> {
+        let mut nonce = [0u8; 16];
+        OsRng.fill_bytes(&mut nonce);
+        let nonce: String = nonce.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let difficulty = (self.policy.base_difficulty + score.saturating_sub(self.policy.step_up_threshold) / 10)
+            .min(self.policy.max_difficulty);
+        let challenge = StepUpChallenge {
+            challenge_id: Uuid::new_v4(),
+            algorithm: "sha256",
+            nonce,
+            difficulty,
+            expires_at: Utc::now() + Duration::seconds(self.policy.challenge_ttl_seconds as i64),
+        };
+
+        // Bound to the IP address it was issued to, so solved challenges cannot be farmed out
+        let stored = format!("{}|{}|{}", challenge.nonce, challenge.difficulty, signals.ip_address.as_deref().unwrap_or(""));
+        let mut conn = self.connection().await?;
+        redis::cmd("SET")
+            .arg(format!("bot:challenge:{}", challenge.challenge_id))
+            .arg(stored)
+            .arg("EX")
+            .arg(self.policy.challenge_ttl_seconds)
+            .query_async::<_, ()>(&mut conn)
+            .await
+            .map_err(redis_error)?;
+        Ok(challenge)
+    }
+
+    /// Whether the solution answers a challenge issued to this client; a challenge is consumed by any attempt
+    pub async fn redeem(&self, signals: &BehaviorSignals, challenge_id: Uuid, solution: &str) -> Result<bool, AppError> {
+        let mut conn = self.connection().await?;
+        let stored: Option<String> = redis::cmd("GETDEL")
+            .arg(format!("bot:challenge:{}", challenge_id))
+            .query_async(&mut conn)
+            .await
+            .map_err(redis_error)?;
+        let Some(stored) = stored else {
+            return Ok(false);
+        };
+        let mut parts = stored.splitn(3, '|');
+        let (Some(nonce), Some(difficulty), Some(ip)) = (parts.next(), parts.next(), parts.next()) else {
+            return Ok(false);
+        };
+        if ip != signals.ip_address.as_deref().unwrap_or("") {
+            return Ok(false);
+        }
+        let difficulty: u32 = difficulty.parse().unwrap_or(u32::MAX);
+        Ok(leading_zero_bits(&Sha256::digest(format!("{}:{}", nonce, solution).as_bytes())) >= difficulty)
+    }
+
+    async fn record_timing(&self, key: &str, conn: &mut MultiplexedConnection) -> Result<TimingSnapshot, AppError> {
+        let now = Utc::now().timestamp_millis();
+        let window_start = now - (self.policy.timing_window_seconds * 1000) as i64;
+        // Members are "<arrival ms>:<id>" so simultaneous requests are all kept
+        let (members,): (Vec<String>,) = redis::pipe()
+            .cmd("ZADD").arg(key).arg(now).arg(format!("{}:{}", now, Uuid::new_v4().simple())).ignore()
+            .cmd("ZREMRANGEBYSCORE").arg(key).arg("-inf").arg(window_start).ignore()
+            // Only the latest 20 arrivals matter
+            .cmd("ZREMRANGEBYRANK").arg(key).arg(0).arg(-21).ignore()
+            .cmd("EXPIRE").arg(key).arg(self.policy.timing_window_seconds).ignore()
+            .cmd("ZRANGE").arg(key).arg(0).arg(-1)
+            .query_async(conn)
+            .await
+            .map_err(redis_error)?;
+        let arrivals: Vec<i64> = members
+            .iter()
+            .filter_map(|member| member.split(':').next()?.parse().ok())
+            .collect();
+
+        let intervals: Vec<f64> = arrivals.windows(2).map(|pair| (pair[1] - pair[0]) as f64).collect();
+        let mut timing = TimingSnapshot {
+            recent_requests: arrivals.len() as u64,
+            ..Default::default()
+        };
+        if !intervals.is_empty() {
+            let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+            let variance = intervals.iter().map(|gap| (gap - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+            timing.last_interval_ms = intervals.last().map(|gap| *gap as u64);
+            timing.mean_interval_ms = Some(mean);
+            timing.interval_variation = (mean > 0.0).then(|| variance.sqrt() / mean);
+        }
+        Ok(timing)
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection, AppError> {
+        self.redis
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| AppError::Internal(format!("Redis connection error: {}", e)))
+    }
+
+    async fn record_security_event(
+        &self,
+        action: &str,
+        signals: &BehaviorSignals,
+        assessment: &BotAssessment,
+    ) -> Result<(), AppError> {
+        let details = serde_json::json!({ "signals": signals, "assessment": assessment });
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_logs (action, resource_type, new_values, ip_address, user_agent, api_endpoint, http_method)
+            VALUES ($1, 'AUTH_ENDPOINT', $2, $3::inet, $4, $5, 'POST')
+            "#,
+        )
+        .bind(action)
+        .bind(details)
+        .bind(&signals.ip_address)
+        .bind(&signals.user_agent)
+        .bind(format!("/api/v1/auth{}", signals.endpoint))
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn redis_error(e: redis::RedisError) -> AppError {
+    AppError::Internal(format!("Redis query error: {}", e))
+}
+
+fn leading_zero_bits(digest: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in digest {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            return bits + byte.leading_zeros();
+        }
+    }
+    bits
+}
+
+/// Screen an authentication request; runs only on routes it is layered on
+pub async fn screen(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let Some(guard) = state.bot_guard.as_ref() else {
+        return next.run(request).await;
+    };
+    let endpoint = request.uri().path().rsplit('/').next().map(|last| format!("/{}", last)).unwrap_or_default();
+    let mut signals = BehaviorSignals::from_headers(&endpoint, request.headers());
+
+    let mut assessment = match guard.assess(&mut signals).await {
+        Ok(assessment) => assessment,
+        // Scoring is a second line of defence; the handlers' own limits still apply
+        Err(e) => {
+            warn!("Bot screening of {} skipped: {}", endpoint, e);
+            return next.run(request).await;
+        }
+    };
+    counter!("user_bot_screenings_total", 1, "endpoint" => endpoint.clone(), "decision" => assessment.decision.as_str());
+
+    match assessment.decision {
+        BotDecision::Allow => {}
+        BotDecision::Block => {
+            if let Err(e) = guard.record_security_event("BOT_BLOCKED", &signals, &assessment).await {
+                warn!("Failed to record blocked bot on {}: {}", endpoint, e);
+            }
+            warn!("Blocked likely bot on {} (score {})", endpoint, assessment.score);
+            return AppError::TooManyRequests("Too many requests; try again later".to_string()).into_response();
+        }
+        BotDecision::StepUp => {
+            let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok()).map(str::trim);
+            let attempt = header("x-step-up-challenge")
+                .and_then(|id| id.parse::<Uuid>().ok())
+                .zip(header("x-step-up-solution").map(str::to_string));
+            let solved = match attempt {
+                Some((challenge_id, solution)) => match guard.redeem(&signals, challenge_id, &solution).await {
+                    Ok(solved) => solved,
+                    Err(e) => return e.into_response(),
+                },
+                None => false,
+            };
+            if !solved {
+                return match guard.issue_challenge(&signals, assessment.score).await {
+                    Ok(challenge) => {
+                        if let Err(e) = guard.record_security_event("BOT_STEP_UP_REQUIRED", &signals, &assessment).await {
+                            warn!("Failed to record bot step-up on {}: {}", endpoint, e);
+                        }
+                        let body = ApiResponse {
+                            success: false,
+                            data: Some(challenge),
+                            error: Some("Additional verification required".to_string()),
+                            timestamp: Utc::now(),
+                        };
+                        (StatusCode::PRECONDITION_REQUIRED, Json(body)).into_response()
+                    }
+                    Err(e) => e.into_response(),
+                };
+            }
+            info!("Step-up challenge solved on {} (score {})", endpoint, assessment.score);
+            assessment.stepped_up = true;
+        }
+    }
+
+    request.extensions_mut().insert(assessment);
+    next.run(request).await
+}
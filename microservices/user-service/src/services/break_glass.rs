@@ -0,0 +1,540 @@
+//! Break-glass emergency access to a tenant
+//!
+//! A SuperAdmin asks for time-boxed access to a tenant with a reason; the
+//! request only becomes an ACTIVE window once BREAK_GLASS_APPROVALS other
+//! SuperAdmins (at least two, never the requester) have approved it, and a
+//! single rejection ends it. Windows close on their own at expires_at or when
+//! revoked. BREAK_GLASS_BUNDLE_DELAY_SECS after a window closes, so that audit
+//! records still being mirrored in have landed, every audit_logs row the
+//! requester produced during the window is collected into a bundle with a
+//! SHA-256 over its content.
+
+use axum::http::{header, HeaderMap};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors};
+
+use crate::{
+    database::Database,
+    error::AppError,
+    models::*,
+};
+
+const SUPER_ADMIN_ROLE: &str = "SUPER_ADMIN";
+
+#[derive(Clone)]
+pub struct BreakGlassSettings {
+    pub required_approvals: i64,
+    pub max_duration_minutes: i32,
+    pub request_ttl: Duration,
+    pub bundle_delay: Duration,
+    pub sweep_interval: std::time::Duration,
+    decoding_key: Arc<DecodingKey>,
+}
+
+impl BreakGlassSettings {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let secret = std::env::var("JWT_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("JWT_SECRET is required to identify break-glass approvers"))?;
+        let number = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Ok(Self {
+            // Dual control cannot be configured away
+            required_approvals: number("BREAK_GLASS_APPROVALS", 2).max(2),
+            max_duration_minutes: number("BREAK_GLASS_MAX_MINUTES", 240).max(5) as i32,
+            request_ttl: Duration::minutes(number("BREAK_GLASS_REQUEST_TTL_MINUTES", 60).max(1)),
+            bundle_delay: Duration::seconds(number("BREAK_GLASS_BUNDLE_DELAY_SECS", 300).max(0)),
+            sweep_interval: std::time::Duration::from_secs(number("BREAK_GLASS_SWEEP_SECS", 60).max(1) as u64),
+            decoding_key: Arc::new(DecodingKey::from_secret(secret.as_bytes())),
+        })
+    }
+}
+
+/// Platform access token claims
+#[derive(Deserialize)]
+struct Claims {
+    sub: Uuid,
+    role: String,
+}
+
+#[derive(Clone)]
+pub struct BreakGlassService {
+    db: Database,
+    settings: BreakGlassSettings,
+}
+
+impl BreakGlassService {
+    pub fn new(db: Database, settings: BreakGlassSettings) -> Self {
+        Self { db, settings }
+    }
+
+    /// The calling SuperAdmin, from the bearer token and confirmed against the users table
+    pub async fn authenticate(&self, headers: &HeaderMap) -> Result<Uuid, AppError> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("Bearer token required".to_string()))?;
+        let claims = jsonwebtoken::decode::<Claims>(
+            token,
+            &self.settings.decoding_key,
+            &Validation::new(Algorithm::HS256),
+        )
+        .map_err(|_| AppError::Unauthorized("Invalid bearer token".to_string()))?
+        .claims;
+        if claims.role != SUPER_ADMIN_ROLE {
+            return Err(AppError::Forbidden("Break-glass access is limited to SuperAdmins".to_string()));
+        }
+
+        // A token issued before the account was demoted or disabled must not count
+        let still_admin = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM users WHERE user_id = $1 AND role = 'SUPER_ADMIN' AND is_active)",
+        )
+        .bind(claims.sub)
+        .fetch_one(&self.db.pool)
+        .await?;
+        if !still_admin {
+            return Err(AppError::Forbidden("Break-glass access is limited to SuperAdmins".to_string()));
+        }
+        Ok(claims.sub)
+    }
+
+    pub async fn request(&self, requested_by: Uuid, request: RequestBreakGlassRequest) -> Result<BreakGlassGrant, AppError> {
+        if request.duration_minutes > self.settings.max_duration_minutes {
+            let mut errors = ValidationErrors::new();
+            let mut error = ValidationError::new("range");
+            error.add_param("max".into(), &self.settings.max_duration_minutes);
+            errors.add("duration_minutes", error);
+            return Err(AppError::Validation(errors));
+        }
+
+        let grant = sqlx::query_as::<_, BreakGlassGrant>(
+            r#"
+            INSERT INTO break_glass_grants (tenant_id, requested_by, reason, duration_minutes, request_expires_at)
+            SELECT tenant_id, $2, $3, $4, $5 FROM tenants WHERE tenant_id = $1
+            RETURNING *
+            "#,
+        )
+        .bind(request.tenant_id)
+        .bind(requested_by)
+        .bind(request.reason.trim())
+        .bind(request.duration_minutes)
+        .bind(Utc::now() + self.settings.request_ttl)
+        .fetch_optional(&self.db.pool)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db) if db.constraint() == Some("uq_break_glass_open") => AppError::Conflict(
+                "A break-glass request for this tenant is already pending or active".to_string(),
+            ),
+            _ => AppError::from(e),
+        })?
+        .ok_or_else(|| AppError::NotFound("Tenant not found".to_string()))?;
+
+        self.record(&grant, requested_by, "BREAK_GLASS_REQUESTED", serde_json::json!({
+            "reason": grant.reason,
+            "duration_minutes": grant.duration_minutes,
+        }))
+        .await?;
+        self.raise(
+            &grant,
+            "WARN",
+            format!("Break-glass access to tenant {} requested by {}", grant.tenant_id, requested_by),
+        )
+        .await;
+        Ok(grant)
+    }
+
+    /// Record an approval or rejection; the grant activates on the last required approval
+    pub async fn decide(
+        &self,
+        grant_id: Uuid,
+        approver_id: Uuid,
+        approve: bool,
+        comment: Option<String>,
+    ) -> Result<BreakGlassGrant, AppError> {
+        let mut tx = self.db.pool.begin().await?;
+        let grant = sqlx::query_as::<_, BreakGlassGrant>(
+            "SELECT * FROM break_glass_grants WHERE grant_id = $1 FOR UPDATE",
+        )
+        .bind(grant_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Break-glass request not found".to_string()))?;
+
+        if grant.status != "PENDING" {
+            return Err(AppError::Conflict(format!("Break-glass request is {}", grant.status)));
+        }
+        if grant.request_expires_at <= Utc::now() {
+            return Err(AppError::Conflict("Break-glass request has expired".to_string()));
+        }
+        if grant.requested_by == approver_id {
+            return Err(AppError::Forbidden("The requester cannot decide on their own break-glass request".to_string()));
+        }
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO break_glass_approvals (grant_id, approver_id, decision, comment)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(grant_id)
+        .bind(approver_id)
+        .bind(if approve { "APPROVE" } else { "REJECT" })
+        .bind(&comment)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if inserted == 0 {
+            return Err(AppError::Conflict("You have already decided on this request".to_string()));
+        }
+
+        let approvals = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM break_glass_approvals WHERE grant_id = $1 AND decision = 'APPROVE'",
+        )
+        .bind(grant_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let (grant, action) = if !approve {
+            let grant = sqlx::query_as::<_, BreakGlassGrant>(
+                r#"
+                UPDATE break_glass_grants
+                SET status = 'REJECTED', closed_at = NOW(), closed_by = $2, close_reason = $3
+                WHERE grant_id = $1
+                RETURNING *
+                "#,
+            )
+            .bind(grant_id)
+            .bind(approver_id)
+            .bind(&comment)
+            .fetch_one(&mut *tx)
+            .await?;
+            (grant, "BREAK_GLASS_REJECTED")
+        } else if approvals >= self.settings.required_approvals {
+            let grant = sqlx::query_as::<_, BreakGlassGrant>(
+                r#"
+                UPDATE break_glass_grants
+                SET status = 'ACTIVE', activated_at = NOW(),
+                    expires_at = NOW() + make_interval(mins => duration_minutes)
+                WHERE grant_id = $1
+                RETURNING *
+                "#,
+            )
+            .bind(grant_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            (grant, "BREAK_GLASS_ACTIVATED")
+        } else {
+            (grant, "BREAK_GLASS_APPROVED")
+        };
+        tx.commit().await?;
+
+        self.record(&grant, approver_id, action, serde_json::json!({
+            "approvals": approvals,
+            "required_approvals": self.settings.required_approvals,
+            "comment": comment,
+        }))
+        .await?;
+        if grant.status == "ACTIVE" {
+            self.raise(
+                &grant,
+                "WARN",
+                format!(
+                    "Break-glass access to tenant {} is active for {} until {}",
+                    grant.tenant_id,
+                    grant.requested_by,
+                    grant.expires_at.map(|at| at.to_rfc3339()).unwrap_or_default()
+                ),
+            )
+            .await;
+        }
+        Ok(grant)
+    }
+
+    /// Close a pending request or active window early
+    pub async fn revoke(&self, grant_id: Uuid, revoked_by: Uuid, comment: Option<String>) -> Result<BreakGlassGrant, AppError> {
+        let grant = sqlx::query_as::<_, BreakGlassGrant>(
+            r#"
+            UPDATE break_glass_grants
+            SET status = 'REVOKED', closed_at = NOW(), closed_by = $2, close_reason = $3
+            WHERE grant_id = $1 AND status IN ('PENDING', 'ACTIVE')
+            RETURNING *
+            "#,
+        )
+        .bind(grant_id)
+        .bind(revoked_by)
+        .bind(&comment)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        let Some(grant) = grant else {
+            return match self.find(grant_id).await? {
+                Some(grant) => Err(AppError::Conflict(format!("Break-glass request is {}", grant.status))),
+                None => Err(AppError::NotFound("Break-glass request not found".to_string())),
+            };
+        };
+
+        self.record(&grant, revoked_by, "BREAK_GLASS_REVOKED", serde_json::json!({ "comment": comment }))
+            .await?;
+        Ok(grant)
+    }
+
+    async fn find(&self, grant_id: Uuid) -> Result<Option<BreakGlassGrant>, AppError> {
+        Ok(sqlx::query_as::<_, BreakGlassGrant>("SELECT * FROM break_glass_grants WHERE grant_id = $1")
+            .bind(grant_id)
+            .fetch_optional(&self.db.pool)
+            .await?)
+    }
+
+    pub async fn get(&self, grant_id: Uuid) -> Result<BreakGlassDetail, AppError> {
+        let grant = self
+            .find(grant_id)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Break-glass request not found".to_string()))?;
+        let approvals = sqlx::query_as::<_, BreakGlassApproval>(
+            r#"
+            SELECT approver_id, decision, comment, decided_at
+            FROM break_glass_approvals
+            WHERE grant_id = $1
+            ORDER BY decided_at
+            "#,
+        )
+        .bind(grant_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+        let bundle_hash = sqlx::query_scalar::<_, String>("SELECT content_hash FROM break_glass_bundles WHERE grant_id = $1")
+            .bind(grant_id)
+            .fetch_optional(&self.db.pool)
+            .await?;
+
+        Ok(BreakGlassDetail {
+            grant,
+            approvals,
+            bundle_hash,
+        })
+    }
+
+    pub async fn list(&self, params: &ListBreakGlassParams) -> Result<Vec<BreakGlassGrant>, AppError> {
+        Ok(sqlx::query_as::<_, BreakGlassGrant>(
+            r#"
+            SELECT * FROM break_glass_grants
+            WHERE ($1::uuid IS NULL OR tenant_id = $1)
+              AND ($2::text IS NULL OR status = $2)
+            ORDER BY requested_at DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(params.tenant_id)
+        .bind(params.status.as_deref().map(str::to_uppercase))
+        .bind(params.limit.unwrap_or(50).clamp(1, 500))
+        .fetch_all(&self.db.pool)
+        .await?)
+    }
+
+    /// The window a user holds on a tenant right now, if any
+    pub async fn active_grant(&self, user_id: Uuid, tenant_id: Uuid) -> Result<Option<BreakGlassGrant>, AppError> {
+        Ok(sqlx::query_as::<_, BreakGlassGrant>(
+            r#"
+            SELECT * FROM break_glass_grants
+            WHERE requested_by = $1 AND tenant_id = $2 AND status = 'ACTIVE' AND expires_at > NOW()
+            "#,
+        )
+        .bind(user_id)
+        .bind(tenant_id)
+        .fetch_optional(&self.db.pool)
+        .await?)
+    }
+
+    pub async fn bundle(&self, grant_id: Uuid) -> Result<BreakGlassBundle, AppError> {
+        if let Some(bundle) = sqlx::query_as::<_, BreakGlassBundle>("SELECT * FROM break_glass_bundles WHERE grant_id = $1")
+            .bind(grant_id)
+            .fetch_optional(&self.db.pool)
+            .await?
+        {
+            return Ok(bundle);
+        }
+        match self.find(grant_id).await? {
+            Some(grant) if grant.activated_at.is_none() => {
+                Err(AppError::NotFound("Break-glass request was never activated".to_string()))
+            }
+            Some(_) => Err(AppError::Conflict(
+                "The audit bundle is generated once the window has closed".to_string(),
+            )),
+            None => Err(AppError::NotFound("Break-glass request not found".to_string())),
+        }
+    }
+
+    pub fn spawn_sweeper(self) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.settings.sweep_interval);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.sweep().await {
+                    error!("Break-glass sweep failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Expire overdue requests and windows, then bundle windows that closed long enough ago
+    async fn sweep(&self) -> Result<(), AppError> {
+        let expired = sqlx::query_as::<_, BreakGlassGrant>(
+            r#"
+            UPDATE break_glass_grants
+            SET status = 'EXPIRED',
+                closed_at = CASE WHEN status = 'ACTIVE' THEN expires_at ELSE NOW() END
+            WHERE (status = 'ACTIVE' AND expires_at <= NOW())
+               OR (status = 'PENDING' AND request_expires_at <= NOW())
+            RETURNING *
+            "#,
+        )
+        .fetch_all(&self.db.pool)
+        .await?;
+        for grant in &expired {
+            let action = if grant.activated_at.is_some() {
+                "BREAK_GLASS_EXPIRED"
+            } else {
+                "BREAK_GLASS_REQUEST_EXPIRED"
+            };
+            self.record(grant, grant.requested_by, action, serde_json::json!({})).await?;
+        }
+
+        let due = sqlx::query_as::<_, BreakGlassGrant>(
+            r#"
+            SELECT g.* FROM break_glass_grants g
+            WHERE g.activated_at IS NOT NULL AND g.closed_at <= $1
+              AND NOT EXISTS (SELECT 1 FROM break_glass_bundles b WHERE b.grant_id = g.grant_id)
+            ORDER BY g.closed_at
+            "#,
+        )
+        .bind(Utc::now() - self.settings.bundle_delay)
+        .fetch_all(&self.db.pool)
+        .await?;
+        for grant in due {
+            if let Err(e) = self.generate_bundle(&grant).await {
+                warn!("Failed to generate audit bundle for break-glass grant {}: {}", grant.grant_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn generate_bundle(&self, grant: &BreakGlassGrant) -> Result<(), AppError> {
+        let (Some(opened), Some(closed)) = (grant.activated_at, grant.closed_at) else {
+            return Ok(());
+        };
+
+        let approvals = self.get(grant.grant_id).await?.approvals;
+        // Everything the requester did while the window was open, on any tenant
+        let actions = sqlx::query_as::<_, BreakGlassAction>(
+            r#"
+            SELECT log_id, tenant_id, session_id, action, resource_type, resource_id, old_values, new_values,
+                   host(ip_address) AS ip_address, user_agent, request_id, api_endpoint, http_method,
+                   response_status, timestamp
+            FROM audit_logs
+            WHERE user_id = $1 AND timestamp >= $2 AND timestamp <= $3
+              AND resource_type <> 'BREAK_GLASS'
+            ORDER BY timestamp, log_id
+            "#,
+        )
+        .bind(grant.requested_by)
+        .bind(opened)
+        .bind(closed)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        let bundle = serde_json::json!({
+            "grant": grant,
+            "approvals": approvals,
+            "window": { "opened_at": opened, "closed_at": closed },
+            "actions": actions,
+        });
+        // serde_json::Value keeps keys sorted, so the hash survives a JSONB round trip
+        let encoded = serde_json::to_vec(&bundle)
+            .map_err(|e| AppError::Internal(format!("Bundle serialization error: {}", e)))?;
+        let content_hash = format!("{:x}", Sha256::digest(&encoded));
+
+        sqlx::query(
+            r#"
+            INSERT INTO break_glass_bundles (grant_id, action_count, bundle, content_hash)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (grant_id) DO NOTHING
+            "#,
+        )
+        .bind(grant.grant_id)
+        .bind(actions.len() as i32)
+        .bind(&bundle)
+        .bind(&content_hash)
+        .execute(&self.db.pool)
+        .await?;
+
+        self.record(grant, grant.requested_by, "BREAK_GLASS_BUNDLE_GENERATED", serde_json::json!({
+            "action_count": actions.len(),
+            "content_hash": content_hash,
+        }))
+        .await?;
+        info!(
+            "Generated audit bundle of {} actions for break-glass grant {}",
+            actions.len(),
+            grant.grant_id
+        );
+        Ok(())
+    }
+
+    /// Lifecycle entry on the tenant's audit trail
+    async fn record(
+        &self,
+        grant: &BreakGlassGrant,
+        user_id: Uuid,
+        action: &str,
+        details: serde_json::Value,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_logs (tenant_id, user_id, action, resource_type, resource_id, new_values)
+            VALUES ($1, $2, $3, 'BREAK_GLASS', $4, $5)
+            "#,
+        )
+        .bind(grant.tenant_id)
+        .bind(user_id)
+        .bind(action)
+        .bind(grant.grant_id)
+        .bind(details)
+        .execute(&self.db.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Ops alert; failing to raise one never blocks the request
+    async fn raise(&self, grant: &BreakGlassGrant, severity: &str, message: String) {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO system_events (event_type, severity, source_system, message, details, correlation_id)
+            VALUES ('BREAK_GLASS', $1, 'user-service', $2, $3, $4)
+            "#,
+        )
+        .bind(severity)
+        .bind(&message)
+        .bind(serde_json::json!({
+            "tenant_id": grant.tenant_id,
+            "requested_by": grant.requested_by,
+            "status": grant.status,
+        }))
+        .bind(grant.grant_id)
+        .execute(&self.db.pool)
+        .await;
+        if let Err(e) = result {
+            error!("Failed to raise break-glass alert for grant {}: {}", grant.grant_id, e);
+        }
+    }
+}
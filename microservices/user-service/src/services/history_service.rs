@@ -0,0 +1,140 @@
+//! Point-in-time reads over configuration_history
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{
+    database::Database,
+    error::AppError,
+    models::*,
+};
+
+/// Version validity predicate shared by every as-of query ($1 is the as-of instant)
+const VALID_AT: &str = "h.valid_from <= $1 AND (h.valid_to IS NULL OR h.valid_to > $1)";
+
+#[derive(Clone)]
+pub struct HistoryService {
+    db: Database,
+}
+
+impl HistoryService {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// User record and permission grants as they stood at `as_of`
+    pub async fn user_access_as_of(
+        &self,
+        user_id: Uuid,
+        as_of: DateTime<Utc>,
+    ) -> Result<UserAccessSnapshot, AppError> {
+        let user = sqlx::query_as::<_, UserVersion>(&format!(
+            r#"
+            SELECT r.*, h.valid_from, h.valid_to, h.changed_by
+            FROM configuration_history h,
+                 jsonb_to_record(h.row_data) AS r(
+                     user_id UUID, tenant_id UUID, username TEXT, email TEXT, role user_role,
+                     is_active BOOLEAN, is_verified BOOLEAN, mfa_enabled BOOLEAN, locked_until TIMESTAMPTZ
+                 )
+            WHERE h.table_name = 'users' AND h.entity_id = $2 AND {}
+            "#,
+            VALID_AT
+        ))
+        .bind(as_of)
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or(AppError::NotFound("User did not exist at the requested time".to_string()))?;
+
+        let permissions = sqlx::query_as::<_, PermissionVersion>(&format!(
+            r#"
+            SELECT r.*, h.valid_from, h.valid_to
+            FROM configuration_history h,
+                 jsonb_to_record(h.row_data) AS r(
+                     permission_id UUID, user_id UUID, resource TEXT, action TEXT,
+                     granted_at TIMESTAMPTZ, granted_by UUID
+                 )
+            WHERE h.table_name = 'user_permissions' AND h.owner_id = $2 AND {}
+            ORDER BY r.resource, r.action
+            "#,
+            VALID_AT
+        ))
+        .bind(as_of)
+        .bind(user_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(UserAccessSnapshot {
+            as_of,
+            user,
+            permissions,
+        })
+    }
+
+    /// Tenant settings, configuration entries and users as they stood at `as_of`
+    pub async fn tenant_as_of(
+        &self,
+        tenant_id: Uuid,
+        as_of: DateTime<Utc>,
+    ) -> Result<TenantSnapshot, AppError> {
+        let tenant = sqlx::query_as::<_, TenantVersion>(&format!(
+            r#"
+            SELECT r.*, h.valid_from, h.valid_to
+            FROM configuration_history h,
+                 jsonb_to_record(h.row_data) AS r(
+                     tenant_id UUID, name TEXT, display_name TEXT, subscription_plan TEXT,
+                     max_users INTEGER, max_trades_per_day INTEGER, is_active BOOLEAN
+                 )
+            WHERE h.table_name = 'tenants' AND h.entity_id = $2 AND {}
+            "#,
+            VALID_AT
+        ))
+        .bind(as_of)
+        .bind(tenant_id)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or(AppError::NotFound("Tenant did not exist at the requested time".to_string()))?;
+
+        let configurations = sqlx::query_as::<_, TenantConfigurationVersion>(&format!(
+            r#"
+            SELECT r.*, h.valid_from, h.valid_to
+            FROM configuration_history h,
+                 jsonb_to_record(h.row_data) AS r(
+                     config_id UUID, tenant_id UUID, config_key TEXT, config_value JSONB
+                 )
+            WHERE h.table_name = 'tenant_configurations' AND h.owner_id = $2 AND {}
+            ORDER BY r.config_key
+            "#,
+            VALID_AT
+        ))
+        .bind(as_of)
+        .bind(tenant_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        let users = sqlx::query_as::<_, UserVersion>(&format!(
+            r#"
+            SELECT r.*, h.valid_from, h.valid_to, h.changed_by
+            FROM configuration_history h,
+                 jsonb_to_record(h.row_data) AS r(
+                     user_id UUID, tenant_id UUID, username TEXT, email TEXT, role user_role,
+                     is_active BOOLEAN, is_verified BOOLEAN, mfa_enabled BOOLEAN, locked_until TIMESTAMPTZ
+                 )
+            WHERE h.table_name = 'users' AND h.owner_id = $2 AND {}
+            ORDER BY r.username
+            "#,
+            VALID_AT
+        ))
+        .bind(as_of)
+        .bind(tenant_id)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        Ok(TenantSnapshot {
+            as_of,
+            tenant,
+            configurations,
+            users,
+        })
+    }
+}
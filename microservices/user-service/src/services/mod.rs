@@ -0,0 +1,13 @@
+//! Business logic services
+
+pub mod user_service;
+pub mod history_service;
+pub mod otp_guard;
+pub mod break_glass;
+pub mod bot_guard;
+
+pub use user_service::*;
+pub use history_service::*;
+pub use otp_guard::*;
+pub use break_glass::*;
+pub use bot_guard::{BotGuard, BotGuardPolicy, BotScorer, HeuristicScorer, HttpScorer};
@@ -0,0 +1,366 @@
+//! Velocity checks and fraud scoring for OTP and password-reset delivery
+//!
+//! Every request is counted in Redis, scored by a set of pluggable rules and,
+//! above the block threshold, the offending account and/or IP address is
+//! blocked for a while. Blocks and elevated-risk requests are written to the
+//! security audit trail.
+
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use tracing::warn;
+
+use crate::{
+    database::Database,
+    error::AppError,
+    models::*,
+};
+
+/// A fraud rule scores one aspect of a request from the shared velocity snapshot
+pub trait FraudRule: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn evaluate(&self, context: &OtpRequestContext, velocity: &VelocitySnapshot) -> Option<FraudSignal>;
+}
+
+/// Many requests for a single account
+pub struct AccountVelocityRule {
+    pub max_requests: u64,
+}
+
+impl FraudRule for AccountVelocityRule {
+    fn name(&self) -> &'static str {
+        "account_velocity"
+    }
+
+    fn evaluate(&self, context: &OtpRequestContext, velocity: &VelocitySnapshot) -> Option<FraudSignal> {
+        if velocity.account_requests <= self.max_requests {
+            return None;
+        }
+        Some(FraudSignal {
+            rule: self.name().to_string(),
+            score: 60 + 10 * (velocity.account_requests - self.max_requests - 1).min(4) as u32,
+            scope: BlockScope::Account,
+            detail: format!(
+                "{} {} requests for the account within the window (limit {})",
+                velocity.account_requests,
+                context.flow.as_str(),
+                self.max_requests
+            ),
+        })
+    }
+}
+
+/// One IP address sending many requests
+pub struct IpVelocityRule {
+    pub max_requests: u64,
+}
+
+impl FraudRule for IpVelocityRule {
+    fn name(&self) -> &'static str {
+        "ip_velocity"
+    }
+
+    fn evaluate(&self, _context: &OtpRequestContext, velocity: &VelocitySnapshot) -> Option<FraudSignal> {
+        if velocity.ip_requests <= self.max_requests {
+            return None;
+        }
+        Some(FraudSignal {
+            rule: self.name().to_string(),
+            score: 50,
+            scope: BlockScope::IpAddress,
+            detail: format!(
+                "{} requests from the IP address within the window (limit {})",
+                velocity.ip_requests, self.max_requests
+            ),
+        })
+    }
+}
+
+/// One IP address targeting many different accounts
+pub struct IpFanOutRule {
+    pub max_accounts: u64,
+}
+
+impl FraudRule for IpFanOutRule {
+    fn name(&self) -> &'static str {
+        "ip_account_fan_out"
+    }
+
+    fn evaluate(&self, _context: &OtpRequestContext, velocity: &VelocitySnapshot) -> Option<FraudSignal> {
+        if velocity.ip_distinct_accounts <= self.max_accounts {
+            return None;
+        }
+        Some(FraudSignal {
+            rule: self.name().to_string(),
+            score: 80,
+            scope: BlockScope::IpAddress,
+            detail: format!(
+                "{} distinct accounts targeted from the IP address (limit {})",
+                velocity.ip_distinct_accounts, self.max_accounts
+            ),
+        })
+    }
+}
+
+/// Request from a device the account has not used for this flow before
+pub struct UnknownDeviceRule {
+    pub score: u32,
+}
+
+impl FraudRule for UnknownDeviceRule {
+    fn name(&self) -> &'static str {
+        "unknown_device"
+    }
+
+    fn evaluate(&self, _context: &OtpRequestContext, velocity: &VelocitySnapshot) -> Option<FraudSignal> {
+        match velocity.known_device {
+            Some(false) => Some(FraudSignal {
+                rule: self.name().to_string(),
+                score: self.score,
+                scope: BlockScope::Account,
+                detail: "device has not been seen for this account".to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Windows and thresholds applied by the guard
+#[derive(Debug, Clone)]
+pub struct OtpGuardPolicy {
+    pub velocity_window_seconds: u64,
+    pub fan_out_window_seconds: u64,
+    pub device_memory_seconds: u64,
+    /// Scores at or above this are logged to the security audit trail
+    pub audit_threshold: u32,
+    /// Scores at or above this block the request and start a temporary block
+    pub block_threshold: u32,
+    pub block_duration_seconds: u64,
+}
+
+impl Default for OtpGuardPolicy {
+    fn default() -> Self {
+        Self {
+            velocity_window_seconds: 15 * 60,
+            fan_out_window_seconds: 60 * 60,
+            device_memory_seconds: 90 * 24 * 60 * 60,
+            audit_threshold: 40,
+            block_threshold: 70,
+            block_duration_seconds: 30 * 60,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OtpGuard {
+    db: Database,
+    redis: redis::Client,
+    policy: OtpGuardPolicy,
+    rules: Arc<Vec<Box<dyn FraudRule>>>,
+}
+
+impl OtpGuard {
+    /// Guard with the built-in velocity, fan-out and device rules
+    pub fn new(db: Database, redis: redis::Client, policy: OtpGuardPolicy) -> Self {
+        let rules: Vec<Box<dyn FraudRule>> = vec![
+            Box::new(AccountVelocityRule { max_requests: 5 }),
+            Box::new(IpVelocityRule { max_requests: 20 }),
+            Box::new(IpFanOutRule { max_accounts: 5 }),
+            Box::new(UnknownDeviceRule { score: 15 }),
+        ];
+        Self::with_rules(db, redis, policy, rules)
+    }
+
+    pub fn with_rules(
+        db: Database,
+        redis: redis::Client,
+        policy: OtpGuardPolicy,
+        rules: Vec<Box<dyn FraudRule>>,
+    ) -> Self {
+        Self {
+            db,
+            redis,
+            policy,
+            rules: Arc::new(rules),
+        }
+    }
+
+    /// Count and score a delivery request, failing with 429 when it is blocked
+    pub async fn check(&self, context: &OtpRequestContext) -> Result<FraudAssessment, AppError> {
+        let mut conn = self.redis.get_connection()
+            .map_err(|e| AppError::Internal(format!("Redis connection error: {}", e)))?;
+
+        let account_block_key = format!("otp:block:account:{}", context.account);
+        let ip_block_key = context.client.ip_address.map(|ip| format!("otp:block:ip:{}", ip));
+
+        for key in std::iter::once(&account_block_key).chain(ip_block_key.as_ref()) {
+            let ttl: i64 = redis_query(redis::cmd("TTL").arg(key), &mut conn)?;
+            if ttl > 0 {
+                warn!("Rejected {} request for {} while blocked", context.flow.as_str(), context.account);
+                return Err(AppError::TooManyRequests(format!(
+                    "Too many requests; try again in {} minutes",
+                    (ttl + 59) / 60
+                )));
+            }
+        }
+
+        let velocity = self.record_velocity(context, &mut conn)?;
+
+        let signals: Vec<FraudSignal> = self
+            .rules
+            .iter()
+            .filter_map(|rule| rule.evaluate(context, &velocity))
+            .collect();
+        let score = signals.iter().map(|signal| signal.score).sum::<u32>().min(100);
+
+        let mut assessment = FraudAssessment {
+            flow: context.flow,
+            score,
+            decision: OtpDecision::Allow,
+            signals,
+            velocity,
+            blocked_until: None,
+        };
+
+        if score >= self.policy.block_threshold {
+            assessment.decision = OtpDecision::Block;
+            assessment.blocked_until =
+                Some(Utc::now() + Duration::seconds(self.policy.block_duration_seconds as i64));
+
+            let scopes = assessment.signals.iter().map(|signal| signal.scope);
+            let block_ip = scopes.clone().any(|scope| scope == BlockScope::IpAddress);
+            // Account-level signals, or a high score from several weak ones, lock the account
+            let block_account = !block_ip || scopes.clone().any(|scope| scope == BlockScope::Account);
+
+            if block_account {
+                self.start_block(&account_block_key, &mut conn)?;
+            }
+            if let (true, Some(key)) = (block_ip, ip_block_key.as_ref()) {
+                self.start_block(key, &mut conn)?;
+            }
+
+            self.record_security_event("OTP_TEMPORARY_BLOCK", context, &assessment).await?;
+            warn!(
+                "Blocked {} delivery for {} (score {})",
+                context.flow.as_str(),
+                context.account,
+                score
+            );
+
+            return Err(AppError::TooManyRequests(format!(
+                "Too many requests; try again in {} minutes",
+                self.policy.block_duration_seconds / 60
+            )));
+        }
+
+        if score >= self.policy.audit_threshold {
+            self.record_security_event("OTP_ELEVATED_RISK", context, &assessment).await?;
+        }
+
+        if let Some(device_id) = &context.client.device_id {
+            let devices_key = format!("otp:devices:{}:{}", context.flow.as_str(), context.account);
+            redis::pipe()
+                .cmd("SADD").arg(&devices_key).arg(device_id).ignore()
+                .cmd("EXPIRE").arg(&devices_key).arg(self.policy.device_memory_seconds).ignore()
+                .query::<()>(&mut conn)
+                .map_err(|e| AppError::Internal(format!("Redis query error: {}", e)))?;
+        }
+
+        Ok(assessment)
+    }
+
+    fn record_velocity(
+        &self,
+        context: &OtpRequestContext,
+        conn: &mut redis::Connection,
+    ) -> Result<VelocitySnapshot, AppError> {
+        let window = self.policy.velocity_window_seconds;
+        let mut velocity = VelocitySnapshot {
+            account_requests: increment(
+                &format!("otp:velocity:account:{}:{}", context.flow.as_str(), context.account),
+                window,
+                conn,
+            )?,
+            ..Default::default()
+        };
+
+        if let Some(ip) = context.client.ip_address {
+            velocity.ip_requests = increment(&format!("otp:velocity:ip:{}", ip), window, conn)?;
+
+            let fan_out_key = format!("otp:fanout:ip:{}", ip);
+            let (_, _, distinct): ((), (), u64) = redis::pipe()
+                .cmd("SADD").arg(&fan_out_key).arg(&context.account)
+                .cmd("EXPIRE").arg(&fan_out_key).arg(self.policy.fan_out_window_seconds)
+                .cmd("SCARD").arg(&fan_out_key)
+                .query(conn)
+                .map_err(|e| AppError::Internal(format!("Redis query error: {}", e)))?;
+            velocity.ip_distinct_accounts = distinct;
+        }
+
+        if let Some(device_id) = &context.client.device_id {
+            let devices_key = format!("otp:devices:{}:{}", context.flow.as_str(), context.account);
+            let known_devices: u64 = redis_query(redis::cmd("SCARD").arg(&devices_key), conn)?;
+            if known_devices > 0 {
+                let is_member: bool =
+                    redis_query(redis::cmd("SISMEMBER").arg(&devices_key).arg(device_id), conn)?;
+                velocity.known_device = Some(is_member);
+            }
+        }
+
+        Ok(velocity)
+    }
+
+    fn start_block(&self, key: &str, conn: &mut redis::Connection) -> Result<(), AppError> {
+        redis_query::<()>(
+            redis::cmd("SET")
+                .arg(key)
+                .arg(Utc::now().to_rfc3339())
+                .arg("EX")
+                .arg(self.policy.block_duration_seconds),
+            conn,
+        )
+    }
+
+    async fn record_security_event(
+        &self,
+        action: &str,
+        context: &OtpRequestContext,
+        assessment: &FraudAssessment,
+    ) -> Result<(), AppError> {
+        let details = serde_json::to_value(assessment)
+            .map_err(|e| AppError::Internal(format!("Assessment serialization error: {}", e)))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_logs (tenant_id, user_id, action, resource_type, resource_id, new_values, ip_address)
+            VALUES ($1, $2, $3, 'USER', $2, $4, $5::inet)
+            "#,
+        )
+        .bind(context.tenant_id)
+        .bind(context.user_id)
+        .bind(action)
+        .bind(details)
+        .bind(context.client.ip_address.map(|ip| ip.to_string()))
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+fn redis_query<T: redis::FromRedisValue>(
+    cmd: &mut redis::Cmd,
+    conn: &mut redis::Connection,
+) -> Result<T, AppError> {
+    cmd.query(conn)
+        .map_err(|e| AppError::Internal(format!("Redis query error: {}", e)))
+}
+
+/// Increment a windowed counter, starting the window on first use
+fn increment(key: &str, window_seconds: u64, conn: &mut redis::Connection) -> Result<u64, AppError> {
+    let count: u64 = redis_query(redis::cmd("INCR").arg(key), conn)?;
+    if count == 1 {
+        redis_query::<()>(redis::cmd("EXPIRE").arg(key).arg(window_seconds), conn)?;
+    }
+    Ok(count)
+}
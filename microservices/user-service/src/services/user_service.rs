@@ -5,6 +5,7 @@ use argon2::{
     Argon2,
 };
 use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
 use sqlx::Row;
 use tracing::{error, info, warn};
 use uuid::Uuid;
@@ -112,6 +113,22 @@ impl UserService {
         Ok(user)
     }
 
+    /// Username, role and tenant name of a user, for audit event enrichment
+    pub async fn get_actor_snapshot(&self, user_id: Uuid) -> Result<ActorSnapshot, AppError> {
+        sqlx::query_as::<_, ActorSnapshot>(
+            r#"
+            SELECT u.user_id, u.username, u.role, u.tenant_id, t.display_name AS tenant_name
+            FROM users u
+            JOIN tenants t ON t.tenant_id = u.tenant_id
+            WHERE u.user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or(AppError::NotFound("User not found".to_string()))
+    }
+
     /// List users with search and pagination
     pub async fn list_users(
         &self,
@@ -321,6 +338,45 @@ impl UserService {
         Ok(())
     }
 
+    /// Find an active user by email address
+    pub async fn find_user_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        let user = sqlx::query_as::<_, User>(
+            "SELECT * FROM users WHERE lower(email) = lower($1) AND is_active = true ORDER BY created_at LIMIT 1"
+        )
+        .bind(email)
+        .fetch_optional(&self.db.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Issue a single-use password reset token valid for 30 minutes
+    pub async fn generate_password_reset(&self, user_id: Uuid) -> Result<String, AppError> {
+        let user = self.get_user_by_id(user_id).await?;
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+        // Only the token hash is stored so a Redis dump cannot be replayed
+        let mut conn = self.redis.get_connection()
+            .map_err(|e| AppError::Internal(format!("Redis connection error: {}", e)))?;
+
+        redis::cmd("SETEX")
+            .arg(format!("password_reset:{:x}", Sha256::digest(token.as_bytes())))
+            .arg(1800) // 30 minute expiry
+            .arg(user.user_id.to_string())
+            .execute(&mut conn);
+
+        info!("Password reset issued for user: {}", user.user_id);
+
+        Ok(token)
+    }
+
+    /// Deliver a password reset link
+    pub async fn send_password_reset_email(&self, user: &User, _token: &str) -> Result<(), AppError> {
+        // TODO: Implement email sending
+        info!("Password reset email would be sent to: {}", user.email);
+        Ok(())
+    }
+
     // Helper methods
 
     async fn user_exists(&self, username: &str, email: &str, tenant_id: Uuid) -> Result<bool, AppError> {
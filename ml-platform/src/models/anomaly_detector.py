@@ -17,6 +17,8 @@ import psycopg2
 from psycopg2.extras import RealDictCursor
 import json
 
+from .market_hours import MarketHours
+
 logger = logging.getLogger(__name__)
 
 class AnomalyDetector:
@@ -28,7 +30,8 @@ class AnomalyDetector:
     def __init__(self, 
                  contamination: float = 0.1,
                  random_state: int = 42,
-                 model_type: str = "isolation_forest"):
+                 model_type: str = "isolation_forest",
+                 market_hours: Optional[MarketHours] = None):
         """
         Initialize anomaly detector
         
@@ -36,10 +39,12 @@ class AnomalyDetector:
             contamination: Expected proportion of outliers
             random_state: Random state for reproducibility
             model_type: Type of model to use
+            market_hours: Tenant sessions for off-hours detection; NSE hours when omitted
         """
         self.contamination = contamination
         self.random_state = random_state
         self.model_type = model_type
+        self.market_hours = market_hours or MarketHours()
         self.model = None
         self.scaler = StandardScaler()
         self.feature_columns = []
@@ -237,9 +242,8 @@ class AnomalyDetector:
             if (time_diffs < 10).any():
                 return "RAPID_TRADING"
         
-        # Check for off-hours trading
-        trade_hour = pd.to_datetime(trade['timestamp']).hour
-        if trade_hour < 9 or trade_hour > 15:
+        # Check for off-hours trading against the tenant's sessions and holidays
+        if not self.market_hours.is_open(trade['timestamp']):
             return "OFF_HOURS_TRADING"
         
         # Check for unusual price movement
@@ -308,7 +312,8 @@ class RealTimeAnomalyDetector:
     Real-time anomaly detection with streaming capabilities
     """
     
-    def __init__(self, model_path: str, redis_config: Dict, db_config: Dict):
+    def __init__(self, model_path: str, redis_config: Dict, db_config: Dict,
+                 tenant_id: Optional[str] = None):
         """
         Initialize real-time detector
         
@@ -316,8 +321,10 @@ class RealTimeAnomalyDetector:
             model_path: Path to saved model
             redis_config: Redis configuration
             db_config: Database configuration
+            tenant_id: Tenant whose market hours apply; NSE hours when omitted
         """
-        self.detector = AnomalyDetector()
+        market_hours = MarketHours.from_db(db_config, tenant_id) if tenant_id else None
+        self.detector = AnomalyDetector(market_hours=market_hours)
         self.detector.load_model(model_path)
         
         # Initialize connections
@@ -0,0 +1,75 @@
+"""
+DharmaGuard ML Platform - Tenant Market Hours
+Exchange sessions and holidays used to tell in-session trading from off-hours activity
+"""
+
+from dataclasses import dataclass, field
+from datetime import date, datetime, time, timezone
+from typing import Dict, List, Set
+from zoneinfo import ZoneInfo
+
+import pandas as pd
+import psycopg2
+from psycopg2.extras import RealDictCursor
+
+WEEKDAYS = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+
+
+@dataclass
+class MarketHours:
+    """
+    A tenant's weekly sessions in the exchange time zone, mirroring
+    tenant_business_calendars and tenant_holidays. Defaults to NSE equity
+    hours (09:15-15:30 IST, Monday to Friday).
+    """
+
+    timezone: str = "Asia/Kolkata"
+    # Weekday index (Monday = 0) to (open, close) sessions
+    sessions: Dict[int, List[tuple]] = field(default_factory=lambda: {
+        day: [(time(9, 15), time(15, 30))] for day in range(5)
+    })
+    holidays: Set[date] = field(default_factory=set)
+
+    @classmethod
+    def from_db(cls, db_config: Dict, tenant_id: str) -> "MarketHours":
+        """Load the tenant's calendar; the default applies when none is configured"""
+        with psycopg2.connect(**db_config) as conn:
+            with conn.cursor(cursor_factory=RealDictCursor) as cursor:
+                cursor.execute(
+                    "SELECT timezone, sessions FROM tenant_business_calendars WHERE tenant_id = %s",
+                    (tenant_id,),
+                )
+                row = cursor.fetchone()
+                cursor.execute(
+                    "SELECT holiday_date FROM tenant_holidays WHERE tenant_id = %s",
+                    (tenant_id,),
+                )
+                holidays = {holiday['holiday_date'] for holiday in cursor.fetchall()}
+
+        if row is None:
+            return cls(holidays=holidays)
+
+        sessions: Dict[int, List[tuple]] = {}
+        for session in row['sessions']:
+            day = WEEKDAYS.index(session['weekday'][:3])
+            sessions.setdefault(day, []).append(
+                (time.fromisoformat(session['open']), time.fromisoformat(session['close']))
+            )
+        return cls(timezone=row['timezone'], sessions=sessions, holidays=holidays)
+
+    def to_local(self, timestamp) -> datetime:
+        """Timestamps without a zone are taken as UTC"""
+        ts = pd.Timestamp(timestamp).to_pydatetime()
+        if ts.tzinfo is None:
+            ts = ts.replace(tzinfo=timezone.utc)
+        return ts.astimezone(ZoneInfo(self.timezone))
+
+    def is_open(self, timestamp) -> bool:
+        """Whether a session is in progress at the given instant"""
+        local = self.to_local(timestamp)
+        if local.date() in self.holidays:
+            return False
+        return any(
+            open_time <= local.time() < close_time
+            for open_time, close_time in self.sessions.get(local.weekday(), [])
+        )